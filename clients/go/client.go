@@ -50,6 +50,7 @@ import (
 	"encoding/binary"
 	"fmt"
 	"io"
+	"math"
 	"net"
 	"sync"
 	"sync/atomic"
@@ -210,16 +211,10 @@ func (c *Client) ClientTag() string {
 // sendHello sends the HELLO message to establish a session with the server.
 // This is called automatically during Dial/DialTLS.
 func (c *Client) sendHello(clientTag string) error {
-	// Build HELLO payload:
-	// protocol_version: u16 (1)
-	// client_tag_len: u16
-	// client_tag: [bytes]
-	// client_meta_json_len: u32 (0)
-	payload := &bytes.Buffer{}
-	_ = binary.Write(payload, binary.LittleEndian, uint16(1)) // protocol version
-	_ = binary.Write(payload, binary.LittleEndian, uint16(len(clientTag)))
-	payload.WriteString(clientTag)
-	_ = binary.Write(payload, binary.LittleEndian, uint32(0)) // no JSON metadata
+	payload, err := encodeHello(clientTag)
+	if err != nil {
+		return err
+	}
 
 	// Set deadline for handshake
 	if err := c.conn.SetDeadline(time.Now().Add(c.timeout)); err != nil {
@@ -228,7 +223,7 @@ func (c *Client) sendHello(clientTag string) error {
 	defer func() { _ = c.conn.SetDeadline(time.Time{}) }()
 
 	reqID := c.reqID.Add(1)
-	if err := c.writeFrame(msgHello, reqID, payload.Bytes()); err != nil {
+	if err := c.writeFrame(msgHello, reqID, payload); err != nil {
 		return err
 	}
 
@@ -253,6 +248,23 @@ func (c *Client) sendHello(clientTag string) error {
 	return nil
 }
 
+// encodeHello builds the HELLO payload:
+// protocol_version: u16 (1)
+// client_tag_len: u16
+// client_tag: [bytes]
+// client_meta_json_len: u32 (0)
+func encodeHello(clientTag string) ([]byte, error) {
+	if len(clientTag) > math.MaxUint16 {
+		return nil, fmt.Errorf("client tag is %d bytes, over the %d-byte limit", len(clientTag), math.MaxUint16)
+	}
+	payload := &bytes.Buffer{}
+	_ = binary.Write(payload, binary.LittleEndian, uint16(1)) // protocol version
+	_ = binary.Write(payload, binary.LittleEndian, uint16(len(clientTag)))
+	payload.WriteString(clientTag)
+	_ = binary.Write(payload, binary.LittleEndian, uint32(0)) // no JSON metadata
+	return payload.Bytes(), nil
+}
+
 // frame represents a binary protocol frame.
 type frame struct {
 	msgType uint16
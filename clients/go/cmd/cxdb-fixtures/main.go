@@ -39,7 +39,8 @@ func main() {
 		getLastFixture("get_last_default", 1, 10, false),
 		getLastFixture("get_last_payload", 1, 5, true),
 		attachFsFixture("attach_fs", 99, testHash(0xAA)),
-		putBlobFixture("put_blob", []byte("hello blob")),
+		putBlobFixture("put_blob", []byte("hello blob"), 0),
+		putBlobFixture("put_blob_context", []byte("hello blob"), 42),
 		appendWithFsFixture("append_with_fs", 1, 0, "cxdb.ConversationItem", 3, []byte{0x91, 0x04}, "", testHash(0xBB)),
 	}
 
@@ -138,13 +139,18 @@ func attachFsFixture(name string, turnID uint64, fsHash [32]byte) Fixture {
 	return Fixture{Name: name, MsgType: 10, Flags: 0, PayloadHex: hex.EncodeToString(payload)}
 }
 
-func putBlobFixture(name string, data []byte) Fixture {
+func putBlobFixture(name string, data []byte, contextID uint64) Fixture {
 	hash := blake3.Sum256(data)
-	payload := make([]byte, 0, 36+len(data))
+	payload := make([]byte, 0, 44+len(data))
 	payload = append(payload, hash[:]...)
 	payload = appendU32(payload, uint32(len(data)))
 	payload = append(payload, data...)
-	return Fixture{Name: name, MsgType: 11, Flags: 0, PayloadHex: hex.EncodeToString(payload)}
+	var flags uint16
+	if contextID != 0 {
+		payload = appendU64(payload, contextID)
+		flags = 1
+	}
+	return Fixture{Name: name, MsgType: 11, Flags: flags, PayloadHex: hex.EncodeToString(payload)}
 }
 
 func appendU16(buf []byte, val uint16) []byte {
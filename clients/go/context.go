@@ -20,10 +20,7 @@ type ContextHead struct {
 // If baseTurnID is 0, creates an empty context.
 // If baseTurnID is non-zero, creates a context starting from that turn.
 func (c *Client) CreateContext(ctx context.Context, baseTurnID uint64) (*ContextHead, error) {
-	payload := make([]byte, 8)
-	binary.LittleEndian.PutUint64(payload, baseTurnID)
-
-	resp, err := c.sendRequest(ctx, msgCtxCreate, payload)
+	resp, err := c.sendRequest(ctx, msgCtxCreate, encodeU64(baseTurnID))
 	if err != nil {
 		return nil, fmt.Errorf("create context: %w", err)
 	}
@@ -34,10 +31,7 @@ func (c *Client) CreateContext(ctx context.Context, baseTurnID uint64) (*Context
 // ForkContext creates a new context branching from a specific turn.
 // This is an O(1) operation - it creates a new head pointer without copying data.
 func (c *Client) ForkContext(ctx context.Context, baseTurnID uint64) (*ContextHead, error) {
-	payload := make([]byte, 8)
-	binary.LittleEndian.PutUint64(payload, baseTurnID)
-
-	resp, err := c.sendRequest(ctx, msgCtxFork, payload)
+	resp, err := c.sendRequest(ctx, msgCtxFork, encodeU64(baseTurnID))
 	if err != nil {
 		return nil, fmt.Errorf("fork context: %w", err)
 	}
@@ -47,10 +41,7 @@ func (c *Client) ForkContext(ctx context.Context, baseTurnID uint64) (*ContextHe
 
 // GetHead retrieves the current head of a context.
 func (c *Client) GetHead(ctx context.Context, contextID uint64) (*ContextHead, error) {
-	payload := make([]byte, 8)
-	binary.LittleEndian.PutUint64(payload, contextID)
-
-	resp, err := c.sendRequest(ctx, msgGetHead, payload)
+	resp, err := c.sendRequest(ctx, msgGetHead, encodeU64(contextID))
 	if err != nil {
 		return nil, fmt.Errorf("get head: %w", err)
 	}
@@ -58,6 +49,13 @@ func (c *Client) GetHead(ctx context.Context, contextID uint64) (*ContextHead, e
 	return parseContextHead(resp.payload)
 }
 
+// encodeU64 builds the CTX_CREATE, CTX_FORK and GET_HEAD payloads: a single u64.
+func encodeU64(value uint64) []byte {
+	payload := make([]byte, 8)
+	binary.LittleEndian.PutUint64(payload, value)
+	return payload
+}
+
 func parseContextHead(payload []byte) (*ContextHead, error) {
 	if len(payload) < 20 {
 		return nil, fmt.Errorf("%w: context head too short (%d bytes)", ErrInvalidResponse, len(payload))
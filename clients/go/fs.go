@@ -37,11 +37,7 @@ type AttachFsResult struct {
 // AttachFs attaches a filesystem snapshot to an existing turn.
 // The tree objects and file blobs must already exist in the blob store.
 func (c *Client) AttachFs(ctx context.Context, req *AttachFsRequest) (*AttachFsResult, error) {
-	payload := &bytes.Buffer{}
-	_ = binary.Write(payload, binary.LittleEndian, req.TurnID)
-	payload.Write(req.FsRootHash[:])
-
-	resp, err := c.sendRequest(ctx, msgAttachFs, payload.Bytes())
+	resp, err := c.sendRequest(ctx, msgAttachFs, encodeAttachFs(req))
 	if err != nil {
 		return nil, fmt.Errorf("attach fs: %w", err)
 	}
@@ -62,6 +58,11 @@ func (c *Client) AttachFs(ctx context.Context, req *AttachFsRequest) (*AttachFsR
 type PutBlobRequest struct {
 	// Data is the raw blob content.
 	Data []byte
+
+	// ContextID is the context the blob belongs to (0 for none). Blobs of an
+	// encrypted context must name it so the server seals them with the
+	// context's key; without it they are stored in plaintext.
+	ContextID uint64
 }
 
 // PutBlobResult contains the result of a put blob operation.
@@ -76,15 +77,8 @@ type PutBlobResult struct {
 // PutBlob stores a blob in the content-addressed store.
 // The hash is computed from the data and verified by the server.
 func (c *Client) PutBlob(ctx context.Context, req *PutBlobRequest) (*PutBlobResult, error) {
-	// Compute hash
-	hash := blake3.Sum256(req.Data)
-
-	payload := &bytes.Buffer{}
-	payload.Write(hash[:])
-	_ = binary.Write(payload, binary.LittleEndian, uint32(len(req.Data)))
-	payload.Write(req.Data)
-
-	resp, err := c.sendRequest(ctx, msgPutBlob, payload.Bytes())
+	flags, payload := encodePutBlob(req)
+	resp, err := c.sendRequestWithFlags(ctx, msgPutBlob, flags, payload)
 	if err != nil {
 		return nil, fmt.Errorf("put blob: %w", err)
 	}
@@ -101,8 +95,34 @@ func (c *Client) PutBlob(ctx context.Context, req *PutBlobRequest) (*PutBlobResu
 	return result, nil
 }
 
+// encodeAttachFs builds the ATTACH_FS payload: turn_id (u64) + fs_root_hash (32 bytes).
+func encodeAttachFs(req *AttachFsRequest) []byte {
+	payload := &bytes.Buffer{}
+	_ = binary.Write(payload, binary.LittleEndian, req.TurnID)
+	payload.Write(req.FsRootHash[:])
+	return payload.Bytes()
+}
+
+// encodePutBlob builds the PUT_BLOB payload: hash (32 bytes) + data_len (u32) + data,
+// followed by context_id (u64) with flag bit 0 when the request names a context.
+func encodePutBlob(req *PutBlobRequest) (uint16, []byte) {
+	hash := blake3.Sum256(req.Data)
+
+	payload := &bytes.Buffer{}
+	payload.Write(hash[:])
+	_ = binary.Write(payload, binary.LittleEndian, uint32(len(req.Data)))
+	payload.Write(req.Data)
+	if req.ContextID == 0 {
+		return 0, payload.Bytes()
+	}
+	_ = binary.Write(payload, binary.LittleEndian, req.ContextID)
+	return 1, payload.Bytes()
+}
+
 // PutBlobIfAbsent stores a blob only if it doesn't already exist.
-// Returns the hash and whether the blob was stored.
+// Returns the hash and whether the blob was stored. The blob is stored
+// without a context, i.e. in plaintext; use PutBlob with ContextID for
+// blobs of an encrypted context.
 func (c *Client) PutBlobIfAbsent(ctx context.Context, data []byte) ([32]byte, bool, error) {
 	result, err := c.PutBlob(ctx, &PutBlobRequest{Data: data})
 	if err != nil {
@@ -114,44 +134,8 @@ func (c *Client) PutBlobIfAbsent(ctx context.Context, data []byte) ([32]byte, bo
 // AppendTurnWithFs appends a new turn with an optional filesystem snapshot.
 // If fsRootHash is non-nil, the filesystem snapshot will be attached to the turn.
 func (c *Client) AppendTurnWithFs(ctx context.Context, req *AppendRequest, fsRootHash *[32]byte) (*AppendResult, error) {
-	encoding := req.Encoding
-	if encoding == 0 {
-		encoding = EncodingMsgpack
-	}
-	compression := req.Compression
-
-	// Compute BLAKE3 hash of payload
-	hash := blake3.Sum256(req.Payload)
-
-	payload := &bytes.Buffer{}
-	_ = binary.Write(payload, binary.LittleEndian, req.ContextID)
-	_ = binary.Write(payload, binary.LittleEndian, req.ParentTurnID)
-
-	_ = binary.Write(payload, binary.LittleEndian, uint32(len(req.TypeID)))
-	payload.WriteString(req.TypeID)
-	_ = binary.Write(payload, binary.LittleEndian, req.TypeVersion)
-
-	_ = binary.Write(payload, binary.LittleEndian, encoding)
-	_ = binary.Write(payload, binary.LittleEndian, compression)
-	_ = binary.Write(payload, binary.LittleEndian, uint32(len(req.Payload))) // uncompressed len
-	payload.Write(hash[:])
-
-	_ = binary.Write(payload, binary.LittleEndian, uint32(len(req.Payload)))
-	payload.Write(req.Payload)
-
-	_ = binary.Write(payload, binary.LittleEndian, uint32(len(req.IdempotencyKey)))
-	if len(req.IdempotencyKey) > 0 {
-		payload.WriteString(req.IdempotencyKey)
-	}
-
-	// If fsRootHash is provided, append it and set flags
-	var flags uint16
-	if fsRootHash != nil {
-		flags = 1 // bit 0 = has_fs_root
-		payload.Write(fsRootHash[:])
-	}
-
-	resp, err := c.sendRequestWithFlags(ctx, msgAppend, flags, payload.Bytes())
+	flags, payload := encodeAppendTurn(req, fsRootHash)
+	resp, err := c.sendRequestWithFlags(ctx, msgAppend, flags, payload)
 	if err != nil {
 		return nil, fmt.Errorf("append turn: %w", err)
 	}
@@ -75,7 +75,7 @@ func TestE2E_FilesystemSnapshots(t *testing.T) {
 			snap.Stats.FileCount, snap.Stats.DirCount, snap.RootHash[:8])
 
 		// Upload snapshot
-		result, err := snap.Upload(ctx, client)
+		result, err := snap.Upload(ctx, client, ctxHead.ContextID)
 		if err != nil {
 			t.Fatalf("Upload failed: %v", err)
 		}
@@ -149,7 +149,7 @@ func TestE2E_FilesystemSnapshots(t *testing.T) {
 			snap.Stats.FileCount, snap.Stats.DirCount, snap.RootHash[:8])
 
 		// Upload snapshot
-		uploadResult, err := snap.Upload(ctx, client)
+		uploadResult, err := snap.Upload(ctx, client, ctxHead.ContextID)
 		if err != nil {
 			t.Fatalf("Upload failed: %v", err)
 		}
@@ -197,7 +197,7 @@ func TestE2E_FilesystemSnapshots(t *testing.T) {
 			t.Fatalf("Capture failed: %v", err)
 		}
 
-		result, err := snap.Upload(ctx, client)
+		result, err := snap.Upload(ctx, client, ctxHead.ContextID)
 		if err != nil {
 			t.Fatalf("Upload failed: %v", err)
 		}
@@ -240,7 +240,7 @@ func TestE2E_FilesystemSnapshots(t *testing.T) {
 			t.Error("Expected at least one symlink")
 		}
 
-		_, err = snap.Upload(ctx, client)
+		_, err = snap.Upload(ctx, client, ctxHead.ContextID)
 		if err != nil {
 			t.Fatalf("Upload failed: %v", err)
 		}
@@ -267,7 +267,7 @@ func TestE2E_FilesystemSnapshots(t *testing.T) {
 		// Add a new file
 		os.WriteFile(filepath.Join(workDir, "late-addition.txt"), []byte("added later"), 0644)
 
-		snap, _, err := CaptureAndUpload(ctx, client, workDir, WithExclude(".git", "*.tmp"))
+		snap, _, err := CaptureAndUpload(ctx, client, workDir, ctxHead.ContextID, WithExclude(".git", "*.tmp"))
 		if err != nil {
 			t.Fatalf("CaptureAndUpload failed: %v", err)
 		}
@@ -314,7 +314,7 @@ func TestE2E_FilesystemSnapshots(t *testing.T) {
 			t.Fatalf("Capture failed: %v", err)
 		}
 
-		result, err := snap.Upload(ctx, client)
+		result, err := snap.Upload(ctx, client, ctxHead.ContextID)
 		if err != nil {
 			t.Fatalf("Upload failed: %v", err)
 		}
@@ -671,7 +671,7 @@ func TestE2E_FsRootInheritance(t *testing.T) {
 	if err != nil {
 		t.Fatalf("Capture failed: %v", err)
 	}
-	snap.Upload(ctx, client)
+	snap.Upload(ctx, client, ctxHead.ContextID)
 
 	turn1, err := client.AppendTurnWithFs(ctx, &cxdb.AppendRequest{
 		ContextID:   ctxHead.ContextID,
@@ -33,16 +33,17 @@ type UploadResult struct {
 	BytesUploaded int64
 }
 
-// Upload uploads all tree objects and file blobs from a snapshot to the server.
+// Upload uploads all tree objects and file blobs from a snapshot to the server
+// as blobs of contextID, so an encrypted context's snapshot is sealed with its key.
 // Returns the root hash which can be used to attach the snapshot to a turn.
-func (s *Snapshot) Upload(ctx context.Context, client *cxdb.Client) (*UploadResult, error) {
+func (s *Snapshot) Upload(ctx context.Context, client *cxdb.Client, contextID uint64) (*UploadResult, error) {
 	result := &UploadResult{
 		RootHash: s.RootHash,
 	}
 
 	// Upload all tree objects first (they're already serialized)
 	for hash, data := range s.Trees {
-		wasNew, err := uploadBlob(ctx, client, hash, data)
+		wasNew, err := uploadBlob(ctx, client, contextID, hash, data)
 		if err != nil {
 			return nil, fmt.Errorf("upload tree %x: %w", hash[:8], err)
 		}
@@ -62,7 +63,7 @@ func (s *Snapshot) Upload(ctx context.Context, client *cxdb.Client) (*UploadResu
 			return nil, fmt.Errorf("read file %s: %w", ref.Path, err)
 		}
 
-		wasNew, err := uploadBlob(ctx, client, hash, content)
+		wasNew, err := uploadBlob(ctx, client, contextID, hash, content)
 		if err != nil {
 			return nil, fmt.Errorf("upload file %s: %w", ref.Path, err)
 		}
@@ -76,7 +77,7 @@ func (s *Snapshot) Upload(ctx context.Context, client *cxdb.Client) (*UploadResu
 
 	// Upload all symlink targets
 	for hash, target := range s.Symlinks {
-		wasNew, err := uploadBlob(ctx, client, hash, []byte(target))
+		wasNew, err := uploadBlob(ctx, client, contextID, hash, []byte(target))
 		if err != nil {
 			return nil, fmt.Errorf("upload symlink target %s: %w", target, err)
 		}
@@ -91,10 +92,13 @@ func (s *Snapshot) Upload(ctx context.Context, client *cxdb.Client) (*UploadResu
 	return result, nil
 }
 
-// uploadBlob uploads a single blob to the server.
-func uploadBlob(ctx context.Context, client *cxdb.Client, hash [32]byte, data []byte) (bool, error) {
-	_, wasNew, err := client.PutBlobIfAbsent(ctx, data)
-	return wasNew, err
+// uploadBlob uploads a single blob of contextID to the server.
+func uploadBlob(ctx context.Context, client *cxdb.Client, contextID uint64, hash [32]byte, data []byte) (bool, error) {
+	result, err := client.PutBlob(ctx, &cxdb.PutBlobRequest{Data: data, ContextID: contextID})
+	if err != nil {
+		return false, err
+	}
+	return result.WasNew, nil
 }
 
 // readFile reads the entire contents of a file.
@@ -107,9 +111,9 @@ func readFile(path string) ([]byte, error) {
 	return io.ReadAll(f)
 }
 
-// UploadAndAttach captures a filesystem snapshot, uploads it, and attaches it to a turn.
-// This is a convenience function that combines Capture, Upload, and AttachFs.
-func UploadAndAttach(ctx context.Context, client *cxdb.Client, root string, turnID uint64, opts ...Option) (*UploadResult, error) {
+// UploadAndAttach captures a filesystem snapshot, uploads it, and attaches it to a turn
+// of contextID. This is a convenience function that combines Capture, Upload, and AttachFs.
+func UploadAndAttach(ctx context.Context, client *cxdb.Client, root string, contextID, turnID uint64, opts ...Option) (*UploadResult, error) {
 	// Capture snapshot
 	snap, err := Capture(root, opts...)
 	if err != nil {
@@ -117,7 +121,7 @@ func UploadAndAttach(ctx context.Context, client *cxdb.Client, root string, turn
 	}
 
 	// Upload all blobs
-	result, err := snap.Upload(ctx, client)
+	result, err := snap.Upload(ctx, client, contextID)
 	if err != nil {
 		return nil, fmt.Errorf("upload: %w", err)
 	}
@@ -134,9 +138,10 @@ func UploadAndAttach(ctx context.Context, client *cxdb.Client, root string, turn
 	return result, nil
 }
 
-// CaptureAndUpload captures a filesystem snapshot and uploads it to the server.
-// Returns the snapshot and upload result. The snapshot can be attached to a turn later.
-func CaptureAndUpload(ctx context.Context, client *cxdb.Client, root string, opts ...Option) (*Snapshot, *UploadResult, error) {
+// CaptureAndUpload captures a filesystem snapshot and uploads it to the server as blobs
+// of contextID. Returns the snapshot and upload result. The snapshot can be attached to
+// a turn of that context later.
+func CaptureAndUpload(ctx context.Context, client *cxdb.Client, root string, contextID uint64, opts ...Option) (*Snapshot, *UploadResult, error) {
 	// Capture snapshot
 	snap, err := Capture(root, opts...)
 	if err != nil {
@@ -144,7 +149,7 @@ func CaptureAndUpload(ctx context.Context, client *cxdb.Client, root string, opt
 	}
 
 	// Upload all blobs
-	result, err := snap.Upload(ctx, client)
+	result, err := snap.Upload(ctx, client, contextID)
 	if err != nil {
 		return nil, nil, fmt.Errorf("upload: %w", err)
 	}
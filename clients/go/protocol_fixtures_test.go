@@ -0,0 +1,123 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+package cxdb
+
+import (
+	"bytes"
+	"encoding/hex"
+	"encoding/json"
+	"os"
+	"path/filepath"
+	"strings"
+	"testing"
+)
+
+// The shared request fixtures live with the Rust client. The server checks
+// every one of them against its wire schema (server/tests/protocol_compat.rs),
+// so matching them here ties the Go encoders to that schema.
+const fixturesDir = "../rust/tests/fixtures"
+
+type frameFixture struct {
+	Name       string `json:"name"`
+	MsgType    uint16 `json:"msg_type"`
+	Flags      uint16 `json:"flags"`
+	PayloadHex string `json:"payload_hex"`
+}
+
+func loadFrameFixture(t *testing.T, name string) frameFixture {
+	t.Helper()
+	data, err := os.ReadFile(filepath.Join(fixturesDir, name+".json"))
+	if err != nil {
+		t.Fatalf("read fixture %s: %v", name, err)
+	}
+	var fixture frameFixture
+	if err := json.Unmarshal(data, &fixture); err != nil {
+		t.Fatalf("parse fixture %s: %v", name, err)
+	}
+	return fixture
+}
+
+func checkFixture(t *testing.T, name string, msgType, flags uint16, payload []byte) {
+	t.Helper()
+	fixture := loadFrameFixture(t, name)
+	if fixture.MsgType != msgType || fixture.Flags != flags {
+		t.Errorf("%s: msg_type/flags = %d/%d, fixture has %d/%d", name, msgType, flags, fixture.MsgType, fixture.Flags)
+	}
+	want, err := hex.DecodeString(fixture.PayloadHex)
+	if err != nil {
+		t.Fatalf("%s: payload_hex: %v", name, err)
+	}
+	if !bytes.Equal(payload, want) {
+		t.Errorf("%s: payload\n got %x\nwant %x", name, payload, want)
+	}
+}
+
+func fillHash(seed byte) [32]byte {
+	var hash [32]byte
+	for i := range hash {
+		hash[i] = seed
+	}
+	return hash
+}
+
+func TestEncodersMatchFixtures(t *testing.T) {
+	for name, tag := range map[string]string{"hello_empty": "", "hello_tag": "test-client"} {
+		payload, err := encodeHello(tag)
+		if err != nil {
+			t.Fatal(err)
+		}
+		checkFixture(t, name, msgHello, 0, payload)
+	}
+
+	checkFixture(t, "ctx_create_base0", msgCtxCreate, 0, encodeU64(0))
+	checkFixture(t, "ctx_fork_base123", msgCtxFork, 0, encodeU64(123))
+	checkFixture(t, "get_head_ctx42", msgGetHead, 0, encodeU64(42))
+
+	appends := []struct {
+		name   string
+		parent uint64
+		body   byte
+		idem   string
+		withFs bool
+	}{
+		{"append_parent0", 0, 0x01, "", false},
+		{"append_parent7", 7, 0x02, "", false},
+		{"append_idempotent", 0, 0x03, "idem-1", false},
+		{"append_with_fs", 0, 0x04, "", true},
+	}
+	for _, tc := range appends {
+		var fsRoot *[32]byte
+		if tc.withFs {
+			hash := fillHash(0xBB)
+			fsRoot = &hash
+		}
+		req := &AppendRequest{
+			ContextID:      1,
+			ParentTurnID:   tc.parent,
+			TypeID:         "cxdb.ConversationItem",
+			TypeVersion:    3,
+			Payload:        []byte{0x91, tc.body},
+			IdempotencyKey: tc.idem,
+		}
+		flags, payload := encodeAppendTurn(req, fsRoot)
+		checkFixture(t, tc.name, msgAppend, flags, payload)
+	}
+
+	checkFixture(t, "get_last_default", msgGetLast, 0, encodeGetLast(1, GetLastOptions{}))
+	checkFixture(t, "get_last_payload", msgGetLast, 0, encodeGetLast(1, GetLastOptions{Limit: 5, IncludePayload: true}))
+	checkFixture(t, "attach_fs", msgAttachFs, 0, encodeAttachFs(&AttachFsRequest{TurnID: 99, FsRootHash: fillHash(0xAA)}))
+	flags, payload := encodePutBlob(&PutBlobRequest{Data: []byte("hello blob")})
+	checkFixture(t, "put_blob", msgPutBlob, flags, payload)
+	flags, payload = encodePutBlob(&PutBlobRequest{Data: []byte("hello blob"), ContextID: 42})
+	checkFixture(t, "put_blob_context", msgPutBlob, flags, payload)
+}
+
+func TestOversizedClientTagIsRefused(t *testing.T) {
+	if _, err := encodeHello(strings.Repeat("t", 0xFFFF)); err != nil {
+		t.Fatalf("65535-byte tag: %v", err)
+	}
+	if _, err := encodeHello(strings.Repeat("t", 0x10000)); err == nil {
+		t.Fatal("65536-byte tag was accepted")
+	}
+}
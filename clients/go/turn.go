@@ -63,6 +63,48 @@ type AppendResult struct {
 
 // AppendTurn appends a new turn to a context.
 func (c *Client) AppendTurn(ctx context.Context, req *AppendRequest) (*AppendResult, error) {
+	flags, payload := encodeAppendTurn(req, nil)
+	resp, err := c.sendRequestWithFlags(ctx, msgAppend, flags, payload)
+	if err != nil {
+		return nil, fmt.Errorf("append turn: %w", err)
+	}
+
+	if len(resp.payload) < 52 {
+		return nil, fmt.Errorf("%w: append response too short (%d bytes)", ErrInvalidResponse, len(resp.payload))
+	}
+
+	result := &AppendResult{
+		ContextID: binary.LittleEndian.Uint64(resp.payload[0:8]),
+		TurnID:    binary.LittleEndian.Uint64(resp.payload[8:16]),
+		Depth:     binary.LittleEndian.Uint32(resp.payload[16:20]),
+	}
+	copy(result.PayloadHash[:], resp.payload[20:52])
+
+	return result, nil
+}
+
+// GetLastOptions configures GetLast behavior.
+type GetLastOptions struct {
+	// Limit is the maximum number of turns to return.
+	Limit uint32
+
+	// IncludePayload controls whether to include turn payloads.
+	IncludePayload bool
+}
+
+// GetLast retrieves the last N turns from a context, walking back from the head.
+func (c *Client) GetLast(ctx context.Context, contextID uint64, opts GetLastOptions) ([]TurnRecord, error) {
+	resp, err := c.sendRequest(ctx, msgGetLast, encodeGetLast(contextID, opts))
+	if err != nil {
+		return nil, fmt.Errorf("get last: %w", err)
+	}
+
+	return parseTurnRecords(resp.payload)
+}
+
+// encodeAppendTurn builds the APPEND_TURN payload and frame flags. A
+// non-nil fsRootHash trails the payload and sets flag bit 0.
+func encodeAppendTurn(req *AppendRequest, fsRootHash *[32]byte) (uint16, []byte) {
 	encoding := req.Encoding
 	if encoding == 0 {
 		encoding = EncodingMsgpack
@@ -93,36 +135,16 @@ func (c *Client) AppendTurn(ctx context.Context, req *AppendRequest) (*AppendRes
 		payload.WriteString(req.IdempotencyKey)
 	}
 
-	resp, err := c.sendRequest(ctx, msgAppend, payload.Bytes())
-	if err != nil {
-		return nil, fmt.Errorf("append turn: %w", err)
+	var flags uint16
+	if fsRootHash != nil {
+		flags = 1 // bit 0 = has_fs_root
+		payload.Write(fsRootHash[:])
 	}
-
-	if len(resp.payload) < 52 {
-		return nil, fmt.Errorf("%w: append response too short (%d bytes)", ErrInvalidResponse, len(resp.payload))
-	}
-
-	result := &AppendResult{
-		ContextID: binary.LittleEndian.Uint64(resp.payload[0:8]),
-		TurnID:    binary.LittleEndian.Uint64(resp.payload[8:16]),
-		Depth:     binary.LittleEndian.Uint32(resp.payload[16:20]),
-	}
-	copy(result.PayloadHash[:], resp.payload[20:52])
-
-	return result, nil
+	return flags, payload.Bytes()
 }
 
-// GetLastOptions configures GetLast behavior.
-type GetLastOptions struct {
-	// Limit is the maximum number of turns to return.
-	Limit uint32
-
-	// IncludePayload controls whether to include turn payloads.
-	IncludePayload bool
-}
-
-// GetLast retrieves the last N turns from a context, walking back from the head.
-func (c *Client) GetLast(ctx context.Context, contextID uint64, opts GetLastOptions) ([]TurnRecord, error) {
+// encodeGetLast builds the GET_LAST payload.
+func encodeGetLast(contextID uint64, opts GetLastOptions) []byte {
 	limit := opts.Limit
 	if limit == 0 {
 		limit = 10
@@ -136,13 +158,7 @@ func (c *Client) GetLast(ctx context.Context, contextID uint64, opts GetLastOpti
 		includePayload = 1
 	}
 	_ = binary.Write(payload, binary.LittleEndian, includePayload)
-
-	resp, err := c.sendRequest(ctx, msgGetLast, payload.Bytes())
-	if err != nil {
-		return nil, fmt.Errorf("get last: %w", err)
-	}
-
-	return parseTurnRecords(resp.payload)
+	return payload.Bytes()
 }
 
 func parseTurnRecords(data []byte) ([]TurnRecord, error) {
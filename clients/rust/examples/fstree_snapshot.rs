@@ -14,7 +14,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let head = client.create_context(&ctx, 0)?;
 
     let snapshot = fstree::capture(&root, vec![fstree::with_exclude(vec![".git", "target"])])?;
-    let upload = snapshot.upload(&ctx, &client)?;
+    let upload = snapshot.upload(&ctx, &client, head.context_id)?;
 
     let payload = encode_msgpack(&new_user_input("Captured snapshot", Vec::new()))?;
     let append = client.append_turn_with_fs(
@@ -10,10 +10,10 @@ use byteorder::{LittleEndian, WriteBytesExt};
 use rustls::pki_types::ServerName;
 use rustls::{ClientConfig, ClientConnection};
 
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ServerError};
 use crate::protocol::{
-    read_frame, write_frame, Frame, DEFAULT_DIAL_TIMEOUT, DEFAULT_REQUEST_TIMEOUT, MSG_ERROR,
-    MSG_HELLO,
+    read_frame, write_frame, write_str16, Frame, DEFAULT_DIAL_TIMEOUT, DEFAULT_REQUEST_TIMEOUT,
+    MSG_ERROR, MSG_HELLO,
 };
 
 pub type ClientOption = Arc<dyn Fn(&mut ClientOptions) + Send + Sync>;
@@ -200,12 +200,7 @@ impl Client {
     }
 
     fn send_hello(&self, client_tag: &str) -> Result<()> {
-        let mut payload = Vec::with_capacity(2 + 2 + client_tag.len() + 4);
-        payload.write_u16::<LittleEndian>(1)?; // protocol version
-        payload.write_u16::<LittleEndian>(client_tag.len() as u16)?;
-        payload.extend_from_slice(client_tag.as_bytes());
-        payload.write_u32::<LittleEndian>(0)?; // no metadata
-
+        let payload = encode_hello(client_tag)?;
         let ctx = RequestContext::with_timeout(self.timeout);
         let frame = self.send_request_with_flags(&ctx, MSG_HELLO, 0, &payload)?;
 
@@ -227,6 +222,14 @@ impl Client {
     }
 }
 
+pub(crate) fn encode_hello(client_tag: &str) -> Result<Vec<u8>> {
+    let mut payload = Vec::with_capacity(2 + 2 + client_tag.len() + 4);
+    payload.write_u16::<LittleEndian>(1)?; // protocol version
+    write_str16(&mut payload, "client tag", client_tag)?;
+    payload.write_u32::<LittleEndian>(0)?; // no metadata
+    Ok(payload)
+}
+
 pub fn dial(addr: &str, opts: impl IntoIterator<Item = ClientOption>) -> Result<Client> {
     let mut options = ClientOptions::default();
     for opt in opts {
@@ -352,7 +355,20 @@ fn parse_server_error(payload: &[u8]) -> Error {
     } else {
         String::new()
     };
-    Error::server(code, detail)
+    let mut err = ServerError {
+        code,
+        detail,
+        ..Default::default()
+    };
+    // error_code u16, retryable u8, retry_after_ms u32; absent from older servers.
+    if let Some(ext) = payload.get(8 + detail_len..8 + detail_len + 7) {
+        err.error_code = u16::from_le_bytes([ext[0], ext[1]]);
+        err.retryable = ext[2] != 0;
+        let retry_after_ms = u32::from_le_bytes([ext[3], ext[4], ext[5], ext[6]]);
+        err.retry_after =
+            (retry_after_ms > 0).then(|| Duration::from_millis(retry_after_ms as u64));
+    }
+    Error::Server(err)
 }
 
 pub(crate) enum Connection {
@@ -610,13 +626,35 @@ mod tests {
         handle.join().unwrap();
     }
 
-    fn hello_payload(tag: &str) -> Vec<u8> {
+    #[test]
+    fn error_payload_carries_retry_classification() {
         let mut payload = Vec::new();
-        payload.write_u16::<LittleEndian>(1).unwrap();
-        payload.write_u16::<LittleEndian>(tag.len() as u16).unwrap();
-        payload.extend_from_slice(tag.as_bytes());
-        payload.write_u32::<LittleEndian>(0).unwrap();
-        payload
+        payload.write_u32::<LittleEndian>(507).unwrap();
+        payload.write_u32::<LittleEndian>(4).unwrap();
+        payload.extend_from_slice(b"full");
+        payload.write_u16::<LittleEndian>(10).unwrap();
+        payload.push(1);
+        payload.write_u32::<LittleEndian>(30_000).unwrap();
+
+        match parse_server_error(&payload) {
+            Error::Server(server) => {
+                assert_eq!((server.code, server.error_code), (507, 10));
+                assert!(server.is_retryable());
+                assert_eq!(server.retry_after, Some(Duration::from_secs(30)));
+            }
+            other => panic!("expected server error, got {other:?}"),
+        }
+    }
+
+    fn hello_payload(tag: &str) -> Vec<u8> {
+        encode_hello(tag).unwrap()
+    }
+
+    #[test]
+    fn oversized_client_tag_is_refused() {
+        assert!(encode_hello(&"t".repeat(u16::MAX as usize)).is_ok());
+        let err = encode_hello(&"t".repeat(u16::MAX as usize + 1)).unwrap_err();
+        assert!(err.to_string().contains("client tag"), "{err}");
     }
 
     fn generate_cert() -> (
@@ -5,7 +5,7 @@ use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::client::{Client, RequestContext};
 use crate::error::{Error, Result};
-use crate::protocol::{MSG_CTX_CREATE, MSG_CTX_FORK, MSG_GET_HEAD};
+use crate::protocol::{write_str16, MSG_CTX_CREATE, MSG_CTX_FORK, MSG_GET_HEAD};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ContextHead {
@@ -16,27 +16,53 @@ pub struct ContextHead {
 
 impl Client {
     pub fn create_context(&self, ctx: &RequestContext, base_turn_id: u64) -> Result<ContextHead> {
-        let mut payload = Vec::with_capacity(8);
-        payload.write_u64::<LittleEndian>(base_turn_id)?;
+        let payload = encode_u64(base_turn_id);
         let frame = self.send_request(ctx, MSG_CTX_CREATE, &payload)?;
         parse_context_head(&frame.payload)
     }
 
+    /// Create a context with a caller-assigned external id, such as a ULID
+    /// or UUID. The server rejects an id that already names a context.
+    pub fn create_context_with_external_id(
+        &self,
+        ctx: &RequestContext,
+        base_turn_id: u64,
+        external_id: &str,
+    ) -> Result<ContextHead> {
+        let payload = encode_ctx_create_with_external_id(base_turn_id, external_id)?;
+        let frame = self.send_request_with_flags(ctx, MSG_CTX_CREATE, 1, &payload)?;
+        parse_context_head(&frame.payload)
+    }
+
     pub fn fork_context(&self, ctx: &RequestContext, base_turn_id: u64) -> Result<ContextHead> {
-        let mut payload = Vec::with_capacity(8);
-        payload.write_u64::<LittleEndian>(base_turn_id)?;
+        let payload = encode_u64(base_turn_id);
         let frame = self.send_request(ctx, MSG_CTX_FORK, &payload)?;
         parse_context_head(&frame.payload)
     }
 
     pub fn get_head(&self, ctx: &RequestContext, context_id: u64) -> Result<ContextHead> {
-        let mut payload = Vec::with_capacity(8);
-        payload.write_u64::<LittleEndian>(context_id)?;
+        let payload = encode_u64(context_id);
         let frame = self.send_request(ctx, MSG_GET_HEAD, &payload)?;
         parse_context_head(&frame.payload)
     }
 }
 
+/// CTX_CREATE, CTX_FORK and GET_HEAD payloads: a single u64.
+pub(crate) fn encode_u64(value: u64) -> Vec<u8> {
+    value.to_le_bytes().to_vec()
+}
+
+/// CTX_CREATE payload sent with flag bit 0.
+pub(crate) fn encode_ctx_create_with_external_id(
+    base_turn_id: u64,
+    external_id: &str,
+) -> Result<Vec<u8>> {
+    let mut payload = Vec::with_capacity(10 + external_id.len());
+    payload.write_u64::<LittleEndian>(base_turn_id)?;
+    write_str16(&mut payload, "external id", external_id)?;
+    Ok(payload)
+}
+
 fn parse_context_head(payload: &[u8]) -> Result<ContextHead> {
     if payload.len() < 20 {
         return Err(Error::invalid_response(format!(
@@ -57,24 +83,18 @@ mod tests {
     use super::*;
     use crate::test_util::{decode_hex, load_fixture};
 
-    fn payload_u64(value: u64) -> Vec<u8> {
-        let mut payload = Vec::with_capacity(8);
-        payload.write_u64::<LittleEndian>(value).unwrap();
-        payload
-    }
-
     #[test]
     fn context_payloads_match_fixtures() {
         let fixture = load_fixture("ctx_create_base0");
         assert_eq!(fixture.msg_type, MSG_CTX_CREATE);
-        assert_eq!(decode_hex(&fixture.payload_hex), payload_u64(0));
+        assert_eq!(decode_hex(&fixture.payload_hex), encode_u64(0));
 
         let fixture = load_fixture("ctx_fork_base123");
         assert_eq!(fixture.msg_type, MSG_CTX_FORK);
-        assert_eq!(decode_hex(&fixture.payload_hex), payload_u64(123));
+        assert_eq!(decode_hex(&fixture.payload_hex), encode_u64(123));
 
         let fixture = load_fixture("get_head_ctx42");
         assert_eq!(fixture.msg_type, MSG_GET_HEAD);
-        assert_eq!(decode_hex(&fixture.payload_hex), payload_u64(42));
+        assert_eq!(decode_hex(&fixture.payload_hex), encode_u64(42));
     }
 }
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::fmt;
+use std::time::Duration;
 
 /// CXDB client error type.
 #[derive(Debug)]
@@ -18,10 +19,25 @@ pub enum Error {
     QueueFull,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct ServerError {
+    /// HTTP-style status code.
     pub code: u32,
     pub detail: String,
+    /// Stable code from the server's error code registry; 0 from servers
+    /// that predate it.
+    pub error_code: u16,
+    /// Whether the same request may succeed when retried.
+    pub retryable: bool,
+    /// Wait the server suggests before retrying.
+    pub retry_after: Option<Duration>,
+}
+
+impl ServerError {
+    /// Whether the same request may succeed when retried.
+    pub fn is_retryable(&self) -> bool {
+        self.retryable
+    }
 }
 
 impl fmt::Display for ServerError {
@@ -90,6 +106,7 @@ impl Error {
         Error::Server(ServerError {
             code,
             detail: detail.into(),
+            ..Default::default()
         })
     }
 }
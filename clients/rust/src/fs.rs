@@ -6,13 +6,29 @@ use std::io::Read;
 
 use crate::client::{Client, RequestContext};
 use crate::error::{Error, Result};
-use crate::protocol::{ENCODING_MSGPACK, MSG_APPEND_TURN, MSG_ATTACH_FS, MSG_PUT_BLOB};
-use crate::turn::{AppendRequest, AppendResult};
+use crate::protocol::{MSG_APPEND_TURN, MSG_ATTACH_FS, MSG_ATTACH_FS_OVERLAY, MSG_PUT_BLOB};
+use crate::turn::{encode_append_turn, parse_append_result, AppendRequest, AppendResult};
 
 #[derive(Debug, Clone)]
 pub struct AttachFsRequest {
     pub turn_id: u64,
     pub fs_root_hash: [u8; 32],
+    /// Working-directory metadata recorded with the snapshot.
+    pub metadata: Option<SnapshotMetadata>,
+}
+
+/// Where and when a snapshot was captured, shown alongside it by the server.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotMetadata {
+    /// Unix milliseconds; 0 if unknown.
+    pub captured_at_unix_ms: u64,
+    pub base_path: String,
+    pub git_commit: String,
+    pub git_branch: String,
+    /// Uncommitted changes in the working tree; ignored without `git_commit`.
+    pub git_dirty: bool,
+    /// Files, directories and symlinks in the snapshot.
+    pub total_entries: u64,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -21,9 +37,58 @@ pub struct AttachFsResult {
     pub fs_root_hash: [u8; 32],
 }
 
+/// Entry kind that removes a path in an overlay change.
+pub const OVERLAY_REMOVE: u8 = 255;
+
+/// One path change for [`Client::attach_fs_overlay`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlayChange {
+    /// Slash-separated path from the snapshot root.
+    pub path: String,
+    /// 0=file, 1=directory, 2=symlink, or [`OVERLAY_REMOVE`].
+    pub kind: u8,
+    pub mode: u32,
+    pub size: u64,
+    pub hash: [u8; 32],
+}
+
+impl OverlayChange {
+    pub fn remove(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            kind: OVERLAY_REMOVE,
+            mode: 0,
+            size: 0,
+            hash: [0; 32],
+        }
+    }
+}
+
+/// Attach a snapshot built server-side from a base root plus changes.
+#[derive(Debug, Clone, Default)]
+pub struct AttachFsOverlayRequest {
+    pub turn_id: u64,
+    /// None applies the changes to the snapshot the turn currently sees.
+    pub base_root_hash: Option<[u8; 32]>,
+    /// New file contents referenced by `changes`.
+    pub blobs: Vec<Vec<u8>>,
+    pub changes: Vec<OverlayChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachFsOverlayResult {
+    pub turn_id: u64,
+    pub fs_root_hash: [u8; 32],
+    pub trees_written: u32,
+}
+
 #[derive(Debug, Clone)]
 pub struct PutBlobRequest {
     pub data: Vec<u8>,
+    /// Context the blob belongs to. Blobs of an encrypted context must name
+    /// it so the server seals them with the context's key; without it they
+    /// are stored in plaintext.
+    pub context_id: Option<u64>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -34,11 +99,8 @@ pub struct PutBlobResult {
 
 impl Client {
     pub fn attach_fs(&self, ctx: &RequestContext, req: &AttachFsRequest) -> Result<AttachFsResult> {
-        let mut payload = Vec::with_capacity(40);
-        payload.write_u64::<LittleEndian>(req.turn_id)?;
-        payload.extend_from_slice(&req.fs_root_hash);
-
-        let frame = self.send_request(ctx, MSG_ATTACH_FS, &payload)?;
+        let (flags, payload) = encode_attach_fs(req)?;
+        let frame = self.send_request_with_flags(ctx, MSG_ATTACH_FS, flags, &payload)?;
         if frame.payload.len() < 40 {
             return Err(Error::invalid_response(format!(
                 "attach fs response too short ({} bytes)",
@@ -57,14 +119,36 @@ impl Client {
         })
     }
 
-    pub fn put_blob(&self, ctx: &RequestContext, req: &PutBlobRequest) -> Result<PutBlobResult> {
-        let hash = blake3::hash(&req.data);
-        let mut payload = Vec::with_capacity(36 + req.data.len());
-        payload.extend_from_slice(hash.as_bytes());
-        payload.write_u32::<LittleEndian>(req.data.len() as u32)?;
-        payload.extend_from_slice(&req.data);
+    pub fn attach_fs_overlay(
+        &self,
+        ctx: &RequestContext,
+        req: &AttachFsOverlayRequest,
+    ) -> Result<AttachFsOverlayResult> {
+        let payload = encode_attach_fs_overlay(req)?;
+        let frame = self.send_request(ctx, MSG_ATTACH_FS_OVERLAY, &payload)?;
+        if frame.payload.len() < 44 {
+            return Err(Error::invalid_response(format!(
+                "attach fs overlay response too short ({} bytes)",
+                frame.payload.len()
+            )));
+        }
+
+        let mut cursor = std::io::Cursor::new(frame.payload);
+        let turn_id = cursor.read_u64::<LittleEndian>()?;
+        let mut hash = [0u8; 32];
+        cursor.read_exact(&mut hash)?;
+        let trees_written = cursor.read_u32::<LittleEndian>()?;
+
+        Ok(AttachFsOverlayResult {
+            turn_id,
+            fs_root_hash: hash,
+            trees_written,
+        })
+    }
 
-        let frame = self.send_request(ctx, MSG_PUT_BLOB, &payload)?;
+    pub fn put_blob(&self, ctx: &RequestContext, req: &PutBlobRequest) -> Result<PutBlobResult> {
+        let (flags, payload) = encode_put_blob(req)?;
+        let frame = self.send_request_with_flags(ctx, MSG_PUT_BLOB, flags, &payload)?;
         if frame.payload.len() < 33 {
             return Err(Error::invalid_response(format!(
                 "put blob response too short ({} bytes)",
@@ -80,12 +164,20 @@ impl Client {
         })
     }
 
+    /// Store `data` without a context, i.e. in plaintext. Use [`Client::put_blob`]
+    /// with `context_id` for blobs of an encrypted context.
     pub fn put_blob_if_absent(
         &self,
         ctx: &RequestContext,
         data: Vec<u8>,
     ) -> Result<([u8; 32], bool)> {
-        let result = self.put_blob(ctx, &PutBlobRequest { data })?;
+        let result = self.put_blob(
+            ctx,
+            &PutBlobRequest {
+                data,
+                context_id: None,
+            },
+        )?;
         Ok((result.hash, result.was_new))
     }
 
@@ -95,123 +187,100 @@ impl Client {
         req: &AppendRequest,
         fs_root_hash: Option<[u8; 32]>,
     ) -> Result<AppendResult> {
-        let encoding = if req.encoding == 0 {
-            ENCODING_MSGPACK
-        } else {
-            req.encoding
-        };
+        let (flags, payload) = encode_append_turn(req, fs_root_hash)?;
+        let frame = self.send_request_with_flags(ctx, MSG_APPEND_TURN, flags, &payload)?;
+        parse_append_result(&frame.payload)
+    }
+}
 
-        let hash = blake3::hash(&req.payload);
-        let mut payload = Vec::with_capacity(128 + req.payload.len());
-        payload.write_u64::<LittleEndian>(req.context_id)?;
-        payload.write_u64::<LittleEndian>(req.parent_turn_id)?;
-        payload.write_u32::<LittleEndian>(req.type_id.len() as u32)?;
-        payload.extend_from_slice(req.type_id.as_bytes());
-        payload.write_u32::<LittleEndian>(req.type_version)?;
-        payload.write_u32::<LittleEndian>(encoding)?;
-        payload.write_u32::<LittleEndian>(req.compression)?;
-        payload.write_u32::<LittleEndian>(req.payload.len() as u32)?;
-        payload.extend_from_slice(hash.as_bytes());
-        payload.write_u32::<LittleEndian>(req.payload.len() as u32)?;
-        payload.extend_from_slice(&req.payload);
-        payload.write_u32::<LittleEndian>(req.idempotency_key.len() as u32)?;
-        if !req.idempotency_key.is_empty() {
-            payload.extend_from_slice(&req.idempotency_key);
-        }
+pub(crate) fn encode_put_blob(req: &PutBlobRequest) -> Result<(u16, Vec<u8>)> {
+    let hash = blake3::hash(&req.data);
+    let mut payload = Vec::with_capacity(44 + req.data.len());
+    payload.extend_from_slice(hash.as_bytes());
+    payload.write_u32::<LittleEndian>(req.data.len() as u32)?;
+    payload.extend_from_slice(&req.data);
+    let Some(context_id) = req.context_id else {
+        return Ok((0, payload));
+    };
+    payload.write_u64::<LittleEndian>(context_id)?;
+    Ok((1, payload))
+}
 
-        let mut flags = 0u16;
-        if let Some(hash) = fs_root_hash {
-            flags |= 1;
-            payload.extend_from_slice(&hash);
-        }
+pub(crate) fn encode_attach_fs(req: &AttachFsRequest) -> Result<(u16, Vec<u8>)> {
+    let mut payload = Vec::with_capacity(40);
+    payload.write_u64::<LittleEndian>(req.turn_id)?;
+    payload.extend_from_slice(&req.fs_root_hash);
+    let Some(meta) = &req.metadata else {
+        return Ok((0, payload));
+    };
+    payload.write_u64::<LittleEndian>(meta.captured_at_unix_ms)?;
+    for value in [&meta.base_path, &meta.git_commit, &meta.git_branch] {
+        payload.write_u32::<LittleEndian>(value.len() as u32)?;
+        payload.extend_from_slice(value.as_bytes());
+    }
+    payload.write_u8(meta.git_dirty as u8)?;
+    payload.write_u64::<LittleEndian>(meta.total_entries)?;
+    Ok((1, payload))
+}
 
-        let frame = self.send_request_with_flags(ctx, MSG_APPEND_TURN, flags, &payload)?;
-        if frame.payload.len() < 52 {
-            return Err(Error::invalid_response(format!(
-                "append response too short ({} bytes)",
-                frame.payload.len()
-            )));
-        }
-        let mut cursor = std::io::Cursor::new(frame.payload);
-        let context_id = cursor.read_u64::<LittleEndian>()?;
-        let turn_id = cursor.read_u64::<LittleEndian>()?;
-        let depth = cursor.read_u32::<LittleEndian>()?;
-        let mut hash = [0u8; 32];
-        cursor.read_exact(&mut hash)?;
-        Ok(AppendResult {
-            context_id,
-            turn_id,
-            depth,
-            payload_hash: hash,
-        })
+pub(crate) fn encode_attach_fs_overlay(req: &AttachFsOverlayRequest) -> Result<Vec<u8>> {
+    let mut payload = Vec::new();
+    payload.write_u64::<LittleEndian>(req.turn_id)?;
+    payload.extend_from_slice(&req.base_root_hash.unwrap_or([0; 32]));
+    payload.write_u32::<LittleEndian>(req.blobs.len() as u32)?;
+    for blob in &req.blobs {
+        payload.write_u32::<LittleEndian>(blob.len() as u32)?;
+        payload.extend_from_slice(blob);
     }
+    payload.write_u32::<LittleEndian>(req.changes.len() as u32)?;
+    for change in &req.changes {
+        payload.write_u32::<LittleEndian>(change.path.len() as u32)?;
+        payload.extend_from_slice(change.path.as_bytes());
+        payload.write_u8(change.kind)?;
+        payload.write_u32::<LittleEndian>(change.mode)?;
+        payload.write_u64::<LittleEndian>(change.size)?;
+        payload.extend_from_slice(&change.hash);
+    }
+    Ok(payload)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::protocol::ENCODING_MSGPACK;
     use crate::test_util::{decode_hex, load_fixture};
 
-    fn build_append_payload(req: &AppendRequest, fs_root_hash: Option<[u8; 32]>) -> Vec<u8> {
-        let encoding = if req.encoding == 0 {
-            ENCODING_MSGPACK
-        } else {
-            req.encoding
-        };
-        let hash = blake3::hash(&req.payload);
-        let mut payload = Vec::new();
-        payload.write_u64::<LittleEndian>(req.context_id).unwrap();
-        payload
-            .write_u64::<LittleEndian>(req.parent_turn_id)
-            .unwrap();
-        payload
-            .write_u32::<LittleEndian>(req.type_id.len() as u32)
-            .unwrap();
-        payload.extend_from_slice(req.type_id.as_bytes());
-        payload.write_u32::<LittleEndian>(req.type_version).unwrap();
-        payload.write_u32::<LittleEndian>(encoding).unwrap();
-        payload.write_u32::<LittleEndian>(req.compression).unwrap();
-        payload
-            .write_u32::<LittleEndian>(req.payload.len() as u32)
-            .unwrap();
-        payload.extend_from_slice(hash.as_bytes());
-        payload
-            .write_u32::<LittleEndian>(req.payload.len() as u32)
-            .unwrap();
-        payload.extend_from_slice(&req.payload);
-        payload
-            .write_u32::<LittleEndian>(req.idempotency_key.len() as u32)
-            .unwrap();
-        if !req.idempotency_key.is_empty() {
-            payload.extend_from_slice(&req.idempotency_key);
-        }
-        if let Some(hash) = fs_root_hash {
-            payload.extend_from_slice(&hash);
-        }
-        payload
-    }
-
     #[test]
     fn fs_payloads_match_fixtures() {
         let fixture = load_fixture("attach_fs");
         assert_eq!(fixture.msg_type, MSG_ATTACH_FS);
         assert_eq!(fixture.flags, 0);
-        let mut payload = Vec::new();
-        payload.write_u64::<LittleEndian>(99).unwrap();
-        payload.extend_from_slice(&[0xAA; 32]);
+        let req = AttachFsRequest {
+            turn_id: 99,
+            fs_root_hash: [0xAA; 32],
+            metadata: None,
+        };
+        let (flags, payload) = encode_attach_fs(&req).unwrap();
+        assert_eq!(flags, 0);
         assert_eq!(decode_hex(&fixture.payload_hex), payload);
 
         let fixture = load_fixture("put_blob");
         assert_eq!(fixture.msg_type, MSG_PUT_BLOB);
         assert_eq!(fixture.flags, 0);
-        let data = b"hello blob";
-        let hash = blake3::hash(data);
-        let mut payload = Vec::new();
-        payload.extend_from_slice(hash.as_bytes());
-        payload
-            .write_u32::<LittleEndian>(data.len() as u32)
-            .unwrap();
-        payload.extend_from_slice(data);
+        let mut req = PutBlobRequest {
+            data: b"hello blob".to_vec(),
+            context_id: None,
+        };
+        let (flags, payload) = encode_put_blob(&req).unwrap();
+        assert_eq!(flags, 0);
+        assert_eq!(decode_hex(&fixture.payload_hex), payload);
+
+        let fixture = load_fixture("put_blob_context");
+        assert_eq!(fixture.msg_type, MSG_PUT_BLOB);
+        assert_eq!(fixture.flags, 1);
+        req.context_id = Some(42);
+        let (flags, payload) = encode_put_blob(&req).unwrap();
+        assert_eq!(flags, 1);
         assert_eq!(decode_hex(&fixture.payload_hex), payload);
 
         let fixture = load_fixture("append_with_fs");
@@ -227,7 +296,66 @@ mod tests {
             encoding: ENCODING_MSGPACK,
             compression: 0,
         };
-        let payload = build_append_payload(&req, Some([0xBB; 32]));
+        let (flags, payload) = encode_append_turn(&req, Some([0xBB; 32])).unwrap();
+        assert_eq!(flags, 1);
         assert_eq!(decode_hex(&fixture.payload_hex), payload);
     }
+
+    #[test]
+    fn attach_fs_metadata_sets_flag_and_trails_payload() {
+        let mut req = AttachFsRequest {
+            turn_id: 99,
+            fs_root_hash: [0xAA; 32],
+            metadata: None,
+        };
+        let (flags, bare) = encode_attach_fs(&req).unwrap();
+        assert_eq!(flags, 0);
+        assert_eq!(bare.len(), 40);
+
+        req.metadata = Some(SnapshotMetadata {
+            captured_at_unix_ms: 7,
+            base_path: "/src".into(),
+            git_commit: "abc".into(),
+            git_branch: String::new(),
+            git_dirty: true,
+            total_entries: 3,
+        });
+        let (flags, payload) = encode_attach_fs(&req).unwrap();
+        assert_eq!(flags, 1);
+        assert_eq!(&payload[..40], &bare[..]);
+        let mut expected = Vec::new();
+        expected.write_u64::<LittleEndian>(7).unwrap();
+        expected.extend_from_slice(&[4, 0, 0, 0]);
+        expected.extend_from_slice(b"/src");
+        expected.extend_from_slice(&[3, 0, 0, 0]);
+        expected.extend_from_slice(b"abc");
+        expected.extend_from_slice(&[0, 0, 0, 0, 1]);
+        expected.write_u64::<LittleEndian>(3).unwrap();
+        assert_eq!(&payload[40..], &expected[..]);
+    }
+
+    #[test]
+    fn overlay_payload_layout() {
+        let req = AttachFsOverlayRequest {
+            turn_id: 7,
+            base_root_hash: None,
+            blobs: vec![b"hi".to_vec()],
+            changes: vec![OverlayChange::remove("a/b")],
+        };
+        let payload = encode_attach_fs_overlay(&req).unwrap();
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&7u64.to_le_bytes());
+        expected.extend_from_slice(&[0; 32]);
+        expected.extend_from_slice(&1u32.to_le_bytes());
+        expected.extend_from_slice(&2u32.to_le_bytes());
+        expected.extend_from_slice(b"hi");
+        expected.extend_from_slice(&1u32.to_le_bytes());
+        expected.extend_from_slice(&3u32.to_le_bytes());
+        expected.extend_from_slice(b"a/b");
+        expected.push(OVERLAY_REMOVE);
+        expected.extend_from_slice(&0u32.to_le_bytes());
+        expected.extend_from_slice(&0u64.to_le_bytes());
+        expected.extend_from_slice(&[0; 32]);
+        assert_eq!(payload, expected);
+    }
 }
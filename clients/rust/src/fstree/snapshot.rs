@@ -3,12 +3,14 @@
 
 use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
 use super::capture::deserialize_tree;
 use super::types::{
     EntryKindDirectory, EntryKindFile, EntryKindSymlink, Snapshot, SnapshotDiff, TreeEntry,
 };
 use super::{FstreeError, FstreeErrorKind};
+use crate::fs::SnapshotMetadata;
 
 impl Snapshot {
     pub fn get_file(&self, hash: [u8; 32]) -> Result<File, FstreeError> {
@@ -36,6 +38,25 @@ impl Snapshot {
         self.get_tree(self.root_hash)
     }
 
+    /// Attach metadata for this snapshot taken from `base_path`. Git fields
+    /// are left empty for the caller to fill in.
+    pub fn metadata(&self, base_path: impl Into<String>) -> SnapshotMetadata {
+        let captured_at_unix_ms = self
+            .captured_at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        SnapshotMetadata {
+            captured_at_unix_ms,
+            base_path: base_path.into(),
+            // The root directory is counted in dir_count but is not an entry.
+            total_entries: (self.stats.file_count
+                + self.stats.dir_count.saturating_sub(1)
+                + self.stats.symlink_count) as u64,
+            ..Default::default()
+        }
+    }
+
     pub fn walk<F>(&self, mut f: F) -> Result<(), FstreeError>
     where
         F: FnMut(&str, &TreeEntry) -> Result<(), FstreeError>,
@@ -115,6 +115,7 @@ fn capture_basic_tree_stats() {
     let snap = capture(dir.path(), Vec::<SnapshotOption>::new()).unwrap();
     assert_eq!(snap.stats.file_count, 4);
     assert_eq!(snap.stats.dir_count, 2);
+    assert_eq!(snap.metadata("/work").total_entries, 5);
 
     let files = snap.list_files().unwrap();
     assert_eq!(files.len(), 4);
@@ -19,14 +19,21 @@ pub struct UploadResult {
 }
 
 impl Snapshot {
-    pub fn upload(&self, ctx: &RequestContext, client: &Client) -> FstreeResult<UploadResult> {
+    /// Upload the snapshot's trees and files as blobs of `context_id`, so an
+    /// encrypted context's snapshot is sealed with its key.
+    pub fn upload(
+        &self,
+        ctx: &RequestContext,
+        client: &Client,
+        context_id: u64,
+    ) -> FstreeResult<UploadResult> {
         let mut result = UploadResult {
             root_hash: self.root_hash,
             ..UploadResult::default()
         };
 
         for data in self.trees.values() {
-            let was_new = upload_blob(ctx, client, data.to_vec())
+            let was_new = upload_blob(ctx, client, context_id, data.to_vec())
                 .map_err(|err| FstreeError::new(FstreeErrorKind::Client, err.to_string()))?;
             if was_new {
                 result.trees_uploaded += 1;
@@ -39,7 +46,7 @@ impl Snapshot {
         for file_ref in self.files.values() {
             let content = std::fs::read(&file_ref.path)
                 .map_err(|err| FstreeError::new(FstreeErrorKind::Io, err.to_string()))?;
-            let was_new = upload_blob(ctx, client, content.clone())
+            let was_new = upload_blob(ctx, client, context_id, content.clone())
                 .map_err(|err| FstreeError::new(FstreeErrorKind::Client, err.to_string()))?;
             if was_new {
                 result.files_uploaded += 1;
@@ -51,7 +58,7 @@ impl Snapshot {
 
         for target in self.symlinks.values() {
             let bytes = target.as_bytes().to_vec();
-            let was_new = upload_blob(ctx, client, bytes.clone())
+            let was_new = upload_blob(ctx, client, context_id, bytes.clone())
                 .map_err(|err| FstreeError::new(FstreeErrorKind::Client, err.to_string()))?;
             if was_new {
                 result.files_uploaded += 1;
@@ -68,9 +75,16 @@ impl Snapshot {
 fn upload_blob(
     ctx: &RequestContext,
     client: &Client,
+    context_id: u64,
     data: Vec<u8>,
 ) -> Result<bool, crate::error::Error> {
-    let result = client.put_blob(ctx, &PutBlobRequest { data })?;
+    let result = client.put_blob(
+        ctx,
+        &PutBlobRequest {
+            data,
+            context_id: Some(context_id),
+        },
+    )?;
     Ok(result.was_new)
 }
 
@@ -78,17 +92,23 @@ pub fn upload_and_attach(
     ctx: &RequestContext,
     client: &Client,
     root: impl AsRef<std::path::Path>,
+    context_id: u64,
     turn_id: u64,
     opts: impl IntoIterator<Item = super::options::SnapshotOption>,
 ) -> FstreeResult<UploadResult> {
+    let base_path = std::fs::canonicalize(root.as_ref())
+        .unwrap_or_else(|_| root.as_ref().to_path_buf())
+        .to_string_lossy()
+        .into_owned();
     let snapshot = super::capture::capture(root, opts)?;
-    let result = snapshot.upload(ctx, client)?;
+    let result = snapshot.upload(ctx, client, context_id)?;
     client
         .attach_fs(
             ctx,
             &crate::fs::AttachFsRequest {
                 turn_id,
                 fs_root_hash: snapshot.root_hash,
+                metadata: Some(snapshot.metadata(base_path)),
             },
         )
         .map_err(|err| FstreeError::new(FstreeErrorKind::Client, err.to_string()))?;
@@ -99,9 +119,10 @@ pub fn capture_and_upload(
     ctx: &RequestContext,
     client: &Client,
     root: impl AsRef<std::path::Path>,
+    context_id: u64,
     opts: impl IntoIterator<Item = super::options::SnapshotOption>,
 ) -> FstreeResult<(super::types::Snapshot, UploadResult)> {
     let snapshot = super::capture::capture(root, opts)?;
-    let result = snapshot.upload(ctx, client)?;
+    let result = snapshot.upload(ctx, client, context_id)?;
     Ok((snapshot, result))
 }
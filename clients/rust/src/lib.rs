@@ -19,6 +19,8 @@ pub mod turn;
 pub mod fstree;
 pub mod types;
 
+#[cfg(test)]
+mod schema_tests;
 #[cfg(test)]
 mod test_util;
 pub use crate::client::{
@@ -28,7 +30,10 @@ pub use crate::client::{
 pub use crate::context::ContextHead;
 pub use crate::encoding::{decode_msgpack, decode_msgpack_into, encode_msgpack};
 pub use crate::error::{is_server_error, Error, Result, ServerError};
-pub use crate::fs::{AttachFsRequest, AttachFsResult, PutBlobRequest, PutBlobResult};
+pub use crate::fs::{
+    AttachFsOverlayRequest, AttachFsOverlayResult, AttachFsRequest, AttachFsResult, OverlayChange,
+    PutBlobRequest, PutBlobResult, SnapshotMetadata,
+};
 pub use crate::reconnect::{
     dial_reconnecting, dial_tls_reconnecting, DialFunc, ReconnectOption, ReconnectingClient,
 };
@@ -14,9 +14,11 @@ pub const MSG_CTX_FORK: u16 = 3;
 pub const MSG_GET_HEAD: u16 = 4;
 pub const MSG_APPEND_TURN: u16 = 5;
 pub const MSG_GET_LAST: u16 = 6;
+pub const MSG_GET_RANGE_BY_DEPTH: u16 = 8;
 pub const MSG_GET_BLOB: u16 = 9;
 pub const MSG_ATTACH_FS: u16 = 10;
 pub const MSG_PUT_BLOB: u16 = 11;
+pub const MSG_ATTACH_FS_OVERLAY: u16 = 12;
 pub const MSG_ERROR: u16 = 255;
 
 pub const ENCODING_MSGPACK: u32 = 1;
@@ -42,6 +44,23 @@ pub struct Frame {
     pub payload: Vec<u8>,
 }
 
+/// Write a u16 length prefix and the string. A string the prefix cannot
+/// hold is refused rather than truncated, which would desync the server.
+pub(crate) fn write_str16(payload: &mut Vec<u8>, field: &str, value: &str) -> Result<()> {
+    let len = u16::try_from(value.len()).map_err(|_| {
+        Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "{field} is {} bytes, over the 65535-byte limit",
+                value.len()
+            ),
+        ))
+    })?;
+    payload.write_u16::<LittleEndian>(len)?;
+    payload.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
 pub fn write_frame<W: Write>(
     writer: &mut W,
     msg_type: u16,
@@ -295,6 +295,25 @@ impl ReconnectingClient {
         Ok(value)
     }
 
+    pub fn get_range_by_depth(
+        &self,
+        ctx: &RequestContext,
+        context_id: u64,
+        start_depth: u32,
+        opts: crate::turn::GetLastOptions,
+    ) -> Result<Vec<crate::turn::TurnRecord>> {
+        let result = Arc::new(Mutex::new(None));
+        let ctx_clone = ctx.clone();
+        let result_clone = result.clone();
+        self.enqueue(ctx, "GetRangeByDepth", move |client| {
+            let res = client.get_range_by_depth(&ctx_clone, context_id, start_depth, opts)?;
+            *result_clone.lock().unwrap() = Some(res);
+            Ok(())
+        })?;
+        let value = result.lock().unwrap().take().unwrap();
+        Ok(value)
+    }
+
     pub fn attach_fs(
         &self,
         ctx: &RequestContext,
@@ -313,6 +332,24 @@ impl ReconnectingClient {
         Ok(value)
     }
 
+    pub fn attach_fs_overlay(
+        &self,
+        ctx: &RequestContext,
+        req: &crate::fs::AttachFsOverlayRequest,
+    ) -> Result<crate::fs::AttachFsOverlayResult> {
+        let result = Arc::new(Mutex::new(None));
+        let req = req.clone();
+        let ctx_clone = ctx.clone();
+        let result_clone = result.clone();
+        self.enqueue(ctx, "AttachFsOverlay", move |client| {
+            let res = client.attach_fs_overlay(&ctx_clone, &req)?;
+            *result_clone.lock().unwrap() = Some(res);
+            Ok(())
+        })?;
+        let value = result.lock().unwrap().take().unwrap();
+        Ok(value)
+    }
+
     pub fn put_blob(
         &self,
         ctx: &RequestContext,
@@ -652,7 +689,8 @@ mod tests {
         assert!(!is_connection_error(&Error::Server(
             crate::error::ServerError {
                 code: 404,
-                detail: "not found".into()
+                detail: "not found".into(),
+                ..Default::default()
             }
         )));
         assert!(is_connection_error(&Error::Io(std::io::Error::new(
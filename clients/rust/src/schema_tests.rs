@@ -0,0 +1,233 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Request encoders checked against the server's wire schema: each payload
+//! must decode with the server's generated codec to the values the client
+//! meant to send, and re-encode to the same bytes.
+
+use std::fmt::Debug;
+
+use cxdb_server::protocol::{self as wire, WireStruct};
+
+use crate::client::encode_hello;
+use crate::context::{encode_ctx_create_with_external_id, encode_u64};
+use crate::fs::{
+    encode_attach_fs, encode_attach_fs_overlay, encode_put_blob, AttachFsOverlayRequest,
+    AttachFsRequest, OverlayChange, PutBlobRequest, SnapshotMetadata,
+};
+use crate::protocol::*;
+use crate::turn::{
+    encode_append_turn, encode_get_last, encode_get_range_by_depth, AppendRequest, GetLastOptions,
+};
+
+fn check<T: WireStruct + Debug + PartialEq>(payload: &[u8], flags: u16, expected: T) {
+    let decoded =
+        T::decode(payload, flags).unwrap_or_else(|err| panic!("{} rejected: {err}", T::NAME));
+    assert_eq!(decoded, expected);
+    assert_eq!(
+        decoded.encode().unwrap(),
+        payload,
+        "{} re-encodes differently",
+        T::NAME
+    );
+}
+
+#[test]
+fn message_types_match_the_server() {
+    use wire::MsgType;
+    let types = [
+        (MSG_HELLO, MsgType::Hello),
+        (MSG_CTX_CREATE, MsgType::CtxCreate),
+        (MSG_CTX_FORK, MsgType::CtxFork),
+        (MSG_GET_HEAD, MsgType::GetHead),
+        (MSG_APPEND_TURN, MsgType::AppendTurn),
+        (MSG_GET_LAST, MsgType::GetLast),
+        (MSG_GET_RANGE_BY_DEPTH, MsgType::GetRangeByDepth),
+        (MSG_GET_BLOB, MsgType::GetBlob),
+        (MSG_ATTACH_FS, MsgType::AttachFs),
+        (MSG_PUT_BLOB, MsgType::PutBlob),
+        (MSG_ATTACH_FS_OVERLAY, MsgType::AttachFsOverlay),
+        (MSG_ERROR, MsgType::Error),
+    ];
+    for (client, server) in types {
+        assert_eq!(client, server as u16, "{server:?}");
+    }
+}
+
+#[test]
+fn hello_matches_the_server_schema() {
+    check(
+        &encode_hello("cxdb-rust").unwrap(),
+        0,
+        wire::HelloRequest {
+            protocol_version: 1,
+            client_tag: "cxdb-rust".into(),
+            ..Default::default()
+        },
+    );
+}
+
+#[test]
+fn context_requests_match_the_server_schema() {
+    check(
+        &encode_u64(7),
+        0,
+        wire::CtxCreateRequest {
+            base_turn_id: 7,
+            ..Default::default()
+        },
+    );
+    check(
+        &encode_ctx_create_with_external_id(0, "01J9ZKQ6").unwrap(),
+        1,
+        wire::CtxCreateRequest {
+            base_turn_id: 0,
+            external_id: Some("01J9ZKQ6".into()),
+            ttl_secs: None,
+        },
+    );
+    check(&encode_u64(3), 0, wire::CtxForkRequest { base_turn_id: 3 });
+    check(&encode_u64(42), 0, wire::GetHeadRequest { context_id: 42 });
+}
+
+#[test]
+fn turn_requests_match_the_server_schema() {
+    let mut req = AppendRequest::new(1, "cxdb.ConversationItem", 3, vec![0x91, 0x01]);
+    req.parent_turn_id = 7;
+    req.idempotency_key = b"idem-1".to_vec();
+    let expected = wire::AppendTurnRequest {
+        context_id: 1,
+        parent_turn_id: 7,
+        declared_type_id: "cxdb.ConversationItem".into(),
+        declared_type_version: 3,
+        encoding: ENCODING_MSGPACK,
+        compression: 0,
+        uncompressed_len: 2,
+        content_hash: *blake3::hash(&req.payload).as_bytes(),
+        payload_bytes: vec![0x91, 0x01],
+        idempotency_key: b"idem-1".to_vec(),
+        fs_root_hash: None,
+    };
+    let (flags, payload) = encode_append_turn(&req, None).unwrap();
+    check(&payload, flags, expected.clone());
+    let (flags, payload) = encode_append_turn(&req, Some([0xbb; 32])).unwrap();
+    check(
+        &payload,
+        flags,
+        wire::AppendTurnRequest {
+            fs_root_hash: Some([0xbb; 32]),
+            ..expected
+        },
+    );
+
+    let opts = GetLastOptions {
+        limit: 5,
+        include_payload: true,
+    };
+    check(
+        &encode_get_last(9, opts).unwrap(),
+        0,
+        wire::GetLastRequest {
+            context_id: 9,
+            limit: 5,
+            include_payload: 1,
+        },
+    );
+    check(
+        &encode_get_range_by_depth(9, 100, GetLastOptions::default()).unwrap(),
+        0,
+        wire::GetRangeByDepthRequest {
+            context_id: 9,
+            start_depth: 100,
+            limit: 10,
+            include_payload: 0,
+        },
+    );
+}
+
+#[test]
+fn fs_requests_match_the_server_schema() {
+    let mut req = AttachFsRequest {
+        turn_id: 42,
+        fs_root_hash: [0xaa; 32],
+        metadata: None,
+    };
+    let (flags, payload) = encode_attach_fs(&req).unwrap();
+    check(
+        &payload,
+        flags,
+        wire::AttachFsRequest {
+            turn_id: 42,
+            fs_root_hash: [0xaa; 32],
+            ..Default::default()
+        },
+    );
+    req.metadata = Some(SnapshotMetadata {
+        captured_at_unix_ms: 1_700_000_000_000,
+        base_path: "/src".into(),
+        git_commit: "abc123".into(),
+        git_branch: String::new(),
+        git_dirty: true,
+        total_entries: 12,
+    });
+    let (flags, payload) = encode_attach_fs(&req).unwrap();
+    check(
+        &payload,
+        flags,
+        wire::AttachFsRequest {
+            turn_id: 42,
+            fs_root_hash: [0xaa; 32],
+            captured_at_unix_ms: Some(1_700_000_000_000),
+            base_path: Some(Some("/src".into())),
+            git_commit: Some(Some("abc123".into())),
+            git_branch: Some(None),
+            git_dirty: Some(1),
+            total_entries: Some(12),
+        },
+    );
+
+    let overlay = AttachFsOverlayRequest {
+        turn_id: 42,
+        base_root_hash: Some([0xcc; 32]),
+        blobs: vec![b"hi".to_vec()],
+        changes: vec![OverlayChange::remove("a/b")],
+    };
+    check(
+        &encode_attach_fs_overlay(&overlay).unwrap(),
+        0,
+        wire::AttachFsOverlayRequest {
+            turn_id: 42,
+            base_root_hash: [0xcc; 32],
+            blobs: vec![wire::OverlayBlob {
+                data: b"hi".to_vec(),
+            }],
+            changes: vec![wire::OverlayChangeItem {
+                path: "a/b".into(),
+                kind: 255,
+                ..Default::default()
+            }],
+        },
+    );
+
+    let mut blob = PutBlobRequest {
+        data: b"blob bytes".to_vec(),
+        context_id: None,
+    };
+    let expected = wire::PutBlobRequest {
+        hash: *blake3::hash(&blob.data).as_bytes(),
+        data: blob.data.clone(),
+        context_id: None,
+    };
+    let (flags, payload) = encode_put_blob(&blob).unwrap();
+    check(&payload, flags, expected.clone());
+    blob.context_id = Some(42);
+    let (flags, payload) = encode_put_blob(&blob).unwrap();
+    check(
+        &payload,
+        flags,
+        wire::PutBlobRequest {
+            context_id: Some(42),
+            ..expected
+        },
+    );
+}
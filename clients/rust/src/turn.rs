@@ -6,7 +6,7 @@ use std::io::Read;
 
 use crate::client::{Client, RequestContext};
 use crate::error::{Error, Result};
-use crate::protocol::{ENCODING_MSGPACK, MSG_APPEND_TURN, MSG_GET_LAST};
+use crate::protocol::{ENCODING_MSGPACK, MSG_APPEND_TURN, MSG_GET_LAST, MSG_GET_RANGE_BY_DEPTH};
 
 #[derive(Debug, Clone)]
 pub struct AppendRequest {
@@ -78,36 +78,8 @@ impl Default for GetLastOptions {
 
 impl Client {
     pub fn append_turn(&self, ctx: &RequestContext, req: &AppendRequest) -> Result<AppendResult> {
-        let encoding = if req.encoding == 0 {
-            ENCODING_MSGPACK
-        } else {
-            req.encoding
-        };
-
-        let hash = blake3::hash(&req.payload);
-
-        let mut payload = Vec::with_capacity(128 + req.payload.len());
-        payload.write_u64::<LittleEndian>(req.context_id)?;
-        payload.write_u64::<LittleEndian>(req.parent_turn_id)?;
-
-        payload.write_u32::<LittleEndian>(req.type_id.len() as u32)?;
-        payload.extend_from_slice(req.type_id.as_bytes());
-        payload.write_u32::<LittleEndian>(req.type_version)?;
-
-        payload.write_u32::<LittleEndian>(encoding)?;
-        payload.write_u32::<LittleEndian>(req.compression)?;
-        payload.write_u32::<LittleEndian>(req.payload.len() as u32)?; // uncompressed len
-        payload.extend_from_slice(hash.as_bytes());
-
-        payload.write_u32::<LittleEndian>(req.payload.len() as u32)?;
-        payload.extend_from_slice(&req.payload);
-
-        payload.write_u32::<LittleEndian>(req.idempotency_key.len() as u32)?;
-        if !req.idempotency_key.is_empty() {
-            payload.extend_from_slice(&req.idempotency_key);
-        }
-
-        let frame = self.send_request(ctx, MSG_APPEND_TURN, &payload)?;
+        let (flags, payload) = encode_append_turn(req, None)?;
+        let frame = self.send_request_with_flags(ctx, MSG_APPEND_TURN, flags, &payload)?;
         parse_append_result(&frame.payload)
     }
 
@@ -117,18 +89,94 @@ impl Client {
         context_id: u64,
         opts: GetLastOptions,
     ) -> Result<Vec<TurnRecord>> {
-        let limit = if opts.limit == 0 { 10 } else { opts.limit };
-        let mut payload = Vec::with_capacity(16);
-        payload.write_u64::<LittleEndian>(context_id)?;
-        payload.write_u32::<LittleEndian>(limit)?;
-        payload.write_u32::<LittleEndian>(if opts.include_payload { 1 } else { 0 })?;
-
+        let payload = encode_get_last(context_id, opts)?;
         let frame = self.send_request(ctx, MSG_GET_LAST, &payload)?;
         parse_turn_records(&frame.payload)
     }
+
+    /// Turns of a context starting at `start_depth`, oldest first. The
+    /// server seeks to the depth directly, so this is the cheap way to page
+    /// through deep histories.
+    pub fn get_range_by_depth(
+        &self,
+        ctx: &RequestContext,
+        context_id: u64,
+        start_depth: u32,
+        opts: GetLastOptions,
+    ) -> Result<Vec<TurnRecord>> {
+        let payload = encode_get_range_by_depth(context_id, start_depth, opts)?;
+        let frame = self.send_request(ctx, MSG_GET_RANGE_BY_DEPTH, &payload)?;
+        parse_turn_records(&frame.payload)
+    }
+}
+
+/// APPEND_TURN payload and frame flags. An fs root hash trails the payload
+/// and sets flag bit 0.
+pub(crate) fn encode_append_turn(
+    req: &AppendRequest,
+    fs_root_hash: Option<[u8; 32]>,
+) -> Result<(u16, Vec<u8>)> {
+    let encoding = if req.encoding == 0 {
+        ENCODING_MSGPACK
+    } else {
+        req.encoding
+    };
+
+    let hash = blake3::hash(&req.payload);
+
+    let mut payload = Vec::with_capacity(128 + req.payload.len());
+    payload.write_u64::<LittleEndian>(req.context_id)?;
+    payload.write_u64::<LittleEndian>(req.parent_turn_id)?;
+
+    payload.write_u32::<LittleEndian>(req.type_id.len() as u32)?;
+    payload.extend_from_slice(req.type_id.as_bytes());
+    payload.write_u32::<LittleEndian>(req.type_version)?;
+
+    payload.write_u32::<LittleEndian>(encoding)?;
+    payload.write_u32::<LittleEndian>(req.compression)?;
+    payload.write_u32::<LittleEndian>(req.payload.len() as u32)?; // uncompressed len
+    payload.extend_from_slice(hash.as_bytes());
+
+    payload.write_u32::<LittleEndian>(req.payload.len() as u32)?;
+    payload.extend_from_slice(&req.payload);
+
+    payload.write_u32::<LittleEndian>(req.idempotency_key.len() as u32)?;
+    if !req.idempotency_key.is_empty() {
+        payload.extend_from_slice(&req.idempotency_key);
+    }
+
+    let mut flags = 0u16;
+    if let Some(hash) = fs_root_hash {
+        flags |= 1;
+        payload.extend_from_slice(&hash);
+    }
+    Ok((flags, payload))
+}
+
+pub(crate) fn encode_get_last(context_id: u64, opts: GetLastOptions) -> Result<Vec<u8>> {
+    let limit = if opts.limit == 0 { 10 } else { opts.limit };
+    let mut payload = Vec::with_capacity(16);
+    payload.write_u64::<LittleEndian>(context_id)?;
+    payload.write_u32::<LittleEndian>(limit)?;
+    payload.write_u32::<LittleEndian>(if opts.include_payload { 1 } else { 0 })?;
+    Ok(payload)
 }
 
-fn parse_append_result(payload: &[u8]) -> Result<AppendResult> {
+pub(crate) fn encode_get_range_by_depth(
+    context_id: u64,
+    start_depth: u32,
+    opts: GetLastOptions,
+) -> Result<Vec<u8>> {
+    let limit = if opts.limit == 0 { 10 } else { opts.limit };
+    let mut payload = Vec::with_capacity(20);
+    payload.write_u64::<LittleEndian>(context_id)?;
+    payload.write_u32::<LittleEndian>(start_depth)?;
+    payload.write_u32::<LittleEndian>(limit)?;
+    payload.write_u32::<LittleEndian>(if opts.include_payload { 1 } else { 0 })?;
+    Ok(payload)
+}
+
+pub(crate) fn parse_append_result(payload: &[u8]) -> Result<AppendResult> {
     if payload.len() < 52 {
         return Err(Error::invalid_response(format!(
             "append response too short ({} bytes)",
@@ -202,42 +250,6 @@ mod tests {
     use super::*;
     use crate::test_util::{decode_hex, load_fixture};
 
-    fn build_append_payload(req: &AppendRequest) -> Vec<u8> {
-        let encoding = if req.encoding == 0 {
-            ENCODING_MSGPACK
-        } else {
-            req.encoding
-        };
-        let hash = blake3::hash(&req.payload);
-        let mut payload = Vec::new();
-        payload.write_u64::<LittleEndian>(req.context_id).unwrap();
-        payload
-            .write_u64::<LittleEndian>(req.parent_turn_id)
-            .unwrap();
-        payload
-            .write_u32::<LittleEndian>(req.type_id.len() as u32)
-            .unwrap();
-        payload.extend_from_slice(req.type_id.as_bytes());
-        payload.write_u32::<LittleEndian>(req.type_version).unwrap();
-        payload.write_u32::<LittleEndian>(encoding).unwrap();
-        payload.write_u32::<LittleEndian>(req.compression).unwrap();
-        payload
-            .write_u32::<LittleEndian>(req.payload.len() as u32)
-            .unwrap();
-        payload.extend_from_slice(hash.as_bytes());
-        payload
-            .write_u32::<LittleEndian>(req.payload.len() as u32)
-            .unwrap();
-        payload.extend_from_slice(&req.payload);
-        payload
-            .write_u32::<LittleEndian>(req.idempotency_key.len() as u32)
-            .unwrap();
-        if !req.idempotency_key.is_empty() {
-            payload.extend_from_slice(&req.idempotency_key);
-        }
-        payload
-    }
-
     #[test]
     fn append_payloads_match_fixtures() {
         let fixture = load_fixture("append_parent0");
@@ -253,7 +265,10 @@ mod tests {
             encoding: ENCODING_MSGPACK,
             compression: 0,
         };
-        assert_eq!(decode_hex(&fixture.payload_hex), build_append_payload(&req));
+        assert_eq!(
+            decode_hex(&fixture.payload_hex),
+            encode_append_turn(&req, None).unwrap().1
+        );
 
         let fixture = load_fixture("append_parent7");
         assert_eq!(fixture.msg_type, MSG_APPEND_TURN);
@@ -268,7 +283,10 @@ mod tests {
             encoding: ENCODING_MSGPACK,
             compression: 0,
         };
-        assert_eq!(decode_hex(&fixture.payload_hex), build_append_payload(&req));
+        assert_eq!(
+            decode_hex(&fixture.payload_hex),
+            encode_append_turn(&req, None).unwrap().1
+        );
 
         let fixture = load_fixture("append_idempotent");
         assert_eq!(fixture.msg_type, MSG_APPEND_TURN);
@@ -283,25 +301,28 @@ mod tests {
             encoding: ENCODING_MSGPACK,
             compression: 0,
         };
-        assert_eq!(decode_hex(&fixture.payload_hex), build_append_payload(&req));
+        assert_eq!(
+            decode_hex(&fixture.payload_hex),
+            encode_append_turn(&req, None).unwrap().1
+        );
     }
 
     #[test]
     fn get_last_payloads_match_fixtures() {
         let fixture = load_fixture("get_last_default");
         assert_eq!(fixture.msg_type, MSG_GET_LAST);
-        let mut payload = Vec::new();
-        payload.write_u64::<LittleEndian>(1).unwrap();
-        payload.write_u32::<LittleEndian>(10).unwrap();
-        payload.write_u32::<LittleEndian>(0).unwrap();
+        let payload = encode_get_last(1, GetLastOptions::default()).unwrap();
         assert_eq!(decode_hex(&fixture.payload_hex), payload);
 
         let fixture = load_fixture("get_last_payload");
         assert_eq!(fixture.msg_type, MSG_GET_LAST);
-        let mut payload = Vec::new();
-        payload.write_u64::<LittleEndian>(1).unwrap();
-        payload.write_u32::<LittleEndian>(5).unwrap();
-        payload.write_u32::<LittleEndian>(1).unwrap();
-        assert_eq!(decode_hex(&fixture.payload_hex), payload);
+        let opts = GetLastOptions {
+            limit: 5,
+            include_payload: true,
+        };
+        assert_eq!(
+            decode_hex(&fixture.payload_hex),
+            encode_get_last(1, opts).unwrap()
+        );
     }
 }
@@ -34,7 +34,9 @@ fn integration_fstree_snapshot_http() {
 
     let snapshot = fstree::capture(temp_dir.path(), Vec::<fstree::SnapshotOption>::new())
         .expect("capture failed");
-    let _upload = snapshot.upload(&ctx, &client).expect("upload failed");
+    let _upload = snapshot
+        .upload(&ctx, &client, head.context_id)
+        .expect("upload failed");
 
     let payload = encode_msgpack(&new_user_input("Initial", Vec::new())).unwrap();
     let append = client
@@ -12,6 +12,7 @@ import { MessageRenderer, isAgentMessage, extractMessageText } from './MessageRe
 import { QuestEventRenderer, QuestSnapshotRenderer, isQuestEvent, isQuestSnapshot } from './QuestRenderer';
 import { FallbackRenderer } from './FallbackRenderer';
 import { ProvenancePanel } from './ProvenancePanel';
+import { HeadActivity } from './HeadActivity';
 import { DynamicRenderer } from './DynamicRenderer';
 import { useRendererManifest } from '@/lib/use-renderer';
 import { getItemTypeLabel, getItemTypeColors } from '@/types/conversation';
@@ -1037,6 +1038,10 @@ export function ContextDebugger({ contextId, isOpen, onClose, lastEvent, initial
                 {/* Content area - Provenance view */}
                 {detailView === 'provenance' && (
                   <div className="flex-1 overflow-y-auto">
+                    <HeadActivity
+                      contextId={contextId}
+                      className="border-b border-theme-border-dim/60"
+                    />
                     <ProvenancePanel
                       contextId={contextId}
                       className="divide-y divide-theme-border-dim/60"
@@ -17,6 +17,8 @@ export function FileViewer({ turnId, filePath, onClose }: FileViewerProps) {
   const [loading, setLoading] = useState(true);
   const [error, setError] = useState<string | null>(null);
   const [fileSize, setFileSize] = useState<number>(0);
+  const [language, setLanguage] = useState<string | null>(null);
+  const [lineCount, setLineCount] = useState<number | null>(null);
 
   // Fetch file content
   useEffect(() => {
@@ -26,14 +28,18 @@ export function FileViewer({ turnId, filePath, onClose }: FileViewerProps) {
       setLoading(true);
       setError(null);
       setContent(null);
+      setLanguage(null);
+      setLineCount(null);
 
       try {
         const response = await fetchFsFile(turnId, filePath);
         if (!cancelled) {
           // Decode base64 content
           const decoded = atob(response.content_base64);
-          setContent(decoded);
+          setContent(response.binary ? null : decoded);
           setFileSize(response.size);
+          setLanguage(response.binary ? 'binary' : response.language ?? null);
+          setLineCount(response.line_count ?? null);
         }
       } catch (err) {
         if (!cancelled) {
@@ -87,6 +93,14 @@ export function FileViewer({ turnId, filePath, onClose }: FileViewerProps) {
           </span>
         </div>
         <div className="flex items-center gap-3 flex-shrink-0">
+          {language && (
+            <span className="text-xs text-theme-text-dim font-mono">{language}</span>
+          )}
+          {lineCount !== null && (
+            <span className="text-xs text-theme-text-dim">
+              {lineCount} {lineCount === 1 ? 'line' : 'lines'}
+            </span>
+          )}
           {fileSize > 0 && (
             <span className="text-xs text-theme-text-dim">
               {formatFileSize(fileSize)}
@@ -113,6 +127,10 @@ export function FileViewer({ turnId, filePath, onClose }: FileViewerProps) {
             <AlertCircle className="w-6 h-6 mb-2" />
             <span className="text-sm">{error}</span>
           </div>
+        ) : content === null ? (
+          <div className="flex items-center justify-center h-full text-sm text-theme-text-dim">
+            Binary file not shown
+          </div>
         ) : (
           <pre className="text-sm text-theme-text-secondary font-mono whitespace-pre-wrap break-words leading-relaxed">
             {content}
@@ -0,0 +1,89 @@
+'use client';
+
+import { useState, useEffect } from 'react';
+import { cn } from '@/lib/utils';
+import { fetchHeadHistory } from '@/lib/api';
+import type { HeadHistoryResponse } from '@/types';
+import { Sparkline } from './dashboard/Sparkline';
+
+interface HeadActivityProps {
+  contextId: string;
+  className?: string;
+}
+
+const HOUR_MS = 60 * 60 * 1000;
+
+// Pick a window so a context's lifetime spans at most ~60 buckets.
+function bucketFor(spanMs: number): number {
+  const steps = [60 * 1000, 5 * 60 * 1000, 15 * 60 * 1000, HOUR_MS, 6 * HOUR_MS, 24 * HOUR_MS];
+  return steps.find((step) => spanMs / step <= 60) ?? 7 * 24 * HOUR_MS;
+}
+
+/**
+ * Activity over time for a context: head moves per time window, with
+ * rewinds and re-parents (branched moves) called out.
+ */
+export function HeadActivity({ contextId, className }: HeadActivityProps) {
+  const [history, setHistory] = useState<HeadHistoryResponse | null>(null);
+  const [error, setError] = useState<string | null>(null);
+
+  useEffect(() => {
+    let cancelled = false;
+    setError(null);
+
+    fetchHeadHistory(contextId)
+      .then((response) => {
+        const times = response.moves.map((m) => m.at_unix_ms);
+        const span = times.length > 0 ? Math.max(...times) - Math.min(...times) : 0;
+        return fetchHeadHistory(contextId, bucketFor(span));
+      })
+      .then((response) => {
+        if (!cancelled) setHistory(response);
+      })
+      .catch((err) => {
+        if (!cancelled) setError(err.message || 'Failed to load head history');
+      });
+
+    return () => {
+      cancelled = true;
+    };
+  }, [contextId]);
+
+  if (error) {
+    return <div className={cn('px-3 py-2 text-xs text-amber-400', className)}>{error}</div>;
+  }
+  if (!history) return null;
+
+  const buckets = history.buckets ?? [];
+  const branched = history.moves.filter((m) => m.cause === 'branched');
+
+  return (
+    <div className={cn('px-3 py-2', className)}>
+      <div className="flex items-center justify-between">
+        <span className="text-xs font-medium text-theme-text-muted uppercase tracking-wide">Activity</span>
+        <span className="text-xs text-theme-text-dim">
+          {history.total} head moves
+          {branched.length > 0 && `, ${branched.length} branched`}
+        </span>
+      </div>
+      <Sparkline
+        values={buckets.map((b) => b.moves)}
+        width={240}
+        height={32}
+        color="#a855f7"
+        className="mt-2 w-full"
+      />
+      {branched.length > 0 && (
+        <ul className="mt-2 space-y-0.5">
+          {branched.slice(-5).map((m) => (
+            <li key={`${m.turn_id}-${m.at_unix_ms}`} className="text-xs text-theme-text-dim font-mono">
+              {new Date(m.at_unix_ms).toLocaleString()} — branched to turn {m.turn_id} (depth {m.depth})
+            </li>
+          ))}
+        </ul>
+      )}
+    </div>
+  );
+}
+
+export default HeadActivity;
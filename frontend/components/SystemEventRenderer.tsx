@@ -0,0 +1,76 @@
+'use client';
+
+import { cn, formatTime } from '@/lib/utils';
+import { ArrowRight, Folder, GitFork, Info, Lock } from './icons';
+import type { TurnRendererProps } from '@/lib/renderer-registry';
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/** Projected payload of a server-appended cxdb:SystemEvent turn. */
+export interface SystemEvent {
+  kind: string;
+  summary: string;
+  occurred_at?: string | number;
+  context_id?: string | number;
+  turn_id?: string | number;
+  principal?: string;
+  attrs?: Record<string, string>;
+}
+
+export function isSystemEvent(data: unknown): data is SystemEvent {
+  if (!data || typeof data !== 'object') return false;
+  const obj = data as Record<string, unknown>;
+  return typeof obj.kind === 'string' && typeof obj.summary === 'string';
+}
+
+const KIND_ICONS: Record<string, typeof Info> = {
+  context_forked: GitFork,
+  fs_attached: Folder,
+  hold_placed: Lock,
+  hold_released: Lock,
+  context_rolled_over: ArrowRight,
+};
+
+function occurredAt(ts: string | number): string {
+  const num = typeof ts === 'string' && /^\d+$/.test(ts) ? parseInt(ts, 10) : ts;
+  return formatTime(num);
+}
+
+// ============================================================================
+// Renderer
+// ============================================================================
+
+export function SystemEventRenderer({ event, className }: { event: SystemEvent; className?: string }) {
+  const Icon = KIND_ICONS[event.kind] ?? Info;
+
+  return (
+    <div
+      className={cn(
+        'flex items-center gap-2 px-3 py-1.5 text-xs text-theme-text-muted',
+        'border-y border-dashed border-theme-border/50',
+        className
+      )}
+    >
+      <Icon className="w-3.5 h-3.5 shrink-0 text-theme-text-dim" />
+      <span className="font-mono text-theme-text-dim">{event.kind}</span>
+      <span className="truncate">{event.summary}</span>
+      {event.principal && <span className="text-theme-text-dim">by {event.principal}</span>}
+      {event.occurred_at !== undefined && (
+        <span className="ml-auto shrink-0 text-theme-text-dim">{occurredAt(event.occurred_at)}</span>
+      )}
+    </div>
+  );
+}
+
+/**
+ * Wrapper for SystemEventRenderer that accepts standard TurnRendererProps.
+ * Used by the dynamic renderer registry.
+ */
+export function SystemEventRendererWrapper({ data, className }: TurnRendererProps) {
+  if (isSystemEvent(data)) {
+    return <SystemEventRenderer event={data} className={className} />;
+  }
+  return null;
+}
@@ -1,4 +1,4 @@
-import type { TurnResponse, FetchTurnsOptions, ErrorResponse, ContextEntry, SessionInfo, Provenance } from '@/types';
+import type { TurnResponse, FetchTurnsOptions, ErrorResponse, ContextEntry, SessionInfo, Provenance, HeadHistoryResponse } from '@/types';
 import type { FsListResponse, FsFileResponse } from '@/types/filesystem';
 
 const API_BASE = '/v1';
@@ -57,6 +57,9 @@ export async function fetchTurns(
   if (options.include_unknown !== undefined) {
     params.set('include_unknown', String(options.include_unknown));
   }
+  if (options.apply_defaults) {
+    params.set('apply_defaults', '1');
+  }
 
   const queryString = params.toString();
   const url = `${API_BASE}/contexts/${encodeURIComponent(contextId)}/turns${queryString ? `?${queryString}` : ''}`;
@@ -243,13 +246,13 @@ export async function fetchFsDirectory(
 
 /**
  * Fetch filesystem file content for a turn.
- * Returns file metadata and base64-encoded content.
+ * Returns file metadata, detected language and base64-encoded content.
  */
 export async function fetchFsFile(
   turnId: string,
   filePath: string
 ): Promise<FsFileResponse> {
-  const url = `${API_BASE}/turns/${encodeURIComponent(turnId)}/fs/${filePath}?format=json`;
+  const url = `${API_BASE}/turns/${encodeURIComponent(turnId)}/fs/${filePath}?format=json&detect=1`;
 
   const response = await fetch(url);
 
@@ -302,6 +305,36 @@ export async function fetchProvenance(contextId: string): Promise<ProvenanceResp
   return response.json();
 }
 
+/**
+ * Fetch how a context's head moved over time, with moves counted per
+ * `bucketMs` window when given.
+ */
+export async function fetchHeadHistory(contextId: string, bucketMs?: number): Promise<HeadHistoryResponse> {
+  const params = new URLSearchParams();
+  if (bucketMs !== undefined) {
+    params.set('bucket_ms', String(bucketMs));
+  }
+  const query = params.toString();
+  const url = `${API_BASE}/contexts/${encodeURIComponent(contextId)}/head-history${query ? `?${query}` : ''}`;
+  const response = await fetch(url);
+
+  if (!response.ok) {
+    let errorData: ErrorResponse | undefined;
+    try {
+      errorData = await response.json();
+    } catch {
+      // Ignore JSON parse errors
+    }
+    throw new ApiError(
+      errorData?.error?.message || `HTTP ${response.status}`,
+      errorData?.error?.code || response.status,
+      errorData
+    );
+  }
+
+  return response.json();
+}
+
 // ============================================================================
 // Renderer Manifest
 // ============================================================================
@@ -9,10 +9,12 @@ const KEYWORDS: Record<string, TokenType> = {
   'OR': 'OR',
   'NOT': 'NOT',
   'IN': 'IN',
+  'CONTAINS': 'CONTAINS',
   'and': 'AND',
   'or': 'OR',
   'not': 'NOT',
   'in': 'IN',
+  'contains': 'CONTAINS',
 };
 
 export class Lexer {
@@ -313,6 +313,9 @@ export class Parser {
       case 'IN':
         this.advance();
         return { ok: true, value: 'in' };
+      case 'CONTAINS':
+        this.advance();
+        return { ok: true, value: 'contains' };
       default:
         return {
           ok: false,
@@ -334,8 +337,8 @@ export class Parser {
 
       // Check if it's a date value for date fields
       if (fieldType === 'date') {
-        // Relative date patterns: -24h, -7d, -30m
-        const relativePattern = /^-(\d+)([hdm])$/;
+        // Relative date patterns: -24h, -7d, -30m, +1d
+        const relativePattern = /^[-+](\d+)([hdm])$/;
         if (relativePattern.test(strValue)) {
           return {
             ok: true,
@@ -11,6 +11,7 @@ export type TokenType =
   | 'OR'
   | 'NOT'
   | 'IN'
+  | 'CONTAINS'
   | 'LPAREN'
   | 'RPAREN'
   | 'COMMA'
@@ -74,7 +75,8 @@ export type Operator =
   | 'gte'       // >=
   | 'lt'        // <
   | 'lte'       // <=
-  | 'in';       // IN
+  | 'in'        // IN
+  | 'contains'; // CONTAINS
 
 export type Value =
   | StringValue
@@ -139,8 +141,19 @@ export const VALID_FIELDS = [
   'parent',
   'root',
   'created',
+  'expires',
   'depth',
+  'tokens',
   'is_live',
+  'has_fs',
+  'author',
+  'author_tag',
+  'fs_count',
+  'fs_bytes',
+  'on_hold',
+  'project',
+  'external_id',
+  'preview',
 ] as const;
 
 export type FieldName = typeof VALID_FIELDS[number];
@@ -220,16 +233,82 @@ export const FIELD_METADATA: Record<FieldName, FieldMeta> = {
     operators: ['eq', 'neq', 'gt', 'gte', 'lt', 'lte'],
     description: 'Creation timestamp (supports relative dates like "-24h")',
   },
+  expires: {
+    name: 'expires',
+    type: 'date',
+    operators: ['eq', 'neq', 'gt', 'gte', 'lt', 'lte'],
+    description: 'Expiry time of contexts created with a TTL (e.g. "+1d", "-0d")',
+  },
   depth: {
     name: 'depth',
     type: 'number',
     operators: ['eq', 'neq', 'gt', 'gte', 'lt', 'lte'],
     description: 'Depth of the head turn in the context',
   },
+  tokens: {
+    name: 'tokens',
+    type: 'number',
+    operators: ['eq', 'neq', 'gt', 'gte', 'lt', 'lte'],
+    description: 'Counted tokens along the head chain of the context',
+  },
   is_live: {
     name: 'is_live',
     type: 'boolean',
     operators: ['eq'],
     description: 'Whether context has active SSE connections',
   },
+  has_fs: {
+    name: 'has_fs',
+    type: 'boolean',
+    operators: ['eq'],
+    description: 'Whether the head turn sees a filesystem snapshot',
+  },
+  author: {
+    name: 'author',
+    type: 'string',
+    operators: ['eq', 'neq', 'in'],
+    description: 'Principal of a session that appended a turn on the head chain',
+  },
+  author_tag: {
+    name: 'author_tag',
+    type: 'string',
+    operators: ['eq', 'neq', 'in'],
+    description: 'Client tag of a session that appended a turn on the head chain',
+  },
+  fs_count: {
+    name: 'fs_count',
+    type: 'number',
+    operators: ['eq', 'neq', 'gt', 'gte', 'lt', 'lte'],
+    description: 'Filesystem snapshots attached along the head chain',
+  },
+  fs_bytes: {
+    name: 'fs_bytes',
+    type: 'number',
+    operators: ['eq', 'neq', 'gt', 'gte', 'lt', 'lte'],
+    description: 'File bytes of the largest snapshot along the head chain',
+  },
+  on_hold: {
+    name: 'on_hold',
+    type: 'boolean',
+    operators: ['eq'],
+    description: 'Whether the context is on legal hold',
+  },
+  project: {
+    name: 'project',
+    type: 'string',
+    operators: ['eq', 'neq', 'in'],
+    description: 'A project the context is assigned to',
+  },
+  external_id: {
+    name: 'external_id',
+    type: 'string',
+    operators: ['eq', 'neq', 'in'],
+    description: 'External id the context was created with',
+  },
+  preview: {
+    name: 'preview',
+    type: 'string',
+    operators: ['contains'],
+    description: 'Latest summary field text on the head chain',
+  },
 };
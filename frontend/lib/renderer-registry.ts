@@ -36,6 +36,10 @@ export interface RendererSpec {
   component?: string;
   /** Subresource integrity hash for security */
   integrity?: string;
+  /** Template for server-side text rendering (view=text) */
+  text_template?: string;
+  /** Same-origin URL the server proxies the module from, when enabled */
+  asset_url?: string;
 }
 
 /**
@@ -76,6 +80,11 @@ const BUILTIN_RENDERERS: Record<string, () => Promise<{ default: ComponentType<T
     default: m.QuestSnapshotRendererWrapper
   })),
 
+  // Server-appended milestones (cxdb:SystemEvent)
+  SystemEventRenderer: () => import('@/components/SystemEventRenderer').then(m => ({
+    default: m.SystemEventRendererWrapper
+  })),
+
   // Fallback renderer for unknown types
   FallbackRenderer: () => import('@/components/FallbackRenderer').then(m => ({
     default: m.FallbackRendererWrapper
@@ -187,8 +196,11 @@ async function loadRendererInternal(
     return loadedModule.default;
   }
 
+  // Prefer the server's proxied copy, which it verified against the integrity hash
+  const moduleUrl = spec.asset_url ?? esm_url;
+
   // Validate external URL
-  if (!isAllowedOrigin(esm_url)) {
+  if (!spec.asset_url && !isAllowedOrigin(esm_url)) {
     throw new Error(
       `Renderer URL not from allowed origin: ${esm_url}. ` +
       `Allowed origins: ${ALLOWED_ORIGINS.join(', ')}`
@@ -197,7 +209,7 @@ async function loadRendererInternal(
 
   // Dynamic import from external URL
   // Note: This uses the browser's native ESM loader
-  const loadedModule = await import(/* webpackIgnore: true */ esm_url);
+  const loadedModule = await import(/* webpackIgnore: true */ moduleUrl);
 
   // Get the specified export
   const exportedComponent = loadedModule[component];
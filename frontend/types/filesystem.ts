@@ -6,12 +6,27 @@ export interface FsEntry {
   mode: string;
   size: number;
   hash: string;
+  // Files only: any execute bit is set
+  executable?: boolean;
+  // Symlinks only: link target, null if unreadable
+  target?: string | null;
+}
+
+export interface FsSnapshotInfo {
+  attached_turn_id: string;
+  captured_at?: number;
+  base_path?: string;
+  git_commit?: string;
+  git_branch?: string;
+  git_dirty?: boolean;
+  total_entries?: number;
 }
 
 export interface FsListResponse {
   turn_id: string;
   path: string;
   fs_root_hash: string;
+  snapshot: FsSnapshotInfo;
   entries: FsEntry[];
 }
 
@@ -23,5 +38,45 @@ export interface FsFileResponse {
   mode: string;
   size: number;
   hash: string;
+  executable?: boolean;
+  target?: string | null;
+  /** Present when requested with `detect=1`. */
+  binary?: boolean;
+  language?: string | null;
+  line_count?: number | null;
   content_base64: string;
 }
+
+export interface FsManifestEntry {
+  path: string;
+  kind: 'file' | 'dir' | 'symlink';
+  mode: string;
+  // 0 for directories and symlinks
+  size: number;
+  hash: string;
+  // Symlinks only
+  target?: string;
+}
+
+export interface FsFetchBlob {
+  hash: string;
+  size: number;
+  paths: string[];
+  url: string;
+}
+
+export interface FsManifestResponse {
+  turn_id: string;
+  fs_root_hash: string;
+  snapshot: FsSnapshotInfo;
+  entries: FsManifestEntry[];
+  fetch: FsFetchBlob[];
+  totals: {
+    files: number;
+    dirs: number;
+    symlinks: number;
+    bytes: number;
+    unique_blobs: number;
+    unique_bytes: number;
+  };
+}
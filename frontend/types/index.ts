@@ -13,7 +13,25 @@ export interface Turn {
   decoded_as?: DeclaredType;
   data?: Record<string, unknown>;
   unknown?: Record<string, unknown>;
+  defaulted?: string[]; // fields filled from descriptor defaults when apply_defaults is set
   raw?: string; // base64-encoded raw payload when view=raw or view=both
+  text?: string; // Markdown rendering when view=text
+  tokens?: number; // counted tokens of annotated fields
+  provenance?: TurnProvenance; // appending session, when include_provenance=1
+  ingest?: TurnIngest; // payload as sent, when ingest transformers rewrote it
+  bookmarks?: BookmarkMarker[]; // bookmarks on this turn
+}
+
+export interface TurnIngest {
+  original_hash: string; // hex BLAKE3 hash of the payload the client sent
+  original_len: number;
+  transformers: string[]; // transformers that changed the payload, in order
+}
+
+export interface TurnProvenance {
+  session_id: string;
+  client_tag?: string;
+  principal?: string;
 }
 
 export interface ContextMeta {
@@ -21,6 +39,22 @@ export interface ContextMeta {
   head_turn_id: string;
   head_depth: number;
   registry_bundle_id?: string;
+  bookmarks?: BookmarkMarker[]; // every bookmark of the context, in timeline order
+}
+
+// A bookmark as a jump point in turns responses
+export interface BookmarkMarker {
+  bookmark_id: string;
+  turn_id: string;
+  depth: number;
+  name: string;
+}
+
+export interface Bookmark extends BookmarkMarker {
+  context_id: string;
+  note: string | null;
+  author: string | null;
+  created_at_unix_ms: number;
 }
 
 export interface TurnResponse {
@@ -42,13 +76,14 @@ export interface ErrorResponse {
 export interface FetchTurnsOptions {
   limit?: number;
   before_turn_id?: string;
-  view?: 'typed' | 'raw' | 'both';
+  view?: 'typed' | 'raw' | 'both' | 'text';
   type_hint_mode?: 'inherit' | 'latest' | 'explicit';
   bytes_render?: 'base64' | 'hex' | 'len_only';
   u64_format?: 'string' | 'number';
   enum_render?: 'label' | 'number' | 'both';
   time_render?: 'iso' | 'unix_ms';
   include_unknown?: boolean;
+  apply_defaults?: boolean;
 }
 
 // Debug event types for the Context Debugger
@@ -94,6 +129,18 @@ export interface ContextEntry {
   client_tag?: string;
   is_live?: boolean;
   last_activity_at?: number;
+  // Read tracking for the signed-in user
+  unread_turns?: number;
+  last_viewed_turn_id?: string;
+  // Counted tokens along the head chain
+  tokens?: number;
+  // Tail of the latest summary_field value along the head chain
+  preview?: string;
+  // Turns the head chain may hold, when a depth limit applies
+  depth_limit?: number;
+  // Context this one was rolled over into, or continues
+  rolled_over_to?: string;
+  rolled_over_from?: string;
   // Filesystem snapshot indicator
   has_fs_snapshot?: boolean;
   // Context metadata (from first turn)
@@ -101,6 +148,91 @@ export interface ContextEntry {
   labels?: string[];
   // Provenance (origin story)
   provenance?: import('./provenance').Provenance;
+  // Current viewers (GET /v1/contexts/:id only)
+  viewers?: ContextViewer[];
+  // Legal hold, when the context is held
+  hold?: ContextHold;
+  // Caller-assigned id given at creation
+  external_id?: string;
+  // When the context expires, if it was created with a TTL
+  expires_at?: number;
+  // Transient progress reported by the writer
+  status?: ProgressStatus;
+}
+
+// A client-reported progress status (in memory only)
+export interface ProgressStatus {
+  status: string;
+  // Fraction done, 0-1
+  progress?: number;
+  updated_at: number;
+}
+
+// One move of a context's head (GET /v1/contexts/:id/head-history)
+export interface HeadMove {
+  turn_id: string;
+  depth: number;
+  at_unix_ms: number;
+  cause: 'created' | 'forked' | 'appended' | 'branched';
+}
+
+// Head moves per time window
+export interface HeadActivityBucket {
+  start_unix_ms: number;
+  moves: number;
+  branches: number;
+}
+
+export interface HeadHistoryResponse {
+  context_id: string;
+  moves: HeadMove[];
+  total: number;
+  buckets?: HeadActivityBucket[];
+}
+
+// A legal hold placement or release
+export interface ContextHold {
+  action: 'place' | 'release';
+  reason: string;
+  principal: string;
+  at_unix_ms: number;
+}
+
+// Result of POST /v1/contexts/:id/rollover
+export interface ContextRollover {
+  context_id: string;
+  rolled_over_to: string;
+  digest_turn_id: string;
+  summarized_through_turn_id: string;
+  generator: string;
+  at_unix_ms: number;
+}
+
+// A context in its lineage tree
+export interface ContextTreeNode {
+  context_id: string;
+  head_depth: number;
+  title?: string;
+  spawn_reason?: string;
+  rolled_over_from?: string;
+  rolled_over_to?: string;
+  children: ContextTreeNode[];
+}
+
+// Response of GET /v1/contexts/:id/tree
+export interface ContextTreeResponse {
+  context_id: string;
+  root: ContextTreeNode;
+  truncated: boolean;
+}
+
+// A principal or anonymous client viewing a context
+export interface ContextViewer {
+  principal?: string;
+  streams: number;
+  polling: boolean;
+  since_unix_ms: number;
+  last_seen_unix_ms: number;
 }
 
 // ============================================
@@ -114,6 +246,7 @@ export interface SessionInfo {
   connected_at: number;
   last_activity_at: number;
   context_count: number;
+  status?: ProgressStatus;
 }
 
 // SSE event types
@@ -122,6 +255,7 @@ export interface ContextCreatedEvent {
   session_id?: string;
   client_tag?: string;
   created_at: number;
+  external_id?: string;
 }
 
 export interface TurnAppendedEvent {
@@ -152,13 +286,119 @@ export interface ClientDisconnectedEvent {
   contexts: string[];
 }
 
+export interface PresenceChangedEvent {
+  context_id: string;
+  viewers: number;
+  principals: string[];
+}
+
+export interface ProjectCreatedEvent {
+  project_id: string;
+  name: string;
+  principal?: string;
+}
+
+export interface ContextExpiredEvent {
+  context_id: string;
+  expires_at: number;
+  expired_at: number;
+}
+
+export interface StatusChangedEvent {
+  context_id?: string;
+  session_id?: string;
+  // null once the status was cleared
+  status: string | null;
+  progress?: number;
+  updated_at: number;
+}
+
+export interface SubscriptionNotifiedEvent {
+  subscription_id: string;
+  context_id: string;
+  // Email-like sink identifier of the subscription
+  target: string;
+  turn_id: string;
+  declared_type_id?: string;
+}
+
+export interface OwnershipChangedEvent {
+  context_id: string;
+  previous_owner?: string;
+  owner: string;
+  // Principal that made the transfer
+  principal: string;
+}
+
+export interface TagsChangedEvent {
+  operation: 'rename' | 'merge';
+  sources: string[];
+  target: string;
+  // Ids of the retagged contexts
+  contexts: string[];
+  principal?: string;
+}
+
+export interface QuotaWarningEvent {
+  tag: string;
+  resource: 'contexts' | 'turns' | 'bytes';
+  level: 'warning' | 'exceeded';
+  usage: number;
+  limit: number;
+  percent: number;
+}
+
+export interface DepthWarningEvent {
+  context_id: string;
+  depth: number;
+  limit: number;
+  level: 'warning' | 'exceeded';
+  percent: number;
+}
+
+export interface BookmarkAddedEvent {
+  context_id: string;
+  bookmark_id: string;
+  turn_id: string;
+  depth: number;
+  name: string;
+  author?: string;
+}
+
+export interface BookmarkDeletedEvent {
+  context_id: string;
+  bookmark_id: string;
+  turn_id: string;
+  // Principal that deleted the bookmark
+  principal?: string;
+}
+
+export interface ProjectAssignedEvent {
+  project_id: string;
+  context_id: string;
+  principal?: string;
+}
+
 // Union type for all SSE events
 export type StoreEvent =
   | { type: 'context_created'; data: ContextCreatedEvent }
   | { type: 'context_metadata_updated'; data: ContextMetadataUpdatedEvent }
   | { type: 'turn_appended'; data: TurnAppendedEvent }
   | { type: 'client_connected'; data: ClientConnectedEvent }
-  | { type: 'client_disconnected'; data: ClientDisconnectedEvent };
+  | { type: 'client_disconnected'; data: ClientDisconnectedEvent }
+  | { type: 'presence_changed'; data: PresenceChangedEvent }
+  | { type: 'project_created'; data: ProjectCreatedEvent }
+  | { type: 'project_assigned'; data: ProjectAssignedEvent }
+  | { type: 'project_unassigned'; data: ProjectAssignedEvent }
+  | { type: 'context_expired'; data: ContextExpiredEvent }
+  | { type: 'status_changed'; data: StatusChangedEvent }
+  | { type: 'subscription_notified'; data: SubscriptionNotifiedEvent }
+  | { type: 'ownership_changed'; data: OwnershipChangedEvent }
+  | { type: 'tags_changed'; data: TagsChangedEvent }
+  | { type: 'quota_warning'; data: QuotaWarningEvent }
+  | { type: 'depth_warning'; data: DepthWarningEvent }
+  | { type: 'bookmark_added'; data: BookmarkAddedEvent }
+  | { type: 'bookmark_deleted'; data: BookmarkDeletedEvent };
 
 // Activity feed item (derived from SSE events)
 export interface ActivityItem {
@@ -177,6 +417,16 @@ export interface ContextsResponse {
   active_tags?: string[];
 }
 
+// Entry for an id that could not be fetched by POST /v1/contexts/batch-get
+export interface ContextBatchError {
+  context_id: string;
+  error: { code: number; message: string };
+}
+
+export interface ContextBatchResponse {
+  contexts: (ContextEntry | ContextBatchError)[];
+}
+
 // ============================================
 // Server Metrics Types (Sprint 008)
 // ============================================
@@ -232,6 +482,9 @@ export interface StorageMetrics {
   heads_table_bytes: number;
   blobs_pack_bytes: number;
   blobs_index_bytes: number;
+  // Pack bytes of referenced and unreferenced blobs
+  blobs_live_bytes?: number;
+  blobs_dead_bytes?: number;
   data_dir_total_bytes: number;
   data_dir_free_bytes: number;
 }
@@ -270,6 +523,198 @@ export interface FilesystemMetrics {
   content_bytes: number;
 }
 
+export interface HistogramSummary {
+  count: number;
+  sum_ms: number;
+  mean_ms: number | null;
+  p50: number | null;
+  p90: number | null;
+  p95: number | null;
+  p99: number | null;
+  p999: number | null;
+  max: number | null;
+  buckets: { le_ms: number; count: number }[];
+}
+
+export interface WindowedSummary {
+  window_5m: HistogramSummary;
+  lifetime: HistogramSummary;
+}
+
+export interface HttpLatency extends WindowedSummary {
+  method: string;
+  route: string;
+  status: number;
+}
+
+export interface LatencyMetrics {
+  operations: Record<string, WindowedSummary>;
+  http: HttpLatency[];
+}
+
+export interface DedupMetrics {
+  payload_refs: number;
+  unique_payloads: number;
+  hits_total: number;
+  hit_rate: number;
+  logical_bytes: number;
+  unique_bytes: number;
+  saved_bytes: number;
+}
+
+// Load attributed to one client tag ("_untagged" or "_other" past the cap)
+export interface TagSummary {
+  tag: string;
+  appends: number;
+  append_bytes: number;
+  reads: number;
+  read_bytes: number;
+  read_latency: WindowedSummary;
+}
+
+export interface TagMetrics {
+  max_tags: number;
+  tags: TagSummary[];
+  heaviest: { tag: string; total_bytes: number; appends: number; reads: number }[];
+}
+
+// Binary protocol frames of one message type
+export interface MessageSummary {
+  msg_type: string;
+  count: number;
+  errors: number;
+  slow: number;
+  request_bytes: number;
+  response_bytes: number;
+  max_request_bytes: number;
+  max_response_bytes: number;
+  latency: WindowedSummary;
+}
+
+// Counted tokens overall and per client tag
+export interface TokenStats {
+  tokenizer?: string;
+  total: number;
+  contexts: number;
+  by_tag: { tag: string; tokens: number; contexts: number }[];
+}
+
+export interface EventBusStats {
+  active_streams: number;
+  max_streams: number;
+  slow_consumers: number;
+  queued_events: number;
+  dropped_events_total: number;
+  overflow_disconnects_total: number;
+  rejected_streams_total: number;
+  // Present when an event sink is configured
+  sink?: SinkStats;
+}
+
+// Event sink outbox backlog and delivery counters
+export interface SinkStats {
+  sink: string;
+  head_seq: number;
+  acked_seq: number;
+  pending: number;
+  lag_ms: number;
+  published_total: number;
+  failures_total: number;
+  last_error?: string;
+}
+
+// Metadata cache accounting
+export interface MetadataCacheStats {
+  entries: number;
+  bytes: number;
+  budget_bytes?: number;
+  hits: number;
+  misses: number;
+  evictions: number;
+}
+
+// CQL secondary indexes and the metadata cache
+export interface IndexStats {
+  contexts_indexed: number;
+  tag_entries: number;
+  title_entries: number;
+  user_entries: number;
+  service_entries: number;
+  host_entries: number;
+  label_namespace_entries: number;
+  custom_key_entries: number;
+  created_entries: number;
+  memory_bytes: number;
+  text_bytes: number;
+  text_budget_bytes?: number;
+  spilled_contexts: number;
+  metadata_cache: MetadataCacheStats;
+}
+
+// Payload cache and read-ahead for turn pagination
+export interface PayloadCacheStats {
+  entries: number;
+  bytes: number;
+  budget_bytes: number;
+  hits: number;
+  misses: number;
+  hit_rate: number;
+  evictions: number;
+  prefetched: number;
+}
+
+// Group commit batching and sync latency
+export interface CommitStats {
+  window_us: number;
+  commits_total: number;
+  batches_total: number;
+  mean_batch_size: number;
+  max_batch_size: number;
+  sync_failures_total: number;
+  /** Sync error that poisoned the pipeline; commits fail until restart */
+  failed?: string;
+  sync_ms: WindowedSummary;
+  wait_ms: WindowedSummary;
+  tracked_files: string[];
+}
+
+// Client tag quota usage over the rolling window
+export interface ResourceUsage {
+  usage: number;
+  limit?: number;
+  percent?: number;
+  level: 'ok' | 'warning' | 'exceeded';
+}
+
+export interface QuotaReport {
+  window_secs: number;
+  warn_at: number;
+  tags: { tag: string; contexts: ResourceUsage; turns: ResourceUsage; bytes: ResourceUsage }[];
+}
+
+// Compressed HTTP responses of one content encoding
+export interface HttpCompressionMetrics {
+  responses: number;
+  input_bytes: number;
+  output_bytes: number;
+  saved_bytes: number;
+  duration_seconds: number;
+}
+
+// Stored data in one age bucket ("0-30d", "30-90d", "90-180d", "180d+")
+export interface AgeBucket {
+  age: string;
+  contexts: number;
+  turns: number;
+  bytes: number;
+}
+
+export interface DataAgeStats {
+  computed_at_unix_ms: number;
+  buckets: AgeBucket[];
+  by_tag: { tag: string; buckets: AgeBucket[] }[];
+}
+
 export interface MetricsSnapshot {
   ts: string;
   uptime_seconds: number;
@@ -278,6 +723,18 @@ export interface MetricsSnapshot {
   objects: ObjectMetrics;
   storage: StorageMetrics;
   filesystem: FilesystemMetrics;
+  dedup: DedupMetrics;
   perf: PerfMetrics;
+  latency: LatencyMetrics;
+  tags?: TagMetrics;
+  protocol?: MessageSummary[];
+  tokens?: TokenStats;
+  events: EventBusStats;
+  indexes?: IndexStats;
+  payload_cache?: PayloadCacheStats;
+  commit?: CommitStats | null;
+  quotas?: QuotaReport | null;
+  http_compression?: Record<string, HttpCompressionMetrics>;
+  data_age?: DataAgeStats;
   errors: ErrorMetrics;
 }
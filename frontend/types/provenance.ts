@@ -86,6 +86,12 @@ export interface Provenance {
   /** Client's source port (set by server). */
   client_port?: number;
 
+  /** Peer uid of a Unix socket connection (set by server). */
+  client_uid?: number;
+
+  /** Peer gid of a Unix socket connection (set by server). */
+  client_gid?: number;
+
   // === Environment Context ===
 
   /** Selected environment variables (from allowlist). */
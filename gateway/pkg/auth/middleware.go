@@ -106,11 +106,15 @@ func RequireAuthForReadsWithOptions(opts AuthMiddlewareOptions, next http.Handle
 	return http.HandlerFunc(func(w http.ResponseWriter, r *http.Request) {
 		path := r.URL.Path
 
-		// Always allow non-GET methods (anonymous writes)
+		// Always allow non-GET methods (anonymous writes). A browser session,
+		// when present, still identifies the user (e.g. for mark-read).
 		if r.Method != http.MethodGet && r.Method != http.MethodHead {
 			if store.Debug() {
 				log.Printf("[auth] allowing write method %s %s", r.Method, path)
 			}
+			if sess, _ := store.SessionFromRequest(r.Context(), r); sess != nil {
+				r = r.WithContext(WithUser(r.Context(), sess))
+			}
 			next.ServeHTTP(w, r)
 			return
 		}
@@ -124,6 +128,17 @@ func RequireAuthForReadsWithOptions(opts AuthMiddlewareOptions, next http.Handle
 			return
 		}
 
+		// Share links carry their own read grant. The backend verifies the
+		// token and confines the request to the shared context, so no
+		// session (and no principal) is attached.
+		if strings.HasPrefix(path, "/v1/") && hasShareToken(r) {
+			if store.Debug() {
+				log.Printf("[auth] share token read %s", path)
+			}
+			next.ServeHTTP(w, r)
+			return
+		}
+
 		sess, _ := store.SessionFromRequest(r.Context(), r)
 
 		// Try bearer token authentication (K8s OIDC, AWS IAM, etc.)
@@ -204,6 +219,12 @@ func extractBearerToken(r *http.Request) string {
 	return ""
 }
 
+// hasShareToken reports whether the request presents a share link token,
+// in the X-CXDB-Share header or the share query parameter.
+func hasShareToken(r *http.Request) bool {
+	return r.Header.Get("X-CXDB-Share") != "" || r.URL.Query().Get("share") != ""
+}
+
 // isAPIRequest returns true if the request appears to be an API request
 // (should get 401 instead of redirect on auth failure).
 func isAPIRequest(r *http.Request) bool {
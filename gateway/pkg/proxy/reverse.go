@@ -11,8 +11,14 @@ import (
 	"net/url"
 	"strings"
 	"time"
+
+	"github.com/strongdm/cxdb/gateway/pkg/auth"
 )
 
+// PrincipalHeader names the authenticated user for the backend. Any value
+// sent by the client is replaced.
+const PrincipalHeader = "X-CXDB-Principal"
+
 // ReverseProxy wraps httputil.ReverseProxy with additional configuration.
 type ReverseProxy struct {
 	proxy  *httputil.ReverseProxy
@@ -58,6 +64,12 @@ func NewReverseProxy(backendURL string, logger *slog.Logger) (*ReverseProxy, err
 		if req.Header.Get("X-Forwarded-Host") == "" {
 			req.Header.Set("X-Forwarded-Host", req.Host)
 		}
+
+		// Forward the authenticated user
+		req.Header.Del(PrincipalHeader)
+		if user := auth.UserFromContext(req.Context()); user != nil && user.Email != "" {
+			req.Header.Set(PrincipalHeader, user.Email)
+		}
 	}
 
 	// Custom error handler
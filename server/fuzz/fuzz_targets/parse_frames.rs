@@ -0,0 +1,37 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Feeds arbitrary bytes to the frame reader and every payload parser.
+//! Parsers must return `Ok` or `Err`; panics, aborts and large allocations
+//! driven by embedded lengths are bugs.
+
+#![no_main]
+
+use cxdb_server::protocol::{
+    parse_append_turn, parse_attach_fs, parse_attach_fs_overlay, parse_ctx_create, parse_ctx_fork,
+    parse_get_blob, parse_get_head, parse_get_last, parse_hello, parse_put_blob,
+    parse_update_session_status, read_frame, GetLastResponse, WireStruct,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = read_frame(&mut &data[..]);
+
+    // First two bytes select the frame flags; the rest is the payload.
+    let (flags, payload) = match data {
+        [a, b, rest @ ..] => (u16::from_le_bytes([*a, *b]), rest),
+        _ => (0, data),
+    };
+    let _ = parse_hello(payload);
+    let _ = parse_ctx_create(payload, flags);
+    let _ = parse_ctx_fork(payload);
+    let _ = parse_get_head(payload);
+    let _ = parse_append_turn(payload, flags);
+    let _ = parse_get_last(payload);
+    let _ = parse_get_blob(payload);
+    let _ = parse_attach_fs(payload, flags);
+    let _ = parse_attach_fs_overlay(payload);
+    let _ = parse_put_blob(payload, flags);
+    let _ = parse_update_session_status(payload, flags);
+    let _ = GetLastResponse::decode(payload, flags);
+});
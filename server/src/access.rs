@@ -0,0 +1,212 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Access policy for binary protocol sessions.
+//!
+//! A policy maps authenticated principals (see [`crate::tls::PeerIdentity`])
+//! to an access level. It is loaded from the JSON file named by
+//! `CXDB_ACCESS_POLICY`:
+//!
+//! ```text
+//! {
+//!   "default": "none",
+//!   "anonymous": "none",
+//!   "principals": {
+//!     "spiffe://prod/ns/agents/*": "write",
+//!     "reporting.internal.example.com": "read"
+//!   }
+//! }
+//! ```
+//!
+//! Exact principal matches win; otherwise the longest matching `*`-suffixed
+//! prefix applies, then `default`. Connections without a principal use
+//! `anonymous` (falling back to `default`). Without a policy file every
+//! connection has write access. A session that authenticates with an OIDC
+//! bearer token in HELLO takes the token's principal and access level
+//! instead (see [`crate::oidc`]).
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::oidc::TokenIdentity;
+use crate::tls::PeerIdentity;
+
+/// What a session may do. `Write` implies `Read`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    None,
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccessPolicy {
+    /// Access for principals not listed in `principals`.
+    #[serde(default = "default_access")]
+    pub default: Access,
+    /// Access for connections without a principal (None uses `default`).
+    #[serde(default)]
+    pub anonymous: Option<Access>,
+    /// Principal (or `prefix*` pattern) to access level.
+    #[serde(default)]
+    pub principals: BTreeMap<String, Access>,
+}
+
+fn default_access() -> Access {
+    Access::None
+}
+
+impl AccessPolicy {
+    /// Policy that grants write access to everyone.
+    pub fn allow_all() -> Self {
+        Self {
+            default: Access::Write,
+            anonymous: None,
+            principals: BTreeMap::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        serde_json::from_slice(&data)
+            .map_err(|e| StoreError::InvalidInput(format!("access policy {}: {e}", path.display())))
+    }
+
+    /// Load the policy named by `CXDB_ACCESS_POLICY`, or allow everything.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("CXDB_ACCESS_POLICY") {
+            Ok(path) if !path.is_empty() => Self::load(Path::new(&path)),
+            _ => Ok(Self::allow_all()),
+        }
+    }
+
+    pub fn access_for(&self, principal: Option<&str>) -> Access {
+        let Some(principal) = principal else {
+            return self.anonymous.unwrap_or(self.default);
+        };
+        if let Some(access) = self.principals.get(principal) {
+            return *access;
+        }
+        self.principals
+            .iter()
+            .filter_map(|(pattern, access)| {
+                let prefix = pattern.strip_suffix('*')?;
+                principal
+                    .starts_with(prefix)
+                    .then_some((prefix.len(), *access))
+            })
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, access)| access)
+            .unwrap_or(self.default)
+    }
+}
+
+/// Who a connection authenticated as and the access it was granted.
+#[derive(Debug, Clone)]
+pub struct SessionAuth {
+    pub identity: Option<PeerIdentity>,
+    /// OIDC token the client sent in HELLO, if any.
+    pub token: Option<TokenIdentity>,
+    pub access: Access,
+}
+
+impl SessionAuth {
+    pub fn resolve(policy: &AccessPolicy, identity: Option<PeerIdentity>) -> Self {
+        let access = policy.access_for(identity.as_ref().map(|i| i.principal.as_str()));
+        Self {
+            identity,
+            token: None,
+            access,
+        }
+    }
+
+    /// Authenticate the session with a verified bearer token, whose
+    /// principal and access level replace the certificate's.
+    pub fn with_token(self, token: TokenIdentity) -> Self {
+        Self {
+            access: token.access,
+            token: Some(token),
+            ..self
+        }
+    }
+
+    pub fn principal(&self) -> Option<&str> {
+        match &self.token {
+            Some(token) => Some(token.principal.as_str()),
+            None => self.identity.as_ref().map(|i| i.principal.as_str()),
+        }
+    }
+
+    /// Fails with `PermissionDenied` unless the session has `required` access.
+    pub fn check(&self, required: Access, what: &str) -> Result<()> {
+        if self.access >= required {
+            return Ok(());
+        }
+        let who = self.principal().unwrap_or("anonymous");
+        Err(StoreError::PermissionDenied(format!(
+            "{who} is not allowed to {what}"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> AccessPolicy {
+        serde_json::from_str(
+            r#"{
+                "default": "read",
+                "anonymous": "none",
+                "principals": {
+                    "spiffe://prod/*": "write",
+                    "spiffe://prod/batch/*": "read",
+                    "ops.example.com": "none"
+                }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_access_resolution() {
+        let policy = policy();
+        assert_eq!(policy.access_for(None), Access::None);
+        assert_eq!(policy.access_for(Some("ops.example.com")), Access::None);
+        assert_eq!(
+            policy.access_for(Some("spiffe://prod/agent")),
+            Access::Write
+        );
+        assert_eq!(
+            policy.access_for(Some("spiffe://prod/batch/x")),
+            Access::Read
+        );
+        assert_eq!(policy.access_for(Some("someone-else")), Access::Read);
+        assert_eq!(AccessPolicy::allow_all().access_for(None), Access::Write);
+    }
+
+    #[test]
+    fn test_session_check() {
+        let auth = SessionAuth {
+            identity: None,
+            token: None,
+            access: Access::Read,
+        };
+        assert!(auth.check(Access::Read, "read").is_ok());
+        let err = auth.check(Access::Write, "append turns").unwrap_err();
+        assert!(matches!(err, StoreError::PermissionDenied(_)));
+
+        let auth = auth.with_token(TokenIdentity {
+            principal: "pat@example.com".into(),
+            subject: "pat".into(),
+            access: Access::Write,
+            expires_at_unix_ms: 0,
+        });
+        assert_eq!(auth.principal(), Some("pat@example.com"));
+        assert!(auth.check(Access::Write, "append turns").is_ok());
+    }
+}
@@ -0,0 +1,396 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! HTTP access log.
+//!
+//! When `CXDB_HTTP_ACCESS_LOG` is set, the HTTP gateway writes one line per
+//! answered request to stdout or to a file, in Apache combined format or as
+//! JSON lines. File logs rotate by size: `access.log` becomes `access.log.1`,
+//! older files shift up and the oldest beyond the kept count is removed.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::json;
+
+use crate::error::{Result, StoreError};
+
+/// Default for `CXDB_HTTP_ACCESS_LOG_MAX_BYTES`.
+const DEFAULT_MAX_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Default for `CXDB_HTTP_ACCESS_LOG_KEEP`.
+const DEFAULT_KEEP: usize = 5;
+
+/// Query parameters that carry credentials; their values are not logged.
+const REDACTED_PARAMS: &[&str] = &["share"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    /// Apache combined format followed by the latency in milliseconds.
+    Combined,
+    /// One JSON object per line.
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessLogTarget {
+    Stdout,
+    File(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct AccessLogConfig {
+    pub target: AccessLogTarget,
+    pub format: AccessLogFormat,
+    /// Size at which a file log is rotated; 0 never rotates.
+    pub max_bytes: u64,
+    /// Rotated files kept next to the live one.
+    pub keep: usize,
+}
+
+impl AccessLogConfig {
+    /// Load config from environment variables. Returns None when
+    /// `CXDB_HTTP_ACCESS_LOG` is unset or `off`.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let target = match var("CXDB_HTTP_ACCESS_LOG").as_deref() {
+            None | Some("off") => return Ok(None),
+            Some("stdout") | Some("-") => AccessLogTarget::Stdout,
+            Some(path) => AccessLogTarget::File(PathBuf::from(path)),
+        };
+        let format = match var("CXDB_HTTP_ACCESS_LOG_FORMAT").as_deref() {
+            None | Some("combined") => AccessLogFormat::Combined,
+            Some("json") => AccessLogFormat::Json,
+            Some(other) => {
+                return Err(StoreError::InvalidInput(format!(
+                    "unknown CXDB_HTTP_ACCESS_LOG_FORMAT {other:?} (expected combined or json)"
+                )))
+            }
+        };
+        let number = |name: &str, default: u64| -> Result<u64> {
+            match var(name) {
+                Some(v) => v
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput(format!("invalid {name} {v:?}"))),
+                None => Ok(default),
+            }
+        };
+        Ok(Some(Self {
+            target,
+            format,
+            max_bytes: number("CXDB_HTTP_ACCESS_LOG_MAX_BYTES", DEFAULT_MAX_BYTES)?,
+            keep: number("CXDB_HTTP_ACCESS_LOG_KEEP", DEFAULT_KEEP as u64)? as usize,
+        }))
+    }
+
+    /// Where the log goes, for startup messages.
+    pub fn describe(&self) -> String {
+        let format = match self.format {
+            AccessLogFormat::Combined => "combined",
+            AccessLogFormat::Json => "json",
+        };
+        match &self.target {
+            AccessLogTarget::Stdout => format!("stdout ({format})"),
+            AccessLogTarget::File(path) => format!("{} ({format})", path.display()),
+        }
+    }
+}
+
+/// One answered request.
+#[derive(Debug, Clone)]
+pub struct AccessEntry {
+    pub time: DateTime<Utc>,
+    pub client_ip: Option<String>,
+    /// Principal named by the gateway (`X-CXDB-Principal`).
+    pub principal: Option<String>,
+    pub method: String,
+    /// Request target, query string included with `share` tokens redacted.
+    pub path: String,
+    /// Route template, e.g. `/v1/contexts/:id/turns`.
+    pub route: String,
+    pub http_version: String,
+    pub status: u16,
+    /// Response body bytes; None for streamed bodies of unknown length.
+    pub bytes: Option<u64>,
+    pub latency: Duration,
+    pub context_id: Option<u64>,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl AccessEntry {
+    /// Apache combined format with the latency in milliseconds appended.
+    pub fn combined(&self) -> String {
+        format!(
+            "{} - {} [{}] \"{} {} {}\" {} {} \"{}\" \"{}\" {}",
+            self.client_ip.as_deref().unwrap_or("-"),
+            self.principal.as_deref().map_or("-".into(), escape),
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            self.method,
+            escape(&self.path),
+            self.http_version,
+            self.status,
+            self.bytes.map_or("-".into(), |b| b.to_string()),
+            self.referer.as_deref().map_or("-".into(), escape),
+            self.user_agent.as_deref().map_or("-".into(), escape),
+            self.latency.as_millis(),
+        )
+    }
+
+    pub fn json(&self) -> String {
+        json!({
+            "time": self.time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            "client_ip": self.client_ip,
+            "principal": self.principal,
+            "method": self.method,
+            "path": self.path,
+            "route": self.route,
+            "status": self.status,
+            "bytes": self.bytes,
+            "latency_ms": self.latency.as_secs_f64() * 1000.0,
+            "context_id": self.context_id.map(|id| id.to_string()),
+            "referer": self.referer,
+            "user_agent": self.user_agent,
+        })
+        .to_string()
+    }
+}
+
+/// `target` (a request target or URL) with the values of credential query
+/// parameters replaced by `REDACTED`.
+pub fn redact(target: &str) -> String {
+    let Some((path, rest)) = target.split_once('?') else {
+        return target.to_string();
+    };
+    let (query, fragment) = match rest.split_once('#') {
+        Some((query, fragment)) => (query, Some(fragment)),
+        None => (rest, None),
+    };
+    let query: Vec<String> = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if REDACTED_PARAMS.contains(&key) => format!("{key}=REDACTED"),
+            _ => pair.to_string(),
+        })
+        .collect();
+    let mut out = format!("{path}?{}", query.join("&"));
+    if let Some(fragment) = fragment {
+        out.push('#');
+        out.push_str(fragment);
+    }
+    out
+}
+
+/// Escape quotes, backslashes and control characters as Apache does.
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+        })
+    }
+
+    /// Shift `path.N` to `path.N+1`, dropping the oldest, and start a new file.
+    fn rotate(&mut self, keep: usize) -> Result<()> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{n}", self.path.display()));
+        if keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            let _ = std::fs::remove_file(rotated(keep));
+            for n in (1..keep).rev() {
+                if rotated(n).exists() {
+                    std::fs::rename(rotated(n), rotated(n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, rotated(1))?;
+        }
+        *self = Self::open(&self.path)?;
+        Ok(())
+    }
+}
+
+enum Sink {
+    Stdout,
+    File(LogFile),
+}
+
+pub struct AccessLog {
+    config: AccessLogConfig,
+    sink: Mutex<Sink>,
+}
+
+impl AccessLog {
+    pub fn open(config: AccessLogConfig) -> Result<Self> {
+        let sink = match &config.target {
+            AccessLogTarget::Stdout => Sink::Stdout,
+            AccessLogTarget::File(path) => {
+                if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                    std::fs::create_dir_all(parent)?;
+                }
+                Sink::File(LogFile::open(path)?)
+            }
+        };
+        Ok(Self {
+            config,
+            sink: Mutex::new(sink),
+        })
+    }
+
+    /// Write one entry. Failures are reported on stderr and do not affect
+    /// the request.
+    pub fn record(&self, entry: &AccessEntry) {
+        let mut line = match self.config.format {
+            AccessLogFormat::Combined => entry.combined(),
+            AccessLogFormat::Json => entry.json(),
+        };
+        line.push('\n');
+        if let Err(err) = self.write(line.as_bytes()) {
+            eprintln!("access log error: {err}");
+        }
+    }
+
+    fn write(&self, line: &[u8]) -> Result<()> {
+        let mut sink = self.sink.lock().unwrap();
+        match &mut *sink {
+            Sink::Stdout => {
+                let mut out = std::io::stdout().lock();
+                out.write_all(line)?;
+                out.flush()?;
+            }
+            Sink::File(log) => {
+                let max_bytes = self.config.max_bytes;
+                if max_bytes > 0 && log.size > 0 && log.size + line.len() as u64 > max_bytes {
+                    log.rotate(self.config.keep)?;
+                }
+                log.file.write_all(line)?;
+                log.size += line.len() as u64;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> AccessEntry {
+        AccessEntry {
+            time: DateTime::parse_from_rfc3339("2025-03-04T05:06:07.089Z")
+                .unwrap()
+                .with_timezone(&Utc),
+            client_ip: Some("10.0.0.7".into()),
+            principal: Some("alice".into()),
+            method: "GET".into(),
+            path: "/v1/contexts/42/turns?limit=5".into(),
+            route: "/v1/contexts/:id/turns".into(),
+            http_version: "HTTP/1.1".into(),
+            status: 200,
+            bytes: Some(512),
+            latency: Duration::from_micros(3_250),
+            context_id: Some(42),
+            referer: None,
+            user_agent: Some("curl/8.5 \"x\"".into()),
+        }
+    }
+
+    #[test]
+    fn test_combined_format() {
+        assert_eq!(
+            entry().combined(),
+            "10.0.0.7 - alice [04/Mar/2025:05:06:07 +0000] \
+             \"GET /v1/contexts/42/turns?limit=5 HTTP/1.1\" 200 512 \"-\" \"curl/8.5 \\\"x\\\"\" 3"
+        );
+        let streamed = AccessEntry {
+            bytes: None,
+            principal: None,
+            ..entry()
+        };
+        assert!(streamed.combined().starts_with("10.0.0.7 - - ["));
+        assert!(streamed.combined().contains(" 200 - "));
+    }
+
+    #[test]
+    fn test_json_format() {
+        let line: serde_json::Value = serde_json::from_str(&entry().json()).unwrap();
+        assert_eq!(line["time"], "2025-03-04T05:06:07.089Z");
+        assert_eq!(line["principal"], "alice");
+        assert_eq!(line["route"], "/v1/contexts/:id/turns");
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["bytes"], 512);
+        assert_eq!(line["latency_ms"], 3.25);
+        assert_eq!(line["context_id"], "42");
+        assert!(line["referer"].is_null());
+    }
+
+    #[test]
+    fn test_share_tokens_are_redacted() {
+        assert_eq!(
+            redact("/v1/contexts/42?share=abc.def&limit=5"),
+            "/v1/contexts/42?share=REDACTED&limit=5"
+        );
+        assert_eq!(
+            redact("https://cxdb.example.com/c/42?view=1&share=abc#turn-9"),
+            "https://cxdb.example.com/c/42?view=1&share=REDACTED#turn-9"
+        );
+        assert_eq!(
+            redact("/v1/contexts/42?shared=1"),
+            "/v1/contexts/42?shared=1"
+        );
+        assert_eq!(redact("/v1/contexts/42"), "/v1/contexts/42");
+    }
+
+    #[test]
+    fn test_file_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let log = AccessLog::open(AccessLogConfig {
+            target: AccessLogTarget::File(path.clone()),
+            format: AccessLogFormat::Json,
+            max_bytes: 600,
+            keep: 2,
+        })
+        .unwrap();
+        for _ in 0..12 {
+            log.record(&entry());
+        }
+        let rotated = |n: usize| PathBuf::from(format!("{}.{n}", path.display()));
+        assert!(rotated(1).exists());
+        assert!(rotated(2).exists());
+        assert!(!rotated(3).exists());
+        for file in [path.clone(), rotated(1), rotated(2)] {
+            let len = std::fs::metadata(&file).unwrap().len();
+            assert!(len > 0 && len <= 600, "{}: {len}", file.display());
+        }
+        let lines = std::fs::read_to_string(&path).unwrap();
+        assert!(lines
+            .lines()
+            .all(|l| serde_json::from_str::<serde_json::Value>(l).is_ok()));
+    }
+}
@@ -0,0 +1,606 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Anchoring of context chain heads to an external notary.
+//!
+//! Turn hash chains make a context's history tamper-evident only as long as
+//! its chain head is trusted. The anchoring job periodically hashes every
+//! context's chain head into a Merkle tree and publishes the root outside
+//! the server: as an S3 object or to an HTTPS notary. Anchors are recorded
+//! in `meta/anchors/anchors.jsonl`, with the head turn of every context at
+//! anchor time in `meta/anchors/{seq}.heads`, so a root can later be
+//! recomputed from the store and compared with the published one.
+//!
+//! Leaves are `BLAKE3(0x00 || context_id u64 || head_turn_id u64 ||
+//! chain_head)` in context id order; inner nodes are
+//! `BLAKE3(0x01 || left || right)`, and an odd node is carried up unchanged.
+
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::Client as S3Client;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::error::{Result, StoreError};
+use crate::jsonl_log::open_log;
+use crate::store::Store;
+use crate::util::unix_ms;
+
+/// Name of the leaf and node hashing scheme, included in published anchors.
+pub const MERKLE_SCHEME: &str = "cxdb-merkle-blake3-v1";
+
+/// Timeout for publishing to an HTTPS notary.
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Changed contexts listed in a verification report; the count is exact.
+const MAX_REPORTED: usize = 100;
+
+/// Where anchors are published.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnchorTarget {
+    /// One object per anchor under `s3://{bucket}/{prefix}anchors/`.
+    S3 {
+        bucket: String,
+        prefix: String,
+        region: String,
+    },
+    /// JSON POSTed to a notary endpoint; the response body is kept as the
+    /// receipt.
+    Https { url: String },
+}
+
+impl AnchorTarget {
+    /// Parse `s3://bucket/prefix` or an `https://` (or `http://`) URL.
+    pub fn parse(target: &str, region: &str) -> Result<Self> {
+        if let Some(rest) = target.strip_prefix("s3://") {
+            let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+            if bucket.is_empty() {
+                return Err(StoreError::InvalidInput(
+                    "CXDB_ANCHOR_TARGET s3:// needs a bucket".into(),
+                ));
+            }
+            let mut prefix = prefix.to_string();
+            if !prefix.is_empty() && !prefix.ends_with('/') {
+                prefix.push('/');
+            }
+            Ok(AnchorTarget::S3 {
+                bucket: bucket.to_string(),
+                prefix,
+                region: region.to_string(),
+            })
+        } else if target.starts_with("https://") || target.starts_with("http://") {
+            Ok(AnchorTarget::Https {
+                url: target.to_string(),
+            })
+        } else {
+            Err(StoreError::InvalidInput(format!(
+                "unknown CXDB_ANCHOR_TARGET {target:?} (expected s3://bucket/prefix or an https:// URL)"
+            )))
+        }
+    }
+
+    /// Target as configured, for anchor records and logs.
+    pub fn describe(&self) -> String {
+        match self {
+            AnchorTarget::S3 { bucket, prefix, .. } => format!("s3://{bucket}/{prefix}"),
+            AnchorTarget::Https { url } => url.clone(),
+        }
+    }
+}
+
+/// Anchoring settings, loaded from the environment.
+#[derive(Debug, Clone)]
+pub struct AnchorConfig {
+    pub target: AnchorTarget,
+    pub interval: Duration,
+    /// Anchors whose context heads are kept for verification.
+    pub retain: usize,
+}
+
+impl AnchorConfig {
+    /// None unless `CXDB_ANCHOR_TARGET` is set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let Some(target) = var("CXDB_ANCHOR_TARGET") else {
+            return Ok(None);
+        };
+        let region = var("CXDB_ANCHOR_S3_REGION")
+            .or_else(|| var("CXDB_S3_REGION"))
+            .unwrap_or_else(|| "us-west-2".to_string());
+        let interval_secs = var("CXDB_ANCHOR_INTERVAL_SECS")
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(3600)
+            .max(1);
+        let retain = var("CXDB_ANCHOR_RETAIN")
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(168)
+            .max(1);
+        Ok(Some(Self {
+            target: AnchorTarget::parse(&target, &region)?,
+            interval: Duration::from_secs(interval_secs),
+            retain,
+        }))
+    }
+}
+
+/// One anchoring run, as recorded locally.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnchorRecord {
+    pub seq: u64,
+    pub anchored_at_unix_ms: u64,
+    /// Hex Merkle root over every context's chain head.
+    pub merkle_root: String,
+    pub contexts: u64,
+    pub target: String,
+    /// S3 key or notary receipt, once published.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+    /// Publishing error; the anchor is retried at the next interval.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// One step of a Merkle inclusion path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MerkleStep {
+    /// Hex sibling hash.
+    pub sibling: String,
+    /// Whether the sibling is the left child.
+    pub left: bool,
+}
+
+/// A context's leaf in an anchor and its path to the Merkle root.
+#[derive(Debug, Clone, Serialize)]
+pub struct AnchorProof {
+    pub context_id: String,
+    pub head_turn_id: String,
+    pub chain_head: String,
+    pub leaf: String,
+    pub path: Vec<MerkleStep>,
+}
+
+/// Result of recomputing an anchor's Merkle root from the store.
+#[derive(Debug, Clone, Serialize)]
+pub struct AnchorVerification {
+    pub seq: u64,
+    pub merkle_root: String,
+    pub recomputed_root: String,
+    pub ok: bool,
+    /// Contexts whose anchored head turn is gone or has a different chain
+    /// hash than at anchor time.
+    pub changed_contexts: u64,
+    /// The first changed context ids.
+    pub changed_context_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proof: Option<AnchorProof>,
+}
+
+pub fn leaf_hash(context_id: u64, head_turn_id: u64, chain_head: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[0]);
+    hasher.update(&context_id.to_le_bytes());
+    hasher.update(&head_turn_id.to_le_bytes());
+    hasher.update(chain_head);
+    *hasher.finalize().as_bytes()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[1]);
+    hasher.update(left);
+    hasher.update(right);
+    *hasher.finalize().as_bytes()
+}
+
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [odd] => *odd,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Merkle root of the leaves; all zeros when there are none.
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return [0u8; 32];
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// Inclusion path of `leaves[index]`, leaf level first.
+pub fn merkle_path(leaves: &[[u8; 32]], mut index: usize) -> Vec<MerkleStep> {
+    let mut path = Vec::new();
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        if sibling < level.len() {
+            path.push(MerkleStep {
+                sibling: hex::encode(level[sibling]),
+                left: sibling < index,
+            });
+        }
+        level = next_level(&level);
+        index /= 2;
+    }
+    path
+}
+
+/// Root reached by applying an inclusion path to a leaf.
+pub fn root_from_path(leaf: [u8; 32], path: &[MerkleStep]) -> Result<[u8; 32]> {
+    path.iter().try_fold(leaf, |hash, step| {
+        let sibling: [u8; 32] = hex::decode(&step.sibling)
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| StoreError::InvalidInput("invalid sibling hash".into()))?;
+        Ok(if step.left {
+            node_hash(&sibling, &hash)
+        } else {
+            node_hash(&hash, &sibling)
+        })
+    })
+}
+
+enum Publisher {
+    S3 {
+        runtime: tokio::runtime::Runtime,
+        client: S3Client,
+        bucket: String,
+        prefix: String,
+    },
+    Https {
+        agent: ureq::Agent,
+        url: String,
+    },
+}
+
+impl Publisher {
+    fn connect(target: &AnchorTarget) -> Result<Self> {
+        match target {
+            AnchorTarget::S3 {
+                bucket,
+                prefix,
+                region,
+            } => {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .map_err(StoreError::Io)?;
+                let aws_config = runtime.block_on(
+                    aws_config::defaults(aws_config::BehaviorVersion::latest())
+                        .region(aws_config::Region::new(region.clone()))
+                        .load(),
+                );
+                Ok(Publisher::S3 {
+                    runtime,
+                    client: S3Client::new(&aws_config),
+                    bucket: bucket.clone(),
+                    prefix: prefix.clone(),
+                })
+            }
+            AnchorTarget::Https { url } => Ok(Publisher::Https {
+                agent: ureq::AgentBuilder::new().timeout(PUBLISH_TIMEOUT).build(),
+                url: url.clone(),
+            }),
+        }
+    }
+
+    /// Publish an anchor statement; returns the S3 key or the notary's
+    /// response body.
+    fn publish(&self, record: &AnchorRecord) -> std::result::Result<String, String> {
+        let statement = json!({
+            "seq": record.seq,
+            "anchored_at_unix_ms": record.anchored_at_unix_ms,
+            "merkle_root": record.merkle_root,
+            "contexts": record.contexts,
+            "scheme": MERKLE_SCHEME,
+        });
+        match self {
+            Publisher::S3 {
+                runtime,
+                client,
+                bucket,
+                prefix,
+            } => {
+                let stamp = Utc
+                    .timestamp_millis_opt(record.anchored_at_unix_ms as i64)
+                    .single()
+                    .unwrap_or_default()
+                    .format("%Y%m%dT%H%M%SZ");
+                let key = format!("{prefix}anchors/{stamp}-{}.json", record.seq);
+                let body = serde_json::to_vec(&statement).map_err(|e| e.to_string())?;
+                runtime
+                    .block_on(
+                        client
+                            .put_object()
+                            .bucket(bucket)
+                            .key(&key)
+                            .body(ByteStream::from(body))
+                            .content_type("application/json")
+                            .send(),
+                    )
+                    .map_err(|e| format!("S3 put anchor failed: {e}"))?;
+                Ok(format!("s3://{bucket}/{key}"))
+            }
+            Publisher::Https { agent, url } => {
+                let response = agent
+                    .post(url)
+                    .send_json(statement)
+                    .map_err(|e| e.to_string())?;
+                let mut receipt = String::new();
+                response
+                    .into_reader()
+                    .take(4096)
+                    .read_to_string(&mut receipt)
+                    .map_err(|e| e.to_string())?;
+                Ok(receipt.trim().to_string())
+            }
+        }
+    }
+}
+
+/// Anchor log and publisher.
+pub struct Anchors {
+    config: AnchorConfig,
+    dir: PathBuf,
+    records: Mutex<Vec<AnchorRecord>>,
+    publisher: Publisher,
+}
+
+impl Anchors {
+    /// Open the anchor log under `meta_dir/anchors`.
+    pub fn open(meta_dir: &Path, config: AnchorConfig) -> Result<Self> {
+        let dir = meta_dir.join("anchors");
+        fs::create_dir_all(&dir)?;
+        // A torn last line is cut off; its anchor is retaken.
+        let (_, records) = open_log::<AnchorRecord>(&dir.join("anchors.jsonl"))?;
+        Ok(Self {
+            publisher: Publisher::connect(&config.target)?,
+            config,
+            dir,
+            records: Mutex::new(records),
+        })
+    }
+
+    pub fn config(&self) -> &AnchorConfig {
+        &self.config
+    }
+
+    /// Recorded anchors, newest first.
+    pub fn list(&self) -> Vec<AnchorRecord> {
+        let mut records = self.records.lock().unwrap().clone();
+        records.reverse();
+        records
+    }
+
+    /// Take an anchor and publish it. Unless `force`, nothing is done when
+    /// the root equals the last published one.
+    pub fn anchor(&self, store: &Mutex<Store>, force: bool) -> Result<Option<AnchorRecord>> {
+        let heads = store.lock().unwrap().chain_heads();
+        let leaves: Vec<[u8; 32]> = heads
+            .iter()
+            .map(|(context_id, head_turn_id, chain)| leaf_hash(*context_id, *head_turn_id, chain))
+            .collect();
+        let root = hex::encode(merkle_root(&leaves));
+
+        let seq = {
+            let records = self.records.lock().unwrap();
+            let last_published = records.iter().rev().find(|r| r.error.is_none());
+            if !force && last_published.is_some_and(|r| r.merkle_root == root) {
+                return Ok(None);
+            }
+            records.last().map_or(1, |r| r.seq + 1)
+        };
+        self.write_heads(seq, &heads)?;
+
+        let mut record = AnchorRecord {
+            seq,
+            anchored_at_unix_ms: unix_ms(),
+            merkle_root: root,
+            contexts: heads.len() as u64,
+            target: self.config.target.describe(),
+            published: None,
+            error: None,
+        };
+        match self.publisher.publish(&record) {
+            Ok(published) => record.published = Some(published),
+            Err(err) => record.error = Some(err),
+        }
+
+        let mut records = self.records.lock().unwrap();
+        let mut line = serde_json::to_vec(&record)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join("anchors.jsonl"))?;
+        file.write_all(&line)?;
+        file.sync_data()?;
+        records.push(record.clone());
+        self.prune(&records);
+        Ok(Some(record))
+    }
+
+    /// Recompute an anchor's root from the current chain hashes of the head
+    /// turns it recorded, with an inclusion proof for `context_id`.
+    pub fn verify(
+        &self,
+        store: &Store,
+        seq: u64,
+        context_id: Option<u64>,
+    ) -> Result<AnchorVerification> {
+        let record = self
+            .records
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|r| r.seq == seq)
+            .cloned()
+            .ok_or_else(|| StoreError::NotFound("anchor".into()))?;
+        let heads = self.read_heads(seq)?;
+
+        let mut leaves = Vec::with_capacity(heads.len());
+        let mut chains = Vec::with_capacity(heads.len());
+        let mut changed = Vec::new();
+        let mut changed_contexts = 0u64;
+        for (ctx, head_turn_id) in &heads {
+            let chain = match store.turn_chain_hash(*head_turn_id) {
+                Ok(chain) => chain,
+                Err(_) => {
+                    changed_contexts += 1;
+                    if changed.len() < MAX_REPORTED {
+                        changed.push(ctx.to_string());
+                    }
+                    [0u8; 32]
+                }
+            };
+            leaves.push(leaf_hash(*ctx, *head_turn_id, &chain));
+            chains.push(chain);
+        }
+        let recomputed_root = hex::encode(merkle_root(&leaves));
+
+        let proof = match context_id {
+            Some(id) => {
+                let index = heads
+                    .binary_search_by_key(&id, |(ctx, _)| *ctx)
+                    .map_err(|_| StoreError::NotFound("context in anchor".into()))?;
+                Some(AnchorProof {
+                    context_id: id.to_string(),
+                    head_turn_id: heads[index].1.to_string(),
+                    chain_head: hex::encode(chains[index]),
+                    leaf: hex::encode(leaves[index]),
+                    path: merkle_path(&leaves, index),
+                })
+            }
+            None => None,
+        };
+
+        Ok(AnchorVerification {
+            seq,
+            ok: recomputed_root == record.merkle_root,
+            merkle_root: record.merkle_root,
+            recomputed_root,
+            changed_contexts,
+            changed_context_ids: changed,
+            proof,
+        })
+    }
+
+    fn heads_path(&self, seq: u64) -> PathBuf {
+        self.dir.join(format!("{seq}.heads"))
+    }
+
+    /// `{seq}.heads`: (context_id u64, head_turn_id u64) per context, by
+    /// context id.
+    fn write_heads(&self, seq: u64, heads: &[(u64, u64, [u8; 32])]) -> Result<()> {
+        let mut buf = Vec::with_capacity(16 * heads.len());
+        for (context_id, head_turn_id, _) in heads {
+            buf.write_u64::<LittleEndian>(*context_id)?;
+            buf.write_u64::<LittleEndian>(*head_turn_id)?;
+        }
+        let tmp = self.heads_path(seq).with_extension("tmp");
+        fs::write(&tmp, buf)?;
+        fs::rename(&tmp, self.heads_path(seq))?;
+        Ok(())
+    }
+
+    fn read_heads(&self, seq: u64) -> Result<Vec<(u64, u64)>> {
+        let bytes = fs::read(self.heads_path(seq)).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                StoreError::NotFound("anchor heads (pruned by CXDB_ANCHOR_RETAIN)".into())
+            }
+            _ => StoreError::Io(e),
+        })?;
+        let mut reader = &bytes[..];
+        let mut heads = Vec::with_capacity(bytes.len() / 16);
+        while reader.len() >= 16 {
+            heads.push((
+                reader.read_u64::<LittleEndian>()?,
+                reader.read_u64::<LittleEndian>()?,
+            ));
+        }
+        Ok(heads)
+    }
+
+    /// Drop the heads of all but the newest `retain` anchors.
+    fn prune(&self, records: &[AnchorRecord]) {
+        let keep = records.len().saturating_sub(self.config.retain);
+        for record in &records[..keep] {
+            let _ = fs::remove_file(self.heads_path(record.seq));
+        }
+    }
+}
+
+/// Start the anchoring thread: one anchor per interval, skipped while no
+/// context has changed since the last published one.
+pub fn start_anchoring(anchors: Arc<Anchors>, store: Arc<Mutex<Store>>) -> thread::JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(anchors.config.interval);
+        match anchors.anchor(&store, false) {
+            Ok(Some(record)) if record.error.is_some() => tracing::warn!(
+                seq = record.seq,
+                error = record.error.as_deref().unwrap_or(""),
+                "anchor publish failed"
+            ),
+            Ok(Some(record)) => tracing::info!(
+                seq = record.seq,
+                root = %record.merkle_root,
+                contexts = record.contexts,
+                "anchored context chain heads"
+            ),
+            Ok(None) => {}
+            Err(err) => tracing::warn!(error = %err, "anchoring failed"),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merkle_paths_lead_to_root() {
+        let leaves: Vec<[u8; 32]> = (0..7u64).map(|i| leaf_hash(i, i, &[0u8; 32])).collect();
+        let root = merkle_root(&leaves);
+        for index in 0..leaves.len() {
+            let path = merkle_path(&leaves, index);
+            assert_eq!(root_from_path(leaves[index], &path).unwrap(), root);
+        }
+        assert_eq!(merkle_root(&leaves[..1]), leaves[0]);
+        assert_eq!(merkle_root(&[]), [0u8; 32]);
+    }
+
+    #[test]
+    fn test_anchor_targets_parse() {
+        assert_eq!(
+            AnchorTarget::parse("s3://audit/cxdb/prod", "eu-west-1").unwrap(),
+            AnchorTarget::S3 {
+                bucket: "audit".into(),
+                prefix: "cxdb/prod/".into(),
+                region: "eu-west-1".into(),
+            }
+        );
+        assert!(matches!(
+            AnchorTarget::parse("https://notary.example.com/anchor", "").unwrap(),
+            AnchorTarget::Https { .. }
+        ));
+        assert!(AnchorTarget::parse("s3://", "").is_err());
+        assert!(AnchorTarget::parse("ftp://x", "").is_err());
+    }
+}
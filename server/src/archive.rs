@@ -0,0 +1,271 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Portable context archives.
+//!
+//! An archive holds one context's head chain so it can be moved between data
+//! directories (`cxdb-server export-context` / `import-context`). It is JSON
+//! lines: an [`ArchiveHeader`], then one [`ArchiveTurn`] per turn, root first.
+//! Payloads are stored uncompressed and in the clear (base64), so archives of
+//! sealed contexts must be handled as sensitive. Snapshot blobs and named
+//! attachments travel with the turn they were attached to, each blob once
+//! per archive. Version 1 archives, which predate attachments, still import.
+//!
+//! Importing appends the turns to a new context: turn and context ids and
+//! creation times are assigned by the importing store, and session authors,
+//! metadata overrides, read marks and holds are not carried over.
+
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::fs_store::SnapshotMeta;
+use crate::store::Store;
+use crate::util::unix_ms;
+
+pub const ARCHIVE_FORMAT: &str = "cxdb-context";
+pub const ARCHIVE_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveHeader {
+    pub format: String,
+    pub version: u32,
+    /// Context id in the exporting store.
+    pub context_id: String,
+    pub turns: usize,
+    pub exported_at_unix_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveTurn {
+    pub depth: u32,
+    pub created_at_unix_ms: u64,
+    pub declared_type_id: String,
+    pub declared_type_version: u32,
+    pub encoding: u32,
+    /// BLAKE3 of the uncompressed payload, hex.
+    pub payload_hash: String,
+    /// Uncompressed payload, base64.
+    pub payload: String,
+    /// Snapshot attached to this turn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fs_root: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fs_meta: Option<SnapshotMeta>,
+    /// Snapshot blobs not already written by an earlier turn.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fs_blobs: Vec<ArchiveBlob>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<ArchiveAttachment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveBlob {
+    pub hash: String,
+    pub data: String,
+}
+
+/// A named attachment. `data` (base64) is omitted when an earlier blob of
+/// the archive has the same hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveAttachment {
+    pub name: String,
+    pub mime_type: String,
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveSummary {
+    pub context_id: String,
+    pub turns: usize,
+    pub fs_blobs: usize,
+    pub attachments: usize,
+}
+
+/// Write the head chain of `context_id` to `out`.
+pub fn export_context(
+    store: &mut Store,
+    context_id: u64,
+    out: &mut impl Write,
+) -> Result<ArchiveSummary> {
+    let links = store.context_chain(context_id)?;
+    write_line(
+        out,
+        &ArchiveHeader {
+            format: ARCHIVE_FORMAT.to_string(),
+            version: ARCHIVE_VERSION,
+            context_id: context_id.to_string(),
+            turns: links.len(),
+            exported_at_unix_ms: unix_ms(),
+        },
+    )?;
+
+    let mut written = HashSet::new();
+    let (mut fs_blob_count, mut attachment_count) = (0, 0);
+    for link in &links {
+        let turn_id = link.record.turn_id;
+        let payload = store
+            .get_turn(turn_id, true)?
+            .payload
+            .ok_or_else(|| StoreError::Corrupt(format!("turn {turn_id} has no payload")))?;
+        let snapshot = store
+            .get_fs_snapshot(turn_id)
+            .filter(|s| s.attached_turn_id == turn_id);
+        let mut fs_blobs = Vec::new();
+        if let Some(snapshot) = &snapshot {
+            for (hash, data) in store.fs_snapshot_blobs(turn_id, snapshot.root_hash)? {
+                if written.insert(hash) {
+                    fs_blobs.push(ArchiveBlob {
+                        hash: hex::encode(hash),
+                        data: BASE64.encode(data),
+                    });
+                }
+            }
+        }
+        let mut attachments = Vec::new();
+        for attachment in store.turn_attachments(turn_id) {
+            let (_, data) = store.get_attachment(turn_id, &attachment.name)?;
+            attachments.push(ArchiveAttachment {
+                name: attachment.name,
+                mime_type: attachment.mime_type,
+                hash: hex::encode(attachment.hash),
+                data: written.insert(attachment.hash).then(|| BASE64.encode(data)),
+            });
+        }
+        fs_blob_count += fs_blobs.len();
+        attachment_count += attachments.len();
+        write_line(
+            out,
+            &ArchiveTurn {
+                depth: link.record.depth,
+                created_at_unix_ms: link.record.created_at_unix_ms,
+                declared_type_id: link.meta.declared_type_id.clone(),
+                declared_type_version: link.meta.declared_type_version,
+                encoding: link.meta.encoding,
+                payload_hash: hex::encode(link.record.payload_hash),
+                payload: BASE64.encode(&payload),
+                fs_root: snapshot.as_ref().map(|s| hex::encode(s.root_hash)),
+                fs_meta: snapshot.and_then(|s| s.meta),
+                fs_blobs,
+                attachments,
+            },
+        )?;
+    }
+    out.flush()?;
+
+    Ok(ArchiveSummary {
+        context_id: context_id.to_string(),
+        turns: links.len(),
+        fs_blobs: fs_blob_count,
+        attachments: attachment_count,
+    })
+}
+
+/// Append the turns of an archive to a new context. A failed import leaves
+/// the turns appended so far in the new context.
+pub fn import_context(store: &mut Store, input: impl BufRead) -> Result<ArchiveSummary> {
+    let mut lines = input.lines();
+    let header: ArchiveHeader = match lines.next() {
+        Some(line) => parse_line(&line?, 1)?,
+        None => return Err(StoreError::InvalidInput("archive is empty".into())),
+    };
+    if header.format != ARCHIVE_FORMAT || !(1..=ARCHIVE_VERSION).contains(&header.version) {
+        return Err(StoreError::InvalidInput(format!(
+            "unsupported archive {} v{}",
+            header.format, header.version
+        )));
+    }
+
+    let context_id = store.create_context(0)?.context_id;
+    let mut turns = 0usize;
+    let mut fs_blobs = 0usize;
+    let mut attachments = 0usize;
+    for (index, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let turn: ArchiveTurn = parse_line(&line, index + 2)?;
+        let payload = decode_base64(&turn.payload)?;
+        let (record, _) = store.append_turn(
+            context_id,
+            0,
+            turn.declared_type_id,
+            turn.declared_type_version,
+            turn.encoding,
+            0,
+            payload.len() as u32,
+            decode_hash(&turn.payload_hash)?,
+            &payload,
+        )?;
+        turns += 1;
+
+        for blob in &turn.fs_blobs {
+            let data = decode_base64(&blob.data)?;
+            store.put_blob(decode_hash(&blob.hash)?, &data, Some(context_id), None)?;
+            fs_blobs += 1;
+        }
+        if let Some(root) = &turn.fs_root {
+            store.attach_fs_with_meta(record.turn_id, decode_hash(root)?, turn.fs_meta)?;
+        }
+        for attachment in &turn.attachments {
+            let hash = decode_hash(&attachment.hash)?;
+            if let Some(data) = &attachment.data {
+                store.put_blob(hash, &decode_base64(data)?, Some(context_id), None)?;
+            }
+            store.attach_blob(
+                record.turn_id,
+                &attachment.name,
+                &attachment.mime_type,
+                hash,
+                None,
+            )?;
+            attachments += 1;
+        }
+    }
+    if turns != header.turns {
+        return Err(StoreError::InvalidInput(format!(
+            "archive declares {} turns but holds {turns}",
+            header.turns
+        )));
+    }
+
+    Ok(ArchiveSummary {
+        context_id: context_id.to_string(),
+        turns,
+        fs_blobs,
+        attachments,
+    })
+}
+
+const BASE64: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
+
+fn write_line(out: &mut impl Write, value: &impl Serialize) -> Result<()> {
+    serde_json::to_writer(&mut *out, value)
+        .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+fn parse_line<T: serde::de::DeserializeOwned>(line: &str, number: usize) -> Result<T> {
+    serde_json::from_str(line)
+        .map_err(|e| StoreError::InvalidInput(format!("archive line {number}: {e}")))
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>> {
+    BASE64
+        .decode(data)
+        .map_err(|e| StoreError::InvalidInput(format!("invalid base64: {e}")))
+}
+
+fn decode_hash(hash: &str) -> Result<[u8; 32]> {
+    hex::decode(hash)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| StoreError::InvalidInput(format!("invalid hash {hash:?}")))
+}
@@ -0,0 +1,232 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Named attachments on turns.
+//!
+//! An attachment links a stored blob to a turn under a file name, with its
+//! MIME type and size, so images and documents an agent produced travel with
+//! the turn instead of being inlined in its payload. Attachments are recorded
+//! in a JSON-lines log: attaching a name again replaces the earlier
+//! attachment, and removing one appends a detach entry.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::jsonl_log::open_log;
+use crate::util::unix_ms;
+
+/// Longest accepted attachment name, in bytes.
+pub const MAX_NAME_LEN: usize = 255;
+
+/// Longest accepted MIME type.
+pub const MAX_MIME_TYPE_LEN: usize = 127;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub name: String,
+    pub mime_type: String,
+    /// Size of the blob's content in bytes.
+    pub size: u64,
+    /// BLAKE3 of the content.
+    pub hash: [u8; 32],
+    pub attached_at_unix_ms: u64,
+}
+
+/// One line of the attachments log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum AttachmentEntry {
+    Attach {
+        turn_id: u64,
+        name: String,
+        mime_type: String,
+        size: u64,
+        /// Content hash, hex.
+        hash: String,
+        at_unix_ms: u64,
+    },
+    Detach {
+        turn_id: u64,
+        name: String,
+        at_unix_ms: u64,
+    },
+}
+
+/// Check that a name can be used as a download file name: 1 to 255 bytes,
+/// no `/`, `\` or control characters, and not `.` or `..`.
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name != "."
+        && name != ".."
+        && !name
+            .chars()
+            .any(|c| c == '/' || c == '\\' || c.is_control());
+    if valid {
+        Ok(())
+    } else {
+        Err(StoreError::InvalidInput(format!(
+            "invalid attachment name {name:?}"
+        )))
+    }
+}
+
+/// Check that a MIME type looks like `type/subtype`, parameters allowed.
+pub fn validate_mime_type(mime_type: &str) -> Result<()> {
+    let essence = mime_type.split(';').next().unwrap_or("").trim();
+    let token = |s: &str| {
+        !s.is_empty()
+            && s.bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$&^_.+-".contains(&b))
+    };
+    let valid = mime_type.len() <= MAX_MIME_TYPE_LEN
+        && mime_type.is_ascii()
+        && !mime_type.chars().any(|c| c.is_control())
+        && essence
+            .split_once('/')
+            .is_some_and(|(kind, subtype)| token(kind) && token(subtype));
+    if valid {
+        Ok(())
+    } else {
+        Err(StoreError::InvalidInput(format!(
+            "invalid mime_type {mime_type:?}"
+        )))
+    }
+}
+
+pub struct Attachments {
+    file: File,
+    by_turn: HashMap<u64, BTreeMap<String, Attachment>>,
+}
+
+impl Attachments {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("attachments.jsonl");
+        let (file, entries) = open_log::<AttachmentEntry>(&path)?;
+
+        let mut attachments = Self {
+            file,
+            by_turn: HashMap::new(),
+        };
+        for entry in entries {
+            attachments.apply(entry);
+        }
+        Ok(attachments)
+    }
+
+    fn apply(&mut self, entry: AttachmentEntry) {
+        match entry {
+            AttachmentEntry::Attach {
+                turn_id,
+                name,
+                mime_type,
+                size,
+                hash,
+                at_unix_ms,
+            } => {
+                let Some(hash) = hex::decode(&hash)
+                    .ok()
+                    .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                else {
+                    return;
+                };
+                self.by_turn.entry(turn_id).or_default().insert(
+                    name.clone(),
+                    Attachment {
+                        name,
+                        mime_type,
+                        size,
+                        hash,
+                        attached_at_unix_ms: at_unix_ms,
+                    },
+                );
+            }
+            AttachmentEntry::Detach { turn_id, name, .. } => {
+                if let Some(names) = self.by_turn.get_mut(&turn_id) {
+                    names.remove(&name);
+                    if names.is_empty() {
+                        self.by_turn.remove(&turn_id);
+                    }
+                }
+            }
+        }
+    }
+
+    fn append(&mut self, entry: AttachmentEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(&entry)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.apply(entry);
+        Ok(())
+    }
+
+    pub fn get(&self, turn_id: u64, name: &str) -> Option<&Attachment> {
+        self.by_turn.get(&turn_id).and_then(|names| names.get(name))
+    }
+
+    /// Attachments of a turn, ordered by name.
+    pub fn of_turn(&self, turn_id: u64) -> Vec<Attachment> {
+        self.by_turn
+            .get(&turn_id)
+            .map(|names| names.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Every attachment as (turn id, attachment).
+    pub fn all(&self) -> impl Iterator<Item = (u64, &Attachment)> + '_ {
+        self.by_turn
+            .iter()
+            .flat_map(|(&turn_id, names)| names.values().map(move |a| (turn_id, a)))
+    }
+
+    /// Record an attachment, replacing any with the same name. Returns the
+    /// new attachment and the one it replaced. The caller checks that the
+    /// turn and blob exist.
+    pub fn attach(
+        &mut self,
+        turn_id: u64,
+        name: &str,
+        mime_type: &str,
+        size: u64,
+        hash: [u8; 32],
+    ) -> Result<(Attachment, Option<Attachment>)> {
+        validate_name(name)?;
+        validate_mime_type(mime_type)?;
+        let previous = self.get(turn_id, name).cloned();
+        self.append(AttachmentEntry::Attach {
+            turn_id,
+            name: name.to_string(),
+            mime_type: mime_type.to_string(),
+            size,
+            hash: hex::encode(hash),
+            at_unix_ms: unix_ms(),
+        })?;
+        let attachment = self
+            .get(turn_id, name)
+            .cloned()
+            .ok_or_else(|| StoreError::Corrupt("attachment not recorded".into()))?;
+        Ok((attachment, previous))
+    }
+
+    /// Remove a turn's attachment, returning it.
+    pub fn detach(&mut self, turn_id: u64, name: &str) -> Result<Attachment> {
+        let attachment = self
+            .get(turn_id, name)
+            .cloned()
+            .ok_or_else(|| StoreError::NotFound(format!("attachment {name:?}")))?;
+        self.append(AttachmentEntry::Detach {
+            turn_id,
+            name: name.to_string(),
+            at_unix_ms: unix_ms(),
+        })?;
+        Ok(attachment)
+    }
+}
@@ -0,0 +1,338 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Bulk context metadata backfill.
+//!
+//! A backfill targets either every context matching a CQL filter (with one
+//! shared patch) or an explicit per-context mapping supplied as JSONL or CSV.
+//! Patches are applied as metadata overrides in batches, releasing the store
+//! lock between batches so regular traffic keeps flowing.
+//!
+//! JSONL mapping lines look like:
+//!
+//! ```text
+//! {"context_id": "42", "labels": ["team:payments"], "provenance": {"service_name": "gen"}}
+//! ```
+//!
+//! CSV mappings use a header row. `context_id` is required; `title`,
+//! `client_tag`, `labels` and `add_labels` (`;`-separated) and
+//! `provenance.<field>` columns are optional.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use serde::Deserialize;
+use serde_json::{json, Map, Value as JsonValue};
+
+use crate::error::{Result, StoreError};
+use crate::events::{EventBus, StoreEvent};
+use crate::metadata_overrides::{MetadataPatch, TITLE_SOURCE_BACKFILL};
+use crate::operations::OperationHandle;
+use crate::store::Store;
+
+const DEFAULT_BATCH_SIZE: usize = 500;
+const MAX_REPORTED_ERRORS: usize = 100;
+
+/// Provenance fields that hold integers; CSV cells for them are parsed as numbers.
+const NUMERIC_PROVENANCE_FIELDS: &[&str] = &[
+    "parent_context_id",
+    "root_context_id",
+    "process_pid",
+    "client_port",
+    "client_uid",
+    "client_gid",
+    "captured_at",
+];
+
+/// Metadata changes requested for a context.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BackfillPatch {
+    #[serde(flatten)]
+    pub set: MetadataPatch,
+    /// Labels appended to the existing labels instead of replacing them.
+    #[serde(default)]
+    pub add_labels: Vec<String>,
+}
+
+impl BackfillPatch {
+    fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.add_labels.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MappingFormat {
+    Jsonl,
+    Csv,
+}
+
+/// Body of `POST /v1/admin/contexts/backfill-metadata`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BackfillRequest {
+    /// CQL filter selecting contexts for `patch`.
+    #[serde(default)]
+    pub filter: Option<String>,
+    #[serde(default)]
+    pub patch: Option<BackfillPatch>,
+    /// Inline per-context mapping, used instead of filter + patch.
+    #[serde(default)]
+    pub mapping: Option<String>,
+    #[serde(default)]
+    pub mapping_format: Option<MappingFormat>,
+    #[serde(default)]
+    pub batch_size: Option<usize>,
+    /// Resolve targets and report the count without applying anything.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Resolved backfill work.
+#[derive(Debug, Clone)]
+pub struct BackfillPlan {
+    pub targets: Vec<(u64, BackfillPatch)>,
+    pub batch_size: usize,
+}
+
+impl BackfillRequest {
+    /// Resolve the request into per-context patches.
+    pub fn plan(&self, store: &mut Store, live_contexts: &HashSet<u64>) -> Result<BackfillPlan> {
+        let batch_size = self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1);
+        let targets = match (&self.mapping, &self.filter) {
+            (Some(_), Some(_)) => {
+                return Err(StoreError::InvalidInput(
+                    "use either filter + patch or mapping, not both".into(),
+                ))
+            }
+            (Some(mapping), None) => {
+                let format = self.mapping_format.ok_or_else(|| {
+                    StoreError::InvalidInput("mapping_format is required with mapping".into())
+                })?;
+                parse_mapping(mapping, format)?
+                    .into_iter()
+                    .filter(|(_, patch)| !patch.is_empty())
+                    .collect()
+            }
+            (None, Some(filter)) => {
+                let patch = self
+                    .patch
+                    .clone()
+                    .filter(|p| !p.is_empty())
+                    .ok_or_else(|| StoreError::InvalidInput("patch is required".into()))?;
+                let result = store
+                    .search_contexts(filter, live_contexts, None)
+                    .map_err(|e| {
+                        StoreError::InvalidInput(format!("invalid filter: {}", e.message))
+                    })?;
+                let mut ids = result.context_ids;
+                ids.sort_unstable();
+                ids.into_iter().map(|id| (id, patch.clone())).collect()
+            }
+            (None, None) => {
+                return Err(StoreError::InvalidInput(
+                    "filter + patch or mapping is required".into(),
+                ))
+            }
+        };
+        Ok(BackfillPlan {
+            targets,
+            batch_size,
+        })
+    }
+}
+
+/// Parse a JSONL or CSV mapping into per-context patches.
+pub fn parse_mapping(input: &str, format: MappingFormat) -> Result<Vec<(u64, BackfillPatch)>> {
+    let rows = match format {
+        MappingFormat::Jsonl => input
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str::<JsonValue>(line)
+                    .map_err(|e| StoreError::InvalidInput(format!("mapping line {}: {e}", i + 1)))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        MappingFormat::Csv => csv_rows(input)?,
+    };
+
+    rows.into_iter()
+        .enumerate()
+        .map(|(i, mut row)| {
+            let line = i + 1;
+            let obj = row.as_object_mut().ok_or_else(|| {
+                StoreError::InvalidInput(format!("mapping row {line}: expected an object"))
+            })?;
+            let context_id = match obj.remove("context_id") {
+                Some(JsonValue::String(s)) => s.trim().parse::<u64>().ok(),
+                Some(JsonValue::Number(n)) => n.as_u64(),
+                _ => None,
+            }
+            .ok_or_else(|| {
+                StoreError::InvalidInput(format!("mapping row {line}: invalid context_id"))
+            })?;
+            let patch: BackfillPatch = serde_json::from_value(row)
+                .map_err(|e| StoreError::InvalidInput(format!("mapping row {line}: {e}")))?;
+            Ok((context_id, patch))
+        })
+        .collect()
+}
+
+fn csv_rows(input: &str) -> Result<Vec<JsonValue>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(input.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| StoreError::InvalidInput(format!("mapping csv header: {e}")))?
+        .clone();
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| StoreError::InvalidInput(format!("mapping csv: {e}")))?;
+        let mut obj = Map::new();
+        let mut provenance = Map::new();
+        for (header, cell) in headers.iter().zip(record.iter()) {
+            if cell.is_empty() {
+                continue;
+            }
+            if let Some(field) = header.strip_prefix("provenance.") {
+                let value = match cell.parse::<i64>() {
+                    Ok(n) if NUMERIC_PROVENANCE_FIELDS.contains(&field) => json!(n),
+                    _ => json!(cell),
+                };
+                provenance.insert(field.to_string(), value);
+            } else if header == "labels" || header == "add_labels" {
+                let labels: Vec<&str> = cell
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty())
+                    .collect();
+                obj.insert(header.to_string(), json!(labels));
+            } else {
+                obj.insert(header.to_string(), json!(cell));
+            }
+        }
+        if !provenance.is_empty() {
+            obj.insert("provenance".into(), JsonValue::Object(provenance));
+        }
+        rows.push(JsonValue::Object(obj));
+    }
+    Ok(rows)
+}
+
+/// Apply a backfill plan in batches, reporting progress on `op`. Cancellation
+/// is checked between batches; batches already applied stay applied.
+pub fn run_backfill(
+    plan: BackfillPlan,
+    store: &Arc<Mutex<Store>>,
+    event_bus: &Arc<EventBus>,
+    op: &OperationHandle,
+) -> Result<JsonValue> {
+    op.set_total(plan.targets.len() as u64);
+    let mut updated = 0u64;
+    let mut errors: Vec<JsonValue> = Vec::new();
+    let mut failed = 0u64;
+
+    for batch in plan.targets.chunks(plan.batch_size) {
+        op.check_cancelled()?;
+        let mut changed = Vec::with_capacity(batch.len());
+        {
+            let mut store = store.lock().unwrap();
+            for (context_id, patch) in batch {
+                let mut set = patch.set.clone();
+                if set.title.is_some() {
+                    set.title_source = Some(TITLE_SOURCE_BACKFILL.to_string());
+                }
+                match store.apply_metadata_patch(*context_id, &set, &patch.add_labels) {
+                    Ok(metadata) => {
+                        updated += 1;
+                        changed.push((*context_id, metadata));
+                    }
+                    Err(err) => {
+                        failed += 1;
+                        if errors.len() < MAX_REPORTED_ERRORS {
+                            errors.push(json!({
+                                "context_id": context_id.to_string(),
+                                "error": err.to_string(),
+                            }));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (context_id, metadata) in changed {
+            event_bus.publish(StoreEvent::ContextMetadataUpdated {
+                context_id: context_id.to_string(),
+                client_tag: metadata.client_tag,
+                title: metadata.title,
+                labels: metadata.labels,
+                has_provenance: metadata.provenance.is_some(),
+            });
+        }
+        op.advance(batch.len() as u64);
+    }
+
+    Ok(json!({
+        "updated": updated,
+        "failed": failed,
+        "errors": errors,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_jsonl_mapping() {
+        let input = r#"
+{"context_id": "42", "labels": ["team:payments"], "provenance": {"service_name": "gen"}}
+{"context_id": 7, "add_labels": ["env:prod"]}
+"#;
+        let rows = parse_mapping(input, MappingFormat::Jsonl).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].0, 42);
+        assert_eq!(
+            rows[0].1.set.labels.as_deref(),
+            Some(&["team:payments".to_string()][..])
+        );
+        assert_eq!(
+            rows[0]
+                .1
+                .set
+                .provenance
+                .as_ref()
+                .and_then(|p| p.service_name.as_deref()),
+            Some("gen")
+        );
+        assert_eq!(rows[1].0, 7);
+        assert_eq!(rows[1].1.add_labels, vec!["env:prod".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_csv_mapping() {
+        let input = "context_id,title,labels,provenance.service_name,provenance.process_pid\n\
+                     12,Nightly run,team:infra;env:prod,cron,4242\n\
+                     13,,,,\n";
+        let rows = parse_mapping(input, MappingFormat::Csv).unwrap();
+        assert_eq!(rows.len(), 2);
+        let (id, patch) = &rows[0];
+        assert_eq!(*id, 12);
+        assert_eq!(patch.set.title.as_deref(), Some("Nightly run"));
+        assert_eq!(
+            patch.set.labels.as_deref(),
+            Some(&["team:infra".to_string(), "env:prod".to_string()][..])
+        );
+        let provenance = patch.set.provenance.as_ref().unwrap();
+        assert_eq!(provenance.service_name.as_deref(), Some("cron"));
+        assert_eq!(provenance.process_pid, Some(4242));
+        assert!(rows[1].1.is_empty());
+    }
+
+    #[test]
+    fn test_parse_mapping_rejects_bad_context_id() {
+        let err = parse_mapping(r#"{"context_id": "abc"}"#, MappingFormat::Jsonl).unwrap_err();
+        assert!(err.to_string().contains("invalid context_id"));
+    }
+}
@@ -0,0 +1,669 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Synthetic load generator (`cxdb-server bench`).
+//!
+//! Generates contexts, turns and filesystem snapshots and writes them through
+//! the same store calls the binary protocol uses (context create and fork,
+//! turn append with a session author, blob upload and snapshot attach), from
+//! several writer threads sharing one store as sessions do. Payloads are
+//! msgpack values of the types in a registry bundle, sized to the configured
+//! distribution by their string fields. The workload is reproducible from
+//! its seed when there is a single writer.
+//!
+//! The report gives throughput and per-operation latency histograms, and the
+//! size of the resulting data directory, for sizing estimates.
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use rmpv::Value;
+use serde::Serialize;
+
+use crate::error::{Result, StoreError};
+use crate::fs_store::{encode_tree_entries, TreeEntry};
+use crate::metrics::{Histogram, HistogramSummary};
+use crate::registry::{FieldDef, RegistryBundle};
+use crate::store::Store;
+use crate::turn_store::TurnAuthor;
+use crate::util::unix_ms;
+
+/// Bundle used when the bench is not given one: a chat-like turn whose
+/// text is token-counted.
+pub const DEFAULT_BUNDLE: &str = r#"{
+  "registry_version": 1,
+  "bundle_id": "cxdb.bench#1",
+  "types": {
+    "cxdb.bench.Turn": {
+      "versions": {
+        "1": {
+          "fields": {
+            "1": { "name": "role", "type": "string" },
+            "2": { "name": "text", "type": "string", "count_tokens": true },
+            "3": { "name": "seq", "type": "uint64" },
+            "4": { "name": "created_at", "type": "unix_ms" }
+          }
+        }
+      }
+    }
+  }
+}"#;
+
+/// Client tag of the bench's sessions.
+pub const BENCH_CLIENT_TAG: &str = "cxdb-bench";
+
+/// Nested `ref` fields deeper than this are left out of payloads.
+const MAX_REF_DEPTH: usize = 3;
+
+/// A distribution of non-negative integers: `N` (fixed), `A..B` (uniform,
+/// inclusive) or `lognormal:MEDIAN:SIGMA`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distribution {
+    Fixed(u64),
+    Uniform(u64, u64),
+    LogNormal { median: f64, sigma: f64 },
+}
+
+impl Distribution {
+    fn sample(&self, rng: &mut Rng) -> u64 {
+        match *self {
+            Distribution::Fixed(n) => n,
+            Distribution::Uniform(low, high) => low + rng.below(high - low + 1),
+            Distribution::LogNormal { median, sigma } => {
+                (median * (sigma * rng.normal()).exp()).round() as u64
+            }
+        }
+    }
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let number = |v: &str| {
+            v.trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite() && *n >= 0.0)
+                .ok_or_else(|| format!("invalid number {v:?} in distribution {s:?}"))
+        };
+        if let Some(params) = s.strip_prefix("lognormal:") {
+            let (median, sigma) = params
+                .split_once(':')
+                .ok_or_else(|| format!("expected lognormal:MEDIAN:SIGMA, got {s:?}"))?;
+            return Ok(Distribution::LogNormal {
+                median: number(median)?,
+                sigma: number(sigma)?,
+            });
+        }
+        if let Some((low, high)) = s.split_once("..") {
+            let (low, high) = (number(low)? as u64, number(high)? as u64);
+            if low > high {
+                return Err(format!("empty range {s:?}"));
+            }
+            return Ok(Distribution::Uniform(low, high));
+        }
+        Ok(Distribution::Fixed(number(s)? as u64))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// Contexts to create, forks included.
+    pub contexts: usize,
+    /// Turns appended per context.
+    pub turns: Distribution,
+    /// Payload size in bytes.
+    pub turn_bytes: Distribution,
+    /// Share of contexts forked from an earlier turn rather than created.
+    pub fork_rate: f64,
+    /// Share of turns followed by a filesystem snapshot.
+    pub fs_rate: f64,
+    /// Files per snapshot tree. Each snapshot after a context's first
+    /// rewrites about a tenth of them, so unchanged files deduplicate.
+    pub fs_files: usize,
+    /// File size in bytes.
+    pub file_bytes: Distribution,
+    /// Concurrent writers, one session each.
+    pub writers: usize,
+    pub seed: u64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            contexts: 100,
+            turns: Distribution::Uniform(10, 50),
+            turn_bytes: Distribution::LogNormal {
+                median: 1024.0,
+                sigma: 1.0,
+            },
+            fork_rate: 0.1,
+            fs_rate: 0.05,
+            fs_files: 50,
+            file_bytes: Distribution::LogNormal {
+                median: 4096.0,
+                sigma: 1.0,
+            },
+            writers: 1,
+            seed: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    pub seed: u64,
+    pub writers: usize,
+    pub contexts: usize,
+    pub forks: usize,
+    pub turns: u64,
+    pub snapshots: u64,
+    /// Uncompressed payload bytes appended.
+    pub payload_bytes: u64,
+    /// File bytes in uploaded snapshot blobs, before deduplication.
+    pub fs_bytes: u64,
+    pub elapsed_ms: u64,
+    pub turns_per_sec: f64,
+    pub payload_mib_per_sec: f64,
+    pub latency: BenchLatency,
+    pub storage: BenchStorage,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchLatency {
+    pub append: HistogramSummary,
+    /// Context creation and fork.
+    pub create: HistogramSummary,
+    /// Blob uploads and attach of one snapshot.
+    pub snapshot: HistogramSummary,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchStorage {
+    pub blobs: usize,
+    pub blobs_pack_bytes: u64,
+    pub turns_log_bytes: u64,
+    pub turns_meta_bytes: u64,
+}
+
+/// A type payloads are generated for.
+#[derive(Debug, Clone)]
+struct BenchType {
+    type_id: String,
+    version: u32,
+    fields: Vec<(u64, FieldDef)>,
+}
+
+/// Run the workload against `store`. The bundle's types must already be in
+/// the store's registry for projections and token counting to see them.
+pub fn run_bench(
+    store: Arc<Mutex<Store>>,
+    bundle: &RegistryBundle,
+    config: &BenchConfig,
+) -> Result<BenchReport> {
+    let types = bench_types(bundle)?;
+    let shared = Arc::new(Shared {
+        store,
+        types,
+        bundle: bundle.clone(),
+        config: config.clone(),
+        next_context: AtomicUsize::new(0),
+        turn_ids: Mutex::new(Vec::new()),
+    });
+
+    let start = Instant::now();
+    let workers: Vec<_> = (0..config.writers.max(1))
+        .map(|writer| {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || Writer::new(shared, writer).run())
+        })
+        .collect();
+    let mut totals = WriterStats::default();
+    for worker in workers {
+        let stats = worker
+            .join()
+            .map_err(|_| StoreError::InvalidInput("bench writer panicked".into()))??;
+        totals.merge(&stats);
+    }
+    let elapsed = start.elapsed();
+
+    let store = shared.store.lock().unwrap();
+    let blobs = store.blob_store.stats();
+    let turns = store.turn_store.stats();
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    Ok(BenchReport {
+        seed: config.seed,
+        writers: config.writers.max(1),
+        contexts: totals.contexts,
+        forks: totals.forks,
+        turns: totals.append.count(),
+        snapshots: totals.snapshot.count(),
+        payload_bytes: totals.payload_bytes,
+        fs_bytes: totals.fs_bytes,
+        elapsed_ms: elapsed.as_millis() as u64,
+        turns_per_sec: totals.append.count() as f64 / secs,
+        payload_mib_per_sec: totals.payload_bytes as f64 / (1024.0 * 1024.0) / secs,
+        latency: BenchLatency {
+            append: totals.append.summary(),
+            create: totals.create.summary(),
+            snapshot: totals.snapshot.summary(),
+        },
+        storage: BenchStorage {
+            blobs: blobs.blobs_total,
+            blobs_pack_bytes: blobs.pack_bytes,
+            turns_log_bytes: turns.turns_log_bytes,
+            turns_meta_bytes: turns.turns_meta_bytes,
+        },
+    })
+}
+
+/// Latest version of every type in the bundle, in type id order.
+fn bench_types(bundle: &RegistryBundle) -> Result<Vec<BenchType>> {
+    let mut types = Vec::new();
+    for (type_id, entry) in &bundle.types {
+        let Some((version, spec)) = entry
+            .versions
+            .iter()
+            .filter_map(|(v, spec)| v.parse::<u32>().ok().map(|v| (v, spec)))
+            .max_by_key(|(v, _)| *v)
+        else {
+            continue;
+        };
+        let mut fields: Vec<(u64, FieldDef)> = spec
+            .fields
+            .iter()
+            .filter_map(|(tag, def)| tag.parse::<u64>().ok().map(|tag| (tag, def.clone())))
+            .collect();
+        fields.sort_by_key(|(tag, _)| *tag);
+        types.push(BenchType {
+            type_id: type_id.clone(),
+            version,
+            fields,
+        });
+    }
+    if types.is_empty() {
+        return Err(StoreError::InvalidInput(format!(
+            "bundle {} has no types",
+            bundle.bundle_id
+        )));
+    }
+    types.sort_by(|a, b| a.type_id.cmp(&b.type_id));
+    Ok(types)
+}
+
+struct Shared {
+    store: Arc<Mutex<Store>>,
+    types: Vec<BenchType>,
+    bundle: RegistryBundle,
+    config: BenchConfig,
+    next_context: AtomicUsize,
+    /// Every turn appended so far, for forks to start from.
+    turn_ids: Mutex<Vec<u64>>,
+}
+
+#[derive(Default)]
+struct WriterStats {
+    contexts: usize,
+    forks: usize,
+    payload_bytes: u64,
+    fs_bytes: u64,
+    append: Histogram,
+    create: Histogram,
+    snapshot: Histogram,
+}
+
+impl WriterStats {
+    fn merge(&mut self, other: &WriterStats) {
+        self.contexts += other.contexts;
+        self.forks += other.forks;
+        self.payload_bytes += other.payload_bytes;
+        self.fs_bytes += other.fs_bytes;
+        self.append.merge(&other.append);
+        self.create.merge(&other.create);
+        self.snapshot.merge(&other.snapshot);
+    }
+}
+
+struct Writer {
+    shared: Arc<Shared>,
+    rng: Rng,
+    author: TurnAuthor,
+    stats: WriterStats,
+    seq: u64,
+}
+
+impl Writer {
+    fn new(shared: Arc<Shared>, writer: usize) -> Self {
+        let seed = shared.config.seed.wrapping_add(writer as u64);
+        Self {
+            shared,
+            rng: Rng::new(seed),
+            author: TurnAuthor {
+                session_id: writer as u64 + 1,
+                client_tag: BENCH_CLIENT_TAG.to_string(),
+                principal: None,
+            },
+            stats: WriterStats::default(),
+            seq: 0,
+        }
+    }
+
+    fn run(mut self) -> Result<WriterStats> {
+        let config = self.shared.config.clone();
+        while self.shared.next_context.fetch_add(1, Ordering::Relaxed) < config.contexts {
+            let context_id = self.create_context(config.fork_rate)?;
+            // The context's current snapshot tree.
+            let mut files: Vec<TreeEntry> = Vec::new();
+            for _ in 0..config.turns.sample(&mut self.rng) {
+                let turn_id = self.append(context_id)?;
+                if self.rng.chance(config.fs_rate) {
+                    self.snapshot(context_id, turn_id, &mut files)?;
+                }
+            }
+        }
+        Ok(self.stats)
+    }
+
+    fn create_context(&mut self, fork_rate: f64) -> Result<u64> {
+        let base = if self.rng.chance(fork_rate) {
+            let turn_ids = self.shared.turn_ids.lock().unwrap();
+            (!turn_ids.is_empty()).then(|| turn_ids[self.rng.below(turn_ids.len() as u64) as usize])
+        } else {
+            None
+        };
+        let start = Instant::now();
+        let head = {
+            let mut store = self.shared.store.lock().unwrap();
+            match base {
+                Some(turn_id) => store.fork_context(turn_id)?,
+                None => store.create_context(0)?,
+            }
+        };
+        self.stats.create.record(start.elapsed());
+        self.stats.contexts += 1;
+        self.stats.forks += base.is_some() as usize;
+        Ok(head.context_id)
+    }
+
+    fn append(&mut self, context_id: u64) -> Result<u64> {
+        let shared = Arc::clone(&self.shared);
+        let ty = &shared.types[self.rng.below(shared.types.len() as u64) as usize];
+        let target = shared.config.turn_bytes.sample(&mut self.rng);
+        let payload = self.payload(ty, target)?;
+        let hash = *blake3::hash(&payload).as_bytes();
+
+        let start = Instant::now();
+        let (record, _) = shared.store.lock().unwrap().append_turn_with_author(
+            context_id,
+            0,
+            ty.type_id.clone(),
+            ty.version,
+            1,
+            0,
+            payload.len() as u32,
+            hash,
+            &payload,
+            Some(self.author.clone()),
+        )?;
+        self.stats.append.record(start.elapsed());
+        self.stats.payload_bytes += payload.len() as u64;
+        shared.turn_ids.lock().unwrap().push(record.turn_id);
+        Ok(record.turn_id)
+    }
+
+    /// Upload a snapshot tree and attach it to `turn_id`. The first snapshot
+    /// of a context writes every file; later ones rewrite about a tenth.
+    fn snapshot(
+        &mut self,
+        context_id: u64,
+        turn_id: u64,
+        files: &mut Vec<TreeEntry>,
+    ) -> Result<()> {
+        let config = &self.shared.config;
+        let mut blobs: Vec<([u8; 32], Vec<u8>)> = Vec::new();
+        let first = files.is_empty() && config.fs_files > 0;
+        let rewrite: Vec<usize> = if first {
+            for i in 0..config.fs_files {
+                files.push(TreeEntry {
+                    name: format!("file-{i:05}.txt"),
+                    kind: 0,
+                    mode: 0o644,
+                    size: 0,
+                    hash: Vec::new(),
+                });
+            }
+            (0..files.len()).collect()
+        } else if files.is_empty() {
+            Vec::new()
+        } else {
+            let n = (files.len() / 10).max(1);
+            (0..n)
+                .map(|_| self.rng.below(files.len() as u64) as usize)
+                .collect()
+        };
+        for index in rewrite {
+            let size = config.file_bytes.sample(&mut self.rng);
+            let content = self.rng.text(size as usize).into_bytes();
+            let hash = *blake3::hash(&content).as_bytes();
+            files[index].size = content.len() as u64;
+            files[index].hash = hash.to_vec();
+            self.stats.fs_bytes += content.len() as u64;
+            blobs.push((hash, content));
+        }
+        let root = encode_tree_entries(files)?;
+        let root_hash = *blake3::hash(&root).as_bytes();
+        blobs.push((root_hash, root));
+
+        let start = Instant::now();
+        {
+            let mut store = self.shared.store.lock().unwrap();
+            for (hash, data) in &blobs {
+                store.put_blob(*hash, data, Some(context_id), Some(BENCH_CLIENT_TAG))?;
+            }
+            store.attach_fs(turn_id, root_hash)?;
+        }
+        self.stats.snapshot.record(start.elapsed());
+        Ok(())
+    }
+
+    /// A msgpack payload of `ty` of roughly `target` bytes: every field gets
+    /// a value, and string fields share what is left of the target.
+    fn payload(&mut self, ty: &BenchType, target: u64) -> Result<Vec<u8>> {
+        self.seq += 1;
+        let shared = Arc::clone(&self.shared);
+        let strings = count_strings(&shared, &ty.fields, 0).max(1);
+        // Fill strings after sizing the rest with empty ones.
+        let skeleton = self.value(&shared, &ty.fields, 0, 0);
+        let overhead = encode(&skeleton)?.len() as u64;
+        let mut per_string = target.saturating_sub(overhead) / strings as u64;
+        // Longer strings take longer length headers; take those off too.
+        let header = match per_string {
+            0..=31 => 0,
+            32..=255 => 1,
+            256..=65535 => 2,
+            _ => 4,
+        };
+        per_string = per_string.saturating_sub(header);
+        encode(&self.value(&shared, &ty.fields, 0, per_string as usize))
+    }
+
+    fn value(
+        &mut self,
+        shared: &Shared,
+        fields: &[(u64, FieldDef)],
+        depth: usize,
+        string_len: usize,
+    ) -> Value {
+        let mut map = Vec::with_capacity(fields.len());
+        for (tag, field) in fields {
+            if let Some(value) = self.field_value(shared, field, depth, string_len) {
+                map.push((Value::from(*tag), value));
+            }
+        }
+        Value::Map(map)
+    }
+
+    fn field_value(
+        &mut self,
+        shared: &Shared,
+        field: &FieldDef,
+        depth: usize,
+        string_len: usize,
+    ) -> Option<Value> {
+        if let Some(enum_id) = &field.enum_ref {
+            let values = shared.bundle.enums.get(enum_id)?;
+            let mut keys: Vec<u64> = values.keys().filter_map(|k| k.parse().ok()).collect();
+            keys.sort_unstable();
+            return (!keys.is_empty())
+                .then(|| Value::from(keys[self.rng.below(keys.len() as u64) as usize]));
+        }
+        Some(match field.field_type.as_str() {
+            "string" => Value::from(self.rng.text(string_len)),
+            "bool" => Value::from(self.rng.chance(0.5)),
+            "u8" | "uint8" => Value::from(self.rng.below(256)),
+            "u32" | "uint32" | "int32" => Value::from(self.rng.below(1 << 31)),
+            "u64" | "uint64" | "int64" => Value::from(self.seq),
+            "unix_ms" | "time_ms" | "timestamp_ms" => Value::from(unix_ms()),
+            "bytes" | "typed_blob" => Value::Binary(self.rng.text(16).into_bytes()),
+            "ref" if depth < MAX_REF_DEPTH => {
+                let ty = shared.find_type(field.type_ref.as_deref()?)?;
+                self.value(shared, &ty.fields, depth + 1, string_len)
+            }
+            "array" => Value::Array(Vec::new()),
+            "map" => Value::Map(Vec::new()),
+            _ => return None,
+        })
+    }
+}
+
+impl Shared {
+    fn find_type(&self, type_id: &str) -> Option<&BenchType> {
+        self.types.iter().find(|ty| ty.type_id == type_id)
+    }
+}
+
+/// String fields a payload of these fields holds, nested refs included.
+fn count_strings(shared: &Shared, fields: &[(u64, FieldDef)], depth: usize) -> usize {
+    fields
+        .iter()
+        .map(|(_, field)| match field.field_type.as_str() {
+            "string" if field.enum_ref.is_none() => 1,
+            "ref" if depth < MAX_REF_DEPTH => field
+                .type_ref
+                .as_deref()
+                .and_then(|type_ref| shared.find_type(type_ref))
+                .map_or(0, |ty| count_strings(shared, &ty.fields, depth + 1)),
+            _ => 0,
+        })
+        .sum()
+}
+
+fn encode(value: &Value) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, value)
+        .map_err(|e| StoreError::InvalidInput(format!("msgpack encode error: {e}")))?;
+    Ok(buf)
+}
+
+/// SplitMix64: small, seedable and good enough for workload shapes.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`; `n` must be non-zero.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, p: f64) -> bool {
+        self.unit() < p
+    }
+
+    /// Standard normal, by Box-Muller.
+    fn normal(&mut self) -> f64 {
+        let u = 1.0 - self.unit();
+        let v = self.unit();
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+
+    /// `len` bytes of lowercase words, so payloads compress and tokenize
+    /// roughly like prose.
+    fn text(&mut self, len: usize) -> String {
+        const WORDS: &[&str] = &[
+            "the",
+            "agent",
+            "context",
+            "turn",
+            "tool",
+            "call",
+            "result",
+            "file",
+            "read",
+            "write",
+            "error",
+            "retry",
+            "plan",
+            "step",
+            "done",
+            "user",
+            "assistant",
+            "value",
+        ];
+        let mut text = String::with_capacity(len + 16);
+        while text.len() < len {
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(WORDS[self.below(WORDS.len() as u64) as usize]);
+        }
+        text.truncate(len);
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distribution_parsing() {
+        assert_eq!("512".parse(), Ok(Distribution::Fixed(512)));
+        assert_eq!("10..20".parse(), Ok(Distribution::Uniform(10, 20)));
+        assert_eq!(
+            "lognormal:1024:0.5".parse(),
+            Ok(Distribution::LogNormal {
+                median: 1024.0,
+                sigma: 0.5
+            })
+        );
+        assert!("20..10".parse::<Distribution>().is_err());
+        assert!("lognormal:1024".parse::<Distribution>().is_err());
+        assert!("-3".parse::<Distribution>().is_err());
+
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            let n = Distribution::Uniform(10, 20).sample(&mut rng);
+            assert!((10..=20).contains(&n));
+        }
+    }
+}
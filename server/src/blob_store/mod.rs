@@ -5,14 +5,25 @@ use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use crc32fast::Hasher;
 
 use crate::error::{Result, StoreError};
+use crate::turn_store::CommitPipeline;
+use crate::util::sync_dir;
 
 const BLOB_MAGIC: u32 = 0x42534C42; // 'B''S''L''B'
 const BLOB_VERSION: u16 = 1;
+/// Pack record header: magic, version, codec, raw_len, stored_len, hash.
+const RECORD_HEADER_LEN: u64 = 4 + 2 + 2 + 4 + 4 + 32;
+/// Pack record trailer: CRC-32.
+const RECORD_TRAILER_LEN: u64 = 4;
+/// Index entry: hash, offset, raw_len, stored_len, codec, reserved.
+const INDEX_ENTRY_LEN: u64 = 32 + 8 + 4 + 4 + 2 + 2;
+/// Present while a sweep swaps in its new pack and index; see `recover_sweep`.
+const SWEEP_COMMIT: &str = "sweep.commit";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BlobCodec {
@@ -28,12 +39,26 @@ pub struct BlobIndexEntry {
     pub codec: BlobCodec,
 }
 
+impl BlobIndexEntry {
+    /// Bytes the blob's record occupies in the pack.
+    pub fn record_len(&self) -> u64 {
+        RECORD_HEADER_LEN + self.stored_len as u64 + RECORD_TRAILER_LEN
+    }
+}
+
 pub struct BlobStore {
     pack_path: PathBuf,
     idx_path: PathBuf,
+    epoch_path: PathBuf,
     pack_file: File,
     idx_file: File,
     index: HashMap<[u8; 32], BlobIndexEntry>,
+    /// Pack length at the previous sweep. Blobs at or past it were written
+    /// since and are never swept.
+    sweep_epoch: u64,
+    /// Group commit pipeline syncing the pack and index, re-pointed at the
+    /// new files when a sweep rewrites them.
+    commit: Option<Arc<CommitPipeline>>,
 }
 
 impl BlobStore {
@@ -41,33 +66,53 @@ impl BlobStore {
         std::fs::create_dir_all(dir)?;
         let pack_path = dir.join("blobs.pack");
         let idx_path = dir.join("blobs.idx");
+        recover_sweep(dir)?;
 
         let pack_file = OpenOptions::new()
             .create(true)
-            .truncate(true)
+            .truncate(false)
             .read(true)
             .write(true)
             .open(&pack_path)?;
 
         let idx_file = OpenOptions::new()
             .create(true)
-            .truncate(true)
+            .truncate(false)
             .read(true)
             .write(true)
             .open(&idx_path)?;
 
+        let epoch_path = dir.join("sweep.epoch");
+        let sweep_epoch = match std::fs::read(&epoch_path) {
+            Ok(bytes) if bytes.len() == 8 => u64::from_le_bytes(bytes.try_into().unwrap()),
+            _ => 0,
+        };
+
         let mut store = Self {
             pack_path,
             idx_path,
+            epoch_path,
             pack_file,
             idx_file,
             index: HashMap::new(),
+            sweep_epoch,
+            commit: None,
         };
 
         store.load_index()?;
+        // An epoch past the end of the pack would make new blobs look old.
+        store.sweep_epoch = store.sweep_epoch.min(file_len(&store.pack_path));
         Ok(store)
     }
 
+    /// Sync the pack and index with every group commit batch.
+    pub fn track_files(&mut self, pipeline: Arc<CommitPipeline>) -> Result<()> {
+        pipeline.track("blobs.pack", &self.pack_file)?;
+        pipeline.track("blobs.idx", &self.idx_file)?;
+        self.commit = Some(pipeline);
+        Ok(())
+    }
+
     fn load_index(&mut self) -> Result<()> {
         self.idx_file.seek(SeekFrom::Start(0))?;
         let mut buf = Vec::new();
@@ -184,24 +229,19 @@ impl BlobStore {
         self.pack_file.write_u32::<LittleEndian>(crc)?;
         self.pack_file.flush()?;
 
-        // append to index
-        let mut idx_entry = Vec::with_capacity(32 + 8 + 4 + 4 + 2 + 2);
-        idx_entry.extend_from_slice(&hash);
-        idx_entry.write_u64::<LittleEndian>(offset)?;
-        idx_entry.write_u32::<LittleEndian>(raw_len)?;
-        idx_entry.write_u32::<LittleEndian>(stored_len)?;
-        idx_entry.write_u16::<LittleEndian>(codec as u16)?;
-        idx_entry.write_u16::<LittleEndian>(0)?;
-        self.idx_file.seek(SeekFrom::End(0))?;
-        self.idx_file.write_all(&idx_entry)?;
-        self.idx_file.flush()?;
-
         let entry = BlobIndexEntry {
             offset,
             raw_len,
             stored_len,
             codec,
         };
+
+        // append to index
+        self.idx_file.seek(SeekFrom::End(0))?;
+        self.idx_file
+            .write_all(&encode_index_entry(&hash, &entry)?)?;
+        self.idx_file.flush()?;
+
         self.index.insert(hash, entry.clone());
         Ok(entry)
     }
@@ -272,6 +312,202 @@ impl BlobStore {
         Ok(raw_bytes)
     }
 
+    /// Read up to `len` bytes of a blob's raw content starting at `offset`
+    /// without loading the rest. Uncompressed blobs are read in place from the
+    /// pack; compressed ones are decoded as a stream up to the end of the
+    /// range. The record CRC covers the whole blob, so it is not checked here.
+    pub fn get_range(&mut self, hash: &[u8; 32], offset: u64, len: u64) -> Result<Vec<u8>> {
+        let entry = self
+            .index
+            .get(hash)
+            .ok_or_else(|| StoreError::NotFound("blob".into()))?
+            .clone();
+        let len = len.min((entry.raw_len as u64).saturating_sub(offset));
+
+        self.pack_file.seek(SeekFrom::Start(entry.offset))?;
+        if self.pack_file.read_u32::<LittleEndian>()? != BLOB_MAGIC {
+            return Err(StoreError::Corrupt("invalid blob magic".into()));
+        }
+        self.pack_file
+            .seek(SeekFrom::Start(entry.offset + RECORD_HEADER_LEN - 32))?;
+        let mut stored_hash = [0u8; 32];
+        self.pack_file.read_exact(&mut stored_hash)?;
+        if &stored_hash != hash {
+            return Err(StoreError::Corrupt("blob hash mismatch".into()));
+        }
+
+        let mut out = Vec::with_capacity(len as usize);
+        match entry.codec {
+            BlobCodec::None => {
+                self.pack_file.seek(SeekFrom::Current(offset as i64))?;
+                (&mut self.pack_file).take(len).read_to_end(&mut out)?;
+            }
+            BlobCodec::Zstd => {
+                let stored = (&mut self.pack_file).take(entry.stored_len as u64);
+                let mut decoder = zstd::Decoder::new(stored)
+                    .map_err(|e| StoreError::Corrupt(format!("zstd decode failed: {e}")))?;
+                std::io::copy(&mut (&mut decoder).take(offset), &mut std::io::sink())
+                    .map_err(|e| StoreError::Corrupt(format!("zstd decode failed: {e}")))?;
+                decoder
+                    .take(len)
+                    .read_to_end(&mut out)
+                    .map_err(|e| StoreError::Corrupt(format!("zstd decode failed: {e}")))?;
+            }
+        }
+        if out.len() as u64 != len {
+            return Err(StoreError::Corrupt("blob length mismatch".into()));
+        }
+        Ok(out)
+    }
+
+    /// Index entries of all stored blobs.
+    pub fn entries(&self) -> impl Iterator<Item = (&[u8; 32], &BlobIndexEntry)> {
+        self.index.iter()
+    }
+
+    /// Rewrite the pack without the blobs `is_live` rejects. Blobs written
+    /// since the previous sweep are kept regardless, so blobs uploaded ahead
+    /// of the turn or snapshot that references them survive one collection.
+    /// With `dry_run` nothing is changed. Otherwise the new pack and index
+    /// replace the old ones by rename and the sweep epoch advances to the end
+    /// of the pack.
+    pub fn sweep(
+        &mut self,
+        is_live: impl Fn(&[u8; 32]) -> bool,
+        dry_run: bool,
+    ) -> Result<SweepStats> {
+        let mut stats = SweepStats {
+            pack_bytes_before: file_len(&self.pack_path),
+            idx_bytes_before: file_len(&self.idx_path),
+            ..Default::default()
+        };
+        let mut kept: Vec<([u8; 32], BlobIndexEntry)> = Vec::new();
+        for (hash, entry) in &self.index {
+            if is_live(hash) {
+                kept.push((*hash, entry.clone()));
+            } else if entry.offset >= self.sweep_epoch {
+                stats.young_blobs += 1;
+                stats.young_bytes += entry.record_len();
+                kept.push((*hash, entry.clone()));
+            } else {
+                stats.swept_blobs += 1;
+                stats.swept_bytes += entry.record_len();
+            }
+        }
+        stats.kept_blobs = kept.len() as u64;
+
+        if dry_run {
+            stats.pack_bytes_after = stats.pack_bytes_before - stats.swept_bytes;
+            stats.idx_bytes_after = if stats.swept_blobs > 0 {
+                stats.kept_blobs * INDEX_ENTRY_LEN
+            } else {
+                stats.idx_bytes_before
+            };
+            return Ok(stats);
+        }
+
+        if stats.swept_blobs > 0 {
+            kept.sort_by_key(|(_, entry)| entry.offset);
+            let pack_tmp = self.pack_path.with_extension("pack.tmp");
+            let idx_tmp = self.idx_path.with_extension("idx.tmp");
+            let mut pack = std::io::BufWriter::new(File::create(&pack_tmp)?);
+            let mut idx = std::io::BufWriter::new(File::create(&idx_tmp)?);
+            let mut index = HashMap::with_capacity(kept.len());
+            let mut offset = 0u64;
+            for (hash, entry) in kept {
+                let len = entry.record_len();
+                self.pack_file.seek(SeekFrom::Start(entry.offset))?;
+                let copied = std::io::copy(&mut (&mut self.pack_file).take(len), &mut pack)?;
+                if copied != len {
+                    return Err(StoreError::Corrupt("blob record truncated".into()));
+                }
+                let moved = BlobIndexEntry { offset, ..entry };
+                idx.write_all(&encode_index_entry(&hash, &moved)?)?;
+                index.insert(hash, moved);
+                offset += len;
+            }
+            pack.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+            idx.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+            // The pack and index are swapped by two renames. The marker makes
+            // the pair atomic: once it is durable a crash between (or before)
+            // the renames is finished on open instead of pairing the new pack
+            // with the old index.
+            let dir = self.pack_path.parent().expect("pack has a parent");
+            let marker = dir.join(SWEEP_COMMIT);
+            File::create(&marker)?.sync_all()?;
+            sync_dir(dir)?;
+            std::fs::rename(&pack_tmp, &self.pack_path)?;
+            std::fs::rename(&idx_tmp, &self.idx_path)?;
+            sync_dir(dir)?;
+            std::fs::remove_file(&marker)?;
+            sync_dir(dir)?;
+            let reopen = |path: &Path| OpenOptions::new().read(true).write(true).open(path);
+            self.pack_file = reopen(&self.pack_path)?;
+            self.idx_file = reopen(&self.idx_path)?;
+            self.index = index;
+            if let Some(pipeline) = self.commit.take() {
+                self.track_files(pipeline)?;
+            }
+        }
+
+        stats.pack_bytes_after = file_len(&self.pack_path);
+        stats.idx_bytes_after = file_len(&self.idx_path);
+        self.sweep_epoch = stats.pack_bytes_after;
+        let epoch_tmp = self.epoch_path.with_extension("tmp");
+        let mut epoch = File::create(&epoch_tmp)?;
+        epoch.write_all(&self.sweep_epoch.to_le_bytes())?;
+        epoch.sync_all()?;
+        std::fs::rename(&epoch_tmp, &self.epoch_path)?;
+        Ok(stats)
+    }
+
+    /// Rewrite `blobs.idx` in `dir` from a scan of `blobs.pack`, for an index
+    /// that was lost or damaged. The scan stops at the first record that does
+    /// not check out (a torn tail from a crash mid-write, or corruption);
+    /// blobs past it are not indexed. Must not run while a store has the
+    /// directory open.
+    pub fn rebuild_index(dir: &Path) -> Result<IndexRebuildStats> {
+        let pack_path = dir.join("blobs.pack");
+        let idx_path = dir.join("blobs.idx");
+        let mut stats = IndexRebuildStats {
+            pack_bytes: file_len(&pack_path),
+            ..Default::default()
+        };
+        let mut pack = std::io::BufReader::new(File::open(&pack_path)?);
+        let idx_tmp = idx_path.with_extension("idx.tmp");
+        let mut idx = std::io::BufWriter::new(File::create(&idx_tmp)?);
+        let mut seen = std::collections::HashSet::new();
+
+        let mut offset = 0u64;
+        while offset + RECORD_HEADER_LEN + RECORD_TRAILER_LEN <= stats.pack_bytes {
+            let Some((hash, entry)) = read_record(&mut pack, offset)? else {
+                break;
+            };
+            offset += entry.record_len();
+            // The store never writes a blob twice, but a lost index may have
+            // let it; the first copy is the one that was indexed.
+            if seen.insert(hash) {
+                idx.write_all(&encode_index_entry(&hash, &entry)?)?;
+                stats.blobs += 1;
+            }
+        }
+        stats.unindexed_bytes = stats.pack_bytes - offset;
+        idx.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(&idx_tmp, &idx_path)?;
+        sync_dir(dir)?;
+        Ok(stats)
+    }
+
+    /// When the last sweep finished, if any has run.
+    pub fn last_sweep_unix_ms(&self) -> Option<u64> {
+        let modified = std::fs::metadata(&self.epoch_path).ok()?.modified().ok()?;
+        modified
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_millis() as u64)
+    }
+
     pub fn stats(&self) -> BlobStoreStats {
         BlobStoreStats {
             blobs_total: self.index.len(),
@@ -291,6 +527,156 @@ impl BlobStore {
     }
 }
 
+/// Read access to blobs by content hash. Implemented by the blob store itself
+/// and by [`crate::keys::SealedBlobs`], which opens encrypted blobs.
+pub trait BlobSource {
+    fn get_blob(&mut self, hash: &[u8; 32]) -> Result<Vec<u8>>;
+
+    /// Up to `len` bytes of a blob starting at `offset`. The default loads
+    /// the whole blob; sources that can read in place override it.
+    fn get_blob_range(&mut self, hash: &[u8; 32], offset: u64, len: u64) -> Result<Vec<u8>> {
+        let blob = self.get_blob(hash)?;
+        let start = (offset as usize).min(blob.len());
+        let end = start.saturating_add(len as usize).min(blob.len());
+        Ok(blob[start..end].to_vec())
+    }
+
+    /// Raw length of a blob if known without reading it.
+    fn blob_len(&self, _hash: &[u8; 32]) -> Option<u64> {
+        None
+    }
+}
+
+impl BlobSource for BlobStore {
+    fn get_blob(&mut self, hash: &[u8; 32]) -> Result<Vec<u8>> {
+        self.get(hash)
+    }
+
+    fn get_blob_range(&mut self, hash: &[u8; 32], offset: u64, len: u64) -> Result<Vec<u8>> {
+        self.get_range(hash, offset, len)
+    }
+
+    fn blob_len(&self, hash: &[u8; 32]) -> Option<u64> {
+        self.raw_len(hash).map(u64::from)
+    }
+}
+
+/// Write access to blobs by content hash, used when the server builds blobs
+/// itself (overlay fs snapshots). Implemented by the blob store and by
+/// [`crate::keys::SealedBlobs`], which seals what it writes.
+pub trait BlobSink {
+    fn has_blob(&self, hash: &[u8; 32]) -> bool;
+    /// Store `data` under its content hash; returns true if it was new.
+    fn put_blob(&mut self, hash: [u8; 32], data: &[u8]) -> Result<bool>;
+}
+
+impl BlobSink for BlobStore {
+    fn has_blob(&self, hash: &[u8; 32]) -> bool {
+        self.contains(hash)
+    }
+
+    fn put_blob(&mut self, hash: [u8; 32], data: &[u8]) -> Result<bool> {
+        let was_new = !self.contains(&hash);
+        self.put_if_absent(hash, data)?;
+        Ok(was_new)
+    }
+}
+
+/// Reference counts for blobs shared by several owners (turn payloads).
+///
+/// Identical payloads appended to many contexts are stored once; each turn
+/// holds a reference. Anything that deletes or compacts blobs must only drop a
+/// blob once [`RefCounts::release`] returns 0.
+#[derive(Debug, Default)]
+pub struct RefCounts {
+    /// hash -> (references, raw length)
+    counts: HashMap<[u8; 32], (u32, u64)>,
+    /// Sum of raw lengths over all references.
+    logical_bytes: u64,
+    /// Sum of raw lengths over distinct blobs.
+    unique_bytes: u64,
+}
+
+impl RefCounts {
+    /// Add a reference; returns the new count (1 = first reference).
+    pub fn retain(&mut self, hash: [u8; 32], raw_len: u64) -> u32 {
+        let entry = self.counts.entry(hash).or_insert((0, raw_len));
+        if entry.0 == 0 {
+            self.unique_bytes += raw_len;
+        }
+        entry.0 += 1;
+        self.logical_bytes += entry.1;
+        entry.0
+    }
+
+    /// Drop a reference; returns the remaining count. At 0 the blob is
+    /// unreferenced and may be collected.
+    pub fn release(&mut self, hash: &[u8; 32]) -> u32 {
+        let Some(entry) = self.counts.get_mut(hash) else {
+            return 0;
+        };
+        entry.0 -= 1;
+        self.logical_bytes -= entry.1;
+        let remaining = entry.0;
+        if remaining == 0 {
+            self.unique_bytes -= entry.1;
+            self.counts.remove(hash);
+        }
+        remaining
+    }
+
+    pub fn count(&self, hash: &[u8; 32]) -> u32 {
+        self.counts.get(hash).map(|e| e.0).unwrap_or(0)
+    }
+
+    pub fn stats(&self) -> DedupStats {
+        let references = self.counts.values().map(|e| e.0 as u64).sum();
+        DedupStats {
+            references,
+            unique_blobs: self.counts.len() as u64,
+            logical_bytes: self.logical_bytes,
+            unique_bytes: self.unique_bytes,
+        }
+    }
+}
+
+/// Deduplication totals from a [`RefCounts`] table.
+#[derive(Debug, Clone, Default)]
+pub struct DedupStats {
+    pub references: u64,
+    pub unique_blobs: u64,
+    /// Bytes as if every reference stored its own copy.
+    pub logical_bytes: u64,
+    pub unique_bytes: u64,
+}
+
+impl DedupStats {
+    /// References that reused an existing blob.
+    pub fn hits(&self) -> u64 {
+        self.references - self.unique_blobs
+    }
+
+    pub fn saved_bytes(&self) -> u64 {
+        self.logical_bytes - self.unique_bytes
+    }
+}
+
+/// Outcome of [`BlobStore::sweep`]. Byte counts are pack bytes.
+#[derive(Debug, Clone, Default)]
+pub struct SweepStats {
+    pub swept_blobs: u64,
+    pub swept_bytes: u64,
+    /// Blobs kept, live or young.
+    pub kept_blobs: u64,
+    /// Unreferenced blobs kept because they were written since the previous sweep.
+    pub young_blobs: u64,
+    pub young_bytes: u64,
+    pub pack_bytes_before: u64,
+    pub pack_bytes_after: u64,
+    pub idx_bytes_before: u64,
+    pub idx_bytes_after: u64,
+}
+
 #[derive(Debug, Clone)]
 pub struct BlobStoreStats {
     pub blobs_total: usize,
@@ -298,6 +684,104 @@ pub struct BlobStoreStats {
     pub idx_bytes: u64,
 }
 
+/// Outcome of [`BlobStore::rebuild_index`].
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct IndexRebuildStats {
+    pub blobs: u64,
+    pub pack_bytes: u64,
+    /// Pack bytes past the last record that checked out.
+    pub unindexed_bytes: u64,
+}
+
+/// Finish or discard a sweep that a crash interrupted. With the commit
+/// marker present the new pack and index are complete, so whichever of them
+/// was not yet renamed into place is; without it any leftover temporaries
+/// are incomplete and the old pair is still current.
+fn recover_sweep(dir: &Path) -> Result<()> {
+    let marker = dir.join(SWEEP_COMMIT);
+    let swaps = [
+        (dir.join("blobs.pack.tmp"), dir.join("blobs.pack")),
+        (dir.join("blobs.idx.tmp"), dir.join("blobs.idx")),
+    ];
+    if marker.exists() {
+        for (tmp, path) in &swaps {
+            if tmp.exists() {
+                std::fs::rename(tmp, path)?;
+            }
+        }
+        sync_dir(dir)?;
+        std::fs::remove_file(&marker)?;
+        sync_dir(dir)?;
+    } else {
+        for (tmp, _) in &swaps {
+            match std::fs::remove_file(tmp) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read and verify the pack record at the reader's position, which is
+/// `offset`. None if it is incomplete or fails its checks.
+fn read_record(pack: &mut impl Read, offset: u64) -> Result<Option<([u8; 32], BlobIndexEntry)>> {
+    let mut header = [0u8; RECORD_HEADER_LEN as usize];
+    if pack.read_exact(&mut header).is_err() {
+        return Ok(None);
+    }
+    let mut cursor = std::io::Cursor::new(&header[..]);
+    let magic = cursor.read_u32::<LittleEndian>()?;
+    let version = cursor.read_u16::<LittleEndian>()?;
+    let codec = match cursor.read_u16::<LittleEndian>()? {
+        0 => BlobCodec::None,
+        1 => BlobCodec::Zstd,
+        _ => return Ok(None),
+    };
+    let raw_len = cursor.read_u32::<LittleEndian>()?;
+    let stored_len = cursor.read_u32::<LittleEndian>()?;
+    let mut hash = [0u8; 32];
+    cursor.read_exact(&mut hash)?;
+    if magic != BLOB_MAGIC || version != BLOB_VERSION {
+        return Ok(None);
+    }
+
+    let mut stored_bytes = vec![0u8; stored_len as usize];
+    let mut crc = [0u8; 4];
+    if pack.read_exact(&mut stored_bytes).is_err() || pack.read_exact(&mut crc).is_err() {
+        return Ok(None);
+    }
+    let mut hasher = Hasher::new();
+    hasher.update(&header);
+    hasher.update(&stored_bytes);
+    if hasher.finalize() != u32::from_le_bytes(crc) {
+        return Ok(None);
+    }
+
+    Ok(Some((
+        hash,
+        BlobIndexEntry {
+            offset,
+            raw_len,
+            stored_len,
+            codec,
+        },
+    )))
+}
+
+/// Index entry: hash(32) + offset(8) + raw_len(4) + stored_len(4) + codec(2) + reserved(2).
+fn encode_index_entry(hash: &[u8; 32], entry: &BlobIndexEntry) -> Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(32 + 8 + 4 + 4 + 2 + 2);
+    buf.extend_from_slice(hash);
+    buf.write_u64::<LittleEndian>(entry.offset)?;
+    buf.write_u32::<LittleEndian>(entry.raw_len)?;
+    buf.write_u32::<LittleEndian>(entry.stored_len)?;
+    buf.write_u16::<LittleEndian>(entry.codec as u16)?;
+    buf.write_u16::<LittleEndian>(0)?;
+    Ok(buf)
+}
+
 fn file_len(path: &PathBuf) -> u64 {
     std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
 }
@@ -0,0 +1,193 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Timeline bookmarks.
+//!
+//! A bookmark names a turn of a context ("the interesting part starts
+//! here") so readers can jump to it. Additions and deletions are appended to
+//! a JSON-lines log; replaying it rebuilds the live bookmarks, and ids keep
+//! counting past deleted ones.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::jsonl_log::open_log;
+use crate::util::unix_ms;
+
+/// Longest bookmark name, in characters.
+pub const MAX_NAME_CHARS: usize = 200;
+/// Longest bookmark note, in characters.
+pub const MAX_NOTE_CHARS: usize = 4000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub bookmark_id: u64,
+    pub context_id: u64,
+    pub turn_id: u64,
+    /// Depth of the turn, so clients can page to it with `from_depth`.
+    pub depth: u32,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    pub created_at_unix_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum BookmarkEntry {
+    Add(Bookmark),
+    Delete {
+        context_id: u64,
+        bookmark_id: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        principal: Option<String>,
+        at_unix_ms: u64,
+    },
+}
+
+pub struct Bookmarks {
+    file: File,
+    /// Live bookmarks by context, then id.
+    contexts: BTreeMap<u64, BTreeMap<u64, Bookmark>>,
+    next_id: u64,
+}
+
+impl Bookmarks {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("bookmarks.jsonl");
+        let (file, entries) = open_log::<BookmarkEntry>(&path)?;
+
+        let mut contexts: BTreeMap<u64, BTreeMap<u64, Bookmark>> = BTreeMap::new();
+        let mut next_id = 1;
+        for entry in entries {
+            match entry {
+                BookmarkEntry::Add(bookmark) => {
+                    next_id = next_id.max(bookmark.bookmark_id + 1);
+                    contexts
+                        .entry(bookmark.context_id)
+                        .or_default()
+                        .insert(bookmark.bookmark_id, bookmark);
+                }
+                BookmarkEntry::Delete {
+                    context_id,
+                    bookmark_id,
+                    ..
+                } => {
+                    if let Some(bookmarks) = contexts.get_mut(&context_id) {
+                        bookmarks.remove(&bookmark_id);
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            file,
+            contexts,
+            next_id,
+        })
+    }
+
+    /// Bookmarks of a context, in timeline order (by depth, then id).
+    pub fn list(&self, context_id: u64) -> Vec<&Bookmark> {
+        let mut bookmarks: Vec<&Bookmark> = self
+            .contexts
+            .get(&context_id)
+            .map(|b| b.values().collect())
+            .unwrap_or_default();
+        bookmarks.sort_by_key(|b| (b.depth, b.bookmark_id));
+        bookmarks
+    }
+
+    /// Add a bookmark. The caller checks that the turn is in the context at
+    /// `depth`.
+    pub fn add(
+        &mut self,
+        context_id: u64,
+        turn_id: u64,
+        depth: u32,
+        name: &str,
+        note: Option<String>,
+        author: Option<String>,
+    ) -> Result<Bookmark> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(StoreError::InvalidInput("bookmark name is required".into()));
+        }
+        if name.chars().count() > MAX_NAME_CHARS {
+            return Err(StoreError::InvalidInput(format!(
+                "bookmark name exceeds {MAX_NAME_CHARS} characters"
+            )));
+        }
+        let note = note.filter(|n| !n.trim().is_empty());
+        if note
+            .as_ref()
+            .is_some_and(|n| n.chars().count() > MAX_NOTE_CHARS)
+        {
+            return Err(StoreError::InvalidInput(format!(
+                "bookmark note exceeds {MAX_NOTE_CHARS} characters"
+            )));
+        }
+        let bookmark = Bookmark {
+            bookmark_id: self.next_id,
+            context_id,
+            turn_id,
+            depth,
+            name: name.to_string(),
+            note,
+            author: author.filter(|a| !a.trim().is_empty()),
+            created_at_unix_ms: unix_ms(),
+        };
+        self.append(&BookmarkEntry::Add(bookmark.clone()))?;
+        self.next_id += 1;
+        self.contexts
+            .entry(context_id)
+            .or_default()
+            .insert(bookmark.bookmark_id, bookmark.clone());
+        Ok(bookmark)
+    }
+
+    /// Delete a bookmark of a context, returning it.
+    pub fn delete(
+        &mut self,
+        context_id: u64,
+        bookmark_id: u64,
+        principal: Option<String>,
+    ) -> Result<Bookmark> {
+        let exists = self
+            .contexts
+            .get(&context_id)
+            .is_some_and(|b| b.contains_key(&bookmark_id));
+        if !exists {
+            return Err(StoreError::NotFound(format!("bookmark {bookmark_id}")));
+        }
+        self.append(&BookmarkEntry::Delete {
+            context_id,
+            bookmark_id,
+            principal,
+            at_unix_ms: unix_ms(),
+        })?;
+        let bookmarks = self.contexts.get_mut(&context_id).expect("checked above");
+        let bookmark = bookmarks.remove(&bookmark_id).expect("checked above");
+        if bookmarks.is_empty() {
+            self.contexts.remove(&context_id);
+        }
+        Ok(bookmark)
+    }
+
+    fn append(&mut self, entry: &BookmarkEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,648 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Admin subcommands of `cxdb-server`.
+//!
+//! Each subcommand works on a data directory through the library modules and
+//! exits; the network services are not started. Except for `config check`,
+//! `s3 verify` and `fs checkout --server`, which only read, they take the
+//! data directory's lock and refuse to run while a server (or another
+//! command) holds it. Reports are printed to stdout as JSON.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use clap::{Args, Parser, Subcommand};
+use serde_json::{json, Value};
+
+use cxdb_server::access::AccessPolicy;
+use cxdb_server::access_log::AccessLogConfig;
+use cxdb_server::anchoring::AnchorConfig;
+use cxdb_server::archive::{export_context, import_context};
+use cxdb_server::bench::{run_bench, BenchConfig, Distribution, DEFAULT_BUNDLE};
+use cxdb_server::blob_store::BlobStore;
+use cxdb_server::deadline::Deadline;
+use cxdb_server::depth_limits::DepthLimits;
+use cxdb_server::error::{Result, StoreError};
+use cxdb_server::format::DataDirLock;
+use cxdb_server::fs_store::checkout::{checkout, CheckoutStats, Manifest};
+use cxdb_server::hooks::SummaryHookConfig;
+use cxdb_server::index_plugins::{IndexPlugins, IndexPluginsConfig};
+use cxdb_server::keys::{EncryptionConfig, KeyRing};
+use cxdb_server::metadata_cache::MetadataCacheConfig;
+use cxdb_server::metrics::LivenessConfig;
+use cxdb_server::pii::PiiConfig;
+use cxdb_server::previews::PreviewConfig;
+use cxdb_server::protocol::compat::{check_fixtures, write_fixtures};
+use cxdb_server::registry::builtin::{builtin_dir_from_env, load_dir};
+use cxdb_server::registry::{Registry, RegistryBundle};
+use cxdb_server::renderer_assets::RendererAssetConfig;
+use cxdb_server::residency::ResidencyConfig;
+use cxdb_server::s3_sync::{S3Sync, S3SyncConfig};
+use cxdb_server::shares::ShareConfig;
+use cxdb_server::sinks::SinkConfig;
+use cxdb_server::store::Store;
+use cxdb_server::system_events::SystemEventConfig;
+use cxdb_server::thumbnails::ThumbnailConfig;
+use cxdb_server::title::TitleConfig;
+use cxdb_server::tls::{TlsAcceptor, TlsConfig};
+use cxdb_server::tokens::{TokenCounter, TokenizerConfig};
+use cxdb_server::turn_store::IdGeneratorConfig;
+
+#[derive(Debug, Parser)]
+#[command(name = "cxdb-server", version, about = "cxdb server and admin tools")]
+pub struct Cli {
+    /// Data directory [default: $CXDB_DATA_DIR or ./data]
+    #[arg(long, global = true)]
+    pub data_dir: Option<PathBuf>,
+
+    /// Run an admin command instead of starting the server.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Verify every turn's chain hash.
+    Fsck,
+    /// Remove unreferenced blobs from the blob pack.
+    Compact {
+        /// Print the compaction plan without changing anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write a context's history and snapshots to an archive.
+    ExportContext {
+        context_id: u64,
+        /// Archive file [default: stdout]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Append the turns of an archive to a new context.
+    ImportContext {
+        /// Archive file [default: stdin]
+        input: Option<PathBuf>,
+    },
+    /// Rebuild the blob and turn indexes from the pack and turn log, then
+    /// check that the secondary indexes build.
+    RebuildIndexes,
+    /// Inspect or restore the S3 backup.
+    S3 {
+        #[command(subcommand)]
+        command: S3Command,
+    },
+    /// Inspect the environment configuration.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Write a synthetic workload into an empty data directory and report
+    /// throughput and latency.
+    Bench(BenchArgs),
+    /// Check or write binary protocol compatibility fixtures.
+    Protocol {
+        #[command(subcommand)]
+        command: ProtocolCommand,
+    },
+    /// Work with filesystem snapshots.
+    Fs {
+        #[command(subcommand)]
+        command: FsCommand,
+    },
+}
+
+/// Distributions are `N`, `A..B` (uniform) or `lognormal:MEDIAN:SIGMA`.
+#[derive(Debug, Args)]
+pub struct BenchArgs {
+    /// Contexts to create, forks included [default: 100]
+    #[arg(long)]
+    contexts: Option<usize>,
+    /// Turns per context [default: 10..50]
+    #[arg(long)]
+    turns: Option<Distribution>,
+    /// Payload bytes per turn [default: lognormal:1024:1]
+    #[arg(long)]
+    turn_bytes: Option<Distribution>,
+    /// Share of contexts forked from an earlier turn [default: 0.1]
+    #[arg(long)]
+    fork_rate: Option<f64>,
+    /// Share of turns followed by a filesystem snapshot [default: 0.05]
+    #[arg(long)]
+    fs_rate: Option<f64>,
+    /// Files per snapshot [default: 50]
+    #[arg(long)]
+    fs_files: Option<usize>,
+    /// Bytes per snapshot file [default: lognormal:4096:1]
+    #[arg(long)]
+    file_bytes: Option<Distribution>,
+    /// Registry bundle whose types payloads are generated from [default: a
+    /// built-in chat turn type]
+    #[arg(long)]
+    bundle: Option<PathBuf>,
+    /// Concurrent writers, one session each [default: 1]
+    #[arg(long)]
+    writers: Option<usize>,
+    /// Workload seed [default: 1]
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+impl BenchArgs {
+    fn config(&self) -> BenchConfig {
+        let defaults = BenchConfig::default();
+        BenchConfig {
+            contexts: self.contexts.unwrap_or(defaults.contexts),
+            turns: self.turns.unwrap_or(defaults.turns),
+            turn_bytes: self.turn_bytes.unwrap_or(defaults.turn_bytes),
+            fork_rate: self.fork_rate.unwrap_or(defaults.fork_rate),
+            fs_rate: self.fs_rate.unwrap_or(defaults.fs_rate),
+            fs_files: self.fs_files.unwrap_or(defaults.fs_files),
+            file_bytes: self.file_bytes.unwrap_or(defaults.file_bytes),
+            writers: self.writers.unwrap_or(defaults.writers),
+            seed: self.seed.unwrap_or(defaults.seed),
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum S3Command {
+    /// Compare the backup with its manifest and the local files.
+    Verify,
+    /// Download the backup into an empty data directory.
+    Restore,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProtocolCommand {
+    /// Decode and re-encode every frame fixture in a directory.
+    CheckFixtures { dir: PathBuf },
+    /// Write the reference fixtures for the current schema version.
+    WriteFixtures { dir: PathBuf },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum FsCommand {
+    /// Write the snapshot a turn sees into a new local directory, verifying
+    /// every file's hash.
+    Checkout {
+        turn_id: u64,
+        /// Directory to create; must be missing or empty.
+        dest: PathBuf,
+        /// Fetch from a running server (e.g. http://localhost:9010) instead
+        /// of the data directory.
+        #[arg(long)]
+        server: Option<String>,
+        /// Bearer token for --server.
+        #[arg(long)]
+        token: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Load every CXDB_* setting the server reads and report problems.
+    Check,
+}
+
+/// Run an admin command. Returns false when it found problems, so the
+/// process can exit non-zero.
+pub fn run(data_dir: &Path, command: Command) -> Result<bool> {
+    match command {
+        Command::Fsck => {
+            let mut store = open_store(data_dir)?;
+            let report = store.fsck();
+            print_json(&to_value(&report)?)?;
+            Ok(report.ok)
+        }
+        Command::Compact { dry_run: true } => {
+            let plan = open_store(data_dir)?.compaction_plan()?;
+            print_json(&to_value(&plan)?)?;
+            Ok(true)
+        }
+        Command::Compact { dry_run: false } => {
+            let mut store = open_store(data_dir)?;
+            let start = Instant::now();
+            let fs = store.prune_fs_roots(false)?;
+            let sweep = store.collect_blobs(false)?;
+            print_json(&json!({
+                "swept_blobs": sweep.swept_blobs,
+                "swept_bytes": sweep.swept_bytes,
+                "kept_blobs": sweep.kept_blobs,
+                "young_blobs": sweep.young_blobs,
+                "young_bytes": sweep.young_bytes,
+                "pack_bytes_before": sweep.pack_bytes_before,
+                "pack_bytes_after": sweep.pack_bytes_after,
+                "pruned_fs_roots": fs.orphan_roots,
+                "fs_unreachable_bytes": fs.unreachable_bytes,
+                "elapsed_ms": start.elapsed().as_millis() as u64,
+            }))?;
+            Ok(true)
+        }
+        Command::ExportContext { context_id, output } => {
+            let mut store = open_store(data_dir)?;
+            let summary = match output {
+                Some(path) => {
+                    let mut out = BufWriter::new(File::create(path)?);
+                    export_context(&mut store, context_id, &mut out)?
+                }
+                None => export_context(&mut store, context_id, &mut io::stdout().lock())?,
+            };
+            // stdout may hold the archive itself.
+            eprintln!(
+                "exported context {} ({} turns, {} snapshot blobs, {} attachments)",
+                summary.context_id, summary.turns, summary.fs_blobs, summary.attachments
+            );
+            Ok(true)
+        }
+        Command::ImportContext { input } => {
+            std::fs::create_dir_all(data_dir)?;
+            let mut store = open_store(data_dir)?;
+            let summary = match input {
+                Some(path) => import_context(&mut store, BufReader::new(File::open(path)?))?,
+                None => import_context(&mut store, io::stdin().lock())?,
+            };
+            print_json(&to_value(&summary)?)?;
+            Ok(true)
+        }
+        Command::RebuildIndexes => {
+            require_data_dir(data_dir)?;
+            let dir_lock = DataDirLock::acquire(data_dir)?;
+            let start = Instant::now();
+            let blobs_dir = data_dir.join("blobs");
+            let blobs = if blobs_dir.join("blobs.pack").exists() {
+                Some(BlobStore::rebuild_index(&blobs_dir)?)
+            } else {
+                None
+            };
+            // Opening the store rewrites turns.idx from the turn log.
+            let mut store = open_locked_store(data_dir, dir_lock)?;
+            let mut build = store.begin_index_build();
+            store.continue_index_build(&mut build, usize::MAX);
+            let turns = store.turn_store.stats();
+            print_json(&json!({
+                "blobs": blobs,
+                "turns": turns.turns_total,
+                "turns_index_bytes": turns.turns_index_bytes,
+                "contexts_indexed": store.index_stats().contexts_indexed,
+                "elapsed_ms": start.elapsed().as_millis() as u64,
+            }))?;
+            Ok(true)
+        }
+        Command::S3 { command } => {
+            let config = S3SyncConfig::from_env().ok_or_else(|| {
+                StoreError::InvalidInput(
+                    "S3 sync is not configured (CXDB_S3_SYNC_ENABLED, CXDB_S3_BUCKET)".into(),
+                )
+            })?;
+            let rt = tokio::runtime::Runtime::new()
+                .map_err(|e| StoreError::Io(std::io::Error::other(e)))?;
+            let sync = rt.block_on(S3Sync::new(config, data_dir.to_path_buf()));
+            match command {
+                S3Command::Verify => {
+                    let verification = rt.block_on(sync.verify())?;
+                    print_json(&to_value(&verification)?)?;
+                    Ok(verification.ok)
+                }
+                S3Command::Restore => {
+                    let _dir_lock = DataDirLock::acquire(data_dir)?;
+                    if sync.has_local_data() {
+                        return Err(StoreError::InvalidInput(format!(
+                            "{} already holds data; restore into an empty data directory",
+                            data_dir.display()
+                        )));
+                    }
+                    let restored = rt.block_on(sync.maybe_restore())?;
+                    print_json(&json!({ "restored": restored }))?;
+                    Ok(restored)
+                }
+            }
+        }
+        Command::Bench(args) => {
+            // The bench must not mix synthetic contexts into real data.
+            if std::fs::read_dir(data_dir).is_ok_and(|mut entries| entries.next().is_some()) {
+                return Err(StoreError::InvalidInput(format!(
+                    "{} is not empty; bench writes into a new data directory",
+                    data_dir.display()
+                )));
+            }
+            let raw = match &args.bundle {
+                Some(path) => std::fs::read(path)?,
+                None => DEFAULT_BUNDLE.as_bytes().to_vec(),
+            };
+            let bundle: RegistryBundle = serde_json::from_slice(&raw)
+                .map_err(|e| StoreError::InvalidInput(format!("invalid bundle: {e}")))?;
+            let dir_lock = DataDirLock::acquire(data_dir)?;
+            Registry::open(&data_dir.join("registry"))?.put_bundle(&bundle.bundle_id, &raw)?;
+            let store = Arc::new(Mutex::new(open_locked_store(data_dir, dir_lock)?));
+            let report = run_bench(store, &bundle, &args.config())?;
+            print_json(&to_value(&report)?)?;
+            Ok(true)
+        }
+        Command::Config {
+            command: ConfigCommand::Check,
+        } => {
+            let checks = check_config(data_dir);
+            let ok = checks.iter().all(|c| c["status"] != "error");
+            print_json(&json!({ "ok": ok, "checks": checks }))?;
+            Ok(ok)
+        }
+        Command::Protocol {
+            command: ProtocolCommand::CheckFixtures { dir },
+        } => {
+            let report = check_fixtures(&dir)?;
+            print_json(&to_value(&report)?)?;
+            Ok(report.ok())
+        }
+        Command::Protocol {
+            command: ProtocolCommand::WriteFixtures { dir },
+        } => {
+            let written = write_fixtures(&dir)?;
+            print_json(&json!({ "written": written, "dir": dir }))?;
+            Ok(true)
+        }
+        Command::Fs {
+            command:
+                FsCommand::Checkout {
+                    turn_id,
+                    dest,
+                    server,
+                    token,
+                },
+        } => {
+            let stats = match server {
+                Some(server) => checkout_from_server(&server, token.as_deref(), turn_id, &dest)?,
+                None => {
+                    let mut store = open_store(data_dir)?;
+                    let manifest = store.fs_manifest(turn_id, &Deadline::none())?;
+                    checkout(&manifest, &dest, |blob| {
+                        let mut hash = [0u8; 32];
+                        hex::decode_to_slice(&blob.hash, &mut hash)
+                            .map_err(|_| StoreError::Corrupt("invalid blob hash".into()))?;
+                        store.read_fs_blob(turn_id, &hash)
+                    })?
+                }
+            };
+            print_json(&to_value(&stats)?)?;
+            Ok(true)
+        }
+    }
+}
+
+/// Check out a turn's snapshot through a server's manifest and file routes.
+fn checkout_from_server(
+    server: &str,
+    token: Option<&str>,
+    turn_id: u64,
+    dest: &Path,
+) -> Result<CheckoutStats> {
+    let base = server.trim_end_matches('/');
+    let agent = ureq::AgentBuilder::new().build();
+    let get = |path: &str| {
+        let mut request = agent.get(&format!("{base}{path}"));
+        if let Some(token) = token {
+            request = request.set("Authorization", &format!("Bearer {token}"));
+        }
+        request
+            .call()
+            .map_err(|e| StoreError::Io(io::Error::other(format!("GET {path}: {e}"))))
+    };
+    let manifest: Manifest = get(&format!("/v1/turns/{turn_id}/fs/manifest"))?.into_json()?;
+    checkout(&manifest, dest, |blob| {
+        let mut content = Vec::new();
+        get(&blob.url)?
+            .into_reader()
+            .take(blob.size + 1)
+            .read_to_end(&mut content)?;
+        Ok(content)
+    })
+}
+
+/// Open the store the way the server does, with the configured id
+/// generator, title derivation, depth limits, PII labeling, index plugins,
+/// token counting and encryption, so imported turns are treated like
+/// appended ones.
+fn open_store(data_dir: &Path) -> Result<Store> {
+    require_data_dir(data_dir)?;
+    open_locked_store(data_dir, DataDirLock::acquire(data_dir)?)
+}
+
+/// [`open_store`] under a lock the command took before touching any file.
+fn open_locked_store(data_dir: &Path, dir_lock: DataDirLock) -> Result<Store> {
+    let mut store =
+        Store::open_unindexed_locked(data_dir, MetadataCacheConfig::from_env(), dir_lock)?;
+    store.set_id_generator(IdGeneratorConfig::from_env()?.build()?);
+    let registry = Arc::new(Mutex::new(Registry::open(&data_dir.join("registry"))?));
+    store.enable_previews(PreviewConfig::from_env(), Arc::clone(&registry));
+    if let Some(title_config) = TitleConfig::from_env() {
+        store.enable_title_derivation(title_config, Arc::clone(&registry));
+    }
+    if let Some(limits) = DepthLimits::from_env()? {
+        store.enable_depth_limits(limits);
+    }
+    if let Some(pii_config) = PiiConfig::from_env()? {
+        store.enable_pii_scanning(pii_config);
+    }
+    if let Some(plugins_config) = IndexPluginsConfig::from_env()? {
+        store.enable_index_plugins(IndexPlugins::load(&plugins_config, Arc::clone(&registry))?);
+    }
+    let tokenizer = TokenizerConfig::from_env().load()?;
+    store.enable_token_counting(TokenCounter::new(tokenizer, registry));
+    if let Some(encryption) = EncryptionConfig::from_env()? {
+        store.enable_encryption(&encryption)?;
+    }
+    Ok(store)
+}
+
+fn require_data_dir(data_dir: &Path) -> Result<()> {
+    if data_dir.is_dir() {
+        Ok(())
+    } else {
+        Err(StoreError::NotFound(format!(
+            "data directory {}",
+            data_dir.display()
+        )))
+    }
+}
+
+/// One entry per configurable component: `ok`, `disabled` or `error`.
+fn check_config(data_dir: &Path) -> Vec<Value> {
+    let mut checks = Vec::new();
+    let mut check = |name: &str, result: Result<Option<String>>| {
+        checks.push(match result {
+            Ok(Some(detail)) => json!({ "name": name, "status": "ok", "detail": detail }),
+            Ok(None) => json!({ "name": name, "status": "disabled" }),
+            Err(e) => json!({ "name": name, "status": "error", "detail": e.to_string() }),
+        });
+    };
+
+    check(
+        "data_dir",
+        Ok(Some(if data_dir.is_dir() {
+            data_dir.display().to_string()
+        } else {
+            format!("{} (will be created)", data_dir.display())
+        })),
+    );
+    check(
+        "encryption",
+        EncryptionConfig::from_env().and_then(|config| {
+            let Some(config) = config else {
+                return Ok(None);
+            };
+            let keys_dir = data_dir.join("keys");
+            if keys_dir.is_dir() {
+                let mut ring = KeyRing::open(&keys_dir)?;
+                // Only a ring with sealed data has remembered a master key;
+                // unlocking an unused ring would record this one.
+                if ring.has_sealed_data() {
+                    ring.unlock(&config)?;
+                }
+            }
+            Ok(Some(format!("{:?}", config.mode)))
+        }),
+    );
+    check(
+        "access_policy",
+        AccessPolicy::from_env().map(|_| {
+            std::env::var("CXDB_ACCESS_POLICY")
+                .ok()
+                .filter(|path| !path.is_empty())
+        }),
+    );
+    check(
+        "access_log",
+        AccessLogConfig::from_env().map(|config| config.map(|config| config.describe())),
+    );
+    check(
+        "shares",
+        ShareConfig::from_env().map(|config| {
+            Some(format!(
+                "{} secret, {}s default ttl",
+                if config.secret.is_some() {
+                    "configured"
+                } else {
+                    "generated"
+                },
+                config.default_ttl.as_secs()
+            ))
+        }),
+    );
+    check(
+        "thumbnails",
+        ThumbnailConfig::from_env()
+            .map(|config| config.map(|config| format!("{} byte cache", config.cache_bytes))),
+    );
+    check(
+        "renderer_assets",
+        RendererAssetConfig::from_env()
+            .map(|config| config.map(|config| config.allowed_origins.join(","))),
+    );
+    check(
+        "liveness",
+        LivenessConfig::from_env().map(|config| {
+            Some(format!(
+                "{} ({}s window)",
+                config.mode.as_str(),
+                config.window.as_secs()
+            ))
+        }),
+    );
+    check(
+        "id_generator",
+        IdGeneratorConfig::from_env()
+            .and_then(IdGeneratorConfig::build)
+            .map(|ids| Some(ids.describe())),
+    );
+    check(
+        "tls",
+        TlsConfig::from_env()
+            .map(|config| TlsAcceptor::new(&config).map(|_| config.cert_path.display().to_string()))
+            .transpose(),
+    );
+    check(
+        "event_sink",
+        SinkConfig::from_env().map(|config| {
+            config.map(|config| format!("{} at {}", config.kind.as_str(), config.servers))
+        }),
+    );
+    check(
+        "anchoring",
+        AnchorConfig::from_env().map(|config| config.map(|config| config.target.describe())),
+    );
+    check(
+        "tokenizer",
+        TokenizerConfig::from_env()
+            .load()
+            .map(|tokenizer| Some(tokenizer.name().to_string())),
+    );
+    let s3_enabled = std::env::var("CXDB_S3_SYNC_ENABLED")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    check(
+        "s3_sync",
+        match S3SyncConfig::from_env() {
+            Some(config) => ResidencyConfig::from_env(&config.region).and_then(|residency| {
+                residency.validate(&config.destination())?;
+                let mut described = format!("s3://{}/{}", config.bucket, config.prefix);
+                if residency.is_configured() {
+                    described.push_str(&format!(" (residency: {})", residency.describe()));
+                }
+                Ok(Some(described))
+            }),
+            // from_env quietly disables sync when the bucket is missing.
+            None if s3_enabled => Err(StoreError::InvalidInput(
+                "CXDB_S3_SYNC_ENABLED is set but CXDB_S3_BUCKET is not".into(),
+            )),
+            None => Ok(None),
+        },
+    );
+    check(
+        "title_derivation",
+        Ok(TitleConfig::from_env().map(|_| "enabled".to_string())),
+    );
+    check(
+        "depth_limits",
+        DepthLimits::from_env().map(|limits| limits.map(|limits| limits.describe())),
+    );
+    check(
+        "pii_detection",
+        PiiConfig::from_env().map(|config| config.map(|config| config.describe())),
+    );
+    check(
+        "index_plugins",
+        IndexPluginsConfig::from_env().map(|config| config.map(|config| config.describe())),
+    );
+    check(
+        "summary_hook",
+        Ok(SummaryHookConfig::from_env().map(|config| config.url)),
+    );
+    check(
+        "builtin_bundles",
+        builtin_dir_from_env()
+            .map(|dir| {
+                load_dir(&dir).map(|bundles| format!("{} in {}", bundles.len(), dir.display()))
+            })
+            .transpose(),
+    );
+    check(
+        "system_events",
+        SystemEventConfig::from_env().map(|config| config.map(|config| config.describe())),
+    );
+    checks
+}
+
+fn to_value(value: &impl serde::Serialize) -> Result<Value> {
+    serde_json::to_value(value)
+        .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))
+}
+
+fn print_json(value: &Value) -> Result<()> {
+    let mut out = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut out, value)
+        .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+    writeln!(out)?;
+    Ok(())
+}
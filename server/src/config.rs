@@ -3,24 +3,83 @@
 
 use std::env;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub data_dir: PathBuf,
     pub bind_addr: String,
-    pub http_bind_addr: String,
+    /// HTTP gateway TCP address; None when `CXDB_HTTP_BIND=none` leaves the
+    /// gateway to its Unix socket.
+    pub http_bind_addr: Option<String>,
+    /// Optional Unix domain socket for the HTTP gateway.
+    pub http_unix_socket_path: Option<PathBuf>,
+    /// Permission bits set on the HTTP socket file.
+    pub http_unix_socket_mode: u32,
+    /// Binary protocol connections served at once; further clients wait in
+    /// the listen backlog until one closes.
+    pub max_connections: usize,
+    /// Binary protocol connections that send no request for this long are
+    /// closed; None when `CXDB_IDLE_TIMEOUT_SECS=0`.
+    pub idle_timeout: Option<Duration>,
+    /// Optional Unix domain socket for the binary protocol, served alongside
+    /// the TCP listener.
+    pub unix_socket_path: Option<PathBuf>,
+    /// Permission bits set on the socket file.
+    pub unix_socket_mode: u32,
 }
 
 impl Config {
     pub fn from_env() -> Self {
         let data_dir = env::var("CXDB_DATA_DIR").unwrap_or_else(|_| "./data".to_string());
         let bind_addr = env::var("CXDB_BIND").unwrap_or_else(|_| "127.0.0.1:9009".to_string());
-        let http_bind_addr =
-            env::var("CXDB_HTTP_BIND").unwrap_or_else(|_| "127.0.0.1:9010".to_string());
+        let http_bind_addr = match env::var("CXDB_HTTP_BIND") {
+            Ok(v) if v.is_empty() || v.eq_ignore_ascii_case("none") => None,
+            Ok(v) => Some(v),
+            Err(_) => Some("127.0.0.1:9010".to_string()),
+        };
+        let max_connections = env::var("CXDB_MAX_CONNECTIONS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(1024);
+        let idle_timeout = Some(
+            env::var("CXDB_IDLE_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(900),
+        )
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs);
+        let unix_socket_path = env::var("CXDB_UNIX_SOCKET")
+            .ok()
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from);
+        let unix_socket_mode = socket_mode("CXDB_UNIX_SOCKET_MODE");
+        let http_unix_socket_path = env::var("CXDB_HTTP_UNIX_SOCKET")
+            .ok()
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from);
+        let http_unix_socket_mode = socket_mode("CXDB_HTTP_UNIX_SOCKET_MODE");
         Self {
             data_dir: PathBuf::from(data_dir),
             bind_addr,
             http_bind_addr,
+            http_unix_socket_path,
+            http_unix_socket_mode,
+            max_connections,
+            idle_timeout,
+            unix_socket_path,
+            unix_socket_mode,
         }
     }
 }
+
+/// Octal socket permission bits from `var`, 660 when unset or invalid.
+fn socket_mode(var: &str) -> u32 {
+    env::var(var)
+        .ok()
+        .and_then(|v| u32::from_str_radix(&v, 8).ok())
+        .filter(|v| *v <= 0o777)
+        .unwrap_or(0o660)
+}
@@ -0,0 +1,143 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! On-demand sampling CPU profiles.
+//!
+//! `POST /v1/admin/profile?seconds=10` samples every thread's stack for the
+//! requested time and answers with a flamegraph SVG, or a pprof protobuf
+//! with `format=pprof` (for `go tool pprof`). Only one capture runs at a
+//! time. Sampling uses pprof-rs and is the optional `cpu-profiling` cargo
+//! feature; without it captures are refused.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::error::{Result, StoreError};
+
+/// Default for `CXDB_CPU_PROFILE_HZ`.
+const DEFAULT_FREQUENCY_HZ: i32 = 99;
+
+/// Capture length when `seconds` is not given.
+pub const DEFAULT_SECONDS: u64 = 10;
+
+/// Longest capture accepted.
+pub const MAX_SECONDS: u64 = 120;
+
+/// Artifact a capture produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileFormat {
+    /// Flamegraph SVG.
+    Flamegraph,
+    /// pprof protobuf.
+    Pprof,
+}
+
+impl ProfileFormat {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "flamegraph" | "svg" => Ok(Self::Flamegraph),
+            "pprof" => Ok(Self::Pprof),
+            other => Err(StoreError::InvalidInput(format!(
+                "unknown profile format {other:?} (expected flamegraph or pprof)"
+            ))),
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Flamegraph => "image/svg+xml",
+            Self::Pprof => "application/octet-stream",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Flamegraph => "svg",
+            Self::Pprof => "pb",
+        }
+    }
+}
+
+/// Runs captures, one at a time.
+pub struct CpuProfiler {
+    /// Samples per second.
+    frequency_hz: i32,
+    running: AtomicBool,
+}
+
+impl CpuProfiler {
+    pub fn new(frequency_hz: i32) -> Self {
+        Self {
+            frequency_hz,
+            running: AtomicBool::new(false),
+        }
+    }
+
+    /// Sampling frequency from `CXDB_CPU_PROFILE_HZ`.
+    pub fn from_env() -> Self {
+        let frequency_hz = std::env::var("CXDB_CPU_PROFILE_HZ")
+            .ok()
+            .and_then(|v| v.parse::<i32>().ok())
+            .filter(|hz| (1..=1000).contains(hz))
+            .unwrap_or(DEFAULT_FREQUENCY_HZ);
+        Self::new(frequency_hz)
+    }
+
+    /// Sample for `duration` and render the profile. Blocks the calling
+    /// thread; a capture started while another runs is refused.
+    pub fn capture(&self, duration: Duration, format: ProfileFormat) -> Result<Vec<u8>> {
+        if duration.is_zero() || duration > Duration::from_secs(MAX_SECONDS) {
+            return Err(StoreError::InvalidInput(format!(
+                "seconds must be between 1 and {MAX_SECONDS}"
+            )));
+        }
+        if self.running.swap(true, Ordering::AcqRel) {
+            return Err(StoreError::Cancelled(
+                "a CPU profile is already being captured".into(),
+            ));
+        }
+        let result = sample(self.frequency_hz, duration, format);
+        self.running.store(false, Ordering::Release);
+        result
+    }
+}
+
+impl Default for CpuProfiler {
+    fn default() -> Self {
+        Self::new(DEFAULT_FREQUENCY_HZ)
+    }
+}
+
+#[cfg(feature = "cpu-profiling")]
+fn sample(frequency_hz: i32, duration: Duration, format: ProfileFormat) -> Result<Vec<u8>> {
+    use pprof::protos::Message;
+
+    let failed = |e: pprof::Error| StoreError::Io(std::io::Error::other(format!("profiler: {e}")));
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(frequency_hz)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()
+        .map_err(failed)?;
+    std::thread::sleep(duration);
+    let report = guard.report().build().map_err(failed)?;
+    drop(guard);
+
+    let mut body = Vec::new();
+    match format {
+        ProfileFormat::Flamegraph => report.flamegraph(&mut body).map_err(failed)?,
+        ProfileFormat::Pprof => report
+            .pprof()
+            .map_err(failed)?
+            .write_to_vec(&mut body)
+            .map_err(|e| StoreError::Io(std::io::Error::other(format!("pprof encode: {e}"))))?,
+    }
+    Ok(body)
+}
+
+#[cfg(not(feature = "cpu-profiling"))]
+fn sample(_frequency_hz: i32, _duration: Duration, _format: ProfileFormat) -> Result<Vec<u8>> {
+    Err(StoreError::InvalidInput(
+        "cxdb-server was built without CPU profiling support (enable the `cpu-profiling` feature)"
+            .into(),
+    ))
+}
@@ -50,6 +50,7 @@ pub enum Operator {
     Lt,       // <
     Lte,      // <=
     In,       // IN
+    Contains, // CONTAINS
 }
 
 /// Value types in CQL expressions.
@@ -105,8 +106,19 @@ pub enum FieldName {
     Parent,
     Root,
     Created,
+    Expires,
     Depth,
+    Tokens,
     IsLive,
+    HasFs,
+    Author,
+    AuthorTag,
+    FsCount,
+    FsBytes,
+    OnHold,
+    Project,
+    ExternalId,
+    Preview,
 }
 
 impl FieldName {
@@ -124,8 +136,19 @@ impl FieldName {
             "parent" => Some(Self::Parent),
             "root" => Some(Self::Root),
             "created" => Some(Self::Created),
+            "expires" => Some(Self::Expires),
             "depth" => Some(Self::Depth),
+            "tokens" => Some(Self::Tokens),
             "is_live" => Some(Self::IsLive),
+            "has_fs" => Some(Self::HasFs),
+            "author" => Some(Self::Author),
+            "author_tag" => Some(Self::AuthorTag),
+            "fs_count" => Some(Self::FsCount),
+            "fs_bytes" => Some(Self::FsBytes),
+            "on_hold" => Some(Self::OnHold),
+            "project" => Some(Self::Project),
+            "external_id" => Some(Self::ExternalId),
+            "preview" => Some(Self::Preview),
             _ => None,
         }
     }
@@ -143,8 +166,19 @@ impl FieldName {
             Self::Parent => "parent",
             Self::Root => "root",
             Self::Created => "created",
+            Self::Expires => "expires",
             Self::Depth => "depth",
+            Self::Tokens => "tokens",
             Self::IsLive => "is_live",
+            Self::HasFs => "has_fs",
+            Self::Author => "author",
+            Self::AuthorTag => "author_tag",
+            Self::FsCount => "fs_count",
+            Self::FsBytes => "fs_bytes",
+            Self::OnHold => "on_hold",
+            Self::Project => "project",
+            Self::ExternalId => "external_id",
+            Self::Preview => "preview",
         }
     }
 
@@ -161,12 +195,44 @@ impl FieldName {
             Self::Parent,
             Self::Root,
             Self::Created,
+            Self::Expires,
             Self::Depth,
+            Self::Tokens,
             Self::IsLive,
+            Self::HasFs,
+            Self::Author,
+            Self::AuthorTag,
+            Self::FsCount,
+            Self::FsBytes,
+            Self::OnHold,
+            Self::Project,
+            Self::ExternalId,
+            Self::Preview,
         ]
     }
 }
 
+/// Namespace addressed by a `label.<namespace>` field, if `field` is one.
+pub fn label_namespace(field: &str) -> Option<&str> {
+    let namespace = field.strip_prefix("label.")?;
+    if namespace.is_empty()
+        || !namespace
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return None;
+    }
+    Some(namespace)
+}
+
+/// Key addressed by a `custom.<key>` field, if `field` is one. Keys are the
+/// entries returned by index plugins (see [`crate::index_plugins`]).
+pub fn custom_key(field: &str) -> Option<&str> {
+    field
+        .strip_prefix("custom.")
+        .filter(|key| crate::index_plugins::is_valid_key(key))
+}
+
 /// CQL parsing/execution error.
 #[derive(Debug, Clone, Serialize)]
 pub struct CqlError {
@@ -5,28 +5,41 @@
 
 use std::collections::HashSet;
 
-use super::ast::{CqlError, CqlErrorType, Expression, FieldName, Operator, Value};
-use super::indexes::SecondaryIndexes;
+use super::ast::{
+    custom_key, label_namespace, CqlError, CqlErrorType, Expression, FieldName, Operator, Value,
+};
+use super::indexes::{SecondaryIndexes, SpilledText};
 
 /// Execute a CQL expression against the secondary indexes.
 pub fn execute(
     expr: &Expression,
     indexes: &SecondaryIndexes,
     live_contexts: &HashSet<u64>,
+) -> Result<HashSet<u64>, CqlError> {
+    execute_with_spilled(expr, indexes, live_contexts, &SpilledText::default())
+}
+
+/// [`execute`] for indexes that spilled text entries: `title` and
+/// `preview` comparisons also match the spilled contexts in `spilled`.
+pub fn execute_with_spilled(
+    expr: &Expression,
+    indexes: &SecondaryIndexes,
+    live_contexts: &HashSet<u64>,
+    spilled: &SpilledText,
 ) -> Result<HashSet<u64>, CqlError> {
     match expr {
         Expression::And { left, right } => {
-            let left_result = execute(left, indexes, live_contexts)?;
-            let right_result = execute(right, indexes, live_contexts)?;
+            let left_result = execute_with_spilled(left, indexes, live_contexts, spilled)?;
+            let right_result = execute_with_spilled(right, indexes, live_contexts, spilled)?;
             Ok(left_result.intersection(&right_result).copied().collect())
         }
         Expression::Or { left, right } => {
-            let left_result = execute(left, indexes, live_contexts)?;
-            let right_result = execute(right, indexes, live_contexts)?;
+            let left_result = execute_with_spilled(left, indexes, live_contexts, spilled)?;
+            let right_result = execute_with_spilled(right, indexes, live_contexts, spilled)?;
             Ok(left_result.union(&right_result).copied().collect())
         }
         Expression::Not { inner } => {
-            let inner_result = execute(inner, indexes, live_contexts)?;
+            let inner_result = execute_with_spilled(inner, indexes, live_contexts, spilled)?;
             Ok(indexes
                 .all_contexts()
                 .difference(&inner_result)
@@ -37,7 +50,18 @@ pub fn execute(
             field,
             operator,
             value,
-        } => execute_comparison(field, *operator, value, indexes, live_contexts),
+        } => execute_comparison(field, *operator, value, indexes, live_contexts, spilled),
+    }
+}
+
+/// Whether `expr` compares `field` anywhere.
+pub fn filters_on(expr: &Expression, field: FieldName) -> bool {
+    match expr {
+        Expression::And { left, right } | Expression::Or { left, right } => {
+            filters_on(left, field) || filters_on(right, field)
+        }
+        Expression::Not { inner } => filters_on(inner, field),
+        Expression::Comparison { field: name, .. } => FieldName::from_str(name) == Some(field),
     }
 }
 
@@ -47,7 +71,29 @@ fn execute_comparison(
     value: &Value,
     indexes: &SecondaryIndexes,
     live_contexts: &HashSet<u64>,
+    spilled: &SpilledText,
 ) -> Result<HashSet<u64>, CqlError> {
+    if let Some(namespace) = label_namespace(field) {
+        return execute_keyed(
+            field,
+            operator,
+            value,
+            indexes,
+            |v| indexes.lookup_label_ns_exact(namespace, v),
+            |v| indexes.lookup_label_ns_prefix(namespace, v),
+        );
+    }
+    if let Some(key) = custom_key(field) {
+        return execute_keyed(
+            field,
+            operator,
+            value,
+            indexes,
+            |v| indexes.lookup_custom_exact(key, v),
+            |v| indexes.lookup_custom_prefix(key, v),
+        );
+    }
+
     let field_name = FieldName::from_str(field).ok_or_else(|| CqlError {
         error_type: CqlErrorType::UnknownField,
         message: format!("Unknown field: {}", field),
@@ -58,7 +104,7 @@ fn execute_comparison(
     match field_name {
         FieldName::Id => execute_id(operator, value, indexes),
         FieldName::Tag => execute_string_field(operator, value, indexes, StringField::Tag),
-        FieldName::Title => execute_string_field(operator, value, indexes, StringField::Title),
+        FieldName::Title => execute_title(operator, value, indexes, spilled),
         FieldName::Label => execute_label(operator, value, indexes),
         FieldName::User => execute_string_field(operator, value, indexes, StringField::User),
         FieldName::Service => execute_string_field(operator, value, indexes, StringField::Service),
@@ -67,8 +113,19 @@ fn execute_comparison(
         FieldName::Parent => execute_parent(operator, value, indexes),
         FieldName::Root => execute_root(operator, value, indexes),
         FieldName::Created => execute_created(operator, value, indexes),
+        FieldName::Expires => execute_expires(operator, value, indexes),
         FieldName::Depth => execute_depth(operator, value, indexes),
+        FieldName::Tokens => execute_tokens(operator, value, indexes),
         FieldName::IsLive => execute_is_live(operator, value, live_contexts, indexes),
+        FieldName::HasFs => execute_has_fs(operator, value, indexes),
+        FieldName::Author => execute_member(operator, value, indexes, MemberField::Author),
+        FieldName::AuthorTag => execute_member(operator, value, indexes, MemberField::AuthorTag),
+        FieldName::Project => execute_member(operator, value, indexes, MemberField::Project),
+        FieldName::ExternalId => execute_member(operator, value, indexes, MemberField::ExternalId),
+        FieldName::FsCount => execute_fs_range(operator, value, indexes, FsField::Count),
+        FieldName::FsBytes => execute_fs_range(operator, value, indexes, FsField::Bytes),
+        FieldName::OnHold => execute_on_hold(operator, value, indexes),
+        FieldName::Preview => execute_preview(operator, value, indexes, spilled),
     }
 }
 
@@ -198,6 +255,47 @@ fn execute_string_field(
     }
 }
 
+/// Title comparisons over the indexed titles plus the spilled ones.
+fn execute_title(
+    operator: Operator,
+    value: &Value,
+    indexes: &SecondaryIndexes,
+    spilled: &SpilledText,
+) -> Result<HashSet<u64>, CqlError> {
+    let mut ids = execute_string_field(operator, value, indexes, StringField::Title)?;
+    if spilled.titles.is_empty() {
+        return Ok(ids);
+    }
+    let s = value.as_string().unwrap_or_default();
+    let lower = s.to_lowercase();
+    match operator {
+        Operator::Eq => ids.extend(spilled.titles_matching(|t| t == s)),
+        Operator::EqCi => ids.extend(spilled.titles_matching(|t| t.to_lowercase() == lower)),
+        Operator::Starts => ids.extend(spilled.titles_matching(|t| t.starts_with(s))),
+        Operator::StartsCi => {
+            ids.extend(spilled.titles_matching(|t| t.to_lowercase().starts_with(&lower)))
+        }
+        // The complement over all contexts already counts every spilled
+        // context; drop the ones whose title is equal.
+        Operator::Neq => {
+            for id in spilled.titles_matching(|t| t == s) {
+                ids.remove(&id);
+            }
+        }
+        Operator::In => {
+            let list: Vec<&str> = value
+                .as_list()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(Value::as_string)
+                .collect();
+            ids.extend(spilled.titles_matching(|t| list.contains(&t)));
+        }
+        _ => {}
+    }
+    Ok(ids)
+}
+
 fn execute_id(
     operator: Operator,
     value: &Value,
@@ -254,6 +352,62 @@ fn execute_id(
     }
 }
 
+/// String fields keyed by a suffix (`label.<namespace>`, `custom.<key>`);
+/// `exact` and `prefix` look up one value.
+fn execute_keyed(
+    field: &str,
+    operator: Operator,
+    value: &Value,
+    indexes: &SecondaryIndexes,
+    exact: impl Fn(&str) -> HashSet<u64>,
+    prefix: impl Fn(&str) -> HashSet<u64>,
+) -> Result<HashSet<u64>, CqlError> {
+    let expect_string = |value: &Value| {
+        value
+            .as_string()
+            .map(|s| s.to_string())
+            .ok_or_else(|| CqlError {
+                error_type: CqlErrorType::InvalidValue,
+                message: "Expected string value".into(),
+                position: None,
+                field: None,
+            })
+    };
+    match operator {
+        Operator::Eq => Ok(exact(&expect_string(value)?)),
+        Operator::Starts => Ok(prefix(&expect_string(value)?)),
+        Operator::Neq => {
+            let matches = exact(&expect_string(value)?);
+            Ok(indexes
+                .all_contexts()
+                .difference(&matches)
+                .copied()
+                .collect())
+        }
+        Operator::In => {
+            let list = value.as_list().ok_or_else(|| CqlError {
+                error_type: CqlErrorType::InvalidValue,
+                message: "Expected list value for IN operator".into(),
+                position: None,
+                field: None,
+            })?;
+            let mut result = HashSet::new();
+            for v in list {
+                if let Some(s) = v.as_string() {
+                    result.extend(exact(s));
+                }
+            }
+            Ok(result)
+        }
+        _ => Err(CqlError {
+            error_type: CqlErrorType::InvalidOperator,
+            message: format!("Operator {:?} not supported for {} field", operator, field),
+            position: None,
+            field: None,
+        }),
+    }
+}
+
 fn execute_label(
     operator: Operator,
     value: &Value,
@@ -307,6 +461,87 @@ fn execute_label(
     }
 }
 
+/// String fields matched exactly through a value -> contexts index.
+#[derive(Clone, Copy)]
+enum MemberField {
+    /// Principal of a session that appended a turn on the head chain.
+    Author,
+    /// Client tag of such a session.
+    AuthorTag,
+    /// A project the context is assigned to.
+    Project,
+    /// The external id the context was created with.
+    ExternalId,
+}
+
+/// Contexts with a matching value among their values of `field`. `!=`
+/// matches contexts without the value.
+fn execute_member(
+    operator: Operator,
+    value: &Value,
+    indexes: &SecondaryIndexes,
+    field: MemberField,
+) -> Result<HashSet<u64>, CqlError> {
+    let lookup = |s: &str| match field {
+        MemberField::Author => indexes.lookup_author_exact(s),
+        MemberField::AuthorTag => indexes.lookup_author_tag_exact(s),
+        MemberField::Project => indexes.lookup_project_exact(s),
+        MemberField::ExternalId => indexes.lookup_external_id_exact(s),
+    };
+    let expect_string = |value: &Value| {
+        value
+            .as_string()
+            .map(|s| s.to_string())
+            .ok_or_else(|| CqlError {
+                error_type: CqlErrorType::InvalidValue,
+                message: "Expected string value".into(),
+                position: None,
+                field: None,
+            })
+    };
+    match operator {
+        Operator::Eq => Ok(lookup(&expect_string(value)?)),
+        Operator::Neq => {
+            let matches = lookup(&expect_string(value)?);
+            Ok(indexes
+                .all_contexts()
+                .difference(&matches)
+                .copied()
+                .collect())
+        }
+        Operator::In => {
+            let list = value.as_list().ok_or_else(|| CqlError {
+                error_type: CqlErrorType::InvalidValue,
+                message: "Expected list value for IN operator".into(),
+                position: None,
+                field: None,
+            })?;
+            let mut result = HashSet::new();
+            for v in list {
+                if let Some(s) = v.as_string() {
+                    result.extend(lookup(s));
+                }
+            }
+            Ok(result)
+        }
+        _ => Err(CqlError {
+            error_type: CqlErrorType::InvalidOperator,
+            message: format!(
+                "Operator {:?} not supported for {} field",
+                operator,
+                match field {
+                    MemberField::Author => "author",
+                    MemberField::AuthorTag => "author_tag",
+                    MemberField::Project => "project",
+                    MemberField::ExternalId => "external_id",
+                }
+            ),
+            position: None,
+            field: None,
+        }),
+    }
+}
+
 fn execute_trace_id(
     operator: Operator,
     value: &Value,
@@ -481,6 +716,39 @@ fn execute_created(
     }
 }
 
+/// Contexts without a TTL never match, not even `!=`.
+fn execute_expires(
+    operator: Operator,
+    value: &Value,
+    indexes: &SecondaryIndexes,
+) -> Result<HashSet<u64>, CqlError> {
+    use std::ops::Bound::{Excluded, Unbounded};
+
+    let timestamp = parse_date_value(value)?;
+
+    match operator {
+        Operator::Eq => Ok(indexes.lookup_expires(timestamp..=timestamp)),
+        Operator::Neq => {
+            let matches = indexes.lookup_expires(timestamp..=timestamp);
+            Ok(indexes
+                .lookup_expires(..)
+                .difference(&matches)
+                .copied()
+                .collect())
+        }
+        Operator::Gt => Ok(indexes.lookup_expires((Excluded(timestamp), Unbounded))),
+        Operator::Gte => Ok(indexes.lookup_expires(timestamp..)),
+        Operator::Lt => Ok(indexes.lookup_expires(..timestamp)),
+        Operator::Lte => Ok(indexes.lookup_expires(..=timestamp)),
+        _ => Err(CqlError {
+            error_type: CqlErrorType::InvalidOperator,
+            message: format!("Operator {:?} not supported for expires field", operator),
+            position: None,
+            field: None,
+        }),
+    }
+}
+
 fn execute_depth(
     operator: Operator,
     value: &Value,
@@ -516,6 +784,92 @@ fn execute_depth(
     }
 }
 
+fn execute_tokens(
+    operator: Operator,
+    value: &Value,
+    indexes: &SecondaryIndexes,
+) -> Result<HashSet<u64>, CqlError> {
+    let tokens = value.as_u64().ok_or_else(|| CqlError {
+        error_type: CqlErrorType::InvalidValue,
+        message: "Expected numeric value for tokens".into(),
+        position: None,
+        field: None,
+    })?;
+
+    match operator {
+        Operator::Eq => Ok(indexes.lookup_tokens(tokens..=tokens)),
+        Operator::Neq => {
+            let matches = indexes.lookup_tokens(tokens..=tokens);
+            Ok(indexes
+                .all_contexts()
+                .difference(&matches)
+                .copied()
+                .collect())
+        }
+        Operator::Gt => Ok(indexes.lookup_tokens((
+            std::ops::Bound::Excluded(tokens),
+            std::ops::Bound::Unbounded,
+        ))),
+        Operator::Gte => Ok(indexes.lookup_tokens(tokens..)),
+        Operator::Lt => Ok(indexes.lookup_tokens(..tokens)),
+        Operator::Lte => Ok(indexes.lookup_tokens(..=tokens)),
+        _ => Err(CqlError {
+            error_type: CqlErrorType::InvalidOperator,
+            message: format!("Operator {:?} not supported for tokens field", operator),
+            position: None,
+            field: None,
+        }),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum FsField {
+    Count,
+    Bytes,
+}
+
+fn execute_fs_range(
+    operator: Operator,
+    value: &Value,
+    indexes: &SecondaryIndexes,
+    field: FsField,
+) -> Result<HashSet<u64>, CqlError> {
+    let name = match field {
+        FsField::Count => "fs_count",
+        FsField::Bytes => "fs_bytes",
+    };
+    let n = value.as_u64().ok_or_else(|| CqlError {
+        error_type: CqlErrorType::InvalidValue,
+        message: format!("Expected numeric value for {name}"),
+        position: None,
+        field: None,
+    })?;
+    let lookup = |range: (std::ops::Bound<u64>, std::ops::Bound<u64>)| match field {
+        FsField::Count => indexes.lookup_fs_count(range),
+        FsField::Bytes => indexes.lookup_fs_bytes(range),
+    };
+
+    use std::ops::Bound::{Excluded, Included, Unbounded};
+    match operator {
+        Operator::Eq => Ok(lookup((Included(n), Included(n)))),
+        Operator::Neq => Ok(indexes
+            .all_contexts()
+            .difference(&lookup((Included(n), Included(n))))
+            .copied()
+            .collect()),
+        Operator::Gt => Ok(lookup((Excluded(n), Unbounded))),
+        Operator::Gte => Ok(lookup((Included(n), Unbounded))),
+        Operator::Lt => Ok(lookup((Unbounded, Excluded(n)))),
+        Operator::Lte => Ok(lookup((Unbounded, Included(n)))),
+        _ => Err(CqlError {
+            error_type: CqlErrorType::InvalidOperator,
+            message: format!("Operator {:?} not supported for {} field", operator, name),
+            position: None,
+            field: None,
+        }),
+    }
+}
+
 fn execute_is_live(
     operator: Operator,
     value: &Value,
@@ -555,6 +909,98 @@ fn execute_is_live(
     }
 }
 
+fn execute_has_fs(
+    operator: Operator,
+    value: &Value,
+    indexes: &SecondaryIndexes,
+) -> Result<HashSet<u64>, CqlError> {
+    let has_fs = match value {
+        Value::String { value } => value == "true",
+        _ => {
+            return Err(CqlError {
+                error_type: CqlErrorType::InvalidValue,
+                message: "Expected boolean value for has_fs".into(),
+                position: None,
+                field: None,
+            });
+        }
+    };
+
+    let with_fs = indexes.lookup_has_fs();
+    match operator {
+        Operator::Eq if has_fs => Ok(with_fs),
+        Operator::Eq => Ok(indexes
+            .all_contexts()
+            .difference(&with_fs)
+            .copied()
+            .collect()),
+        _ => Err(CqlError {
+            error_type: CqlErrorType::InvalidOperator,
+            message: format!("Operator {:?} not supported for has_fs field", operator),
+            position: None,
+            field: None,
+        }),
+    }
+}
+
+fn execute_on_hold(
+    operator: Operator,
+    value: &Value,
+    indexes: &SecondaryIndexes,
+) -> Result<HashSet<u64>, CqlError> {
+    let on_hold = match value {
+        Value::String { value } => value == "true",
+        _ => {
+            return Err(CqlError {
+                error_type: CqlErrorType::InvalidValue,
+                message: "Expected boolean value for on_hold".into(),
+                position: None,
+                field: None,
+            });
+        }
+    };
+
+    let held = indexes.lookup_on_hold();
+    match operator {
+        Operator::Eq if on_hold => Ok(held),
+        Operator::Eq => Ok(indexes.all_contexts().difference(&held).copied().collect()),
+        _ => Err(CqlError {
+            error_type: CqlErrorType::InvalidOperator,
+            message: format!("Operator {:?} not supported for on_hold field", operator),
+            position: None,
+            field: None,
+        }),
+    }
+}
+
+fn execute_preview(
+    operator: Operator,
+    value: &Value,
+    indexes: &SecondaryIndexes,
+    spilled: &SpilledText,
+) -> Result<HashSet<u64>, CqlError> {
+    let needle = value.as_string().ok_or_else(|| CqlError {
+        error_type: CqlErrorType::InvalidValue,
+        message: "Expected string value".into(),
+        position: None,
+        field: None,
+    })?;
+    match operator {
+        Operator::Contains => {
+            let mut ids = indexes.lookup_preview_contains(needle);
+            let needle = needle.to_lowercase();
+            ids.extend(spilled.previews_matching(|text| text.to_lowercase().contains(&needle)));
+            Ok(ids)
+        }
+        _ => Err(CqlError {
+            error_type: CqlErrorType::InvalidOperator,
+            message: format!("Operator {:?} not supported for preview field", operator),
+            position: None,
+            field: None,
+        }),
+    }
+}
+
 /// Parse a date value (relative or absolute) into a Unix timestamp in milliseconds.
 fn parse_date_value(value: &Value) -> Result<u64, CqlError> {
     match value {
@@ -583,8 +1029,9 @@ fn parse_date_value(value: &Value) -> Result<u64, CqlError> {
     }
 }
 
+/// `-N<unit>` is N units ago and `+N<unit>` N units from now.
 fn parse_relative_date(value: &str) -> Result<u64, CqlError> {
-    let re = regex::Regex::new(r"^-(\d+)([hdm])$").unwrap();
+    let re = regex::Regex::new(r"^([-+])(\d+)([hdm])$").unwrap();
     let caps = re.captures(value).ok_or_else(|| CqlError {
         error_type: CqlErrorType::InvalidValue,
         message: format!("Invalid relative date format: {}", value),
@@ -592,14 +1039,14 @@ fn parse_relative_date(value: &str) -> Result<u64, CqlError> {
         field: None,
     })?;
 
-    let amount: u64 = caps[1].parse().map_err(|_| CqlError {
+    let amount: u64 = caps[2].parse().map_err(|_| CqlError {
         error_type: CqlErrorType::InvalidValue,
         message: format!("Invalid number in relative date: {}", value),
         position: None,
         field: None,
     })?;
 
-    let unit = &caps[2];
+    let unit = &caps[3];
     let millis = match unit {
         "h" => amount * 60 * 60 * 1000,
         "d" => amount * 24 * 60 * 60 * 1000,
@@ -619,7 +1066,11 @@ fn parse_relative_date(value: &str) -> Result<u64, CqlError> {
         .unwrap()
         .as_millis() as u64;
 
-    Ok(now - millis)
+    if &caps[1] == "+" {
+        Ok(now.saturating_add(millis))
+    } else {
+        Ok(now.saturating_sub(millis))
+    }
 }
 
 fn parse_absolute_date(value: &str) -> Result<u64, CqlError> {
@@ -660,6 +1111,10 @@ mod tests {
         let expected = now - (24 * 60 * 60 * 1000);
         // Allow 1 second tolerance
         assert!((result as i64 - expected as i64).abs() < 1000);
+
+        let result = parse_relative_date("+2d").unwrap();
+        let expected = now + (2 * 24 * 60 * 60 * 1000);
+        assert!((result as i64 - expected as i64).abs() < 1000);
     }
 
     #[test]
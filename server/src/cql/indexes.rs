@@ -3,13 +3,31 @@
 
 //! Secondary indexes for efficient CQL query execution.
 //!
-//! These indexes are built in-memory from the context_metadata_cache at startup
-//! and maintained incrementally as new contexts are created.
+//! These indexes are built in-memory from context metadata at startup and
+//! maintained incrementally as new contexts are created. Their memory is
+//! reported in [`IndexStats`]. Most entries are a fixed size per context;
+//! the title and preview entries grow with the text, so with a budget set
+//! the oldest contexts' text entries are spilled once they outgrow it. A
+//! query on `title` or `preview` then matches spilled contexts against
+//! text the store loads from disk (see [`SpilledText`]).
 
 use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+use std::mem::size_of;
 
+use crate::metadata_cache::MetadataCacheStats;
 use crate::store::ContextMetadata;
-use crate::turn_store::ContextHead;
+use crate::turn_store::{ContextHead, TurnAuthor};
+
+/// Filesystem snapshots attached along a context's head chain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FsStats {
+    pub count: u64,
+    /// File bytes of the largest snapshot.
+    pub max_bytes: u64,
+    /// Most recent turn with a snapshot attached; 0 when there is none.
+    pub last_turn_id: u64,
+}
 
 /// Secondary indexes for CQL queries.
 ///
@@ -28,6 +46,11 @@ pub struct SecondaryIndexes {
     title_lower_sorted: Vec<(String, u64)>,
 
     label_exact: HashMap<String, HashSet<u64>>,
+    // Namespaced labels (`team:payments`): namespace -> value -> contexts
+    label_ns: HashMap<String, BTreeMap<String, HashSet<u64>>>,
+    // Index plugin entries from turns on each context's head chain:
+    // key -> value -> contexts
+    custom: HashMap<String, BTreeMap<String, HashSet<u64>>>,
 
     user_exact: HashMap<String, HashSet<u64>>,
     user_sorted: Vec<(String, u64)>,
@@ -44,6 +67,17 @@ pub struct SecondaryIndexes {
 
     trace_id_exact: HashMap<String, HashSet<u64>>,
 
+    // Principals and client tags of the sessions that appended turns on
+    // each context's head chain
+    author_exact: HashMap<String, HashSet<u64>>,
+    author_tag_exact: HashMap<String, HashSet<u64>>,
+
+    // Projects each context is assigned to
+    project_exact: HashMap<String, HashSet<u64>>,
+
+    // Caller-assigned external ids; each names one context
+    external_id_exact: HashMap<String, HashSet<u64>>,
+
     // Numeric field indexes
     parent_exact: HashMap<u64, HashSet<u64>>,
     root_exact: HashMap<u64, HashSet<u64>>,
@@ -54,6 +88,36 @@ pub struct SecondaryIndexes {
     // Depth index
     depth_btree: BTreeMap<u32, HashSet<u64>>,
 
+    // Token totals along each context's head chain
+    tokens_btree: BTreeMap<u64, HashSet<u64>>,
+
+    // Contexts whose head turn sees a filesystem snapshot
+    has_fs: HashSet<u64>,
+
+    // Contexts on legal hold
+    on_hold: HashSet<u64>,
+
+    // Expiry times set by clients; contexts without a TTL are absent
+    expires: HashMap<u64, u64>,
+    expires_btree: BTreeMap<u64, HashSet<u64>>,
+
+    // Snapshot aggregates along each context's head chain; contexts
+    // without snapshots are absent
+    fs_stats: HashMap<u64, FsStats>,
+    fs_count_btree: BTreeMap<u64, HashSet<u64>>,
+    fs_bytes_btree: BTreeMap<u64, HashSet<u64>>,
+
+    // Latest summary_field preview per context, and the lowercase
+    // trigrams of each preview for CONTAINS lookups
+    preview: HashMap<u64, String>,
+    preview_trigrams: HashMap<String, HashSet<u64>>,
+
+    // Bytes the title and preview entries may hold, their estimated size,
+    // and the context id below which those entries have been spilled
+    text_budget_bytes: Option<u64>,
+    text_bytes: u64,
+    spilled_below: u64,
+
     // Track all indexed context IDs for NOT operations
     all_context_ids: HashSet<u64>,
 }
@@ -63,48 +127,60 @@ impl SecondaryIndexes {
         Self::default()
     }
 
-    /// Build indexes from existing context metadata cache.
-    pub fn build_from_cache(
-        &mut self,
-        metadata_cache: &HashMap<u64, Option<ContextMetadata>>,
-        heads: &[ContextHead],
-    ) {
-        let start = std::time::Instant::now();
-
-        // Index metadata from cache
-        for (context_id, metadata_opt) in metadata_cache {
-            self.all_context_ids.insert(*context_id);
-            if let Some(metadata) = metadata_opt {
-                self.index_metadata(*context_id, metadata);
-            }
+    /// Indexes whose title and preview entries are kept within
+    /// `budget_bytes`; None means unbounded.
+    pub fn with_text_budget(budget_bytes: Option<u64>) -> Self {
+        Self {
+            text_budget_bytes: budget_bytes,
+            ..Self::default()
         }
+    }
 
-        // Index head data (created_at, depth) from heads
-        for head in heads {
-            self.all_context_ids.insert(head.context_id);
-            self.created_btree
-                .entry(head.created_at_unix_ms)
-                .or_default()
-                .insert(head.context_id);
-            self.depth_btree
-                .entry(head.head_depth)
-                .or_default()
-                .insert(head.context_id);
+    pub fn text_budget_bytes(&self) -> Option<u64> {
+        self.text_budget_bytes
+    }
+
+    /// Whether a context's title and preview entries were spilled.
+    pub fn is_spilled(&self, context_id: u64) -> bool {
+        context_id < self.spilled_below
+    }
+
+    /// Indexed contexts whose title and preview entries were spilled.
+    pub fn spilled_contexts(&self) -> Vec<u64> {
+        self.all_context_ids
+            .iter()
+            .copied()
+            .filter(|&id| self.is_spilled(id))
+            .collect()
+    }
+
+    /// Index one existing context during a bulk build. The sorted indexes
+    /// are left unsorted until [`SecondaryIndexes::finish_build`].
+    pub fn build_context(&mut self, head: &ContextHead, metadata: Option<&ContextMetadata>) {
+        self.all_context_ids.insert(head.context_id);
+        if let Some(metadata) = metadata {
+            self.index_metadata(head.context_id, metadata);
         }
+        self.created_btree
+            .entry(head.created_at_unix_ms)
+            .or_default()
+            .insert(head.context_id);
+        self.depth_btree
+            .entry(head.head_depth)
+            .or_default()
+            .insert(head.context_id);
+        self.enforce_text_budget();
+    }
 
-        // Sort the sorted indexes
+    /// Complete a bulk build by sorting the sorted indexes.
+    pub fn finish_build(&mut self) {
         self.sort_indexes();
-
-        let elapsed = start.elapsed();
-        tracing::info!(
-            contexts = self.all_context_ids.len(),
-            elapsed_ms = elapsed.as_millis(),
-            "Built secondary indexes"
-        );
     }
 
     /// Index a single context's metadata.
     fn index_metadata(&mut self, context_id: u64, metadata: &ContextMetadata) {
+        let spilled = self.is_spilled(context_id);
+
         // Tag
         if let Some(tag) = &metadata.client_tag {
             self.tag_exact
@@ -121,7 +197,8 @@ impl SecondaryIndexes {
         }
 
         // Title
-        if let Some(title) = &metadata.title {
+        if let Some(title) = metadata.title.as_ref().filter(|_| !spilled) {
+            self.text_bytes += title_bytes(title);
             self.title_exact
                 .entry(title.clone())
                 .or_default()
@@ -144,6 +221,14 @@ impl SecondaryIndexes {
                     .insert(context_id);
             }
         }
+        for (namespace, value) in metadata.namespaced_labels() {
+            self.label_ns
+                .entry(namespace.to_string())
+                .or_default()
+                .entry(value.to_string())
+                .or_default()
+                .insert(context_id);
+        }
 
         // Provenance fields
         if let Some(prov) = &metadata.provenance {
@@ -236,6 +321,7 @@ impl SecondaryIndexes {
             self.index_metadata(context_id, metadata);
             // Re-sort (expensive, but appends are infrequent compared to queries)
             self.sort_indexes();
+            self.enforce_text_budget();
         }
 
         self.created_btree
@@ -249,11 +335,337 @@ impl SecondaryIndexes {
             .insert(context_id);
     }
 
+    /// Move a context to its new head depth after an append.
+    pub fn update_depth(&mut self, context_id: u64, old_depth: u32, new_depth: u32) {
+        if old_depth == new_depth {
+            return;
+        }
+        if let Some(ids) = self.depth_btree.get_mut(&old_depth) {
+            ids.remove(&context_id);
+            if ids.is_empty() {
+                self.depth_btree.remove(&old_depth);
+            }
+        }
+        self.depth_btree
+            .entry(new_depth)
+            .or_default()
+            .insert(context_id);
+    }
+
+    /// Move a context to its new token total.
+    pub fn update_tokens(&mut self, context_id: u64, old_tokens: u64, new_tokens: u64) {
+        if let Some(ids) = self.tokens_btree.get_mut(&old_tokens) {
+            ids.remove(&context_id);
+            if ids.is_empty() {
+                self.tokens_btree.remove(&old_tokens);
+            }
+        }
+        self.tokens_btree
+            .entry(new_tokens)
+            .or_default()
+            .insert(context_id);
+    }
+
+    /// Record that a turn on a context's head chain was appended by `author`.
+    pub fn add_author(&mut self, context_id: u64, author: &TurnAuthor) {
+        if let Some(principal) = &author.principal {
+            self.author_exact
+                .entry(principal.clone())
+                .or_default()
+                .insert(context_id);
+        }
+        if !author.client_tag.is_empty() {
+            self.author_tag_exact
+                .entry(author.client_tag.clone())
+                .or_default()
+                .insert(context_id);
+        }
+    }
+
+    /// Replace a context's snapshot aggregates. A context with snapshots
+    /// also matches `has_fs`.
+    pub fn update_fs(&mut self, context_id: u64, stats: FsStats) {
+        if let Some(old) = self.fs_stats.remove(&context_id) {
+            remove_from(&mut self.fs_count_btree, old.count, context_id);
+            remove_from(&mut self.fs_bytes_btree, old.max_bytes, context_id);
+        }
+        if stats.count == 0 {
+            self.has_fs.remove(&context_id);
+            return;
+        }
+        self.has_fs.insert(context_id);
+        self.fs_count_btree
+            .entry(stats.count)
+            .or_default()
+            .insert(context_id);
+        self.fs_bytes_btree
+            .entry(stats.max_bytes)
+            .or_default()
+            .insert(context_id);
+        self.fs_stats.insert(context_id, stats);
+    }
+
+    pub fn set_on_hold(&mut self, context_id: u64, held: bool) {
+        if held {
+            self.on_hold.insert(context_id);
+        } else {
+            self.on_hold.remove(&context_id);
+        }
+    }
+
+    /// Replace the time a context expires at.
+    pub fn set_expires(&mut self, context_id: u64, expires_at_unix_ms: u64) {
+        if let Some(old) = self.expires.insert(context_id, expires_at_unix_ms) {
+            remove_from(&mut self.expires_btree, old, context_id);
+        }
+        self.expires_btree
+            .entry(expires_at_unix_ms)
+            .or_default()
+            .insert(context_id);
+    }
+
+    /// Add a context to or remove it from a project.
+    pub fn set_project(&mut self, context_id: u64, project: &str, member: bool) {
+        if member {
+            self.project_exact
+                .entry(project.to_string())
+                .or_default()
+                .insert(context_id);
+        } else if let Some(ids) = self.project_exact.get_mut(project) {
+            ids.remove(&context_id);
+            if ids.is_empty() {
+                self.project_exact.remove(project);
+            }
+        }
+    }
+
+    /// Add an index plugin entry to a context.
+    pub fn add_custom(&mut self, context_id: u64, key: &str, value: &str) {
+        self.custom
+            .entry(key.to_string())
+            .or_default()
+            .entry(value.to_string())
+            .or_default()
+            .insert(context_id);
+    }
+
+    /// Record the external id a context was created with.
+    pub fn set_external_id(&mut self, context_id: u64, external_id: &str) {
+        self.external_id_exact
+            .entry(external_id.to_string())
+            .or_default()
+            .insert(context_id);
+    }
+
+    /// Replace a context's preview.
+    pub fn set_preview(&mut self, context_id: u64, preview: &str) {
+        if self.is_spilled(context_id) {
+            return;
+        }
+        if let Some(old) = self.preview.remove(&context_id) {
+            self.text_bytes = self.text_bytes.saturating_sub(preview_bytes(&old));
+            for trigram in trigrams(&old) {
+                if let Some(ids) = self.preview_trigrams.get_mut(&trigram) {
+                    ids.remove(&context_id);
+                    if ids.is_empty() {
+                        self.preview_trigrams.remove(&trigram);
+                    }
+                }
+            }
+        }
+        for trigram in trigrams(preview) {
+            self.preview_trigrams
+                .entry(trigram)
+                .or_default()
+                .insert(context_id);
+        }
+        self.text_bytes += preview_bytes(preview);
+        self.preview.insert(context_id, preview.to_string());
+        self.enforce_text_budget();
+    }
+
+    pub fn preview(&self, context_id: u64) -> Option<&str> {
+        self.preview.get(&context_id).map(String::as_str)
+    }
+
+    pub fn has_fs(&self, context_id: u64) -> bool {
+        self.has_fs.contains(&context_id)
+    }
+
+    pub fn fs_stats(&self, context_id: u64) -> FsStats {
+        self.fs_stats.get(&context_id).copied().unwrap_or_default()
+    }
+
+    /// Replace a context's indexed metadata (e.g. after an override is applied).
+    pub fn update_metadata(
+        &mut self,
+        context_id: u64,
+        old: Option<&ContextMetadata>,
+        new: Option<&ContextMetadata>,
+    ) {
+        if let Some(old) = old {
+            self.unindex_metadata(context_id, old);
+        }
+        if let Some(new) = new {
+            self.index_metadata(context_id, new);
+        }
+        self.sort_indexes();
+        self.enforce_text_budget();
+    }
+
+    /// Spill the title and preview entries of the oldest contexts until the
+    /// text entries are back under three quarters of the budget, so that
+    /// spills stay rare as new contexts arrive.
+    fn enforce_text_budget(&mut self) {
+        let Some(budget) = self.text_budget_bytes else {
+            return;
+        };
+        if self.text_bytes <= budget {
+            return;
+        }
+        let mut costs: BTreeMap<u64, u64> = BTreeMap::new();
+        for (title, ids) in &self.title_exact {
+            for &id in ids {
+                *costs.entry(id).or_default() += title_bytes(title);
+            }
+        }
+        for (&id, text) in &self.preview {
+            *costs.entry(id).or_default() += preview_bytes(text);
+        }
+        let target = budget / 4 * 3;
+        for (id, cost) in costs {
+            if self.text_bytes <= target {
+                break;
+            }
+            self.text_bytes = self.text_bytes.saturating_sub(cost);
+            self.spilled_below = id + 1;
+        }
+
+        let below = self.spilled_below;
+        for map in [
+            &mut self.title_exact,
+            &mut self.title_lower_exact,
+            &mut self.preview_trigrams,
+        ] {
+            map.retain(|_, ids| {
+                ids.retain(|&id| id >= below);
+                !ids.is_empty()
+            });
+            map.shrink_to_fit();
+        }
+        for sorted in [&mut self.title_sorted, &mut self.title_lower_sorted] {
+            sorted.retain(|&(_, id)| id >= below);
+            sorted.shrink_to_fit();
+        }
+        self.preview.retain(|&id, _| id >= below);
+        self.preview.shrink_to_fit();
+    }
+
+    /// Remove a single context's metadata from the indexes.
+    fn unindex_metadata(&mut self, context_id: u64, metadata: &ContextMetadata) {
+        fn remove_exact<K: std::hash::Hash + Eq>(
+            map: &mut HashMap<K, HashSet<u64>>,
+            key: &K,
+            context_id: u64,
+        ) {
+            if let Some(ids) = map.get_mut(key) {
+                ids.remove(&context_id);
+                if ids.is_empty() {
+                    map.remove(key);
+                }
+            }
+        }
+        fn remove_sorted(sorted: &mut Vec<(String, u64)>, value: &str, context_id: u64) {
+            sorted.retain(|(v, id)| !(*id == context_id && v == value));
+        }
+
+        if let Some(tag) = &metadata.client_tag {
+            let lower = tag.to_lowercase();
+            remove_exact(&mut self.tag_exact, tag, context_id);
+            remove_sorted(&mut self.tag_sorted, tag, context_id);
+            remove_exact(&mut self.tag_lower_exact, &lower, context_id);
+            remove_sorted(&mut self.tag_lower_sorted, &lower, context_id);
+        }
+
+        if let Some(title) = metadata
+            .title
+            .as_ref()
+            .filter(|_| !self.is_spilled(context_id))
+        {
+            self.text_bytes = self.text_bytes.saturating_sub(title_bytes(title));
+            let lower = title.to_lowercase();
+            remove_exact(&mut self.title_exact, title, context_id);
+            remove_sorted(&mut self.title_sorted, title, context_id);
+            remove_exact(&mut self.title_lower_exact, &lower, context_id);
+            remove_sorted(&mut self.title_lower_sorted, &lower, context_id);
+        }
+
+        if let Some(labels) = &metadata.labels {
+            for label in labels {
+                remove_exact(&mut self.label_exact, label, context_id);
+            }
+        }
+        for (namespace, value) in metadata.namespaced_labels() {
+            if let Some(values) = self.label_ns.get_mut(namespace) {
+                if let Some(ids) = values.get_mut(value) {
+                    ids.remove(&context_id);
+                    if ids.is_empty() {
+                        values.remove(value);
+                    }
+                }
+                if values.is_empty() {
+                    self.label_ns.remove(namespace);
+                }
+            }
+        }
+
+        if let Some(prov) = &metadata.provenance {
+            if let Some(user) = &prov.on_behalf_of {
+                let lower = user.to_lowercase();
+                remove_exact(&mut self.user_exact, user, context_id);
+                remove_sorted(&mut self.user_sorted, user, context_id);
+                remove_exact(&mut self.user_lower_exact, &lower, context_id);
+                remove_sorted(&mut self.user_lower_sorted, &lower, context_id);
+            }
+            if let Some(service) = &prov.service_name {
+                let lower = service.to_lowercase();
+                remove_exact(&mut self.service_exact, service, context_id);
+                remove_sorted(&mut self.service_sorted, service, context_id);
+                remove_exact(&mut self.service_lower_exact, &lower, context_id);
+                remove_sorted(&mut self.service_lower_sorted, &lower, context_id);
+            }
+            if let Some(host) = &prov.host_name {
+                remove_exact(&mut self.host_exact, host, context_id);
+                remove_sorted(&mut self.host_sorted, host, context_id);
+            }
+            if let Some(trace_id) = &prov.trace_id {
+                remove_exact(&mut self.trace_id_exact, trace_id, context_id);
+            }
+            if let Some(parent) = prov.parent_context_id {
+                remove_exact(&mut self.parent_exact, &parent, context_id);
+            }
+            if let Some(root) = prov.root_context_id {
+                remove_exact(&mut self.root_exact, &root, context_id);
+            }
+        }
+    }
+
     /// Get all context IDs (for NOT operations).
     pub fn all_contexts(&self) -> &HashSet<u64> {
         &self.all_context_ids
     }
 
+    /// Client tag of every tagged context.
+    pub fn context_tags(&self) -> HashMap<u64, &str> {
+        let mut tags = HashMap::with_capacity(self.all_context_ids.len());
+        for (tag, ids) in &self.tag_exact {
+            for id in ids {
+                tags.insert(*id, tag.as_str());
+            }
+        }
+        tags
+    }
+
     // =========================================================================
     // Exact match lookups - O(1)
     // =========================================================================
@@ -284,6 +696,73 @@ impl SecondaryIndexes {
         self.label_exact.get(value).cloned().unwrap_or_default()
     }
 
+    pub fn lookup_label_ns_exact(&self, namespace: &str, value: &str) -> HashSet<u64> {
+        self.label_ns
+            .get(namespace)
+            .and_then(|values| values.get(value))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Contexts with any label in `namespace` whose value starts with `prefix`.
+    pub fn lookup_label_ns_prefix(&self, namespace: &str, prefix: &str) -> HashSet<u64> {
+        let Some(values) = self.label_ns.get(namespace) else {
+            return HashSet::new();
+        };
+        values
+            .range(prefix.to_string()..)
+            .take_while(|(value, _)| value.starts_with(prefix))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect()
+    }
+
+    pub fn lookup_custom_exact(&self, key: &str, value: &str) -> HashSet<u64> {
+        self.custom
+            .get(key)
+            .and_then(|values| values.get(value))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Contexts with a `custom.<key>` entry whose value starts with `prefix`.
+    pub fn lookup_custom_prefix(&self, key: &str, prefix: &str) -> HashSet<u64> {
+        let Some(values) = self.custom.get(key) else {
+            return HashSet::new();
+        };
+        values
+            .range(prefix.to_string()..)
+            .take_while(|(value, _)| value.starts_with(prefix))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect()
+    }
+
+    /// Label namespaces with the number of contexts carrying each.
+    pub fn label_namespaces(&self) -> Vec<(String, usize)> {
+        let mut out: Vec<(String, usize)> = self
+            .label_ns
+            .iter()
+            .map(|(namespace, values)| {
+                let contexts: HashSet<u64> = values.values().flatten().copied().collect();
+                (namespace.clone(), contexts.len())
+            })
+            .collect();
+        out.sort();
+        out
+    }
+
+    /// Values used in a label namespace with per-value context counts, sorted by value.
+    pub fn label_namespace_values(&self, namespace: &str) -> Vec<(String, usize)> {
+        self.label_ns
+            .get(namespace)
+            .map(|values| {
+                values
+                    .iter()
+                    .map(|(value, ids)| (value.clone(), ids.len()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     pub fn lookup_user_exact(&self, value: &str) -> HashSet<u64> {
         self.user_exact.get(value).cloned().unwrap_or_default()
     }
@@ -314,6 +793,73 @@ impl SecondaryIndexes {
         self.trace_id_exact.get(value).cloned().unwrap_or_default()
     }
 
+    pub fn lookup_author_exact(&self, value: &str) -> HashSet<u64> {
+        self.author_exact.get(value).cloned().unwrap_or_default()
+    }
+
+    pub fn lookup_author_tag_exact(&self, value: &str) -> HashSet<u64> {
+        self.author_tag_exact
+            .get(value)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn lookup_project_exact(&self, value: &str) -> HashSet<u64> {
+        self.project_exact.get(value).cloned().unwrap_or_default()
+    }
+
+    pub fn lookup_external_id_exact(&self, value: &str) -> HashSet<u64> {
+        self.external_id_exact
+            .get(value)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Contexts whose preview contains `needle`, ignoring case. Candidates
+    /// sharing every trigram of the needle are checked against the text;
+    /// needles shorter than a trigram scan every preview.
+    pub fn lookup_preview_contains(&self, needle: &str) -> HashSet<u64> {
+        let needle = needle.to_lowercase();
+        let matches = |id: &u64| {
+            self.preview
+                .get(id)
+                .is_some_and(|text| text.to_lowercase().contains(&needle))
+        };
+        let mut candidates: Option<HashSet<u64>> = None;
+        for trigram in trigrams(&needle) {
+            let Some(ids) = self.preview_trigrams.get(&trigram) else {
+                return HashSet::new();
+            };
+            candidates = Some(match candidates {
+                None => ids.clone(),
+                Some(found) => found.intersection(ids).copied().collect(),
+            });
+        }
+        match candidates {
+            Some(ids) => ids.into_iter().filter(matches).collect(),
+            None => self.preview.keys().copied().filter(matches).collect(),
+        }
+    }
+
+    pub fn lookup_has_fs(&self) -> HashSet<u64> {
+        self.has_fs.clone()
+    }
+
+    /// Whether the depth index holds a context at `depth`.
+    pub fn has_depth(&self, context_id: u64, depth: u32) -> bool {
+        self.depth_btree
+            .get(&depth)
+            .is_some_and(|ids| ids.contains(&context_id))
+    }
+
+    pub fn is_on_hold(&self, context_id: u64) -> bool {
+        self.on_hold.contains(&context_id)
+    }
+
+    pub fn lookup_on_hold(&self) -> HashSet<u64> {
+        self.on_hold.clone()
+    }
+
     pub fn lookup_parent_exact(&self, value: u64) -> HashSet<u64> {
         self.parent_exact.get(&value).cloned().unwrap_or_default()
     }
@@ -455,6 +1001,52 @@ impl SecondaryIndexes {
         self.depth_btree.get(&depth).cloned().unwrap_or_default()
     }
 
+    /// Contexts whose token total satisfies `range`.
+    pub fn lookup_tokens(&self, range: impl std::ops::RangeBounds<u64>) -> HashSet<u64> {
+        self.tokens_btree
+            .range(range)
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect()
+    }
+
+    /// Contexts with a TTL whose expiry time satisfies `range`.
+    pub fn lookup_expires(&self, range: impl std::ops::RangeBounds<u64>) -> HashSet<u64> {
+        self.expires_btree
+            .range(range)
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect()
+    }
+
+    /// Contexts whose snapshot count satisfies `range`.
+    pub fn lookup_fs_count(&self, range: impl std::ops::RangeBounds<u64>) -> HashSet<u64> {
+        self.lookup_fs(&self.fs_count_btree, range)
+    }
+
+    /// Contexts whose largest snapshot's file bytes satisfy `range`.
+    pub fn lookup_fs_bytes(&self, range: impl std::ops::RangeBounds<u64>) -> HashSet<u64> {
+        self.lookup_fs(&self.fs_bytes_btree, range)
+    }
+
+    /// Contexts without snapshots count as 0.
+    fn lookup_fs(
+        &self,
+        btree: &BTreeMap<u64, HashSet<u64>>,
+        range: impl std::ops::RangeBounds<u64>,
+    ) -> HashSet<u64> {
+        let mut ids: HashSet<u64> = btree
+            .range((range.start_bound().cloned(), range.end_bound().cloned()))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect();
+        if range.contains(&0) {
+            ids.extend(
+                self.all_context_ids
+                    .iter()
+                    .filter(|id| !self.fs_stats.contains_key(id)),
+            );
+        }
+        ids
+    }
+
     /// Get index statistics.
     pub fn stats(&self) -> IndexStats {
         IndexStats {
@@ -464,11 +1056,166 @@ impl SecondaryIndexes {
             user_entries: self.user_exact.len(),
             service_entries: self.service_exact.len(),
             host_entries: self.host_exact.len(),
+            label_namespace_entries: self.label_ns.len(),
+            custom_key_entries: self.custom.len(),
             created_entries: self.created_btree.len(),
+            memory_bytes: self.memory_bytes() as u64,
+            text_bytes: self.text_bytes,
+            text_budget_bytes: self.text_budget_bytes,
+            spilled_contexts: self.spilled_contexts().len(),
+            metadata_cache: MetadataCacheStats::default(),
+        }
+    }
+
+    /// Approximate heap bytes held by the indexes.
+    fn memory_bytes(&self) -> usize {
+        let string_maps = [
+            &self.tag_exact,
+            &self.tag_lower_exact,
+            &self.title_exact,
+            &self.title_lower_exact,
+            &self.label_exact,
+            &self.user_exact,
+            &self.user_lower_exact,
+            &self.service_exact,
+            &self.service_lower_exact,
+            &self.host_exact,
+            &self.trace_id_exact,
+            &self.author_exact,
+            &self.author_tag_exact,
+            &self.project_exact,
+            &self.external_id_exact,
+            &self.preview_trigrams,
+        ];
+        let sorted = [
+            &self.tag_sorted,
+            &self.tag_lower_sorted,
+            &self.title_sorted,
+            &self.title_lower_sorted,
+            &self.user_sorted,
+            &self.user_lower_sorted,
+            &self.service_sorted,
+            &self.service_lower_sorted,
+            &self.host_sorted,
+        ];
+        let keyed = |key: &String, values: &BTreeMap<String, HashSet<u64>>| {
+            key.capacity()
+                + values.len() * btree_entry_bytes::<String, HashSet<u64>>()
+                + values
+                    .iter()
+                    .map(|(v, ids)| v.capacity() + set_bytes(ids))
+                    .sum::<usize>()
+        };
+        let label_ns = map_bytes(&self.label_ns, keyed) + map_bytes(&self.custom, keyed);
+
+        string_maps
+            .into_iter()
+            .map(|m| map_bytes(m, |k, ids| k.capacity() + set_bytes(ids)))
+            .sum::<usize>()
+            + sorted
+                .into_iter()
+                .map(|v| {
+                    v.capacity() * size_of::<(String, u64)>()
+                        + v.iter().map(|(s, _)| s.capacity()).sum::<usize>()
+                })
+                .sum::<usize>()
+            + label_ns
+            + map_bytes(&self.parent_exact, |_, ids| set_bytes(ids))
+            + map_bytes(&self.root_exact, |_, ids| set_bytes(ids))
+            + btree_bytes(&self.created_btree)
+            + btree_bytes(&self.depth_btree)
+            + btree_bytes(&self.tokens_btree)
+            + set_bytes(&self.has_fs)
+            + set_bytes(&self.on_hold)
+            + self.expires.capacity() * (size_of::<(u64, u64)>() + 1)
+            + btree_bytes(&self.expires_btree)
+            + self.fs_stats.capacity() * (size_of::<(u64, FsStats)>() + 1)
+            + btree_bytes(&self.fs_count_btree)
+            + btree_bytes(&self.fs_bytes_btree)
+            + map_bytes(&self.preview, |_, text| text.capacity())
+            + set_bytes(&self.all_context_ids)
+    }
+}
+
+/// Hash table storage: one slot plus a control byte per bucket, and the
+/// heap data each entry owns.
+fn map_bytes<K: Eq + Hash, V>(map: &HashMap<K, V>, owned: impl Fn(&K, &V) -> usize) -> usize {
+    map.capacity() * (size_of::<(K, V)>() + 1) + map.iter().map(|(k, v)| owned(k, v)).sum::<usize>()
+}
+
+fn set_bytes(set: &HashSet<u64>) -> usize {
+    set.capacity() * (size_of::<u64>() + 1)
+}
+
+/// B-tree nodes hold up to 11 entries; count each entry with its share of
+/// node overhead.
+fn btree_entry_bytes<K, V>() -> usize {
+    size_of::<K>() + size_of::<V>() + 2 * size_of::<usize>()
+}
+
+/// Estimated bytes of a context's title entries: the exact and lowercase
+/// copies in both the hash and the sorted indexes.
+fn title_bytes(title: &str) -> u64 {
+    (4 * title.len() + 2 * size_of::<(String, u64)>() + 2 * (size_of::<u64>() + 1)) as u64
+}
+
+/// Estimated bytes of a context's preview entries: the text and the
+/// context's slot in the set of each of its trigrams.
+fn preview_bytes(preview: &str) -> u64 {
+    (preview.len()
+        + size_of::<(u64, String)>()
+        + 1
+        + trigrams(preview).len() * (size_of::<u64>() + 1)) as u64
+}
+
+/// Distinct lowercase three-character windows of `text`.
+fn trigrams(text: &str) -> HashSet<String> {
+    let chars: Vec<char> = text.to_lowercase().chars().collect();
+    chars.windows(3).map(|w| w.iter().collect()).collect()
+}
+
+/// Remove a context from the set under `key`, dropping the set once empty.
+fn remove_from(btree: &mut BTreeMap<u64, HashSet<u64>>, key: u64, context_id: u64) {
+    if let Some(ids) = btree.get_mut(&key) {
+        ids.remove(&context_id);
+        if ids.is_empty() {
+            btree.remove(&key);
         }
     }
 }
 
+fn btree_bytes<K>(map: &BTreeMap<K, HashSet<u64>>) -> usize {
+    map.len() * btree_entry_bytes::<K, HashSet<u64>>() + map.values().map(set_bytes).sum::<usize>()
+}
+
+/// Title and preview text of spilled contexts, loaded by the store for a
+/// query that filters on either field.
+#[derive(Debug, Default)]
+pub struct SpilledText {
+    pub titles: HashMap<u64, String>,
+    pub previews: HashMap<u64, String>,
+}
+
+impl SpilledText {
+    /// Spilled contexts whose title passes `matches`.
+    pub fn titles_matching(&self, matches: impl Fn(&str) -> bool) -> HashSet<u64> {
+        matching(&self.titles, matches)
+    }
+
+    /// Spilled contexts whose preview passes `matches`.
+    pub fn previews_matching(&self, matches: impl Fn(&str) -> bool) -> HashSet<u64> {
+        matching(&self.previews, matches)
+    }
+}
+
+fn matching(texts: &HashMap<u64, String>, matches: impl Fn(&str) -> bool) -> HashSet<u64> {
+    texts
+        .iter()
+        .filter(|(_, text)| matches(text))
+        .map(|(&id, _)| id)
+        .collect()
+}
+
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct IndexStats {
     pub contexts_indexed: usize,
@@ -477,5 +1224,19 @@ pub struct IndexStats {
     pub user_entries: usize,
     pub service_entries: usize,
     pub host_entries: usize,
+    pub label_namespace_entries: usize,
+    /// Distinct `custom.<key>` keys.
+    pub custom_key_entries: usize,
     pub created_entries: usize,
+    /// Approximate heap bytes held by the secondary indexes.
+    pub memory_bytes: u64,
+    /// Estimated bytes of the title and preview entries.
+    pub text_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_budget_bytes: Option<u64>,
+    /// Contexts whose title and preview entries were spilled to stay
+    /// within `text_budget_bytes`.
+    pub spilled_contexts: usize,
+    /// Metadata cache accounting; filled in by the store.
+    pub metadata_cache: MetadataCacheStats,
 }
@@ -14,6 +14,7 @@
 //! service ^= "dot"
 //! user ~= "Jay"
 //! tag IN ("amplifier", "dotrunner", "gen")
+//! label.team = "payments" AND label.env ^= "prod"
 //! NOT tag = "test"
 //! ```
 //!
@@ -28,6 +29,7 @@
 //! | `^~=` | Case-insensitive prefix | `service ^~= "DOT"` |
 //! | `>`, `>=`, `<`, `<=` | Range | `created > "-24h"` |
 //! | `IN` | List membership | `tag IN ("a", "b")` |
+//! | `CONTAINS` | Case-insensitive substring | `preview CONTAINS "flaky"` |
 //! | `NOT` | Negation | `NOT tag = "test"` |
 //!
 //! # Fields
@@ -38,6 +40,8 @@
 //! | `tag` | string | Client tag |
 //! | `title` | string | Context title |
 //! | `label` | string | Context labels |
+//! | `label.<ns>` | string | Value of namespaced labels (`team:payments` → `label.team = "payments"`) |
+//! | `custom.<key>` | string | Entry an index plugin returned for a turn on the head chain |
 //! | `user` | string | User (on_behalf_of) |
 //! | `service` | string | Service name |
 //! | `host` | string | Host name |
@@ -46,7 +50,16 @@
 //! | `root` | number | Root context ID |
 //! | `created` | date | Creation timestamp |
 //! | `depth` | number | Head turn depth |
-//! | `is_live` | boolean | Has active SSE connections |
+//! | `is_live` | boolean | Live under `CXDB_LIVENESS` (open session, recent append or heartbeat) |
+//! | `has_fs` | boolean | Head turn sees a filesystem snapshot |
+//! | `author` | string | Principal of a session that appended a turn on the head chain |
+//! | `author_tag` | string | Client tag of a session that appended a turn on the head chain |
+//! | `fs_count` | number | Snapshots attached along the head chain |
+//! | `fs_bytes` | number | File bytes of the largest of those snapshots |
+//! | `on_hold` | boolean | Context is on legal hold |
+//! | `project` | string | A project the context is assigned to |
+//! | `external_id` | string | External id the context was created with |
+//! | `preview` | string | Latest `summary_field` value on the head chain (`CONTAINS` only) |
 
 pub mod ast;
 pub mod executor;
@@ -54,6 +67,6 @@ pub mod indexes;
 pub mod parser;
 
 pub use ast::{CqlError, CqlQuery, Expression, FieldName, Operator, Value};
-pub use executor::execute;
-pub use indexes::{IndexStats, SecondaryIndexes};
+pub use executor::{execute, execute_with_spilled};
+pub use indexes::{FsStats, IndexStats, SecondaryIndexes, SpilledText};
 pub use parser::parse;
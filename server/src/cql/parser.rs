@@ -13,7 +13,8 @@
 //!   comparison  = field operator value ;
 
 use super::ast::{
-    CqlError, CqlErrorType, CqlQuery, Expression, FieldName, Operator, Position, Value,
+    custom_key, label_namespace, CqlError, CqlErrorType, CqlQuery, Expression, FieldName, Operator,
+    Position, Value,
 };
 
 /// Token types for the lexer.
@@ -23,6 +24,7 @@ enum TokenType {
     Or,
     Not,
     In,
+    Contains,
     LParen,
     RParen,
     Comma,
@@ -203,7 +205,8 @@ impl<'a> Lexer<'a> {
         let start = self.pos;
 
         while let Some(ch) = self.peek() {
-            if ch.is_alphanumeric() || ch == '_' {
+            // '.' allows namespaced fields such as `label.team`
+            if ch.is_alphanumeric() || ch == '_' || (ch == '.' && self.pos > start) {
                 self.advance();
             } else {
                 break;
@@ -216,6 +219,7 @@ impl<'a> Lexer<'a> {
             "OR" => TokenType::Or,
             "NOT" => TokenType::Not,
             "IN" => TokenType::In,
+            "CONTAINS" => TokenType::Contains,
             _ => TokenType::Ident(value.to_string()),
         };
 
@@ -563,12 +567,15 @@ impl Parser {
         self.advance();
 
         // Validate field name
-        if FieldName::from_str(&field_name).is_none() {
+        if FieldName::from_str(&field_name).is_none()
+            && label_namespace(&field_name).is_none()
+            && custom_key(&field_name).is_none()
+        {
             let valid_fields: Vec<_> = FieldName::all().iter().map(|f| f.as_str()).collect();
             return Err(CqlError {
                 error_type: CqlErrorType::UnknownField,
                 message: format!(
-                    "Unknown field '{}'. Valid fields: {}",
+                    "Unknown field '{}'. Valid fields: {}, label.<namespace>, custom.<key>",
                     field_name,
                     valid_fields.join(", ")
                 ),
@@ -590,6 +597,7 @@ impl Parser {
             TokenType::Lt => Operator::Lt,
             TokenType::Lte => Operator::Lte,
             TokenType::In => Operator::In,
+            TokenType::Contains => Operator::Contains,
             _ => {
                 return Err(CqlError {
                     error_type: CqlErrorType::SyntaxError,
@@ -620,8 +628,8 @@ impl Parser {
         match &token.token_type {
             TokenType::String(s) => {
                 self.advance();
-                // Check if it's a relative date
-                let relative_pattern = regex::Regex::new(r"^-(\d+)([hdm])$").unwrap();
+                // Check if it's a relative date, in the past or the future
+                let relative_pattern = regex::Regex::new(r"^[-+](\d+)([hdm])$").unwrap();
                 if relative_pattern.is_match(s) {
                     Ok(Value::Date {
                         value: s.clone(),
@@ -752,6 +760,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_contains_operator() {
+        let result = parse(r#"preview contains "flaky test""#).unwrap();
+        match result.ast {
+            Expression::Comparison {
+                field, operator, ..
+            } => {
+                assert_eq!(field, "preview");
+                assert_eq!(operator, Operator::Contains);
+            }
+            _ => panic!("Expected comparison"),
+        }
+    }
+
     #[test]
     fn test_parentheses() {
         let result = parse(r#"(tag = "a" OR tag = "b") AND user = "jay""#).unwrap();
@@ -772,6 +794,30 @@ mod tests {
         assert!(matches!(err.error_type, CqlErrorType::UnknownField));
     }
 
+    #[test]
+    fn test_label_namespace_field() {
+        let result = parse(r#"label.team = "payments""#).unwrap();
+        match result.ast {
+            Expression::Comparison { field, .. } => assert_eq!(field, "label.team"),
+            _ => panic!("Expected comparison"),
+        }
+
+        let err = parse(r#"label. = "x""#).unwrap_err();
+        assert!(matches!(err.error_type, CqlErrorType::UnknownField));
+    }
+
+    #[test]
+    fn test_custom_key_field() {
+        let result = parse(r#"custom.model ^= "gpt""#).unwrap();
+        match result.ast {
+            Expression::Comparison { field, .. } => assert_eq!(field, "custom.model"),
+            _ => panic!("Expected comparison"),
+        }
+
+        let err = parse(r#"custom. = "x""#).unwrap_err();
+        assert!(matches!(err.error_type, CqlErrorType::UnknownField));
+    }
+
     #[test]
     fn test_relative_date() {
         let result = parse(r#"created > "-24h""#).unwrap();
@@ -782,5 +828,14 @@ mod tests {
             },
             _ => panic!("Expected comparison"),
         }
+
+        let result = parse(r#"expires < "+7d""#).unwrap();
+        match result.ast {
+            Expression::Comparison { value, .. } => match value {
+                Value::Date { relative, .. } => assert!(relative),
+                _ => panic!("Expected date value"),
+            },
+            _ => panic!("Expected comparison"),
+        }
     }
 }
@@ -0,0 +1,89 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-request time budgets.
+//!
+//! Expensive reads (typed projection of many turns, filesystem tree walks)
+//! run while holding the store lock. A `Deadline` lets them check a time
+//! budget between units of work and bail out with
+//! `StoreError::DeadlineExceeded` instead of stalling every other client.
+
+use std::time::{Duration, Instant};
+
+use crate::error::{Result, StoreError};
+
+/// A point in time after which work should stop. `Deadline::none()` never expires.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Deadline {
+    expires_at: Option<Instant>,
+}
+
+impl Deadline {
+    pub fn none() -> Self {
+        Self { expires_at: None }
+    }
+
+    pub fn after(budget: Duration) -> Self {
+        Self {
+            expires_at: Some(Instant::now() + budget),
+        }
+    }
+
+    /// Build a deadline from a millisecond budget; 0 means unbounded.
+    pub fn from_budget_ms(budget_ms: u64) -> Self {
+        if budget_ms == 0 {
+            Self::none()
+        } else {
+            Self::after(Duration::from_millis(budget_ms))
+        }
+    }
+
+    pub fn is_bounded(&self) -> bool {
+        self.expires_at.is_some()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .map(|at| Instant::now() >= at)
+            .unwrap_or(false)
+    }
+
+    /// Time left before expiry; None when unbounded.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.expires_at
+            .map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Returns `StoreError::DeadlineExceeded` once the deadline has passed.
+    pub fn check(&self, what: &str) -> Result<()> {
+        if self.is_expired() {
+            return Err(StoreError::DeadlineExceeded(what.to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unbounded_never_expires() {
+        let deadline = Deadline::from_budget_ms(0);
+        assert!(!deadline.is_bounded());
+        assert!(!deadline.is_expired());
+        assert!(deadline.remaining().is_none());
+        assert!(deadline.check("test").is_ok());
+    }
+
+    #[test]
+    fn test_expired_deadline() {
+        let deadline = Deadline::after(Duration::ZERO);
+        assert!(deadline.is_expired());
+        assert!(matches!(
+            deadline.check("projection"),
+            Err(StoreError::DeadlineExceeded(msg)) if msg == "projection"
+        ));
+        assert_eq!(deadline.remaining(), Some(Duration::ZERO));
+    }
+}
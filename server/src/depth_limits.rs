@@ -0,0 +1,187 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Maximum context depth.
+//!
+//! A runaway agent loop can grow one context to millions of turns, which
+//! slows every read of its chain. `CXDB_MAX_DEPTH` caps the turns in a
+//! chain: an append that would place a turn at that depth or deeper is
+//! rejected with [`StoreError::DepthLimitExceeded`], and the client is
+//! expected to fork from a recent turn or continue in a new context seeded
+//! with a summary. `CXDB_MAX_DEPTH_TAGS` sets the limit per client tag, e.g.
+//! `ci=5000,_untagged=100000`.
+//!
+//! An append publishes a `depth_warning` event when its chain crosses
+//! `CXDB_DEPTH_WARN_AT` of the limit (level `warning`), and when it takes
+//! the last turn the limit allows (level `exceeded`).
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::error::{Result, StoreError};
+use crate::events::StoreEvent;
+use crate::metrics::UNTAGGED;
+use crate::quotas::QuotaLevel;
+
+const DEFAULT_WARN_AT: f64 = 0.9;
+
+/// Depth limits, loaded from the environment.
+#[derive(Debug, Clone)]
+pub struct DepthLimits {
+    /// Turns allowed in a chain of tags not listed in `tags`.
+    pub default: Option<u32>,
+    /// Turns allowed by client tag; `_untagged` covers contexts without one.
+    pub tags: BTreeMap<String, u32>,
+    /// Fraction of a limit at which the warning is published.
+    pub warn_at: f64,
+}
+
+impl DepthLimits {
+    /// Load the limits; None when neither `CXDB_MAX_DEPTH` nor
+    /// `CXDB_MAX_DEPTH_TAGS` is set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let default = var("CXDB_MAX_DEPTH")
+            .map(|v| parse_limit("CXDB_MAX_DEPTH", &v))
+            .transpose()?;
+        let tags = match var("CXDB_MAX_DEPTH_TAGS") {
+            Some(map) => parse_tag_limits(&map)?,
+            None => BTreeMap::new(),
+        };
+        if default.is_none() && tags.is_empty() {
+            return Ok(None);
+        }
+        let warn_at = match var("CXDB_DEPTH_WARN_AT") {
+            Some(v) => v
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|w| *w > 0.0 && *w <= 1.0)
+                .ok_or_else(|| {
+                    StoreError::InvalidInput(format!(
+                        "CXDB_DEPTH_WARN_AT must be in (0, 1], got {v:?}"
+                    ))
+                })?,
+            None => DEFAULT_WARN_AT,
+        };
+        Ok(Some(Self {
+            default,
+            tags,
+            warn_at,
+        }))
+    }
+
+    /// One-line summary for the startup log.
+    pub fn describe(&self) -> String {
+        let mut parts: Vec<String> = self
+            .default
+            .map(|limit| format!("{limit} turns"))
+            .into_iter()
+            .collect();
+        parts.extend(
+            self.tags
+                .iter()
+                .map(|(tag, limit)| format!("{tag}={limit}")),
+        );
+        format!(
+            "{}, warning at {:.0}%",
+            parts.join(", "),
+            self.warn_at * 100.0
+        )
+    }
+
+    /// Turns allowed in a chain of a context with `client_tag`.
+    pub fn limit(&self, client_tag: Option<&str>) -> Option<u32> {
+        let tag = client_tag.filter(|t| !t.is_empty()).unwrap_or(UNTAGGED);
+        self.tags.get(tag).copied().or(self.default)
+    }
+
+    /// Refuse a turn at `depth` when the chain is already full.
+    pub fn check(&self, context_id: u64, client_tag: Option<&str>, depth: u32) -> Result<()> {
+        match self.limit(client_tag) {
+            Some(limit) if depth >= limit => Err(StoreError::DepthLimitExceeded {
+                context_id,
+                depth,
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The threshold a turn appended at `depth` crossed, if any.
+    pub fn warning(
+        &self,
+        context_id: u64,
+        client_tag: Option<&str>,
+        depth: u32,
+    ) -> Option<DepthWarning> {
+        let limit = self.limit(client_tag)?;
+        let turns = depth as u64 + 1;
+        let warn_turns = (self.warn_at * limit as f64).ceil() as u64;
+        let level = if turns == limit as u64 {
+            QuotaLevel::Exceeded
+        } else if turns == warn_turns {
+            QuotaLevel::Warning
+        } else {
+            return None;
+        };
+        Some(DepthWarning {
+            context_id,
+            depth,
+            limit,
+            level,
+            percent: turns as f64 * 100.0 / limit as f64,
+        })
+    }
+}
+
+fn parse_limit(name: &str, value: &str) -> Result<u32> {
+    value
+        .trim()
+        .parse::<u32>()
+        .ok()
+        .filter(|limit| *limit > 0)
+        .ok_or_else(|| {
+            StoreError::InvalidInput(format!("{name} must be a positive integer, got {value:?}"))
+        })
+}
+
+/// Parse `tag=limit` pairs separated by commas.
+fn parse_tag_limits(map: &str) -> Result<BTreeMap<String, u32>> {
+    map.split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (tag, limit) = pair.split_once('=').ok_or_else(|| {
+                StoreError::InvalidInput(format!("invalid CXDB_MAX_DEPTH_TAGS entry {pair:?}"))
+            })?;
+            Ok((
+                tag.trim().to_string(),
+                parse_limit("CXDB_MAX_DEPTH_TAGS", limit)?,
+            ))
+        })
+        .collect()
+}
+
+/// A context's chain crossed a depth threshold.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DepthWarning {
+    pub context_id: u64,
+    /// Depth of the turn that crossed the threshold.
+    pub depth: u32,
+    pub limit: u32,
+    pub level: QuotaLevel,
+    pub percent: f64,
+}
+
+impl DepthWarning {
+    pub fn to_event(&self) -> StoreEvent {
+        StoreEvent::DepthWarning {
+            context_id: self.context_id.to_string(),
+            depth: self.depth,
+            limit: self.limit,
+            level: self.level.as_str().to_string(),
+            percent: self.percent,
+        }
+    }
+}
@@ -0,0 +1,219 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Structural diffs between projected turn payloads.
+//!
+//! Both sides are compared as JSON trees. Objects are compared key by key and
+//! arrays index by index; every difference is reported under a JSON Pointer
+//! path (RFC 6901) as `added`, `removed` or `changed`. Long string fields can
+//! additionally carry a line-level unified diff.
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use similar::TextDiff;
+
+const DEFAULT_TEXT_DIFF_MIN_CHARS: usize = 80;
+
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    /// Attach a unified text diff to changed strings at least this long.
+    /// None disables text diffs.
+    pub text_diff_min_chars: Option<usize>,
+    /// Context lines around each text diff hunk.
+    pub text_context_lines: usize,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            text_diff_min_chars: None,
+            text_context_lines: 3,
+        }
+    }
+}
+
+impl DiffOptions {
+    /// Enable text diffs for strings of at least `min_chars` characters
+    /// (default 80 when None).
+    pub fn with_text_diffs(min_chars: Option<usize>) -> Self {
+        Self {
+            text_diff_min_chars: Some(min_chars.unwrap_or(DEFAULT_TEXT_DIFF_MIN_CHARS)),
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffOp {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffEntry {
+    pub path: String,
+    pub op: DiffOp,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<JsonValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<JsonValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_diff: Option<String>,
+}
+
+/// Compute the structural differences from `old` to `new`.
+pub fn diff_json(old: &JsonValue, new: &JsonValue, options: &DiffOptions) -> Vec<DiffEntry> {
+    let mut out = Vec::new();
+    diff_value("", old, new, options, &mut out);
+    out
+}
+
+fn diff_value(
+    path: &str,
+    old: &JsonValue,
+    new: &JsonValue,
+    options: &DiffOptions,
+    out: &mut Vec<DiffEntry>,
+) {
+    match (old, new) {
+        (JsonValue::Object(a), JsonValue::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let child = format!("{path}/{}", escape_pointer(key));
+                match (a.get(key), b.get(key)) {
+                    (Some(x), Some(y)) => diff_value(&child, x, y, options, out),
+                    (Some(x), None) => out.push(removed(child, x)),
+                    (None, Some(y)) => out.push(added(child, y)),
+                    (None, None) => {}
+                }
+            }
+        }
+        (JsonValue::Array(a), JsonValue::Array(b)) => {
+            for i in 0..a.len().max(b.len()) {
+                let child = format!("{path}/{i}");
+                match (a.get(i), b.get(i)) {
+                    (Some(x), Some(y)) => diff_value(&child, x, y, options, out),
+                    (Some(x), None) => out.push(removed(child, x)),
+                    (None, Some(y)) => out.push(added(child, y)),
+                    (None, None) => {}
+                }
+            }
+        }
+        _ if old == new => {}
+        _ => {
+            let text_diff = match (old, new, options.text_diff_min_chars) {
+                (JsonValue::String(a), JsonValue::String(b), Some(min_chars))
+                    if a.chars().count().max(b.chars().count()) >= min_chars =>
+                {
+                    Some(text_diff(a, b, options.text_context_lines))
+                }
+                _ => None,
+            };
+            out.push(DiffEntry {
+                path: path.to_string(),
+                op: DiffOp::Changed,
+                old: Some(old.clone()),
+                new: Some(new.clone()),
+                text_diff,
+            });
+        }
+    }
+}
+
+fn added(path: String, value: &JsonValue) -> DiffEntry {
+    DiffEntry {
+        path,
+        op: DiffOp::Added,
+        old: None,
+        new: Some(value.clone()),
+        text_diff: None,
+    }
+}
+
+fn removed(path: String, value: &JsonValue) -> DiffEntry {
+    DiffEntry {
+        path,
+        op: DiffOp::Removed,
+        old: Some(value.clone()),
+        new: None,
+        text_diff: None,
+    }
+}
+
+/// Line-level unified diff of two strings.
+fn text_diff(old: &str, new: &str, context_lines: usize) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(context_lines)
+        .header("old", "new")
+        .to_string()
+}
+
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_objects_and_arrays() {
+        let old = json!({
+            "status": "running",
+            "steps": ["plan", "build"],
+            "owner": { "name": "ops" },
+            "a/b": 1
+        });
+        let new = json!({
+            "status": "done",
+            "steps": ["plan"],
+            "owner": { "name": "ops", "team": "infra" },
+            "a/b": 1
+        });
+        let changes = diff_json(&old, &new, &DiffOptions::default());
+        let summary: Vec<(&str, DiffOp)> =
+            changes.iter().map(|c| (c.path.as_str(), c.op)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("/owner/team", DiffOp::Added),
+                ("/status", DiffOp::Changed),
+                ("/steps/1", DiffOp::Removed),
+            ]
+        );
+        assert_eq!(changes[1].old, Some(json!("running")));
+        assert_eq!(changes[1].new, Some(json!("done")));
+        assert!(diff_json(&old, &old, &DiffOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_type_change_and_pointer_escaping() {
+        let changes = diff_json(
+            &json!({ "x~y": [1] }),
+            &json!({ "x~y": { "0": 1 } }),
+            &DiffOptions::default(),
+        );
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "/x~0y");
+        assert_eq!(changes[0].op, DiffOp::Changed);
+    }
+
+    #[test]
+    fn test_text_diff_for_long_strings() {
+        let old = json!({ "text": "line one\nline two\nline three\n", "tag": "a" });
+        let new = json!({ "text": "line one\nline 2\nline three\n", "tag": "b" });
+        let changes = diff_json(&old, &new, &DiffOptions::with_text_diffs(Some(10)));
+        let text = changes.iter().find(|c| c.path == "/text").unwrap();
+        let unified = text.text_diff.as_deref().unwrap();
+        assert!(unified.contains("-line two"));
+        assert!(unified.contains("+line 2"));
+        let tag = changes.iter().find(|c| c.path == "/tag").unwrap();
+        assert!(tag.text_diff.is_none());
+    }
+}
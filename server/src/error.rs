@@ -13,6 +13,44 @@ pub enum StoreError {
     NotFound(String),
     #[error("invalid input: {0}")]
     InvalidInput(String),
+    #[error("cancelled: {0}")]
+    Cancelled(String),
+    #[error("deadline exceeded: {0}")]
+    DeadlineExceeded(String),
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("shredded: {0}")]
+    Shredded(String),
+    #[error("expired: {0}")]
+    Expired(String),
+    #[error("unauthenticated: {0}")]
+    Unauthenticated(String),
+    #[error("malformed frame: {message}.{field}: {reason}")]
+    MalformedFrame {
+        message: String,
+        field: String,
+        reason: String,
+    },
+    #[error("unsupported message: msg_type {msg_type}")]
+    UnsupportedMessage { msg_type: u16 },
+    #[error(
+        "depth limit exceeded: context {context_id} allows {limit} turns and cannot take a turn at \
+         depth {depth}; fork from a recent turn or continue in a new context with a summary"
+    )]
+    DepthLimitExceeded {
+        context_id: u64,
+        depth: u32,
+        limit: u32,
+    },
+    #[error(
+        "unsupported store format: {path} is format version {found}, but this server reads up to \
+         version {supported}; run a newer cxdb-server or restore a backup"
+    )]
+    UnsupportedFormat {
+        path: String,
+        found: u32,
+        supported: u32,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, StoreError>;
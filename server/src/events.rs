@@ -5,13 +5,19 @@
 //!
 //! This module provides an EventBus that broadcasts store events to SSE subscribers.
 //! Events originate from the binary protocol handler and are fanned out to all
-//! connected HTTP SSE clients.
+//! connected HTTP SSE clients. Each subscriber has a bounded queue; see
+//! [`EventBusConfig`] for the limits and overflow policy.
 
-use std::sync::mpsc::{self, Receiver, Sender};
-use std::sync::{Arc, Mutex};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
 
 use serde::Serialize;
 
+use crate::error::Result;
+use crate::sinks::{Outbox, SinkStats};
+use crate::util::env_usize;
+
 /// Store events that can be broadcast to SSE subscribers.
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -22,6 +28,9 @@ pub enum StoreEvent {
         session_id: String,
         client_tag: String,
         created_at: u64,
+        /// Caller-assigned id the context was created with.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        external_id: Option<String>,
     },
     /// Context metadata was extracted from the first turn.
     ContextMetadataUpdated {
@@ -56,6 +65,135 @@ pub enum StoreEvent {
         client_tag: String,
         contexts: Vec<String>,
     },
+    /// A long-running operation finished (succeeded, failed or cancelled).
+    OperationCompleted {
+        operation_id: String,
+        kind: String,
+        state: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// A watch expression's result set changed.
+    WatchTriggered {
+        watch_id: String,
+        name: String,
+        entered: Vec<String>,
+        left: Vec<String>,
+    },
+    /// A context's set of viewers changed.
+    PresenceChanged {
+        context_id: String,
+        viewers: usize,
+        /// Principals among the viewers; anonymous viewers are only counted.
+        principals: Vec<String>,
+    },
+    /// A project was created.
+    ProjectCreated {
+        project_id: String,
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        principal: Option<String>,
+    },
+    /// A context was added to a project.
+    ProjectAssigned {
+        project_id: String,
+        context_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        principal: Option<String>,
+    },
+    /// A context was removed from a project.
+    ProjectUnassigned {
+        project_id: String,
+        context_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        principal: Option<String>,
+    },
+    /// A context's TTL ran out and the expiry sweeper tombstoned it.
+    ContextExpired {
+        context_id: String,
+        expires_at: u64,
+        expired_at: u64,
+    },
+    /// A client reported or cleared the progress status of a context or of
+    /// its session.
+    StatusChanged {
+        /// Set for a context's status; absent for a session's own status.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        context_id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+        /// None when the status was cleared.
+        status: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        progress: Option<f64>,
+        updated_at: u64,
+    },
+    /// A context was transferred to a new owner.
+    OwnershipChanged {
+        context_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        previous_owner: Option<String>,
+        owner: String,
+        principal: String,
+    },
+    /// Contexts were moved off one or more client tags by a rename or merge.
+    TagsChanged {
+        /// `rename` or `merge`.
+        operation: String,
+        sources: Vec<String>,
+        target: String,
+        contexts: Vec<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        principal: Option<String>,
+    },
+    /// A client tag's usage crossed a quota threshold.
+    QuotaWarning {
+        tag: String,
+        /// `contexts`, `turns` or `bytes`.
+        resource: String,
+        /// `warning` at the warning threshold, `exceeded` at the limit.
+        level: String,
+        usage: u64,
+        limit: u64,
+        percent: f64,
+    },
+    /// A context's chain crossed a depth limit threshold.
+    DepthWarning {
+        context_id: String,
+        depth: u32,
+        limit: u32,
+        /// `warning` at the warning threshold, `exceeded` when the chain is full.
+        level: String,
+        percent: f64,
+    },
+    /// A turn of a context was bookmarked.
+    BookmarkAdded {
+        context_id: String,
+        bookmark_id: String,
+        turn_id: String,
+        depth: u32,
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        author: Option<String>,
+    },
+    /// A bookmark was deleted.
+    BookmarkDeleted {
+        context_id: String,
+        bookmark_id: String,
+        turn_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        principal: Option<String>,
+    },
+    /// A turn matched a context subscription whose target is a sink
+    /// identifier rather than a webhook.
+    SubscriptionNotified {
+        subscription_id: String,
+        context_id: String,
+        target: String,
+        turn_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        declared_type_id: Option<String>,
+    },
 }
 
 impl StoreEvent {
@@ -67,6 +205,21 @@ impl StoreEvent {
             StoreEvent::TurnAppended { .. } => "turn_appended",
             StoreEvent::ClientConnected { .. } => "client_connected",
             StoreEvent::ClientDisconnected { .. } => "client_disconnected",
+            StoreEvent::OperationCompleted { .. } => "operation_completed",
+            StoreEvent::WatchTriggered { .. } => "watch_triggered",
+            StoreEvent::PresenceChanged { .. } => "presence_changed",
+            StoreEvent::ProjectCreated { .. } => "project_created",
+            StoreEvent::ProjectAssigned { .. } => "project_assigned",
+            StoreEvent::ProjectUnassigned { .. } => "project_unassigned",
+            StoreEvent::ContextExpired { .. } => "context_expired",
+            StoreEvent::StatusChanged { .. } => "status_changed",
+            StoreEvent::SubscriptionNotified { .. } => "subscription_notified",
+            StoreEvent::OwnershipChanged { .. } => "ownership_changed",
+            StoreEvent::TagsChanged { .. } => "tags_changed",
+            StoreEvent::QuotaWarning { .. } => "quota_warning",
+            StoreEvent::DepthWarning { .. } => "depth_warning",
+            StoreEvent::BookmarkAdded { .. } => "bookmark_added",
+            StoreEvent::BookmarkDeleted { .. } => "bookmark_deleted",
         };
 
         // Serialize without the type tag (frontend expects flat structure)
@@ -76,12 +229,19 @@ impl StoreEvent {
                 session_id,
                 client_tag,
                 created_at,
-            } => serde_json::json!({
-                "context_id": context_id,
-                "session_id": session_id,
-                "client_tag": client_tag,
-                "created_at": created_at,
-            }),
+                external_id,
+            } => {
+                let mut data = serde_json::json!({
+                    "context_id": context_id,
+                    "session_id": session_id,
+                    "client_tag": client_tag,
+                    "created_at": created_at,
+                });
+                if let Some(external_id) = external_id {
+                    data["external_id"] = serde_json::json!(external_id);
+                }
+                data
+            }
             StoreEvent::ContextMetadataUpdated {
                 context_id,
                 client_tag,
@@ -142,61 +302,515 @@ impl StoreEvent {
                 "client_tag": client_tag,
                 "contexts": contexts,
             }),
+            StoreEvent::OperationCompleted {
+                operation_id,
+                kind,
+                state,
+                error,
+            } => {
+                let mut obj = serde_json::json!({
+                    "operation_id": operation_id,
+                    "kind": kind,
+                    "state": state,
+                });
+                if let Some(e) = error {
+                    obj["error"] = serde_json::Value::String(e.clone());
+                }
+                obj
+            }
+            StoreEvent::WatchTriggered {
+                watch_id,
+                name,
+                entered,
+                left,
+            } => serde_json::json!({
+                "watch_id": watch_id,
+                "name": name,
+                "entered": entered,
+                "left": left,
+            }),
+            StoreEvent::PresenceChanged {
+                context_id,
+                viewers,
+                principals,
+            } => serde_json::json!({
+                "context_id": context_id,
+                "viewers": viewers,
+                "principals": principals,
+            }),
+            StoreEvent::ProjectCreated {
+                project_id,
+                name,
+                principal,
+            } => {
+                let mut obj = serde_json::json!({
+                    "project_id": project_id,
+                    "name": name,
+                });
+                if let Some(p) = principal {
+                    obj["principal"] = serde_json::Value::String(p.clone());
+                }
+                obj
+            }
+            StoreEvent::ProjectAssigned {
+                project_id,
+                context_id,
+                principal,
+            }
+            | StoreEvent::ProjectUnassigned {
+                project_id,
+                context_id,
+                principal,
+            } => {
+                let mut obj = serde_json::json!({
+                    "project_id": project_id,
+                    "context_id": context_id,
+                });
+                if let Some(p) = principal {
+                    obj["principal"] = serde_json::Value::String(p.clone());
+                }
+                obj
+            }
+            StoreEvent::ContextExpired {
+                context_id,
+                expires_at,
+                expired_at,
+            } => serde_json::json!({
+                "context_id": context_id,
+                "expires_at": expires_at,
+                "expired_at": expired_at,
+            }),
+            StoreEvent::StatusChanged {
+                context_id,
+                session_id,
+                status,
+                progress,
+                updated_at,
+            } => {
+                let mut obj = serde_json::json!({
+                    "status": status,
+                    "updated_at": updated_at,
+                });
+                if let Some(id) = context_id {
+                    obj["context_id"] = serde_json::Value::String(id.clone());
+                }
+                if let Some(id) = session_id {
+                    obj["session_id"] = serde_json::Value::String(id.clone());
+                }
+                if let Some(p) = progress {
+                    obj["progress"] = serde_json::json!(p);
+                }
+                obj
+            }
+            StoreEvent::SubscriptionNotified {
+                subscription_id,
+                context_id,
+                target,
+                turn_id,
+                declared_type_id,
+            } => {
+                let mut obj = serde_json::json!({
+                    "subscription_id": subscription_id,
+                    "context_id": context_id,
+                    "target": target,
+                    "turn_id": turn_id,
+                });
+                if let Some(t) = declared_type_id {
+                    obj["declared_type_id"] = serde_json::Value::String(t.clone());
+                }
+                obj
+            }
+            StoreEvent::OwnershipChanged {
+                context_id,
+                previous_owner,
+                owner,
+                principal,
+            } => {
+                let mut obj = serde_json::json!({
+                    "context_id": context_id,
+                    "owner": owner,
+                    "principal": principal,
+                });
+                if let Some(p) = previous_owner {
+                    obj["previous_owner"] = serde_json::Value::String(p.clone());
+                }
+                obj
+            }
+            StoreEvent::TagsChanged {
+                operation,
+                sources,
+                target,
+                contexts,
+                principal,
+            } => {
+                let mut obj = serde_json::json!({
+                    "operation": operation,
+                    "sources": sources,
+                    "target": target,
+                    "contexts": contexts,
+                });
+                if let Some(p) = principal {
+                    obj["principal"] = serde_json::Value::String(p.clone());
+                }
+                obj
+            }
+            StoreEvent::QuotaWarning {
+                tag,
+                resource,
+                level,
+                usage,
+                limit,
+                percent,
+            } => serde_json::json!({
+                "tag": tag,
+                "resource": resource,
+                "level": level,
+                "usage": usage,
+                "limit": limit,
+                "percent": percent,
+            }),
+            StoreEvent::DepthWarning {
+                context_id,
+                depth,
+                limit,
+                level,
+                percent,
+            } => serde_json::json!({
+                "context_id": context_id,
+                "depth": depth,
+                "limit": limit,
+                "level": level,
+                "percent": percent,
+            }),
+            StoreEvent::BookmarkAdded {
+                context_id,
+                bookmark_id,
+                turn_id,
+                depth,
+                name,
+                author,
+            } => {
+                let mut obj = serde_json::json!({
+                    "context_id": context_id,
+                    "bookmark_id": bookmark_id,
+                    "turn_id": turn_id,
+                    "depth": depth,
+                    "name": name,
+                });
+                if let Some(a) = author {
+                    obj["author"] = serde_json::Value::String(a.clone());
+                }
+                obj
+            }
+            StoreEvent::BookmarkDeleted {
+                context_id,
+                bookmark_id,
+                turn_id,
+                principal,
+            } => {
+                let mut obj = serde_json::json!({
+                    "context_id": context_id,
+                    "bookmark_id": bookmark_id,
+                    "turn_id": turn_id,
+                });
+                if let Some(p) = principal {
+                    obj["principal"] = serde_json::Value::String(p.clone());
+                }
+                obj
+            }
         };
 
         (event_type, data.to_string())
     }
 }
 
+/// What happens when a subscriber's queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued event to make room for the new one.
+    DropOldest,
+    /// Close the subscription; the SSE stream ends.
+    Disconnect,
+}
+
+/// Limits for SSE subscribers.
+#[derive(Debug, Clone)]
+pub struct EventBusConfig {
+    /// Events buffered per subscriber before the overflow policy applies.
+    pub queue_capacity: usize,
+    /// Overflow policy for SSE streams. Internal subscribers always drop oldest.
+    pub overflow: OverflowPolicy,
+    /// Maximum concurrent SSE streams (0 = unlimited).
+    pub max_streams: usize,
+}
+
+impl EventBusConfig {
+    /// Load config from environment variables with defaults.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let overflow = match std::env::var("CXDB_SSE_OVERFLOW_POLICY").ok().as_deref() {
+            Some("disconnect") => OverflowPolicy::Disconnect,
+            Some("drop_oldest") | None => OverflowPolicy::DropOldest,
+            Some(other) => {
+                eprintln!("unknown CXDB_SSE_OVERFLOW_POLICY {other:?}, using drop_oldest");
+                OverflowPolicy::DropOldest
+            }
+        };
+        Self {
+            queue_capacity: env_usize("CXDB_SSE_QUEUE_CAPACITY", defaults.queue_capacity).max(1),
+            overflow,
+            max_streams: env_usize("CXDB_SSE_MAX_STREAMS", defaults.max_streams),
+        }
+    }
+}
+
+impl Default for EventBusConfig {
+    fn default() -> Self {
+        Self {
+            queue_capacity: 1024,
+            overflow: OverflowPolicy::DropOldest,
+            max_streams: 256,
+        }
+    }
+}
+
+/// Point-in-time SSE accounting, reported in the metrics snapshot.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EventBusStats {
+    pub active_streams: u64,
+    pub max_streams: u64,
+    /// Streams whose queue is at least half full.
+    pub slow_consumers: u64,
+    pub queued_events: u64,
+    pub dropped_events_total: u64,
+    pub overflow_disconnects_total: u64,
+    pub rejected_streams_total: u64,
+    /// Event sink outbox, when a sink is configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sink: Option<SinkStats>,
+}
+
+/// Bounded event queue shared between the bus and one subscriber.
+struct SubscriberQueue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+    capacity: usize,
+    overflow: OverflowPolicy,
+    /// SSE stream (counted against `max_streams`) rather than an internal consumer.
+    stream: bool,
+}
+
+#[derive(Default)]
+struct QueueState {
+    events: VecDeque<StoreEvent>,
+    /// Events dropped since the subscriber last called `take_dropped`.
+    dropped: u64,
+    closed: bool,
+}
+
+enum Delivery {
+    Queued,
+    DroppedOldest,
+    Disconnected,
+    Closed,
+}
+
+impl SubscriberQueue {
+    fn push(&self, event: &StoreEvent) -> Delivery {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Delivery::Closed;
+        }
+        let mut delivery = Delivery::Queued;
+        if state.events.len() >= self.capacity {
+            match self.overflow {
+                OverflowPolicy::DropOldest => {
+                    state.events.pop_front();
+                    state.dropped += 1;
+                    delivery = Delivery::DroppedOldest;
+                }
+                OverflowPolicy::Disconnect => {
+                    state.events.clear();
+                    state.closed = true;
+                    drop(state);
+                    self.ready.notify_all();
+                    return Delivery::Disconnected;
+                }
+            }
+        }
+        state.events.push_back(event.clone());
+        drop(state);
+        self.ready.notify_one();
+        delivery
+    }
+}
+
 /// A subscriber to the event bus.
 pub struct EventSubscriber {
-    rx: Receiver<StoreEvent>,
+    queue: Arc<SubscriberQueue>,
 }
 
 impl EventSubscriber {
     /// Receive the next event, blocking until available.
+    /// Returns None once the subscription is closed.
     pub fn recv(&self) -> Option<StoreEvent> {
-        self.rx.recv().ok()
+        let state = self.queue.state.lock().unwrap();
+        let mut state = self
+            .queue
+            .ready
+            .wait_while(state, |s| s.events.is_empty() && !s.closed)
+            .unwrap();
+        state.events.pop_front()
     }
 
     /// Try to receive an event without blocking.
     pub fn try_recv(&self) -> Option<StoreEvent> {
-        self.rx.try_recv().ok()
+        self.queue.state.lock().unwrap().events.pop_front()
     }
 
     /// Receive with timeout.
     pub fn recv_timeout(&self, timeout: std::time::Duration) -> Option<StoreEvent> {
-        self.rx.recv_timeout(timeout).ok()
+        let state = self.queue.state.lock().unwrap();
+        let (mut state, _) = self
+            .queue
+            .ready
+            .wait_timeout_while(state, timeout, |s| s.events.is_empty() && !s.closed)
+            .unwrap();
+        state.events.pop_front()
+    }
+
+    /// True once the bus disconnected this subscriber for overflowing its queue.
+    pub fn is_closed(&self) -> bool {
+        self.queue.state.lock().unwrap().closed
+    }
+
+    /// Number of events dropped since the last call.
+    pub fn take_dropped(&self) -> u64 {
+        std::mem::take(&mut self.queue.state.lock().unwrap().dropped)
+    }
+}
+
+impl Drop for EventSubscriber {
+    fn drop(&mut self) {
+        let mut state = self.queue.state.lock().unwrap();
+        state.closed = true;
+        state.events.clear();
     }
 }
 
 /// Thread-safe event bus for broadcasting store events to SSE subscribers.
+///
+/// Every subscriber gets a bounded queue so a stalled consumer cannot hold
+/// memory or block `publish`. SSE streams are capped by `max_streams` and use
+/// the configured overflow policy; internal subscribers (watches, hooks) are
+/// not capped and drop their oldest events on overflow.
 pub struct EventBus {
-    subscribers: Arc<Mutex<Vec<Sender<StoreEvent>>>>,
+    config: EventBusConfig,
+    subscribers: Arc<Mutex<Vec<Arc<SubscriberQueue>>>>,
+    dropped_events_total: AtomicU64,
+    overflow_disconnects_total: AtomicU64,
+    rejected_streams_total: AtomicU64,
+    /// Durable record of exported events, written before fan-out.
+    outbox: Mutex<Option<Arc<Outbox>>>,
 }
 
 impl EventBus {
-    /// Create a new event bus.
+    /// Create a new event bus with default limits.
     pub fn new() -> Self {
+        Self::with_config(EventBusConfig::default())
+    }
+
+    pub fn with_config(config: EventBusConfig) -> Self {
         Self {
+            config,
             subscribers: Arc::new(Mutex::new(Vec::new())),
+            dropped_events_total: AtomicU64::new(0),
+            overflow_disconnects_total: AtomicU64::new(0),
+            rejected_streams_total: AtomicU64::new(0),
+            outbox: Mutex::new(None),
         }
     }
 
+    /// Record exported events in `outbox` from now on.
+    pub fn attach_outbox(&self, outbox: Arc<Outbox>) {
+        *self.outbox.lock().unwrap() = Some(outbox);
+    }
+
     /// Subscribe to events. Returns a subscriber that receives all future events.
     pub fn subscribe(&self) -> EventSubscriber {
-        let (tx, rx) = mpsc::channel();
         let mut subs = self.subscribers.lock().unwrap();
-        subs.push(tx);
-        EventSubscriber { rx }
+        self.add_subscriber(&mut subs, OverflowPolicy::DropOldest, false)
+    }
+
+    /// Subscribe an SSE stream. Returns None when `max_streams` streams are
+    /// already open.
+    pub fn subscribe_stream(&self) -> Option<EventSubscriber> {
+        let mut subs = self.subscribers.lock().unwrap();
+        if self.config.max_streams > 0 && active_streams(&subs) >= self.config.max_streams {
+            self.rejected_streams_total.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(self.add_subscriber(&mut subs, self.config.overflow, true))
+    }
+
+    fn add_subscriber(
+        &self,
+        subs: &mut Vec<Arc<SubscriberQueue>>,
+        overflow: OverflowPolicy,
+        stream: bool,
+    ) -> EventSubscriber {
+        let queue = Arc::new(SubscriberQueue {
+            state: Mutex::new(QueueState::default()),
+            ready: Condvar::new(),
+            capacity: self.config.queue_capacity.max(1),
+            overflow,
+            stream,
+        });
+        subs.push(Arc::clone(&queue));
+        EventSubscriber { queue }
     }
 
-    /// Publish an event to all subscribers.
-    /// Disconnected subscribers are automatically removed.
+    /// Publish an event to all subscribers. Never blocks on a slow subscriber.
+    /// Closed subscribers are automatically removed. Exported events are
+    /// recorded in the attached outbox first; a failure to record is logged
+    /// and the event is still delivered to subscribers.
     pub fn publish(&self, event: StoreEvent) {
+        if let Some(outbox) = self.outbox.lock().unwrap().as_ref() {
+            if let Err(e) = outbox.record(&event) {
+                eprintln!("event sink: failed to record event in outbox: {e}");
+            }
+        }
+        self.fan_out(&event);
+    }
+
+    /// Like [`EventBus::publish`], but fails without publishing when an
+    /// exported event cannot be recorded in the outbox. Writes publish their
+    /// events this way before acknowledging, so an acknowledged write is
+    /// never missing from the sink.
+    pub fn publish_recorded(&self, event: StoreEvent) -> Result<()> {
+        if let Some(outbox) = self.outbox.lock().unwrap().as_ref() {
+            outbox.record(&event)?;
+        }
+        self.fan_out(&event);
+        Ok(())
+    }
+
+    fn fan_out(&self, event: &StoreEvent) {
         let mut subs = self.subscribers.lock().unwrap();
-        // Send to all, remove disconnected
-        subs.retain(|tx| tx.send(event.clone()).is_ok());
+        subs.retain(|queue| match queue.push(event) {
+            Delivery::Queued => true,
+            Delivery::DroppedOldest => {
+                self.dropped_events_total.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Delivery::Disconnected => {
+                self.overflow_disconnects_total
+                    .fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            Delivery::Closed => false,
+        });
     }
 
     /// Get the current number of subscribers.
@@ -204,6 +818,51 @@ impl EventBus {
         let subs = self.subscribers.lock().unwrap();
         subs.len()
     }
+
+    /// SSE stream counts and overflow counters.
+    pub fn stats(&self) -> EventBusStats {
+        let subs = self.subscribers.lock().unwrap();
+        let mut stats = EventBusStats {
+            max_streams: self.config.max_streams as u64,
+            dropped_events_total: self.dropped_events_total.load(Ordering::Relaxed),
+            overflow_disconnects_total: self.overflow_disconnects_total.load(Ordering::Relaxed),
+            rejected_streams_total: self.rejected_streams_total.load(Ordering::Relaxed),
+            sink: self.outbox.lock().unwrap().as_ref().map(|o| o.stats()),
+            ..Default::default()
+        };
+        for queue in subs.iter().filter(|q| q.stream) {
+            let state = queue.state.lock().unwrap();
+            if state.closed {
+                continue;
+            }
+            stats.active_streams += 1;
+            stats.queued_events += state.events.len() as u64;
+            if state.events.len() * 2 >= queue.capacity {
+                stats.slow_consumers += 1;
+            }
+        }
+        stats
+    }
+
+    /// Approximate bytes held by undelivered events in every subscriber
+    /// queue: each event's inline size plus its JSON encoding as a proxy for
+    /// the strings it owns.
+    pub fn queued_bytes(&self) -> u64 {
+        let subs = self.subscribers.lock().unwrap();
+        subs.iter()
+            .map(|queue| {
+                let state = queue.state.lock().unwrap();
+                state
+                    .events
+                    .iter()
+                    .map(|event| {
+                        std::mem::size_of::<StoreEvent>()
+                            + serde_json::to_vec(event).map_or(0, |json| json.len())
+                    })
+                    .sum::<usize>() as u64
+            })
+            .sum()
+    }
 }
 
 impl Default for EventBus {
@@ -212,6 +871,12 @@ impl Default for EventBus {
     }
 }
 
+fn active_streams(subs: &[Arc<SubscriberQueue>]) -> usize {
+    subs.iter()
+        .filter(|q| q.stream && !q.state.lock().unwrap().closed)
+        .count()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -248,6 +913,7 @@ mod tests {
             session_id: "2".to_string(),
             client_tag: "tag".to_string(),
             created_at: 12345,
+            external_id: None,
         });
 
         assert!(sub1.recv_timeout(Duration::from_millis(100)).is_some());
@@ -288,4 +954,58 @@ mod tests {
         // Now the dead subscriber should be removed
         assert_eq!(bus.subscriber_count(), 0);
     }
+
+    fn connected(n: u32) -> StoreEvent {
+        StoreEvent::ClientConnected {
+            session_id: n.to_string(),
+            client_tag: "test".to_string(),
+        }
+    }
+
+    fn bounded(overflow: OverflowPolicy) -> EventBus {
+        EventBus::with_config(EventBusConfig {
+            queue_capacity: 2,
+            overflow,
+            max_streams: 1,
+        })
+    }
+
+    #[test]
+    fn test_stream_queue_drops_oldest() {
+        let bus = bounded(OverflowPolicy::DropOldest);
+        let sub = bus.subscribe_stream().unwrap();
+        for n in 1..=4 {
+            bus.publish(connected(n));
+        }
+
+        let stats = bus.stats();
+        assert_eq!(stats.active_streams, 1);
+        assert_eq!(stats.slow_consumers, 1);
+        assert_eq!(stats.dropped_events_total, 2);
+        assert_eq!(sub.take_dropped(), 2);
+        assert_eq!(sub.take_dropped(), 0);
+        match sub.try_recv() {
+            Some(StoreEvent::ClientConnected { session_id, .. }) => assert_eq!(session_id, "3"),
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_stream_disconnects_on_overflow_and_frees_slot() {
+        let bus = bounded(OverflowPolicy::Disconnect);
+        let sub = bus.subscribe_stream().unwrap();
+        assert!(bus.subscribe_stream().is_none());
+
+        for n in 1..=3 {
+            bus.publish(connected(n));
+        }
+        assert!(sub.is_closed());
+        assert!(sub.recv_timeout(Duration::from_millis(10)).is_none());
+
+        let stats = bus.stats();
+        assert_eq!(stats.active_streams, 0);
+        assert_eq!(stats.overflow_disconnects_total, 1);
+        assert_eq!(stats.rejected_streams_total, 1);
+        assert!(bus.subscribe_stream().is_some());
+    }
 }
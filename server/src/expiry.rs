@@ -0,0 +1,199 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Client-set expiry of contexts.
+//!
+//! A context can be created with a TTL, for agent runs that are explicitly
+//! throwaway. Its expiry time is reported as `expires_at` and can be queried
+//! with the CQL field `expires`. Once it passes, the expiry sweeper
+//! tombstones the context: its turns stay on disk, but reads and appends
+//! fail with `Expired` and it drops out of listings and searches. Contexts
+//! on legal hold are not tombstoned until the hold is released.
+//!
+//! Expiry times and tombstones are appended to a JSON-lines log that is
+//! replayed on open; the latest entry per context wins.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::events::{EventBus, StoreEvent};
+use crate::jsonl_log::open_log;
+use crate::store::Store;
+use crate::turn_store::CommitPipeline;
+use crate::util::unix_ms;
+
+const DEFAULT_SWEEP_SECS: u64 = 60;
+
+/// Longest accepted TTL: ten years.
+pub const MAX_TTL_SECS: u64 = 10 * 365 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExpiryConfig {
+    /// How often the sweeper looks for expired contexts.
+    pub sweep_interval: Duration,
+}
+
+impl ExpiryConfig {
+    pub fn from_env() -> Self {
+        let secs = std::env::var("CXDB_EXPIRY_SWEEP_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|&secs| secs > 0)
+            .unwrap_or(DEFAULT_SWEEP_SECS);
+        Self {
+            sweep_interval: Duration::from_secs(secs),
+        }
+    }
+}
+
+impl Default for ExpiryConfig {
+    fn default() -> Self {
+        Self {
+            sweep_interval: Duration::from_secs(DEFAULT_SWEEP_SECS),
+        }
+    }
+}
+
+/// Check that a TTL is between 1 second and [`MAX_TTL_SECS`].
+pub fn validate_ttl(ttl_secs: u64) -> Result<Duration> {
+    if ttl_secs == 0 || ttl_secs > MAX_TTL_SECS {
+        return Err(StoreError::InvalidInput(format!(
+            "invalid ttl_secs {ttl_secs}: use 1-{MAX_TTL_SECS}"
+        )));
+    }
+    Ok(Duration::from_secs(ttl_secs))
+}
+
+/// When a context expires, and when it was tombstoned once it did.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextExpiry {
+    pub context_id: u64,
+    pub expires_at_unix_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expired_at_unix_ms: Option<u64>,
+}
+
+impl ContextExpiry {
+    pub fn is_tombstoned(&self) -> bool {
+        self.expired_at_unix_ms.is_some()
+    }
+}
+
+pub struct Expiries {
+    file: File,
+    by_context: HashMap<u64, ContextExpiry>,
+}
+
+impl Expiries {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("expiries.jsonl");
+        let (file, entries) = open_log::<ContextExpiry>(&path)?;
+
+        let mut by_context = HashMap::new();
+        for entry in entries {
+            by_context.insert(entry.context_id, entry);
+        }
+
+        Ok(Self { file, by_context })
+    }
+
+    pub fn get(&self, context_id: u64) -> Option<&ContextExpiry> {
+        self.by_context.get(&context_id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ContextExpiry> {
+        self.by_context.values()
+    }
+
+    pub fn is_tombstoned(&self, context_id: u64) -> bool {
+        self.get(context_id)
+            .is_some_and(ContextExpiry::is_tombstoned)
+    }
+
+    /// Contexts whose expiry time is at or before `now_unix_ms` and that
+    /// are not tombstoned yet, soonest first.
+    pub fn due(&self, now_unix_ms: u64) -> Vec<ContextExpiry> {
+        let mut due: Vec<ContextExpiry> = self
+            .by_context
+            .values()
+            .filter(|e| !e.is_tombstoned() && e.expires_at_unix_ms <= now_unix_ms)
+            .cloned()
+            .collect();
+        due.sort_by_key(|e| (e.expires_at_unix_ms, e.context_id));
+        due
+    }
+
+    /// Sync the log with every group commit batch.
+    pub fn track_files(&self, pipeline: &CommitPipeline) -> Result<()> {
+        pipeline.track("meta/expiries.jsonl", &self.file)
+    }
+
+    /// Set when a context expires.
+    pub fn set(&mut self, context_id: u64, expires_at_unix_ms: u64) -> Result<ContextExpiry> {
+        self.record(ContextExpiry {
+            context_id,
+            expires_at_unix_ms,
+            expired_at_unix_ms: None,
+        })
+    }
+
+    /// Tombstone a context whose expiry time has passed.
+    pub fn tombstone(&mut self, context_id: u64, now_unix_ms: u64) -> Result<ContextExpiry> {
+        let expiry = self
+            .get(context_id)
+            .cloned()
+            .ok_or_else(|| StoreError::NotFound(format!("context {context_id} has no expiry")))?;
+        self.record(ContextExpiry {
+            expired_at_unix_ms: Some(now_unix_ms),
+            ..expiry
+        })
+    }
+
+    fn record(&mut self, entry: ContextExpiry) -> Result<ContextExpiry> {
+        let mut line = serde_json::to_vec(&entry)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.by_context.insert(entry.context_id, entry.clone());
+        Ok(entry)
+    }
+}
+
+/// Tombstone expired contexts every `config.sweep_interval` and publish a
+/// `context_expired` event for each.
+pub fn start_expiry_sweeper(
+    store: Arc<Mutex<Store>>,
+    event_bus: Arc<EventBus>,
+    config: ExpiryConfig,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(config.sweep_interval);
+        let expired = store.lock().unwrap().expire_due(unix_ms());
+        match expired {
+            Ok(expired) => {
+                for expiry in expired {
+                    event_bus.publish(expired_event(&expiry));
+                }
+            }
+            Err(err) => eprintln!("expiry sweep failed: {err}"),
+        }
+    })
+}
+
+pub fn expired_event(expiry: &ContextExpiry) -> StoreEvent {
+    StoreEvent::ContextExpired {
+        context_id: expiry.context_id.to_string(),
+        expires_at: expiry.expires_at_unix_ms,
+        expired_at: expiry.expired_at_unix_ms.unwrap_or_default(),
+    }
+}
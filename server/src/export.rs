@@ -0,0 +1,212 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Analytical export of context metadata.
+//!
+//! One row per context, as CSV or Parquet. CSV rows are encoded as the
+//! response body is read, so large exports are streamed; Parquet is written
+//! by an in-process arrow writer into memory, a row group per
+//! [`PARQUET_BATCH_ROWS`] contexts, without intermediate files.
+
+use std::io::{self, Read};
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, RecordBatch, StringArray, UInt32Array, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+
+use crate::error::{Result, StoreError};
+
+/// Contexts per Parquet row group.
+pub const PARQUET_BATCH_ROWS: usize = 8192;
+
+const COLUMNS: &[&str] = &[
+    "context_id",
+    "client_tag",
+    "title",
+    "user",
+    "service",
+    "host",
+    "created_at_unix_ms",
+    "head_turn_id",
+    "head_depth",
+    "turns",
+    "payload_bytes",
+    "tokens",
+];
+
+/// Metadata of one exported context.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportRow {
+    pub context_id: u64,
+    pub client_tag: Option<String>,
+    pub title: Option<String>,
+    /// User the context was written on behalf of.
+    pub user: Option<String>,
+    pub service: Option<String>,
+    pub host: Option<String>,
+    pub created_at_unix_ms: u64,
+    pub head_turn_id: u64,
+    pub head_depth: u32,
+    /// Turns along the head chain, shared history included.
+    pub turns: u64,
+    /// Uncompressed payload bytes along the head chain.
+    pub payload_bytes: u64,
+    pub tokens: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "parquet" => Ok(Self::Parquet),
+            other => Err(StoreError::InvalidInput(format!(
+                "unknown export format: {other} (expected csv or parquet)"
+            ))),
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Parquet => "parquet",
+        }
+    }
+}
+
+/// CSV body that encodes one row at a time as it is read.
+pub struct CsvStream {
+    rows: std::vec::IntoIter<ExportRow>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl CsvStream {
+    pub fn new(rows: Vec<ExportRow>) -> Self {
+        let mut stream = Self {
+            rows: rows.into_iter(),
+            buf: Vec::new(),
+            pos: 0,
+        };
+        stream.encode(COLUMNS.iter().map(|c| c.to_string()));
+        stream
+    }
+
+    fn encode(&mut self, record: impl Iterator<Item = String>) {
+        let mut writer = csv::Writer::from_writer(std::mem::take(&mut self.buf));
+        // Writing to a Vec cannot fail.
+        let _ = writer.write_record(record);
+        self.buf = writer.into_inner().unwrap_or_default();
+    }
+}
+
+impl Read for CsvStream {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buf.len() {
+            let Some(row) = self.rows.next() else {
+                return Ok(0);
+            };
+            self.buf.clear();
+            self.pos = 0;
+            self.encode(csv_record(row).into_iter());
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+fn csv_record(row: ExportRow) -> [String; 12] {
+    [
+        row.context_id.to_string(),
+        row.client_tag.unwrap_or_default(),
+        row.title.unwrap_or_default(),
+        row.user.unwrap_or_default(),
+        row.service.unwrap_or_default(),
+        row.host.unwrap_or_default(),
+        row.created_at_unix_ms.to_string(),
+        row.head_turn_id.to_string(),
+        row.head_depth.to_string(),
+        row.turns.to_string(),
+        row.payload_bytes.to_string(),
+        row.tokens.to_string(),
+    ]
+}
+
+fn parquet_schema() -> Schema {
+    let nullable = |name: &str| Field::new(name, DataType::Utf8, true);
+    let count = |name: &str| Field::new(name, DataType::UInt64, false);
+    Schema::new(vec![
+        count("context_id"),
+        nullable("client_tag"),
+        nullable("title"),
+        nullable("user"),
+        nullable("service"),
+        nullable("host"),
+        count("created_at_unix_ms"),
+        count("head_turn_id"),
+        Field::new("head_depth", DataType::UInt32, false),
+        count("turns"),
+        count("payload_bytes"),
+        count("tokens"),
+    ])
+}
+
+fn record_batch(schema: &Arc<Schema>, rows: &[ExportRow]) -> Result<RecordBatch> {
+    let strings = |f: fn(&ExportRow) -> Option<&str>| -> ArrayRef {
+        Arc::new(rows.iter().map(f).collect::<StringArray>())
+    };
+    let counts = |f: fn(&ExportRow) -> u64| -> ArrayRef {
+        Arc::new(rows.iter().map(f).collect::<UInt64Array>())
+    };
+    let columns = vec![
+        counts(|r| r.context_id),
+        strings(|r| r.client_tag.as_deref()),
+        strings(|r| r.title.as_deref()),
+        strings(|r| r.user.as_deref()),
+        strings(|r| r.service.as_deref()),
+        strings(|r| r.host.as_deref()),
+        counts(|r| r.created_at_unix_ms),
+        counts(|r| r.head_turn_id),
+        Arc::new(rows.iter().map(|r| r.head_depth).collect::<UInt32Array>()) as ArrayRef,
+        counts(|r| r.turns),
+        counts(|r| r.payload_bytes),
+        counts(|r| r.tokens),
+    ];
+    RecordBatch::try_new(schema.clone(), columns)
+        .map_err(|e| StoreError::InvalidInput(format!("arrow batch error: {e}")))
+}
+
+/// Encode rows as a Parquet file.
+pub fn write_parquet(rows: &[ExportRow]) -> Result<Vec<u8>> {
+    let parquet_err = |e: parquet::errors::ParquetError| {
+        StoreError::InvalidInput(format!("parquet encode error: {e}"))
+    };
+    let schema = Arc::new(parquet_schema());
+    let props = WriterProperties::builder()
+        .set_max_row_group_size(PARQUET_BATCH_ROWS)
+        .build();
+    let mut writer =
+        ArrowWriter::try_new(Vec::new(), schema.clone(), Some(props)).map_err(parquet_err)?;
+    for chunk in rows.chunks(PARQUET_BATCH_ROWS) {
+        writer
+            .write(&record_batch(&schema, chunk)?)
+            .map_err(parquet_err)?;
+    }
+    writer.into_inner().map_err(parquet_err)
+}
@@ -0,0 +1,135 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Caller-assigned external ids for contexts.
+//!
+//! A context can be created with an external id, such as the ULID or UUID an
+//! orchestrator already gave the run, and looked up by it later. Each id
+//! names at most one context and is fixed once assigned. Assignments are
+//! appended to a JSON-lines log that is replayed on open.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::jsonl_log::open_log;
+use crate::turn_store::CommitPipeline;
+use crate::util::unix_ms;
+
+/// Longest accepted external id.
+pub const MAX_EXTERNAL_ID_LEN: usize = 128;
+
+/// One line of the external ids log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ExternalIdEntry {
+    context_id: u64,
+    external_id: String,
+    at_unix_ms: u64,
+}
+
+/// Check that an external id is 1 to 128 ASCII letters, digits, `.`, `_`,
+/// `-` or `:`. ULIDs, UUIDs and most URNs fit; ids are compared exactly.
+pub fn validate_external_id(id: &str) -> Result<()> {
+    let valid = !id.is_empty()
+        && id.len() <= MAX_EXTERNAL_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-' | b':'));
+    if valid {
+        Ok(())
+    } else {
+        Err(StoreError::InvalidInput(format!(
+            "invalid external_id {id:?}: use 1-{MAX_EXTERNAL_ID_LEN} letters, digits, '.', '_', '-' or ':'"
+        )))
+    }
+}
+
+pub struct ExternalIds {
+    file: File,
+    by_external_id: HashMap<String, u64>,
+    by_context: HashMap<u64, String>,
+}
+
+impl ExternalIds {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("external_ids.jsonl");
+        let (file, entries) = open_log::<ExternalIdEntry>(&path)?;
+
+        let mut ids = Self {
+            file,
+            by_external_id: HashMap::new(),
+            by_context: HashMap::new(),
+        };
+        for entry in entries {
+            ids.apply(entry);
+        }
+        Ok(ids)
+    }
+
+    fn apply(&mut self, entry: ExternalIdEntry) {
+        self.by_external_id
+            .insert(entry.external_id.clone(), entry.context_id);
+        self.by_context.insert(entry.context_id, entry.external_id);
+    }
+
+    /// Context with an external id.
+    pub fn context(&self, external_id: &str) -> Option<u64> {
+        self.by_external_id.get(external_id).copied()
+    }
+
+    /// External id of a context.
+    pub fn of_context(&self, context_id: u64) -> Option<&str> {
+        self.by_context.get(&context_id).map(String::as_str)
+    }
+
+    /// Every assignment as (context id, external id).
+    pub fn all(&self) -> impl Iterator<Item = (u64, &str)> + '_ {
+        self.by_context
+            .iter()
+            .map(|(&context_id, id)| (context_id, id.as_str()))
+    }
+
+    /// Fail unless `external_id` is valid and names no context yet.
+    pub fn check_available(&self, external_id: &str) -> Result<()> {
+        validate_external_id(external_id)?;
+        match self.context(external_id) {
+            Some(context_id) => Err(StoreError::InvalidInput(format!(
+                "external_id {external_id:?} already names context {context_id}"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Sync the log with every group commit batch.
+    pub fn track_files(&self, pipeline: &CommitPipeline) -> Result<()> {
+        pipeline.track("meta/external_ids.jsonl", &self.file)
+    }
+
+    /// Give a context an external id. The caller checks that the context
+    /// exists.
+    pub fn assign(&mut self, context_id: u64, external_id: &str) -> Result<()> {
+        self.check_available(external_id)?;
+        if let Some(existing) = self.of_context(context_id) {
+            return Err(StoreError::InvalidInput(format!(
+                "context {context_id} already has external_id {existing:?}"
+            )));
+        }
+        let entry = ExternalIdEntry {
+            context_id,
+            external_id: external_id.to_string(),
+            at_unix_ms: unix_ms(),
+        };
+        let mut line = serde_json::to_vec(&entry)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.apply(entry);
+        Ok(())
+    }
+}
@@ -0,0 +1,494 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Read-only federation across cxdb servers.
+//!
+//! With peers configured in `CXDB_FEDERATION_PEERS`, a server answers
+//! `GET /v1/federated/contexts/search` by running the CQL query locally and
+//! on every peer in parallel, and merges the results with the name of the
+//! server each context lives on. `GET /v1/federated/:origin/contexts/:id`
+//! and `.../turns` proxy to that server. Data is never copied between
+//! servers; proxied responses are only cached in memory for
+//! `CXDB_FEDERATION_CACHE_TTL_SECS`. A peer that fails or times out is
+//! reported next to the results of the others rather than failing the
+//! search.
+//!
+//! Every request to a peer names the residencies this server may hold
+//! (`CXDB_FEDERATION_RESIDENCY`) in the `X-Cxdb-Federation-Residency`
+//! header. The peer leaves contexts labeled with any other residency out of
+//! search results and answers requests for them with 403, so labeled data
+//! only crosses to servers allowed to hold it. Unlabeled contexts are served
+//! to every peer.
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use url::Url;
+
+use crate::error::{Result, StoreError};
+use crate::residency::parse_residencies;
+
+/// Name of this server in federated results, unless `CXDB_FEDERATION_NAME`
+/// is set.
+pub const DEFAULT_LOCAL_NAME: &str = "local";
+
+/// Request header naming the residencies the requesting server may hold.
+pub const RESIDENCY_HEADER: &str = "X-Cxdb-Federation-Residency";
+
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(30);
+const DEFAULT_CACHE_ENTRIES: usize = 1024;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Larger proxied responses are refused.
+const MAX_RESPONSE_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FederationPeer {
+    pub name: String,
+    /// Base URL, ending in `/`.
+    pub url: Url,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FederationConfig {
+    /// Name of this server in federated results.
+    pub name: String,
+    pub peers: Vec<FederationPeer>,
+    /// Bearer token sent to peers, for servers that require one.
+    pub token: Option<String>,
+    /// How long a proxied response is served from memory.
+    pub cache_ttl: Duration,
+    /// Proxied responses kept; 0 disables the cache.
+    pub cache_entries: usize,
+    /// Timeout of one request to a peer.
+    pub timeout: Duration,
+    /// Residencies this server may hold; peers serve it no others.
+    pub residencies: BTreeSet<String>,
+}
+
+impl FederationConfig {
+    /// Load config from environment variables. Returns None unless
+    /// `CXDB_FEDERATION_PEERS` lists at least one peer as `name=url`.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let Some(peers) = var("CXDB_FEDERATION_PEERS") else {
+            return Ok(None);
+        };
+        let name = var("CXDB_FEDERATION_NAME").unwrap_or_else(|| DEFAULT_LOCAL_NAME.to_string());
+        let peers = parse_peers(&peers)?;
+        if peers.is_empty() {
+            return Ok(None);
+        }
+        let number = |var_name: &str, default: u64| -> Result<u64> {
+            match var(var_name) {
+                Some(v) => v
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput(format!("invalid {var_name} {v:?}"))),
+                None => Ok(default),
+            }
+        };
+        let config = Self {
+            name,
+            peers,
+            token: var("CXDB_FEDERATION_TOKEN"),
+            cache_ttl: Duration::from_secs(number(
+                "CXDB_FEDERATION_CACHE_TTL_SECS",
+                DEFAULT_CACHE_TTL.as_secs(),
+            )?),
+            cache_entries: number(
+                "CXDB_FEDERATION_CACHE_ENTRIES",
+                DEFAULT_CACHE_ENTRIES as u64,
+            )? as usize,
+            timeout: Duration::from_millis(number(
+                "CXDB_FEDERATION_TIMEOUT_MS",
+                DEFAULT_TIMEOUT.as_millis() as u64,
+            )?),
+            residencies: parse_residencies(&var("CXDB_FEDERATION_RESIDENCY").unwrap_or_default())?,
+        };
+        config.validate()?;
+        Ok(Some(config))
+    }
+
+    /// A config with default cache and timeout settings.
+    pub fn new(name: &str, peers: Vec<FederationPeer>) -> Self {
+        Self {
+            name: name.to_string(),
+            peers,
+            token: None,
+            cache_ttl: DEFAULT_CACHE_TTL,
+            cache_entries: DEFAULT_CACHE_ENTRIES,
+            timeout: DEFAULT_TIMEOUT,
+            residencies: BTreeSet::new(),
+        }
+    }
+
+    /// Names must be usable as path segments and unique, this server's
+    /// included.
+    pub fn validate(&self) -> Result<()> {
+        let mut seen = vec![self.name.as_str()];
+        check_name(&self.name)?;
+        for peer in &self.peers {
+            check_name(&peer.name)?;
+            if seen.contains(&peer.name.as_str()) {
+                return Err(StoreError::InvalidInput(format!(
+                    "federation peer name {:?} is used twice",
+                    peer.name
+                )));
+            }
+            seen.push(&peer.name);
+        }
+        Ok(())
+    }
+}
+
+impl FederationPeer {
+    pub fn new(name: &str, url: &str) -> Result<Self> {
+        let mut url = Url::parse(url)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .ok_or_else(|| {
+                StoreError::InvalidInput(format!("invalid federation peer url {url:?}"))
+            })?;
+        url.set_query(None);
+        url.set_fragment(None);
+        if !url.path().ends_with('/') {
+            let path = format!("{}/", url.path());
+            url.set_path(&path);
+        }
+        Ok(Self {
+            name: name.to_string(),
+            url,
+        })
+    }
+}
+
+/// Parse `name=url,name=url`.
+fn parse_peers(raw: &str) -> Result<Vec<FederationPeer>> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|peer| {
+            let (name, url) = peer.split_once('=').ok_or_else(|| {
+                StoreError::InvalidInput(format!(
+                    "invalid CXDB_FEDERATION_PEERS entry {peer:?} (expected name=url)"
+                ))
+            })?;
+            FederationPeer::new(name.trim(), url.trim())
+        })
+        .collect()
+}
+
+fn check_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(StoreError::InvalidInput(format!(
+            "invalid federation name {name:?}: use letters, digits, - and _"
+        )))
+    }
+}
+
+/// One server's share of a federated search.
+#[derive(Debug, Clone, Serialize)]
+pub struct OriginSearch {
+    pub origin: String,
+    pub ok: bool,
+    /// Contexts returned, before the merged limit.
+    pub count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub elapsed_ms: u64,
+    /// Contexts as the origin listed them.
+    #[serde(skip)]
+    pub contexts: Vec<JsonValue>,
+}
+
+impl OriginSearch {
+    /// Results of a search that succeeded.
+    pub fn found(origin: &str, response: &JsonValue, elapsed: Duration) -> Self {
+        let contexts = response["contexts"].as_array().cloned().unwrap_or_default();
+        Self {
+            origin: origin.to_string(),
+            ok: true,
+            count: contexts.len(),
+            total_count: response["total_count"].as_u64(),
+            error: None,
+            elapsed_ms: elapsed.as_millis() as u64,
+            contexts,
+        }
+    }
+
+    fn failed(origin: &str, error: String, elapsed: Duration) -> Self {
+        Self {
+            origin: origin.to_string(),
+            ok: false,
+            count: 0,
+            total_count: None,
+            error: Some(error),
+            elapsed_ms: elapsed.as_millis() as u64,
+            contexts: Vec::new(),
+        }
+    }
+}
+
+/// A response proxied from a peer.
+#[derive(Debug, Clone)]
+pub struct ProxiedResponse {
+    pub status: u16,
+    pub content_type: String,
+    pub body: Arc<Vec<u8>>,
+    /// Served from the cache.
+    pub cached: bool,
+}
+
+/// Proxy cache accounting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct FederationCacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Default)]
+struct ProxyCache {
+    entries: HashMap<String, (Instant, ProxiedResponse)>,
+    /// Keys oldest first, for eviction.
+    order: VecDeque<String>,
+    hits: u64,
+    misses: u64,
+}
+
+pub struct Federation {
+    config: FederationConfig,
+    agent: ureq::Agent,
+    cache: Mutex<ProxyCache>,
+}
+
+impl Federation {
+    pub fn new(config: FederationConfig) -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new()
+                .timeout(config.timeout)
+                .redirects(0)
+                .build(),
+            config,
+            cache: Mutex::new(ProxyCache::default()),
+        }
+    }
+
+    pub fn config(&self) -> &FederationConfig {
+        &self.config
+    }
+
+    /// Name of this server in federated results.
+    pub fn local_name(&self) -> &str {
+        &self.config.name
+    }
+
+    pub fn peer(&self, name: &str) -> Result<&FederationPeer> {
+        self.config
+            .peers
+            .iter()
+            .find(|peer| peer.name == name)
+            .ok_or_else(|| StoreError::NotFound(format!("federation peer {name:?}")))
+    }
+
+    /// Run a CQL search on every peer in parallel.
+    pub fn search_peers(&self, query: &str, limit: Option<u32>) -> Vec<OriginSearch> {
+        std::thread::scope(|scope| {
+            let searches: Vec<_> = self
+                .config
+                .peers
+                .iter()
+                .map(|peer| scope.spawn(move || self.search_peer(peer, query, limit)))
+                .collect();
+            searches
+                .into_iter()
+                .zip(&self.config.peers)
+                .map(|(search, peer)| {
+                    search.join().unwrap_or_else(|_| {
+                        OriginSearch::failed(&peer.name, "search panicked".into(), Duration::ZERO)
+                    })
+                })
+                .collect()
+        })
+    }
+
+    fn search_peer(&self, peer: &FederationPeer, query: &str, limit: Option<u32>) -> OriginSearch {
+        let start = Instant::now();
+        let result = peer
+            .url
+            .join("v1/contexts/search")
+            .map_err(|e| e.to_string())
+            .and_then(|url| {
+                let mut request = self
+                    .authorized(self.agent.get(url.as_str()))
+                    .query("q", query);
+                if let Some(limit) = limit {
+                    request = request.query("limit", &limit.to_string());
+                }
+                request
+                    .call()
+                    .map_err(peer_error)?
+                    .into_json::<JsonValue>()
+                    .map_err(|e| format!("invalid search response: {e}"))
+            });
+        match result {
+            Ok(response) => OriginSearch::found(&peer.name, &response, start.elapsed()),
+            Err(error) => {
+                tracing::warn!(peer = %peer.name, error = %error, "federated search failed");
+                OriginSearch::failed(&peer.name, error, start.elapsed())
+            }
+        }
+    }
+
+    /// `GET` `path_and_query` (relative, without a leading `/`) on a peer,
+    /// served from the cache while fresh. Only successful responses are
+    /// cached.
+    pub fn fetch(&self, peer_name: &str, path_and_query: &str) -> Result<ProxiedResponse> {
+        let peer = self.peer(peer_name)?;
+        let key = format!("{peer_name} {path_and_query}");
+        {
+            let mut cache = self.cache.lock().unwrap();
+            let fresh = cache
+                .entries
+                .get(&key)
+                .filter(|(at, _)| at.elapsed() < self.config.cache_ttl)
+                .map(|(_, response)| response.clone());
+            if let Some(mut response) = fresh {
+                cache.hits += 1;
+                response.cached = true;
+                return Ok(response);
+            }
+            cache.misses += 1;
+        }
+
+        let url = peer.url.join(path_and_query).map_err(|e| {
+            StoreError::InvalidInput(format!("invalid federated path {path_and_query:?}: {e}"))
+        })?;
+        let response = match self.authorized(self.agent.get(url.as_str())).call() {
+            Ok(response) => response,
+            // Errors from the peer are passed on as they are.
+            Err(ureq::Error::Status(_, response)) => response,
+            Err(e) => {
+                return Err(StoreError::Io(std::io::Error::other(format!(
+                    "federation peer {peer_name}: {e}"
+                ))))
+            }
+        };
+        let status = response.status();
+        let content_type = response.content_type().to_string();
+        let mut body = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_RESPONSE_BYTES + 1)
+            .read_to_end(&mut body)?;
+        if body.len() as u64 > MAX_RESPONSE_BYTES {
+            return Err(StoreError::InvalidInput(format!(
+                "response of federation peer {peer_name} is larger than {MAX_RESPONSE_BYTES} bytes"
+            )));
+        }
+        let proxied = ProxiedResponse {
+            status,
+            content_type,
+            body: Arc::new(body),
+            cached: false,
+        };
+        if status == 200 {
+            self.remember(key, proxied.clone());
+        }
+        Ok(proxied)
+    }
+
+    fn remember(&self, key: String, response: ProxiedResponse) {
+        if self.config.cache_entries == 0 {
+            return;
+        }
+        let mut cache = self.cache.lock().unwrap();
+        if cache
+            .entries
+            .insert(key.clone(), (Instant::now(), response))
+            .is_some()
+        {
+            cache.order.retain(|k| k != &key);
+        }
+        cache.order.push_back(key);
+        while cache.entries.len() > self.config.cache_entries {
+            let Some(oldest) = cache.order.pop_front() else {
+                break;
+            };
+            cache.entries.remove(&oldest);
+        }
+    }
+
+    pub fn cache_stats(&self) -> FederationCacheStats {
+        let cache = self.cache.lock().unwrap();
+        FederationCacheStats {
+            entries: cache.entries.len(),
+            hits: cache.hits,
+            misses: cache.misses,
+        }
+    }
+
+    /// Add the bearer token, if any, and the residencies this server may
+    /// hold.
+    fn authorized(&self, request: ureq::Request) -> ureq::Request {
+        let residencies: Vec<&str> = self.config.residencies.iter().map(String::as_str).collect();
+        let request = request.set(RESIDENCY_HEADER, &residencies.join(","));
+        match &self.config.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {token}")),
+            None => request,
+        }
+    }
+}
+
+/// Merge per-origin results newest context first, annotating each context
+/// with its origin, and keep the first `limit`.
+pub fn merge_results(searches: &[OriginSearch], limit: Option<u32>) -> Vec<JsonValue> {
+    let mut merged: Vec<JsonValue> = searches
+        .iter()
+        .flat_map(|search| {
+            search.contexts.iter().map(|context| {
+                let mut context = context.clone();
+                context["origin"] = JsonValue::String(search.origin.clone());
+                context
+            })
+        })
+        .collect();
+    merged.sort_by_key(|context| {
+        std::cmp::Reverse(context["created_at_unix_ms"].as_u64().unwrap_or(0))
+    });
+    if let Some(limit) = limit {
+        merged.truncate(limit as usize);
+    }
+    merged
+}
+
+/// Error text of a failed peer request. Search errors carry a string
+/// `error`; other cxdb errors an `error.message`.
+fn peer_error(err: ureq::Error) -> String {
+    match err {
+        ureq::Error::Status(status, response) => {
+            let body = response.into_json::<JsonValue>().ok();
+            body.as_ref()
+                .and_then(|body| {
+                    body["error"]
+                        .as_str()
+                        .or_else(|| body["error"]["message"].as_str())
+                })
+                .map(|message| format!("{status}: {message}"))
+                .unwrap_or_else(|| format!("peer answered {status}"))
+        }
+        other => other.to_string(),
+    }
+}
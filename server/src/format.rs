@@ -0,0 +1,267 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Store format versions and migrations.
+//!
+//! Every data directory carries a superblock, `format.json`, naming the
+//! format version its files are written in. [`prepare`] runs before any
+//! store file is opened: a new directory is stamped with [`FORMAT_VERSION`];
+//! an older one is upgraded one [`Migration`] at a time, each step backing
+//! up the files it rewrites under `backups/` and restamping the superblock
+//! when it completes, so an interrupted upgrade resumes at the step that
+//! failed. A directory written by a newer server is refused untouched.
+//!
+//! Directories from before the superblock existed are format version 1.
+//!
+//! The superblock is the only version stamp. Store files are only read
+//! through a data directory that was prepared here, and S3 sync uploads
+//! and restores `format.json` together with them, so a header on every
+//! file would repeat what the superblock already says and have to be
+//! kept in step with it. Headers that predate the superblock (`turns.idx`,
+//! blob records) stay as consistency checks.
+//!
+//! One process at a time may open a data directory: [`DataDirLock`] holds
+//! an exclusive lock on its `LOCK` file for as long as the store (or an
+//! admin command working on the files directly) has it open.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::turn_store::{self, TurnStore};
+use crate::util::unix_ms;
+
+/// Format version this server reads and writes.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Superblock file name, relative to the data directory.
+pub const SUPERBLOCK_FILE: &str = "format.json";
+
+/// Lock file, relative to the data directory.
+pub const LOCK_FILE: &str = "LOCK";
+
+/// Directory, relative to the data directory, holding pre-migration copies.
+pub const BACKUP_DIR: &str = "backups";
+
+const MAGIC: &str = "cxdb-store";
+
+/// Format version of data directories without a superblock.
+const UNSTAMPED_VERSION: u32 = 1;
+
+/// Entries whose presence marks a directory as holding store data.
+const STORE_ENTRIES: &[&str] = &["blobs", "turns", "fs", "meta", "keys"];
+
+/// Contents of `format.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Superblock {
+    pub magic: String,
+    pub format_version: u32,
+    pub created_at_unix_ms: u64,
+    /// Migrations applied to this directory, oldest first.
+    #[serde(default)]
+    pub migrations: Vec<AppliedMigration>,
+}
+
+/// A migration step that ran against a data directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppliedMigration {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub name: String,
+    /// Backup of the rewritten files, relative to the data directory.
+    pub backup: String,
+    pub at_unix_ms: u64,
+}
+
+/// One step from `from_version` to the next version.
+pub struct Migration {
+    pub from_version: u32,
+    pub name: &'static str,
+    /// Files the step rewrites, relative to the data directory. They are
+    /// copied to a backup before it runs.
+    pub files: &'static [&'static str],
+    run: fn(&Path) -> Result<()>,
+}
+
+/// Every migration, in version order.
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    from_version: 1,
+    name: "turns-index-header",
+    files: &["turns/turns.idx"],
+    run: migrate_turns_index,
+}];
+
+/// Exclusive lock on a data directory, released when dropped or when the
+/// process exits.
+#[derive(Debug)]
+pub struct DataDirLock {
+    _file: File,
+}
+
+impl DataDirLock {
+    /// Lock the data directory at `dir`, creating it if needed. Fails
+    /// without waiting when another process holds the lock.
+    pub fn acquire(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join(LOCK_FILE))?;
+        match file.try_lock() {
+            Ok(()) => Ok(Self { _file: file }),
+            Err(fs::TryLockError::WouldBlock) => Err(StoreError::Io(std::io::Error::new(
+                std::io::ErrorKind::ResourceBusy,
+                format!(
+                    "data directory {} is in use by another cxdb process",
+                    dir.display()
+                ),
+            ))),
+            Err(fs::TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+}
+
+/// Stamp, upgrade or refuse the data directory at `dir` before it is
+/// opened. Returns its superblock at [`FORMAT_VERSION`].
+pub fn prepare(dir: &Path) -> Result<Superblock> {
+    fs::create_dir_all(dir)?;
+    let mut superblock = match read_superblock(dir)? {
+        Some(superblock) => superblock,
+        None if holds_store_data(dir) => Superblock::new(UNSTAMPED_VERSION),
+        None => {
+            let superblock = Superblock::new(FORMAT_VERSION);
+            write_superblock(dir, &superblock)?;
+            return Ok(superblock);
+        }
+    };
+
+    if superblock.format_version > FORMAT_VERSION {
+        return Err(StoreError::UnsupportedFormat {
+            path: dir.display().to_string(),
+            found: superblock.format_version,
+            supported: FORMAT_VERSION,
+        });
+    }
+    let pending: Vec<&Migration> = MIGRATIONS
+        .iter()
+        .filter(|m| m.from_version >= superblock.format_version)
+        .collect();
+    for (step, migration) in pending.iter().enumerate() {
+        eprintln!(
+            "store format: migration {}/{} {} (version {} to {})",
+            step + 1,
+            pending.len(),
+            migration.name,
+            migration.from_version,
+            migration.from_version + 1
+        );
+        let backup = backup_files(dir, migration)?;
+        (migration.run)(dir).map_err(|e| {
+            eprintln!(
+                "store format: migration {} failed: {e}; pre-migration files are in {backup}",
+                migration.name
+            );
+            e
+        })?;
+        superblock.format_version = migration.from_version + 1;
+        superblock.migrations.push(AppliedMigration {
+            from_version: migration.from_version,
+            to_version: superblock.format_version,
+            name: migration.name.to_string(),
+            backup,
+            at_unix_ms: unix_ms(),
+        });
+        write_superblock(dir, &superblock)?;
+    }
+    if !pending.is_empty() {
+        eprintln!("store format: upgraded to version {FORMAT_VERSION}");
+    }
+    Ok(superblock)
+}
+
+/// The superblock of `dir`, if it has one.
+pub fn read_superblock(dir: &Path) -> Result<Option<Superblock>> {
+    let path = dir.join(SUPERBLOCK_FILE);
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let superblock: Superblock = serde_json::from_slice(&bytes)
+        .map_err(|e| StoreError::Corrupt(format!("invalid {}: {e}", path.display())))?;
+    if superblock.magic != MAGIC {
+        return Err(StoreError::Corrupt(format!(
+            "{} is not a cxdb superblock",
+            path.display()
+        )));
+    }
+    Ok(Some(superblock))
+}
+
+impl Superblock {
+    fn new(format_version: u32) -> Self {
+        Self {
+            magic: MAGIC.to_string(),
+            format_version,
+            created_at_unix_ms: unix_ms(),
+            migrations: Vec::new(),
+        }
+    }
+}
+
+fn write_superblock(dir: &Path, superblock: &Superblock) -> Result<()> {
+    let bytes = serde_json::to_vec_pretty(superblock)
+        .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+    let path = dir.join(SUPERBLOCK_FILE);
+    let tmp = path.with_extension("json.tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(&bytes)?;
+    file.sync_all()?;
+    fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+fn holds_store_data(dir: &Path) -> bool {
+    STORE_ENTRIES.iter().any(|entry| dir.join(entry).exists())
+}
+
+/// Copy the files `migration` rewrites to a fresh backup directory and
+/// return its path relative to `dir`.
+fn backup_files(dir: &Path, migration: &Migration) -> Result<String> {
+    let backup = format!(
+        "{BACKUP_DIR}/format-v{}-{}",
+        migration.from_version,
+        unix_ms()
+    );
+    for file in migration.files {
+        let source = dir.join(file);
+        if !source.exists() {
+            continue;
+        }
+        let target = dir.join(&backup).join(file);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&source, &target)?;
+    }
+    Ok(backup)
+}
+
+/// Version 1 to 2: `turns.idx` gains a magic and version header and skip
+/// pointers. The index is derived from `turns.log`, and opening the turn
+/// store rewrites it in the current layout, so the step opens it and then
+/// checks that the rewritten file carries the new header.
+fn migrate_turns_index(dir: &Path) -> Result<()> {
+    let turns_dir = dir.join("turns");
+    drop(TurnStore::open(&turns_dir)?);
+    match turn_store::index_version(&turns_dir)? {
+        turn_store::INDEX_VERSION => Ok(()),
+        found => Err(StoreError::Corrupt(format!(
+            "turns.idx is still version {found} after migration"
+        ))),
+    }
+}
@@ -0,0 +1,346 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Snapshot manifests and local checkouts.
+//!
+//! A manifest lists every entry of a snapshot by path, sorted bytewise, so
+//! the same root always yields the same manifest. Its fetch plan names each
+//! distinct file content once, with every path that holds it and a URL to
+//! read it from; [`checkout`] fetches each blob once, verifies its hash and
+//! writes it to all of its paths.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+
+use super::{load_tree_entries, read_symlink_target, EntryKind};
+use crate::blob_store::BlobSource;
+use crate::deadline::Deadline;
+use crate::error::{Result, StoreError};
+
+/// Characters left unescaped in fetch URL path segments.
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// One entry of a snapshot manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Slash-separated path from the snapshot root.
+    pub path: String,
+    pub kind: ManifestKind,
+    /// POSIX permission bits, in octal.
+    pub mode: String,
+    /// File size; 0 for directories and symlinks.
+    pub size: u64,
+    /// Hex content hash for files, target hash for symlinks, tree hash for
+    /// directories.
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// A file content blob to fetch once and write to each of its paths.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FetchBlob {
+    /// Hex BLAKE3 hash of the content.
+    pub hash: String,
+    pub size: u64,
+    pub paths: Vec<String>,
+    /// Path and query of the file route serving the content.
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestTotals {
+    pub files: u64,
+    pub dirs: u64,
+    pub symlinks: u64,
+    /// Sum of file sizes.
+    pub bytes: u64,
+    pub unique_blobs: u64,
+    pub unique_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub turn_id: String,
+    pub fs_root_hash: String,
+    pub entries: Vec<ManifestEntry>,
+    /// Distinct file contents, ordered by hash.
+    pub fetch: Vec<FetchBlob>,
+    pub totals: ManifestTotals,
+}
+
+impl Manifest {
+    /// Manifest of a turn's snapshot. Fetch URLs point at the turn's file
+    /// route, `/v1/turns/{turn_id}/fs/{path}`, using the first path of each
+    /// blob.
+    pub fn new(turn_id: u64, root_hash: &[u8; 32], entries: Vec<ManifestEntry>) -> Self {
+        let mut totals = ManifestTotals::default();
+        let mut blobs: BTreeMap<&str, (u64, Vec<String>)> = BTreeMap::new();
+        for entry in &entries {
+            match entry.kind {
+                ManifestKind::Dir => totals.dirs += 1,
+                ManifestKind::Symlink => totals.symlinks += 1,
+                ManifestKind::File => {
+                    totals.files += 1;
+                    totals.bytes += entry.size;
+                    blobs
+                        .entry(entry.hash.as_str())
+                        .or_insert_with(|| (entry.size, Vec::new()))
+                        .1
+                        .push(entry.path.clone());
+                }
+            }
+        }
+        let fetch: Vec<FetchBlob> = blobs
+            .into_iter()
+            .map(|(hash, (size, paths))| FetchBlob {
+                hash: hash.to_string(),
+                size,
+                url: file_url(turn_id, &paths[0]),
+                paths,
+            })
+            .collect();
+        totals.unique_blobs = fetch.len() as u64;
+        totals.unique_bytes = fetch.iter().map(|b| b.size).sum();
+        Self {
+            turn_id: turn_id.to_string(),
+            fs_root_hash: hex::encode(root_hash),
+            entries,
+            fetch,
+            totals,
+        }
+    }
+}
+
+/// URL of a file's raw content, without following symlinks. Segments are
+/// percent-encoded; a top-level `manifest` also has its first letter
+/// encoded so it does not route to the manifest itself.
+fn file_url(turn_id: u64, path: &str) -> String {
+    let encoded: Vec<String> = path
+        .split('/')
+        .map(|segment| utf8_percent_encode(segment, UNRESERVED).to_string())
+        .collect();
+    let mut encoded = encoded.join("/");
+    if encoded == "manifest" {
+        encoded = "%6Danifest".into();
+    }
+    format!("/v1/turns/{turn_id}/fs/{encoded}?follow_symlinks=0")
+}
+
+/// Every entry of a snapshot, sorted by path. The deadline is checked
+/// before loading each tree.
+pub fn manifest_entries(
+    blob_store: &mut impl BlobSource,
+    root_hash: &[u8; 32],
+    deadline: &Deadline,
+) -> Result<Vec<ManifestEntry>> {
+    let mut entries = Vec::new();
+    let mut pending = vec![(String::new(), *root_hash)];
+    while let Some((dir, tree)) = pending.pop() {
+        deadline.check("fs manifest")?;
+        for entry in load_tree_entries(blob_store, &tree)? {
+            let path = if dir.is_empty() {
+                entry.name.clone()
+            } else {
+                format!("{dir}/{}", entry.name)
+            };
+            let hash = entry.hash_array()?;
+            let (kind, target) = match entry.kind_enum() {
+                EntryKind::Directory => {
+                    pending.push((path.clone(), hash));
+                    (ManifestKind::Dir, None)
+                }
+                EntryKind::File => (ManifestKind::File, None),
+                EntryKind::Symlink => (
+                    ManifestKind::Symlink,
+                    Some(read_symlink_target(blob_store, &entry)?),
+                ),
+            };
+            entries.push(ManifestEntry {
+                path,
+                kind,
+                mode: format!("{:o}", entry.mode),
+                size: if kind == ManifestKind::File {
+                    entry.size
+                } else {
+                    0
+                },
+                hash: hex::encode(&entry.hash),
+                target,
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Outcome of [`checkout`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CheckoutStats {
+    pub files: u64,
+    pub dirs: u64,
+    pub symlinks: u64,
+    pub bytes: u64,
+    pub fetched_blobs: u64,
+    pub fetched_bytes: u64,
+}
+
+/// Materialize a manifest into `dest`, which must be missing or empty.
+/// `fetch` returns a blob's content; each is checked against its hash and
+/// size before it is written. Paths that could leave `dest`, or whose parent
+/// is not a directory of the manifest, are refused, and directory modes are
+/// applied last so read-only directories can be filled.
+/// Symlinks are recreated as recorded, even when their targets point
+/// outside the tree.
+pub fn checkout(
+    manifest: &Manifest,
+    dest: &Path,
+    mut fetch: impl FnMut(&FetchBlob) -> Result<Vec<u8>>,
+) -> Result<CheckoutStats> {
+    if fs::read_dir(dest).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(StoreError::InvalidInput(format!(
+            "{} is not empty; check out into a new directory",
+            dest.display()
+        )));
+    }
+    let mut stats = CheckoutStats::default();
+    let mut dirs = Vec::new();
+    let mut created: HashSet<&str> = HashSet::new();
+    fs::create_dir_all(dest)?;
+    // Entries are sorted by path, so parents come before their children.
+    for entry in &manifest.entries {
+        let path = local_path(dest, &entry.path)?;
+        check_parent(&created, &entry.path)?;
+        match entry.kind {
+            ManifestKind::Dir => {
+                fs::create_dir(&path)?;
+                created.insert(&entry.path);
+                dirs.push((path, parse_mode(&entry.mode)?));
+                stats.dirs += 1;
+            }
+            ManifestKind::Symlink => {
+                let target = entry.target.as_deref().ok_or_else(|| {
+                    StoreError::InvalidInput(format!("symlink {} has no target", entry.path))
+                })?;
+                make_symlink(target, &path)?;
+                stats.symlinks += 1;
+            }
+            ManifestKind::File => {}
+        }
+    }
+
+    let modes: BTreeMap<&str, &str> = manifest
+        .entries
+        .iter()
+        .filter(|e| e.kind == ManifestKind::File)
+        .map(|e| (e.path.as_str(), e.mode.as_str()))
+        .collect();
+    for blob in &manifest.fetch {
+        let content = fetch(blob)?;
+        if content.len() as u64 != blob.size
+            || blake3::hash(&content).to_hex().as_str() != blob.hash
+        {
+            return Err(StoreError::Corrupt(format!(
+                "fetched content does not match blob {}",
+                blob.hash
+            )));
+        }
+        stats.fetched_blobs += 1;
+        stats.fetched_bytes += blob.size;
+        for path in &blob.paths {
+            let mode = modes.get(path.as_str()).ok_or_else(|| {
+                StoreError::InvalidInput(format!("fetch plan names unknown file {path}"))
+            })?;
+            let local = local_path(dest, path)?;
+            check_parent(&created, path)?;
+            // Never write through an existing entry, such as a symlink.
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&local)?;
+            file.write_all(&content)?;
+            set_mode(&local, parse_mode(mode)?)?;
+            stats.files += 1;
+            stats.bytes += blob.size;
+        }
+    }
+
+    // Children before parents, so a read-only parent does not block them.
+    for (path, mode) in dirs.iter().rev() {
+        set_mode(path, *mode)?;
+    }
+    Ok(stats)
+}
+
+/// `path` under `dest`, refused unless every component is a plain name.
+fn local_path(dest: &Path, path: &str) -> Result<PathBuf> {
+    let relative = Path::new(path);
+    let plain = !path.is_empty()
+        && !path.contains('\\')
+        && relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        && relative.components().count() == path.split('/').count();
+    if !plain {
+        return Err(StoreError::InvalidInput(format!(
+            "refusing to check out path {path:?}"
+        )));
+    }
+    Ok(dest.join(relative))
+}
+
+fn check_parent(created: &HashSet<&str>, path: &str) -> Result<()> {
+    match path.rsplit_once('/') {
+        Some((parent, _)) if !created.contains(parent) => Err(StoreError::InvalidInput(format!(
+            "parent of {path:?} is not a directory of the manifest"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn parse_mode(mode: &str) -> Result<u32> {
+    u32::from_str_radix(mode, 8)
+        .map_err(|_| StoreError::InvalidInput(format!("invalid mode {mode:?}")))
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn make_symlink(target: &str, path: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(target, path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn make_symlink(target: &str, path: &Path) -> Result<()> {
+    // Without symlink support the target is written as a plain file.
+    fs::write(path, target)?;
+    Ok(())
+}
@@ -0,0 +1,255 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Text/binary classification and language detection for snapshot files.
+//!
+//! The language is taken from well-known file names, then the extension,
+//! then a shebang line, then a few unambiguous content markers. Names follow
+//! the identifiers common syntax highlighters accept (`rust`, `python`,
+//! `shell`, ...). Binary files get no language or line count.
+
+/// How much of a file is scanned for NUL bytes when classifying it.
+const BINARY_SNIFF_BYTES: usize = 8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileInfo {
+    pub binary: bool,
+    pub language: Option<&'static str>,
+    /// Newline-terminated lines, plus an unterminated last line. None for
+    /// binary files.
+    pub line_count: Option<u64>,
+}
+
+/// Classify a file's content. `path` is only used for its file name.
+pub fn detect(path: &str, content: &[u8]) -> FileInfo {
+    if is_binary(content) {
+        return FileInfo {
+            binary: true,
+            language: None,
+            line_count: None,
+        };
+    }
+    FileInfo {
+        binary: false,
+        language: language_from_name(path).or_else(|| language_from_content(content)),
+        line_count: Some(line_count(content)),
+    }
+}
+
+/// A file is binary if its head contains a NUL byte, as git decides, or is
+/// not UTF-8 apart from a character cut off at the sniffed boundary.
+fn is_binary(content: &[u8]) -> bool {
+    let head = &content[..content.len().min(BINARY_SNIFF_BYTES)];
+    if head.contains(&0) {
+        return true;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => false,
+        // `error_len` is None when the input ends mid-character.
+        Err(e) => e.error_len().is_some() || head.len() == content.len(),
+    }
+}
+
+fn line_count(content: &[u8]) -> u64 {
+    let newlines = content.iter().filter(|&&b| b == b'\n').count() as u64;
+    match content.last() {
+        Some(b'\n') | None => newlines,
+        Some(_) => newlines + 1,
+    }
+}
+
+fn language_from_name(path: &str) -> Option<&'static str> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let language = match name {
+        "Makefile" | "makefile" | "GNUmakefile" => "makefile",
+        "Dockerfile" | "Containerfile" => "dockerfile",
+        "CMakeLists.txt" => "cmake",
+        "Gemfile" | "Rakefile" | "Vagrantfile" => "ruby",
+        "Jenkinsfile" => "groovy",
+        ".bashrc" | ".bash_profile" | ".profile" | ".zshrc" => "shell",
+        ".gitignore" | ".dockerignore" => "ignore",
+        _ => "",
+    };
+    if !language.is_empty() {
+        return Some(language);
+    }
+    if let Some(rest) = name.strip_prefix("Dockerfile.") {
+        if !rest.is_empty() {
+            return Some("dockerfile");
+        }
+    }
+    let (stem, ext) = name.rsplit_once('.')?;
+    if stem.is_empty() {
+        // Dotfiles such as `.env` have no extension.
+        return None;
+    }
+    let language = match ext.to_ascii_lowercase().as_str() {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "tsx",
+        "go" => "go",
+        "rb" => "ruby",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "scala" => "scala",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "m" => "objectivec",
+        "php" => "php",
+        "pl" | "pm" => "perl",
+        "lua" => "lua",
+        "r" => "r",
+        "sh" | "bash" | "zsh" => "shell",
+        "ps1" => "powershell",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "xml" | "xsd" | "plist" => "xml",
+        "svg" => "svg",
+        "json" => "json",
+        "jsonl" | "ndjson" => "jsonl",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "ini" | "cfg" => "ini",
+        "md" | "markdown" => "markdown",
+        "proto" => "protobuf",
+        "graphql" | "gql" => "graphql",
+        "tf" | "hcl" => "hcl",
+        "diff" | "patch" => "diff",
+        "csv" => "csv",
+        "txt" => "text",
+        "vue" => "vue",
+        "svelte" => "svelte",
+        "ex" | "exs" => "elixir",
+        "erl" => "erlang",
+        "hs" => "haskell",
+        "ml" | "mli" => "ocaml",
+        "zig" => "zig",
+        "dart" => "dart",
+        _ => return None,
+    };
+    Some(language)
+}
+
+fn language_from_content(content: &[u8]) -> Option<&'static str> {
+    let head = &content[..content.len().min(BINARY_SNIFF_BYTES)];
+    let text = String::from_utf8_lossy(head);
+    let text = text.trim_start_matches('\u{feff}');
+    if let Some(shebang) = text.strip_prefix("#!") {
+        return language_from_shebang(shebang.lines().next().unwrap_or(""));
+    }
+    let start = text.trim_start();
+    let lower = start
+        .get(..start.len().min(64))
+        .unwrap_or(start)
+        .to_ascii_lowercase();
+    if lower.starts_with("<?php") {
+        Some("php")
+    } else if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        Some("html")
+    } else if lower.starts_with("<?xml") {
+        Some("xml")
+    } else if lower.starts_with("diff --git ") || lower.starts_with("--- a/") {
+        Some("diff")
+    } else if (start.starts_with('{') || start.starts_with('['))
+        && serde_json::from_slice::<serde::de::IgnoredAny>(content).is_ok()
+    {
+        Some("json")
+    } else {
+        None
+    }
+}
+
+/// Language of an interpreter line, without the leading `#!`.
+fn language_from_shebang(line: &str) -> Option<&'static str> {
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        // `env -S python3 -u`: skip options to reach the program.
+        program = words.find(|w| !w.starts_with('-'))?;
+    }
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let language = match name {
+        "sh" | "bash" | "zsh" | "dash" | "ksh" | "ash" => "shell",
+        "python" | "pypy" => "python",
+        "node" | "nodejs" | "bun" => "javascript",
+        "deno" | "ts-node" | "tsx" => "typescript",
+        "ruby" => "ruby",
+        "perl" => "perl",
+        "php" => "php",
+        "lua" | "luajit" => "lua",
+        "Rscript" => "r",
+        "pwsh" => "powershell",
+        "fish" => "fish",
+        "awk" | "gawk" => "awk",
+        _ => return None,
+    };
+    Some(language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_language_from_name_and_extension() {
+        assert_eq!(
+            detect("src/main.rs", b"fn main() {}\n").language,
+            Some("rust")
+        );
+        assert_eq!(detect("Makefile", b"all:\n").language, Some("makefile"));
+        assert_eq!(
+            detect("ops/Dockerfile.dev", b"FROM x\n").language,
+            Some("dockerfile")
+        );
+        assert_eq!(detect("web/App.TSX", b"x").language, Some("tsx"));
+        assert_eq!(detect(".env", b"A=1\n").language, None);
+    }
+
+    #[test]
+    fn test_detects_extensionless_files_from_content() {
+        let detect = |content: &[u8]| detect("bin/tool", content).language;
+        assert_eq!(
+            detect(b"#!/usr/bin/env python3\nprint(1)\n"),
+            Some("python")
+        );
+        assert_eq!(
+            detect(b"#!/usr/bin/env -S node --harmony\n"),
+            Some("javascript")
+        );
+        assert_eq!(detect(b"#!/bin/bash -e\necho hi\n"), Some("shell"));
+        assert_eq!(detect(b"#!/usr/local/bin/ruby2.7\n"), Some("ruby"));
+        assert_eq!(detect(b"#!/opt/unknown\n"), None);
+        assert_eq!(detect(b"<?php echo 1;"), Some("php"));
+        assert_eq!(detect(b"\n<!DOCTYPE html>\n<html></html>"), Some("html"));
+        assert_eq!(detect(b"{\"a\": [1, 2]}\n"), Some("json"));
+        assert_eq!(detect(b"{ not json"), None);
+        assert_eq!(detect(b"plain words\n"), None);
+    }
+
+    #[test]
+    fn test_classifies_binary_and_counts_lines() {
+        let text = detect("notes.txt", b"one\ntwo\nthree");
+        assert!(!text.binary);
+        assert_eq!(text.line_count, Some(3));
+        assert_eq!(detect("a.txt", b"one\ntwo\n").line_count, Some(2));
+        assert_eq!(detect("empty", b"").line_count, Some(0));
+
+        let png = detect("image.rs", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR");
+        assert!(png.binary);
+        assert_eq!(png.language, None);
+        assert_eq!(png.line_count, None);
+        assert!(detect("latin1.txt", b"caf\xe9\n").binary);
+
+        // A multi-byte character cut at the sniffed boundary is still text.
+        let mut long = vec![b'a'; BINARY_SNIFF_BYTES - 1];
+        long.extend_from_slice("é and more\n".as_bytes());
+        assert!(!detect("long.txt", &long).binary);
+    }
+}
@@ -15,7 +15,14 @@
 //! - crc32: u32 (4 bytes)
 //! - Total: 44 bytes per record
 //!
-//! Last-write-wins semantics per turn_id (like heads.tbl).
+//! Last-write-wins semantics per turn_id (like heads.tbl). A record replaced
+//! by a later one with a different root leaves an orphan root behind; the
+//! compaction drops those records with [`FsRootsIndex::compact`] and lists
+//! them in `fs/pruned_roots.jsonl`.
+//!
+//! Working-directory metadata sent with a snapshot (capture time, base path,
+//! git state, entry count) is kept next to it in `fs/snapshots.jsonl`, an
+//! append-only JSON-lines log where the latest entry per turn wins.
 //!
 //! # Tree Object Format
 //!
@@ -29,19 +36,39 @@
 //!     hash: [u8; 32],    // msgpack tag 5 (content hash)
 //! }
 //! ```
+//!
+//! # Overlays
+//!
+//! [`apply_overlay`] builds a new snapshot from a base root plus a list of
+//! changed and removed paths, so incremental snapshots only upload what
+//! changed. Only the trees on the paths to changed entries are rewritten;
+//! everything else is shared with the base.
+//!
+//! # Manifests
+//!
+//! [`checkout::manifest_entries`] lists a whole snapshot by path for
+//! reproducing it elsewhere; [`checkout::checkout`] writes one to disk.
+
+pub mod checkout;
+pub mod detect;
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, VecDeque};
 use std::fs::{File, OpenOptions};
 use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
 use crc32fast::Hasher;
 use rmpv::Value;
+use serde::{Deserialize, Serialize};
 
-use crate::blob_store::BlobStore;
+use crate::blob_store::{BlobSink, BlobSource};
+use crate::deadline::Deadline;
 use crate::error::{Result, StoreError};
-use crate::turn_store::TurnStore;
+use crate::jsonl_log::open_log;
+use crate::turn_store::{CommitPipeline, TurnStore};
 
 /// Entry kinds for filesystem tree entries.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -103,10 +130,17 @@ impl TreeEntry {
 }
 
 /// Sparse index mapping turn_id → fs_root_hash.
+/// Bytes per record in `fs/roots.idx`.
+const ROOT_RECORD_LEN: u64 = 44;
+
 pub struct FsRootsIndex {
     path: PathBuf,
     file: File,
     roots: HashMap<u64, [u8; 32]>,
+    /// (turn_id, root) records replaced by a later attach of another root.
+    superseded: BTreeSet<(u64, [u8; 32])>,
+    /// Group commit pipeline syncing the index, re-tracked after compaction.
+    commit: Option<Arc<CommitPipeline>>,
 }
 
 impl FsRootsIndex {
@@ -126,6 +160,8 @@ impl FsRootsIndex {
             path,
             file,
             roots: HashMap::new(),
+            superseded: BTreeSet::new(),
+            commit: None,
         };
 
         index.load()?;
@@ -135,6 +171,7 @@ impl FsRootsIndex {
     /// Load existing entries from disk.
     fn load(&mut self) -> Result<()> {
         self.roots.clear();
+        self.superseded.clear();
         self.file.seek(SeekFrom::Start(0))?;
 
         loop {
@@ -169,7 +206,7 @@ impl FsRootsIndex {
                 break;
             }
 
-            self.roots.insert(turn_id, fs_root_hash);
+            self.insert(turn_id, fs_root_hash);
         }
 
         Ok(())
@@ -185,25 +222,95 @@ impl FsRootsIndex {
         hasher.finalize()
     }
 
+    /// Encode one index record.
+    fn encode_record(buf: &mut Vec<u8>, turn_id: u64, fs_root_hash: &[u8; 32]) -> Result<()> {
+        buf.write_u64::<LittleEndian>(turn_id)?;
+        buf.extend_from_slice(fs_root_hash);
+        buf.write_u32::<LittleEndian>(Self::compute_crc(turn_id, fs_root_hash))?;
+        Ok(())
+    }
+
+    /// Make `fs_root_hash` the turn's root, remembering the one it replaces.
+    fn insert(&mut self, turn_id: u64, fs_root_hash: [u8; 32]) {
+        if let Some(previous) = self.roots.insert(turn_id, fs_root_hash) {
+            if previous != fs_root_hash {
+                self.superseded.insert((turn_id, previous));
+            }
+        }
+        self.superseded.remove(&(turn_id, fs_root_hash));
+    }
+
+    /// Sync the index with every group commit batch.
+    pub fn track_files(&mut self, pipeline: Arc<CommitPipeline>) -> Result<()> {
+        pipeline.track("fs/roots.idx", &self.file)?;
+        self.commit = Some(pipeline);
+        Ok(())
+    }
+
     /// Attach a filesystem snapshot to a turn.
     pub fn attach(&mut self, turn_id: u64, fs_root_hash: [u8; 32]) -> Result<()> {
         // Write record to file
-        let mut buf = Vec::with_capacity(44);
-        buf.write_u64::<LittleEndian>(turn_id)?;
-        buf.extend_from_slice(&fs_root_hash);
-        let crc = Self::compute_crc(turn_id, &fs_root_hash);
-        buf.write_u32::<LittleEndian>(crc)?;
+        let mut buf = Vec::with_capacity(ROOT_RECORD_LEN as usize);
+        Self::encode_record(&mut buf, turn_id, &fs_root_hash)?;
 
         self.file.seek(SeekFrom::End(0))?;
         self.file.write_all(&buf)?;
         self.file.flush()?;
 
         // Update in-memory index
-        self.roots.insert(turn_id, fs_root_hash);
+        self.insert(turn_id, fs_root_hash);
 
         Ok(())
     }
 
+    /// Roots once attached to a turn and since replaced by another root, as
+    /// (turn_id, root). Their records stay in the index until [`compact`](Self::compact).
+    pub fn superseded(&self) -> Vec<(u64, [u8; 32])> {
+        self.superseded.iter().copied().collect()
+    }
+
+    /// Size of the index file once compacted with the same `keep`.
+    pub fn compacted_bytes(&self, keep: impl Fn(u64) -> bool) -> u64 {
+        let kept = self
+            .superseded
+            .iter()
+            .filter(|&&(turn_id, _)| keep(turn_id));
+        (self.roots.len() + kept.count()) as u64 * ROOT_RECORD_LEN
+    }
+
+    /// Rewrite the index with only the current root of each turn, dropping
+    /// superseded records except those of turns `keep` accepts. Returns the
+    /// dropped (turn_id, root) pairs.
+    pub fn compact(&mut self, keep: impl Fn(u64) -> bool) -> Result<Vec<(u64, [u8; 32])>> {
+        let (kept, dropped): (BTreeSet<_>, BTreeSet<_>) = std::mem::take(&mut self.superseded)
+            .into_iter()
+            .partition(|&(turn_id, _)| keep(turn_id));
+        self.superseded = kept;
+        if dropped.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut roots: Vec<(u64, [u8; 32])> = self.roots().collect();
+        roots.sort_unstable();
+        let mut buf =
+            Vec::with_capacity((roots.len() + self.superseded.len()) * ROOT_RECORD_LEN as usize);
+        // A kept record goes first so that it reloads as superseded.
+        for (turn_id, hash) in self.superseded.iter().chain(&roots) {
+            Self::encode_record(&mut buf, *turn_id, hash)?;
+        }
+
+        let tmp = self.path.with_extension("idx.tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&buf)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &self.path)?;
+        self.file = OpenOptions::new().read(true).write(true).open(&self.path)?;
+        if let Some(pipeline) = self.commit.take() {
+            self.track_files(pipeline)?;
+        }
+
+        Ok(dropped.into_iter().collect())
+    }
+
     /// Get the fs_root_hash directly attached to a turn.
     pub fn get(&self, turn_id: u64) -> Option<[u8; 32]> {
         self.roots.get(&turn_id).copied()
@@ -211,9 +318,20 @@ impl FsRootsIndex {
 
     /// Get the fs_root_hash for a turn, walking parent chain if not directly attached.
     pub fn get_inherited(&self, turn_id: u64, turn_store: &TurnStore) -> Option<[u8; 32]> {
+        self.get_inherited_with_source(turn_id, turn_store)
+            .map(|(_, hash)| hash)
+    }
+
+    /// Like [`get_inherited`](Self::get_inherited), also returning the turn the
+    /// snapshot is attached to.
+    pub fn get_inherited_with_source(
+        &self,
+        turn_id: u64,
+        turn_store: &TurnStore,
+    ) -> Option<(u64, [u8; 32])> {
         // First check direct attachment
         if let Some(hash) = self.roots.get(&turn_id) {
-            return Some(*hash);
+            return Some((turn_id, *hash));
         }
 
         // Walk parent chain
@@ -221,7 +339,7 @@ impl FsRootsIndex {
         while current != 0 {
             if let Ok(turn) = turn_store.get_turn(current) {
                 if let Some(hash) = self.roots.get(&turn.turn_id) {
-                    return Some(*hash);
+                    return Some((turn.turn_id, *hash));
                 }
                 current = turn.parent_turn_id;
             } else {
@@ -246,6 +364,11 @@ impl FsRootsIndex {
         }
     }
 
+    /// Every turn with a directly attached snapshot, with its root hash.
+    pub fn roots(&self) -> impl Iterator<Item = (u64, [u8; 32])> + '_ {
+        self.roots.iter().map(|(turn_id, hash)| (*turn_id, *hash))
+    }
+
     /// Get all unique root hashes for computing content size.
     pub fn unique_roots(&self) -> Vec<[u8; 32]> {
         let mut seen = std::collections::HashSet::new();
@@ -268,12 +391,176 @@ pub struct FsRootsStats {
     pub content_bytes: u64,
 }
 
+/// Which snapshot blobs are reachable from the current roots, and which only
+/// from superseded ones. Byte counts are raw blob lengths.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FsReachability {
+    /// Turns with a directly attached root.
+    pub live_roots: u64,
+    /// Superseded records still in `fs/roots.idx`, other than held ones.
+    pub orphan_roots: u64,
+    /// Superseded records of turns in contexts on legal hold. They are not
+    /// pruned while the hold lasts, and the blobs they reach are kept.
+    pub held_roots: u64,
+    /// Tree and file blobs reachable from a current root.
+    pub reachable_blobs: u64,
+    pub reachable_bytes: u64,
+    /// Blobs still stored that only orphan roots reach; the next collection
+    /// drops them.
+    pub unreachable_blobs: u64,
+    pub unreachable_bytes: u64,
+}
+
+/// An orphan root dropped from the index by a compaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrunedRoot {
+    pub turn_id: u64,
+    /// Hex root hash.
+    pub root_hash: String,
+    pub pruned_at_unix_ms: u64,
+}
+
+/// Audit trail of pruned roots, persisted as `fs/pruned_roots.jsonl`.
+pub struct PrunedRootsLog {
+    file: File,
+    entries: Vec<PrunedRoot>,
+}
+
+impl PrunedRootsLog {
+    pub fn open(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join("pruned_roots.jsonl");
+        let (file, entries) = open_log::<PrunedRoot>(&path)?;
+
+        Ok(Self { file, entries })
+    }
+
+    /// Pruned roots, oldest first.
+    pub fn entries(&self) -> &[PrunedRoot] {
+        &self.entries
+    }
+
+    /// Record roots about to be dropped from the index.
+    pub fn record(&mut self, roots: &[(u64, [u8; 32])]) -> Result<()> {
+        if roots.is_empty() {
+            return Ok(());
+        }
+        let pruned_at_unix_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let mut lines = Vec::new();
+        let mut pruned = Vec::with_capacity(roots.len());
+        for (turn_id, root) in roots {
+            let entry = PrunedRoot {
+                turn_id: *turn_id,
+                root_hash: hex::encode(root),
+                pruned_at_unix_ms,
+            };
+            serde_json::to_writer(&mut lines, &entry)
+                .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+            lines.push(b'\n');
+            pruned.push(entry);
+        }
+        self.file.write_all(&lines)?;
+        self.file.sync_data()?;
+        self.entries.extend(pruned);
+        Ok(())
+    }
+}
+
+/// Working-directory metadata recorded with a snapshot.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotMeta {
+    /// Unix milliseconds when the client captured the snapshot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub captured_at: Option<u64>,
+    /// Directory the snapshot was taken from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_branch: Option<String>,
+    /// Whether the working tree had uncommitted changes; None outside git.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_dirty: Option<bool>,
+    /// Files, directories and symlinks in the snapshot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_entries: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotMetaEntry {
+    turn_id: u64,
+    #[serde(flatten)]
+    meta: SnapshotMeta,
+}
+
+/// Snapshot metadata per turn, persisted as `fs/snapshots.jsonl`.
+pub struct SnapshotMetaLog {
+    file: File,
+    entries: HashMap<u64, SnapshotMeta>,
+}
+
+impl SnapshotMetaLog {
+    pub fn open(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join("snapshots.jsonl");
+        let (file, records) = open_log::<SnapshotMetaEntry>(&path)?;
+
+        let mut entries = HashMap::new();
+        for entry in records {
+            if entry.meta == SnapshotMeta::default() {
+                entries.remove(&entry.turn_id);
+            } else {
+                entries.insert(entry.turn_id, entry.meta);
+            }
+        }
+
+        Ok(Self { file, entries })
+    }
+
+    pub fn get(&self, turn_id: u64) -> Option<&SnapshotMeta> {
+        self.entries.get(&turn_id)
+    }
+
+    /// Sync the log with every group commit batch.
+    pub fn track_files(&self, pipeline: &CommitPipeline) -> Result<()> {
+        pipeline.track("fs/snapshots.jsonl", &self.file)
+    }
+
+    /// Record metadata for the snapshot attached to a turn, replacing any previous entry.
+    pub fn set(&mut self, turn_id: u64, meta: SnapshotMeta) -> Result<()> {
+        let entry = SnapshotMetaEntry {
+            turn_id,
+            meta: meta.clone(),
+        };
+        let mut line = serde_json::to_vec(&entry)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.entries.insert(turn_id, meta);
+        Ok(())
+    }
+
+    /// Forget metadata of a turn whose snapshot was replaced without any.
+    pub fn clear(&mut self, turn_id: u64) -> Result<()> {
+        if self.entries.contains_key(&turn_id) {
+            self.set(turn_id, SnapshotMeta::default())?;
+            self.entries.remove(&turn_id);
+        }
+        Ok(())
+    }
+}
+
 /// Load and deserialize tree entries from the blob store.
 pub fn load_tree_entries(
-    blob_store: &mut BlobStore,
+    blob_store: &mut impl BlobSource,
     tree_hash: &[u8; 32],
 ) -> Result<Vec<TreeEntry>> {
-    let bytes = blob_store.get(tree_hash)?;
+    let bytes = blob_store.get_blob(tree_hash)?;
     parse_tree_entries(&bytes)
 }
 
@@ -365,113 +652,389 @@ fn parse_tree_entry(value: &Value) -> Result<TreeEntry> {
     })
 }
 
-/// Resolve a path to its tree hash (for directories) or blob hash (for files).
-/// Returns (hash, is_directory).
-pub fn resolve_path(
-    blob_store: &mut BlobStore,
-    root_hash: &[u8; 32],
-    path: &str,
-) -> Result<([u8; 32], bool)> {
-    if path.is_empty() || path == "/" {
-        return Ok((*root_hash, true));
-    }
+/// Most symlinks followed in one lookup, like Linux's `MAXSYMLINKS`.
+const MAX_SYMLINK_HOPS: usize = 40;
 
-    let parts: Vec<&str> = path
-        .trim_matches('/')
-        .split('/')
-        .filter(|s| !s.is_empty() && *s != ".")
-        .collect();
+/// Where a path lookup ended: the entry found, or `None` for a directory
+/// reached without one (the snapshot root, or via `..`).
+#[derive(Debug, Clone)]
+pub struct ResolvedPath {
+    /// Tree hash for directories, content or target hash otherwise.
+    pub hash: [u8; 32],
+    pub entry: Option<TreeEntry>,
+}
 
-    if parts.is_empty() {
-        return Ok((*root_hash, true));
+impl ResolvedPath {
+    pub fn is_dir(&self) -> bool {
+        self.entry
+            .as_ref()
+            .is_none_or(|e| e.kind_enum() == EntryKind::Directory)
     }
+}
 
-    let mut current_hash = *root_hash;
-
-    for (i, part) in parts.iter().enumerate() {
-        let entries = load_tree_entries(blob_store, &current_hash)?;
-
-        let entry = entries
-            .iter()
-            .find(|e| e.name == *part)
-            .ok_or_else(|| StoreError::NotFound(format!("path component not found: {part}")))?;
+/// Read the target of a symlink entry (stored as the blob its hash names).
+pub fn read_symlink_target(blob_store: &mut impl BlobSource, entry: &TreeEntry) -> Result<String> {
+    let bytes = blob_store.get_blob(&entry.hash_array()?)?;
+    String::from_utf8(bytes)
+        .map_err(|_| StoreError::Corrupt(format!("symlink target is not UTF-8: {}", entry.name)))
+}
 
-        let entry_hash = entry.hash_array()?;
-        let is_last = i == parts.len() - 1;
+fn path_parts(path: &str) -> impl DoubleEndedIterator<Item = &str> {
+    path.split('/').filter(|s| !s.is_empty() && *s != ".")
+}
 
-        if is_last {
-            return Ok((entry_hash, entry.kind_enum() == EntryKind::Directory));
+/// Look up a path in a snapshot. `..` walks up but never above the root.
+///
+/// With `follow_symlinks`, symlinks anywhere in the path are replaced by
+/// their targets, resolved relative to the directory containing them;
+/// absolute targets and targets leaving the snapshot are refused. Without
+/// it, a symlink is returned as the final entry and is not a directory when
+/// it appears mid-path. The deadline is checked before loading each tree.
+pub fn lookup_path(
+    blob_store: &mut impl BlobSource,
+    root_hash: &[u8; 32],
+    path: &str,
+    follow_symlinks: bool,
+    deadline: &Deadline,
+) -> Result<ResolvedPath> {
+    let mut pending: VecDeque<String> = path_parts(path).map(str::to_string).collect();
+    // Directories from the root down to the one being searched.
+    let mut dirs = vec![*root_hash];
+    let mut found: Option<TreeEntry> = None;
+    let mut hops = 0;
+
+    while let Some(part) = pending.pop_front() {
+        if part == ".." {
+            if dirs.len() == 1 {
+                return Err(StoreError::InvalidInput(format!(
+                    "path leaves the snapshot root: {path}"
+                )));
+            }
+            dirs.pop();
+            found = None;
+            continue;
         }
 
-        // Must be a directory to continue
-        if entry.kind_enum() != EntryKind::Directory {
-            return Err(StoreError::InvalidInput(format!("not a directory: {part}")));
-        }
+        deadline.check("fs tree walk")?;
+        let current = *dirs.last().expect("root stays on the stack");
+        let entry = load_tree_entries(blob_store, &current)?
+            .into_iter()
+            .find(|e| e.name == part)
+            .ok_or_else(|| StoreError::NotFound(format!("path component not found: {part}")))?;
 
-        current_hash = entry_hash;
+        match entry.kind_enum() {
+            EntryKind::Symlink if follow_symlinks => {
+                hops += 1;
+                if hops > MAX_SYMLINK_HOPS {
+                    return Err(StoreError::InvalidInput(format!(
+                        "too many levels of symbolic links: {path}"
+                    )));
+                }
+                let target = read_symlink_target(blob_store, &entry)?;
+                if target.starts_with('/') {
+                    return Err(StoreError::InvalidInput(format!(
+                        "symlink {part} points outside the snapshot: {target}"
+                    )));
+                }
+                for component in path_parts(&target).rev() {
+                    pending.push_front(component.to_string());
+                }
+                found = None;
+            }
+            EntryKind::Directory => {
+                dirs.push(entry.hash_array()?);
+                found = Some(entry);
+            }
+            _ if pending.is_empty() => found = Some(entry),
+            _ => return Err(StoreError::InvalidInput(format!("not a directory: {part}"))),
+        }
     }
 
-    unreachable!()
+    Ok(match found {
+        Some(entry) if entry.kind_enum() != EntryKind::Directory => ResolvedPath {
+            hash: entry.hash_array()?,
+            entry: Some(entry),
+        },
+        entry => ResolvedPath {
+            hash: *dirs.last().expect("root stays on the stack"),
+            entry,
+        },
+    })
+}
+
+/// Resolve a path to its tree hash (for directories) or blob hash (for files).
+/// Returns (hash, is_directory). The deadline is checked before loading each tree.
+pub fn resolve_path(
+    blob_store: &mut impl BlobSource,
+    root_hash: &[u8; 32],
+    path: &str,
+    follow_symlinks: bool,
+    deadline: &Deadline,
+) -> Result<([u8; 32], bool)> {
+    let resolved = lookup_path(blob_store, root_hash, path, follow_symlinks, deadline)?;
+    Ok((resolved.hash, resolved.is_dir()))
 }
 
-/// Get a file's content by path from a filesystem snapshot.
+/// Get a file's content by path from a filesystem snapshot. An unfollowed
+/// symlink yields its target as content.
+/// The deadline is checked before loading each tree.
 pub fn get_file_at_path(
-    blob_store: &mut BlobStore,
+    blob_store: &mut impl BlobSource,
     root_hash: &[u8; 32],
     path: &str,
+    follow_symlinks: bool,
+    deadline: &Deadline,
 ) -> Result<(Vec<u8>, TreeEntry)> {
-    let parts: Vec<&str> = path
-        .trim_matches('/')
-        .split('/')
-        .filter(|s| !s.is_empty() && *s != ".")
-        .collect();
+    let entry = file_entry_at_path(blob_store, root_hash, path, follow_symlinks, deadline)?;
+    let content = blob_store.get_blob(&entry.hash_array()?)?;
+    Ok((content, entry))
+}
 
-    if parts.is_empty() {
+/// Entry of the file (or unfollowed symlink) at a path, without reading its
+/// content.
+pub fn file_entry_at_path(
+    blob_store: &mut impl BlobSource,
+    root_hash: &[u8; 32],
+    path: &str,
+    follow_symlinks: bool,
+    deadline: &Deadline,
+) -> Result<TreeEntry> {
+    if path_parts(path).next().is_none() {
         return Err(StoreError::InvalidInput("empty path".into()));
     }
 
-    let mut current_hash = *root_hash;
+    let resolved = lookup_path(blob_store, root_hash, path, follow_symlinks, deadline)?;
+    match resolved.entry {
+        Some(entry) if entry.kind_enum() != EntryKind::Directory => Ok(entry),
+        _ => Err(StoreError::InvalidInput(format!(
+            "path is a directory: {path}"
+        ))),
+    }
+}
 
-    for (i, part) in parts.iter().enumerate() {
-        let entries = load_tree_entries(blob_store, &current_hash)?;
+/// Total size of the files in a snapshot, as recorded in its tree entries.
+/// Files present in several directories count once per path.
+pub fn snapshot_bytes(blob_store: &mut impl BlobSource, root_hash: &[u8; 32]) -> Result<u64> {
+    let mut pending = vec![*root_hash];
+    let mut total = 0u64;
+    while let Some(tree) = pending.pop() {
+        for entry in load_tree_entries(blob_store, &tree)? {
+            match entry.kind_enum() {
+                EntryKind::Directory => pending.push(entry.hash_array()?),
+                EntryKind::File => total += entry.size,
+                EntryKind::Symlink => {}
+            }
+        }
+    }
+    Ok(total)
+}
 
-        let entry = entries
-            .iter()
-            .find(|e| e.name == *part)
-            .ok_or_else(|| StoreError::NotFound(format!("path component not found: {part}")))?;
+/// One change in an overlay: `entry` replaces or adds the entry at `path`
+/// (its `name` is ignored); `None` removes the entry.
+#[derive(Debug, Clone)]
+pub struct OverlayChange {
+    pub path: String,
+    pub entry: Option<TreeEntry>,
+}
 
-        let entry_hash = entry.hash_array()?;
-        let is_last = i == parts.len() - 1;
+#[derive(Debug, Clone)]
+pub struct OverlayResult {
+    pub root_hash: [u8; 32],
+    /// Tree objects that did not exist before.
+    pub trees_written: usize,
+}
 
-        if is_last {
-            // Return file content
-            match entry.kind_enum() {
-                EntryKind::File => {
-                    let content = blob_store.get(&entry_hash)?;
-                    return Ok((content, entry.clone()));
-                }
-                EntryKind::Symlink => {
-                    // For symlinks, return the target path as content
-                    let content = blob_store.get(&entry_hash)?;
-                    return Ok((content, entry.clone()));
-                }
-                EntryKind::Directory => {
-                    return Err(StoreError::InvalidInput(format!(
-                        "path is a directory: {path}"
-                    )));
+/// Mode of directories created implicitly for nested overlay paths.
+const IMPLICIT_DIR_MODE: u32 = 0o755;
+
+type OverlayEdit<'a> = (Vec<&'a str>, Option<&'a TreeEntry>);
+
+/// Apply `changes` on top of `base_root` (an empty tree when None) and write
+/// the rewritten trees. Replacing or removing a path applies before changes
+/// beneath it, so `rm dir` followed by `dir/file` recreates `dir`. Entries
+/// must reference blobs that already exist; removing a missing path fails.
+pub fn apply_overlay<B: BlobSource + BlobSink>(
+    blobs: &mut B,
+    base_root: Option<&[u8; 32]>,
+    changes: &[OverlayChange],
+) -> Result<OverlayResult> {
+    let mut edits: Vec<OverlayEdit<'_>> = Vec::with_capacity(changes.len());
+    for change in changes {
+        let parts: Vec<&str> = change
+            .path
+            .split('/')
+            .filter(|s| !s.is_empty() && *s != ".")
+            .collect();
+        if parts.is_empty() || parts.contains(&"..") {
+            return Err(StoreError::InvalidInput(format!(
+                "invalid overlay path: {:?}",
+                change.path
+            )));
+        }
+        if let Some(entry) = &change.entry {
+            if !blobs.has_blob(&entry.hash_array()?) {
+                return Err(StoreError::NotFound(format!(
+                    "blob for overlay path: {}",
+                    change.path
+                )));
+            }
+        }
+        edits.push((parts, change.entry.as_ref()));
+    }
+
+    let mut trees_written = 0;
+    let root_hash = rewrite_tree(blobs, base_root.copied(), &edits, "", &mut trees_written)?;
+    Ok(OverlayResult {
+        root_hash,
+        trees_written,
+    })
+}
+
+fn rewrite_tree<B: BlobSource + BlobSink>(
+    blobs: &mut B,
+    tree: Option<[u8; 32]>,
+    edits: &[OverlayEdit<'_>],
+    prefix: &str,
+    trees_written: &mut usize,
+) -> Result<[u8; 32]> {
+    let mut entries = match tree {
+        Some(hash) => load_tree_entries(blobs, &hash)?,
+        None => Vec::new(),
+    };
+
+    for (parts, entry) in edits.iter().filter(|(parts, _)| parts.len() == 1) {
+        let name = parts[0];
+        let existing = entries.iter().position(|e| e.name == name);
+        match (entry, existing) {
+            (Some(entry), existing) => {
+                let entry = TreeEntry {
+                    name: name.to_string(),
+                    ..(*entry).clone()
+                };
+                match existing {
+                    Some(i) => entries[i] = entry,
+                    None => entries.push(entry),
                 }
             }
+            (None, Some(i)) => {
+                entries.remove(i);
+            }
+            (None, None) => {
+                return Err(StoreError::NotFound(format!(
+                    "overlay path not in base snapshot: {prefix}{name}"
+                )))
+            }
         }
+    }
 
-        // Must be a directory to continue
-        if entry.kind_enum() != EntryKind::Directory {
-            return Err(StoreError::InvalidInput(format!("not a directory: {part}")));
+    let mut dirs: Vec<&str> = Vec::new();
+    for (parts, _) in edits {
+        if parts.len() > 1 && !dirs.contains(&parts[0]) {
+            dirs.push(parts[0]);
+        }
+    }
+    for dir in dirs {
+        let nested: Vec<OverlayEdit<'_>> = edits
+            .iter()
+            .filter(|(parts, _)| parts.len() > 1 && parts[0] == dir)
+            .map(|(parts, entry)| (parts[1..].to_vec(), *entry))
+            .collect();
+        let existing = entries.iter().position(|e| e.name == dir);
+        let subtree = match existing {
+            Some(i) if entries[i].kind_enum() == EntryKind::Directory => {
+                Some(entries[i].hash_array()?)
+            }
+            Some(_) => {
+                return Err(StoreError::InvalidInput(format!(
+                    "not a directory: {prefix}{dir}"
+                )))
+            }
+            None => None,
+        };
+        let hash = rewrite_tree(
+            blobs,
+            subtree,
+            &nested,
+            &format!("{prefix}{dir}/"),
+            trees_written,
+        )?;
+        match existing {
+            Some(i) => entries[i].hash = hash.to_vec(),
+            None => entries.push(TreeEntry {
+                name: dir.to_string(),
+                kind: EntryKind::Directory as u8,
+                mode: IMPLICIT_DIR_MODE,
+                size: 0,
+                hash: hash.to_vec(),
+            }),
         }
+    }
+
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    let bytes = encode_tree_entries(&entries)?;
+    let hash = *blake3::hash(&bytes).as_bytes();
+    if blobs.put_blob(hash, &bytes)? {
+        *trees_written += 1;
+    }
+    Ok(hash)
+}
 
-        current_hash = entry_hash;
+/// Encode tree entries the way the clients do (string keys "1".."5",
+/// fixed-width integers), so a tree built by an overlay hashes the same as
+/// the tree a client would upload for the same directory.
+pub fn encode_tree_entries(entries: &[TreeEntry]) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    write_array_len(&mut buf, entries.len())?;
+    for entry in entries {
+        let hash = entry.hash_array()?;
+        buf.push(0x85);
+        write_str(&mut buf, "1")?;
+        write_str(&mut buf, &entry.name)?;
+        write_str(&mut buf, "2")?;
+        buf.push(0xcc);
+        buf.push(entry.kind);
+        write_str(&mut buf, "3")?;
+        buf.push(0xce);
+        buf.write_u32::<BigEndian>(entry.mode)?;
+        write_str(&mut buf, "4")?;
+        buf.push(0xcf);
+        buf.write_u64::<BigEndian>(entry.size)?;
+        write_str(&mut buf, "5")?;
+        buf.extend_from_slice(&[0xc4, 32]);
+        buf.extend_from_slice(&hash);
     }
+    Ok(buf)
+}
+
+/// msgpack array header: fix form below 16, else 16- or 32-bit length.
+fn write_array_len(buf: &mut Vec<u8>, len: usize) -> Result<()> {
+    if len < 16 {
+        buf.push(0x90 | len as u8);
+    } else if len <= u16::MAX as usize {
+        buf.push(0xdc);
+        buf.write_u16::<BigEndian>(len as u16)?;
+    } else {
+        buf.push(0xdd);
+        buf.write_u32::<BigEndian>(len as u32)?;
+    }
+    Ok(())
+}
 
-    unreachable!()
+fn write_str(buf: &mut Vec<u8>, s: &str) -> Result<()> {
+    let len = s.len();
+    if len < 32 {
+        buf.push(0xa0 | len as u8);
+    } else if len <= u8::MAX as usize {
+        buf.extend_from_slice(&[0xd9, len as u8]);
+    } else if len <= u16::MAX as usize {
+        buf.push(0xda);
+        buf.write_u16::<BigEndian>(len as u16)?;
+    } else {
+        buf.push(0xdb);
+        buf.write_u32::<BigEndian>(len as u32)?;
+    }
+    buf.extend_from_slice(s.as_bytes());
+    Ok(())
 }
 
 #[cfg(test)]
@@ -514,4 +1077,142 @@ mod tests {
         // Last write wins
         assert_eq!(index.get(1), Some(hash2));
     }
+
+    #[test]
+    fn test_symlinks_resolve_only_when_followed() {
+        let tmpdir = TempDir::new().unwrap();
+        let mut blobs = crate::blob_store::BlobStore::open(tmpdir.path()).unwrap();
+        let mut put = |data: &[u8]| {
+            let hash = *blake3::hash(data).as_bytes();
+            blobs.put_if_absent(hash, data).unwrap();
+            hash
+        };
+        let entry = |name: &str, kind: EntryKind, hash: [u8; 32]| TreeEntry {
+            name: name.to_string(),
+            kind: kind as u8,
+            mode: 0o644,
+            size: 0,
+            hash: hash.to_vec(),
+        };
+        let content = put(b"fn main() {}");
+        let src = put(&encode_tree_entries(&[entry("main.rs", EntryKind::File, content)]).unwrap());
+        let root = encode_tree_entries(&[
+            entry("abs", EntryKind::Symlink, put(b"/etc/passwd")),
+            entry("current", EntryKind::Symlink, put(b"src")),
+            entry(
+                "entry.rs",
+                EntryKind::Symlink,
+                put(b"current/../src/main.rs"),
+            ),
+            entry("escape", EntryKind::Symlink, put(b"../outside")),
+            entry("loop", EntryKind::Symlink, put(b"loop")),
+            entry("src", EntryKind::Directory, src),
+        ])
+        .unwrap();
+        let root_hash = put(&root);
+        let none = Deadline::none();
+        let mut read = |path: &str, follow: bool| {
+            get_file_at_path(&mut blobs, &root_hash, path, follow, &none)
+        };
+
+        // Unfollowed, a symlink reads as its target and cannot be walked through.
+        let (target, link) = read("entry.rs", false).unwrap();
+        assert_eq!(target, b"current/../src/main.rs");
+        assert_eq!(link.kind_enum(), EntryKind::Symlink);
+        assert!(read("current/main.rs", false).is_err());
+
+        let (data, file) = read("entry.rs", true).unwrap();
+        assert_eq!(data, b"fn main() {}");
+        assert_eq!(file.name, "main.rs");
+        assert_eq!(read("current/main.rs", true).unwrap().0, b"fn main() {}");
+
+        for refused in ["abs", "escape", "loop"] {
+            let err = read(refused, true).unwrap_err();
+            assert!(
+                matches!(err, StoreError::InvalidInput(_)),
+                "{refused}: {err:?}"
+            );
+        }
+
+        let (hash, is_dir) = resolve_path(&mut blobs, &root_hash, "current", true, &none).unwrap();
+        assert_eq!((hash, is_dir), (src, true));
+    }
+
+    #[test]
+    fn test_overlay_rewrites_only_changed_paths() {
+        let tmpdir = TempDir::new().unwrap();
+        let mut blobs = crate::blob_store::BlobStore::open(tmpdir.path()).unwrap();
+        let put = |blobs: &mut crate::blob_store::BlobStore, data: &[u8]| {
+            let hash = *blake3::hash(data).as_bytes();
+            blobs.put_if_absent(hash, data).unwrap();
+            hash
+        };
+        let file = |name: &str, hash: [u8; 32]| TreeEntry {
+            name: name.to_string(),
+            kind: EntryKind::File as u8,
+            mode: 0o644,
+            size: 1,
+            hash: hash.to_vec(),
+        };
+        let a = put(&mut blobs, b"a");
+        let b = put(&mut blobs, b"b");
+
+        let lib = encode_tree_entries(&[file("x.rs", a)]).unwrap();
+        let lib_hash = put(&mut blobs, &lib);
+        let mut lib_dir = file("lib", lib_hash);
+        lib_dir.kind = EntryKind::Directory as u8;
+        lib_dir.size = 0;
+        let root = encode_tree_entries(&[file("README", a), lib_dir]).unwrap();
+        let root_hash = put(&mut blobs, &root);
+
+        let changes = vec![
+            OverlayChange {
+                path: "README".into(),
+                entry: None,
+            },
+            OverlayChange {
+                path: "src/main.rs".into(),
+                entry: Some(file("", b)),
+            },
+        ];
+        let result = apply_overlay(&mut blobs, Some(&root_hash), &changes).unwrap();
+        assert_eq!(result.trees_written, 2);
+
+        let entries = load_tree_entries(&mut blobs, &result.root_hash).unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["lib", "src"]);
+        assert_eq!(entries[0].hash_array().unwrap(), lib_hash);
+        let (content, _) = get_file_at_path(
+            &mut blobs,
+            &result.root_hash,
+            "src/main.rs",
+            false,
+            &Deadline::none(),
+        )
+        .unwrap();
+        assert_eq!(content, b"b");
+
+        let missing = vec![OverlayChange {
+            path: "nope".into(),
+            entry: None,
+        }];
+        assert!(matches!(
+            apply_overlay(&mut blobs, Some(&root_hash), &missing),
+            Err(StoreError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_encode_matches_client_tree_encoding() {
+        // "src" tree from the clients' fstree_basic fixture.
+        let fixture = hex::decode(
+            "9285a131a66c69622e676fa132cc00a133ce000001a4a134cf000000000000001ba135c420\
+             086c48c992cb0560586627eebcd9e578a6b5dcd399f0c83af82d13c263fdbc39\
+             85a131a76d61696e2e676fa132cc00a133ce000001a4a134cf000000000000000ca135c420\
+             f13810b2e1ffddc4e8ada1f2928f6e6f4d7a1f7f7b6ef4ee53b50a9bc9c67f21",
+        )
+        .unwrap();
+        let entries = parse_tree_entries(&fixture).unwrap();
+        assert_eq!(encode_tree_entries(&entries).unwrap(), fixture);
+    }
 }
@@ -0,0 +1,106 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Legal holds on contexts.
+//!
+//! A held context is preserved indefinitely: its encryption key cannot be
+//! shredded, alone or through its client tag, so a blob collection never
+//! reclaims its data. Every placement and release is appended to a
+//! JSON-lines log with its reason and principal; the log is the audit trail
+//! of the hold, and the latest entry per context decides whether it is held.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::jsonl_log::open_log;
+use crate::util::unix_ms;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldAction {
+    Place,
+    Release,
+}
+
+/// One placement or release of a hold.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HoldEntry {
+    pub context_id: u64,
+    pub action: HoldAction,
+    pub reason: String,
+    pub principal: String,
+    pub at_unix_ms: u64,
+}
+
+pub struct Holds {
+    file: File,
+    history: HashMap<u64, Vec<HoldEntry>>,
+}
+
+impl Holds {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("holds.jsonl");
+        let (file, entries) = open_log::<HoldEntry>(&path)?;
+
+        let mut history: HashMap<u64, Vec<HoldEntry>> = HashMap::new();
+        for entry in entries {
+            history.entry(entry.context_id).or_default().push(entry);
+        }
+
+        Ok(Self { file, history })
+    }
+
+    /// The placement holding a context, if it is held.
+    pub fn get(&self, context_id: u64) -> Option<&HoldEntry> {
+        self.history
+            .get(&context_id)
+            .and_then(|entries| entries.last())
+            .filter(|entry| entry.action == HoldAction::Place)
+    }
+
+    /// Every placement and release for a context, oldest first.
+    pub fn history(&self, context_id: u64) -> &[HoldEntry] {
+        self.history.get(&context_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Contexts currently held.
+    pub fn held(&self) -> impl Iterator<Item = u64> + '_ {
+        self.history
+            .keys()
+            .copied()
+            .filter(|&context_id| self.get(context_id).is_some())
+    }
+
+    /// Record a placement or release. The caller checks that it applies.
+    pub fn record(
+        &mut self,
+        context_id: u64,
+        action: HoldAction,
+        reason: String,
+        principal: String,
+    ) -> Result<HoldEntry> {
+        let entry = HoldEntry {
+            context_id,
+            action,
+            reason,
+            principal,
+            at_unix_ms: unix_ms(),
+        };
+        let mut line = serde_json::to_vec(&entry)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.history
+            .entry(context_id)
+            .or_default()
+            .push(entry.clone());
+        Ok(entry)
+    }
+}
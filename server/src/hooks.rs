@@ -0,0 +1,623 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Post-processing hooks for contexts.
+//!
+//! The hook runner listens on the event bus for appended turns and tracks
+//! per-context activity. When a configured rule fires (the context went idle,
+//! or enough turns accumulated since the last summary) the recent turns are
+//! sent to a [`Summarizer`] and the result is appended back to the context as a
+//! typed `cxdb.ContextSummary` turn marked as machine-generated.
+//!
+//! The default summarizer POSTs JSON to a user-supplied HTTP endpoint:
+//!
+//! ```text
+//! POST {CXDB_SUMMARY_HOOK_URL}
+//! { "context_id": "42", "trigger": "idle", "head_turn_id": "99", "turns": [...] }
+//!
+//! 200 OK
+//! { "summary": "User asked for ...", "model": "optional-model-name" }
+//! ```
+//!
+//! The same summarizer writes the digest that seeds a context rolled over
+//! without one (see [`crate::rollovers`]), with trigger `rollover`.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use rmpv::Value;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::error::{Result, StoreError};
+use crate::events::{EventBus, StoreEvent};
+use crate::projection::{BytesRender, EnumRender, RenderOptions, TimeRender, U64Format};
+use crate::registry::Registry;
+use crate::store::Store;
+use crate::util::{env_u64, unix_ms};
+
+/// Declared type id of summary turns appended by the hook runner.
+pub const SUMMARY_TYPE_ID: &str = "cxdb.ContextSummary";
+/// Declared type version of summary turns appended by the hook runner.
+pub const SUMMARY_TYPE_VERSION: u32 = 1;
+
+/// Wait before summarizing a context again after its summary failed.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Configuration for the summary hook, loaded from the environment.
+#[derive(Debug, Clone)]
+pub struct SummaryHookConfig {
+    /// Summarizer endpoint that receives recent turns.
+    pub url: String,
+    /// Name recorded as the summary's generator.
+    pub name: String,
+    /// Summarize once a context has been idle this long (None disables the rule).
+    pub idle_timeout: Option<Duration>,
+    /// Summarize once this many turns accumulated since the last summary (None disables the rule).
+    pub turn_threshold: Option<u32>,
+    /// Number of most recent turns sent to the summarizer.
+    pub recent_turns: u32,
+    /// Timeout for the outbound HTTP call.
+    pub request_timeout: Duration,
+}
+
+impl SummaryHookConfig {
+    /// Load config from environment variables. Returns None when no hook URL is set.
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var("CXDB_SUMMARY_HOOK_URL").ok()?;
+        if url.is_empty() {
+            return None;
+        }
+        let name =
+            std::env::var("CXDB_SUMMARY_HOOK_NAME").unwrap_or_else(|_| "summary-hook".to_string());
+        let idle_secs = env_u64("CXDB_SUMMARY_IDLE_SECS", 300);
+        let turn_threshold = env_u64("CXDB_SUMMARY_TURN_THRESHOLD", 0);
+        let recent_turns = env_u64("CXDB_SUMMARY_RECENT_TURNS", 50).clamp(1, 1000) as u32;
+        let timeout_ms = env_u64("CXDB_SUMMARY_HOOK_TIMEOUT_MS", 30_000);
+        Some(Self {
+            url,
+            name,
+            idle_timeout: (idle_secs > 0).then(|| Duration::from_secs(idle_secs)),
+            turn_threshold: (turn_threshold > 0).then_some(turn_threshold as u32),
+            recent_turns,
+            request_timeout: Duration::from_millis(timeout_ms),
+        })
+    }
+}
+
+/// Why a summary was requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookTrigger {
+    Idle,
+    TurnThreshold,
+    /// The context is being rolled over into a new one.
+    Rollover,
+}
+
+impl HookTrigger {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookTrigger::Idle => "idle",
+            HookTrigger::TurnThreshold => "turn_threshold",
+            HookTrigger::Rollover => "rollover",
+        }
+    }
+}
+
+/// A turn as sent to the summarizer.
+#[derive(Debug, Clone, Serialize)]
+pub struct SummaryTurn {
+    pub turn_id: String,
+    pub parent_turn_id: String,
+    pub depth: u32,
+    pub type_id: String,
+    pub type_version: u32,
+    /// Typed projection, present when the registry knows the declared type.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<JsonValue>,
+    /// Raw msgpack payload, present when no projection was possible.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes_b64: Option<String>,
+}
+
+/// Request body sent to a summarizer.
+#[derive(Debug, Clone, Serialize)]
+pub struct SummaryRequest {
+    pub context_id: String,
+    pub trigger: HookTrigger,
+    pub head_turn_id: String,
+    pub turns: Vec<SummaryTurn>,
+}
+
+/// Response expected from a summarizer.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SummaryResponse {
+    pub summary: String,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// Produces a summary for a batch of recent turns.
+///
+/// Returning `Ok(None)` skips the summary without treating it as an error.
+pub trait Summarizer: Send {
+    fn summarize(&self, request: &SummaryRequest) -> Result<Option<SummaryResponse>>;
+}
+
+/// Summarizer that POSTs the request as JSON to an HTTP endpoint.
+pub struct HttpSummarizer {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl HttpSummarizer {
+    pub fn new(url: String, timeout: Duration) -> Self {
+        let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+        Self { url, agent }
+    }
+}
+
+impl Summarizer for HttpSummarizer {
+    fn summarize(&self, request: &SummaryRequest) -> Result<Option<SummaryResponse>> {
+        let body = serde_json::to_value(request)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        let response = match self.agent.post(&self.url).send_json(body) {
+            Ok(resp) => resp,
+            // 204 or an explicit "nothing to add" from the summarizer
+            Err(ureq::Error::Status(204, _)) => return Ok(None),
+            Err(e) => {
+                return Err(StoreError::Io(std::io::Error::other(format!(
+                    "summary hook request failed: {e}"
+                ))))
+            }
+        };
+        if response.status() == 204 {
+            return Ok(None);
+        }
+        let parsed: SummaryResponse = response
+            .into_json()
+            .map_err(|e| StoreError::InvalidInput(format!("invalid summary hook response: {e}")))?;
+        if parsed.summary.trim().is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(parsed))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ContextActivity {
+    last_append: Instant,
+    pending_turns: u32,
+    /// Set after a failed summary; rules do not fire again before it.
+    retry_at: Option<Instant>,
+}
+
+/// Per-context activity tracking used to evaluate hook rules.
+#[derive(Debug, Default)]
+pub struct HookTracker {
+    activity: HashMap<u64, ContextActivity>,
+}
+
+impl HookTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a non-summary turn appended to a context.
+    pub fn record_append(&mut self, context_id: u64, now: Instant) {
+        let entry = self.activity.entry(context_id).or_insert(ContextActivity {
+            last_append: now,
+            pending_turns: 0,
+            retry_at: None,
+        });
+        entry.last_append = now;
+        entry.pending_turns += 1;
+    }
+
+    /// Forget pending turns for a context after it was summarized.
+    pub fn mark_summarized(&mut self, context_id: u64) {
+        self.activity.remove(&context_id);
+    }
+
+    /// Keep a context's pending turns after its summary failed, and hold
+    /// its rules back until `retry_at`.
+    pub fn mark_failed(&mut self, context_id: u64, retry_at: Instant) {
+        if let Some(activity) = self.activity.get_mut(&context_id) {
+            activity.retry_at = Some(retry_at);
+        }
+    }
+
+    /// Contexts whose rules fire at `now`, with the rule that fired.
+    /// The turn threshold takes precedence over idleness.
+    pub fn due(
+        &self,
+        now: Instant,
+        idle_timeout: Option<Duration>,
+        turn_threshold: Option<u32>,
+    ) -> Vec<(u64, HookTrigger)> {
+        let mut due: Vec<(u64, HookTrigger)> = self
+            .activity
+            .iter()
+            .filter(|(_, a)| a.pending_turns > 0)
+            .filter(|(_, a)| a.retry_at.is_none_or(|retry_at| now >= retry_at))
+            .filter_map(|(context_id, a)| {
+                if turn_threshold.is_some_and(|t| a.pending_turns >= t) {
+                    Some((*context_id, HookTrigger::TurnThreshold))
+                } else if idle_timeout.is_some_and(|t| now.duration_since(a.last_append) >= t) {
+                    Some((*context_id, HookTrigger::Idle))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        due.sort_by_key(|(context_id, _)| *context_id);
+        due
+    }
+
+    pub fn pending_turns(&self, context_id: u64) -> u32 {
+        self.activity
+            .get(&context_id)
+            .map(|a| a.pending_turns)
+            .unwrap_or(0)
+    }
+}
+
+/// Handle to stop the summary hook runner.
+pub struct SummaryHooks {
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl SummaryHooks {
+    /// Stop the runner once its current summary, if any, finishes.
+    pub fn shutdown(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.handle.join();
+    }
+}
+
+/// Start the summary hook runner on a background thread.
+pub fn start_summary_hooks(
+    config: SummaryHookConfig,
+    store: Arc<Mutex<Store>>,
+    registry: Arc<Mutex<Registry>>,
+    event_bus: Arc<EventBus>,
+) -> Result<SummaryHooks> {
+    let summarizer = HttpSummarizer::new(config.url.clone(), config.request_timeout);
+    let subscriber = event_bus.subscribe();
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopping = Arc::clone(&stop);
+    let handle = thread::spawn(move || {
+        let mut tracker = HookTracker::new();
+        while !stopping.load(Ordering::Relaxed) {
+            if let Some(StoreEvent::TurnAppended {
+                context_id,
+                declared_type_id,
+                ..
+            }) = subscriber.recv_timeout(Duration::from_secs(1))
+            {
+                let Ok(context_id) = context_id.parse::<u64>() else {
+                    continue;
+                };
+                if declared_type_id.as_deref() == Some(SUMMARY_TYPE_ID) {
+                    tracker.mark_summarized(context_id);
+                } else {
+                    tracker.record_append(context_id, Instant::now());
+                }
+            }
+
+            let due = tracker.due(Instant::now(), config.idle_timeout, config.turn_threshold);
+            for (context_id, trigger) in due {
+                let pending = tracker.pending_turns(context_id);
+                match run_summary(
+                    &config,
+                    &summarizer,
+                    &store,
+                    &registry,
+                    &event_bus,
+                    context_id,
+                    trigger,
+                    pending,
+                ) {
+                    Ok(()) => tracker.mark_summarized(context_id),
+                    Err(err) => {
+                        tracing::warn!(context_id, error = %err, "summary hook failed");
+                        tracker.mark_failed(context_id, Instant::now() + RETRY_DELAY);
+                    }
+                }
+                if stopping.load(Ordering::Relaxed) {
+                    break;
+                }
+            }
+        }
+    });
+    Ok(SummaryHooks { stop, handle })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_summary(
+    config: &SummaryHookConfig,
+    summarizer: &dyn Summarizer,
+    store: &Arc<Mutex<Store>>,
+    registry: &Arc<Mutex<Registry>>,
+    event_bus: &Arc<EventBus>,
+    context_id: u64,
+    trigger: HookTrigger,
+    pending_turns: u32,
+) -> Result<()> {
+    let Some((head_turn_id, request)) =
+        summary_request(config, store, registry, context_id, trigger)?
+    else {
+        return Ok(());
+    };
+    let Some(response) = summarizer.summarize(&request)? else {
+        return Ok(());
+    };
+
+    let payload = encode_summary_payload(
+        &response,
+        head_turn_id,
+        pending_turns,
+        trigger,
+        &config.name,
+        unix_ms(),
+    )?;
+    let hash = blake3::hash(&payload);
+
+    let mut store = store.lock().unwrap();
+    // Skip the append if the context moved on while the summarizer was running;
+    // the new turns will trigger another summary.
+    if store.get_head(context_id)?.head_turn_id != head_turn_id {
+        return Ok(());
+    }
+    let (record, _) = store.append_turn(
+        context_id,
+        head_turn_id,
+        SUMMARY_TYPE_ID.to_string(),
+        SUMMARY_TYPE_VERSION,
+        1,
+        0,
+        payload.len() as u32,
+        *hash.as_bytes(),
+        &payload,
+    )?;
+    drop(store);
+
+    event_bus.publish_recorded(StoreEvent::TurnAppended {
+        context_id: context_id.to_string(),
+        turn_id: record.turn_id.to_string(),
+        parent_turn_id: record.parent_turn_id.to_string(),
+        depth: record.depth,
+        declared_type_id: Some(SUMMARY_TYPE_ID.to_string()),
+        declared_type_version: Some(SUMMARY_TYPE_VERSION),
+    })?;
+    Ok(())
+}
+
+/// Collect the most recent turns of a context into a summarizer request,
+/// with the head turn it ends at. Returns None for an empty context.
+fn summary_request(
+    config: &SummaryHookConfig,
+    store: &Arc<Mutex<Store>>,
+    registry: &Arc<Mutex<Registry>>,
+    context_id: u64,
+    trigger: HookTrigger,
+) -> Result<Option<(u64, SummaryRequest)>> {
+    let (head_turn_id, turns) = {
+        let mut store = store.lock().unwrap();
+        let head = store.get_head(context_id)?;
+        let turns = store.get_last(context_id, config.recent_turns, true)?;
+        (head.head_turn_id, turns)
+    };
+    if turns.is_empty() {
+        return Ok(None);
+    }
+
+    let options = RenderOptions {
+        bytes_render: BytesRender::Base64,
+        u64_format: U64Format::String,
+        enum_render: EnumRender::Label,
+        time_render: TimeRender::Iso,
+        include_unknown: false,
+        apply_defaults: false,
+    };
+    let summary_turns = {
+        let registry = registry.lock().unwrap();
+        turns
+            .iter()
+            .map(|item| {
+                let payload = item.payload.as_deref().unwrap_or_default();
+                let data = registry
+                    .get_type_version(&item.meta.declared_type_id, item.meta.declared_type_version)
+                    .and_then(|desc| {
+                        crate::projection::project_msgpack(payload, desc, &registry, &options).ok()
+                    })
+                    .map(|p| p.data);
+                let bytes_b64 = data
+                    .is_none()
+                    .then(|| base64::engine::general_purpose::STANDARD.encode(payload));
+                SummaryTurn {
+                    turn_id: item.record.turn_id.to_string(),
+                    parent_turn_id: item.record.parent_turn_id.to_string(),
+                    depth: item.record.depth,
+                    type_id: item.meta.declared_type_id.clone(),
+                    type_version: item.meta.declared_type_version,
+                    data,
+                    bytes_b64,
+                }
+            })
+            .collect()
+    };
+
+    let request = SummaryRequest {
+        context_id: context_id.to_string(),
+        trigger,
+        head_turn_id: head_turn_id.to_string(),
+        turns: summary_turns,
+    };
+    Ok(Some((head_turn_id, request)))
+}
+
+/// Ask the summarizer for the digest that seeds a rollover of a context.
+/// Returns the digest and the head turn it covers, or None when the context
+/// is empty or the summarizer had nothing to add.
+pub fn rollover_digest(
+    config: &SummaryHookConfig,
+    summarizer: &dyn Summarizer,
+    store: &Arc<Mutex<Store>>,
+    registry: &Arc<Mutex<Registry>>,
+    context_id: u64,
+) -> Result<Option<(u64, SummaryResponse)>> {
+    let Some((head_turn_id, request)) =
+        summary_request(config, store, registry, context_id, HookTrigger::Rollover)?
+    else {
+        return Ok(None);
+    };
+    Ok(summarizer
+        .summarize(&request)?
+        .map(|response| (head_turn_id, response)))
+}
+
+/// Encode a summary turn payload as a msgpack map with numeric tags
+/// matching the `cxdb.ContextSummary` v1 descriptor.
+pub fn encode_summary_payload(
+    response: &SummaryResponse,
+    summarized_through_turn_id: u64,
+    turn_count: u32,
+    trigger: HookTrigger,
+    generator: &str,
+    generated_at_unix_ms: u64,
+) -> Result<Vec<u8>> {
+    let fields = summary_fields(
+        response,
+        summarized_through_turn_id,
+        turn_count,
+        trigger,
+        generator,
+        generated_at_unix_ms,
+    );
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &Value::Map(fields))
+        .map_err(|e| StoreError::InvalidInput(format!("msgpack encode error: {e}")))?;
+    Ok(buf)
+}
+
+/// The `cxdb.ContextSummary` v1 fields of a summary, for payloads that add
+/// their own keys.
+pub(crate) fn summary_fields(
+    response: &SummaryResponse,
+    summarized_through_turn_id: u64,
+    turn_count: u32,
+    trigger: HookTrigger,
+    generator: &str,
+    generated_at_unix_ms: u64,
+) -> Vec<(Value, Value)> {
+    let mut fields = vec![
+        (Value::from(1), Value::from(response.summary.as_str())),
+        (Value::from(2), Value::from(summarized_through_turn_id)),
+        (Value::from(3), Value::from(turn_count)),
+        (Value::from(4), Value::from(trigger.as_str())),
+        (Value::from(5), Value::from(generator)),
+    ];
+    if let Some(model) = &response.model {
+        fields.push((Value::from(6), Value::from(model.as_str())));
+    }
+    fields.push((Value::from(7), Value::from(true)));
+    fields.push((Value::from(8), Value::from(generated_at_unix_ms)));
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::builtin::ingest_builtin_bundles;
+
+    #[test]
+    fn test_turn_threshold_fires() {
+        let mut tracker = HookTracker::new();
+        let now = Instant::now();
+        tracker.record_append(1, now);
+        tracker.record_append(1, now);
+        tracker.record_append(2, now);
+
+        let due = tracker.due(now, None, Some(2));
+        assert_eq!(due, vec![(1, HookTrigger::TurnThreshold)]);
+    }
+
+    #[test]
+    fn test_idle_fires_and_resets() {
+        let mut tracker = HookTracker::new();
+        let start = Instant::now();
+        tracker.record_append(7, start);
+
+        let idle = Some(Duration::from_secs(60));
+        assert!(tracker.due(start, idle, None).is_empty());
+
+        let later = start + Duration::from_secs(61);
+        assert_eq!(tracker.due(later, idle, None), vec![(7, HookTrigger::Idle)]);
+
+        tracker.mark_summarized(7);
+        assert!(tracker.due(later, idle, None).is_empty());
+        assert_eq!(tracker.pending_turns(7), 0);
+    }
+
+    #[test]
+    fn test_failed_summary_is_retried() {
+        let mut tracker = HookTracker::new();
+        let now = Instant::now();
+        tracker.record_append(3, now);
+        tracker.record_append(3, now);
+
+        tracker.mark_failed(3, now + RETRY_DELAY);
+        assert!(tracker.due(now, None, Some(2)).is_empty());
+        assert_eq!(tracker.pending_turns(3), 2);
+        assert_eq!(
+            tracker.due(now + RETRY_DELAY, None, Some(2)),
+            vec![(3, HookTrigger::TurnThreshold)]
+        );
+    }
+
+    #[test]
+    fn test_summary_payload_projects() {
+        let response = SummaryResponse {
+            summary: "User asked about retries".to_string(),
+            model: Some("test-model".to_string()),
+        };
+        let payload = encode_summary_payload(
+            &response,
+            42,
+            5,
+            HookTrigger::Idle,
+            "hook",
+            1_700_000_000_000,
+        )
+        .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut registry = Registry::open(dir.path()).unwrap();
+        ingest_builtin_bundles(&mut registry, None).unwrap();
+        let desc = registry
+            .get_type_version(SUMMARY_TYPE_ID, SUMMARY_TYPE_VERSION)
+            .unwrap();
+        let options = RenderOptions {
+            bytes_render: BytesRender::Base64,
+            u64_format: U64Format::String,
+            enum_render: EnumRender::Label,
+            time_render: TimeRender::UnixMs,
+            include_unknown: false,
+            apply_defaults: false,
+        };
+        let projected =
+            crate::projection::project_msgpack(&payload, desc, &registry, &options).unwrap();
+        assert_eq!(projected.data["text"], "User asked about retries");
+        assert_eq!(projected.data["summarized_through_turn_id"], "42");
+        assert_eq!(projected.data["trigger"], "idle");
+        assert_eq!(projected.data["machine_generated"], true);
+        assert_eq!(projected.data["model"], "test-model");
+    }
+}
@@ -1,66 +1,344 @@
 // Copyright 2025 StrongDM Inc
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::HashMap;
-use std::io::Write;
+mod compression;
+mod msgpack;
+mod server;
+
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Read, Write};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use base64::Engine;
+use http::Method;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use serde_json::{json, Map, Value as JsonValue};
-use tiny_http::{Header, Method, Response, Server, StatusCode};
 use url::Url;
 
+use self::compression::ContentEncoding;
+use self::msgpack::MSGPACK_CONTENT_TYPE;
+use self::server::{Header, Request, Response};
+use crate::access::Access;
+use crate::access_log::{redact, AccessEntry, AccessLog};
+use crate::anchoring::Anchors;
+use crate::attachments::Attachment;
+use crate::backfill::{BackfillRequest, MappingFormat};
+use crate::bookmarks::Bookmark;
+use crate::config::Config;
+use crate::cpu_profile::{self, CpuProfiler, ProfileFormat};
+use crate::cql::CqlError;
+use crate::deadline::Deadline;
+use crate::diff::{diff_json, DiffOp, DiffOptions};
 use crate::error::{Result, StoreError};
 use crate::events::EventBus;
-use crate::fs_store::EntryKind;
-use crate::metrics::{Metrics, SessionTracker};
+use crate::events::StoreEvent;
+use crate::expiry::validate_ttl;
+use crate::export::{write_parquet, CsvStream, ExportFormat};
+use crate::federation::{merge_results, Federation, OriginSearch, RESIDENCY_HEADER};
+use crate::fs_store::detect::{detect, FileInfo};
+use crate::fs_store::{EntryKind, TreeEntry};
+use crate::holds::HoldEntry;
+use crate::hooks::{
+    rollover_digest, HttpSummarizer, SummaryHookConfig, SummaryResponse, SUMMARY_TYPE_ID,
+    SUMMARY_TYPE_VERSION,
+};
+use crate::memory::{heap_stats, MemoryConfig};
+use crate::metrics::{status_changed_event, Metrics, ProgressStatus, SessionTracker};
+use crate::oidc::{OidcVerifier, TokenIdentity};
+use crate::operations::Operations;
+use crate::ownership::{OwnershipTransfer, TransferRequest};
+use crate::presence::{Presence, ViewerId};
 use crate::projection::{BytesRender, EnumRender, RenderOptions, TimeRender, U64Format};
+use crate::projects::{Project, ProjectSpec};
+use crate::read_marks::ReadMark;
+use crate::registry::diff::DiffBase;
+use crate::registry::sync::{RegistrySync, SyncDirection, SyncMode};
 use crate::registry::{PutOutcome, Registry, RegistryBundle, RendererSpec, TypeVersionSpec};
-use crate::store::Store;
+use crate::renderer_assets::{asset_path, RendererAssets};
+use crate::replay::{ReplayControl, ReplaySpeed, ReplayWait, Replays};
+use crate::residency::{may_hold, residencies};
+use crate::rollovers::{Rollover, RolloverRequest, CLIENT_GENERATOR};
+use crate::shares::{ShareBound, ShareScope, ShareSpec, Shares};
+use crate::startup::Readiness;
+use crate::store::{ContextTreeNode, FsSnapshot, ProjectStats, Store, TurnWithMeta};
+use crate::subscriptions::{SubscriptionSpec, Subscriptions};
+use crate::tag_changes::{TagChange, TagMergeRequest, TagRenameRequest};
+use crate::thumbnails::{parse_width, Thumbnailer};
+use crate::turn_store::{
+    ChainLink, ContextHead, HeadAsOf, HeadCause, HeadMove, TurnAuthor, TurnRecord, ROOT_CHAIN_HASH,
+};
+use crate::watches::{WatchSpec, Watches};
 
 type HttpResponse = (u16, Response<std::io::Cursor<Vec<u8>>>);
 
+/// Header carrying a per-request time budget in milliseconds.
+const BUDGET_HEADER: &str = "X-CXDB-Budget-Ms";
+
+/// Header naming the authenticated principal, set by the gateway in front of
+/// the HTTP server. It is trusted as-is.
+const PRINCIPAL_HEADER: &str = "X-CXDB-Principal";
+
+/// Header carrying a share link token; the `share` query param also works.
+const SHARE_HEADER: &str = "X-CXDB-Share";
+
+/// Most context ids accepted by one `POST /v1/contexts/batch-get`.
+const MAX_BATCH_GET: usize = 1000;
+
+/// Default for `CXDB_HTTP_COMPRESS_MIN_BYTES`.
+const DEFAULT_COMPRESS_MIN_BYTES: usize = 8 * 1024;
+
+/// Default for `CXDB_HTTP_MAX_CONCURRENCY`.
+const DEFAULT_MAX_CONCURRENCY: usize = 64;
+
+/// Default for `CXDB_HTTP_MAX_BODY_BYTES`.
+const DEFAULT_MAX_BODY_BYTES: usize = 64 * 1024 * 1024;
+
+/// Default for `CXDB_HTTP_HEADER_TIMEOUT_MS`.
+const DEFAULT_HEADER_TIMEOUT_MS: u64 = 30_000;
+
+/// Default for `CXDB_HTTP_BODY_TIMEOUT_MS`.
+const DEFAULT_BODY_TIMEOUT_MS: u64 = 30_000;
+
+/// HTTP gateway settings, loaded from the environment.
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    /// Default time budget for expensive reads in milliseconds; 0 disables it.
+    /// Requests may ask for a shorter budget, never a longer one.
+    pub read_budget_ms: u64,
+    /// Smallest `/v1` JSON response body that is compressed when the client
+    /// accepts gzip or zstd; 0 disables compression.
+    pub compress_min_bytes: usize,
+    /// Requests handled at once; others wait for a slot. Event streams only
+    /// hold a slot while they are opened.
+    pub max_concurrency: usize,
+    /// Largest request body accepted; larger ones are answered 413. 0 means
+    /// no limit.
+    pub max_body_bytes: usize,
+    /// Time a connection has to send a request's headers; 0 disables it.
+    pub header_timeout_ms: u64,
+    /// Time a request has to send its body once the headers are in; 0
+    /// disables it.
+    pub body_timeout_ms: u64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            read_budget_ms: 0,
+            compress_min_bytes: DEFAULT_COMPRESS_MIN_BYTES,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            header_timeout_ms: DEFAULT_HEADER_TIMEOUT_MS,
+            body_timeout_ms: DEFAULT_BODY_TIMEOUT_MS,
+        }
+    }
+}
+
+impl HttpConfig {
+    pub fn from_env() -> Self {
+        let read_budget_ms = std::env::var("CXDB_HTTP_READ_BUDGET_MS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(0);
+        let compress_min_bytes = std::env::var("CXDB_HTTP_COMPRESS_MIN_BYTES")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_COMPRESS_MIN_BYTES);
+        let max_concurrency = std::env::var("CXDB_HTTP_MAX_CONCURRENCY")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(DEFAULT_MAX_CONCURRENCY);
+        let max_body_bytes = std::env::var("CXDB_HTTP_MAX_BODY_BYTES")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_BODY_BYTES);
+        let header_timeout_ms = std::env::var("CXDB_HTTP_HEADER_TIMEOUT_MS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_HEADER_TIMEOUT_MS);
+        let body_timeout_ms = std::env::var("CXDB_HTTP_BODY_TIMEOUT_MS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_BODY_TIMEOUT_MS);
+        Self {
+            read_budget_ms,
+            compress_min_bytes,
+            max_concurrency,
+            max_body_bytes,
+            header_timeout_ms,
+            body_timeout_ms,
+        }
+    }
+}
+
+/// Shared state handed to every HTTP request.
+#[derive(Clone)]
+pub struct HttpState {
+    pub config: HttpConfig,
+    pub store: Arc<Mutex<Store>>,
+    pub registry: Arc<Mutex<Registry>>,
+    pub metrics: Arc<Metrics>,
+    pub session_tracker: Arc<SessionTracker>,
+    pub event_bus: Arc<EventBus>,
+    pub operations: Arc<Operations>,
+    pub watches: Arc<Watches>,
+    pub readiness: Arc<Readiness>,
+    /// External anchoring of chain heads, when configured.
+    pub anchors: Option<Arc<Anchors>>,
+    pub presence: Arc<Presence>,
+    /// Access log, when `CXDB_HTTP_ACCESS_LOG` is set.
+    pub access_log: Option<Arc<AccessLog>>,
+    /// Image previews, when `CXDB_THUMBNAILS` is on.
+    pub thumbnails: Option<Arc<Thumbnailer>>,
+    /// Renderer module proxy, when `CXDB_RENDERER_ASSET_ORIGINS` is set.
+    pub renderer_assets: Option<Arc<RendererAssets>>,
+    /// Signed share links for read-only access to one context.
+    pub shares: Arc<Shares>,
+    pub subscriptions: Arc<Subscriptions>,
+    /// Bearer token verification, when `CXDB_OIDC_ISSUER` is set.
+    pub oidc: Option<Arc<OidcVerifier>>,
+    /// Heap profile dumps, when `CXDB_HEAP_PROFILE_DIR` is set.
+    pub memory: MemoryConfig,
+    /// On-demand CPU profile captures.
+    pub cpu_profiler: Arc<CpuProfiler>,
+    /// Summarizer that writes rollover digests, when `CXDB_SUMMARY_HOOK_URL`
+    /// is set.
+    pub summary_hook: Option<SummaryHookConfig>,
+    /// Running context replays and their pause/resume switches.
+    pub replays: Arc<Replays>,
+    /// Bundle pull and push, when `CXDB_REGISTRY_SYNC_REMOTES` is set.
+    pub registry_sync: Option<Arc<RegistrySync>>,
+    /// Read-only view of peer servers, when `CXDB_FEDERATION_PEERS` is set.
+    pub federation: Option<Arc<Federation>>,
+}
+
+/// Bind the gateway's TCP address and Unix socket, whichever are
+/// configured, and serve it on `runtime` until the returned task is aborted
+/// or the runtime shuts down.
 pub fn start_http(
-    bind_addr: String,
-    store: Arc<Mutex<Store>>,
-    registry: Arc<Mutex<Registry>>,
-    metrics: Arc<Metrics>,
-    session_tracker: Arc<SessionTracker>,
-    event_bus: Arc<EventBus>,
-) -> Result<thread::JoinHandle<()>> {
-    let server = Server::http(&bind_addr)
-        .map_err(|e| StoreError::InvalidInput(format!("http bind error: {e}")))?;
-    let handle = thread::spawn(move || {
-        for request in server.incoming_requests() {
-            if let Err(err) = handle_request(
-                request,
-                &store,
-                &registry,
-                &metrics,
-                &session_tracker,
-                &event_bus,
-            ) {
-                eprintln!("http error: {err}");
+    config: &Config,
+    state: HttpState,
+    runtime: &tokio::runtime::Handle,
+) -> Result<tokio::task::JoinHandle<()>> {
+    let mut listeners = Vec::new();
+    if let Some(bind_addr) = &config.http_bind_addr {
+        let listener = std::net::TcpListener::bind(bind_addr)
+            .map_err(|e| StoreError::InvalidInput(format!("http bind error: {e}")))?;
+        listeners.push(HttpListener::Tcp(listener));
+    }
+    if let Some(path) = &config.http_unix_socket_path {
+        #[cfg(unix)]
+        listeners.push(HttpListener::Unix(crate::unix_socket::bind(
+            path,
+            config.http_unix_socket_mode,
+        )?));
+        #[cfg(not(unix))]
+        return Err(StoreError::InvalidInput(format!(
+            "cannot serve HTTP on {}: Unix sockets are not supported on this platform",
+            path.display()
+        )));
+    }
+    serve_http_on(listeners, state, runtime)
+}
+
+/// A bound socket the gateway accepts connections on.
+pub enum HttpListener {
+    Tcp(std::net::TcpListener),
+    /// Requests over a Unix socket have no remote address.
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener),
+}
+
+/// Serve the gateway on an already bound listener.
+pub fn serve_http(
+    listener: std::net::TcpListener,
+    state: HttpState,
+    runtime: &tokio::runtime::Handle,
+) -> Result<tokio::task::JoinHandle<()>> {
+    serve_http_on(vec![HttpListener::Tcp(listener)], state, runtime)
+}
+
+/// Serve the gateway on already bound listeners, which share one
+/// `max_concurrency` limit.
+pub fn serve_http_on(
+    listeners: Vec<HttpListener>,
+    state: HttpState,
+    runtime: &tokio::runtime::Handle,
+) -> Result<tokio::task::JoinHandle<()>> {
+    if listeners.is_empty() {
+        return Err(StoreError::InvalidInput(
+            "the HTTP gateway needs CXDB_HTTP_BIND or CXDB_HTTP_UNIX_SOCKET".into(),
+        ));
+    }
+    let _runtime = runtime.enter();
+    let listeners = listeners
+        .into_iter()
+        .map(|listener| match listener {
+            HttpListener::Tcp(listener) => {
+                listener.set_nonblocking(true)?;
+                Ok(server::Listener::Tcp(tokio::net::TcpListener::from_std(
+                    listener,
+                )?))
             }
-        }
-    });
-    Ok(handle)
+            #[cfg(unix)]
+            HttpListener::Unix(listener) => {
+                listener.set_nonblocking(true)?;
+                Ok(server::Listener::Unix(tokio::net::UnixListener::from_std(
+                    listener,
+                )?))
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(runtime.spawn(server::serve(listeners, state)))
 }
 
-fn handle_request(
-    mut request: tiny_http::Request,
-    store: &Arc<Mutex<Store>>,
-    registry: &Arc<Mutex<Registry>>,
-    metrics: &Arc<Metrics>,
-    session_tracker: &Arc<SessionTracker>,
-    event_bus: &Arc<EventBus>,
-) -> Result<()> {
+fn handle_request(mut request: Request, state: &HttpState) -> Result<()> {
+    let HttpState {
+        config,
+        store,
+        registry,
+        metrics,
+        session_tracker,
+        event_bus,
+        operations,
+        watches,
+        readiness,
+        anchors,
+        presence,
+        access_log,
+        thumbnails,
+        renderer_assets,
+        shares,
+        subscriptions,
+        oidc,
+        memory,
+        cpu_profiler,
+        summary_hook,
+        replays,
+        registry_sync,
+        federation,
+    } = state;
     let start = Instant::now();
 
+    // A verified bearer token names the principal in place of the
+    // gateway's header.
+    let token = match oidc {
+        Some(oidc) => oidc.authenticate(header_value(&request, "Authorization").as_deref()),
+        None => Ok(None),
+    };
+    let principal = match &token {
+        Ok(Some(identity)) => Some(identity.principal.clone()),
+        Ok(None) => request_principal(&request),
+        Err(_) => None,
+    };
+
     // Check for SSE request early - it needs special handling
     let url_str = format!("http://localhost{}", request.url());
+    let mut route = String::from("other");
+    let mut access = None;
     if let Ok(url) = Url::parse(&url_str) {
         let segments: Vec<String> = url
             .path_segments()
@@ -68,44 +346,177 @@ fn handle_request(
             .unwrap_or_default();
         let segments_ref: Vec<&str> = segments.iter().map(|s| s.as_str()).collect();
 
-        if request.method() == &Method::Get && segments_ref.as_slice() == ["v1", "events"] {
-            return handle_sse_stream(request, event_bus);
+        route = route_template(&segments_ref);
+        access = PendingAccess::begin(
+            access_log.as_deref(),
+            &request,
+            principal.clone(),
+            &route,
+            request_context_id(&segments_ref, &url),
+            start,
+        );
+        if !readiness.is_ready() && needs_warm_store(&segments_ref) {
+            return respond_warming(request, readiness, metrics, access, &route, start);
+        }
+        // Share-token requests skip the streaming routes below; the share
+        // scope check refuses them.
+        let shared = request_share_token(&request, &url).is_some();
+        // Unauthorized requests fall through to the buffered path, which
+        // reports why.
+        let authorized = check_bearer(
+            oidc.as_deref(),
+            &token,
+            request.method(),
+            &segments_ref,
+            shared,
+        )
+        .is_ok();
+        if request.method() == Method::GET
+            && segments_ref.as_slice() == ["v1", "events"]
+            && !shared
+            && authorized
+        {
+            let viewing = match sse_viewing(&url, &request, principal.as_deref(), store) {
+                Ok(viewing) => viewing,
+                Err(err) => {
+                    let (status, message) = map_error(&err);
+                    let response = sse_error_response(status, &message);
+                    PendingAccess::finish(access, status, response.data_length());
+                    let _ = request.respond(response);
+                    return Ok(());
+                }
+            };
+            let presence = viewing.map(|(context_id, viewer)| (presence, context_id, viewer));
+            return handle_sse_stream(request, event_bus, presence, access);
+        }
+        if let (&Method::GET, ["v1", "contexts", context_id, "replay"]) =
+            (request.method(), segments_ref.as_slice())
+        {
+            if !shared && authorized {
+                let replay = start_replay(context_id, &url, store, replays);
+                return handle_replay_stream(request, replay, store, registry, replays, access);
+            }
+        }
+        // Exports stream their body, so they bypass the buffered responses below.
+        if request.method() == Method::GET
+            && segments_ref.as_slice() == ["v1", "contexts", "export"]
+            && !shared
+            && authorized
+        {
+            let result = export_response(&url, store, session_tracker);
+            return respond_streamed(request, result, metrics, access, &route, start);
         }
     }
+    let access = access.or_else(|| {
+        PendingAccess::begin(
+            access_log.as_deref(),
+            &request,
+            principal.clone(),
+            &route,
+            None,
+            start,
+        )
+    });
+    let method_label = request.method().as_str().to_string();
 
+    // Context of a request made through a `latest` or `head` turn alias.
+    let mut alias_context = None;
     let result: Result<HttpResponse> = (|| {
         let method = request.method().clone();
         let url_str = format!("http://localhost{}", request.url());
         let url =
             Url::parse(&url_str).map_err(|_| StoreError::InvalidInput("invalid url".into()))?;
-        let segments: Vec<String> = url
+        let mut segments: Vec<String> = url
             .path_segments()
             .map(|c| c.map(|s| s.to_string()).collect())
             .unwrap_or_default();
+        // A share token confines the request to what its share grants.
+        let share = match request_share_token(&request, &url) {
+            Some(token) => Some(shares.verify(&token)?),
+            None => None,
+        };
+        if let (Some(claims), [v1, contexts, context_id, ..]) = (&share, segments.as_slice()) {
+            if v1 == "v1"
+                && contexts == "contexts"
+                && *context_id != claims.scope.context_id.to_string()
+            {
+                return Err(share_denied());
+            }
+        }
+        // `/v1/contexts/:id/turns/latest/...` stands for the single-turn
+        // route `/v1/turns/<head turn>/...` of the context's current head.
+        if let Some((context_id, canonical)) = resolve_head_alias(&segments, store)? {
+            alias_context = Some(context_id);
+            let params = parse_query(url.query().unwrap_or(""));
+            if params.get("follow_head").is_some_and(|v| v == "1") {
+                return Ok(head_redirect(&canonical, &url));
+            }
+            segments = canonical;
+        }
         let segments_ref: Vec<&str> = segments.iter().map(|s| s.as_str()).collect();
+        let share_scope = share.as_ref().map(|claims| &claims.scope);
+        if let Some(scope) = share_scope {
+            check_share_scope(&method, &segments_ref, &url, scope, store)?;
+        }
+        let peer_residencies = federation_residencies(&request);
+        if let Some(allowed) = &peer_residencies {
+            check_federated_residency(&segments_ref, allowed, store)?;
+        }
+        check_bearer(
+            oidc.as_deref(),
+            &token,
+            &method,
+            &segments_ref,
+            share.is_some(),
+        )?;
 
         match (method, segments_ref.as_slice()) {
             // Health check endpoint
-            (Method::Get, ["healthz"]) => Ok((
+            (Method::GET, ["healthz"]) => Ok((
                 200,
                 Response::from_data(b"ok".to_vec())
-                    .with_status_code(StatusCode(200))
-                    .with_header(
-                        Header::from_bytes(&b"Content-Type"[..], &b"text/plain"[..]).unwrap(),
-                    ),
+                    .with_status_code(200)
+                    .with_header(Header::new("Content-Type", "text/plain")),
             )),
-            (Method::Put, ["v1", "registry", "bundles", _bundle_id_raw]) => {
+            // Readiness: 503 until the startup index build has finished
+            (Method::GET, ["readyz"]) => {
+                let status = readiness.status();
+                if readiness.is_ready() {
+                    return json_response(200, &json!(status));
+                }
+                let (code, response) = json_response(503, &json!(status))?;
+                Ok((code, with_retry_after(response, readiness)))
+            }
+            (Method::PUT, ["v1", "registry", "bundles", _bundle_id_raw]) => {
                 let mut body = Vec::new();
                 request.as_reader().read_to_end(&mut body)?;
-                let bundle: RegistryBundle = serde_json::from_slice(&body)
-                    .map_err(|e| StoreError::InvalidInput(format!("invalid json: {e}")))?;
+                let bundle: RegistryBundle = parse_body(&request, &body)?;
                 let body_id = bundle.bundle_id.clone();
                 let mut registry = registry.lock().unwrap();
+
+                // Merge mode applies only the additions to an existing bundle
+                let params = parse_query(url.query().unwrap_or(""));
+                if params.get("mode").map(|s| s.as_str()) == Some("merge") {
+                    let report = registry.merge_bundle(&body_id, &body)?;
+                    let status = if !report.conflicts.is_empty() {
+                        409
+                    } else if report.created {
+                        201
+                    } else {
+                        200
+                    };
+                    if status != 409 {
+                        metrics.record_registry_ingest();
+                    }
+                    let body = serde_json::to_value(&report)
+                        .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                    return json_response(status, &body);
+                }
+
                 match registry.put_bundle(&body_id, &body)? {
-                    PutOutcome::AlreadyExists => Ok((
-                        204,
-                        Response::from_data(Vec::new()).with_status_code(StatusCode(204)),
-                    )),
+                    PutOutcome::AlreadyExists => {
+                        Ok((204, Response::from_data(Vec::new()).with_status_code(204)))
+                    }
                     PutOutcome::Created => {
                         metrics.record_registry_ingest();
                         let bytes =
@@ -115,19 +526,17 @@ fn handle_request(
                         Ok((
                             201,
                             Response::from_data(bytes)
-                                .with_status_code(StatusCode(201))
-                                .with_header(
-                                    Header::from_bytes(
-                                        &b"Content-Type"[..],
-                                        &b"application/json"[..],
-                                    )
-                                    .unwrap(),
-                                ),
+                                .with_status_code(201)
+                                .with_header(Header::new("Content-Type", "application/json")),
                         ))
                     }
                 }
             }
-            (Method::Get, ["v1", "registry", "bundles", bundle_id]) => {
+            (Method::GET, ["v1", "registry", "bundles"]) => {
+                let registry = registry.lock().unwrap();
+                json_response(200, &json!({ "bundles": registry.list_bundles() }))
+            }
+            (Method::GET, ["v1", "registry", "bundles", bundle_id]) => {
                 let registry = registry.lock().unwrap();
                 let bundle = registry
                     .get_bundle(bundle_id)
@@ -136,27 +545,32 @@ fn handle_request(
                 if let Some(header) = request
                     .headers()
                     .iter()
-                    .find(|h| h.field.equiv("If-None-Match"))
+                    .find(|h| h.field == "If-None-Match")
                 {
                     if header.value.as_str() == etag {
-                        return Ok((
-                            304,
-                            Response::from_data(Vec::new()).with_status_code(StatusCode(304)),
-                        ));
+                        return Ok((304, Response::from_data(Vec::new()).with_status_code(304)));
                     }
                 }
                 Ok((
                     200,
                     Response::from_data(bundle.to_vec())
-                        .with_status_code(StatusCode(200))
-                        .with_header(
-                            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
-                                .unwrap(),
-                        )
-                        .with_header(Header::from_bytes(&b"ETag"[..], etag.as_bytes()).unwrap()),
+                        .with_status_code(200)
+                        .with_header(Header::new("Content-Type", "application/json"))
+                        .with_header(Header::new("ETag", &etag)),
                 ))
             }
-            (Method::Get, ["v1", "registry", "types", type_id, "versions", version]) => {
+            (Method::GET, ["v1", "registry", "bundles", bundle_id, "diff"]) => {
+                let params = parse_query(url.query().unwrap_or(""));
+                let base = match params.get("against").map(String::as_str) {
+                    None | Some("current") => DiffBase::Current,
+                    Some(other) => DiffBase::Bundle(other),
+                };
+                let diff = registry.lock().unwrap().diff_bundle(bundle_id, base)?;
+                let body = serde_json::to_value(&diff)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(200, &body)
+            }
+            (Method::GET, ["v1", "registry", "types", type_id, "versions", version]) => {
                 let version: u32 = version
                     .parse()
                     .map_err(|_| StoreError::InvalidInput("invalid version".into()))?;
@@ -170,19 +584,54 @@ fn handle_request(
                 Ok((
                     200,
                     Response::from_data(bytes)
-                        .with_status_code(StatusCode(200))
-                        .with_header(
-                            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
-                                .unwrap(),
-                        ),
+                        .with_status_code(200)
+                        .with_header(Header::new("Content-Type", "application/json")),
+                ))
+            }
+            (Method::GET, ["v1", "registry", "renderers", "assets", type_id]) => {
+                let renderer_assets = renderer_assets.as_ref().ok_or_else(|| {
+                    StoreError::NotFound(
+                        "renderer asset proxy is not enabled (set CXDB_RENDERER_ASSET_ORIGINS)"
+                            .into(),
+                    )
+                })?;
+                let type_id = decode_segment(type_id)?;
+                let spec = registry
+                    .lock()
+                    .unwrap()
+                    .get_renderer(&type_id)
+                    .cloned()
+                    .ok_or_else(|| StoreError::NotFound("renderer".into()))?;
+                // Only the lookup holds the registry lock; fetching does not.
+                let asset = renderer_assets.asset(&type_id, &spec)?;
+                if header_value(&request, "If-None-Match").as_deref() == Some(asset.etag.as_str()) {
+                    return Ok((304, Response::from_data(Vec::new()).with_status_code(304)));
+                }
+                Ok((
+                    200,
+                    Response::from_data(asset.body.to_vec())
+                        .with_status_code(200)
+                        .with_header(Header::new(
+                            "Content-Type",
+                            "text/javascript; charset=utf-8",
+                        ))
+                        .with_header(Header::new("ETag", &asset.etag))
+                        // The module changes with the type's renderer spec.
+                        .with_header(Header::new("Cache-Control", "no-cache")),
                 ))
             }
-            (Method::Get, ["v1", "registry", "renderers"]) => {
+            (Method::GET, ["v1", "registry", "renderers"]) => {
                 let registry = registry.lock().unwrap();
                 let renderers = registry.get_all_renderers();
                 let renderers_json: serde_json::Map<String, JsonValue> = renderers
                     .into_iter()
-                    .map(|(type_id, spec)| (type_id, renderer_spec_to_json(&spec)))
+                    .map(|(type_id, spec)| {
+                        let mut json = renderer_spec_to_json(&spec);
+                        if renderer_assets.as_ref().is_some_and(|a| a.proxies(&spec)) {
+                            json["asset_url"] = JsonValue::String(asset_path(&type_id));
+                        }
+                        (type_id, json)
+                    })
                     .collect();
                 let resp = json!({ "renderers": JsonValue::Object(renderers_json) });
                 let bytes = serde_json::to_vec(&resp)
@@ -190,92 +639,89 @@ fn handle_request(
                 Ok((
                     200,
                     Response::from_data(bytes)
-                        .with_status_code(StatusCode(200))
-                        .with_header(
-                            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
-                                .unwrap(),
-                        ),
+                        .with_status_code(200)
+                        .with_header(Header::new("Content-Type", "application/json")),
                 ))
             }
-            (Method::Get, ["v1", "contexts"]) => {
+            (Method::GET, ["v1", "contexts"]) => {
                 let params = parse_query(url.query().unwrap_or(""));
                 let limit = params
                     .get("limit")
                     .and_then(|v| v.parse::<u32>().ok())
                     .unwrap_or(20);
                 let tag_filter = params.get("tag").cloned();
+                let project_filter = params.get("project").cloned();
                 let include_provenance = params
                     .get("include_provenance")
                     .map(|v| v == "1")
                     .unwrap_or(false);
+                let principal = principal.clone();
+
+                if let Some(raw) = params.get("as_of") {
+                    let as_of = parse_as_of(raw)?;
+                    let mut store = store.lock().unwrap();
+                    let members = match &project_filter {
+                        Some(project) => Some(store.project_contexts(project)?),
+                        None => None,
+                    };
+                    let window_ms = session_tracker.liveness().window.as_millis() as u64;
+                    let contexts_json: Vec<JsonValue> = store
+                        .list_contexts_as_of(as_of, u32::MAX)
+                        .iter()
+                        .filter(|entry| {
+                            members
+                                .as_ref()
+                                .is_none_or(|m| m.binary_search(&entry.head.context_id).is_ok())
+                        })
+                        .map(|entry| historical_context_json(&mut store, entry, as_of, window_ms))
+                        .filter(|obj| match &tag_filter {
+                            Some(filter) => obj["client_tag"].as_str().unwrap_or("") == filter,
+                            None => true,
+                        })
+                        .take(limit as usize)
+                        .collect();
+                    return json_response(
+                        200,
+                        &json!({
+                            "historical": true,
+                            "as_of": as_of,
+                            "liveness": "activity",
+                            "window_ms": window_ms,
+                            "contexts": contexts_json,
+                            "count": contexts_json.len(),
+                        }),
+                    );
+                }
 
                 let mut store = store.lock().unwrap();
-                let contexts = store.list_recent_contexts(limit);
+                let contexts = match &project_filter {
+                    Some(project) => {
+                        let members = store.project_contexts(project)?;
+                        store
+                            .list_recent_contexts(u32::MAX)
+                            .into_iter()
+                            .filter(|head| members.binary_search(&head.context_id).is_ok())
+                            .take(limit as usize)
+                            .collect()
+                    }
+                    None => store.list_recent_contexts(limit),
+                };
 
                 let contexts_json: Vec<JsonValue> = contexts
                     .iter()
-                    .filter_map(|c| {
-                        // Get session info for this context (for live status)
-                        let session = session_tracker.get_session_for_context(c.context_id);
-                        let session_id = session.as_ref().map(|s| s.session_id);
-                        let is_live = session.is_some();
-                        let last_activity_at = session.as_ref().map(|s| s.last_activity_at);
-                        let session_peer_addr = session.as_ref().and_then(|s| s.peer_addr.clone());
-
-                        // Get client_tag: prefer stored metadata, fall back to session
-                        let stored_metadata = store.get_context_metadata(c.context_id);
-                        let client_tag = stored_metadata
-                            .as_ref()
-                            .and_then(|m| m.client_tag.clone())
-                            .or_else(|| session.as_ref().map(|s| s.client_tag.clone()))
-                            .filter(|t| !t.is_empty());
-
+                    .map(|c| {
+                        context_json(
+                            &mut store,
+                            session_tracker,
+                            c,
+                            principal.as_deref(),
+                            include_provenance,
+                        )
+                    })
+                    .filter(|obj| match &tag_filter {
                         // Apply tag filter if specified
-                        if let Some(ref filter) = tag_filter {
-                            let tag = client_tag.as_deref().unwrap_or("");
-                            if tag != filter {
-                                return None;
-                            }
-                        }
-
-                        let mut obj = json!({
-                            "context_id": c.context_id.to_string(),
-                            "head_turn_id": c.head_turn_id.to_string(),
-                            "head_depth": c.head_depth,
-                            "created_at_unix_ms": c.created_at_unix_ms,
-                            "is_live": is_live,
-                        });
-
-                        if let Some(tag) = client_tag {
-                            obj["client_tag"] = JsonValue::String(tag);
-                        }
-                        if let Some(sid) = session_id {
-                            obj["session_id"] = JsonValue::String(sid.to_string());
-                        }
-                        if let Some(ts) = last_activity_at {
-                            obj["last_activity_at"] = JsonValue::Number(ts.into());
-                        }
-
-                        // Include provenance if requested
-                        if include_provenance {
-                            if let Some(ref metadata) = stored_metadata {
-                                if let Some(ref prov) = metadata.provenance {
-                                    // Clone provenance and inject server-side client_address if not present
-                                    let mut prov_with_server_info = prov.clone();
-                                    if prov_with_server_info.client_address.is_none() {
-                                        prov_with_server_info.client_address =
-                                            session_peer_addr.clone();
-                                    }
-                                    if let Ok(prov_json) =
-                                        serde_json::to_value(&prov_with_server_info)
-                                    {
-                                        obj["provenance"] = prov_json;
-                                    }
-                                }
-                            }
-                        }
-
-                        Some(obj)
+                        Some(filter) => obj["client_tag"].as_str().unwrap_or("") == filter,
+                        None => true,
                     })
                     .collect();
 
@@ -290,10 +736,23 @@ fn handle_request(
                             "connected_at": s.connected_at,
                             "last_activity_at": s.last_activity_at,
                             "context_count": s.contexts_created.len(),
+                            "unsupported_messages": s.unsupported_messages,
                         });
                         if let Some(ref addr) = s.peer_addr {
                             session_obj["peer_addr"] = JsonValue::String(addr.clone());
                         }
+                        if let Some(ref principal) = s.principal {
+                            session_obj["principal"] = JsonValue::String(principal.clone());
+                        }
+                        if let Some(ref credentials) = s.peer_credentials {
+                            session_obj["peer_credentials"] = json!(credentials);
+                        }
+                        if let Some(detached_at) = s.detached_at {
+                            session_obj["detached_at"] = json!(detached_at);
+                        }
+                        if let Some(ref status) = s.status {
+                            session_obj["status"] = json!(status);
+                        }
                         session_obj
                     })
                     .collect();
@@ -313,15 +772,261 @@ fn handle_request(
                 Ok((
                     200,
                     Response::from_data(bytes)
-                        .with_status_code(StatusCode(200))
-                        .with_header(
-                            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
-                                .unwrap(),
-                        ),
+                        .with_status_code(200)
+                        .with_header(Header::new("Content-Type", "application/json")),
                 ))
             }
+            (Method::POST, ["v1", "contexts", "create"]) => {
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let parsed: JsonValue = if body.iter().all(u8::is_ascii_whitespace) {
+                    json!({})
+                } else {
+                    parse_body(&request, &body)?
+                };
+                let base_turn_id = match parsed.get("base_turn_id") {
+                    None | Some(JsonValue::Null) => 0,
+                    Some(raw) => json_u64(raw)
+                        .ok_or_else(|| StoreError::InvalidInput("invalid base_turn_id".into()))?,
+                };
+                let external_id = match parsed.get("external_id") {
+                    None | Some(JsonValue::Null) => None,
+                    Some(JsonValue::String(id)) => Some(id.as_str()),
+                    Some(_) => {
+                        return Err(StoreError::InvalidInput(
+                            "external_id must be a string".into(),
+                        ))
+                    }
+                };
+
+                let ttl = match parsed.get("ttl_secs") {
+                    None | Some(JsonValue::Null) => None,
+                    Some(raw) => {
+                        let ttl_secs = json_u64(raw)
+                            .ok_or_else(|| StoreError::InvalidInput("invalid ttl_secs".into()))?;
+                        Some(validate_ttl(ttl_secs)?)
+                    }
+                };
+
+                let mut store = store.lock().unwrap();
+                let head = store.create_context_with_ttl(base_turn_id, external_id, ttl)?;
+                let expires_at = store.expiry(head.context_id).map(|e| e.expires_at_unix_ms);
+                drop(store);
+                event_bus.publish_recorded(StoreEvent::ContextCreated {
+                    context_id: head.context_id.to_string(),
+                    session_id: String::new(),
+                    client_tag: String::new(),
+                    created_at: head.created_at_unix_ms,
+                    external_id: external_id.map(str::to_string),
+                })?;
+                let mut obj = json!({
+                    "context_id": head.context_id.to_string(),
+                    "head_turn_id": head.head_turn_id.to_string(),
+                    "head_depth": head.head_depth,
+                });
+                if let Some(external_id) = external_id {
+                    obj["external_id"] = json!(external_id);
+                }
+                if let Some(expires_at) = expires_at {
+                    obj["expires_at"] = json!(expires_at);
+                }
+                json_response(201, &obj)
+            }
+            (Method::GET, ["v1", "contexts", "by-external-id", external_id]) => {
+                let external_id = decode_segment(external_id)?;
+                let params = parse_query(url.query().unwrap_or(""));
+                let include_provenance = params
+                    .get("include_provenance")
+                    .map(|v| v == "1")
+                    .unwrap_or(false);
+                let principal = principal.clone();
+
+                let mut store = store.lock().unwrap();
+                let context_id = store.context_by_external_id(&external_id).ok_or_else(|| {
+                    StoreError::NotFound(format!("no context with external_id {external_id:?}"))
+                })?;
+                let head = store.get_head(context_id)?;
+                let mut obj = context_json(
+                    &mut store,
+                    session_tracker,
+                    &head,
+                    principal.as_deref(),
+                    include_provenance,
+                );
+                drop(store);
+                obj["viewers"] = json!(presence.viewers(context_id));
+                json_response(200, &obj)
+            }
+            // Details for a list of contexts in one request
+            (Method::POST, ["v1", "contexts", "transfer"]) => {
+                let principal = principal.clone().ok_or_else(|| {
+                    StoreError::InvalidInput(format!("{PRINCIPAL_HEADER} header required"))
+                })?;
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let spec: TransferRequest = parse_body(&request, &body)?;
+                let filter = spec.filter.as_deref().ok_or_else(|| {
+                    StoreError::InvalidInput("filter is required for a bulk transfer".into())
+                })?;
+                let live_contexts = session_tracker.get_live_context_ids();
+                let bulk = store.lock().unwrap().transfer_ownership_matching(
+                    filter,
+                    &live_contexts,
+                    &spec.owner,
+                    &spec.reason,
+                    &principal,
+                )?;
+                for entry in &bulk.transferred {
+                    event_bus.publish(ownership_changed(entry));
+                }
+                tracing::info!(
+                    principal = %principal,
+                    owner = %spec.owner.trim(),
+                    filter,
+                    transferred = bulk.transferred.len(),
+                    "Contexts transferred"
+                );
+                json_response(
+                    200,
+                    &json!({
+                        "owner": spec.owner.trim(),
+                        "transferred": bulk.transferred.iter().map(transfer_json).collect::<Vec<_>>(),
+                        "unchanged": bulk.unchanged.iter().map(|id| id.to_string()).collect::<Vec<_>>(),
+                    }),
+                )
+            }
+            (Method::POST, ["v1", "contexts", "batch-get"]) => {
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let parsed: JsonValue = parse_body(&request, &body)?;
+                let ids = parsed
+                    .get("context_ids")
+                    .and_then(|v| v.as_array())
+                    .ok_or_else(|| StoreError::InvalidInput("context_ids array required".into()))?;
+                if ids.len() > MAX_BATCH_GET {
+                    return Err(StoreError::InvalidInput(format!(
+                        "at most {MAX_BATCH_GET} context_ids per request"
+                    )));
+                }
+                let include_provenance = parsed
+                    .get("include_provenance")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let principal = principal.clone();
+
+                let mut store = store.lock().unwrap();
+                let contexts: Vec<JsonValue> = ids
+                    .iter()
+                    .map(|raw| {
+                        let head = json_u64(raw)
+                            .ok_or_else(|| StoreError::InvalidInput("invalid context_id".into()))
+                            .and_then(|id| store.get_head(id));
+                        match head {
+                            Ok(head) => context_json(
+                                &mut store,
+                                session_tracker,
+                                &head,
+                                principal.as_deref(),
+                                include_provenance,
+                            ),
+                            Err(err) => {
+                                let (code, message) = map_error(&err);
+                                let context_id = match raw {
+                                    JsonValue::Number(n) => JsonValue::String(n.to_string()),
+                                    other => other.clone(),
+                                };
+                                json!({
+                                    "context_id": context_id,
+                                    "error": { "code": code, "message": message },
+                                })
+                            }
+                        }
+                    })
+                    .collect();
+                json_response(200, &json!({ "contexts": contexts }))
+            }
+            // Side-by-side comparison of two branches from their common ancestor
+            (Method::GET, ["v1", "contexts", "compare"]) => {
+                let params = parse_query(url.query().unwrap_or(""));
+                let context_param = |name: &str| -> Result<u64> {
+                    params
+                        .get(name)
+                        .and_then(|v| v.parse::<u64>().ok())
+                        .ok_or_else(|| StoreError::InvalidInput(format!("{name} is required")))
+                };
+                let a = context_param("a")?;
+                let b = context_param("b")?;
+                let limit = params
+                    .get("limit")
+                    .and_then(|v| v.parse::<u32>().ok())
+                    .unwrap_or(64);
+                let options = parse_render_options(&params);
+                let deadline = request_deadline(config, &request, &params);
+
+                let comparison = store.lock().unwrap().compare_contexts(a, b, limit)?;
+
+                let registry = registry.lock().unwrap();
+                let branch =
+                    |context_id: u64, side: &crate::store::BranchComparison| -> Result<JsonValue> {
+                        let mut turns = Vec::with_capacity(side.turns.len());
+                        for item in &side.turns {
+                            let desc = registry
+                                .get_type_version(
+                                    &item.meta.declared_type_id,
+                                    item.meta.declared_type_version,
+                                )
+                                .ok_or_else(|| StoreError::NotFound("type descriptor".into()))?;
+                            let payload = item.payload.as_ref().ok_or_else(|| {
+                                StoreError::InvalidInput("payload not loaded".into())
+                            })?;
+                            let projected = crate::projection::cache::project_cached(
+                                &item.record.payload_hash,
+                                payload,
+                                &item.meta.declared_type_id,
+                                desc,
+                                &registry,
+                                &options,
+                                &deadline,
+                            )?;
+                            turns.push(json!({
+                                "turn_id": item.record.turn_id.to_string(),
+                                "parent_turn_id": item.record.parent_turn_id.to_string(),
+                                "depth": item.record.depth,
+                                "created_at_unix_ms": item.record.created_at_unix_ms,
+                                "declared_type": {
+                                    "type_id": item.meta.declared_type_id,
+                                    "type_version": item.meta.declared_type_version,
+                                },
+                                "data": projected.data,
+                            }));
+                        }
+                        Ok(json!({
+                            "context_id": context_id.to_string(),
+                            "head_turn_id": side.head.head_turn_id.to_string(),
+                            "head_depth": side.head.head_depth,
+                            "stats": side.stats,
+                            "truncated": (turns.len() as u64) < side.stats.divergent_turns,
+                            "turns": turns,
+                        }))
+                    };
+                let common_ancestor = comparison.common_ancestor.as_ref().map(|t| {
+                    json!({
+                        "turn_id": t.turn_id.to_string(),
+                        "depth": t.depth,
+                        "created_at_unix_ms": t.created_at_unix_ms,
+                    })
+                });
+                json_response(
+                    200,
+                    &json!({
+                        "common_ancestor": common_ancestor,
+                        "a": branch(a, &comparison.a)?,
+                        "b": branch(b, &comparison.b)?,
+                    }),
+                )
+            }
             // CQL search endpoint
-            (Method::Get, ["v1", "contexts", "search"]) => {
+            (Method::GET, ["v1", "contexts", "search"]) => {
                 let params = parse_query(url.query().unwrap_or(""));
                 let query = params.get("q").cloned().unwrap_or_default();
                 let limit = params.get("limit").and_then(|v| v.parse::<u32>().ok());
@@ -335,76 +1040,30 @@ fn handle_request(
                             }))
                             .unwrap(),
                         )
-                        .with_status_code(StatusCode(400))
-                        .with_header(
-                            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
-                                .unwrap(),
-                        ),
+                        .with_status_code(400)
+                        .with_header(Header::new("Content-Type", "application/json")),
                     ));
                 }
 
-                // Get live context IDs from session tracker
-                let live_contexts = session_tracker.get_live_context_ids();
-
-                let store = store.lock().unwrap();
-                match store.search_contexts(&query, &live_contexts, limit) {
-                    Ok(result) => {
-                        // Fetch full context details for matching IDs
-                        let contexts_json: Vec<JsonValue> = result
-                            .context_ids
-                            .iter()
-                            .filter_map(|&context_id| {
-                                let head = store.turn_store.get_head(context_id).ok()?;
-                                let session = session_tracker.get_session_for_context(context_id);
-                                let is_live = session.is_some();
-
-                                let mut obj = json!({
-                                    "context_id": context_id.to_string(),
-                                    "head_turn_id": head.head_turn_id.to_string(),
-                                    "head_depth": head.head_depth,
-                                    "created_at_unix_ms": head.created_at_unix_ms,
-                                    "is_live": is_live,
-                                });
-
-                                // Add metadata if available (use cached data)
-                                if let Some(metadata) = store
-                                    .context_metadata_cache
-                                    .get(&context_id)
-                                    .and_then(|m| m.as_ref())
-                                {
-                                    if let Some(ref tag) = metadata.client_tag {
-                                        obj["client_tag"] = JsonValue::String(tag.clone());
-                                    }
-                                    if let Some(ref title) = metadata.title {
-                                        obj["title"] = JsonValue::String(title.clone());
-                                    }
-                                }
-
-                                Some(obj)
-                            })
-                            .collect();
-
-                        let resp = json!({
-                            "contexts": contexts_json,
-                            "total_count": result.total_count,
-                            "elapsed_ms": result.elapsed_ms,
-                            "query": result.query.raw,
-                        });
-
+                let mut store = store.lock().unwrap();
+                let result = context_search_json(&mut store, session_tracker, &query, limit);
+                let result = match &peer_residencies {
+                    Some(allowed) => {
+                        result.map(|resp| withhold_residencies(&mut store, resp, allowed))
+                    }
+                    None => result,
+                };
+                drop(store);
+                match result {
+                    Ok(resp) => {
                         let bytes = serde_json::to_vec(&resp).map_err(|e| {
                             StoreError::InvalidInput(format!("json encode error: {e}"))
                         })?;
                         Ok((
                             200,
                             Response::from_data(bytes)
-                                .with_status_code(StatusCode(200))
-                                .with_header(
-                                    Header::from_bytes(
-                                        &b"Content-Type"[..],
-                                        &b"application/json"[..],
-                                    )
-                                    .unwrap(),
-                                ),
+                                .with_status_code(200)
+                                .with_header(Header::new("Content-Type", "application/json")),
                         ))
                     }
                     Err(cql_error) => {
@@ -420,68 +1079,1293 @@ fn handle_request(
                         Ok((
                             400,
                             Response::from_data(bytes)
-                                .with_status_code(StatusCode(400))
-                                .with_header(
-                                    Header::from_bytes(
-                                        &b"Content-Type"[..],
-                                        &b"application/json"[..],
-                                    )
-                                    .unwrap(),
-                                ),
+                                .with_status_code(400)
+                                .with_header(Header::new("Content-Type", "application/json")),
                         ))
                     }
                 }
             }
-            // Get provenance for a specific context
-            (Method::Get, ["v1", "contexts", context_id, "provenance"]) => {
+            (Method::GET, ["v1", "federated", "contexts", "search"]) => {
+                let federation = federation.as_ref().ok_or_else(federation_disabled)?;
+                let params = parse_query(url.query().unwrap_or(""));
+                let query = params
+                    .get("q")
+                    .filter(|q| !q.is_empty())
+                    .ok_or_else(|| StoreError::InvalidInput("q is required".into()))?;
+                let limit = params.get("limit").and_then(|v| v.parse::<u32>().ok());
+
+                let start = Instant::now();
+                let local =
+                    context_search_json(&mut store.lock().unwrap(), session_tracker, query, limit)
+                        .map_err(|e| {
+                            StoreError::InvalidInput(format!("invalid query: {}", e.message))
+                        })?;
+                let mut searches = vec![OriginSearch::found(
+                    federation.local_name(),
+                    &local,
+                    start.elapsed(),
+                )];
+                searches.extend(federation.search_peers(query, limit));
+                let contexts = merge_results(&searches, limit);
+                let total_count: u64 = searches.iter().filter_map(|s| s.total_count).sum();
+                json_response(
+                    200,
+                    &json!({
+                        "contexts": contexts,
+                        "total_count": total_count,
+                        "query": query,
+                        "origins": searches,
+                        "partial": searches.iter().any(|s| !s.ok),
+                    }),
+                )
+            }
+            (Method::GET, ["v1", "federated", origin, "contexts", context_id, rest @ ..])
+                if rest.is_empty() || rest == ["turns"] =>
+            {
+                let federation = federation.as_ref().ok_or_else(federation_disabled)?;
                 let context_id: u64 = context_id
                     .parse()
                     .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let mut path = format!("v1/contexts/{context_id}");
+                if !rest.is_empty() {
+                    path.push_str("/turns");
+                }
+                if let Some(query) = url.query() {
+                    path = format!("{path}?{query}");
+                }
+                if *origin == federation.local_name() {
+                    let location = format!("/{path}");
+                    let response = Response::from_data(Vec::new())
+                        .with_status_code(307)
+                        .with_header(Header::new("Location", &location));
+                    return Ok((307, response));
+                }
+                let proxied = federation.fetch(origin, &path)?;
+                let response = Response::from_data(proxied.body.to_vec())
+                    .with_status_code(proxied.status)
+                    .with_header(Header::new("Content-Type", &proxied.content_type))
+                    .with_header(Header::new("X-Cxdb-Origin", origin))
+                    .with_header(Header::new(
+                        "X-Cxdb-Cache",
+                        if proxied.cached { "hit" } else { "miss" },
+                    ));
+                Ok((proxied.status, response))
+            }
+            (Method::POST, ["v1", "admin", "contexts", "backfill-metadata"]) => {
+                let content_type = request
+                    .headers()
+                    .iter()
+                    .find(|h| h.field == "Content-Type")
+                    .map(|h| h.value.as_str().to_ascii_lowercase())
+                    .unwrap_or_default();
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
 
-                let mut store = store.lock().unwrap();
-                let metadata = store.get_context_metadata(context_id);
-
-                // Get session info for server-side data
-                let session = session_tracker.get_session_for_context(context_id);
-                let session_peer_addr = session.as_ref().and_then(|s| s.peer_addr.clone());
-
-                let resp = if let Some(ref meta) = metadata {
-                    if let Some(ref prov) = meta.provenance {
-                        // Inject server-side client_address if not present
-                        let mut prov_with_server_info = prov.clone();
-                        if prov_with_server_info.client_address.is_none() {
-                            prov_with_server_info.client_address = session_peer_addr;
-                        }
-                        json!({
-                            "context_id": context_id.to_string(),
-                            "provenance": prov_with_server_info,
-                        })
-                    } else {
-                        json!({
-                            "context_id": context_id.to_string(),
-                            "provenance": null,
-                        })
-                    }
+                // A raw CSV/JSONL body is a mapping file; anything else is a JSON request.
+                let mapping_format = if content_type.starts_with("text/csv") {
+                    Some(MappingFormat::Csv)
+                } else if content_type.starts_with("application/x-ndjson")
+                    || content_type.starts_with("application/jsonl")
+                {
+                    Some(MappingFormat::Jsonl)
                 } else {
-                    json!({
-                        "context_id": context_id.to_string(),
-                        "provenance": null,
-                    })
+                    None
+                };
+                let backfill: BackfillRequest = match mapping_format {
+                    Some(format) => BackfillRequest {
+                        mapping: Some(String::from_utf8(body).map_err(|_| {
+                            StoreError::InvalidInput("mapping must be utf-8".into())
+                        })?),
+                        mapping_format: Some(format),
+                        dry_run: parse_query(url.query().unwrap_or(""))
+                            .get("dry_run")
+                            .is_some_and(|v| v == "1" || v == "true"),
+                        ..Default::default()
+                    },
+                    None => parse_body(&request, &body)?,
                 };
 
-                let bytes = serde_json::to_vec(&resp)
-                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
-                Ok((
-                    200,
-                    Response::from_data(bytes)
-                        .with_status_code(StatusCode(200))
-                        .with_header(
-                            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
-                                .unwrap(),
-                        ),
-                ))
+                let live_contexts = session_tracker.get_live_context_ids();
+                let plan = backfill.plan(&mut store.lock().unwrap(), &live_contexts)?;
+                if backfill.dry_run {
+                    let sample: Vec<String> = plan
+                        .targets
+                        .iter()
+                        .take(20)
+                        .map(|(id, _)| id.to_string())
+                        .collect();
+                    return json_response(
+                        200,
+                        &json!({
+                            "dry_run": true,
+                            "targets": plan.targets.len(),
+                            "sample_context_ids": sample,
+                        }),
+                    );
+                }
+
+                let targets = plan.targets.len();
+                let store = Arc::clone(store);
+                let event_bus = Arc::clone(event_bus);
+                let op_id = operations.submit("backfill_metadata", move |op| {
+                    crate::backfill::run_backfill(plan, &store, &event_bus, op)
+                });
+                json_response(
+                    202,
+                    &json!({
+                        "operation_id": op_id.to_string(),
+                        "targets": targets,
+                    }),
+                )
             }
-            (Method::Get, ["v1", "contexts", context_id, "turns"]) => {
+            (Method::GET, ["v1", "admin", "tags", "changes"]) => {
+                let store = store.lock().unwrap();
+                let changes: Vec<JsonValue> =
+                    store.tag_changes().iter().map(tag_change_json).collect();
+                json_response(200, &json!({ "changes": changes }))
+            }
+            (Method::POST, ["v1", "admin", "tags", "rename"]) => {
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let spec: TagRenameRequest = parse_body(&request, &body)?;
+                let change = store.lock().unwrap().rename_tag(
+                    &spec.from,
+                    &spec.to,
+                    &spec.reason,
+                    principal.as_deref(),
+                )?;
+                publish_tag_change(store, event_bus, &change);
+                json_response(200, &tag_change_json(&change))
+            }
+            (Method::POST, ["v1", "admin", "tags", "merge"]) => {
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let spec: TagMergeRequest = parse_body(&request, &body)?;
+                let change = store.lock().unwrap().merge_tags(
+                    &spec.sources,
+                    &spec.into,
+                    &spec.reason,
+                    principal.as_deref(),
+                )?;
+                publish_tag_change(store, event_bus, &change);
+                json_response(200, &tag_change_json(&change))
+            }
+            (Method::POST, ["v1", "admin", "registry", direction @ ("pull" | "push")]) => {
+                let registry_sync = registry_sync.as_ref().ok_or_else(|| {
+                    StoreError::NotFound(
+                        "registry sync is not enabled (set CXDB_REGISTRY_SYNC_REMOTES)".into(),
+                    )
+                })?;
+                let params = parse_query(url.query().unwrap_or(""));
+                let (direction, remote_param) = match *direction {
+                    "pull" => (SyncDirection::Pull, "from"),
+                    _ => (SyncDirection::Push, "to"),
+                };
+                let remote = params.get(remote_param).ok_or_else(|| {
+                    StoreError::InvalidInput(format!("{remote_param} is required"))
+                })?;
+                let mode = match params.get("mode") {
+                    Some(mode) => SyncMode::parse(mode)?,
+                    None => SyncMode::default(),
+                };
+                let dry_run = params
+                    .get("dry_run")
+                    .is_some_and(|v| v == "1" || v == "true");
+                let report = match direction {
+                    SyncDirection::Pull => registry_sync.pull(registry, remote, mode, dry_run)?,
+                    SyncDirection::Push => registry_sync.push(registry, remote, mode, dry_run)?,
+                };
+                if direction == SyncDirection::Pull && !dry_run {
+                    for _ in &report.applied {
+                        metrics.record_registry_ingest();
+                    }
+                }
+                tracing::info!(
+                    direction = ?report.direction,
+                    remote = %report.remote,
+                    applied = report.applied.len(),
+                    skipped = report.skipped.len(),
+                    conflicts = report.conflicts.len(),
+                    dry_run,
+                    "registry sync"
+                );
+                let body = serde_json::to_value(&report)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(200, &body)
+            }
+            (Method::POST, ["v1", "admin", "blobs", "gc"]) => {
+                let dry_run = parse_query(url.query().unwrap_or(""))
+                    .get("dry_run")
+                    .is_some_and(|v| v == "1" || v == "true");
+                let mut store = store.lock().unwrap();
+                let fs = store.prune_fs_roots(dry_run)?;
+                let sweep = store.collect_blobs(dry_run)?;
+                let remaining = store.blob_gc_stats();
+                json_response(
+                    200,
+                    &json!({
+                        "dry_run": dry_run,
+                        "swept_blobs": sweep.swept_blobs,
+                        "swept_bytes": sweep.swept_bytes,
+                        "kept_blobs": sweep.kept_blobs,
+                        "young_blobs": sweep.young_blobs,
+                        "young_bytes": sweep.young_bytes,
+                        "pack_bytes_before": sweep.pack_bytes_before,
+                        "pack_bytes_after": sweep.pack_bytes_after,
+                        "live_bytes": remaining.live_bytes,
+                        "dead_bytes": remaining.dead_bytes,
+                        "pruned_fs_roots": fs.orphan_roots,
+                        "fs_unreachable_bytes": fs.unreachable_bytes,
+                    }),
+                )
+            }
+            (Method::GET, ["v1", "admin", "compaction", "plan"]) => {
+                let plan = store.lock().unwrap().compaction_plan()?;
+                let body = serde_json::to_value(&plan)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(200, &body)
+            }
+            (Method::POST, ["v1", "admin", "fsck"]) => {
+                let report = store.lock().unwrap().fsck();
+                if !report.chains.is_ok() {
+                    tracing::warn!(
+                        mismatched_turns = report.chains.mismatched_turns,
+                        "fsck found turns whose chain hash does not match"
+                    );
+                }
+                if !report.invariants.ok {
+                    tracing::warn!(
+                        violations = report.invariants.violation_count,
+                        "fsck found store invariant violations"
+                    );
+                }
+                let body = serde_json::to_value(&report)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(200, &body)
+            }
+            (Method::GET, ["v1", "admin", "memory"]) => {
+                let (indexes, payload_cache) = {
+                    let store = store.lock().unwrap();
+                    (store.index_stats(), store.payload_cache_stats())
+                };
+                let projection_cache = registry.lock().unwrap().projection_cache_stats();
+                let body = json!({
+                    "heap": heap_stats(),
+                    "subsystems": {
+                        "indexes_bytes": indexes.memory_bytes,
+                        "metadata_cache_bytes": indexes.metadata_cache.bytes,
+                        "payload_cache_bytes": payload_cache.bytes,
+                        "projection_cache_bytes": projection_cache.bytes,
+                        "event_queues_bytes": event_bus.queued_bytes(),
+                    },
+                    "profiling": memory.profile_dir.is_some(),
+                });
+                json_response(200, &body)
+            }
+            (Method::POST, ["v1", "admin", "memory", "profile"]) => {
+                let path = memory.dump_profile()?;
+                tracing::info!(path = %path.display(), "dumped heap profile");
+                json_response(201, &json!({ "path": path.display().to_string() }))
+            }
+            (Method::POST, ["v1", "admin", "profile"]) => {
+                let params = parse_query(url.query().unwrap_or(""));
+                let seconds = match params.get("seconds") {
+                    Some(s) => s
+                        .parse::<u64>()
+                        .map_err(|_| StoreError::InvalidInput("invalid seconds".into()))?,
+                    None => cpu_profile::DEFAULT_SECONDS,
+                };
+                let format = match params.get("format") {
+                    Some(f) => ProfileFormat::parse(f)?,
+                    None => ProfileFormat::Flamegraph,
+                };
+                tracing::info!(seconds, "capturing CPU profile");
+                let body = cpu_profiler.capture(Duration::from_secs(seconds), format)?;
+                let unix_ms = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_millis())
+                    .unwrap_or(0);
+                let disposition = format!(
+                    "attachment; filename=\"cxdb-cpu-{unix_ms}.{}\"",
+                    format.extension()
+                );
+                Ok((
+                    200,
+                    Response::from_data(body)
+                        .with_status_code(200)
+                        .with_header(Header::new("Content-Type", format.content_type()))
+                        .with_header(Header::new("Content-Disposition", &disposition)),
+                ))
+            }
+            (Method::GET, ["v1", "admin", "anchors"]) => {
+                let anchors = anchors.as_ref().ok_or_else(anchoring_disabled)?;
+                let body = serde_json::to_value(anchors.list())
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(200, &json!({ "anchors": body }))
+            }
+            (Method::POST, ["v1", "admin", "anchors"]) => {
+                let anchors = anchors.as_ref().ok_or_else(anchoring_disabled)?;
+                let record = anchors
+                    .anchor(store, true)?
+                    .ok_or_else(|| StoreError::Corrupt("forced anchor was skipped".into()))?;
+                let body = serde_json::to_value(&record)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(201, &body)
+            }
+            (Method::GET, ["v1", "admin", "anchors", seq, "verify"]) => {
+                let anchors = anchors.as_ref().ok_or_else(anchoring_disabled)?;
+                let seq: u64 = seq
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid anchor seq".into()))?;
+                let context_id = match parse_query(url.query().unwrap_or("")).get("context_id") {
+                    Some(id) => Some(
+                        id.parse::<u64>()
+                            .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?,
+                    ),
+                    None => None,
+                };
+                let report = anchors.verify(&store.lock().unwrap(), seq, context_id)?;
+                let body = serde_json::to_value(&report)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(200, &body)
+            }
+            (Method::GET, ["v1", "operations"]) => {
+                let params = parse_query(url.query().unwrap_or(""));
+                let ops = operations.list(
+                    params.get("state").map(|s| s.as_str()),
+                    params.get("kind").map(|s| s.as_str()),
+                );
+                let body = serde_json::to_value(&ops)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(200, &json!({ "operations": body }))
+            }
+            (Method::POST, ["v1", "operations", op_id, "cancel"]) => {
+                let op_id: u64 = op_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid operation id".into()))?;
+                let info = operations.cancel(op_id)?;
+                let body = serde_json::to_value(&info)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(202, &body)
+            }
+            (Method::GET, ["v1", "operations", op_id]) => {
+                let op_id: u64 = op_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid operation id".into()))?;
+                let info = operations
+                    .get(op_id)
+                    .ok_or_else(|| StoreError::NotFound("operation".into()))?;
+                let body = serde_json::to_value(&info)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(200, &body)
+            }
+            (Method::GET, ["v1", "protocol", "schema"]) => {
+                json_response(200, &crate::protocol::protocol_schema())
+            }
+            // Projects
+            (Method::POST, ["v1", "projects"]) => {
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let spec: ProjectSpec = parse_body(&request, &body)?;
+                let principal = principal.clone();
+                let project = store.lock().unwrap().create_project(
+                    &spec.id,
+                    spec.name.as_deref(),
+                    spec.description.as_deref(),
+                    principal.as_deref(),
+                )?;
+                event_bus.publish(StoreEvent::ProjectCreated {
+                    project_id: project.id.clone(),
+                    name: project.name.clone(),
+                    principal,
+                });
+                json_response(201, &project_json(&project, None))
+            }
+            (Method::GET, ["v1", "projects"]) => {
+                let store = store.lock().unwrap();
+                let projects: Vec<JsonValue> = store
+                    .projects()
+                    .iter()
+                    .map(|project| {
+                        let mut obj = project_json(project, None);
+                        obj["context_count"] = json!(store
+                            .project_contexts(&project.id)
+                            .map_or(0, |ids| ids.len()));
+                        obj
+                    })
+                    .collect();
+                json_response(200, &json!({ "projects": projects }))
+            }
+            (Method::GET, ["v1", "projects", project_id]) => {
+                let mut store = store.lock().unwrap();
+                let project = store
+                    .project(project_id)
+                    .cloned()
+                    .ok_or_else(|| StoreError::NotFound(format!("project {project_id}")))?;
+                let contexts = store.project_contexts(project_id)?;
+                let stats = store.project_stats(project_id)?;
+                let live = contexts
+                    .iter()
+                    .filter(|&&id| session_tracker.get_session_for_context(id).is_some())
+                    .count();
+                let mut obj = project_json(&project, Some(&stats));
+                obj["stats"]["live"] = json!(live);
+                obj["context_ids"] =
+                    json!(contexts.iter().map(|id| id.to_string()).collect::<Vec<_>>());
+                json_response(200, &obj)
+            }
+            (Method::PUT, ["v1", "projects", project_id, "contexts", context_id])
+            | (Method::DELETE, ["v1", "projects", project_id, "contexts", context_id]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let principal = principal.clone();
+                let assign = *request.method() == Method::PUT;
+                let changed = {
+                    let mut store = store.lock().unwrap();
+                    if assign {
+                        store.assign_project(project_id, context_id, principal.as_deref())?
+                    } else {
+                        store.unassign_project(project_id, context_id, principal.as_deref())?
+                    }
+                };
+                if changed {
+                    let (project_id, context_id) = (project_id.to_string(), context_id.to_string());
+                    event_bus.publish(if assign {
+                        StoreEvent::ProjectAssigned {
+                            project_id,
+                            context_id,
+                            principal,
+                        }
+                    } else {
+                        StoreEvent::ProjectUnassigned {
+                            project_id,
+                            context_id,
+                            principal,
+                        }
+                    });
+                }
+                json_response(
+                    200,
+                    &json!({
+                        "project_id": project_id,
+                        "context_id": context_id.to_string(),
+                        "assigned": assign,
+                        "changed": changed,
+                    }),
+                )
+            }
+            (Method::POST, ["v1", "contexts", context_id, "share"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let spec: ShareSpec = if body.iter().all(u8::is_ascii_whitespace) {
+                    ShareSpec::default()
+                } else {
+                    parse_body(&request, &body)?
+                };
+
+                let (from, to) = {
+                    let store = store.lock().unwrap();
+                    store.get_head(context_id)?;
+                    let bound = |field: &str, raw: &Option<JsonValue>| -> Result<_> {
+                        let Some(raw) = raw.as_ref().filter(|v| !v.is_null()) else {
+                            return Ok(None);
+                        };
+                        let turn_id = json_u64(raw)
+                            .ok_or_else(|| StoreError::InvalidInput(format!("invalid {field}")))?;
+                        let proof = store.turn_proof(turn_id, context_id).map_err(|_| {
+                            StoreError::InvalidInput(format!(
+                                "{field} {turn_id} is not in context {context_id}"
+                            ))
+                        })?;
+                        Ok(Some(ShareBound {
+                            turn_id,
+                            depth: proof.turn.record.depth,
+                        }))
+                    };
+                    (
+                        bound("from_turn_id", &spec.from_turn_id)?,
+                        bound("to_turn_id", &spec.to_turn_id)?,
+                    )
+                };
+                let (info, token) =
+                    shares.create(context_id, &spec, from, to, principal.clone())?;
+                let mut body = serde_json::to_value(&info)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                body["path"] = json!(format!(
+                    "/v1/contexts/{context_id}?share={}",
+                    utf8_percent_encode(&token, UNRESERVED)
+                ));
+                body["token"] = json!(token);
+                json_response(201, &body)
+            }
+            (Method::GET, ["v1", "contexts", context_id, "shares"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let body = serde_json::to_value(shares.list(context_id))
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(200, &json!({ "shares": body }))
+            }
+            (Method::DELETE, ["v1", "shares", share_id]) => {
+                shares.revoke(share_id)?;
+                Ok((204, Response::from_data(Vec::new()).with_status_code(204)))
+            }
+            (Method::GET, ["v1", "watches"]) => {
+                let body = serde_json::to_value(watches.list())
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(200, &json!({ "watches": body }))
+            }
+            (Method::POST, ["v1", "watches"]) => {
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let spec: WatchSpec = parse_body(&request, &body)?;
+                let info = watches.register(spec)?;
+                let body = serde_json::to_value(&info)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(201, &body)
+            }
+            (Method::GET, ["v1", "watches", watch_id]) => {
+                let watch_id: u64 = watch_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid watch id".into()))?;
+                let info = watches
+                    .get(watch_id)
+                    .ok_or_else(|| StoreError::NotFound("watch".into()))?;
+                let mut body = serde_json::to_value(&info)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                let matches: Vec<String> = watches
+                    .matches(watch_id)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect();
+                body["context_ids"] = json!(matches);
+                json_response(200, &body)
+            }
+            (Method::DELETE, ["v1", "watches", watch_id]) => {
+                let watch_id: u64 = watch_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid watch id".into()))?;
+                if !watches.remove(watch_id)? {
+                    return Err(StoreError::NotFound("watch".into()));
+                }
+                Ok((204, Response::from_data(Vec::new()).with_status_code(204)))
+            }
+            (Method::GET, ["v1", "contexts", context_id, "subscriptions"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                store.lock().unwrap().get_head(context_id)?;
+                let body = serde_json::to_value(subscriptions.list(context_id))
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(200, &json!({ "subscriptions": body }))
+            }
+            (Method::POST, ["v1", "contexts", context_id, "subscriptions"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let spec: SubscriptionSpec = parse_body(&request, &body)?;
+                store.lock().unwrap().get_head(context_id)?;
+                let info = subscriptions.create(context_id, spec)?;
+                let body = serde_json::to_value(&info)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(201, &body)
+            }
+            (Method::DELETE, ["v1", "contexts", context_id, "subscriptions", subscription_id]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let subscription_id: u64 = subscription_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid subscription id".into()))?;
+                if !subscriptions.remove(context_id, subscription_id)? {
+                    return Err(StoreError::NotFound("subscription".into()));
+                }
+                Ok((204, Response::from_data(Vec::new()).with_status_code(204)))
+            }
+            // Encryption keys and crypto-shredding
+            (Method::GET, ["v1", "keys"]) => {
+                let store = store.lock().unwrap();
+                let body = serde_json::to_value(store.list_keys())
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(200, &json!({ "keys": body }))
+            }
+            (Method::POST, ["v1", "contexts", context_id, "shred"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let info = store.lock().unwrap().shred_context_key(context_id)?;
+                registry.lock().unwrap().clear_projection_cache();
+                let body = serde_json::to_value(&info)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(200, &body)
+            }
+            (Method::POST, ["v1", "tags", tag, "shred"]) => {
+                let info = store.lock().unwrap().shred_tag_key(tag)?;
+                registry.lock().unwrap().clear_projection_cache();
+                let body = serde_json::to_value(&info)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                json_response(200, &body)
+            }
+            // Namespaced label discovery
+            (Method::GET, ["v1", "labels"]) => {
+                let store = store.lock().unwrap();
+                let namespaces: Vec<JsonValue> = store
+                    .label_namespaces()
+                    .into_iter()
+                    .map(|(namespace, count)| {
+                        json!({ "namespace": namespace, "context_count": count })
+                    })
+                    .collect();
+                json_response(200, &json!({ "namespaces": namespaces }))
+            }
+            (Method::GET, ["v1", "labels", namespace, "values"]) => {
+                let store = store.lock().unwrap();
+                let values: Vec<JsonValue> = store
+                    .label_namespace_values(namespace)
+                    .into_iter()
+                    .map(|(value, count)| json!({ "value": value, "context_count": count }))
+                    .collect();
+                json_response(200, &json!({ "namespace": namespace, "values": values }))
+            }
+            // Get provenance for a specific context
+            (Method::GET, ["v1", "contexts", context_id]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let params = parse_query(url.query().unwrap_or(""));
+                let include_provenance = params
+                    .get("include_provenance")
+                    .map(|v| v == "1")
+                    .unwrap_or(false);
+                let principal = principal.clone();
+
+                let mut store = store.lock().unwrap();
+                let head = store.get_head(context_id)?;
+                let mut obj = context_json(
+                    &mut store,
+                    session_tracker,
+                    &head,
+                    principal.as_deref(),
+                    include_provenance,
+                );
+                drop(store);
+                obj["viewers"] = json!(presence.viewers(context_id));
+                json_response(200, &obj)
+            }
+            (Method::GET, ["v1", "contexts", context_id, "provenance"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+
+                let mut store = store.lock().unwrap();
+                let metadata = store.get_context_metadata(context_id);
+
+                // Get session info for server-side data
+                let session = session_tracker.get_session_for_context(context_id);
+                let session_peer_addr = session.as_ref().and_then(|s| s.peer_addr.clone());
+
+                let resp = if let Some(ref meta) = metadata {
+                    if let Some(ref prov) = meta.provenance {
+                        // Inject server-side client_address if not present
+                        let mut prov_with_server_info = prov.clone();
+                        if prov_with_server_info.client_address.is_none() {
+                            prov_with_server_info.client_address = session_peer_addr;
+                        }
+                        json!({
+                            "context_id": context_id.to_string(),
+                            "provenance": prov_with_server_info,
+                        })
+                    } else {
+                        json!({
+                            "context_id": context_id.to_string(),
+                            "provenance": null,
+                        })
+                    }
+                } else {
+                    json!({
+                        "context_id": context_id.to_string(),
+                        "provenance": null,
+                    })
+                };
+
+                let bytes = serde_json::to_vec(&resp)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+                Ok((
+                    200,
+                    Response::from_data(bytes)
+                        .with_status_code(200)
+                        .with_header(Header::new("Content-Type", "application/json")),
+                ))
+            }
+            // Hash chain of a context's history, for audits
+            (Method::GET, ["v1", "contexts", context_id, "proof"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let store = store.lock().unwrap();
+                let head = store.get_head(context_id)?;
+                let links = store.context_chain(context_id)?;
+                let chain_head = links.last().map_or(ROOT_CHAIN_HASH, |l| l.chain_hash);
+                json_response(
+                    200,
+                    &json!({
+                        "context_id": context_id.to_string(),
+                        "head_turn_id": head.head_turn_id.to_string(),
+                        "chain_head": hex::encode(chain_head),
+                        "links": links.iter().map(chain_link_json).collect::<Vec<_>>(),
+                    }),
+                )
+            }
+            // Legal holds
+            (Method::GET, ["v1", "contexts", context_id, "head-history"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let params = parse_query(url.query().unwrap_or(""));
+                let limit = params
+                    .get("limit")
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .unwrap_or(DEFAULT_HEAD_HISTORY_LIMIT);
+                let bucket_ms = params
+                    .get("bucket_ms")
+                    .map(|v| {
+                        v.parse::<u64>()
+                            .ok()
+                            .filter(|&ms| ms > 0)
+                            .ok_or_else(|| StoreError::InvalidInput("invalid bucket_ms".into()))
+                    })
+                    .transpose()?;
+                let history = store.lock().unwrap().head_history(context_id)?;
+                let moves: Vec<JsonValue> = history[history.len().saturating_sub(limit)..]
+                    .iter()
+                    .map(|m| {
+                        json!({
+                            "turn_id": m.turn_id.to_string(),
+                            "depth": m.depth,
+                            "at_unix_ms": m.at_unix_ms,
+                            "cause": m.cause.as_str(),
+                        })
+                    })
+                    .collect();
+                let mut resp = json!({
+                    "context_id": context_id.to_string(),
+                    "moves": moves,
+                    "total": history.len(),
+                });
+                if let Some(bucket_ms) = bucket_ms {
+                    resp["buckets"] = json!(head_activity_buckets(&history, bucket_ms)?);
+                }
+                json_response(200, &resp)
+            }
+            (Method::GET, ["v1", "contexts", context_id, "hold"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let store = store.lock().unwrap();
+                store.get_head(context_id)?;
+                let history: Vec<JsonValue> = store
+                    .hold_history(context_id)
+                    .iter()
+                    .map(hold_json)
+                    .collect();
+                let mut obj = json!({
+                    "context_id": context_id.to_string(),
+                    "on_hold": store.hold(context_id).is_some(),
+                    "history": history,
+                });
+                if let Some(hold) = store.hold(context_id) {
+                    obj["hold"] = hold_json(hold);
+                }
+                json_response(200, &obj)
+            }
+            (Method::PUT, ["v1", "contexts", context_id, "hold"])
+            | (Method::DELETE, ["v1", "contexts", context_id, "hold"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let principal = principal.clone().ok_or_else(|| {
+                    StoreError::InvalidInput(format!("{PRINCIPAL_HEADER} header required"))
+                })?;
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let reason = if body.iter().all(u8::is_ascii_whitespace) {
+                    String::new()
+                } else {
+                    let parsed: JsonValue = parse_body(&request, &body)?;
+                    match parsed.get("reason") {
+                        None | Some(JsonValue::Null) => String::new(),
+                        Some(JsonValue::String(reason)) => reason.clone(),
+                        Some(_) => {
+                            return Err(StoreError::InvalidInput("reason must be a string".into()))
+                        }
+                    }
+                };
+
+                let mut store = store.lock().unwrap();
+                let entry = if *request.method() == Method::PUT {
+                    store.place_hold(context_id, &reason, &principal)?
+                } else {
+                    store.release_hold(context_id, &reason, &principal)?
+                };
+                tracing::info!(
+                    context_id,
+                    principal = %entry.principal,
+                    reason = %entry.reason,
+                    action = ?entry.action,
+                    "Legal hold changed"
+                );
+                json_response(200, &hold_json(&entry))
+            }
+            (Method::GET, ["v1", "contexts", context_id, "transfer"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let mut store = store.lock().unwrap();
+                store.get_head(context_id)?;
+                let history: Vec<JsonValue> = store
+                    .ownership_history(context_id)
+                    .iter()
+                    .map(transfer_json)
+                    .collect();
+                json_response(
+                    200,
+                    &json!({
+                        "context_id": context_id.to_string(),
+                        "owner": store.owner(context_id),
+                        "history": history,
+                    }),
+                )
+            }
+            (Method::POST, ["v1", "contexts", context_id, "transfer"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let principal = principal.clone().ok_or_else(|| {
+                    StoreError::InvalidInput(format!("{PRINCIPAL_HEADER} header required"))
+                })?;
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let spec: TransferRequest = parse_body(&request, &body)?;
+                if spec.filter.is_some() {
+                    return Err(StoreError::InvalidInput(
+                        "filter is only accepted by POST /v1/contexts/transfer".into(),
+                    ));
+                }
+                let entry = store.lock().unwrap().transfer_ownership(
+                    context_id,
+                    &spec.owner,
+                    &spec.reason,
+                    &principal,
+                )?;
+                event_bus.publish(ownership_changed(&entry));
+                tracing::info!(
+                    context_id,
+                    principal = %entry.principal,
+                    owner = %entry.owner,
+                    previous_owner = ?entry.previous_owner,
+                    "Context transferred"
+                );
+                json_response(200, &transfer_json(&entry))
+            }
+            (Method::GET, ["v1", "replays", replay_id]) => {
+                let replay_id: u64 = replay_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid replay_id".into()))?;
+                json_response(200, &json!(replays.get(replay_id)?.status()))
+            }
+            (Method::POST, ["v1", "replays", replay_id, action @ ("pause" | "resume")]) => {
+                let replay_id: u64 = replay_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid replay_id".into()))?;
+                let control = replays.get(replay_id)?;
+                if *action == "pause" {
+                    control.pause();
+                } else {
+                    control.resume();
+                }
+                json_response(200, &json!(control.status()))
+            }
+            (Method::DELETE, ["v1", "replays", replay_id]) => {
+                let replay_id: u64 = replay_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid replay_id".into()))?;
+                let control = replays.get(replay_id)?;
+                control.stop();
+                json_response(200, &json!(control.status()))
+            }
+            (Method::POST, ["v1", "contexts", context_id, "clone"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let params = parse_query(url.query().unwrap_or(""));
+                let up_to_turn_id = match params.get("up_to_turn") {
+                    Some(raw) => Some(
+                        raw.parse::<u64>()
+                            .map_err(|_| StoreError::InvalidInput("invalid up_to_turn".into()))?,
+                    ),
+                    None => None,
+                };
+
+                let mut store = store.lock().unwrap();
+                let clone = store.clone_context(context_id, up_to_turn_id)?;
+                let metadata = store.get_context_metadata(clone.head.context_id);
+                drop(store);
+                event_bus.publish_recorded(StoreEvent::ContextCreated {
+                    context_id: clone.head.context_id.to_string(),
+                    session_id: String::new(),
+                    client_tag: metadata
+                        .as_ref()
+                        .and_then(|m| m.client_tag.clone())
+                        .unwrap_or_default(),
+                    created_at: clone.head.created_at_unix_ms,
+                    external_id: None,
+                })?;
+                if let Some(meta) = metadata {
+                    event_bus.publish(StoreEvent::ContextMetadataUpdated {
+                        context_id: clone.head.context_id.to_string(),
+                        client_tag: meta.client_tag,
+                        title: meta.title,
+                        labels: meta.labels,
+                        has_provenance: meta.provenance.is_some(),
+                    });
+                }
+                tracing::info!(
+                    context_id,
+                    clone_context_id = clone.head.context_id,
+                    turns = clone.turns,
+                    "Context cloned"
+                );
+                json_response(
+                    201,
+                    &json!({
+                        "context_id": clone.head.context_id.to_string(),
+                        "head_turn_id": clone.head.head_turn_id.to_string(),
+                        "head_depth": clone.head.head_depth,
+                        "source_context_id": clone.source_context_id.to_string(),
+                        "up_to_turn_id": clone.up_to_turn_id.to_string(),
+                        "turns": clone.turns,
+                        "fs_snapshots": clone.fs_snapshots,
+                        "attachments": clone.attachments,
+                    }),
+                )
+            }
+            (Method::POST, ["v1", "contexts", context_id, "rollover"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let spec: RolloverRequest = if body.iter().all(u8::is_ascii_whitespace) {
+                    RolloverRequest::default()
+                } else {
+                    parse_body(&request, &body)?
+                };
+                store.lock().unwrap().check_rollover(context_id)?;
+
+                let digest = spec.digest.filter(|d| !d.trim().is_empty());
+                let (digest, generator, through_turn_id) = match digest {
+                    Some(summary) => (
+                        SummaryResponse {
+                            summary,
+                            model: spec.model,
+                        },
+                        spec.generator
+                            .unwrap_or_else(|| CLIENT_GENERATOR.to_string()),
+                        None,
+                    ),
+                    None => {
+                        let hook = summary_hook.as_ref().ok_or_else(|| {
+                            StoreError::InvalidInput(
+                                "digest is required unless the summary hook is configured \
+                                 (set CXDB_SUMMARY_HOOK_URL)"
+                                    .into(),
+                            )
+                        })?;
+                        // The summarizer runs without holding the store.
+                        let summarizer =
+                            HttpSummarizer::new(hook.url.clone(), hook.request_timeout);
+                        let (through, response) =
+                            rollover_digest(hook, &summarizer, store, registry, context_id)?
+                                .ok_or_else(|| {
+                                    StoreError::InvalidInput(format!(
+                                "the summary hook returned no digest for context {context_id}"
+                            ))
+                                })?;
+                        (response, hook.name.clone(), Some(through))
+                    }
+                };
+
+                let mut store = store.lock().unwrap();
+                let rollover =
+                    store.rollover_context(context_id, &digest, &generator, through_turn_id)?;
+                let head = store.get_head(rollover.to_context_id)?;
+                let metadata = store.get_context_metadata(rollover.to_context_id);
+                drop(store);
+                event_bus.publish_recorded(StoreEvent::ContextCreated {
+                    context_id: rollover.to_context_id.to_string(),
+                    session_id: String::new(),
+                    client_tag: metadata
+                        .as_ref()
+                        .and_then(|m| m.client_tag.clone())
+                        .unwrap_or_default(),
+                    created_at: head.created_at_unix_ms,
+                    external_id: None,
+                })?;
+                event_bus.publish_recorded(StoreEvent::TurnAppended {
+                    context_id: rollover.to_context_id.to_string(),
+                    turn_id: rollover.digest_turn_id.to_string(),
+                    parent_turn_id: "0".to_string(),
+                    depth: 0,
+                    declared_type_id: Some(SUMMARY_TYPE_ID.to_string()),
+                    declared_type_version: Some(SUMMARY_TYPE_VERSION),
+                })?;
+                if let Some(meta) = metadata {
+                    event_bus.publish(StoreEvent::ContextMetadataUpdated {
+                        context_id: rollover.to_context_id.to_string(),
+                        client_tag: meta.client_tag,
+                        title: meta.title,
+                        labels: meta.labels,
+                        has_provenance: meta.provenance.is_some(),
+                    });
+                }
+                tracing::info!(
+                    context_id,
+                    to_context_id = rollover.to_context_id,
+                    generator = %rollover.generator,
+                    "Context rolled over"
+                );
+                json_response(201, &rollover_json(&rollover))
+            }
+            (Method::GET, ["v1", "contexts", context_id, "tree"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let tree = store.lock().unwrap().context_tree(context_id)?;
+                json_response(
+                    200,
+                    &json!({
+                        "context_id": context_id.to_string(),
+                        "root": tree_node_json(&tree.root),
+                        "truncated": tree.truncated,
+                    }),
+                )
+            }
+            (Method::POST, ["v1", "contexts", context_id, "heartbeat"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                store.lock().unwrap().get_head(context_id)?;
+                session_tracker.record_context_activity(context_id);
+                let liveness = session_tracker.liveness();
+                json_response(
+                    200,
+                    &json!({
+                        "context_id": context_id.to_string(),
+                        "is_live": session_tracker.is_context_live(context_id),
+                        "liveness": liveness.mode.as_str(),
+                        "window_ms": liveness.window.as_millis() as u64,
+                    }),
+                )
+            }
+            (Method::PUT, ["v1", "contexts", context_id, "status"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let parsed: JsonValue = parse_body(&request, &body)?;
+                let status = match parsed.get("status") {
+                    None | Some(JsonValue::Null) => String::new(),
+                    Some(JsonValue::String(status)) => status.clone(),
+                    Some(_) => {
+                        return Err(StoreError::InvalidInput("status must be a string".into()))
+                    }
+                };
+                let progress = match parsed.get("progress") {
+                    None | Some(JsonValue::Null) => None,
+                    Some(value) => Some(value.as_f64().ok_or_else(|| {
+                        StoreError::InvalidInput("progress must be a number".into())
+                    })?),
+                };
+                let status = ProgressStatus::new(status, progress, None)?;
+                store.lock().unwrap().get_head(context_id)?;
+                session_tracker.set_context_status(context_id, status.clone());
+                event_bus.publish(status_changed_event(
+                    Some(context_id),
+                    None,
+                    status.as_ref(),
+                ));
+                json_response(
+                    200,
+                    &json!({
+                        "context_id": context_id.to_string(),
+                        "status": status,
+                    }),
+                )
+            }
+            (Method::POST, ["v1", "contexts", context_id, "mark-read"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let principal = principal.clone().ok_or_else(|| {
+                    StoreError::InvalidInput(format!("{PRINCIPAL_HEADER} header required"))
+                })?;
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let turn_id = if body.iter().all(u8::is_ascii_whitespace) {
+                    None
+                } else {
+                    let parsed: JsonValue = parse_body(&request, &body)?;
+                    match parsed.get("turn_id") {
+                        None | Some(JsonValue::Null) => None,
+                        Some(v) => Some(json_u64(v).ok_or_else(|| {
+                            StoreError::InvalidInput("turn_id must be an integer".into())
+                        })?),
+                    }
+                };
+
+                let mut store = store.lock().unwrap();
+                let mark = store.mark_read(&principal, context_id, turn_id)?;
+                let head = store.get_head(context_id)?;
+                json_response(
+                    200,
+                    &json!({
+                        "context_id": context_id.to_string(),
+                        "principal": principal,
+                        "last_viewed_turn_id": mark.turn_id.to_string(),
+                        "viewed_at_unix_ms": mark.viewed_at_unix_ms,
+                        "unread_turns": ReadMark::unread_turns(
+                            Some(&mark),
+                            head.head_turn_id,
+                            head.head_depth
+                        ),
+                    }),
+                )
+            }
+            (Method::GET, ["v1", "contexts", context_id, "bookmarks"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let store = store.lock().unwrap();
+                store.get_head(context_id)?;
+                let bookmarks: Vec<JsonValue> = store
+                    .bookmarks(context_id)
+                    .into_iter()
+                    .map(bookmark_json)
+                    .collect();
+                json_response(
+                    200,
+                    &json!({
+                        "context_id": context_id.to_string(),
+                        "bookmarks": bookmarks,
+                    }),
+                )
+            }
+            (Method::POST, ["v1", "contexts", context_id, "bookmarks"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let parsed: JsonValue = parse_body(&request, &body)?;
+                let turn_id = parsed
+                    .get("turn_id")
+                    .and_then(json_u64)
+                    .ok_or_else(|| StoreError::InvalidInput("turn_id is required".into()))?;
+                let string_field = |field: &str| match parsed.get(field) {
+                    None | Some(JsonValue::Null) => Ok(None),
+                    Some(JsonValue::String(value)) => Ok(Some(value.clone())),
+                    Some(_) => Err(StoreError::InvalidInput(format!(
+                        "{field} must be a string"
+                    ))),
+                };
+                let name = string_field("name")?.unwrap_or_default();
+                let note = string_field("note")?;
+                // The author defaults to the requesting principal.
+                let author = string_field("author")?.or_else(|| principal.clone());
+
+                let bookmark = store
+                    .lock()
+                    .unwrap()
+                    .add_bookmark(context_id, turn_id, &name, note, author)?;
+                event_bus.publish(StoreEvent::BookmarkAdded {
+                    context_id: context_id.to_string(),
+                    bookmark_id: bookmark.bookmark_id.to_string(),
+                    turn_id: bookmark.turn_id.to_string(),
+                    depth: bookmark.depth,
+                    name: bookmark.name.clone(),
+                    author: bookmark.author.clone(),
+                });
+                json_response(201, &bookmark_json(&bookmark))
+            }
+            (Method::DELETE, ["v1", "contexts", context_id, "bookmarks", bookmark_id]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let bookmark_id: u64 = bookmark_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid bookmark_id".into()))?;
+                let bookmark = store.lock().unwrap().delete_bookmark(
+                    context_id,
+                    bookmark_id,
+                    principal.clone(),
+                )?;
+                event_bus.publish(StoreEvent::BookmarkDeleted {
+                    context_id: context_id.to_string(),
+                    bookmark_id: bookmark.bookmark_id.to_string(),
+                    turn_id: bookmark.turn_id.to_string(),
+                    principal: principal.clone(),
+                });
+                json_response(200, &bookmark_json(&bookmark))
+            }
+            // Search the turns of one context by text, type and field values
+            (Method::GET, ["v1", "contexts", context_id, "turns", "search"]) => {
+                let context_id: u64 = context_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+                let params = parse_query(url.query().unwrap_or(""));
+                let mut query = crate::turn_search::TurnSearchQuery::from_params(&params)?;
+                // A share only searches the depths it shows.
+                if let Some(scope) = share_scope {
+                    query.from_depth = query.from_depth.max(scope.from_depth.unwrap_or(0));
+                    query.to_depth = scope.to_depth;
+                }
+                let options = parse_render_options(&params);
+                let deadline = request_deadline(config, &request, &params);
+
+                let mut store = store.lock().unwrap();
+                let registry = registry.lock().unwrap();
+                presence.touch(context_id, request_viewer(&request, principal.as_deref()));
+                let result = crate::turn_search::search_turns(
+                    &mut store, &registry, context_id, &query, &options, &deadline,
+                )?;
+
+                let mut resp = json!({
+                    "context_id": context_id.to_string(),
+                    "matches": result.matches.iter().map(|m| json!({
+                        "turn_id": m.turn_id.to_string(),
+                        "depth": m.depth,
+                        "type_id": m.type_id,
+                        "type_version": m.type_version,
+                        "snippets": m.snippets,
+                    })).collect::<Vec<_>>(),
+                    "scanned": result.scanned,
+                    "next_from_depth": result.next_from_depth,
+                });
+                if result.partial {
+                    resp["partial"] = JsonValue::Bool(true);
+                    resp["partial_reason"] = JsonValue::String("deadline_exceeded".into());
+                }
+                json_response(200, &resp)
+            }
+            (Method::GET, ["v1", "contexts", context_id, "turns"]) => {
                 let context_id: u64 = context_id
                     .parse()
                     .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
@@ -494,273 +2378,467 @@ fn handle_request(
                     .get("before_turn_id")
                     .and_then(|v| v.parse::<u64>().ok())
                     .unwrap_or(0);
-                let view = params.get("view").map(|v| v.as_str()).unwrap_or("typed");
-                let type_hint_mode = params
-                    .get("type_hint_mode")
-                    .map(|v| v.as_str())
-                    .unwrap_or("inherit");
-
-                let bytes_render = match params.get("bytes_render").map(|v| v.as_str()) {
-                    Some("hex") => BytesRender::Hex,
-                    Some("len_only") => BytesRender::LenOnly,
-                    _ => BytesRender::Base64,
-                };
-                let u64_format = match params.get("u64_format").map(|v| v.as_str()) {
-                    Some("number") => U64Format::Number,
-                    _ => U64Format::String,
-                };
-                let enum_render = match params.get("enum_render").map(|v| v.as_str()) {
-                    Some("number") => EnumRender::Number,
-                    Some("both") => EnumRender::Both,
-                    _ => EnumRender::Label,
-                };
-                let time_render = match params.get("time_render").map(|v| v.as_str()) {
-                    Some("unix_ms") => TimeRender::UnixMs,
-                    _ => TimeRender::Iso,
+                let from_depth = params
+                    .get("from_depth")
+                    .map(|v| {
+                        v.parse::<u32>()
+                            .map_err(|_| StoreError::InvalidInput("invalid from_depth".into()))
+                    })
+                    .transpose()?;
+                if from_depth.is_some() && before_turn_id != 0 {
+                    return Err(StoreError::InvalidInput(
+                        "from_depth and before_turn_id are mutually exclusive".into(),
+                    ));
+                }
+                // A share ending before the head starts its newest page at
+                // its last turn.
+                let from_depth = match share_scope.and_then(|scope| scope.to_depth) {
+                    Some(to) if from_depth.is_none() && before_turn_id == 0 => Some(
+                        (to + 1)
+                            .saturating_sub(limit)
+                            .max(share_scope.and_then(|s| s.from_depth).unwrap_or(0)),
+                    ),
+                    _ => from_depth,
                 };
-                let include_unknown = params
-                    .get("include_unknown")
-                    .map(|v| v == "1")
-                    .unwrap_or(false);
+                let turn_view = TurnView::from_params(&params);
 
-                let as_type_id = params.get("as_type_id").cloned();
-                let as_type_version = params
-                    .get("as_type_version")
-                    .and_then(|v| v.parse::<u32>().ok());
-
-                let options = RenderOptions {
-                    bytes_render,
-                    u64_format,
-                    enum_render,
-                    time_render,
-                    include_unknown,
-                };
+                let deadline = request_deadline(config, &request, &params);
 
                 let mut store = store.lock().unwrap();
                 let head = store.get_head(context_id)?;
+                presence.touch(context_id, request_viewer(&request, principal.as_deref()));
                 let t0 = Instant::now();
-                let turns = if before_turn_id == 0 {
+                let turns = if let Some(from_depth) = from_depth {
+                    store.get_range_by_depth(context_id, from_depth, limit, true)?
+                } else if before_turn_id == 0 {
                     store.get_last(context_id, limit, true)?
                 } else {
                     store.get_before(context_id, before_turn_id, limit, true)?
                 };
                 metrics.record_get_last(t0.elapsed());
+                // The newest page shows the head, so the principal has seen it.
+                if before_turn_id == 0 && from_depth.is_none() {
+                    if let Some(principal) = principal.clone() {
+                        store.mark_read(&principal, context_id, Some(head.head_turn_id))?;
+                    }
+                }
 
                 let registry = registry.lock().unwrap();
+                // Render newest first so that a request cut short by its
+                // deadline returns the most recent turns and pages backwards
+                // from the oldest turn it managed to render.
                 let mut out_turns = Vec::new();
-                for item in turns.iter() {
-                    let declared_type_id = item.meta.declared_type_id.clone();
-                    let declared_type_version = item.meta.declared_type_version;
-
-                    let (decoded_type_id, decoded_type_version) = match type_hint_mode {
-                        "explicit" => {
-                            let id = as_type_id.clone().ok_or_else(|| {
-                                StoreError::InvalidInput("as_type_id required".into())
-                            })?;
-                            let ver = as_type_version.ok_or_else(|| {
-                                StoreError::InvalidInput("as_type_version required".into())
-                            })?;
-                            (id, ver)
-                        }
-                        "latest" => {
-                            let latest = registry
-                                .get_latest_type_version(&declared_type_id)
-                                .ok_or_else(|| StoreError::NotFound("type descriptor".into()))?;
-                            (declared_type_id.clone(), latest.version)
-                        }
-                        _ => (declared_type_id.clone(), declared_type_version),
-                    };
-
-                    let mut turn_obj = Map::new();
-                    turn_obj.insert(
-                        "turn_id".into(),
-                        JsonValue::String(item.record.turn_id.to_string()),
-                    );
-                    turn_obj.insert(
-                        "parent_turn_id".into(),
-                        JsonValue::String(item.record.parent_turn_id.to_string()),
-                    );
-                    turn_obj.insert("depth".into(), JsonValue::Number(item.record.depth.into()));
-                    turn_obj.insert(
-                        "declared_type".into(),
-                        json!({
-                            "type_id": declared_type_id,
-                            "type_version": declared_type_version,
-                        }),
-                    );
-
-                    if view == "typed" || view == "both" {
-                        let desc = registry
-                            .get_type_version(&decoded_type_id, decoded_type_version)
-                            .ok_or_else(|| StoreError::NotFound("type descriptor".into()))?;
-                        let payload = item
-                            .payload
-                            .as_ref()
-                            .ok_or_else(|| StoreError::InvalidInput("payload not loaded".into()))?;
-                        let projected =
-                            crate::projection::project_msgpack(payload, desc, &registry, &options)?;
-                        turn_obj.insert(
-                            "decoded_as".into(),
-                            json!({
-                                "type_id": decoded_type_id,
-                                "type_version": decoded_type_version,
-                            }),
-                        );
-                        turn_obj.insert("data".into(), projected.data);
-                        if let Some(unknown) = projected.unknown {
-                            turn_obj.insert("unknown".into(), unknown);
-                        }
+                let mut rendered_from = turns.len();
+                for (index, item) in turns.iter().enumerate().rev() {
+                    if deadline.is_expired() {
+                        break;
                     }
-
-                    if view == "raw" || view == "both" {
-                        let raw_payload = item
-                            .payload
-                            .as_ref()
-                            .ok_or_else(|| StoreError::InvalidInput("payload not loaded".into()))?;
-                        turn_obj.insert(
-                            "content_hash_b3".into(),
-                            JsonValue::String(hex::encode(item.record.payload_hash)),
-                        );
-                        turn_obj.insert(
-                            "encoding".into(),
-                            JsonValue::Number(item.meta.encoding.into()),
-                        );
-                        turn_obj.insert("compression".into(), JsonValue::Number(0u32.into()));
-                        turn_obj.insert(
-                            "uncompressed_len".into(),
-                            JsonValue::Number((raw_payload.len() as u32).into()),
-                        );
-                        match bytes_render {
-                            BytesRender::Base64 => {
-                                turn_obj.insert(
-                                    "bytes_b64".into(),
-                                    JsonValue::String(
-                                        base64::engine::general_purpose::STANDARD
-                                            .encode(raw_payload),
-                                    ),
-                                );
-                            }
-                            BytesRender::Hex => {
-                                turn_obj.insert(
-                                    "bytes_hex".into(),
-                                    JsonValue::String(hex::encode(raw_payload)),
-                                );
-                            }
-                            BytesRender::LenOnly => {
-                                turn_obj.insert(
-                                    "bytes_len".into(),
-                                    JsonValue::Number((raw_payload.len() as u64).into()),
-                                );
-                            }
-                        }
+                    if share_scope.is_some_and(|scope| !scope.allows_depth(item.record.depth)) {
+                        rendered_from = index;
+                        continue;
+                    }
+                    let Some(turn_obj) = turn_json(&store, &registry, item, &turn_view, &deadline)?
+                    else {
+                        break;
+                    };
+                    out_turns.push(turn_obj);
+                    rendered_from = index;
+                }
+                out_turns.reverse();
+                // Every bookmark of the context, so the UI can jump to turns
+                // outside this page; the turns on it carry their own.
+                let bookmarks: Vec<&Bookmark> = store
+                    .bookmarks(context_id)
+                    .into_iter()
+                    .filter(|b| share_scope.is_none_or(|scope| scope.allows_depth(b.depth)))
+                    .collect();
+                for turn_obj in &mut out_turns {
+                    let turn_id = turn_obj["turn_id"].as_str().and_then(|id| id.parse().ok());
+                    let markers: Vec<JsonValue> = bookmarks
+                        .iter()
+                        .filter(|b| Some(b.turn_id) == turn_id)
+                        .map(|b| bookmark_marker(b))
+                        .collect();
+                    if !markers.is_empty() {
+                        turn_obj["bookmarks"] = JsonValue::Array(markers);
                     }
-
-                    out_turns.push(JsonValue::Object(turn_obj));
                 }
 
-                let next_before = turns.first().map(|t| t.record.turn_id.to_string());
+                let partial = rendered_from > 0;
+                if partial && out_turns.is_empty() {
+                    return Err(StoreError::DeadlineExceeded(
+                        "no turns rendered within budget".into(),
+                    ));
+                }
+                let next_before = turns[rendered_from..]
+                    .first()
+                    .filter(|t| {
+                        share_scope
+                            .and_then(|scope| scope.from_depth)
+                            .is_none_or(|from| t.record.depth > from)
+                    })
+                    .map(|t| t.record.turn_id.to_string());
                 let meta = json!({
                     "context_id": context_id.to_string(),
                     "head_turn_id": head.head_turn_id.to_string(),
                     "head_depth": head.head_depth,
                     "registry_bundle_id": registry.last_bundle_id(),
+                    "bookmarks": bookmarks.iter().map(|b| bookmark_marker(b)).collect::<Vec<_>>(),
                 });
 
-                let resp = json!({
+                let mut resp = json!({
                     "meta": meta,
                     "turns": out_turns,
                     "next_before_turn_id": next_before,
                 });
+                if partial {
+                    resp["partial"] = JsonValue::Bool(true);
+                    resp["partial_reason"] = JsonValue::String("deadline_exceeded".into());
+                }
 
                 let bytes = serde_json::to_vec(&resp)
                     .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
                 Ok((
                     200,
                     Response::from_data(bytes)
-                        .with_status_code(StatusCode(200))
-                        .with_header(
-                            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
-                                .unwrap(),
-                        ),
+                        .with_status_code(200)
+                        .with_header(Header::new("Content-Type", "application/json")),
+                ))
+            }
+            (Method::GET, ["metrics"]) => {
+                let mut store = store.lock().unwrap();
+                let registry = registry.lock().unwrap();
+                let snapshot = metrics.snapshot(&mut store, &registry, event_bus.stats());
+                Ok((
+                    200,
+                    Response::from_data(snapshot.to_prometheus())
+                        .with_status_code(200)
+                        .with_header(Header::new("Content-Type", "text/plain; version=0.0.4")),
                 ))
             }
-            (Method::Get, ["v1", "metrics"]) => {
+            (Method::GET, ["v1", "metrics"]) => {
                 let mut store = store.lock().unwrap();
                 let registry = registry.lock().unwrap();
-                let snapshot = metrics.snapshot(&mut store, &registry);
+                let snapshot = metrics.snapshot(&mut store, &registry, event_bus.stats());
                 let bytes = serde_json::to_vec(&snapshot)
                     .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
                 Ok((
                     200,
                     Response::from_data(bytes)
-                        .with_status_code(StatusCode(200))
-                        .with_header(
-                            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
-                                .unwrap(),
-                        ),
+                        .with_status_code(200)
+                        .with_header(Header::new("Content-Type", "application/json")),
                 ))
             }
-            // Filesystem snapshot: list directory entries
-            (Method::Get, ["v1", "turns", turn_id, "fs"]) => {
+            (Method::GET, ["v1", "quotas"]) => {
+                let report = metrics.quota_report().ok_or_else(|| {
+                    StoreError::NotFound("quotas are not configured (set CXDB_QUOTAS)".into())
+                })?;
+                json_response(200, &json!(report))
+            }
+            (Method::GET, ["v1", "turns", turn_id]) => {
                 let turn_id: u64 = turn_id
                     .parse()
                     .map_err(|_| StoreError::InvalidInput("invalid turn_id".into()))?;
                 let params = parse_query(url.query().unwrap_or(""));
-                let path = params.get("path").map(|s| s.as_str()).unwrap_or("");
+                let turn_view = TurnView::from_params(&params);
+                let deadline = request_deadline(config, &request, &params);
 
                 let mut store = store.lock().unwrap();
+                let item = store.get_turn(turn_id, true)?;
+                let registry = registry.lock().unwrap();
+                let turn = turn_json(&store, &registry, &item, &turn_view, &deadline)?.ok_or_else(
+                    || StoreError::DeadlineExceeded("turn not rendered within budget".into()),
+                )?;
+                json_response(200, &turn)
+            }
+            // Structural diff between two turns of the same type
+            (Method::GET, ["v1", "turns", from_id, "diff", to_id]) => {
+                let from_id: u64 = from_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid turn_id".into()))?;
+                let to_id: u64 = to_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid turn_id".into()))?;
+                let params = parse_query(url.query().unwrap_or(""));
+                let options = parse_render_options(&params);
+                let text_diff = params
+                    .get("text_diff")
+                    .map(|v| v == "1" || v == "true")
+                    .unwrap_or(false);
+                let diff_options = if text_diff {
+                    DiffOptions::with_text_diffs(
+                        params
+                            .get("text_diff_min_chars")
+                            .and_then(|v| v.parse::<usize>().ok()),
+                    )
+                } else {
+                    DiffOptions::default()
+                };
+                let deadline = request_deadline(config, &request, &params);
 
-                // Get fs_root for this turn
-                let fs_root = store
-                    .get_fs_root(turn_id)
-                    .ok_or_else(|| StoreError::NotFound("no fs snapshot for turn".into()))?;
+                let (from, to) = {
+                    let mut store = store.lock().unwrap();
+                    (store.get_turn(from_id, true)?, store.get_turn(to_id, true)?)
+                };
+                if from.meta.declared_type_id != to.meta.declared_type_id {
+                    return Err(StoreError::InvalidInput(format!(
+                        "turns have different types: {} vs {}",
+                        from.meta.declared_type_id, to.meta.declared_type_id
+                    )));
+                }
 
-                // List entries at the given path
-                let entries = store.list_fs_entries(turn_id, path)?;
+                let registry = registry.lock().unwrap();
+                let project = |item: &crate::store::TurnWithMeta| -> Result<JsonValue> {
+                    let desc = registry
+                        .get_type_version(
+                            &item.meta.declared_type_id,
+                            item.meta.declared_type_version,
+                        )
+                        .ok_or_else(|| StoreError::NotFound("type descriptor".into()))?;
+                    let payload = item
+                        .payload
+                        .as_ref()
+                        .ok_or_else(|| StoreError::InvalidInput("payload not loaded".into()))?;
+                    let projected = crate::projection::cache::project_cached(
+                        &item.record.payload_hash,
+                        payload,
+                        &item.meta.declared_type_id,
+                        desc,
+                        &registry,
+                        &options,
+                        &deadline,
+                    )?;
+                    Ok(projected.data)
+                };
+                let old = project(&from)?;
+                let new = project(&to)?;
+                let changes = diff_json(&old, &new, &diff_options);
 
-                let entries_json: Vec<JsonValue> = entries
-                    .iter()
-                    .map(|e| {
-                        let kind_str = match EntryKind::from(e.kind) {
-                            EntryKind::File => "file",
-                            EntryKind::Directory => "dir",
-                            EntryKind::Symlink => "symlink",
-                        };
-                        json!({
-                            "name": e.name,
-                            "kind": kind_str,
-                            "mode": format!("{:o}", e.mode),
-                            "size": e.size,
-                            "hash": hex::encode(&e.hash),
-                        })
+                let count = |op: DiffOp| changes.iter().filter(|c| c.op == op).count();
+                let side = |item: &crate::store::TurnWithMeta| {
+                    json!({
+                        "turn_id": item.record.turn_id.to_string(),
+                        "depth": item.record.depth,
+                        "declared_type": {
+                            "type_id": item.meta.declared_type_id,
+                            "type_version": item.meta.declared_type_version,
+                        },
+                        "content_hash_b3": hex::encode(item.record.payload_hash),
                     })
-                    .collect();
+                };
+                json_response(
+                    200,
+                    &json!({
+                        "type_id": from.meta.declared_type_id,
+                        "from": side(&from),
+                        "to": side(&to),
+                        "identical": changes.is_empty(),
+                        "summary": {
+                            "added": count(DiffOp::Added),
+                            "removed": count(DiffOp::Removed),
+                            "changed": count(DiffOp::Changed),
+                        },
+                        "changes": changes,
+                    }),
+                )
+            }
+            // Raw blob content by hash
+            (Method::GET, ["v1", "blobs", hash]) => {
+                let mut content_hash = [0u8; 32];
+                hex::decode_to_slice(hash, &mut content_hash)
+                    .map_err(|_| StoreError::InvalidInput("invalid blob hash".into()))?;
+                let range = header_value(&request, "Range");
 
-                let resp = json!({
+                let mut store = store.lock().unwrap();
+                let total = store
+                    .blob_len(&content_hash)
+                    .ok_or_else(|| StoreError::NotFound("blob".into()))?;
+                let (content, partial) = match parse_byte_range(range.as_deref(), total) {
+                    ByteRange::Full => (store.get_blob(&content_hash)?, None),
+                    ByteRange::Partial { start, end } => (
+                        store.get_blob_range(&content_hash, start, end - start + 1)?,
+                        Some((start, end)),
+                    ),
+                    ByteRange::Unsatisfiable => return Ok(range_not_satisfiable(total)),
+                };
+                Ok(ranged_response(
+                    Response::from_data(content)
+                        .with_header(Header::new("Content-Type", "application/octet-stream")),
+                    partial,
+                    total,
+                ))
+            }
+            // Downscaled preview of an image blob
+            (Method::GET, ["v1", "blobs", hash, "thumbnail"]) => {
+                let thumbnails = thumbnails.as_ref().ok_or_else(|| {
+                    StoreError::NotFound(
+                        "thumbnails are not enabled (set CXDB_THUMBNAILS=on)".into(),
+                    )
+                })?;
+                let mut content_hash = [0u8; 32];
+                hex::decode_to_slice(hash, &mut content_hash)
+                    .map_err(|_| StoreError::InvalidInput("invalid blob hash".into()))?;
+                let params = parse_query(url.query().unwrap_or(""));
+                let width = parse_width(params.get("w").map(|s| s.as_str()))?;
+                let etag = format!("\"{hash}-w{width}\"");
+                if header_value(&request, "If-None-Match").as_deref() == Some(etag.as_str()) {
+                    return Ok((304, Response::from_data(Vec::new()).with_status_code(304)));
+                }
+
+                let size = store
+                    .lock()
+                    .unwrap()
+                    .blob_len(&content_hash)
+                    .ok_or_else(|| StoreError::NotFound("blob".into()))?;
+                // Only the read holds the store lock; decoding does not.
+                let thumbnail = thumbnails.thumbnail(content_hash, size, width, || {
+                    store.lock().unwrap().get_blob(&content_hash)
+                })?;
+                Ok((
+                    200,
+                    Response::from_data(thumbnail.data)
+                        .with_status_code(200)
+                        .with_header(Header::new("Content-Type", thumbnail.content_type))
+                        .with_header(Header::new("ETag", &etag))
+                        .with_header(Header::new(
+                            "Cache-Control",
+                            "public, max-age=31536000, immutable",
+                        )),
+                ))
+            }
+            // Filesystem snapshot: list directory entries
+            (Method::GET, ["v1", "turns", turn_id, "proof"]) => {
+                let turn_id: u64 = turn_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid turn_id".into()))?;
+                let context_id: u64 = match parse_query(url.query().unwrap_or("")).get("context_id")
+                {
+                    Some(raw) => raw
+                        .parse()
+                        .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?,
+                    None => alias_context
+                        .or(share_scope.map(|scope| scope.context_id))
+                        .ok_or_else(|| StoreError::InvalidInput("context_id required".into()))?,
+                };
+                let proof = store.lock().unwrap().turn_proof(turn_id, context_id)?;
+                json_response(
+                    200,
+                    &json!({
+                        "turn": chain_link_json(&proof.turn),
+                        "context_id": proof.context_id.to_string(),
+                        "head_turn_id": proof.head_turn_id.to_string(),
+                        "chain_head": hex::encode(proof.chain_head),
+                        "path": proof.path.iter().map(chain_link_json).collect::<Vec<_>>(),
+                    }),
+                )
+            }
+            // Linear transcript from the root to a turn, paged from the root
+            (Method::GET, ["v1", "turns", turn_id, "ancestry"]) => {
+                let turn_id: u64 = turn_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid turn_id".into()))?;
+                let params = parse_query(url.query().unwrap_or(""));
+                let limit = params
+                    .get("limit")
+                    .and_then(|v| v.parse::<u32>().ok())
+                    .unwrap_or(64);
+                let from_depth = params
+                    .get("from_depth")
+                    .map(|v| {
+                        v.parse::<u32>()
+                            .map_err(|_| StoreError::InvalidInput("invalid from_depth".into()))
+                    })
+                    .transpose()?
+                    .unwrap_or(0);
+                // A share starting past the root hides the turns before it.
+                let from_depth =
+                    from_depth.max(share_scope.and_then(|scope| scope.from_depth).unwrap_or(0));
+                // Only ids, depths and types unless a view is asked for.
+                let mut turn_view = TurnView::from_params(&params);
+                if !params.contains_key("view") {
+                    turn_view.view = "meta".into();
+                }
+                let include_payload = turn_view.view != "meta";
+                let deadline = request_deadline(config, &request, &params);
+
+                let mut store = store.lock().unwrap();
+                let tip = store.get_turn(turn_id, false)?;
+                let turns = store.get_ancestry(turn_id, from_depth, limit, include_payload)?;
+                let registry = registry.lock().unwrap();
+                let mut next_from_depth = turns
+                    .last()
+                    .map(|t| t.record.depth + 1)
+                    .filter(|depth| *depth <= tip.record.depth);
+                let mut partial = false;
+                let mut out_turns = Vec::with_capacity(turns.len());
+                for item in &turns {
+                    match turn_json(&store, &registry, item, &turn_view, &deadline)? {
+                        Some(turn_obj) => out_turns.push(turn_obj),
+                        None => {
+                            partial = true;
+                            next_from_depth = Some(item.record.depth);
+                            break;
+                        }
+                    }
+                }
+                if partial && out_turns.is_empty() {
+                    return Err(StoreError::DeadlineExceeded(
+                        "no turns rendered within budget".into(),
+                    ));
+                }
+
+                let mut resp = json!({
                     "turn_id": turn_id.to_string(),
-                    "path": path,
-                    "fs_root_hash": hex::encode(fs_root),
-                    "entries": entries_json,
+                    "depth": tip.record.depth,
+                    "turns": out_turns,
+                    "next_from_depth": next_from_depth,
                 });
+                if partial {
+                    resp["partial"] = JsonValue::Bool(true);
+                    resp["partial_reason"] = JsonValue::String("deadline_exceeded".into());
+                }
+                json_response(200, &resp)
+            }
+            (Method::GET, ["v1", "turns", turn_id, "fs"]) => {
+                let turn_id: u64 = turn_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid turn_id".into()))?;
+                let params = parse_query(url.query().unwrap_or(""));
+                let path = params.get("path").map(|s| s.as_str()).unwrap_or("");
+                let follow_symlinks = follow_symlinks_param(&params);
+                let deadline = request_deadline(config, &request, &params);
+
+                let mut store = store.lock().unwrap();
+                fs_listing_response(&mut store, turn_id, path, follow_symlinks, &deadline)
+            }
+            // Whole-snapshot manifest with a fetch plan for local checkouts
+            (Method::GET, ["v1", "turns", turn_id, "fs", "manifest"]) => {
+                let turn_id: u64 = turn_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid turn_id".into()))?;
+                let params = parse_query(url.query().unwrap_or(""));
+                let deadline = request_deadline(config, &request, &params);
 
-                let bytes = serde_json::to_vec(&resp)
+                let mut store = store.lock().unwrap();
+                let manifest = store.fs_manifest(turn_id, &deadline)?;
+                let snapshot = store
+                    .get_fs_snapshot(turn_id)
+                    .ok_or_else(|| StoreError::NotFound("no fs snapshot for turn".into()))?;
+                let mut body = serde_json::to_value(&manifest)
                     .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
-                Ok((
-                    200,
-                    Response::from_data(bytes)
-                        .with_status_code(StatusCode(200))
-                        .with_header(
-                            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
-                                .unwrap(),
-                        ),
-                ))
+                body["snapshot"] = fs_snapshot_json(&snapshot);
+                json_response(200, &body)
             }
             // Filesystem snapshot: get file content or directory listing
-            (Method::Get, ["v1", "turns", turn_id, "fs", rest @ ..]) => {
+            (Method::GET, ["v1", "turns", turn_id, "fs", rest @ ..]) => {
                 let turn_id: u64 = turn_id
                     .parse()
                     .map_err(|_| StoreError::InvalidInput("invalid turn_id".into()))?;
-                let path = rest.join("/");
+                let path = rest
+                    .iter()
+                    .map(|segment| decode_segment(segment))
+                    .collect::<Result<Vec<_>>>()?
+                    .join("/");
 
                 if path.is_empty() {
                     return Err(StoreError::InvalidInput("empty file path".into()));
@@ -768,253 +2846,1129 @@ fn handle_request(
 
                 let params = parse_query(url.query().unwrap_or(""));
                 let as_json = params.get("format").map(|s| s.as_str()) == Some("json");
+                let detect_language = params
+                    .get("detect")
+                    .is_some_and(|v| v == "1" || v == "true");
+                let follow_symlinks = follow_symlinks_param(&params);
+                let deadline = request_deadline(config, &request, &params);
+
+                let range = header_value(&request, "Range").filter(|_| !as_json);
 
                 let mut store = store.lock().unwrap();
 
+                // Byte ranges are read from the blob pack without loading the whole file
+                if let Some(range) = &range {
+                    match store.stat_fs_file(turn_id, &path, follow_symlinks, &deadline) {
+                        Ok((entry, total)) => match parse_byte_range(Some(range), total) {
+                            ByteRange::Full => {}
+                            ByteRange::Partial { start, end } => {
+                                let content =
+                                    store.read_fs_range(turn_id, &entry, start, end - start + 1)?;
+                                return Ok(ranged_response(
+                                    fs_raw_response(content, &entry, &path, None),
+                                    Some((start, end)),
+                                    total,
+                                ));
+                            }
+                            ByteRange::Unsatisfiable => return Ok(range_not_satisfiable(total)),
+                        },
+                        Err(StoreError::InvalidInput(msg))
+                            if msg.starts_with("path is a directory") => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+
                 // First try to get it as a file
-                match store.get_fs_file(turn_id, &path) {
-                    Ok((content, entry)) => {
-                        if as_json {
-                            // Return as JSON with base64 content
-                            let kind_str = match EntryKind::from(entry.kind) {
-                                EntryKind::File => "file",
-                                EntryKind::Directory => "dir",
-                                EntryKind::Symlink => "symlink",
-                            };
-                            let resp = json!({
-                                "turn_id": turn_id.to_string(),
-                                "path": path,
-                                "name": entry.name,
-                                "kind": kind_str,
-                                "mode": format!("{:o}", entry.mode),
-                                "size": entry.size,
-                                "hash": hex::encode(&entry.hash),
-                                "content_base64": base64::Engine::encode(
-                                    &base64::engine::general_purpose::STANDARD,
-                                    &content
-                                ),
-                            });
-                            let bytes = serde_json::to_vec(&resp).map_err(|e| {
-                                StoreError::InvalidInput(format!("json encode error: {e}"))
-                            })?;
-                            Ok((
-                                200,
-                                Response::from_data(bytes)
-                                    .with_status_code(StatusCode(200))
-                                    .with_header(
-                                        Header::from_bytes(
-                                            &b"Content-Type"[..],
-                                            &b"application/json"[..],
-                                        )
-                                        .unwrap(),
-                                    ),
-                            ))
-                        } else {
-                            // Return raw content
-                            let content_type = guess_content_type(&path);
-                            Ok((
-                                200,
-                                Response::from_data(content)
-                                    .with_status_code(StatusCode(200))
-                                    .with_header(
-                                        Header::from_bytes(
-                                            &b"Content-Type"[..],
-                                            content_type.as_bytes(),
-                                        )
-                                        .unwrap(),
-                                    )
-                                    .with_header(
-                                        Header::from_bytes(
-                                            &b"X-Fs-Hash"[..],
-                                            hex::encode(&entry.hash).as_bytes(),
-                                        )
-                                        .unwrap(),
-                                    )
-                                    .with_header(
-                                        Header::from_bytes(
-                                            &b"X-Fs-Mode"[..],
-                                            format!("{:o}", entry.mode).as_bytes(),
-                                        )
-                                        .unwrap(),
-                                    ),
-                            ))
+                match store.get_fs_file(turn_id, &path, follow_symlinks, &deadline) {
+                    Ok((content, entry)) if as_json => {
+                        // Return as JSON with base64 content
+                        let mut resp = fs_entry_json(&mut store, turn_id, &entry);
+                        resp["turn_id"] = json!(turn_id.to_string());
+                        resp["path"] = json!(path);
+                        if let Some(info) = file_info(detect_language, &entry, &path, &content) {
+                            resp["binary"] = json!(info.binary);
+                            resp["language"] = json!(info.language);
+                            resp["line_count"] = json!(info.line_count);
                         }
+                        resp["content_base64"] = json!(base64::Engine::encode(
+                            &base64::engine::general_purpose::STANDARD,
+                            &content
+                        ));
+                        json_response(200, &resp)
                     }
-                    Err(StoreError::InvalidInput(msg)) if msg.contains("directory") => {
-                        // Path is a directory - return listing instead
-                        let fs_root = store.get_fs_root(turn_id).ok_or_else(|| {
-                            StoreError::NotFound("no fs snapshot for turn".into())
-                        })?;
-
-                        let entries = store.list_fs_entries(turn_id, &path)?;
-
-                        let entries_json: Vec<JsonValue> = entries
-                            .iter()
-                            .map(|e| {
-                                let kind_str = match EntryKind::from(e.kind) {
-                                    EntryKind::File => "file",
-                                    EntryKind::Directory => "dir",
-                                    EntryKind::Symlink => "symlink",
-                                };
-                                json!({
-                                    "name": e.name,
-                                    "kind": kind_str,
-                                    "mode": format!("{:o}", e.mode),
-                                    "size": e.size,
-                                    "hash": hex::encode(&e.hash),
-                                })
-                            })
-                            .collect();
-
-                        let resp = json!({
-                            "turn_id": turn_id.to_string(),
-                            "path": path,
-                            "fs_root_hash": hex::encode(fs_root),
-                            "entries": entries_json,
-                        });
-
-                        let bytes = serde_json::to_vec(&resp).map_err(|e| {
-                            StoreError::InvalidInput(format!("json encode error: {e}"))
-                        })?;
-                        Ok((
-                            200,
-                            Response::from_data(bytes)
-                                .with_status_code(StatusCode(200))
-                                .with_header(
-                                    Header::from_bytes(
-                                        &b"Content-Type"[..],
-                                        &b"application/json"[..],
-                                    )
-                                    .unwrap(),
-                                ),
+                    Ok((content, entry)) => {
+                        let total = content.len() as u64;
+                        let info = file_info(detect_language, &entry, &path, &content);
+                        Ok(ranged_response(
+                            fs_raw_response(content, &entry, &path, info),
+                            None,
+                            total,
                         ))
                     }
+                    Err(StoreError::InvalidInput(msg))
+                        if msg.starts_with("path is a directory") =>
+                    {
+                        // Path is a directory - return listing instead
+                        fs_listing_response(&mut store, turn_id, &path, follow_symlinks, &deadline)
+                    }
                     Err(e) => Err(e),
                 }
             }
+            // Named attachments on turns
+            (Method::GET, ["v1", "turns", turn_id, "attachments"]) => {
+                let turn_id: u64 = turn_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid turn_id".into()))?;
+                let mut store = store.lock().unwrap();
+                store.get_turn(turn_id, false)?;
+                let attachments: Vec<JsonValue> = store
+                    .turn_attachments(turn_id)
+                    .iter()
+                    .map(|a| attachment_json(turn_id, a))
+                    .collect();
+                json_response(
+                    200,
+                    &json!({
+                        "turn_id": turn_id.to_string(),
+                        "attachments": attachments,
+                    }),
+                )
+            }
+            (Method::POST, ["v1", "turns", turn_id, "attachments"]) => {
+                let turn_id: u64 = turn_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid turn_id".into()))?;
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let parsed: JsonValue = parse_body(&request, &body)?;
+                let field = |name: &str| {
+                    parsed
+                        .get(name)
+                        .and_then(|v| v.as_str())
+                        .ok_or_else(|| StoreError::InvalidInput(format!("{name} required")))
+                };
+                let name = field("name")?;
+                let mime_type = field("mime_type")?;
+                let mut hash = [0u8; 32];
+                hex::decode_to_slice(field("hash")?, &mut hash)
+                    .map_err(|_| StoreError::InvalidInput("invalid hash".into()))?;
+                let size = match parsed.get("size") {
+                    None | Some(JsonValue::Null) => None,
+                    Some(v) => Some(
+                        json_u64(v)
+                            .ok_or_else(|| StoreError::InvalidInput("invalid size".into()))?,
+                    ),
+                };
+                let attachment = store
+                    .lock()
+                    .unwrap()
+                    .attach_blob(turn_id, name, mime_type, hash, size)?;
+                json_response(201, &attachment_json(turn_id, &attachment))
+            }
+            (Method::PUT, ["v1", "turns", turn_id, "attachments", name]) => {
+                let turn_id: u64 = turn_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid turn_id".into()))?;
+                let name = decode_segment(name)?;
+                let mime_type = header_value(&request, "Content-Type")
+                    .unwrap_or_else(|| guess_content_type(&name).to_string());
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body)?;
+                let attachment = store
+                    .lock()
+                    .unwrap()
+                    .attach_data(turn_id, &name, &mime_type, &body)?;
+                json_response(200, &attachment_json(turn_id, &attachment))
+            }
+            (Method::GET, ["v1", "turns", turn_id, "attachments", name]) => {
+                let turn_id: u64 = turn_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid turn_id".into()))?;
+                let name = decode_segment(name)?;
+                let (attachment, content) = store.lock().unwrap().get_attachment(turn_id, &name)?;
+                let total = content.len() as u64;
+                let range = header_value(&request, "Range");
+                let (content, partial) = match parse_byte_range(range.as_deref(), total) {
+                    ByteRange::Full => (content, None),
+                    ByteRange::Partial { start, end } => (
+                        content[start as usize..=end as usize].to_vec(),
+                        Some((start, end)),
+                    ),
+                    ByteRange::Unsatisfiable => return Ok(range_not_satisfiable(total)),
+                };
+                let disposition = format!(
+                    "attachment; filename*=UTF-8''{}",
+                    utf8_percent_encode(&attachment.name, UNRESERVED)
+                );
+                let response = Response::from_data(content)
+                    .with_header(Header::new("Content-Type", &attachment.mime_type))
+                    .with_header(Header::new("Content-Disposition", &disposition))
+                    .with_header(Header::new(
+                        "ETag",
+                        &format!("\"{}\"", hex::encode(attachment.hash)),
+                    ));
+                Ok(ranged_response(response, partial, total))
+            }
+            (Method::DELETE, ["v1", "turns", turn_id, "attachments", name]) => {
+                let turn_id: u64 = turn_id
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput("invalid turn_id".into()))?;
+                let name = decode_segment(name)?;
+                store.lock().unwrap().detach(turn_id, &name)?;
+                Ok((204, Response::from_data(Vec::new()).with_status_code(204)))
+            }
             _ => Err(StoreError::NotFound("route".into())),
         }
     })();
 
+    // An alias resolves to a different turn once the context grows.
+    let result = match alias_context {
+        Some(_) => result.map(|(status, response)| (status, no_store(response))),
+        None => result,
+    };
     match result {
         Ok((status, response)) => {
-            metrics.record_http(status, start.elapsed());
+            let response = if route.starts_with("/v1/") {
+                let response = msgpack_response(response, &request);
+                compress_response(response, &request, config, metrics)
+            } else {
+                response
+            };
+            metrics.record_http(&method_label, &route, status, start.elapsed());
+            PendingAccess::finish(access, status, response.data_length());
             request.respond(response).map_err(StoreError::Io)
         }
         Err(err) => {
             let (status, message) = map_error(&err);
-            metrics.record_http(status, start.elapsed());
+            metrics.record_http(&method_label, &route, status, start.elapsed());
             metrics.record_error("http");
             let bytes = serde_json::to_vec(&json!({"error": {"code": status, "message": message}}))
                 .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
-            let response = Response::from_data(bytes)
-                .with_status_code(StatusCode(status))
-                .with_header(
-                    Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
-                );
+            let mut response = Response::from_data(bytes)
+                .with_status_code(status)
+                .with_header(Header::new("Content-Type", "application/json"));
+            if status == 401 {
+                response.add_header(Header::new("WWW-Authenticate", "Bearer"));
+            }
+            let response = msgpack_response(response, &request);
+            PendingAccess::finish(access, status, response.data_length());
+            request.respond(response).map_err(StoreError::Io)
+        }
+    }
+}
+
+/// A request body as JSON, or as MessagePack when its `Content-Type` says so.
+fn parse_body<T: serde::de::DeserializeOwned>(request: &Request, body: &[u8]) -> Result<T> {
+    let content_type = request
+        .headers()
+        .iter()
+        .find(|h| h.field == "Content-Type")
+        .map(|h| h.value.as_str());
+    if content_type.is_some_and(msgpack::is_msgpack) {
+        serde_json::from_value(msgpack::decode_body(body)?)
+            .map_err(|e| StoreError::InvalidInput(format!("invalid msgpack: {e}")))
+    } else {
+        serde_json::from_slice(body)
+            .map_err(|e| StoreError::InvalidInput(format!("invalid json: {e}")))
+    }
+}
+
+/// Re-encode a JSON response as MessagePack when the request's `Accept`
+/// header prefers it. Other responses pass through.
+fn msgpack_response(
+    response: Response<std::io::Cursor<Vec<u8>>>,
+    request: &Request,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let wanted = request
+        .headers()
+        .iter()
+        .find(|h| h.field == "Accept")
+        .is_some_and(|h| msgpack::prefers_msgpack(h.value.as_str()));
+    let is_json = response
+        .headers()
+        .iter()
+        .any(|h| h.field == "Content-Type" && h.value.as_str().starts_with("application/json"));
+    if !wanted || !is_json {
+        return response;
+    }
+
+    let status = response.status_code();
+    let mut headers = response.headers().to_vec();
+    let body = response.into_reader().into_inner();
+    let Ok(encoded) = msgpack::encode_json_body(&body) else {
+        let len = body.len();
+        return Response::new(status, headers, std::io::Cursor::new(body), Some(len));
+    };
+    headers.retain(|h| h.field != "Content-Type");
+    headers.push(Header::new("Content-Type", MSGPACK_CONTENT_TYPE));
+    headers.push(Header::new("Vary", "Accept"));
+    let len = encoded.len();
+    Response::new(status, headers, std::io::Cursor::new(encoded), Some(len))
+}
+
+/// Compress a JSON or MessagePack response body with the encoding the client prefers, when
+/// it is at least `compress_min_bytes`. Other responses pass through.
+fn compress_response(
+    response: Response<std::io::Cursor<Vec<u8>>>,
+    request: &Request,
+    config: &HttpConfig,
+    metrics: &Metrics,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let min_bytes = config.compress_min_bytes;
+    if min_bytes == 0 || response.data_length().is_none_or(|len| len < min_bytes) {
+        return response;
+    }
+    let header = |name: &'static str| {
+        response
+            .headers()
+            .iter()
+            .find(|h| h.field == name)
+            .map(|h| h.value.as_str())
+    };
+    if !header("Content-Type")
+        .is_some_and(|v| v.starts_with("application/json") || v == MSGPACK_CONTENT_TYPE)
+        || header("Content-Encoding").is_some()
+        || header("Content-Range").is_some()
+    {
+        return response;
+    }
+    let Some(encoding) = request
+        .headers()
+        .iter()
+        .find(|h| h.field == "Accept-Encoding")
+        .and_then(|h| ContentEncoding::negotiate(h.value.as_str()))
+    else {
+        return response;
+    };
+
+    let status = response.status_code();
+    let mut headers = response.headers().to_vec();
+    let body = response.into_reader().into_inner();
+    let t0 = Instant::now();
+    let compressed = match encoding.compress(&body) {
+        Ok(compressed) if compressed.len() < body.len() => compressed,
+        _ => {
+            let len = body.len();
+            return Response::new(status, headers, std::io::Cursor::new(body), Some(len));
+        }
+    };
+    metrics.record_http_compression(encoding.name(), body.len(), compressed.len(), t0.elapsed());
+
+    headers.push(Header::new("Content-Encoding", encoding.name()));
+    headers.push(Header::new("Vary", "Accept-Encoding"));
+    let len = compressed.len();
+    Response::new(status, headers, std::io::Cursor::new(compressed), Some(len))
+}
+
+/// One row of context metadata per matching context, as CSV or Parquet.
+fn export_response(
+    url: &Url,
+    store: &Arc<Mutex<Store>>,
+    session_tracker: &Arc<SessionTracker>,
+) -> Result<Response<Box<dyn Read + Send>>> {
+    let params = parse_query(url.query().unwrap_or(""));
+    let format = ExportFormat::parse(params.get("format").map_or("csv", |s| s.as_str()))?;
+    let rows = {
+        let mut store = store.lock().unwrap();
+        match params.get("q").filter(|q| !q.is_empty()) {
+            Some(query) => {
+                let live_contexts = session_tracker.get_live_context_ids();
+                let ids = store
+                    .search_contexts(query, &live_contexts, None)
+                    .map_err(|e| StoreError::InvalidInput(format!("invalid query: {}", e.message)))?
+                    .context_ids;
+                store.export_rows(Some(&ids))?
+            }
+            None => store.export_rows(None)?,
+        }
+    };
+
+    let disposition = format!("attachment; filename=\"contexts.{}\"", format.extension());
+    let headers = vec![
+        Header::new("Content-Type", format.content_type()),
+        Header::new("Content-Disposition", &disposition),
+    ];
+    Ok(match format {
+        // No length: sent with chunked transfer encoding as rows are encoded.
+        ExportFormat::Csv => Response::new(
+            200,
+            headers,
+            Box::new(CsvStream::new(rows)) as Box<dyn Read + Send>,
+            None,
+        ),
+        ExportFormat::Parquet => {
+            let bytes = write_parquet(&rows)?;
+            let len = bytes.len();
+            Response::new(
+                200,
+                headers,
+                Box::new(std::io::Cursor::new(bytes)) as Box<dyn Read + Send>,
+                Some(len),
+            )
+        }
+    })
+}
+
+/// Send a streamed response, or the usual JSON error, recording metrics.
+fn respond_streamed(
+    request: Request,
+    result: Result<Response<Box<dyn Read + Send>>>,
+    metrics: &Metrics,
+    access: Option<PendingAccess<'_>>,
+    route: &str,
+    start: Instant,
+) -> Result<()> {
+    let method_label = request.method().as_str().to_string();
+    match result {
+        Ok(response) => {
+            metrics.record_http(&method_label, route, 200, start.elapsed());
+            PendingAccess::finish(access, 200, response.data_length());
+            request.respond(response).map_err(StoreError::Io)
+        }
+        Err(err) => {
+            let (status, message) = map_error(&err);
+            metrics.record_http(&method_label, route, status, start.elapsed());
+            metrics.record_error("http");
+            let body = json!({"error": {"code": status, "message": message}});
+            let response = Response::from_data(body.to_string())
+                .with_status_code(status)
+                .with_header(Header::new("Content-Type", "application/json"));
+            PendingAccess::finish(access, status, response.data_length());
             request.respond(response).map_err(StoreError::Io)
         }
     }
 }
 
+/// Whether a route reads the store, so it waits for the startup index build.
+/// Registry, schema, metrics, quota, operations and event routes are served during
+/// warm-up.
+fn needs_warm_store(segments: &[&str]) -> bool {
+    match segments {
+        ["v1", "registry", ..]
+        | ["v1", "protocol", ..]
+        | ["v1", "metrics"]
+        | ["v1", "quotas"]
+        | ["v1", "operations", ..]
+        | ["v1", "admin", "profile"]
+        | ["v1", "events"] => false,
+        ["v1", ..] => true,
+        _ => false,
+    }
+}
+
+fn with_retry_after<R: Read>(response: Response<R>, readiness: &Readiness) -> Response<R> {
+    let secs = readiness.retry_after_secs().to_string();
+    response.with_header(Header::new("Retry-After", &secs))
+}
+
+/// Answer 503 with the warm-up progress while the server is not ready.
+fn respond_warming(
+    request: Request,
+    readiness: &Readiness,
+    metrics: &Metrics,
+    access: Option<PendingAccess<'_>>,
+    route: &str,
+    start: Instant,
+) -> Result<()> {
+    let method_label = request.method().as_str().to_string();
+    let body = json!({
+        "error": { "code": 503, "message": "server is warming up" },
+        "readiness": readiness.status(),
+    });
+    let (status, response) = json_response(503, &body)?;
+    metrics.record_http(&method_label, route, status, start.elapsed());
+    PendingAccess::finish(access, status, response.data_length());
+    request
+        .respond(with_retry_after(response, readiness))
+        .map_err(StoreError::Io)
+}
+
 /// Handle SSE (Server-Sent Events) stream for /v1/events.
 ///
 /// This function takes ownership of the request and streams events to the client.
-/// It spawns a thread to handle the long-lived connection.
-fn handle_sse_stream(request: tiny_http::Request, event_bus: &Arc<EventBus>) -> Result<()> {
-    let event_bus = Arc::clone(event_bus);
+/// It spawns a thread to handle the long-lived connection. Responds 503 when
+/// the event bus is at its stream limit.
+fn handle_sse_stream(
+    request: Request,
+    event_bus: &Arc<EventBus>,
+    viewing: Option<(&Arc<Presence>, u64, ViewerId)>,
+    access: Option<PendingAccess<'_>>,
+) -> Result<()> {
+    // Subscribe to event bus
+    let Some(subscriber) = event_bus.subscribe_stream() else {
+        let response = sse_error_response(503, "too many event streams")
+            .with_header(Header::new("Retry-After", "5"));
+        PendingAccess::finish(access, 503, response.data_length());
+        let _ = request.respond(response);
+        return Ok(());
+    };
+    // The stream is logged when it opens; its length is unknown.
+    PendingAccess::finish(access, 200, None);
+    // The stream counts as viewing its context until the thread below exits.
+    let viewing =
+        viewing.map(|(presence, context_id, viewer)| presence.open_stream(context_id, viewer));
+
+    let headers = vec![
+        Header::new("Content-Type", "text/event-stream"),
+        Header::new("Cache-Control", "no-cache"),
+        Header::new("Access-Control-Allow-Origin", "*"),
+    ];
+    let Ok(mut writer) = request.into_writer(200, headers) else {
+        return Ok(()); // Client disconnected
+    };
+
+    // Spawn thread to stream events
+    thread::spawn(move || {
+        let _viewing = viewing;
+        let heartbeat_interval = Duration::from_secs(20);
+        let mut last_heartbeat = Instant::now();
+
+        // Send initial connected event
+        if write_sse_event(&mut writer, "connected", "{}").is_err() {
+            return;
+        }
+
+        loop {
+            // Check for events with timeout
+            match subscriber.recv_timeout(Duration::from_secs(5)) {
+                Some(event) => {
+                    // Tell the client it fell behind so it can refetch state.
+                    let dropped = subscriber.take_dropped();
+                    if dropped > 0 {
+                        let data = serde_json::json!({ "count": dropped }).to_string();
+                        if write_sse_event(&mut writer, "events_dropped", &data).is_err() {
+                            break;
+                        }
+                    }
+                    let (event_type, data) = event.to_sse();
+                    if write_sse_event(&mut writer, event_type, &data).is_err() {
+                        break; // Connection closed
+                    }
+                    last_heartbeat = Instant::now();
+                }
+                None => {
+                    // Disconnected by the bus for overflowing its queue.
+                    if subscriber.is_closed() {
+                        break;
+                    }
+                    // No event, check if we need to send heartbeat
+                    if last_heartbeat.elapsed() >= heartbeat_interval {
+                        if write_sse_heartbeat(&mut writer).is_err() {
+                            break;
+                        }
+                        last_heartbeat = Instant::now();
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// JSON error answered instead of opening an event stream.
+fn sse_error_response(status: u16, message: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    let body = serde_json::json!({
+        "error": { "code": status, "message": message }
+    });
+    Response::from_data(body.to_string())
+        .with_status_code(status)
+        .with_header(Header::new("Content-Type", "application/json"))
+}
+
+/// The context an event stream views (`?viewing=<context_id>`), if any.
+fn sse_viewing(
+    url: &Url,
+    request: &Request,
+    principal: Option<&str>,
+    store: &Mutex<Store>,
+) -> Result<Option<(u64, ViewerId)>> {
+    let params = parse_query(url.query().unwrap_or(""));
+    let Some(viewing) = params.get("viewing") else {
+        return Ok(None);
+    };
+    let context_id: u64 = viewing
+        .parse()
+        .map_err(|_| StoreError::InvalidInput("invalid viewing context_id".into()))?;
+    store.lock().unwrap().get_head(context_id)?;
+    Ok(Some((context_id, request_viewer(request, principal))))
+}
+
+/// A replay ready to stream: its control and the turns it delivers.
+struct ReplayPlan {
+    control: Arc<ReplayControl>,
+    turns: Vec<TurnRecord>,
+    max_gap: Option<Duration>,
+    view: TurnView,
+}
+
+/// Validate a replay request and register the replay.
+fn start_replay(
+    context_id: &str,
+    url: &Url,
+    store: &Mutex<Store>,
+    replays: &Replays,
+) -> Result<ReplayPlan> {
+    let context_id: u64 = context_id
+        .parse()
+        .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+    let params = parse_query(url.query().unwrap_or(""));
+    let speed = match params.get("speed") {
+        Some(raw) => ReplaySpeed::parse(raw)?,
+        None => ReplaySpeed::default(),
+    };
+    let max_gap = match params.get("max_gap_ms") {
+        Some(raw) => {
+            Some(Duration::from_millis(raw.parse().map_err(|_| {
+                StoreError::InvalidInput("invalid max_gap_ms".into())
+            })?))
+        }
+        None => None,
+    };
+
+    let mut store = store.lock().unwrap();
+    let head = store.get_head(context_id)?;
+    let start_depth = match params.get("from_turn") {
+        Some(raw) => {
+            let turn_id: u64 = raw
+                .parse()
+                .map_err(|_| StoreError::InvalidInput("invalid from_turn".into()))?;
+            if !store.is_turn_in_chain(turn_id, head.head_turn_id) {
+                return Err(StoreError::InvalidInput(format!(
+                    "turn {turn_id} is not in the history of context {context_id}"
+                )));
+            }
+            store.get_turn(turn_id, false)?.record.depth
+        }
+        None => 0,
+    };
+    let turns: Vec<TurnRecord> = if head.head_turn_id == 0 {
+        Vec::new()
+    } else {
+        store
+            .get_ancestry(head.head_turn_id, start_depth, u32::MAX, false)?
+            .into_iter()
+            .map(|item| item.record)
+            .collect()
+    };
+    drop(store);
+    Ok(ReplayPlan {
+        control: replays.start(context_id, turns.len(), speed),
+        turns,
+        max_gap,
+        view: TurnView::from_params(&params),
+    })
+}
 
-    // Build SSE headers
+/// Stream a replay over SSE from a thread of its own: `replay_started`,
+/// then one `turn` event per turn once its scaled gap has elapsed (followed
+/// by `fs_attached` when a snapshot was attached to it), `replay_paused` and
+/// `replay_resumed` as the replay is paused, and finally `replay_completed`
+/// or `replay_stopped`.
+fn handle_replay_stream(
+    request: Request,
+    replay: Result<ReplayPlan>,
+    store: &Arc<Mutex<Store>>,
+    registry: &Arc<Mutex<Registry>>,
+    replays: &Arc<Replays>,
+    access: Option<PendingAccess<'_>>,
+) -> Result<()> {
+    let replay = match replay {
+        Ok(replay) => replay,
+        Err(err) => {
+            let (status, message) = map_error(&err);
+            let response = sse_error_response(status, &message);
+            PendingAccess::finish(access, status, response.data_length());
+            let _ = request.respond(response);
+            return Ok(());
+        }
+    };
+    PendingAccess::finish(access, 200, None);
     let headers = vec![
-        Header::from_bytes(&b"Content-Type"[..], &b"text/event-stream"[..]).unwrap(),
-        Header::from_bytes(&b"Cache-Control"[..], &b"no-cache"[..]).unwrap(),
-        Header::from_bytes(&b"Connection"[..], &b"keep-alive"[..]).unwrap(),
-        Header::from_bytes(&b"Access-Control-Allow-Origin"[..], &b"*"[..]).unwrap(),
+        Header::new("Content-Type", "text/event-stream"),
+        Header::new("Cache-Control", "no-cache"),
+        Header::new("Access-Control-Allow-Origin", "*"),
     ];
+    let Ok(mut writer) = request.into_writer(200, headers) else {
+        replays.finish(replay.control.replay_id);
+        return Ok(());
+    };
+
+    let store = Arc::clone(store);
+    let registry = Arc::clone(registry);
+    let replays = Arc::clone(replays);
+    thread::spawn(move || {
+        let control = &replay.control;
+        let _ = stream_replay(&mut writer, &replay, &store, &registry);
+        // A write error means the client went away; stop either way.
+        control.stop();
+        replays.finish(control.replay_id);
+    });
+    Ok(())
+}
+
+fn stream_replay<W: Write>(
+    writer: &mut W,
+    replay: &ReplayPlan,
+    store: &Mutex<Store>,
+    registry: &Mutex<Registry>,
+) -> std::io::Result<()> {
+    let heartbeat_interval = Duration::from_secs(20);
+    let control = &replay.control;
+    let status = serde_json::to_string(&control.status()).unwrap_or_default();
+    write_sse_event(writer, "replay_started", &status)?;
+    let mut last_write = Instant::now();
+    let mut paused = false;
+    let mut previous_at = None;
+
+    for record in &replay.turns {
+        let gap = previous_at.map_or(0, |at: u64| record.created_at_unix_ms.saturating_sub(at));
+        previous_at = Some(record.created_at_unix_ms);
+        let mut remaining = control.speed.scale(gap, replay.max_gap);
+        loop {
+            let outcome = control.wait(&mut remaining, Duration::from_secs(1));
+            if control.is_paused() != paused {
+                paused = !paused;
+                let event = if paused {
+                    "replay_paused"
+                } else {
+                    "replay_resumed"
+                };
+                let status = serde_json::to_string(&control.status()).unwrap_or_default();
+                write_sse_event(writer, event, &status)?;
+                last_write = Instant::now();
+            }
+            match outcome {
+                ReplayWait::Due => break,
+                ReplayWait::Stopped => {
+                    let status = serde_json::to_string(&control.status()).unwrap_or_default();
+                    return write_sse_event(writer, "replay_stopped", &status);
+                }
+                ReplayWait::Waiting => {
+                    if last_write.elapsed() >= heartbeat_interval {
+                        write_sse_heartbeat(writer)?;
+                        last_write = Instant::now();
+                    }
+                }
+            }
+        }
+
+        let rendered = {
+            let mut store = store.lock().unwrap();
+            let item = store.get_turn(record.turn_id, true);
+            let registry = registry.lock().unwrap();
+            item.and_then(|item| {
+                turn_json(&store, &registry, &item, &replay.view, &Deadline::none())
+            })
+            .map(|turn| (turn, store.get_fs_root_direct(record.turn_id)))
+        };
+        let (mut turn, fs_root) = match rendered {
+            Ok((Some(turn), fs_root)) => (turn, fs_root),
+            Ok((None, _)) => continue,
+            Err(err) => {
+                let (status, message) = map_error(&err);
+                let data = json!({
+                    "turn_id": record.turn_id.to_string(),
+                    "error": { "code": status, "message": message },
+                });
+                write_sse_event(writer, "turn_error", &data.to_string())?;
+                continue;
+            }
+        };
+        turn["appended_at"] = json!(record.created_at_unix_ms);
+        write_sse_event(writer, "turn", &turn.to_string())?;
+        if let Some(root) = fs_root {
+            let data = json!({
+                "turn_id": record.turn_id.to_string(),
+                "root_hash": hex::encode(root),
+            });
+            write_sse_event(writer, "fs_attached", &data.to_string())?;
+        }
+        control.record_delivered();
+        last_write = Instant::now();
+    }
+    let status = serde_json::to_string(&control.status()).unwrap_or_default();
+    write_sse_event(writer, "replay_completed", &status)
+}
+
+/// Write an SSE event to the stream as one chunk.
+fn write_sse_event<W: Write>(writer: &mut W, event_type: &str, data: &str) -> std::io::Result<()> {
+    let message = format!("event: {}\ndata: {}\n\n", event_type, data);
+    writer.write_all(message.as_bytes())?;
+    writer.flush()
+}
+
+/// Write an SSE heartbeat comment to keep the connection alive.
+fn write_sse_heartbeat<W: Write>(writer: &mut W) -> std::io::Result<()> {
+    writer.write_all(b":heartbeat\n\n")?;
+    writer.flush()
+}
+
+fn federation_disabled() -> StoreError {
+    StoreError::NotFound("federation is not enabled (set CXDB_FEDERATION_PEERS)".into())
+}
+
+/// Contexts matching a CQL query, as `GET /v1/contexts/search` lists them.
+fn context_search_json(
+    store: &mut Store,
+    session_tracker: &SessionTracker,
+    query: &str,
+    limit: Option<u32>,
+) -> std::result::Result<JsonValue, CqlError> {
+    // Get live context IDs from session tracker
+    let live_contexts = session_tracker.get_live_context_ids();
+    let result = store.search_contexts(query, &live_contexts, limit)?;
+    // Fetch full context details for matching IDs
+    let contexts_json: Vec<JsonValue> = result
+        .context_ids
+        .iter()
+        .filter_map(|&context_id| {
+            let head = store.turn_store.get_head(context_id).ok()?;
+            let is_live = session_tracker.is_context_live(context_id);
+
+            let mut obj = json!({
+                "context_id": context_id.to_string(),
+                "head_turn_id": head.head_turn_id.to_string(),
+                "head_depth": head.head_depth,
+                "created_at_unix_ms": head.created_at_unix_ms,
+                "is_live": is_live,
+            });
+
+            // Add metadata if available
+            if let Some(metadata) = store.get_context_metadata(context_id) {
+                if let Some(ref tag) = metadata.client_tag {
+                    obj["client_tag"] = JsonValue::String(tag.clone());
+                }
+                if let Some(ref title) = metadata.title {
+                    obj["title"] = JsonValue::String(title.clone());
+                }
+            }
+
+            Some(obj)
+        })
+        .collect();
 
-    // Create a response with chunked transfer encoding
-    // We use an empty data vector and will write to the underlying stream
-    let response = Response::empty(200);
-    let mut response = response.with_status_code(StatusCode(200));
-    for header in headers {
-        response = response.with_header(header);
+    Ok(json!({
+        "contexts": contexts_json,
+        "total_count": result.total_count,
+        "elapsed_ms": result.elapsed_ms,
+        "query": result.query.raw,
+    }))
+}
+
+fn json_response(status: u16, body: &JsonValue) -> Result<HttpResponse> {
+    let bytes = serde_json::to_vec(body)
+        .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+    Ok((
+        status,
+        Response::from_data(bytes)
+            .with_status_code(status)
+            .with_header(Header::new("Content-Type", "application/json")),
+    ))
+}
+
+/// Projection options shared by endpoints that render typed payloads.
+fn parse_render_options(params: &HashMap<String, String>) -> RenderOptions {
+    let bytes_render = match params.get("bytes_render").map(|v| v.as_str()) {
+        Some("hex") => BytesRender::Hex,
+        Some("len_only") => BytesRender::LenOnly,
+        _ => BytesRender::Base64,
+    };
+    let u64_format = match params.get("u64_format").map(|v| v.as_str()) {
+        Some("number") => U64Format::Number,
+        _ => U64Format::String,
+    };
+    let enum_render = match params.get("enum_render").map(|v| v.as_str()) {
+        Some("number") => EnumRender::Number,
+        Some("both") => EnumRender::Both,
+        _ => EnumRender::Label,
+    };
+    let time_render = match params.get("time_render").map(|v| v.as_str()) {
+        Some("unix_ms") => TimeRender::UnixMs,
+        _ => TimeRender::Iso,
+    };
+    let include_unknown = params
+        .get("include_unknown")
+        .map(|v| v == "1")
+        .unwrap_or(false);
+    let apply_defaults = params.get("apply_defaults").is_some_and(|v| v == "1");
+    RenderOptions {
+        bytes_render,
+        u64_format,
+        enum_render,
+        time_render,
+        include_unknown,
+        apply_defaults,
+    }
+}
+
+/// How a turns request renders each turn, from its query parameters.
+struct TurnView {
+    view: String,
+    type_hint_mode: String,
+    as_type_id: Option<String>,
+    as_type_version: Option<u32>,
+    options: RenderOptions,
+    include_provenance: bool,
+}
+
+impl TurnView {
+    fn from_params(params: &HashMap<String, String>) -> Self {
+        Self {
+            view: params
+                .get("view")
+                .cloned()
+                .unwrap_or_else(|| "typed".into()),
+            type_hint_mode: params
+                .get("type_hint_mode")
+                .cloned()
+                .unwrap_or_else(|| "inherit".into()),
+            as_type_id: params.get("as_type_id").cloned(),
+            as_type_version: params
+                .get("as_type_version")
+                .and_then(|v| v.parse::<u32>().ok()),
+            options: parse_render_options(params),
+            include_provenance: params
+                .get("include_provenance")
+                .map(|v| v == "1")
+                .unwrap_or(false),
+        }
     }
+}
 
-    // Get the raw writer from the request
-    // tiny_http's into_writer() takes ownership and returns a Write trait object
-    let mut writer = request.into_writer();
+/// Render a turn as `GET /v1/contexts/:id/turns` lists it. Returns None when
+/// the deadline expires while its payload is being projected.
+fn turn_json(
+    store: &Store,
+    registry: &Registry,
+    item: &TurnWithMeta,
+    view: &TurnView,
+    deadline: &Deadline,
+) -> Result<Option<JsonValue>> {
+    let declared_type_id = item.meta.declared_type_id.clone();
+    let declared_type_version = item.meta.declared_type_version;
 
-    // Write HTTP response headers manually since we're taking raw control
-    let status_line = "HTTP/1.1 200 OK\r\n";
-    let headers_str = "Content-Type: text/event-stream\r\n\
-                       Cache-Control: no-cache\r\n\
-                       Connection: keep-alive\r\n\
-                       Access-Control-Allow-Origin: *\r\n\
-                       Transfer-Encoding: chunked\r\n\r\n";
+    let (decoded_type_id, decoded_type_version) = match view.type_hint_mode.as_str() {
+        "explicit" => {
+            let id = view
+                .as_type_id
+                .clone()
+                .ok_or_else(|| StoreError::InvalidInput("as_type_id required".into()))?;
+            let ver = view
+                .as_type_version
+                .ok_or_else(|| StoreError::InvalidInput("as_type_version required".into()))?;
+            (id, ver)
+        }
+        "latest" => {
+            let latest = registry
+                .get_latest_type_version(&declared_type_id)
+                .ok_or_else(|| StoreError::NotFound("type descriptor".into()))?;
+            (declared_type_id.clone(), latest.version)
+        }
+        _ => (declared_type_id.clone(), declared_type_version),
+    };
 
-    if writer.write_all(status_line.as_bytes()).is_err() {
-        return Ok(()); // Client disconnected
+    let mut turn_obj = Map::new();
+    turn_obj.insert(
+        "turn_id".into(),
+        JsonValue::String(item.record.turn_id.to_string()),
+    );
+    turn_obj.insert(
+        "parent_turn_id".into(),
+        JsonValue::String(item.record.parent_turn_id.to_string()),
+    );
+    turn_obj.insert("depth".into(), JsonValue::Number(item.record.depth.into()));
+    if let Some(tokens) = store.turn_tokens(item.record.turn_id) {
+        turn_obj.insert("tokens".into(), json!(tokens.tokens));
     }
-    if writer.write_all(headers_str.as_bytes()).is_err() {
-        return Ok(());
+    let attachments = store.turn_attachments(item.record.turn_id);
+    if !attachments.is_empty() {
+        turn_obj.insert(
+            "attachments".into(),
+            attachments
+                .iter()
+                .map(|a| attachment_json(item.record.turn_id, a))
+                .collect(),
+        );
+    }
+    if let Some(ingest) = store.turn_ingest(item.record.turn_id) {
+        turn_obj.insert("ingest".into(), json!(ingest.audit));
     }
-    if writer.flush().is_err() {
-        return Ok(());
+    if view.include_provenance {
+        if let Some(author) = &item.meta.author {
+            turn_obj.insert("provenance".into(), turn_author_json(author));
+        }
     }
+    turn_obj.insert(
+        "declared_type".into(),
+        json!({
+            "type_id": declared_type_id,
+            "type_version": declared_type_version,
+        }),
+    );
 
-    // Subscribe to event bus
-    let subscriber = event_bus.subscribe();
-
-    // Spawn thread to stream events
-    thread::spawn(move || {
-        let heartbeat_interval = Duration::from_secs(20);
-        let mut last_heartbeat = Instant::now();
-
-        // Send initial connected event
-        if write_sse_event(&mut writer, "connected", "{}").is_err() {
-            return;
+    if matches!(view.view.as_str(), "typed" | "both" | "text") {
+        let desc = registry
+            .get_type_version(&decoded_type_id, decoded_type_version)
+            .ok_or_else(|| StoreError::NotFound("type descriptor".into()))?;
+        let payload = item
+            .payload
+            .as_ref()
+            .ok_or_else(|| StoreError::InvalidInput("payload not loaded".into()))?;
+        let projected = match crate::projection::cache::project_cached(
+            &item.record.payload_hash,
+            payload,
+            &decoded_type_id,
+            desc,
+            registry,
+            &view.options,
+            deadline,
+        ) {
+            Ok(projected) => projected,
+            Err(StoreError::DeadlineExceeded(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        turn_obj.insert(
+            "decoded_as".into(),
+            json!({
+                "type_id": decoded_type_id,
+                "type_version": decoded_type_version,
+            }),
+        );
+        if view.view == "text" {
+            let template = desc
+                .renderer
+                .as_ref()
+                .and_then(|r| r.text_template.as_deref());
+            turn_obj.insert(
+                "text".into(),
+                JsonValue::String(crate::projection::text::render_text(
+                    &projected.data,
+                    template,
+                )),
+            );
+        } else {
+            turn_obj.insert("data".into(), projected.data);
         }
+        if let Some(unknown) = projected.unknown {
+            turn_obj.insert("unknown".into(), unknown);
+        }
+        if let Some(defaulted) = projected.defaulted {
+            turn_obj.insert("defaulted".into(), json!(defaulted));
+        }
+    }
 
-        loop {
-            // Check for events with timeout
-            match subscriber.recv_timeout(Duration::from_secs(5)) {
-                Some(event) => {
-                    let (event_type, data) = event.to_sse();
-                    if write_sse_event(&mut writer, event_type, &data).is_err() {
-                        break; // Connection closed
-                    }
-                    last_heartbeat = Instant::now();
-                }
-                None => {
-                    // No event, check if we need to send heartbeat
-                    if last_heartbeat.elapsed() >= heartbeat_interval {
-                        if write_sse_heartbeat(&mut writer).is_err() {
-                            break;
-                        }
-                        last_heartbeat = Instant::now();
-                    }
-                }
+    if matches!(view.view.as_str(), "raw" | "both") {
+        let raw_payload = item
+            .payload
+            .as_ref()
+            .ok_or_else(|| StoreError::InvalidInput("payload not loaded".into()))?;
+        turn_obj.insert(
+            "content_hash_b3".into(),
+            JsonValue::String(hex::encode(item.record.payload_hash)),
+        );
+        turn_obj.insert(
+            "encoding".into(),
+            JsonValue::Number(item.meta.encoding.into()),
+        );
+        turn_obj.insert("compression".into(), JsonValue::Number(0u32.into()));
+        turn_obj.insert(
+            "uncompressed_len".into(),
+            JsonValue::Number((raw_payload.len() as u32).into()),
+        );
+        match view.options.bytes_render {
+            BytesRender::Base64 => {
+                turn_obj.insert(
+                    "bytes_b64".into(),
+                    JsonValue::String(
+                        base64::engine::general_purpose::STANDARD.encode(raw_payload),
+                    ),
+                );
+            }
+            BytesRender::Hex => {
+                turn_obj.insert(
+                    "bytes_hex".into(),
+                    JsonValue::String(hex::encode(raw_payload)),
+                );
+            }
+            BytesRender::LenOnly => {
+                turn_obj.insert(
+                    "bytes_len".into(),
+                    JsonValue::Number((raw_payload.len() as u64).into()),
+                );
             }
         }
-    });
+    }
 
-    Ok(())
+    Ok(Some(JsonValue::Object(turn_obj)))
 }
 
-/// Write an SSE event to the stream using chunked encoding.
-fn write_sse_event<W: Write>(writer: &mut W, event_type: &str, data: &str) -> std::io::Result<()> {
-    let message = format!("event: {}\ndata: {}\n\n", event_type, data);
-    let chunk = format!("{:x}\r\n{}\r\n", message.len(), message);
-    writer.write_all(chunk.as_bytes())?;
-    writer.flush()
+/// Resolve the time budget for an expensive read. Clients may request one via
+/// the `budget_ms` query param or the `X-CXDB-Budget-Ms` header; it is capped
+/// by the configured default when that is set.
+fn request_deadline(
+    config: &HttpConfig,
+    request: &Request,
+    params: &HashMap<String, String>,
+) -> Deadline {
+    let requested = params
+        .get("budget_ms")
+        .and_then(|v| v.parse::<u64>().ok())
+        .or_else(|| {
+            request
+                .headers()
+                .iter()
+                .find(|h| h.field == BUDGET_HEADER)
+                .and_then(|h| h.value.as_str().trim().parse::<u64>().ok())
+        })
+        .filter(|v| *v > 0);
+    let budget_ms = match (requested, config.read_budget_ms) {
+        (Some(requested), 0) => requested,
+        (Some(requested), default) => requested.min(default),
+        (None, default) => default,
+    };
+    Deadline::from_budget_ms(budget_ms)
 }
 
-/// Write an SSE heartbeat comment to keep the connection alive.
-fn write_sse_heartbeat<W: Write>(writer: &mut W) -> std::io::Result<()> {
-    let message = ":heartbeat\n\n";
-    let chunk = format!("{:x}\r\n{}\r\n", message.len(), message);
-    writer.write_all(chunk.as_bytes())?;
-    writer.flush()
+/// Head moves returned by `GET /v1/contexts/:id/head-history` by default.
+const DEFAULT_HEAD_HISTORY_LIMIT: usize = 1000;
+/// Most activity buckets one head-history request may produce.
+const MAX_HEAD_HISTORY_BUCKETS: u64 = 10_000;
+
+/// Head moves per `bucket_ms` window, from the window of the first move to
+/// that of the last, empty windows included.
+fn head_activity_buckets(history: &[HeadMove], bucket_ms: u64) -> Result<Vec<JsonValue>> {
+    let (Some(first), Some(last)) = (
+        history.iter().map(|m| m.at_unix_ms).min(),
+        history.iter().map(|m| m.at_unix_ms).max(),
+    ) else {
+        return Ok(Vec::new());
+    };
+    let start = first - first % bucket_ms;
+    let count = (last - start) / bucket_ms + 1;
+    if count > MAX_HEAD_HISTORY_BUCKETS {
+        return Err(StoreError::InvalidInput(format!(
+            "bucket_ms too small: {count} buckets (max {MAX_HEAD_HISTORY_BUCKETS})"
+        )));
+    }
+    let mut moves = vec![0u64; count as usize];
+    let mut branches = vec![0u64; count as usize];
+    for m in history {
+        let i = ((m.at_unix_ms - start) / bucket_ms) as usize;
+        moves[i] += 1;
+        if m.cause == HeadCause::Branched {
+            branches[i] += 1;
+        }
+    }
+    Ok((0..count as usize)
+        .map(|i| {
+            json!({
+                "start_unix_ms": start + i as u64 * bucket_ms,
+                "moves": moves[i],
+                "branches": branches[i],
+            })
+        })
+        .collect())
 }
 
 fn parse_query(query: &str) -> HashMap<String, String> {
@@ -1035,7 +3989,125 @@ fn map_error(err: &StoreError) -> (u16, String) {
         StoreError::InvalidInput(msg) => (422, msg.clone()),
         StoreError::Corrupt(msg) => (500, msg.clone()),
         StoreError::Io(msg) => (500, msg.to_string()),
+        StoreError::Cancelled(msg) => (409, msg.clone()),
+        StoreError::DeadlineExceeded(msg) => (504, msg.clone()),
+        StoreError::PermissionDenied(msg) => (403, msg.clone()),
+        StoreError::Unauthenticated(msg) => (401, msg.clone()),
+        StoreError::Shredded(msg) | StoreError::Expired(msg) => (410, msg.clone()),
+        StoreError::MalformedFrame { .. } => (400, err.to_string()),
+        StoreError::UnsupportedMessage { .. } => (501, err.to_string()),
+        StoreError::DepthLimitExceeded { .. } => (409, err.to_string()),
+        StoreError::UnsupportedFormat { .. } => (500, err.to_string()),
+    }
+}
+
+/// Path segments that name routes rather than carry parameters.
+const ROUTE_LITERALS: &[&str] = &[
+    "admin",
+    "ancestry",
+    "anchors",
+    "assets",
+    "attachments",
+    "backfill-metadata",
+    "batch-get",
+    "blobs",
+    "bookmarks",
+    "bundles",
+    "by-external-id",
+    "cancel",
+    "changes",
+    "clone",
+    "compaction",
+    "compare",
+    "contexts",
+    "create",
+    "diff",
+    "events",
+    "export",
+    "federated",
+    "fs",
+    "fsck",
+    "gc",
+    "healthz",
+    "head",
+    "head-history",
+    "heartbeat",
+    "hold",
+    "keys",
+    "labels",
+    "latest",
+    "mark-read",
+    "memory",
+    "merge",
+    "metrics",
+    "operations",
+    "pause",
+    "plan",
+    "profile",
+    "projects",
+    "proof",
+    "protocol",
+    "provenance",
+    "pull",
+    "push",
+    "quotas",
+    "readyz",
+    "registry",
+    "rename",
+    "renderers",
+    "replay",
+    "replays",
+    "resume",
+    "rollover",
+    "schema",
+    "search",
+    "share",
+    "shares",
+    "shred",
+    "status",
+    "subscriptions",
+    "tags",
+    "thumbnail",
+    "transfer",
+    "tree",
+    "turns",
+    "types",
+    "v1",
+    "values",
+    "verify",
+    "versions",
+    "watches",
+];
+
+/// Route template used to label latency metrics, e.g.
+/// `/v1/contexts/:id/turns`. Parameters become `:id` and filesystem paths
+/// `*` (except the snapshot manifest), so the number of distinct templates
+/// stays bounded.
+pub fn route_template(segments: &[&str]) -> String {
+    if segments.len() > 8 {
+        return "other".to_string();
+    }
+    let mut route = String::new();
+    for (i, segment) in segments.iter().enumerate() {
+        route.push('/');
+        if ROUTE_LITERALS.contains(segment) {
+            route.push_str(segment);
+            if *segment == "fs" && route.starts_with("/v1/turns/") {
+                match &segments[i + 1..] {
+                    [] => {}
+                    ["manifest"] => route.push_str("/manifest"),
+                    _ => route.push_str("/*"),
+                }
+                break;
+            }
+        } else {
+            route.push_str(":id");
+        }
+    }
+    if route.is_empty() {
+        route.push('/');
     }
+    route
 }
 
 fn renderer_spec_to_json(spec: &RendererSpec) -> JsonValue {
@@ -1047,10 +4119,15 @@ fn renderer_spec_to_json(spec: &RendererSpec) -> JsonValue {
     if let Some(integrity) = &spec.integrity {
         obj.insert("integrity".into(), JsonValue::String(integrity.clone()));
     }
+    if let Some(template) = &spec.text_template {
+        obj.insert("text_template".into(), JsonValue::String(template.clone()));
+    }
     JsonValue::Object(obj)
 }
 
-fn type_version_to_json(spec: &TypeVersionSpec) -> JsonValue {
+/// JSON form of a type version as served by the registry endpoints; publishing
+/// it back in a bundle yields the same descriptor.
+pub fn type_version_to_json(spec: &TypeVersionSpec) -> JsonValue {
     use crate::registry::ItemsSpec;
 
     let mut fields = Map::new();
@@ -1065,18 +4142,37 @@ fn type_version_to_json(spec: &TypeVersionSpec) -> JsonValue {
             obj.insert("ref".into(), JsonValue::String(type_ref.clone()));
         }
         if let Some(items) = &field.items {
-            match items {
-                ItemsSpec::Simple(s) => {
-                    obj.insert("items".into(), JsonValue::String(s.clone()));
-                }
-                ItemsSpec::Ref(r) => {
-                    obj.insert("items".into(), json!({"type": "ref", "ref": r}));
-                }
+            obj.insert("items".into(), items_spec_to_json(items));
+        }
+        match &field.shape {
+            Some(ItemsSpec::Map {
+                key_type,
+                value_type,
+            }) => {
+                obj.insert("key_type".into(), JsonValue::String(key_type.clone()));
+                obj.insert("value_type".into(), items_spec_to_json(value_type));
             }
+            Some(ItemsSpec::OneOf(variants)) => {
+                obj.insert("variants".into(), variants_to_json(variants));
+            }
+            _ => {}
         }
         if field.optional {
             obj.insert("optional".into(), JsonValue::Bool(true));
         }
+        if let Some(default) = &field.default {
+            obj.insert("default".into(), default.clone());
+        }
+        if let Some(deprecation) = &field.deprecation {
+            let deprecated = match &deprecation.note {
+                Some(note) => JsonValue::String(note.clone()),
+                None => JsonValue::Bool(true),
+            };
+            obj.insert("deprecated".into(), deprecated);
+        }
+        if field.count_tokens {
+            obj.insert("count_tokens".into(), JsonValue::Bool(true));
+        }
         fields.insert(tag.to_string(), JsonValue::Object(obj));
     }
     let mut result = Map::new();
@@ -1084,9 +4180,935 @@ fn type_version_to_json(spec: &TypeVersionSpec) -> JsonValue {
     if let Some(renderer) = &spec.renderer {
         result.insert("renderer".into(), renderer_spec_to_json(renderer));
     }
+    if let Some(field) = spec.summary_field.and_then(|tag| spec.fields.get(&tag)) {
+        result.insert(
+            "summary_field".into(),
+            JsonValue::String(field.name.clone()),
+        );
+    }
     JsonValue::Object(result)
 }
 
+/// Inverse of the registry's items parsing, so descriptors round-trip.
+fn items_spec_to_json(spec: &crate::registry::ItemsSpec) -> JsonValue {
+    use crate::registry::ItemsSpec;
+
+    match spec {
+        ItemsSpec::Simple(s) => JsonValue::String(s.clone()),
+        ItemsSpec::Ref(r) => json!({"type": "ref", "ref": r}),
+        ItemsSpec::Map {
+            key_type,
+            value_type,
+        } => json!({
+            "type": "map",
+            "key_type": key_type,
+            "value_type": items_spec_to_json(value_type),
+        }),
+        ItemsSpec::OneOf(variants) => {
+            json!({"type": "oneof", "variants": variants_to_json(variants)})
+        }
+    }
+}
+
+fn variants_to_json(variants: &std::collections::BTreeMap<u64, String>) -> JsonValue {
+    JsonValue::Object(
+        variants
+            .iter()
+            .map(|(tag, type_ref)| (tag.to_string(), JsonValue::String(type_ref.clone())))
+            .collect(),
+    )
+}
+
+/// `follow_symlinks` query flag for fs reads; symlinks are not followed by default.
+fn follow_symlinks_param(params: &HashMap<String, String>) -> bool {
+    params
+        .get("follow_symlinks")
+        .is_some_and(|v| v == "1" || v == "true")
+}
+
+/// Language and text classification of a file read with `?detect=1`.
+/// Symlinks read as their target are not classified.
+fn file_info(enabled: bool, entry: &TreeEntry, path: &str, content: &[u8]) -> Option<FileInfo> {
+    (enabled && entry.kind_enum() != EntryKind::Symlink).then(|| detect(path, content))
+}
+
+/// Raw content of a snapshot file; an unfollowed symlink's content is its
+/// target. Detected file info is sent as `X-Fs-Binary`, `X-Fs-Language` and
+/// `X-Fs-Lines` headers.
+fn fs_raw_response(
+    content: Vec<u8>,
+    entry: &TreeEntry,
+    path: &str,
+    info: Option<FileInfo>,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let (content_type, kind) = match entry.kind_enum() {
+        EntryKind::Symlink => ("text/plain; charset=utf-8", "symlink"),
+        _ => (guess_content_type(path), "file"),
+    };
+    let mut response = Response::from_data(content);
+    if let Some(info) = info {
+        response.add_header(Header::new("X-Fs-Binary", &info.binary.to_string()));
+        if let Some(language) = info.language {
+            response.add_header(Header::new("X-Fs-Language", language));
+        }
+        if let Some(lines) = info.line_count {
+            response.add_header(Header::new("X-Fs-Lines", &lines.to_string()));
+        }
+    }
+    response
+        .with_header(Header::new("Content-Type", content_type))
+        .with_header(Header::new("X-Fs-Hash", &hex::encode(&entry.hash)))
+        .with_header(Header::new("X-Fs-Mode", &format!("{:o}", entry.mode)))
+        .with_header(Header::new("X-Fs-Kind", kind))
+}
+
+/// Result of matching a `Range` header against a body of known length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// No usable range: serve the whole body.
+    Full,
+    /// Inclusive byte offsets to serve with 206.
+    Partial { start: u64, end: u64 },
+    /// The range starts past the end of the body: 416.
+    Unsatisfiable,
+}
+
+/// Parse a single `bytes=start-end`, `bytes=start-` or `bytes=-suffix` range.
+/// Malformed headers, other units and multi-range requests fall back to the
+/// full body, as RFC 9110 allows.
+pub fn parse_byte_range(header: Option<&str>, total: u64) -> ByteRange {
+    let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", "") => return ByteRange::Full,
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return ByteRange::Unsatisfiable,
+            Ok(n) => (total.saturating_sub(n), total.saturating_sub(1)),
+            Err(_) => return ByteRange::Full,
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => (start, total.saturating_sub(1)),
+            Err(_) => return ByteRange::Full,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(total.saturating_sub(1))),
+            _ => return ByteRange::Full,
+        },
+    };
+    if start >= total {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial { start, end }
+}
+
+/// Content response with range headers: every body advertises
+/// `Accept-Ranges`, and a partial one is sent as 206 with its `Content-Range`.
+fn ranged_response(
+    response: Response<std::io::Cursor<Vec<u8>>>,
+    partial: Option<(u64, u64)>,
+    total: u64,
+) -> HttpResponse {
+    let response = response.with_header(Header::new("Accept-Ranges", "bytes"));
+    match partial {
+        Some((start, end)) => (
+            206,
+            response.with_status_code(206).with_header(Header::new(
+                "Content-Range",
+                &format!("bytes {start}-{end}/{total}"),
+            )),
+        ),
+        None => (200, response.with_status_code(200)),
+    }
+}
+
+/// Share token sent with a request, from the header or the `share` param.
+fn request_share_token(request: &Request, url: &Url) -> Option<String> {
+    header_value(request, SHARE_HEADER)
+        .or_else(|| {
+            url.query_pairs()
+                .find(|(key, _)| key == "share")
+                .map(|(_, value)| value.into_owned())
+        })
+        .filter(|token| !token.is_empty())
+}
+
+fn share_denied() -> StoreError {
+    StoreError::PermissionDenied("outside the scope of the share link".into())
+}
+
+/// Refuse a request made with a share token unless it reads the shared
+/// context, or a turn of it within the share's depth range. Filesystem
+/// snapshots need a share with `fs`.
+fn check_share_scope(
+    method: &Method,
+    segments: &[&str],
+    url: &Url,
+    scope: &ShareScope,
+    store: &Mutex<Store>,
+) -> Result<()> {
+    if !matches!(*method, Method::GET | Method::HEAD) {
+        return Err(StoreError::PermissionDenied(
+            "share links are read-only".into(),
+        ));
+    }
+    let turn_id = match segments {
+        ["v1", "contexts", _]
+        | ["v1", "contexts", _, "turns"]
+        | ["v1", "contexts", _, "turns", "search"] => return Ok(()),
+        ["v1", "turns", turn_id]
+        | ["v1", "turns", turn_id, "proof"]
+        | ["v1", "turns", turn_id, "ancestry"]
+        | ["v1", "turns", turn_id, "attachments", ..] => turn_id,
+        ["v1", "turns", turn_id, "fs", ..] if scope.fs => turn_id,
+        _ => return Err(share_denied()),
+    };
+    let params = parse_query(url.query().unwrap_or(""));
+    if params
+        .get("context_id")
+        .is_some_and(|id| *id != scope.context_id.to_string())
+    {
+        return Err(share_denied());
+    }
+    let turn_id: u64 = turn_id.parse().map_err(|_| share_denied())?;
+    let proof = store
+        .lock()
+        .unwrap()
+        .turn_proof(turn_id, scope.context_id)
+        .map_err(|_| share_denied())?;
+    if scope.allows_depth(proof.turn.record.depth) {
+        Ok(())
+    } else {
+        Err(share_denied())
+    }
+}
+
+/// For `["v1", "contexts", id, "turns", "latest" | "head", rest..]`, the
+/// context and the segments of the same request against its head turn,
+/// `["v1", "turns", head_turn_id, rest..]`.
+fn resolve_head_alias(
+    segments: &[String],
+    store: &Mutex<Store>,
+) -> Result<Option<(u64, Vec<String>)>> {
+    let [v1, contexts, context_id, turns, alias, rest @ ..] = segments else {
+        return Ok(None);
+    };
+    if (v1.as_str(), contexts.as_str(), turns.as_str()) != ("v1", "contexts", "turns")
+        || !matches!(alias.as_str(), "latest" | "head")
+    {
+        return Ok(None);
+    }
+    let context_id: u64 = context_id
+        .parse()
+        .map_err(|_| StoreError::InvalidInput("invalid context_id".into()))?;
+    let head = store.lock().unwrap().get_head(context_id)?;
+    if head.head_turn_id == 0 {
+        return Err(StoreError::NotFound(format!(
+            "context {context_id} has no turns"
+        )));
+    }
+    let mut canonical = vec![
+        "v1".to_string(),
+        "turns".to_string(),
+        head.head_turn_id.to_string(),
+    ];
+    canonical.extend(rest.iter().cloned());
+    Ok(Some((context_id, canonical)))
+}
+
+/// `307` to the canonical URL of a turn alias, keeping the query apart from
+/// `follow_head`.
+fn head_redirect(canonical: &[String], url: &Url) -> HttpResponse {
+    let mut location = format!("/{}", canonical.join("/"));
+    let query: Vec<String> = url
+        .query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| !pair.is_empty() && pair.split('=').next() != Some("follow_head"))
+        .map(str::to_string)
+        .collect();
+    if !query.is_empty() {
+        location.push('?');
+        location.push_str(&query.join("&"));
+    }
+    let body = json!({ "turn_id": canonical[2], "location": location });
+    let response = Response::from_data(body.to_string().into_bytes())
+        .with_status_code(307)
+        .with_header(Header::new("Content-Type", "application/json"))
+        .with_header(Header::new("Location", &location));
+    (307, response)
+}
+
+/// Replace a response's `Cache-Control` with `no-store`.
+fn no_store(response: Response<std::io::Cursor<Vec<u8>>>) -> Response<std::io::Cursor<Vec<u8>>> {
+    let status = response.status_code();
+    let mut headers: Vec<Header> = response
+        .headers()
+        .iter()
+        .filter(|h| h.field != "Cache-Control")
+        .cloned()
+        .collect();
+    headers.push(Header::new("Cache-Control", "no-store"));
+    let body = response.into_reader().into_inner();
+    let len = body.len();
+    Response::new(status, headers, std::io::Cursor::new(body), Some(len))
+}
+
+fn range_not_satisfiable(total: u64) -> HttpResponse {
+    (
+        416,
+        Response::from_data(Vec::new())
+            .with_status_code(416)
+            .with_header(Header::new("Content-Range", &format!("bytes */{total}"))),
+    )
+}
+
+/// Summary of a context as listed by `GET /v1/contexts`: head, liveness,
+/// client tag, token and unread counts, and provenance when requested.
+/// `as_of` for historical listings: unix milliseconds or RFC 3339.
+fn parse_as_of(raw: &str) -> Result<u64> {
+    if let Ok(ms) = raw.parse::<u64>() {
+        return Ok(ms);
+    }
+    chrono::DateTime::parse_from_rfc3339(raw)
+        .ok()
+        .and_then(|dt| u64::try_from(dt.timestamp_millis()).ok())
+        .ok_or_else(|| {
+            StoreError::InvalidInput("as_of must be unix milliseconds or RFC 3339".into())
+        })
+}
+
+/// A context as it was at `as_of`. Sessions are not recorded, so liveness
+/// is approximated by whether the head moved within the liveness window.
+fn historical_context_json(
+    store: &mut Store,
+    entry: &HeadAsOf,
+    as_of: u64,
+    window_ms: u64,
+) -> JsonValue {
+    let head = &entry.head;
+    let mut obj = json!({
+        "context_id": head.context_id.to_string(),
+        "head_turn_id": head.head_turn_id.to_string(),
+        "head_depth": head.head_depth,
+        "created_at_unix_ms": head.created_at_unix_ms,
+        "is_live": as_of.saturating_sub(entry.moved_at_unix_ms) < window_ms,
+        "last_activity_at": entry.moved_at_unix_ms,
+    });
+    if let Some(tag) = store
+        .get_context_metadata(head.context_id)
+        .and_then(|m| m.client_tag)
+        .filter(|t| !t.is_empty())
+    {
+        obj["client_tag"] = JsonValue::String(tag);
+    }
+    if let Some(external_id) = store.external_id(head.context_id) {
+        obj["external_id"] = json!(external_id);
+    }
+    obj
+}
+
+fn context_json(
+    store: &mut Store,
+    session_tracker: &SessionTracker,
+    head: &ContextHead,
+    principal: Option<&str>,
+    include_provenance: bool,
+) -> JsonValue {
+    // Get session info for this context (for live status)
+    let session = session_tracker.get_session_for_context(head.context_id);
+
+    // Get client_tag: prefer stored metadata, fall back to session
+    let stored_metadata = store.get_context_metadata(head.context_id);
+    let client_tag = stored_metadata
+        .as_ref()
+        .and_then(|m| m.client_tag.clone())
+        .or_else(|| session.as_ref().map(|s| s.client_tag.clone()))
+        .filter(|t| !t.is_empty());
+
+    let mut obj = json!({
+        "context_id": head.context_id.to_string(),
+        "head_turn_id": head.head_turn_id.to_string(),
+        "head_depth": head.head_depth,
+        "created_at_unix_ms": head.created_at_unix_ms,
+        "is_live": session_tracker.is_context_live(head.context_id),
+    });
+
+    if let Some(tag) = client_tag {
+        obj["client_tag"] = JsonValue::String(tag);
+    }
+    if let Some(s) = &session {
+        obj["session_id"] = JsonValue::String(s.session_id.to_string());
+    }
+    // The latest of the session's activity and the context's own appends
+    // and heartbeats.
+    let last_activity_at = session
+        .as_ref()
+        .map(|s| s.last_activity_at)
+        .max(session_tracker.context_last_activity(head.context_id));
+    if let Some(at) = last_activity_at {
+        obj["last_activity_at"] = JsonValue::Number(at.into());
+    }
+    let tokens = store.context_tokens(head.context_id);
+    if tokens.total > 0 {
+        obj["tokens"] = json!(tokens.total);
+    }
+    if let Some(hold) = store.hold(head.context_id) {
+        obj["hold"] = hold_json(hold);
+    }
+    if let Some(external_id) = store.external_id(head.context_id) {
+        obj["external_id"] = json!(external_id);
+    }
+    if let Some(expiry) = store.expiry(head.context_id) {
+        obj["expires_at"] = json!(expiry.expires_at_unix_ms);
+    }
+    if let Some(preview) = store.context_preview(head.context_id) {
+        obj["preview"] = json!(preview);
+    }
+    if let Some(limit) = store.context_depth_limit(head.context_id) {
+        obj["depth_limit"] = json!(limit);
+    }
+    if let Some(rollover) = store.rolled_over_to(head.context_id) {
+        obj["rolled_over_to"] = json!(rollover.to_context_id.to_string());
+    }
+    if let Some(rollover) = store.rolled_over_from(head.context_id) {
+        obj["rolled_over_from"] = json!(rollover.from_context_id.to_string());
+    }
+    if let Some(status) = session_tracker.context_status(head.context_id) {
+        obj["status"] = json!(status);
+    }
+    let projects = store.context_projects(head.context_id);
+    if !projects.is_empty() {
+        obj["projects"] = json!(projects);
+    }
+    if let Some(principal) = principal {
+        let mark = store.read_mark(principal, head.context_id);
+        obj["unread_turns"] = json!(ReadMark::unread_turns(
+            mark,
+            head.head_turn_id,
+            head.head_depth
+        ));
+        if let Some(mark) = mark {
+            obj["last_viewed_turn_id"] = json!(mark.turn_id.to_string());
+        }
+    }
+
+    // Include provenance if requested
+    if include_provenance {
+        if let Some(prov) = stored_metadata.and_then(|m| m.provenance) {
+            // Inject server-side client_address if not present
+            let mut prov_with_server_info = prov;
+            if prov_with_server_info.client_address.is_none() {
+                prov_with_server_info.client_address =
+                    session.as_ref().and_then(|s| s.peer_addr.clone());
+            }
+            if let Ok(prov_json) = serde_json::to_value(&prov_with_server_info) {
+                obj["provenance"] = prov_json;
+            }
+        }
+    }
+
+    obj
+}
+
+/// The session that appended a turn.
+fn turn_author_json(author: &TurnAuthor) -> JsonValue {
+    let mut obj = json!({ "session_id": author.session_id.to_string() });
+    if !author.client_tag.is_empty() {
+        obj["client_tag"] = json!(author.client_tag);
+    }
+    if let Some(principal) = &author.principal {
+        obj["principal"] = json!(principal);
+    }
+    obj
+}
+
+/// A project, with its aggregates when given.
+fn project_json(project: &Project, stats: Option<&ProjectStats>) -> JsonValue {
+    let mut obj = json!({
+        "id": project.id,
+        "name": project.name,
+        "created_at_unix_ms": project.created_at_unix_ms,
+    });
+    if let Some(description) = &project.description {
+        obj["description"] = json!(description);
+    }
+    if let Some(created_by) = &project.created_by {
+        obj["created_by"] = json!(created_by);
+    }
+    if let Some(stats) = stats {
+        obj["stats"] = json!({
+            "contexts": stats.contexts,
+            "turns": stats.turns,
+            "tokens": stats.tokens,
+            "on_hold": stats.on_hold,
+            "client_tags": stats.client_tags,
+            "first_created_at_unix_ms": stats.first_created_at_unix_ms,
+            "last_created_at_unix_ms": stats.last_created_at_unix_ms,
+        });
+    }
+    obj
+}
+
+/// Characters percent-encoded in attachment names: all but RFC 3986
+/// unreserved ones.
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// A turn's attachment, with the URL it downloads from.
+fn attachment_json(turn_id: u64, attachment: &Attachment) -> JsonValue {
+    json!({
+        "name": attachment.name,
+        "mime_type": attachment.mime_type,
+        "size": attachment.size,
+        "hash": hex::encode(attachment.hash),
+        "attached_at_unix_ms": attachment.attached_at_unix_ms,
+        "url": format!(
+            "/v1/turns/{turn_id}/attachments/{}",
+            utf8_percent_encode(&attachment.name, UNRESERVED)
+        ),
+    })
+}
+
+/// A percent-decoded path segment.
+fn decode_segment(segment: &str) -> Result<String> {
+    percent_decode_str(segment)
+        .decode_utf8()
+        .map(|s| s.into_owned())
+        .map_err(|_| StoreError::InvalidInput(format!("invalid path segment {segment:?}")))
+}
+
+/// A legal hold placement or release.
+fn hold_json(entry: &HoldEntry) -> JsonValue {
+    json!({
+        "action": entry.action,
+        "reason": entry.reason,
+        "principal": entry.principal,
+        "at_unix_ms": entry.at_unix_ms,
+    })
+}
+
+fn bookmark_json(bookmark: &Bookmark) -> JsonValue {
+    json!({
+        "bookmark_id": bookmark.bookmark_id.to_string(),
+        "context_id": bookmark.context_id.to_string(),
+        "turn_id": bookmark.turn_id.to_string(),
+        "depth": bookmark.depth,
+        "name": bookmark.name,
+        "note": bookmark.note,
+        "author": bookmark.author,
+        "created_at_unix_ms": bookmark.created_at_unix_ms,
+    })
+}
+
+/// Jump point of a bookmark in a turns response.
+fn bookmark_marker(bookmark: &Bookmark) -> JsonValue {
+    json!({
+        "bookmark_id": bookmark.bookmark_id.to_string(),
+        "turn_id": bookmark.turn_id.to_string(),
+        "depth": bookmark.depth,
+        "name": bookmark.name,
+    })
+}
+
+fn transfer_json(entry: &OwnershipTransfer) -> JsonValue {
+    json!({
+        "context_id": entry.context_id.to_string(),
+        "previous_owner": entry.previous_owner,
+        "owner": entry.owner,
+        "reason": entry.reason,
+        "principal": entry.principal,
+        "at_unix_ms": entry.at_unix_ms,
+    })
+}
+
+fn rollover_json(rollover: &Rollover) -> JsonValue {
+    json!({
+        "context_id": rollover.from_context_id.to_string(),
+        "rolled_over_to": rollover.to_context_id.to_string(),
+        "digest_turn_id": rollover.digest_turn_id.to_string(),
+        "summarized_through_turn_id": rollover.summarized_through_turn_id.to_string(),
+        "generator": rollover.generator,
+        "at_unix_ms": rollover.at_unix_ms,
+    })
+}
+
+fn tree_node_json(node: &ContextTreeNode) -> JsonValue {
+    let mut obj = json!({
+        "context_id": node.context_id.to_string(),
+        "head_depth": node.head_depth,
+        "children": node.children.iter().map(tree_node_json).collect::<Vec<_>>(),
+    });
+    if let Some(title) = &node.title {
+        obj["title"] = json!(title);
+    }
+    if let Some(reason) = &node.spawn_reason {
+        obj["spawn_reason"] = json!(reason);
+    }
+    if let Some(from) = node.rolled_over_from {
+        obj["rolled_over_from"] = json!(from.to_string());
+    }
+    if let Some(to) = node.rolled_over_to {
+        obj["rolled_over_to"] = json!(to.to_string());
+    }
+    obj
+}
+
+fn tag_change_json(change: &TagChange) -> JsonValue {
+    let mut obj = json!({
+        "operation": change.operation.as_str(),
+        "sources": change.sources,
+        "target": change.target,
+        "context_ids": change.context_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>(),
+        "reason": change.reason,
+        "at_unix_ms": change.at_unix_ms,
+    });
+    if let Some(principal) = &change.principal {
+        obj["principal"] = json!(principal);
+    }
+    obj
+}
+
+/// Announce a tag change: one metadata update per retagged context, so
+/// clients caching context metadata refresh it, then the change itself.
+fn publish_tag_change(store: &Mutex<Store>, event_bus: &EventBus, change: &TagChange) {
+    let mut store = store.lock().unwrap();
+    for &context_id in &change.context_ids {
+        let Some(meta) = store.get_context_metadata(context_id) else {
+            continue;
+        };
+        event_bus.publish(StoreEvent::ContextMetadataUpdated {
+            context_id: context_id.to_string(),
+            client_tag: meta.client_tag,
+            title: meta.title,
+            labels: meta.labels,
+            has_provenance: meta.provenance.is_some(),
+        });
+    }
+    drop(store);
+    event_bus.publish(StoreEvent::TagsChanged {
+        operation: change.operation.as_str().to_string(),
+        sources: change.sources.clone(),
+        target: change.target.clone(),
+        contexts: change.context_ids.iter().map(|id| id.to_string()).collect(),
+        principal: change.principal.clone(),
+    });
+    tracing::info!(
+        operation = change.operation.as_str(),
+        sources = ?change.sources,
+        target = %change.target,
+        contexts = change.context_ids.len(),
+        principal = ?change.principal,
+        "Client tags changed"
+    );
+}
+
+fn ownership_changed(entry: &OwnershipTransfer) -> StoreEvent {
+    StoreEvent::OwnershipChanged {
+        context_id: entry.context_id.to_string(),
+        previous_owner: entry.previous_owner.clone(),
+        owner: entry.owner.clone(),
+        principal: entry.principal.clone(),
+    }
+}
+
+fn anchoring_disabled() -> StoreError {
+    StoreError::NotFound("anchoring is not configured (set CXDB_ANCHOR_TARGET)".into())
+}
+
+/// A hash chain link with the inputs needed to recompute its chain hash.
+fn chain_link_json(link: &ChainLink) -> JsonValue {
+    json!({
+        "turn_id": link.record.turn_id.to_string(),
+        "parent_turn_id": link.record.parent_turn_id.to_string(),
+        "depth": link.record.depth,
+        "created_at_unix_ms": link.record.created_at_unix_ms,
+        "payload_hash": hex::encode(link.record.payload_hash),
+        "declared_type_id": link.meta.declared_type_id,
+        "declared_type_version": link.meta.declared_type_version,
+        "encoding": link.meta.encoding,
+        "compression": link.meta.compression,
+        "uncompressed_len": link.meta.uncompressed_len,
+        "chain_hash": hex::encode(link.chain_hash),
+    })
+}
+
+/// An id from a JSON body: a number or, as ids are rendered, a decimal string.
+fn json_u64(value: &JsonValue) -> Option<u64> {
+    match value {
+        JsonValue::Number(n) => n.as_u64(),
+        JsonValue::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Access-log entry of a request being answered.
+struct PendingAccess<'a> {
+    log: &'a AccessLog,
+    entry: AccessEntry,
+    start: Instant,
+}
+
+impl<'a> PendingAccess<'a> {
+    fn begin(
+        log: Option<&'a AccessLog>,
+        request: &Request,
+        principal: Option<String>,
+        route: &str,
+        context_id: Option<u64>,
+        start: Instant,
+    ) -> Option<Self> {
+        let log = log?;
+        let entry = AccessEntry {
+            time: chrono::Utc::now(),
+            client_ip: request.remote_addr().map(|addr| addr.ip().to_string()),
+            principal,
+            method: request.method().as_str().to_string(),
+            path: redact(request.url()),
+            route: route.to_string(),
+            http_version: format!("{:?}", request.http_version()),
+            status: 0,
+            bytes: None,
+            latency: Duration::ZERO,
+            context_id,
+            referer: header_value(request, "Referer").map(|r| redact(&r)),
+            user_agent: header_value(request, "User-Agent"),
+        };
+        Some(Self { log, entry, start })
+    }
+
+    /// Write the entry, if the access log is on.
+    fn finish(access: Option<Self>, status: u16, bytes: Option<usize>) {
+        if let Some(Self { log, entry, start }) = access {
+            log.record(&AccessEntry {
+                status,
+                bytes: bytes.map(|b| b as u64),
+                latency: start.elapsed(),
+                ..entry
+            });
+        }
+    }
+}
+
+/// Context a request addresses: the id in `/v1/contexts/:id/...`, or a
+/// `context_id` / `viewing` query parameter.
+fn request_context_id(segments: &[&str], url: &Url) -> Option<u64> {
+    if let ["v1", "contexts", id, ..] = segments {
+        if let Ok(id) = id.parse() {
+            return Some(id);
+        }
+    }
+    url.query_pairs()
+        .find(|(key, _)| key == "context_id" || key == "viewing")
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+/// Check a request's bearer token against its route. With OIDC on, `/v1`
+/// requests without a token pass unless tokens are required (share links
+/// carry their own), and a token's access level must allow the method:
+/// `read` allows GET and HEAD, `write` everything.
+fn check_bearer(
+    oidc: Option<&OidcVerifier>,
+    token: &Result<Option<TokenIdentity>>,
+    method: &Method,
+    segments: &[&str],
+    shared: bool,
+) -> Result<()> {
+    let Some(oidc) = oidc else {
+        return Ok(());
+    };
+    if segments.first() != Some(&"v1") {
+        return Ok(());
+    }
+    let identity = match token {
+        Ok(Some(identity)) => identity,
+        Ok(None) if shared || !oidc.config().required => return Ok(()),
+        Ok(None) => return Err(StoreError::Unauthenticated("bearer token required".into())),
+        Err(err) => return Err(StoreError::Unauthenticated(map_error(err).1)),
+    };
+    let required = match *method {
+        Method::GET | Method::HEAD => Access::Read,
+        _ => Access::Write,
+    };
+    if identity.access >= required {
+        return Ok(());
+    }
+    Err(StoreError::PermissionDenied(format!(
+        "{} is not allowed to {} {}",
+        identity.principal,
+        method.as_str(),
+        route_template(segments)
+    )))
+}
+
+/// Principal named by the gateway for this request, if any.
+fn request_principal(request: &Request) -> Option<String> {
+    header_value(request, PRINCIPAL_HEADER).filter(|p| !p.trim().is_empty())
+}
+
+/// Presence identity of a request: its principal, else its address.
+fn request_viewer(request: &Request, principal: Option<&str>) -> ViewerId {
+    match principal {
+        Some(principal) => ViewerId::Principal(principal.to_string()),
+        None => ViewerId::Address(
+            request
+                .remote_addr()
+                .map(|addr| addr.ip().to_string())
+                .unwrap_or_default(),
+        ),
+    }
+}
+
+fn header_value(request: &Request, name: &'static str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field == name)
+        .map(|h| h.value.as_str().to_string())
+}
+
+/// Residencies a requesting federation peer may hold, when the request
+/// comes from one.
+fn federation_residencies(request: &Request) -> Option<BTreeSet<String>> {
+    header_value(request, RESIDENCY_HEADER).map(|raw| {
+        raw.split(',')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .map(str::to_string)
+            .collect()
+    })
+}
+
+/// Refuse a federation peer any context route of a context whose residency
+/// it may not hold.
+fn check_federated_residency(
+    segments: &[&str],
+    allowed: &BTreeSet<String>,
+    store: &Mutex<Store>,
+) -> Result<()> {
+    let ["v1", "contexts", context_id, ..] = segments else {
+        return Ok(());
+    };
+    let Ok(context_id) = context_id.parse::<u64>() else {
+        return Ok(());
+    };
+    let metadata = store.lock().unwrap().get_context_metadata(context_id);
+    let context_residencies = residencies(metadata.as_ref());
+    if may_hold(allowed, &context_residencies) {
+        Ok(())
+    } else {
+        Err(StoreError::PermissionDenied(format!(
+            "context {context_id} has residency {}, which the requesting server may not hold",
+            context_residencies.join(", ")
+        )))
+    }
+}
+
+/// Drop contexts a federation peer may not hold from search results.
+fn withhold_residencies(
+    store: &mut Store,
+    mut resp: JsonValue,
+    allowed: &BTreeSet<String>,
+) -> JsonValue {
+    let Some(contexts) = resp["contexts"].as_array_mut() else {
+        return resp;
+    };
+    let before = contexts.len();
+    contexts.retain(|context| {
+        let context_id = context["context_id"]
+            .as_str()
+            .and_then(|id| id.parse().ok());
+        context_id.is_some_and(|id| {
+            may_hold(
+                allowed,
+                &residencies(store.get_context_metadata(id).as_ref()),
+            )
+        })
+    });
+    let withheld = (before - contexts.len()) as u64;
+    if let Some(total) = resp["total_count"].as_u64() {
+        resp["total_count"] = json!(total.saturating_sub(withheld));
+    }
+    resp
+}
+
+/// Directory listing of a turn's snapshot at `path`.
+fn fs_listing_response(
+    store: &mut Store,
+    turn_id: u64,
+    path: &str,
+    follow_symlinks: bool,
+    deadline: &Deadline,
+) -> Result<HttpResponse> {
+    let entries = store.list_fs_entries(turn_id, path, follow_symlinks, deadline)?;
+    let snapshot = store
+        .get_fs_snapshot(turn_id)
+        .ok_or_else(|| StoreError::NotFound("no fs snapshot for turn".into()))?;
+    let entries_json: Vec<JsonValue> = entries
+        .iter()
+        .map(|e| fs_entry_json(store, turn_id, e))
+        .collect();
+
+    json_response(
+        200,
+        &json!({
+            "turn_id": turn_id.to_string(),
+            "path": path,
+            "fs_root_hash": hex::encode(snapshot.root_hash),
+            "snapshot": fs_snapshot_json(&snapshot),
+            "entries": entries_json,
+        }),
+    )
+}
+
+/// JSON for one snapshot entry. Symlinks carry their `target` (null if it
+/// cannot be read) and files an `executable` flag from their mode bits.
+fn fs_entry_json(store: &mut Store, turn_id: u64, entry: &TreeEntry) -> JsonValue {
+    let kind_str = match entry.kind_enum() {
+        EntryKind::File => "file",
+        EntryKind::Directory => "dir",
+        EntryKind::Symlink => "symlink",
+    };
+    let mut value = json!({
+        "name": entry.name,
+        "kind": kind_str,
+        "mode": format!("{:o}", entry.mode),
+        "size": entry.size,
+        "hash": hex::encode(&entry.hash),
+    });
+    match entry.kind_enum() {
+        EntryKind::File => value["executable"] = json!(entry.mode & 0o111 != 0),
+        EntryKind::Symlink => {
+            value["target"] = json!(store.read_fs_symlink(turn_id, entry).ok());
+        }
+        EntryKind::Directory => {}
+    }
+    value
+}
+
+/// Snapshot envelope for fs listings: the turn it is attached to plus the
+/// recorded working-directory metadata.
+fn fs_snapshot_json(snapshot: &FsSnapshot) -> JsonValue {
+    let mut value = json!({ "attached_turn_id": snapshot.attached_turn_id.to_string() });
+    if let Some(meta) = &snapshot.meta {
+        if let (JsonValue::Object(obj), Ok(JsonValue::Object(fields))) =
+            (&mut value, serde_json::to_value(meta))
+        {
+            obj.extend(fields);
+        }
+    }
+    value
+}
+
 /// Guess content type from file extension.
 fn guess_content_type(path: &str) -> &'static str {
     let ext = path.rsplit('.').next().unwrap_or("");
@@ -0,0 +1,164 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! MessagePack request and response bodies.
+//!
+//! Write endpoints that take a JSON body also take the same document as
+//! MessagePack when sent with `Content-Type: application/msgpack`. Binary
+//! values decode to lowercase hex strings, so hashes can be sent as raw
+//! bytes. JSON responses are re-encoded as MessagePack when the client's
+//! `Accept` header prefers it.
+
+use std::io::Cursor;
+
+use rmpv::Value;
+use serde_json::{Map, Number, Value as JsonValue};
+
+use crate::error::{Result, StoreError};
+use crate::projection::json_to_msgpack;
+
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+/// Whether a `Content-Type` value names MessagePack.
+pub fn is_msgpack(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    [
+        "application/msgpack",
+        "application/x-msgpack",
+        "application/vnd.msgpack",
+    ]
+    .iter()
+    .any(|m| mime.eq_ignore_ascii_case(m))
+}
+
+/// Whether an `Accept` value prefers MessagePack to JSON. MessagePack must
+/// be listed explicitly; it wins ties with JSON and wildcards.
+pub fn prefers_msgpack(accept: &str) -> bool {
+    let mut msgpack: f32 = 0.0;
+    let mut json: f32 = 0.0;
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let mime = parts.next().unwrap_or("").trim();
+        let q = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if is_msgpack(mime) {
+            msgpack = msgpack.max(q);
+        } else if ["application/json", "application/*", "*/*"]
+            .iter()
+            .any(|m| mime.eq_ignore_ascii_case(m))
+        {
+            json = json.max(q);
+        }
+    }
+    msgpack > 0.0 && msgpack >= json
+}
+
+/// Decode a MessagePack body into the JSON document it stands for.
+pub fn decode_body(body: &[u8]) -> Result<JsonValue> {
+    let mut cursor = Cursor::new(body);
+    let value = rmpv::decode::read_value(&mut cursor)
+        .map_err(|e| StoreError::InvalidInput(format!("invalid msgpack: {e}")))?;
+    if cursor.position() as usize != body.len() {
+        return Err(StoreError::InvalidInput(
+            "invalid msgpack: trailing bytes".into(),
+        ));
+    }
+    msgpack_to_json(&value)
+}
+
+/// Re-encode a JSON response body as MessagePack.
+pub fn encode_json_body(body: &[u8]) -> Result<Vec<u8>> {
+    let value: JsonValue = serde_json::from_slice(body)
+        .map_err(|e| StoreError::InvalidInput(format!("invalid json: {e}")))?;
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &json_to_msgpack(&value))
+        .map_err(|e| StoreError::InvalidInput(format!("msgpack encode error: {e}")))?;
+    Ok(buf)
+}
+
+fn msgpack_to_json(value: &Value) -> Result<JsonValue> {
+    Ok(match value {
+        Value::Nil => JsonValue::Null,
+        Value::Boolean(b) => JsonValue::Bool(*b),
+        Value::Integer(i) => match (i.as_u64(), i.as_i64()) {
+            (Some(u), _) => JsonValue::from(u),
+            (None, Some(i)) => JsonValue::from(i),
+            (None, None) => JsonValue::Null,
+        },
+        Value::F32(f) => float(f64::from(*f))?,
+        Value::F64(f) => float(*f)?,
+        Value::String(s) => JsonValue::String(
+            s.as_str()
+                .ok_or_else(|| StoreError::InvalidInput("invalid msgpack: non-utf8 string".into()))?
+                .to_string(),
+        ),
+        Value::Binary(bytes) => JsonValue::String(hex::encode(bytes)),
+        Value::Array(items) => JsonValue::Array(
+            items
+                .iter()
+                .map(msgpack_to_json)
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        Value::Map(entries) => {
+            let mut obj = Map::with_capacity(entries.len());
+            for (key, value) in entries {
+                let key = key.as_str().ok_or_else(|| {
+                    StoreError::InvalidInput("invalid msgpack: map keys must be strings".into())
+                })?;
+                obj.insert(key.to_string(), msgpack_to_json(value)?);
+            }
+            JsonValue::Object(obj)
+        }
+        Value::Ext(..) => {
+            return Err(StoreError::InvalidInput(
+                "invalid msgpack: extension types are not supported".into(),
+            ))
+        }
+    })
+}
+
+fn float(f: f64) -> Result<JsonValue> {
+    Number::from_f64(f)
+        .map(JsonValue::Number)
+        .ok_or_else(|| StoreError::InvalidInput("invalid msgpack: non-finite float".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_prefers_msgpack_only_when_asked() {
+        assert!(prefers_msgpack("application/msgpack"));
+        assert!(prefers_msgpack("application/msgpack, application/json"));
+        assert!(prefers_msgpack(
+            "application/json;q=0.5, application/x-msgpack"
+        ));
+        assert!(!prefers_msgpack("application/msgpack;q=0.5, */*"));
+        assert!(!prefers_msgpack("application/json"));
+        assert!(!prefers_msgpack("*/*"));
+        assert!(!prefers_msgpack(""));
+    }
+
+    #[test]
+    fn test_bodies_round_trip_and_binary_becomes_hex() {
+        let doc = json!({"name": "notes.txt", "size": 12, "ratio": 0.5, "tags": ["a", null]});
+        let encoded = encode_json_body(doc.to_string().as_bytes()).unwrap();
+        assert_eq!(decode_body(&encoded).unwrap(), doc);
+
+        let mut buf = Vec::new();
+        let value = Value::Map(vec![(Value::from("hash"), Value::Binary(vec![0xab, 0x01]))]);
+        rmpv::encode::write_value(&mut buf, &value).unwrap();
+        assert_eq!(decode_body(&buf).unwrap(), json!({"hash": "ab01"}));
+
+        buf.push(0xc0);
+        assert!(decode_body(&buf).is_err());
+        let mut buf = Vec::new();
+        let value = Value::Map(vec![(Value::from(1), Value::from(2))]);
+        rmpv::encode::write_value(&mut buf, &value).unwrap();
+        assert!(decode_body(&buf).is_err());
+    }
+}
@@ -0,0 +1,466 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! The gateway's transport on the tokio runtime.
+//!
+//! hyper accepts connections and parses requests on the runtime. Each
+//! request has its body read up to `CXDB_HTTP_MAX_BODY_BYTES` within
+//! `CXDB_HTTP_BODY_TIMEOUT_MS`, then waits for one of
+//! `CXDB_HTTP_MAX_CONCURRENCY` slots and is handed to the blocking pool, where
+//! the synchronous handlers run against the store. A slow upload never holds
+//! a slot. A handler answers through
+//! [`Request::respond`]: the status and headers go back to the connection
+//! task, and the body follows from the blocking thread, so a streamed export
+//! holds its slot until it is sent. Event streams write through a
+//! [`ResponseWriter`] from their own thread and hold no slot. The gateway
+//! can listen on TCP, a Unix domain socket, or both; every listener shares
+//! the same slots.
+
+use std::convert::Infallible;
+use std::io::{self, Cursor, Read, Write};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::Bytes;
+use http::header::{HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use http::{Method, StatusCode, Version};
+use http_body::{Frame, SizeHint};
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::{TokioIo, TokioTimer};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::sync::{mpsc, oneshot, Semaphore};
+use tokio::task::JoinSet;
+
+use super::HttpState;
+
+/// Bodies up to this size are sent in one frame; larger ones are read and
+/// sent in chunks of this size.
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Body chunks buffered between a handler and its connection.
+const CHUNK_BUFFER: usize = 8;
+
+/// One request or response header.
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub field: HeaderName,
+    pub value: String,
+}
+
+impl Header {
+    /// Header `name: value`. `name` must be a valid header name.
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            field: HeaderName::from_bytes(name.as_bytes()).expect("valid header name"),
+            value: value.to_string(),
+        }
+    }
+}
+
+/// A response as handlers build it, before it is sent.
+pub struct Response<R> {
+    status: u16,
+    headers: Vec<Header>,
+    data: R,
+    data_length: Option<usize>,
+}
+
+impl Response<Cursor<Vec<u8>>> {
+    /// A 200 response with `data` as its body.
+    pub fn from_data(data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
+        let len = data.len();
+        Self::new(200, Vec::new(), Cursor::new(data), Some(len))
+    }
+}
+
+impl<R> Response<R> {
+    /// A response whose body is read from `data`; without a length it is
+    /// sent with chunked transfer encoding.
+    pub fn new(status: u16, headers: Vec<Header>, data: R, data_length: Option<usize>) -> Self {
+        let mut response = Self {
+            status,
+            headers: Vec::with_capacity(headers.len()),
+            data,
+            data_length,
+        };
+        for header in headers {
+            response.add_header(header);
+        }
+        response
+    }
+
+    pub fn with_status_code(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn with_header(mut self, header: Header) -> Self {
+        self.add_header(header);
+        self
+    }
+
+    /// Add a header. A `Content-Type` replaces the current one; framing
+    /// headers are left to the server and ignored.
+    pub fn add_header(&mut self, header: Header) {
+        if header.field == "Connection"
+            || header.field == "Content-Length"
+            || header.field == "Transfer-Encoding"
+        {
+            return;
+        }
+        if header.field == CONTENT_TYPE {
+            self.headers.retain(|h| h.field != CONTENT_TYPE);
+        }
+        self.headers.push(header);
+    }
+
+    pub fn status_code(&self) -> u16 {
+        self.status
+    }
+
+    pub fn headers(&self) -> &[Header] {
+        &self.headers
+    }
+
+    pub fn data_length(&self) -> Option<usize> {
+        self.data_length
+    }
+
+    pub fn into_reader(self) -> R {
+        self.data
+    }
+}
+
+/// A request handed to the synchronous handlers, with its body read.
+pub struct Request {
+    method: Method,
+    url: String,
+    version: Version,
+    headers: Vec<Header>,
+    remote_addr: Option<SocketAddr>,
+    body: Cursor<Vec<u8>>,
+    reply: oneshot::Sender<hyper::Response<Body>>,
+}
+
+impl Request {
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Path and query, as sent.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn http_version(&self) -> Version {
+        self.version
+    }
+
+    pub fn headers(&self) -> &[Header] {
+        &self.headers
+    }
+
+    pub fn remote_addr(&self) -> Option<&SocketAddr> {
+        self.remote_addr.as_ref()
+    }
+
+    pub fn as_reader(&mut self) -> &mut impl Read {
+        &mut self.body
+    }
+
+    /// Send `response`, blocking until its body has been handed to the
+    /// connection. Fails when the client has gone away.
+    pub fn respond<R: Read>(self, response: Response<R>) -> io::Result<()> {
+        let Response {
+            status,
+            headers,
+            mut data,
+            data_length,
+        } = response;
+        match data_length {
+            Some(len) if len <= CHUNK_BYTES => {
+                let mut body = Vec::with_capacity(len);
+                data.read_to_end(&mut body)?;
+                send(self.reply, status, headers, Body::Full(Some(body.into())))
+            }
+            _ => {
+                let mut headers = headers;
+                if let Some(len) = data_length {
+                    headers.push(Header {
+                        field: CONTENT_LENGTH,
+                        value: len.to_string(),
+                    });
+                }
+                let (mut writer, body) = ResponseWriter::channel();
+                send(self.reply, status, headers, body)?;
+                let mut chunk = vec![0; CHUNK_BYTES];
+                loop {
+                    match data.read(&mut chunk) {
+                        Ok(0) => return Ok(()),
+                        Ok(n) => writer.write_all(&chunk[..n])?,
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                        Err(err) => {
+                            // Cut the response short rather than end it cleanly.
+                            writer.fail(&err);
+                            return Err(err);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Answer with `status` and `headers` now, and return a writer for a body
+    /// of unknown length, sent with chunked transfer encoding.
+    pub fn into_writer(self, status: u16, headers: Vec<Header>) -> io::Result<ResponseWriter> {
+        let (writer, body) = ResponseWriter::channel();
+        send(self.reply, status, headers, body)?;
+        Ok(writer)
+    }
+}
+
+/// Writes a streamed response body. Each write is sent as one chunk; writes
+/// fail once the client has gone away.
+pub struct ResponseWriter {
+    chunks: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl ResponseWriter {
+    fn channel() -> (Self, Body) {
+        let (chunks, rx) = mpsc::channel(CHUNK_BUFFER);
+        (Self { chunks }, Body::Stream(rx))
+    }
+
+    fn fail(&self, err: &io::Error) {
+        let _ = self
+            .chunks
+            .blocking_send(Err(io::Error::new(err.kind(), err.to_string())));
+    }
+}
+
+impl Write for ResponseWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.chunks
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client went away"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A response body: sent whole, or chunk by chunk as a handler writes it.
+pub enum Body {
+    Full(Option<Bytes>),
+    Stream(mpsc::Receiver<io::Result<Bytes>>),
+}
+
+impl http_body::Body for Body {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<Frame<Bytes>>>> {
+        match self.get_mut() {
+            Body::Full(data) => Poll::Ready(data.take().map(|data| Ok(Frame::data(data)))),
+            Body::Stream(chunks) => chunks
+                .poll_recv(cx)
+                .map(|chunk| chunk.map(|chunk| chunk.map(Frame::data))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        matches!(self, Body::Full(None))
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self {
+            Body::Full(data) => SizeHint::with_exact(data.as_ref().map_or(0, |d| d.len() as u64)),
+            Body::Stream(_) => SizeHint::default(),
+        }
+    }
+}
+
+fn send(
+    reply: oneshot::Sender<hyper::Response<Body>>,
+    status: u16,
+    headers: Vec<Header>,
+    body: Body,
+) -> io::Result<()> {
+    let mut response = hyper::Response::new(body);
+    *response.status_mut() =
+        StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    for header in headers {
+        // Values that cannot be sent as header text are dropped.
+        if let Ok(value) = HeaderValue::from_str(&header.value) {
+            response.headers_mut().append(header.field, value);
+        }
+    }
+    reply
+        .send(response)
+        .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client went away"))
+}
+
+/// A listener registered with the runtime.
+pub(super) enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+/// Serve HTTP/1.1 connections from `listeners` until the task is dropped.
+pub(super) async fn serve(listeners: Vec<Listener>, state: HttpState) {
+    let state = Arc::new(state);
+    let slots = Arc::new(Semaphore::new(state.config.max_concurrency.max(1)));
+    // Dropping the set, when this task is aborted, stops every accept loop.
+    let mut accept_loops = JoinSet::new();
+    for listener in listeners {
+        accept_loops.spawn(accept(listener, Arc::clone(&state), Arc::clone(&slots)));
+    }
+    while accept_loops.join_next().await.is_some() {}
+}
+
+async fn accept(listener: Listener, state: Arc<HttpState>, slots: Arc<Semaphore>) {
+    loop {
+        let accepted = match &listener {
+            Listener::Tcp(listener) => listener.accept().await.map(|(stream, remote_addr)| {
+                let _ = stream.set_nodelay(true);
+                let connection = serve_connection(
+                    stream,
+                    Some(remote_addr),
+                    Arc::clone(&state),
+                    Arc::clone(&slots),
+                );
+                tokio::spawn(connection);
+            }),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener.accept().await.map(|(stream, _)| {
+                let connection =
+                    serve_connection(stream, None, Arc::clone(&state), Arc::clone(&slots));
+                tokio::spawn(connection);
+            }),
+        };
+        if let Err(err) = accepted {
+            // Usually out of file descriptors; give connections time to close.
+            eprintln!("http accept error: {err}");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+async fn serve_connection<S>(
+    stream: S,
+    remote_addr: Option<SocketAddr>,
+    state: Arc<HttpState>,
+    slots: Arc<Semaphore>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let header_timeout = Duration::from_millis(state.config.header_timeout_ms);
+    let service = service_fn(move |request| {
+        dispatch(request, remote_addr, Arc::clone(&state), Arc::clone(&slots))
+    });
+    let mut builder = http1::Builder::new();
+    if !header_timeout.is_zero() {
+        builder
+            .timer(TokioTimer::new())
+            .header_read_timeout(header_timeout);
+    }
+    if let Err(err) = builder
+        .serve_connection(TokioIo::new(stream), service)
+        .await
+    {
+        if !err.is_incomplete_message() && !err.is_timeout() {
+            eprintln!("http connection error: {err}");
+        }
+    }
+}
+
+/// Read one request and run its handler on the blocking pool.
+async fn dispatch(
+    request: hyper::Request<Incoming>,
+    remote_addr: Option<SocketAddr>,
+    state: Arc<HttpState>,
+    slots: Arc<Semaphore>,
+) -> std::result::Result<hyper::Response<Body>, Infallible> {
+    let (parts, body) = request.into_parts();
+    let limit = match state.config.max_body_bytes {
+        0 => usize::MAX,
+        limit => limit,
+    };
+    let read = Limited::new(body, limit).collect();
+    let read = match state.config.body_timeout_ms {
+        0 => Ok(read.await),
+        ms => tokio::time::timeout(Duration::from_millis(ms), read).await,
+    };
+    let body = match read {
+        Ok(Ok(body)) => body.to_bytes(),
+        Ok(Err(err)) if err.is::<LengthLimitError>() => {
+            let message = format!("request body exceeds {limit} bytes");
+            return Ok(error_response(413, &message));
+        }
+        Ok(Err(_)) => return Ok(error_response(400, "could not read request body")),
+        Err(_) => return Ok(error_response(408, "request body not received in time")),
+    };
+    let Ok(slot) = slots.acquire_owned().await else {
+        return Ok(error_response(503, "server is shutting down"));
+    };
+
+    let (reply, replied) = oneshot::channel();
+    let request = Request {
+        method: parts.method,
+        url: parts
+            .uri
+            .path_and_query()
+            .map_or("/", |p| p.as_str())
+            .to_string(),
+        version: parts.version,
+        headers: parts
+            .headers
+            .iter()
+            .map(|(name, value)| Header {
+                field: name.clone(),
+                value: String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            })
+            .collect(),
+        remote_addr,
+        body: Cursor::new(body.to_vec()),
+        reply,
+    };
+    tokio::task::spawn_blocking(move || {
+        let _slot = slot;
+        if let Err(err) = super::handle_request(request, &state) {
+            eprintln!("http error: {err}");
+        }
+    });
+    Ok(replied
+        .await
+        .unwrap_or_else(|_| error_response(500, "request was not answered")))
+}
+
+fn error_response(status: u16, message: &str) -> hyper::Response<Body> {
+    let body = serde_json::json!({"error": {"code": status, "message": message}});
+    let mut response = hyper::Response::new(Body::Full(Some(body.to_string().into())));
+    *response.status_mut() = StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_REQUEST);
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
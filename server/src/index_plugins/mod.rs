@@ -0,0 +1,503 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Custom index plugins.
+//!
+//! Plugins are small WASM modules that see every appended turn, projected
+//! through the type registry, and return key/value entries to index. Entries
+//! are queried with the CQL field `custom.<key>`, which matches contexts
+//! with a turn on their head chain that produced the entry
+//! (`custom.model = "gpt-4o"`). Plugins are listed in a JSON file named by
+//! `CXDB_INDEX_PLUGINS_FILE`:
+//!
+//! ```text
+//! [
+//!   { "name": "model", "wasm": "model.wasm", "types": ["com.example.*"] },
+//!   { "name": "tools", "wasm": "/opt/cxdb/tools.wasm",
+//!     "fuel": 1000000, "memory_bytes": 4194304 }
+//! ]
+//! ```
+//!
+//! A module imports nothing and exports `memory`, `alloc(len: i32) -> i32`
+//! and `index(ptr: i32, len: i32) -> i64`. The server writes the turn as
+//! JSON into memory obtained from `alloc`; `index` returns the pointer and
+//! length of its JSON answer packed as `(ptr << 32) | len`:
+//!
+//! ```text
+//! in:  { "context_id": "42", "turn_id": "100", "depth": 3,
+//!        "declared_type_id": "com.example.Message", "declared_type_version": 1,
+//!        "data": { ...projected payload... } }
+//! out: { "model": "gpt-4o", "tool": ["grep", "sed"] }
+//! ```
+//!
+//! Each call runs in a fresh instance bounded by a fuel budget and a memory
+//! limit. A plugin that traps, runs out of fuel or answers with invalid
+//! entries is skipped for that turn; the append itself never fails. After
+//! `CXDB_INDEX_PLUGIN_MAX_FAILURES` consecutive failures the plugin is
+//! disabled until restart. WASM support is the optional `wasm-plugins`
+//! cargo feature.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::jsonl_log::open_log;
+use crate::projection::{BytesRender, EnumRender, RenderOptions, TimeRender, U64Format};
+use crate::registry::Registry;
+use crate::turn_store::CommitPipeline;
+
+#[cfg(feature = "wasm-plugins")]
+mod wasm;
+
+/// Default for `CXDB_INDEX_PLUGIN_FUEL`.
+const DEFAULT_FUEL: u64 = 10_000_000;
+/// Default for `CXDB_INDEX_PLUGIN_MEMORY_BYTES`.
+const DEFAULT_MEMORY_BYTES: u64 = 16 * 1024 * 1024;
+/// Default for `CXDB_INDEX_PLUGIN_MAX_FAILURES`.
+const DEFAULT_MAX_FAILURES: u32 = 10;
+
+/// Entries one plugin may return for a turn.
+pub const MAX_ENTRIES: usize = 64;
+/// Longest indexed value, in bytes.
+pub const MAX_VALUE_LEN: usize = 256;
+
+/// One plugin, as configured.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndexPluginSpec {
+    pub name: String,
+    /// WASM module; relative paths are resolved against the plugin file.
+    pub wasm: PathBuf,
+    /// Declared type ids the plugin sees; a trailing `*` matches a prefix.
+    /// Empty matches every turn.
+    #[serde(default)]
+    pub types: Vec<String>,
+    /// Overrides `CXDB_INDEX_PLUGIN_FUEL` for this plugin.
+    #[serde(default)]
+    pub fuel: Option<u64>,
+    /// Overrides `CXDB_INDEX_PLUGIN_MEMORY_BYTES` for this plugin.
+    #[serde(default)]
+    pub memory_bytes: Option<u64>,
+}
+
+impl IndexPluginSpec {
+    pub fn matches(&self, type_id: &str) -> bool {
+        self.types.is_empty()
+            || self
+                .types
+                .iter()
+                .any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => type_id.starts_with(prefix),
+                    None => type_id == pattern,
+                })
+    }
+}
+
+/// Resource limits of one plugin call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PluginLimits {
+    /// Instructions (roughly) a call may execute.
+    pub fuel: u64,
+    /// Linear memory a call may grow to.
+    pub memory_bytes: u64,
+}
+
+/// Index plugin settings, loaded from the environment.
+#[derive(Debug, Clone)]
+pub struct IndexPluginsConfig {
+    pub plugins: Vec<IndexPluginSpec>,
+    /// Limits of plugins that do not set their own.
+    pub limits: PluginLimits,
+    /// Consecutive failures that disable a plugin; 0 never disables.
+    pub max_failures: u32,
+}
+
+impl IndexPluginsConfig {
+    /// Returns None when `CXDB_INDEX_PLUGINS_FILE` is unset or empty.
+    pub fn from_env() -> Result<Option<Self>> {
+        let path = match std::env::var("CXDB_INDEX_PLUGINS_FILE") {
+            Ok(path) if !path.is_empty() => PathBuf::from(path),
+            _ => return Ok(None),
+        };
+        let json = std::fs::read_to_string(&path).map_err(|e| {
+            StoreError::InvalidInput(format!("CXDB_INDEX_PLUGINS_FILE {}: {e}", path.display()))
+        })?;
+        let mut plugins = Self::parse(&json)?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        for plugin in &mut plugins {
+            plugin.wasm = base.join(&plugin.wasm);
+        }
+        let var = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        Ok(Some(Self {
+            plugins,
+            limits: PluginLimits {
+                fuel: var("CXDB_INDEX_PLUGIN_FUEL").unwrap_or(DEFAULT_FUEL),
+                memory_bytes: var("CXDB_INDEX_PLUGIN_MEMORY_BYTES").unwrap_or(DEFAULT_MEMORY_BYTES),
+            },
+            max_failures: var("CXDB_INDEX_PLUGIN_MAX_FAILURES")
+                .map(|v| v as u32)
+                .unwrap_or(DEFAULT_MAX_FAILURES),
+        }))
+    }
+
+    /// Parse and validate a plugin list.
+    pub fn parse(json: &str) -> Result<Vec<IndexPluginSpec>> {
+        let specs: Vec<IndexPluginSpec> = serde_json::from_str(json)
+            .map_err(|e| StoreError::InvalidInput(format!("invalid index plugin list: {e}")))?;
+        let mut names = HashSet::new();
+        for spec in &specs {
+            if spec.name.trim().is_empty() {
+                return Err(StoreError::InvalidInput(
+                    "every index plugin needs a name".into(),
+                ));
+            }
+            if !names.insert(spec.name.as_str()) {
+                return Err(StoreError::InvalidInput(format!(
+                    "index plugin {:?} is listed twice",
+                    spec.name
+                )));
+            }
+            if spec.fuel == Some(0) || spec.memory_bytes == Some(0) {
+                return Err(StoreError::InvalidInput(format!(
+                    "index plugin {:?}: fuel and memory_bytes must be positive",
+                    spec.name
+                )));
+            }
+        }
+        Ok(specs)
+    }
+
+    /// Limits a plugin runs with.
+    pub fn limits_of(&self, spec: &IndexPluginSpec) -> PluginLimits {
+        PluginLimits {
+            fuel: spec.fuel.unwrap_or(self.limits.fuel),
+            memory_bytes: spec.memory_bytes.unwrap_or(self.limits.memory_bytes),
+        }
+    }
+
+    /// Plugin names and types, for logging.
+    pub fn describe(&self) -> String {
+        self.plugins
+            .iter()
+            .map(|p| {
+                if p.types.is_empty() {
+                    p.name.clone()
+                } else {
+                    format!("{} ({})", p.name, p.types.join(","))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// A turn as a plugin sees it.
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexInput<'a> {
+    pub context_id: String,
+    pub turn_id: String,
+    pub depth: u32,
+    pub declared_type_id: &'a str,
+    pub declared_type_version: u32,
+    /// Projected payload; absent when the registry does not know the type.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+/// Maps a turn, as JSON, to a JSON object of index entries.
+pub trait IndexPlugin: Send {
+    fn index(&self, input: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// Per-plugin accounting.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IndexPluginStats {
+    pub name: String,
+    /// Turns handed to the plugin.
+    pub invocations: u64,
+    /// Entries it returned.
+    pub entries: u64,
+    pub failures: u64,
+    /// Disabled after too many consecutive failures.
+    pub disabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+struct Slot {
+    spec: IndexPluginSpec,
+    plugin: Box<dyn IndexPlugin>,
+    consecutive_failures: u32,
+    stats: IndexPluginStats,
+}
+
+/// Runs the configured plugins on appended turns; held by the store.
+pub struct IndexPlugins {
+    slots: Vec<Slot>,
+    registry: Arc<Mutex<Registry>>,
+    max_failures: u32,
+}
+
+impl IndexPlugins {
+    /// Compile the configured modules.
+    pub fn load(config: &IndexPluginsConfig, registry: Arc<Mutex<Registry>>) -> Result<Self> {
+        let plugins = load_modules(config)?;
+        Ok(Self::from_plugins(
+            config.plugins.iter().cloned().zip(plugins).collect(),
+            registry,
+            config.max_failures,
+        ))
+    }
+
+    pub fn from_plugins(
+        plugins: Vec<(IndexPluginSpec, Box<dyn IndexPlugin>)>,
+        registry: Arc<Mutex<Registry>>,
+        max_failures: u32,
+    ) -> Self {
+        let slots = plugins
+            .into_iter()
+            .map(|(spec, plugin)| Slot {
+                stats: IndexPluginStats {
+                    name: spec.name.clone(),
+                    ..Default::default()
+                },
+                spec,
+                plugin,
+                consecutive_failures: 0,
+            })
+            .collect();
+        Self {
+            slots,
+            registry,
+            max_failures,
+        }
+    }
+
+    /// Entries of a turn from every enabled plugin matching its type.
+    /// Failures are recorded and the plugin's entries dropped.
+    #[allow(clippy::too_many_arguments)]
+    pub fn run(
+        &mut self,
+        context_id: u64,
+        turn_id: u64,
+        depth: u32,
+        declared_type_id: &str,
+        declared_type_version: u32,
+        payload: &[u8],
+    ) -> BTreeMap<String, Vec<String>> {
+        let mut entries: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        if !self
+            .slots
+            .iter()
+            .any(|s| !s.stats.disabled && s.spec.matches(declared_type_id))
+        {
+            return entries;
+        }
+        let input = IndexInput {
+            context_id: context_id.to_string(),
+            turn_id: turn_id.to_string(),
+            depth,
+            declared_type_id,
+            declared_type_version,
+            data: self.project(declared_type_id, declared_type_version, payload),
+        };
+        let Ok(input) = serde_json::to_vec(&input) else {
+            return entries;
+        };
+
+        for slot in &mut self.slots {
+            if slot.stats.disabled || !slot.spec.matches(declared_type_id) {
+                continue;
+            }
+            slot.stats.invocations += 1;
+            match slot
+                .plugin
+                .index(&input)
+                .and_then(|out| parse_entries(&out))
+            {
+                Ok(found) => {
+                    slot.consecutive_failures = 0;
+                    for (key, value) in found {
+                        slot.stats.entries += 1;
+                        let values = entries.entry(key).or_default();
+                        if !values.contains(&value) {
+                            values.push(value);
+                        }
+                    }
+                }
+                Err(err) => {
+                    slot.consecutive_failures += 1;
+                    slot.stats.failures += 1;
+                    slot.stats.last_error = Some(err.to_string());
+                    tracing::warn!(plugin = %slot.spec.name, turn_id, error = %err, "index plugin failed");
+                    if self.max_failures > 0 && slot.consecutive_failures >= self.max_failures {
+                        slot.stats.disabled = true;
+                        tracing::error!(
+                            plugin = %slot.spec.name,
+                            failures = slot.consecutive_failures,
+                            "index plugin disabled after consecutive failures"
+                        );
+                    }
+                }
+            }
+        }
+        entries
+    }
+
+    fn project(
+        &self,
+        type_id: &str,
+        type_version: u32,
+        payload: &[u8],
+    ) -> Option<serde_json::Value> {
+        let options = RenderOptions {
+            bytes_render: BytesRender::Base64,
+            u64_format: U64Format::String,
+            enum_render: EnumRender::Label,
+            time_render: TimeRender::Iso,
+            include_unknown: false,
+            apply_defaults: false,
+        };
+        let registry = self.registry.lock().unwrap();
+        let desc = registry.get_type_version(type_id, type_version)?;
+        crate::projection::project_msgpack(payload, desc, &registry, &options)
+            .ok()
+            .map(|p| p.data)
+    }
+
+    pub fn stats(&self) -> Vec<IndexPluginStats> {
+        self.slots.iter().map(|s| s.stats.clone()).collect()
+    }
+}
+
+#[cfg(feature = "wasm-plugins")]
+fn load_modules(config: &IndexPluginsConfig) -> Result<Vec<Box<dyn IndexPlugin>>> {
+    let engine = wasm::engine()?;
+    config
+        .plugins
+        .iter()
+        .map(|spec| {
+            let plugin = wasm::WasmPlugin::load(&engine, &spec.wasm, config.limits_of(spec))
+                .map_err(|e| match e {
+                    StoreError::InvalidInput(msg) => {
+                        StoreError::InvalidInput(format!("index plugin {:?}: {msg}", spec.name))
+                    }
+                    other => other,
+                })?;
+            Ok(Box::new(plugin) as Box<dyn IndexPlugin>)
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "wasm-plugins"))]
+fn load_modules(config: &IndexPluginsConfig) -> Result<Vec<Box<dyn IndexPlugin>>> {
+    if config.plugins.is_empty() {
+        return Ok(Vec::new());
+    }
+    Err(StoreError::InvalidInput(
+        "cxdb-server was built without WASM plugin support (enable the `wasm-plugins` feature)"
+            .into(),
+    ))
+}
+
+/// Valid keys of `custom.<key>` fields.
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= 64 && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Entries of a plugin's answer: a JSON object whose values are strings,
+/// numbers, booleans or arrays of them. Null values are ignored.
+pub fn parse_entries(out: &[u8]) -> Result<Vec<(String, String)>> {
+    let invalid = |msg: String| StoreError::InvalidInput(format!("invalid plugin output: {msg}"));
+    let value: serde_json::Value =
+        serde_json::from_slice(out).map_err(|e| invalid(e.to_string()))?;
+    let serde_json::Value::Object(object) = value else {
+        return Err(invalid("expected a JSON object".into()));
+    };
+    let mut entries = Vec::new();
+    for (key, value) in object {
+        if !is_valid_key(&key) {
+            return Err(invalid(format!("key {key:?} is not [A-Za-z0-9_]{{1,64}}")));
+        }
+        let values = match value {
+            serde_json::Value::Array(items) => items,
+            other => vec![other],
+        };
+        for value in values {
+            let value = match value {
+                serde_json::Value::Null => continue,
+                serde_json::Value::String(s) => s,
+                serde_json::Value::Bool(b) => b.to_string(),
+                serde_json::Value::Number(n) => n.to_string(),
+                _ => return Err(invalid(format!("value of {key:?} is not a scalar"))),
+            };
+            if value.len() > MAX_VALUE_LEN {
+                return Err(invalid(format!(
+                    "value of {key:?} is longer than {MAX_VALUE_LEN} bytes"
+                )));
+            }
+            entries.push((key.clone(), value));
+        }
+    }
+    if entries.len() > MAX_ENTRIES {
+        return Err(invalid(format!(
+            "{} entries; at most {MAX_ENTRIES} are allowed",
+            entries.len()
+        )));
+    }
+    Ok(entries)
+}
+
+/// Entries plugins returned for one turn.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomIndexRecord {
+    pub turn_id: u64,
+    pub entries: BTreeMap<String, Vec<String>>,
+}
+
+/// Plugin entries per turn, persisted as `meta/custom_index.jsonl` so the
+/// index is rebuilt on restart without running plugins again.
+pub struct CustomIndexLog {
+    file: File,
+    entries: HashMap<u64, BTreeMap<String, Vec<String>>>,
+}
+
+impl CustomIndexLog {
+    pub fn open(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join("custom_index.jsonl");
+        let (file, records) = open_log::<CustomIndexRecord>(&path)?;
+
+        let mut entries = HashMap::new();
+        for record in records {
+            entries.insert(record.turn_id, record.entries);
+        }
+
+        Ok(Self { file, entries })
+    }
+
+    pub fn get(&self, turn_id: u64) -> Option<&BTreeMap<String, Vec<String>>> {
+        self.entries.get(&turn_id)
+    }
+
+    /// Sync the log with every group commit batch.
+    pub fn track_files(&self, pipeline: &CommitPipeline) -> Result<()> {
+        pipeline.track("meta/custom_index.jsonl", &self.file)
+    }
+
+    pub fn record(&mut self, turn_id: u64, entries: BTreeMap<String, Vec<String>>) -> Result<()> {
+        let record = CustomIndexRecord { turn_id, entries };
+        let mut line = serde_json::to_vec(&record)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.entries.insert(turn_id, record.entries);
+        Ok(())
+    }
+}
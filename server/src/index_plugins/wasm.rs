@@ -0,0 +1,89 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Index plugins compiled with wasmtime.
+
+use std::path::Path;
+
+use wasmtime::{Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use super::{IndexPlugin, PluginLimits};
+use crate::error::{Result, StoreError};
+
+/// Engine shared by every plugin, with fuel metering on.
+pub fn engine() -> Result<Engine> {
+    let mut config = wasmtime::Config::new();
+    config.consume_fuel(true);
+    Engine::new(&config).map_err(|e| StoreError::InvalidInput(format!("wasm engine: {e}")))
+}
+
+/// A compiled module. Every call gets a fresh instance, so no state leaks
+/// between turns and a trap cannot leave the plugin half-updated.
+pub struct WasmPlugin {
+    engine: Engine,
+    module: Module,
+    limits: PluginLimits,
+}
+
+impl WasmPlugin {
+    /// Compile a binary or text module and check its exports.
+    pub fn load(engine: &Engine, path: &Path, limits: PluginLimits) -> Result<Self> {
+        let module = Module::from_file(engine, path)
+            .map_err(|e| StoreError::InvalidInput(format!("{}: {e}", path.display())))?;
+        if module.imports().len() > 0 {
+            return Err(StoreError::InvalidInput(format!(
+                "{}: modules may not import anything",
+                path.display()
+            )));
+        }
+        for export in ["memory", "alloc", "index"] {
+            if module.get_export(export).is_none() {
+                return Err(StoreError::InvalidInput(format!(
+                    "{}: missing export `{export}`",
+                    path.display()
+                )));
+            }
+        }
+        Ok(Self {
+            engine: engine.clone(),
+            module,
+            limits,
+        })
+    }
+
+    fn call(&self, input: &[u8]) -> wasmtime::Result<Vec<u8>> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.limits.memory_bytes as usize)
+            .instances(1)
+            .build();
+        let mut store: Store<StoreLimits> = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(self.limits.fuel)?;
+
+        let instance = Instance::new(&mut store, &self.module, &[])?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| wasmtime::Error::msg("`memory` is not a memory"))?;
+        let alloc = instance.get_typed_func::<u32, u32>(&mut store, "alloc")?;
+        let index = instance.get_typed_func::<(u32, u32), u64>(&mut store, "index")?;
+
+        let len = u32::try_from(input.len())?;
+        let ptr = alloc.call(&mut store, len)?;
+        memory.write(&mut store, ptr as usize, input)?;
+        let packed = index.call(&mut store, (ptr, len))?;
+        let (out_ptr, out_len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        if out_ptr.saturating_add(out_len) > memory.data_size(&store) {
+            return Err(wasmtime::Error::msg("output out of bounds"));
+        }
+        let mut out = vec![0; out_len];
+        memory.read(&store, out_ptr, &mut out)?;
+        Ok(out)
+    }
+}
+
+impl IndexPlugin for WasmPlugin {
+    fn index(&self, input: &[u8]) -> Result<Vec<u8>> {
+        self.call(input)
+            .map_err(|e| StoreError::InvalidInput(format!("wasm: {e}")))
+    }
+}
@@ -0,0 +1,411 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Ingest middleware: payload transformers run on append.
+//!
+//! Transformers normalize or enrich turn payloads (strip ANSI codes, add
+//! computed fields, ...) before they are stored. They are listed in a JSON
+//! file named by `CXDB_INGEST_TRANSFORMERS_FILE` and run in order on every
+//! binary protocol append whose declared type matches one of their `types`
+//! (a trailing `*` matches a prefix; no `types` matches every turn):
+//!
+//! ```text
+//! [
+//!   { "name": "strip-ansi", "url": "http://127.0.0.1:8085/strip",
+//!     "types": ["com.example.ToolOutput"] },
+//!   { "name": "enrich", "url": "http://127.0.0.1:8085/enrich",
+//!     "types": ["com.example.*"], "on_error": "skip", "timeout_ms": 500 }
+//! ]
+//! ```
+//!
+//! Each transformer receives the current payload and answers with a
+//! replacement, or 204 to leave it unchanged:
+//!
+//! ```text
+//! POST {url}
+//! { "context_id": "42", "declared_type_id": "com.example.ToolOutput",
+//!   "declared_type_version": 1, "encoding": 1, "payload_b64": "..." }
+//!
+//! 200 OK
+//! { "payload_b64": "..." }
+//! ```
+//!
+//! A failing transformer rejects the append unless its `on_error` is
+//! `skip`. The turn stores the final payload under its own hash; the hash
+//! and length the client sent, and the transformers that changed it, are
+//! kept in `meta/ingest.jsonl` for audit. Only HTTP transformers are
+//! supported; entries naming a `wasm` module are refused at startup.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::jsonl_log::open_log;
+use crate::protocol::AppendTurnRequest;
+use crate::store::decode_payload;
+use crate::turn_store::CommitPipeline;
+
+/// Default for `CXDB_INGEST_TIMEOUT_MS`.
+const DEFAULT_TIMEOUT_MS: u64 = 5_000;
+
+/// What happens to an append when a transformer fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    /// Refuse the append.
+    #[default]
+    Reject,
+    /// Pass the payload on unchanged.
+    Skip,
+}
+
+/// One transformer, as configured.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransformerSpec {
+    pub name: String,
+    /// HTTP endpoint the payload is POSTed to.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// WASM module path; recognized only to refuse it.
+    #[serde(default)]
+    pub wasm: Option<String>,
+    /// Declared type ids transformed; a trailing `*` matches a prefix.
+    /// Empty matches every turn.
+    #[serde(default)]
+    pub types: Vec<String>,
+    #[serde(default)]
+    pub on_error: OnError,
+    /// Overrides `CXDB_INGEST_TIMEOUT_MS` for this transformer.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+impl TransformerSpec {
+    pub fn matches(&self, type_id: &str) -> bool {
+        self.types.is_empty()
+            || self
+                .types
+                .iter()
+                .any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => type_id.starts_with(prefix),
+                    None => type_id == pattern,
+                })
+    }
+}
+
+/// Ingest middleware settings, loaded from the environment.
+#[derive(Debug, Clone)]
+pub struct IngestConfig {
+    pub transformers: Vec<TransformerSpec>,
+    /// Time a transformer has to answer, unless it sets its own.
+    pub timeout: Duration,
+}
+
+impl IngestConfig {
+    /// Returns None when `CXDB_INGEST_TRANSFORMERS_FILE` is unset or empty.
+    pub fn from_env() -> Result<Option<Self>> {
+        let path = match std::env::var("CXDB_INGEST_TRANSFORMERS_FILE") {
+            Ok(path) if !path.is_empty() => path,
+            _ => return Ok(None),
+        };
+        let json = std::fs::read_to_string(&path).map_err(|e| {
+            StoreError::InvalidInput(format!("CXDB_INGEST_TRANSFORMERS_FILE {path}: {e}"))
+        })?;
+        let timeout_ms = std::env::var("CXDB_INGEST_TIMEOUT_MS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_TIMEOUT_MS);
+        Ok(Some(Self {
+            transformers: Self::parse(&json)?,
+            timeout: Duration::from_millis(timeout_ms),
+        }))
+    }
+
+    /// Parse and validate a transformer list.
+    pub fn parse(json: &str) -> Result<Vec<TransformerSpec>> {
+        let specs: Vec<TransformerSpec> = serde_json::from_str(json)
+            .map_err(|e| StoreError::InvalidInput(format!("invalid transformer list: {e}")))?;
+        let mut names = HashSet::new();
+        for spec in &specs {
+            if spec.name.trim().is_empty() {
+                return Err(StoreError::InvalidInput(
+                    "every transformer needs a name".into(),
+                ));
+            }
+            if !names.insert(spec.name.as_str()) {
+                return Err(StoreError::InvalidInput(format!(
+                    "transformer {:?} is listed twice",
+                    spec.name
+                )));
+            }
+            if spec.wasm.is_some() {
+                return Err(StoreError::InvalidInput(format!(
+                    "transformer {:?}: WASM modules are not supported; serve it over HTTP",
+                    spec.name
+                )));
+            }
+            match spec.url.as_deref() {
+                Some(url) if url.starts_with("http://") || url.starts_with("https://") => {}
+                _ => {
+                    return Err(StoreError::InvalidInput(format!(
+                        "transformer {:?} needs an http(s) url",
+                        spec.name
+                    )))
+                }
+            }
+        }
+        Ok(specs)
+    }
+
+    /// Transformer names and types, for logging.
+    pub fn describe(&self) -> String {
+        self.transformers
+            .iter()
+            .map(|t| {
+                if t.types.is_empty() {
+                    t.name.clone()
+                } else {
+                    format!("{} ({})", t.name, t.types.join(","))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Request body sent to a transformer.
+#[derive(Debug, Clone, Serialize)]
+pub struct TransformRequest {
+    pub context_id: String,
+    pub declared_type_id: String,
+    pub declared_type_version: u32,
+    pub encoding: u32,
+    /// The uncompressed payload.
+    pub payload_b64: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransformResponse {
+    payload_b64: String,
+}
+
+/// Rewrites a payload. `Ok(None)` leaves it unchanged.
+pub trait Transformer: Send + Sync {
+    fn transform(&self, request: &TransformRequest) -> Result<Option<Vec<u8>>>;
+}
+
+/// Transformer that POSTs the request as JSON to an HTTP endpoint.
+pub struct HttpTransformer {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl HttpTransformer {
+    pub fn new(url: String, timeout: Duration) -> Self {
+        let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+        Self { url, agent }
+    }
+}
+
+impl Transformer for HttpTransformer {
+    fn transform(&self, request: &TransformRequest) -> Result<Option<Vec<u8>>> {
+        let response = match self.agent.post(&self.url).send_json(request) {
+            Ok(response) => response,
+            // The transformer refused the payload itself.
+            Err(ureq::Error::Status(status, response)) if (400..500).contains(&status) => {
+                let body = response.into_string().unwrap_or_default();
+                return Err(StoreError::InvalidInput(format!(
+                    "rejected with {status}: {}",
+                    body.trim()
+                )));
+            }
+            Err(e) => return Err(StoreError::Io(std::io::Error::other(e.to_string()))),
+        };
+        if response.status() == 204 {
+            return Ok(None);
+        }
+        let parsed: TransformResponse = response
+            .into_json()
+            .map_err(|e| StoreError::InvalidInput(format!("invalid response: {e}")))?;
+        base64::engine::general_purpose::STANDARD
+            .decode(parsed.payload_b64)
+            .map(Some)
+            .map_err(|e| StoreError::InvalidInput(format!("invalid payload_b64: {e}")))
+    }
+}
+
+/// A transformer and the turns it applies to.
+pub struct IngestStage {
+    pub spec: TransformerSpec,
+    pub transformer: Box<dyn Transformer>,
+}
+
+/// The payload an append arrived with, before transformers changed it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IngestAudit {
+    /// Hex BLAKE3 hash of the payload the client sent.
+    pub original_hash: String,
+    pub original_len: u64,
+    /// Transformers that changed the payload, in the order they ran.
+    pub transformers: Vec<String>,
+}
+
+/// Runs the configured transformers on appended payloads.
+pub struct IngestPipeline {
+    stages: Vec<IngestStage>,
+}
+
+impl IngestPipeline {
+    /// HTTP transformers for `config`.
+    pub fn new(config: IngestConfig) -> Self {
+        let stages = config
+            .transformers
+            .into_iter()
+            .map(|spec| {
+                let timeout = spec
+                    .timeout_ms
+                    .map_or(config.timeout, Duration::from_millis);
+                let url = spec.url.clone().unwrap_or_default();
+                IngestStage {
+                    spec,
+                    transformer: Box::new(HttpTransformer::new(url, timeout)),
+                }
+            })
+            .collect();
+        Self { stages }
+    }
+
+    pub fn from_stages(stages: Vec<IngestStage>) -> Self {
+        Self { stages }
+    }
+
+    /// Run the transformers matching the request's declared type. When one
+    /// changed the payload, the request is rewritten to carry the result
+    /// uncompressed, and the original is described by the returned audit.
+    pub fn apply(&self, request: &mut AppendTurnRequest) -> Result<Option<IngestAudit>> {
+        let stages: Vec<&IngestStage> = self
+            .stages
+            .iter()
+            .filter(|stage| stage.spec.matches(&request.declared_type_id))
+            .collect();
+        if stages.is_empty() {
+            return Ok(None);
+        }
+        let original = decode_payload(
+            request.compression,
+            request.uncompressed_len,
+            &request.content_hash,
+            &request.payload_bytes,
+        )?;
+        let mut payload = original.clone();
+        let mut applied = Vec::new();
+        for stage in stages {
+            let transform_request = TransformRequest {
+                context_id: request.context_id.to_string(),
+                declared_type_id: request.declared_type_id.clone(),
+                declared_type_version: request.declared_type_version,
+                encoding: request.encoding,
+                payload_b64: base64::engine::general_purpose::STANDARD.encode(&payload),
+            };
+            match stage.transformer.transform(&transform_request) {
+                Ok(Some(transformed)) if transformed != payload => {
+                    payload = transformed;
+                    applied.push(stage.spec.name.clone());
+                }
+                Ok(_) => {}
+                Err(err) if stage.spec.on_error == OnError::Skip => {
+                    eprintln!("ingest transformer {} skipped: {err}", stage.spec.name);
+                }
+                Err(err) => {
+                    let message = format!("ingest transformer {}: {err}", stage.spec.name);
+                    return Err(match err {
+                        StoreError::InvalidInput(_) => StoreError::InvalidInput(message),
+                        _ => StoreError::Io(std::io::Error::other(message)),
+                    });
+                }
+            }
+        }
+        if applied.is_empty() {
+            return Ok(None);
+        }
+        let uncompressed_len = u32::try_from(payload.len()).map_err(|_| {
+            StoreError::InvalidInput(format!(
+                "ingest transformers {} produced a payload over 4 GiB",
+                applied.join(", ")
+            ))
+        })?;
+        request.compression = 0;
+        request.uncompressed_len = uncompressed_len;
+        request.content_hash = *blake3::hash(&payload).as_bytes();
+        request.payload_bytes = payload;
+        Ok(Some(IngestAudit {
+            original_hash: blake3::hash(&original).to_hex().to_string(),
+            original_len: original.len() as u64,
+            transformers: applied,
+        }))
+    }
+}
+
+/// A transformed turn's audit entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IngestRecord {
+    pub turn_id: u64,
+    #[serde(flatten)]
+    pub audit: IngestAudit,
+    pub transformed_at_unix_ms: u64,
+}
+
+/// Audit entries of transformed turns, persisted as `meta/ingest.jsonl`.
+pub struct IngestLog {
+    file: File,
+    entries: HashMap<u64, IngestRecord>,
+}
+
+impl IngestLog {
+    pub fn open(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join("ingest.jsonl");
+        let (file, records) = open_log::<IngestRecord>(&path)?;
+
+        let mut entries = HashMap::new();
+        for entry in records {
+            entries.insert(entry.turn_id, entry);
+        }
+
+        Ok(Self { file, entries })
+    }
+
+    pub fn get(&self, turn_id: u64) -> Option<&IngestRecord> {
+        self.entries.get(&turn_id)
+    }
+
+    /// Sync the log with every group commit batch.
+    pub fn track_files(&self, pipeline: &CommitPipeline) -> Result<()> {
+        pipeline.track("meta/ingest.jsonl", &self.file)
+    }
+
+    pub fn record(&mut self, turn_id: u64, audit: IngestAudit) -> Result<()> {
+        let entry = IngestRecord {
+            turn_id,
+            audit,
+            transformed_at_unix_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+        };
+        let mut line = serde_json::to_vec(&entry)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.entries.insert(turn_id, entry);
+        Ok(())
+    }
+}
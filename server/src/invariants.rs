@@ -0,0 +1,166 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Cross-file store invariants.
+//!
+//! [`crate::store::Store::verify_invariants`] checks that the turn log, the
+//! chain hashes, the head table and its history, the blob pack and the
+//! secondary indexes agree with each other, and reports every violation it
+//! finds. fsck runs it over the whole store; integration tests can run it
+//! after exercising the store and call [`InvariantReport::assert_ok`].
+
+use serde::Serialize;
+
+/// Violations kept in a report; later ones are only counted.
+const MAX_REPORTED: usize = 100;
+
+/// How much of the store to check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyScope {
+    /// Every turn and every context.
+    Full,
+    /// This many contexts, spread evenly over the context ids, with every
+    /// turn on their head chains.
+    Sample(usize),
+}
+
+/// An invariant the store must uphold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Invariant {
+    /// A turn's parent turn exists.
+    ParentExists,
+    /// A turn's depth is its parent's depth plus one, or 0 for a root.
+    DepthFollowsParent,
+    /// A turn's stored chain hash matches the recomputed one.
+    ChainHash,
+    /// A turn's payload is in the blob pack.
+    PayloadStored,
+    /// A context's head turn exists.
+    HeadTurnExists,
+    /// A context's head depth is its head turn's depth.
+    HeadDepth,
+    /// A context's latest head move is its current head.
+    HeadHistory,
+    /// The secondary indexes cover the context, once it has a turn.
+    IndexCoversContext,
+    /// The secondary indexes hold the context at its head depth.
+    IndexDepth,
+    /// The secondary indexes agree with the legal hold log.
+    IndexHold,
+}
+
+/// One broken invariant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Violation {
+    pub invariant: Invariant,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub turn_id: Option<u64>,
+    pub detail: String,
+}
+
+/// Result of [`crate::store::Store::verify_invariants`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InvariantReport {
+    pub ok: bool,
+    /// False when only a sample of contexts was checked.
+    pub full: bool,
+    pub contexts_checked: u64,
+    pub turns_checked: u64,
+    pub violation_count: u64,
+    /// The first violations found.
+    pub violations: Vec<Violation>,
+}
+
+impl InvariantReport {
+    pub(crate) fn new(scope: VerifyScope) -> Self {
+        Self {
+            ok: true,
+            full: scope == VerifyScope::Full,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn violation(
+        &mut self,
+        invariant: Invariant,
+        context_id: Option<u64>,
+        turn_id: Option<u64>,
+        detail: String,
+    ) {
+        self.ok = false;
+        self.violation_count += 1;
+        if self.violations.len() < MAX_REPORTED {
+            self.violations.push(Violation {
+                invariant,
+                context_id,
+                turn_id,
+                detail,
+            });
+        }
+    }
+
+    /// Panic with the reported violations unless every invariant held.
+    pub fn assert_ok(&self) {
+        if self.ok {
+            return;
+        }
+        let lines: Vec<String> = self
+            .violations
+            .iter()
+            .map(|v| {
+                let mut line = format!("{:?}", v.invariant);
+                if let Some(context_id) = v.context_id {
+                    line.push_str(&format!(" context {context_id}"));
+                }
+                if let Some(turn_id) = v.turn_id {
+                    line.push_str(&format!(" turn {turn_id}"));
+                }
+                format!("{line}: {}", v.detail)
+            })
+            .collect();
+        panic!(
+            "{} store invariant violations:\n  {}",
+            self.violation_count,
+            lines.join("\n  ")
+        );
+    }
+
+    /// Contexts to check for `scope`, from the sorted context ids.
+    pub(crate) fn sample(scope: VerifyScope, mut context_ids: Vec<u64>) -> Vec<u64> {
+        context_ids.sort_unstable();
+        match scope {
+            VerifyScope::Full => context_ids,
+            VerifyScope::Sample(n) if n >= context_ids.len() => context_ids,
+            VerifyScope::Sample(0) => Vec::new(),
+            VerifyScope::Sample(n) => {
+                let step = context_ids.len() as f64 / n as f64;
+                (0..n)
+                    .map(|i| context_ids[(i as f64 * step) as usize])
+                    .collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_spreads_over_ids() {
+        let ids: Vec<u64> = (1..=10).rev().collect();
+        assert_eq!(
+            InvariantReport::sample(VerifyScope::Sample(3), ids.clone()),
+            vec![1, 4, 7]
+        );
+        assert_eq!(
+            InvariantReport::sample(VerifyScope::Sample(20), ids.clone()).len(),
+            10
+        );
+        assert!(InvariantReport::sample(VerifyScope::Sample(0), ids.clone()).is_empty());
+        assert_eq!(InvariantReport::sample(VerifyScope::Full, ids)[0], 1);
+    }
+}
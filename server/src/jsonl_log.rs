@@ -0,0 +1,83 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Append-only JSON-lines logs.
+//!
+//! Side logs (holds, overrides, bookmarks, ...) append one JSON record per
+//! line and sync it before acknowledging the write. A crash mid-write leaves
+//! a torn last line without its newline. [`open_log`] cuts such a tail off
+//! before handing out the file, so the next record starts on a line of its
+//! own instead of being glued onto the torn bytes and lost on the following
+//! open.
+
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+
+use crate::error::Result;
+
+/// Open the log at `path` for appending, creating it if needed, and read
+/// its records oldest first. Complete lines that do not parse are skipped.
+pub fn open_log<T: DeserializeOwned>(path: &Path) -> Result<(File, Vec<T>)> {
+    let (file, records) = open_log_sized(path)?;
+    Ok((
+        file,
+        records.into_iter().map(|(record, _)| record).collect(),
+    ))
+}
+
+/// [`open_log`], with the bytes each record takes in the file, newline
+/// included.
+pub fn open_log_sized<T: DeserializeOwned>(path: &Path) -> Result<(File, Vec<(T, u64)>)> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+
+    let mut records = Vec::new();
+    let mut valid_len = 0;
+    for line in buf.split_inclusive(|&b| b == b'\n') {
+        if !line.ends_with(b"\n") {
+            break;
+        }
+        valid_len += line.len();
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        if let Ok(record) = serde_json::from_slice(line) {
+            records.push((record, line.len() as u64));
+        }
+    }
+    if valid_len < buf.len() {
+        file.set_len(valid_len as u64)?;
+        file.sync_data()?;
+    }
+    Ok((file, records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_torn_tail_is_cut_before_appending() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("log.jsonl");
+        std::fs::write(&path, b"1\n\n{\"not\": \"a number\"}\n2\n\xff\xfe3").unwrap();
+
+        let (mut file, records) = open_log::<u64>(&path).unwrap();
+        assert_eq!(records, vec![1, 2]);
+        file.write_all(b"4\n").unwrap();
+        drop(file);
+
+        let (_, records) = open_log_sized::<u64>(&path).unwrap();
+        assert_eq!(records, vec![(1, 2), (2, 2), (4, 2)]);
+    }
+}
@@ -0,0 +1,865 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-context and per-tag data-encryption keys (crypto-shredding).
+//!
+//! When encryption is enabled each context is bound to a data-encryption key
+//! (DEK) the first time it stores data: its own key in `context` mode, or the
+//! key of its client tag in `tag` mode (untagged contexts get their own key).
+//! Forks keep the key of the turn they fork from. DEKs are random and stored
+//! only wrapped (AES-256-GCM) by the master key in `keys/keys.json`. New
+//! keys and context bindings are appended to `keys/keys.log` and folded into
+//! `keys.json` when it is next rewritten (on unlock and shred), so binding a
+//! context costs one small fsync'd append.
+//!
+//! Turn payloads and filesystem blobs of bound contexts are sealed with their
+//! DEK and stored under `blake3::keyed_hash(dek, content_hash)`, so the pack
+//! never holds the plaintext or its content hash. Shredding deletes the
+//! wrapped DEK and leaves a tombstone; the sealed blobs stay in the packfile
+//! but can no longer be decrypted.
+//!
+//! `keys/turns.log` maps sealed turns to their key as fixed 16-byte records
+//! (turn_id u64, key_id u64). A record is synced before its turn is written,
+//! and records for turns that never landed are dropped when the store opens.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use base64::Engine;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::blob_store::{BlobSink, BlobSource, BlobStore};
+use crate::error::{Result, StoreError};
+use crate::turn_store::CommitPipeline;
+use crate::util::{sync_dir, unix_ms};
+
+const SEALED_VERSION: u8 = 1;
+const SEALED_RAW: u8 = 0;
+const SEALED_ZSTD: u8 = 1;
+const MASTER_CHECK_CONTEXT: &[u8] = b"cxdb master key check";
+const TURN_LOG_ENTRY_SIZE: usize = 16;
+
+/// How new contexts are assigned a data-encryption key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EncryptionMode {
+    /// New data is stored in plaintext; existing sealed data stays readable.
+    Off,
+    /// Every context gets its own key.
+    Context,
+    /// Contexts share the key of their client tag.
+    Tag,
+}
+
+/// Encryption settings, loaded from the environment.
+#[derive(Clone)]
+pub struct EncryptionConfig {
+    pub mode: EncryptionMode,
+    pub master_key: [u8; 32],
+}
+
+impl EncryptionConfig {
+    /// Reads `CXDB_ENCRYPTION` (off, context, tag) and the master key from
+    /// `CXDB_MASTER_KEY` or `CXDB_MASTER_KEY_FILE` (64 hex characters).
+    /// Returns None when neither a mode nor a master key is configured.
+    pub fn from_env() -> Result<Option<Self>> {
+        let mode = match std::env::var("CXDB_ENCRYPTION")
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "" | "off" => EncryptionMode::Off,
+            "context" => EncryptionMode::Context,
+            "tag" => EncryptionMode::Tag,
+            other => {
+                return Err(StoreError::InvalidInput(format!(
+                    "CXDB_ENCRYPTION: unknown mode {other:?}"
+                )))
+            }
+        };
+        let hex_key = match (
+            std::env::var("CXDB_MASTER_KEY"),
+            std::env::var("CXDB_MASTER_KEY_FILE"),
+        ) {
+            (Ok(key), _) if !key.is_empty() => Some(key),
+            (_, Ok(path)) if !path.is_empty() => Some(fs::read_to_string(path)?),
+            _ => None,
+        };
+        let Some(hex_key) = hex_key else {
+            if mode == EncryptionMode::Off {
+                return Ok(None);
+            }
+            return Err(StoreError::InvalidInput(
+                "CXDB_ENCRYPTION requires CXDB_MASTER_KEY or CXDB_MASTER_KEY_FILE".into(),
+            ));
+        };
+        let mut master_key = [0u8; 32];
+        hex::decode_to_slice(hex_key.trim(), &mut master_key)
+            .map_err(|_| StoreError::InvalidInput("master key must be 64 hex characters".into()))?;
+        Ok(Some(Self { mode, master_key }))
+    }
+}
+
+/// What a key protects.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum KeyScope {
+    Context { context_id: u64 },
+    Tag { tag: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredKey {
+    key_id: u64,
+    scope: KeyScope,
+    /// base64(nonce || AES-256-GCM(master, dek)); removed when shredded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wrapped_key: Option<String>,
+    created_at_unix_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shredded_at_unix_ms: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct KeyFile {
+    #[serde(default)]
+    master_key_check: Option<String>,
+    #[serde(default)]
+    next_key_id: u64,
+    #[serde(default)]
+    keys: Vec<StoredKey>,
+    /// context_id -> key_id
+    #[serde(default)]
+    contexts: BTreeMap<u64, u64>,
+}
+
+/// A `keys.log` record: a change not yet folded into `keys.json`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum KeyLogEntry {
+    Key(StoredKey),
+    Bind { context_id: u64, key_id: u64 },
+}
+
+/// Public view of a key (never includes key material).
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyInfo {
+    #[serde(skip)]
+    pub id: u64,
+    pub key_id: String,
+    pub scope: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    pub context_count: usize,
+    pub created_at_unix_ms: u64,
+    pub shredded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shredded_at_unix_ms: Option<u64>,
+}
+
+/// An unwrapped data-encryption key.
+#[derive(Clone)]
+pub struct DataKey {
+    pub key_id: u64,
+    bytes: [u8; 32],
+}
+
+impl DataKey {
+    /// Blob store key for content sealed under this key.
+    pub fn storage_hash(&self, content_hash: &[u8; 32]) -> [u8; 32] {
+        *blake3::keyed_hash(&self.bytes, content_hash).as_bytes()
+    }
+
+    /// Compress (when it helps) and encrypt `plaintext`, bound to its content hash.
+    pub fn seal(&self, content_hash: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let (codec, mut body) = match zstd::encode_all(plaintext, 1) {
+            Ok(compressed) if compressed.len() < plaintext.len() => (SEALED_ZSTD, compressed),
+            _ => (SEALED_RAW, plaintext.to_vec()),
+        };
+        let nonce = random_nonce()?;
+        aead_key(&self.bytes)
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(content_hash),
+                &mut body,
+            )
+            .map_err(|_| StoreError::Corrupt("payload encryption failed".into()))?;
+        let mut out = Vec::with_capacity(2 + NONCE_LEN + body.len());
+        out.push(SEALED_VERSION);
+        out.push(codec);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&body);
+        Ok(out)
+    }
+
+    /// Decrypt a sealed blob and verify it against its content hash.
+    pub fn open(&self, content_hash: &[u8; 32], sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < 2 + NONCE_LEN || sealed[0] != SEALED_VERSION {
+            return Err(StoreError::Corrupt("invalid sealed blob".into()));
+        }
+        let codec = sealed[1];
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&sealed[2..2 + NONCE_LEN]);
+        let mut body = sealed[2 + NONCE_LEN..].to_vec();
+        let plain = aead_key(&self.bytes)
+            .open_in_place(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(content_hash),
+                &mut body,
+            )
+            .map_err(|_| StoreError::Corrupt("sealed blob failed authentication".into()))?;
+        let plaintext = match codec {
+            SEALED_RAW => plain.to_vec(),
+            SEALED_ZSTD => zstd::decode_all(&plain[..])
+                .map_err(|e| StoreError::Corrupt(format!("sealed blob zstd: {e}")))?,
+            other => {
+                return Err(StoreError::Corrupt(format!(
+                    "unknown sealed blob codec {other}"
+                )))
+            }
+        };
+        if blake3::hash(&plaintext).as_bytes() != content_hash {
+            return Err(StoreError::Corrupt("sealed blob hash mismatch".into()));
+        }
+        Ok(plaintext)
+    }
+}
+
+/// Blob reads for one context: sealed blobs are opened with its key, and
+/// plaintext blobs (uploaded without a context) are read as-is.
+pub struct SealedBlobs<'a> {
+    pub blobs: &'a mut BlobStore,
+    pub key: Option<DataKey>,
+}
+
+impl SealedBlobs<'_> {
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.key
+            .as_ref()
+            .is_some_and(|key| self.blobs.contains(&key.storage_hash(hash)))
+            || self.blobs.contains(hash)
+    }
+
+    /// Hash the blob is stored under: its sealed hash if it was sealed with
+    /// this context's key, else its content hash.
+    pub fn storage_hash(&self, hash: &[u8; 32]) -> [u8; 32] {
+        match &self.key {
+            Some(key) if self.blobs.contains(&key.storage_hash(hash)) => key.storage_hash(hash),
+            _ => *hash,
+        }
+    }
+
+    fn is_sealed(&self, hash: &[u8; 32]) -> bool {
+        self.key
+            .as_ref()
+            .is_some_and(|key| self.blobs.contains(&key.storage_hash(hash)))
+    }
+}
+
+impl BlobSource for SealedBlobs<'_> {
+    fn get_blob(&mut self, hash: &[u8; 32]) -> Result<Vec<u8>> {
+        if let Some(key) = &self.key {
+            let storage_hash = key.storage_hash(hash);
+            if self.blobs.contains(&storage_hash) {
+                return key.open(hash, &self.blobs.get(&storage_hash)?);
+            }
+        }
+        self.blobs.get(hash)
+    }
+
+    fn get_blob_range(&mut self, hash: &[u8; 32], offset: u64, len: u64) -> Result<Vec<u8>> {
+        // Sealed blobs are authenticated as a whole, so they are opened in full.
+        if self.is_sealed(hash) {
+            let blob = self.get_blob(hash)?;
+            let start = (offset as usize).min(blob.len());
+            let end = start.saturating_add(len as usize).min(blob.len());
+            return Ok(blob[start..end].to_vec());
+        }
+        self.blobs.get_range(hash, offset, len)
+    }
+
+    fn blob_len(&self, hash: &[u8; 32]) -> Option<u64> {
+        if self.is_sealed(hash) {
+            return None;
+        }
+        self.blobs.raw_len(hash).map(u64::from)
+    }
+}
+
+impl BlobSink for SealedBlobs<'_> {
+    fn has_blob(&self, hash: &[u8; 32]) -> bool {
+        self.contains(hash)
+    }
+
+    fn put_blob(&mut self, hash: [u8; 32], data: &[u8]) -> Result<bool> {
+        if self.contains(&hash) {
+            return Ok(false);
+        }
+        match &self.key {
+            Some(key) => {
+                let sealed = key.seal(&hash, data)?;
+                self.blobs.put_if_absent(key.storage_hash(&hash), &sealed)?;
+            }
+            None => {
+                self.blobs.put_if_absent(hash, data)?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+pub struct KeyRing {
+    path: PathBuf,
+    file: KeyFile,
+    key_log: File,
+    mode: EncryptionMode,
+    master: Option<LessSafeKey>,
+    /// Unwrapped keys by key_id.
+    cache: HashMap<u64, [u8; 32]>,
+    turn_keys: HashMap<u64, u64>,
+    turn_log: File,
+    /// Group commit pipeline syncing the logs, re-tracked after rewrites.
+    commit: Option<Arc<CommitPipeline>>,
+}
+
+impl KeyRing {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("keys.json");
+        let file = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| StoreError::Corrupt(format!("keys.json: {e}")))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => KeyFile::default(),
+            Err(e) => return Err(e.into()),
+        };
+        let (key_log, file) = replay_key_log(&dir.join("keys.log"), file)?;
+
+        let log_path = dir.join("turns.log");
+        let mut turn_log = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&log_path)?;
+        let mut buf = Vec::new();
+        turn_log.read_to_end(&mut buf)?;
+        let valid_len = buf.len() - buf.len() % TURN_LOG_ENTRY_SIZE;
+        let mut turn_keys = HashMap::new();
+        let mut cursor = &buf[..valid_len];
+        while !cursor.is_empty() {
+            let turn_id = cursor.read_u64::<LittleEndian>()?;
+            let key_id = cursor.read_u64::<LittleEndian>()?;
+            turn_keys.insert(turn_id, key_id);
+        }
+        if valid_len < buf.len() {
+            // Drop a partially written record.
+            turn_log.set_len(valid_len as u64)?;
+        }
+
+        Ok(Self {
+            path,
+            file,
+            key_log,
+            mode: EncryptionMode::Off,
+            master: None,
+            cache: HashMap::new(),
+            turn_keys,
+            turn_log,
+            commit: None,
+        })
+    }
+
+    /// Sync the key and turn logs with every group commit batch. Both are
+    /// also synced as each record is written, since a turn's key record
+    /// must be on disk before the turn is.
+    pub fn track_files(&mut self, pipeline: Arc<CommitPipeline>) -> Result<()> {
+        pipeline.track("keys/keys.log", &self.key_log)?;
+        pipeline.track("keys/turns.log", &self.turn_log)?;
+        self.commit = Some(pipeline);
+        Ok(())
+    }
+
+    /// Install the master key and mode. The first master key used with a key
+    /// ring is remembered (as a keyed hash) and a different one is rejected.
+    pub fn unlock(&mut self, config: &EncryptionConfig) -> Result<()> {
+        let check =
+            hex::encode(blake3::keyed_hash(&config.master_key, MASTER_CHECK_CONTEXT).as_bytes());
+        match &self.file.master_key_check {
+            Some(existing) if *existing != check => {
+                return Err(StoreError::InvalidInput(
+                    "master key does not match the one used for existing keys".into(),
+                ))
+            }
+            Some(_) => {}
+            None => {
+                self.file.master_key_check = Some(check);
+                self.persist()?;
+            }
+        }
+        self.master = Some(aead_key(&config.master_key));
+        self.mode = config.mode;
+        Ok(())
+    }
+
+    pub fn mode(&self) -> EncryptionMode {
+        self.mode
+    }
+
+    pub fn has_sealed_data(&self) -> bool {
+        !self.file.contexts.is_empty()
+    }
+
+    pub fn context_key(&self, context_id: u64) -> Option<u64> {
+        self.file.contexts.get(&context_id).copied()
+    }
+
+    pub fn turn_key(&self, turn_id: u64) -> Option<u64> {
+        self.turn_keys.get(&turn_id).copied()
+    }
+
+    /// Key for new data in `context_id`, binding the context on first use.
+    /// `inherited` is the key of the turn the context continues from, and
+    /// `tag` its client tag. Returns None when the data should be stored in
+    /// plaintext.
+    pub fn key_for_write(
+        &mut self,
+        context_id: u64,
+        inherited: Option<u64>,
+        tag: Option<&str>,
+    ) -> Result<Option<DataKey>> {
+        if let Some(key_id) = self.context_key(context_id) {
+            return self.data_key(key_id).map(Some);
+        }
+        let key_id = match (inherited, self.mode, tag) {
+            (Some(key_id), _, _) => key_id,
+            (None, EncryptionMode::Off, _) => return Ok(None),
+            (None, EncryptionMode::Tag, Some(tag)) if !tag.is_empty() => {
+                match self.find_tag_key(tag) {
+                    Some(key_id) => key_id,
+                    None => self.create_key(KeyScope::Tag {
+                        tag: tag.to_string(),
+                    })?,
+                }
+            }
+            (None, _, _) => self.create_key(KeyScope::Context { context_id })?,
+        };
+        // Fail before binding if the key is unusable (e.g. shredded).
+        let key = self.data_key(key_id)?;
+        self.append_key_log(&KeyLogEntry::Bind { context_id, key_id })?;
+        self.file.contexts.insert(context_id, key_id);
+        Ok(Some(key))
+    }
+
+    /// Remember that `turn_id` is sealed with `key_id`. Called before the
+    /// turn is written, so the record is synced before returning.
+    pub fn record_turn(&mut self, turn_id: u64, key_id: u64) -> Result<()> {
+        let mut entry = Vec::with_capacity(TURN_LOG_ENTRY_SIZE);
+        entry.write_u64::<LittleEndian>(turn_id)?;
+        entry.write_u64::<LittleEndian>(key_id)?;
+        self.turn_log.write_all(&entry)?;
+        self.turn_log.sync_data()?;
+        self.turn_keys.insert(turn_id, key_id);
+        Ok(())
+    }
+
+    /// Drop the records of turns after `last_turn_id`: turns whose record was
+    /// written but whose append failed or was cut short by a crash.
+    pub fn forget_turns_after(&mut self, last_turn_id: u64) -> Result<()> {
+        if self
+            .turn_keys
+            .keys()
+            .all(|&turn_id| turn_id <= last_turn_id)
+        {
+            return Ok(());
+        }
+        self.turn_keys.retain(|&turn_id, _| turn_id <= last_turn_id);
+        let mut records: Vec<_> = self.turn_keys.iter().collect();
+        records.sort();
+        let mut bytes = Vec::with_capacity(records.len() * TURN_LOG_ENTRY_SIZE);
+        for (&turn_id, &key_id) in records {
+            bytes.write_u64::<LittleEndian>(turn_id)?;
+            bytes.write_u64::<LittleEndian>(key_id)?;
+        }
+        let dir = self.path.parent().expect("keys.json has a parent");
+        let log_path = dir.join("turns.log");
+        let tmp = dir.join("turns.log.tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &log_path)?;
+        sync_dir(dir)?;
+        self.turn_log = OpenOptions::new().append(true).read(true).open(&log_path)?;
+        if let Some(pipeline) = self.commit.take() {
+            self.track_files(pipeline)?;
+        }
+        Ok(())
+    }
+
+    /// Unwrap a key. Fails with `Shredded` once the key was shredded.
+    pub fn data_key(&mut self, key_id: u64) -> Result<DataKey> {
+        if let Some(bytes) = self.cache.get(&key_id) {
+            return Ok(DataKey {
+                key_id,
+                bytes: *bytes,
+            });
+        }
+        let stored = self
+            .file
+            .keys
+            .iter()
+            .find(|k| k.key_id == key_id)
+            .ok_or_else(|| StoreError::Corrupt(format!("unknown encryption key {key_id}")))?;
+        let Some(wrapped) = &stored.wrapped_key else {
+            return Err(StoreError::Shredded(format!(
+                "encryption key {key_id} was shredded"
+            )));
+        };
+        let master = self.master.as_ref().ok_or_else(|| {
+            StoreError::Corrupt("sealed data requires the master key (CXDB_MASTER_KEY)".into())
+        })?;
+        let mut sealed = base64::engine::general_purpose::STANDARD
+            .decode(wrapped)
+            .map_err(|e| StoreError::Corrupt(format!("wrapped key {key_id}: {e}")))?;
+        if sealed.len() < NONCE_LEN {
+            return Err(StoreError::Corrupt(format!(
+                "wrapped key {key_id} too short"
+            )));
+        }
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&sealed[..NONCE_LEN]);
+        let body = &mut sealed[NONCE_LEN..];
+        let plain = master
+            .open_in_place(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(key_id.to_le_bytes()),
+                body,
+            )
+            .map_err(|_| StoreError::Corrupt(format!("cannot unwrap key {key_id}")))?;
+        let bytes: [u8; 32] = plain
+            .try_into()
+            .map_err(|_| StoreError::Corrupt(format!("wrapped key {key_id} has bad length")))?;
+        self.cache.insert(key_id, bytes);
+        Ok(DataKey { key_id, bytes })
+    }
+
+    /// Shred the key of a context. Contexts on a tag key must be shredded
+    /// through their tag, since the key is shared.
+    pub fn shred_context(&mut self, context_id: u64) -> Result<KeyInfo> {
+        let key_id = self.context_key(context_id).ok_or_else(|| {
+            StoreError::NotFound(format!("context {context_id} has no encryption key"))
+        })?;
+        if let Some(KeyScope::Tag { tag }) = self.stored(key_id).map(|k| k.scope.clone()) {
+            return Err(StoreError::InvalidInput(format!(
+                "context {context_id} uses the key of tag {tag:?}; shred the tag instead"
+            )));
+        }
+        self.shred(key_id)
+    }
+
+    pub fn shred_tag(&mut self, tag: &str) -> Result<KeyInfo> {
+        let key_id = self
+            .find_tag_key(tag)
+            .ok_or_else(|| StoreError::NotFound(format!("tag {tag:?} has no encryption key")))?;
+        self.shred(key_id)
+    }
+
+    pub fn contexts_with_key(&self, key_id: u64) -> Vec<u64> {
+        self.file
+            .contexts
+            .iter()
+            .filter(|(_, &k)| k == key_id)
+            .map(|(&context_id, _)| context_id)
+            .collect()
+    }
+
+    pub fn is_shredded(&self, key_id: u64) -> bool {
+        self.stored(key_id).is_some_and(|k| k.wrapped_key.is_none())
+    }
+
+    pub fn list(&self) -> Vec<KeyInfo> {
+        self.file.keys.iter().map(|k| self.info(k)).collect()
+    }
+
+    fn shred(&mut self, key_id: u64) -> Result<KeyInfo> {
+        let stored = self
+            .file
+            .keys
+            .iter_mut()
+            .find(|k| k.key_id == key_id)
+            .ok_or_else(|| StoreError::NotFound(format!("encryption key {key_id}")))?;
+        if stored.wrapped_key.take().is_some() {
+            stored.shredded_at_unix_ms = Some(unix_ms());
+        }
+        self.cache.remove(&key_id);
+        self.persist()?;
+        let stored = self.stored(key_id).expect("key exists");
+        Ok(self.info(stored))
+    }
+
+    pub fn find_tag_key(&self, tag: &str) -> Option<u64> {
+        self.file
+            .keys
+            .iter()
+            .find(|k| matches!(&k.scope, KeyScope::Tag { tag: t } if t == tag))
+            .map(|k| k.key_id)
+    }
+
+    fn stored(&self, key_id: u64) -> Option<&StoredKey> {
+        self.file.keys.iter().find(|k| k.key_id == key_id)
+    }
+
+    fn create_key(&mut self, scope: KeyScope) -> Result<u64> {
+        let master = self.master.as_ref().ok_or_else(|| {
+            StoreError::InvalidInput("encryption requires the master key (CXDB_MASTER_KEY)".into())
+        })?;
+        let rng = SystemRandom::new();
+        let mut dek = [0u8; 32];
+        rng.fill(&mut dek)
+            .map_err(|_| StoreError::Corrupt("random key generation failed".into()))?;
+
+        self.file.next_key_id = self.file.next_key_id.max(1);
+        let key_id = self.file.next_key_id;
+        let nonce = random_nonce()?;
+        let mut wrapped = dek.to_vec();
+        master
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(key_id.to_le_bytes()),
+                &mut wrapped,
+            )
+            .map_err(|_| StoreError::Corrupt("key wrapping failed".into()))?;
+        let mut encoded = nonce.to_vec();
+        encoded.extend_from_slice(&wrapped);
+
+        self.file.next_key_id += 1;
+        self.file.keys.push(StoredKey {
+            key_id,
+            scope,
+            wrapped_key: Some(base64::engine::general_purpose::STANDARD.encode(encoded)),
+            created_at_unix_ms: unix_ms(),
+            shredded_at_unix_ms: None,
+        });
+        let stored = self.file.keys.last().expect("key was just pushed");
+        self.append_key_log(&KeyLogEntry::Key(stored.clone()))?;
+        self.cache.insert(key_id, dek);
+        Ok(key_id)
+    }
+
+    fn info(&self, stored: &StoredKey) -> KeyInfo {
+        let (scope, context_id, tag) = match &stored.scope {
+            KeyScope::Context { context_id } => ("context", Some(context_id.to_string()), None),
+            KeyScope::Tag { tag } => ("tag", None, Some(tag.clone())),
+        };
+        KeyInfo {
+            id: stored.key_id,
+            key_id: stored.key_id.to_string(),
+            scope,
+            context_id,
+            tag,
+            context_count: self
+                .file
+                .contexts
+                .values()
+                .filter(|&&k| k == stored.key_id)
+                .count(),
+            created_at_unix_ms: stored.created_at_unix_ms,
+            shredded: stored.wrapped_key.is_none(),
+            shredded_at_unix_ms: stored.shredded_at_unix_ms,
+        }
+    }
+
+    fn append_key_log(&mut self, entry: &KeyLogEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.key_log.write_all(&line)?;
+        self.key_log.sync_data()?;
+        Ok(())
+    }
+
+    /// Rewrite `keys.json` with every change so far and empty `keys.log`.
+    /// Shredding goes through here, which also drops the wrapped key from
+    /// the log.
+    fn persist(&self) -> Result<()> {
+        let bytes = serde_json::to_vec_pretty(&self.file)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        let tmp = self.path.with_extension("json.tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        sync_dir(self.path.parent().expect("keys.json has a parent"))?;
+        self.key_log.set_len(0)?;
+        self.key_log.sync_all()?;
+        Ok(())
+    }
+}
+
+/// Open `keys.log` and apply its records to `file`. Replaying is idempotent,
+/// since a crash between rewriting `keys.json` and truncating the log leaves
+/// records that are already folded in; a torn last line is dropped.
+fn replay_key_log(path: &Path, mut file: KeyFile) -> Result<(File, KeyFile)> {
+    let mut log = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)?;
+    let mut buf = Vec::new();
+    log.read_to_end(&mut buf)?;
+    let mut valid_len = 0;
+    for line in buf.split_inclusive(|&b| b == b'\n') {
+        if !line.ends_with(b"\n") {
+            break;
+        }
+        let entry: KeyLogEntry = serde_json::from_slice(line)
+            .map_err(|e| StoreError::Corrupt(format!("keys.log: {e}")))?;
+        match entry {
+            KeyLogEntry::Key(stored) => {
+                if !file.keys.iter().any(|k| k.key_id == stored.key_id) {
+                    file.next_key_id = file.next_key_id.max(stored.key_id + 1);
+                    file.keys.push(stored);
+                }
+            }
+            KeyLogEntry::Bind { context_id, key_id } => {
+                file.contexts.insert(context_id, key_id);
+            }
+        }
+        valid_len += line.len();
+    }
+    if valid_len < buf.len() {
+        log.set_len(valid_len as u64)?;
+    }
+    Ok((log, file))
+}
+
+fn aead_key(bytes: &[u8; 32]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, bytes).expect("32-byte AES-256 key"))
+}
+
+fn random_nonce() -> Result<[u8; NONCE_LEN]> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| StoreError::Corrupt("random nonce generation failed".into()))?;
+    Ok(nonce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(mode: EncryptionMode) -> EncryptionConfig {
+        EncryptionConfig {
+            mode,
+            master_key: [7u8; 32],
+        }
+    }
+
+    #[test]
+    fn test_seal_roundtrip_and_tamper() {
+        let key = DataKey {
+            key_id: 1,
+            bytes: [3u8; 32],
+        };
+        let plaintext = b"hello hello hello hello hello hello".to_vec();
+        let hash = *blake3::hash(&plaintext).as_bytes();
+        let sealed = key.seal(&hash, &plaintext).unwrap();
+        assert!(!sealed.windows(5).any(|w| w == b"hello"));
+        assert_eq!(key.open(&hash, &sealed).unwrap(), plaintext);
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(key.open(&hash, &tampered).is_err());
+        assert_ne!(key.storage_hash(&hash), hash);
+    }
+
+    #[test]
+    fn test_tag_keys_are_shared_and_shredding_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut ring = KeyRing::open(dir.path()).unwrap();
+        ring.unlock(&config(EncryptionMode::Tag)).unwrap();
+
+        let a = ring.key_for_write(1, None, Some("acme")).unwrap().unwrap();
+        let b = ring.key_for_write(2, None, Some("acme")).unwrap().unwrap();
+        let c = ring.key_for_write(3, None, None).unwrap().unwrap();
+        assert_eq!(a.key_id, b.key_id);
+        assert_ne!(a.key_id, c.key_id);
+        ring.record_turn(10, a.key_id).unwrap();
+
+        assert!(ring.shred_context(1).is_err());
+        let info = ring.shred_tag("acme").unwrap();
+        assert!(info.shredded);
+        assert_eq!(info.context_count, 2);
+
+        let mut reopened = KeyRing::open(dir.path()).unwrap();
+        reopened.unlock(&config(EncryptionMode::Tag)).unwrap();
+        assert_eq!(reopened.turn_key(10), Some(a.key_id));
+        assert!(matches!(
+            reopened.data_key(a.key_id),
+            Err(StoreError::Shredded(_))
+        ));
+        assert_eq!(reopened.data_key(c.key_id).unwrap().bytes, c.bytes);
+
+        let mut wrong = KeyRing::open(dir.path()).unwrap();
+        let bad = EncryptionConfig {
+            mode: EncryptionMode::Tag,
+            master_key: [8u8; 32],
+        };
+        assert!(wrong.unlock(&bad).is_err());
+    }
+
+    #[test]
+    fn test_bindings_survive_without_rewriting_keys_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut ring = KeyRing::open(dir.path()).unwrap();
+        ring.unlock(&config(EncryptionMode::Context)).unwrap();
+        let snapshot = fs::read(dir.path().join("keys.json")).unwrap();
+
+        let a = ring.key_for_write(1, None, None).unwrap().unwrap();
+        let b = ring
+            .key_for_write(2, Some(a.key_id), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(a.key_id, b.key_id);
+        assert_eq!(fs::read(dir.path().join("keys.json")).unwrap(), snapshot);
+        ring.record_turn(10, a.key_id).unwrap();
+        ring.record_turn(11, a.key_id).unwrap();
+        ring.forget_turns_after(10).unwrap();
+        assert_eq!(ring.turn_key(11), None);
+
+        // A torn last record is dropped on open.
+        let mut log = OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("keys.log"))
+            .unwrap();
+        log.write_all(b"{\"bind\":{\"cont").unwrap();
+
+        let mut reopened = KeyRing::open(dir.path()).unwrap();
+        reopened.unlock(&config(EncryptionMode::Context)).unwrap();
+        assert_eq!(reopened.context_key(1), Some(a.key_id));
+        assert_eq!(reopened.context_key(2), Some(a.key_id));
+        assert_eq!(reopened.turn_key(10), Some(a.key_id));
+        assert_eq!(reopened.turn_key(11), None);
+        assert_eq!(reopened.data_key(a.key_id).unwrap().bytes, a.bytes);
+
+        // Shredding folds the log into keys.json, so the wrapped key is gone
+        // from both.
+        reopened.shred_context(1).unwrap();
+        assert_eq!(fs::metadata(dir.path().join("keys.log")).unwrap().len(), 0);
+        let mut again = KeyRing::open(dir.path()).unwrap();
+        again.unlock(&config(EncryptionMode::Context)).unwrap();
+        assert!(matches!(
+            again.data_key(a.key_id),
+            Err(StoreError::Shredded(_))
+        ));
+        let c = again.key_for_write(3, None, None).unwrap().unwrap();
+        assert_ne!(c.key_id, a.key_id);
+    }
+}
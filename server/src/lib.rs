@@ -3,17 +3,73 @@
 
 //! Library crate for the AI Context Store service.
 
+pub mod access;
+pub mod access_log;
+pub mod anchoring;
+pub mod archive;
+pub mod attachments;
+pub mod backfill;
+pub mod bench;
 pub mod blob_store;
+pub mod bookmarks;
 pub mod config;
+pub mod cpu_profile;
 pub mod cql;
+pub mod deadline;
+pub mod depth_limits;
+pub mod diff;
 pub mod error;
 pub mod events;
+pub mod expiry;
+pub mod export;
+pub mod external_ids;
+pub mod federation;
+pub mod format;
 pub mod fs_store;
+pub mod holds;
+pub mod hooks;
 pub mod http;
+pub mod index_plugins;
+pub mod ingest;
+pub mod invariants;
+pub mod jsonl_log;
+pub mod keys;
+pub mod memory;
+pub mod metadata_cache;
+pub mod metadata_overrides;
 pub mod metrics;
+pub mod oidc;
+pub mod operations;
+pub mod ownership;
+pub mod payload_cache;
+pub mod pii;
+pub mod presence;
+pub mod previews;
 pub mod projection;
+pub mod projects;
 pub mod protocol;
+pub mod quotas;
+pub mod read_marks;
 pub mod registry;
+pub mod renderer_assets;
+pub mod replay;
+pub mod residency;
+pub mod rollovers;
 pub mod s3_sync;
+pub mod shares;
+pub mod sinks;
+pub mod startup;
 pub mod store;
+pub mod subscriptions;
+pub mod system_events;
+pub mod tag_changes;
+pub mod thumbnails;
+pub mod title;
+pub mod tls;
+pub mod tokens;
+pub mod turn_search;
 pub mod turn_store;
+pub mod unix_socket;
+pub mod util;
+pub mod watches;
+pub mod webhooks;
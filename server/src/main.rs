@@ -1,40 +1,123 @@
 // Copyright 2025 StrongDM Inc
 // SPDX-License-Identifier: Apache-2.0
 
-use std::io::Write;
-use std::net::{TcpListener, TcpStream};
-use std::sync::atomic::{AtomicBool, Ordering};
+mod cli;
+
+use std::future::Future;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use std::thread;
 use std::time::Duration;
 
-use byteorder::WriteBytesExt;
+use clap::Parser;
+use cxdb_server::access::{Access, AccessPolicy, SessionAuth};
+use cxdb_server::access_log::{AccessLog, AccessLogConfig};
+use cxdb_server::anchoring::{start_anchoring, AnchorConfig, Anchors};
 use cxdb_server::config::Config;
+use cxdb_server::cpu_profile::CpuProfiler;
+use cxdb_server::depth_limits::DepthLimits;
 use cxdb_server::error::{Result, StoreError};
-use cxdb_server::events::{EventBus, StoreEvent};
-use cxdb_server::http::start_http;
-use cxdb_server::metrics::Metrics;
-use cxdb_server::metrics::SessionTracker;
+use cxdb_server::events::{EventBus, EventBusConfig, StoreEvent};
+use cxdb_server::expiry::{start_expiry_sweeper, validate_ttl, ExpiryConfig};
+use cxdb_server::federation::{Federation, FederationConfig};
+use cxdb_server::format::DataDirLock;
+use cxdb_server::hooks::{start_summary_hooks, SummaryHookConfig};
+use cxdb_server::http::{start_http, HttpConfig, HttpState};
+use cxdb_server::index_plugins::{IndexPlugins, IndexPluginsConfig};
+use cxdb_server::ingest::{IngestConfig, IngestPipeline};
+use cxdb_server::keys::EncryptionConfig;
+use cxdb_server::memory::{CountingAllocator, MemoryConfig};
+use cxdb_server::metadata_cache::MetadataCacheConfig;
+use cxdb_server::metadata_overrides::MetadataPatch;
+use cxdb_server::metrics::{
+    start_session_sweeper, status_changed_event, LivenessConfig, ProgressStatus,
+    SessionResumeConfig, SessionTracker,
+};
+use cxdb_server::metrics::{MessageSample, Metrics, SessionGuard};
+use cxdb_server::oidc::{OidcConfig, OidcVerifier};
+use cxdb_server::operations::{Operations, OperationsConfig};
+use cxdb_server::payload_cache::{start_prefetcher, PayloadCacheConfig};
+use cxdb_server::pii::PiiConfig;
+use cxdb_server::presence::{start_presence_sweeper, Presence, PresenceConfig};
+use cxdb_server::previews::PreviewConfig;
+use cxdb_server::projection::cache::ProjectionCacheConfig;
 use cxdb_server::protocol::{
-    encode_append_ack, encode_attach_fs_resp, encode_ctx_create_resp, encode_error,
-    encode_hello_resp, encode_put_blob_resp, parse_append_turn, parse_attach_fs, parse_ctx_create,
-    parse_ctx_fork, parse_get_blob, parse_get_head, parse_get_last, parse_hello, parse_put_blob,
-    read_frame, write_frame, MsgType,
+    attach_fs_meta, encode_append_ack, encode_attach_fs_overlay_resp, encode_attach_fs_resp,
+    encode_ctx_create_resp, encode_error, encode_hello_resp, encode_put_blob_resp,
+    encode_update_session_status_resp, overlay_changes, parse_append_turn, parse_attach_fs,
+    parse_attach_fs_overlay, parse_ctx_create, parse_ctx_fork, parse_get_blob, parse_get_head,
+    parse_get_last, parse_get_range_by_depth, parse_hello, parse_put_blob,
+    parse_update_session_status, read_frame_async, request_summary, write_frame_async, ErrorCode,
+    FrameHeader, GetBlobResponse, GetLastResponse, MsgType, TurnItem, WireStruct,
 };
-use cxdb_server::registry::Registry;
+use cxdb_server::quotas::QuotaPolicy;
+use cxdb_server::registry::builtin::{builtin_dir_from_env, ingest_builtin_bundles};
+use cxdb_server::registry::sync::{RegistrySync, RegistrySyncConfig};
+use cxdb_server::registry::{BuiltinOutcome, Registry};
+use cxdb_server::renderer_assets::{RendererAssetConfig, RendererAssets};
+use cxdb_server::replay::Replays;
+use cxdb_server::residency::{resident_contexts, ResidencyConfig};
 use cxdb_server::s3_sync::{S3Sync, S3SyncConfig, S3SyncHandle};
-use cxdb_server::store::Store;
+use cxdb_server::shares::{ShareConfig, Shares};
+use cxdb_server::sinks::{self, Outbox, SinkConfig};
+use cxdb_server::startup::{warm_up, Readiness};
+use cxdb_server::store::{Store, TurnWithMeta};
+use cxdb_server::subscriptions::{
+    start_subscription_dispatcher, SubscriptionConfig, Subscriptions,
+};
+use cxdb_server::system_events::{SystemEventConfig, SystemEvents};
+use cxdb_server::thumbnails::{ThumbnailConfig, Thumbnailer};
+use cxdb_server::title::{start_title_worker, TitleConfig};
+use cxdb_server::tls::{TlsAcceptor, TlsConfig};
+use cxdb_server::tokens::{TokenCounter, TokenizerConfig};
+use cxdb_server::turn_store::{CommitConfig, IdGeneratorConfig, TurnAuthor};
+use cxdb_server::unix_socket::PeerCredentials;
+use cxdb_server::util::unix_ms;
+use cxdb_server::watches::{start_watcher, WatchConfig, Watches};
+use cxdb_server::webhooks::{start_webhook_dispatcher, WebhookConfig, Webhooks};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{Notify, Semaphore};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator::new();
+
+/// How long shutdown waits for requests already running on the blocking pool.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
 
 fn main() -> Result<()> {
-    // Create tokio runtime for async S3 operations
-    let rt =
-        tokio::runtime::Runtime::new().map_err(|e| StoreError::Io(std::io::Error::other(e)))?;
+    let cli = cli::Cli::parse();
+    let mut config = Config::from_env();
+    if let Some(data_dir) = cli.data_dir {
+        config.data_dir = data_dir;
+    }
+    if let Some(command) = cli.command {
+        if !cli::run(&config.data_dir, command)? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // One runtime serves HTTP, the binary protocol and S3 sync. Store access
+    // stays synchronous on its blocking pool, which is sized for a request
+    // in flight on every binary connection plus the HTTP handlers.
+    let http_config = HttpConfig::from_env();
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .max_blocking_threads(config.max_connections + http_config.max_concurrency + 16)
+        .build()
+        .map_err(|e| StoreError::Io(std::io::Error::other(e)))?;
 
-    let config = Config::from_env();
-    std::fs::create_dir_all(&config.data_dir)?;
+    // Held from before an S3 restore until the store closes at exit.
+    let dir_lock = DataDirLock::acquire(&config.data_dir)?;
 
     // S3 sync: restore from S3 if local data is empty
-    let s3_sync_handle: Option<S3SyncHandle> = if let Some(s3_config) = S3SyncConfig::from_env() {
+    let s3_sync = if let Some(s3_config) = S3SyncConfig::from_env() {
+        let residency = ResidencyConfig::from_env(&s3_config.region)?;
+        residency.validate(&s3_config.destination())?;
+        if residency.is_configured() {
+            eprintln!("s3 residency: {}", residency.describe());
+        }
+
         // Run restore synchronously before opening stores
         let restored = rt.block_on(async {
             let s3_sync = S3Sync::new(s3_config.clone(), config.data_dir.clone()).await;
@@ -55,88 +138,458 @@ fn main() -> Result<()> {
             eprintln!("Data restored from S3, continuing startup");
         }
 
-        // Start background sync task
-        let handle = rt.block_on(async {
-            let s3_sync = S3Sync::new(s3_config, config.data_dir.clone()).await;
-            s3_sync.start_background_sync()
-        });
-
-        Some(handle)
+        Some((s3_config, residency))
     } else {
         eprintln!("S3 sync disabled (set CXDB_S3_SYNC_ENABLED=1 to enable)");
         None
     };
 
-    let store = Arc::new(Mutex::new(Store::open(&config.data_dir)?));
+    // Indexes are built after the HTTP gateway starts, so /readyz can report
+    // warm-up progress.
+    let readiness = Arc::new(Readiness::new());
+    let store = Arc::new(Mutex::new(Store::open_unindexed_locked(
+        &config.data_dir,
+        MetadataCacheConfig::from_env(),
+        dir_lock,
+    )?));
+    let ids = IdGeneratorConfig::from_env()?.build()?;
+    eprintln!("id generator: {}", ids.describe());
+    store.lock().unwrap().set_id_generator(ids);
     let registry = Arc::new(Mutex::new(Registry::open(
         &config.data_dir.join("registry"),
     )?));
-    let metrics = Arc::new(Metrics::new(config.data_dir.clone()));
-    let session_tracker = Arc::new(SessionTracker::new());
-    let event_bus = Arc::new(EventBus::new());
+    let builtin_dir = builtin_dir_from_env();
+    for (bundle_id, outcome) in
+        ingest_builtin_bundles(&mut registry.lock().unwrap(), builtin_dir.as_deref())?
+    {
+        match outcome {
+            BuiltinOutcome::Unchanged => {}
+            BuiltinOutcome::Created => eprintln!("builtin bundle {bundle_id}: registered"),
+            BuiltinOutcome::Merged(added) => {
+                eprintln!(
+                    "builtin bundle {bundle_id}: merged {} new versions",
+                    added.len()
+                )
+            }
+            BuiltinOutcome::Outdated { stored_version } => eprintln!(
+                "builtin bundle {bundle_id}: kept stored registry_version {stored_version}"
+            ),
+        }
+    }
+    store
+        .lock()
+        .unwrap()
+        .enable_previews(PreviewConfig::from_env(), Arc::clone(&registry));
+    let title_config = TitleConfig::from_env();
+    let titles_enabled = title_config.is_some();
+    if let Some(title_config) = title_config {
+        store
+            .lock()
+            .unwrap()
+            .enable_title_derivation(title_config, Arc::clone(&registry));
+    }
+    let ingest = match IngestConfig::from_env()? {
+        Some(ingest_config) => {
+            eprintln!("ingest transformers: {}", ingest_config.describe());
+            Some(Arc::new(IngestPipeline::new(ingest_config)))
+        }
+        None => None,
+    };
+    if let Some(limits) = DepthLimits::from_env()? {
+        eprintln!("depth limits: {}", limits.describe());
+        store.lock().unwrap().enable_depth_limits(limits);
+    }
+    if let Some(pii_config) = PiiConfig::from_env()? {
+        eprintln!("pii detection: {}", pii_config.describe());
+        store.lock().unwrap().enable_pii_scanning(pii_config);
+    }
+    if let Some(plugins_config) = IndexPluginsConfig::from_env()? {
+        let plugins = IndexPlugins::load(&plugins_config, Arc::clone(&registry))?;
+        eprintln!("index plugins: {}", plugins_config.describe());
+        store.lock().unwrap().enable_index_plugins(plugins);
+    }
+    let tokenizer = TokenizerConfig::from_env().load()?;
+    eprintln!("token counting: {} tokenizer", tokenizer.name());
+    store
+        .lock()
+        .unwrap()
+        .enable_token_counting(TokenCounter::new(tokenizer, Arc::clone(&registry)));
+    if let Some(encryption) = EncryptionConfig::from_env()? {
+        store.lock().unwrap().enable_encryption(&encryption)?;
+        eprintln!("encryption at rest: {:?}", encryption.mode);
+    }
+    let group_commit = CommitConfig::from_env();
+    store.lock().unwrap().enable_group_commit(group_commit)?;
+    if group_commit.enabled {
+        eprintln!("group commit: {:?} window", group_commit.window);
+    }
 
-    let _http = start_http(
-        config.http_bind_addr.clone(),
+    // The background sync starts once the store can be read, after refusing
+    // to run with contexts whose residency it has no destination for.
+    let s3_sync_handle: Option<S3SyncHandle> = match s3_sync {
+        Some((s3_config, residency)) => {
+            residency
+                .plan(resident_contexts(&mut store.lock().unwrap()))
+                .refuse_excluded()?;
+            Some(rt.block_on(async {
+                S3Sync::new(s3_config, config.data_dir.clone())
+                    .await
+                    .with_residency(residency, Arc::clone(&store))
+                    .await
+                    .start_background_sync()
+            }))
+        }
+        None => None,
+    };
+    let payload_cache = PayloadCacheConfig::from_env();
+    store.lock().unwrap().enable_payload_cache(payload_cache);
+    let _prefetcher = start_prefetcher(Arc::clone(&store));
+    if payload_cache.budget_bytes > 0 {
+        eprintln!("payload cache: {} bytes", payload_cache.budget_bytes);
+    }
+    let projection_cache = ProjectionCacheConfig::from_env();
+    registry
+        .lock()
+        .unwrap()
+        .enable_projection_cache(projection_cache);
+    if projection_cache.budget_bytes > 0 {
+        eprintln!("projection cache: {} bytes", projection_cache.budget_bytes);
+    }
+    let webhooks = Arc::new(Webhooks::new(WebhookConfig::from_env()));
+    let _webhook_dispatcher = start_webhook_dispatcher(Arc::clone(&webhooks));
+    let mut metrics = Metrics::new(config.data_dir.clone());
+    if let Some(policy) = QuotaPolicy::from_env()? {
+        eprintln!(
+            "quotas: {} tagged limits over {}s, warning at {:.0}%",
+            policy.tags.len(),
+            policy.window_secs,
+            policy.warn_at * 100.0
+        );
+        metrics = metrics.with_quotas(policy, Arc::clone(&webhooks));
+    }
+    let metrics = Arc::new(metrics);
+    let session_resume = SessionResumeConfig::from_env();
+    let liveness = LivenessConfig::from_env()?;
+    eprintln!(
+        "context liveness: {} ({}s window)",
+        liveness.mode.as_str(),
+        liveness.window.as_secs()
+    );
+    let session_tracker =
+        Arc::new(SessionTracker::with_resume(session_resume).with_liveness(liveness));
+    let event_bus = Arc::new(EventBus::with_config(EventBusConfig::from_env()));
+    if let Some(system_config) = SystemEventConfig::from_env()? {
+        eprintln!("system events: {}", system_config.describe());
+        store
+            .lock()
+            .unwrap()
+            .enable_system_events(SystemEvents::new(system_config, Arc::clone(&event_bus)));
+    }
+    let _title_worker =
+        titles_enabled.then(|| start_title_worker(Arc::clone(&store), Arc::clone(&event_bus)));
+    let operations = Operations::start(OperationsConfig::from_env(), Arc::clone(&event_bus));
+    let _sink = match SinkConfig::from_env()? {
+        Some(sink_config) => {
+            let outbox = Arc::new(Outbox::open(
+                &config.data_dir.join("meta"),
+                sink_config.kind.as_str(),
+                sink_config.events.clone(),
+            )?);
+            let publisher = sinks::connect(&sink_config)?;
+            eprintln!(
+                "event sink: {} at {} ({} pending)",
+                sink_config.kind.as_str(),
+                sink_config.servers,
+                outbox.stats().pending
+            );
+            event_bus.attach_outbox(Arc::clone(&outbox));
+            Some(sinks::start_sink(sink_config, outbox, publisher))
+        }
+        None => None,
+    };
+    let watches = Arc::new(Watches::open(&config.data_dir.join("meta"))?);
+    let subscriptions = Arc::new(Subscriptions::open(&config.data_dir.join("meta"))?);
+    let _subscription_dispatcher = start_subscription_dispatcher(
+        SubscriptionConfig::from_env(),
+        Arc::clone(&subscriptions),
+        Arc::clone(&webhooks),
+        Arc::clone(&event_bus),
+    );
+    let presence = Arc::new(Presence::new(
+        PresenceConfig::from_env(),
+        Arc::clone(&event_bus),
+    ));
+    let _presence_sweeper = start_presence_sweeper(Arc::clone(&presence));
+    let _session_sweeper =
+        start_session_sweeper(Arc::clone(&session_tracker), Arc::clone(&event_bus));
+    let _expiry_sweeper = start_expiry_sweeper(
         Arc::clone(&store),
-        Arc::clone(&registry),
-        Arc::clone(&metrics),
-        Arc::clone(&session_tracker),
         Arc::clone(&event_bus),
+        ExpiryConfig::from_env(),
+    );
+    if !session_resume.grace.is_zero() {
+        eprintln!(
+            "session resumption: {}s grace window",
+            session_resume.grace.as_secs()
+        );
+    }
+    let anchors = match AnchorConfig::from_env()? {
+        Some(anchor_config) => {
+            eprintln!(
+                "anchoring chain heads to {} every {}s",
+                anchor_config.target.describe(),
+                anchor_config.interval.as_secs()
+            );
+            Some(Arc::new(Anchors::open(
+                &config.data_dir.join("meta"),
+                anchor_config,
+            )?))
+        }
+        None => None,
+    };
+
+    let access_log = match AccessLogConfig::from_env()? {
+        Some(access_config) => {
+            eprintln!("http access log: {}", access_config.describe());
+            Some(Arc::new(AccessLog::open(access_config)?))
+        }
+        None => None,
+    };
+    let shares = Arc::new(Shares::open(
+        &config.data_dir.join("meta"),
+        ShareConfig::from_env()?,
+    )?);
+    let thumbnails = match ThumbnailConfig::from_env()? {
+        Some(thumbnail_config) => {
+            eprintln!("thumbnails: {} byte cache", thumbnail_config.cache_bytes);
+            Some(Arc::new(Thumbnailer::new(thumbnail_config)))
+        }
+        None => None,
+    };
+    let renderer_assets = match RendererAssetConfig::from_env()? {
+        Some(asset_config) => {
+            eprintln!(
+                "renderer asset proxy: {}",
+                asset_config.allowed_origins.join(",")
+            );
+            Some(Arc::new(RendererAssets::new(asset_config)))
+        }
+        None => None,
+    };
+
+    let registry_sync = match RegistrySyncConfig::from_env()? {
+        Some(sync_config) => {
+            eprintln!(
+                "registry sync remotes: {}",
+                sync_config.allowed_remotes.join(",")
+            );
+            Some(Arc::new(RegistrySync::new(sync_config)))
+        }
+        None => None,
+    };
+
+    let federation = match FederationConfig::from_env()? {
+        Some(federation_config) => {
+            let peers: Vec<&str> = federation_config
+                .peers
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect();
+            eprintln!(
+                "federation as {} with peers: {}",
+                federation_config.name,
+                peers.join(",")
+            );
+            Some(Arc::new(Federation::new(federation_config)))
+        }
+        None => None,
+    };
+
+    let oidc = match OidcConfig::from_env()? {
+        Some(oidc_config) => {
+            eprintln!(
+                "oidc bearer tokens from {}{}",
+                oidc_config.issuer,
+                if oidc_config.required {
+                    " (required)"
+                } else {
+                    ""
+                }
+            );
+            Some(Arc::new(OidcVerifier::new(oidc_config)))
+        }
+        None => None,
+    };
+
+    let summary_hook = SummaryHookConfig::from_env();
+    let http = start_http(
+        &config,
+        HttpState {
+            config: http_config,
+            store: Arc::clone(&store),
+            registry: Arc::clone(&registry),
+            metrics: Arc::clone(&metrics),
+            session_tracker: Arc::clone(&session_tracker),
+            event_bus: Arc::clone(&event_bus),
+            operations: Arc::clone(&operations),
+            watches: Arc::clone(&watches),
+            readiness: Arc::clone(&readiness),
+            anchors: anchors.clone(),
+            presence: Arc::clone(&presence),
+            access_log,
+            thumbnails,
+            renderer_assets,
+            shares,
+            subscriptions,
+            oidc: oidc.clone(),
+            memory: MemoryConfig::from_env(),
+            cpu_profiler: Arc::new(CpuProfiler::from_env()),
+            summary_hook: summary_hook.clone(),
+            replays: Arc::new(Replays::new()),
+            registry_sync,
+            federation,
+        },
+        rt.handle(),
     )?;
 
+    let summary_hooks = if let Some(hook_config) = summary_hook {
+        eprintln!("summary hook enabled: {}", hook_config.url);
+        Some(start_summary_hooks(
+            hook_config,
+            Arc::clone(&store),
+            Arc::clone(&registry),
+            Arc::clone(&event_bus),
+        )?)
+    } else {
+        None
+    };
+
     // Setup graceful shutdown on SIGTERM/SIGINT
-    let shutdown = Arc::new(AtomicBool::new(false));
+    let shutdown = Arc::new(Notify::new());
     let shutdown_clone = Arc::clone(&shutdown);
     ctrlc::set_handler(move || {
         eprintln!("\nReceived shutdown signal");
-        shutdown_clone.store(true, Ordering::SeqCst);
+        shutdown_clone.notify_one();
     })
     .expect("Error setting signal handler");
 
+    warm_up(&store, &readiness);
+    // Watches evaluate CQL queries, so they start once the indexes are built.
+    let _watcher = start_watcher(
+        WatchConfig::from_env(),
+        Arc::clone(&watches),
+        Arc::clone(&store),
+        Arc::clone(&session_tracker),
+        webhooks,
+        Arc::clone(&event_bus),
+    );
+
+    let _anchoring = anchors.map(|anchors| start_anchoring(anchors, Arc::clone(&store)));
+
+    let tls = match TlsConfig::from_env() {
+        Some(tls_config) => {
+            eprintln!(
+                "binary protocol TLS enabled{}",
+                if tls_config.client_ca_path.is_some() {
+                    " (client certificates)"
+                } else {
+                    ""
+                }
+            );
+            Some(TlsAcceptor::new(&tls_config)?)
+        }
+        None => None,
+    };
+    let access_policy = Arc::new(AccessPolicy::from_env()?);
+
     let listener = TcpListener::bind(&config.bind_addr)?;
-    listener
-        .set_nonblocking(true)
-        .expect("Cannot set non-blocking");
+    listener.set_nonblocking(true)?;
     eprintln!("cxdb listening on {}", config.bind_addr);
 
-    // Accept loop with shutdown check
-    while !shutdown.load(Ordering::Relaxed) {
-        match listener.accept() {
-            Ok((stream, peer_addr)) => {
-                // Set blocking mode for client handling (listener is non-blocking for shutdown checks)
-                if let Err(e) = stream.set_nonblocking(false) {
-                    eprintln!("failed to set blocking mode: {e}");
-                    continue;
+    let connections = Connections {
+        store: Arc::clone(&store),
+        metrics: Arc::clone(&metrics),
+        session_tracker: Arc::clone(&session_tracker),
+        event_bus: Arc::clone(&event_bus),
+        access_policy,
+        oidc,
+        ingest,
+        idle_timeout: config.idle_timeout,
+    };
+
+    // Connections are served on the runtime and run each request on the
+    // blocking pool. At the connection limit the accept loops wait for a
+    // slot; the TCP and Unix socket listeners share the limit.
+    let slots = Arc::new(Semaphore::new(config.max_connections));
+    let unix_listener = match &config.unix_socket_path {
+        Some(path) => Some(start_unix_listener(
+            &rt,
+            path,
+            config.unix_socket_mode,
+            Arc::clone(&slots),
+            connections.clone(),
+        )?),
+        None => None,
+    };
+    rt.block_on(async {
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+        loop {
+            let slot = tokio::select! {
+                _ = shutdown.notified() => break,
+                slot = Arc::clone(&slots).acquire_owned() => {
+                    slot.expect("connection slots are never closed")
                 }
-                let store = Arc::clone(&store);
-                let metrics = Arc::clone(&metrics);
-                let session_tracker = Arc::clone(&session_tracker);
-                let event_bus = Arc::clone(&event_bus);
-                let peer_addr_str = peer_addr.to_string();
-                thread::spawn(move || {
-                    if let Err(err) = handle_client(
-                        stream,
-                        store,
-                        metrics,
-                        session_tracker,
-                        event_bus,
-                        peer_addr_str,
-                    ) {
-                        eprintln!("connection error: {err}");
+            };
+            let (stream, peer_addr) = tokio::select! {
+                _ = shutdown.notified() => break,
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        eprintln!("accept error: {e}");
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
                     }
-                });
-            }
-            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                // No incoming connection, sleep briefly and check shutdown
-                thread::sleep(Duration::from_millis(100));
-            }
-            Err(e) => {
-                eprintln!("accept error: {e}");
-            }
+                },
+            };
+            let connections = connections.clone();
+            let peer_addr_str = peer_addr.to_string();
+            let tls = tls.clone();
+            tokio::spawn(async move {
+                let _slot = slot;
+                let result = match tls {
+                    Some(tls) => match tls.accept(stream).await {
+                        Ok((stream, identity)) => {
+                            let auth = SessionAuth::resolve(&connections.access_policy, identity);
+                            connections.serve(stream, peer_addr_str, auth, None).await
+                        }
+                        Err(err) => Err(err),
+                    },
+                    None => {
+                        let auth = SessionAuth::resolve(&connections.access_policy, None);
+                        connections.serve(stream, peer_addr_str, auth, None).await
+                    }
+                };
+                if let Err(err) = result {
+                    eprintln!("connection error: {err}");
+                }
+            });
         }
-    }
+        Ok::<(), StoreError>(())
+    })?;
 
     eprintln!("Shutting down...");
+    http.abort();
+    if let Some(path) = &config.http_unix_socket_path {
+        let _ = std::fs::remove_file(path);
+    }
+    if let Some((task, path)) = unix_listener {
+        task.abort();
+        let _ = std::fs::remove_file(path);
+    }
+
+    if let Some(hooks) = summary_hooks {
+        hooks.shutdown();
+    }
 
     // Graceful S3 sync shutdown (performs final sync)
     if let Some(handle) = s3_sync_handle {
@@ -145,277 +598,777 @@ fn main() -> Result<()> {
         });
     }
 
+    // Connections waiting for their next request are dropped; requests
+    // already running against the store get a moment to finish.
+    rt.shutdown_timeout(SHUTDOWN_GRACE);
     eprintln!("Shutdown complete");
     Ok(())
 }
 
-fn handle_client(
-    mut stream: TcpStream,
+/// Shared state handed to each binary protocol connection.
+#[derive(Clone)]
+struct Connections {
     store: Arc<Mutex<Store>>,
     metrics: Arc<Metrics>,
     session_tracker: Arc<SessionTracker>,
     event_bus: Arc<EventBus>,
-    peer_addr: String,
-) -> Result<()> {
-    let session = metrics.register_session();
-    let session_id = session.session_id();
-    // Client tag will be set when HELLO is received
-    let mut client_tag_received = false;
-    let mut client_tag = String::new();
-
-    loop {
-        let (header, payload) = match read_frame(&mut stream) {
-            Ok(v) => v,
-            Err(StoreError::Io(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
-            Err(e) => return Err(e),
+    access_policy: Arc<AccessPolicy>,
+    oidc: Option<Arc<OidcVerifier>>,
+    ingest: Option<Arc<IngestPipeline>>,
+    /// Connections that send no request for this long are closed.
+    idle_timeout: Option<Duration>,
+}
+
+impl Connections {
+    /// Serve one connection until the client closes it, goes idle or a
+    /// request fails. Frames are read and written on the runtime; each
+    /// request runs on the blocking pool only while the store handles it.
+    async fn serve<S: AsyncRead + AsyncWrite + Unpin>(
+        self,
+        mut stream: S,
+        peer_addr: String,
+        auth: SessionAuth,
+        peer_credentials: Option<PeerCredentials>,
+    ) -> Result<()> {
+        let idle_timeout = self.idle_timeout;
+        let mut session = ClientSession::new(self, peer_addr, auth, peer_credentials);
+        let result = loop {
+            let (header, payload) = match within(idle_timeout, read_frame_async(&mut stream)).await
+            {
+                // Idle clients reconnect, resuming their session if they
+                // asked for a resume token.
+                None => break Ok(()),
+                Some(Ok(frame)) => frame,
+                Some(Err(StoreError::Io(err)))
+                    if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    break Ok(())
+                }
+                Some(Err(e)) => break Err(e),
+            };
+            let handled = tokio::task::spawn_blocking(move || {
+                let response = session.handle_frame(&header, &payload);
+                (session, response)
+            })
+            .await;
+            let response;
+            (session, response) = handled.map_err(|e| StoreError::Io(std::io::Error::other(e)))?;
+            let (msg_type, payload) = match response {
+                Ok(frame) => frame,
+                Err(e) => break Err(e),
+            };
+            let write = write_frame_async(&mut stream, msg_type, 0, header.req_id, &payload);
+            match within(idle_timeout, write).await {
+                Some(Ok(())) => {}
+                Some(Err(e)) => break Err(e),
+                None => {
+                    break Err(StoreError::DeadlineExceeded(
+                        "client stopped reading responses".into(),
+                    ))
+                }
+            }
         };
+        session.close();
+        result
+    }
+}
+
+/// `future`'s output, or None when `timeout` passes first.
+async fn within<T>(timeout: Option<Duration>, future: impl Future<Output = T>) -> Option<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await.ok(),
+        None => Some(future.await),
+    }
+}
 
-        metrics.record_session_activity(session_id);
-        session_tracker.record_activity(session_id);
+/// Serve the binary protocol on a Unix domain socket until the returned task
+/// is aborted. Connections skip TLS; the peer's uid and gid are recorded
+/// with the session instead.
+#[cfg(unix)]
+fn start_unix_listener(
+    rt: &tokio::runtime::Runtime,
+    path: &Path,
+    mode: u32,
+    slots: Arc<Semaphore>,
+    connections: Connections,
+) -> Result<(tokio::task::JoinHandle<()>, PathBuf)> {
+    use cxdb_server::unix_socket;
+
+    let listener = unix_socket::bind(path, mode)?;
+    listener.set_nonblocking(true)?;
+    let listener = {
+        let _runtime = rt.enter();
+        tokio::net::UnixListener::from_std(listener)?
+    };
+    eprintln!("cxdb listening on unix:{} (mode {mode:o})", path.display());
+    let peer_addr = format!("unix:{}", path.display());
+    let task = rt.spawn(async move {
+        loop {
+            let slot = Arc::clone(&slots)
+                .acquire_owned()
+                .await
+                .expect("connection slots are never closed");
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    eprintln!("unix socket accept error: {e}");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let credentials = match unix_socket::peer_credentials(&stream) {
+                Ok(credentials) => credentials,
+                Err(e) => {
+                    eprintln!("failed to read peer credentials: {e}");
+                    continue;
+                }
+            };
+            let connections = connections.clone();
+            let peer_addr = peer_addr.clone();
+            tokio::spawn(async move {
+                let _slot = slot;
+                let auth = SessionAuth::resolve(&connections.access_policy, None);
+                let served = connections.serve(stream, peer_addr, auth, Some(credentials));
+                if let Err(err) = served.await {
+                    eprintln!("connection error: {err}");
+                }
+            });
+        }
+    });
+    Ok((task, path.to_path_buf()))
+}
+
+#[cfg(not(unix))]
+fn start_unix_listener(
+    _rt: &tokio::runtime::Runtime,
+    _path: &Path,
+    _mode: u32,
+    _slots: Arc<Semaphore>,
+    _connections: Connections,
+) -> Result<(tokio::task::JoinHandle<()>, PathBuf)> {
+    Err(StoreError::InvalidInput(
+        "CXDB_UNIX_SOCKET is only supported on Unix platforms".into(),
+    ))
+}
+
+/// A binary protocol session's state between requests.
+struct ClientSession {
+    store: Arc<Mutex<Store>>,
+    metrics: Arc<Metrics>,
+    session_tracker: Arc<SessionTracker>,
+    event_bus: Arc<EventBus>,
+    oidc: Option<Arc<OidcVerifier>>,
+    ingest: Option<Arc<IngestPipeline>>,
+    peer_addr: String,
+    auth: SessionAuth,
+    peer_credentials: Option<PeerCredentials>,
+    /// Counts the connection in the session metrics until it closes.
+    guard: SessionGuard,
+    /// The connection's own id until a HELLO resumes an earlier session.
+    session_id: u64,
+    /// Client tag will be set when HELLO is received
+    client_tag_received: bool,
+    client_tag: String,
+}
+
+impl ClientSession {
+    fn new(
+        connections: Connections,
+        peer_addr: String,
+        auth: SessionAuth,
+        peer_credentials: Option<PeerCredentials>,
+    ) -> Self {
+        let guard = connections.metrics.register_session();
+        Self {
+            store: connections.store,
+            metrics: connections.metrics,
+            session_tracker: connections.session_tracker,
+            event_bus: connections.event_bus,
+            oidc: connections.oidc,
+            ingest: connections.ingest,
+            peer_addr,
+            auth,
+            peer_credentials,
+            session_id: guard.session_id(),
+            guard,
+            client_tag_received: false,
+            client_tag: String::new(),
+        }
+    }
+
+    /// Handle one request frame against the store and return the response
+    /// frame's message type and payload. Requests that fail are answered
+    /// with an ERROR frame; an `Err` ends the connection.
+    fn handle_frame(&mut self, header: &FrameHeader, payload: &[u8]) -> Result<(u16, Vec<u8>)> {
+        let connection_id = self.guard.session_id();
+        let ClientSession {
+            store,
+            metrics,
+            session_tracker,
+            event_bus,
+            oidc,
+            ingest,
+            peer_addr,
+            auth,
+            peer_credentials,
+            session_id,
+            client_tag_received,
+            client_tag,
+            ..
+        } = self;
+        metrics.record_session_activity(connection_id);
+        session_tracker.record_activity(*session_id);
         let msg_type = header.msg_type;
         let req_id = header.req_id;
 
         let op_start = std::time::Instant::now();
-        let response = match msg_type {
-            x if x == MsgType::Hello as u16 => {
-                let hello = parse_hello(&payload)?;
-                // Register session with client tag and peer address
-                if !client_tag_received {
-                    client_tag = hello.client_tag.clone();
-                    session_tracker.register(
-                        session_id,
-                        hello.client_tag.clone(),
-                        Some(peer_addr.clone()),
-                    );
-                    client_tag_received = true;
-
-                    // Publish ClientConnected event
-                    event_bus.publish(StoreEvent::ClientConnected {
-                        session_id: session_id.to_string(),
-                        client_tag: hello.client_tag.clone(),
+        // Malformed payloads are answered with an ERROR frame; the frame
+        // boundary is intact, so the connection stays usable.
+        let response = 'dispatch: {
+            if let Err(err) = auth.check(required_access(msg_type), "send this request") {
+                break 'dispatch Err(err);
+            }
+            match msg_type {
+                x if x == MsgType::Hello as u16 => {
+                    let hello = match parse_hello(payload, header.flags) {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
+                    };
+                    if let Some(token) = &hello.auth_token {
+                        let verified = match &oidc {
+                            Some(oidc) => oidc.verify(token),
+                            None => Err(StoreError::Unauthenticated(
+                                "bearer tokens are not enabled (set CXDB_OIDC_ISSUER)".into(),
+                            )),
+                        };
+                        match verified {
+                            Ok(identity) => *auth = auth.clone().with_token(identity),
+                            Err(err) => break 'dispatch Err(err),
+                        }
+                    }
+                    // Register session with client tag and peer address
+                    if !*client_tag_received {
+                        let resumed = match (hello.resume_session_id, &hello.resume_token) {
+                            (Some(resume_id), Some(token)) if resume_id != 0 => {
+                                let resumed = session_tracker.resume(
+                                    resume_id,
+                                    token,
+                                    connection_id,
+                                    auth.principal(),
+                                    Some(peer_addr.clone()),
+                                );
+                                metrics.record_session_resume(resumed.is_some());
+                                resumed
+                            }
+                            _ => None,
+                        };
+                        match resumed {
+                            // The session's contexts stayed live; no new
+                            // ClientConnected event.
+                            Some(resumed) => {
+                                *session_id = resumed.session_id;
+                                *client_tag = resumed.client_tag;
+                            }
+                            None => {
+                                *client_tag = hello.client_tag.clone();
+                                session_tracker.register(
+                                    *session_id,
+                                    hello.client_tag.clone(),
+                                    Some(peer_addr.clone()),
+                                    auth.principal().map(str::to_string),
+                                );
+
+                                // Publish ClientConnected event
+                                event_bus.publish(StoreEvent::ClientConnected {
+                                    session_id: session_id.to_string(),
+                                    client_tag: hello.client_tag.clone(),
+                                });
+                            }
+                        }
+                        session_tracker.set_peer_credentials(*session_id, *peer_credentials);
+                        *client_tag_received = true;
+                    }
+                    // Clients asking for a token get an empty one when the
+                    // server does not resume sessions.
+                    let resume_token = hello.resume_session_id.map(|_| {
+                        session_tracker
+                            .issue_resume_token(*session_id)
+                            .unwrap_or_default()
                     });
+                    let resp = encode_hello_resp(*session_id, 1, resume_token)?; // protocol version 1
+                    Ok((MsgType::Hello as u16, resp))
                 }
-                let resp = encode_hello_resp(session_id, 1)?; // protocol version 1
-                Ok((MsgType::Hello as u16, resp))
-            }
-            x if x == MsgType::CtxCreate as u16 => {
-                // If no HELLO was sent, register with empty tag
-                if !client_tag_received {
-                    session_tracker.register(session_id, String::new(), Some(peer_addr.clone()));
-                    client_tag_received = true;
+                x if x == MsgType::CtxCreate as u16 => {
+                    // If no HELLO was sent, register with empty tag
+                    if !*client_tag_received {
+                        session_tracker.register(
+                            *session_id,
+                            String::new(),
+                            Some(peer_addr.clone()),
+                            auth.principal().map(str::to_string),
+                        );
+                        session_tracker.set_peer_credentials(*session_id, *peer_credentials);
+                        *client_tag_received = true;
+                    }
+                    let req = match parse_ctx_create(payload, header.flags) {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
+                    };
+                    let ttl = match req.ttl_secs.map(validate_ttl).transpose() {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
+                    };
+                    let mut store = store.lock().unwrap();
+                    let head = store.create_context_with_ttl(
+                        req.base_turn_id,
+                        req.external_id.as_deref(),
+                        ttl,
+                    )?;
+                    // Associate context with this session
+                    session_tracker.add_context(*session_id, head.context_id);
+                    for warning in metrics.record_tag_context(client_tag) {
+                        event_bus.publish(warning.to_event());
+                    }
+
+                    // Publish ContextCreated event
+                    event_bus.publish_recorded(StoreEvent::ContextCreated {
+                        context_id: head.context_id.to_string(),
+                        session_id: session_id.to_string(),
+                        client_tag: client_tag.clone(),
+                        created_at: unix_ms(),
+                        external_id: req.external_id,
+                    })?;
+
+                    let commit = store.commit_pipeline();
+                    drop(store);
+                    if let Some(commit) = commit {
+                        commit.commit()?;
+                    }
+
+                    let resp = encode_ctx_create_resp(
+                        head.context_id,
+                        head.head_turn_id,
+                        head.head_depth,
+                    )?;
+                    Ok((MsgType::CtxCreate as u16, resp))
                 }
-                let base_turn_id = parse_ctx_create(&payload)?;
-                let mut store = store.lock().unwrap();
-                let head = store.create_context(base_turn_id)?;
-                // Associate context with this session
-                session_tracker.add_context(session_id, head.context_id);
-
-                // Publish ContextCreated event
-                event_bus.publish(StoreEvent::ContextCreated {
-                    context_id: head.context_id.to_string(),
-                    session_id: session_id.to_string(),
-                    client_tag: client_tag.clone(),
-                    created_at: unix_ms(),
-                });
-
-                let resp =
-                    encode_ctx_create_resp(head.context_id, head.head_turn_id, head.head_depth)?;
-                Ok((MsgType::CtxCreate as u16, resp))
-            }
-            x if x == MsgType::CtxFork as u16 => {
-                // If no HELLO was sent, register with empty tag
-                if !client_tag_received {
-                    session_tracker.register(session_id, String::new(), Some(peer_addr.clone()));
-                    client_tag_received = true;
+                x if x == MsgType::CtxFork as u16 => {
+                    // If no HELLO was sent, register with empty tag
+                    if !*client_tag_received {
+                        session_tracker.register(
+                            *session_id,
+                            String::new(),
+                            Some(peer_addr.clone()),
+                            auth.principal().map(str::to_string),
+                        );
+                        session_tracker.set_peer_credentials(*session_id, *peer_credentials);
+                        *client_tag_received = true;
+                    }
+                    let base_turn_id = match parse_ctx_fork(payload) {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
+                    };
+                    let mut store = store.lock().unwrap();
+                    let head = store.fork_context(base_turn_id)?;
+                    // Associate forked context with this session
+                    session_tracker.add_context(*session_id, head.context_id);
+                    for warning in metrics.record_tag_context(client_tag) {
+                        event_bus.publish(warning.to_event());
+                    }
+
+                    // Publish ContextCreated event for forked context
+                    event_bus.publish_recorded(StoreEvent::ContextCreated {
+                        context_id: head.context_id.to_string(),
+                        session_id: session_id.to_string(),
+                        client_tag: client_tag.clone(),
+                        created_at: unix_ms(),
+                        external_id: None,
+                    })?;
+
+                    let commit = store.commit_pipeline();
+                    drop(store);
+                    if let Some(commit) = commit {
+                        commit.commit()?;
+                    }
+
+                    let resp = encode_ctx_create_resp(
+                        head.context_id,
+                        head.head_turn_id,
+                        head.head_depth,
+                    )?;
+                    Ok((MsgType::CtxFork as u16, resp))
                 }
-                let base_turn_id = parse_ctx_fork(&payload)?;
-                let mut store = store.lock().unwrap();
-                let head = store.fork_context(base_turn_id)?;
-                // Associate forked context with this session
-                session_tracker.add_context(session_id, head.context_id);
-
-                // Publish ContextCreated event for forked context
-                event_bus.publish(StoreEvent::ContextCreated {
-                    context_id: head.context_id.to_string(),
-                    session_id: session_id.to_string(),
-                    client_tag: client_tag.clone(),
-                    created_at: unix_ms(),
-                });
-
-                let resp =
-                    encode_ctx_create_resp(head.context_id, head.head_turn_id, head.head_depth)?;
-                Ok((MsgType::CtxFork as u16, resp))
-            }
-            x if x == MsgType::GetHead as u16 => {
-                let context_id = parse_get_head(&payload)?;
-                let store = store.lock().unwrap();
-                let head = store.get_head(context_id)?;
-                let resp =
-                    encode_ctx_create_resp(head.context_id, head.head_turn_id, head.head_depth)?;
-                Ok((MsgType::GetHead as u16, resp))
-            }
-            x if x == MsgType::AppendTurn as u16 => {
-                let req = parse_append_turn(&payload, header.flags)?;
-                let declared_type_id_clone = req.declared_type_id.clone();
-                let declared_type_version = req.declared_type_version;
-                let mut store = store.lock().unwrap();
-                let (record, metadata) = store.append_turn(
-                    req.context_id,
-                    req.parent_turn_id,
-                    req.declared_type_id,
-                    req.declared_type_version,
-                    req.encoding,
-                    req.compression,
-                    req.uncompressed_len,
-                    req.content_hash,
-                    &req.payload_bytes,
-                )?;
-                // If fs_root_hash was provided, attach it to this turn
-                if let Some(fs_root_hash) = req.fs_root_hash {
-                    store.attach_fs(record.turn_id, fs_root_hash)?;
+                x if x == MsgType::GetHead as u16 => {
+                    let context_id = match parse_get_head(payload) {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
+                    };
+                    let store = store.lock().unwrap();
+                    let head = store.get_head(context_id)?;
+                    let resp = encode_ctx_create_resp(
+                        head.context_id,
+                        head.head_turn_id,
+                        head.head_depth,
+                    )?;
+                    Ok((MsgType::GetHead as u16, resp))
                 }
-                metrics.record_append(op_start.elapsed());
-
-                // Publish TurnAppended event
-                event_bus.publish(StoreEvent::TurnAppended {
-                    context_id: req.context_id.to_string(),
-                    turn_id: record.turn_id.to_string(),
-                    parent_turn_id: record.parent_turn_id.to_string(),
-                    depth: record.depth,
-                    declared_type_id: Some(declared_type_id_clone),
-                    declared_type_version: Some(declared_type_version),
-                });
-
-                // If metadata was extracted (first turn), publish ContextMetadataUpdated
-                if let Some(meta) = metadata {
-                    event_bus.publish(StoreEvent::ContextMetadataUpdated {
+                x if x == MsgType::AppendTurn as u16 => {
+                    let mut req = match parse_append_turn(payload, header.flags) {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
+                    };
+                    // Transformers run before the store is locked.
+                    let ingest_audit = match ingest.as_ref().map(|ingest| ingest.apply(&mut req)) {
+                        Some(Ok(audit)) => audit,
+                        Some(Err(err)) => break 'dispatch Err(err),
+                        None => None,
+                    };
+                    let declared_type_id_clone = req.declared_type_id.clone();
+                    let declared_type_version = req.declared_type_version;
+                    let mut store = store.lock().unwrap();
+                    let author = TurnAuthor {
+                        session_id: *session_id,
+                        client_tag: client_tag.clone(),
+                        principal: auth.principal().map(str::to_string),
+                    };
+                    let (record, metadata) = store.append_turn_with_author(
+                        req.context_id,
+                        req.parent_turn_id,
+                        req.declared_type_id,
+                        req.declared_type_version,
+                        req.encoding,
+                        req.compression,
+                        req.uncompressed_len,
+                        req.content_hash,
+                        &req.payload_bytes,
+                        Some(author),
+                    )?;
+                    if let Some(audit) = ingest_audit {
+                        store.record_ingest(record.turn_id, audit)?;
+                    }
+                    // If fs_root_hash was provided, attach it to this turn
+                    if let Some(fs_root_hash) = req.fs_root_hash {
+                        store.attach_fs(record.turn_id, fs_root_hash)?;
+                    }
+                    metrics.record_append(op_start.elapsed());
+                    for warning in
+                        metrics.record_tag_append(client_tag, req.uncompressed_len as u64)
+                    {
+                        event_bus.publish(warning.to_event());
+                    }
+                    if let Some(warning) = store.depth_warning(req.context_id, record.depth) {
+                        event_bus.publish(warning.to_event());
+                    }
+                    session_tracker.record_context_activity(req.context_id);
+
+                    // Publish TurnAppended event
+                    event_bus.publish_recorded(StoreEvent::TurnAppended {
                         context_id: req.context_id.to_string(),
-                        client_tag: meta.client_tag,
-                        title: meta.title,
-                        labels: meta.labels,
-                        has_provenance: meta.provenance.is_some(),
-                    });
-                }
+                        turn_id: record.turn_id.to_string(),
+                        parent_turn_id: record.parent_turn_id.to_string(),
+                        depth: record.depth,
+                        declared_type_id: Some(declared_type_id_clone),
+                        declared_type_version: Some(declared_type_version),
+                    })?;
 
-                let resp = encode_append_ack(
-                    req.context_id,
-                    record.turn_id,
-                    record.depth,
-                    &record.payload_hash,
-                )?;
-                Ok((MsgType::AppendTurn as u16, resp))
-            }
-            x if x == MsgType::AttachFs as u16 => {
-                let req = parse_attach_fs(&payload)?;
-                let mut store = store.lock().unwrap();
-                store.attach_fs(req.turn_id, req.fs_root_hash)?;
-                let resp = encode_attach_fs_resp(req.turn_id, &req.fs_root_hash)?;
-                Ok((MsgType::AttachFs as u16, resp))
-            }
-            x if x == MsgType::PutBlob as u16 => {
-                let req = parse_put_blob(&payload)?;
-                let mut store = store.lock().unwrap();
-                // Verify hash matches
-                let actual_hash = blake3::hash(&req.data);
-                if actual_hash.as_bytes() != &req.hash {
-                    return Err(StoreError::InvalidInput("blob hash mismatch".into()));
+                    // Record the verified client certificate as the context's
+                    // writer, and the uid/gid of a Unix socket peer
+                    let metadata = if record.depth == 0
+                        && (auth.identity.is_some() || peer_credentials.is_some())
+                    {
+                        let mut patch = MetadataPatch::default();
+                        if let Some(identity) = &auth.identity {
+                            patch.merge(&identity.provenance_patch());
+                        }
+                        if let Some(credentials) = &peer_credentials {
+                            patch.merge(&credentials.provenance_patch());
+                        }
+                        Some(store.apply_metadata_patch(req.context_id, &patch, &[])?)
+                    } else {
+                        metadata
+                    };
+
+                    // If metadata was extracted (first turn), publish ContextMetadataUpdated
+                    if let Some(meta) = metadata {
+                        event_bus.publish_recorded(StoreEvent::ContextMetadataUpdated {
+                            context_id: req.context_id.to_string(),
+                            client_tag: meta.client_tag,
+                            title: meta.title,
+                            labels: meta.labels,
+                            has_provenance: meta.provenance.is_some(),
+                        })?;
+                    }
+
+                    // Acknowledge only once the append is durable.
+                    let commit = store.commit_pipeline();
+                    drop(store);
+                    if let Some(commit) = commit {
+                        commit.commit()?;
+                    }
+
+                    let resp = encode_append_ack(
+                        req.context_id,
+                        record.turn_id,
+                        record.depth,
+                        &record.payload_hash,
+                    )?;
+                    Ok((MsgType::AppendTurn as u16, resp))
                 }
-                let was_new = !store.blob_store.contains(&req.hash);
-                store.blob_store.put_if_absent(req.hash, &req.data)?;
-                let resp = encode_put_blob_resp(&req.hash, was_new)?;
-                Ok((MsgType::PutBlob as u16, resp))
-            }
-            x if x == MsgType::GetLast as u16 => {
-                let req = parse_get_last(&payload)?;
-                let mut store = store.lock().unwrap();
-                let items = store.get_last(req.context_id, req.limit, req.include_payload != 0)?;
-                metrics.record_get_last(op_start.elapsed());
-                let mut resp = Vec::new();
-                resp.write_u32::<byteorder::LittleEndian>(items.len() as u32)?;
-                for item in items {
-                    resp.write_u64::<byteorder::LittleEndian>(item.record.turn_id)?;
-                    resp.write_u64::<byteorder::LittleEndian>(item.record.parent_turn_id)?;
-                    resp.write_u32::<byteorder::LittleEndian>(item.record.depth)?;
-                    resp.write_u32::<byteorder::LittleEndian>(
-                        item.meta.declared_type_id.len() as u32
+                x if x == MsgType::AttachFs as u16 => {
+                    let req = match parse_attach_fs(payload, header.flags) {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
+                    };
+                    let mut store = store.lock().unwrap();
+                    store.attach_fs_with_meta(
+                        req.turn_id,
+                        req.fs_root_hash,
+                        attach_fs_meta(&req),
                     )?;
-                    resp.extend_from_slice(item.meta.declared_type_id.as_bytes());
-                    resp.write_u32::<byteorder::LittleEndian>(item.meta.declared_type_version)?;
-                    resp.write_u32::<byteorder::LittleEndian>(item.meta.encoding)?;
-                    // always return raw payload when included
-                    let compression = if item.payload.is_some() {
-                        0
+                    let resp = encode_attach_fs_resp(req.turn_id, &req.fs_root_hash)?;
+                    Ok((MsgType::AttachFs as u16, resp))
+                }
+                x if x == MsgType::AttachFsOverlay as u16 => {
+                    let req = match parse_attach_fs_overlay(payload) {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
+                    };
+                    let changes = match overlay_changes(&req.changes) {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
+                    };
+                    let base_root = (req.base_root_hash != [0u8; 32]).then_some(req.base_root_hash);
+                    let blobs: Vec<&[u8]> = req.blobs.iter().map(|b| b.data.as_slice()).collect();
+                    let mut store = store.lock().unwrap();
+                    let result =
+                        match store.attach_fs_overlay(req.turn_id, base_root, &blobs, &changes) {
+                            Ok(v) => v,
+                            Err(err) => break 'dispatch Err(err),
+                        };
+                    let resp = encode_attach_fs_overlay_resp(
+                        req.turn_id,
+                        &result.root_hash,
+                        result.trees_written as u32,
+                    )?;
+                    Ok((MsgType::AttachFsOverlay as u16, resp))
+                }
+                x if x == MsgType::PutBlob as u16 => {
+                    let req = match parse_put_blob(payload, header.flags) {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
+                    };
+                    let mut store = store.lock().unwrap();
+                    let tag = (!client_tag.is_empty()).then_some(client_tag.as_str());
+                    let was_new = match store.put_blob(req.hash, &req.data, req.context_id, tag) {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
+                    };
+                    let resp = encode_put_blob_resp(&req.hash, was_new)?;
+                    Ok((MsgType::PutBlob as u16, resp))
+                }
+                x if x == MsgType::UpdateSessionStatus as u16 => {
+                    let req = match parse_update_session_status(payload, header.flags) {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
+                    };
+                    let progress = req.progress_bp.map(|bp| f64::from(bp) / 10_000.0);
+                    let status = match ProgressStatus::new(req.status, progress, Some(*session_id))
+                    {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
+                    };
+                    if req.context_id == 0 {
+                        session_tracker.set_session_status(*session_id, status.clone());
                     } else {
-                        item.meta.compression
+                        if let Err(err) = store.lock().unwrap().get_head(req.context_id) {
+                            break 'dispatch Err(err);
+                        }
+                        session_tracker.set_context_status(req.context_id, status.clone());
+                    }
+                    event_bus.publish(status_changed_event(
+                        (req.context_id != 0).then_some(req.context_id),
+                        Some(*session_id),
+                        status.as_ref(),
+                    ));
+                    let updated_at = status.map_or(0, |s| s.updated_at);
+                    let resp = encode_update_session_status_resp(req.context_id, updated_at)?;
+                    Ok((MsgType::UpdateSessionStatus as u16, resp))
+                }
+                x if x == MsgType::GetLast as u16 => {
+                    let req = match parse_get_last(payload) {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
+                    };
+                    let mut store = store.lock().unwrap();
+                    let items =
+                        store.get_last(req.context_id, req.limit, req.include_payload != 0)?;
+                    metrics.record_get_last(op_start.elapsed());
+                    let read_bytes = items
+                        .iter()
+                        .filter_map(|item| item.payload.as_ref())
+                        .map(|p| p.len() as u64)
+                        .sum();
+                    metrics.record_tag_read(client_tag, read_bytes, op_start.elapsed());
+                    let turns = turn_items(items);
+                    let resp = GetLastResponse { turns }.encode()?;
+                    Ok((MsgType::GetLast as u16, resp))
+                }
+                x if x == MsgType::GetRangeByDepth as u16 => {
+                    let req = match parse_get_range_by_depth(payload) {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
                     };
-                    resp.write_u32::<byteorder::LittleEndian>(compression)?;
-                    let uncompressed_len = item
-                        .payload
-                        .as_ref()
-                        .map(|p| p.len() as u32)
-                        .unwrap_or(item.meta.uncompressed_len);
-                    resp.write_u32::<byteorder::LittleEndian>(uncompressed_len)?;
-                    resp.extend_from_slice(&item.record.payload_hash);
-                    if let Some(payload) = item.payload {
-                        resp.write_u32::<byteorder::LittleEndian>(payload.len() as u32)?;
-                        resp.extend_from_slice(&payload);
+                    let mut store = store.lock().unwrap();
+                    let items = store.get_range_by_depth(
+                        req.context_id,
+                        req.start_depth,
+                        req.limit,
+                        req.include_payload != 0,
+                    )?;
+                    metrics.record_get_last(op_start.elapsed());
+                    let read_bytes = items
+                        .iter()
+                        .filter_map(|item| item.payload.as_ref())
+                        .map(|p| p.len() as u64)
+                        .sum();
+                    metrics.record_tag_read(client_tag, read_bytes, op_start.elapsed());
+                    let resp = GetLastResponse {
+                        turns: turn_items(items),
                     }
+                    .encode()?;
+                    Ok((MsgType::GetRangeByDepth as u16, resp))
+                }
+                x if x == MsgType::GetBlob as u16 => {
+                    let hash = match parse_get_blob(payload) {
+                        Ok(v) => v,
+                        Err(err) => break 'dispatch Err(err),
+                    };
+                    let mut store = store.lock().unwrap();
+                    let bytes = store.get_blob(&hash)?;
+                    metrics.record_get_blob(op_start.elapsed());
+                    metrics.record_tag_read(client_tag, bytes.len() as u64, op_start.elapsed());
+                    let resp = GetBlobResponse { data: bytes }.encode()?;
+                    Ok((MsgType::GetBlob as u16, resp))
+                }
+                // Newer clients may send message types this server predates.
+                // The payload was consumed with the frame, so the stream stays
+                // in sync and the session continues.
+                _ => {
+                    metrics.record_unsupported_message(msg_type);
+                    session_tracker.record_unsupported_message(*session_id);
+                    Err(StoreError::UnsupportedMessage { msg_type })
                 }
-                Ok((MsgType::GetLast as u16, resp))
-            }
-            x if x == MsgType::GetBlob as u16 => {
-                let hash = parse_get_blob(&payload)?;
-                let mut store = store.lock().unwrap();
-                let bytes = store.get_blob(&hash)?;
-                metrics.record_get_blob(op_start.elapsed());
-                let mut resp = Vec::new();
-                resp.write_u32::<byteorder::LittleEndian>(bytes.len() as u32)?;
-                resp.extend_from_slice(&bytes);
-                Ok((MsgType::GetBlob as u16, resp))
             }
-            _ => Err(StoreError::InvalidInput("unknown msg_type".into())),
         };
 
-        match response {
-            Ok((resp_type, resp_payload)) => {
-                write_frame(&mut stream, resp_type, 0, req_id, &resp_payload)?;
-                stream.flush()?;
-            }
+        let (frame, error) = match response {
+            Ok(frame) => (frame, None),
             Err(err) => {
                 metrics.record_error("binary");
-                let (code, detail) = map_error(&err);
-                let payload = encode_error(code, &detail)?;
-                write_frame(&mut stream, MsgType::Error as u16, 0, req_id, &payload)?;
-                stream.flush()?;
+                let code = ErrorCode::of(&err);
+                ((MsgType::Error as u16, encode_error(&err)?), Some(code))
             }
+        };
+        let response_bytes = frame.1.len();
+
+        let duration = op_start.elapsed();
+        let slow = metrics
+            .slow_request_threshold()
+            .is_some_and(|threshold| duration >= threshold);
+        if slow {
+            eprintln!(
+                "slow request: req_id={req_id} session={session_id} client_tag={client_tag:?} msg_type={} duration_ms={} request_bytes={} response_bytes={response_bytes}{} {}",
+                MsgType::name(msg_type),
+                duration.as_millis(),
+                payload.len(),
+                error
+                    .map(|code| format!(" error={}/{}", code.status(), code.name()))
+                    .unwrap_or_default(),
+                request_summary(msg_type, header.flags, payload),
+            );
         }
+        metrics.record_message(
+            MsgType::name(msg_type),
+            &MessageSample {
+                request_bytes: payload.len() as u64,
+                response_bytes: response_bytes as u64,
+                duration,
+                error: error.is_some(),
+                slow,
+            },
+        );
+        Ok(frame)
     }
 
-    // Unregister session on disconnect and publish event
-    let orphaned_contexts = session_tracker.unregister(session_id);
-    event_bus.publish(StoreEvent::ClientDisconnected {
-        session_id: session_id.to_string(),
-        client_tag,
-        contexts: orphaned_contexts.iter().map(|id| id.to_string()).collect(),
-    });
-
-    Ok(())
+    /// Unregister the session on disconnect and publish the event. A
+    /// resumable session keeps its contexts live until its grace window ends.
+    fn close(self) {
+        let connection_id = self.guard.session_id();
+        if let Some(orphaned_contexts) = self
+            .session_tracker
+            .disconnect(self.session_id, connection_id)
+        {
+            self.event_bus.publish(StoreEvent::ClientDisconnected {
+                session_id: self.session_id.to_string(),
+                client_tag: self.client_tag,
+                contexts: orphaned_contexts.iter().map(|id| id.to_string()).collect(),
+            });
+        }
+    }
 }
 
-/// Get current time in milliseconds since Unix epoch.
-fn unix_ms() -> u64 {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_millis() as u64)
-        .unwrap_or(0)
+/// Access a session needs to send `msg_type`.
+fn required_access(msg_type: u16) -> Access {
+    match msg_type {
+        x if x == MsgType::CtxCreate as u16
+            || x == MsgType::CtxFork as u16
+            || x == MsgType::AppendTurn as u16
+            || x == MsgType::AttachFs as u16
+            || x == MsgType::AttachFsOverlay as u16
+            || x == MsgType::PutBlob as u16
+            || x == MsgType::UpdateSessionStatus as u16 =>
+        {
+            Access::Write
+        }
+        x if x == MsgType::GetHead as u16
+            || x == MsgType::GetLast as u16
+            || x == MsgType::GetRangeByDepth as u16
+            || x == MsgType::GetBlob as u16 =>
+        {
+            Access::Read
+        }
+        _ => Access::None,
+    }
 }
 
-fn map_error(err: &StoreError) -> (u32, String) {
-    match err {
-        StoreError::NotFound(msg) => (404, msg.clone()),
-        StoreError::InvalidInput(msg) => (422, msg.clone()),
-        StoreError::Corrupt(msg) => (500, msg.clone()),
-        StoreError::Io(msg) => (500, msg.to_string()),
-    }
+/// Wire form of fetched turns for GET_LAST and GET_RANGE_BY_DEPTH.
+fn turn_items(items: Vec<TurnWithMeta>) -> Vec<TurnItem> {
+    items
+        .into_iter()
+        .map(|item| {
+            // always return raw payload when included
+            let compression = if item.payload.is_some() {
+                0
+            } else {
+                item.meta.compression
+            };
+            let uncompressed_len = item
+                .payload
+                .as_ref()
+                .map(|p| p.len() as u32)
+                .unwrap_or(item.meta.uncompressed_len);
+            TurnItem {
+                turn_id: item.record.turn_id,
+                parent_turn_id: item.record.parent_turn_id,
+                depth: item.record.depth,
+                declared_type_id: item.meta.declared_type_id,
+                declared_type_version: item.meta.declared_type_version,
+                encoding: item.meta.encoding,
+                compression,
+                uncompressed_len,
+                content_hash: item.record.payload_hash,
+                payload: item.payload,
+            }
+        })
+        .collect()
 }
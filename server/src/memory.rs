@@ -0,0 +1,218 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Heap accounting and profiling.
+//!
+//! The server binary installs [`CountingAllocator`] as its global allocator.
+//! It forwards to the system allocator, or to jemalloc when built with the
+//! `jemalloc` feature, and counts live and peak heap bytes on the way. With
+//! jemalloc, allocator statistics are reported as well, and heap profiles can
+//! be dumped on demand into `CXDB_HEAP_PROFILE_DIR` when jemalloc profiling
+//! is active (`_RJEM_MALLOC_CONF=prof:true`). Profiles are read with `jeprof`.
+
+use std::alloc::{GlobalAlloc, Layout};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+use crate::error::{Result, StoreError};
+
+#[cfg(feature = "jemalloc")]
+type Inner = tikv_jemallocator::Jemalloc;
+#[cfg(not(feature = "jemalloc"))]
+type Inner = std::alloc::System;
+
+#[cfg(feature = "jemalloc")]
+const INNER: Inner = tikv_jemallocator::Jemalloc;
+#[cfg(not(feature = "jemalloc"))]
+const INNER: Inner = std::alloc::System;
+
+static ALLOCATED: AtomicU64 = AtomicU64::new(0);
+static PEAK: AtomicU64 = AtomicU64::new(0);
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static DEALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Global allocator that counts heap bytes.
+pub struct CountingAllocator;
+
+impl CountingAllocator {
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for CountingAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn allocated(bytes: usize) {
+    let now = ALLOCATED.fetch_add(bytes as u64, Ordering::Relaxed) + bytes as u64;
+    PEAK.fetch_max(now, Ordering::Relaxed);
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+fn freed(bytes: usize) {
+    ALLOCATED.fetch_sub(bytes as u64, Ordering::Relaxed);
+    DEALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { INNER.alloc(layout) };
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { INNER.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { INNER.dealloc(ptr, layout) };
+        freed(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { INNER.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            // A move counts as one allocation and one deallocation.
+            freed(layout.size());
+            allocated(new_size);
+        }
+        new_ptr
+    }
+}
+
+/// Heap accounting since startup.
+#[derive(Debug, Clone, Serialize)]
+pub struct HeapStats {
+    /// `system` or `jemalloc`.
+    pub allocator: &'static str,
+    /// Bytes requested by live allocations.
+    pub allocated_bytes: u64,
+    /// Highest `allocated_bytes` seen.
+    pub peak_bytes: u64,
+    pub allocations_total: u64,
+    pub deallocations_total: u64,
+    /// Allocator-level statistics, with the `jemalloc` feature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jemalloc: Option<JemallocStats>,
+}
+
+/// jemalloc's own view of the heap, in bytes.
+#[derive(Debug, Clone, Serialize)]
+pub struct JemallocStats {
+    pub allocated: u64,
+    pub active: u64,
+    pub metadata: u64,
+    pub resident: u64,
+    pub mapped: u64,
+    pub retained: u64,
+}
+
+/// Heap accounting, or None when [`CountingAllocator`] is not the global
+/// allocator (as in tests linking the library).
+pub fn heap_stats() -> Option<HeapStats> {
+    let allocations_total = ALLOCATIONS.load(Ordering::Relaxed);
+    if allocations_total == 0 {
+        return None;
+    }
+    Some(HeapStats {
+        allocator: if cfg!(feature = "jemalloc") {
+            "jemalloc"
+        } else {
+            "system"
+        },
+        allocated_bytes: ALLOCATED.load(Ordering::Relaxed),
+        peak_bytes: PEAK.load(Ordering::Relaxed),
+        allocations_total,
+        deallocations_total: DEALLOCATIONS.load(Ordering::Relaxed),
+        jemalloc: jemalloc_stats(),
+    })
+}
+
+#[cfg(feature = "jemalloc")]
+fn jemalloc_stats() -> Option<JemallocStats> {
+    use tikv_jemalloc_ctl::{epoch, stats};
+
+    // Statistics are cached until the epoch advances.
+    epoch::advance().ok()?;
+    Some(JemallocStats {
+        allocated: stats::allocated::read().ok()? as u64,
+        active: stats::active::read().ok()? as u64,
+        metadata: stats::metadata::read().ok()? as u64,
+        resident: stats::resident::read().ok()? as u64,
+        mapped: stats::mapped::read().ok()? as u64,
+        retained: stats::retained::read().ok()? as u64,
+    })
+}
+
+#[cfg(not(feature = "jemalloc"))]
+fn jemalloc_stats() -> Option<JemallocStats> {
+    None
+}
+
+/// Heap profiling settings, loaded from the environment.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryConfig {
+    /// Directory heap profiles are dumped into; dumps are refused when unset.
+    pub profile_dir: Option<PathBuf>,
+}
+
+impl MemoryConfig {
+    pub fn from_env() -> Self {
+        let profile_dir = std::env::var("CXDB_HEAP_PROFILE_DIR")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from);
+        Self { profile_dir }
+    }
+
+    /// Dump a heap profile into the profile directory and return its path.
+    pub fn dump_profile(&self) -> Result<PathBuf> {
+        let dir = self.profile_dir.as_ref().ok_or_else(|| {
+            StoreError::NotFound("heap profiling is disabled (set CXDB_HEAP_PROFILE_DIR)".into())
+        })?;
+        std::fs::create_dir_all(dir)?;
+        let unix_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let path = dir.join(format!("cxdb-heap-{unix_ms}.prof"));
+        dump_jemalloc_profile(&path)?;
+        Ok(path)
+    }
+}
+
+#[cfg(feature = "jemalloc")]
+fn dump_jemalloc_profile(path: &std::path::Path) -> Result<()> {
+    use std::ffi::CString;
+
+    if !tikv_jemalloc_ctl::profiling::prof::read().unwrap_or(false) {
+        return Err(StoreError::InvalidInput(
+            "jemalloc profiling is inactive (start the server with _RJEM_MALLOC_CONF=prof:true)"
+                .into(),
+        ));
+    }
+    let path = CString::new(path.to_string_lossy().into_owned())
+        .map_err(|_| StoreError::InvalidInput("profile path contains a NUL byte".into()))?;
+    // SAFETY: prof.dump takes a NUL-terminated file name, which outlives the call.
+    unsafe { tikv_jemalloc_ctl::raw::write(b"prof.dump\0", path.as_ptr()) }
+        .map_err(|e| StoreError::Io(std::io::Error::other(format!("prof.dump: {e}"))))
+}
+
+#[cfg(not(feature = "jemalloc"))]
+fn dump_jemalloc_profile(_path: &std::path::Path) -> Result<()> {
+    Err(StoreError::InvalidInput(
+        "cxdb-server was built without jemalloc support (enable the `jemalloc` feature)".into(),
+    ))
+}
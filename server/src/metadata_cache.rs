@@ -0,0 +1,245 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Memory-bounded cache of context metadata.
+//!
+//! Metadata is extracted from a context's first turn (plus overrides), which
+//! costs a payload read and decode, so the store caches it per context. With
+//! a budget set, the least recently used entries are evicted once the cache
+//! outgrows it; an evicted entry is loaded from disk again on its next use.
+//! Entry sizes are the inline size of the entry plus the heap bytes of its
+//! strings, so the reported total tracks what the cache actually holds.
+
+use std::collections::{BTreeMap, HashMap};
+use std::mem::size_of;
+
+use serde::Serialize;
+
+use crate::store::ContextMetadata;
+
+/// Per-entry bookkeeping besides the metadata itself: the hash table slot
+/// and the recency index node.
+const ENTRY_OVERHEAD: usize = size_of::<(u64, CacheEntry)>() + 1 + 2 * size_of::<u64>() + 16;
+
+/// Metadata cache settings.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MetadataCacheConfig {
+    /// Bytes the cache may hold; None means unbounded.
+    pub budget_bytes: Option<u64>,
+    /// Bytes the title and preview entries of the CQL indexes may hold
+    /// before the oldest contexts' entries are spilled; None means unbounded.
+    pub index_text_bytes: Option<u64>,
+}
+
+impl MetadataCacheConfig {
+    /// Load config from `CXDB_METADATA_CACHE_BYTES` and
+    /// `CXDB_INDEX_TEXT_BYTES`; unset or 0 means unbounded.
+    pub fn from_env() -> Self {
+        let budget = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|&b| b > 0)
+        };
+        Self {
+            budget_bytes: budget("CXDB_METADATA_CACHE_BYTES"),
+            index_text_bytes: budget("CXDB_INDEX_TEXT_BYTES"),
+        }
+    }
+}
+
+/// Cache accounting, reported with the index stats.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MetadataCacheStats {
+    pub entries: usize,
+    /// Approximate bytes held by cached entries.
+    pub bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_bytes: Option<u64>,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    /// None means the context was checked and has no metadata.
+    metadata: Option<ContextMetadata>,
+    bytes: usize,
+    /// Position in `recency`.
+    tick: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct MetadataCache {
+    budget_bytes: Option<u64>,
+    entries: HashMap<u64, CacheEntry>,
+    /// Access tick -> context id, oldest first.
+    recency: BTreeMap<u64, u64>,
+    next_tick: u64,
+    bytes: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl MetadataCache {
+    pub fn new(config: MetadataCacheConfig) -> Self {
+        Self {
+            budget_bytes: config.budget_bytes,
+            ..Self::default()
+        }
+    }
+
+    /// Cached metadata of a context, marking it recently used. The outer
+    /// None means the context is not cached.
+    pub fn get(&mut self, context_id: u64) -> Option<Option<ContextMetadata>> {
+        let tick = self.tick();
+        let Some(entry) = self.entries.get_mut(&context_id) else {
+            self.misses += 1;
+            return None;
+        };
+        self.recency.remove(&entry.tick);
+        self.recency.insert(tick, context_id);
+        entry.tick = tick;
+        self.hits += 1;
+        Some(entry.metadata.clone())
+    }
+
+    /// Cached metadata of a context without touching its recency.
+    pub fn peek(&self, context_id: u64) -> Option<&Option<ContextMetadata>> {
+        self.entries.get(&context_id).map(|e| &e.metadata)
+    }
+
+    /// Cache metadata for a context, evicting cold entries over budget.
+    pub fn insert(&mut self, context_id: u64, metadata: Option<ContextMetadata>) {
+        self.remove(context_id);
+        let bytes = ENTRY_OVERHEAD + metadata.as_ref().map_or(0, |m| m.memory_bytes());
+        let tick = self.tick();
+        self.recency.insert(tick, context_id);
+        self.entries.insert(
+            context_id,
+            CacheEntry {
+                metadata,
+                bytes,
+                tick,
+            },
+        );
+        self.bytes += bytes as u64;
+        self.evict();
+    }
+
+    /// Drop a context's entry, returning its metadata if it was cached.
+    pub fn remove(&mut self, context_id: u64) -> Option<Option<ContextMetadata>> {
+        let entry = self.entries.remove(&context_id)?;
+        self.recency.remove(&entry.tick);
+        self.bytes -= entry.bytes as u64;
+        Some(entry.metadata)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.bytes = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> MetadataCacheStats {
+        MetadataCacheStats {
+            entries: self.entries.len(),
+            bytes: self.bytes,
+            budget_bytes: self.budget_bytes,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+
+    /// Evict least recently used entries until the cache fits its budget.
+    /// The most recent entry is always kept.
+    fn evict(&mut self) {
+        let Some(budget) = self.budget_bytes else {
+            return;
+        };
+        while self.bytes > budget && self.entries.len() > 1 {
+            let Some((_, context_id)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&context_id) {
+                self.bytes -= entry.bytes as u64;
+                self.evictions += 1;
+            }
+        }
+        if self.entries.capacity() > 4 * self.entries.len().max(1024) {
+            self.entries.shrink_to_fit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(i: u64) -> Option<ContextMetadata> {
+        Some(ContextMetadata {
+            client_tag: Some(format!("tag-{}", i % 7)),
+            title: Some(format!("context number {i}")),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_budget_bounds_cache_under_many_contexts() {
+        let budget = 256 * 1024;
+        let mut cache = MetadataCache::new(MetadataCacheConfig {
+            budget_bytes: Some(budget),
+            ..Default::default()
+        });
+        for i in 0..1_000_000u64 {
+            cache.insert(i, metadata(i));
+            assert!(cache.stats().bytes <= budget);
+        }
+        let stats = cache.stats();
+        assert!(stats.entries > 100);
+        assert_eq!(stats.entries as u64 + stats.evictions, 1_000_000);
+        // The hottest entries survive; the oldest were evicted.
+        assert!(cache.get(999_999).is_some());
+        assert!(cache.get(0).is_none());
+    }
+
+    #[test]
+    fn test_recently_used_entries_are_kept() {
+        let mut cache = MetadataCache::new(MetadataCacheConfig::default());
+        for i in 0..3 {
+            cache.insert(i, metadata(i));
+        }
+        let entry_bytes = cache.stats().bytes / 3;
+        cache.budget_bytes = Some(entry_bytes * 3);
+
+        assert!(cache.get(0).is_some());
+        cache.insert(3, None);
+        assert!(cache.peek(1).is_none());
+        assert!(cache.peek(0).is_some());
+
+        let removed = cache.remove(0).unwrap().unwrap();
+        assert_eq!(removed.title.as_deref(), Some("context number 0"));
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.evictions), (2, 1));
+        assert_eq!(
+            stats.bytes,
+            cache.entries.values().map(|e| e.bytes as u64).sum::<u64>()
+        );
+    }
+}
@@ -0,0 +1,200 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Persisted context metadata overrides.
+//!
+//! Context metadata is normally extracted from the first turn's payload and is
+//! immutable. Overrides let the server layer additional metadata on top (for
+//! example an auto-derived title or backfilled provenance) without rewriting
+//! turns. They are stored as an append-only JSON-lines log where the latest
+//! entry per context wins.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::error::{Result, StoreError};
+use crate::jsonl_log::open_log;
+use crate::store::{ContextMetadata, Provenance};
+use crate::turn_store::CommitPipeline;
+
+/// Title source recorded when a title was derived from turn content.
+pub const TITLE_SOURCE_DERIVED: &str = "derived";
+
+/// Title source recorded when a title was set by a backfill.
+pub const TITLE_SOURCE_BACKFILL: &str = "backfill";
+
+/// Metadata fields layered over the extracted first-turn metadata.
+///
+/// Fields left as None keep the extracted value. Provenance is overlaid
+/// field-by-field, so a patch only needs the fields it sets.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetadataPatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Where the override title came from (e.g. "derived").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// Provenance fields cleared, e.g. an owner email that no longer
+    /// applies. A None provenance field keeps its value instead.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unset_provenance: Vec<String>,
+}
+
+impl MetadataPatch {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.client_tag.is_none()
+            && self.labels.is_none()
+            && self.provenance.is_none()
+            && self.unset_provenance.is_empty()
+    }
+
+    /// Apply this patch on top of base metadata.
+    pub fn apply(&self, base: Option<ContextMetadata>) -> Option<ContextMetadata> {
+        if self.is_empty() {
+            return base;
+        }
+        let mut metadata = base.unwrap_or_default();
+        if let Some(title) = &self.title {
+            metadata.title = Some(title.clone());
+        }
+        if let Some(client_tag) = &self.client_tag {
+            metadata.client_tag = Some(client_tag.clone());
+        }
+        if let Some(labels) = &self.labels {
+            metadata.labels = Some(labels.clone());
+        }
+        if self.provenance.is_some() || !self.unset_provenance.is_empty() {
+            metadata.provenance = Some(overlay_provenance(
+                metadata.provenance.take().unwrap_or_default(),
+                self.provenance.as_ref(),
+                &self.unset_provenance,
+            ));
+        }
+        Some(metadata)
+    }
+
+    /// Fold `other` into this patch; fields set in `other` win.
+    pub fn merge(&mut self, other: &MetadataPatch) {
+        if other.title.is_some() {
+            self.title = other.title.clone();
+            self.title_source = other.title_source.clone();
+        }
+        if other.client_tag.is_some() {
+            self.client_tag = other.client_tag.clone();
+        }
+        if other.labels.is_some() {
+            self.labels = other.labels.clone();
+        }
+        if other.provenance.is_some() || !other.unset_provenance.is_empty() {
+            let provenance = overlay_provenance(
+                self.provenance.take().unwrap_or_default(),
+                other.provenance.as_ref(),
+                &other.unset_provenance,
+            );
+            // A field set again is no longer cleared.
+            let set = serde_json::to_value(&provenance).unwrap_or_default();
+            self.unset_provenance
+                .retain(|field| set.get(field).is_none_or(JsonValue::is_null));
+            for field in &other.unset_provenance {
+                if !self.unset_provenance.contains(field) {
+                    self.unset_provenance.push(field.clone());
+                }
+            }
+            self.provenance = Some(provenance);
+        }
+    }
+}
+
+/// Overlay the fields set in `patch` onto `base`, then clear the `unset`
+/// fields.
+fn overlay_provenance(
+    base: Provenance,
+    patch: Option<&Provenance>,
+    unset: &[String],
+) -> Provenance {
+    let Ok(JsonValue::Object(mut base_obj)) = serde_json::to_value(&base) else {
+        return base;
+    };
+    if let Some(Ok(JsonValue::Object(patch_obj))) = patch.map(serde_json::to_value) {
+        for (key, value) in patch_obj {
+            if !value.is_null() {
+                base_obj.insert(key, value);
+            }
+        }
+    }
+    for field in unset {
+        base_obj.remove(field);
+    }
+    serde_json::from_value(JsonValue::Object(base_obj)).unwrap_or(base)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OverrideEntry {
+    context_id: u64,
+    #[serde(flatten)]
+    patch: MetadataPatch,
+}
+
+pub struct MetadataOverrides {
+    file: File,
+    entries: HashMap<u64, MetadataPatch>,
+}
+
+impl MetadataOverrides {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("overrides.jsonl");
+        let (file, records) = open_log::<OverrideEntry>(&path)?;
+
+        let mut entries = HashMap::new();
+        for entry in records {
+            entries.insert(entry.context_id, entry.patch);
+        }
+
+        Ok(Self { file, entries })
+    }
+
+    pub fn get(&self, context_id: u64) -> Option<&MetadataPatch> {
+        self.entries.get(&context_id)
+    }
+
+    /// Sync the log with every group commit batch.
+    pub fn track_files(&self, pipeline: &CommitPipeline) -> Result<()> {
+        pipeline.track("meta/overrides.jsonl", &self.file)
+    }
+
+    /// Persist a new patch for a context, replacing any previous one.
+    pub fn set(&mut self, context_id: u64, patch: MetadataPatch) -> Result<()> {
+        let entry = OverrideEntry {
+            context_id,
+            patch: patch.clone(),
+        };
+        let mut line = serde_json::to_vec(&entry)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.entries.insert(context_id, patch);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
@@ -1,18 +1,139 @@
 // Copyright 2025 StrongDM Inc
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt::Write as _;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
-use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use chrono::{SecondsFormat, Utc};
+use ring::rand::{SecureRandom, SystemRandom};
 use serde::Serialize;
 use sysinfo::{Disks, Pid, System};
 
+use crate::cql::IndexStats;
+use crate::error::{Result, StoreError};
+use crate::events::{EventBus, EventBusStats, StoreEvent};
+use crate::index_plugins::IndexPluginStats;
+use crate::payload_cache::PayloadCacheStats;
+use crate::pii::PiiStats;
+use crate::projection::cache::ProjectionCacheStats;
+use crate::quotas::{QuotaPolicy, QuotaReport, QuotaResource, QuotaWarning, Quotas};
 use crate::registry::Registry;
 use crate::store::Store;
+use crate::tokens::TokenStats;
+use crate::turn_store::CommitStats;
+use crate::unix_socket::PeerCredentials;
+use crate::util::{env_f64, env_u64, unix_ms};
+use crate::webhooks::Webhooks;
+
+mod age;
+mod histogram;
+mod protocol;
+mod tags;
+
+pub use age::{AgeBucket, ContextFootprint, DataAgeStats, TagAgeBuckets, AGE_BUCKETS};
+pub use histogram::{
+    BucketCount, Histogram, HistogramSummary, WindowedHistogram, WindowedSummary, BUCKET_BOUNDS_MS,
+};
+pub use protocol::{MessageSample, MessageSummary, ProtocolMetrics};
+pub use tags::{HeavyTag, TagMetrics, TagMetricsSnapshot, TagSummary, OTHER_TAGS, UNTAGGED};
+
+/// Longest accepted status string, in bytes.
+pub const MAX_STATUS_LEN: usize = 256;
+
+/// Default for `CXDB_SESSION_RESUME_GRACE_SECS`.
+const DEFAULT_RESUME_GRACE_SECS: u64 = 30;
+/// How often sessions past their resume grace window are ended.
+const SESSION_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a session outlives its connection. The default disables
+/// resumption: sessions end with their connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionResumeConfig {
+    /// A client reconnecting within this window can resume its session with
+    /// the token from its last HELLO response. Zero disables resumption.
+    pub grace: Duration,
+}
+
+impl SessionResumeConfig {
+    pub fn from_env() -> Self {
+        Self {
+            grace: Duration::from_secs(env_u64(
+                "CXDB_SESSION_RESUME_GRACE_SECS",
+                DEFAULT_RESUME_GRACE_SECS,
+            )),
+        }
+    }
+}
+
+/// Default for `CXDB_LIVENESS_WINDOW_SECS`.
+const DEFAULT_LIVENESS_WINDOW_SECS: u64 = 300;
+
+/// What makes a context live.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LivenessMode {
+    /// A binary protocol session that created or appended to it is open.
+    #[default]
+    Session,
+    /// It had an append or a heartbeat within the liveness window.
+    Activity,
+    /// Either of the above.
+    Any,
+}
+
+impl LivenessMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LivenessMode::Session => "session",
+            LivenessMode::Activity => "activity",
+            LivenessMode::Any => "any",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LivenessConfig {
+    pub mode: LivenessMode,
+    /// How long an append or heartbeat keeps a context live.
+    pub window: Duration,
+}
+
+impl Default for LivenessConfig {
+    fn default() -> Self {
+        Self {
+            mode: LivenessMode::default(),
+            window: Duration::from_secs(DEFAULT_LIVENESS_WINDOW_SECS),
+        }
+    }
+}
+
+impl LivenessConfig {
+    /// Load config from `CXDB_LIVENESS` (`session`, `activity` or `any`,
+    /// default `any`) and `CXDB_LIVENESS_WINDOW_SECS`.
+    pub fn from_env() -> Result<Self> {
+        let mode = match std::env::var("CXDB_LIVENESS").ok().as_deref() {
+            None | Some("") | Some("any") => LivenessMode::Any,
+            Some("session") => LivenessMode::Session,
+            Some("activity") => LivenessMode::Activity,
+            Some(other) => {
+                return Err(StoreError::InvalidInput(format!(
+                    "unknown CXDB_LIVENESS {other:?} (expected session, activity or any)"
+                )))
+            }
+        };
+        Ok(Self {
+            mode,
+            window: Duration::from_secs(env_u64(
+                "CXDB_LIVENESS_WINDOW_SECS",
+                DEFAULT_LIVENESS_WINDOW_SECS,
+            )),
+        })
+    }
+}
 
 /// Information about a connected client session.
 #[derive(Debug, Clone, Serialize)]
@@ -20,9 +141,83 @@ pub struct ClientSession {
     pub session_id: u64,
     pub client_tag: String,
     pub peer_addr: Option<String>, // Client IP:port from TCP connection
+    pub principal: Option<String>, // Verified TLS client certificate identity
     pub connected_at: u64,         // unix_ms
     pub last_activity_at: u64,     // unix_ms
     pub contexts_created: Vec<u64>, // context IDs created by this session
+    pub unsupported_messages: u64, // frames of message types the server does not implement
+    pub detached_at: Option<u64>,  // unix_ms the connection dropped, while resumable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<ProgressStatus>, // last status reported for the session itself
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peer_credentials: Option<PeerCredentials>, // uid/gid of a Unix socket peer
+}
+
+/// A transient progress report, such as "step 3/10: running tests", set by a
+/// client without appending turns. Kept in memory only.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProgressStatus {
+    pub status: String,
+    /// Fraction done, from 0 to 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<f64>,
+    pub updated_at: u64, // unix_ms
+    /// Session that reported it; its statuses end with it.
+    #[serde(skip)]
+    pub session_id: Option<u64>,
+}
+
+impl ProgressStatus {
+    /// Validate a status update. An empty status without progress clears
+    /// the status and yields None.
+    pub fn new(
+        status: String,
+        progress: Option<f64>,
+        session_id: Option<u64>,
+    ) -> Result<Option<Self>> {
+        if status.len() > MAX_STATUS_LEN {
+            return Err(StoreError::InvalidInput(format!(
+                "status longer than {MAX_STATUS_LEN} bytes"
+            )));
+        }
+        if progress.is_some_and(|p| !(0.0..=1.0).contains(&p)) {
+            return Err(StoreError::InvalidInput(
+                "progress must be between 0 and 1".into(),
+            ));
+        }
+        if status.is_empty() && progress.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            status,
+            progress,
+            updated_at: unix_ms(),
+            session_id,
+        }))
+    }
+}
+
+/// The `status_changed` event for a status set on a context (or, without a
+/// context, on the session itself); None reports the status was cleared.
+pub fn status_changed_event(
+    context_id: Option<u64>,
+    session_id: Option<u64>,
+    status: Option<&ProgressStatus>,
+) -> StoreEvent {
+    StoreEvent::StatusChanged {
+        context_id: context_id.map(|id| id.to_string()),
+        session_id: session_id.map(|id| id.to_string()),
+        status: status.map(|s| s.status.clone()),
+        progress: status.and_then(|s| s.progress),
+        updated_at: status.map_or_else(unix_ms, |s| s.updated_at),
+    }
+}
+
+/// The connection serving a session and the token that resumes it.
+struct SessionBinding {
+    connection: u64,
+    /// Hash of the last issued resume token.
+    token_hash: Option<blake3::Hash>,
 }
 
 /// Tracks connected client sessions and their metadata.
@@ -30,28 +225,175 @@ pub struct ClientSession {
 pub struct SessionTracker {
     sessions: RwLock<HashMap<u64, ClientSession>>,
     context_to_session: RwLock<HashMap<u64, u64>>,
+    bindings: Mutex<HashMap<u64, SessionBinding>>,
+    resume: SessionResumeConfig,
+    liveness: LivenessConfig,
+    /// Context id -> unix_ms of its last append or heartbeat.
+    context_activity: RwLock<HashMap<u64, u64>>,
+    /// Context id -> its last reported progress status.
+    context_status: RwLock<HashMap<u64, ProgressStatus>>,
 }
 
 impl SessionTracker {
     pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A tracker that keeps sessions resumable for `resume.grace` after
+    /// their connection drops.
+    pub fn with_resume(resume: SessionResumeConfig) -> Self {
         Self {
-            sessions: RwLock::new(HashMap::new()),
-            context_to_session: RwLock::new(HashMap::new()),
+            resume,
+            ..Self::default()
         }
     }
 
-    /// Register a new session with the given client tag and optional peer address.
-    pub fn register(&self, session_id: u64, client_tag: String, peer_addr: Option<String>) {
+    /// Use `liveness` to decide which contexts are live.
+    pub fn with_liveness(mut self, liveness: LivenessConfig) -> Self {
+        self.liveness = liveness;
+        self
+    }
+
+    pub fn liveness(&self) -> LivenessConfig {
+        self.liveness
+    }
+
+    pub fn resume_enabled(&self) -> bool {
+        !self.resume.grace.is_zero()
+    }
+
+    /// Register a new session with the given client tag, optional peer address
+    /// and authenticated principal. The session is served by the connection
+    /// with the same id.
+    pub fn register(
+        &self,
+        session_id: u64,
+        client_tag: String,
+        peer_addr: Option<String>,
+        principal: Option<String>,
+    ) {
         let now_ms = unix_ms();
         let session = ClientSession {
             session_id,
             client_tag,
             peer_addr,
+            principal,
             connected_at: now_ms,
             last_activity_at: now_ms,
             contexts_created: Vec::new(),
+            unsupported_messages: 0,
+            detached_at: None,
+            status: None,
+            peer_credentials: None,
         };
         self.sessions.write().unwrap().insert(session_id, session);
+        self.bindings.lock().unwrap().insert(
+            session_id,
+            SessionBinding {
+                connection: session_id,
+                token_hash: None,
+            },
+        );
+    }
+
+    /// Issue a token that resumes `session_id`, replacing any earlier one.
+    /// None when resumption is disabled or the session is unknown.
+    pub fn issue_resume_token(&self, session_id: u64) -> Option<Vec<u8>> {
+        if !self.resume_enabled() {
+            return None;
+        }
+        let mut token = vec![0u8; 32];
+        SystemRandom::new().fill(&mut token).ok()?;
+        let mut bindings = self.bindings.lock().unwrap();
+        bindings.get_mut(&session_id)?.token_hash = Some(blake3::hash(&token));
+        Some(token)
+    }
+
+    /// Rebind `session_id` to `connection`. Succeeds when `token` is the
+    /// session's current resume token, the new connection authenticated as
+    /// the same principal, and the grace window has not ended. A session
+    /// still attached to another connection is taken over: its old
+    /// connection may not have noticed the drop yet.
+    pub fn resume(
+        &self,
+        session_id: u64,
+        token: &[u8],
+        connection: u64,
+        principal: Option<&str>,
+        peer_addr: Option<String>,
+    ) -> Option<ClientSession> {
+        let now_ms = unix_ms();
+        let mut sessions = self.sessions.write().unwrap();
+        let session = sessions.get_mut(&session_id)?;
+        let mut bindings = self.bindings.lock().unwrap();
+        let binding = bindings.get_mut(&session_id)?;
+        // blake3::Hash compares in constant time.
+        if binding.token_hash != Some(blake3::hash(token))
+            || session.principal.as_deref() != principal
+            || session
+                .detached_at
+                .is_some_and(|at| now_ms >= at + self.resume.grace.as_millis() as u64)
+        {
+            return None;
+        }
+        binding.connection = connection;
+        session.detached_at = None;
+        session.last_activity_at = now_ms;
+        if peer_addr.is_some() {
+            session.peer_addr = peer_addr;
+        }
+        Some(session.clone())
+    }
+
+    /// End `connection`'s hold on `session_id`. Returns the session's
+    /// orphaned contexts when the session ended; None when it stays
+    /// resumable for the grace window or another connection resumed it.
+    pub fn disconnect(&self, session_id: u64, connection: u64) -> Option<Vec<u64>> {
+        {
+            let mut sessions = self.sessions.write().unwrap();
+            let bindings = self.bindings.lock().unwrap();
+            if let (Some(session), Some(binding)) =
+                (sessions.get_mut(&session_id), bindings.get(&session_id))
+            {
+                if binding.connection != connection {
+                    return None;
+                }
+                // Without an issued token nobody can resume the session.
+                if self.resume_enabled() && binding.token_hash.is_some() {
+                    session.detached_at = Some(unix_ms());
+                    return None;
+                }
+            }
+        }
+        Some(self.unregister(session_id))
+    }
+
+    /// End detached sessions whose grace window has passed and return them.
+    pub fn expire_detached(&self) -> Vec<ClientSession> {
+        let cutoff = unix_ms().saturating_sub(self.resume.grace.as_millis() as u64);
+        let expired: Vec<ClientSession> = {
+            let mut sessions = self.sessions.write().unwrap();
+            let ids: Vec<u64> = sessions
+                .values()
+                .filter(|s| s.detached_at.is_some_and(|at| at <= cutoff))
+                .map(|s| s.session_id)
+                .collect();
+            ids.iter().filter_map(|id| sessions.remove(id)).collect()
+        };
+        {
+            let mut bindings = self.bindings.lock().unwrap();
+            let mut ctx_map = self.context_to_session.write().unwrap();
+            for session in &expired {
+                bindings.remove(&session.session_id);
+                for ctx_id in &session.contexts_created {
+                    ctx_map.remove(ctx_id);
+                }
+            }
+        }
+        for session in &expired {
+            self.clear_session_statuses(session.session_id);
+        }
+        expired
     }
 
     /// Get the peer address for a session.
@@ -63,6 +405,14 @@ impl SessionTracker {
             .and_then(|s| s.peer_addr.clone())
     }
 
+    /// Record the credentials of the Unix socket peer serving a session, or
+    /// clear them when it is served over TCP.
+    pub fn set_peer_credentials(&self, session_id: u64, credentials: Option<PeerCredentials>) {
+        if let Some(session) = self.sessions.write().unwrap().get_mut(&session_id) {
+            session.peer_credentials = credentials;
+        }
+    }
+
     /// Record activity for a session (updates last_activity_at).
     pub fn record_activity(&self, session_id: u64) {
         let now_ms = unix_ms();
@@ -71,8 +421,17 @@ impl SessionTracker {
         }
     }
 
-    /// Associate a context with a session.
+    /// Count a frame of a message type the server does not implement.
+    pub fn record_unsupported_message(&self, session_id: u64) {
+        if let Some(session) = self.sessions.write().unwrap().get_mut(&session_id) {
+            session.unsupported_messages += 1;
+        }
+    }
+
+    /// Associate a context with a session. Creating a context counts as
+    /// activity on it.
     pub fn add_context(&self, session_id: u64, context_id: u64) {
+        self.record_context_activity(context_id);
         self.context_to_session
             .write()
             .unwrap()
@@ -87,6 +446,8 @@ impl SessionTracker {
     /// Unregister a session and return its orphaned contexts.
     pub fn unregister(&self, session_id: u64) -> Vec<u64> {
         let session = self.sessions.write().unwrap().remove(&session_id);
+        self.bindings.lock().unwrap().remove(&session_id);
+        self.clear_session_statuses(session_id);
         if let Some(session) = session {
             let mut ctx_map = self.context_to_session.write().unwrap();
             for ctx_id in &session.contexts_created {
@@ -111,14 +472,78 @@ impl SessionTracker {
         self.sessions.read().unwrap().values().cloned().collect()
     }
 
-    /// Get all context IDs that have active sessions (are "live").
-    pub fn get_live_context_ids(&self) -> std::collections::HashSet<u64> {
-        self.context_to_session
+    /// Get all context IDs that are live under the configured liveness
+    /// mode. Activity older than the window is forgotten.
+    pub fn get_live_context_ids(&self) -> HashSet<u64> {
+        let mut live = HashSet::new();
+        if self.liveness.mode != LivenessMode::Activity {
+            live.extend(self.context_to_session.read().unwrap().keys().copied());
+        }
+        if self.liveness.mode != LivenessMode::Session {
+            let cutoff = self.activity_cutoff();
+            let mut activity = self.context_activity.write().unwrap();
+            activity.retain(|_, at| *at > cutoff);
+            live.extend(activity.keys().copied());
+        }
+        live
+    }
+
+    /// Record an append to or a heartbeat of a context.
+    pub fn record_context_activity(&self, context_id: u64) {
+        self.context_activity
+            .write()
+            .unwrap()
+            .insert(context_id, unix_ms());
+    }
+
+    /// When a context last had an append or heartbeat, if within the window.
+    pub fn context_last_activity(&self, context_id: u64) -> Option<u64> {
+        let cutoff = self.activity_cutoff();
+        self.context_activity
             .read()
             .unwrap()
-            .keys()
+            .get(&context_id)
             .copied()
-            .collect()
+            .filter(|at| *at > cutoff)
+    }
+
+    /// Replace a context's progress status; None clears it. Reporting a
+    /// status counts as activity on the context.
+    pub fn set_context_status(&self, context_id: u64, status: Option<ProgressStatus>) {
+        self.record_context_activity(context_id);
+        let mut statuses = self.context_status.write().unwrap();
+        match status {
+            Some(status) => statuses.insert(context_id, status),
+            None => statuses.remove(&context_id),
+        };
+    }
+
+    /// A context's last reported progress status.
+    pub fn context_status(&self, context_id: u64) -> Option<ProgressStatus> {
+        self.context_status
+            .read()
+            .unwrap()
+            .get(&context_id)
+            .cloned()
+    }
+
+    /// Replace the progress status of a session itself; None clears it.
+    pub fn set_session_status(&self, session_id: u64, status: Option<ProgressStatus>) {
+        if let Some(session) = self.sessions.write().unwrap().get_mut(&session_id) {
+            session.status = status;
+        }
+    }
+
+    /// Drop the context statuses a session reported.
+    fn clear_session_statuses(&self, session_id: u64) {
+        self.context_status
+            .write()
+            .unwrap()
+            .retain(|_, status| status.session_id != Some(session_id));
+    }
+
+    fn activity_cutoff(&self) -> u64 {
+        unix_ms().saturating_sub(self.liveness.window.as_millis() as u64)
     }
 
     /// Get the client tag for a session.
@@ -130,12 +555,20 @@ impl SessionTracker {
             .map(|s| s.client_tag.clone())
     }
 
-    /// Check if a context is live (has an active session).
+    /// Check if a context is live under the configured liveness mode.
     pub fn is_context_live(&self, context_id: u64) -> bool {
-        self.context_to_session
-            .read()
-            .unwrap()
-            .contains_key(&context_id)
+        let has_session = || {
+            self.context_to_session
+                .read()
+                .unwrap()
+                .contains_key(&context_id)
+        };
+        let has_activity = || self.context_last_activity(context_id).is_some();
+        match self.liveness.mode {
+            LivenessMode::Session => has_session(),
+            LivenessMode::Activity => has_activity(),
+            LivenessMode::Any => has_session() || has_activity(),
+        }
     }
 
     /// Get all unique client tags from active sessions.
@@ -151,7 +584,30 @@ impl SessionTracker {
     }
 }
 
-const MAX_LATENCY_SAMPLES: usize = 2048;
+/// End sessions whose resume grace window passed in the background,
+/// publishing their disconnect. None when resumption is disabled.
+pub fn start_session_sweeper(
+    tracker: Arc<SessionTracker>,
+    event_bus: Arc<EventBus>,
+) -> Option<thread::JoinHandle<()>> {
+    if !tracker.resume_enabled() {
+        return None;
+    }
+    Some(thread::spawn(move || loop {
+        thread::sleep(SESSION_SWEEP_INTERVAL);
+        for session in tracker.expire_detached() {
+            event_bus.publish(StoreEvent::ClientDisconnected {
+                session_id: session.session_id.to_string(),
+                client_tag: session.client_tag,
+                contexts: session
+                    .contexts_created
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect(),
+            });
+        }
+    }))
+}
 
 #[derive(Debug, Clone)]
 pub struct MetricsConfig {
@@ -161,6 +617,15 @@ pub struct MetricsConfig {
     pub hot_ratio: f64,
     pub critical_ratio: f64,
     pub idle_seconds: u64,
+    /// Distinct client tags with their own series; others fold into `_other`.
+    pub max_tags: usize,
+    /// Tags listed in the `heaviest` ranking.
+    pub top_tags: usize,
+    /// Binary protocol requests slower than this are logged; `None` disables
+    /// the log.
+    pub slow_request: Option<Duration>,
+    /// How long the data age breakdown is cached between recomputations.
+    pub data_age_refresh: Duration,
 }
 
 impl MetricsConfig {
@@ -171,6 +636,13 @@ impl MetricsConfig {
         let hot_ratio = env_f64("CXDB_METRICS_HOT_RATIO", 0.80).clamp(0.10, 0.98);
         let critical_ratio = env_f64("CXDB_METRICS_CRITICAL_RATIO", 0.92).clamp(0.10, 0.999);
         let idle_seconds = env_u64("CXDB_METRICS_IDLE_SECONDS", 60);
+        let max_tags = env_u64("CXDB_METRICS_MAX_TAGS", 100) as usize;
+        let top_tags = env_u64("CXDB_METRICS_TOP_TAGS", 10) as usize;
+        let slow_request = match env_u64("CXDB_SLOW_REQUEST_MS", 0) {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        };
+        let data_age_refresh = Duration::from_secs(env_u64("CXDB_METRICS_AGE_REFRESH_SECS", 300));
         Self {
             budget_pct,
             hard_cap_bytes,
@@ -178,6 +650,10 @@ impl MetricsConfig {
             hot_ratio,
             critical_ratio,
             idle_seconds,
+            max_tags,
+            top_tags,
+            slow_request,
+            data_age_refresh,
         }
     }
 }
@@ -190,6 +666,8 @@ pub struct Metrics {
 
     sessions_total: AtomicU64,
     sessions_active: AtomicU64,
+    sessions_resumed: AtomicU64,
+    session_resume_failures: AtomicU64,
     last_session_activity_ms: AtomicU64,
     next_session_id: AtomicU64,
     session_activity: Mutex<HashMap<u64, u64>>,
@@ -205,19 +683,29 @@ pub struct Metrics {
 
     rates: Mutex<RateStore>,
     latencies: Mutex<LatencyStore>,
+    tags: Mutex<TagMetrics>,
+    protocol: Mutex<ProtocolMetrics>,
+    http_compression: Mutex<BTreeMap<String, HttpCompressionMetrics>>,
+    data_age: Mutex<Option<DataAgeStats>>,
+    /// Per-tag quotas, when a quota policy is configured.
+    quotas: Option<Quotas>,
     system: Mutex<System>,
 }
 
 impl Metrics {
     pub fn new(data_dir: PathBuf) -> Self {
         let pid = Pid::from_u32(std::process::id());
+        let config = MetricsConfig::from_env();
         Self {
-            config: MetricsConfig::from_env(),
+            tags: Mutex::new(TagMetrics::new(config.max_tags)),
+            config,
             start: Instant::now(),
             pid,
             data_dir,
             sessions_total: AtomicU64::new(0),
             sessions_active: AtomicU64::new(0),
+            sessions_resumed: AtomicU64::new(0),
+            session_resume_failures: AtomicU64::new(0),
             last_session_activity_ms: AtomicU64::new(0),
             next_session_id: AtomicU64::new(1),
             session_activity: Mutex::new(HashMap::new()),
@@ -230,11 +718,22 @@ impl Metrics {
             errors_total: AtomicU64::new(0),
             errors_by_type: Mutex::new(HashMap::new()),
             rates: Mutex::new(RateStore::new()),
-            latencies: Mutex::new(LatencyStore::new()),
+            latencies: Mutex::new(LatencyStore::default()),
+            protocol: Mutex::new(ProtocolMetrics::default()),
+            http_compression: Mutex::new(BTreeMap::new()),
+            data_age: Mutex::new(None),
+            quotas: None,
             system: Mutex::new(System::new()),
         }
     }
 
+    /// Count appends and context creates against `policy`, queueing its
+    /// warning webhooks on `webhooks`.
+    pub fn with_quotas(mut self, policy: QuotaPolicy, webhooks: Arc<Webhooks>) -> Self {
+        self.quotas = Some(Quotas::new(policy, webhooks));
+        self
+    }
+
     pub fn register_session(self: &Arc<Self>) -> SessionGuard {
         let session_id = self.next_session_id.fetch_add(1, Ordering::Relaxed);
         self.sessions_total.fetch_add(1, Ordering::Relaxed);
@@ -261,6 +760,16 @@ impl Metrics {
         }
     }
 
+    /// Count a HELLO that asked to resume a session.
+    pub fn record_session_resume(&self, resumed: bool) {
+        let counter = if resumed {
+            &self.sessions_resumed
+        } else {
+            &self.session_resume_failures
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
     fn unregister_session(&self, session_id: u64) {
         self.sessions_active.fetch_sub(1, Ordering::Relaxed);
         self.session_activity.lock().unwrap().remove(&session_id);
@@ -272,7 +781,7 @@ impl Metrics {
             .lock()
             .unwrap()
             .append
-            .push(duration_to_ms(duration));
+            .record(duration, unix_secs());
     }
 
     pub fn record_get_last(&self, duration: Duration) {
@@ -281,7 +790,7 @@ impl Metrics {
             .lock()
             .unwrap()
             .get_last
-            .push(duration_to_ms(duration));
+            .record(duration, unix_secs());
     }
 
     pub fn record_get_blob(&self, duration: Duration) {
@@ -290,23 +799,99 @@ impl Metrics {
             .lock()
             .unwrap()
             .get_blob
-            .push(duration_to_ms(duration));
+            .record(duration, unix_secs());
+    }
+
+    /// Attribute an append of `bytes` (uncompressed payload) to a client tag.
+    pub fn record_tag_append(&self, client_tag: &str, bytes: u64) -> Vec<QuotaWarning> {
+        self.tags.lock().unwrap().record_append(client_tag, bytes);
+        match &self.quotas {
+            Some(quotas) => quotas.record(client_tag, 0, 1, bytes, unix_secs()),
+            None => Vec::new(),
+        }
+    }
+
+    /// Count a context created by a client tag against its quota, returning
+    /// the thresholds it crossed.
+    pub fn record_tag_context(&self, client_tag: &str) -> Vec<QuotaWarning> {
+        match &self.quotas {
+            Some(quotas) => quotas.record(client_tag, 1, 0, 0, unix_secs()),
+            None => Vec::new(),
+        }
+    }
+
+    /// Quota usage per tag; None without a quota policy.
+    pub fn quota_report(&self) -> Option<QuotaReport> {
+        self.quotas.as_ref().map(|q| q.report(unix_secs()))
+    }
+
+    /// Attribute a read returning `bytes` to a client tag.
+    pub fn record_tag_read(&self, client_tag: &str, bytes: u64, duration: Duration) {
+        self.tags
+            .lock()
+            .unwrap()
+            .record_read(client_tag, bytes, duration, unix_secs());
+    }
+
+    /// Threshold for the binary protocol slow-request log.
+    pub fn slow_request_threshold(&self) -> Option<Duration> {
+        self.config.slow_request
+    }
+
+    /// Account one binary protocol frame under its message type.
+    pub fn record_message(&self, msg_type: &'static str, sample: &MessageSample) {
+        self.protocol
+            .lock()
+            .unwrap()
+            .record(msg_type, sample, unix_secs());
+    }
+
+    /// Count a binary protocol frame of a message type this server does not
+    /// implement.
+    pub fn record_unsupported_message(&self, msg_type: u16) {
+        self.protocol.lock().unwrap().record_unsupported(msg_type);
     }
 
     pub fn record_registry_ingest(&self) {
         self.registry_ingest_total.fetch_add(1, Ordering::Relaxed);
     }
 
-    pub fn record_http(&self, status_code: u16, duration: Duration) {
+    /// Record an HTTP request. `route` is the route template (see
+    /// `http::route_template`), so per-route series stay bounded.
+    pub fn record_http(&self, method: &str, route: &str, status_code: u16, duration: Duration) {
         self.http_total.fetch_add(1, Ordering::Relaxed);
         if status_code >= 400 {
             self.http_errors_total.fetch_add(1, Ordering::Relaxed);
         }
-        self.latencies
-            .lock()
-            .unwrap()
-            .http
-            .push(duration_to_ms(duration));
+        let now = unix_secs();
+        let mut latencies = self.latencies.lock().unwrap();
+        latencies.http.record(duration, now);
+        latencies
+            .http_routes
+            .entry(HttpSeries {
+                method: method.to_string(),
+                route: route.to_string(),
+                status: status_code,
+            })
+            .or_default()
+            .record(duration, now);
+    }
+
+    /// Record a compressed HTTP response body.
+    pub fn record_http_compression(
+        &self,
+        encoding: &str,
+        input_bytes: usize,
+        output_bytes: usize,
+        duration: Duration,
+    ) {
+        let mut map = self.http_compression.lock().unwrap();
+        let entry = map.entry(encoding.to_string()).or_default();
+        entry.responses += 1;
+        entry.input_bytes += input_bytes as u64;
+        entry.output_bytes += output_bytes as u64;
+        entry.saved_bytes += input_bytes.saturating_sub(output_bytes) as u64;
+        entry.duration_seconds += duration.as_secs_f64();
     }
 
     pub fn record_error(&self, kind: &str) {
@@ -316,7 +901,12 @@ impl Metrics {
         *entry += 1;
     }
 
-    pub fn snapshot(&self, store: &mut Store, registry: &Registry) -> MetricsSnapshot {
+    pub fn snapshot(
+        &self,
+        store: &mut Store,
+        registry: &Registry,
+        events: EventBusStats,
+    ) -> MetricsSnapshot {
         let now = Utc::now();
         let uptime_seconds = self.start.elapsed().as_secs_f64();
 
@@ -337,11 +927,24 @@ impl Metrics {
         let http_rates = rates.update_http(http_total);
         let http_error_rates = rates.update_http_errors(http_errors);
 
+        let now_secs = unix_secs();
         let latencies = self.latencies.lock().unwrap();
-        let append_latency = LatencySummary::from_samples(&latencies.append);
-        let get_last_latency = LatencySummary::from_samples(&latencies.get_last);
-        let get_blob_latency = LatencySummary::from_samples(&latencies.get_blob);
-        let http_latency = LatencySummary::from_samples(&latencies.http);
+        let append_latency = LatencySummary::from_histogram(&latencies.append.window(now_secs));
+        let get_last_latency = LatencySummary::from_histogram(&latencies.get_last.window(now_secs));
+        let get_blob_latency = LatencySummary::from_histogram(&latencies.get_blob.window(now_secs));
+        let http_latency = LatencySummary::from_histogram(&latencies.http.window(now_secs));
+        let latency = latencies.summary(now_secs);
+        drop(latencies);
+        let tags = self
+            .tags
+            .lock()
+            .unwrap()
+            .summary(now_secs, self.config.top_tags);
+        let (protocol, unsupported_messages) = {
+            let protocol = self.protocol.lock().unwrap();
+            (protocol.summary(now_secs), protocol.unsupported())
+        };
+        let http_compression = self.http_compression.lock().unwrap().clone();
 
         let sessions_active = self.sessions_active.load(Ordering::Relaxed);
         let sessions_total = self.sessions_total.load(Ordering::Relaxed);
@@ -353,15 +956,47 @@ impl Metrics {
             map.values().filter(|v| **v < idle_cutoff).count() as u64
         };
 
-        let errors_by_type = self.errors_by_type.lock().unwrap().clone();
-        let errors_total = self.errors_total.load(Ordering::Relaxed);
+        let mut errors_by_type = self.errors_by_type.lock().unwrap().clone();
+        let mut errors_total = self.errors_total.load(Ordering::Relaxed);
 
         let store_stats = store.stats();
+        // Steps that failed after their turn was appended are errors too,
+        // even though the append itself succeeded.
+        if store_stats.append_side_effect_failures > 0 {
+            errors_total += store_stats.append_side_effect_failures;
+            errors_by_type.insert(
+                "append_side_effect".to_string(),
+                store_stats.append_side_effect_failures,
+            );
+        }
+        let tokens = store.token_stats();
+        let indexes = store.index_stats();
+        let payload_cache = store.payload_cache_stats();
+        let commit = store.commit_stats();
+        let quotas = self.quota_report();
+        let pii = store.pii_stats();
+        let index_plugins = store.index_plugin_stats();
+        let projection_cache = registry.projection_cache_stats();
+        let data_age = self.data_age(store, now_ms);
         let filesystem = FilesystemMetrics {
             snapshots_total: store_stats.fs_roots_total,
             index_bytes: store_stats.fs_roots_bytes,
             content_bytes: store_stats.fs_content_bytes,
         };
+        let dedup = &store_stats.dedup;
+        let dedup = DedupMetrics {
+            payload_refs: dedup.references,
+            unique_payloads: dedup.unique_blobs,
+            hits_total: dedup.hits(),
+            hit_rate: if dedup.references > 0 {
+                dedup.hits() as f64 / dedup.references as f64
+            } else {
+                0.0
+            },
+            logical_bytes: dedup.logical_bytes,
+            unique_bytes: dedup.unique_bytes,
+            saved_bytes: dedup.saved_bytes(),
+        };
 
         MetricsSnapshot {
             ts: now.to_rfc3339_opts(SecondsFormat::Millis, true),
@@ -372,10 +1007,28 @@ impl Metrics {
                 active: sessions_active,
                 idle: idle_sessions,
                 last_activity_unix_ms: last_activity_ms,
+                resumed: self.sessions_resumed.load(Ordering::Relaxed),
+                resume_failures: self.session_resume_failures.load(Ordering::Relaxed),
             },
             objects,
             storage,
             filesystem,
+            dedup,
+            latency,
+            tags,
+            protocol,
+            unsupported_messages,
+            tokens,
+            events,
+            indexes,
+            payload_cache,
+            commit,
+            quotas,
+            projection_cache,
+            pii,
+            index_plugins,
+            http_compression,
+            data_age,
             perf: PerfMetrics {
                 append_tps_1m: append_rates.rate_1m,
                 append_tps_5m: append_rates.rate_5m,
@@ -405,6 +1058,23 @@ impl Metrics {
         }
     }
 
+    /// Data age breakdown, recomputed once the cached one is older than
+    /// `data_age_refresh`.
+    fn data_age(&self, store: &Store, now_ms: u64) -> DataAgeStats {
+        let mut cached = self.data_age.lock().unwrap();
+        let refresh_ms = self.config.data_age_refresh.as_millis() as u64;
+        match cached.as_ref() {
+            Some(stats) if now_ms.saturating_sub(stats.computed_at_unix_ms) < refresh_ms => {
+                stats.clone()
+            }
+            _ => {
+                let stats = store.data_age_stats(now_ms, self.config.max_tags);
+                *cached = Some(stats.clone());
+                stats
+            }
+        }
+    }
+
     fn collect_stats(
         &self,
         store: &mut Store,
@@ -463,7 +1133,7 @@ impl Metrics {
             sys_swap_free_bytes: swap_free_bytes,
             process_rss_bytes,
             process_vmem_bytes,
-            process_heap_bytes: None,
+            process_heap_bytes: crate::memory::heap_stats().map(|heap| heap.allocated_bytes),
             process_open_fds: None,
             budget_bytes,
             budget_pct: self.config.budget_pct,
@@ -490,6 +1160,8 @@ impl Metrics {
             heads_table_bytes: store_stats.heads_table_bytes,
             blobs_pack_bytes: store_stats.blobs_pack_bytes,
             blobs_index_bytes: store_stats.blobs_index_bytes,
+            blobs_live_bytes: store_stats.blob_gc.live_bytes,
+            blobs_dead_bytes: store_stats.blob_gc.dead_bytes,
             data_dir_total_bytes: disk_total,
             data_dir_free_bytes: disk_free,
         };
@@ -524,10 +1196,858 @@ pub struct MetricsSnapshot {
     pub objects: ObjectMetrics,
     pub storage: StorageMetrics,
     pub filesystem: FilesystemMetrics,
+    pub dedup: DedupMetrics,
     pub perf: PerfMetrics,
+    pub latency: LatencyMetrics,
+    /// Load per client tag.
+    pub tags: TagMetricsSnapshot,
+    /// Binary protocol frames per message type.
+    pub protocol: Vec<MessageSummary>,
+    /// Frames of message types this server does not implement, by type
+    /// number; usually clients newer than the server.
+    pub unsupported_messages: BTreeMap<u16, u64>,
+    /// Counted tokens overall and per client tag.
+    pub tokens: TokenStats,
+    /// SSE stream accounting from the event bus.
+    pub events: EventBusStats,
+    /// Secondary index and metadata cache memory.
+    pub indexes: IndexStats,
+    /// Payload cache and read-ahead for turn pagination.
+    pub payload_cache: PayloadCacheStats,
+    /// Group commit batching and sync latency; null when group commit is off.
+    pub commit: Option<CommitStats>,
+    /// Quota usage per client tag; null without a quota policy.
+    pub quotas: Option<QuotaReport>,
+    /// Typed projections cached for the read path.
+    pub projection_cache: ProjectionCacheStats,
+    /// Ingest-time PII scanning; null when disabled.
+    pub pii: Option<PiiStats>,
+    /// Custom index plugins; null when none are configured.
+    pub index_plugins: Option<Vec<IndexPluginStats>>,
+    /// Compressed HTTP responses per content encoding.
+    pub http_compression: BTreeMap<String, HttpCompressionMetrics>,
+    /// Stored data by age of the last append, refreshed periodically.
+    pub data_age: DataAgeStats,
     pub errors: ErrorMetrics,
 }
 
+impl MetricsSnapshot {
+    /// Render the snapshot in the Prometheus text exposition format. Latency
+    /// histograms carry lifetime buckets; the 5-minute window is exported as
+    /// a summary with quantiles.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let gauges: [(&str, &str, f64); 12] = [
+            (
+                "cxdb_uptime_seconds",
+                "Seconds since the server started",
+                self.uptime_seconds,
+            ),
+            (
+                "cxdb_sessions_active",
+                "Connected binary protocol sessions",
+                self.sessions.active as f64,
+            ),
+            (
+                "cxdb_contexts_total",
+                "Contexts in the store",
+                self.objects.contexts_total as f64,
+            ),
+            (
+                "cxdb_turns_total",
+                "Turns in the store",
+                self.objects.turns_total as f64,
+            ),
+            (
+                "cxdb_blobs_total",
+                "Blobs in the store",
+                self.objects.blobs_total as f64,
+            ),
+            (
+                "cxdb_registry_types_total",
+                "Types in the registry",
+                self.objects.registry_types_total as f64,
+            ),
+            (
+                "cxdb_fs_snapshots_total",
+                "Filesystem snapshots attached to turns",
+                self.filesystem.snapshots_total as f64,
+            ),
+            (
+                "cxdb_process_resident_memory_bytes",
+                "Resident memory of the server process",
+                self.memory.process_rss_bytes.unwrap_or(0) as f64,
+            ),
+            (
+                "cxdb_memory_pressure_ratio",
+                "Resident memory relative to the memory budget",
+                self.memory.pressure_ratio,
+            ),
+            (
+                "cxdb_data_dir_free_bytes",
+                "Free space on the data directory's disk",
+                self.storage.data_dir_free_bytes as f64,
+            ),
+            (
+                "cxdb_payload_cache_hit_ratio",
+                "Paged payload reads served from the payload cache",
+                self.payload_cache.hit_rate,
+            ),
+            (
+                "cxdb_projection_cache_hit_ratio",
+                "Typed projections served from the projection cache",
+                self.projection_cache.hit_rate,
+            ),
+        ];
+        for (name, help, value) in gauges {
+            let _ = writeln!(
+                out,
+                "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}"
+            );
+        }
+
+        let cache = &self.indexes.metadata_cache;
+        let payloads = &self.payload_cache;
+        let projections = &self.projection_cache;
+        let counts: [(&str, &str, &str, u64); 32] = [
+            (
+                "cxdb_sessions_resumed_total",
+                "Binary protocol sessions resumed after a reconnect",
+                "counter",
+                self.sessions.resumed,
+            ),
+            (
+                "cxdb_session_resume_failures_total",
+                "Session resumptions refused (unknown or expired session, bad token)",
+                "counter",
+                self.sessions.resume_failures,
+            ),
+            (
+                "cxdb_blob_dedup_hits_total",
+                "Turn appends whose payload was already stored",
+                "counter",
+                self.dedup.hits_total,
+            ),
+            (
+                "cxdb_payload_dedup_saved_bytes",
+                "Payload bytes not stored thanks to deduplication",
+                "gauge",
+                self.dedup.saved_bytes,
+            ),
+            (
+                "cxdb_tokens_total",
+                "Tokens counted in appended turns",
+                "counter",
+                self.tokens.total,
+            ),
+            (
+                "cxdb_sse_streams_active",
+                "Open SSE event streams",
+                "gauge",
+                self.events.active_streams,
+            ),
+            (
+                "cxdb_sse_slow_consumers",
+                "SSE streams with a queue at least half full",
+                "gauge",
+                self.events.slow_consumers,
+            ),
+            (
+                "cxdb_sse_queued_events",
+                "Events buffered for SSE streams",
+                "gauge",
+                self.events.queued_events,
+            ),
+            (
+                "cxdb_sse_events_dropped_total",
+                "Events dropped from full subscriber queues",
+                "counter",
+                self.events.dropped_events_total,
+            ),
+            (
+                "cxdb_sse_overflow_disconnects_total",
+                "SSE streams closed for overflowing their queue",
+                "counter",
+                self.events.overflow_disconnects_total,
+            ),
+            (
+                "cxdb_sse_rejected_streams_total",
+                "SSE streams refused at the stream limit",
+                "counter",
+                self.events.rejected_streams_total,
+            ),
+            (
+                "cxdb_contexts_indexed",
+                "Contexts in the secondary indexes",
+                "gauge",
+                self.indexes.contexts_indexed as u64,
+            ),
+            (
+                "cxdb_index_memory_bytes",
+                "Approximate memory held by the secondary indexes",
+                "gauge",
+                self.indexes.memory_bytes,
+            ),
+            (
+                "cxdb_index_text_bytes",
+                "Estimated memory held by the title and preview index entries",
+                "gauge",
+                self.indexes.text_bytes,
+            ),
+            (
+                "cxdb_index_spilled_contexts",
+                "Contexts whose title and preview index entries were spilled",
+                "gauge",
+                self.indexes.spilled_contexts as u64,
+            ),
+            (
+                "cxdb_metadata_cache_entries",
+                "Contexts with cached metadata",
+                "gauge",
+                cache.entries as u64,
+            ),
+            (
+                "cxdb_metadata_cache_bytes",
+                "Approximate memory held by the metadata cache",
+                "gauge",
+                cache.bytes,
+            ),
+            (
+                "cxdb_metadata_cache_hits_total",
+                "Metadata lookups served from the cache",
+                "counter",
+                cache.hits,
+            ),
+            (
+                "cxdb_metadata_cache_misses_total",
+                "Metadata lookups loaded from disk",
+                "counter",
+                cache.misses,
+            ),
+            (
+                "cxdb_metadata_cache_evictions_total",
+                "Metadata cache entries evicted to stay within the budget",
+                "counter",
+                cache.evictions,
+            ),
+            (
+                "cxdb_payload_cache_entries",
+                "Turn payloads in the payload cache",
+                "gauge",
+                payloads.entries as u64,
+            ),
+            (
+                "cxdb_payload_cache_bytes",
+                "Approximate memory held by the payload cache",
+                "gauge",
+                payloads.bytes,
+            ),
+            (
+                "cxdb_payload_cache_hits_total",
+                "Paged payload reads served from the cache",
+                "counter",
+                payloads.hits,
+            ),
+            (
+                "cxdb_payload_cache_misses_total",
+                "Paged payload reads from the blob pack",
+                "counter",
+                payloads.misses,
+            ),
+            (
+                "cxdb_payload_cache_evictions_total",
+                "Payload cache entries evicted to stay within the budget",
+                "counter",
+                payloads.evictions,
+            ),
+            (
+                "cxdb_payload_prefetched_total",
+                "Payloads read ahead of turn pagination",
+                "counter",
+                payloads.prefetched,
+            ),
+            (
+                "cxdb_projection_cache_entries",
+                "Typed projections in the projection cache",
+                "gauge",
+                projections.entries as u64,
+            ),
+            (
+                "cxdb_projection_cache_bytes",
+                "Approximate memory held by the projection cache",
+                "gauge",
+                projections.bytes,
+            ),
+            (
+                "cxdb_projection_cache_hits_total",
+                "Typed projections served from the cache",
+                "counter",
+                projections.hits,
+            ),
+            (
+                "cxdb_projection_cache_misses_total",
+                "Typed projections computed from the payload",
+                "counter",
+                projections.misses,
+            ),
+            (
+                "cxdb_projection_cache_evictions_total",
+                "Projection cache entries evicted to stay within the budget",
+                "counter",
+                projections.evictions,
+            ),
+            (
+                "cxdb_projection_cache_invalidations_total",
+                "Projection cache entries dropped by new type versions",
+                "counter",
+                projections.invalidations,
+            ),
+        ];
+        for (name, help, kind, value) in counts {
+            let _ = writeln!(
+                out,
+                "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}"
+            );
+        }
+
+        if let Some(commit) = &self.commit {
+            let commit_counts: [(&str, &str, &str, f64); 5] = [
+                (
+                    "cxdb_commits_total",
+                    "Appends acknowledged after a group commit sync",
+                    "counter",
+                    commit.commits_total as f64,
+                ),
+                (
+                    "cxdb_commit_batches_total",
+                    "Group commit syncs, one per batch of appends",
+                    "counter",
+                    commit.batches_total as f64,
+                ),
+                (
+                    "cxdb_commit_sync_failures_total",
+                    "Group commit syncs that failed",
+                    "counter",
+                    commit.sync_failures_total as f64,
+                ),
+                (
+                    "cxdb_commit_batch_size_mean",
+                    "Appends per group commit batch",
+                    "gauge",
+                    commit.mean_batch_size,
+                ),
+                (
+                    "cxdb_commit_window_seconds",
+                    "How long a group commit leader waits for more appends",
+                    "gauge",
+                    commit.window_us as f64 / 1_000_000.0,
+                ),
+            ];
+            for (name, help, kind, value) in commit_counts {
+                let _ = writeln!(
+                    out,
+                    "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}"
+                );
+            }
+            write_latency(
+                &mut out,
+                "cxdb_commit_duration_seconds",
+                "Group commit latency: the sync per batch, the wait per append",
+                &[
+                    ("phase=\"sync\"".to_string(), &commit.sync_ms),
+                    ("phase=\"wait\"".to_string(), &commit.wait_ms),
+                ],
+            );
+        }
+
+        if let Some(quotas) = &self.quotas {
+            let _ = writeln!(
+                out,
+                "# HELP cxdb_quota_usage Client tag usage over the quota window\n# TYPE cxdb_quota_usage gauge"
+            );
+            for tag in &quotas.tags {
+                for resource in QuotaResource::ALL {
+                    let _ = writeln!(
+                        out,
+                        "cxdb_quota_usage{{tag=\"{}\",resource=\"{}\"}} {}",
+                        escape_label(&tag.tag),
+                        resource.as_str(),
+                        tag.resource(resource).usage
+                    );
+                }
+            }
+            let _ = writeln!(
+                out,
+                "# HELP cxdb_quota_limit Client tag quota limit\n# TYPE cxdb_quota_limit gauge"
+            );
+            for tag in &quotas.tags {
+                for resource in QuotaResource::ALL {
+                    if let Some(limit) = tag.resource(resource).limit {
+                        let _ = writeln!(
+                            out,
+                            "cxdb_quota_limit{{tag=\"{}\",resource=\"{}\"}} {limit}",
+                            escape_label(&tag.tag),
+                            resource.as_str()
+                        );
+                    }
+                }
+            }
+        }
+
+        if let Some(sink) = &self.events.sink {
+            let sink_counts: [(&str, &str, &str, u64); 4] = [
+                (
+                    "cxdb_sink_pending_events",
+                    "Events in the sink outbox not yet acknowledged by the broker",
+                    "gauge",
+                    sink.pending,
+                ),
+                (
+                    "cxdb_sink_lag_milliseconds",
+                    "Age of the oldest unacknowledged event in the sink outbox",
+                    "gauge",
+                    sink.lag_ms,
+                ),
+                (
+                    "cxdb_sink_published_total",
+                    "Events acknowledged by the sink broker",
+                    "counter",
+                    sink.published_total,
+                ),
+                (
+                    "cxdb_sink_failures_total",
+                    "Failed sink publish attempts",
+                    "counter",
+                    sink.failures_total,
+                ),
+            ];
+            for (name, help, kind, value) in sink_counts {
+                let _ = writeln!(
+                    out,
+                    "# HELP {name} {help}\n# TYPE {name} {kind}\n{name}{{sink=\"{}\"}} {value}",
+                    escape_label(&sink.sink)
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP cxdb_storage_bytes On-disk size by component\n# TYPE cxdb_storage_bytes gauge"
+        );
+        for (component, bytes) in [
+            ("turns_log", self.storage.turns_log_bytes),
+            ("turns_index", self.storage.turns_index_bytes),
+            ("turns_meta", self.storage.turns_meta_bytes),
+            ("heads_table", self.storage.heads_table_bytes),
+            ("blobs_pack", self.storage.blobs_pack_bytes),
+            ("blobs_index", self.storage.blobs_index_bytes),
+        ] {
+            let _ = writeln!(
+                out,
+                "cxdb_storage_bytes{{component=\"{component}\"}} {bytes}"
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP cxdb_blob_pack_bytes Blob pack bytes by liveness\n# TYPE cxdb_blob_pack_bytes gauge"
+        );
+        for (state, bytes) in [
+            ("live", self.storage.blobs_live_bytes),
+            ("dead", self.storage.blobs_dead_bytes),
+        ] {
+            let _ = writeln!(out, "cxdb_blob_pack_bytes{{state=\"{state}\"}} {bytes}");
+        }
+
+        type AgeValue = fn(&AgeBucket) -> u64;
+        let age_gauges: [(&str, &str, AgeValue); 3] = [
+            (
+                "contexts",
+                "Contexts by time since their last append",
+                |b| b.contexts,
+            ),
+            (
+                "turns",
+                "Turns by time since their context's last append",
+                |b| b.turns,
+            ),
+            (
+                "bytes",
+                "Uncompressed payload bytes by time since their context's last append",
+                |b| b.bytes,
+            ),
+        ];
+        for (suffix, help, value) in age_gauges {
+            let name = format!("cxdb_data_age_{suffix}");
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} gauge");
+            for bucket in &self.data_age.buckets {
+                let _ = writeln!(out, "{name}{{age=\"{}\"}} {}", bucket.age, value(bucket));
+            }
+            let name = format!("cxdb_tag_data_age_{suffix}");
+            let _ = writeln!(
+                out,
+                "# HELP {name} {help}, by client tag\n# TYPE {name} gauge"
+            );
+            for tag in &self.data_age.by_tag {
+                for bucket in &tag.buckets {
+                    let _ = writeln!(
+                        out,
+                        "{name}{{tag=\"{}\",age=\"{}\"}} {}",
+                        escape_label(&tag.tag),
+                        bucket.age,
+                        value(bucket)
+                    );
+                }
+            }
+        }
+
+        let compression: Vec<(&String, [f64; 4])> = self
+            .http_compression
+            .iter()
+            .map(|(encoding, m)| {
+                let values = [
+                    m.responses as f64,
+                    m.input_bytes as f64,
+                    m.saved_bytes as f64,
+                    m.duration_seconds,
+                ];
+                (encoding, values)
+            })
+            .collect();
+        for (i, (name, help)) in [
+            (
+                "cxdb_http_compressed_responses_total",
+                "HTTP responses sent compressed",
+            ),
+            (
+                "cxdb_http_compression_input_bytes_total",
+                "HTTP response bytes before compression",
+            ),
+            (
+                "cxdb_http_compression_saved_bytes_total",
+                "HTTP response bytes saved by compression",
+            ),
+            (
+                "cxdb_http_compression_seconds_total",
+                "Time spent compressing HTTP responses",
+            ),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} counter");
+            for (encoding, values) in &compression {
+                let _ = writeln!(out, "{name}{{encoding=\"{encoding}\"}} {}", values[i]);
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP cxdb_errors_total Errors by source\n# TYPE cxdb_errors_total counter"
+        );
+        let mut errors: Vec<_> = self.errors.by_type.iter().collect();
+        errors.sort();
+        for (kind, count) in errors {
+            let _ = writeln!(
+                out,
+                "cxdb_errors_total{{kind=\"{}\"}} {count}",
+                escape_label(kind)
+            );
+        }
+
+        let operations: Vec<(String, &WindowedSummary)> = self
+            .latency
+            .operations
+            .iter()
+            .map(|(op, summary)| (format!("op=\"{op}\""), summary))
+            .collect();
+        write_latency(
+            &mut out,
+            "cxdb_operation_duration_seconds",
+            "Binary protocol operation latency",
+            &operations,
+        );
+
+        let http: Vec<(String, &WindowedSummary)> = self
+            .latency
+            .http
+            .iter()
+            .map(|h| {
+                let labels = format!(
+                    "method=\"{}\",route=\"{}\",status=\"{}\"",
+                    escape_label(&h.method),
+                    escape_label(&h.route),
+                    h.status
+                );
+                (labels, &h.summary)
+            })
+            .collect();
+        write_latency(
+            &mut out,
+            "cxdb_http_request_duration_seconds",
+            "HTTP request latency by route and status",
+            &http,
+        );
+
+        type TagCounter = fn(&TagSummary) -> u64;
+        let tag_counters: [(&str, &str, TagCounter); 4] = [
+            (
+                "cxdb_tag_appends_total",
+                "Turn appends by client tag",
+                |t| t.appends,
+            ),
+            (
+                "cxdb_tag_append_bytes_total",
+                "Uncompressed payload bytes appended by client tag",
+                |t| t.append_bytes,
+            ),
+            (
+                "cxdb_tag_reads_total",
+                "Binary protocol reads by client tag",
+                |t| t.reads,
+            ),
+            (
+                "cxdb_tag_read_bytes_total",
+                "Bytes returned by reads by client tag",
+                |t| t.read_bytes,
+            ),
+        ];
+        for (name, help, value) in tag_counters {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} counter");
+            for tag in &self.tags.tags {
+                let _ = writeln!(
+                    out,
+                    "{name}{{tag=\"{}\"}} {}",
+                    escape_label(&tag.tag),
+                    value(tag)
+                );
+            }
+        }
+        let tag_latency: Vec<(String, &WindowedSummary)> = self
+            .tags
+            .tags
+            .iter()
+            .map(|t| (format!("tag=\"{}\"", escape_label(&t.tag)), &t.read_latency))
+            .collect();
+        write_latency(
+            &mut out,
+            "cxdb_tag_read_duration_seconds",
+            "Binary protocol read latency by client tag",
+            &tag_latency,
+        );
+
+        type MessageCounter = fn(&MessageSummary) -> u64;
+        let message_counters: [(&str, &str, MessageCounter); 5] = [
+            (
+                "cxdb_protocol_messages_total",
+                "Binary protocol frames handled by message type",
+                |m| m.count,
+            ),
+            (
+                "cxdb_protocol_errors_total",
+                "Binary protocol frames answered with an error by message type",
+                |m| m.errors,
+            ),
+            (
+                "cxdb_protocol_slow_requests_total",
+                "Binary protocol requests over the slow-request threshold by message type",
+                |m| m.slow,
+            ),
+            (
+                "cxdb_protocol_request_bytes_total",
+                "Binary protocol request payload bytes by message type",
+                |m| m.request_bytes,
+            ),
+            (
+                "cxdb_protocol_response_bytes_total",
+                "Binary protocol response payload bytes by message type",
+                |m| m.response_bytes,
+            ),
+        ];
+        for (name, help, value) in message_counters {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} counter");
+            for message in &self.protocol {
+                let _ = writeln!(
+                    out,
+                    "{name}{{msg_type=\"{}\"}} {}",
+                    message.msg_type,
+                    value(message)
+                );
+            }
+        }
+        let name = "cxdb_protocol_unsupported_messages_total";
+        let _ = writeln!(
+            out,
+            "# HELP {name} Binary protocol frames of message types the server does not implement\n# TYPE {name} counter"
+        );
+        for (msg_type, count) in &self.unsupported_messages {
+            let _ = writeln!(out, "{name}{{msg_type=\"{msg_type}\"}} {count}");
+        }
+        if let Some(pii) = &self.pii {
+            for (name, help, value) in [
+                (
+                    "cxdb_pii_turns_scanned_total",
+                    "Appended turns scanned for PII",
+                    pii.turns_scanned,
+                ),
+                (
+                    "cxdb_pii_turns_excluded_total",
+                    "Appended turns not scanned for PII because of their declared type",
+                    pii.turns_excluded,
+                ),
+            ] {
+                let _ = writeln!(
+                    out,
+                    "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}"
+                );
+            }
+            let name = "cxdb_pii_detections_total";
+            let _ = writeln!(
+                out,
+                "# HELP {name} Appended turns a PII detector fired on\n# TYPE {name} counter"
+            );
+            for (detector, count) in &pii.detections {
+                let _ = writeln!(out, "{name}{{detector=\"{detector}\"}} {count}");
+            }
+        }
+        if let Some(plugins) = &self.index_plugins {
+            let mut counter = |name: &str, help: &str, value: fn(&IndexPluginStats) -> u64| {
+                let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} counter");
+                for plugin in plugins {
+                    let _ = writeln!(
+                        out,
+                        "{name}{{plugin=\"{}\"}} {}",
+                        plugin.name,
+                        value(plugin)
+                    );
+                }
+            };
+            counter(
+                "cxdb_index_plugin_invocations_total",
+                "Appended turns handed to an index plugin",
+                |p| p.invocations,
+            );
+            counter(
+                "cxdb_index_plugin_entries_total",
+                "Index entries an index plugin returned",
+                |p| p.entries,
+            );
+            counter(
+                "cxdb_index_plugin_failures_total",
+                "Index plugin calls that trapped, ran out of resources or returned invalid entries",
+                |p| p.failures,
+            );
+            let name = "cxdb_index_plugin_disabled";
+            let _ = writeln!(
+                out,
+                "# HELP {name} Whether an index plugin was disabled after consecutive failures\n# TYPE {name} gauge"
+            );
+            for plugin in plugins {
+                let _ = writeln!(
+                    out,
+                    "{name}{{plugin=\"{}\"}} {}",
+                    plugin.name, plugin.disabled as u8
+                );
+            }
+        }
+        let message_latency: Vec<(String, &WindowedSummary)> = self
+            .protocol
+            .iter()
+            .map(|m| (format!("msg_type=\"{}\"", m.msg_type), &m.latency))
+            .collect();
+        write_latency(
+            &mut out,
+            "cxdb_protocol_message_duration_seconds",
+            "Binary protocol latency from frame read to response by message type",
+            &message_latency,
+        );
+        out
+    }
+}
+
+/// Write a histogram family (lifetime) and a `_5m` summary family (window).
+fn write_latency(out: &mut String, name: &str, help: &str, series: &[(String, &WindowedSummary)]) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} histogram");
+    for (labels, summary) in series {
+        let lifetime = &summary.lifetime;
+        for bucket in &lifetime.buckets {
+            let le = bucket.le_ms / 1000.0;
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{labels},le=\"{le}\"}} {}",
+                bucket.count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{labels},le=\"+Inf\"}} {}",
+            lifetime.count
+        );
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {}", lifetime.sum_ms / 1000.0);
+        let _ = writeln!(out, "{name}_count{{{labels}}} {}", lifetime.count);
+    }
+
+    let window = format!("{name}_5m");
+    let _ = writeln!(
+        out,
+        "# HELP {window} {help} over the last 5 minutes\n# TYPE {window} summary"
+    );
+    for (labels, summary) in series {
+        let w = &summary.window_5m;
+        for (quantile, value) in [
+            ("0.5", w.p50),
+            ("0.9", w.p90),
+            ("0.95", w.p95),
+            ("0.99", w.p99),
+            ("0.999", w.p999),
+        ] {
+            if let Some(ms) = value {
+                let _ = writeln!(
+                    out,
+                    "{window}{{{labels},quantile=\"{quantile}\"}} {}",
+                    ms / 1000.0
+                );
+            }
+        }
+        let _ = writeln!(out, "{window}_sum{{{labels}}} {}", w.sum_ms / 1000.0);
+        let _ = writeln!(out, "{window}_count{{{labels}}} {}", w.count);
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Compressed HTTP responses of one content encoding.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HttpCompressionMetrics {
+    pub responses: u64,
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+    pub saved_bytes: u64,
+    pub duration_seconds: f64,
+}
+
+/// Latency histograms: binary protocol operations by name, HTTP requests by
+/// method, route and status.
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyMetrics {
+    pub operations: BTreeMap<String, WindowedSummary>,
+    pub http: Vec<HttpLatency>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HttpLatency {
+    pub method: String,
+    pub route: String,
+    pub status: u16,
+    #[serde(flatten)]
+    pub summary: WindowedSummary,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct MemoryMetrics {
     pub sys_total_bytes: u64,
@@ -555,6 +2075,10 @@ pub struct SessionMetrics {
     pub active: u64,
     pub idle: u64,
     pub last_activity_unix_ms: u64,
+    /// HELLOs that resumed a session after a reconnect.
+    pub resumed: u64,
+    /// HELLOs that asked to resume a session and got a new one instead.
+    pub resume_failures: u64,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -567,6 +2091,21 @@ pub struct ObjectMetrics {
     pub heads_total: usize,
 }
 
+/// Turn payload deduplication. Sizes are uncompressed payload bytes.
+#[derive(Debug, Clone, Serialize)]
+pub struct DedupMetrics {
+    /// Turns referencing a payload blob.
+    pub payload_refs: u64,
+    /// Distinct payload blobs.
+    pub unique_payloads: u64,
+    /// Appends whose payload was already stored.
+    pub hits_total: u64,
+    pub hit_rate: f64,
+    pub logical_bytes: u64,
+    pub unique_bytes: u64,
+    pub saved_bytes: u64,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct StorageMetrics {
     pub turns_log_bytes: u64,
@@ -575,6 +2114,10 @@ pub struct StorageMetrics {
     pub heads_table_bytes: u64,
     pub blobs_pack_bytes: u64,
     pub blobs_index_bytes: u64,
+    /// Pack bytes of blobs referenced by turns or fs snapshots.
+    pub blobs_live_bytes: u64,
+    /// Pack bytes of unreferenced blobs, reclaimable by blob collection.
+    pub blobs_dead_bytes: u64,
     pub data_dir_total_bytes: u64,
     pub data_dir_free_bytes: u64,
 }
@@ -626,60 +2169,62 @@ pub struct LatencySummary {
 }
 
 impl LatencySummary {
-    fn from_samples(samples: &VecDeque<f64>) -> Self {
-        if samples.is_empty() {
-            return Self {
-                p50: None,
-                p95: None,
-                p99: None,
-                max: None,
-                count: 0,
-            };
-        }
-        let mut values: Vec<f64> = samples.iter().copied().collect();
-        values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-        let p50 = percentile(&values, 0.50);
-        let p95 = percentile(&values, 0.95);
-        let p99 = percentile(&values, 0.99);
-        let max = values.last().copied();
+    fn from_histogram(histogram: &Histogram) -> Self {
+        let summary = histogram.summary();
         Self {
-            p50,
-            p95,
-            p99,
-            max,
-            count: values.len(),
+            p50: summary.p50,
+            p95: summary.p95,
+            p99: summary.p99,
+            max: summary.max,
+            count: summary.count as usize,
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct HttpSeries {
+    method: String,
+    route: String,
+    status: u16,
+}
+
+#[derive(Default)]
 struct LatencyStore {
-    append: VecDeque<f64>,
-    get_last: VecDeque<f64>,
-    get_blob: VecDeque<f64>,
-    http: VecDeque<f64>,
+    append: WindowedHistogram,
+    get_last: WindowedHistogram,
+    get_blob: WindowedHistogram,
+    /// All HTTP requests, for the `perf.http_latency_ms` summary.
+    http: WindowedHistogram,
+    http_routes: BTreeMap<HttpSeries, WindowedHistogram>,
 }
 
 impl LatencyStore {
-    fn new() -> Self {
-        Self {
-            append: VecDeque::with_capacity(MAX_LATENCY_SAMPLES),
-            get_last: VecDeque::with_capacity(MAX_LATENCY_SAMPLES),
-            get_blob: VecDeque::with_capacity(MAX_LATENCY_SAMPLES),
-            http: VecDeque::with_capacity(MAX_LATENCY_SAMPLES),
-        }
+    fn operations(&self) -> [(&'static str, &WindowedHistogram); 3] {
+        [
+            ("append", &self.append),
+            ("get_last", &self.get_last),
+            ("get_blob", &self.get_blob),
+        ]
     }
-}
-
-trait SampleBuffer {
-    fn push(&mut self, value: f64);
-}
 
-impl SampleBuffer for VecDeque<f64> {
-    fn push(&mut self, value: f64) {
-        if self.len() == MAX_LATENCY_SAMPLES {
-            self.pop_front();
+    fn summary(&self, now_secs: u64) -> LatencyMetrics {
+        LatencyMetrics {
+            operations: self
+                .operations()
+                .into_iter()
+                .map(|(name, h)| (name.to_string(), h.summary(now_secs)))
+                .collect(),
+            http: self
+                .http_routes
+                .iter()
+                .map(|(series, h)| HttpLatency {
+                    method: series.method.clone(),
+                    route: series.route.clone(),
+                    status: series.status,
+                    summary: h.summary(now_secs),
+                })
+                .collect(),
         }
-        self.push_back(value);
     }
 }
 
@@ -787,45 +2332,8 @@ struct RateSnapshot {
     history: Vec<f64>,
 }
 
-fn env_f64(key: &str, default: f64) -> f64 {
-    std::env::var(key)
-        .ok()
-        .and_then(|v| v.parse::<f64>().ok())
-        .unwrap_or(default)
-}
-
-fn env_u64(key: &str, default: u64) -> u64 {
-    std::env::var(key)
-        .ok()
-        .and_then(|v| v.parse::<u64>().ok())
-        .unwrap_or(default)
-}
-
-fn duration_to_ms(duration: Duration) -> f64 {
-    duration.as_secs_f64() * 1000.0
-}
-
-fn unix_ms() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis() as u64
-}
-
-fn percentile(sorted: &[f64], pct: f64) -> Option<f64> {
-    if sorted.is_empty() {
-        return None;
-    }
-    let rank = (sorted.len() as f64 - 1.0) * pct;
-    let low = rank.floor() as usize;
-    let high = rank.ceil() as usize;
-    if low == high {
-        return sorted.get(low).copied();
-    }
-    let low_val = sorted[low];
-    let high_val = sorted[high];
-    let weight = rank - low as f64;
-    Some(low_val + (high_val - low_val) * weight)
+fn unix_secs() -> u64 {
+    unix_ms() / 1000
 }
 
 fn alpha(dt: f64, window_seconds: f64) -> f64 {
@@ -0,0 +1,181 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Stored data segmented by age, for capacity planning.
+//!
+//! A context's age is the time since its last append (its creation when it
+//! has none). Each turn is counted once, against the oldest context that
+//! reaches it, so forks only add the turns appended after the fork point.
+//! Walking every head chain is linear in the number of turns, so the
+//! result is cached and recomputed every `CXDB_METRICS_AGE_REFRESH_SECS`.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use super::tags::{OTHER_TAGS, UNTAGGED};
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Age bucket labels and their lower bounds in days.
+pub const AGE_BUCKETS: [(&str, u64); 4] = [
+    ("0-30d", 0),
+    ("30-90d", 30),
+    ("90-180d", 90),
+    ("180d+", 180),
+];
+
+/// Stored data of one context, as input to [`DataAgeStats::compute`].
+#[derive(Debug, Clone)]
+pub struct ContextFootprint {
+    pub context_id: u64,
+    /// Client tag; empty when the context has none.
+    pub tag: String,
+    pub last_append_unix_ms: u64,
+    /// Turns attributed to this context.
+    pub turns: u64,
+    /// Uncompressed payload bytes of those turns.
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AgeBucket {
+    pub age: &'static str,
+    pub contexts: u64,
+    pub turns: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TagAgeBuckets {
+    pub tag: String,
+    pub buckets: Vec<AgeBucket>,
+}
+
+/// Contexts, turns and bytes per age bucket, overall and per client tag.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DataAgeStats {
+    pub computed_at_unix_ms: u64,
+    pub buckets: Vec<AgeBucket>,
+    /// Tags by bytes stored, largest first; tags beyond `max_tags` fold into
+    /// `_other`.
+    pub by_tag: Vec<TagAgeBuckets>,
+}
+
+impl DataAgeStats {
+    pub fn compute(footprints: &[ContextFootprint], now_ms: u64, max_tags: usize) -> Self {
+        let mut buckets = empty_buckets();
+        let mut by_tag: HashMap<&str, Vec<AgeBucket>> = HashMap::new();
+        for footprint in footprints {
+            let index = bucket_index(now_ms.saturating_sub(footprint.last_append_unix_ms));
+            let tag = if footprint.tag.is_empty() {
+                UNTAGGED
+            } else {
+                footprint.tag.as_str()
+            };
+            add(&mut buckets[index], footprint);
+            add(
+                &mut by_tag.entry(tag).or_insert_with(empty_buckets)[index],
+                footprint,
+            );
+        }
+
+        let mut by_tag: Vec<TagAgeBuckets> = by_tag
+            .into_iter()
+            .map(|(tag, buckets)| TagAgeBuckets {
+                tag: tag.to_string(),
+                buckets,
+            })
+            .collect();
+        by_tag.sort_by(|a, b| {
+            total_bytes(b)
+                .cmp(&total_bytes(a))
+                .then_with(|| a.tag.cmp(&b.tag))
+        });
+        if by_tag.len() > max_tags {
+            let mut other = empty_buckets();
+            for folded in by_tag.drain(max_tags..) {
+                for (into, from) in other.iter_mut().zip(folded.buckets) {
+                    into.contexts += from.contexts;
+                    into.turns += from.turns;
+                    into.bytes += from.bytes;
+                }
+            }
+            by_tag.push(TagAgeBuckets {
+                tag: OTHER_TAGS.to_string(),
+                buckets: other,
+            });
+        }
+
+        Self {
+            computed_at_unix_ms: now_ms,
+            buckets,
+            by_tag,
+        }
+    }
+}
+
+fn empty_buckets() -> Vec<AgeBucket> {
+    AGE_BUCKETS
+        .iter()
+        .map(|(age, _)| AgeBucket {
+            age,
+            ..AgeBucket::default()
+        })
+        .collect()
+}
+
+fn bucket_index(age_ms: u64) -> usize {
+    AGE_BUCKETS
+        .iter()
+        .rposition(|(_, days)| age_ms >= days * DAY_MS)
+        .unwrap_or(0)
+}
+
+fn add(bucket: &mut AgeBucket, footprint: &ContextFootprint) {
+    bucket.contexts += 1;
+    bucket.turns += footprint.turns;
+    bucket.bytes += footprint.bytes;
+}
+
+fn total_bytes(tag: &TagAgeBuckets) -> u64 {
+    tag.buckets.iter().map(|b| b.bytes).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn footprint(tag: &str, age_days: u64, bytes: u64) -> ContextFootprint {
+        ContextFootprint {
+            context_id: 0,
+            tag: tag.to_string(),
+            last_append_unix_ms: 1_000 * DAY_MS - age_days * DAY_MS,
+            turns: 1,
+            bytes,
+        }
+    }
+
+    #[test]
+    fn test_contexts_bucket_by_age_and_tag() {
+        let footprints = [
+            footprint("a", 1, 10),
+            footprint("a", 45, 20),
+            footprint("b", 120, 35),
+            footprint("", 400, 40),
+            footprint("c", 400, 5),
+        ];
+        let stats = DataAgeStats::compute(&footprints, 1_000 * DAY_MS, 2);
+        let bytes: Vec<u64> = stats.buckets.iter().map(|b| b.bytes).collect();
+        assert_eq!(bytes, vec![10, 20, 35, 45]);
+        assert_eq!(stats.buckets[3].contexts, 2);
+
+        let tags: Vec<&str> = stats.by_tag.iter().map(|t| t.tag.as_str()).collect();
+        assert_eq!(tags, vec![UNTAGGED, "b", OTHER_TAGS]);
+        let other = &stats.by_tag[2].buckets;
+        assert_eq!(
+            (other[0].bytes, other[1].bytes, other[3].bytes),
+            (10, 20, 5)
+        );
+    }
+}
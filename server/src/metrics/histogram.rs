@@ -0,0 +1,286 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Log-linear latency histograms.
+//!
+//! Values are recorded in microseconds into HDR-style buckets: every power of
+//! two is split into 8 linear sub-buckets, so percentiles are accurate to
+//! within 12.5% at any magnitude while a histogram stays a fixed-size array.
+//! Each histogram also keeps counts for a small set of fixed bucket bounds
+//! (`BUCKET_BOUNDS_MS`) used for the JSON `buckets` list and Prometheus `le`
+//! buckets.
+//!
+//! [`WindowedHistogram`] keeps lifetime totals plus a rolling 5-minute window
+//! made of 30-second slots.
+
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// Linear sub-buckets per power of two (2^SUB_BITS).
+const SUB_BITS: u32 = 3;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+/// Largest tracked exponent; larger values land in the last bucket (~71 min).
+const MAX_EXP: u32 = 32;
+const FINE_BUCKETS: usize = SUB_BUCKETS + (MAX_EXP - SUB_BITS + 1) as usize * SUB_BUCKETS;
+
+/// Upper bounds (inclusive, milliseconds) of the exported buckets.
+pub const BUCKET_BOUNDS_MS: &[f64] = &[
+    0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+];
+
+const WINDOW_SLOT_SECS: u64 = 30;
+const WINDOW_SLOTS: usize = 10;
+
+#[derive(Clone)]
+pub struct Histogram {
+    fine: Vec<u64>,
+    /// Counts per `BUCKET_BOUNDS_MS` entry, plus one for values above the last bound.
+    bounded: Vec<u64>,
+    count: u64,
+    sum_us: u64,
+    max_us: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            fine: vec![0; FINE_BUCKETS],
+            bounded: vec![0; BUCKET_BOUNDS_MS.len() + 1],
+            count: 0,
+            sum_us: 0,
+            max_us: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, duration: Duration) {
+        let us = duration.as_micros().min(u64::MAX as u128) as u64;
+        self.fine[fine_index(us)] += 1;
+        let ms = us as f64 / 1000.0;
+        let bounded = BUCKET_BOUNDS_MS
+            .iter()
+            .position(|&le| ms <= le)
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+        self.bounded[bounded] += 1;
+        self.count += 1;
+        self.sum_us = self.sum_us.saturating_add(us);
+        self.max_us = self.max_us.max(us);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (a, b) in self.fine.iter_mut().zip(&other.fine) {
+            *a += b;
+        }
+        for (a, b) in self.bounded.iter_mut().zip(&other.bounded) {
+            *a += b;
+        }
+        self.count += other.count;
+        self.sum_us = self.sum_us.saturating_add(other.sum_us);
+        self.max_us = self.max_us.max(other.max_us);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum_seconds(&self) -> f64 {
+        self.sum_us as f64 / 1_000_000.0
+    }
+
+    /// Value at quantile `q` (0..=1) in milliseconds, or None when empty.
+    /// Reports the midpoint of the containing bucket, capped at the maximum.
+    pub fn quantile_ms(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((self.count as f64 * q).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        for (index, &n) in self.fine.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                let (low, high) = fine_bounds(index);
+                let mid = (low + high) as f64 / 2.0;
+                return Some(mid.min(self.max_us as f64) / 1000.0);
+            }
+        }
+        Some(self.max_us as f64 / 1000.0)
+    }
+
+    /// Cumulative counts per exported bound, as Prometheus `le` buckets.
+    pub fn cumulative_buckets(&self) -> Vec<(f64, u64)> {
+        let mut total = 0;
+        BUCKET_BOUNDS_MS
+            .iter()
+            .zip(&self.bounded)
+            .map(|(&le, &n)| {
+                total += n;
+                (le, total)
+            })
+            .collect()
+    }
+
+    pub fn summary(&self) -> HistogramSummary {
+        HistogramSummary {
+            count: self.count,
+            sum_ms: self.sum_us as f64 / 1000.0,
+            mean_ms: (self.count > 0).then(|| self.sum_us as f64 / 1000.0 / self.count as f64),
+            p50: self.quantile_ms(0.50),
+            p90: self.quantile_ms(0.90),
+            p95: self.quantile_ms(0.95),
+            p99: self.quantile_ms(0.99),
+            p999: self.quantile_ms(0.999),
+            max: (self.count > 0).then(|| self.max_us as f64 / 1000.0),
+            buckets: self
+                .cumulative_buckets()
+                .into_iter()
+                .map(|(le_ms, count)| BucketCount { le_ms, count })
+                .collect(),
+        }
+    }
+}
+
+fn fine_index(us: u64) -> usize {
+    if us < SUB_BUCKETS as u64 {
+        return us as usize;
+    }
+    let exp = (63 - us.leading_zeros()).min(MAX_EXP);
+    if exp == MAX_EXP && us >> MAX_EXP > 1 {
+        return FINE_BUCKETS - 1;
+    }
+    let sub = ((us >> (exp - SUB_BITS)) as usize) & (SUB_BUCKETS - 1);
+    SUB_BUCKETS + (exp - SUB_BITS) as usize * SUB_BUCKETS + sub
+}
+
+/// Value range `[low, high)` in microseconds covered by a fine bucket.
+fn fine_bounds(index: usize) -> (u64, u64) {
+    if index < SUB_BUCKETS {
+        return (index as u64, index as u64 + 1);
+    }
+    let exp = SUB_BITS + ((index - SUB_BUCKETS) / SUB_BUCKETS) as u32;
+    let sub = ((index - SUB_BUCKETS) % SUB_BUCKETS) as u64;
+    let width = 1u64 << (exp - SUB_BITS);
+    let low = (1u64 << exp) + sub * width;
+    (low, low + width)
+}
+
+/// Percentiles and buckets of one histogram, in milliseconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistogramSummary {
+    pub count: u64,
+    pub sum_ms: f64,
+    pub mean_ms: Option<f64>,
+    pub p50: Option<f64>,
+    pub p90: Option<f64>,
+    pub p95: Option<f64>,
+    pub p99: Option<f64>,
+    pub p999: Option<f64>,
+    pub max: Option<f64>,
+    /// Cumulative counts of values <= `le_ms`.
+    pub buckets: Vec<BucketCount>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BucketCount {
+    pub le_ms: f64,
+    pub count: u64,
+}
+
+/// Lifetime and 5-minute summaries of a [`WindowedHistogram`].
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowedSummary {
+    pub window_5m: HistogramSummary,
+    pub lifetime: HistogramSummary,
+}
+
+/// Lifetime histogram plus a rolling 5-minute window.
+#[derive(Clone, Default)]
+pub struct WindowedHistogram {
+    lifetime: Histogram,
+    /// (slot epoch, histogram); slot epoch = unix seconds / WINDOW_SLOT_SECS.
+    slots: Vec<(u64, Histogram)>,
+}
+
+impl WindowedHistogram {
+    pub fn record(&mut self, duration: Duration, now_secs: u64) {
+        self.lifetime.record(duration);
+        if self.slots.is_empty() {
+            self.slots = vec![(u64::MAX, Histogram::default()); WINDOW_SLOTS];
+        }
+        let epoch = now_secs / WINDOW_SLOT_SECS;
+        let slot = &mut self.slots[(epoch % WINDOW_SLOTS as u64) as usize];
+        if slot.0 != epoch {
+            *slot = (epoch, Histogram::default());
+        }
+        slot.1.record(duration);
+    }
+
+    pub fn lifetime(&self) -> &Histogram {
+        &self.lifetime
+    }
+
+    /// Merged histogram of the slots covering the last 5 minutes.
+    pub fn window(&self, now_secs: u64) -> Histogram {
+        let current = now_secs / WINDOW_SLOT_SECS;
+        let oldest = current.saturating_sub(WINDOW_SLOTS as u64 - 1);
+        let mut merged = Histogram::default();
+        for (epoch, histogram) in &self.slots {
+            if (oldest..=current).contains(epoch) {
+                merged.merge(histogram);
+            }
+        }
+        merged
+    }
+
+    pub fn summary(&self, now_secs: u64) -> WindowedSummary {
+        WindowedSummary {
+            window_5m: self.window(now_secs).summary(),
+            lifetime: self.lifetime.summary(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_bounds_cover_values() {
+        for us in [0u64, 1, 7, 8, 9, 15, 16, 100, 1023, 1024, 123_456, 1 << 31] {
+            let (low, high) = fine_bounds(fine_index(us));
+            assert!(low <= us && us < high, "{us} not in [{low}, {high})");
+        }
+        assert_eq!(fine_index(u64::MAX), FINE_BUCKETS - 1);
+    }
+
+    #[test]
+    fn test_quantiles_within_bucket_error() {
+        let mut h = Histogram::default();
+        for ms in 1..=1000u64 {
+            h.record(Duration::from_millis(ms));
+        }
+        let p50 = h.quantile_ms(0.50).unwrap();
+        let p99 = h.quantile_ms(0.99).unwrap();
+        assert!((p50 - 500.0).abs() / 500.0 < 0.125, "p50 = {p50}");
+        assert!((p99 - 990.0).abs() / 990.0 < 0.125, "p99 = {p99}");
+        assert_eq!(h.quantile_ms(1.0), Some(1000.0));
+
+        let buckets = h.cumulative_buckets();
+        assert_eq!(buckets[1], (1.0, 1));
+        assert_eq!(buckets.last().unwrap().1, 1000);
+    }
+
+    #[test]
+    fn test_window_drops_old_slots() {
+        let mut h = WindowedHistogram::default();
+        h.record(Duration::from_millis(5), 1_000);
+        h.record(Duration::from_millis(7), 1_200);
+        assert_eq!(h.window(1_200).count(), 2);
+        // Six minutes later only the lifetime totals remember the first sample.
+        h.record(Duration::from_millis(9), 1_360);
+        assert_eq!(h.window(1_360).count(), 2);
+        assert_eq!(h.window(1_600).count(), 1);
+        assert_eq!(h.lifetime().count(), 3);
+    }
+}
@@ -0,0 +1,145 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Binary protocol accounting per message type.
+//!
+//! Every frame handled by a session is counted under its message type with
+//! request and response payload sizes and end-to-end latency, so unusual
+//! request patterns (huge GET_LAST limits, oversized appends) show up without
+//! client cooperation. Unknown message types share one series; their type
+//! numbers are counted separately, so frames from clients newer than the
+//! server are visible.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::histogram::{WindowedHistogram, WindowedSummary};
+
+#[derive(Default)]
+struct MessageCounters {
+    count: u64,
+    errors: u64,
+    slow: u64,
+    request_bytes: u64,
+    response_bytes: u64,
+    max_request_bytes: u64,
+    max_response_bytes: u64,
+    latency: WindowedHistogram,
+}
+
+/// One handled frame.
+pub struct MessageSample {
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+    pub duration: Duration,
+    /// Answered with an ERROR frame.
+    pub error: bool,
+    /// Exceeded the slow-request threshold.
+    pub slow: bool,
+}
+
+#[derive(Default)]
+pub struct ProtocolMetrics {
+    messages: BTreeMap<&'static str, MessageCounters>,
+    /// Frames answered UNSUPPORTED_MESSAGE, by message type number.
+    unsupported: BTreeMap<u16, u64>,
+}
+
+impl ProtocolMetrics {
+    pub fn record(&mut self, msg_type: &'static str, sample: &MessageSample, now_secs: u64) {
+        let counters = self.messages.entry(msg_type).or_default();
+        counters.count += 1;
+        counters.errors += u64::from(sample.error);
+        counters.slow += u64::from(sample.slow);
+        counters.request_bytes += sample.request_bytes;
+        counters.response_bytes += sample.response_bytes;
+        counters.max_request_bytes = counters.max_request_bytes.max(sample.request_bytes);
+        counters.max_response_bytes = counters.max_response_bytes.max(sample.response_bytes);
+        counters.latency.record(sample.duration, now_secs);
+    }
+
+    /// Count a frame of a message type this server does not implement.
+    pub fn record_unsupported(&mut self, msg_type: u16) {
+        *self.unsupported.entry(msg_type).or_default() += 1;
+    }
+
+    /// Unsupported frames by message type number.
+    pub fn unsupported(&self) -> BTreeMap<u16, u64> {
+        self.unsupported.clone()
+    }
+
+    /// Message types seen so far, sorted by name.
+    pub fn summary(&self, now_secs: u64) -> Vec<MessageSummary> {
+        self.messages
+            .iter()
+            .map(|(msg_type, c)| MessageSummary {
+                msg_type: msg_type.to_string(),
+                count: c.count,
+                errors: c.errors,
+                slow: c.slow,
+                request_bytes: c.request_bytes,
+                response_bytes: c.response_bytes,
+                max_request_bytes: c.max_request_bytes,
+                max_response_bytes: c.max_response_bytes,
+                latency: c.latency.summary(now_secs),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageSummary {
+    pub msg_type: String,
+    pub count: u64,
+    pub errors: u64,
+    /// Requests over `CXDB_SLOW_REQUEST_MS`.
+    pub slow: u64,
+    /// Frame payload bytes received.
+    pub request_bytes: u64,
+    /// Frame payload bytes sent, including ERROR frames.
+    pub response_bytes: u64,
+    pub max_request_bytes: u64,
+    pub max_response_bytes: u64,
+    pub latency: WindowedSummary,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_are_accounted_per_type() {
+        let mut protocol = ProtocolMetrics::default();
+        let sample = |request_bytes, response_bytes, error| MessageSample {
+            request_bytes,
+            response_bytes,
+            duration: Duration::from_millis(2),
+            error,
+            slow: false,
+        };
+        protocol.record("GET_LAST", &sample(20, 5000, false), 0);
+        protocol.record("GET_LAST", &sample(20, 90_000, false), 0);
+        protocol.record("APPEND_TURN", &sample(300, 40, false), 0);
+        protocol.record("APPEND_TURN", &sample(12, 30, true), 0);
+
+        let summary = protocol.summary(0);
+        let names: Vec<&str> = summary.iter().map(|m| m.msg_type.as_str()).collect();
+        assert_eq!(names, ["APPEND_TURN", "GET_LAST"]);
+        assert_eq!(summary[0].errors, 1);
+        assert_eq!(summary[0].request_bytes, 312);
+        assert_eq!(summary[1].response_bytes, 95_000);
+        assert_eq!(summary[1].max_response_bytes, 90_000);
+        assert_eq!(summary[1].latency.lifetime.count, 2);
+    }
+
+    #[test]
+    fn test_unsupported_messages_are_counted_per_type_number() {
+        let mut protocol = ProtocolMetrics::default();
+        protocol.record_unsupported(40);
+        protocol.record_unsupported(40);
+        protocol.record_unsupported(7000);
+        assert_eq!(protocol.unsupported(), BTreeMap::from([(40, 2), (7000, 1)]));
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Load accounting per client tag.
+//!
+//! Appends, bytes and read latency are kept per client tag so load can be
+//! attributed. Sessions without a tag count under [`UNTAGGED`]. Once
+//! `max_tags` distinct tags are tracked, further tags are folded into
+//! [`OTHER_TAGS`] so the number of exported series stays bounded.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::histogram::{WindowedHistogram, WindowedSummary};
+
+/// Label for operations from sessions that sent no client tag.
+pub const UNTAGGED: &str = "_untagged";
+/// Label for tags beyond the cardinality cap.
+pub const OTHER_TAGS: &str = "_other";
+
+#[derive(Default)]
+struct TagCounters {
+    appends: u64,
+    append_bytes: u64,
+    reads: u64,
+    read_bytes: u64,
+    read_latency: WindowedHistogram,
+}
+
+pub struct TagMetrics {
+    max_tags: usize,
+    /// Distinct client tags tracked under their own name.
+    distinct: usize,
+    tags: HashMap<String, TagCounters>,
+}
+
+impl TagMetrics {
+    pub fn new(max_tags: usize) -> Self {
+        Self {
+            max_tags,
+            distinct: 0,
+            tags: HashMap::new(),
+        }
+    }
+
+    pub fn record_append(&mut self, tag: &str, bytes: u64) {
+        let counters = self.counters(tag);
+        counters.appends += 1;
+        counters.append_bytes += bytes;
+    }
+
+    pub fn record_read(&mut self, tag: &str, bytes: u64, duration: Duration, now_secs: u64) {
+        let counters = self.counters(tag);
+        counters.reads += 1;
+        counters.read_bytes += bytes;
+        counters.read_latency.record(duration, now_secs);
+    }
+
+    fn counters(&mut self, tag: &str) -> &mut TagCounters {
+        let label = if tag.is_empty() {
+            UNTAGGED
+        } else if self.tags.contains_key(tag) {
+            tag
+        } else if self.distinct < self.max_tags {
+            self.distinct += 1;
+            tag
+        } else {
+            OTHER_TAGS
+        };
+        self.tags.entry(label.to_string()).or_default()
+    }
+
+    /// Every tracked tag, sorted by name, plus the `top_n` heaviest by bytes
+    /// appended and read.
+    pub fn summary(&self, now_secs: u64, top_n: usize) -> TagMetricsSnapshot {
+        let mut tags: Vec<TagSummary> = self
+            .tags
+            .iter()
+            .map(|(tag, c)| TagSummary {
+                tag: tag.clone(),
+                appends: c.appends,
+                append_bytes: c.append_bytes,
+                reads: c.reads,
+                read_bytes: c.read_bytes,
+                read_latency: c.read_latency.summary(now_secs),
+            })
+            .collect();
+        tags.sort_by(|a, b| a.tag.cmp(&b.tag));
+
+        let mut heaviest: Vec<HeavyTag> = tags
+            .iter()
+            .map(|t| HeavyTag {
+                tag: t.tag.clone(),
+                total_bytes: t.append_bytes + t.read_bytes,
+                appends: t.appends,
+                reads: t.reads,
+            })
+            .collect();
+        heaviest.sort_by(|a, b| {
+            b.total_bytes
+                .cmp(&a.total_bytes)
+                .then_with(|| a.tag.cmp(&b.tag))
+        });
+        heaviest.truncate(top_n);
+
+        TagMetricsSnapshot {
+            max_tags: self.max_tags,
+            tags,
+            heaviest,
+        }
+    }
+}
+
+/// Per-tag section of the metrics snapshot.
+#[derive(Debug, Clone, Serialize)]
+pub struct TagMetricsSnapshot {
+    pub max_tags: usize,
+    pub tags: Vec<TagSummary>,
+    /// Tags ranked by `append_bytes + read_bytes`.
+    pub heaviest: Vec<HeavyTag>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TagSummary {
+    pub tag: String,
+    pub appends: u64,
+    /// Uncompressed payload bytes appended.
+    pub append_bytes: u64,
+    /// GET_LAST and GET_BLOB requests.
+    pub reads: u64,
+    pub read_bytes: u64,
+    pub read_latency: WindowedSummary,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HeavyTag {
+    pub tag: String,
+    pub total_bytes: u64,
+    pub appends: u64,
+    pub reads: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tags_beyond_the_cap_fold_into_other() {
+        let mut tags = TagMetrics::new(2);
+        tags.record_append("a", 10);
+        tags.record_append("b", 20);
+        tags.record_append("c", 30);
+        tags.record_append("d", 40);
+        tags.record_append("a", 5);
+        tags.record_append("", 1);
+
+        let summary = tags.summary(0, 2);
+        let names: Vec<&str> = summary.tags.iter().map(|t| t.tag.as_str()).collect();
+        assert_eq!(names, ["_other", "_untagged", "a", "b"]);
+        assert_eq!(summary.tags[0].append_bytes, 70);
+        assert_eq!(summary.tags[2].appends, 2);
+        assert_eq!(summary.heaviest[0].tag, "_other");
+        assert_eq!(summary.heaviest[1].tag, "b");
+    }
+}
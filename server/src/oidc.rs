@@ -0,0 +1,576 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! OpenID Connect bearer tokens.
+//!
+//! When `CXDB_OIDC_ISSUER` is set, HTTP requests may carry
+//! `Authorization: Bearer <jwt>` and binary protocol clients may send the
+//! token in HELLO. Tokens are verified against the issuer's JSON Web Key
+//! Set, fetched from `CXDB_OIDC_JWKS_URL` or the issuer's discovery
+//! document. The key set is cached for `CXDB_OIDC_JWKS_CACHE_SECS` and
+//! refetched early when a token names a key id it does not contain, so
+//! signing keys can rotate without a restart.
+//!
+//! A verified token's principal is its `CXDB_OIDC_PRINCIPAL_CLAIM` claim
+//! (default `email`, falling back to `sub`). Its access level comes from
+//! the role rules in the JSON file named by `CXDB_OIDC_ROLE_RULES`:
+//!
+//! ```text
+//! {
+//!   "default": "read",
+//!   "rules": [
+//!     { "claim": "email", "value": "*@contractor.example.com", "access": "none" },
+//!     { "claim": "groups", "value": "cxdb-admins", "access": "write" },
+//!     { "claim": "realm_access.roles", "value": "cxdb-*", "access": "write" }
+//!   ]
+//! }
+//! ```
+//!
+//! A rule matches when the claim (a dotted path into nested objects) is a
+//! string equal to `value`, or an array containing one; `value` may hold
+//! one `*` wildcard. The first matching rule applies, else `default`. Without a rules file every verified token has write access.
+
+use std::path::Path;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+use serde_json::{Map, Value as JsonValue};
+
+use crate::access::Access;
+use crate::error::{Result, StoreError};
+
+const DEFAULT_JWKS_CACHE_SECS: u64 = 3_600;
+const DEFAULT_LEEWAY_SECS: u64 = 60;
+const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Minimum time between key set fetches triggered by unknown key ids.
+const DEFAULT_JWKS_REFETCH_MIN: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    /// Expected `iss` claim.
+    pub issuer: String,
+    /// Required `aud` entry, if any.
+    pub audience: Option<String>,
+    /// Key set location; None discovers it from the issuer.
+    pub jwks_url: Option<String>,
+    pub principal_claim: String,
+    pub roles: RoleRules,
+    /// Reject `/v1` HTTP requests that carry no bearer token.
+    pub required: bool,
+    pub jwks_cache: Duration,
+    pub jwks_refetch_min: Duration,
+    /// Clock skew tolerated for `exp` and `nbf`.
+    pub leeway: Duration,
+    pub fetch_timeout: Duration,
+}
+
+impl OidcConfig {
+    /// Config for `issuer` with the defaults `from_env` uses.
+    pub fn new(issuer: impl Into<String>) -> Self {
+        Self {
+            issuer: issuer.into(),
+            audience: None,
+            jwks_url: None,
+            principal_claim: "email".into(),
+            roles: RoleRules::allow_all(),
+            required: false,
+            jwks_cache: Duration::from_secs(DEFAULT_JWKS_CACHE_SECS),
+            jwks_refetch_min: DEFAULT_JWKS_REFETCH_MIN,
+            leeway: Duration::from_secs(DEFAULT_LEEWAY_SECS),
+            fetch_timeout: DEFAULT_FETCH_TIMEOUT,
+        }
+    }
+
+    /// Load config from environment variables. Returns None when
+    /// `CXDB_OIDC_ISSUER` is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let Some(issuer) = var("CXDB_OIDC_ISSUER") else {
+            return Ok(None);
+        };
+        let number = |name: &str, default: u64| -> Result<u64> {
+            match var(name) {
+                Some(v) => v
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput(format!("invalid {name} {v:?}"))),
+                None => Ok(default),
+            }
+        };
+        let required = match var("CXDB_OIDC_REQUIRED").as_deref() {
+            None | Some("0") | Some("false") => false,
+            Some("1") | Some("true") => true,
+            Some(other) => {
+                return Err(StoreError::InvalidInput(format!(
+                    "invalid CXDB_OIDC_REQUIRED {other:?} (expected true or false)"
+                )))
+            }
+        };
+        let mut config = Self::new(issuer);
+        config.audience = var("CXDB_OIDC_AUDIENCE");
+        config.jwks_url = var("CXDB_OIDC_JWKS_URL");
+        if let Some(claim) = var("CXDB_OIDC_PRINCIPAL_CLAIM") {
+            config.principal_claim = claim;
+        }
+        if let Some(path) = var("CXDB_OIDC_ROLE_RULES") {
+            config.roles = RoleRules::load(Path::new(&path))?;
+        }
+        config.required = required;
+        config.jwks_cache = Duration::from_secs(number(
+            "CXDB_OIDC_JWKS_CACHE_SECS",
+            DEFAULT_JWKS_CACHE_SECS,
+        )?);
+        config.leeway = Duration::from_secs(number("CXDB_OIDC_LEEWAY_SECS", DEFAULT_LEEWAY_SECS)?);
+        Ok(Some(config))
+    }
+}
+
+/// Claim to access level rules.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RoleRules {
+    /// Access when no rule matches.
+    #[serde(default = "default_access")]
+    pub default: Access,
+    #[serde(default)]
+    pub rules: Vec<RoleRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RoleRule {
+    /// Claim name; dots descend into nested objects.
+    pub claim: String,
+    /// Expected value, with at most one `*` wildcard.
+    pub value: String,
+    pub access: Access,
+}
+
+fn default_access() -> Access {
+    Access::None
+}
+
+impl RoleRules {
+    /// Rules that grant write access to every verified token.
+    pub fn allow_all() -> Self {
+        Self {
+            default: Access::Write,
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        serde_json::from_slice(&data)
+            .map_err(|e| StoreError::InvalidInput(format!("role rules {}: {e}", path.display())))
+    }
+
+    /// Access of the first rule the claims match, else `default`.
+    pub fn access_for(&self, claims: &Map<String, JsonValue>) -> Access {
+        self.rules
+            .iter()
+            .find(|rule| {
+                let Some(value) = claim(claims, &rule.claim) else {
+                    return false;
+                };
+                match value {
+                    JsonValue::String(s) => wildcard_match(&rule.value, s),
+                    JsonValue::Array(items) => items
+                        .iter()
+                        .filter_map(JsonValue::as_str)
+                        .any(|s| wildcard_match(&rule.value, s)),
+                    JsonValue::Bool(_) | JsonValue::Number(_) => {
+                        wildcard_match(&rule.value, &value.to_string())
+                    }
+                    _ => false,
+                }
+            })
+            .map_or(self.default, |rule| rule.access)
+    }
+}
+
+fn claim<'a>(claims: &'a Map<String, JsonValue>, path: &str) -> Option<&'a JsonValue> {
+    let mut parts = path.split('.');
+    let mut value = claims.get(parts.next()?)?;
+    for part in parts {
+        value = value.as_object()?.get(part)?;
+    }
+    Some(value)
+}
+
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => {
+            value.len() >= prefix.len() + suffix.len()
+                && value.starts_with(prefix)
+                && value.ends_with(suffix)
+        }
+        None => pattern == value,
+    }
+}
+
+/// Who a verified token belongs to and what it may do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenIdentity {
+    pub principal: String,
+    pub subject: String,
+    pub access: Access,
+    pub expires_at_unix_ms: u64,
+}
+
+/// One key of a JSON Web Key Set.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(default)]
+    pub kid: Option<String>,
+    #[serde(default)]
+    pub alg: Option<String>,
+    #[serde(default, rename = "use")]
+    pub key_use: Option<String>,
+    #[serde(default)]
+    pub n: Option<String>,
+    #[serde(default)]
+    pub e: Option<String>,
+    #[serde(default)]
+    pub crv: Option<String>,
+    #[serde(default)]
+    pub x: Option<String>,
+    #[serde(default)]
+    pub y: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+#[derive(Default)]
+struct KeyCache {
+    keys: Vec<Jwk>,
+    /// Last successful fetch.
+    fetched_at: Option<Instant>,
+    /// Last fetch attempt, successful or not.
+    checked_at: Option<Instant>,
+}
+
+/// Verifies bearer tokens and caches the issuer's signing keys.
+pub struct OidcVerifier {
+    config: OidcConfig,
+    agent: ureq::Agent,
+    keys: RwLock<KeyCache>,
+    /// Held while fetching the key set, so that one request fetches and
+    /// the others wait for its result.
+    fetching: Mutex<()>,
+}
+
+impl OidcVerifier {
+    pub fn new(config: OidcConfig) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(config.fetch_timeout)
+            .build();
+        Self {
+            config,
+            agent,
+            keys: RwLock::new(KeyCache::default()),
+            fetching: Mutex::new(()),
+        }
+    }
+
+    pub fn config(&self) -> &OidcConfig {
+        &self.config
+    }
+
+    /// Verify the token of an `Authorization` header value. Headers using
+    /// another scheme yield None.
+    pub fn authenticate(&self, authorization: Option<&str>) -> Result<Option<TokenIdentity>> {
+        match authorization.and_then(bearer_token) {
+            Some(token) => self.verify(token).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn verify(&self, token: &str) -> Result<TokenIdentity> {
+        let mut parts = token.split('.');
+        let (Some(header_b64), Some(claims_b64), Some(signature_b64), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(unauthenticated("malformed token"));
+        };
+        let header: JwtHeader = serde_json::from_slice(&decode_segment(header_b64)?)
+            .map_err(|_| unauthenticated("malformed token header"))?;
+        let claims: Map<String, JsonValue> =
+            serde_json::from_slice(&decode_segment(claims_b64)?)
+                .map_err(|_| unauthenticated("malformed token claims"))?;
+        let signature = decode_segment(signature_b64)?;
+
+        let key = self.key_for(header.kid.as_deref(), &header.alg)?;
+        let signed = &token[..header_b64.len() + 1 + claims_b64.len()];
+        verify_signature(&header.alg, &key, signed.as_bytes(), &signature)?;
+        self.check_claims(&claims)
+    }
+
+    fn check_claims(&self, claims: &Map<String, JsonValue>) -> Result<TokenIdentity> {
+        if claims.get("iss").and_then(JsonValue::as_str) != Some(self.config.issuer.as_str()) {
+            return Err(unauthenticated("token issuer does not match"));
+        }
+        if let Some(audience) = &self.config.audience {
+            let matches = match claims.get("aud") {
+                Some(JsonValue::String(aud)) => aud == audience,
+                Some(JsonValue::Array(auds)) => auds.iter().any(|a| a.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !matches {
+                return Err(unauthenticated("token audience does not match"));
+            }
+        }
+        let now = unix_secs();
+        let leeway = self.config.leeway.as_secs();
+        let exp = claims
+            .get("exp")
+            .and_then(JsonValue::as_u64)
+            .ok_or_else(|| unauthenticated("token has no exp"))?;
+        if now > exp.saturating_add(leeway) {
+            return Err(unauthenticated("token expired"));
+        }
+        if let Some(nbf) = claims.get("nbf").and_then(JsonValue::as_u64) {
+            if now.saturating_add(leeway) < nbf {
+                return Err(unauthenticated("token not yet valid"));
+            }
+        }
+        let subject = claims
+            .get("sub")
+            .and_then(JsonValue::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let principal = claim(claims, &self.config.principal_claim)
+            .and_then(JsonValue::as_str)
+            .filter(|p| !p.is_empty())
+            .map(str::to_string)
+            .unwrap_or_else(|| subject.clone());
+        if principal.is_empty() {
+            return Err(unauthenticated("token names no principal"));
+        }
+        Ok(TokenIdentity {
+            principal,
+            subject,
+            access: self.config.roles.access_for(claims),
+            expires_at_unix_ms: exp.saturating_mul(1000),
+        })
+    }
+
+    /// The signing key for a token, fetching the key set when the cache is
+    /// stale or, at most every `jwks_refetch_min`, when the key is unknown.
+    /// The fetch runs without the cache lock held, one at a time.
+    fn key_for(&self, kid: Option<&str>, alg: &str) -> Result<Jwk> {
+        let find = |cache: &KeyCache| {
+            cache
+                .keys
+                .iter()
+                .find(|k| {
+                    kid.is_none_or(|kid| k.kid.as_deref() == Some(kid))
+                        && k.alg.as_deref().is_none_or(|a| a == alg)
+                        && k.key_use.as_deref().is_none_or(|u| u == "sig")
+                })
+                .cloned()
+        };
+        // The answer the cache gives without fetching, if it gives one.
+        let cached = |cache: &KeyCache| {
+            let fresh = cache
+                .fetched_at
+                .is_some_and(|t| t.elapsed() < self.config.jwks_cache);
+            let may_refetch = cache
+                .checked_at
+                .is_none_or(|t| t.elapsed() >= self.config.jwks_refetch_min);
+            match find(cache) {
+                Some(key) if fresh || !may_refetch => Some(Ok(key)),
+                None if !may_refetch => Some(Err(unauthenticated("unknown signing key"))),
+                _ => None,
+            }
+        };
+        if let Some(result) = cached(&self.keys.read().unwrap()) {
+            return result;
+        }
+        let _fetching = self.fetching.lock().unwrap();
+        // A request fetched while this one waited.
+        if let Some(result) = cached(&self.keys.read().unwrap()) {
+            return result;
+        }
+        let fetched = self.fetch_keys();
+        let mut cache = self.keys.write().unwrap();
+        cache.checked_at = Some(Instant::now());
+        match fetched {
+            Ok(keys) => {
+                cache.keys = keys;
+                cache.fetched_at = cache.checked_at;
+            }
+            // Keep serving the cached keys while the issuer is unreachable.
+            Err(err) if cache.fetched_at.is_some() => {
+                eprintln!("oidc: failed to refresh signing keys: {err}");
+            }
+            Err(err) => return Err(err),
+        }
+        find(&cache).ok_or_else(|| unauthenticated("unknown signing key"))
+    }
+
+    fn fetch_keys(&self) -> Result<Vec<Jwk>> {
+        let url = match &self.config.jwks_url {
+            Some(url) => url.clone(),
+            None => {
+                let discovery = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.config.issuer.trim_end_matches('/')
+                );
+                let doc: JsonValue = self.get_json(&discovery)?;
+                doc.get("jwks_uri")
+                    .and_then(JsonValue::as_str)
+                    .ok_or_else(|| unauthenticated("issuer discovery document has no jwks_uri"))?
+                    .to_string()
+            }
+        };
+        let set: JwkSet = serde_json::from_value(self.get_json(&url)?)
+            .map_err(|e| unauthenticated(&format!("invalid key set: {e}")))?;
+        Ok(set.keys)
+    }
+
+    fn get_json(&self, url: &str) -> Result<JsonValue> {
+        self.agent
+            .get(url)
+            .call()
+            .map_err(|e| unauthenticated(&format!("fetching {url}: {e}")))?
+            .into_json()
+            .map_err(|e| unauthenticated(&format!("fetching {url}: {e}")))
+    }
+}
+
+/// The token of a `Bearer` authorization value.
+pub fn bearer_token(authorization: &str) -> Option<&str> {
+    let (scheme, token) = authorization.trim().split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| token.trim())
+        .filter(|t| !t.is_empty())
+}
+
+fn verify_signature(alg: &str, key: &Jwk, message: &[u8], sig: &[u8]) -> Result<()> {
+    let field = |value: &Option<String>| -> Result<Vec<u8>> {
+        decode_segment(
+            value
+                .as_deref()
+                .ok_or_else(|| unauthenticated("incomplete key"))?,
+        )
+    };
+    let verified = match (alg, key.kty.as_str()) {
+        ("RS256" | "RS384" | "RS512", "RSA") => {
+            let params = match alg {
+                "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+                "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+                _ => &signature::RSA_PKCS1_2048_8192_SHA512,
+            };
+            let components = RsaPublicKeyComponents {
+                n: field(&key.n)?,
+                e: field(&key.e)?,
+            };
+            components.verify(params, message, sig).is_ok()
+        }
+        ("ES256" | "ES384", "EC") => {
+            let (params, crv) = match alg {
+                "ES256" => (&signature::ECDSA_P256_SHA256_FIXED, "P-256"),
+                _ => (&signature::ECDSA_P384_SHA384_FIXED, "P-384"),
+            };
+            if key.crv.as_deref() != Some(crv) {
+                return Err(unauthenticated("key curve does not match alg"));
+            }
+            let mut point = vec![0x04];
+            point.extend(field(&key.x)?);
+            point.extend(field(&key.y)?);
+            UnparsedPublicKey::new(params, point)
+                .verify(message, sig)
+                .is_ok()
+        }
+        ("EdDSA", "OKP") if key.crv.as_deref() == Some("Ed25519") => {
+            UnparsedPublicKey::new(&signature::ED25519, field(&key.x)?)
+                .verify(message, sig)
+                .is_ok()
+        }
+        _ => return Err(unauthenticated(&format!("unsupported token alg {alg:?}"))),
+    };
+    if verified {
+        Ok(())
+    } else {
+        Err(unauthenticated("invalid token signature"))
+    }
+}
+
+fn decode_segment(segment: &str) -> Result<Vec<u8>> {
+    URL_SAFE_NO_PAD
+        .decode(segment.trim_end_matches('='))
+        .map_err(|_| unauthenticated("malformed token"))
+}
+
+fn unauthenticated(message: &str) -> StoreError {
+    StoreError::Unauthenticated(message.to_string())
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_role_rules() {
+        let rules: RoleRules = serde_json::from_value(json!({
+            "default": "read",
+            "rules": [
+                { "claim": "email", "value": "*@contractor.example.com", "access": "none" },
+                { "claim": "email_verified", "value": "false", "access": "none" },
+                { "claim": "groups", "value": "cxdb-admins", "access": "write" },
+                { "claim": "realm_access.roles", "value": "cxdb-*", "access": "write" }
+            ]
+        }))
+        .unwrap();
+        let access = |claims: JsonValue| rules.access_for(claims.as_object().unwrap());
+        assert_eq!(
+            access(json!({"groups": ["eng", "cxdb-admins"]})),
+            Access::Write
+        );
+        assert_eq!(
+            access(json!({"realm_access": {"roles": ["cxdb-editor"]}})),
+            Access::Write
+        );
+        assert_eq!(
+            access(json!({"email": "pat@contractor.example.com"})),
+            Access::None
+        );
+        assert_eq!(access(json!({"email_verified": false})), Access::None);
+        assert_eq!(access(json!({"email": "pat@example.com"})), Access::Read);
+        // The first matching rule wins.
+        assert_eq!(
+            access(json!({"email": "pat@contractor.example.com", "groups": "cxdb-admins"})),
+            Access::None
+        );
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(bearer_token("Bearer abc.def.ghi"), Some("abc.def.ghi"));
+        assert_eq!(bearer_token("bearer  abc "), Some("abc"));
+        assert_eq!(bearer_token("Basic dXNlcjpwYXNz"), None);
+        assert_eq!(bearer_token("Bearer "), None);
+    }
+}
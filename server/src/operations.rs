@@ -0,0 +1,489 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Long-running operations.
+//!
+//! Requests that can take minutes (backfills, rebuilds, exports, compaction)
+//! are submitted as operations instead of blocking an HTTP request. Each
+//! operation gets an ID and is queued for a fixed pool of worker threads.
+//! Progress, state and the final result are polled via
+//! `GET /v1/operations/{id}`; `POST /v1/operations/{id}/cancel` requests
+//! cancellation, which running work observes at its next
+//! [`OperationHandle::check_cancelled`] call. When an operation finishes, an
+//! `operation_completed` event is published on the event bus.
+//!
+//! Operations are kept in memory only and do not survive a restart.
+
+use std::collections::{HashMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+use crate::error::{Result, StoreError};
+use crate::events::{EventBus, StoreEvent};
+use crate::util::{env_usize, unix_ms};
+
+/// Configuration for the operations worker pool.
+#[derive(Debug, Clone)]
+pub struct OperationsConfig {
+    /// Number of worker threads running operations concurrently.
+    pub workers: usize,
+    /// Number of finished operations kept for polling.
+    pub history: usize,
+}
+
+impl OperationsConfig {
+    /// Load config from environment variables with defaults.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            workers: env_usize("CXDB_OPERATION_WORKERS", defaults.workers).max(1),
+            history: env_usize("CXDB_OPERATION_HISTORY", defaults.history),
+        }
+    }
+}
+
+impl Default for OperationsConfig {
+    fn default() -> Self {
+        Self {
+            workers: 2,
+            history: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationState {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl OperationState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OperationState::Queued => "queued",
+            OperationState::Running => "running",
+            OperationState::Succeeded => "succeeded",
+            OperationState::Failed => "failed",
+            OperationState::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            OperationState::Succeeded | OperationState::Failed | OperationState::Cancelled
+        )
+    }
+}
+
+/// Snapshot of an operation's state.
+#[derive(Debug, Clone, Serialize)]
+pub struct OperationInfo {
+    pub id: String,
+    pub kind: String,
+    pub state: OperationState,
+    pub done: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    /// Human-readable description of the current step.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub cancel_requested: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<JsonValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created_at_unix_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at_unix_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at_unix_ms: Option<u64>,
+    pub updated_at_unix_ms: u64,
+}
+
+type Work = Box<dyn FnOnce(&OperationHandle) -> Result<JsonValue> + Send + 'static>;
+
+struct Job {
+    id: u64,
+    cancel: Arc<AtomicBool>,
+    work: Work,
+}
+
+struct Entry {
+    info: OperationInfo,
+    cancel: Arc<AtomicBool>,
+}
+
+/// Registry and worker pool for long-running operations.
+pub struct Operations {
+    next_id: AtomicU64,
+    ops: RwLock<HashMap<u64, Entry>>,
+    finished: Mutex<VecDeque<u64>>,
+    history: usize,
+    queue: Mutex<Sender<Job>>,
+    event_bus: Arc<EventBus>,
+}
+
+/// Handle passed to a running operation for progress reporting and
+/// cancellation checks.
+pub struct OperationHandle {
+    id: u64,
+    cancel: Arc<AtomicBool>,
+    ops: Arc<Operations>,
+}
+
+impl OperationHandle {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn set_total(&self, total: u64) {
+        self.ops.update(self.id, |op| op.total = Some(total));
+    }
+
+    pub fn advance(&self, n: u64) {
+        self.ops.update(self.id, |op| op.done += n);
+    }
+
+    pub fn set_message(&self, message: impl Into<String>) {
+        let message = message.into();
+        self.ops.update(self.id, |op| op.message = Some(message));
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::SeqCst)
+    }
+
+    /// Returns `StoreError::Cancelled` once cancellation was requested.
+    /// Long-running work should call this between units of work.
+    pub fn check_cancelled(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(StoreError::Cancelled(format!("operation {}", self.id)));
+        }
+        Ok(())
+    }
+}
+
+impl Operations {
+    /// Create the operations registry and start its worker threads.
+    pub fn start(config: OperationsConfig, event_bus: Arc<EventBus>) -> Arc<Self> {
+        let (tx, rx) = mpsc::channel::<Job>();
+        let ops = Arc::new(Self {
+            next_id: AtomicU64::new(0),
+            ops: RwLock::new(HashMap::new()),
+            finished: Mutex::new(VecDeque::new()),
+            history: config.history,
+            queue: Mutex::new(tx),
+            event_bus,
+        });
+
+        let rx = Arc::new(Mutex::new(rx));
+        for i in 0..config.workers {
+            let ops = Arc::clone(&ops);
+            let rx = Arc::clone(&rx);
+            thread::Builder::new()
+                .name(format!("cxdb-op-worker-{i}"))
+                .spawn(move || worker_loop(ops, rx))
+                .expect("spawn operation worker");
+        }
+        ops
+    }
+
+    /// Queue `work` as a new operation; returns its ID.
+    pub fn submit<F>(&self, kind: &str, work: F) -> u64
+    where
+        F: FnOnce(&OperationHandle) -> Result<JsonValue> + Send + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let now = unix_ms();
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.ops.write().unwrap().insert(
+            id,
+            Entry {
+                info: OperationInfo {
+                    id: id.to_string(),
+                    kind: kind.to_string(),
+                    state: OperationState::Queued,
+                    done: 0,
+                    total: None,
+                    message: None,
+                    cancel_requested: false,
+                    result: None,
+                    error: None,
+                    created_at_unix_ms: now,
+                    started_at_unix_ms: None,
+                    finished_at_unix_ms: None,
+                    updated_at_unix_ms: now,
+                },
+                cancel: Arc::clone(&cancel),
+            },
+        );
+
+        let job = Job {
+            id,
+            cancel,
+            work: Box::new(work),
+        };
+        if self.queue.lock().unwrap().send(job).is_err() {
+            self.finish(id, Err(StoreError::Io(std::io::Error::other("no workers"))));
+        }
+        id
+    }
+
+    pub fn get(&self, id: u64) -> Option<OperationInfo> {
+        self.ops.read().unwrap().get(&id).map(|e| e.info.clone())
+    }
+
+    /// List operations, newest first, optionally filtered by state and kind.
+    pub fn list(&self, state: Option<&str>, kind: Option<&str>) -> Vec<OperationInfo> {
+        let ops = self.ops.read().unwrap();
+        let mut out: Vec<OperationInfo> = ops
+            .values()
+            .map(|e| &e.info)
+            .filter(|info| state.is_none_or(|s| info.state.as_str() == s))
+            .filter(|info| kind.is_none_or(|k| info.kind == k))
+            .cloned()
+            .collect();
+        out.sort_by_key(|info| std::cmp::Reverse(info.id.parse::<u64>().unwrap_or(0)));
+        out
+    }
+
+    /// Request cancellation. Queued operations are cancelled immediately;
+    /// running ones stop at their next cancellation check.
+    pub fn cancel(&self, id: u64) -> Result<OperationInfo> {
+        let queued = {
+            let mut ops = self.ops.write().unwrap();
+            let entry = ops
+                .get_mut(&id)
+                .ok_or_else(|| StoreError::NotFound("operation".into()))?;
+            if entry.info.state.is_finished() {
+                return Err(StoreError::InvalidInput(format!(
+                    "operation already {}",
+                    entry.info.state.as_str()
+                )));
+            }
+            entry.cancel.store(true, Ordering::SeqCst);
+            entry.info.cancel_requested = true;
+            entry.info.updated_at_unix_ms = unix_ms();
+            entry.info.state == OperationState::Queued
+        };
+        if queued {
+            self.finish(id, Err(StoreError::Cancelled(format!("operation {id}"))));
+        }
+        self.get(id)
+            .ok_or_else(|| StoreError::NotFound("operation".into()))
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut OperationInfo)) {
+        if let Some(entry) = self.ops.write().unwrap().get_mut(&id) {
+            f(&mut entry.info);
+            entry.info.updated_at_unix_ms = unix_ms();
+        }
+    }
+
+    /// Move a queued operation to running; false if it is no longer queued.
+    fn mark_running(&self, id: u64) -> bool {
+        let mut ops = self.ops.write().unwrap();
+        match ops.get_mut(&id) {
+            Some(entry) if entry.info.state == OperationState::Queued => {
+                let now = unix_ms();
+                entry.info.state = OperationState::Running;
+                entry.info.started_at_unix_ms = Some(now);
+                entry.info.updated_at_unix_ms = now;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn finish(&self, id: u64, outcome: Result<JsonValue>) {
+        let mut finished_info = None;
+        self.update(id, |op| {
+            if op.state.is_finished() {
+                return;
+            }
+            match outcome {
+                Ok(result) => {
+                    op.state = OperationState::Succeeded;
+                    op.result = Some(result);
+                }
+                Err(StoreError::Cancelled(_)) => op.state = OperationState::Cancelled,
+                Err(err) => {
+                    op.state = OperationState::Failed;
+                    op.error = Some(err.to_string());
+                }
+            }
+            op.finished_at_unix_ms = Some(unix_ms());
+            finished_info = Some(op.clone());
+        });
+        let Some(info) = finished_info else {
+            return;
+        };
+
+        self.event_bus.publish(StoreEvent::OperationCompleted {
+            operation_id: info.id.clone(),
+            kind: info.kind.clone(),
+            state: info.state.as_str().to_string(),
+            error: info.error.clone(),
+        });
+
+        // Trim finished history
+        let mut finished = self.finished.lock().unwrap();
+        finished.push_back(id);
+        while finished.len() > self.history {
+            if let Some(old) = finished.pop_front() {
+                self.ops.write().unwrap().remove(&old);
+            }
+        }
+    }
+}
+
+fn worker_loop(ops: Arc<Operations>, rx: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        let job = match rx.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        if !ops.mark_running(job.id) {
+            // Cancelled while queued.
+            continue;
+        }
+        let handle = OperationHandle {
+            id: job.id,
+            cancel: job.cancel,
+            ops: Arc::clone(&ops),
+        };
+        // A panicking job fails its operation; the worker keeps serving.
+        let work = job.work;
+        let outcome =
+            panic::catch_unwind(AssertUnwindSafe(|| work(&handle))).unwrap_or_else(|panic| {
+                Err(StoreError::Io(std::io::Error::other(panic_message(&panic))))
+            });
+        ops.finish(job.id, outcome);
+    }
+}
+
+fn panic_message(panic: &Box<dyn std::any::Any + Send>) -> String {
+    let detail = panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    format!("operation panicked: {detail}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn wait_finished(ops: &Operations, id: u64) -> OperationInfo {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let info = ops.get(id).expect("operation");
+            if info.state.is_finished() {
+                return info;
+            }
+            assert!(Instant::now() < deadline, "operation did not finish");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_operation_succeeds_and_publishes_event() {
+        let bus = Arc::new(EventBus::new());
+        let sub = bus.subscribe();
+        let ops = Operations::start(OperationsConfig::default(), Arc::clone(&bus));
+
+        let id = ops.submit("test", |op| {
+            op.set_total(3);
+            op.advance(3);
+            Ok(serde_json::json!({ "ok": true }))
+        });
+        let info = wait_finished(&ops, id);
+        assert_eq!(info.state, OperationState::Succeeded);
+        assert_eq!((info.done, info.total), (3, Some(3)));
+        assert_eq!(info.result.unwrap()["ok"], true);
+
+        match sub.recv_timeout(Duration::from_secs(1)) {
+            Some(StoreEvent::OperationCompleted {
+                operation_id,
+                state,
+                ..
+            }) => {
+                assert_eq!(operation_id, id.to_string());
+                assert_eq!(state, "succeeded");
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_panicking_operation_fails_and_worker_survives() {
+        let config = OperationsConfig {
+            workers: 1,
+            history: 10,
+        };
+        let ops = Operations::start(config, Arc::new(EventBus::new()));
+        let id = ops.submit("test", |_| panic!("boom"));
+        let info = wait_finished(&ops, id);
+        assert_eq!(info.state, OperationState::Failed);
+        assert!(info.error.unwrap().contains("boom"));
+
+        let next = ops.submit("test", |_| Ok(JsonValue::Null));
+        assert_eq!(wait_finished(&ops, next).state, OperationState::Succeeded);
+    }
+
+    #[test]
+    fn test_cancel_running_operation() {
+        let ops = Operations::start(OperationsConfig::default(), Arc::new(EventBus::new()));
+        let (started_tx, started_rx) = mpsc::channel();
+        let id = ops.submit("test", move |op| {
+            started_tx.send(()).unwrap();
+            loop {
+                op.check_cancelled()?;
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+        started_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        let info = ops.cancel(id).unwrap();
+        assert!(info.cancel_requested);
+        assert_eq!(wait_finished(&ops, id).state, OperationState::Cancelled);
+        assert!(ops.cancel(id).is_err());
+    }
+
+    #[test]
+    fn test_cancel_queued_operation() {
+        let config = OperationsConfig {
+            workers: 1,
+            history: 10,
+        };
+        let ops = Operations::start(config, Arc::new(EventBus::new()));
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let blocker = ops.submit("blocker", move |_| {
+            release_rx.recv().ok();
+            Ok(JsonValue::Null)
+        });
+        let queued = ops.submit("queued", |_| Ok(JsonValue::Null));
+
+        assert_eq!(ops.cancel(queued).unwrap().state, OperationState::Cancelled);
+        release_tx.send(()).unwrap();
+        assert_eq!(
+            wait_finished(&ops, blocker).state,
+            OperationState::Succeeded
+        );
+        assert_eq!(ops.get(queued).unwrap().state, OperationState::Cancelled);
+        assert_eq!(ops.list(Some("cancelled"), None).len(), 1);
+    }
+}
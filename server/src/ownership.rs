@@ -0,0 +1,106 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Context ownership transfers.
+//!
+//! A context's owner is the principal it acts on behalf of
+//! (`provenance.on_behalf_of`, queried in CQL as `user`). A transfer
+//! overrides it through the context's metadata overrides and is appended to
+//! a JSON-lines log with the previous owner, the reason and the principal
+//! that made it; the log is the audit trail of the context's ownership.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::jsonl_log::open_log;
+use crate::util::unix_ms;
+
+/// One change of a context's owner.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnershipTransfer {
+    pub context_id: u64,
+    /// None when the context had no owner.
+    pub previous_owner: Option<String>,
+    pub owner: String,
+    pub reason: String,
+    pub principal: String,
+    pub at_unix_ms: u64,
+}
+
+/// Body of a transfer request. `filter` selects contexts by CQL for a bulk
+/// transfer.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransferRequest {
+    pub owner: String,
+    #[serde(default)]
+    pub reason: String,
+    #[serde(default)]
+    pub filter: Option<String>,
+}
+
+/// Outcome of reassigning the contexts matching a filter.
+#[derive(Debug, Clone, Default)]
+pub struct BulkTransfer {
+    pub transferred: Vec<OwnershipTransfer>,
+    /// Matching contexts already owned by the new owner.
+    pub unchanged: Vec<u64>,
+}
+
+pub struct OwnershipLog {
+    file: File,
+    history: HashMap<u64, Vec<OwnershipTransfer>>,
+}
+
+impl OwnershipLog {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("ownership.jsonl");
+        let (file, entries) = open_log::<OwnershipTransfer>(&path)?;
+
+        let mut history: HashMap<u64, Vec<OwnershipTransfer>> = HashMap::new();
+        for entry in entries {
+            history.entry(entry.context_id).or_default().push(entry);
+        }
+
+        Ok(Self { file, history })
+    }
+
+    /// Every transfer of a context, oldest first.
+    pub fn history(&self, context_id: u64) -> &[OwnershipTransfer] {
+        self.history.get(&context_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Append a transfer. The caller applies it to the context's metadata.
+    pub fn record(
+        &mut self,
+        context_id: u64,
+        previous_owner: Option<String>,
+        owner: String,
+        reason: String,
+        principal: String,
+    ) -> Result<OwnershipTransfer> {
+        let entry = OwnershipTransfer {
+            context_id,
+            previous_owner,
+            owner,
+            reason,
+            principal,
+            at_unix_ms: unix_ms(),
+        };
+        let mut line = serde_json::to_vec(&entry)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.history
+            .entry(context_id)
+            .or_default()
+            .push(entry.clone());
+        Ok(entry)
+    }
+}
@@ -0,0 +1,275 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Payload read-ahead for turn pagination.
+//!
+//! Clients page backwards through a context with `before_turn_id`, and each
+//! page reads its payloads from the blob pack. After serving a page the
+//! store queues a prefetch of the page before it; a background thread reads
+//! those payloads into an LRU cache, so the next request is served from
+//! memory. Cached payloads are decrypted, and the cache is cleared when a
+//! key is shredded.
+
+use std::collections::{BTreeMap, HashMap};
+use std::mem::size_of;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde::Serialize;
+
+use crate::store::Store;
+
+/// Per-entry bookkeeping besides the payload: the hash table slot and the
+/// recency index node.
+const ENTRY_OVERHEAD: usize = size_of::<(u64, CacheEntry)>() + 1 + 2 * size_of::<u64>() + 16;
+
+/// Default cache size when `CXDB_PAYLOAD_CACHE_BYTES` is unset.
+const DEFAULT_BUDGET_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Prefetch requests queued ahead of the prefetcher; further requests are
+/// dropped while it is behind.
+const PREFETCH_QUEUE: usize = 8;
+
+/// Payload cache settings.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PayloadCacheConfig {
+    /// Bytes the cache may hold; 0 disables caching and read-ahead.
+    pub budget_bytes: u64,
+}
+
+impl PayloadCacheConfig {
+    /// Load config from `CXDB_PAYLOAD_CACHE_BYTES` (default 64 MiB, 0 disables).
+    pub fn from_env() -> Self {
+        let budget_bytes = std::env::var("CXDB_PAYLOAD_CACHE_BYTES")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_BUDGET_BYTES);
+        Self { budget_bytes }
+    }
+}
+
+/// Cache accounting, reported in the metrics snapshot.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PayloadCacheStats {
+    pub entries: usize,
+    /// Approximate bytes held by cached payloads.
+    pub bytes: u64,
+    pub budget_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+    /// Hits over lookups; 0 before the first lookup.
+    pub hit_rate: f64,
+    pub evictions: u64,
+    /// Payloads read ahead by the prefetcher.
+    pub prefetched: u64,
+}
+
+/// A page of turns to read ahead: up to `limit` turns older than
+/// `before_turn_id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrefetchRequest {
+    pub context_id: u64,
+    pub before_turn_id: u64,
+    pub limit: u32,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    payload: Vec<u8>,
+    /// Position in `recency`.
+    tick: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct PayloadCache {
+    budget_bytes: u64,
+    entries: HashMap<u64, CacheEntry>,
+    /// Access tick -> turn id, oldest first.
+    recency: BTreeMap<u64, u64>,
+    next_tick: u64,
+    bytes: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    prefetched: u64,
+    /// Queue to the prefetcher thread, once started.
+    prefetch_tx: Option<SyncSender<PrefetchRequest>>,
+}
+
+impl PayloadCache {
+    pub fn new(config: PayloadCacheConfig) -> Self {
+        Self {
+            budget_bytes: config.budget_bytes,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.budget_bytes > 0
+    }
+
+    /// Cached payload of a turn, marking it recently used.
+    pub fn get(&mut self, turn_id: u64) -> Option<Vec<u8>> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let tick = self.tick();
+        let Some(entry) = self.entries.get_mut(&turn_id) else {
+            self.misses += 1;
+            return None;
+        };
+        self.recency.remove(&entry.tick);
+        self.recency.insert(tick, turn_id);
+        entry.tick = tick;
+        self.hits += 1;
+        Some(entry.payload.clone())
+    }
+
+    pub fn contains(&self, turn_id: u64) -> bool {
+        self.entries.contains_key(&turn_id)
+    }
+
+    /// Cache a turn's payload, evicting cold entries over budget. Payloads
+    /// larger than the whole budget are not cached.
+    pub fn insert(&mut self, turn_id: u64, payload: Vec<u8>) {
+        let bytes = (ENTRY_OVERHEAD + payload.len()) as u64;
+        if bytes > self.budget_bytes {
+            return;
+        }
+        self.remove(turn_id);
+        let tick = self.tick();
+        self.recency.insert(tick, turn_id);
+        self.entries.insert(turn_id, CacheEntry { payload, tick });
+        self.bytes += bytes;
+        self.evict();
+    }
+
+    /// Record a payload read ahead by the prefetcher.
+    pub fn insert_prefetched(&mut self, turn_id: u64, payload: Vec<u8>) {
+        self.insert(turn_id, payload);
+        self.prefetched += 1;
+    }
+
+    fn remove(&mut self, turn_id: u64) {
+        if let Some(entry) = self.entries.remove(&turn_id) {
+            self.recency.remove(&entry.tick);
+            self.bytes -= entry_bytes(&entry);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.bytes = 0;
+    }
+
+    /// Queue a read-ahead if the prefetcher is running. Never blocks.
+    pub fn request_prefetch(&self, request: PrefetchRequest) {
+        if let Some(tx) = &self.prefetch_tx {
+            // Dropped while the prefetcher is behind; the page is then read
+            // on demand.
+            let _ = tx.try_send(request);
+        }
+    }
+
+    /// Create the prefetch queue; requests arrive on the returned receiver.
+    /// None when the cache is disabled.
+    pub fn enable_prefetch(&mut self) -> Option<Receiver<PrefetchRequest>> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let (tx, rx) = sync_channel(PREFETCH_QUEUE);
+        self.prefetch_tx = Some(tx);
+        Some(rx)
+    }
+
+    pub fn stats(&self) -> PayloadCacheStats {
+        let lookups = self.hits + self.misses;
+        PayloadCacheStats {
+            entries: self.entries.len(),
+            bytes: self.bytes,
+            budget_bytes: self.budget_bytes,
+            hits: self.hits,
+            misses: self.misses,
+            hit_rate: if lookups > 0 {
+                self.hits as f64 / lookups as f64
+            } else {
+                0.0
+            },
+            evictions: self.evictions,
+            prefetched: self.prefetched,
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+
+    /// Evict least recently used entries until the cache fits its budget.
+    fn evict(&mut self) {
+        while self.bytes > self.budget_bytes {
+            let Some((_, turn_id)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&turn_id) {
+                self.bytes -= entry_bytes(&entry);
+                self.evictions += 1;
+            }
+        }
+    }
+}
+
+fn entry_bytes(entry: &CacheEntry) -> u64 {
+    (ENTRY_OVERHEAD + entry.payload.len()) as u64
+}
+
+/// Start the prefetcher thread, which reads queued pages into the store's
+/// payload cache. Returns None when the cache is disabled.
+pub fn start_prefetcher(store: Arc<Mutex<Store>>) -> Option<thread::JoinHandle<()>> {
+    let rx = store.lock().unwrap().enable_prefetch()?;
+    Some(thread::spawn(move || {
+        while let Ok(request) = rx.recv() {
+            let result = store.lock().unwrap().prefetch(&request);
+            if let Err(err) = result {
+                tracing::debug!(
+                    context_id = request.context_id,
+                    error = %err,
+                    "payload prefetch failed"
+                );
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let entry = (ENTRY_OVERHEAD + 100) as u64;
+        let mut cache = PayloadCache::new(PayloadCacheConfig {
+            budget_bytes: entry * 3,
+        });
+        for turn_id in 1..=3 {
+            cache.insert(turn_id, vec![0u8; 100]);
+        }
+        assert!(cache.get(1).is_some());
+        cache.insert(4, vec![0u8; 100]);
+        assert!(!cache.contains(2));
+        assert!(cache.contains(1));
+
+        // Too large to ever fit.
+        cache.insert(5, vec![0u8; entry as usize * 3]);
+        assert!(!cache.contains(5));
+
+        assert!(cache.get(2).is_none());
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.evictions), (3, 1));
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        assert_eq!(stats.hit_rate, 0.5);
+        assert_eq!(stats.bytes, entry * 3);
+    }
+}
@@ -0,0 +1,308 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Ingest-time PII detection.
+//!
+//! When enabled, every appended turn's payload is scanned for personal data
+//! and the context is labeled `pii:<detector>` (`pii:email`, `pii:phone`,
+//! `pii:credit_card`) the first time a detector fires. Labels go through the
+//! metadata override layer, so they are indexed like any other label and
+//! can be queried with `label.pii = "email"`. Detection is pattern based:
+//! string values of the msgpack payload are matched against regexes, and
+//! card numbers must also pass the Luhn check.
+//!
+//! `CXDB_PII_DETECTORS` selects the detectors, as a comma-separated list or
+//! `all`; `CXDB_PII_EXCLUDE_TYPES` lists declared type ids whose turns are
+//! never scanned (a trailing `*` matches a prefix):
+//!
+//! ```text
+//! CXDB_PII_DETECTORS=email,credit_card
+//! CXDB_PII_EXCLUDE_TYPES=com.example.ToolOutput,cxdb:*
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use regex::Regex;
+use rmpv::Value;
+use serde::Serialize;
+
+use crate::error::{Result, StoreError};
+
+/// Label namespace of detections.
+pub const PII_LABEL_NAMESPACE: &str = "pii";
+
+/// Kind of personal data a detector looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PiiDetector {
+    Email,
+    Phone,
+    CreditCard,
+}
+
+impl PiiDetector {
+    pub const ALL: [PiiDetector; 3] = [
+        PiiDetector::Email,
+        PiiDetector::Phone,
+        PiiDetector::CreditCard,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PiiDetector::Email => "email",
+            PiiDetector::Phone => "phone",
+            PiiDetector::CreditCard => "credit_card",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|d| d.as_str() == name)
+    }
+
+    /// Context label added when the detector fires.
+    pub fn label(&self) -> String {
+        format!("{PII_LABEL_NAMESPACE}:{}", self.as_str())
+    }
+}
+
+/// Detectors and exclusions, loaded from the environment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PiiConfig {
+    pub detectors: BTreeSet<PiiDetector>,
+    /// Declared type ids never scanned; a trailing `*` matches a prefix.
+    pub exclude_types: Vec<String>,
+}
+
+impl PiiConfig {
+    /// Returns None when `CXDB_PII_DETECTORS` is unset or empty.
+    pub fn from_env() -> Result<Option<Self>> {
+        let list = match std::env::var("CXDB_PII_DETECTORS") {
+            Ok(list) if !list.trim().is_empty() => list,
+            _ => return Ok(None),
+        };
+        let mut config = Self::parse(&list)?;
+        if let Ok(types) = std::env::var("CXDB_PII_EXCLUDE_TYPES") {
+            config.exclude_types = types
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(String::from)
+                .collect();
+        }
+        Ok(Some(config))
+    }
+
+    /// Parse a comma-separated list of detectors, or `all`.
+    pub fn parse(list: &str) -> Result<Self> {
+        let mut detectors = BTreeSet::new();
+        for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            if name == "all" {
+                detectors.extend(PiiDetector::ALL);
+                continue;
+            }
+            let detector = PiiDetector::parse(name).ok_or_else(|| {
+                StoreError::InvalidInput(format!(
+                    "CXDB_PII_DETECTORS: unknown detector {name:?} (expected {} or all)",
+                    PiiDetector::ALL.map(|d| d.as_str()).join(", ")
+                ))
+            })?;
+            detectors.insert(detector);
+        }
+        Ok(Self {
+            detectors,
+            exclude_types: Vec::new(),
+        })
+    }
+
+    pub fn excludes(&self, type_id: &str) -> bool {
+        self.exclude_types
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => type_id.starts_with(prefix),
+                None => type_id == pattern,
+            })
+    }
+
+    /// Detectors and exclusions, for logging.
+    pub fn describe(&self) -> String {
+        let detectors = self
+            .detectors
+            .iter()
+            .map(|d| d.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+        if self.exclude_types.is_empty() {
+            detectors
+        } else {
+            format!("{detectors} (excluding {})", self.exclude_types.join(","))
+        }
+    }
+}
+
+/// Scanner accounting.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PiiStats {
+    pub turns_scanned: u64,
+    /// Turns skipped because of their declared type.
+    pub turns_excluded: u64,
+    /// Turns each detector fired on.
+    pub detections: BTreeMap<String, u64>,
+}
+
+/// PII scanning state held by the store.
+pub struct PiiScanner {
+    config: PiiConfig,
+    email: Regex,
+    phone: Regex,
+    card: Regex,
+    stats: PiiStats,
+}
+
+impl PiiScanner {
+    pub fn new(config: PiiConfig) -> Self {
+        let stats = PiiStats {
+            detections: config
+                .detectors
+                .iter()
+                .map(|d| (d.as_str().to_string(), 0))
+                .collect(),
+            ..Default::default()
+        };
+        Self {
+            config,
+            email: Regex::new(r"(?i)\b[a-z0-9._%+-]+@[a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,}\b")
+                .unwrap(),
+            // NANP-style groups with separators, or E.164.
+            phone: Regex::new(
+                r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{3}\)\s?|\b\d{3}[\s.-])\d{3}[\s.-]\d{4}\b|\+\d{8,15}\b",
+            )
+            .unwrap(),
+            card: Regex::new(r"\b\d(?:[ -]?\d){12,18}\b").unwrap(),
+            stats,
+        }
+    }
+
+    /// Detectors that fire on a turn payload. Payloads that are not msgpack
+    /// are scanned as UTF-8 text.
+    pub fn scan(&mut self, type_id: &str, payload: &[u8]) -> BTreeSet<PiiDetector> {
+        if self.config.excludes(type_id) {
+            self.stats.turns_excluded += 1;
+            return BTreeSet::new();
+        }
+        self.stats.turns_scanned += 1;
+        let decoded = rmpv::decode::read_value(&mut &payload[..]).ok();
+        let mut strings = Vec::new();
+        match &decoded {
+            Some(value) => collect_strings(value, &mut strings),
+            None => strings.extend(std::str::from_utf8(payload).ok()),
+        }
+        let found: BTreeSet<PiiDetector> = self
+            .config
+            .detectors
+            .iter()
+            .copied()
+            .filter(|detector| strings.iter().any(|s| self.detect(*detector, s)))
+            .collect();
+        for detector in &found {
+            *self
+                .stats
+                .detections
+                .entry(detector.as_str().to_string())
+                .or_default() += 1;
+        }
+        found
+    }
+
+    fn detect(&self, detector: PiiDetector, text: &str) -> bool {
+        match detector {
+            PiiDetector::Email => self.email.is_match(text),
+            PiiDetector::Phone => self.phone.find_iter(text).any(|m| {
+                let digits = m.as_str().chars().filter(char::is_ascii_digit).count();
+                (10..=15).contains(&digits)
+            }),
+            PiiDetector::CreditCard => self.card.find_iter(text).any(|m| {
+                let digits: Vec<u32> = m.as_str().chars().filter_map(|c| c.to_digit(10)).collect();
+                (13..=19).contains(&digits.len()) && luhn_valid(&digits)
+            }),
+        }
+    }
+
+    pub fn stats(&self) -> PiiStats {
+        self.stats.clone()
+    }
+}
+
+fn collect_strings<'a>(value: &'a Value, out: &mut Vec<&'a str>) {
+    match value {
+        Value::String(s) => out.extend(s.as_str()),
+        Value::Array(items) => items.iter().for_each(|v| collect_strings(v, out)),
+        Value::Map(entries) => entries.iter().for_each(|(_, v)| collect_strings(v, out)),
+        _ => {}
+    }
+}
+
+/// Luhn checksum of a card number's digits.
+pub fn luhn_valid(digits: &[u32]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| match (i % 2 == 1, d * 2) {
+            (true, doubled) if doubled > 9 => doubled - 9,
+            (true, doubled) => doubled,
+            (false, _) => d,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scanner() -> PiiScanner {
+        PiiScanner::new(PiiConfig::parse("all").unwrap())
+    }
+
+    fn detect(text: &str) -> Vec<&'static str> {
+        let mut payload = Vec::new();
+        rmpv::encode::write_value(&mut payload, &Value::from(text)).unwrap();
+        scanner()
+            .scan("com.example.Message", &payload)
+            .iter()
+            .map(|d| d.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_detectors() {
+        assert_eq!(detect("mail jane.doe@example.co.uk today"), vec!["email"]);
+        assert_eq!(detect("call (555) 123-4567"), vec!["phone"]);
+        assert_eq!(detect("call +442079460958"), vec!["phone"]);
+        assert_eq!(
+            detect("card 4111 1111 1111 1111 exp 12/30"),
+            vec!["credit_card"]
+        );
+        // Fails the Luhn check.
+        assert_eq!(detect("order 4111 1111 1111 1112"), Vec::<&str>::new());
+        assert_eq!(detect("build 1234 finished in 30s"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_config_parse_and_exclusions() {
+        let mut config = PiiConfig::parse("email, credit_card").unwrap();
+        assert_eq!(
+            config.detectors,
+            BTreeSet::from([PiiDetector::Email, PiiDetector::CreditCard])
+        );
+        assert!(PiiConfig::parse("email,ssn").is_err());
+
+        config.exclude_types = vec!["cxdb:*".into(), "com.example.Tool".into()];
+        assert!(config.excludes("cxdb:SystemEvent"));
+        assert!(config.excludes("com.example.Tool"));
+        assert!(!config.excludes("com.example.ToolOutput"));
+        assert_eq!(
+            config.describe(),
+            "email,credit_card (excluding cxdb:*,com.example.Tool)"
+        );
+    }
+}
@@ -0,0 +1,303 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Soft real-time presence: who is viewing which context.
+//!
+//! A viewer is an SSE stream opened with `GET /v1/events?viewing=<id>`, or a
+//! client paging through `GET /v1/contexts/:id/turns`. Viewers are identified
+//! by their principal when the request carries one and by their address
+//! otherwise, so several tabs of one person count once. Streams count until
+//! they close; polls count for `CXDB_PRESENCE_TTL_SECS` after the last
+//! request. Every change in a context's set of viewers is published as a
+//! `presence_changed` event. Presence is kept in memory only.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::events::{EventBus, StoreEvent};
+use crate::util::{env_u64, unix_ms};
+
+const DEFAULT_TTL_SECS: u64 = 30;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+pub struct PresenceConfig {
+    /// How long a poll keeps its viewer present.
+    pub poll_ttl: Duration,
+}
+
+impl PresenceConfig {
+    pub fn from_env() -> Self {
+        Self {
+            poll_ttl: Duration::from_secs(env_u64("CXDB_PRESENCE_TTL_SECS", DEFAULT_TTL_SECS)),
+        }
+    }
+}
+
+impl Default for PresenceConfig {
+    fn default() -> Self {
+        Self {
+            poll_ttl: Duration::from_secs(DEFAULT_TTL_SECS),
+        }
+    }
+}
+
+/// Who is viewing: an authenticated principal, or an anonymous client
+/// identified by its address.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ViewerId {
+    Principal(String),
+    Address(String),
+}
+
+/// One viewer of a context, as reported in context responses.
+#[derive(Debug, Clone, Serialize)]
+pub struct Viewer {
+    /// Absent for anonymous viewers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub principal: Option<String>,
+    /// Open SSE streams viewing the context.
+    pub streams: usize,
+    /// Whether the viewer polled the context within the TTL.
+    pub polling: bool,
+    pub since_unix_ms: u64,
+    pub last_seen_unix_ms: u64,
+}
+
+#[derive(Debug)]
+struct ViewerState {
+    streams: usize,
+    poll_until: Option<Instant>,
+    since_unix_ms: u64,
+    last_seen_unix_ms: u64,
+}
+
+impl ViewerState {
+    fn is_present(&self, now: Instant) -> bool {
+        self.streams > 0 || self.poll_until.is_some_and(|until| until > now)
+    }
+}
+
+type Viewers = HashMap<ViewerId, ViewerState>;
+
+pub struct Presence {
+    config: PresenceConfig,
+    contexts: Mutex<HashMap<u64, Viewers>>,
+    event_bus: Arc<EventBus>,
+}
+
+impl Presence {
+    pub fn new(config: PresenceConfig, event_bus: Arc<EventBus>) -> Self {
+        Self {
+            config,
+            contexts: Mutex::new(HashMap::new()),
+            event_bus,
+        }
+    }
+
+    /// Count `viewer` as present until the returned guard is dropped.
+    pub fn open_stream(self: &Arc<Self>, context_id: u64, viewer: ViewerId) -> PresenceStream {
+        self.update(context_id, &viewer, |state| state.streams += 1);
+        PresenceStream {
+            presence: Arc::clone(self),
+            context_id,
+            viewer,
+        }
+    }
+
+    /// Count `viewer` as present for the poll TTL.
+    pub fn touch(&self, context_id: u64, viewer: ViewerId) {
+        let until = Instant::now() + self.config.poll_ttl;
+        self.update(context_id, &viewer, |state| state.poll_until = Some(until));
+    }
+
+    /// Current viewers of a context, principals first.
+    pub fn viewers(&self, context_id: u64) -> Vec<Viewer> {
+        let now = Instant::now();
+        let contexts = self.contexts.lock().unwrap();
+        let Some(viewers) = contexts.get(&context_id) else {
+            return Vec::new();
+        };
+        let mut out: Vec<Viewer> = viewers
+            .iter()
+            .filter(|(_, state)| state.is_present(now))
+            .map(|(id, state)| Viewer {
+                principal: match id {
+                    ViewerId::Principal(p) => Some(p.clone()),
+                    ViewerId::Address(_) => None,
+                },
+                streams: state.streams,
+                polling: state.poll_until.is_some_and(|until| until > now),
+                since_unix_ms: state.since_unix_ms,
+                last_seen_unix_ms: state.last_seen_unix_ms,
+            })
+            .collect();
+        out.sort_by(|a, b| {
+            a.principal
+                .is_none()
+                .cmp(&b.principal.is_none())
+                .then_with(|| a.principal.cmp(&b.principal))
+                .then_with(|| a.since_unix_ms.cmp(&b.since_unix_ms))
+        });
+        out
+    }
+
+    /// Drop viewers whose polls expired, publishing the contexts they left.
+    pub fn expire(&self) {
+        let now = Instant::now();
+        let mut changed = Vec::new();
+        {
+            let mut contexts = self.contexts.lock().unwrap();
+            contexts.retain(|context_id, viewers| {
+                let before = viewers.len();
+                viewers.retain(|_, state| state.is_present(now));
+                if viewers.len() != before {
+                    changed.push(presence_event(*context_id, viewers));
+                }
+                !viewers.is_empty()
+            });
+        }
+        for event in changed {
+            self.event_bus.publish(event);
+        }
+    }
+
+    /// Apply `f` to a viewer's state, publishing when the viewer joined.
+    fn update(&self, context_id: u64, viewer: &ViewerId, f: impl FnOnce(&mut ViewerState)) {
+        let now = Instant::now();
+        let now_ms = unix_ms();
+        let event = {
+            let mut contexts = self.contexts.lock().unwrap();
+            let viewers = contexts.entry(context_id).or_default();
+            let state = viewers.entry(viewer.clone()).or_insert(ViewerState {
+                streams: 0,
+                poll_until: None,
+                since_unix_ms: now_ms,
+                last_seen_unix_ms: now_ms,
+            });
+            let joined = !state.is_present(now);
+            if joined {
+                state.since_unix_ms = now_ms;
+            }
+            state.last_seen_unix_ms = now_ms;
+            f(state);
+            joined.then(|| presence_event(context_id, viewers))
+        };
+        if let Some(event) = event {
+            self.event_bus.publish(event);
+        }
+    }
+
+    fn close_stream(&self, context_id: u64, viewer: &ViewerId) {
+        let now = Instant::now();
+        let event = {
+            let mut contexts = self.contexts.lock().unwrap();
+            let Some(viewers) = contexts.get_mut(&context_id) else {
+                return;
+            };
+            let Some(state) = viewers.get_mut(viewer) else {
+                return;
+            };
+            state.streams = state.streams.saturating_sub(1);
+            state.last_seen_unix_ms = unix_ms();
+            if state.is_present(now) {
+                return;
+            }
+            viewers.remove(viewer);
+            let event = presence_event(context_id, viewers);
+            if viewers.is_empty() {
+                contexts.remove(&context_id);
+            }
+            event
+        };
+        self.event_bus.publish(event);
+    }
+}
+
+/// Keeps a viewer present while an SSE stream is open.
+pub struct PresenceStream {
+    presence: Arc<Presence>,
+    context_id: u64,
+    viewer: ViewerId,
+}
+
+impl Drop for PresenceStream {
+    fn drop(&mut self) {
+        self.presence.close_stream(self.context_id, &self.viewer);
+    }
+}
+
+/// Expire polling viewers in the background.
+pub fn start_presence_sweeper(presence: Arc<Presence>) -> thread::JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(SWEEP_INTERVAL);
+        presence.expire();
+    })
+}
+
+fn presence_event(context_id: u64, viewers: &Viewers) -> StoreEvent {
+    let mut principals: Vec<String> = viewers
+        .keys()
+        .filter_map(|id| match id {
+            ViewerId::Principal(p) => Some(p.clone()),
+            ViewerId::Address(_) => None,
+        })
+        .collect();
+    principals.sort();
+    StoreEvent::PresenceChanged {
+        context_id: context_id.to_string(),
+        viewers: viewers.len(),
+        principals,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn viewer_counts(subscriber: &crate::events::EventSubscriber) -> Vec<usize> {
+        let mut counts = Vec::new();
+        while let Some(event) = subscriber.try_recv() {
+            if let StoreEvent::PresenceChanged { viewers, .. } = event {
+                counts.push(viewers);
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn test_viewers_join_and_leave() {
+        let bus = Arc::new(EventBus::new());
+        let subscriber = bus.subscribe();
+        let presence = Arc::new(Presence::new(
+            PresenceConfig {
+                poll_ttl: Duration::from_millis(50),
+            },
+            Arc::clone(&bus),
+        ));
+        let alice = ViewerId::Principal("alice".into());
+
+        // Two tabs of one principal are one viewer.
+        let tab1 = presence.open_stream(7, alice.clone());
+        let tab2 = presence.open_stream(7, alice.clone());
+        presence.touch(7, ViewerId::Address("10.0.0.2".into()));
+        let viewers = presence.viewers(7);
+        assert_eq!(viewers.len(), 2);
+        assert_eq!(viewers[0].principal.as_deref(), Some("alice"));
+        assert_eq!(viewers[0].streams, 2);
+        assert!(viewers[1].polling);
+        assert_eq!(viewer_counts(&subscriber), vec![1, 2]);
+
+        drop(tab1);
+        assert!(viewer_counts(&subscriber).is_empty());
+        drop(tab2);
+        thread::sleep(Duration::from_millis(60));
+        presence.expire();
+        assert!(presence.viewers(7).is_empty());
+        assert_eq!(viewer_counts(&subscriber), vec![1, 0]);
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Context previews from registry `summary_field`s.
+//!
+//! A type version may name one of its string fields as its `summary_field`.
+//! Each appended turn of such a type records the tail of that field's value,
+//! and a context's preview is the one recorded by the latest such turn on
+//! its head chain. Previews appear in context listings and are searchable
+//! with `preview CONTAINS "..."`.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use rmpv::Value;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::jsonl_log::open_log;
+use crate::registry::Registry;
+use crate::turn_store::CommitPipeline;
+
+const DEFAULT_MAX_CHARS: usize = 200;
+
+/// Preview settings, loaded from the environment.
+#[derive(Debug, Clone)]
+pub struct PreviewConfig {
+    /// Characters kept from the end of a summary field.
+    pub max_chars: usize,
+}
+
+impl PreviewConfig {
+    pub fn from_env() -> Self {
+        let max_chars = std::env::var("CXDB_PREVIEW_MAX_CHARS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(DEFAULT_MAX_CHARS);
+        Self { max_chars }
+    }
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self {
+            max_chars: DEFAULT_MAX_CHARS,
+        }
+    }
+}
+
+/// Reads summary fields out of turn payloads.
+pub struct PreviewExtractor {
+    config: PreviewConfig,
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl PreviewExtractor {
+    pub fn new(config: PreviewConfig, registry: Arc<Mutex<Registry>>) -> Self {
+        Self { config, registry }
+    }
+
+    /// The preview a turn contributes, if its type declares a summary field
+    /// and the payload carries non-blank text in it.
+    pub fn extract(&self, type_id: &str, type_version: u32, payload: &[u8]) -> Option<String> {
+        let tag = {
+            let registry = self.registry.lock().unwrap();
+            registry
+                .get_type_version(type_id, type_version)?
+                .summary_field?
+        };
+        let Ok(Value::Map(entries)) = rmpv::decode::read_value(&mut &payload[..]) else {
+            return None;
+        };
+        let text = entries.iter().find_map(|(key, value)| {
+            let key_tag = match key {
+                Value::Integer(int) => int.as_u64(),
+                Value::String(s) => s.as_str().and_then(|s| s.parse::<u64>().ok()),
+                _ => None,
+            };
+            (key_tag == Some(tag)).then(|| value.as_str()).flatten()
+        })?;
+        tail(text, self.config.max_chars)
+    }
+}
+
+/// Collapse whitespace and keep the last `max_chars` characters.
+pub fn tail(text: &str, max_chars: usize) -> Option<String> {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.is_empty() {
+        return None;
+    }
+    let skip = collapsed.chars().count().saturating_sub(max_chars);
+    Some(
+        collapsed
+            .chars()
+            .skip(skip)
+            .collect::<String>()
+            .trim_start()
+            .to_string(),
+    )
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PreviewRecord {
+    turn_id: u64,
+    preview: String,
+}
+
+/// Previews recorded per turn, persisted as `meta/previews.jsonl`.
+pub struct PreviewLog {
+    file: File,
+    entries: HashMap<u64, String>,
+}
+
+impl PreviewLog {
+    pub fn open(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join("previews.jsonl");
+        let (file, records) = open_log::<PreviewRecord>(&path)?;
+
+        let mut entries = HashMap::new();
+        for entry in records {
+            entries.insert(entry.turn_id, entry.preview);
+        }
+
+        Ok(Self { file, entries })
+    }
+
+    pub fn get(&self, turn_id: u64) -> Option<&str> {
+        self.entries.get(&turn_id).map(String::as_str)
+    }
+
+    /// Sync the log with every group commit batch.
+    pub fn track_files(&self, pipeline: &CommitPipeline) -> Result<()> {
+        pipeline.track("meta/previews.jsonl", &self.file)
+    }
+
+    pub fn record(&mut self, turn_id: u64, preview: String) -> Result<()> {
+        let entry = PreviewRecord { turn_id, preview };
+        let mut line = serde_json::to_vec(&entry)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.entries.insert(turn_id, entry.preview);
+        Ok(())
+    }
+}
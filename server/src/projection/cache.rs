@@ -0,0 +1,391 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Cache of typed projections for the read path.
+//!
+//! Viewing a context again projects the same payloads with the same
+//! descriptors, so projections are cached by payload content hash, decoded
+//! type and version, and render options. Referenced types project with their
+//! latest version, so each entry records every type it was projected with;
+//! when the registry ingests a new version of one of them the entry is
+//! dropped. The cache is bounded by `CXDB_PROJECTION_CACHE_BYTES` and
+//! evicts least recently used entries.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::mem::size_of;
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+use crate::deadline::Deadline;
+use crate::error::Result;
+use crate::registry::{ItemsSpec, Registry, TypeVersionSpec};
+
+use super::{project_msgpack_with_deadline, ProjectionResult, RenderOptions};
+
+/// Per-entry bookkeeping besides the projected JSON: the key, the hash table
+/// slot and the recency index node.
+const ENTRY_OVERHEAD: usize =
+    size_of::<(ProjectionKey, CacheEntry)>() + 1 + 2 * size_of::<u64>() + 16;
+
+/// Approximate per-node cost of a JSON value.
+const JSON_NODE_BYTES: usize = size_of::<JsonValue>();
+
+/// Default cache size when `CXDB_PROJECTION_CACHE_BYTES` is unset.
+const DEFAULT_BUDGET_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Projection cache settings.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProjectionCacheConfig {
+    /// Bytes the cache may hold; 0 disables caching.
+    pub budget_bytes: u64,
+}
+
+impl ProjectionCacheConfig {
+    /// Load config from `CXDB_PROJECTION_CACHE_BYTES` (default 32 MiB, 0 disables).
+    pub fn from_env() -> Self {
+        let budget_bytes = std::env::var("CXDB_PROJECTION_CACHE_BYTES")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_BUDGET_BYTES);
+        Self { budget_bytes }
+    }
+}
+
+/// Cache accounting, reported in the metrics snapshot.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProjectionCacheStats {
+    pub entries: usize,
+    /// Approximate bytes held by cached projections.
+    pub bytes: u64,
+    pub budget_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+    /// Hits over lookups; 0 before the first lookup.
+    pub hit_rate: f64,
+    pub evictions: u64,
+    /// Entries dropped because a type they were projected with got a new
+    /// version.
+    pub invalidations: u64,
+}
+
+/// What a projection depends on besides the registry contents.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProjectionKey {
+    pub payload_hash: [u8; 32],
+    pub type_id: String,
+    pub type_version: u32,
+    pub options: RenderOptions,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    data: JsonValue,
+    unknown: Option<JsonValue>,
+    defaulted: Option<Vec<String>>,
+    /// The decoded type and every type referenced while projecting.
+    types: Vec<String>,
+    bytes: u64,
+    /// Position in `recency`.
+    tick: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ProjectionCache {
+    budget_bytes: u64,
+    entries: HashMap<ProjectionKey, CacheEntry>,
+    /// Access tick -> key, oldest first.
+    recency: BTreeMap<u64, ProjectionKey>,
+    next_tick: u64,
+    bytes: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    invalidations: u64,
+}
+
+impl ProjectionCache {
+    pub fn new(config: ProjectionCacheConfig) -> Self {
+        Self {
+            budget_bytes: config.budget_bytes,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.budget_bytes > 0
+    }
+
+    /// Cached projection, marking it recently used.
+    pub fn get(&mut self, key: &ProjectionKey) -> Option<ProjectionResult> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let tick = self.tick();
+        let Some(entry) = self.entries.get_mut(key) else {
+            self.misses += 1;
+            return None;
+        };
+        self.recency.remove(&entry.tick);
+        self.recency.insert(tick, key.clone());
+        entry.tick = tick;
+        self.hits += 1;
+        Some(ProjectionResult {
+            data: entry.data.clone(),
+            unknown: entry.unknown.clone(),
+            defaulted: entry.defaulted.clone(),
+        })
+    }
+
+    pub fn contains(&self, key: &ProjectionKey) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Cache a projection made with `types`, evicting cold entries over
+    /// budget. Projections larger than the whole budget are not cached.
+    pub fn insert(&mut self, key: ProjectionKey, result: &ProjectionResult, types: Vec<String>) {
+        let bytes = (ENTRY_OVERHEAD
+            + json_bytes(&result.data)
+            + result.unknown.as_ref().map_or(0, json_bytes)
+            + result
+                .defaulted
+                .as_ref()
+                .map_or(0, |names| names.iter().map(|n| n.len() + 24).sum())
+            + types.iter().map(|t| t.len() + 24).sum::<usize>()) as u64;
+        if bytes > self.budget_bytes {
+            return;
+        }
+        self.remove(&key);
+        let tick = self.tick();
+        self.recency.insert(tick, key.clone());
+        self.entries.insert(
+            key,
+            CacheEntry {
+                data: result.data.clone(),
+                unknown: result.unknown.clone(),
+                defaulted: result.defaulted.clone(),
+                types,
+                bytes,
+                tick,
+            },
+        );
+        self.bytes += bytes;
+        self.evict();
+    }
+
+    /// Drop every entry projected with one of `type_ids`.
+    pub fn invalidate_types(&mut self, type_ids: &HashSet<String>) {
+        if type_ids.is_empty() || self.entries.is_empty() {
+            return;
+        }
+        let stale: Vec<ProjectionKey> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.types.iter().any(|t| type_ids.contains(t)))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in stale {
+            self.remove(&key);
+            self.invalidations += 1;
+        }
+    }
+
+    fn remove(&mut self, key: &ProjectionKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.tick);
+            self.bytes -= entry.bytes;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.bytes = 0;
+    }
+
+    pub fn stats(&self) -> ProjectionCacheStats {
+        let lookups = self.hits + self.misses;
+        ProjectionCacheStats {
+            entries: self.entries.len(),
+            bytes: self.bytes,
+            budget_bytes: self.budget_bytes,
+            hits: self.hits,
+            misses: self.misses,
+            hit_rate: if lookups > 0 {
+                self.hits as f64 / lookups as f64
+            } else {
+                0.0
+            },
+            evictions: self.evictions,
+            invalidations: self.invalidations,
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+
+    /// Evict least recently used entries until the cache fits its budget.
+    fn evict(&mut self) {
+        while self.bytes > self.budget_bytes {
+            let Some((_, key)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.bytes -= entry.bytes;
+                self.evictions += 1;
+            }
+        }
+    }
+}
+
+/// Project a payload like [`project_msgpack_with_deadline`], serving repeated
+/// projections from the registry's projection cache.
+pub fn project_cached(
+    payload_hash: &[u8; 32],
+    payload: &[u8],
+    type_id: &str,
+    descriptor: &TypeVersionSpec,
+    registry: &Registry,
+    options: &RenderOptions,
+    deadline: &Deadline,
+) -> Result<ProjectionResult> {
+    let cache = registry.projection_cache();
+    if !cache.lock().unwrap().is_enabled() {
+        return project_msgpack_with_deadline(payload, descriptor, registry, options, deadline);
+    }
+    let key = ProjectionKey {
+        payload_hash: *payload_hash,
+        type_id: type_id.to_string(),
+        type_version: descriptor.version,
+        options: options.clone(),
+    };
+    if let Some(cached) = cache.lock().unwrap().get(&key) {
+        return Ok(cached);
+    }
+    let projected =
+        project_msgpack_with_deadline(payload, descriptor, registry, options, deadline)?;
+    let types = referenced_types(type_id, descriptor, registry);
+    cache.lock().unwrap().insert(key, &projected, types);
+    Ok(projected)
+}
+
+/// `type_id` and every type reachable through its refs, each resolved to its
+/// latest version as projection does.
+fn referenced_types(
+    type_id: &str,
+    descriptor: &TypeVersionSpec,
+    registry: &Registry,
+) -> Vec<String> {
+    let mut seen = BTreeSet::from([type_id.to_string()]);
+    let mut pending = Vec::new();
+    collect_refs(descriptor, &mut pending);
+    while let Some(type_ref) = pending.pop() {
+        if !seen.insert(type_ref.clone()) {
+            continue;
+        }
+        if let Some(spec) = registry.get_latest_type_version(&type_ref) {
+            collect_refs(spec, &mut pending);
+        }
+    }
+    seen.into_iter().collect()
+}
+
+fn collect_refs(descriptor: &TypeVersionSpec, out: &mut Vec<String>) {
+    for field in descriptor.fields.values() {
+        if let Some(type_ref) = &field.type_ref {
+            out.push(type_ref.clone());
+        }
+        for spec in [&field.items, &field.shape].into_iter().flatten() {
+            collect_items_refs(spec, out);
+        }
+    }
+}
+
+fn collect_items_refs(spec: &ItemsSpec, out: &mut Vec<String>) {
+    match spec {
+        ItemsSpec::Simple(_) => {}
+        ItemsSpec::Ref(type_ref) => out.push(type_ref.clone()),
+        ItemsSpec::Map { value_type, .. } => collect_items_refs(value_type, out),
+        ItemsSpec::OneOf(variants) => out.extend(variants.values().cloned()),
+    }
+}
+
+/// Rough heap size of a JSON value.
+fn json_bytes(value: &JsonValue) -> usize {
+    JSON_NODE_BYTES
+        + match value {
+            JsonValue::String(s) => s.len(),
+            JsonValue::Array(items) => items.iter().map(json_bytes).sum(),
+            JsonValue::Object(map) => map.iter().map(|(k, v)| k.len() + json_bytes(v)).sum(),
+            _ => 0,
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projection::{BytesRender, EnumRender, TimeRender, U64Format};
+    use serde_json::json;
+
+    fn key(payload: u8, type_id: &str) -> ProjectionKey {
+        ProjectionKey {
+            payload_hash: [payload; 32],
+            type_id: type_id.to_string(),
+            type_version: 1,
+            options: RenderOptions {
+                bytes_render: BytesRender::Base64,
+                u64_format: U64Format::Number,
+                enum_render: EnumRender::Label,
+                time_render: TimeRender::Iso,
+                include_unknown: false,
+                apply_defaults: false,
+            },
+        }
+    }
+
+    fn result() -> ProjectionResult {
+        ProjectionResult {
+            data: json!({ "text": "hello" }),
+            unknown: None,
+            defaulted: None,
+        }
+    }
+
+    #[test]
+    fn test_cache_evicts_and_invalidates() {
+        let mut sizing = ProjectionCache::new(ProjectionCacheConfig {
+            budget_bytes: u64::MAX,
+        });
+        sizing.insert(key(0, "a.Message"), &result(), vec!["a.Message".into()]);
+        let entry = sizing.stats().bytes;
+
+        let mut cache = ProjectionCache::new(ProjectionCacheConfig {
+            budget_bytes: entry * 3,
+        });
+        cache.insert(key(1, "a.Message"), &result(), vec!["a.Message".into()]);
+        cache.insert(key(2, "b.Message"), &result(), vec!["b.Message".into()]);
+        cache.insert(key(3, "b.Message"), &result(), vec!["b.Message".into()]);
+        assert!(cache.get(&key(1, "a.Message")).is_some());
+        cache.insert(key(4, "b.Message"), &result(), vec!["b.Message".into()]);
+        assert!(!cache.contains(&key(2, "b.Message")));
+
+        // Only entries projected with the changed type are dropped.
+        cache.invalidate_types(&HashSet::from(["b.Message".to_string()]));
+        assert!(cache.contains(&key(1, "a.Message")));
+        assert!(!cache.contains(&key(3, "b.Message")));
+
+        let mut options_key = key(1, "a.Message");
+        options_key.options.u64_format = U64Format::String;
+        assert!(cache.get(&options_key).is_none());
+
+        let stats = cache.stats();
+        assert_eq!(
+            (stats.entries, stats.evictions, stats.invalidations),
+            (1, 1, 2)
+        );
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        assert_eq!(stats.bytes, entry);
+    }
+}
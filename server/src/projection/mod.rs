@@ -1,54 +1,62 @@
 // Copyright 2025 StrongDM Inc
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use base64::Engine;
 use chrono::{DateTime, Utc};
 use rmpv::Value;
 use serde_json::{Map, Number, Value as JsonValue};
 
+use crate::deadline::Deadline;
 use crate::error::{Result, StoreError};
 use crate::registry::{ItemsSpec, Registry, TypeVersionSpec};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub mod cache;
+pub mod text;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum BytesRender {
     Base64,
     Hex,
     LenOnly,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum U64Format {
     String,
     Number,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum EnumRender {
     Label,
     Number,
     Both,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TimeRender {
     Iso,
     UnixMs,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct RenderOptions {
     pub bytes_render: BytesRender,
     pub u64_format: U64Format,
     pub enum_render: EnumRender,
     pub time_render: TimeRender,
     pub include_unknown: bool,
+    /// Project a field's declared default when the payload lacks it.
+    pub apply_defaults: bool,
 }
 
 pub struct ProjectionResult {
     pub data: JsonValue,
     pub unknown: Option<JsonValue>,
+    /// Top-level fields filled from defaults, when `apply_defaults` is set.
+    pub defaulted: Option<Vec<String>>,
 }
 
 pub fn project_msgpack(
@@ -56,6 +64,18 @@ pub fn project_msgpack(
     descriptor: &TypeVersionSpec,
     registry: &Registry,
     options: &RenderOptions,
+) -> Result<ProjectionResult> {
+    project_msgpack_with_deadline(payload, descriptor, registry, options, &Deadline::none())
+}
+
+/// Like `project_msgpack`, but checks `deadline` between fields and array
+/// items and aborts with `StoreError::DeadlineExceeded` once it passes.
+pub fn project_msgpack_with_deadline(
+    payload: &[u8],
+    descriptor: &TypeVersionSpec,
+    registry: &Registry,
+    options: &RenderOptions,
+    deadline: &Deadline,
 ) -> Result<ProjectionResult> {
     let mut cursor = std::io::Cursor::new(payload);
     let value = rmpv::decode::read_value(&mut cursor)
@@ -64,13 +84,19 @@ pub fn project_msgpack(
     let map = normalize_tags(&value)?;
     let mut data = Map::new();
     let mut unknown = Map::new();
+    let mut defaulted = Vec::new();
 
     for (tag, field) in descriptor.fields.iter() {
+        deadline.check("projection")?;
         if let Some(val) = map.get(tag) {
-            let rendered = render_field_value(val, field, registry, options);
+            let rendered = render_field_value(val, field, registry, options, deadline)?;
             data.insert(field.name.clone(), rendered);
+        } else if let Some(rendered) = render_default(field, registry, options, deadline)? {
+            data.insert(field.name.clone(), rendered);
+            defaulted.push(field.name.clone());
         }
     }
+    defaulted.sort();
 
     if options.include_unknown {
         for (tag, val) in map.iter() {
@@ -88,9 +114,50 @@ pub fn project_msgpack(
         } else {
             None
         },
+        defaulted: options.apply_defaults.then_some(defaulted),
     })
 }
 
+/// A missing field's default, rendered like a payload value so render options
+/// apply to it.
+fn render_default(
+    field: &crate::registry::FieldSpec,
+    registry: &Registry,
+    options: &RenderOptions,
+    deadline: &Deadline,
+) -> Result<Option<JsonValue>> {
+    match &field.default {
+        Some(default) if options.apply_defaults => {
+            let value = json_to_msgpack(default);
+            render_field_value(&value, field, registry, options, deadline).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+pub(crate) fn json_to_msgpack(value: &JsonValue) -> Value {
+    match value {
+        JsonValue::Null => Value::Nil,
+        JsonValue::Bool(b) => Value::Boolean(*b),
+        JsonValue::Number(n) => {
+            if let Some(u) = n.as_u64() {
+                Value::from(u)
+            } else if let Some(i) = n.as_i64() {
+                Value::from(i)
+            } else {
+                Value::F64(n.as_f64().unwrap_or(0.0))
+            }
+        }
+        JsonValue::String(s) => Value::from(s.as_str()),
+        JsonValue::Array(items) => Value::Array(items.iter().map(json_to_msgpack).collect()),
+        JsonValue::Object(obj) => Value::Map(
+            obj.iter()
+                .map(|(k, v)| (Value::from(k.as_str()), json_to_msgpack(v)))
+                .collect(),
+        ),
+    }
+}
+
 fn normalize_tags(value: &Value) -> Result<HashMap<u64, Value>> {
     let mut out = HashMap::new();
     let map = match value {
@@ -123,12 +190,13 @@ fn render_field_value(
     field: &crate::registry::FieldSpec,
     registry: &Registry,
     options: &RenderOptions,
-) -> JsonValue {
+    deadline: &Deadline,
+) -> Result<JsonValue> {
     if let Some(enum_ref) = &field.enum_ref {
         if let Some(num) = value_to_u64(value) {
             if let Some(map) = registry.get_enum(enum_ref) {
                 if let Some(label) = map.get(&num.to_string()) {
-                    return match options.enum_render {
+                    return Ok(match options.enum_render {
                         EnumRender::Label => JsonValue::String(label.clone()),
                         EnumRender::Number => JsonValue::Number(Number::from(num)),
                         EnumRender::Both => {
@@ -137,7 +205,7 @@ fn render_field_value(
                             obj.insert("value".into(), JsonValue::Number(Number::from(num)));
                             JsonValue::Object(obj)
                         }
-                    };
+                    });
                 }
             }
         }
@@ -146,21 +214,27 @@ fn render_field_value(
     // Handle type references - recursively project using the referenced type
     if field.field_type == "ref" {
         if let Some(type_ref) = &field.type_ref {
-            return render_type_ref(value, type_ref, registry, options);
+            return render_type_ref(value, type_ref, registry, options, deadline);
         }
     }
 
     let field_type = field.field_type.as_str();
-    match field_type {
+    Ok(match field_type {
         "u64" | "uint64" | "int64" => render_u64(value, options),
         "u32" | "uint32" | "u8" | "uint8" | "int32" => render_int(value),
         "string" => render_string(value),
         "bool" => render_bool(value),
         "bytes" | "typed_blob" => render_bytes(value, options),
-        "array" => render_array(value, field.items.as_ref(), registry, options),
+        "array" => return render_array(value, field.items.as_ref(), registry, options, deadline),
+        "map" | "oneof" => {
+            return match &field.shape {
+                Some(shape) => render_items(value, shape, registry, options, deadline),
+                None => Ok(render_value(value, options)),
+            }
+        }
         "unix_ms" | "time_ms" | "timestamp_ms" => render_time(value, options),
         _ => render_value(value, options),
-    }
+    })
 }
 
 /// Recursively project a value using a referenced type's descriptor
@@ -169,28 +243,31 @@ fn render_type_ref(
     type_ref: &str,
     registry: &Registry,
     options: &RenderOptions,
-) -> JsonValue {
+    deadline: &Deadline,
+) -> Result<JsonValue> {
     // Get the latest version of the referenced type
     let Some(type_spec) = registry.get_latest_type_version(type_ref) else {
         // Fall back to raw rendering if type not found
-        return render_value(value, options);
+        return Ok(render_value(value, options));
     };
 
     // Normalize the value to a tag map
     let Ok(map) = normalize_tags(value) else {
-        return render_value(value, options);
+        return Ok(render_value(value, options));
     };
 
     // Project using the type descriptor
     let mut data = Map::new();
     for (tag, field) in type_spec.fields.iter() {
         if let Some(val) = map.get(tag) {
-            let rendered = render_field_value(val, field, registry, options);
+            let rendered = render_field_value(val, field, registry, options, deadline)?;
+            data.insert(field.name.clone(), rendered);
+        } else if let Some(rendered) = render_default(field, registry, options, deadline)? {
             data.insert(field.name.clone(), rendered);
         }
     }
 
-    JsonValue::Object(data)
+    Ok(JsonValue::Object(data))
 }
 
 fn render_value(value: &Value, options: &RenderOptions) -> JsonValue {
@@ -288,36 +365,115 @@ fn render_array(
     items_spec: Option<&ItemsSpec>,
     registry: &Registry,
     options: &RenderOptions,
-) -> JsonValue {
+    deadline: &Deadline,
+) -> Result<JsonValue> {
     let arr = match value {
         Value::Array(arr) => arr,
-        _ => return JsonValue::Null,
+        _ => return Ok(JsonValue::Null),
     };
 
     let mut out = Vec::with_capacity(arr.len());
     for item in arr.iter() {
+        deadline.check("projection")?;
         let rendered = match items_spec {
-            Some(ItemsSpec::Simple(item_type)) => {
-                let dummy_field = crate::registry::FieldSpec {
-                    name: "".into(),
-                    field_type: item_type.clone(),
-                    enum_ref: None,
-                    type_ref: None,
-                    optional: false,
-                    items: None,
-                };
-                render_field_value(item, &dummy_field, registry, options)
-            }
-            Some(ItemsSpec::Ref(type_ref)) => {
-                // Recursively project array items using the referenced type
-                render_type_ref(item, type_ref, registry, options)
-            }
+            Some(spec) => render_items(item, spec, registry, options, deadline)?,
             None => render_value(item, options),
         };
         out.push(rendered);
     }
 
-    JsonValue::Array(out)
+    Ok(JsonValue::Array(out))
+}
+
+/// Render a contained value (array item, map value, union variant) by its spec.
+fn render_items(
+    value: &Value,
+    spec: &ItemsSpec,
+    registry: &Registry,
+    options: &RenderOptions,
+    deadline: &Deadline,
+) -> Result<JsonValue> {
+    match spec {
+        ItemsSpec::Simple(item_type) => {
+            let dummy_field = crate::registry::FieldSpec {
+                name: "".into(),
+                field_type: item_type.clone(),
+                enum_ref: None,
+                type_ref: None,
+                optional: false,
+                items: None,
+                shape: None,
+                default: None,
+                deprecation: None,
+                count_tokens: false,
+            };
+            render_field_value(value, &dummy_field, registry, options, deadline)
+        }
+        // Recursively project using the referenced type
+        ItemsSpec::Ref(type_ref) => render_type_ref(value, type_ref, registry, options, deadline),
+        ItemsSpec::Map { value_type, .. } => {
+            render_map(value, value_type, registry, options, deadline)
+        }
+        ItemsSpec::OneOf(variants) => render_oneof(value, variants, registry, options, deadline),
+    }
+}
+
+/// Render a msgpack map as a JSON object; integer keys become decimal strings.
+fn render_map(
+    value: &Value,
+    value_type: &ItemsSpec,
+    registry: &Registry,
+    options: &RenderOptions,
+    deadline: &Deadline,
+) -> Result<JsonValue> {
+    let Value::Map(map) = value else {
+        return Ok(JsonValue::Null);
+    };
+
+    let mut out = Map::new();
+    for (k, v) in map.iter() {
+        deadline.check("projection")?;
+        let key = match k {
+            Value::String(s) => s.as_str().unwrap_or("").to_string(),
+            Value::Integer(int) => int.to_string(),
+            _ => continue,
+        };
+        out.insert(
+            key,
+            render_items(v, value_type, registry, options, deadline)?,
+        );
+    }
+
+    Ok(JsonValue::Object(out))
+}
+
+/// Render a tagged union, encoded as a single-entry map of variant tag to
+/// value, as `{"type": <type ref>, "value": <projected value>}`. Unknown
+/// variants and malformed encodings fall back to raw rendering.
+fn render_oneof(
+    value: &Value,
+    variants: &BTreeMap<u64, String>,
+    registry: &Registry,
+    options: &RenderOptions,
+    deadline: &Deadline,
+) -> Result<JsonValue> {
+    let variant = match value {
+        Value::Map(map) if map.len() == 1 => key_to_tag(&map[0].0)
+            .and_then(|tag| variants.get(&tag))
+            .map(|type_ref| (type_ref, &map[0].1)),
+        _ => None,
+    };
+    let Some((type_ref, inner)) = variant else {
+        return Ok(render_value(value, options));
+    };
+
+    let mut obj = Map::new();
+    obj.insert("type".into(), JsonValue::String(type_ref.clone()));
+    obj.insert(
+        "value".into(),
+        render_type_ref(inner, type_ref, registry, options, deadline)?,
+    );
+    Ok(JsonValue::Object(obj))
 }
 
 fn render_time(value: &Value, options: &RenderOptions) -> JsonValue {
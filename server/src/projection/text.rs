@@ -0,0 +1,120 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Plain text / Markdown rendering of projected turns.
+//!
+//! A type may declare a `text_template` in its renderer spec. Placeholders of
+//! the form `{{path}}` are replaced with the projected value at that dotted
+//! path (`{{message.text}}`, `{{items.0.name}}`); missing values render as
+//! nothing. Types without a template fall back to an indented field dump.
+
+use serde_json::Value as JsonValue;
+
+/// Render projected turn data as text, using `template` when given.
+pub fn render_text(data: &JsonValue, template: Option<&str>) -> String {
+    match template {
+        Some(template) => render_template(template, data),
+        None => {
+            let mut out = String::new();
+            dump_value(data, 0, &mut out);
+            out.trim_end().to_string()
+        }
+    }
+}
+
+fn render_template(template: &str, data: &JsonValue) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            // Unterminated placeholder; emit the remainder verbatim.
+            out.push_str(&rest[start..]);
+            return out;
+        };
+        if let Some(value) = lookup(data, after[..end].trim()) {
+            out.push_str(&scalar_text(value));
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Projected value at a dotted path (`message.text`, `items.0.name`).
+pub(crate) fn lookup<'a>(data: &'a JsonValue, path: &str) -> Option<&'a JsonValue> {
+    if path.is_empty() {
+        return None;
+    }
+    path.split('.')
+        .try_fold(data, |value, segment| match value {
+            JsonValue::Object(obj) => obj.get(segment),
+            JsonValue::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        })
+}
+
+/// Text of a value as a template placeholder shows it.
+pub(crate) fn scalar_text(value: &JsonValue) -> String {
+    match value {
+        JsonValue::Null => String::new(),
+        JsonValue::String(s) => s.clone(),
+        JsonValue::Bool(_) | JsonValue::Number(_) => value.to_string(),
+        // Composite values inside a template are shown compactly.
+        JsonValue::Array(_) | JsonValue::Object(_) => value.to_string(),
+    }
+}
+
+fn dump_value(value: &JsonValue, indent: usize, out: &mut String) {
+    let pad = "  ".repeat(indent);
+    match value {
+        JsonValue::Object(obj) => {
+            for (key, field) in obj {
+                if field.is_null() {
+                    continue;
+                }
+                dump_entry(&format!("{pad}**{key}**:"), field, indent, out);
+            }
+        }
+        JsonValue::Array(items) => {
+            for item in items {
+                dump_entry(&format!("{pad}-"), item, indent, out);
+            }
+        }
+        _ => {
+            out.push_str(&pad);
+            out.push_str(&scalar_text(value));
+            out.push('\n');
+        }
+    }
+}
+
+fn dump_entry(label: &str, value: &JsonValue, indent: usize, out: &mut String) {
+    out.push_str(label);
+    match value {
+        JsonValue::Object(obj) if !obj.is_empty() => {
+            out.push('\n');
+            dump_value(value, indent + 1, out);
+        }
+        JsonValue::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            dump_value(value, indent + 1, out);
+        }
+        JsonValue::String(s) if s.contains('\n') => {
+            // Multi-line text goes on its own indented lines.
+            out.push('\n');
+            let pad = "  ".repeat(indent + 1);
+            for line in s.lines() {
+                out.push_str(&pad);
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        _ => {
+            out.push(' ');
+            out.push_str(&scalar_text(value));
+            out.push('\n');
+        }
+    }
+}
@@ -0,0 +1,258 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Projects: named groups of related contexts.
+//!
+//! A context can belong to any number of projects. Creations, assignments
+//! and removals are appended to a JSON-lines log; replaying it rebuilds the
+//! projects and their members.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::jsonl_log::open_log;
+use crate::util::unix_ms;
+
+/// Longest accepted project id.
+pub const MAX_PROJECT_ID_LEN: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Project {
+    /// Caller-chosen id, e.g. `exp-42`.
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    pub created_at_unix_ms: u64,
+}
+
+/// Body of `POST /v1/projects`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProjectSpec {
+    pub id: String,
+    /// Display name; defaults to the id.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// One line of the projects log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum ProjectEntry {
+    Create(Project),
+    Assign {
+        project_id: String,
+        context_id: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        principal: Option<String>,
+        at_unix_ms: u64,
+    },
+    Unassign {
+        project_id: String,
+        context_id: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        principal: Option<String>,
+        at_unix_ms: u64,
+    },
+}
+
+/// Check that a project id is 1 to 64 ASCII letters, digits, `.`, `_` or `-`.
+pub fn validate_project_id(id: &str) -> Result<()> {
+    let valid = !id.is_empty()
+        && id.len() <= MAX_PROJECT_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(StoreError::InvalidInput(format!(
+            "invalid project id {id:?}: use 1-{MAX_PROJECT_ID_LEN} letters, digits, '.', '_' or '-'"
+        )))
+    }
+}
+
+pub struct Projects {
+    file: File,
+    projects: BTreeMap<String, Project>,
+    members: HashMap<String, BTreeSet<u64>>,
+    by_context: HashMap<u64, BTreeSet<String>>,
+}
+
+impl Projects {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("projects.jsonl");
+        let (file, entries) = open_log::<ProjectEntry>(&path)?;
+
+        let mut projects = Self {
+            file,
+            projects: BTreeMap::new(),
+            members: HashMap::new(),
+            by_context: HashMap::new(),
+        };
+        for entry in entries {
+            projects.apply(entry);
+        }
+        Ok(projects)
+    }
+
+    fn apply(&mut self, entry: ProjectEntry) {
+        match entry {
+            ProjectEntry::Create(project) => {
+                self.projects.insert(project.id.clone(), project);
+            }
+            ProjectEntry::Assign {
+                project_id,
+                context_id,
+                ..
+            } => {
+                self.by_context
+                    .entry(context_id)
+                    .or_default()
+                    .insert(project_id.clone());
+                self.members
+                    .entry(project_id)
+                    .or_default()
+                    .insert(context_id);
+            }
+            ProjectEntry::Unassign {
+                project_id,
+                context_id,
+                ..
+            } => {
+                if let Some(ids) = self.by_context.get_mut(&context_id) {
+                    ids.remove(&project_id);
+                    if ids.is_empty() {
+                        self.by_context.remove(&context_id);
+                    }
+                }
+                if let Some(members) = self.members.get_mut(&project_id) {
+                    members.remove(&context_id);
+                }
+            }
+        }
+    }
+
+    fn append(&mut self, entry: ProjectEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(&entry)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.apply(entry);
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Project> {
+        self.projects.get(id)
+    }
+
+    /// Every project, ordered by id.
+    pub fn list(&self) -> impl Iterator<Item = &Project> {
+        self.projects.values()
+    }
+
+    /// Contexts assigned to a project, ascending.
+    pub fn contexts(&self, id: &str) -> Vec<u64> {
+        self.members
+            .get(id)
+            .map(|members| members.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Projects a context is assigned to, ordered by id.
+    pub fn of_context(&self, context_id: u64) -> Vec<String> {
+        self.by_context
+            .get(&context_id)
+            .map(|ids| ids.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Create a project. Ids are never reused.
+    pub fn create(
+        &mut self,
+        id: &str,
+        name: Option<&str>,
+        description: Option<&str>,
+        principal: Option<&str>,
+    ) -> Result<Project> {
+        validate_project_id(id)?;
+        if self.projects.contains_key(id) {
+            return Err(StoreError::InvalidInput(format!(
+                "project {id} already exists"
+            )));
+        }
+        let project = Project {
+            id: id.to_string(),
+            name: name
+                .map(str::trim)
+                .filter(|n| !n.is_empty())
+                .unwrap_or(id)
+                .to_string(),
+            description: description
+                .map(str::trim)
+                .filter(|d| !d.is_empty())
+                .map(str::to_string),
+            created_by: principal.map(str::to_string),
+            created_at_unix_ms: unix_ms(),
+        };
+        self.append(ProjectEntry::Create(project.clone()))?;
+        Ok(project)
+    }
+
+    /// Add a context to a project. Returns false if it already belonged to
+    /// it. The caller checks that the context exists.
+    pub fn assign(&mut self, id: &str, context_id: u64, principal: Option<&str>) -> Result<bool> {
+        self.require(id)?;
+        if self.is_member(id, context_id) {
+            return Ok(false);
+        }
+        self.append(ProjectEntry::Assign {
+            project_id: id.to_string(),
+            context_id,
+            principal: principal.map(str::to_string),
+            at_unix_ms: unix_ms(),
+        })?;
+        Ok(true)
+    }
+
+    /// Remove a context from a project. Returns false if it did not belong
+    /// to it.
+    pub fn unassign(&mut self, id: &str, context_id: u64, principal: Option<&str>) -> Result<bool> {
+        self.require(id)?;
+        if !self.is_member(id, context_id) {
+            return Ok(false);
+        }
+        self.append(ProjectEntry::Unassign {
+            project_id: id.to_string(),
+            context_id,
+            principal: principal.map(str::to_string),
+            at_unix_ms: unix_ms(),
+        })?;
+        Ok(true)
+    }
+
+    fn require(&self, id: &str) -> Result<()> {
+        if self.projects.contains_key(id) {
+            Ok(())
+        } else {
+            Err(StoreError::NotFound(format!("project {id}")))
+        }
+    }
+
+    fn is_member(&self, id: &str, context_id: u64) -> bool {
+        self.members
+            .get(id)
+            .is_some_and(|members| members.contains(&context_id))
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Stable error codes sent in ERROR frames.
+//!
+//! The HTTP-style `code` of an error frame groups failures coarsely (a 500
+//! may be a full disk or a corrupt record); the error code names the failure
+//! and says whether the same request may succeed when retried. Codes are
+//! never renumbered; new failures get new codes.
+
+use std::io::ErrorKind;
+
+use serde_json::{json, Value as JsonValue};
+
+use crate::error::StoreError;
+
+/// Wait suggested before retrying after a storage failure.
+const STORAGE_RETRY_AFTER_MS: u32 = 1_000;
+/// Wait suggested before retrying after running out of disk space.
+const STORAGE_FULL_RETRY_AFTER_MS: u32 = 30_000;
+
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    MalformedFrame = 1,
+    InvalidInput = 2,
+    NotFound = 3,
+    PermissionDenied = 4,
+    Cancelled = 5,
+    Shredded = 6,
+    DeadlineExceeded = 7,
+    Corrupt = 8,
+    Storage = 9,
+    StorageFull = 10,
+    UnsupportedMessage = 11,
+    Expired = 12,
+    Unauthenticated = 13,
+    DepthLimitExceeded = 14,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 14] = [
+        ErrorCode::MalformedFrame,
+        ErrorCode::InvalidInput,
+        ErrorCode::NotFound,
+        ErrorCode::PermissionDenied,
+        ErrorCode::Cancelled,
+        ErrorCode::Shredded,
+        ErrorCode::DeadlineExceeded,
+        ErrorCode::Corrupt,
+        ErrorCode::Storage,
+        ErrorCode::StorageFull,
+        ErrorCode::UnsupportedMessage,
+        ErrorCode::Expired,
+        ErrorCode::Unauthenticated,
+        ErrorCode::DepthLimitExceeded,
+    ];
+
+    /// Classify a store error.
+    pub fn of(err: &StoreError) -> Self {
+        match err {
+            StoreError::MalformedFrame { .. } => ErrorCode::MalformedFrame,
+            StoreError::InvalidInput(_) => ErrorCode::InvalidInput,
+            StoreError::NotFound(_) => ErrorCode::NotFound,
+            StoreError::PermissionDenied(_) => ErrorCode::PermissionDenied,
+            StoreError::Cancelled(_) => ErrorCode::Cancelled,
+            StoreError::Shredded(_) => ErrorCode::Shredded,
+            StoreError::DeadlineExceeded(_) => ErrorCode::DeadlineExceeded,
+            StoreError::Corrupt(_) | StoreError::UnsupportedFormat { .. } => ErrorCode::Corrupt,
+            StoreError::Io(e) if e.kind() == ErrorKind::StorageFull => ErrorCode::StorageFull,
+            StoreError::Io(_) => ErrorCode::Storage,
+            StoreError::UnsupportedMessage { .. } => ErrorCode::UnsupportedMessage,
+            StoreError::Expired(_) => ErrorCode::Expired,
+            StoreError::Unauthenticated(_) => ErrorCode::Unauthenticated,
+            StoreError::DepthLimitExceeded { .. } => ErrorCode::DepthLimitExceeded,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCode::MalformedFrame => "MALFORMED_FRAME",
+            ErrorCode::InvalidInput => "INVALID_INPUT",
+            ErrorCode::NotFound => "NOT_FOUND",
+            ErrorCode::PermissionDenied => "PERMISSION_DENIED",
+            ErrorCode::Cancelled => "CANCELLED",
+            ErrorCode::Shredded => "SHREDDED",
+            ErrorCode::DeadlineExceeded => "DEADLINE_EXCEEDED",
+            ErrorCode::Corrupt => "CORRUPT",
+            ErrorCode::Storage => "STORAGE",
+            ErrorCode::StorageFull => "STORAGE_FULL",
+            ErrorCode::UnsupportedMessage => "UNSUPPORTED_MESSAGE",
+            ErrorCode::Expired => "EXPIRED",
+            ErrorCode::Unauthenticated => "UNAUTHENTICATED",
+            ErrorCode::DepthLimitExceeded => "DEPTH_LIMIT_EXCEEDED",
+        }
+    }
+
+    /// HTTP-style status sent as the frame's `code`.
+    pub fn status(self) -> u32 {
+        match self {
+            ErrorCode::MalformedFrame => 400,
+            ErrorCode::InvalidInput => 422,
+            ErrorCode::NotFound => 404,
+            ErrorCode::PermissionDenied => 403,
+            ErrorCode::Unauthenticated => 401,
+            ErrorCode::Cancelled | ErrorCode::DepthLimitExceeded => 409,
+            ErrorCode::Shredded | ErrorCode::Expired => 410,
+            ErrorCode::DeadlineExceeded => 504,
+            ErrorCode::Corrupt | ErrorCode::Storage => 500,
+            ErrorCode::StorageFull => 507,
+            ErrorCode::UnsupportedMessage => 501,
+        }
+    }
+
+    /// Whether the same request may succeed if sent again. Failures caused
+    /// by the request itself or by stored data are permanent.
+    pub fn retryable(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::DeadlineExceeded | ErrorCode::Storage | ErrorCode::StorageFull
+        )
+    }
+
+    /// Suggested wait before retrying; None leaves it to the client's backoff.
+    pub fn retry_after_ms(self) -> Option<u32> {
+        match self {
+            ErrorCode::Storage => Some(STORAGE_RETRY_AFTER_MS),
+            ErrorCode::StorageFull => Some(STORAGE_FULL_RETRY_AFTER_MS),
+            _ => None,
+        }
+    }
+}
+
+/// The error code registry, for `/v1/protocol/schema`.
+pub fn error_code_schema() -> JsonValue {
+    JsonValue::Array(
+        ErrorCode::ALL
+            .iter()
+            .map(|code| {
+                json!({
+                    "error_code": *code as u16,
+                    "name": code.name(),
+                    "status": code.status(),
+                    "retryable": code.retryable(),
+                    "retry_after_ms": code.retry_after_ms(),
+                })
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,605 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Binary protocol payload definitions.
+//!
+//! This file is the single source of truth for payload layouts: structs,
+//! decoders, encoders and `/v1/protocol/schema` are all generated from it.
+//! To add a message, declare its request/response payloads here, add a
+//! `MsgType` variant, list it in `protocol_schema` and give it golden
+//! fixtures in `compat`.
+
+use serde_json::{json, Value as JsonValue};
+
+use super::error_codes::error_code_schema;
+use super::wire::{wire_struct, WireStruct};
+use super::{MsgType, MAX_FRAME_SIZE};
+
+/// Version of the wire schema; bumped when a payload layout changes.
+pub const SCHEMA_VERSION: u32 = 9;
+
+wire_struct! {
+    /// HELLO request. An empty payload (legacy clients) decodes to defaults.
+    /// Flag bit 0 asks for a resume token, and resumes the session named by
+    /// resume_session_id unless it is 0. Flag bit 1 carries an OIDC bearer
+    /// token that authenticates the session.
+    HelloRequest {
+        protocol_version: U16,
+        client_tag: Str16,
+        /// JSON object with client metadata.
+        client_meta_json: OptStr,
+        resume_session_id: U64 [flag 0],
+        /// Token from the HELLO response that started or last resumed the session.
+        resume_token: Bytes [flag 0],
+        /// Signed JWT from the configured OIDC issuer.
+        auth_token: Str [flag 1],
+    }
+}
+
+wire_struct! {
+    /// HELLO response. session_id differs from the requested
+    /// resume_session_id when the session could not be resumed.
+    HelloResponse {
+        session_id: U64,
+        protocol_version: U16,
+        /// Present when the request set flag bit 0 (decode with flag bit 0 set).
+        resume_token: Bytes [flag 0],
+    }
+}
+
+wire_struct! {
+    /// CTX_CREATE request. base_turn_id 0 creates an empty context.
+    CtxCreateRequest {
+        base_turn_id: U64,
+        /// Caller-assigned id (e.g. a ULID) no other context may have.
+        external_id: Str16 [flag 0],
+        /// Seconds until the context expires and is tombstoned.
+        ttl_secs: U64 [flag 1],
+    }
+}
+
+wire_struct! {
+    /// CTX_FORK request.
+    CtxForkRequest {
+        base_turn_id: U64,
+    }
+}
+
+wire_struct! {
+    /// GET_HEAD request.
+    GetHeadRequest {
+        context_id: U64,
+    }
+}
+
+wire_struct! {
+    /// Response to CTX_CREATE, CTX_FORK and GET_HEAD.
+    ContextHeadResponse {
+        context_id: U64,
+        head_turn_id: U64,
+        head_depth: U32,
+    }
+}
+
+wire_struct! {
+    /// APPEND_TURN request.
+    AppendTurnRequest {
+        context_id: U64,
+        parent_turn_id: U64,
+        declared_type_id: Str,
+        declared_type_version: U32,
+        encoding: U32,
+        compression: U32,
+        uncompressed_len: U32,
+        /// BLAKE3 hash of the uncompressed payload.
+        content_hash: Hash32,
+        payload_bytes: Bytes,
+        idempotency_key: Bytes,
+        /// Filesystem snapshot root to attach to the new turn.
+        fs_root_hash: Hash32 [flag 0],
+    }
+}
+
+wire_struct! {
+    /// APPEND_TURN response.
+    AppendTurnResponse {
+        context_id: U64,
+        new_turn_id: U64,
+        new_depth: U32,
+        content_hash: Hash32,
+    }
+}
+
+wire_struct! {
+    /// GET_LAST request. include_payload is 0 or 1.
+    GetLastRequest {
+        context_id: U64,
+        limit: U32,
+        include_payload: U32,
+    }
+}
+
+wire_struct! {
+    /// GET_RANGE_BY_DEPTH request: up to limit turns of the context's
+    /// history starting at start_depth. include_payload is 0 or 1.
+    GetRangeByDepthRequest {
+        context_id: U64,
+        start_depth: U32,
+        limit: U32,
+        include_payload: U32,
+    }
+}
+
+wire_struct! {
+    /// One turn in a GET_LAST or GET_RANGE_BY_DEPTH response.
+    TurnItem {
+        turn_id: U64,
+        parent_turn_id: U64,
+        depth: U32,
+        declared_type_id: Str,
+        declared_type_version: U32,
+        encoding: U32,
+        compression: U32,
+        uncompressed_len: U32,
+        content_hash: Hash32,
+        /// Present when the request set include_payload (decode with flag bit 0 set).
+        payload: Bytes [flag 0],
+    }
+}
+
+wire_struct! {
+    /// GET_LAST and GET_RANGE_BY_DEPTH response, oldest turn first.
+    GetLastResponse {
+        turns: List<TurnItem>,
+    }
+}
+
+wire_struct! {
+    /// GET_BLOB request.
+    GetBlobRequest {
+        hash: Hash32,
+    }
+}
+
+wire_struct! {
+    /// GET_BLOB response.
+    GetBlobResponse {
+        data: Bytes,
+    }
+}
+
+wire_struct! {
+    /// ATTACH_FS request. Flag bit 0 adds the working-directory metadata the
+    /// snapshot was captured with.
+    AttachFsRequest {
+        turn_id: U64,
+        fs_root_hash: Hash32,
+        /// Unix milliseconds when the snapshot was captured; 0 = unknown.
+        captured_at_unix_ms: U64 [flag 0],
+        base_path: OptStr [flag 0],
+        git_commit: OptStr [flag 0],
+        git_branch: OptStr [flag 0],
+        /// 1 if the working tree had uncommitted changes; ignored without git_commit.
+        git_dirty: U8 [flag 0],
+        /// Files, directories and symlinks in the snapshot.
+        total_entries: U64 [flag 0],
+    }
+}
+
+wire_struct! {
+    /// ATTACH_FS response.
+    AttachFsResponse {
+        turn_id: U64,
+        fs_root_hash: Hash32,
+    }
+}
+
+wire_struct! {
+    /// A blob sent inline with ATTACH_FS_OVERLAY, stored under its BLAKE3 hash.
+    OverlayBlob {
+        data: Bytes,
+    }
+}
+
+wire_struct! {
+    /// One path change in ATTACH_FS_OVERLAY.
+    OverlayChangeItem {
+        /// Slash-separated path from the snapshot root.
+        path: Str,
+        /// 0=file, 1=directory, 2=symlink, 255=remove.
+        kind: U8,
+        mode: U32,
+        size: U64,
+        /// Content (file), tree (directory) or target (symlink) hash; ignored when removing.
+        hash: Hash32,
+    }
+}
+
+wire_struct! {
+    /// ATTACH_FS_OVERLAY request: build a snapshot from a base root plus
+    /// changes and attach it to a turn.
+    AttachFsOverlayRequest {
+        turn_id: U64,
+        /// Snapshot to apply the changes to; all zeros means the snapshot the
+        /// turn currently sees (or an empty tree).
+        base_root_hash: Hash32,
+        /// New file contents and subtrees referenced by the changes.
+        blobs: List<OverlayBlob>,
+        changes: List<OverlayChangeItem>,
+    }
+}
+
+wire_struct! {
+    /// ATTACH_FS_OVERLAY response.
+    AttachFsOverlayResponse {
+        turn_id: U64,
+        fs_root_hash: Hash32,
+        /// Tree objects the server created.
+        trees_written: U32,
+    }
+}
+
+wire_struct! {
+    /// PUT_BLOB request.
+    PutBlobRequest {
+        /// BLAKE3 hash of data.
+        hash: Hash32,
+        data: Bytes,
+        /// Context the blob belongs to; encrypted contexts seal it with their key.
+        context_id: U64 [flag 0],
+    }
+}
+
+wire_struct! {
+    /// PUT_BLOB response.
+    PutBlobResponse {
+        hash: Hash32,
+        /// 1 if the blob was newly stored, 0 if it already existed.
+        was_new: U8,
+    }
+}
+
+wire_struct! {
+    /// UPDATE_SESSION_STATUS request: a transient progress report. context_id
+    /// 0 reports on the session itself. An empty status without progress
+    /// clears the status.
+    UpdateSessionStatusRequest {
+        context_id: U64,
+        /// E.g. "step 3/10: running tests"; at most 256 bytes.
+        status: Str16,
+        /// Fraction done in basis points (0-10000).
+        progress_bp: U16 [flag 0],
+    }
+}
+
+wire_struct! {
+    /// UPDATE_SESSION_STATUS response.
+    UpdateSessionStatusResponse {
+        context_id: U64,
+        /// When the status was recorded; 0 when it was cleared.
+        updated_at_unix_ms: U64,
+    }
+}
+
+wire_struct! {
+    /// ERROR response, sent in place of any response.
+    ErrorResponse {
+        /// HTTP-style status code.
+        code: U32,
+        detail: Str,
+        /// Stable code from the error code registry.
+        error_code: U16,
+        /// 1 if the same request may succeed when retried.
+        retryable: U8,
+        /// Suggested wait before retrying in milliseconds; 0 = no suggestion.
+        retry_after_ms: U32,
+    }
+}
+
+fn message<Req: WireStruct, Resp: WireStruct>(msg_type: MsgType, name: &str) -> JsonValue {
+    json!({
+        "msg_type": msg_type as u16,
+        "name": name,
+        "request": Req::NAME,
+        "response": Resp::NAME,
+    })
+}
+
+/// Machine-readable description of the binary protocol.
+pub fn protocol_schema() -> JsonValue {
+    let messages = vec![
+        message::<HelloRequest, HelloResponse>(MsgType::Hello, "HELLO"),
+        message::<CtxCreateRequest, ContextHeadResponse>(MsgType::CtxCreate, "CTX_CREATE"),
+        message::<CtxForkRequest, ContextHeadResponse>(MsgType::CtxFork, "CTX_FORK"),
+        message::<GetHeadRequest, ContextHeadResponse>(MsgType::GetHead, "GET_HEAD"),
+        message::<AppendTurnRequest, AppendTurnResponse>(MsgType::AppendTurn, "APPEND_TURN"),
+        message::<GetLastRequest, GetLastResponse>(MsgType::GetLast, "GET_LAST"),
+        message::<GetRangeByDepthRequest, GetLastResponse>(
+            MsgType::GetRangeByDepth,
+            "GET_RANGE_BY_DEPTH",
+        ),
+        message::<GetBlobRequest, GetBlobResponse>(MsgType::GetBlob, "GET_BLOB"),
+        message::<AttachFsRequest, AttachFsResponse>(MsgType::AttachFs, "ATTACH_FS"),
+        message::<PutBlobRequest, PutBlobResponse>(MsgType::PutBlob, "PUT_BLOB"),
+        message::<AttachFsOverlayRequest, AttachFsOverlayResponse>(
+            MsgType::AttachFsOverlay,
+            "ATTACH_FS_OVERLAY",
+        ),
+        message::<UpdateSessionStatusRequest, UpdateSessionStatusResponse>(
+            MsgType::UpdateSessionStatus,
+            "UPDATE_SESSION_STATUS",
+        ),
+    ];
+    let structs = vec![
+        HelloRequest::schema(),
+        HelloResponse::schema(),
+        CtxCreateRequest::schema(),
+        CtxForkRequest::schema(),
+        GetHeadRequest::schema(),
+        ContextHeadResponse::schema(),
+        AppendTurnRequest::schema(),
+        AppendTurnResponse::schema(),
+        GetLastRequest::schema(),
+        GetRangeByDepthRequest::schema(),
+        TurnItem::schema(),
+        GetLastResponse::schema(),
+        GetBlobRequest::schema(),
+        GetBlobResponse::schema(),
+        AttachFsRequest::schema(),
+        AttachFsResponse::schema(),
+        PutBlobRequest::schema(),
+        PutBlobResponse::schema(),
+        OverlayBlob::schema(),
+        OverlayChangeItem::schema(),
+        AttachFsOverlayRequest::schema(),
+        AttachFsOverlayResponse::schema(),
+        UpdateSessionStatusRequest::schema(),
+        UpdateSessionStatusResponse::schema(),
+        ErrorResponse::schema(),
+    ];
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "byte_order": "little_endian",
+        "max_frame_size": MAX_FRAME_SIZE,
+        "frame_header": [
+            { "name": "len", "type": "u32", "doc": "Payload length in bytes" },
+            { "name": "msg_type", "type": "u16" },
+            { "name": "flags", "type": "u16", "doc": "Gates optional request fields" },
+            { "name": "req_id", "type": "u64", "doc": "Echoed in the response" },
+        ],
+        "error": { "msg_type": MsgType::Error as u16, "name": "ERROR", "payload": ErrorResponse::NAME },
+        "error_codes": error_code_schema(),
+        "messages": messages,
+        "structs": structs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_turn_layout() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&7u64.to_le_bytes());
+        bytes.extend_from_slice(&3u64.to_le_bytes());
+        bytes.extend_from_slice(&4u32.to_le_bytes());
+        bytes.extend_from_slice(b"t.v1");
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&[9u8; 32]);
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&[0x80, 0x01]);
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+
+        let req = AppendTurnRequest::decode(&bytes, 0).unwrap();
+        assert_eq!(req.context_id, 7);
+        assert_eq!(req.parent_turn_id, 3);
+        assert_eq!(req.declared_type_id, "t.v1");
+        assert_eq!(req.payload_bytes, vec![0x80, 0x01]);
+        assert!(req.idempotency_key.is_empty());
+        assert_eq!(req.fs_root_hash, None);
+        assert_eq!(req.encode().unwrap(), bytes);
+
+        // Flag bit 0 adds a trailing fs_root_hash.
+        let mut with_fs = bytes.clone();
+        with_fs.extend_from_slice(&[5u8; 32]);
+        let req = AppendTurnRequest::decode(&with_fs, 1).unwrap();
+        assert_eq!(req.fs_root_hash, Some([5u8; 32]));
+        assert_eq!(req.encode().unwrap(), with_fs);
+        assert!(AppendTurnRequest::decode(&bytes, 1).is_err());
+    }
+
+    #[test]
+    fn test_get_last_response_roundtrip() {
+        let resp = GetLastResponse {
+            turns: vec![TurnItem {
+                turn_id: 2,
+                parent_turn_id: 1,
+                depth: 1,
+                declared_type_id: "com.example.Message".into(),
+                declared_type_version: 1,
+                encoding: 1,
+                compression: 0,
+                uncompressed_len: 3,
+                content_hash: [1u8; 32],
+                payload: Some(vec![1, 2, 3]),
+            }],
+        };
+        let bytes = resp.encode().unwrap();
+        assert_eq!(&bytes[..4], &1u32.to_le_bytes());
+        assert_eq!(GetLastResponse::decode(&bytes, 1).unwrap(), resp);
+    }
+
+    #[test]
+    fn test_error_frame_classifies_errors() {
+        use crate::error::StoreError;
+        use crate::protocol::encode_error;
+
+        let bytes = encode_error(&StoreError::NotFound("context".into())).unwrap();
+        // Legacy readers stop after code and detail.
+        assert_eq!(&bytes[..4], &404u32.to_le_bytes());
+        let resp = ErrorResponse::decode(&bytes, 0).unwrap();
+        assert_eq!(resp.detail, "context");
+        assert_eq!(resp.error_code, 3);
+        assert_eq!((resp.retryable, resp.retry_after_ms), (0, 0));
+
+        let disk_full = std::io::Error::from(std::io::ErrorKind::StorageFull);
+        let resp = ErrorResponse::decode(&encode_error(&disk_full.into()).unwrap(), 0).unwrap();
+        assert_eq!((resp.code, resp.error_code), (507, 10));
+        assert_eq!((resp.retryable, resp.retry_after_ms), (1, 30_000));
+
+        let timeout = StoreError::DeadlineExceeded("projection".into());
+        let resp = ErrorResponse::decode(&encode_error(&timeout).unwrap(), 0).unwrap();
+        assert_eq!(
+            (resp.code, resp.retryable, resp.retry_after_ms),
+            (504, 1, 0)
+        );
+    }
+
+    #[test]
+    fn test_unknown_frame_is_skipped_with_typed_error() {
+        use crate::error::StoreError;
+        use crate::protocol::{encode_error, read_frame, write_frame, MsgType};
+
+        // A frame from a newer client followed by a known one.
+        let mut stream = Vec::new();
+        write_frame(&mut stream, 40, 0x8000, 7, &[0xAB; 13]).unwrap();
+        write_frame(
+            &mut stream,
+            MsgType::GetHead as u16,
+            0,
+            8,
+            &42u64.to_le_bytes(),
+        )
+        .unwrap();
+        let mut reader = stream.as_slice();
+        let (header, payload) = read_frame(&mut reader).unwrap();
+        assert_eq!((header.msg_type, payload.len()), (40, 13));
+        let (header, _) = read_frame(&mut reader).unwrap();
+        assert_eq!(
+            (header.msg_type, header.req_id),
+            (MsgType::GetHead as u16, 8)
+        );
+
+        let err = StoreError::UnsupportedMessage { msg_type: 40 };
+        let resp = ErrorResponse::decode(&encode_error(&err).unwrap(), 0).unwrap();
+        assert_eq!((resp.code, resp.error_code, resp.retryable), (501, 11, 0));
+        assert_eq!(resp.detail, "unsupported message: msg_type 40");
+    }
+
+    #[test]
+    fn test_async_frames_match_blocking_frames() {
+        use crate::protocol::{read_frame, read_frame_async, write_frame, write_frame_async};
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut written = Vec::new();
+        runtime
+            .block_on(write_frame_async(&mut written, 5, 1, 9, b"payload"))
+            .unwrap();
+        let mut expected = Vec::new();
+        write_frame(&mut expected, 5, 1, 9, b"payload").unwrap();
+        assert_eq!(written, expected);
+
+        let mut reader = expected.as_slice();
+        let (header, payload) = runtime.block_on(read_frame_async(&mut reader)).unwrap();
+        let (blocking_header, _) = read_frame(&mut expected.as_slice()).unwrap();
+        assert_eq!(
+            (header, payload.as_slice()),
+            (blocking_header, &b"payload"[..])
+        );
+    }
+
+    #[test]
+    fn test_truncated_payload_names_field() {
+        let err = HelloRequest::decode(&[1, 0, 10, 0, b'a'], 0).unwrap_err();
+        match err {
+            crate::error::StoreError::MalformedFrame { message, field, .. } => {
+                assert_eq!(message, "HelloRequest");
+                assert_eq!(field, "client_tag");
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+    }
+
+    #[test]
+    fn test_declared_lengths_exceeding_payload_are_rejected() {
+        // A huge declared length must fail before allocating.
+        let mut bytes = vec![0u8; 32];
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert!(PutBlobRequest::decode(&bytes, 0).is_err());
+
+        // Likewise a list count that cannot fit in what is left.
+        let bytes = u32::MAX.to_le_bytes();
+        assert!(GetLastResponse::decode(&bytes, 1).is_err());
+    }
+
+    #[test]
+    fn test_every_truncation_is_malformed() {
+        let req = AppendTurnRequest {
+            context_id: 1,
+            parent_turn_id: 2,
+            declared_type_id: "com.example.Message".into(),
+            payload_bytes: vec![0xAB; 64],
+            idempotency_key: b"key".to_vec(),
+            fs_root_hash: Some([7u8; 32]),
+            ..Default::default()
+        };
+        let bytes = req.encode().unwrap();
+        for len in 0..bytes.len() {
+            let err = AppendTurnRequest::decode(&bytes[..len], 1).unwrap_err();
+            assert!(
+                matches!(err, crate::error::StoreError::MalformedFrame { .. }),
+                "prefix {len}: {err}"
+            );
+        }
+        assert_eq!(AppendTurnRequest::decode(&bytes, 1).unwrap(), req);
+    }
+
+    #[test]
+    fn test_lengths_beyond_the_prefix_are_refused() {
+        let req = HelloRequest {
+            client_tag: "x".repeat(u16::MAX as usize),
+            ..Default::default()
+        };
+        assert!(req.encode().is_ok());
+        let req = HelloRequest {
+            client_tag: "x".repeat(u16::MAX as usize + 1),
+            ..Default::default()
+        };
+        let err = req.encode().unwrap_err();
+        assert!(err.to_string().contains("client_tag"), "{err}");
+    }
+
+    #[test]
+    fn test_schema_describes_every_message() {
+        let schema = protocol_schema();
+        let messages = schema["messages"].as_array().unwrap();
+        let structs = schema["structs"].as_array().unwrap();
+        for message in messages {
+            for side in ["request", "response"] {
+                let name = message[side].as_str().unwrap();
+                assert!(
+                    structs.iter().any(|s| s["name"] == name),
+                    "missing struct {name}"
+                );
+            }
+        }
+        let append = structs
+            .iter()
+            .find(|s| s["name"] == "AppendTurnRequest")
+            .unwrap();
+        let fs_root = append["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|f| f["name"] == "fs_root_hash")
+            .unwrap();
+        assert_eq!(fs_root["present_if_flag_bit"], 0);
+        assert_eq!(fs_root["fixed_len"], 32);
+    }
+}
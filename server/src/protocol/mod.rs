@@ -2,12 +2,35 @@
 // SPDX-License-Identifier: Apache-2.0
 
 //! Binary protocol framing and message helpers.
+//!
+//! Payload layouts are declared in `messages` with the `wire` DSL; the
+//! `parse_*` / `encode_*` helpers below are thin wrappers over the generated
+//! codecs. Decoding never trusts an embedded length: anything that does not
+//! fit in the payload fails with `StoreError::MalformedFrame`.
+
+pub mod compat;
+mod error_codes;
+mod messages;
+pub mod wire;
 
 use std::io::{Read, Write};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 use crate::error::{Result, StoreError};
+use crate::fs_store::{OverlayChange, SnapshotMeta, TreeEntry};
+
+pub use error_codes::ErrorCode;
+pub use messages::{
+    protocol_schema, AppendTurnRequest, AppendTurnResponse, AttachFsOverlayRequest,
+    AttachFsOverlayResponse, AttachFsRequest, AttachFsResponse, ContextHeadResponse,
+    CtxCreateRequest, CtxForkRequest, ErrorResponse, GetBlobRequest, GetBlobResponse,
+    GetHeadRequest, GetLastRequest, GetLastResponse, GetRangeByDepthRequest, HelloRequest,
+    HelloResponse, OverlayBlob, OverlayChangeItem, PutBlobRequest, PutBlobResponse, TurnItem,
+    UpdateSessionStatusRequest, UpdateSessionStatusResponse, SCHEMA_VERSION,
+};
+pub use wire::WireStruct;
 
 /// Maximum frame payload size (64 MB). Frames larger than this are rejected
 /// to prevent memory exhaustion from malicious or corrupted clients.
@@ -27,9 +50,34 @@ pub enum MsgType {
     GetBlob = 9,
     AttachFs = 10,
     PutBlob = 11,
+    AttachFsOverlay = 12,
+    UpdateSessionStatus = 13,
     Error = 255,
 }
 
+impl MsgType {
+    /// Wire name of a message type, for logs and metric labels.
+    pub fn name(msg_type: u16) -> &'static str {
+        match msg_type {
+            1 => "HELLO",
+            2 => "CTX_CREATE",
+            3 => "CTX_FORK",
+            4 => "GET_HEAD",
+            5 => "APPEND_TURN",
+            6 => "GET_LAST",
+            7 => "GET_BEFORE",
+            8 => "GET_RANGE_BY_DEPTH",
+            9 => "GET_BLOB",
+            10 => "ATTACH_FS",
+            11 => "PUT_BLOB",
+            12 => "ATTACH_FS_OVERLAY",
+            13 => "UPDATE_SESSION_STATUS",
+            255 => "ERROR",
+            _ => "UNKNOWN",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct FrameHeader {
     pub len: u32,
@@ -38,44 +86,6 @@ pub struct FrameHeader {
     pub req_id: u64,
 }
 
-#[derive(Debug, Clone)]
-pub struct AppendTurnRequest {
-    pub context_id: u64,
-    pub parent_turn_id: u64,
-    pub declared_type_id: String,
-    pub declared_type_version: u32,
-    pub encoding: u32,
-    pub compression: u32,
-    pub uncompressed_len: u32,
-    pub content_hash: [u8; 32],
-    pub payload_bytes: Vec<u8>,
-    pub idempotency_key: Vec<u8>,
-    /// Optional filesystem snapshot root hash to attach to this turn.
-    /// Present if flags bit 0 is set.
-    pub fs_root_hash: Option<[u8; 32]>,
-}
-
-/// Request to attach a filesystem snapshot to an existing turn.
-#[derive(Debug, Clone)]
-pub struct AttachFsRequest {
-    pub turn_id: u64,
-    pub fs_root_hash: [u8; 32],
-}
-
-/// Request to store a blob (for filesystem tree objects or file content).
-#[derive(Debug, Clone)]
-pub struct PutBlobRequest {
-    pub hash: [u8; 32],
-    pub data: Vec<u8>,
-}
-
-#[derive(Debug, Clone, Copy)]
-pub struct GetLastRequest {
-    pub context_id: u64,
-    pub limit: u32,
-    pub include_payload: u32,
-}
-
 pub fn read_frame<R: Read>(reader: &mut R) -> Result<(FrameHeader, Vec<u8>)> {
     let len = match reader.read_u32::<LittleEndian>() {
         Ok(v) => v,
@@ -106,6 +116,48 @@ pub fn read_frame<R: Read>(reader: &mut R) -> Result<(FrameHeader, Vec<u8>)> {
     ))
 }
 
+/// [`read_frame`] on the runtime.
+pub async fn read_frame_async<R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> Result<(FrameHeader, Vec<u8>)> {
+    let mut head = [0u8; 16];
+    reader.read_exact(&mut head).await?;
+    let header = FrameHeader {
+        len: u32::from_le_bytes(head[0..4].try_into().unwrap()),
+        msg_type: u16::from_le_bytes(head[4..6].try_into().unwrap()),
+        flags: u16::from_le_bytes(head[6..8].try_into().unwrap()),
+        req_id: u64::from_le_bytes(head[8..16].try_into().unwrap()),
+    };
+    if header.len > MAX_FRAME_SIZE {
+        return Err(StoreError::InvalidInput(format!(
+            "frame size {} exceeds maximum {}",
+            header.len, MAX_FRAME_SIZE
+        )));
+    }
+    let mut payload = vec![0u8; header.len as usize];
+    reader.read_exact(&mut payload).await?;
+    Ok((header, payload))
+}
+
+/// [`write_frame`] on the runtime, flushing the frame once written.
+pub async fn write_frame_async<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    msg_type: u16,
+    flags: u16,
+    req_id: u64,
+    payload: &[u8],
+) -> Result<()> {
+    let mut head = [0u8; 16];
+    head[0..4].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+    head[4..6].copy_from_slice(&msg_type.to_le_bytes());
+    head[6..8].copy_from_slice(&flags.to_le_bytes());
+    head[8..16].copy_from_slice(&req_id.to_le_bytes());
+    writer.write_all(&head).await?;
+    writer.write_all(payload).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
 pub fn write_frame<W: Write>(
     writer: &mut W,
     msg_type: u16,
@@ -121,136 +173,156 @@ pub fn write_frame<W: Write>(
     Ok(())
 }
 
-pub fn parse_ctx_create(payload: &[u8]) -> Result<u64> {
-    let mut cursor = std::io::Cursor::new(payload);
-    Ok(cursor.read_u64::<LittleEndian>()?)
+pub fn parse_ctx_create(payload: &[u8], flags: u16) -> Result<CtxCreateRequest> {
+    CtxCreateRequest::decode(payload, flags)
 }
 
 pub fn parse_ctx_fork(payload: &[u8]) -> Result<u64> {
-    parse_ctx_create(payload)
+    Ok(CtxForkRequest::decode(payload, 0)?.base_turn_id)
 }
 
 pub fn parse_get_head(payload: &[u8]) -> Result<u64> {
-    parse_ctx_create(payload)
+    Ok(GetHeadRequest::decode(payload, 0)?.context_id)
 }
 
 pub fn parse_get_last(payload: &[u8]) -> Result<GetLastRequest> {
-    let mut cursor = std::io::Cursor::new(payload);
-    Ok(GetLastRequest {
-        context_id: cursor.read_u64::<LittleEndian>()?,
-        limit: cursor.read_u32::<LittleEndian>()?,
-        include_payload: cursor.read_u32::<LittleEndian>()?,
-    })
+    GetLastRequest::decode(payload, 0)
+}
+
+pub fn parse_get_range_by_depth(payload: &[u8]) -> Result<GetRangeByDepthRequest> {
+    GetRangeByDepthRequest::decode(payload, 0)
 }
 
 pub fn parse_get_blob(payload: &[u8]) -> Result<[u8; 32]> {
     if payload.len() != 32 {
-        return Err(StoreError::InvalidInput("invalid blob hash length".into()));
+        return Err(StoreError::MalformedFrame {
+            message: GetBlobRequest::NAME.to_string(),
+            field: "hash".to_string(),
+            reason: format!("expected 32 bytes, got {}", payload.len()),
+        });
     }
-    let mut hash = [0u8; 32];
-    hash.copy_from_slice(payload);
-    Ok(hash)
+    Ok(GetBlobRequest::decode(payload, 0)?.hash)
 }
 
+/// Parse APPEND_TURN; `flags` bit 0 signals a trailing fs_root_hash.
 pub fn parse_append_turn(payload: &[u8], flags: u16) -> Result<AppendTurnRequest> {
-    let mut cursor = std::io::Cursor::new(payload);
-    let context_id = cursor.read_u64::<LittleEndian>()?;
-    let parent_turn_id = cursor.read_u64::<LittleEndian>()?;
-
-    let type_id_len = cursor.read_u32::<LittleEndian>()? as usize;
-    let mut type_id_bytes = vec![0u8; type_id_len];
-    cursor.read_exact(&mut type_id_bytes)?;
-    let declared_type_id = String::from_utf8(type_id_bytes)
-        .map_err(|_| StoreError::InvalidInput("declared_type_id not utf8".into()))?;
-    let declared_type_version = cursor.read_u32::<LittleEndian>()?;
-
-    let encoding = cursor.read_u32::<LittleEndian>()?;
-    let compression = cursor.read_u32::<LittleEndian>()?;
-    let uncompressed_len = cursor.read_u32::<LittleEndian>()?;
-    let mut content_hash = [0u8; 32];
-    cursor.read_exact(&mut content_hash)?;
-
-    let payload_len = cursor.read_u32::<LittleEndian>()? as usize;
-    let mut payload_bytes = vec![0u8; payload_len];
-    cursor.read_exact(&mut payload_bytes)?;
-
-    let idempotency_len = cursor.read_u32::<LittleEndian>()? as usize;
-    let mut idempotency_key = vec![0u8; idempotency_len];
-    if idempotency_len > 0 {
-        cursor.read_exact(&mut idempotency_key)?;
-    }
-
-    // Check for optional fs_root_hash (flags bit 0)
-    let fs_root_hash = if flags & 1 != 0 {
-        let mut hash = [0u8; 32];
-        cursor.read_exact(&mut hash)?;
-        Some(hash)
-    } else {
-        None
-    };
+    AppendTurnRequest::decode(payload, flags)
+}
 
-    Ok(AppendTurnRequest {
-        context_id,
-        parent_turn_id,
-        declared_type_id,
-        declared_type_version,
-        encoding,
-        compression,
-        uncompressed_len,
-        content_hash,
-        payload_bytes,
-        idempotency_key,
-        fs_root_hash,
-    })
+/// Parse ATTACH_FS request: turn_id (u64) + fs_root_hash (32 bytes), then
+/// snapshot metadata when `flags` bit 0 is set.
+pub fn parse_attach_fs(payload: &[u8], flags: u16) -> Result<AttachFsRequest> {
+    AttachFsRequest::decode(payload, flags)
 }
 
-/// Parse ATTACH_FS request: turn_id (u64) + fs_root_hash (32 bytes)
-pub fn parse_attach_fs(payload: &[u8]) -> Result<AttachFsRequest> {
-    if payload.len() < 40 {
-        return Err(StoreError::InvalidInput(
-            "attach_fs payload too short".into(),
-        ));
-    }
-    let mut cursor = std::io::Cursor::new(payload);
-    let turn_id = cursor.read_u64::<LittleEndian>()?;
-    let mut fs_root_hash = [0u8; 32];
-    cursor.read_exact(&mut fs_root_hash)?;
-    Ok(AttachFsRequest {
-        turn_id,
-        fs_root_hash,
+/// Snapshot metadata carried by an ATTACH_FS request, if any.
+pub fn attach_fs_meta(req: &AttachFsRequest) -> Option<SnapshotMeta> {
+    let total_entries = req.total_entries?;
+    let git_commit = req.git_commit.clone().flatten();
+    Some(SnapshotMeta {
+        captured_at: req.captured_at_unix_ms.filter(|&ms| ms != 0),
+        base_path: req.base_path.clone().flatten(),
+        git_dirty: git_commit.as_ref().map(|_| req.git_dirty.unwrap_or(0) != 0),
+        git_commit,
+        git_branch: req.git_branch.clone().flatten(),
+        total_entries: Some(total_entries),
     })
 }
 
 /// Encode ATTACH_FS response: turn_id (u64) + fs_root_hash (32 bytes)
 pub fn encode_attach_fs_resp(turn_id: u64, fs_root_hash: &[u8; 32]) -> Result<Vec<u8>> {
-    let mut buf = Vec::with_capacity(40);
-    buf.write_u64::<LittleEndian>(turn_id)?;
-    buf.extend_from_slice(fs_root_hash);
-    Ok(buf)
+    AttachFsResponse {
+        turn_id,
+        fs_root_hash: *fs_root_hash,
+    }
+    .encode()
 }
 
-/// Parse PUT_BLOB request: hash (32 bytes) + data_len (u32) + data
-pub fn parse_put_blob(payload: &[u8]) -> Result<PutBlobRequest> {
-    if payload.len() < 36 {
-        return Err(StoreError::InvalidInput(
-            "put_blob payload too short".into(),
-        ));
+/// Parse ATTACH_FS_OVERLAY request: turn_id + base root + inline blobs + changes.
+pub fn parse_attach_fs_overlay(payload: &[u8]) -> Result<AttachFsOverlayRequest> {
+    AttachFsOverlayRequest::decode(payload, 0)
+}
+
+/// Kind value that removes the path in an ATTACH_FS_OVERLAY change.
+pub const OVERLAY_REMOVE: u8 = 255;
+
+/// Convert ATTACH_FS_OVERLAY change items to overlay changes.
+pub fn overlay_changes(items: &[OverlayChangeItem]) -> Result<Vec<OverlayChange>> {
+    items
+        .iter()
+        .map(|item| {
+            let entry = match item.kind {
+                OVERLAY_REMOVE => None,
+                0..=2 => Some(TreeEntry {
+                    name: String::new(),
+                    kind: item.kind,
+                    mode: item.mode,
+                    size: item.size,
+                    hash: item.hash.to_vec(),
+                }),
+                other => {
+                    return Err(StoreError::InvalidInput(format!(
+                        "unknown overlay entry kind {other} for {}",
+                        item.path
+                    )))
+                }
+            };
+            Ok(OverlayChange {
+                path: item.path.clone(),
+                entry,
+            })
+        })
+        .collect()
+}
+
+/// Encode ATTACH_FS_OVERLAY response: turn_id + new root + trees written.
+pub fn encode_attach_fs_overlay_resp(
+    turn_id: u64,
+    fs_root_hash: &[u8; 32],
+    trees_written: u32,
+) -> Result<Vec<u8>> {
+    AttachFsOverlayResponse {
+        turn_id,
+        fs_root_hash: *fs_root_hash,
+        trees_written,
     }
-    let mut cursor = std::io::Cursor::new(payload);
-    let mut hash = [0u8; 32];
-    cursor.read_exact(&mut hash)?;
-    let data_len = cursor.read_u32::<LittleEndian>()? as usize;
-    let mut data = vec![0u8; data_len];
-    cursor.read_exact(&mut data)?;
-    Ok(PutBlobRequest { hash, data })
+    .encode()
+}
+
+/// Parse PUT_BLOB request: hash (32 bytes) + data_len (u32) + data, then
+/// context_id (u64) when `flags` bit 0 is set.
+pub fn parse_put_blob(payload: &[u8], flags: u16) -> Result<PutBlobRequest> {
+    PutBlobRequest::decode(payload, flags)
 }
 
 /// Encode PUT_BLOB response: hash (32 bytes) + stored (u8: 1=new, 0=exists)
 pub fn encode_put_blob_resp(hash: &[u8; 32], was_new: bool) -> Result<Vec<u8>> {
-    let mut buf = Vec::with_capacity(33);
-    buf.extend_from_slice(hash);
-    buf.push(if was_new { 1 } else { 0 });
-    Ok(buf)
+    PutBlobResponse {
+        hash: *hash,
+        was_new: u8::from(was_new),
+    }
+    .encode()
+}
+
+/// Parse UPDATE_SESSION_STATUS request: context_id (u64) + status (str16),
+/// then progress in basis points (u16) when `flags` bit 0 is set.
+pub fn parse_update_session_status(
+    payload: &[u8],
+    flags: u16,
+) -> Result<UpdateSessionStatusRequest> {
+    UpdateSessionStatusRequest::decode(payload, flags)
+}
+
+/// Encode UPDATE_SESSION_STATUS response: context_id + updated_at_unix_ms.
+pub fn encode_update_session_status_resp(
+    context_id: u64,
+    updated_at_unix_ms: u64,
+) -> Result<Vec<u8>> {
+    UpdateSessionStatusResponse {
+        context_id,
+        updated_at_unix_ms,
+    }
+    .encode()
 }
 
 pub fn encode_ctx_create_resp(
@@ -258,11 +330,12 @@ pub fn encode_ctx_create_resp(
     head_turn_id: u64,
     head_depth: u32,
 ) -> Result<Vec<u8>> {
-    let mut buf = Vec::with_capacity(8 + 8 + 4);
-    buf.write_u64::<LittleEndian>(context_id)?;
-    buf.write_u64::<LittleEndian>(head_turn_id)?;
-    buf.write_u32::<LittleEndian>(head_depth)?;
-    Ok(buf)
+    ContextHeadResponse {
+        context_id,
+        head_turn_id,
+        head_depth,
+    }
+    .encode()
 }
 
 pub fn encode_append_ack(
@@ -271,80 +344,163 @@ pub fn encode_append_ack(
     new_depth: u32,
     hash: &[u8; 32],
 ) -> Result<Vec<u8>> {
-    let mut buf = Vec::with_capacity(8 + 8 + 4 + 32);
-    buf.write_u64::<LittleEndian>(context_id)?;
-    buf.write_u64::<LittleEndian>(new_turn_id)?;
-    buf.write_u32::<LittleEndian>(new_depth)?;
-    buf.extend_from_slice(hash);
-    Ok(buf)
+    AppendTurnResponse {
+        context_id,
+        new_turn_id,
+        new_depth,
+        content_hash: *hash,
+    }
+    .encode()
 }
 
-pub fn encode_error(code: u32, detail: &str) -> Result<Vec<u8>> {
-    let mut buf = Vec::new();
-    buf.write_u32::<LittleEndian>(code)?;
-    buf.write_u32::<LittleEndian>(detail.len() as u32)?;
-    buf.extend_from_slice(detail.as_bytes());
-    Ok(buf)
+/// Encode an error frame payload classified by the error code registry.
+pub fn encode_error(err: &StoreError) -> Result<Vec<u8>> {
+    let error_code = ErrorCode::of(err);
+    ErrorResponse {
+        code: error_code.status(),
+        detail: error_detail(err),
+        error_code: error_code as u16,
+        retryable: error_code.retryable() as u8,
+        retry_after_ms: error_code.retry_after_ms().unwrap_or(0),
+    }
+    .encode()
+}
+
+/// Error detail as sent to clients: the message without the variant prefix,
+/// except for malformed frames, whose full text names the field.
+fn error_detail(err: &StoreError) -> String {
+    match err {
+        StoreError::NotFound(msg)
+        | StoreError::InvalidInput(msg)
+        | StoreError::Corrupt(msg)
+        | StoreError::Cancelled(msg)
+        | StoreError::DeadlineExceeded(msg)
+        | StoreError::PermissionDenied(msg)
+        | StoreError::Shredded(msg)
+        | StoreError::Expired(msg)
+        | StoreError::Unauthenticated(msg) => msg.clone(),
+        StoreError::Io(e) => e.to_string(),
+        StoreError::MalformedFrame { .. }
+        | StoreError::UnsupportedMessage { .. }
+        | StoreError::DepthLimitExceeded { .. }
+        | StoreError::UnsupportedFormat { .. } => err.to_string(),
+    }
 }
 
-/// Parsed HELLO request with optional client metadata.
-#[derive(Debug, Clone, Default)]
-pub struct HelloRequest {
-    pub protocol_version: u16,
-    pub client_tag: String,
-    pub client_meta_json: Option<String>,
+/// Short parameter summary of a request for the slow-request log. Payload
+/// bodies are reduced to their sizes; undecodable payloads report their
+/// length only.
+pub fn request_summary(msg_type: u16, flags: u16, payload: &[u8]) -> String {
+    let summary = match msg_type {
+        x if x == MsgType::Hello as u16 => {
+            parse_hello(payload, flags).map(|r| match r.resume_session_id {
+                Some(session_id) if session_id != 0 => {
+                    format!(
+                        "client_tag={:?} resume_session_id={session_id}",
+                        r.client_tag
+                    )
+                }
+                _ => format!("client_tag={:?}", r.client_tag),
+            })
+        }
+        x if x == MsgType::CtxCreate as u16 => parse_ctx_create(payload, flags).map(|r| {
+            let mut summary = format!("base_turn_id={}", r.base_turn_id);
+            if let Some(external_id) = r.external_id {
+                summary.push_str(&format!(" external_id={external_id:?}"));
+            }
+            if let Some(ttl_secs) = r.ttl_secs {
+                summary.push_str(&format!(" ttl_secs={ttl_secs}"));
+            }
+            summary
+        }),
+        x if x == MsgType::CtxFork as u16 => {
+            parse_ctx_fork(payload).map(|base_turn_id| format!("base_turn_id={base_turn_id}"))
+        }
+        x if x == MsgType::GetHead as u16 => {
+            parse_get_head(payload).map(|context_id| format!("context_id={context_id}"))
+        }
+        x if x == MsgType::AppendTurn as u16 => parse_append_turn(payload, flags).map(|r| {
+            format!(
+                "context_id={} parent_turn_id={} type={}@{} payload_bytes={}",
+                r.context_id,
+                r.parent_turn_id,
+                r.declared_type_id,
+                r.declared_type_version,
+                r.payload_bytes.len()
+            )
+        }),
+        x if x == MsgType::GetLast as u16 => parse_get_last(payload).map(|r| {
+            format!(
+                "context_id={} limit={} include_payload={}",
+                r.context_id, r.limit, r.include_payload
+            )
+        }),
+        x if x == MsgType::GetRangeByDepth as u16 => parse_get_range_by_depth(payload).map(|r| {
+            format!(
+                "context_id={} start_depth={} limit={} include_payload={}",
+                r.context_id, r.start_depth, r.limit, r.include_payload
+            )
+        }),
+        x if x == MsgType::GetBlob as u16 => {
+            parse_get_blob(payload).map(|hash| format!("hash={}", hex::encode(hash)))
+        }
+        x if x == MsgType::AttachFs as u16 => parse_attach_fs(payload, flags).map(|r| {
+            format!(
+                "turn_id={} fs_root_hash={}",
+                r.turn_id,
+                hex::encode(r.fs_root_hash)
+            )
+        }),
+        x if x == MsgType::AttachFsOverlay as u16 => parse_attach_fs_overlay(payload).map(|r| {
+            format!(
+                "turn_id={} blobs={} changes={}",
+                r.turn_id,
+                r.blobs.len(),
+                r.changes.len()
+            )
+        }),
+        x if x == MsgType::PutBlob as u16 => parse_put_blob(payload, flags).map(|r| {
+            format!(
+                "hash={} data_bytes={} context_id={}",
+                hex::encode(r.hash),
+                r.data.len(),
+                r.context_id.unwrap_or(0)
+            )
+        }),
+        x if x == MsgType::UpdateSessionStatus as u16 => {
+            parse_update_session_status(payload, flags).map(|r| {
+                let mut summary = format!("context_id={} status={:?}", r.context_id, r.status);
+                if let Some(progress_bp) = r.progress_bp {
+                    summary.push_str(&format!(" progress_bp={progress_bp}"));
+                }
+                summary
+            })
+        }
+        _ => Err(StoreError::InvalidInput("unknown msg_type".into())),
+    };
+    summary.unwrap_or_else(|_| format!("payload_bytes={}", payload.len()))
 }
 
 /// Parse HELLO payload. Supports both old (empty) and new (with metadata) formats.
-pub fn parse_hello(payload: &[u8]) -> Result<HelloRequest> {
+pub fn parse_hello(payload: &[u8], flags: u16) -> Result<HelloRequest> {
     // Empty payload = old client, use defaults
     if payload.is_empty() {
         return Ok(HelloRequest::default());
     }
-
-    // New format: protocol_version(u16) + client_tag_len(u16) + client_tag + meta_json_len(u32) + meta_json
-    if payload.len() < 4 {
-        return Err(StoreError::InvalidInput("hello payload too short".into()));
-    }
-
-    let mut cursor = std::io::Cursor::new(payload);
-    let protocol_version = cursor.read_u16::<LittleEndian>()?;
-    let client_tag_len = cursor.read_u16::<LittleEndian>()? as usize;
-
-    if payload.len() < 4 + client_tag_len + 4 {
-        return Err(StoreError::InvalidInput("hello payload truncated".into()));
-    }
-
-    let mut client_tag_bytes = vec![0u8; client_tag_len];
-    if client_tag_len > 0 {
-        cursor.read_exact(&mut client_tag_bytes)?;
-    }
-    let client_tag = String::from_utf8(client_tag_bytes)
-        .map_err(|_| StoreError::InvalidInput("client_tag not utf8".into()))?;
-
-    let client_meta_json_len = cursor.read_u32::<LittleEndian>()? as usize;
-    let client_meta_json = if client_meta_json_len > 0 {
-        let mut meta_bytes = vec![0u8; client_meta_json_len];
-        cursor.read_exact(&mut meta_bytes)?;
-        Some(
-            String::from_utf8(meta_bytes)
-                .map_err(|_| StoreError::InvalidInput("client_meta_json not utf8".into()))?,
-        )
-    } else {
-        None
-    };
-
-    Ok(HelloRequest {
-        protocol_version,
-        client_tag,
-        client_meta_json,
-    })
+    HelloRequest::decode(payload, flags)
 }
 
-/// Encode HELLO response with session_id and protocol_version.
-pub fn encode_hello_resp(session_id: u64, protocol_version: u16) -> Result<Vec<u8>> {
-    let mut buf = Vec::with_capacity(10);
-    buf.write_u64::<LittleEndian>(session_id)?;
-    buf.write_u16::<LittleEndian>(protocol_version)?;
-    Ok(buf)
+/// Encode HELLO response with session_id, protocol_version and, for
+/// resumable sessions, the token to resume with.
+pub fn encode_hello_resp(
+    session_id: u64,
+    protocol_version: u16,
+    resume_token: Option<Vec<u8>>,
+) -> Result<Vec<u8>> {
+    HelloResponse {
+        session_id,
+        protocol_version,
+        resume_token,
+    }
+    .encode()
 }
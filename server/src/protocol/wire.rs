@@ -0,0 +1,397 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Declarative wire schema for binary protocol payloads.
+//!
+//! Each payload is declared once with `wire_struct!`, listing its fields in
+//! wire order with a wire kind (`U64`, `Str`, `Hash32`, ...). The macro
+//! generates the Rust struct, its decoder and encoder, and the description
+//! published at `/v1/protocol/schema`, so parsers, encoders and the schema
+//! document cannot drift apart.
+//!
+//! A field may be gated on a frame flag bit (`field: Hash32 [flag 0]`); it is
+//! then an `Option` and is present on the wire only when the bit is set.
+//! All integers are little-endian; variable-length fields carry a length
+//! prefix (u32 unless the kind says otherwise).
+
+use std::marker::PhantomData;
+
+use serde_json::{json, Value as JsonValue};
+
+use crate::error::{Result, StoreError};
+
+/// Cursor over a payload. Every declared length is checked against the bytes
+/// left in the payload before anything is read or allocated, and failures
+/// surface as `StoreError::MalformedFrame` naming the message and field.
+pub struct WireReader<'a> {
+    payload: &'a [u8],
+    pos: usize,
+    flags: u16,
+    message: &'static str,
+}
+
+impl<'a> WireReader<'a> {
+    pub fn new(payload: &'a [u8], flags: u16, message: &'static str) -> Self {
+        Self {
+            payload,
+            pos: 0,
+            flags,
+            message,
+        }
+    }
+
+    pub fn flags(&self) -> u16 {
+        self.flags
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.payload.len() - self.pos
+    }
+
+    pub fn malformed(&self, field: &str, reason: impl Into<String>) -> StoreError {
+        StoreError::MalformedFrame {
+            message: self.message.to_string(),
+            field: field.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Consume exactly `n` bytes. Lengths are checked against the remaining
+    /// payload before anything is allocated.
+    pub fn take(&mut self, n: usize, field: &str) -> Result<&'a [u8]> {
+        if n > self.remaining() {
+            return Err(self.malformed(field, format!("need {n} bytes, {} left", self.remaining())));
+        }
+        let bytes = &self.payload[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self, field: &str) -> Result<[u8; N]> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N, field)?);
+        Ok(out)
+    }
+
+    fn utf8(&self, bytes: &[u8], field: &str) -> Result<String> {
+        String::from_utf8(bytes.to_vec()).map_err(|_| self.malformed(field, "not valid utf-8"))
+    }
+}
+
+/// How one field is laid out on the wire.
+pub trait WireKind {
+    type Value;
+    fn read(reader: &mut WireReader<'_>, field: &str) -> Result<Self::Value>;
+    fn write(value: &Self::Value, buf: &mut Vec<u8>, field: &str) -> Result<()>;
+    fn describe() -> JsonValue;
+}
+
+/// Length prefix for `len` bytes or items. A length the prefix cannot hold
+/// is refused rather than truncated, which would desync the reader.
+fn length_prefix<T: TryFrom<usize>>(len: usize, field: &str, prefix: &str) -> Result<T> {
+    T::try_from(len).map_err(|_| {
+        StoreError::InvalidInput(format!(
+            "{field}: length {len} does not fit its {prefix} length prefix"
+        ))
+    })
+}
+
+/// A payload declared with `wire_struct!`.
+pub trait WireStruct: Sized {
+    const NAME: &'static str;
+
+    fn read_from(reader: &mut WireReader<'_>) -> Result<Self>;
+    fn write_to(&self, buf: &mut Vec<u8>) -> Result<()>;
+    fn fields() -> Vec<JsonValue>;
+    fn doc() -> &'static str;
+
+    /// Decode a payload. `flags` gates optional fields (frame flags for requests).
+    fn decode(payload: &[u8], flags: u16) -> Result<Self> {
+        let mut reader = WireReader::new(payload, flags, Self::NAME);
+        Self::read_from(&mut reader)
+    }
+
+    /// Encode a payload. Fails when a variable-length field does not fit its
+    /// length prefix.
+    fn encode(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.write_to(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn schema() -> JsonValue {
+        json!({
+            "name": Self::NAME,
+            "doc": Self::doc().trim(),
+            "fields": Self::fields(),
+        })
+    }
+}
+
+macro_rules! int_kind {
+    ($kind:ident, $ty:ty, $name:literal) => {
+        pub struct $kind;
+
+        impl WireKind for $kind {
+            type Value = $ty;
+
+            fn read(reader: &mut WireReader<'_>, field: &str) -> Result<$ty> {
+                Ok(<$ty>::from_le_bytes(reader.take_array(field)?))
+            }
+
+            fn write(value: &$ty, buf: &mut Vec<u8>, _field: &str) -> Result<()> {
+                buf.extend_from_slice(&value.to_le_bytes());
+                Ok(())
+            }
+
+            fn describe() -> JsonValue {
+                json!({ "type": $name })
+            }
+        }
+    };
+}
+
+int_kind!(U8, u8, "u8");
+int_kind!(U16, u16, "u16");
+int_kind!(U32, u32, "u32");
+int_kind!(U64, u64, "u64");
+
+/// 32 raw bytes (BLAKE3 hashes).
+pub struct Hash32;
+
+impl WireKind for Hash32 {
+    type Value = [u8; 32];
+
+    fn read(reader: &mut WireReader<'_>, field: &str) -> Result<[u8; 32]> {
+        reader.take_array(field)
+    }
+
+    fn write(value: &[u8; 32], buf: &mut Vec<u8>, _field: &str) -> Result<()> {
+        buf.extend_from_slice(value);
+        Ok(())
+    }
+
+    fn describe() -> JsonValue {
+        json!({ "type": "bytes", "fixed_len": 32 })
+    }
+}
+
+/// u32 length + raw bytes.
+pub struct Bytes;
+
+impl WireKind for Bytes {
+    type Value = Vec<u8>;
+
+    fn read(reader: &mut WireReader<'_>, field: &str) -> Result<Vec<u8>> {
+        let len = U32::read(reader, field)? as usize;
+        Ok(reader.take(len, field)?.to_vec())
+    }
+
+    fn write(value: &Vec<u8>, buf: &mut Vec<u8>, field: &str) -> Result<()> {
+        U32::write(&length_prefix(value.len(), field, "u32")?, buf, field)?;
+        buf.extend_from_slice(value);
+        Ok(())
+    }
+
+    fn describe() -> JsonValue {
+        json!({ "type": "bytes", "length_prefix": "u32" })
+    }
+}
+
+/// u32 length + UTF-8 bytes.
+pub struct Str;
+
+impl WireKind for Str {
+    type Value = String;
+
+    fn read(reader: &mut WireReader<'_>, field: &str) -> Result<String> {
+        let len = U32::read(reader, field)? as usize;
+        let bytes = reader.take(len, field)?;
+        reader.utf8(bytes, field)
+    }
+
+    fn write(value: &String, buf: &mut Vec<u8>, field: &str) -> Result<()> {
+        U32::write(&length_prefix(value.len(), field, "u32")?, buf, field)?;
+        buf.extend_from_slice(value.as_bytes());
+        Ok(())
+    }
+
+    fn describe() -> JsonValue {
+        json!({ "type": "string", "length_prefix": "u32" })
+    }
+}
+
+/// u16 length + UTF-8 bytes.
+pub struct Str16;
+
+impl WireKind for Str16 {
+    type Value = String;
+
+    fn read(reader: &mut WireReader<'_>, field: &str) -> Result<String> {
+        let len = U16::read(reader, field)? as usize;
+        let bytes = reader.take(len, field)?;
+        reader.utf8(bytes, field)
+    }
+
+    fn write(value: &String, buf: &mut Vec<u8>, field: &str) -> Result<()> {
+        U16::write(&length_prefix(value.len(), field, "u16")?, buf, field)?;
+        buf.extend_from_slice(value.as_bytes());
+        Ok(())
+    }
+
+    fn describe() -> JsonValue {
+        json!({ "type": "string", "length_prefix": "u16" })
+    }
+}
+
+/// u32 length + UTF-8 bytes, where length 0 means absent.
+pub struct OptStr;
+
+impl WireKind for OptStr {
+    type Value = Option<String>;
+
+    fn read(reader: &mut WireReader<'_>, field: &str) -> Result<Option<String>> {
+        let value = Str::read(reader, field)?;
+        Ok(if value.is_empty() { None } else { Some(value) })
+    }
+
+    fn write(value: &Option<String>, buf: &mut Vec<u8>, field: &str) -> Result<()> {
+        Str::write(&value.clone().unwrap_or_default(), buf, field)
+    }
+
+    fn describe() -> JsonValue {
+        json!({ "type": "string", "length_prefix": "u32", "empty_means_absent": true })
+    }
+}
+
+/// u32 count + that many nested structs.
+pub struct List<T>(PhantomData<T>);
+
+impl<T: WireStruct> WireKind for List<T> {
+    type Value = Vec<T>;
+
+    fn read(reader: &mut WireReader<'_>, field: &str) -> Result<Vec<T>> {
+        let count = U32::read(reader, field)? as usize;
+        // Every item takes at least one byte, so a count above the remaining
+        // length can never be satisfied.
+        if count > reader.remaining() {
+            return Err(reader.malformed(
+                field,
+                format!("{count} items declared, {} bytes left", reader.remaining()),
+            ));
+        }
+        let mut items = Vec::with_capacity(count);
+        for _ in 0..count {
+            items.push(T::read_from(reader)?);
+        }
+        Ok(items)
+    }
+
+    fn write(value: &Vec<T>, buf: &mut Vec<u8>, field: &str) -> Result<()> {
+        U32::write(&length_prefix(value.len(), field, "u32")?, buf, field)?;
+        for item in value {
+            item.write_to(buf)?;
+        }
+        Ok(())
+    }
+
+    fn describe() -> JsonValue {
+        json!({ "type": "list", "count_prefix": "u32", "items": T::NAME })
+    }
+}
+
+/// Declare a wire payload. See the module docs for the syntax.
+macro_rules! wire_struct {
+    (@ty $kind:ident $(<$inner:ident>)?) => {
+        <$crate::protocol::wire::$kind$(<$inner>)? as $crate::protocol::wire::WireKind>::Value
+    };
+    (@ty $kind:ident $(<$inner:ident>)? [$bit:literal]) => {
+        Option<wire_struct!(@ty $kind $(<$inner>)?)>
+    };
+    (@read $reader:ident, $field:ident, $kind:ident $(<$inner:ident>)?) => {
+        <$crate::protocol::wire::$kind$(<$inner>)? as $crate::protocol::wire::WireKind>::read(
+            $reader,
+            stringify!($field),
+        )?
+    };
+    (@read $reader:ident, $field:ident, $kind:ident $(<$inner:ident>)? [$bit:literal]) => {
+        if $reader.flags() & (1 << $bit) != 0 {
+            Some(wire_struct!(@read $reader, $field, $kind $(<$inner>)?))
+        } else {
+            None
+        }
+    };
+    (@write $buf:ident, $field:ident, $value:expr, $kind:ident $(<$inner:ident>)?) => {
+        <$crate::protocol::wire::$kind$(<$inner>)? as $crate::protocol::wire::WireKind>::write(
+            &$value,
+            $buf,
+            stringify!($field),
+        )?
+    };
+    (@write $buf:ident, $field:ident, $value:expr, $kind:ident $(<$inner:ident>)? [$bit:literal]) => {
+        if let Some(value) = &$value {
+            wire_struct!(@write $buf, $field, *value, $kind $(<$inner>)?);
+        }
+    };
+    (@describe $kind:ident $(<$inner:ident>)? $([$bit:literal])?) => {{
+        #[allow(unused_mut)]
+        let mut desc =
+            <$crate::protocol::wire::$kind$(<$inner>)? as $crate::protocol::wire::WireKind>::describe();
+        $( desc["present_if_flag_bit"] = serde_json::json!($bit); )?
+        desc
+    }};
+    (
+        $(#[doc = $doc:literal])*
+        $name:ident {
+            $(
+                $(#[doc = $field_doc:literal])*
+                $field:ident : $kind:ident $(<$inner:ident>)? $([flag $bit:literal])?
+            ),* $(,)?
+        }
+    ) => {
+        $(#[doc = $doc])*
+        #[derive(Debug, Clone, Default, PartialEq, Eq)]
+        pub struct $name {
+            $(
+                $(#[doc = $field_doc])*
+                pub $field: wire_struct!(@ty $kind $(<$inner>)? $([$bit])?),
+            )*
+        }
+
+        impl $crate::protocol::wire::WireStruct for $name {
+            const NAME: &'static str = stringify!($name);
+
+            fn read_from(
+                reader: &mut $crate::protocol::wire::WireReader<'_>,
+            ) -> $crate::error::Result<Self> {
+                $( let $field = wire_struct!(@read reader, $field, $kind $(<$inner>)? $([$bit])?); )*
+                Ok(Self { $($field),* })
+            }
+
+            fn write_to(&self, buf: &mut Vec<u8>) -> $crate::error::Result<()> {
+                $( wire_struct!(@write buf, $field, self.$field, $kind $(<$inner>)? $([$bit])?); )*
+                Ok(())
+            }
+
+            fn fields() -> Vec<serde_json::Value> {
+                vec![$({
+                    let mut desc = wire_struct!(@describe $kind $(<$inner>)? $([$bit])?);
+                    desc["name"] = serde_json::json!(stringify!($field));
+                    let doc: &[&str] = &[$($field_doc),*];
+                    if !doc.is_empty() {
+                        desc["doc"] = serde_json::json!(
+                            doc.iter().map(|l| l.trim()).collect::<Vec<_>>().join(" ")
+                        );
+                    }
+                    desc
+                }),*]
+            }
+
+            fn doc() -> &'static str {
+                concat!($($doc),*)
+            }
+        }
+    };
+}
+
+pub(crate) use wire_struct;
@@ -0,0 +1,414 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-tag usage quotas with soft warnings.
+//!
+//! A quota policy (`CXDB_QUOTAS`, a JSON file) sets limits per client tag on
+//! contexts created, turns appended and payload bytes appended over a
+//! rolling window. Usage is counted in slots of 1/24th of the window. When a
+//! tag's usage of a resource crosses `warn_at` of its limit, and again when
+//! it reaches the limit, a `quota_warning` event is published; the event
+//! repeats only after usage falls back below the threshold as the window
+//! rolls on. With `webhook_url` set, each warning is also queued as a
+//! webhook (see [`crate::webhooks`]). Quotas are advisory: appends over the
+//! limit are still accepted.
+//!
+//! ```json
+//! {
+//!   "window_secs": 86400,
+//!   "warn_at": 0.8,
+//!   "default": { "turns": 100000 },
+//!   "tags": { "ci": { "contexts": 500, "bytes": 1073741824 } },
+//!   "webhook_url": "https://alerts.example.com/cxdb-quota"
+//! }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::events::StoreEvent;
+use crate::metrics::UNTAGGED;
+use crate::webhooks::{WebhookOrigin, Webhooks};
+
+const DEFAULT_WINDOW_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_WARN_AT: f64 = 0.8;
+const SLOTS: u64 = 24;
+
+fn default_window_secs() -> u64 {
+    DEFAULT_WINDOW_SECS
+}
+
+fn default_warn_at() -> f64 {
+    DEFAULT_WARN_AT
+}
+
+/// Limits of one tag over the window; unset resources are unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contexts: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub turns: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+}
+
+impl QuotaLimits {
+    fn get(&self, resource: QuotaResource) -> Option<u64> {
+        match resource {
+            QuotaResource::Contexts => self.contexts,
+            QuotaResource::Turns => self.turns,
+            QuotaResource::Bytes => self.bytes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuotaPolicy {
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    /// Fraction of a limit at which the first warning is published.
+    #[serde(default = "default_warn_at")]
+    pub warn_at: f64,
+    /// Limits of tags not listed in `tags`.
+    #[serde(default)]
+    pub default: Option<QuotaLimits>,
+    /// Limits by client tag; `_untagged` covers sessions without a tag.
+    #[serde(default)]
+    pub tags: BTreeMap<String, QuotaLimits>,
+    /// Receives a `POST` of every warning.
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+}
+
+impl QuotaPolicy {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        let policy: Self = serde_json::from_slice(&data)
+            .map_err(|e| StoreError::InvalidInput(format!("quotas {}: {e}", path.display())))?;
+        if policy.window_secs == 0 {
+            return Err(StoreError::InvalidInput(
+                "quotas: window_secs must be positive".into(),
+            ));
+        }
+        if !(policy.warn_at > 0.0 && policy.warn_at <= 1.0) {
+            return Err(StoreError::InvalidInput(
+                "quotas: warn_at must be in (0, 1]".into(),
+            ));
+        }
+        Ok(policy)
+    }
+
+    /// Load the policy named by `CXDB_QUOTAS`; None when unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var("CXDB_QUOTAS") {
+            Ok(path) if !path.is_empty() => Self::load(Path::new(&path)).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn limits(&self, tag: &str) -> Option<&QuotaLimits> {
+        self.tags.get(tag).or(self.default.as_ref())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaResource {
+    Contexts,
+    Turns,
+    Bytes,
+}
+
+impl QuotaResource {
+    pub const ALL: [QuotaResource; 3] = [Self::Contexts, Self::Turns, Self::Bytes];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Contexts => "contexts",
+            Self::Turns => "turns",
+            Self::Bytes => "bytes",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaLevel {
+    #[default]
+    Ok,
+    /// Usage is at or above `warn_at` of the limit.
+    Warning,
+    /// Usage reached the limit.
+    Exceeded,
+}
+
+impl QuotaLevel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Warning => "warning",
+            Self::Exceeded => "exceeded",
+        }
+    }
+}
+
+/// A tag's usage of a resource crossed a threshold.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QuotaWarning {
+    pub tag: String,
+    pub resource: QuotaResource,
+    pub level: QuotaLevel,
+    pub usage: u64,
+    pub limit: u64,
+    pub percent: f64,
+}
+
+impl QuotaWarning {
+    pub fn to_event(&self) -> StoreEvent {
+        StoreEvent::QuotaWarning {
+            tag: self.tag.clone(),
+            resource: self.resource.as_str().to_string(),
+            level: self.level.as_str().to_string(),
+            usage: self.usage,
+            limit: self.limit,
+            percent: self.percent,
+        }
+    }
+}
+
+/// Usage of one resource against its limit.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResourceUsage {
+    pub usage: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percent: Option<f64>,
+    pub level: QuotaLevel,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TagQuota {
+    pub tag: String,
+    pub contexts: ResourceUsage,
+    pub turns: ResourceUsage,
+    pub bytes: ResourceUsage,
+}
+
+impl TagQuota {
+    pub fn resource(&self, resource: QuotaResource) -> &ResourceUsage {
+        match resource {
+            QuotaResource::Contexts => &self.contexts,
+            QuotaResource::Turns => &self.turns,
+            QuotaResource::Bytes => &self.bytes,
+        }
+    }
+}
+
+/// Current usage of every tag with a quota, sorted by tag.
+#[derive(Debug, Clone, Serialize)]
+pub struct QuotaReport {
+    pub window_secs: u64,
+    pub warn_at: f64,
+    pub tags: Vec<TagQuota>,
+}
+
+/// Counts of one tag: (slot epoch, [contexts, turns, bytes]) per slot, and
+/// the level each resource was last seen at.
+#[derive(Default)]
+struct TagUsage {
+    slots: Vec<(u64, [u64; 3])>,
+    levels: [QuotaLevel; 3],
+}
+
+impl TagUsage {
+    fn add(&mut self, epoch: u64, counts: [u64; 3]) {
+        if self.slots.is_empty() {
+            self.slots = vec![(u64::MAX, [0; 3]); SLOTS as usize];
+        }
+        let slot = &mut self.slots[(epoch % SLOTS) as usize];
+        if slot.0 != epoch {
+            *slot = (epoch, [0; 3]);
+        }
+        for (total, n) in slot.1.iter_mut().zip(counts) {
+            *total += n;
+        }
+    }
+
+    fn totals(&self, epoch: u64) -> [u64; 3] {
+        let oldest = epoch.saturating_sub(SLOTS - 1);
+        let mut totals = [0; 3];
+        for (slot_epoch, counts) in &self.slots {
+            if (oldest..=epoch).contains(slot_epoch) {
+                for (total, n) in totals.iter_mut().zip(counts) {
+                    *total += n;
+                }
+            }
+        }
+        totals
+    }
+}
+
+/// Sender of warning webhooks. Warnings keep no delivery state: failures
+/// are logged by the dispatcher once its retries run out.
+struct QuotaWebhooks;
+
+impl WebhookOrigin for QuotaWebhooks {
+    fn kind(&self) -> &'static str {
+        "quota"
+    }
+
+    fn is_active(&self, _id: u64) -> bool {
+        true
+    }
+
+    fn record_delivery(&self, _id: u64, _result: std::result::Result<(), String>) {}
+}
+
+pub struct Quotas {
+    policy: QuotaPolicy,
+    usage: Mutex<HashMap<String, TagUsage>>,
+    /// Queue of the warnings posted to `webhook_url`.
+    webhooks: Arc<Webhooks>,
+    /// Warnings queued so far, numbering them in webhook logs.
+    sent: AtomicU64,
+}
+
+impl Quotas {
+    pub fn new(policy: QuotaPolicy, webhooks: Arc<Webhooks>) -> Self {
+        Self {
+            policy,
+            usage: Mutex::new(HashMap::new()),
+            webhooks,
+            sent: AtomicU64::new(0),
+        }
+    }
+
+    pub fn policy(&self) -> &QuotaPolicy {
+        &self.policy
+    }
+
+    fn epoch(&self, now_secs: u64) -> u64 {
+        now_secs / (self.policy.window_secs / SLOTS).max(1)
+    }
+
+    fn level(&self, usage: u64, limit: u64) -> QuotaLevel {
+        if usage >= limit {
+            QuotaLevel::Exceeded
+        } else if usage as f64 >= self.policy.warn_at * limit as f64 {
+            QuotaLevel::Warning
+        } else {
+            QuotaLevel::Ok
+        }
+    }
+
+    /// Count usage of a tag, returning the thresholds it crossed.
+    pub fn record(
+        &self,
+        tag: &str,
+        contexts: u64,
+        turns: u64,
+        bytes: u64,
+        now_secs: u64,
+    ) -> Vec<QuotaWarning> {
+        let tag = if tag.is_empty() { UNTAGGED } else { tag };
+        let Some(limits) = self.policy.limits(tag) else {
+            return Vec::new();
+        };
+        let epoch = self.epoch(now_secs);
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(tag.to_string()).or_default();
+        entry.add(epoch, [contexts, turns, bytes]);
+        let totals = entry.totals(epoch);
+
+        let mut warnings = Vec::new();
+        for (i, resource) in QuotaResource::ALL.into_iter().enumerate() {
+            let Some(limit) = limits.get(resource) else {
+                continue;
+            };
+            let level = self.level(totals[i], limit);
+            if level > entry.levels[i] {
+                warnings.push(QuotaWarning {
+                    tag: tag.to_string(),
+                    resource,
+                    level,
+                    usage: totals[i],
+                    limit,
+                    percent: percent(totals[i], limit),
+                });
+            }
+            entry.levels[i] = level;
+        }
+        drop(usage);
+        if let Some(url) = &self.policy.webhook_url {
+            for warning in &warnings {
+                self.send_webhook(url, warning);
+            }
+        }
+        warnings
+    }
+
+    fn send_webhook(&self, url: &str, warning: &QuotaWarning) {
+        let body = match serde_json::to_value(warning) {
+            Ok(body) => body,
+            Err(err) => {
+                tracing::warn!(tag = %warning.tag, error = %err, "Failed to encode quota webhook");
+                return;
+            }
+        };
+        let id = self.sent.fetch_add(1, Ordering::Relaxed) + 1;
+        self.webhooks.send(url, body, Arc::new(QuotaWebhooks), id);
+    }
+
+    /// Usage of every tag with configured limits or counted usage.
+    pub fn report(&self, now_secs: u64) -> QuotaReport {
+        let epoch = self.epoch(now_secs);
+        let usage = self.usage.lock().unwrap();
+        let mut tags: Vec<&str> = self.policy.tags.keys().map(String::as_str).collect();
+        tags.extend(usage.keys().map(String::as_str));
+        tags.sort_unstable();
+        tags.dedup();
+
+        let tags = tags
+            .into_iter()
+            .map(|tag| {
+                let totals = usage.get(tag).map_or([0; 3], |u| u.totals(epoch));
+                let limits = self.policy.limits(tag).copied().unwrap_or_default();
+                let resource = |i: usize, resource: QuotaResource| {
+                    let limit = limits.get(resource);
+                    ResourceUsage {
+                        usage: totals[i],
+                        limit,
+                        percent: limit.map(|l| percent(totals[i], l)),
+                        level: limit.map_or(QuotaLevel::Ok, |l| self.level(totals[i], l)),
+                    }
+                };
+                TagQuota {
+                    tag: tag.to_string(),
+                    contexts: resource(0, QuotaResource::Contexts),
+                    turns: resource(1, QuotaResource::Turns),
+                    bytes: resource(2, QuotaResource::Bytes),
+                }
+            })
+            .collect();
+        QuotaReport {
+            window_secs: self.policy.window_secs,
+            warn_at: self.policy.warn_at,
+            tags,
+        }
+    }
+}
+
+fn percent(usage: u64, limit: u64) -> f64 {
+    if limit == 0 {
+        100.0
+    } else {
+        usage as f64 * 100.0 / limit as f64
+    }
+}
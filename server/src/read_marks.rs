@@ -0,0 +1,108 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-principal read marks.
+//!
+//! A read mark records the turn a principal last viewed in a context, so
+//! listings can report how many turns arrived since. Marks are stored as an
+//! append-only JSON-lines log where the latest entry per (principal, context)
+//! wins.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::jsonl_log::open_log;
+use crate::util::unix_ms;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadMark {
+    /// Last viewed turn; 0 when the context was empty.
+    pub turn_id: u64,
+    pub depth: u32,
+    pub viewed_at_unix_ms: u64,
+}
+
+impl ReadMark {
+    /// Turns on a head at `head_depth` that are newer than this mark.
+    pub fn unread_turns(mark: Option<&ReadMark>, head_turn_id: u64, head_depth: u32) -> u64 {
+        if head_turn_id == 0 {
+            return 0;
+        }
+        match mark {
+            Some(mark) if mark.turn_id != 0 => head_depth.saturating_sub(mark.depth) as u64,
+            // Depths start at 0, so the head's depth + 1 turns are unread.
+            _ => head_depth as u64 + 1,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MarkEntry {
+    principal: String,
+    context_id: u64,
+    #[serde(flatten)]
+    mark: ReadMark,
+}
+
+pub struct ReadMarks {
+    file: File,
+    entries: HashMap<(String, u64), ReadMark>,
+}
+
+impl ReadMarks {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("read_marks.jsonl");
+        let (file, records) = open_log::<MarkEntry>(&path)?;
+
+        let mut entries = HashMap::new();
+        for entry in records {
+            entries.insert((entry.principal, entry.context_id), entry.mark);
+        }
+
+        Ok(Self { file, entries })
+    }
+
+    pub fn get(&self, principal: &str, context_id: u64) -> Option<&ReadMark> {
+        self.entries.get(&(principal.to_string(), context_id))
+    }
+
+    /// Persist a mark, replacing the principal's previous one for the context.
+    /// Re-marking the turn already stored keeps the existing mark.
+    pub fn set(
+        &mut self,
+        principal: &str,
+        context_id: u64,
+        turn_id: u64,
+        depth: u32,
+    ) -> Result<ReadMark> {
+        let key = (principal.to_string(), context_id);
+        if let Some(existing) = self.entries.get(&key) {
+            if existing.turn_id == turn_id {
+                return Ok(existing.clone());
+            }
+        }
+        let mark = ReadMark {
+            turn_id,
+            depth,
+            viewed_at_unix_ms: unix_ms(),
+        };
+        let entry = MarkEntry {
+            principal: principal.to_string(),
+            context_id,
+            mark: mark.clone(),
+        };
+        let mut line = serde_json::to_vec(&entry)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.entries.insert(key, mark.clone());
+        Ok(mark)
+    }
+}
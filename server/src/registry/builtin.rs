@@ -0,0 +1,106 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Bundles the server ingests into the registry at startup.
+//!
+//! Types the server itself writes (system events, context summaries) ship
+//! embedded in the binary. Deployments can add their own baseline bundles by
+//! pointing `CXDB_BUILTIN_BUNDLES_DIR` at a directory of bundle JSON files, so
+//! writers never hit a missing type descriptor because nobody published it.
+//! Ingestion is idempotent; see [`Registry::ingest_builtin`] for how
+//! re-ingesting a changed bundle is checked.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, StoreError};
+
+use super::{BuiltinOutcome, Registry, RegistryBundle};
+
+/// Source recorded for bundles embedded in the binary.
+pub const EMBEDDED_SOURCE: &str = "embedded";
+
+const EMBEDDED_BUNDLES: &[&str] = &[
+    include_str!("bundles/context-summary.json"),
+    include_str!("bundles/system-events.json"),
+];
+
+/// A bundle to ingest at startup, with where it came from.
+#[derive(Debug, Clone)]
+pub struct BuiltinBundle {
+    pub bundle_id: String,
+    pub bundle: RegistryBundle,
+    pub raw: Vec<u8>,
+    /// `embedded`, or the path of the file it was read from.
+    pub source: String,
+}
+
+impl BuiltinBundle {
+    pub fn parse(raw: Vec<u8>, source: String) -> Result<Self> {
+        let bundle: RegistryBundle = serde_json::from_slice(&raw).map_err(|e| {
+            StoreError::InvalidInput(format!("builtin bundle {source}: invalid json: {e}"))
+        })?;
+        Ok(Self {
+            bundle_id: bundle.bundle_id.clone(),
+            bundle,
+            raw,
+            source,
+        })
+    }
+}
+
+/// Bundles embedded in the binary.
+pub fn embedded_bundles() -> Vec<BuiltinBundle> {
+    EMBEDDED_BUNDLES
+        .iter()
+        .map(|raw| {
+            BuiltinBundle::parse(raw.as_bytes().to_vec(), EMBEDDED_SOURCE.to_string())
+                .expect("embedded bundles are valid")
+        })
+        .collect()
+}
+
+/// Directory of deployment builtin bundles, from `CXDB_BUILTIN_BUNDLES_DIR`.
+pub fn builtin_dir_from_env() -> Option<PathBuf> {
+    std::env::var("CXDB_BUILTIN_BUNDLES_DIR")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Every `*.json` file of `dir`, in file name order.
+pub fn load_dir(dir: &Path) -> Result<Vec<BuiltinBundle>> {
+    let entries = fs::read_dir(dir).map_err(|e| {
+        StoreError::InvalidInput(format!("CXDB_BUILTIN_BUNDLES_DIR {}: {e}", dir.display()))
+    })?;
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) == Some("json") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| BuiltinBundle::parse(fs::read(&path)?, path.display().to_string()))
+        .collect()
+}
+
+/// Ingest the embedded bundles, then those of `dir`. Returns each bundle_id
+/// with its outcome; the first bundle that fails stops startup.
+pub fn ingest_builtin_bundles(
+    registry: &mut Registry,
+    dir: Option<&Path>,
+) -> Result<Vec<(String, BuiltinOutcome)>> {
+    let mut bundles = embedded_bundles();
+    if let Some(dir) = dir {
+        bundles.extend(load_dir(dir)?);
+    }
+    let mut outcomes = Vec::with_capacity(bundles.len());
+    for bundle in &bundles {
+        let outcome = registry.ingest_builtin(bundle)?;
+        outcomes.push((bundle.bundle_id.clone(), outcome));
+    }
+    Ok(outcomes)
+}
@@ -0,0 +1,472 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Schema-aware comparison of a stored bundle with another bundle or with the
+//! active registry.
+//!
+//! Both sides are normalized the way ingestion normalizes them, so the report
+//! lists semantic differences (types, versions, fields by tag, enum values
+//! and renderers) rather than JSON formatting ones. Entries describe the
+//! bundle relative to its base: `added` is in the bundle only, `removed` in
+//! the base only.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::Serialize;
+use serde_json::{json, Value as JsonValue};
+
+use crate::error::{Result, StoreError};
+
+use super::{
+    normalize_version, parse_version, FieldSpec, ItemsSpec, Registry, RegistryBundle,
+    TypeVersionSpec,
+};
+
+/// What a bundle is compared against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffBase<'a> {
+    /// The registered versions of the types and enums the bundle declares.
+    Current,
+    /// Another stored bundle.
+    Bundle(&'a str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// Result of [`Registry::diff_bundle`]. Unchanged types, versions, fields
+/// and enums are omitted.
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleDiff {
+    pub bundle_id: String,
+    /// `current`, or the bundle_id of the base bundle.
+    pub against: String,
+    pub identical: bool,
+    pub types: Vec<TypeDiff>,
+    pub enums: Vec<EnumDiff>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TypeDiff {
+    pub type_id: String,
+    pub change: Change,
+    pub versions: Vec<VersionDiff>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionDiff {
+    pub version: u32,
+    pub change: Change,
+    /// Field differences of a changed version.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldDiff>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renderer: Option<AttributeChange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary_field: Option<AttributeChange>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldDiff {
+    pub tag: u64,
+    /// Field name in the bundle, or in the base for removed fields.
+    pub name: String,
+    pub change: Change,
+    /// Attribute differences of a changed field.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<AttributeChange>,
+}
+
+/// A descriptor attribute with different values on each side; a missing
+/// side means the attribute is unset there.
+#[derive(Debug, Clone, Serialize)]
+pub struct AttributeChange {
+    pub attribute: String,
+    pub before: Option<JsonValue>,
+    pub after: Option<JsonValue>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EnumDiff {
+    pub enum_id: String,
+    pub change: Change,
+    /// Value differences of a changed enum.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<EnumValueDiff>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EnumValueDiff {
+    pub value: String,
+    pub change: Change,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Normalized types and enums of one side of a diff.
+#[derive(Default)]
+struct Schema {
+    types: BTreeMap<String, BTreeMap<u32, TypeVersionSpec>>,
+    enums: BTreeMap<String, HashMap<String, String>>,
+}
+
+impl Registry {
+    /// Compare stored bundle `bundle_id` with `base`.
+    pub fn diff_bundle(&self, bundle_id: &str, base: DiffBase<'_>) -> Result<BundleDiff> {
+        let bundle = self.stored_bundle(bundle_id)?;
+        let target = Schema::from_bundle(&bundle)?;
+        let (against, base) = match base {
+            DiffBase::Current => ("current".to_string(), self.current_schema(&bundle)),
+            DiffBase::Bundle(other) => (
+                other.to_string(),
+                Schema::from_bundle(&self.stored_bundle(other)?)?,
+            ),
+        };
+
+        let types = diff_keys(&base.types, &target.types)
+            .filter_map(|(type_id, before, after)| diff_type(type_id, before, after))
+            .collect::<Vec<_>>();
+        let enums = diff_keys(&base.enums, &target.enums)
+            .filter_map(|(enum_id, before, after)| diff_enum(enum_id, before, after))
+            .collect::<Vec<_>>();
+
+        Ok(BundleDiff {
+            bundle_id: bundle_id.to_string(),
+            against,
+            identical: types.is_empty() && enums.is_empty(),
+            types,
+            enums,
+        })
+    }
+
+    fn stored_bundle(&self, bundle_id: &str) -> Result<RegistryBundle> {
+        let raw = self
+            .bundles
+            .get(bundle_id)
+            .ok_or_else(|| StoreError::NotFound(format!("bundle {bundle_id}")))?;
+        serde_json::from_slice(raw)
+            .map_err(|e| StoreError::Corrupt(format!("invalid bundle json: {e}")))
+    }
+
+    /// Registered versions of the types and enums `bundle` declares.
+    fn current_schema(&self, bundle: &RegistryBundle) -> Schema {
+        let mut schema = Schema::default();
+        for type_id in bundle.types.keys() {
+            if let Some(spec) = self.types.get(type_id) {
+                schema.types.insert(type_id.clone(), spec.versions.clone());
+            }
+        }
+        for enum_id in bundle.enums.keys() {
+            if let Some(mapping) = self.enums.get(enum_id) {
+                schema.enums.insert(enum_id.clone(), mapping.clone());
+            }
+        }
+        schema
+    }
+}
+
+impl Schema {
+    fn from_bundle(bundle: &RegistryBundle) -> Result<Self> {
+        let mut schema = Schema::default();
+        for (type_id, entry) in &bundle.types {
+            let versions = schema.types.entry(type_id.clone()).or_default();
+            for (version, def) in &entry.versions {
+                let version = parse_version(version)?;
+                versions.insert(version, normalize_version(version, def)?);
+            }
+        }
+        schema.enums = bundle
+            .enums
+            .iter()
+            .map(|(id, mapping)| (id.clone(), mapping.clone()))
+            .collect();
+        Ok(schema)
+    }
+}
+
+/// Union of the keys of both maps in order, with each side's value.
+fn diff_keys<'a, K: Ord, V>(
+    before: &'a BTreeMap<K, V>,
+    after: &'a BTreeMap<K, V>,
+) -> impl Iterator<Item = (&'a K, Option<&'a V>, Option<&'a V>)> {
+    let keys: BTreeSet<&K> = before.keys().chain(after.keys()).collect();
+    keys.into_iter()
+        .map(move |key| (key, before.get(key), after.get(key)))
+}
+
+fn diff_type(
+    type_id: &str,
+    before: Option<&BTreeMap<u32, TypeVersionSpec>>,
+    after: Option<&BTreeMap<u32, TypeVersionSpec>>,
+) -> Option<TypeDiff> {
+    let empty = BTreeMap::new();
+    let versions: Vec<VersionDiff> = diff_keys(before.unwrap_or(&empty), after.unwrap_or(&empty))
+        .filter_map(|(version, before, after)| diff_version(*version, before, after))
+        .collect();
+    let change = match (before, after) {
+        (None, _) => Change::Added,
+        (_, None) => Change::Removed,
+        _ if versions.is_empty() => return None,
+        _ => Change::Changed,
+    };
+    Some(TypeDiff {
+        type_id: type_id.to_string(),
+        change,
+        versions,
+    })
+}
+
+fn diff_version(
+    version: u32,
+    before: Option<&TypeVersionSpec>,
+    after: Option<&TypeVersionSpec>,
+) -> Option<VersionDiff> {
+    let (before, after) = match (before, after) {
+        (Some(before), Some(after)) => (before, after),
+        (before, _) => {
+            return Some(VersionDiff {
+                version,
+                change: if before.is_some() {
+                    Change::Removed
+                } else {
+                    Change::Added
+                },
+                fields: Vec::new(),
+                renderer: None,
+                summary_field: None,
+            })
+        }
+    };
+
+    let before_fields: BTreeMap<u64, &FieldSpec> =
+        before.fields.iter().map(|(tag, f)| (*tag, f)).collect();
+    let after_fields: BTreeMap<u64, &FieldSpec> =
+        after.fields.iter().map(|(tag, f)| (*tag, f)).collect();
+    let fields: Vec<FieldDiff> = diff_keys(&before_fields, &after_fields)
+        .filter_map(|(tag, before, after)| diff_field(*tag, before.copied(), after.copied()))
+        .collect();
+
+    let renderer = |spec: &TypeVersionSpec| {
+        spec.renderer
+            .as_ref()
+            .and_then(|r| serde_json::to_value(r).ok())
+    };
+    let renderer = attribute_change("renderer", renderer(before), renderer(after));
+    let summary_field = |spec: &TypeVersionSpec| {
+        spec.summary_field
+            .and_then(|tag| spec.fields.get(&tag))
+            .map(|field| json!(field.name))
+    };
+    let summary_field =
+        attribute_change("summary_field", summary_field(before), summary_field(after));
+
+    if fields.is_empty() && renderer.is_none() && summary_field.is_none() {
+        return None;
+    }
+    Some(VersionDiff {
+        version,
+        change: Change::Changed,
+        fields,
+        renderer,
+        summary_field,
+    })
+}
+
+fn diff_field(
+    tag: u64,
+    before: Option<&FieldSpec>,
+    after: Option<&FieldSpec>,
+) -> Option<FieldDiff> {
+    let (change, attributes) = match (before, after) {
+        (Some(before), Some(after)) => {
+            let before = field_attributes(before);
+            let after = field_attributes(after);
+            let attributes: Vec<AttributeChange> = diff_keys(&before, &after)
+                .filter_map(|(name, before, after)| {
+                    attribute_change(name, before.cloned(), after.cloned())
+                })
+                .collect();
+            if attributes.is_empty() {
+                return None;
+            }
+            (Change::Changed, attributes)
+        }
+        (None, _) => (Change::Added, Vec::new()),
+        (_, None) => (Change::Removed, Vec::new()),
+    };
+    let name = after.or(before)?.name.clone();
+    Some(FieldDiff {
+        tag,
+        name,
+        change,
+        attributes,
+    })
+}
+
+fn attribute_change(
+    attribute: &str,
+    before: Option<JsonValue>,
+    after: Option<JsonValue>,
+) -> Option<AttributeChange> {
+    (before != after).then(|| AttributeChange {
+        attribute: attribute.to_string(),
+        before,
+        after,
+    })
+}
+
+/// A field's set attributes, named as in bundle JSON.
+fn field_attributes(field: &FieldSpec) -> BTreeMap<&'static str, JsonValue> {
+    let mut attrs = BTreeMap::new();
+    attrs.insert("name", json!(field.name));
+    attrs.insert("type", json!(field.field_type));
+    if let Some(enum_ref) = &field.enum_ref {
+        attrs.insert("enum", json!(enum_ref));
+    }
+    if let Some(type_ref) = &field.type_ref {
+        attrs.insert("ref", json!(type_ref));
+    }
+    if let Some(items) = &field.items {
+        attrs.insert("items", items_json(items));
+    }
+    if let Some(shape) = &field.shape {
+        attrs.insert("shape", items_json(shape));
+    }
+    if field.optional {
+        attrs.insert("optional", json!(true));
+    }
+    if let Some(default) = &field.default {
+        attrs.insert("default", default.clone());
+    }
+    if let Some(deprecation) = &field.deprecation {
+        attrs.insert(
+            "deprecated",
+            deprecation
+                .note
+                .as_ref()
+                .map_or(json!(true), |note| json!(note)),
+        );
+    }
+    if field.count_tokens {
+        attrs.insert("count_tokens", json!(true));
+    }
+    attrs
+}
+
+fn items_json(spec: &ItemsSpec) -> JsonValue {
+    match spec {
+        ItemsSpec::Simple(s) => json!(s),
+        ItemsSpec::Ref(r) => json!({ "type": "ref", "ref": r }),
+        ItemsSpec::Map {
+            key_type,
+            value_type,
+        } => json!({
+            "type": "map",
+            "key_type": key_type,
+            "value_type": items_json(value_type),
+        }),
+        ItemsSpec::OneOf(variants) => json!({ "type": "oneof", "variants": variants }),
+    }
+}
+
+fn diff_enum(
+    enum_id: &str,
+    before: Option<&HashMap<String, String>>,
+    after: Option<&HashMap<String, String>>,
+) -> Option<EnumDiff> {
+    let sorted = |mapping: Option<&HashMap<String, String>>| -> BTreeMap<String, String> {
+        mapping
+            .map(|m| m.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+            .unwrap_or_default()
+    };
+    let (before_values, after_values) = (sorted(before), sorted(after));
+    let values: Vec<EnumValueDiff> = diff_keys(&before_values, &after_values)
+        .filter(|(_, b, a)| b != a)
+        .map(|(value, b, a)| EnumValueDiff {
+            value: value.clone(),
+            change: match (b, a) {
+                (None, _) => Change::Added,
+                (_, None) => Change::Removed,
+                _ => Change::Changed,
+            },
+            before: b.cloned(),
+            after: a.cloned(),
+        })
+        .collect();
+    let change = match (before, after) {
+        (None, _) => Change::Added,
+        (_, None) => Change::Removed,
+        _ if values.is_empty() => return None,
+        _ => Change::Changed,
+    };
+    Some(EnumDiff {
+        enum_id: enum_id.to_string(),
+        change,
+        values: if change == Change::Changed {
+            values
+        } else {
+            Vec::new()
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(raw: &str) -> Schema {
+        Schema::from_bundle(&serde_json::from_str(raw).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_field_and_enum_changes() {
+        let before = schema(
+            r#"{ "registry_version": 1, "bundle_id": "a",
+                 "types": { "t": { "versions": { "1": { "fields": {
+                   "1": { "name": "role", "type": "u8", "enum": "e" },
+                   "2": { "name": "text", "type": "string" },
+                   "3": { "name": "old", "type": "bool" } } } } } },
+                 "enums": { "e": { "1": "system", "2": "user" } } }"#,
+        );
+        let after = schema(
+            r#"{ "registry_version": 1, "bundle_id": "b",
+                 "types": { "t": { "versions": { "1": { "fields": {
+                   "1": { "name": "role", "type": "u8", "enum": "e" },
+                   "2": { "name": "body", "type": "string", "optional": true },
+                   "4": { "name": "new", "type": "array", "items": { "type": "ref", "ref": "u" } } } } } } },
+                 "enums": { "e": { "1": "system", "2": "human", "3": "tool" } } }"#,
+        );
+
+        let version = diff_version(1, before.types["t"].get(&1), after.types["t"].get(&1)).unwrap();
+        assert_eq!(version.change, Change::Changed);
+        let fields = serde_json::to_value(&version.fields).unwrap();
+        assert_eq!(
+            fields,
+            json!([
+                {
+                    "tag": 2, "name": "body", "change": "changed",
+                    "attributes": [
+                        { "attribute": "name", "before": "text", "after": "body" },
+                        { "attribute": "optional", "before": null, "after": true }
+                    ]
+                },
+                { "tag": 3, "name": "old", "change": "removed" },
+                { "tag": 4, "name": "new", "change": "added" }
+            ])
+        );
+
+        let enum_diff = diff_enum("e", before.enums.get("e"), after.enums.get("e")).unwrap();
+        let values: Vec<(&str, Change)> = enum_diff
+            .values
+            .iter()
+            .map(|v| (v.value.as_str(), v.change))
+            .collect();
+        assert_eq!(values, vec![("2", Change::Changed), ("3", Change::Added)]);
+        assert!(diff_enum("e", before.enums.get("e"), before.enums.get("e")).is_none());
+    }
+}
@@ -1,13 +1,21 @@
 // Copyright 2025 StrongDM Inc
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 use serde::{Deserialize, Serialize};
 
 use crate::error::{Result, StoreError};
+use crate::projection::cache::{ProjectionCache, ProjectionCacheConfig, ProjectionCacheStats};
+
+pub mod builtin;
+pub mod diff;
+pub mod sync;
+
+use builtin::BuiltinBundle;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RegistryBundle {
@@ -36,6 +44,10 @@ pub struct RendererSpec {
     /// Subresource Integrity hash for security (optional).
     #[serde(default)]
     pub integrity: Option<String>,
+    /// Template for server-side text rendering (`view=text`), with `{{path}}`
+    /// placeholders into the projected payload.
+    #[serde(default)]
+    pub text_template: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +57,9 @@ pub struct TypeVersion {
     /// Optional frontend renderer specification.
     #[serde(default)]
     pub renderer: Option<RendererSpec>,
+    /// Name of a string field whose latest value previews the context.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary_field: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -60,6 +75,24 @@ pub struct FieldDef {
     pub optional: Option<bool>,
     #[serde(default)]
     pub items: Option<serde_json::Value>,
+    /// Key type of a `map` field.
+    #[serde(default)]
+    pub key_type: Option<String>,
+    /// Value type of a `map` field, in the same forms as `items`.
+    #[serde(default)]
+    pub value_type: Option<serde_json::Value>,
+    /// Variant tag to type reference of a `oneof` field.
+    #[serde(default)]
+    pub variants: Option<HashMap<String, String>>,
+    /// Value projected for payloads that predate the field (`apply_defaults`).
+    #[serde(default)]
+    pub default: Option<serde_json::Value>,
+    /// `true`, or a note pointing writers at the replacement.
+    #[serde(default)]
+    pub deprecated: Option<serde_json::Value>,
+    /// Count tokens in the field's string content at append time.
+    #[serde(default)]
+    pub count_tokens: Option<bool>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -70,15 +103,33 @@ pub struct FieldSpec {
     pub type_ref: Option<String>,
     pub optional: bool,
     pub items: Option<ItemsSpec>,
+    /// Key and value types of a `map` field, or variants of a `oneof` field.
+    pub shape: Option<ItemsSpec>,
+    pub default: Option<serde_json::Value>,
+    pub deprecation: Option<Deprecation>,
+    pub count_tokens: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deprecation {
+    pub note: Option<String>,
 }
 
-/// Specifies array item type - either a simple type string or a type reference
+/// Specifies a contained value type: array items, map values, or the shape
+/// of a `map` or `oneof` field itself.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ItemsSpec {
     /// Simple type like "string", "int64"
     Simple(String),
     /// Reference to another type like "cxdb:ToolCallItem"
     Ref(String),
+    /// String-keyed map, encoded as a msgpack map
+    Map {
+        key_type: String,
+        value_type: Box<ItemsSpec>,
+    },
+    /// Tagged union, encoded as a single-entry msgpack map of variant tag to value
+    OneOf(BTreeMap<u64, String>),
 }
 
 #[derive(Debug, Clone)]
@@ -87,6 +138,8 @@ pub struct TypeVersionSpec {
     pub fields: HashMap<u64, FieldSpec>,
     /// Optional frontend renderer specification (passed through from TypeVersion).
     pub renderer: Option<RendererSpec>,
+    /// Tag of the string field previewing contexts, when declared.
+    pub summary_field: Option<u64>,
 }
 
 #[derive(Debug, Clone)]
@@ -108,6 +161,14 @@ pub struct Registry {
     types: HashMap<String, TypeSpec>,
     enums: HashMap<String, HashMap<String, String>>,
     last_bundle_id: Option<String>,
+    /// Type ids declared by builtin bundles. Clients cannot publish versions
+    /// of them that differ from the builtin ones.
+    reserved_types: HashSet<String>,
+    /// Source of each bundle ingested as builtin at startup.
+    builtin_sources: BTreeMap<String, String>,
+    /// Projections of the read path; entries depending on a type are dropped
+    /// when a new version of it is ingested.
+    projection_cache: Arc<Mutex<ProjectionCache>>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -116,6 +177,81 @@ pub enum PutOutcome {
     AlreadyExists,
 }
 
+/// Result of [`Registry::ingest_builtin`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuiltinOutcome {
+    /// The bundle_id did not exist before.
+    Created,
+    /// Every type version and enum was already registered.
+    Unchanged,
+    /// New type versions, enums or renderers were merged into the stored bundle.
+    Merged(Vec<TypeVersionRef>),
+    /// The stored bundle has a higher registry_version (the binary or
+    /// bundle directory is older than the data) and was left as it is.
+    Outdated { stored_version: u32 },
+}
+
+/// A stored bundle as listed by [`Registry::list_bundles`].
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleInfo {
+    pub bundle_id: String,
+    pub registry_version: u32,
+    pub types: Vec<String>,
+    /// `builtin` for bundles ingested by the server at startup, else `client`.
+    pub provenance: &'static str,
+    /// Where a builtin bundle came from: `embedded` or its file path.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// Result of [`Registry::merge_bundle`]. When `conflicts` is non-empty
+/// nothing was applied.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MergeReport {
+    pub bundle_id: String,
+    /// The bundle_id did not exist before the merge.
+    pub created: bool,
+    pub added_versions: Vec<TypeVersionRef>,
+    pub added_enums: Vec<String>,
+    /// Existing versions that gained a renderer.
+    pub added_renderers: Vec<TypeVersionRef>,
+    /// Versions in the bundle identical to the registered ones.
+    pub unchanged_versions: usize,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct TypeVersionRef {
+    pub type_id: String,
+    pub version: u32,
+}
+
+/// One way a merged bundle is not strictly additive.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct MergeConflict {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enum_id: Option<String>,
+    pub reason: String,
+}
+
+impl MergeConflict {
+    fn field(type_id: &str, version: u32, tag: u64, reason: String) -> Self {
+        Self {
+            type_id: Some(type_id.to_string()),
+            version: Some(version),
+            tag: Some(tag),
+            enum_id: None,
+            reason,
+        }
+    }
+}
+
 impl Registry {
     pub fn open(dir: &Path) -> Result<Self> {
         fs::create_dir_all(dir)?;
@@ -125,6 +261,9 @@ impl Registry {
             types: HashMap::new(),
             enums: HashMap::new(),
             last_bundle_id: None,
+            reserved_types: HashSet::new(),
+            builtin_sources: BTreeMap::new(),
+            projection_cache: Arc::default(),
         };
 
         for entry in fs::read_dir(dir)? {
@@ -154,6 +293,10 @@ impl Registry {
     }
 
     pub fn put_bundle(&mut self, bundle_id: &str, raw: &[u8]) -> Result<PutOutcome> {
+        self.put(bundle_id, raw, false)
+    }
+
+    fn put(&mut self, bundle_id: &str, raw: &[u8], builtin: bool) -> Result<PutOutcome> {
         if let Some(existing) = self.bundles.get(bundle_id) {
             if existing == raw {
                 return Ok(PutOutcome::AlreadyExists);
@@ -170,6 +313,9 @@ impl Registry {
                 "bundle_id does not match path".into(),
             ));
         }
+        if !builtin {
+            self.check_reserved(&bundle)?;
+        }
 
         self.ingest_bundle(bundle.clone(), raw, false)?;
 
@@ -183,6 +329,318 @@ impl Registry {
         Ok(PutOutcome::Created)
     }
 
+    /// Ingest a bundle shipped with the server, idempotently. A new bundle_id
+    /// is stored as is; an existing one is merged, so a newer builtin adds
+    /// its versions while versions already registered must be unchanged.
+    /// A stored bundle with a higher registry_version is kept. The bundle's
+    /// types become reserved: [`Registry::put_bundle`] and
+    /// [`Registry::merge_bundle`] reject client bundles that declare
+    /// versions of them differing from the builtin ones.
+    pub fn ingest_builtin(&mut self, builtin: &BuiltinBundle) -> Result<BuiltinOutcome> {
+        let bundle_id = &builtin.bundle_id;
+        let outcome = match self.bundles.get(bundle_id) {
+            None => {
+                self.put(bundle_id, &builtin.raw, true)?;
+                BuiltinOutcome::Created
+            }
+            Some(existing) if *existing == builtin.raw => BuiltinOutcome::Unchanged,
+            Some(existing) => {
+                let stored: RegistryBundle = serde_json::from_slice(existing)
+                    .map_err(|e| StoreError::Corrupt(format!("invalid bundle json: {e}")))?;
+                if stored.registry_version > builtin.bundle.registry_version {
+                    BuiltinOutcome::Outdated {
+                        stored_version: stored.registry_version,
+                    }
+                } else {
+                    let report = self.merge(bundle_id, &builtin.raw, true)?;
+                    if !report.conflicts.is_empty() {
+                        let reasons: Vec<String> = report
+                            .conflicts
+                            .iter()
+                            .map(|c| match (&c.type_id, c.version) {
+                                (Some(type_id), Some(version)) => {
+                                    format!("{type_id} v{version}: {}", c.reason)
+                                }
+                                _ => c.reason.clone(),
+                            })
+                            .collect();
+                        return Err(StoreError::InvalidInput(format!(
+                            "builtin bundle {}: conflicts with the registered bundle: {}",
+                            builtin.source,
+                            reasons.join("; ")
+                        )));
+                    }
+                    let mut added = report.added_versions;
+                    added.extend(report.added_renderers);
+                    if added.is_empty() && report.added_enums.is_empty() {
+                        BuiltinOutcome::Unchanged
+                    } else {
+                        BuiltinOutcome::Merged(added)
+                    }
+                }
+            }
+        };
+
+        self.reserved_types
+            .extend(builtin.bundle.types.keys().cloned());
+        self.builtin_sources
+            .insert(bundle_id.clone(), builtin.source.clone());
+        Ok(outcome)
+    }
+
+    /// Stored bundles ordered by bundle_id, marking those ingested as builtin.
+    pub fn list_bundles(&self) -> Vec<BundleInfo> {
+        let mut ids: Vec<&String> = self.bundles.keys().collect();
+        ids.sort();
+        ids.into_iter()
+            .filter_map(|bundle_id| {
+                let bundle: RegistryBundle =
+                    serde_json::from_slice(&self.bundles[bundle_id]).ok()?;
+                let mut types: Vec<String> = bundle.types.into_keys().collect();
+                types.sort();
+                let source = self.builtin_sources.get(bundle_id).cloned();
+                Some(BundleInfo {
+                    bundle_id: bundle_id.clone(),
+                    registry_version: bundle.registry_version,
+                    types,
+                    provenance: if source.is_some() {
+                        "builtin"
+                    } else {
+                        "client"
+                    },
+                    source,
+                })
+            })
+            .collect()
+    }
+
+    /// Reject client bundles that add or change versions of builtin types.
+    /// Repeating a builtin version verbatim is allowed.
+    fn check_reserved(&self, bundle: &RegistryBundle) -> Result<()> {
+        let mut type_ids: Vec<&String> = bundle
+            .types
+            .keys()
+            .filter(|type_id| self.reserved_types.contains(*type_id))
+            .collect();
+        type_ids.sort();
+        for type_id in type_ids {
+            for (version, def) in &bundle.types[type_id].versions {
+                let version = parse_version(version)?;
+                let normalized = normalize_version(version, def)?;
+                let matches = self
+                    .get_type_version(type_id, version)
+                    .is_some_and(|existing| existing.fields == normalized.fields);
+                if !matches {
+                    return Err(StoreError::InvalidInput(format!(
+                        "type {type_id} is reserved for builtin bundles"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Merge-ingest a bundle: re-publishing an existing bundle_id with more
+    /// type versions or enums applies only the additions. Versions that are
+    /// already registered must have identical field definitions; every
+    /// difference is reported as a conflict and nothing is applied. On
+    /// success the stored bundle becomes the union of the old and new one.
+    pub fn merge_bundle(&mut self, bundle_id: &str, raw: &[u8]) -> Result<MergeReport> {
+        self.merge(bundle_id, raw, false)
+    }
+
+    fn merge(&mut self, bundle_id: &str, raw: &[u8], builtin: bool) -> Result<MergeReport> {
+        let bundle: RegistryBundle = serde_json::from_slice(raw)
+            .map_err(|e| StoreError::InvalidInput(format!("invalid json: {e}")))?;
+        if bundle.bundle_id != bundle_id {
+            return Err(StoreError::InvalidInput(
+                "bundle_id does not match path".into(),
+            ));
+        }
+        if bundle.registry_version == 0 {
+            return Err(StoreError::InvalidInput(
+                "registry_version must be > 0".into(),
+            ));
+        }
+        if !builtin {
+            self.check_reserved(&bundle)?;
+        }
+
+        let mut report = self.plan_merge(&bundle)?;
+        report.bundle_id = bundle_id.to_string();
+        if !report.conflicts.is_empty() {
+            report.conflicts.sort();
+            return Ok(report);
+        }
+
+        let merged = match self.bundles.get(bundle_id) {
+            Some(existing) => {
+                let mut merged: RegistryBundle = serde_json::from_slice(existing)
+                    .map_err(|e| StoreError::Corrupt(format!("invalid bundle json: {e}")))?;
+                merged.registry_version = merged.registry_version.max(bundle.registry_version);
+                for (enum_id, mapping) in &bundle.enums {
+                    merged
+                        .enums
+                        .entry(enum_id.clone())
+                        .or_insert_with(|| mapping.clone());
+                }
+                for (type_id, entry) in &bundle.types {
+                    let merged_entry = merged.types.entry(type_id.clone()).or_insert(TypeEntry {
+                        versions: HashMap::new(),
+                    });
+                    for (version, def) in &entry.versions {
+                        let slot = merged_entry
+                            .versions
+                            .entry(version.clone())
+                            .or_insert_with(|| def.clone());
+                        if slot.renderer.is_none() {
+                            slot.renderer = def.renderer.clone();
+                        }
+                        if slot.summary_field.is_none() {
+                            slot.summary_field = def.summary_field.clone();
+                        }
+                    }
+                }
+                serde_json::to_vec(&merged)
+                    .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?
+            }
+            None => {
+                report.created = true;
+                raw.to_vec()
+            }
+        };
+
+        self.ingest_bundle(bundle, raw, false)?;
+        fs::write(self.dir.join(bundle_filename(bundle_id)), &merged)?;
+        self.bundles.insert(bundle_id.to_string(), merged);
+        self.last_bundle_id = Some(bundle_id.to_string());
+        Ok(report)
+    }
+
+    /// Compare a bundle with the registered types and enums without applying
+    /// anything: additions and unchanged versions are listed, and every
+    /// difference becomes a conflict.
+    fn plan_merge(&self, bundle: &RegistryBundle) -> Result<MergeReport> {
+        let mut report = MergeReport::default();
+
+        for (enum_id, mapping) in &bundle.enums {
+            match self.enums.get(enum_id) {
+                None => report.added_enums.push(enum_id.clone()),
+                Some(existing) if existing == mapping => {}
+                Some(existing) => {
+                    let mut keys: Vec<&String> = existing.keys().chain(mapping.keys()).collect();
+                    keys.sort();
+                    keys.dedup();
+                    for key in keys {
+                        let reason = match (existing.get(key), mapping.get(key)) {
+                            (Some(a), Some(b)) if a != b => {
+                                format!("value {key} changed from {a:?} to {b:?}")
+                            }
+                            (Some(a), None) => format!("value {key} ({a:?}) removed"),
+                            (None, Some(b)) => format!("value {key} ({b:?}) added"),
+                            _ => continue,
+                        };
+                        report.conflicts.push(MergeConflict {
+                            type_id: None,
+                            version: None,
+                            tag: None,
+                            enum_id: Some(enum_id.clone()),
+                            reason,
+                        });
+                    }
+                }
+            }
+        }
+
+        for (type_id, entry) in &bundle.types {
+            let registered = self.types.get(type_id);
+            let mut tag_schema = registered.map(|t| t.tag_schema.clone()).unwrap_or_default();
+            let mut versions: Vec<(u32, &TypeVersion)> = entry
+                .versions
+                .iter()
+                .map(|(v, def)| Ok((parse_version(v)?, def)))
+                .collect::<Result<_>>()?;
+            versions.sort_by_key(|(v, _)| *v);
+
+            for (version, def) in versions {
+                let normalized = normalize_version(version, def)?;
+                let version_ref = TypeVersionRef {
+                    type_id: type_id.clone(),
+                    version,
+                };
+                if let Some(existing) = registered.and_then(|t| t.versions.get(&version)) {
+                    let before = report.conflicts.len();
+                    field_conflicts(type_id, existing, &normalized, &mut report.conflicts);
+                    match (&existing.renderer, &normalized.renderer) {
+                        (Some(a), Some(b))
+                            if a.esm_url != b.esm_url
+                                || a.component != b.component
+                                || a.integrity != b.integrity
+                                || a.text_template != b.text_template =>
+                        {
+                            report.conflicts.push(MergeConflict {
+                                type_id: Some(type_id.clone()),
+                                version: Some(version),
+                                tag: None,
+                                enum_id: None,
+                                reason: "renderer differs from registered version".into(),
+                            });
+                        }
+                        (None, Some(_)) => report.added_renderers.push(version_ref),
+                        _ if report.conflicts.len() == before => report.unchanged_versions += 1,
+                        _ => {}
+                    }
+                    continue;
+                }
+
+                let mut tags: Vec<(&u64, &FieldSpec)> = normalized.fields.iter().collect();
+                tags.sort_by_key(|(tag, _)| **tag);
+                for (tag, field) in tags {
+                    let signature = FieldSignature {
+                        field_type: field.field_type.clone(),
+                        enum_ref: field.enum_ref.clone(),
+                    };
+                    match tag_schema.get(tag) {
+                        Some(existing) if existing != &signature => {
+                            report.conflicts.push(MergeConflict::field(
+                                type_id,
+                                version,
+                                *tag,
+                                format!(
+                                    "tag reused with type {} (previously {})",
+                                    describe_signature(&signature),
+                                    describe_signature(existing)
+                                ),
+                            ));
+                        }
+                        Some(_) => {}
+                        None => {
+                            tag_schema.insert(*tag, signature);
+                        }
+                    }
+                    if let Some(enum_ref) = &field.enum_ref {
+                        if !self.enums.contains_key(enum_ref)
+                            && !bundle.enums.contains_key(enum_ref)
+                        {
+                            report.conflicts.push(MergeConflict::field(
+                                type_id,
+                                version,
+                                *tag,
+                                format!("missing enum {enum_ref}"),
+                            ));
+                        }
+                    }
+                }
+                report.added_versions.push(version_ref);
+            }
+        }
+
+        report.added_versions.sort();
+        report.added_enums.sort();
+        report.added_renderers.sort();
+        Ok(report)
+    }
+
     pub fn get_type_version(&self, type_id: &str, version: u32) -> Option<&TypeVersionSpec> {
         self.types.get(type_id)?.versions.get(&version)
     }
@@ -208,6 +666,36 @@ impl Registry {
         }
     }
 
+    /// Start caching projections within `config`'s budget, dropping any
+    /// cached ones.
+    pub fn enable_projection_cache(&mut self, config: ProjectionCacheConfig) {
+        *self.projection_cache.lock().unwrap() = ProjectionCache::new(config);
+    }
+
+    pub fn projection_cache(&self) -> &Mutex<ProjectionCache> {
+        &self.projection_cache
+    }
+
+    /// Drop every cached projection, such as after a key is shredded.
+    pub fn clear_projection_cache(&self) {
+        self.projection_cache.lock().unwrap().clear();
+    }
+
+    pub fn projection_cache_stats(&self) -> ProjectionCacheStats {
+        self.projection_cache.lock().unwrap().stats()
+    }
+
+    /// Renderer of a type's latest version.
+    pub fn get_renderer(&self, type_id: &str) -> Option<&RendererSpec> {
+        self.types
+            .get(type_id)?
+            .versions
+            .values()
+            .next_back()?
+            .renderer
+            .as_ref()
+    }
+
     /// Returns a mapping of type_id -> RendererSpec for all types with renderers.
     /// Uses the latest version's renderer for each type.
     pub fn get_all_renderers(&self) -> HashMap<String, RendererSpec> {
@@ -267,6 +755,9 @@ impl Registry {
                     if normalized.renderer.is_some() && existing.renderer.is_none() {
                         existing.renderer = normalized.renderer.clone();
                     }
+                    if existing.summary_field.is_none() {
+                        existing.summary_field = normalized.summary_field;
+                    }
                     continue;
                 }
 
@@ -287,6 +778,10 @@ impl Registry {
                 }
 
                 type_spec.versions.insert(version, normalized);
+                self.projection_cache
+                    .lock()
+                    .unwrap()
+                    .invalidate_types(&HashSet::from([type_id.clone()]));
             }
         }
 
@@ -333,27 +828,30 @@ fn normalize_version(version: u32, def: &TypeVersion) -> Result<TypeVersionSpec>
             .parse()
             .map_err(|_| StoreError::InvalidInput("invalid field tag".into()))?;
 
-        // Parse items spec - can be a simple string or an object with type/ref
         let items = match &field_def.items {
-            Some(serde_json::Value::String(s)) => Some(ItemsSpec::Simple(s.clone())),
-            Some(serde_json::Value::Object(obj)) => {
-                // Check for { "type": "ref", "ref": "cxdb:SomeType" }
-                if let Some(serde_json::Value::String(t)) = obj.get("type") {
-                    if t == "ref" {
-                        if let Some(serde_json::Value::String(r)) = obj.get("ref") {
-                            Some(ItemsSpec::Ref(r.clone()))
-                        } else {
-                            None
-                        }
-                    } else {
-                        Some(ItemsSpec::Simple(t.clone()))
-                    }
-                } else {
-                    None
-                }
-            }
+            Some(items) => parse_items(items)?,
+            None => None,
+        };
+        let shape = match field_def.field_type.as_str() {
+            "map" => Some(map_spec(
+                field_def.key_type.as_deref(),
+                field_def.value_type.as_ref(),
+            )?),
+            "oneof" => Some(oneof_spec(field_def.variants.as_ref())?),
             _ => None,
         };
+        let deprecation = match &field_def.deprecated {
+            None | Some(serde_json::Value::Bool(false)) => None,
+            Some(serde_json::Value::Bool(true)) => Some(Deprecation { note: None }),
+            Some(serde_json::Value::String(note)) => Some(Deprecation {
+                note: Some(note.clone()),
+            }),
+            Some(_) => {
+                return Err(StoreError::InvalidInput(format!(
+                    "field {tag}: deprecated must be a bool or a note"
+                )))
+            }
+        };
 
         fields.insert(
             tag,
@@ -364,16 +862,181 @@ fn normalize_version(version: u32, def: &TypeVersion) -> Result<TypeVersionSpec>
                 type_ref: field_def.type_ref.clone(),
                 optional: field_def.optional.unwrap_or(false),
                 items,
+                shape,
+                default: field_def.default.clone(),
+                deprecation,
+                count_tokens: field_def.count_tokens.unwrap_or(false),
             },
         );
     }
+    let summary_field = match &def.summary_field {
+        None => None,
+        Some(name) => {
+            let Some((tag, field)) = fields.iter().find(|(_, f)| &f.name == name) else {
+                return Err(StoreError::InvalidInput(format!(
+                    "summary_field {name:?} is not a field of version {version}"
+                )));
+            };
+            if field.field_type != "string" {
+                return Err(StoreError::InvalidInput(format!(
+                    "summary_field {name:?} must be a string field"
+                )));
+            }
+            Some(*tag)
+        }
+    };
     Ok(TypeVersionSpec {
         version,
         fields,
         renderer: def.renderer.clone(),
+        summary_field,
     })
 }
 
+/// Parses a contained value type: a type name, or an object with a `type`
+/// of `ref`, `map` or `oneof` and the matching `ref`, `key_type`/`value_type`
+/// or `variants`.
+fn parse_items(value: &serde_json::Value) -> Result<Option<ItemsSpec>> {
+    let obj = match value {
+        serde_json::Value::String(s) => return Ok(Some(ItemsSpec::Simple(s.clone()))),
+        serde_json::Value::Object(obj) => obj,
+        _ => return Ok(None),
+    };
+    let Some(serde_json::Value::String(t)) = obj.get("type") else {
+        return Ok(None);
+    };
+    Ok(match t.as_str() {
+        "ref" => match obj.get("ref") {
+            Some(serde_json::Value::String(r)) => Some(ItemsSpec::Ref(r.clone())),
+            _ => None,
+        },
+        "map" => Some(map_spec(
+            obj.get("key_type").and_then(|k| k.as_str()),
+            obj.get("value_type"),
+        )?),
+        "oneof" => {
+            let variants = match obj.get("variants") {
+                Some(v) => Some(
+                    serde_json::from_value::<HashMap<String, String>>(v.clone()).map_err(|_| {
+                        StoreError::InvalidInput("oneof variants must be type refs".into())
+                    })?,
+                ),
+                None => None,
+            };
+            Some(oneof_spec(variants.as_ref())?)
+        }
+        _ => Some(ItemsSpec::Simple(t.clone())),
+    })
+}
+
+fn map_spec(key_type: Option<&str>, value_type: Option<&serde_json::Value>) -> Result<ItemsSpec> {
+    let key_type = key_type.unwrap_or("string");
+    if !matches!(
+        key_type,
+        "string" | "u64" | "uint64" | "int64" | "u32" | "uint32" | "int32"
+    ) {
+        return Err(StoreError::InvalidInput(format!(
+            "unsupported map key_type: {key_type}"
+        )));
+    }
+    let value_type = match value_type {
+        Some(v) => parse_items(v)?,
+        None => None,
+    }
+    .unwrap_or_else(|| ItemsSpec::Simple("any".into()));
+    Ok(ItemsSpec::Map {
+        key_type: key_type.to_string(),
+        value_type: Box::new(value_type),
+    })
+}
+
+fn oneof_spec(variants: Option<&HashMap<String, String>>) -> Result<ItemsSpec> {
+    let variants = variants
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| StoreError::InvalidInput("oneof requires variants".into()))?;
+    let mut out = BTreeMap::new();
+    for (tag, type_ref) in variants {
+        let tag: u64 = tag
+            .parse()
+            .map_err(|_| StoreError::InvalidInput("invalid oneof variant tag".into()))?;
+        out.insert(tag, type_ref.clone());
+    }
+    Ok(ItemsSpec::OneOf(out))
+}
+
+/// Per-tag differences between a registered version and a re-published one.
+fn field_conflicts(
+    type_id: &str,
+    existing: &TypeVersionSpec,
+    incoming: &TypeVersionSpec,
+    conflicts: &mut Vec<MergeConflict>,
+) {
+    let version = existing.version;
+    let mut tags: Vec<u64> = existing
+        .fields
+        .keys()
+        .chain(incoming.fields.keys())
+        .copied()
+        .collect();
+    tags.sort_unstable();
+    tags.dedup();
+    for tag in tags {
+        let reason = match (existing.fields.get(&tag), incoming.fields.get(&tag)) {
+            (Some(a), Some(b)) if a != b => {
+                let mut changes = Vec::new();
+                if a.name != b.name {
+                    changes.push(format!("name {:?} -> {:?}", a.name, b.name));
+                }
+                if a.field_type != b.field_type {
+                    changes.push(format!("type {} -> {}", a.field_type, b.field_type));
+                }
+                if a.enum_ref != b.enum_ref {
+                    changes.push(format!("enum {:?} -> {:?}", a.enum_ref, b.enum_ref));
+                }
+                if a.type_ref != b.type_ref {
+                    changes.push(format!("ref {:?} -> {:?}", a.type_ref, b.type_ref));
+                }
+                if a.optional != b.optional {
+                    changes.push(format!("optional {} -> {}", a.optional, b.optional));
+                }
+                if a.items != b.items {
+                    changes.push("items changed".to_string());
+                }
+                if a.default != b.default {
+                    changes.push("default changed".to_string());
+                }
+                if a.deprecation != b.deprecation {
+                    changes.push("deprecation changed".to_string());
+                }
+                if a.count_tokens != b.count_tokens {
+                    changes.push(format!(
+                        "count_tokens {} -> {}",
+                        a.count_tokens, b.count_tokens
+                    ));
+                }
+                if a.shape != b.shape {
+                    changes.push(match b.shape {
+                        Some(ItemsSpec::OneOf(_)) => "variants changed".to_string(),
+                        _ => "map types changed".to_string(),
+                    });
+                }
+                format!("field changed: {}", changes.join(", "))
+            }
+            (Some(a), None) => format!("field {:?} missing from re-published version", a.name),
+            (None, Some(b)) => format!("field {:?} added to a registered version", b.name),
+            _ => continue,
+        };
+        conflicts.push(MergeConflict::field(type_id, version, tag, reason));
+    }
+}
+
+fn describe_signature(signature: &FieldSignature) -> String {
+    match &signature.enum_ref {
+        Some(enum_ref) => format!("{} (enum {enum_ref})", signature.field_type),
+        None => signature.field_type.clone(),
+    }
+}
+
 fn bundle_filename(bundle_id: &str) -> String {
     let mut safe = bundle_id.replace('/', "_");
     safe = safe.replace(':', "_");
@@ -0,0 +1,541 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Differential bundle sync with another cxdb server.
+//!
+//! `POST /v1/admin/registry/pull?from=<url>` compares the remote server's
+//! bundle inventory with the local one and ingests the bundles missing here;
+//! `push?to=<url>` does the reverse. Bundles are compared by content hash
+//! through the bundle endpoint's ETag, so identical bundles are never
+//! transferred. Every transferred bundle is validated by the receiving
+//! registry as if a client had published it. A bundle that exists on both
+//! sides with different content is a conflict, unless the sync runs in
+//! `merge` mode, which applies the additions the way `PUT ?mode=merge`
+//! does. Builtin bundles are never transferred: each server ingests its own.
+//!
+//! Remotes must be listed in `CXDB_REGISTRY_SYNC_REMOTES`.
+
+use std::io::Read;
+use std::time::Duration;
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use url::Url;
+
+use crate::error::{Result, StoreError};
+
+use super::{MergeReport, PutOutcome, Registry, RegistryBundle};
+
+/// Timeout of one request to the remote.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Larger remote bundles are refused.
+const MAX_BUNDLE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Characters escaped in a bundle id path segment. The bundle routes match
+/// the raw segment, so only what cannot appear in a segment is escaped.
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'?');
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistrySyncConfig {
+    /// Origins (`scheme://host[:port]`) bundles may be pulled from or pushed to.
+    pub allowed_remotes: Vec<String>,
+    /// Bearer token sent to remotes, for servers that require one.
+    pub token: Option<String>,
+}
+
+impl RegistrySyncConfig {
+    /// Load config from environment variables. Returns None unless
+    /// `CXDB_REGISTRY_SYNC_REMOTES` lists at least one origin.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let Some(remotes) = var("CXDB_REGISTRY_SYNC_REMOTES") else {
+            return Ok(None);
+        };
+        let allowed_remotes = remotes
+            .split(',')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .map(|remote| {
+                Url::parse(remote)
+                    .ok()
+                    .filter(|url| matches!(url.scheme(), "http" | "https"))
+                    .map(|url| url.origin().ascii_serialization())
+                    .ok_or_else(|| {
+                        StoreError::InvalidInput(format!(
+                            "invalid CXDB_REGISTRY_SYNC_REMOTES origin {remote:?}"
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if allowed_remotes.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            allowed_remotes,
+            token: var("CXDB_REGISTRY_SYNC_TOKEN"),
+        }))
+    }
+
+    fn allows(&self, url: &Url) -> bool {
+        let origin = url.origin().ascii_serialization();
+        self.allowed_remotes.contains(&origin)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncDirection {
+    Pull,
+    Push,
+}
+
+/// How a bundle present on both sides with different content is handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncMode {
+    /// Report it as a conflict.
+    #[default]
+    Strict,
+    /// Apply its additions; conflicting versions are reported.
+    Merge,
+}
+
+impl SyncMode {
+    pub fn parse(raw: &str) -> Result<Self> {
+        match raw {
+            "strict" => Ok(SyncMode::Strict),
+            "merge" => Ok(SyncMode::Merge),
+            other => Err(StoreError::InvalidInput(format!(
+                "unknown sync mode {other:?} (expected strict or merge)"
+            ))),
+        }
+    }
+}
+
+/// One bundle in a sync report.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SyncedBundle {
+    pub bundle_id: String,
+    pub detail: String,
+}
+
+impl SyncedBundle {
+    fn new(bundle_id: &str, detail: impl Into<String>) -> Self {
+        Self {
+            bundle_id: bundle_id.to_string(),
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Outcome of a pull or push. With `dry_run`, `applied` lists what would be
+/// transferred; transferred bundles are then not validated.
+#[derive(Debug, Clone, Serialize)]
+pub struct RegistrySyncReport {
+    pub direction: SyncDirection,
+    pub remote: String,
+    pub mode: SyncMode,
+    pub dry_run: bool,
+    pub applied: Vec<SyncedBundle>,
+    pub skipped: Vec<SyncedBundle>,
+    pub conflicts: Vec<SyncedBundle>,
+}
+
+/// A bundle as listed by the remote's `GET /v1/registry/bundles`.
+#[derive(Debug, Deserialize)]
+struct RemoteBundleInfo {
+    bundle_id: String,
+    #[serde(default)]
+    provenance: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RemoteInventory {
+    bundles: Vec<RemoteBundleInfo>,
+}
+
+/// A remote bundle compared with a local copy.
+enum RemoteBundle {
+    Missing,
+    Identical,
+    Differs(Vec<u8>),
+}
+
+pub struct RegistrySync {
+    config: RegistrySyncConfig,
+    agent: ureq::Agent,
+}
+
+impl RegistrySync {
+    pub fn new(config: RegistrySyncConfig) -> Self {
+        Self {
+            config,
+            agent: ureq::AgentBuilder::new()
+                .timeout(REQUEST_TIMEOUT)
+                .redirects(0)
+                .build(),
+        }
+    }
+
+    pub fn config(&self) -> &RegistrySyncConfig {
+        &self.config
+    }
+
+    /// Ingest the remote's client bundles that are missing or, in merge
+    /// mode, differ locally. The registry lock is not held across requests.
+    pub fn pull(
+        &self,
+        registry: &std::sync::Mutex<Registry>,
+        remote: &str,
+        mode: SyncMode,
+        dry_run: bool,
+    ) -> Result<RegistrySyncReport> {
+        let base = self.remote_base(remote)?;
+        let mut report = RegistrySyncReport::new(SyncDirection::Pull, &base, mode, dry_run);
+        let mut inventory = self.inventory(&base)?;
+        inventory.sort_by(|a, b| a.bundle_id.cmp(&b.bundle_id));
+
+        for info in inventory {
+            let bundle_id = info.bundle_id.as_str();
+            if info.provenance == "builtin" {
+                report
+                    .skipped
+                    .push(SyncedBundle::new(bundle_id, "builtin on the remote"));
+                continue;
+            }
+            let local = registry
+                .lock()
+                .unwrap()
+                .get_bundle(bundle_id)
+                .map(<[u8]>::to_vec);
+            let raw = match self.fetch_bundle(&base, bundle_id, local.as_deref())? {
+                RemoteBundle::Identical => {
+                    report
+                        .skipped
+                        .push(SyncedBundle::new(bundle_id, "identical"));
+                    continue;
+                }
+                // Removed on the remote since it was listed.
+                RemoteBundle::Missing => continue,
+                RemoteBundle::Differs(raw) => raw,
+            };
+            if let Err(reason) = check_bundle_id(bundle_id, &raw) {
+                report.conflicts.push(SyncedBundle::new(bundle_id, reason));
+                continue;
+            }
+
+            match (local.is_some(), mode) {
+                (true, SyncMode::Strict) => report.conflicts.push(SyncedBundle::new(
+                    bundle_id,
+                    "differs from the local bundle",
+                )),
+                (false, _) if dry_run => {
+                    report.applied.push(SyncedBundle::new(bundle_id, "missing"))
+                }
+                (true, SyncMode::Merge) if dry_run => {
+                    report.applied.push(SyncedBundle::new(bundle_id, "differs"))
+                }
+                (false, _) => {
+                    let outcome = registry.lock().unwrap().put_bundle(bundle_id, &raw);
+                    match outcome {
+                        Ok(PutOutcome::Created) => {
+                            tracing::info!(bundle_id, remote = %base, "pulled registry bundle");
+                            report.applied.push(SyncedBundle::new(bundle_id, "created"));
+                        }
+                        Ok(PutOutcome::AlreadyExists) => report
+                            .skipped
+                            .push(SyncedBundle::new(bundle_id, "identical")),
+                        Err(StoreError::InvalidInput(reason)) => {
+                            report.conflicts.push(SyncedBundle::new(bundle_id, reason))
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                (true, SyncMode::Merge) => {
+                    let outcome = registry.lock().unwrap().merge_bundle(bundle_id, &raw);
+                    match outcome {
+                        Ok(merge) => {
+                            if merge.conflicts.is_empty() && merge_added(&merge) > 0 {
+                                tracing::info!(bundle_id, remote = %base, "merged registry bundle");
+                            }
+                            report.record_merge(bundle_id, &merge);
+                        }
+                        Err(StoreError::InvalidInput(reason)) => {
+                            report.conflicts.push(SyncedBundle::new(bundle_id, reason))
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Publish the local client bundles that are missing or, in merge mode,
+    /// differ on the remote. The remote validates each one.
+    pub fn push(
+        &self,
+        registry: &std::sync::Mutex<Registry>,
+        remote: &str,
+        mode: SyncMode,
+        dry_run: bool,
+    ) -> Result<RegistrySyncReport> {
+        let base = self.remote_base(remote)?;
+        let mut report = RegistrySyncReport::new(SyncDirection::Push, &base, mode, dry_run);
+        let local: Vec<(String, Vec<u8>)> = {
+            let registry = registry.lock().unwrap();
+            registry
+                .list_bundles()
+                .into_iter()
+                .filter_map(|info| {
+                    if info.provenance == "builtin" {
+                        report
+                            .skipped
+                            .push(SyncedBundle::new(&info.bundle_id, "builtin"));
+                        return None;
+                    }
+                    let raw = registry.get_bundle(&info.bundle_id)?.to_vec();
+                    Some((info.bundle_id, raw))
+                })
+                .collect()
+        };
+
+        for (bundle_id, raw) in local {
+            let merge = match self.fetch_bundle(&base, &bundle_id, Some(&raw))? {
+                RemoteBundle::Identical => {
+                    report
+                        .skipped
+                        .push(SyncedBundle::new(&bundle_id, "identical"));
+                    continue;
+                }
+                RemoteBundle::Missing => false,
+                RemoteBundle::Differs(_) if mode == SyncMode::Strict => {
+                    report.conflicts.push(SyncedBundle::new(
+                        &bundle_id,
+                        "differs from the remote bundle",
+                    ));
+                    continue;
+                }
+                RemoteBundle::Differs(_) => true,
+            };
+            if dry_run {
+                let detail = if merge { "differs" } else { "missing" };
+                report.applied.push(SyncedBundle::new(&bundle_id, detail));
+                continue;
+            }
+
+            let mut url = self.bundle_url(&base, &bundle_id)?;
+            if merge {
+                url.set_query(Some("mode=merge"));
+            }
+            let request = self.authorized(self.agent.put(url.as_str()));
+            match request
+                .set("Content-Type", "application/json")
+                .send_bytes(&raw)
+            {
+                Ok(response) if merge => report.record_remote_merge(&bundle_id, response),
+                // A merge with conflicts answers 409 with the merge report.
+                Err(ureq::Error::Status(409, response)) if merge => {
+                    report.record_remote_merge(&bundle_id, response)
+                }
+                Ok(_) => {
+                    tracing::info!(bundle_id, remote = %base, "pushed registry bundle");
+                    report
+                        .applied
+                        .push(SyncedBundle::new(&bundle_id, "created"));
+                }
+                Err(ureq::Error::Status(409 | 422, response)) => {
+                    report
+                        .conflicts
+                        .push(SyncedBundle::new(&bundle_id, remote_error(response)));
+                }
+                Err(e) => return Err(remote_failure(&url, e)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// The remote's base URL, if it is an allowed origin.
+    fn remote_base(&self, remote: &str) -> Result<Url> {
+        let mut url = Url::parse(remote)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .ok_or_else(|| StoreError::InvalidInput(format!("invalid remote url {remote:?}")))?;
+        if !self.config.allows(&url) {
+            return Err(StoreError::PermissionDenied(format!(
+                "remote {} is not in CXDB_REGISTRY_SYNC_REMOTES",
+                url.origin().ascii_serialization()
+            )));
+        }
+        url.set_query(None);
+        url.set_fragment(None);
+        if !url.path().ends_with('/') {
+            let path = format!("{}/", url.path());
+            url.set_path(&path);
+        }
+        Ok(url)
+    }
+
+    fn inventory(&self, base: &Url) -> Result<Vec<RemoteBundleInfo>> {
+        let url = join(base, "v1/registry/bundles")?;
+        let response = self
+            .authorized(self.agent.get(url.as_str()))
+            .call()
+            .map_err(|e| remote_failure(&url, e))?;
+        let inventory: RemoteInventory = response.into_json().map_err(|e| {
+            StoreError::InvalidInput(format!("invalid bundle inventory from {url}: {e}"))
+        })?;
+        Ok(inventory.bundles)
+    }
+
+    /// Fetch a remote bundle, conditionally on the local copy's hash.
+    fn fetch_bundle(
+        &self,
+        base: &Url,
+        bundle_id: &str,
+        local: Option<&[u8]>,
+    ) -> Result<RemoteBundle> {
+        let url = self.bundle_url(base, bundle_id)?;
+        let mut request = self.authorized(self.agent.get(url.as_str()));
+        if let Some(local) = local {
+            request = request.set(
+                "If-None-Match",
+                &format!("\"{}\"", blake3::hash(local).to_hex()),
+            );
+        }
+        let response = match request.call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Ok(RemoteBundle::Missing),
+            Err(e) => return Err(remote_failure(&url, e)),
+        };
+        match response.status() {
+            304 => return Ok(RemoteBundle::Identical),
+            200 => {}
+            status => {
+                return Err(StoreError::Io(std::io::Error::other(format!(
+                    "registry sync {url}: remote answered {status}"
+                ))))
+            }
+        }
+        let mut raw = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_BUNDLE_BYTES + 1)
+            .read_to_end(&mut raw)?;
+        if raw.len() as u64 > MAX_BUNDLE_BYTES {
+            return Err(StoreError::InvalidInput(format!(
+                "remote bundle {bundle_id} is larger than {MAX_BUNDLE_BYTES} bytes"
+            )));
+        }
+        if local == Some(raw.as_slice()) {
+            return Ok(RemoteBundle::Identical);
+        }
+        Ok(RemoteBundle::Differs(raw))
+    }
+
+    fn bundle_url(&self, base: &Url, bundle_id: &str) -> Result<Url> {
+        let segment = utf8_percent_encode(bundle_id, SEGMENT).to_string();
+        join(base, &format!("v1/registry/bundles/{segment}"))
+    }
+
+    fn authorized(&self, request: ureq::Request) -> ureq::Request {
+        match &self.config.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {token}")),
+            None => request,
+        }
+    }
+}
+
+impl RegistrySyncReport {
+    fn new(direction: SyncDirection, remote: &Url, mode: SyncMode, dry_run: bool) -> Self {
+        Self {
+            direction,
+            remote: remote.as_str().trim_end_matches('/').to_string(),
+            mode,
+            dry_run,
+            applied: Vec::new(),
+            skipped: Vec::new(),
+            conflicts: Vec::new(),
+        }
+    }
+
+    /// Record the merge report a remote answered a merging push with.
+    fn record_remote_merge(&mut self, bundle_id: &str, response: ureq::Response) {
+        let status = response.status();
+        match response.into_json::<MergeReport>() {
+            Ok(merge) => self.record_merge(bundle_id, &merge),
+            Err(_) if status == 409 => self
+                .conflicts
+                .push(SyncedBundle::new(bundle_id, "remote reported conflicts")),
+            Err(_) => self.applied.push(SyncedBundle::new(bundle_id, "merged")),
+        }
+    }
+
+    fn record_merge(&mut self, bundle_id: &str, merge: &MergeReport) {
+        if !merge.conflicts.is_empty() {
+            let reasons: Vec<&str> = merge.conflicts.iter().map(|c| c.reason.as_str()).collect();
+            self.conflicts
+                .push(SyncedBundle::new(bundle_id, reasons.join("; ")));
+        } else if merge_added(merge) == 0 {
+            self.skipped
+                .push(SyncedBundle::new(bundle_id, "nothing to merge"));
+        } else {
+            self.applied.push(SyncedBundle::new(
+                bundle_id,
+                format!(
+                    "merged {} type versions, {} enums, {} renderers",
+                    merge.added_versions.len(),
+                    merge.added_enums.len(),
+                    merge.added_renderers.len()
+                ),
+            ));
+        }
+    }
+}
+
+fn merge_added(merge: &MergeReport) -> usize {
+    merge.added_versions.len() + merge.added_enums.len() + merge.added_renderers.len()
+}
+
+/// A fetched bundle must parse and carry the id it was fetched under.
+fn check_bundle_id(bundle_id: &str, raw: &[u8]) -> std::result::Result<(), String> {
+    let bundle: RegistryBundle =
+        serde_json::from_slice(raw).map_err(|e| format!("invalid bundle json: {e}"))?;
+    if bundle.bundle_id != bundle_id {
+        return Err(format!("declares bundle_id {:?}", bundle.bundle_id));
+    }
+    Ok(())
+}
+
+fn join(base: &Url, path: &str) -> Result<Url> {
+    base.join(path)
+        .map_err(|e| StoreError::InvalidInput(format!("invalid remote url {base}: {e}")))
+}
+
+/// The error message of a cxdb error response.
+fn remote_error(response: ureq::Response) -> String {
+    let status = response.status();
+    response
+        .into_json::<JsonValue>()
+        .ok()
+        .and_then(|body| body["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("remote answered {status}"))
+}
+
+fn remote_failure(url: &Url, err: ureq::Error) -> StoreError {
+    let detail = match err {
+        ureq::Error::Status(_, response) => remote_error(response),
+        other => other.to_string(),
+    };
+    StoreError::Io(std::io::Error::other(format!(
+        "registry sync {url}: {detail}"
+    )))
+}
@@ -0,0 +1,314 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Same-origin proxy for renderer ESM modules.
+//!
+//! A type's `RendererSpec` names an `esm_url` the viewer imports, which
+//! browsers on locked-down networks cannot reach. When
+//! `CXDB_RENDERER_ASSET_ORIGINS` lists the origins the server may fetch from,
+//! `GET /v1/registry/renderers/assets/:type_id` fetches the module of the
+//! type's latest renderer, verifies it against the spec's subresource
+//! `integrity`, and serves it from memory afterwards. A cached module is
+//! refetched once the type's renderer spec changes, so publishing a new
+//! version with a new renderer never serves the old code.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use base64::Engine;
+use ring::digest;
+use serde::Serialize;
+use url::Url;
+
+use crate::error::{Result, StoreError};
+use crate::registry::RendererSpec;
+
+/// Default for `CXDB_RENDERER_ASSET_MAX_BYTES`.
+const DEFAULT_MAX_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Timeout of one module fetch.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Path the viewer loads a type's proxied module from.
+pub fn asset_path(type_id: &str) -> String {
+    format!(
+        "/v1/registry/renderers/assets/{}",
+        percent_encoding::utf8_percent_encode(type_id, percent_encoding::NON_ALPHANUMERIC)
+    )
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RendererAssetConfig {
+    /// Origins (`scheme://host[:port]`) modules may be fetched from.
+    pub allowed_origins: Vec<String>,
+    /// Larger modules are refused.
+    pub max_bytes: u64,
+    /// Refuse renderers without an `integrity` hash.
+    pub require_integrity: bool,
+}
+
+impl RendererAssetConfig {
+    /// Load config from environment variables. Returns None unless
+    /// `CXDB_RENDERER_ASSET_ORIGINS` lists at least one origin.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let Some(origins) = var("CXDB_RENDERER_ASSET_ORIGINS") else {
+            return Ok(None);
+        };
+        let allowed_origins = origins
+            .split(',')
+            .map(str::trim)
+            .filter(|o| !o.is_empty())
+            .map(|origin| {
+                Url::parse(origin)
+                    .ok()
+                    .filter(|url| matches!(url.scheme(), "http" | "https"))
+                    .map(|url| url.origin().ascii_serialization())
+                    .ok_or_else(|| {
+                        StoreError::InvalidInput(format!(
+                            "invalid CXDB_RENDERER_ASSET_ORIGINS origin {origin:?}"
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if allowed_origins.is_empty() {
+            return Ok(None);
+        }
+        let max_bytes = match var("CXDB_RENDERER_ASSET_MAX_BYTES") {
+            Some(v) => v.parse().map_err(|_| {
+                StoreError::InvalidInput(format!("invalid CXDB_RENDERER_ASSET_MAX_BYTES {v:?}"))
+            })?,
+            None => DEFAULT_MAX_BYTES,
+        };
+        let require_integrity = match var("CXDB_RENDERER_ASSET_REQUIRE_INTEGRITY").as_deref() {
+            None | Some("on") | Some("true") | Some("1") => true,
+            Some("off") | Some("false") | Some("0") => false,
+            Some(other) => {
+                return Err(StoreError::InvalidInput(format!(
+                    "unknown CXDB_RENDERER_ASSET_REQUIRE_INTEGRITY {other:?} (expected on or off)"
+                )))
+            }
+        };
+        Ok(Some(Self {
+            allowed_origins,
+            max_bytes,
+            require_integrity,
+        }))
+    }
+
+    fn allows(&self, url: &Url) -> bool {
+        let origin = url.origin().ascii_serialization();
+        self.allowed_origins.contains(&origin)
+    }
+}
+
+/// A fetched and verified renderer module.
+#[derive(Debug, Clone)]
+pub struct RendererAsset {
+    /// The spec it was fetched for; a different spec refetches.
+    pub esm_url: String,
+    pub integrity: Option<String>,
+    pub body: Arc<Vec<u8>>,
+    /// Quoted content hash, for conditional requests.
+    pub etag: String,
+}
+
+/// Proxy accounting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RendererAssetStats {
+    pub entries: usize,
+    pub bytes: u64,
+    pub hits: u64,
+    pub fetches: u64,
+    /// Fetched modules rejected because they did not match their integrity.
+    pub integrity_failures: u64,
+}
+
+#[derive(Debug, Default)]
+struct AssetCache {
+    /// Type id -> module of its current renderer.
+    entries: HashMap<String, RendererAsset>,
+    hits: u64,
+    fetches: u64,
+    integrity_failures: u64,
+}
+
+pub struct RendererAssets {
+    config: RendererAssetConfig,
+    agent: ureq::Agent,
+    cache: Mutex<AssetCache>,
+}
+
+impl RendererAssets {
+    pub fn new(config: RendererAssetConfig) -> Self {
+        Self {
+            config,
+            agent: ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build(),
+            cache: Mutex::new(AssetCache::default()),
+        }
+    }
+
+    /// Whether the viewer can load `spec` through the proxy.
+    pub fn proxies(&self, spec: &RendererSpec) -> bool {
+        (spec.integrity.is_some() || !self.config.require_integrity)
+            && Url::parse(&spec.esm_url).is_ok_and(|url| self.config.allows(&url))
+    }
+
+    /// Module of `type_id`'s renderer `spec`, fetched on first use or when
+    /// the spec changed since it was cached.
+    pub fn asset(&self, type_id: &str, spec: &RendererSpec) -> Result<RendererAsset> {
+        if spec.esm_url.starts_with("builtin:") {
+            return Err(StoreError::InvalidInput(format!(
+                "renderer of {type_id} is builtin to the viewer"
+            )));
+        }
+        {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some(asset) = cache.entries.get(type_id) {
+                if asset.esm_url == spec.esm_url && asset.integrity == spec.integrity {
+                    let asset = asset.clone();
+                    cache.hits += 1;
+                    return Ok(asset);
+                }
+            }
+        }
+
+        let url = Url::parse(&spec.esm_url).map_err(|e| {
+            StoreError::InvalidInput(format!("invalid esm_url {:?}: {e}", spec.esm_url))
+        })?;
+        if !self.config.allows(&url) {
+            return Err(StoreError::PermissionDenied(format!(
+                "renderer origin {} is not in CXDB_RENDERER_ASSET_ORIGINS",
+                url.origin().ascii_serialization()
+            )));
+        }
+        if spec.integrity.is_none() && self.config.require_integrity {
+            return Err(StoreError::InvalidInput(format!(
+                "renderer of {type_id} has no integrity hash"
+            )));
+        }
+
+        // The fetch does not hold the cache lock.
+        let body = self.fetch(&url)?;
+        self.cache.lock().unwrap().fetches += 1;
+        if let Some(integrity) = &spec.integrity {
+            if !verify_integrity(&body, integrity)? {
+                self.cache.lock().unwrap().integrity_failures += 1;
+                return Err(StoreError::Corrupt(format!(
+                    "renderer module {} does not match its integrity hash",
+                    spec.esm_url
+                )));
+            }
+        }
+
+        let asset = RendererAsset {
+            esm_url: spec.esm_url.clone(),
+            integrity: spec.integrity.clone(),
+            etag: format!("\"{}\"", blake3::hash(&body).to_hex()),
+            body: Arc::new(body),
+        };
+        self.cache
+            .lock()
+            .unwrap()
+            .entries
+            .insert(type_id.to_string(), asset.clone());
+        Ok(asset)
+    }
+
+    fn fetch(&self, url: &Url) -> Result<Vec<u8>> {
+        let fetch_error = |detail: String| {
+            StoreError::Io(std::io::Error::other(format!("fetch {url}: {detail}")))
+        };
+        let response = self
+            .agent
+            .get(url.as_str())
+            .call()
+            .map_err(|e| fetch_error(e.to_string()))?;
+        // Redirects must stay within the allowed origins too.
+        let final_url = Url::parse(response.get_url()).map_err(|e| fetch_error(e.to_string()))?;
+        if !self.config.allows(&final_url) {
+            return Err(StoreError::PermissionDenied(format!(
+                "renderer module redirected to {}, which is not in CXDB_RENDERER_ASSET_ORIGINS",
+                final_url.origin().ascii_serialization()
+            )));
+        }
+        let mut body = Vec::new();
+        response
+            .into_reader()
+            .take(self.config.max_bytes + 1)
+            .read_to_end(&mut body)?;
+        if body.len() as u64 > self.config.max_bytes {
+            return Err(StoreError::InvalidInput(format!(
+                "renderer module is larger than {} bytes",
+                self.config.max_bytes
+            )));
+        }
+        Ok(body)
+    }
+
+    pub fn stats(&self) -> RendererAssetStats {
+        let cache = self.cache.lock().unwrap();
+        RendererAssetStats {
+            entries: cache.entries.len(),
+            bytes: cache.entries.values().map(|a| a.body.len() as u64).sum(),
+            hits: cache.hits,
+            fetches: cache.fetches,
+            integrity_failures: cache.integrity_failures,
+        }
+    }
+}
+
+/// Check `body` against subresource integrity metadata: space-separated
+/// `sha256-`, `sha384-` or `sha512-` base64 digests. As in browsers, only
+/// the strongest algorithm listed counts, and any of its digests may match.
+pub fn verify_integrity(body: &[u8], integrity: &str) -> Result<bool> {
+    let mut strongest: Option<(usize, Vec<&str>)> = None;
+    for token in integrity.split_whitespace() {
+        // Options after `?` are reserved and ignored.
+        let token = token.split('?').next().unwrap_or_default();
+        let Some((algorithm, digest)) = token.split_once('-') else {
+            continue;
+        };
+        let Some(rank) = ["sha256", "sha384", "sha512"]
+            .iter()
+            .position(|a| *a == algorithm)
+        else {
+            continue;
+        };
+        match &mut strongest {
+            Some((best, digests)) if *best == rank => digests.push(digest),
+            Some((best, _)) if *best > rank => {}
+            _ => strongest = Some((rank, vec![digest])),
+        }
+    }
+    let Some((rank, digests)) = strongest else {
+        return Err(StoreError::InvalidInput(format!(
+            "unsupported integrity {integrity:?} (expected sha256-, sha384- or sha512-)"
+        )));
+    };
+    let algorithm = [&digest::SHA256, &digest::SHA384, &digest::SHA512][rank];
+    let actual = base64::engine::general_purpose::STANDARD.encode(digest::digest(algorithm, body));
+    Ok(digests.iter().any(|d| *d == actual))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_integrity_uses_strongest_algorithm() {
+        let body = b"export default function Renderer() {}";
+        let b64 = |alg| base64::engine::general_purpose::STANDARD.encode(digest::digest(alg, body));
+        let sha256 = format!("sha256-{}", b64(&digest::SHA256));
+        let sha384 = format!("sha384-{}", b64(&digest::SHA384));
+
+        assert!(verify_integrity(body, &sha384).unwrap());
+        assert!(verify_integrity(body, &format!("{sha256} sha384-bogus {sha384}")).unwrap());
+        // A matching weaker digest does not save a wrong stronger one.
+        assert!(!verify_integrity(body, &format!("{sha256} sha384-bogus")).unwrap());
+        assert!(!verify_integrity(b"tampered", &sha256).unwrap());
+        assert!(verify_integrity(body, "md5-abc").is_err());
+    }
+}
@@ -0,0 +1,295 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Context replays.
+//!
+//! `GET /v1/contexts/:id/replay` streams a context's turns over SSE with the
+//! gaps between them as they were appended, scaled by a speed factor, so
+//! debugging tools can watch a session play back. Each stream registers a
+//! [`ReplayControl`] under a replay id that `POST /v1/replays/:id/pause`,
+//! `/resume` and `DELETE /v1/replays/:id` act on. Paused time does not count
+//! toward the gap to the next turn. Replays are kept in memory only.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::error::{Result, StoreError};
+
+/// Fastest speed factor accepted, short of `max`.
+const MAX_SPEED: f64 = 1000.0;
+
+/// Playback speed of a replay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Gaps between turns are divided by this factor.
+    Factor(f64),
+    /// Turns are delivered back to back.
+    Max,
+}
+
+impl ReplaySpeed {
+    /// Parse `2x`, `0.5`, `1x` or `max`.
+    pub fn parse(raw: &str) -> Result<Self> {
+        let raw = raw.trim();
+        if raw.eq_ignore_ascii_case("max") {
+            return Ok(ReplaySpeed::Max);
+        }
+        let number = raw.strip_suffix(['x', 'X']).unwrap_or(raw);
+        match number.parse::<f64>() {
+            Ok(factor) if factor > 0.0 && factor <= MAX_SPEED => Ok(ReplaySpeed::Factor(factor)),
+            _ => Err(StoreError::InvalidInput(format!(
+                "invalid speed {raw:?}: expected a factor such as 2x (at most {MAX_SPEED}x) or max"
+            ))),
+        }
+    }
+
+    /// Wall-clock wait for a gap of `gap_ms` between two turns, capped at
+    /// `max_gap` when given.
+    pub fn scale(self, gap_ms: u64, max_gap: Option<Duration>) -> Duration {
+        let wait = match self {
+            ReplaySpeed::Max => Duration::ZERO,
+            ReplaySpeed::Factor(factor) => Duration::from_secs_f64(gap_ms as f64 / 1000.0 / factor),
+        };
+        match max_gap {
+            Some(max_gap) => wait.min(max_gap),
+            None => wait,
+        }
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            ReplaySpeed::Max => "max".to_string(),
+            ReplaySpeed::Factor(factor) => format!("{factor}x"),
+        }
+    }
+}
+
+impl Default for ReplaySpeed {
+    fn default() -> Self {
+        ReplaySpeed::Factor(1.0)
+    }
+}
+
+/// Outcome of one [`ReplayControl::wait`] step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayWait {
+    /// The gap has elapsed; deliver the next turn.
+    Due,
+    /// Still waiting (or paused); call again.
+    Waiting,
+    /// The replay was stopped.
+    Stopped,
+}
+
+#[derive(Debug, Default)]
+struct ControlState {
+    paused: bool,
+    stopped: bool,
+}
+
+/// Pause, resume and stop switches of one running replay.
+#[derive(Debug)]
+pub struct ReplayControl {
+    pub replay_id: u64,
+    pub context_id: u64,
+    /// Turns the replay delivers in total.
+    pub turns: usize,
+    pub speed: ReplaySpeed,
+    delivered: AtomicUsize,
+    state: Mutex<ControlState>,
+    changed: Condvar,
+}
+
+/// Status of a replay, as reported by the control endpoints.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayStatus {
+    pub replay_id: String,
+    pub context_id: String,
+    pub speed: String,
+    pub turns: usize,
+    pub delivered: usize,
+    pub paused: bool,
+    pub stopped: bool,
+}
+
+impl ReplayControl {
+    pub fn pause(&self) {
+        self.state.lock().unwrap().paused = true;
+        self.changed.notify_all();
+    }
+
+    pub fn resume(&self) {
+        self.state.lock().unwrap().paused = false;
+        self.changed.notify_all();
+    }
+
+    pub fn stop(&self) {
+        self.state.lock().unwrap().stopped = true;
+        self.changed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+
+    /// Count a delivered turn.
+    pub fn record_delivered(&self) {
+        self.delivered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Wait for at most `slice` toward the `remaining` gap to the next turn,
+    /// taking what elapsed off `remaining`. While paused nothing is taken
+    /// off. Returns early when the replay is paused, resumed or stopped.
+    pub fn wait(&self, remaining: &mut Duration, slice: Duration) -> ReplayWait {
+        let state = self.state.lock().unwrap();
+        if state.stopped {
+            return ReplayWait::Stopped;
+        }
+        if state.paused {
+            let (state, _) = self.changed.wait_timeout(state, slice).unwrap();
+            return if state.stopped {
+                ReplayWait::Stopped
+            } else {
+                ReplayWait::Waiting
+            };
+        }
+        if remaining.is_zero() {
+            return ReplayWait::Due;
+        }
+        let started = Instant::now();
+        let (state, _) = self
+            .changed
+            .wait_timeout(state, slice.min(*remaining))
+            .unwrap();
+        *remaining = remaining.saturating_sub(started.elapsed());
+        if state.stopped {
+            ReplayWait::Stopped
+        } else if remaining.is_zero() && !state.paused {
+            ReplayWait::Due
+        } else {
+            ReplayWait::Waiting
+        }
+    }
+
+    pub fn status(&self) -> ReplayStatus {
+        let state = self.state.lock().unwrap();
+        ReplayStatus {
+            replay_id: self.replay_id.to_string(),
+            context_id: self.context_id.to_string(),
+            speed: self.speed.label(),
+            turns: self.turns,
+            delivered: self.delivered.load(Ordering::Relaxed),
+            paused: state.paused,
+            stopped: state.stopped,
+        }
+    }
+}
+
+/// Running replays by id.
+#[derive(Debug, Default)]
+pub struct Replays {
+    next_id: AtomicU64,
+    running: Mutex<HashMap<u64, Arc<ReplayControl>>>,
+}
+
+impl Replays {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a replay. It stays registered until [`Replays::finish`].
+    pub fn start(&self, context_id: u64, turns: usize, speed: ReplaySpeed) -> Arc<ReplayControl> {
+        let replay_id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let control = Arc::new(ReplayControl {
+            replay_id,
+            context_id,
+            turns,
+            speed,
+            delivered: AtomicUsize::new(0),
+            state: Mutex::new(ControlState::default()),
+            changed: Condvar::new(),
+        });
+        self.running
+            .lock()
+            .unwrap()
+            .insert(replay_id, Arc::clone(&control));
+        control
+    }
+
+    pub fn get(&self, replay_id: u64) -> Result<Arc<ReplayControl>> {
+        self.running
+            .lock()
+            .unwrap()
+            .get(&replay_id)
+            .cloned()
+            .ok_or_else(|| StoreError::NotFound(format!("replay {replay_id}")))
+    }
+
+    pub fn finish(&self, replay_id: u64) {
+        self.running.lock().unwrap().remove(&replay_id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.running.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_parse_and_scale_speed() {
+        assert_eq!(ReplaySpeed::parse("2x").unwrap(), ReplaySpeed::Factor(2.0));
+        assert_eq!(ReplaySpeed::parse("0.5").unwrap(), ReplaySpeed::Factor(0.5));
+        assert_eq!(ReplaySpeed::parse("MAX").unwrap(), ReplaySpeed::Max);
+        for raw in ["0x", "-1", "fast", "1001x"] {
+            assert!(ReplaySpeed::parse(raw).is_err(), "{raw}");
+        }
+
+        let speed = ReplaySpeed::Factor(2.0);
+        assert_eq!(speed.scale(3000, None), Duration::from_millis(1500));
+        assert_eq!(
+            speed.scale(3000, Some(Duration::from_secs(1))),
+            Duration::from_secs(1)
+        );
+        assert_eq!(ReplaySpeed::Max.scale(3000, None), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_paused_time_does_not_count() {
+        let replays = Replays::new();
+        let control = replays.start(7, 2, ReplaySpeed::default());
+        control.pause();
+
+        let mut remaining = Duration::from_millis(20);
+        let slice = Duration::from_millis(30);
+        assert_eq!(control.wait(&mut remaining, slice), ReplayWait::Waiting);
+        assert_eq!(remaining, Duration::from_millis(20));
+
+        let resumer = {
+            let control = Arc::clone(&control);
+            thread::spawn(move || control.resume())
+        };
+        resumer.join().unwrap();
+        let mut outcome = ReplayWait::Waiting;
+        while outcome == ReplayWait::Waiting {
+            outcome = control.wait(&mut remaining, slice);
+        }
+        assert_eq!(outcome, ReplayWait::Due);
+
+        replays.get(control.replay_id).unwrap().stop();
+        assert_eq!(control.wait(&mut remaining, slice), ReplayWait::Stopped);
+        replays.finish(control.replay_id);
+        assert!(replays.get(control.replay_id).is_err());
+    }
+}
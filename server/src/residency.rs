@@ -0,0 +1,480 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Data residency.
+//!
+//! A context's residency is the value of its `residency:<region>` label, for
+//! example `residency:eu`, and decides where layers that copy context data
+//! off the server may take it. Federation peers declare the residencies they
+//! may hold with each request, and [`may_hold`] decides which contexts they
+//! are served. S3 sync asks a [`ResidencyConfig`] instead: the primary
+//! bucket holds unlabeled contexts and the residencies listed in
+//! `CXDB_S3_RESIDENCY`, and `CXDB_S3_RESIDENCY_TARGETS` gives every other
+//! residency a bucket and prefix of its own.
+//!
+//! Store files mix every context, so they are copied whole only while all
+//! contexts may live in the primary bucket. Otherwise a [`ResidencyPlan`]
+//! archives each context to its own destination. Contexts whose residency
+//! has no destination, or that carry conflicting residency labels, are not
+//! copied anywhere. Neither are sealed contexts while store files are
+//! withheld: archives hold payloads in the clear, and a copy outside the
+//! store would outlive shredding its key.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use crate::error::{Result, StoreError};
+use crate::store::{ContextMetadata, Store};
+
+/// Label namespace naming a context's residency.
+pub const RESIDENCY_NAMESPACE: &str = "residency";
+
+/// Excluded contexts named in a startup refusal; the count is exact.
+const MAX_REPORTED: usize = 20;
+
+/// A bucket and key prefix in a region.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct S3Destination {
+    pub bucket: String,
+    /// Key prefix without surrounding `/`; empty for the bucket root.
+    pub prefix: String,
+    pub region: String,
+}
+
+impl S3Destination {
+    pub fn new(bucket: &str, prefix: &str, region: &str) -> Self {
+        Self {
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+            region: region.to_string(),
+        }
+    }
+
+    /// Parse `bucket[/prefix][@region]`; the region defaults to
+    /// `default_region`.
+    pub fn parse(raw: &str, default_region: &str) -> Result<Self> {
+        let raw = raw.trim();
+        let raw = raw.strip_prefix("s3://").unwrap_or(raw);
+        let (location, region) = match raw.rsplit_once('@') {
+            Some((location, region)) if !region.is_empty() => (location, region),
+            Some(_) => {
+                return Err(StoreError::InvalidInput(format!(
+                    "invalid S3 destination {raw:?}: empty region after '@'"
+                )))
+            }
+            None => (raw, default_region),
+        };
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            return Err(StoreError::InvalidInput(format!(
+                "invalid S3 destination {raw:?}: expected bucket[/prefix][@region]"
+            )));
+        }
+        Ok(Self::new(bucket, prefix, region))
+    }
+
+    /// Object key of `relative_path` under the prefix.
+    pub fn key(&self, relative_path: &str) -> String {
+        if self.prefix.is_empty() {
+            relative_path.to_string()
+        } else {
+            format!("{}/{}", self.prefix, relative_path)
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!("s3://{}/{} ({})", self.bucket, self.prefix, self.region)
+    }
+
+    /// Whether objects written under one could land under the other: same
+    /// bucket, and one prefix contains the other.
+    fn overlaps(&self, other: &S3Destination) -> bool {
+        if self.bucket != other.bucket {
+            return false;
+        }
+        let (a, b) = (&self.prefix, &other.prefix);
+        a == b
+            || a.is_empty()
+            || b.is_empty()
+            || a.starts_with(&format!("{b}/"))
+            || b.starts_with(&format!("{a}/"))
+    }
+}
+
+/// Where S3 sync may copy each residency, loaded from the environment.
+#[derive(Debug, Clone, Default)]
+pub struct ResidencyConfig {
+    /// Residencies the primary bucket may hold besides unlabeled contexts.
+    pub primary: BTreeSet<String>,
+    /// Bucket and prefix of every other residency.
+    pub targets: BTreeMap<String, S3Destination>,
+}
+
+/// Where one context may be copied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    Primary,
+    /// The destination configured for this residency.
+    Target(String),
+    /// Nowhere, for the reason given.
+    Excluded(String),
+}
+
+/// A context with the residencies its labels name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResidentContext {
+    pub context_id: u64,
+    pub head_turn_id: u64,
+    pub residencies: Vec<String>,
+    /// Whether the context holds sealed data.
+    pub sealed: bool,
+}
+
+/// A context no destination may hold.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExcludedContext {
+    pub context_id: u64,
+    pub reason: String,
+}
+
+/// How one sync pass copies the store.
+#[derive(Debug, Clone, Default)]
+pub struct ResidencyPlan {
+    /// Whether store files may be copied to the primary bucket whole.
+    pub raw_files: bool,
+    /// Contexts archived to the primary bucket; empty when `raw_files`.
+    pub primary: Vec<ResidentContext>,
+    /// Contexts archived to each residency's destination.
+    pub targets: BTreeMap<String, Vec<ResidentContext>>,
+    pub excluded: Vec<ExcludedContext>,
+}
+
+impl ResidencyConfig {
+    /// Load `CXDB_S3_RESIDENCY` (comma separated residencies) and
+    /// `CXDB_S3_RESIDENCY_TARGETS` (`eu=bucket/prefix@eu-central-1,...`).
+    /// Target regions default to `default_region`.
+    pub fn from_env(default_region: &str) -> Result<Self> {
+        Self::parse(
+            &std::env::var("CXDB_S3_RESIDENCY").unwrap_or_default(),
+            &std::env::var("CXDB_S3_RESIDENCY_TARGETS").unwrap_or_default(),
+            default_region,
+        )
+    }
+
+    pub fn parse(primary: &str, targets: &str, default_region: &str) -> Result<Self> {
+        let mut config = Self {
+            primary: parse_residencies(primary)?,
+            ..Self::default()
+        };
+        for entry in targets.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (residency, destination) = entry.split_once('=').ok_or_else(|| {
+                StoreError::InvalidInput(format!(
+                    "invalid CXDB_S3_RESIDENCY_TARGETS entry {entry:?}: expected residency=bucket[/prefix][@region]"
+                ))
+            })?;
+            let residency = validate_residency(residency.trim())?;
+            let destination = S3Destination::parse(destination, default_region)?;
+            if config
+                .targets
+                .insert(residency.clone(), destination)
+                .is_some()
+            {
+                return Err(StoreError::InvalidInput(format!(
+                    "CXDB_S3_RESIDENCY_TARGETS names residency {residency:?} twice"
+                )));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Refuse combinations that could put a residency in the wrong place: a
+    /// residency both held by the primary bucket and sent elsewhere, or two
+    /// destinations whose prefixes overlap.
+    pub fn validate(&self, primary: &S3Destination) -> Result<()> {
+        for (residency, destination) in &self.targets {
+            if self.primary.contains(residency) {
+                return Err(StoreError::InvalidInput(format!(
+                    "residency {residency:?} is in both CXDB_S3_RESIDENCY and CXDB_S3_RESIDENCY_TARGETS"
+                )));
+            }
+            if destination.overlaps(primary) {
+                return Err(StoreError::InvalidInput(format!(
+                    "S3 destination of residency {residency:?} ({}) overlaps the primary bucket ({}); give it a distinct bucket or prefix",
+                    destination.describe(),
+                    primary.describe()
+                )));
+            }
+        }
+        let targets: Vec<(&String, &S3Destination)> = self.targets.iter().collect();
+        for (i, (residency, destination)) in targets.iter().enumerate() {
+            for (other, other_destination) in &targets[i + 1..] {
+                if destination.overlaps(other_destination) {
+                    return Err(StoreError::InvalidInput(format!(
+                        "S3 destinations of residencies {residency:?} and {other:?} overlap ({} and {})",
+                        destination.describe(),
+                        other_destination.describe()
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_configured(&self) -> bool {
+        !self.primary.is_empty() || !self.targets.is_empty()
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        if !self.primary.is_empty() {
+            let primary: Vec<&str> = self.primary.iter().map(String::as_str).collect();
+            parts.push(format!("{} in the primary bucket", primary.join(",")));
+        }
+        for (residency, destination) in &self.targets {
+            parts.push(format!("{residency} to {}", destination.describe()));
+        }
+        parts.join("; ")
+    }
+
+    /// Where a context naming `residencies` may be copied.
+    pub fn route(&self, residencies: &[String]) -> Route {
+        match residencies {
+            [] => Route::Primary,
+            [residency] if self.primary.contains(residency) => Route::Primary,
+            [residency] if self.targets.contains_key(residency) => Route::Target(residency.clone()),
+            [residency] => {
+                Route::Excluded(format!("no S3 destination for residency {residency:?}"))
+            }
+            _ => Route::Excluded(format!(
+                "conflicting residency labels: {}",
+                residencies.join(", ")
+            )),
+        }
+    }
+
+    /// Route every context. Store files go whole only when every context
+    /// may live in the primary bucket; otherwise sealed contexts, which
+    /// cannot be archived, are excluded.
+    pub fn plan(&self, contexts: Vec<ResidentContext>) -> ResidencyPlan {
+        let mut plan = ResidencyPlan::default();
+        for context in contexts {
+            match self.route(&context.residencies) {
+                Route::Primary => plan.primary.push(context),
+                Route::Target(residency) => {
+                    plan.targets.entry(residency).or_default().push(context)
+                }
+                Route::Excluded(reason) => plan.excluded.push(ExcludedContext {
+                    context_id: context.context_id,
+                    reason,
+                }),
+            }
+        }
+        plan.raw_files = plan.targets.is_empty() && plan.excluded.is_empty();
+        if plan.raw_files {
+            plan.primary.clear();
+            return plan;
+        }
+        let mut sealed: Vec<u64> = Vec::new();
+        for contexts in std::iter::once(&mut plan.primary).chain(plan.targets.values_mut()) {
+            sealed.extend(contexts.iter().filter(|c| c.sealed).map(|c| c.context_id));
+            contexts.retain(|c| !c.sealed);
+        }
+        plan.targets.retain(|_, contexts| !contexts.is_empty());
+        sealed.sort_unstable();
+        plan.excluded
+            .extend(sealed.into_iter().map(|context_id| ExcludedContext {
+                context_id,
+                reason: "sealed contexts are not archived".into(),
+            }));
+        plan
+    }
+}
+
+impl ResidencyPlan {
+    /// Error naming the contexts no destination may hold, for refusing to
+    /// start with a configuration that cannot sync them.
+    pub fn refuse_excluded(&self) -> Result<()> {
+        if self.excluded.is_empty() {
+            return Ok(());
+        }
+        let named: Vec<String> = self
+            .excluded
+            .iter()
+            .take(MAX_REPORTED)
+            .map(|c| format!("context {} ({})", c.context_id, c.reason))
+            .collect();
+        Err(StoreError::InvalidInput(format!(
+            "S3 sync cannot place {} contexts: {}{}; set CXDB_S3_RESIDENCY or CXDB_S3_RESIDENCY_TARGETS",
+            self.excluded.len(),
+            named.join(", "),
+            if self.excluded.len() > MAX_REPORTED {
+                ", ..."
+            } else {
+                ""
+            }
+        )))
+    }
+}
+
+/// Residencies named by a context's labels, sorted and deduplicated.
+pub fn residencies(metadata: Option<&ContextMetadata>) -> Vec<String> {
+    let residencies: BTreeSet<String> = metadata
+        .map(|m| m.namespaced_labels())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(namespace, _)| *namespace == RESIDENCY_NAMESPACE)
+        .map(|(_, value)| value.to_string())
+        .collect();
+    residencies.into_iter().collect()
+}
+
+/// Parse a comma separated list of residencies.
+pub fn parse_residencies(raw: &str) -> Result<BTreeSet<String>> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+        .map(validate_residency)
+        .collect()
+}
+
+/// Whether a holder of `allowed` may hold a context naming `residencies`.
+/// Unlabeled contexts may go anywhere.
+pub fn may_hold(allowed: &BTreeSet<String>, residencies: &[String]) -> bool {
+    residencies
+        .iter()
+        .all(|residency| allowed.contains(residency))
+}
+
+/// Every live context of `store` with its residencies.
+pub fn resident_contexts(store: &mut Store) -> Vec<ResidentContext> {
+    store
+        .list_recent_contexts(u32::MAX)
+        .into_iter()
+        .map(|head| ResidentContext {
+            context_id: head.context_id,
+            head_turn_id: head.head_turn_id,
+            residencies: residencies(store.get_context_metadata(head.context_id).as_ref()),
+            sealed: store.is_context_sealed(head.context_id),
+        })
+        .collect()
+}
+
+fn validate_residency(residency: &str) -> Result<String> {
+    if residency.is_empty()
+        || !residency
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(StoreError::InvalidInput(format!(
+            "invalid residency {residency:?}: use letters, digits, '-' and '_'"
+        )));
+    }
+    Ok(residency.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resident(context_id: u64, residencies: &[&str]) -> ResidentContext {
+        ResidentContext {
+            context_id,
+            head_turn_id: context_id * 10,
+            residencies: residencies.iter().map(|r| r.to_string()).collect(),
+            sealed: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_destinations() {
+        assert_eq!(
+            S3Destination::parse("s3://cxdb-eu/prod/@eu-central-1", "us-west-2").unwrap(),
+            S3Destination::new("cxdb-eu", "prod", "eu-central-1")
+        );
+        assert_eq!(
+            S3Destination::parse("cxdb-eu", "us-west-2").unwrap(),
+            S3Destination::new("cxdb-eu", "", "us-west-2")
+        );
+        assert!(S3Destination::parse("/prefix", "us-west-2").is_err());
+        assert!(S3Destination::parse("bucket@", "us-west-2").is_err());
+        assert_eq!(
+            S3Destination::new("b", "cxdb/eu/", "r").key("contexts/1.jsonl"),
+            "cxdb/eu/contexts/1.jsonl"
+        );
+    }
+
+    #[test]
+    fn test_refuses_overlapping_destinations() {
+        let primary = S3Destination::new("cxdb", "prod", "us-west-2");
+        let config = |targets: &str| ResidencyConfig::parse("us", targets, "us-west-2").unwrap();
+
+        assert!(config("eu=cxdb-eu/prod@eu-central-1,apac=cxdb/apac")
+            .validate(&primary)
+            .is_ok());
+        assert!(config("eu=cxdb/prod/eu").validate(&primary).is_err());
+        assert!(config("eu=cxdb").validate(&primary).is_err());
+        assert!(config("eu=cxdb-eu/x,apac=cxdb-eu/x/y")
+            .validate(&primary)
+            .is_err());
+        assert!(config("us=cxdb-us").validate(&primary).is_err());
+        assert!(ResidencyConfig::parse("", "eu=a,eu=b", "r").is_err());
+        assert!(ResidencyConfig::parse("e u", "", "r").is_err());
+        assert!(ResidencyConfig::parse("", "eu", "r").is_err());
+    }
+
+    #[test]
+    fn test_plan_partitions_by_residency() {
+        let config = ResidencyConfig::parse("us", "eu=cxdb-eu", "us-west-2").unwrap();
+
+        let plan = config.plan(vec![resident(1, &[]), resident(2, &["us"])]);
+        assert!(plan.raw_files);
+        assert!(plan.primary.is_empty() && plan.targets.is_empty());
+
+        let plan = config.plan(vec![
+            resident(1, &[]),
+            resident(2, &["eu"]),
+            resident(3, &["apac"]),
+            resident(4, &["eu", "us"]),
+        ]);
+        assert!(!plan.raw_files);
+        assert_eq!(plan.primary, vec![resident(1, &[])]);
+        assert_eq!(plan.targets["eu"], vec![resident(2, &["eu"])]);
+        let excluded: Vec<u64> = plan.excluded.iter().map(|c| c.context_id).collect();
+        assert_eq!(excluded, vec![3, 4]);
+        let err = plan.refuse_excluded().unwrap_err().to_string();
+        assert!(
+            err.contains("context 3") && err.contains("\"apac\""),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_plan_excludes_sealed_contexts_from_archives() {
+        let config = ResidencyConfig::parse("", "eu=cxdb-eu", "us-west-2").unwrap();
+        let sealed = |context_id, residencies| ResidentContext {
+            sealed: true,
+            ..resident(context_id, residencies)
+        };
+
+        // Store files carry sealed data as it is.
+        let plan = config.plan(vec![resident(1, &[]), sealed(2, &[])]);
+        assert!(plan.raw_files && plan.excluded.is_empty());
+
+        let plan = config.plan(vec![
+            resident(1, &[]),
+            sealed(2, &[]),
+            resident(3, &["eu"]),
+            sealed(4, &["eu"]),
+        ]);
+        assert!(!plan.raw_files);
+        assert_eq!(plan.primary, vec![resident(1, &[])]);
+        assert_eq!(plan.targets["eu"], vec![resident(3, &["eu"])]);
+        let excluded: Vec<u64> = plan.excluded.iter().map(|c| c.context_id).collect();
+        assert_eq!(excluded, vec![2, 4]);
+
+        let plan = config.plan(vec![resident(1, &[]), sealed(4, &["eu"])]);
+        assert!(plan.targets.is_empty());
+        assert_eq!(plan.excluded.len(), 1);
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Context rollovers.
+//!
+//! A context approaching its depth limit can be rolled over: a new context
+//! is created whose first turn is a `cxdb.ContextSummary` digest of the old
+//! one (trigger `rollover`), and whose metadata names the old context as its
+//! parent with spawn reason `rollover`. The old context keeps its turns and is
+//! marked as rolled over. A context is rolled over at most once. Rollovers
+//! are appended to a JSON-lines log that is replayed on open, so both
+//! contexts can report the link.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use rmpv::Value;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::hooks::{summary_fields, HookTrigger, SummaryResponse};
+use crate::jsonl_log::open_log;
+use crate::store::ContextMetadata;
+
+/// Provenance spawn reason of contexts created by a rollover.
+pub const ROLLOVER_SPAWN_REASON: &str = "rollover";
+
+/// Default generator recorded for digests written by the caller.
+pub const CLIENT_GENERATOR: &str = "client";
+
+/// Body of a rollover request. Without a `digest`, the summary hook
+/// writes one.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RolloverRequest {
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub generator: Option<String>,
+}
+
+/// A context rolled over into a new one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rollover {
+    pub from_context_id: u64,
+    pub to_context_id: u64,
+    /// First turn of the new context, holding the digest.
+    pub digest_turn_id: u64,
+    /// Last turn of the old context the digest covers.
+    pub summarized_through_turn_id: u64,
+    pub generator: String,
+    pub at_unix_ms: u64,
+}
+
+pub struct Rollovers {
+    file: File,
+    by_from: HashMap<u64, Rollover>,
+    /// New context id to the context it continues.
+    by_to: HashMap<u64, u64>,
+}
+
+impl Rollovers {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("rollovers.jsonl");
+        let (file, entries) = open_log::<Rollover>(&path)?;
+
+        let mut rollovers = Self {
+            file,
+            by_from: HashMap::new(),
+            by_to: HashMap::new(),
+        };
+        for rollover in entries {
+            rollovers.apply(rollover);
+        }
+        Ok(rollovers)
+    }
+
+    fn apply(&mut self, rollover: Rollover) {
+        self.by_to
+            .insert(rollover.to_context_id, rollover.from_context_id);
+        self.by_from.insert(rollover.from_context_id, rollover);
+    }
+
+    /// The rollover that replaced a context.
+    pub fn successor(&self, context_id: u64) -> Option<&Rollover> {
+        self.by_from.get(&context_id)
+    }
+
+    /// The rollover that created a context.
+    pub fn predecessor(&self, context_id: u64) -> Option<&Rollover> {
+        self.by_to
+            .get(&context_id)
+            .and_then(|from| self.by_from.get(from))
+    }
+
+    /// Fail if a context was already rolled over.
+    pub fn check_available(&self, context_id: u64) -> Result<()> {
+        match self.successor(context_id) {
+            Some(rollover) => Err(StoreError::InvalidInput(format!(
+                "context {context_id} was already rolled over to context {}",
+                rollover.to_context_id
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn record(&mut self, rollover: Rollover) -> Result<()> {
+        self.check_available(rollover.from_context_id)?;
+        let mut line = serde_json::to_vec(&rollover)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.apply(rollover);
+        Ok(())
+    }
+}
+
+/// Encode the first turn of a rolled-over context: the digest as a
+/// `cxdb.ContextSummary`, plus context metadata (key 30) that carries the
+/// old context's tag, title and labels and names it as the parent.
+#[allow(clippy::too_many_arguments)]
+pub fn encode_rollover_payload(
+    digest: &SummaryResponse,
+    generator: &str,
+    from_context_id: u64,
+    summarized_through_turn_id: u64,
+    turn_count: u32,
+    metadata: Option<&ContextMetadata>,
+    generated_at_unix_ms: u64,
+) -> Result<Vec<u8>> {
+    let mut fields = summary_fields(
+        digest,
+        summarized_through_turn_id,
+        turn_count,
+        HookTrigger::Rollover,
+        generator,
+        generated_at_unix_ms,
+    );
+
+    let root_context_id = metadata
+        .and_then(|m| m.provenance.as_ref())
+        .and_then(|p| p.root_context_id)
+        .unwrap_or(from_context_id);
+    let provenance = vec![
+        (Value::from(1), Value::from(from_context_id)),
+        (Value::from(2), Value::from(ROLLOVER_SPAWN_REASON)),
+        (Value::from(3), Value::from(root_context_id)),
+        (Value::from(80), Value::from(generated_at_unix_ms as i64)),
+    ];
+    let mut context_metadata = Vec::new();
+    if let Some(metadata) = metadata {
+        if let Some(tag) = &metadata.client_tag {
+            context_metadata.push((Value::from(1), Value::from(tag.as_str())));
+        }
+        if let Some(title) = &metadata.title {
+            context_metadata.push((Value::from(2), Value::from(title.as_str())));
+        }
+        if let Some(labels) = &metadata.labels {
+            let labels = labels.iter().map(|l| Value::from(l.as_str())).collect();
+            context_metadata.push((Value::from(3), Value::Array(labels)));
+        }
+    }
+    context_metadata.push((Value::from(10), Value::Map(provenance)));
+    fields.push((Value::from(30), Value::Map(context_metadata)));
+
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &Value::Map(fields))
+        .map_err(|e| StoreError::InvalidInput(format!("msgpack encode error: {e}")))?;
+    Ok(buf)
+}
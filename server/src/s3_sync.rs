@@ -27,15 +27,36 @@
 //!   turns/heads.tbl
 //!   registry/{bundle_id}.json
 //!   sync_manifest.json    # metadata about last sync
+//!   contexts/{context_id}.jsonl   # residency archives, see below
 //! ```
+//!
+//! # Residency
+//!
+//! Contexts labeled `residency:<region>` may only be copied where
+//! [`ResidencyConfig`] allows. While every context may live in the primary
+//! bucket the store files are copied as above. Otherwise they are withheld,
+//! and each context whose head moved is exported as an archive to
+//! `contexts/{context_id}.jsonl` under its residency's destination (or the
+//! primary bucket). An archive left in a destination the context may no
+//! longer use is deleted. Contexts with no destination are not copied, and
+//! neither are sealed contexts: archives hold payloads in the clear.
+//!
+//! When store files start being withheld, the manifest is rewritten without
+//! them and marked `store_files_withheld`, then their copies in the primary
+//! bucket are deleted. Restore never reads store files from a manifest so
+//! marked.
 
+use crate::archive::export_context;
 use crate::error::{Result, StoreError};
+use crate::residency::{resident_contexts, ResidencyConfig, ResidencyPlan, S3Destination};
+use crate::store::Store;
 use aws_sdk_s3::primitives::ByteStream;
 use aws_sdk_s3::Client as S3Client;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::sync::watch;
 use tokio::time::interval;
@@ -82,6 +103,11 @@ impl S3SyncConfig {
             enabled: true,
         })
     }
+
+    /// The primary bucket and prefix.
+    pub fn destination(&self) -> S3Destination {
+        S3Destination::new(&self.bucket, &self.prefix, &self.region)
+    }
 }
 
 /// Tracks sync state for each file
@@ -89,8 +115,27 @@ impl S3SyncConfig {
 pub struct SyncState {
     /// Map of relative file path -> last synced size in bytes
     pub file_sizes: HashMap<String, u64>,
+    /// Map of relative file path -> identity (inode) of the file last
+    /// synced. A file replaced by rename, like a swept pack, gets a new one.
+    #[serde(default)]
+    pub file_ids: HashMap<String, u64>,
     /// Unix timestamp of last successful sync
     pub last_sync_time: u64,
+    /// Context archives uploaded for residency, by context id
+    #[serde(default)]
+    pub archives: HashMap<u64, ArchivedContext>,
+    /// Store files dropped from the manifest whose copies in the bucket are
+    /// still to be deleted
+    #[serde(default)]
+    pub withdrawn: BTreeSet<String>,
+}
+
+/// Where a context's archive was last uploaded, and at which head.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedContext {
+    /// Target residency, or `None` for the primary bucket
+    pub residency: Option<String>,
+    pub head_turn_id: u64,
 }
 
 impl SyncState {
@@ -110,6 +155,20 @@ impl SyncState {
         fs::write(&path, json)?;
         Ok(())
     }
+
+    /// Stop tracking synced store files, queueing their copies for deletion.
+    /// Returns whether any were tracked.
+    fn withdraw_store_files(&mut self) -> bool {
+        let mut withdrew = false;
+        for relative_path in SYNC_FILES {
+            self.file_ids.remove(*relative_path);
+            if self.file_sizes.remove(*relative_path).is_some() {
+                self.withdrawn.insert(relative_path.to_string());
+                withdrew = true;
+            }
+        }
+        withdrew
+    }
 }
 
 /// S3 manifest stored in the bucket
@@ -121,6 +180,41 @@ pub struct S3Manifest {
     pub created_at: u64,
     /// Version for future compatibility
     pub version: u32,
+    /// Set while residency keeps store files out of the primary bucket;
+    /// restore then skips any listed.
+    #[serde(default)]
+    pub store_files_withheld: bool,
+}
+
+impl S3Manifest {
+    /// Files restore may download.
+    fn restorable_files(&self) -> Vec<(&String, u64)> {
+        self.files
+            .iter()
+            .filter(|(path, _)| !(self.store_files_withheld && SYNC_FILES.contains(&path.as_str())))
+            .map(|(path, size)| (path, *size))
+            .collect()
+    }
+}
+
+/// Result of [`S3Sync::verify`].
+#[derive(Debug, Clone, Serialize)]
+pub struct S3Verification {
+    /// Whether a manifest was found and every file it lists checks out.
+    pub ok: bool,
+    /// Unix timestamp of the manifest, absent when there is none.
+    pub manifest_created_at: Option<u64>,
+    pub files: Vec<S3FileCheck>,
+}
+
+/// One backed-up file: sizes in the manifest, the bucket and locally.
+#[derive(Debug, Clone, Serialize)]
+pub struct S3FileCheck {
+    pub path: String,
+    pub ok: bool,
+    pub manifest_bytes: u64,
+    pub remote_bytes: Option<u64>,
+    pub local_bytes: Option<u64>,
 }
 
 /// Files to sync (relative to data_dir)
@@ -131,6 +225,7 @@ const SYNC_FILES: &[&str] = &[
     "turns/turns.idx",
     "turns/turns.meta",
     "turns/heads.tbl",
+    "format.json",
 ];
 
 /// S3 sync manager
@@ -138,6 +233,12 @@ pub struct S3Sync {
     config: S3SyncConfig,
     data_dir: PathBuf,
     s3_client: S3Client,
+    residency: ResidencyConfig,
+    /// Clients for residency destinations, by residency
+    target_clients: HashMap<String, S3Client>,
+    /// Store consulted for residency labels; without it store files are
+    /// always copied whole.
+    store: Option<Arc<Mutex<Store>>>,
 }
 
 impl S3Sync {
@@ -156,16 +257,36 @@ impl S3Sync {
             config,
             data_dir,
             s3_client,
+            residency: ResidencyConfig::default(),
+            target_clients: HashMap::new(),
+            store: None,
+        }
+    }
+
+    /// Route contexts of `store` by residency from now on. `residency` must
+    /// already be validated against the primary bucket.
+    pub async fn with_residency(
+        mut self,
+        residency: ResidencyConfig,
+        store: Arc<Mutex<Store>>,
+    ) -> Self {
+        for (name, destination) in &residency.targets {
+            let aws_config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+                .region(aws_config::Region::new(destination.region.clone()))
+                .load()
+                .await;
+            self.target_clients
+                .insert(name.clone(), S3Client::new(&aws_config));
         }
+        self.residency = residency;
+        self.store = Some(store);
+        self
     }
 
     /// Check if local data directory needs restoration from S3.
     /// Returns true if data was restored.
     pub async fn maybe_restore(&self) -> Result<bool> {
-        // Check if any core data files exist locally
-        let has_local_data = SYNC_FILES.iter().any(|f| self.data_dir.join(f).exists());
-
-        if has_local_data {
+        if self.has_local_data() {
             eprintln!("[s3_sync] Local data exists, skipping restore");
             return Ok(false);
         }
@@ -191,8 +312,12 @@ impl S3Sync {
             manifest.created_at
         );
 
+        if manifest.store_files_withheld {
+            eprintln!("[s3_sync] Store files are withheld for residency, not restoring them");
+        }
+
         // Restore each file
-        for (relative_path, expected_size) in &manifest.files {
+        for (relative_path, expected_size) in manifest.restorable_files() {
             let local_path = self.data_dir.join(relative_path);
 
             // Create parent directories
@@ -202,7 +327,7 @@ impl S3Sync {
 
             match self.download_file(relative_path, &local_path).await {
                 Ok(size) => {
-                    if size != *expected_size {
+                    if size != expected_size {
                         eprintln!(
                             "[s3_sync] Warning: {relative_path} size mismatch (expected {expected_size}, got {size})"
                         );
@@ -224,6 +349,56 @@ impl S3Sync {
         Ok(true)
     }
 
+    /// Whether any core data file exists locally.
+    pub fn has_local_data(&self) -> bool {
+        SYNC_FILES.iter().any(|f| self.data_dir.join(f).exists())
+    }
+
+    /// Compare the backup with its manifest: every file the manifest lists
+    /// must exist in the bucket with the listed size. Local sizes are
+    /// reported alongside; a local file larger than its backup has grown
+    /// since the last sync.
+    pub async fn verify(&self) -> Result<S3Verification> {
+        let Some(manifest) = self.fetch_manifest().await? else {
+            return Ok(S3Verification {
+                ok: false,
+                manifest_created_at: None,
+                files: Vec::new(),
+            });
+        };
+
+        let mut paths: Vec<&String> = manifest.files.keys().collect();
+        paths.sort();
+        let mut files = Vec::with_capacity(paths.len());
+        for path in paths {
+            let manifest_bytes = manifest.files[path];
+            let remote_bytes = self
+                .s3_client
+                .head_object()
+                .bucket(&self.config.bucket)
+                .key(self.s3_key(path))
+                .send()
+                .await
+                .ok()
+                .and_then(|head| head.content_length())
+                .map(|len| len as u64);
+            let local_bytes = fs::metadata(self.data_dir.join(path)).ok().map(|m| m.len());
+            files.push(S3FileCheck {
+                path: path.clone(),
+                ok: remote_bytes == Some(manifest_bytes),
+                manifest_bytes,
+                remote_bytes,
+                local_bytes,
+            });
+        }
+
+        Ok(S3Verification {
+            ok: files.iter().all(|f| f.ok),
+            manifest_created_at: Some(manifest.created_at),
+            files,
+        })
+    }
+
     /// Start the background sync loop. Returns a handle to stop it.
     pub fn start_background_sync(self) -> S3SyncHandle {
         let (shutdown_tx, shutdown_rx) = watch::channel(false);
@@ -273,25 +448,56 @@ impl S3Sync {
         let mut files_synced = 0;
         let mut bytes_synced = 0u64;
 
-        // Sync each tracked file
-        for relative_path in SYNC_FILES {
+        let plan = self.residency_plan().await?;
+        for excluded in &plan.excluded {
+            eprintln!(
+                "[s3_sync] Not syncing context {}: {}",
+                excluded.context_id, excluded.reason
+            );
+        }
+        if plan.raw_files {
+            // Uploads below replace anything left to delete.
+            state.withdrawn.clear();
+        } else {
+            self.withdraw_store_files(&mut state).await?;
+        }
+        let archives_synced = self.sync_archives(&plan, &mut state).await;
+
+        // Sync each tracked file, unless residency keeps some contexts out
+        // of the primary bucket
+        let sync_files: &[&str] = if plan.raw_files { SYNC_FILES } else { &[] };
+        for relative_path in sync_files {
             let local_path = self.data_dir.join(relative_path);
 
             if !local_path.exists() {
                 continue;
             }
 
-            let current_size = fs::metadata(&local_path)?.len();
-            let last_size = state.file_sizes.get(*relative_path).copied().unwrap_or(0);
-
-            if current_size > last_size {
+            let metadata = fs::metadata(&local_path)?;
+            let current_size = metadata.len();
+            let current_id = file_id(&metadata);
+            // A rewritten file (a swept pack, a rebuilt index) may be smaller
+            // than the copy in the bucket, so its size says nothing about
+            // what was synced.
+            let rewritten = state
+                .file_ids
+                .get(*relative_path)
+                .is_some_and(|&id| id != current_id);
+            let last_size = if rewritten {
+                0
+            } else {
+                state.file_sizes.get(*relative_path).copied().unwrap_or(0)
+            };
+
+            if current_size != last_size {
                 match self.upload_file(&local_path, relative_path).await {
                     Ok(()) => {
                         state
                             .file_sizes
                             .insert(relative_path.to_string(), current_size);
+                        state.file_ids.insert(relative_path.to_string(), current_id);
                         files_synced += 1;
-                        bytes_synced += current_size - last_size;
+                        bytes_synced += current_size.saturating_sub(last_size);
                     }
                     Err(e) => {
                         eprintln!("[s3_sync] Failed to upload {relative_path}: {e}");
@@ -303,9 +509,9 @@ impl S3Sync {
         // Sync registry files
         let registry_synced = self.sync_registry(&mut state).await?;
 
-        if files_synced > 0 || registry_synced > 0 {
+        if files_synced > 0 || registry_synced > 0 || archives_synced > 0 {
             // Update manifest
-            self.upload_manifest(&state).await?;
+            self.upload_manifest(&state, !plan.raw_files).await?;
 
             state.last_sync_time = SystemTime::now()
                 .duration_since(UNIX_EPOCH)
@@ -314,14 +520,178 @@ impl S3Sync {
             state.save(&self.data_dir)?;
 
             eprintln!(
-                "[s3_sync] Synced {} files ({} bytes) + {} registry bundles",
-                files_synced, bytes_synced, registry_synced
+                "[s3_sync] Synced {} files ({} bytes) + {} registry bundles + {} context archives",
+                files_synced, bytes_synced, registry_synced, archives_synced
             );
         }
 
         Ok(())
     }
 
+    /// Take store files out of the primary bucket once residency withholds
+    /// them: first from the manifest, so restore no longer uses them, then
+    /// the objects themselves. Failed deletions are retried next pass.
+    async fn withdraw_store_files(&self, state: &mut SyncState) -> Result<()> {
+        if state.withdraw_store_files() {
+            self.upload_manifest(state, true).await?;
+            state.save(&self.data_dir)?;
+            eprintln!("[s3_sync] Residency withholds store files, deleting them from the bucket");
+        }
+        if state.withdrawn.is_empty() {
+            return Ok(());
+        }
+        for relative_path in state.withdrawn.clone() {
+            match self.delete_file(&relative_path).await {
+                Ok(()) => {
+                    state.withdrawn.remove(&relative_path);
+                }
+                Err(e) => eprintln!("[s3_sync] Failed to delete {relative_path}: {e}"),
+            }
+        }
+        state.save(&self.data_dir)
+    }
+
+    async fn residency_plan(&self) -> Result<ResidencyPlan> {
+        let Some(store) = &self.store else {
+            return Ok(ResidencyPlan {
+                raw_files: true,
+                ..Default::default()
+            });
+        };
+        let store = Arc::clone(store);
+        let residency = self.residency.clone();
+        tokio::task::spawn_blocking(move || {
+            residency.plan(resident_contexts(&mut store.lock().unwrap()))
+        })
+        .await
+        .map_err(|e| StoreError::Io(std::io::Error::other(e)))
+    }
+
+    /// Upload archives of contexts whose head moved or whose destination
+    /// changed, and delete archives from destinations a context may no
+    /// longer use. Returns the number uploaded.
+    async fn sync_archives(&self, plan: &ResidencyPlan, state: &mut SyncState) -> usize {
+        let mut wanted: HashMap<u64, ArchivedContext> = HashMap::new();
+        let placements =
+            plan.primary
+                .iter()
+                .map(|c| (None, c))
+                .chain(plan.targets.iter().flat_map(|(residency, contexts)| {
+                    contexts.iter().map(move |c| (Some(residency.clone()), c))
+                }));
+        for (residency, context) in placements {
+            wanted.insert(
+                context.context_id,
+                ArchivedContext {
+                    residency,
+                    head_turn_id: context.head_turn_id,
+                },
+            );
+        }
+
+        // Archives in the wrong place go first. In raw mode the primary
+        // bucket may hold every context, so its archives stay.
+        let stale: Vec<(u64, ArchivedContext)> = state
+            .archives
+            .iter()
+            .filter(|(context_id, archived)| match wanted.get(context_id) {
+                Some(want) => want.residency != archived.residency,
+                None => !(plan.raw_files && archived.residency.is_none()),
+            })
+            .map(|(context_id, archived)| (*context_id, archived.clone()))
+            .collect();
+        for (context_id, archived) in stale {
+            match self
+                .delete_archive(archived.residency.as_deref(), context_id)
+                .await
+            {
+                Ok(()) => {
+                    state.archives.remove(&context_id);
+                }
+                Err(e) => {
+                    eprintln!("[s3_sync] Failed to delete archive of context {context_id}: {e}")
+                }
+            }
+        }
+
+        let mut synced = 0;
+        for (context_id, want) in wanted {
+            if state.archives.get(&context_id) == Some(&want) {
+                continue;
+            }
+            let result = match self.export_archive(context_id).await {
+                Ok(bytes) => {
+                    self.upload_archive(want.residency.as_deref(), context_id, bytes)
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => {
+                    state.archives.insert(context_id, want);
+                    synced += 1;
+                }
+                Err(e) => {
+                    eprintln!("[s3_sync] Failed to archive context {context_id}: {e}")
+                }
+            }
+        }
+        synced
+    }
+
+    async fn export_archive(&self, context_id: u64) -> Result<Vec<u8>> {
+        let Some(store) = &self.store else {
+            return Err(StoreError::InvalidInput("no store to archive from".into()));
+        };
+        let store = Arc::clone(store);
+        tokio::task::spawn_blocking(move || {
+            let mut store = store.lock().unwrap();
+            // Archives hold payloads in the clear; the plan already excludes
+            // sealed contexts, but one may have been sealed since.
+            if store.is_context_sealed(context_id) {
+                return Err(StoreError::InvalidInput(format!(
+                    "context {context_id} is sealed and cannot be archived"
+                )));
+            }
+            let mut out = Vec::new();
+            export_context(&mut store, context_id, &mut out)?;
+            Ok(out)
+        })
+        .await
+        .map_err(|e| StoreError::Io(std::io::Error::other(e)))?
+    }
+
+    /// Client, bucket and key of a context archive in the primary bucket
+    /// (`residency` is `None`) or a residency destination.
+    fn archive_location(
+        &self,
+        residency: Option<&str>,
+        context_id: u64,
+    ) -> Result<(&S3Client, String, String)> {
+        let relative_path = format!("contexts/{context_id}.jsonl");
+        match residency {
+            None => Ok((
+                &self.s3_client,
+                self.config.bucket.clone(),
+                self.s3_key(&relative_path),
+            )),
+            Some(residency) => {
+                let destination = self.residency.targets.get(residency);
+                let client = self.target_clients.get(residency);
+                match (destination, client) {
+                    (Some(destination), Some(client)) => Ok((
+                        client,
+                        destination.bucket.clone(),
+                        destination.key(&relative_path),
+                    )),
+                    _ => Err(StoreError::InvalidInput(format!(
+                        "no S3 destination for residency {residency:?}"
+                    ))),
+                }
+            }
+        }
+    }
+
     async fn sync_registry(&self, state: &mut SyncState) -> Result<usize> {
         let registry_dir = self.data_dir.join("registry");
         if !registry_dir.exists() {
@@ -442,7 +812,7 @@ impl S3Sync {
         }
     }
 
-    async fn upload_manifest(&self, state: &SyncState) -> Result<()> {
+    async fn upload_manifest(&self, state: &SyncState, store_files_withheld: bool) -> Result<()> {
         let manifest = S3Manifest {
             files: state.file_sizes.clone(),
             created_at: SystemTime::now()
@@ -450,6 +820,7 @@ impl S3Sync {
                 .unwrap()
                 .as_secs(),
             version: 1,
+            store_files_withheld,
         };
 
         let json = serde_json::to_vec_pretty(&manifest)
@@ -493,6 +864,48 @@ impl S3Sync {
         Ok(())
     }
 
+    async fn upload_archive(
+        &self,
+        residency: Option<&str>,
+        context_id: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let (client, bucket, key) = self.archive_location(residency, context_id)?;
+        client
+            .put_object()
+            .bucket(bucket)
+            .key(&key)
+            .body(ByteStream::from(data))
+            .content_type("application/x-ndjson")
+            .send()
+            .await
+            .map_err(|e| StoreError::Io(std::io::Error::other(format!("S3 upload failed: {e}"))))?;
+        Ok(())
+    }
+
+    async fn delete_archive(&self, residency: Option<&str>, context_id: u64) -> Result<()> {
+        let (client, bucket, key) = self.archive_location(residency, context_id)?;
+        client
+            .delete_object()
+            .bucket(bucket)
+            .key(&key)
+            .send()
+            .await
+            .map_err(|e| StoreError::Io(std::io::Error::other(format!("S3 delete failed: {e}"))))?;
+        Ok(())
+    }
+
+    async fn delete_file(&self, relative_path: &str) -> Result<()> {
+        self.s3_client
+            .delete_object()
+            .bucket(&self.config.bucket)
+            .key(self.s3_key(relative_path))
+            .send()
+            .await
+            .map_err(|e| StoreError::Io(std::io::Error::other(format!("S3 delete failed: {e}"))))?;
+        Ok(())
+    }
+
     async fn download_file(&self, relative_path: &str, local_path: &Path) -> Result<u64> {
         let key = self.s3_key(relative_path);
 
@@ -538,6 +951,18 @@ impl S3SyncHandle {
     }
 }
 
+/// Identity of a file that changes when it is replaced rather than
+/// appended to.
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(metadata)
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> u64 {
+    0
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -559,6 +984,40 @@ mod tests {
         assert_eq!(loaded.last_sync_time, 1700000000);
     }
 
+    #[test]
+    fn test_withdrawn_store_files_leave_the_manifest() {
+        let mut state = SyncState::default();
+        state.file_sizes.insert("blobs/blobs.pack".to_string(), 100);
+        state.file_ids.insert("blobs/blobs.pack".to_string(), 7);
+        state.file_sizes.insert("registry/b1.json".to_string(), 10);
+
+        assert!(state.withdraw_store_files());
+        assert!(!state.withdraw_store_files());
+        assert_eq!(state.file_sizes.len(), 1);
+        assert!(state.file_ids.is_empty());
+        assert!(state.withdrawn.contains("blobs/blobs.pack"));
+
+        let mut manifest = S3Manifest {
+            files: HashMap::from([
+                ("turns/turns.log".to_string(), 50),
+                ("registry/b1.json".to_string(), 10),
+            ]),
+            created_at: 0,
+            version: 1,
+            store_files_withheld: false,
+        };
+        assert_eq!(manifest.restorable_files().len(), 2);
+        manifest.store_files_withheld = true;
+        let restorable = manifest.restorable_files();
+        assert_eq!(restorable, vec![(&"registry/b1.json".to_string(), 10)]);
+
+        // Manifests from before residency restore everything.
+        let old: S3Manifest =
+            serde_json::from_str(r#"{"files":{"turns/turns.log":50},"created_at":0,"version":1}"#)
+                .unwrap();
+        assert_eq!(old.restorable_files().len(), 1);
+    }
+
     #[test]
     fn test_s3_key_with_prefix() {
         // Note: Can't easily test S3Sync::s3_key without async context,
@@ -0,0 +1,490 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Pre-signed, expiring share links for read-only access to one context.
+//!
+//! A share token is `base64url(claims) "." base64url(HMAC-SHA256(claims))`,
+//! where the claims are a JSON object naming the share, its scope (context,
+//! optional depth range, filesystem access) and its expiry. A token is only
+//! honored while its share is in the share list and not revoked, so a link
+//! whose record is gone (or a forged id under a leaked secret) is refused.
+//!
+//! The signing secret comes from `CXDB_SHARE_SECRET`, or is generated once
+//! and kept in `meta/share_secret`. Changing it invalidates every link.
+//! Shares are persisted in `meta/shares.json`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::util::{env_u64, unix_ms};
+
+const DEFAULT_TTL_SECS: u64 = 7 * 24 * 3600;
+const DEFAULT_MAX_TTL_SECS: u64 = 90 * 24 * 3600;
+
+/// Share link settings, loaded from the environment.
+#[derive(Clone)]
+pub struct ShareConfig {
+    /// Signing secret; None to use (or create) `meta/share_secret`.
+    pub secret: Option<Vec<u8>>,
+    /// Lifetime of links created without `ttl_secs`.
+    pub default_ttl: Duration,
+    /// Longest lifetime a link may be given.
+    pub max_ttl: Duration,
+}
+
+impl ShareConfig {
+    /// Reads `CXDB_SHARE_SECRET`, `CXDB_SHARE_DEFAULT_TTL_SECS` (default 7
+    /// days) and `CXDB_SHARE_MAX_TTL_SECS` (default 90 days).
+    pub fn from_env() -> Result<Self> {
+        let secret = match std::env::var("CXDB_SHARE_SECRET") {
+            Ok(secret) if secret.len() >= 32 => Some(secret.into_bytes()),
+            Ok(secret) if !secret.is_empty() => {
+                return Err(StoreError::InvalidInput(
+                    "CXDB_SHARE_SECRET must be at least 32 bytes".into(),
+                ))
+            }
+            _ => None,
+        };
+        let config = Self {
+            secret,
+            default_ttl: Duration::from_secs(env_u64(
+                "CXDB_SHARE_DEFAULT_TTL_SECS",
+                DEFAULT_TTL_SECS,
+            )),
+            max_ttl: Duration::from_secs(env_u64("CXDB_SHARE_MAX_TTL_SECS", DEFAULT_MAX_TTL_SECS)),
+        };
+        if config.default_ttl.is_zero() || config.default_ttl > config.max_ttl {
+            return Err(StoreError::InvalidInput(
+                "CXDB_SHARE_DEFAULT_TTL_SECS must be between 1 and CXDB_SHARE_MAX_TTL_SECS".into(),
+            ));
+        }
+        Ok(config)
+    }
+}
+
+impl Default for ShareConfig {
+    fn default() -> Self {
+        Self {
+            secret: None,
+            default_ttl: Duration::from_secs(DEFAULT_TTL_SECS),
+            max_ttl: Duration::from_secs(DEFAULT_MAX_TTL_SECS),
+        }
+    }
+}
+
+/// What a share link grants: reads of one context, optionally limited to
+/// the turns at depths `from_depth..=to_depth`, and optionally of their
+/// filesystem snapshots.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareScope {
+    pub context_id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_depth: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_depth: Option<u32>,
+    #[serde(default)]
+    pub fs: bool,
+}
+
+impl ShareScope {
+    /// Whether a turn of the shared context at `depth` is visible.
+    pub fn allows_depth(&self, depth: u32) -> bool {
+        self.from_depth.is_none_or(|from| depth >= from)
+            && self.to_depth.is_none_or(|to| depth <= to)
+    }
+}
+
+/// Signed content of a share token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareClaims {
+    /// Share id, for revocation.
+    pub sid: String,
+    pub scope: ShareScope,
+    pub exp_unix_ms: u64,
+}
+
+/// A created share link, as listed. The token itself is not kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareInfo {
+    pub share_id: String,
+    pub context_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_turn_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_turn_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_depth: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_depth: Option<u32>,
+    pub fs: bool,
+    pub created_at_unix_ms: u64,
+    pub expires_at_unix_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked_at_unix_ms: Option<u64>,
+}
+
+/// Body of `POST /v1/contexts/:id/share`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ShareSpec {
+    /// Lifetime in seconds; the configured default when absent.
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+    /// First turn visible through the link.
+    #[serde(default)]
+    pub from_turn_id: Option<serde_json::Value>,
+    /// Last turn visible through the link.
+    #[serde(default)]
+    pub to_turn_id: Option<serde_json::Value>,
+    /// Whether filesystem snapshots of visible turns may be read.
+    #[serde(default)]
+    pub fs: bool,
+}
+
+/// A turn bounding a share, resolved against the shared context.
+#[derive(Debug, Clone, Copy)]
+pub struct ShareBound {
+    pub turn_id: u64,
+    pub depth: u32,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct PersistedShares {
+    shares: Vec<ShareInfo>,
+}
+
+pub struct Shares {
+    path: PathBuf,
+    key: hmac::Key,
+    config: ShareConfig,
+    shares: RwLock<BTreeMap<String, ShareInfo>>,
+}
+
+impl Shares {
+    pub fn open(dir: &Path, config: ShareConfig) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let secret = match &config.secret {
+            Some(secret) => secret.clone(),
+            None => load_or_create_secret(&dir.join("share_secret"))?,
+        };
+        let path = dir.join("shares.json");
+        let mut shares = BTreeMap::new();
+        if path.exists() {
+            let bytes = fs::read(&path)?;
+            let persisted: PersistedShares = serde_json::from_slice(&bytes)
+                .map_err(|e| StoreError::Corrupt(format!("shares.json: {e}")))?;
+            for info in persisted.shares {
+                shares.insert(info.share_id.clone(), info);
+            }
+        }
+        Ok(Self {
+            path,
+            key: hmac::Key::new(hmac::HMAC_SHA256, &secret),
+            config,
+            shares: RwLock::new(shares),
+        })
+    }
+
+    /// Create a share link for `context_id` and return it with its token.
+    /// `from` and `to` must already be resolved against the context.
+    pub fn create(
+        &self,
+        context_id: u64,
+        spec: &ShareSpec,
+        from: Option<ShareBound>,
+        to: Option<ShareBound>,
+        created_by: Option<String>,
+    ) -> Result<(ShareInfo, String)> {
+        if let (Some(from), Some(to)) = (from, to) {
+            if from.depth > to.depth {
+                return Err(StoreError::InvalidInput(
+                    "from_turn_id must not come after to_turn_id".into(),
+                ));
+            }
+        }
+        let ttl = match spec.ttl_secs {
+            Some(0) => return Err(StoreError::InvalidInput("ttl_secs must be positive".into())),
+            Some(secs) => Duration::from_secs(secs),
+            None => self.config.default_ttl,
+        };
+        if ttl > self.config.max_ttl {
+            return Err(StoreError::InvalidInput(format!(
+                "ttl_secs exceeds the maximum of {}",
+                self.config.max_ttl.as_secs()
+            )));
+        }
+
+        let now = unix_ms();
+        let claims = ShareClaims {
+            sid: random_id()?,
+            scope: ShareScope {
+                context_id,
+                from_depth: from.map(|b| b.depth),
+                to_depth: to.map(|b| b.depth),
+                fs: spec.fs,
+            },
+            exp_unix_ms: now + ttl.as_millis() as u64,
+        };
+        let info = ShareInfo {
+            share_id: claims.sid.clone(),
+            context_id: context_id.to_string(),
+            from_turn_id: from.map(|b| b.turn_id.to_string()),
+            to_turn_id: to.map(|b| b.turn_id.to_string()),
+            from_depth: claims.scope.from_depth,
+            to_depth: claims.scope.to_depth,
+            fs: spec.fs,
+            created_at_unix_ms: now,
+            expires_at_unix_ms: claims.exp_unix_ms,
+            created_by,
+            revoked_at_unix_ms: None,
+        };
+        let token = self.sign(&claims)?;
+
+        let mut shares = self.shares.write().unwrap();
+        // Expired links can no longer be used, so they are dropped as new
+        // ones are made.
+        shares.retain(|_, s| s.expires_at_unix_ms > now);
+        shares.insert(info.share_id.clone(), info.clone());
+        self.persist(&shares)?;
+        Ok((info, token))
+    }
+
+    fn sign(&self, claims: &ShareClaims) -> Result<String> {
+        let body = serde_json::to_vec(claims)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        let tag = hmac::sign(&self.key, &body);
+        Ok(format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&body),
+            URL_SAFE_NO_PAD.encode(tag.as_ref())
+        ))
+    }
+
+    /// Check a token's signature and expiry, and that its share is listed
+    /// and not revoked, and return its claims.
+    pub fn verify(&self, token: &str) -> Result<ShareClaims> {
+        let invalid = || StoreError::PermissionDenied("invalid share token".into());
+        let (body, tag) = token.split_once('.').ok_or_else(invalid)?;
+        let body = URL_SAFE_NO_PAD.decode(body).map_err(|_| invalid())?;
+        let tag = URL_SAFE_NO_PAD.decode(tag).map_err(|_| invalid())?;
+        hmac::verify(&self.key, &body, &tag).map_err(|_| invalid())?;
+        let claims: ShareClaims = serde_json::from_slice(&body).map_err(|_| invalid())?;
+        if claims.exp_unix_ms <= unix_ms() {
+            return Err(StoreError::PermissionDenied("share link expired".into()));
+        }
+        let shares = self.shares.read().unwrap();
+        let info = shares.get(&claims.sid).ok_or_else(invalid)?;
+        if info.revoked_at_unix_ms.is_some() {
+            return Err(StoreError::PermissionDenied("share link revoked".into()));
+        }
+        Ok(claims)
+    }
+
+    /// Unexpired shares of a context, oldest first.
+    pub fn list(&self, context_id: u64) -> Vec<ShareInfo> {
+        let now = unix_ms();
+        let context_id = context_id.to_string();
+        let mut shares: Vec<ShareInfo> = self
+            .shares
+            .read()
+            .unwrap()
+            .values()
+            .filter(|s| s.context_id == context_id && s.expires_at_unix_ms > now)
+            .cloned()
+            .collect();
+        shares.sort_by_key(|s| s.created_at_unix_ms);
+        shares
+    }
+
+    /// Revoke a share so its token stops working. Revoking twice keeps the
+    /// first revocation time.
+    pub fn revoke(&self, share_id: &str) -> Result<ShareInfo> {
+        let mut shares = self.shares.write().unwrap();
+        let info = shares
+            .get_mut(share_id)
+            .ok_or_else(|| StoreError::NotFound("share".into()))?;
+        if info.revoked_at_unix_ms.is_none() {
+            info.revoked_at_unix_ms = Some(unix_ms());
+        }
+        let info = info.clone();
+        self.persist(&shares)?;
+        Ok(info)
+    }
+
+    fn persist(&self, shares: &BTreeMap<String, ShareInfo>) -> Result<()> {
+        let persisted = PersistedShares {
+            shares: shares.values().cloned().collect(),
+        };
+        let bytes = serde_json::to_vec_pretty(&persisted)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, bytes)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Read the generated signing secret, creating it on first use.
+fn load_or_create_secret(path: &Path) -> Result<Vec<u8>> {
+    if path.exists() {
+        let text = fs::read_to_string(path)?;
+        return hex::decode(text.trim())
+            .map_err(|e| StoreError::Corrupt(format!("{}: {e}", path.display())));
+    }
+    let mut secret = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut secret)
+        .map_err(|_| StoreError::Corrupt("random secret generation failed".into()))?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, hex::encode(secret))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&tmp, fs::Permissions::from_mode(0o600))?;
+    }
+    fs::rename(&tmp, path)?;
+    Ok(secret.to_vec())
+}
+
+fn random_id() -> Result<String> {
+    let mut id = [0u8; 12];
+    SystemRandom::new()
+        .fill(&mut id)
+        .map_err(|_| StoreError::Corrupt("random id generation failed".into()))?;
+    Ok(hex::encode(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn bound(turn_id: u64, depth: u32) -> Option<ShareBound> {
+        Some(ShareBound { turn_id, depth })
+    }
+
+    #[test]
+    fn test_tokens_carry_their_scope_and_reject_tampering() {
+        let dir = tempdir().unwrap();
+        let shares = Shares::open(dir.path(), ShareConfig::default()).unwrap();
+        let spec = ShareSpec {
+            fs: true,
+            ..Default::default()
+        };
+        let (info, token) = shares
+            .create(
+                7,
+                &spec,
+                bound(11, 2),
+                bound(19, 5),
+                Some("a@example.com".into()),
+            )
+            .unwrap();
+        let claims = shares.verify(&token).unwrap();
+        assert_eq!(claims.sid, info.share_id);
+        assert_eq!(claims.scope.context_id, 7);
+        assert!(claims.scope.fs);
+        assert!(!claims.scope.allows_depth(1));
+        assert!(claims.scope.allows_depth(2) && claims.scope.allows_depth(5));
+        assert!(!claims.scope.allows_depth(6));
+
+        // Widening the scope invalidates the signature.
+        let (_, tag) = token.split_once('.').unwrap();
+        let mut forged = claims.clone();
+        forged.scope.to_depth = None;
+        let forged_body = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap());
+        assert!(matches!(
+            shares.verify(&format!("{forged_body}.{tag}")),
+            Err(StoreError::PermissionDenied(_))
+        ));
+        assert!(shares.verify("not-a-token").is_err());
+
+        // Another secret does not accept the token.
+        let other = tempdir().unwrap();
+        let other = Shares::open(other.path(), ShareConfig::default()).unwrap();
+        assert!(other.verify(&token).is_err());
+
+        let backwards = shares.create(7, &spec, bound(19, 5), bound(11, 2), None);
+        assert!(matches!(backwards, Err(StoreError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_expired_and_revoked_tokens_are_refused() {
+        let dir = tempdir().unwrap();
+        let shares = Shares::open(dir.path(), ShareConfig::default()).unwrap();
+        let (info, token) = shares
+            .create(1, &ShareSpec::default(), None, None, None)
+            .unwrap();
+        assert_eq!(
+            info.expires_at_unix_ms - info.created_at_unix_ms,
+            DEFAULT_TTL_SECS * 1000
+        );
+        let too_long = ShareSpec {
+            ttl_secs: Some(DEFAULT_MAX_TTL_SECS + 1),
+            ..Default::default()
+        };
+        assert!(shares.create(1, &too_long, None, None, None).is_err());
+
+        let expired = ShareClaims {
+            sid: "old".into(),
+            scope: ShareScope {
+                context_id: 1,
+                from_depth: None,
+                to_depth: None,
+                fs: false,
+            },
+            exp_unix_ms: unix_ms() - 1,
+        };
+        let expired = shares.sign(&expired).unwrap();
+        assert!(matches!(
+            shares.verify(&expired),
+            Err(StoreError::PermissionDenied(msg)) if msg.contains("expired")
+        ));
+
+        // A signed token is refused unless its share is on record.
+        let unlisted = ShareClaims {
+            sid: "unlisted".into(),
+            exp_unix_ms: unix_ms() + 60_000,
+            ..shares.verify(&token).unwrap()
+        };
+        let unlisted = shares.sign(&unlisted).unwrap();
+        assert!(matches!(
+            shares.verify(&unlisted),
+            Err(StoreError::PermissionDenied(msg)) if msg.contains("invalid")
+        ));
+
+        shares.revoke(&info.share_id).unwrap();
+        assert!(matches!(
+            shares.verify(&token),
+            Err(StoreError::PermissionDenied(msg)) if msg.contains("revoked")
+        ));
+        assert!(matches!(
+            shares.revoke("missing"),
+            Err(StoreError::NotFound(_))
+        ));
+
+        // The generated secret and the revocation survive a restart.
+        drop(shares);
+        let shares = Shares::open(dir.path(), ShareConfig::default()).unwrap();
+        assert!(shares.verify(&token).is_err());
+        let (_, token) = shares
+            .create(1, &ShareSpec::default(), None, None, None)
+            .unwrap();
+        drop(shares);
+        let shares = Shares::open(dir.path(), ShareConfig::default()).unwrap();
+        assert!(shares.verify(&token).is_ok());
+        assert_eq!(shares.list(1).len(), 2);
+        assert!(shares.list(2).is_empty());
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Kafka publisher. Messages are keyed by context id, so events of one
+//! context land on one partition in order; the producer is idempotent so
+//! librdkafka's own retries do not duplicate messages.
+
+use std::time::Duration;
+
+use rdkafka::config::ClientConfig;
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+
+use super::{EventPublisher, SinkMessage};
+use crate::error::{Result, StoreError};
+
+pub struct KafkaPublisher {
+    producer: FutureProducer,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl KafkaPublisher {
+    pub fn new(servers: &str) -> Result<Self> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", servers)
+            .set("enable.idempotence", "true")
+            .set("message.timeout.ms", "30000")
+            .create()
+            .map_err(|e| StoreError::InvalidInput(format!("kafka producer: {e}")))?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(Self { producer, runtime })
+    }
+}
+
+impl EventPublisher for KafkaPublisher {
+    fn publish(&mut self, messages: &[SinkMessage]) -> Result<()> {
+        let kafka_err = |e: rdkafka::error::KafkaError| {
+            StoreError::Io(std::io::Error::other(format!("kafka publish failed: {e}")))
+        };
+        // Enqueue the whole batch, then wait for every delivery report.
+        let mut deliveries = Vec::with_capacity(messages.len());
+        for message in messages {
+            let headers = OwnedHeaders::new().insert(Header {
+                key: "cxdb-event-id",
+                value: Some(message.id.as_str()),
+            });
+            let record = FutureRecord::to(&message.topic)
+                .key(&message.key)
+                .payload(&message.payload)
+                .headers(headers);
+            let delivery = self
+                .producer
+                .send_result(record)
+                .map_err(|(e, _)| kafka_err(e))?;
+            deliveries.push(delivery);
+        }
+        self.runtime.block_on(async {
+            for delivery in deliveries {
+                match tokio::time::timeout(Duration::from_secs(60), delivery).await {
+                    Ok(Ok(Ok(_))) => {}
+                    Ok(Ok(Err((e, _)))) => return Err(kafka_err(e)),
+                    Ok(Err(_)) | Err(_) => {
+                        return Err(StoreError::Io(std::io::Error::other(
+                            "kafka delivery report not received",
+                        )))
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,253 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Continuous export of store events to a message broker.
+//!
+//! When a sink is configured, `context_created` and `turn_appended` events
+//! (or the types listed in `CXDB_SINK_EVENTS`) are recorded in a durable
+//! [`Outbox`] as they are published on the event bus. A publisher thread
+//! drains the outbox in batches to Kafka or NATS JetStream and acknowledges
+//! each batch only once the broker has, so delivery is at-least-once: a
+//! failed or interrupted batch is sent again.
+//!
+//! Messages are keyed by context id and carry a versioned envelope:
+//!
+//! ```text
+//! { "schema_version": 1, "seq": 42, "event_type": "turn_appended",
+//!   "emitted_at_unix_ms": 1734600000000, "data": { "context_id": "7", ... } }
+//! ```
+//!
+//! encoded as JSON or as a msgpack map with the same keys. Kafka support
+//! needs the `kafka` cargo feature and NATS support the `nats` feature.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::error::{Result, StoreError};
+use crate::projection::json_to_msgpack;
+use crate::util::env_u64;
+
+#[cfg(feature = "kafka")]
+mod kafka;
+#[cfg(feature = "nats")]
+mod nats;
+mod outbox;
+
+pub use outbox::{Outbox, OutboxEntry, SinkStats};
+
+/// Version of the message envelope.
+pub const SCHEMA_VERSION: u32 = 1;
+
+const DEFAULT_EVENTS: &[&str] = &["context_created", "turn_appended"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkKind {
+    Kafka,
+    Nats,
+}
+
+impl SinkKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SinkKind::Kafka => "kafka",
+            SinkKind::Nats => "nats",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkFormat {
+    Json,
+    Msgpack,
+}
+
+/// Event sink settings, loaded from the environment.
+#[derive(Debug, Clone)]
+pub struct SinkConfig {
+    pub kind: SinkKind,
+    /// Kafka bootstrap servers or NATS server URLs, comma separated.
+    pub servers: String,
+    pub format: SinkFormat,
+    /// Event types exported.
+    pub events: HashSet<String>,
+    /// Topic (or subject) for event types without an explicit mapping;
+    /// `{event_type}` is replaced with the event type.
+    pub topic_template: String,
+    /// Topic per event type.
+    pub topics: HashMap<String, String>,
+    /// Events sent per broker round trip.
+    pub batch_size: usize,
+    /// Longest wait between retries of a failing batch.
+    pub max_backoff: Duration,
+}
+
+impl SinkConfig {
+    /// Load config from environment variables. Returns None when `CXDB_SINK`
+    /// is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let kind = match var("CXDB_SINK").as_deref() {
+            None => return Ok(None),
+            Some("kafka") => SinkKind::Kafka,
+            Some("nats") => SinkKind::Nats,
+            Some(other) => {
+                return Err(StoreError::InvalidInput(format!(
+                    "unknown CXDB_SINK {other:?} (expected kafka or nats)"
+                )))
+            }
+        };
+        let format = match var("CXDB_SINK_FORMAT").as_deref() {
+            None | Some("json") => SinkFormat::Json,
+            Some("msgpack") => SinkFormat::Msgpack,
+            Some(other) => {
+                return Err(StoreError::InvalidInput(format!(
+                    "unknown CXDB_SINK_FORMAT {other:?} (expected json or msgpack)"
+                )))
+            }
+        };
+        let servers = var("CXDB_SINK_SERVERS").unwrap_or_else(|| match kind {
+            SinkKind::Kafka => "localhost:9092".to_string(),
+            SinkKind::Nats => "nats://localhost:4222".to_string(),
+        });
+        let events = match var("CXDB_SINK_EVENTS") {
+            Some(list) => list
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            None => DEFAULT_EVENTS.iter().map(|s| s.to_string()).collect(),
+        };
+        let topics = match var("CXDB_SINK_TOPICS") {
+            Some(map) => parse_topic_map(&map)?,
+            None => HashMap::new(),
+        };
+        Ok(Some(Self {
+            kind,
+            servers,
+            format,
+            events,
+            topic_template: var("CXDB_SINK_TOPIC")
+                .unwrap_or_else(|| "cxdb.{event_type}".to_string()),
+            topics,
+            batch_size: env_u64("CXDB_SINK_BATCH_SIZE", 256).clamp(1, 10_000) as usize,
+            max_backoff: Duration::from_millis(env_u64("CXDB_SINK_MAX_BACKOFF_MS", 30_000)),
+        }))
+    }
+
+    /// Topic (or subject) an event type is published to.
+    pub fn topic(&self, event_type: &str) -> String {
+        match self.topics.get(event_type) {
+            Some(topic) => topic.clone(),
+            None => self.topic_template.replace("{event_type}", event_type),
+        }
+    }
+}
+
+/// Parse `event_type=topic` pairs separated by commas.
+fn parse_topic_map(map: &str) -> Result<HashMap<String, String>> {
+    map.split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (event_type, topic) = pair.split_once('=').ok_or_else(|| {
+                StoreError::InvalidInput(format!("invalid CXDB_SINK_TOPICS entry {pair:?}"))
+            })?;
+            Ok((event_type.trim().to_string(), topic.trim().to_string()))
+        })
+        .collect()
+}
+
+/// A message ready for the broker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SinkMessage {
+    pub topic: String,
+    pub key: String,
+    /// Unique per event, for broker-side deduplication of redeliveries.
+    pub id: String,
+    pub payload: Vec<u8>,
+}
+
+/// Encode an outbox entry as a broker message.
+pub fn encode_message(entry: &OutboxEntry, config: &SinkConfig) -> Result<SinkMessage> {
+    let envelope = json!({
+        "schema_version": SCHEMA_VERSION,
+        "seq": entry.seq,
+        "event_type": entry.event_type,
+        "emitted_at_unix_ms": entry.emitted_at_unix_ms,
+        "data": entry.data,
+    });
+    let payload = match config.format {
+        SinkFormat::Json => serde_json::to_vec(&envelope)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?,
+        SinkFormat::Msgpack => {
+            let mut buf = Vec::new();
+            rmpv::encode::write_value(&mut buf, &json_to_msgpack(&envelope))
+                .map_err(|e| StoreError::InvalidInput(format!("msgpack encode error: {e}")))?;
+            buf
+        }
+    };
+    Ok(SinkMessage {
+        topic: config.topic(&entry.event_type),
+        key: entry.key.clone(),
+        id: format!("cxdb-{}", entry.seq),
+        payload,
+    })
+}
+
+/// Delivers messages to a broker.
+pub trait EventPublisher: Send {
+    /// Publish a batch in order, returning once the broker acknowledged every
+    /// message. On error the whole batch is retried.
+    fn publish(&mut self, messages: &[SinkMessage]) -> Result<()>;
+}
+
+/// Connect to the configured broker.
+pub fn connect(config: &SinkConfig) -> Result<Box<dyn EventPublisher>> {
+    match config.kind {
+        #[cfg(feature = "kafka")]
+        SinkKind::Kafka => Ok(Box::new(kafka::KafkaPublisher::new(&config.servers)?)),
+        #[cfg(feature = "nats")]
+        SinkKind::Nats => Ok(Box::new(nats::NatsPublisher::connect(&config.servers)?)),
+        #[allow(unreachable_patterns)]
+        kind => Err(StoreError::InvalidInput(format!(
+            "cxdb-server was built without {} support (enable the `{}` feature)",
+            kind.as_str(),
+            kind.as_str()
+        ))),
+    }
+}
+
+/// Start the thread draining `outbox` into `publisher`.
+pub fn start_sink(
+    config: SinkConfig,
+    outbox: Arc<Outbox>,
+    mut publisher: Box<dyn EventPublisher>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut backoff = Duration::from_millis(100);
+        loop {
+            let batch = outbox.next_batch(config.batch_size, Duration::from_secs(1));
+            let Some(last) = batch.last().map(|e| e.seq) else {
+                continue;
+            };
+            let result = batch
+                .iter()
+                .map(|entry| encode_message(entry, &config))
+                .collect::<Result<Vec<_>>>()
+                .and_then(|messages| publisher.publish(&messages))
+                .and_then(|()| outbox.ack(last));
+            match result {
+                Ok(()) => backoff = Duration::from_millis(100),
+                Err(e) => {
+                    eprintln!("event sink: publish failed, retrying in {backoff:?}: {e}");
+                    outbox.record_failure(&e.to_string());
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(config.max_backoff);
+                }
+            }
+        }
+    })
+}
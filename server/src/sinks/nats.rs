@@ -0,0 +1,64 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! NATS JetStream publisher. A stream must capture the configured subjects;
+//! each message is acknowledged by JetStream once stored. The event id is
+//! sent as `Nats-Msg-Id` so redelivered events are deduplicated within the
+//! stream's duplicate window.
+
+use async_nats::jetstream;
+use async_nats::HeaderMap;
+
+use super::{EventPublisher, SinkMessage};
+use crate::error::{Result, StoreError};
+
+pub struct NatsPublisher {
+    jetstream: jetstream::Context,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl NatsPublisher {
+    pub fn connect(servers: &str) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let client = runtime
+            .block_on(async_nats::connect(servers))
+            .map_err(|e| StoreError::Io(std::io::Error::other(format!("nats connect: {e}"))))?;
+        Ok(Self {
+            jetstream: jetstream::new(client),
+            runtime,
+        })
+    }
+}
+
+impl EventPublisher for NatsPublisher {
+    fn publish(&mut self, messages: &[SinkMessage]) -> Result<()> {
+        let nats_err = |e: &dyn std::fmt::Display| {
+            StoreError::Io(std::io::Error::other(format!("nats publish failed: {e}")))
+        };
+        self.runtime.block_on(async {
+            // Send the whole batch, then wait for every acknowledgement.
+            let mut acks = Vec::with_capacity(messages.len());
+            for message in messages {
+                let mut headers = HeaderMap::new();
+                headers.insert("Nats-Msg-Id", message.id.as_str());
+                headers.insert("Cxdb-Context-Id", message.key.as_str());
+                let ack = self
+                    .jetstream
+                    .publish_with_headers(
+                        message.topic.clone(),
+                        headers,
+                        message.payload.clone().into(),
+                    )
+                    .await
+                    .map_err(|e| nats_err(&e))?;
+                acks.push(ack);
+            }
+            for ack in acks {
+                ack.await.map_err(|e| nats_err(&e))?;
+            }
+            Ok(())
+        })
+    }
+}
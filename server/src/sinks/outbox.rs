@@ -0,0 +1,342 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Durable outbox of events awaiting publication.
+//!
+//! Events are appended to `sink_outbox.jsonl` with a sequence number before
+//! the event bus fans them out, so nothing published to the bus is lost if
+//! the broker is down or the server restarts. The publisher acknowledges
+//! events in sequence order; the last acknowledged sequence is kept in
+//! `sink_outbox.ack` and everything after it is replayed on open. Once the
+//! acknowledged part of the log outgrows [`COMPACT_BYTES`] the log is
+//! rewritten with only the pending events.
+
+use std::collections::{HashSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::error::{Result, StoreError};
+use crate::events::StoreEvent;
+use crate::jsonl_log::open_log_sized;
+use crate::util::{sync_dir, unix_ms};
+
+/// Acknowledged log bytes above which the log is rewritten without them.
+pub const COMPACT_BYTES: u64 = 16 * 1024 * 1024;
+
+/// An event recorded for publication.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub seq: u64,
+    pub event_type: String,
+    /// Context the event belongs to; the partition key.
+    pub key: String,
+    pub emitted_at_unix_ms: u64,
+    pub data: JsonValue,
+}
+
+/// Outbox accounting, reported in the metrics snapshot.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SinkStats {
+    /// Broker kind the outbox drains to.
+    pub sink: String,
+    /// Sequence of the last recorded event.
+    pub head_seq: u64,
+    /// Sequence of the last event acknowledged by the broker.
+    pub acked_seq: u64,
+    /// Events recorded but not yet acknowledged.
+    pub pending: u64,
+    /// Age of the oldest pending event; 0 when caught up.
+    pub lag_ms: u64,
+    pub published_total: u64,
+    pub failures_total: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+struct OutboxState {
+    file: File,
+    file_len: u64,
+    head_seq: u64,
+    acked_seq: u64,
+    /// Pending events and the length of their line in the log.
+    pending: VecDeque<(OutboxEntry, u64)>,
+    /// Log bytes taken by pending events.
+    pending_bytes: u64,
+    published_total: u64,
+    failures_total: u64,
+    last_error: Option<String>,
+}
+
+pub struct Outbox {
+    sink: String,
+    /// Event types recorded; others are ignored.
+    events: HashSet<String>,
+    path: PathBuf,
+    ack_path: PathBuf,
+    /// Acknowledged log bytes that trigger a rewrite ([`COMPACT_BYTES`]).
+    compact_bytes: u64,
+    state: Mutex<OutboxState>,
+    ready: Condvar,
+}
+
+impl Outbox {
+    pub fn open(dir: &Path, sink: &str, events: HashSet<String>) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("sink_outbox.jsonl");
+        let ack_path = dir.join("sink_outbox.ack");
+        let acked_seq = match fs::read_to_string(&ack_path) {
+            Ok(s) => s
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| StoreError::Corrupt(format!("{}", ack_path.display())))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        let (file, entries) = open_log_sized::<OutboxEntry>(&path)?;
+        let file_len = file.metadata()?.len();
+
+        let mut head_seq = acked_seq;
+        let mut pending = VecDeque::new();
+        let mut pending_bytes = 0;
+        for (entry, len) in entries {
+            head_seq = head_seq.max(entry.seq);
+            if entry.seq > acked_seq {
+                pending.push_back((entry, len));
+                pending_bytes += len;
+            }
+        }
+
+        Ok(Self {
+            sink: sink.to_string(),
+            events,
+            path,
+            ack_path,
+            compact_bytes: COMPACT_BYTES,
+            state: Mutex::new(OutboxState {
+                file,
+                file_len,
+                head_seq,
+                acked_seq,
+                pending,
+                pending_bytes,
+                published_total: 0,
+                failures_total: 0,
+                last_error: None,
+            }),
+            ready: Condvar::new(),
+        })
+    }
+
+    /// Record an event if its type is exported. Returns whether it was.
+    pub fn record(&self, event: &StoreEvent) -> Result<bool> {
+        let (event_type, data) = event.to_sse();
+        if !self.events.contains(event_type) {
+            return Ok(false);
+        }
+        let data: JsonValue = serde_json::from_str(&data)
+            .map_err(|e| StoreError::InvalidInput(format!("json decode error: {e}")))?;
+        let key = data
+            .get("context_id")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        let mut state = self.state.lock().unwrap();
+        let entry = OutboxEntry {
+            seq: state.head_seq + 1,
+            event_type: event_type.to_string(),
+            key,
+            emitted_at_unix_ms: unix_ms(),
+            data,
+        };
+        let mut line = serde_json::to_vec(&entry)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        state.file.write_all(&line)?;
+        state.file.sync_data()?;
+        state.file_len += line.len() as u64;
+        state.pending_bytes += line.len() as u64;
+        state.head_seq = entry.seq;
+        state.pending.push_back((entry, line.len() as u64));
+        drop(state);
+        self.ready.notify_one();
+        Ok(true)
+    }
+
+    /// Up to `max` of the oldest pending events, waiting up to `timeout` for
+    /// one to arrive. Events stay pending until acknowledged.
+    pub fn next_batch(&self, max: usize, timeout: Duration) -> Vec<OutboxEntry> {
+        let state = self.state.lock().unwrap();
+        let (state, _) = self
+            .ready
+            .wait_timeout_while(state, timeout, |s| s.pending.is_empty())
+            .unwrap();
+        state
+            .pending
+            .iter()
+            .take(max)
+            .map(|(entry, _)| entry.clone())
+            .collect()
+    }
+
+    /// Acknowledge every event up to and including `seq`.
+    pub fn ack(&self, seq: u64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if seq <= state.acked_seq {
+            return Ok(());
+        }
+        // The ack must be durable before the events it covers can leave
+        // the log.
+        let tmp = self.ack_path.with_extension("ack.tmp");
+        let mut ack = File::create(&tmp)?;
+        ack.write_all(seq.to_string().as_bytes())?;
+        ack.sync_all()?;
+        fs::rename(&tmp, &self.ack_path)?;
+        sync_dir(self.ack_path.parent().expect("ack file has a parent"))?;
+
+        while state
+            .pending
+            .front()
+            .is_some_and(|(entry, _)| entry.seq <= seq)
+        {
+            let (_, len) = state.pending.pop_front().expect("front exists");
+            state.pending_bytes -= len;
+            state.published_total += 1;
+        }
+        state.acked_seq = seq;
+        state.last_error = None;
+
+        if state.file_len.saturating_sub(state.pending_bytes) > self.compact_bytes {
+            self.compact(&mut state)?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the log with only the pending events.
+    fn compact(&self, state: &mut OutboxState) -> Result<()> {
+        let tmp = self.path.with_extension("jsonl.tmp");
+        let mut file = File::create(&tmp)?;
+        let mut file_len = 0;
+        for (entry, len) in state.pending.iter_mut() {
+            let mut line = serde_json::to_vec(entry)
+                .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+            line.push(b'\n');
+            file.write_all(&line)?;
+            *len = line.len() as u64;
+            file_len += *len;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        sync_dir(self.path.parent().expect("outbox has a parent"))?;
+        state.file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        state.file_len = file_len;
+        state.pending_bytes = file_len;
+        Ok(())
+    }
+
+    /// Note a failed publish attempt; the events stay pending.
+    pub fn record_failure(&self, error: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.failures_total += 1;
+        state.last_error = Some(error.to_string());
+    }
+
+    pub fn stats(&self) -> SinkStats {
+        let state = self.state.lock().unwrap();
+        SinkStats {
+            sink: self.sink.clone(),
+            head_seq: state.head_seq,
+            acked_seq: state.acked_seq,
+            pending: state.pending.len() as u64,
+            lag_ms: state
+                .pending
+                .front()
+                .map_or(0, |(e, _)| unix_ms().saturating_sub(e.emitted_at_unix_ms)),
+            published_total: state.published_total,
+            failures_total: state.failures_total,
+            last_error: state.last_error.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn(context_id: u64, turn_id: u64) -> StoreEvent {
+        StoreEvent::TurnAppended {
+            context_id: context_id.to_string(),
+            turn_id: turn_id.to_string(),
+            parent_turn_id: "0".to_string(),
+            depth: 0,
+            declared_type_id: None,
+            declared_type_version: None,
+        }
+    }
+
+    #[test]
+    fn test_unacked_events_replay_after_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let events: HashSet<String> = ["turn_appended".to_string()].into();
+        let outbox = Outbox::open(dir.path(), "test", events.clone()).unwrap();
+        assert!(outbox.record(&turn(1, 10)).unwrap());
+        assert!(outbox.record(&turn(2, 11)).unwrap());
+        assert!(!outbox
+            .record(&StoreEvent::ClientConnected {
+                session_id: "1".to_string(),
+                client_tag: "t".to_string(),
+            })
+            .unwrap());
+
+        let batch = outbox.next_batch(10, Duration::ZERO);
+        assert_eq!(batch.iter().map(|e| e.seq).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(batch[0].key, "1");
+        assert_eq!(batch[0].data["turn_id"], "10");
+        outbox.ack(1).unwrap();
+        assert_eq!(outbox.stats().pending, 1);
+        drop(outbox);
+
+        let outbox = Outbox::open(dir.path(), "test", events).unwrap();
+        let batch = outbox.next_batch(10, Duration::ZERO);
+        assert_eq!(batch.len(), 1);
+        assert_eq!((batch[0].seq, batch[0].key.as_str()), (2, "2"));
+        assert!(outbox.record(&turn(3, 12)).unwrap());
+        assert_eq!(outbox.stats().head_seq, 3);
+    }
+
+    #[test]
+    fn test_acked_events_are_compacted_away_while_others_pend() {
+        let dir = tempfile::tempdir().unwrap();
+        let events: HashSet<String> = ["turn_appended".to_string()].into();
+        let mut outbox = Outbox::open(dir.path(), "test", events.clone()).unwrap();
+        outbox.compact_bytes = 0;
+        for turn_id in 1..=4 {
+            outbox.record(&turn(1, turn_id)).unwrap();
+        }
+        let log = dir.path().join("sink_outbox.jsonl");
+        let full = fs::metadata(&log).unwrap().len();
+
+        outbox.ack(2).unwrap();
+        let compacted = fs::metadata(&log).unwrap().len();
+        assert!(compacted < full, "{compacted} >= {full}");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("sink_outbox.ack")).unwrap(),
+            "2"
+        );
+        outbox.record(&turn(1, 5)).unwrap();
+        drop(outbox);
+
+        let outbox = Outbox::open(dir.path(), "test", events).unwrap();
+        let batch = outbox.next_batch(10, Duration::ZERO);
+        assert_eq!(batch.iter().map(|e| e.seq).collect::<Vec<_>>(), [3, 4, 5]);
+    }
+}
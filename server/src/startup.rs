@@ -0,0 +1,162 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Startup phases and readiness.
+//!
+//! The server opens its store, then builds the secondary indexes, then
+//! accepts traffic. The HTTP gateway starts before the index build so
+//! `/healthz` and `/readyz` answer during warm-up; endpoints that read the
+//! store answer 503 with `Retry-After` until the server is ready, instead of
+//! returning results from half-built indexes. The binary protocol listener
+//! is bound only once the server is ready.
+
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::store::Store;
+
+/// Contexts indexed per store lock acquisition during warm-up.
+pub const INDEX_BATCH: usize = 10_000;
+
+/// Interval between index build progress log lines.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupPhase {
+    OpeningStore,
+    BuildingIndexes,
+    Ready,
+}
+
+impl StartupPhase {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => StartupPhase::OpeningStore,
+            1 => StartupPhase::BuildingIndexes,
+            _ => StartupPhase::Ready,
+        }
+    }
+}
+
+/// Readiness report for `/readyz` and 503 responses during warm-up.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessStatus {
+    /// "ready" or "warming".
+    pub status: &'static str,
+    pub phase: StartupPhase,
+    pub contexts_indexed: u64,
+    pub contexts_total: u64,
+    /// Time since startup began.
+    pub elapsed_ms: u64,
+}
+
+/// Startup phase shared between the startup sequence and request handlers.
+#[derive(Debug)]
+pub struct Readiness {
+    phase: AtomicU8,
+    contexts_indexed: AtomicU64,
+    contexts_total: AtomicU64,
+    start: Instant,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Readiness {
+    /// Readiness of a server that is starting up.
+    pub fn new() -> Self {
+        Self {
+            phase: AtomicU8::new(StartupPhase::OpeningStore as u8),
+            contexts_indexed: AtomicU64::new(0),
+            contexts_total: AtomicU64::new(0),
+            start: Instant::now(),
+        }
+    }
+
+    pub fn phase(&self) -> StartupPhase {
+        StartupPhase::from_u8(self.phase.load(Ordering::Acquire))
+    }
+
+    pub fn set_phase(&self, phase: StartupPhase) {
+        self.phase.store(phase as u8, Ordering::Release);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.phase() == StartupPhase::Ready
+    }
+
+    pub fn set_progress(&self, indexed: usize, total: usize) {
+        self.contexts_indexed
+            .store(indexed as u64, Ordering::Relaxed);
+        self.contexts_total.store(total as u64, Ordering::Relaxed);
+    }
+
+    pub fn status(&self) -> ReadinessStatus {
+        ReadinessStatus {
+            status: if self.is_ready() { "ready" } else { "warming" },
+            phase: self.phase(),
+            contexts_indexed: self.contexts_indexed.load(Ordering::Relaxed),
+            contexts_total: self.contexts_total.load(Ordering::Relaxed),
+            elapsed_ms: self.start.elapsed().as_millis() as u64,
+        }
+    }
+
+    /// Seconds a client should wait before retrying: the estimated time
+    /// left in the index build, between 1 and 60.
+    pub fn retry_after_secs(&self) -> u64 {
+        let status = self.status();
+        if status.contexts_indexed == 0 || status.phase != StartupPhase::BuildingIndexes {
+            return 5;
+        }
+        let remaining = status
+            .contexts_total
+            .saturating_sub(status.contexts_indexed);
+        let estimate_ms = status.elapsed_ms.saturating_mul(remaining) / status.contexts_indexed;
+        estimate_ms.div_ceil(1000).clamp(1, 60)
+    }
+}
+
+/// Build the store's secondary indexes in batches of [`INDEX_BATCH`],
+/// releasing the store lock between batches and logging progress, then
+/// mark the server ready.
+pub fn warm_up(store: &Mutex<Store>, readiness: &Readiness) {
+    readiness.set_phase(StartupPhase::BuildingIndexes);
+    let start = Instant::now();
+    let mut build = store.lock().unwrap().begin_index_build();
+    let total = build.total();
+    readiness.set_progress(0, total);
+    eprintln!("index build: {total} contexts");
+
+    let mut last_log = Instant::now();
+    loop {
+        let done = store
+            .lock()
+            .unwrap()
+            .continue_index_build(&mut build, INDEX_BATCH);
+        readiness.set_progress(build.indexed(), total);
+        if done {
+            break;
+        }
+        if last_log.elapsed() >= PROGRESS_INTERVAL {
+            eprintln!(
+                "index build: {}/{total} contexts ({}%)",
+                build.indexed(),
+                build.indexed() * 100 / total.max(1)
+            );
+            last_log = Instant::now();
+        }
+    }
+
+    eprintln!(
+        "index build: {total} contexts indexed in {} ms",
+        start.elapsed().as_millis()
+    );
+    readiness.set_phase(StartupPhase::Ready);
+}
@@ -1,17 +1,64 @@
 // Copyright 2025 StrongDM Inc
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use blake3::Hasher;
 use rmpv::Value;
 
-use crate::blob_store::BlobStore;
-use crate::cql::{self, CqlError, CqlQuery, IndexStats, SecondaryIndexes};
+use crate::attachments::{self, Attachment, Attachments};
+use crate::blob_store::{BlobSink, BlobSource, BlobStore, DedupStats, RefCounts, SweepStats};
+use crate::bookmarks::{Bookmark, Bookmarks};
+use crate::cql::{
+    self, CqlError, CqlQuery, FieldName, FsStats, IndexStats, SecondaryIndexes, SpilledText,
+};
+use crate::deadline::Deadline;
+use crate::depth_limits::{DepthLimits, DepthWarning};
 use crate::error::{Result, StoreError};
-use crate::fs_store::{FsRootsIndex, TreeEntry};
-use crate::turn_store::{ContextHead, TurnMeta, TurnRecord, TurnStore};
+use crate::events::StoreEvent;
+use crate::expiry::{ContextExpiry, Expiries};
+use crate::export::ExportRow;
+use crate::external_ids::ExternalIds;
+use crate::format::{self, DataDirLock};
+use crate::fs_store::checkout::{manifest_entries, Manifest};
+use crate::fs_store::{
+    apply_overlay, load_tree_entries, snapshot_bytes, EntryKind, FsReachability, FsRootsIndex,
+    OverlayChange, OverlayResult, PrunedRoot, PrunedRootsLog, SnapshotMeta, SnapshotMetaLog,
+    TreeEntry,
+};
+use crate::holds::{HoldAction, HoldEntry, Holds};
+use crate::hooks::{SummaryResponse, SUMMARY_TYPE_ID, SUMMARY_TYPE_VERSION};
+use crate::index_plugins::{CustomIndexLog, IndexPluginStats, IndexPlugins};
+use crate::ingest::{IngestAudit, IngestLog, IngestRecord};
+use crate::invariants::{Invariant, InvariantReport, VerifyScope};
+use crate::keys::{DataKey, EncryptionConfig, KeyInfo, KeyRing, SealedBlobs};
+use crate::metadata_cache::{MetadataCache, MetadataCacheConfig};
+use crate::metadata_overrides::{MetadataOverrides, MetadataPatch, TITLE_SOURCE_DERIVED};
+use crate::metrics::DataAgeStats;
+use crate::ownership::{BulkTransfer, OwnershipLog, OwnershipTransfer};
+use crate::payload_cache::{PayloadCache, PayloadCacheConfig, PayloadCacheStats, PrefetchRequest};
+use crate::pii::{PiiConfig, PiiDetector, PiiScanner, PiiStats};
+use crate::previews::{PreviewConfig, PreviewExtractor, PreviewLog};
+use crate::projects::{Project, Projects};
+use crate::read_marks::{ReadMark, ReadMarks};
+use crate::registry::Registry;
+use crate::rollovers::{encode_rollover_payload, Rollover, Rollovers};
+use crate::system_events::{
+    encode_system_event_payload, SystemEvent, SystemEventKind, SystemEvents, SYSTEM_EVENT_TYPE_ID,
+    SYSTEM_EVENT_TYPE_VERSION,
+};
+use crate::tag_changes::{TagChange, TagChangeLog, TagOperation};
+use crate::title::{TitleAttempt, TitleCandidate, TitleConfig, TitleDeriver};
+use crate::tokens::{ContextTokens, TagTokens, TokenCounter, TokenLedger, TokenStats, TurnTokens};
+use crate::turn_store::{
+    ChainLink, ChainVerification, CommitConfig, CommitPipeline, CommitStats, ContextHead, HeadAsOf,
+    HeadMove, IdGenerator, TurnAuthor, TurnMeta, TurnProof, TurnRecord, TurnStore,
+};
+use crate::util::unix_ms;
 
 #[derive(Debug, Clone)]
 pub struct TurnWithMeta {
@@ -22,7 +69,7 @@ pub struct TurnWithMeta {
 
 /// Provenance captures the origin story of a context.
 /// Extracted from the first turn's payload.
-#[derive(Debug, Clone, Default, serde::Serialize)]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 pub struct Provenance {
     // Context Lineage
     pub parent_context_id: Option<u64>,
@@ -56,6 +103,9 @@ pub struct Provenance {
     // Network Identity (server-injected)
     pub client_address: Option<String>,
     pub client_port: Option<i64>,
+    /// Peer uid and gid of a Unix socket connection.
+    pub client_uid: Option<i64>,
+    pub client_gid: Option<i64>,
 
     // Environment
     pub env: Option<std::collections::HashMap<String, String>>,
@@ -68,6 +118,45 @@ pub struct Provenance {
     pub captured_at: Option<i64>,
 }
 
+impl Provenance {
+    /// Heap bytes owned by the string fields and the env map.
+    fn heap_bytes(&self) -> usize {
+        let strings = [
+            &self.spawn_reason,
+            &self.trace_id,
+            &self.span_id,
+            &self.correlation_id,
+            &self.on_behalf_of,
+            &self.on_behalf_of_source,
+            &self.on_behalf_of_email,
+            &self.writer_method,
+            &self.writer_subject,
+            &self.writer_issuer,
+            &self.service_name,
+            &self.service_version,
+            &self.service_instance_id,
+            &self.process_owner,
+            &self.host_name,
+            &self.host_arch,
+            &self.client_address,
+            &self.sdk_name,
+            &self.sdk_version,
+        ];
+        let env = self.env.as_ref().map_or(0, |env| {
+            env.capacity() * (2 * std::mem::size_of::<String>() + 1)
+                + env
+                    .iter()
+                    .map(|(k, v)| k.capacity() + v.capacity())
+                    .sum::<usize>()
+        });
+        strings.into_iter().map(string_bytes).sum::<usize>() + env
+    }
+}
+
+fn string_bytes(s: &Option<String>) -> usize {
+    s.as_ref().map_or(0, String::capacity)
+}
+
 /// Cached context metadata extracted from the first turn of a context.
 #[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct ContextMetadata {
@@ -77,6 +166,47 @@ pub struct ContextMetadata {
     pub provenance: Option<Provenance>,
 }
 
+impl ContextMetadata {
+    /// Bytes held by this metadata: its inline size plus owned heap data.
+    pub fn memory_bytes(&self) -> usize {
+        let labels = self.labels.as_ref().map_or(0, |labels| {
+            labels.capacity() * std::mem::size_of::<String>()
+                + labels.iter().map(String::capacity).sum::<usize>()
+        });
+        std::mem::size_of::<Self>()
+            + string_bytes(&self.client_tag)
+            + string_bytes(&self.title)
+            + labels
+            + self.provenance.as_ref().map_or(0, Provenance::heap_bytes)
+    }
+
+    /// Labels of the form `namespace:value`, split into their parts.
+    pub fn namespaced_labels(&self) -> Vec<(&str, &str)> {
+        self.labels
+            .iter()
+            .flatten()
+            .filter_map(|label| parse_namespaced_label(label))
+            .collect()
+    }
+}
+
+/// Split a `namespace:value` label. The namespace must be a non-empty run of
+/// ASCII alphanumerics or `_` (so it can be addressed as `label.<ns>` in CQL);
+/// the value is everything after the first `:` and may itself contain `:` for
+/// deeper hierarchies (`team:payments:billing`).
+pub fn parse_namespaced_label(label: &str) -> Option<(&str, &str)> {
+    let (namespace, value) = label.split_once(':')?;
+    if namespace.is_empty()
+        || value.is_empty()
+        || !namespace
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return None;
+    }
+    Some((namespace, value))
+}
+
 /// Result of a CQL search query.
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct SearchResult {
@@ -86,53 +216,606 @@ pub struct SearchResult {
     pub elapsed_ms: u64,
 }
 
+/// Two contexts aligned at the deepest turn on both head chains.
+#[derive(Debug, Clone)]
+pub struct ContextComparison {
+    /// None when the contexts share no turns.
+    pub common_ancestor: Option<TurnRecord>,
+    pub a: BranchComparison,
+    pub b: BranchComparison,
+}
+
+/// One side of a [`ContextComparison`].
+#[derive(Debug, Clone)]
+pub struct BranchComparison {
+    pub head: ContextHead,
+    /// Turns after the common ancestor, oldest first, up to the requested limit.
+    pub turns: Vec<TurnWithMeta>,
+    pub stats: BranchStats,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct BranchStats {
+    /// Turns on the head chain, shared ones included.
+    pub total_turns: u64,
+    /// Turns after the common ancestor.
+    pub divergent_turns: u64,
+    /// Uncompressed payload bytes of the divergent turns.
+    pub divergent_bytes: u64,
+    /// Counted tokens of the divergent turns.
+    pub divergent_tokens: u64,
+    /// Time from the common ancestor (or the branch's first turn) to its
+    /// last turn.
+    pub duration_ms: u64,
+}
+
+/// Aggregates over a project's contexts, from [`Store::project_stats`].
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ProjectStats {
+    pub contexts: usize,
+    /// Turns on the contexts' head chains. Turns a fork shares with its
+    /// parent count once per context.
+    pub turns: u64,
+    /// Counted tokens along the contexts' head chains.
+    pub tokens: u64,
+    /// Contexts on legal hold.
+    pub on_hold: usize,
+    /// Contexts per client tag; untagged contexts are not counted.
+    pub client_tags: BTreeMap<String, usize>,
+    pub first_created_at_unix_ms: Option<u64>,
+    pub last_created_at_unix_ms: Option<u64>,
+}
+
+/// Progress of a secondary index build started by
+/// [`Store::begin_index_build`].
+#[derive(Debug)]
+pub struct IndexBuild {
+    heads: Vec<ContextHead>,
+    next: usize,
+    start: std::time::Instant,
+}
+
+impl IndexBuild {
+    /// Contexts indexed so far.
+    pub fn indexed(&self) -> usize {
+        self.next
+    }
+
+    /// Contexts in the store when the build started.
+    pub fn total(&self) -> usize {
+        self.heads.len()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next >= self.heads.len()
+    }
+}
+
+/// A filesystem snapshot as seen from a turn.
+#[derive(Debug, Clone)]
+pub struct FsSnapshot {
+    pub root_hash: [u8; 32],
+    /// Turn the snapshot is attached to; an ancestor when inherited.
+    pub attached_turn_id: u64,
+    pub meta: Option<SnapshotMeta>,
+}
+
+/// Pack copy rate assumed by compaction plans before any collection ran.
+pub const DEFAULT_COLLECTION_RATE: f64 = 100.0 * 1024.0 * 1024.0;
+
+/// Result of [`Store::compaction_plan`].
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CompactionPlan {
+    /// Whether a collection would run; false when a blocker refuses it.
+    pub runnable: bool,
+    pub reclaim_blobs: u64,
+    pub reclaim_bytes: u64,
+    pub files: Vec<CompactionFile>,
+    pub contexts_to_expire: Vec<ExpiringContext>,
+    pub estimated_duration_ms: u64,
+    /// "last_collection" or "default", see [`Store::compaction_plan`].
+    pub estimate_basis: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_collection_unix_ms: Option<u64>,
+    /// Snapshot blobs reachable from current and from orphan fs roots.
+    pub fs: FsReachability,
+    pub blockers: Vec<CompactionBlocker>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CompactionFile {
+    /// Path relative to the data directory.
+    pub file: &'static str,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    pub reclaim_bytes: u64,
+}
+
+/// A context whose sealed blobs the collection drops.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ExpiringContext {
+    pub context_id: String,
+    pub key_id: String,
+    pub shredded_at_unix_ms: u64,
+}
+
+/// Something that keeps data from being reclaimed, or the collection from
+/// running at all.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CompactionBlocker {
+    pub kind: &'static str,
+    pub detail: String,
+    pub blobs: u64,
+    pub bytes: u64,
+}
+
+/// Result of [`Store::fsck`].
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FsckReport {
+    pub ok: bool,
+    /// Turn chain hashes recomputed and compared with the stored ones.
+    pub chains: ChainVerification,
+    /// Cross-file invariants; chain hashes are reported under `chains`.
+    pub invariants: InvariantReport,
+}
+
 pub struct Store {
     pub blob_store: BlobStore,
     pub turn_store: TurnStore,
     pub fs_roots: FsRootsIndex,
-    /// Cache of context metadata, populated lazily from first turn.
-    /// None value means we checked but found no metadata.
-    pub context_metadata_cache: HashMap<u64, Option<ContextMetadata>>,
+    /// Working-directory metadata of attached snapshots.
+    fs_meta: SnapshotMetaLog,
+    /// Orphan roots dropped from the roots index by compactions.
+    fs_pruned: PrunedRootsLog,
+    /// File bytes per snapshot root, computed on first use.
+    fs_root_bytes: HashMap<[u8; 32], u64>,
+    /// Cache of context metadata, populated lazily from first turn and
+    /// bounded by its memory budget.
+    context_metadata_cache: MetadataCache,
+    /// Decrypted payloads of recently paged turns, filled ahead of paging
+    /// requests by the prefetcher.
+    payload_cache: PayloadCache,
+    /// Group commit pipeline, when appends are synced before they are
+    /// acknowledged.
+    commit: Option<Arc<CommitPipeline>>,
     /// Secondary indexes for CQL queries.
     secondary_indexes: SecondaryIndexes,
+    /// Whether the secondary indexes cover every context.
+    indexed: bool,
+    /// Persisted metadata layered over first-turn metadata.
+    metadata_overrides: MetadataOverrides,
+    /// Last viewed turn per principal and context.
+    read_marks: ReadMarks,
+    /// Legal holds and their audit trail.
+    holds: Holds,
+    /// Named jump points on context timelines.
+    bookmarks: Bookmarks,
+    /// Original hashes of payloads rewritten by ingest transformers.
+    ingest_log: IngestLog,
+    /// Index plugin entries per turn.
+    custom_index: CustomIndexLog,
+    /// Summary field previews per turn.
+    previews: PreviewLog,
+    /// Ownership transfers, the audit trail of context owners.
+    ownership: OwnershipLog,
+    /// Client tag renames and merges, the audit trail of tag administration.
+    tag_changes: TagChangeLog,
+    /// Projects and the contexts assigned to them.
+    projects: Projects,
+    /// Caller-assigned context ids.
+    external_ids: ExternalIds,
+    /// Client-set expiry times and the tombstones of expired contexts.
+    expiries: Expiries,
+    /// Contexts rolled over into new contexts.
+    rollovers: Rollovers,
+    /// Title auto-derivation, when enabled.
+    title_deriver: Option<TitleDeriver>,
+    /// Turns whose title derivation waited for the registry.
+    title_candidates: Vec<TitleCandidate>,
+    /// Token counting of annotated fields, when enabled.
+    token_counter: Option<TokenCounter>,
+    /// System event turns for milestones, when enabled.
+    system_events: Option<SystemEvents>,
+    /// PII labeling of appended turns, when enabled.
+    pii_scanner: Option<PiiScanner>,
+    /// Custom index plugins run on appended turns, when configured.
+    index_plugins: Option<IndexPlugins>,
+    /// Summary field extraction, once enabled with the registry.
+    preview_extractor: Option<PreviewExtractor>,
+    /// Maximum chain depths, when configured.
+    depth_limits: Option<DepthLimits>,
+    /// Token counts per turn and context.
+    token_ledger: TokenLedger,
+    /// Data-encryption keys for sealed contexts.
+    keys: KeyRing,
+    /// Turn references per stored payload blob.
+    payload_refs: RefCounts,
+    /// References to fs blobs: one per turn attachment for each root, and
+    /// one per containing tree for everything below it.
+    fs_refs: RefCounts,
+    /// Named blobs attached to turns.
+    attachments: Attachments,
+    /// References to attached blobs: one per attachment.
+    attachment_refs: RefCounts,
+    /// Pack bytes per second copied by the last blob collection.
+    collection_rate: Option<f64>,
+    /// Steps that failed after a turn was already durable (see
+    /// [`Store::after_append`]).
+    append_side_effect_failures: u64,
+    /// Held while the store is open, so no other process opens the
+    /// directory.
+    _dir_lock: DataDirLock,
 }
 
 impl Store {
     pub fn open(dir: &Path) -> Result<Self> {
+        Self::open_with_metadata_cache(dir, MetadataCacheConfig::default())
+    }
+
+    /// Open a store whose metadata cache is bounded per `cache_config`.
+    pub fn open_with_metadata_cache(dir: &Path, cache_config: MetadataCacheConfig) -> Result<Self> {
+        let mut store = Self::open_unindexed(dir, cache_config)?;
+        store.build_indexes();
+        Ok(store)
+    }
+
+    /// Open a store without building its secondary indexes, so a server can
+    /// build them in batches (see [`Store::begin_index_build`]) while it
+    /// reports that it is warming up. CQL queries see no contexts until then.
+    pub fn open_unindexed(dir: &Path, cache_config: MetadataCacheConfig) -> Result<Self> {
+        Self::open_unindexed_locked(dir, cache_config, DataDirLock::acquire(dir)?)
+    }
+
+    /// [`Store::open_unindexed`] for a caller that already holds the lock
+    /// on `dir`, e.g. to restore or repair files before the store opens.
+    pub fn open_unindexed_locked(
+        dir: &Path,
+        cache_config: MetadataCacheConfig,
+        dir_lock: DataDirLock,
+    ) -> Result<Self> {
+        format::prepare(dir)?;
         let mut store = Self {
             blob_store: BlobStore::open(&dir.join("blobs"))?,
             turn_store: TurnStore::open(&dir.join("turns"))?,
             fs_roots: FsRootsIndex::open(&dir.join("fs"))?,
-            context_metadata_cache: HashMap::new(),
-            secondary_indexes: SecondaryIndexes::new(),
+            fs_meta: SnapshotMetaLog::open(&dir.join("fs"))?,
+            fs_pruned: PrunedRootsLog::open(&dir.join("fs"))?,
+            fs_root_bytes: HashMap::new(),
+            context_metadata_cache: MetadataCache::new(cache_config),
+            payload_cache: PayloadCache::default(),
+            commit: None,
+            secondary_indexes: SecondaryIndexes::with_text_budget(cache_config.index_text_bytes),
+            indexed: false,
+            metadata_overrides: MetadataOverrides::open(&dir.join("meta"))?,
+            read_marks: ReadMarks::open(&dir.join("meta"))?,
+            holds: Holds::open(&dir.join("meta"))?,
+            bookmarks: Bookmarks::open(&dir.join("meta"))?,
+            ingest_log: IngestLog::open(&dir.join("meta"))?,
+            custom_index: CustomIndexLog::open(&dir.join("meta"))?,
+            previews: PreviewLog::open(&dir.join("meta"))?,
+            ownership: OwnershipLog::open(&dir.join("meta"))?,
+            tag_changes: TagChangeLog::open(&dir.join("meta"))?,
+            projects: Projects::open(&dir.join("meta"))?,
+            external_ids: ExternalIds::open(&dir.join("meta"))?,
+            expiries: Expiries::open(&dir.join("meta"))?,
+            rollovers: Rollovers::open(&dir.join("meta"))?,
+            title_deriver: None,
+            title_candidates: Vec::new(),
+            append_side_effect_failures: 0,
+            token_counter: None,
+            system_events: None,
+            pii_scanner: None,
+            index_plugins: None,
+            preview_extractor: None,
+            depth_limits: None,
+            token_ledger: TokenLedger::open(&dir.join("meta"))?,
+            keys: KeyRing::open(&dir.join("keys"))?,
+            payload_refs: RefCounts::default(),
+            fs_refs: RefCounts::default(),
+            attachments: Attachments::open(&dir.join("meta"))?,
+            attachment_refs: RefCounts::default(),
+            collection_rate: None,
+            _dir_lock: dir_lock,
         };
 
-        // Pre-populate metadata cache and build secondary indexes
-        store.build_indexes();
-
+        // Key records are written ahead of their turn; drop any whose turn
+        // never landed.
+        let last_turn_id = store.turn_store.last_turn_id();
+        store.keys.forget_turns_after(last_turn_id)?;
+        store.rebuild_blob_refs();
         Ok(store)
     }
 
     /// Build secondary indexes from existing data.
     fn build_indexes(&mut self) {
-        // Get all context heads
-        let heads = self.turn_store.list_recent_contexts(u32::MAX);
+        let mut build = self.begin_index_build();
+        self.continue_index_build(&mut build, usize::MAX);
+    }
 
-        // Pre-populate metadata cache for all contexts
-        for head in &heads {
-            let _ = self.get_context_metadata(head.context_id);
+    /// Start rebuilding the secondary indexes from scratch. Queries see only
+    /// the contexts indexed so far until the build finishes.
+    pub fn begin_index_build(&mut self) -> IndexBuild {
+        self.secondary_indexes =
+            SecondaryIndexes::with_text_budget(self.secondary_indexes.text_budget_bytes());
+        self.indexed = false;
+        IndexBuild {
+            heads: self.turn_store.list_recent_contexts(u32::MAX),
+            next: 0,
+            start: std::time::Instant::now(),
         }
+    }
 
-        // Build secondary indexes from the cache
-        self.secondary_indexes
-            .build_from_cache(&self.context_metadata_cache, &heads);
+    /// Index up to `max` more contexts of `build`, loading their metadata
+    /// through the cache. Returns true once every context is indexed.
+    pub fn continue_index_build(&mut self, build: &mut IndexBuild, max: usize) -> bool {
+        let end = build.next.saturating_add(max).min(build.heads.len());
+        for head in &build.heads[build.next..end] {
+            let metadata = self.get_context_metadata(head.context_id);
+            self.secondary_indexes
+                .build_context(head, metadata.as_ref());
+            let fs = self.chain_fs_stats(head.head_turn_id);
+            self.secondary_indexes.update_fs(head.context_id, fs);
+            self.index_chain_authors(head.context_id, head.head_turn_id);
+            let tokens = self.token_ledger.context(head.context_id).total;
+            self.secondary_indexes
+                .update_tokens(head.context_id, tokens, tokens);
+            let held = self.holds.get(head.context_id).is_some();
+            self.secondary_indexes.set_on_hold(head.context_id, held);
+            for project in self.projects.of_context(head.context_id) {
+                self.secondary_indexes
+                    .set_project(head.context_id, &project, true);
+            }
+            if let Some(external_id) = self.external_ids.of_context(head.context_id) {
+                self.secondary_indexes
+                    .set_external_id(head.context_id, external_id);
+            }
+            if let Some(expiry) = self.expiries.get(head.context_id) {
+                self.secondary_indexes
+                    .set_expires(head.context_id, expiry.expires_at_unix_ms);
+            }
+        }
+        build.next = end;
+        if build.is_done() && !self.indexed {
+            self.secondary_indexes.finish_build();
+            self.indexed = true;
+            tracing::info!(
+                contexts = build.heads.len(),
+                elapsed_ms = build.start.elapsed().as_millis(),
+                "Built secondary indexes"
+            );
+        }
+        build.is_done()
+    }
+
+    /// Allocate ids of new contexts and turns with `ids`; see
+    /// [`crate::turn_store::IdGenerator`].
+    pub fn set_id_generator(&mut self, ids: Box<dyn IdGenerator>) {
+        self.turn_store.set_id_generator(ids);
+    }
+
+    /// Enable title auto-derivation for untitled contexts.
+    pub fn enable_title_derivation(&mut self, config: TitleConfig, registry: Arc<Mutex<Registry>>) {
+        self.title_deriver = Some(TitleDeriver::new(config, registry));
+    }
+
+    /// The title deriver, for deriving outside the store lock.
+    pub fn title_deriver(&self) -> Option<TitleDeriver> {
+        self.title_deriver.clone()
+    }
+
+    /// Take the turns whose title derivation was deferred; see
+    /// [`crate::title::derive_pending_titles`].
+    pub fn take_title_candidates(&mut self) -> Vec<TitleCandidate> {
+        std::mem::take(&mut self.title_candidates)
+    }
+
+    /// Count tokens of annotated fields in appended turns.
+    pub fn enable_token_counting(&mut self, counter: TokenCounter) {
+        self.token_counter = Some(counter);
+    }
+
+    /// Append system event turns for the milestones `events` records.
+    pub fn enable_system_events(&mut self, events: SystemEvents) {
+        self.system_events = Some(events);
+    }
+
+    /// Label contexts whose appended turns contain PII.
+    pub fn enable_pii_scanning(&mut self, config: PiiConfig) {
+        self.pii_scanner = Some(PiiScanner::new(config));
+    }
+
+    /// Run `plugins` on appended turns and index their entries as
+    /// `custom.<key>` fields.
+    pub fn enable_index_plugins(&mut self, plugins: IndexPlugins) {
+        self.index_plugins = Some(plugins);
+    }
+
+    /// Record previews of appended turns whose types declare a
+    /// `summary_field`.
+    pub fn enable_previews(&mut self, config: PreviewConfig, registry: Arc<Mutex<Registry>>) {
+        self.preview_extractor = Some(PreviewExtractor::new(config, registry));
+    }
+
+    /// Refuse appends beyond the depth limits of their contexts.
+    pub fn enable_depth_limits(&mut self, limits: DepthLimits) {
+        self.depth_limits = Some(limits);
+    }
+
+    /// Turns allowed in the chain of a context; None when unlimited.
+    pub fn context_depth_limit(&mut self, context_id: u64) -> Option<u32> {
+        self.depth_limits.as_ref()?;
+        let tag = self
+            .get_context_metadata(context_id)
+            .and_then(|m| m.client_tag);
+        self.depth_limits.as_ref()?.limit(tag.as_deref())
+    }
+
+    /// The depth threshold a turn appended at `depth` crossed, if any.
+    pub fn depth_warning(&mut self, context_id: u64, depth: u32) -> Option<DepthWarning> {
+        self.depth_limits.as_ref()?;
+        let tag = self
+            .get_context_metadata(context_id)
+            .and_then(|m| m.client_tag);
+        self.depth_limits
+            .as_ref()?
+            .warning(context_id, tag.as_deref(), depth)
+    }
+
+    /// Per-plugin accounting; None when no plugins are configured.
+    pub fn index_plugin_stats(&self) -> Option<Vec<IndexPluginStats>> {
+        self.index_plugins.as_ref().map(IndexPlugins::stats)
+    }
+
+    /// PII scanner accounting; None when scanning is disabled.
+    pub fn pii_stats(&self) -> Option<PiiStats> {
+        self.pii_scanner.as_ref().map(PiiScanner::stats)
+    }
+
+    /// Unlock the key ring and start sealing new contexts per `config.mode`.
+    /// Indexes are rebuilt so metadata of sealed first turns becomes visible.
+    pub fn enable_encryption(&mut self, config: &EncryptionConfig) -> Result<()> {
+        self.keys.unlock(config)?;
+        if self.keys.has_sealed_data() {
+            self.context_metadata_cache.clear();
+            if self.indexed {
+                self.build_indexes();
+            }
+            self.rebuild_blob_refs();
+        }
+        Ok(())
+    }
+
+    /// Recount blob references from the turn log, the fs roots index and
+    /// the attachments. Sealed data counts against its sealed blob; data
+    /// whose key was shredded is not counted. Returns false when some sealed
+    /// data could not be counted because its key is unavailable (e.g. the key
+    /// ring is locked).
+    fn rebuild_blob_refs(&mut self) -> bool {
+        let (payload_refs, fs_refs, attachment_refs, complete) = self.count_blob_refs();
+        self.payload_refs = payload_refs;
+        self.fs_refs = fs_refs;
+        self.attachment_refs = attachment_refs;
+        complete
+    }
+
+    /// Blob references counted afresh as (payload refs, fs refs, attachment
+    /// refs, complete), leaving the store's own counts untouched.
+    fn count_blob_refs(&mut self) -> (RefCounts, RefCounts, RefCounts, bool) {
+        let mut complete = true;
+        let mut refs = RefCounts::default();
+        for record in self.turn_store.turns() {
+            let storage_hash = match self.keys.turn_key(record.turn_id) {
+                None => record.payload_hash,
+                Some(key_id) => match self.keys.data_key(key_id) {
+                    Ok(key) => key.storage_hash(&record.payload_hash),
+                    Err(err) => {
+                        complete &= matches!(err, StoreError::Shredded(_));
+                        continue;
+                    }
+                },
+            };
+            let raw_len = self
+                .turn_store
+                .get_turn_meta(record.turn_id)
+                .map(|m| m.uncompressed_len as u64)
+                .unwrap_or(0);
+            refs.retain(storage_hash, raw_len);
+        }
+
+        let mut fs_refs = RefCounts::default();
+        let held = self.held_turns();
+        let mut roots: Vec<(u64, [u8; 32])> = self.fs_roots.roots().collect();
+        roots.extend(
+            self.fs_roots
+                .superseded()
+                .into_iter()
+                .filter(|(turn_id, _)| held.contains(turn_id)),
+        );
+        for (turn_id, root) in roots {
+            let retained = retain_fs_tree(
+                &mut self.keys,
+                &mut self.blob_store,
+                &mut fs_refs,
+                turn_id,
+                root,
+            );
+            if let Err(err) = retained {
+                complete &= matches!(err, StoreError::Shredded(_));
+            }
+        }
+
+        let mut attachment_refs = RefCounts::default();
+        let attached: Vec<(u64, [u8; 32])> = self
+            .attachments
+            .all()
+            .map(|(turn_id, attachment)| (turn_id, attachment.hash))
+            .collect();
+        for (turn_id, hash) in attached {
+            match self.attachment_storage_hash(turn_id, &hash) {
+                Ok((storage_hash, len)) => {
+                    attachment_refs.retain(storage_hash, len);
+                }
+                Err(err) => complete &= matches!(err, StoreError::Shredded(_)),
+            }
+        }
+        (refs, fs_refs, attachment_refs, complete)
+    }
+
+    /// Hash an attached blob is stored under, and its stored length.
+    fn attachment_storage_hash(
+        &mut self,
+        turn_id: u64,
+        hash: &[u8; 32],
+    ) -> Result<([u8; 32], u64)> {
+        let blobs = self.turn_blobs(turn_id)?;
+        let storage_hash = blobs.storage_hash(hash);
+        let len = blobs.blobs.raw_len(&storage_hash).unwrap_or(0) as u64;
+        Ok((storage_hash, len))
+    }
+
+    /// Reference an fs root for a turn attachment. The first reference to a
+    /// tree also references its entries, so a subtree shared by many
+    /// snapshots holds one reference per distinct parent tree. Trees that
+    /// cannot be read contribute only themselves.
+    fn retain_fs_root(&mut self, turn_id: u64, root: [u8; 32]) -> Result<()> {
+        retain_fs_tree(
+            &mut self.keys,
+            &mut self.blob_store,
+            &mut self.fs_refs,
+            turn_id,
+            root,
+        )
+    }
+
+    /// Drop a turn attachment's reference to an fs root; entries of trees
+    /// that lose their last reference are released in turn.
+    fn release_fs_root(&mut self, turn_id: u64, root: [u8; 32]) -> Result<()> {
+        let key = match self.keys.turn_key(turn_id) {
+            Some(key_id) => Some(self.keys.data_key(key_id)?),
+            None => None,
+        };
+        let mut blobs = SealedBlobs {
+            blobs: &mut self.blob_store,
+            key,
+        };
+        let mut pending = vec![(root, true)];
+        while let Some((hash, is_dir)) = pending.pop() {
+            let storage_hash = blobs.storage_hash(&hash);
+            if self.fs_refs.count(&storage_hash) > 0
+                && self.fs_refs.release(&storage_hash) == 0
+                && is_dir
+            {
+                pending.extend(tree_children(&mut blobs, &hash));
+            }
+        }
+        Ok(())
     }
 
     /// Get cached context metadata, loading from first turn if not cached.
     pub fn get_context_metadata(&mut self, context_id: u64) -> Option<ContextMetadata> {
         // Check cache first
-        if let Some(cached) = self.context_metadata_cache.get(&context_id) {
-            return cached.clone();
+        if let Some(cached) = self.context_metadata_cache.get(context_id) {
+            return cached;
         }
 
         // Try to load from first turn (depth=0)
@@ -142,12 +825,19 @@ impl Store {
         metadata
     }
 
-    /// Load context metadata from the first turn of a context.
+    /// Load context metadata from the first turn of a context, with overrides applied.
     fn load_context_metadata(&mut self, context_id: u64) -> Option<ContextMetadata> {
         // Get the first turn (depth=0) for this context
-        let first_turn = self.turn_store.get_first_turn(context_id).ok()?;
-        let payload = self.blob_store.get(&first_turn.payload_hash).ok()?;
-        extract_context_metadata(&payload)
+        let extracted = self
+            .turn_store
+            .get_first_turn(context_id)
+            .ok()
+            .and_then(|first_turn| self.read_payload(&first_turn).ok())
+            .and_then(|payload| extract_context_metadata(&payload));
+        match self.metadata_overrides.get(context_id) {
+            Some(patch) => patch.apply(extracted),
+            None => extracted,
+        }
     }
 
     /// Update the metadata cache when a new first turn is appended.
@@ -169,118 +859,1311 @@ impl Store {
     }
 
     pub fn create_context(&mut self, base_turn_id: u64) -> Result<ContextHead> {
-        self.turn_store.create_context(base_turn_id)
+        self.create_context_with_external_id(base_turn_id, None)
+    }
+
+    /// Create a context, optionally naming it with an external id that no
+    /// other context has. The id is checked before the context is created.
+    pub fn create_context_with_external_id(
+        &mut self,
+        base_turn_id: u64,
+        external_id: Option<&str>,
+    ) -> Result<ContextHead> {
+        self.create_context_with_ttl(base_turn_id, external_id, None)
+    }
+
+    /// Create a context that expires `ttl` after its creation, when given
+    /// (see [`crate::expiry`]), optionally naming it with an external id.
+    pub fn create_context_with_ttl(
+        &mut self,
+        base_turn_id: u64,
+        external_id: Option<&str>,
+        ttl: Option<Duration>,
+    ) -> Result<ContextHead> {
+        if let Some(external_id) = external_id {
+            self.external_ids.check_available(external_id)?;
+        }
+        let head = self.turn_store.create_context(base_turn_id)?;
+        if let Some(external_id) = external_id {
+            self.external_ids.assign(head.context_id, external_id)?;
+            self.secondary_indexes
+                .set_external_id(head.context_id, external_id);
+        }
+        if let Some(ttl) = ttl {
+            let expires_at = head
+                .created_at_unix_ms
+                .saturating_add(ttl.as_millis() as u64);
+            self.expiries.set(head.context_id, expires_at)?;
+            self.secondary_indexes
+                .set_expires(head.context_id, expires_at);
+        }
+        self.index_inherited_chain(&head);
+        self.record_inherited_tokens(&head)?;
+        if base_turn_id == 0 {
+            return Ok(head);
+        }
+        self.record_fork(head)
+    }
+
+    /// Context named by an external id.
+    pub fn context_by_external_id(&self, external_id: &str) -> Option<u64> {
+        self.external_ids.context(external_id)
+    }
+
+    /// External id a context was created with.
+    pub fn external_id(&self, context_id: u64) -> Option<&str> {
+        self.external_ids.of_context(context_id)
     }
 
     pub fn fork_context(&mut self, base_turn_id: u64) -> Result<ContextHead> {
-        self.turn_store.fork_context(base_turn_id)
+        let head = self.turn_store.fork_context(base_turn_id)?;
+        self.index_inherited_chain(&head);
+        self.record_inherited_tokens(&head)?;
+        self.record_fork(head)
     }
 
-    pub fn get_head(&self, context_id: u64) -> Result<ContextHead> {
-        self.turn_store.get_head(context_id)
+    /// Record a fork in the new context's timeline. Returns its head, which
+    /// is the system event turn when one was appended.
+    fn record_fork(&mut self, head: ContextHead) -> Result<ContextHead> {
+        let event = SystemEvent::new(
+            SystemEventKind::ContextForked,
+            format!("Forked from turn {}", head.head_turn_id),
+        )
+        .with_turn(head.head_turn_id);
+        if self.record_system_event(head.context_id, event).is_none() {
+            return Ok(head);
+        }
+        self.turn_store.get_head(head.context_id)
     }
 
-    /// Append a turn to a context.
-    ///
-    /// Returns the turn record and, if this is the first turn (depth=0), the extracted metadata.
-    #[allow(clippy::too_many_arguments)]
-    pub fn append_turn(
+    /// Copy a context's chain, up to `up_to_turn_id` or its head, into a new
+    /// context that shares no turns with it. Turns get new ids but keep their
+    /// payload hashes, so unencrypted payloads are stored once. Filesystem
+    /// snapshots and attachments of the copied turns are attached to their
+    /// copies, and the new context's provenance names the source as its
+    /// parent with spawn reason `clone`.
+    pub fn clone_context(
         &mut self,
         context_id: u64,
-        parent_turn_id: u64,
-        declared_type_id: String,
-        declared_type_version: u32,
-        encoding: u32,
-        compression: u32,
-        uncompressed_len: u32,
-        content_hash: [u8; 32],
-        payload_bytes: &[u8],
-    ) -> Result<(TurnRecord, Option<ContextMetadata>)> {
-        let raw_bytes = match compression {
-            0 => payload_bytes.to_vec(),
-            1 => zstd::decode_all(payload_bytes)
-                .map_err(|e| StoreError::InvalidInput(format!("zstd decode failed: {e}")))?,
-            other => {
-                return Err(StoreError::InvalidInput(format!(
-                    "unsupported compression: {other}"
-                )))
-            }
-        };
+        up_to_turn_id: Option<u64>,
+    ) -> Result<ContextClone> {
+        let head = self.turn_store.get_head(context_id)?;
+        self.check_not_expired(context_id)?;
+        let up_to = up_to_turn_id.unwrap_or(head.head_turn_id);
+        if up_to == 0 {
+            return Err(StoreError::InvalidInput(format!(
+                "context {context_id} has no turns to clone"
+            )));
+        }
+        if !self.turn_store.is_ancestor(up_to, head.head_turn_id) {
+            return Err(StoreError::InvalidInput(format!(
+                "turn {up_to} is not in the history of context {context_id}"
+            )));
+        }
+        let source_metadata = self.get_context_metadata(context_id);
+        let tag = source_metadata.as_ref().and_then(|m| m.client_tag.clone());
+        let chain = self.turn_store.get_ancestry(up_to, 0, u32::MAX)?;
+        // Refuse up front rather than leave a partial copy behind.
+        if let (Some(limits), Some(last)) = (&self.depth_limits, chain.last()) {
+            limits.check(context_id, tag.as_deref(), last.depth)?;
+        }
 
-        if raw_bytes.len() as u32 != uncompressed_len {
-            return Err(StoreError::InvalidInput(
-                "uncompressed length mismatch".into(),
-            ));
+        let new_head = self.create_context(0)?;
+        let mut clone = ContextClone {
+            source_context_id: context_id,
+            up_to_turn_id: up_to,
+            head: new_head,
+            turns: 0,
+            fs_snapshots: 0,
+            attachments: 0,
+        };
+        if let Err(err) = self.copy_into_clone(&mut clone, chain, source_metadata) {
+            // Tombstone the partial copy so it is never listed or read.
+            let clone_id = clone.head.context_id;
+            let now = unix_ms();
+            let hidden = self
+                .expiries
+                .set(clone_id, now)
+                .and_then(|_| self.expiries.tombstone(clone_id, now));
+            if let Err(hide_err) = hidden {
+                tracing::warn!(
+                    context_id = clone_id,
+                    error = %hide_err,
+                    "Failed to tombstone partial clone"
+                );
+            }
+            return Err(err);
         }
+        clone.head = self.turn_store.get_head(clone.head.context_id)?;
+        Ok(clone)
+    }
+
+    /// Copy `chain` and the source's metadata into the new context of
+    /// `clone`, counting what was copied.
+    fn copy_into_clone(
+        &mut self,
+        clone: &mut ContextClone,
+        chain: Vec<TurnRecord>,
+        source_metadata: Option<ContextMetadata>,
+    ) -> Result<()> {
+        let context_id = clone.source_context_id;
+        let mut parent_turn_id = 0;
+        for source in chain {
+            let turn = self.get_turn(source.turn_id, true)?;
+            let payload = turn.payload.unwrap_or_default();
+            let (record, _) = self.append_turn_with_author(
+                clone.head.context_id,
+                parent_turn_id,
+                turn.meta.declared_type_id,
+                turn.meta.declared_type_version,
+                turn.meta.encoding,
+                0,
+                payload.len() as u32,
+                source.payload_hash,
+                &payload,
+                turn.meta.author,
+            )?;
+            parent_turn_id = record.turn_id;
+            clone.turns += 1;
 
-        let mut hasher = Hasher::new();
-        hasher.update(&raw_bytes);
-        let hash = hasher.finalize();
-        if hash.as_bytes() != &content_hash {
-            return Err(StoreError::InvalidInput("content hash mismatch".into()));
+            if let Some(root) = self.fs_roots.get(source.turn_id) {
+                self.copy_fs_snapshot(source.turn_id, record.turn_id, root)?;
+                clone.fs_snapshots += 1;
+            }
+            for attachment in self.attachments.of_turn(source.turn_id) {
+                let data = self
+                    .turn_blobs(source.turn_id)?
+                    .get_blob(&attachment.hash)?;
+                self.attach_data(
+                    record.turn_id,
+                    &attachment.name,
+                    &attachment.mime_type,
+                    &data,
+                )?;
+                clone.attachments += 1;
+            }
         }
 
-        self.blob_store.put_if_absent(content_hash, &raw_bytes)?;
+        // Carry over the source's overrides (a renamed tag, say), then point
+        // the lineage at the source.
+        let root_context_id = source_metadata
+            .and_then(|m| m.provenance)
+            .and_then(|p| p.root_context_id)
+            .unwrap_or(context_id);
+        let mut patch = self
+            .metadata_overrides
+            .get(context_id)
+            .cloned()
+            .unwrap_or_default();
+        patch.merge(&MetadataPatch {
+            provenance: Some(Provenance {
+                parent_context_id: Some(context_id),
+                spawn_reason: Some(CLONE_SPAWN_REASON.to_string()),
+                root_context_id: Some(root_context_id),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        self.apply_metadata_patch(clone.head.context_id, &patch, &[])?;
+        Ok(())
+    }
+
+    /// Attach the snapshot `root` of `source_turn_id` to `turn_id`, copying
+    /// its blobs first when the target turn cannot read them in place (its
+    /// context is sealed with a different key).
+    fn copy_fs_snapshot(
+        &mut self,
+        source_turn_id: u64,
+        turn_id: u64,
+        root: [u8; 32],
+    ) -> Result<()> {
+        if !self.turn_blobs(turn_id)?.contains(&root) {
+            let blobs = self.fs_snapshot_blobs(source_turn_id, root)?;
+            let mut sink = self.turn_blobs(turn_id)?;
+            for (hash, data) in blobs {
+                sink.put_blob(hash, &data)?;
+            }
+        }
+        self.set_fs_root(turn_id, root)?;
+        let meta = self.fs_meta.get(source_turn_id).cloned();
+        self.record_fs_attachment(turn_id, meta)
+    }
 
-        let record = self.turn_store.append_turn(
+    /// Roll a context over into a new context whose first turn is `digest`
+    /// (see [`crate::rollovers`]). The digest covers the chain up to
+    /// `through_turn_id`, or up to the head when None.
+    pub fn rollover_context(
+        &mut self,
+        context_id: u64,
+        digest: &SummaryResponse,
+        generator: &str,
+        through_turn_id: Option<u64>,
+    ) -> Result<Rollover> {
+        let head = self.check_rollover(context_id)?;
+        if digest.summary.trim().is_empty() {
+            return Err(StoreError::InvalidInput("digest must not be empty".into()));
+        }
+        let through = self
+            .get_turn(through_turn_id.unwrap_or(head.head_turn_id), false)?
+            .record;
+        let metadata = self.get_context_metadata(context_id);
+        let at_unix_ms = unix_ms();
+        let payload = encode_rollover_payload(
+            digest,
+            generator,
             context_id,
-            parent_turn_id,
-            content_hash,
-            encoding,
-            declared_type_id,
-            declared_type_version,
-            compression,
-            uncompressed_len,
+            through.turn_id,
+            through.depth + 1,
+            metadata.as_ref(),
+            at_unix_ms,
         )?;
 
-        // Cache metadata if this is the first turn, and return it for event publishing
-        let metadata = self.maybe_cache_metadata(context_id, record.depth, &raw_bytes);
+        let new_head = self.create_context(0)?;
+        let (record, _) = self.append_turn(
+            new_head.context_id,
+            0,
+            SUMMARY_TYPE_ID.to_string(),
+            SUMMARY_TYPE_VERSION,
+            1,
+            0,
+            payload.len() as u32,
+            *blake3::hash(&payload).as_bytes(),
+            &payload,
+        )?;
+        let rollover = Rollover {
+            from_context_id: context_id,
+            to_context_id: new_head.context_id,
+            digest_turn_id: record.turn_id,
+            summarized_through_turn_id: through.turn_id,
+            generator: generator.to_string(),
+            at_unix_ms,
+        };
+        self.rollovers.record(rollover.clone())?;
 
-        // Update secondary indexes if this is the first turn (depth=0)
-        if record.depth == 0 {
-            let head = self.turn_store.get_head(context_id)?;
-            self.secondary_indexes.add_context(
-                context_id,
-                metadata.as_ref(),
-                head.created_at_unix_ms,
-                record.depth,
-            );
+        let event = SystemEvent::new(
+            SystemEventKind::ContextRolledOver,
+            format!("Rolled over to context {}", new_head.context_id),
+        )
+        .with_turn(through.turn_id)
+        .with_attr("to_context_id", new_head.context_id.to_string());
+        self.record_system_event(context_id, event);
+        Ok(rollover)
+    }
+
+    /// Fail unless a context exists, has turns and was not rolled over yet.
+    /// Returns its head.
+    pub fn check_rollover(&self, context_id: u64) -> Result<ContextHead> {
+        let head = self.get_head(context_id)?;
+        self.rollovers.check_available(context_id)?;
+        if head.head_turn_id == 0 {
+            return Err(StoreError::InvalidInput(format!(
+                "context {context_id} has no turns to roll over"
+            )));
         }
+        Ok(head)
+    }
 
-        Ok((record, metadata))
+    /// The rollover that replaced a context, once it was rolled over.
+    pub fn rolled_over_to(&self, context_id: u64) -> Option<&Rollover> {
+        self.rollovers.successor(context_id)
     }
 
-    pub fn get_last(
-        &mut self,
-        context_id: u64,
-        limit: u32,
-        include_payload: bool,
-    ) -> Result<Vec<TurnWithMeta>> {
-        let turns = self.turn_store.get_last(context_id, limit)?;
-        let mut out = Vec::with_capacity(turns.len());
-        for record in turns {
-            let meta = self.turn_store.get_turn_meta(record.turn_id)?;
-            let payload = if include_payload {
-                Some(self.blob_store.get(&record.payload_hash)?)
-            } else {
-                None
+    /// The rollover that created a context.
+    pub fn rolled_over_from(&self, context_id: u64) -> Option<&Rollover> {
+        self.rollovers.predecessor(context_id)
+    }
+
+    /// The lineage tree a context belongs to: contexts linked by provenance
+    /// parents and rollovers, from the topmost ancestor down. Trees larger
+    /// than [`MAX_TREE_NODES`] contexts are cut off breadth-first.
+    pub fn context_tree(&mut self, context_id: u64) -> Result<ContextTree> {
+        self.get_head(context_id)?;
+        let mut root = context_id;
+        let mut visited = HashSet::from([root]);
+        while let Some(parent) = self.lineage_parent(root) {
+            if !visited.insert(parent) || self.get_head(parent).is_err() {
+                break;
+            }
+            root = parent;
+        }
+
+        let mut order = vec![root];
+        let mut visited = HashSet::from([root]);
+        let mut children: HashMap<u64, Vec<u64>> = HashMap::new();
+        let mut truncated = false;
+        let mut next = 0;
+        while next < order.len() {
+            let id = order[next];
+            next += 1;
+            let mut kids: Vec<u64> = self
+                .secondary_indexes
+                .lookup_parent_exact(id)
+                .into_iter()
+                .chain(self.rollovers.successor(id).map(|r| r.to_context_id))
+                .filter(|kid| !visited.contains(kid))
+                .collect();
+            kids.sort_unstable();
+            kids.dedup();
+            let room = MAX_TREE_NODES.saturating_sub(order.len());
+            if kids.len() > room {
+                kids.truncate(room);
+                truncated = true;
+            }
+            visited.extend(kids.iter().copied());
+            order.extend(kids.iter().copied());
+            children.insert(id, kids);
+        }
+
+        let mut nodes: HashMap<u64, ContextTreeNode> = HashMap::new();
+        for &id in order.iter().rev() {
+            let metadata = self.get_context_metadata(id);
+            let node = ContextTreeNode {
+                context_id: id,
+                head_depth: self.get_head(id).map(|h| h.head_depth).unwrap_or(0),
+                title: metadata.as_ref().and_then(|m| m.title.clone()),
+                spawn_reason: metadata
+                    .and_then(|m| m.provenance)
+                    .and_then(|p| p.spawn_reason),
+                rolled_over_from: self.rollovers.predecessor(id).map(|r| r.from_context_id),
+                rolled_over_to: self.rollovers.successor(id).map(|r| r.to_context_id),
+                children: children
+                    .remove(&id)
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|kid| nodes.remove(kid))
+                    .collect(),
             };
-            out.push(TurnWithMeta {
-                record,
-                meta,
-                payload,
+            nodes.insert(id, node);
+        }
+        let root = nodes.remove(&root).expect("root node is built last");
+        Ok(ContextTree { root, truncated })
+    }
+
+    /// The context a context was spawned or rolled over from.
+    fn lineage_parent(&mut self, context_id: u64) -> Option<u64> {
+        self.get_context_metadata(context_id)
+            .and_then(|m| m.provenance)
+            .and_then(|p| p.parent_context_id)
+            .or_else(|| {
+                self.rollovers
+                    .predecessor(context_id)
+                    .map(|r| r.from_context_id)
+            })
+    }
+
+    /// Append a system event turn at a context's head, if its kind is
+    /// recorded. Empty contexts are skipped so a context's first turn always
+    /// comes from a client. Failures are logged rather than failing the
+    /// milestone itself.
+    fn record_system_event(&mut self, context_id: u64, event: SystemEvent) -> Option<TurnRecord> {
+        let bus = self
+            .system_events
+            .as_ref()
+            .filter(|events| events.records(event.kind))
+            .map(|events| Arc::clone(events.event_bus()))?;
+        let head = self.turn_store.get_head(context_id).ok()?;
+        if head.head_turn_id == 0 {
+            return None;
+        }
+        let appended =
+            encode_system_event_payload(&event, context_id, unix_ms()).and_then(|payload| {
+                self.append_turn(
+                    context_id,
+                    head.head_turn_id,
+                    SYSTEM_EVENT_TYPE_ID.to_string(),
+                    SYSTEM_EVENT_TYPE_VERSION,
+                    1,
+                    0,
+                    payload.len() as u32,
+                    *blake3::hash(&payload).as_bytes(),
+                    &payload,
+                )
             });
+        match appended {
+            Ok((record, _)) => {
+                bus.publish(StoreEvent::TurnAppended {
+                    context_id: context_id.to_string(),
+                    turn_id: record.turn_id.to_string(),
+                    parent_turn_id: record.parent_turn_id.to_string(),
+                    depth: record.depth,
+                    declared_type_id: Some(SYSTEM_EVENT_TYPE_ID.to_string()),
+                    declared_type_version: Some(SYSTEM_EVENT_TYPE_VERSION),
+                });
+                Some(record)
+            }
+            Err(err) => {
+                tracing::warn!(
+                    context_id,
+                    kind = event.kind.as_str(),
+                    error = %err,
+                    "Failed to append system event"
+                );
+                None
+            }
         }
-        Ok(out)
     }
 
-    pub fn get_before(
-        &mut self,
+    /// A context created from a turn starts with that turn's token total.
+    fn record_inherited_tokens(&mut self, head: &ContextHead) -> Result<()> {
+        let total = self.chain_tokens(head.context_id, head.head_turn_id)?;
+        if head.head_turn_id != 0 {
+            self.token_ledger
+                .record(head.context_id, head.head_turn_id, 0, total)?;
+        }
+        self.secondary_indexes
+            .update_tokens(head.context_id, 0, total);
+        Ok(())
+    }
+
+    /// Tokens along the parent chain of `turn_id`, itself included. Walks up
+    /// to the nearest counted turn or the context's last recorded turn.
+    fn chain_tokens(&self, context_id: u64, mut turn_id: u64) -> Result<u64> {
+        if self.token_ledger.is_empty() {
+            return Ok(0);
+        }
+        let context = self.token_ledger.context(context_id);
+        loop {
+            if turn_id == 0 {
+                return Ok(0);
+            }
+            if turn_id == context.turn_id {
+                return Ok(context.total);
+            }
+            if let Some(turn) = self.token_ledger.turn(turn_id) {
+                return Ok(turn.total);
+            }
+            turn_id = self.turn_store.get_turn(turn_id)?.parent_turn_id;
+        }
+    }
+
+    /// Counted tokens of a turn, if it had any.
+    pub fn turn_tokens(&self, turn_id: u64) -> Option<TurnTokens> {
+        self.token_ledger.turn(turn_id)
+    }
+
+    pub fn context_tokens(&self, context_id: u64) -> ContextTokens {
+        self.token_ledger.context(context_id)
+    }
+
+    /// Contexts, turns and bytes by age of the last append, overall and per
+    /// client tag. Linear in the number of turns; tags come from the
+    /// secondary indexes, so no context metadata is read.
+    pub fn data_age_stats(&self, now_ms: u64, max_tags: usize) -> DataAgeStats {
+        let tags = self.secondary_indexes.context_tags();
+        let mut footprints = self.turn_store.context_footprints();
+        for footprint in &mut footprints {
+            if let Some(tag) = tags.get(&footprint.context_id) {
+                footprint.tag = tag.to_string();
+            }
+        }
+        DataAgeStats::compute(&footprints, now_ms, max_tags)
+    }
+
+    /// Token totals overall and per client tag.
+    pub fn token_stats(&mut self) -> TokenStats {
+        let counted: Vec<(u64, u64)> = self
+            .token_ledger
+            .contexts()
+            .filter(|(_, tokens)| tokens.appended > 0)
+            .map(|(id, tokens)| (id, tokens.appended))
+            .collect();
+        let mut by_tag: HashMap<String, TagTokens> = HashMap::new();
+        for (context_id, appended) in &counted {
+            let tag = self
+                .get_context_metadata(*context_id)
+                .and_then(|m| m.client_tag)
+                .filter(|t| !t.is_empty())
+                .unwrap_or_else(|| crate::metrics::UNTAGGED.to_string());
+            let entry = by_tag.entry(tag.clone()).or_insert(TagTokens {
+                tag,
+                tokens: 0,
+                contexts: 0,
+            });
+            entry.tokens += appended;
+            entry.contexts += 1;
+        }
+        let mut by_tag: Vec<TagTokens> = by_tag.into_values().collect();
+        by_tag.sort_by(|a, b| b.tokens.cmp(&a.tokens).then_with(|| a.tag.cmp(&b.tag)));
+        TokenStats {
+            tokenizer: self
+                .token_counter
+                .as_ref()
+                .map(|c| c.tokenizer_name().to_string()),
+            total: counted.iter().map(|(_, appended)| appended).sum(),
+            contexts: counted.len(),
+            by_tag,
+        }
+    }
+
+    /// A context created from a turn is indexed with the metadata,
+    /// snapshots and authors along its chain; its own appends never start
+    /// at depth 0.
+    fn index_inherited_chain(&mut self, head: &ContextHead) {
+        if head.head_turn_id != 0 {
+            let metadata = self.get_context_metadata(head.context_id);
+            self.secondary_indexes.add_context(
+                head.context_id,
+                metadata.as_ref(),
+                head.created_at_unix_ms,
+                head.head_depth,
+            );
+            let fs = self.chain_fs_stats(head.head_turn_id);
+            self.secondary_indexes.update_fs(head.context_id, fs);
+            self.index_chain_authors(head.context_id, head.head_turn_id);
+        }
+    }
+
+    /// Index the authors and index plugin entries of `turn_id` and its
+    /// ancestors for `context_id`, and the preview of the latest of them
+    /// that recorded one.
+    fn index_chain_authors(&mut self, context_id: u64, turn_id: u64) {
+        let mut current = turn_id;
+        let mut previewed = false;
+        while current != 0 {
+            let Ok(turn) = self.turn_store.get_turn(current) else {
+                break;
+            };
+            if let Some(author) = self
+                .turn_store
+                .get_turn_meta(current)
+                .ok()
+                .and_then(|meta| meta.author)
+            {
+                self.secondary_indexes.add_author(context_id, &author);
+            }
+            if let Some(entries) = self.custom_index.get(current) {
+                for (key, values) in entries {
+                    for value in values {
+                        self.secondary_indexes.add_custom(context_id, key, value);
+                    }
+                }
+            }
+            if !previewed {
+                if let Some(preview) = self.previews.get(current) {
+                    self.secondary_indexes.set_preview(context_id, preview);
+                    previewed = true;
+                }
+            }
+            current = turn.parent_turn_id;
+        }
+    }
+
+    /// Snapshots attached to `turn_id` and its ancestors.
+    fn chain_fs_stats(&mut self, turn_id: u64) -> FsStats {
+        let mut attached = Vec::new();
+        let mut current = turn_id;
+        while current != 0 {
+            let Ok(turn) = self.turn_store.get_turn(current) else {
+                break;
+            };
+            if let Some(root) = self.fs_roots.get(current) {
+                attached.push((current, root));
+            }
+            current = turn.parent_turn_id;
+        }
+
+        let mut stats = FsStats::default();
+        for (turn_id, root) in attached {
+            let bytes = self.fs_root_bytes(turn_id, root);
+            stats.count += 1;
+            stats.max_bytes = stats.max_bytes.max(bytes);
+            stats.last_turn_id = stats.last_turn_id.max(turn_id);
+        }
+        stats
+    }
+
+    /// File bytes of a snapshot root attached to `turn_id`. Roots whose
+    /// trees cannot be read count as empty.
+    fn fs_root_bytes(&mut self, turn_id: u64, root: [u8; 32]) -> u64 {
+        if let Some(bytes) = self.fs_root_bytes.get(&root) {
+            return *bytes;
+        }
+        let bytes = match self
+            .turn_blobs(turn_id)
+            .and_then(|mut blobs| snapshot_bytes(&mut blobs, &root))
+        {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                tracing::warn!(turn_id, error = %e, "Failed to size fs snapshot");
+                0
+            }
+        };
+        self.fs_root_bytes.insert(root, bytes);
+        bytes
+    }
+
+    pub fn get_head(&self, context_id: u64) -> Result<ContextHead> {
+        self.check_not_expired(context_id)?;
+        self.turn_store.get_head(context_id)
+    }
+
+    /// Every head the context has had, oldest first.
+    pub fn head_history(&self, context_id: u64) -> Result<Vec<HeadMove>> {
+        self.check_not_expired(context_id)?;
+        Ok(self.turn_store.head_history(context_id)?.to_vec())
+    }
+
+    /// When a context expires, if it was created with a TTL.
+    pub fn expiry(&self, context_id: u64) -> Option<&ContextExpiry> {
+        self.expiries.get(context_id)
+    }
+
+    /// Fail with `Expired` if the expiry sweeper tombstoned a context.
+    pub fn check_not_expired(&self, context_id: u64) -> Result<()> {
+        match self.expiries.get(context_id) {
+            Some(expiry) if expiry.is_tombstoned() => Err(StoreError::Expired(format!(
+                "context {context_id} expired at {}",
+                expiry.expires_at_unix_ms
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Refuse a turn that only expired contexts reach. A context forked off
+    /// before the expiry keeps the turns it shares.
+    pub fn check_turn_not_expired(&self, turn_id: u64) -> Result<()> {
+        let mut expired = self.expiries.iter().filter(|expiry| {
+            expiry.is_tombstoned()
+                && self
+                    .turn_store
+                    .get_head(expiry.context_id)
+                    .is_ok_and(|head| self.is_turn_in_chain(turn_id, head.head_turn_id))
+        });
+        let Some(owner) = expired.next() else {
+            return Ok(());
+        };
+        let live = self.turn_store.heads().any(|head| {
+            !self.expiries.is_tombstoned(head.context_id)
+                && self.is_turn_in_chain(turn_id, head.head_turn_id)
+        });
+        if live {
+            return Ok(());
+        }
+        self.check_not_expired(owner.context_id)
+    }
+
+    /// Tombstone every context whose expiry time is at or before
+    /// `now_unix_ms`, except those on legal hold. Returns the tombstones.
+    pub fn expire_due(&mut self, now_unix_ms: u64) -> Result<Vec<ContextExpiry>> {
+        let mut expired = Vec::new();
+        for due in self.expiries.due(now_unix_ms) {
+            if self.holds.get(due.context_id).is_some() {
+                continue;
+            }
+            expired.push(self.expiries.tombstone(due.context_id, now_unix_ms)?);
+        }
+        Ok(expired)
+    }
+
+    /// Record that `principal` has viewed a context up to `turn_id` (the head
+    /// when None). The turn must be on the context's head chain.
+    pub fn mark_read(
+        &mut self,
+        principal: &str,
+        context_id: u64,
+        turn_id: Option<u64>,
+    ) -> Result<ReadMark> {
+        let head = self.get_head(context_id)?;
+        let turn_id = turn_id.unwrap_or(head.head_turn_id);
+        let depth = self.depth_on_head_chain(&head, turn_id)?;
+        self.read_marks.set(principal, context_id, turn_id, depth)
+    }
+
+    pub fn read_mark(&self, principal: &str, context_id: u64) -> Option<&ReadMark> {
+        self.read_marks.get(principal, context_id)
+    }
+
+    /// Depth of `turn_id`, which must be on the chain ending at `head`.
+    fn depth_on_head_chain(&self, head: &ContextHead, turn_id: u64) -> Result<u32> {
+        if turn_id == head.head_turn_id {
+            return Ok(head.head_depth);
+        }
+        let turn = self.turn_store.get_turn(turn_id)?;
+        let mut cursor = head.head_turn_id;
+        for _ in turn.depth..head.head_depth {
+            cursor = self.turn_store.get_turn(cursor)?.parent_turn_id;
+        }
+        if cursor != turn_id {
+            return Err(StoreError::InvalidInput(format!(
+                "turn {turn_id} is not in context {}",
+                head.context_id
+            )));
+        }
+        Ok(turn.depth)
+    }
+
+    /// Bookmark a turn on a context's head chain.
+    pub fn add_bookmark(
+        &mut self,
+        context_id: u64,
+        turn_id: u64,
+        name: &str,
+        note: Option<String>,
+        author: Option<String>,
+    ) -> Result<Bookmark> {
+        let head = self.get_head(context_id)?;
+        let depth = self.depth_on_head_chain(&head, turn_id)?;
+        self.bookmarks
+            .add(context_id, turn_id, depth, name, note, author)
+    }
+
+    /// Bookmarks of a context, in timeline order.
+    pub fn bookmarks(&self, context_id: u64) -> Vec<&Bookmark> {
+        self.bookmarks.list(context_id)
+    }
+
+    pub fn delete_bookmark(
+        &mut self,
+        context_id: u64,
+        bookmark_id: u64,
+        principal: Option<String>,
+    ) -> Result<Bookmark> {
+        self.get_head(context_id)?;
+        self.bookmarks.delete(context_id, bookmark_id, principal)
+    }
+
+    /// Align two contexts at their common ancestor and collect the turns each
+    /// added since, loading payloads for up to `limit` turns per side.
+    pub fn compare_contexts(&mut self, a: u64, b: u64, limit: u32) -> Result<ContextComparison> {
+        let head_a = self.get_head(a)?;
+        let head_b = self.get_head(b)?;
+
+        // Walk the deeper chain up until both cursors meet.
+        let mut cursor_a = self.turn_record_opt(head_a.head_turn_id)?;
+        let mut cursor_b = self.turn_record_opt(head_b.head_turn_id)?;
+        let mut chain_a = Vec::new();
+        let mut chain_b = Vec::new();
+        let common_ancestor = loop {
+            match (cursor_a.take(), cursor_b.take()) {
+                (Some(x), Some(y)) if x.turn_id == y.turn_id => break Some(x),
+                (Some(x), Some(y)) => {
+                    let (depth_a, depth_b) = (x.depth, y.depth);
+                    if depth_a >= depth_b {
+                        cursor_a = self.turn_record_opt(x.parent_turn_id)?;
+                        chain_a.push(x);
+                    } else {
+                        cursor_a = Some(x);
+                    }
+                    if depth_b >= depth_a {
+                        cursor_b = self.turn_record_opt(y.parent_turn_id)?;
+                        chain_b.push(y);
+                    } else {
+                        cursor_b = Some(y);
+                    }
+                }
+                (Some(x), None) => {
+                    cursor_a = self.turn_record_opt(x.parent_turn_id)?;
+                    chain_a.push(x);
+                }
+                (None, Some(y)) => {
+                    cursor_b = self.turn_record_opt(y.parent_turn_id)?;
+                    chain_b.push(y);
+                }
+                (None, None) => break None,
+            }
+        };
+
+        let since = common_ancestor.as_ref().map(|t| t.created_at_unix_ms);
+        let a = self.branch_comparison(head_a, chain_a, since, limit)?;
+        let b = self.branch_comparison(head_b, chain_b, since, limit)?;
+        Ok(ContextComparison {
+            common_ancestor,
+            a,
+            b,
+        })
+    }
+
+    fn turn_record_opt(&self, turn_id: u64) -> Result<Option<TurnRecord>> {
+        if turn_id == 0 {
+            return Ok(None);
+        }
+        self.turn_store.get_turn(turn_id).map(Some)
+    }
+
+    /// `chain` holds the divergent records newest first.
+    fn branch_comparison(
+        &mut self,
+        head: ContextHead,
+        mut chain: Vec<TurnRecord>,
+        since_unix_ms: Option<u64>,
+        limit: u32,
+    ) -> Result<BranchComparison> {
+        chain.reverse();
+        let mut stats = BranchStats {
+            total_turns: if head.head_turn_id == 0 {
+                0
+            } else {
+                head.head_depth as u64 + 1
+            },
+            divergent_turns: chain.len() as u64,
+            ..BranchStats::default()
+        };
+        if let (Some(first), Some(last)) = (chain.first(), chain.last()) {
+            let start = since_unix_ms.unwrap_or(first.created_at_unix_ms);
+            stats.duration_ms = last.created_at_unix_ms.saturating_sub(start);
+        }
+
+        let mut turns = Vec::new();
+        for record in chain {
+            let meta = self.turn_store.get_turn_meta(record.turn_id)?;
+            stats.divergent_bytes += meta.uncompressed_len as u64;
+            stats.divergent_tokens += self
+                .token_ledger
+                .turn(record.turn_id)
+                .map_or(0, |t| t.tokens);
+            if turns.len() < limit as usize {
+                let payload = Some(self.read_payload(&record)?);
+                turns.push(TurnWithMeta {
+                    record,
+                    meta,
+                    payload,
+                });
+            }
+        }
+        Ok(BranchComparison { head, turns, stats })
+    }
+
+    /// Append a turn to a context.
+    ///
+    /// Returns the turn record and, if this is the first turn (depth=0) or a title was
+    /// derived from it, the context's updated metadata.
+    #[allow(clippy::too_many_arguments)]
+    pub fn append_turn(
+        &mut self,
+        context_id: u64,
+        parent_turn_id: u64,
+        declared_type_id: String,
+        declared_type_version: u32,
+        encoding: u32,
+        compression: u32,
+        uncompressed_len: u32,
+        content_hash: [u8; 32],
+        payload_bytes: &[u8],
+    ) -> Result<(TurnRecord, Option<ContextMetadata>)> {
+        self.append_turn_with_author(
+            context_id,
+            parent_turn_id,
+            declared_type_id,
+            declared_type_version,
+            encoding,
+            compression,
+            uncompressed_len,
+            content_hash,
+            payload_bytes,
+            None,
+        )
+    }
+
+    /// Append a turn, recording the session that appended it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn append_turn_with_author(
+        &mut self,
+        context_id: u64,
+        parent_turn_id: u64,
+        declared_type_id: String,
+        declared_type_version: u32,
+        encoding: u32,
+        compression: u32,
+        uncompressed_len: u32,
+        content_hash: [u8; 32],
+        payload_bytes: &[u8],
+        author: Option<TurnAuthor>,
+    ) -> Result<(TurnRecord, Option<ContextMetadata>)> {
+        self.check_not_expired(context_id)?;
+        let raw_bytes =
+            decode_payload(compression, uncompressed_len, &content_hash, payload_bytes)?;
+
+        // Seal the payload when the context is (or becomes) encrypted. In tag
+        // mode the tag comes from the first turn's client_tag.
+        let head = self.turn_store.get_head(context_id)?;
+        let tag = if head.head_turn_id == 0 {
+            extract_context_metadata(&raw_bytes).and_then(|m| m.client_tag)
+        } else {
+            None
+        };
+        let inherit_from = if parent_turn_id != 0 {
+            parent_turn_id
+        } else {
+            head.head_turn_id
+        };
+        if self.depth_limits.is_some() && inherit_from != 0 {
+            let depth = self.turn_store.get_turn(inherit_from)?.depth + 1;
+            let tag = self
+                .get_context_metadata(context_id)
+                .and_then(|m| m.client_tag);
+            if let Some(limits) = &self.depth_limits {
+                limits.check(context_id, tag.as_deref(), depth)?;
+            }
+        }
+        // Payloads are content-addressed, so identical payloads (e.g. the same
+        // system prompt in many contexts) are stored once and referenced.
+        let key = self.context_data_key(context_id, inherit_from, tag.as_deref())?;
+        let storage_hash = match &key {
+            Some(key) => key.storage_hash(&content_hash),
+            None => content_hash,
+        };
+        if !self.blob_store.contains(&storage_hash) {
+            match &key {
+                Some(key) => {
+                    let sealed = key.seal(&content_hash, &raw_bytes)?;
+                    self.blob_store.put_if_absent(storage_hash, &sealed)?;
+                }
+                None => {
+                    self.blob_store.put_if_absent(content_hash, &raw_bytes)?;
+                }
+            }
+        }
+
+        let type_id_for_title = declared_type_id.clone();
+        let previous_depth = head.head_depth;
+        // Which key sealed the turn must be durable before the turn is.
+        if let Some(key) = &key {
+            let turn_id = self.turn_store.reserve_turn_id();
+            self.keys.record_turn(turn_id, key.key_id)?;
+        }
+        let record = match self.turn_store.append_turn(
+            context_id,
+            parent_turn_id,
+            content_hash,
+            encoding,
+            declared_type_id,
+            declared_type_version,
+            compression,
+            uncompressed_len,
+        ) {
+            Ok(record) => record,
+            Err(err) => {
+                if key.is_some() {
+                    let last_turn_id = self.turn_store.last_turn_id();
+                    self.keys.forget_turns_after(last_turn_id)?;
+                }
+                return Err(err);
+            }
+        };
+        self.payload_refs
+            .retain(storage_hash, uncompressed_len as u64);
+
+        // Cache metadata if this is the first turn, and return it for event publishing
+        let metadata = self.maybe_cache_metadata(context_id, record.depth, &raw_bytes);
+
+        // The turn is durable from here on: a failing step below must not
+        // fail the append, or a client retry would append the turn twice.
+        // Update secondary indexes if this is the first turn (depth=0)
+        if record.depth == 0 {
+            let created_at_unix_ms = self
+                .turn_store
+                .get_head(context_id)
+                .map_or(record.created_at_unix_ms, |head| head.created_at_unix_ms);
+            self.secondary_indexes.add_context(
+                context_id,
+                metadata.as_ref(),
+                created_at_unix_ms,
+                record.depth,
+            );
+        } else {
+            self.secondary_indexes
+                .update_depth(context_id, previous_depth, record.depth);
+        }
+        if let Some(author) = author {
+            self.secondary_indexes.add_author(context_id, &author);
+            let result = self.turn_store.set_author(record.turn_id, author);
+            self.after_append(context_id, record.turn_id, "record author", result);
+        }
+
+        let tokens = match &self.token_counter {
+            Some(counter) => counter.count(&type_id_for_title, declared_type_version, &raw_bytes),
+            None => 0,
+        };
+        let previous_tokens = self.token_ledger.context(context_id).total;
+        let result = self
+            .chain_tokens(context_id, record.parent_turn_id)
+            .and_then(|chain| {
+                self.token_ledger
+                    .record(context_id, record.turn_id, tokens, tokens + chain)?;
+                Ok(tokens + chain)
+            });
+        if let Some(total_tokens) =
+            self.after_append(context_id, record.turn_id, "count tokens", result)
+        {
+            self.secondary_indexes
+                .update_tokens(context_id, previous_tokens, total_tokens);
+        }
+
+        let result = self.maybe_index_custom(
+            context_id,
+            &record,
+            &type_id_for_title,
+            declared_type_version,
+            &raw_bytes,
+        );
+        self.after_append(context_id, record.turn_id, "run index plugins", result);
+
+        let result = self.maybe_record_preview(
+            context_id,
+            record.turn_id,
+            &type_id_for_title,
+            declared_type_version,
+            &raw_bytes,
+        );
+        self.after_append(context_id, record.turn_id, "record preview", result);
+
+        // A derived title also counts as a metadata change for event publishing
+        let result = self.maybe_derive_title(
+            context_id,
+            &type_id_for_title,
+            declared_type_version,
+            &raw_bytes,
+        );
+        let derived = self
+            .after_append(context_id, record.turn_id, "derive title", result)
+            .flatten();
+        // So do new PII labels; applied last, they carry the title too.
+        let result = self.maybe_label_pii(context_id, &type_id_for_title, &raw_bytes);
+        let labeled = self
+            .after_append(context_id, record.turn_id, "label PII", result)
+            .flatten();
+
+        Ok((record, labeled.or(derived).or(metadata)))
+    }
+
+    /// Result of a step run after `turn_id` was appended. The turn stays
+    /// appended either way, so a failure is logged and counted instead.
+    fn after_append<T>(
+        &mut self,
+        context_id: u64,
+        turn_id: u64,
+        step: &str,
+        result: Result<T>,
+    ) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.append_side_effect_failures += 1;
+                tracing::warn!(
+                    context_id,
+                    turn_id,
+                    error = %err,
+                    "Failed to {step} of an appended turn"
+                );
+                None
+            }
+        }
+    }
+
+    /// Run the index plugins on an appended turn and index what they return.
+    fn maybe_index_custom(
+        &mut self,
+        context_id: u64,
+        record: &TurnRecord,
+        declared_type_id: &str,
+        declared_type_version: u32,
+        payload: &[u8],
+    ) -> Result<()> {
+        let Some(plugins) = &mut self.index_plugins else {
+            return Ok(());
+        };
+        let entries = plugins.run(
+            context_id,
+            record.turn_id,
+            record.depth,
+            declared_type_id,
+            declared_type_version,
+            payload,
+        );
+        if entries.is_empty() {
+            return Ok(());
+        }
+        for (key, values) in &entries {
+            for value in values {
+                self.secondary_indexes.add_custom(context_id, key, value);
+            }
+        }
+        self.custom_index.record(record.turn_id, entries)
+    }
+
+    /// Record the preview of an appended turn whose type declares a summary
+    /// field, making it its context's preview.
+    fn maybe_record_preview(
+        &mut self,
+        context_id: u64,
+        turn_id: u64,
+        declared_type_id: &str,
+        declared_type_version: u32,
+        payload: &[u8],
+    ) -> Result<()> {
+        let Some(preview) = self.preview_extractor.as_ref().and_then(|extractor| {
+            extractor.extract(declared_type_id, declared_type_version, payload)
+        }) else {
+            return Ok(());
+        };
+        self.secondary_indexes.set_preview(context_id, &preview);
+        self.previews.record(turn_id, preview)
+    }
+
+    /// Scan a turn payload for PII and add a `pii:<detector>` label for each
+    /// detector the context is not labeled with yet. Returns the updated
+    /// metadata when a label was added.
+    fn maybe_label_pii(
+        &mut self,
+        context_id: u64,
+        declared_type_id: &str,
+        payload: &[u8],
+    ) -> Result<Option<ContextMetadata>> {
+        let Some(scanner) = &mut self.pii_scanner else {
+            return Ok(None);
+        };
+        let found = scanner.scan(declared_type_id, payload);
+        if found.is_empty() {
+            return Ok(None);
+        }
+        let current = self
+            .get_context_metadata(context_id)
+            .and_then(|m| m.labels)
+            .unwrap_or_default();
+        let labels: Vec<String> = found
+            .iter()
+            .map(PiiDetector::label)
+            .filter(|label| !current.contains(label))
+            .collect();
+        if labels.is_empty() {
+            return Ok(None);
+        }
+        self.apply_metadata_patch(context_id, &MetadataPatch::default(), &labels)
+            .map(Some)
+    }
+
+    /// Derive and persist a title for an untitled context from a turn payload.
+    /// Returns the updated metadata when a title was derived.
+    fn maybe_derive_title(
+        &mut self,
+        context_id: u64,
+        declared_type_id: &str,
+        declared_type_version: u32,
+        payload: &[u8],
+    ) -> Result<Option<ContextMetadata>> {
+        if self.title_deriver.is_none() {
+            return Ok(None);
+        }
+        let current = self.get_context_metadata(context_id);
+        if current.as_ref().is_some_and(|m| m.title.is_some()) {
+            return Ok(None);
+        }
+        let Some(deriver) = &self.title_deriver else {
+            return Ok(None);
+        };
+        match deriver.try_derive(declared_type_id, declared_type_version, payload) {
+            TitleAttempt::Derived(Some(title)) => self.apply_derived_title(context_id, title),
+            TitleAttempt::Derived(None) => Ok(None),
+            TitleAttempt::Busy => {
+                self.title_candidates.push(TitleCandidate {
+                    context_id,
+                    type_id: declared_type_id.to_string(),
+                    type_version: declared_type_version,
+                    payload: payload.to_vec(),
+                });
+                Ok(None)
+            }
+        }
+    }
+
+    /// Record a derived title unless the context gained one meanwhile.
+    /// Returns the updated metadata when the title was recorded.
+    pub fn apply_derived_title(
+        &mut self,
+        context_id: u64,
+        title: String,
+    ) -> Result<Option<ContextMetadata>> {
+        let current = self.get_context_metadata(context_id);
+        if current.as_ref().is_some_and(|m| m.title.is_some()) {
+            return Ok(None);
+        }
+        let patch = MetadataPatch {
+            title: Some(title),
+            title_source: Some(TITLE_SOURCE_DERIVED.to_string()),
+            ..Default::default()
+        };
+        self.apply_metadata_patch(context_id, &patch, &[]).map(Some)
+    }
+
+    /// Layer a metadata patch over a context's metadata, persisting it and
+    /// updating the secondary indexes. `add_labels` are appended to the
+    /// context's effective labels (after the patch) if not already present.
+    pub fn apply_metadata_patch(
+        &mut self,
+        context_id: u64,
+        patch: &MetadataPatch,
+        add_labels: &[String],
+    ) -> Result<ContextMetadata> {
+        self.turn_store.get_head(context_id)?;
+        let current = self.get_context_metadata(context_id);
+
+        let mut stored = self
+            .metadata_overrides
+            .get(context_id)
+            .cloned()
+            .unwrap_or_default();
+        stored.merge(patch);
+        if !add_labels.is_empty() {
+            let mut labels = stored
+                .labels
+                .clone()
+                .or_else(|| current.as_ref().and_then(|m| m.labels.clone()))
+                .unwrap_or_default();
+            for label in add_labels {
+                if !labels.contains(label) {
+                    labels.push(label.clone());
+                }
+            }
+            stored.labels = Some(labels);
+        }
+        self.metadata_overrides.set(context_id, stored.clone())?;
+
+        let updated = stored.apply(current.clone()).unwrap_or_default();
+        self.secondary_indexes
+            .update_metadata(context_id, current.as_ref(), Some(&updated));
+        self.context_metadata_cache
+            .insert(context_id, Some(updated.clone()));
+        Ok(updated)
+    }
+
+    /// Metadata override recorded for a context, if any.
+    pub fn get_metadata_override(&self, context_id: u64) -> Option<&MetadataPatch> {
+        self.metadata_overrides.get(context_id)
+    }
+
+    pub fn get_last(
+        &mut self,
+        context_id: u64,
+        limit: u32,
+        include_payload: bool,
+    ) -> Result<Vec<TurnWithMeta>> {
+        self.check_not_expired(context_id)?;
+        let turns = self.turn_store.get_last(context_id, limit)?;
+        let mut out = Vec::with_capacity(turns.len());
+        for record in turns {
+            let meta = self.turn_store.get_turn_meta(record.turn_id)?;
+            let payload = if include_payload {
+                Some(self.cached_payload(&record)?)
+            } else {
+                None
+            };
+            out.push(TurnWithMeta {
+                record,
+                meta,
+                payload,
+            });
+        }
+        Ok(out)
+    }
+
+    pub fn get_before(
+        &mut self,
         context_id: u64,
         before_turn_id: u64,
         limit: u32,
         include_payload: bool,
     ) -> Result<Vec<TurnWithMeta>> {
+        self.check_not_expired(context_id)?;
         let turns = self
             .turn_store
             .get_before(context_id, before_turn_id, limit)?;
@@ -288,7 +2171,65 @@ impl Store {
         for record in turns {
             let meta = self.turn_store.get_turn_meta(record.turn_id)?;
             let payload = if include_payload {
-                Some(self.blob_store.get(&record.payload_hash)?)
+                Some(self.cached_payload(&record)?)
+            } else {
+                None
+            };
+            out.push(TurnWithMeta {
+                record,
+                meta,
+                payload,
+            });
+        }
+        if include_payload {
+            if let Some(oldest) = out.first() {
+                self.payload_cache.request_prefetch(PrefetchRequest {
+                    context_id,
+                    before_turn_id: oldest.record.turn_id,
+                    limit,
+                });
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn get_range_by_depth(
+        &mut self,
+        context_id: u64,
+        start_depth: u32,
+        limit: u32,
+        include_payload: bool,
+    ) -> Result<Vec<TurnWithMeta>> {
+        self.check_not_expired(context_id)?;
+        let turns = self
+            .turn_store
+            .get_range_by_depth(context_id, start_depth, limit)?;
+        self.with_meta(turns, include_payload)
+    }
+
+    /// Up to `limit` turns of the linear path from the root to `turn_id`,
+    /// starting at `start_depth`, oldest first.
+    pub fn get_ancestry(
+        &mut self,
+        turn_id: u64,
+        start_depth: u32,
+        limit: u32,
+        include_payload: bool,
+    ) -> Result<Vec<TurnWithMeta>> {
+        let turns = self.turn_store.get_ancestry(turn_id, start_depth, limit)?;
+        self.with_meta(turns, include_payload)
+    }
+
+    fn with_meta(
+        &mut self,
+        turns: Vec<TurnRecord>,
+        include_payload: bool,
+    ) -> Result<Vec<TurnWithMeta>> {
+        let mut out = Vec::with_capacity(turns.len());
+        for record in turns {
+            let meta = self.turn_store.get_turn_meta(record.turn_id)?;
+            let payload = if include_payload {
+                Some(self.cached_payload(&record)?)
             } else {
                 None
             };
@@ -298,24 +2239,904 @@ impl Store {
                 payload,
             });
         }
-        Ok(out)
+        Ok(out)
+    }
+
+    /// Whether `turn_id` is on the chain ending at `head_turn_id` (the head
+    /// itself included).
+    pub fn is_turn_in_chain(&self, turn_id: u64, head_turn_id: u64) -> bool {
+        self.turn_store.is_ancestor(turn_id, head_turn_id)
+    }
+
+    pub fn get_turn(&mut self, turn_id: u64, include_payload: bool) -> Result<TurnWithMeta> {
+        self.check_turn_not_expired(turn_id)?;
+        let record = self.turn_store.get_turn(turn_id)?;
+        let meta = self.turn_store.get_turn_meta(turn_id)?;
+        let payload = if include_payload {
+            Some(self.read_payload(&record)?)
+        } else {
+            None
+        };
+        Ok(TurnWithMeta {
+            record,
+            meta,
+            payload,
+        })
+    }
+
+    /// Number of turns referencing the payload blob stored under `hash`.
+    /// Blob deletion and compaction must keep blobs with a non-zero count.
+    pub fn payload_ref_count(&self, hash: &[u8; 32]) -> u32 {
+        self.payload_refs.count(hash)
+    }
+
+    /// Payload deduplication totals.
+    pub fn dedup_stats(&self) -> DedupStats {
+        self.payload_refs.stats()
+    }
+
+    pub fn get_blob(&mut self, hash: &[u8; 32]) -> Result<Vec<u8>> {
+        self.blob_store.get(hash)
+    }
+
+    /// Up to `len` bytes of a blob starting at `offset`, read in place.
+    pub fn get_blob_range(&mut self, hash: &[u8; 32], offset: u64, len: u64) -> Result<Vec<u8>> {
+        self.blob_store.get_range(hash, offset, len)
+    }
+
+    /// Uncompressed length of a blob, if stored.
+    pub fn blob_len(&self, hash: &[u8; 32]) -> Option<u64> {
+        self.blob_store.raw_len(hash).map(u64::from)
+    }
+
+    /// Store a blob after verifying its hash. With a `context_id` the blob is
+    /// sealed with that context's key when the context is encrypted, binding
+    /// the context to a key if it has none yet (`client_tag` selects the key
+    /// in tag mode). Returns true if the blob was newly stored.
+    pub fn put_blob(
+        &mut self,
+        hash: [u8; 32],
+        data: &[u8],
+        context_id: Option<u64>,
+        client_tag: Option<&str>,
+    ) -> Result<bool> {
+        if blake3::hash(data).as_bytes() != &hash {
+            return Err(StoreError::InvalidInput("blob hash mismatch".into()));
+        }
+        let key = match context_id {
+            Some(context_id) => {
+                let head_turn_id = self.turn_store.get_head(context_id)?.head_turn_id;
+                self.context_data_key(context_id, head_turn_id, client_tag)?
+            }
+            None => None,
+        };
+        let (storage_hash, stored) = match &key {
+            Some(key) => (key.storage_hash(&hash), key.seal(&hash, data)?),
+            None => (hash, data.to_vec()),
+        };
+        let was_new = !self.blob_store.contains(&storage_hash);
+        self.blob_store.put_if_absent(storage_hash, &stored)?;
+        Ok(was_new)
+    }
+
+    /// Read a turn's payload, opening it with its key if it was sealed.
+    /// Cache payloads of paged turns, up to `config.budget_bytes`. Call
+    /// [`Store::enable_prefetch`] (or [`start_prefetcher`]) to read pages
+    /// ahead of `get_before`.
+    ///
+    /// [`start_prefetcher`]: crate::payload_cache::start_prefetcher
+    pub fn enable_payload_cache(&mut self, config: PayloadCacheConfig) {
+        self.payload_cache = PayloadCache::new(config);
+    }
+
+    /// Queue a read-ahead of the page before each `get_before` page. The
+    /// caller runs [`Store::prefetch`] for requests from the receiver.
+    pub fn enable_prefetch(&mut self) -> Option<Receiver<PrefetchRequest>> {
+        self.payload_cache.enable_prefetch()
+    }
+
+    /// Read the payloads of a page into the payload cache, returning how
+    /// many were read.
+    pub fn prefetch(&mut self, request: &PrefetchRequest) -> Result<usize> {
+        let turns = self.turn_store.get_before(
+            request.context_id,
+            request.before_turn_id,
+            request.limit,
+        )?;
+        let mut prefetched = 0;
+        for record in turns {
+            if self.payload_cache.contains(record.turn_id) {
+                continue;
+            }
+            let payload = self.read_payload(&record)?;
+            self.payload_cache
+                .insert_prefetched(record.turn_id, payload);
+            prefetched += 1;
+        }
+        Ok(prefetched)
+    }
+
+    pub fn payload_cache_stats(&self) -> PayloadCacheStats {
+        self.payload_cache.stats()
+    }
+
+    /// Sync every file a context creation or append writes (the turn
+    /// files, the blob pack, the key logs, the fs roots and the side logs)
+    /// in batches before they are acknowledged. Writers call
+    /// [`CommitPipeline::commit`] on [`Store::commit_pipeline`] after
+    /// releasing the store.
+    pub fn enable_group_commit(&mut self, config: CommitConfig) -> Result<()> {
+        if !config.enabled {
+            return Ok(());
+        }
+        let pipeline = Arc::new(CommitPipeline::new(config.window));
+        self.turn_store.track_files(&pipeline)?;
+        self.blob_store.track_files(Arc::clone(&pipeline))?;
+        self.keys.track_files(Arc::clone(&pipeline))?;
+        self.fs_roots.track_files(Arc::clone(&pipeline))?;
+        self.fs_meta.track_files(&pipeline)?;
+        self.metadata_overrides.track_files(&pipeline)?;
+        self.token_ledger.track_files(&pipeline)?;
+        self.custom_index.track_files(&pipeline)?;
+        self.previews.track_files(&pipeline)?;
+        self.ingest_log.track_files(&pipeline)?;
+        self.external_ids.track_files(&pipeline)?;
+        self.expiries.track_files(&pipeline)?;
+        self.commit = Some(pipeline);
+        Ok(())
+    }
+
+    pub fn commit_pipeline(&self) -> Option<Arc<CommitPipeline>> {
+        self.commit.clone()
+    }
+
+    /// Group commit accounting; None when group commit is off.
+    pub fn commit_stats(&self) -> Option<CommitStats> {
+        self.commit.as_ref().map(|c| c.stats())
+    }
+
+    /// Payload of a paged turn, from the payload cache when present.
+    fn cached_payload(&mut self, record: &TurnRecord) -> Result<Vec<u8>> {
+        if let Some(payload) = self.payload_cache.get(record.turn_id) {
+            return Ok(payload);
+        }
+        let payload = self.read_payload(record)?;
+        if self.payload_cache.is_enabled() {
+            self.payload_cache.insert(record.turn_id, payload.clone());
+        }
+        Ok(payload)
+    }
+
+    fn read_payload(&mut self, record: &TurnRecord) -> Result<Vec<u8>> {
+        let Some(key_id) = self.keys.turn_key(record.turn_id) else {
+            return self.blob_store.get(&record.payload_hash);
+        };
+        let key = self.keys.data_key(key_id)?;
+        let sealed = self
+            .blob_store
+            .get(&key.storage_hash(&record.payload_hash))?;
+        key.open(&record.payload_hash, &sealed)
+    }
+
+    /// Key for new data in a context. Unbound contexts inherit the key of
+    /// `inherit_from` (their parent or head turn), else get one per the
+    /// encryption mode.
+    fn context_data_key(
+        &mut self,
+        context_id: u64,
+        inherit_from: u64,
+        tag: Option<&str>,
+    ) -> Result<Option<DataKey>> {
+        let inherited = match inherit_from {
+            0 => None,
+            turn_id => self.keys.turn_key(turn_id),
+        };
+        self.keys.key_for_write(context_id, inherited, tag)
+    }
+
+    /// Blob reader for the fs snapshot visible at a turn.
+    fn turn_blobs(&mut self, turn_id: u64) -> Result<SealedBlobs<'_>> {
+        let key = match self.keys.turn_key(turn_id) {
+            Some(key_id) => Some(self.keys.data_key(key_id)?),
+            None => None,
+        };
+        Ok(SealedBlobs {
+            blobs: &mut self.blob_store,
+            key,
+        })
+    }
+
+    /// Attach a stored blob to a turn under `name`, replacing any attachment
+    /// with that name. The blob must be readable with the turn's key; when
+    /// `size` is given it must match the blob's content length.
+    pub fn attach_blob(
+        &mut self,
+        turn_id: u64,
+        name: &str,
+        mime_type: &str,
+        hash: [u8; 32],
+        size: Option<u64>,
+    ) -> Result<Attachment> {
+        self.turn_store.get_turn(turn_id)?;
+        self.check_turn_not_expired(turn_id)?;
+        let len = match self.turn_blobs(turn_id)?.get_blob(&hash) {
+            Ok(data) => data.len() as u64,
+            Err(StoreError::NotFound(_)) => {
+                return Err(StoreError::NotFound(format!("blob {}", hex::encode(hash))))
+            }
+            Err(err) => return Err(err),
+        };
+        if size.is_some_and(|size| size != len) {
+            return Err(StoreError::InvalidInput(format!(
+                "attachment size {} does not match the blob's {len} bytes",
+                size.unwrap_or_default()
+            )));
+        }
+        let (attachment, previous) = self
+            .attachments
+            .attach(turn_id, name, mime_type, len, hash)?;
+        let (storage_hash, stored_len) = self.attachment_storage_hash(turn_id, &hash)?;
+        self.attachment_refs.retain(storage_hash, stored_len);
+        if let Some(previous) = previous {
+            self.release_attachment(turn_id, &previous)?;
+        }
+        Ok(attachment)
+    }
+
+    /// Store `data` as a blob, sealed with the turn's key if it has one, and
+    /// attach it to the turn under `name`.
+    pub fn attach_data(
+        &mut self,
+        turn_id: u64,
+        name: &str,
+        mime_type: &str,
+        data: &[u8],
+    ) -> Result<Attachment> {
+        self.turn_store.get_turn(turn_id)?;
+        self.check_turn_not_expired(turn_id)?;
+        attachments::validate_name(name)?;
+        attachments::validate_mime_type(mime_type)?;
+        let hash = *blake3::hash(data).as_bytes();
+        let key = match self.keys.turn_key(turn_id) {
+            Some(key_id) => Some(self.keys.data_key(key_id)?),
+            None => None,
+        };
+        let (storage_hash, stored) = match &key {
+            Some(key) => (key.storage_hash(&hash), key.seal(&hash, data)?),
+            None => (hash, data.to_vec()),
+        };
+        self.blob_store.put_if_absent(storage_hash, &stored)?;
+        self.attach_blob(turn_id, name, mime_type, hash, Some(data.len() as u64))
+    }
+
+    /// Remove a turn's attachment. Its blob is reclaimed by the next blob
+    /// collection unless something else references it.
+    pub fn detach(&mut self, turn_id: u64, name: &str) -> Result<Attachment> {
+        let attachment = self.attachments.detach(turn_id, name)?;
+        self.release_attachment(turn_id, &attachment)?;
+        Ok(attachment)
+    }
+
+    fn release_attachment(&mut self, turn_id: u64, attachment: &Attachment) -> Result<()> {
+        let (storage_hash, _) = self.attachment_storage_hash(turn_id, &attachment.hash)?;
+        self.attachment_refs.release(&storage_hash);
+        Ok(())
+    }
+
+    /// Attachments of a turn, ordered by name.
+    pub fn turn_attachments(&self, turn_id: u64) -> Vec<Attachment> {
+        self.attachments.of_turn(turn_id)
+    }
+
+    /// Record the payload a turn arrived with before ingest transformers
+    /// rewrote it.
+    pub fn record_ingest(&mut self, turn_id: u64, audit: IngestAudit) -> Result<()> {
+        self.ingest_log.record(turn_id, audit)
+    }
+
+    /// Ingest audit entry of a turn whose payload was transformed.
+    pub fn turn_ingest(&self, turn_id: u64) -> Option<&IngestRecord> {
+        self.ingest_log.get(turn_id)
+    }
+
+    /// Index plugin entries of a turn.
+    /// Latest summary field preview on a context's head chain.
+    pub fn context_preview(&self, context_id: u64) -> Option<&str> {
+        if self.secondary_indexes.is_spilled(context_id) {
+            return self.head_preview(context_id);
+        }
+        self.secondary_indexes.preview(context_id)
+    }
+
+    /// Latest recorded preview on a context's head chain, read from the
+    /// preview log rather than the indexes.
+    fn head_preview(&self, context_id: u64) -> Option<&str> {
+        let mut current = self.turn_store.get_head(context_id).ok()?.head_turn_id;
+        while current != 0 {
+            if let Some(preview) = self.previews.get(current) {
+                return Some(preview);
+            }
+            current = self.turn_store.get_turn(current).ok()?.parent_turn_id;
+        }
+        None
+    }
+
+    pub fn turn_custom_index(&self, turn_id: u64) -> Option<&BTreeMap<String, Vec<String>>> {
+        self.custom_index.get(turn_id)
+    }
+
+    /// A turn's attachment and its content.
+    pub fn get_attachment(&mut self, turn_id: u64, name: &str) -> Result<(Attachment, Vec<u8>)> {
+        self.check_turn_not_expired(turn_id)?;
+        let attachment = self
+            .attachments
+            .get(turn_id, name)
+            .cloned()
+            .ok_or_else(|| StoreError::NotFound(format!("attachment {name:?}")))?;
+        let data = self.turn_blobs(turn_id)?.get_blob(&attachment.hash)?;
+        Ok((attachment, data))
+    }
+
+    /// All data-encryption keys (without key material).
+    pub fn list_keys(&self) -> Vec<KeyInfo> {
+        self.keys.list()
+    }
+
+    /// Whether a context holds sealed data: it is bound to a data key, or its
+    /// head turn (inherited by a fork that has not written yet) is sealed.
+    pub fn is_context_sealed(&self, context_id: u64) -> bool {
+        self.keys.context_key(context_id).is_some()
+            || self
+                .turn_store
+                .get_head(context_id)
+                .is_ok_and(|head| self.keys.turn_key(head.head_turn_id).is_some())
+    }
+
+    /// Shred the key of a context (and of forks that share it). Sealed
+    /// payloads and fs blobs of those contexts become unreadable.
+    pub fn shred_context_key(&mut self, context_id: u64) -> Result<KeyInfo> {
+        self.turn_store.get_head(context_id)?;
+        self.check_not_held(self.keys.context_key(context_id))?;
+        let previous = self.metadata_sealed_by(self.keys.context_key(context_id));
+        let info = self.keys.shred_context(context_id)?;
+        self.payload_cache.clear();
+        self.refresh_shredded_metadata(previous);
+        self.rebuild_blob_refs();
+        Ok(info)
+    }
+
+    /// Shred the shared key of a client tag.
+    pub fn shred_tag_key(&mut self, tag: &str) -> Result<KeyInfo> {
+        self.check_not_held(self.keys.find_tag_key(tag))?;
+        let previous = self.metadata_sealed_by(self.keys.find_tag_key(tag));
+        let info = self.keys.shred_tag(tag)?;
+        self.payload_cache.clear();
+        self.refresh_shredded_metadata(previous);
+        self.rebuild_blob_refs();
+        Ok(info)
+    }
+
+    /// Refuse to shred a key while a context sealed with it is on hold.
+    fn check_not_held(&self, key_id: Option<u64>) -> Result<()> {
+        let contexts = key_id.map_or_else(Vec::new, |id| self.keys.contexts_with_key(id));
+        match contexts.into_iter().find(|&c| self.holds.get(c).is_some()) {
+            Some(context_id) => Err(StoreError::InvalidInput(format!(
+                "context {context_id} is on legal hold"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Place a legal hold on a context, or replace the reason of its hold.
+    /// Contexts whose key was already shredded cannot be held.
+    pub fn place_hold(
+        &mut self,
+        context_id: u64,
+        reason: &str,
+        principal: &str,
+    ) -> Result<HoldEntry> {
+        self.turn_store.get_head(context_id)?;
+        if reason.trim().is_empty() {
+            return Err(StoreError::InvalidInput("hold reason is required".into()));
+        }
+        if self
+            .keys
+            .context_key(context_id)
+            .is_some_and(|key_id| self.keys.is_shredded(key_id))
+        {
+            return Err(StoreError::Shredded(format!(
+                "context {context_id} was already shredded"
+            )));
+        }
+        let entry = self.holds.record(
+            context_id,
+            HoldAction::Place,
+            reason.to_string(),
+            principal.to_string(),
+        )?;
+        self.secondary_indexes.set_on_hold(context_id, true);
+        let event = SystemEvent::new(
+            SystemEventKind::HoldPlaced,
+            format!("Legal hold placed: {}", entry.reason),
+        )
+        .with_principal(&entry.principal);
+        self.record_system_event(context_id, event);
+        Ok(entry)
+    }
+
+    /// Release a context's legal hold.
+    pub fn release_hold(
+        &mut self,
+        context_id: u64,
+        reason: &str,
+        principal: &str,
+    ) -> Result<HoldEntry> {
+        if self.holds.get(context_id).is_none() {
+            return Err(StoreError::NotFound(format!(
+                "context {context_id} is not on hold"
+            )));
+        }
+        let entry = self.holds.record(
+            context_id,
+            HoldAction::Release,
+            reason.to_string(),
+            principal.to_string(),
+        )?;
+        self.secondary_indexes.set_on_hold(context_id, false);
+        let summary = match entry.reason.as_str() {
+            "" => "Legal hold released".to_string(),
+            reason => format!("Legal hold released: {reason}"),
+        };
+        let event = SystemEvent::new(SystemEventKind::HoldReleased, summary)
+            .with_principal(&entry.principal);
+        self.record_system_event(context_id, event);
+        Ok(entry)
+    }
+
+    /// The placement holding a context, if it is on hold.
+    pub fn hold(&self, context_id: u64) -> Option<&HoldEntry> {
+        self.holds.get(context_id)
+    }
+
+    /// Placements and releases of a context's holds, oldest first.
+    pub fn hold_history(&self, context_id: u64) -> &[HoldEntry] {
+        self.holds.history(context_id)
+    }
+
+    /// A context's owner: the principal it acts on behalf of.
+    pub fn owner(&mut self, context_id: u64) -> Option<String> {
+        self.get_context_metadata(context_id)
+            .and_then(|m| m.provenance)
+            .and_then(|p| p.on_behalf_of)
+    }
+
+    /// Reassign a context to `owner`, recording the transfer in the
+    /// ownership log and re-indexing the context's `user`.
+    pub fn transfer_ownership(
+        &mut self,
+        context_id: u64,
+        owner: &str,
+        reason: &str,
+        principal: &str,
+    ) -> Result<OwnershipTransfer> {
+        self.turn_store.get_head(context_id)?;
+        self.check_not_expired(context_id)?;
+        let owner = owner.trim();
+        if owner.is_empty() {
+            return Err(StoreError::InvalidInput("owner is required".into()));
+        }
+        let previous_owner = self.owner(context_id);
+        if previous_owner.as_deref() == Some(owner) {
+            return Err(StoreError::InvalidInput(format!(
+                "context {context_id} is already owned by {owner}"
+            )));
+        }
+        let email = owner.contains('@').then(|| owner.to_string());
+        let patch = MetadataPatch {
+            // The previous owner's email must not stay on a new owner
+            // without one.
+            unset_provenance: match email {
+                Some(_) => Vec::new(),
+                None => vec!["on_behalf_of_email".to_string()],
+            },
+            provenance: Some(Provenance {
+                on_behalf_of: Some(owner.to_string()),
+                on_behalf_of_source: Some("transfer".to_string()),
+                on_behalf_of_email: email,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        // The audit trail only lists transfers that took effect.
+        self.apply_metadata_patch(context_id, &patch, &[])?;
+        self.ownership.record(
+            context_id,
+            previous_owner,
+            owner.to_string(),
+            reason.to_string(),
+            principal.to_string(),
+        )
+    }
+
+    /// Reassign every context matching a CQL filter to `owner`. Contexts
+    /// already owned by `owner` are left as they are.
+    pub fn transfer_ownership_matching(
+        &mut self,
+        filter: &str,
+        live_contexts: &HashSet<u64>,
+        owner: &str,
+        reason: &str,
+        principal: &str,
+    ) -> Result<BulkTransfer> {
+        if owner.trim().is_empty() {
+            return Err(StoreError::InvalidInput("owner is required".into()));
+        }
+        let result = self
+            .search_contexts(filter, live_contexts, None)
+            .map_err(|e| StoreError::InvalidInput(format!("invalid filter: {}", e.message)))?;
+        let mut ids = result.context_ids;
+        ids.sort_unstable();
+        let mut bulk = BulkTransfer::default();
+        for context_id in ids {
+            if self.owner(context_id).as_deref() == Some(owner.trim()) {
+                bulk.unchanged.push(context_id);
+                continue;
+            }
+            let entry = self.transfer_ownership(context_id, owner, reason, principal)?;
+            bulk.transferred.push(entry);
+        }
+        Ok(bulk)
+    }
+
+    /// Transfers of a context's ownership, oldest first.
+    pub fn ownership_history(&self, context_id: u64) -> &[OwnershipTransfer] {
+        self.ownership.history(context_id)
+    }
+
+    /// Move every context tagged `from` to `to`, which must not be in use
+    /// yet (merge into a tag in use instead).
+    pub fn rename_tag(
+        &mut self,
+        from: &str,
+        to: &str,
+        reason: &str,
+        principal: Option<&str>,
+    ) -> Result<TagChange> {
+        let (from, to) = (from.trim(), to.trim());
+        if from.is_empty() || to.is_empty() {
+            return Err(StoreError::InvalidInput("from and to are required".into()));
+        }
+        if from == to {
+            return Err(StoreError::InvalidInput(format!(
+                "tag {from:?} cannot be renamed to itself"
+            )));
+        }
+        if !self.secondary_indexes.lookup_tag_exact(to).is_empty() {
+            return Err(StoreError::InvalidInput(format!(
+                "tag {to:?} is already in use; merge into it instead"
+            )));
+        }
+        self.retag(
+            TagOperation::Rename,
+            vec![from.to_string()],
+            to,
+            reason,
+            principal,
+        )
+    }
+
+    /// Move every context tagged with one of `sources` to `into`, which may
+    /// already be in use.
+    pub fn merge_tags(
+        &mut self,
+        sources: &[String],
+        into: &str,
+        reason: &str,
+        principal: Option<&str>,
+    ) -> Result<TagChange> {
+        let into = into.trim();
+        if into.is_empty() {
+            return Err(StoreError::InvalidInput("into is required".into()));
+        }
+        let mut tags: Vec<String> = Vec::new();
+        for source in sources {
+            let source = source.trim();
+            if source.is_empty() {
+                return Err(StoreError::InvalidInput(
+                    "sources must not contain empty tags".into(),
+                ));
+            }
+            if source == into {
+                return Err(StoreError::InvalidInput(format!(
+                    "tag {into:?} cannot be merged into itself"
+                )));
+            }
+            if !tags.iter().any(|t| t == source) {
+                tags.push(source.to_string());
+            }
+        }
+        if tags.is_empty() {
+            return Err(StoreError::InvalidInput("sources are required".into()));
+        }
+        self.retag(TagOperation::Merge, tags, into, reason, principal)
+    }
+
+    /// Record a tag change in the audit log and apply it to every context
+    /// carrying one of `sources`, re-indexing each context's tag.
+    fn retag(
+        &mut self,
+        operation: TagOperation,
+        sources: Vec<String>,
+        target: &str,
+        reason: &str,
+        principal: Option<&str>,
+    ) -> Result<TagChange> {
+        let mut context_ids: Vec<u64> = sources
+            .iter()
+            .flat_map(|tag| self.secondary_indexes.lookup_tag_exact(tag))
+            .collect();
+        if context_ids.is_empty() {
+            return Err(StoreError::NotFound(format!(
+                "no contexts tagged {}",
+                sources.join(", ")
+            )));
+        }
+        context_ids.sort_unstable();
+        context_ids.dedup();
+
+        let change = TagChange {
+            operation,
+            sources,
+            target: target.to_string(),
+            context_ids,
+            reason: reason.to_string(),
+            principal: principal.map(str::to_string),
+            at_unix_ms: unix_ms(),
+        };
+        self.tag_changes.record(change.clone())?;
+        let patch = MetadataPatch {
+            client_tag: Some(target.to_string()),
+            ..Default::default()
+        };
+        for &context_id in &change.context_ids {
+            self.apply_metadata_patch(context_id, &patch, &[])?;
+        }
+        Ok(change)
+    }
+
+    /// Every tag rename and merge, oldest first.
+    pub fn tag_changes(&self) -> &[TagChange] {
+        self.tag_changes.changes()
+    }
+
+    /// Create a project. Its id must be unused.
+    pub fn create_project(
+        &mut self,
+        id: &str,
+        name: Option<&str>,
+        description: Option<&str>,
+        principal: Option<&str>,
+    ) -> Result<Project> {
+        self.projects.create(id, name, description, principal)
+    }
+
+    pub fn project(&self, id: &str) -> Option<&Project> {
+        self.projects.get(id)
+    }
+
+    /// Every project, ordered by id.
+    pub fn projects(&self) -> Vec<Project> {
+        self.projects.list().cloned().collect()
+    }
+
+    /// Add a context to a project. Returns false if it already belonged to it.
+    pub fn assign_project(
+        &mut self,
+        id: &str,
+        context_id: u64,
+        principal: Option<&str>,
+    ) -> Result<bool> {
+        self.turn_store.get_head(context_id)?;
+        let added = self.projects.assign(id, context_id, principal)?;
+        self.secondary_indexes.set_project(context_id, id, true);
+        Ok(added)
+    }
+
+    /// Remove a context from a project. Returns false if it did not belong
+    /// to it.
+    pub fn unassign_project(
+        &mut self,
+        id: &str,
+        context_id: u64,
+        principal: Option<&str>,
+    ) -> Result<bool> {
+        let removed = self.projects.unassign(id, context_id, principal)?;
+        self.secondary_indexes.set_project(context_id, id, false);
+        Ok(removed)
+    }
+
+    /// Contexts assigned to a project, ascending.
+    pub fn project_contexts(&self, id: &str) -> Result<Vec<u64>> {
+        if self.projects.get(id).is_none() {
+            return Err(StoreError::NotFound(format!("project {id}")));
+        }
+        Ok(self.projects.contexts(id))
+    }
+
+    /// Projects a context is assigned to, ordered by id.
+    pub fn context_projects(&self, context_id: u64) -> Vec<String> {
+        self.projects.of_context(context_id)
+    }
+
+    /// Context, turn and token totals of a project's contexts.
+    pub fn project_stats(&mut self, id: &str) -> Result<ProjectStats> {
+        let mut stats = ProjectStats::default();
+        for context_id in self.project_contexts(id)? {
+            let Ok(head) = self.turn_store.get_head(context_id) else {
+                continue;
+            };
+            stats.contexts += 1;
+            if head.head_turn_id != 0 {
+                stats.turns += u64::from(head.head_depth) + 1;
+            }
+            stats.tokens += self.token_ledger.context(context_id).total;
+            if self.holds.get(context_id).is_some() {
+                stats.on_hold += 1;
+            }
+            if let Some(tag) = self
+                .get_context_metadata(context_id)
+                .and_then(|m| m.client_tag)
+                .filter(|t| !t.is_empty())
+            {
+                *stats.client_tags.entry(tag).or_default() += 1;
+            }
+            let created = head.created_at_unix_ms;
+            stats.first_created_at_unix_ms = Some(
+                stats
+                    .first_created_at_unix_ms
+                    .map_or(created, |t| t.min(created)),
+            );
+            stats.last_created_at_unix_ms = Some(
+                stats
+                    .last_created_at_unix_ms
+                    .map_or(created, |t| t.max(created)),
+            );
+        }
+        Ok(stats)
     }
 
-    pub fn get_blob(&mut self, hash: &[u8; 32]) -> Result<Vec<u8>> {
-        self.blob_store.get(hash)
+    /// Metadata of the contexts sealed with a key, loaded before the key is
+    /// shredded so it can be unindexed even if it was not cached.
+    fn metadata_sealed_by(&mut self, key_id: Option<u64>) -> Vec<(u64, Option<ContextMetadata>)> {
+        let contexts = key_id.map_or_else(Vec::new, |id| self.keys.contexts_with_key(id));
+        contexts
+            .into_iter()
+            .map(|context_id| (context_id, self.get_context_metadata(context_id)))
+            .collect()
     }
 
+    /// Reload metadata of contexts whose key was shredded.
+    fn refresh_shredded_metadata(&mut self, previous: Vec<(u64, Option<ContextMetadata>)>) {
+        for (context_id, previous) in previous {
+            self.context_metadata_cache.remove(context_id);
+            let current = self.get_context_metadata(context_id);
+            self.secondary_indexes
+                .update_metadata(context_id, previous.as_ref(), current.as_ref());
+        }
+    }
+
+    /// Most recent contexts first, skipping expired ones.
     pub fn list_recent_contexts(&self, limit: u32) -> Vec<ContextHead> {
-        self.turn_store.list_recent_contexts(limit)
+        if !self.expiries.iter().any(ContextExpiry::is_tombstoned) {
+            return self.turn_store.list_recent_contexts(limit);
+        }
+        self.turn_store
+            .list_recent_contexts(u32::MAX)
+            .into_iter()
+            .filter(|head| !self.expiries.is_tombstoned(head.context_id))
+            .take(limit as usize)
+            .collect()
+    }
+
+    /// Contexts as they were at `as_of_ms`, most recent first. Contexts
+    /// that had expired or been tombstoned by then are left out; ones
+    /// tombstoned since are listed as they were.
+    pub fn list_contexts_as_of(&self, as_of_ms: u64, limit: u32) -> Vec<HeadAsOf> {
+        self.turn_store
+            .list_contexts_as_of(as_of_ms, u32::MAX)
+            .into_iter()
+            .filter(|entry| match self.expiries.get(entry.head.context_id) {
+                Some(expiry) => {
+                    expiry.expires_at_unix_ms > as_of_ms
+                        && expiry.expired_at_unix_ms.is_none_or(|at| at > as_of_ms)
+                }
+                None => true,
+            })
+            .take(limit as usize)
+            .collect()
+    }
+
+    /// Export rows for the given contexts, or for every context (most recent
+    /// first) when `context_ids` is None. Unknown contexts are skipped.
+    pub fn export_rows(&mut self, context_ids: Option<&[u64]>) -> Result<Vec<ExportRow>> {
+        let heads: Vec<ContextHead> = match context_ids {
+            Some(ids) => ids
+                .iter()
+                .filter_map(|&id| self.turn_store.get_head(id).ok())
+                .collect(),
+            None => self.turn_store.list_recent_contexts(u32::MAX),
+        };
+        let mut rows = Vec::with_capacity(heads.len());
+        for head in heads {
+            let metadata = self.get_context_metadata(head.context_id);
+            let provenance = metadata.as_ref().and_then(|m| m.provenance.as_ref());
+            let mut payload_bytes = 0u64;
+            let mut turn_id = head.head_turn_id;
+            while turn_id != 0 {
+                if let Ok(meta) = self.turn_store.get_turn_meta(turn_id) {
+                    payload_bytes += meta.uncompressed_len as u64;
+                }
+                turn_id = self.turn_store.get_turn(turn_id)?.parent_turn_id;
+            }
+            rows.push(ExportRow {
+                context_id: head.context_id,
+                client_tag: metadata.as_ref().and_then(|m| m.client_tag.clone()),
+                title: metadata.as_ref().and_then(|m| m.title.clone()),
+                user: provenance.and_then(|p| p.on_behalf_of.clone()),
+                service: provenance.and_then(|p| p.service_name.clone()),
+                host: provenance.and_then(|p| p.host_name.clone()),
+                created_at_unix_ms: head.created_at_unix_ms,
+                head_turn_id: head.head_turn_id,
+                head_depth: head.head_depth,
+                turns: if head.head_turn_id == 0 {
+                    0
+                } else {
+                    head.head_depth as u64 + 1
+                },
+                payload_bytes,
+                tokens: self.token_ledger.context(head.context_id).total,
+            });
+        }
+        Ok(rows)
     }
 
     // =========================================================================
     // CQL Search Methods
     // =========================================================================
 
+    /// Titles and previews of the contexts spilled from the indexes, for a
+    /// query that filters on them. Metadata is read through the cache
+    /// without caching it, so a scan of cold contexts does not evict hot
+    /// ones.
+    fn load_spilled_text(&mut self, expr: &cql::Expression) -> SpilledText {
+        let mut spilled = SpilledText::default();
+        let titles = cql::executor::filters_on(expr, FieldName::Title);
+        let previews = cql::executor::filters_on(expr, FieldName::Preview);
+        if !titles && !previews {
+            return spilled;
+        }
+        for context_id in self.secondary_indexes.spilled_contexts() {
+            if titles {
+                let metadata = match self.context_metadata_cache.peek(context_id) {
+                    Some(cached) => cached.clone(),
+                    None => self.load_context_metadata(context_id),
+                };
+                if let Some(title) = metadata.and_then(|m| m.title) {
+                    spilled.titles.insert(context_id, title);
+                }
+            }
+            if previews {
+                if let Some(preview) = self.head_preview(context_id) {
+                    spilled.previews.insert(context_id, preview.to_string());
+                }
+            }
+        }
+        spilled
+    }
+
     /// Search contexts using a CQL query string.
     pub fn search_contexts(
-        &self,
+        &mut self,
         query: &str,
         live_contexts: &HashSet<u64>,
         limit: Option<u32>,
@@ -326,10 +3147,19 @@ impl Store {
         let parsed = cql::parse(query)?;
 
         // Execute the query
-        let matching_ids = cql::execute(&parsed.ast, &self.secondary_indexes, live_contexts)?;
+        let spilled = self.load_spilled_text(&parsed.ast);
+        let matching_ids = cql::execute_with_spilled(
+            &parsed.ast,
+            &self.secondary_indexes,
+            live_contexts,
+            &spilled,
+        )?;
 
         // Sort by context_id descending (most recent first) and apply limit
-        let mut sorted_ids: Vec<u64> = matching_ids.into_iter().collect();
+        let mut sorted_ids: Vec<u64> = matching_ids
+            .into_iter()
+            .filter(|&id| !self.expiries.is_tombstoned(id))
+            .collect();
         sorted_ids.sort_by(|a, b| b.cmp(a));
 
         let total_count = sorted_ids.len();
@@ -349,7 +3179,7 @@ impl Store {
 
     /// Search contexts using a pre-parsed CQL query.
     pub fn search_contexts_parsed(
-        &self,
+        &mut self,
         query: &CqlQuery,
         live_contexts: &HashSet<u64>,
         limit: Option<u32>,
@@ -357,10 +3187,19 @@ impl Store {
         let start = std::time::Instant::now();
 
         // Execute the query
-        let matching_ids = cql::execute(&query.ast, &self.secondary_indexes, live_contexts)?;
+        let spilled = self.load_spilled_text(&query.ast);
+        let matching_ids = cql::execute_with_spilled(
+            &query.ast,
+            &self.secondary_indexes,
+            live_contexts,
+            &spilled,
+        )?;
 
         // Sort by context_id descending (most recent first) and apply limit
-        let mut sorted_ids: Vec<u64> = matching_ids.into_iter().collect();
+        let mut sorted_ids: Vec<u64> = matching_ids
+            .into_iter()
+            .filter(|&id| !self.expiries.is_tombstoned(id))
+            .collect();
         sorted_ids.sort_by(|a, b| b.cmp(a));
 
         let total_count = sorted_ids.len();
@@ -378,9 +3217,21 @@ impl Store {
         })
     }
 
-    /// Get secondary index statistics.
+    /// Label namespaces in use, with context counts.
+    pub fn label_namespaces(&self) -> Vec<(String, usize)> {
+        self.secondary_indexes.label_namespaces()
+    }
+
+    /// Values used within a label namespace, with context counts.
+    pub fn label_namespace_values(&self, namespace: &str) -> Vec<(String, usize)> {
+        self.secondary_indexes.label_namespace_values(namespace)
+    }
+
+    /// Get secondary index and metadata cache statistics.
     pub fn index_stats(&self) -> IndexStats {
-        self.secondary_indexes.stats()
+        let mut stats = self.secondary_indexes.stats();
+        stats.metadata_cache = self.context_metadata_cache.stats();
+        stats
     }
 
     // =========================================================================
@@ -390,15 +3241,160 @@ impl Store {
     /// Attach a filesystem snapshot to a turn.
     /// The tree objects and file blobs must already exist in the blob store.
     pub fn attach_fs(&mut self, turn_id: u64, fs_root_hash: [u8; 32]) -> Result<()> {
+        self.attach_fs_with_meta(turn_id, fs_root_hash, None)
+    }
+
+    /// Attach a filesystem snapshot to a turn along with the working-directory
+    /// metadata it was captured with. Attaching without metadata drops any
+    /// metadata recorded for the turn's previous snapshot.
+    pub fn attach_fs_with_meta(
+        &mut self,
+        turn_id: u64,
+        fs_root_hash: [u8; 32],
+        meta: Option<SnapshotMeta>,
+    ) -> Result<()> {
         // Verify the turn exists
         let _ = self.turn_store.get_turn(turn_id)?;
+        self.check_turn_not_expired(turn_id)?;
 
         // Verify the root tree exists in blob store
-        if !self.blob_store.contains(&fs_root_hash) {
+        if !self.turn_blobs(turn_id)?.contains(&fs_root_hash) {
             return Err(StoreError::NotFound("fs root tree blob".into()));
         }
 
-        self.fs_roots.attach(turn_id, fs_root_hash)
+        self.set_fs_root(turn_id, fs_root_hash)?;
+        self.record_fs_attachment(turn_id, meta)?;
+        self.record_fs_event(turn_id, fs_root_hash);
+        Ok(())
+    }
+
+    /// Content hash and content of every blob of a snapshot root as seen
+    /// from `turn_id`: the trees and the files they reference. Blobs that
+    /// were never uploaded are left out.
+    pub fn fs_snapshot_blobs(
+        &mut self,
+        turn_id: u64,
+        root: [u8; 32],
+    ) -> Result<Vec<([u8; 32], Vec<u8>)>> {
+        let mut blobs = self.turn_blobs(turn_id)?;
+        let mut seen = HashSet::new();
+        let mut out = Vec::new();
+        let mut pending = vec![(root, true)];
+        while let Some((hash, is_dir)) = pending.pop() {
+            if !seen.insert(hash) {
+                continue;
+            }
+            match blobs.get_blob(&hash) {
+                Ok(data) => out.push((hash, data)),
+                Err(StoreError::NotFound(_)) => continue,
+                Err(err) => return Err(err),
+            }
+            if is_dir {
+                pending.extend(tree_children(&mut blobs, &hash));
+            }
+        }
+        Ok(out)
+    }
+
+    /// Build a snapshot from `base_root` plus `changes` and attach it to a
+    /// turn. `blobs` (new file contents and subtrees) are stored first. With no
+    /// base the overlay applies to the snapshot the turn currently sees, or
+    /// to an empty tree.
+    pub fn attach_fs_overlay(
+        &mut self,
+        turn_id: u64,
+        base_root: Option<[u8; 32]>,
+        blobs: &[&[u8]],
+        changes: &[OverlayChange],
+    ) -> Result<OverlayResult> {
+        let _ = self.turn_store.get_turn(turn_id)?;
+        self.check_turn_not_expired(turn_id)?;
+        let base_root = base_root.or_else(|| self.get_fs_root(turn_id));
+
+        let mut sink = self.turn_blobs(turn_id)?;
+        if let Some(base) = &base_root {
+            if !sink.contains(base) {
+                return Err(StoreError::NotFound("fs base root tree blob".into()));
+            }
+        }
+        for data in blobs {
+            sink.put_blob(*blake3::hash(data).as_bytes(), data)?;
+        }
+        let result = apply_overlay(&mut sink, base_root.as_ref(), changes)?;
+
+        self.set_fs_root(turn_id, result.root_hash)?;
+        self.record_fs_attachment(turn_id, None)?;
+        self.record_fs_event(turn_id, result.root_hash);
+        Ok(result)
+    }
+
+    /// Record an attached snapshot in the timeline of every context whose
+    /// head is the turn it was attached to.
+    fn record_fs_event(&mut self, turn_id: u64, root: [u8; 32]) {
+        if self.system_events.is_none() {
+            return;
+        }
+        let contexts: Vec<u64> = self
+            .turn_store
+            .heads()
+            .filter(|head| head.head_turn_id == turn_id)
+            .map(|head| head.context_id)
+            .collect();
+        for context_id in contexts {
+            let event = SystemEvent::new(
+                SystemEventKind::FsAttached,
+                format!("Filesystem snapshot attached to turn {turn_id}"),
+            )
+            .with_turn(turn_id)
+            .with_attr("root_hash", hex::encode(root));
+            self.record_system_event(context_id, event);
+        }
+    }
+
+    /// Attach a root to a turn, moving the turn's blob reference from the
+    /// root it replaces. The new root is retained first so shared subtrees
+    /// never drop to zero in between.
+    fn set_fs_root(&mut self, turn_id: u64, root: [u8; 32]) -> Result<()> {
+        let previous = self.fs_roots.get(turn_id);
+        self.fs_roots.attach(turn_id, root)?;
+        self.retain_fs_root(turn_id, root)?;
+        if let Some(previous) = previous {
+            self.release_fs_root(turn_id, previous)?;
+        }
+        Ok(())
+    }
+
+    /// Store snapshot metadata for a newly attached root and re-index the
+    /// snapshots of every context whose head now sees it.
+    fn record_fs_attachment(&mut self, turn_id: u64, meta: Option<SnapshotMeta>) -> Result<()> {
+        match meta {
+            Some(meta) => self.fs_meta.set(turn_id, meta)?,
+            None => self.fs_meta.clear(turn_id)?,
+        }
+        let heads: Vec<(u64, u64)> = self
+            .turn_store
+            .heads()
+            .filter(|head| self.turn_store.is_ancestor(turn_id, head.head_turn_id))
+            .map(|head| (head.context_id, head.head_turn_id))
+            .collect();
+        for (context_id, head_turn_id) in heads {
+            let fs = self.chain_fs_stats(head_turn_id);
+            self.secondary_indexes.update_fs(context_id, fs);
+        }
+        Ok(())
+    }
+
+    /// The snapshot a turn sees (direct or inherited), with the turn it is
+    /// attached to and its recorded metadata.
+    pub fn get_fs_snapshot(&self, turn_id: u64) -> Option<FsSnapshot> {
+        let (attached_turn_id, root_hash) = self
+            .fs_roots
+            .get_inherited_with_source(turn_id, &self.turn_store)?;
+        Some(FsSnapshot {
+            root_hash,
+            attached_turn_id,
+            meta: self.fs_meta.get(attached_turn_id).cloned(),
+        })
     }
 
     /// Get the filesystem root hash for a turn (direct or inherited).
@@ -412,14 +3408,22 @@ impl Store {
     }
 
     /// List entries at a path in the filesystem snapshot for a turn.
-    pub fn list_fs_entries(&mut self, turn_id: u64, path: &str) -> Result<Vec<TreeEntry>> {
+    pub fn list_fs_entries(
+        &mut self,
+        turn_id: u64,
+        path: &str,
+        follow_symlinks: bool,
+        deadline: &Deadline,
+    ) -> Result<Vec<TreeEntry>> {
+        self.check_turn_not_expired(turn_id)?;
         let fs_root = self
             .fs_roots
             .get_inherited(turn_id, &self.turn_store)
             .ok_or_else(|| StoreError::NotFound("no fs snapshot for turn".into()))?;
 
+        let mut blobs = self.turn_blobs(turn_id)?;
         let (tree_hash, is_dir) =
-            crate::fs_store::resolve_path(&mut self.blob_store, &fs_root, path)?;
+            crate::fs_store::resolve_path(&mut blobs, &fs_root, path, follow_symlinks, deadline)?;
 
         if !is_dir {
             return Err(StoreError::InvalidInput(format!(
@@ -427,17 +3431,614 @@ impl Store {
             )));
         }
 
-        crate::fs_store::load_tree_entries(&mut self.blob_store, &tree_hash)
+        crate::fs_store::load_tree_entries(&mut blobs, &tree_hash)
     }
 
     /// Get file content at a path in the filesystem snapshot for a turn.
-    pub fn get_fs_file(&mut self, turn_id: u64, path: &str) -> Result<(Vec<u8>, TreeEntry)> {
+    /// Without `follow_symlinks` a symlink's content is its target.
+    pub fn get_fs_file(
+        &mut self,
+        turn_id: u64,
+        path: &str,
+        follow_symlinks: bool,
+        deadline: &Deadline,
+    ) -> Result<(Vec<u8>, TreeEntry)> {
+        self.check_turn_not_expired(turn_id)?;
+        let fs_root = self
+            .fs_roots
+            .get_inherited(turn_id, &self.turn_store)
+            .ok_or_else(|| StoreError::NotFound("no fs snapshot for turn".into()))?;
+
+        let mut blobs = self.turn_blobs(turn_id)?;
+        crate::fs_store::get_file_at_path(&mut blobs, &fs_root, path, follow_symlinks, deadline)
+    }
+
+    /// Entry and content length of the file at a path in a turn's snapshot,
+    /// without reading the content.
+    pub fn stat_fs_file(
+        &mut self,
+        turn_id: u64,
+        path: &str,
+        follow_symlinks: bool,
+        deadline: &Deadline,
+    ) -> Result<(TreeEntry, u64)> {
+        self.check_turn_not_expired(turn_id)?;
+        let fs_root = self
+            .fs_roots
+            .get_inherited(turn_id, &self.turn_store)
+            .ok_or_else(|| StoreError::NotFound("no fs snapshot for turn".into()))?;
+
+        let mut blobs = self.turn_blobs(turn_id)?;
+        let entry = crate::fs_store::file_entry_at_path(
+            &mut blobs,
+            &fs_root,
+            path,
+            follow_symlinks,
+            deadline,
+        )?;
+        let len = blobs.blob_len(&entry.hash_array()?).unwrap_or(entry.size);
+        Ok((entry, len))
+    }
+
+    /// Read up to `len` bytes of a file entry's content starting at `offset`.
+    pub fn read_fs_range(
+        &mut self,
+        turn_id: u64,
+        entry: &TreeEntry,
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<u8>> {
+        self.check_turn_not_expired(turn_id)?;
+        let hash = entry.hash_array()?;
+        self.turn_blobs(turn_id)?.get_blob_range(&hash, offset, len)
+    }
+
+    /// Manifest of the snapshot a turn sees (direct or inherited), with its
+    /// fetch plan.
+    pub fn fs_manifest(&mut self, turn_id: u64, deadline: &Deadline) -> Result<Manifest> {
+        self.check_turn_not_expired(turn_id)?;
         let fs_root = self
             .fs_roots
             .get_inherited(turn_id, &self.turn_store)
             .ok_or_else(|| StoreError::NotFound("no fs snapshot for turn".into()))?;
 
-        crate::fs_store::get_file_at_path(&mut self.blob_store, &fs_root, path)
+        let mut blobs = self.turn_blobs(turn_id)?;
+        let entries = manifest_entries(&mut blobs, &fs_root, deadline)?;
+        Ok(Manifest::new(turn_id, &fs_root, entries))
+    }
+
+    /// Content of a blob of a turn's snapshot, by content hash.
+    pub fn read_fs_blob(&mut self, turn_id: u64, hash: &[u8; 32]) -> Result<Vec<u8>> {
+        self.check_turn_not_expired(turn_id)?;
+        self.turn_blobs(turn_id)?.get_blob(hash)
+    }
+
+    /// Read the target of a symlink entry from a turn's snapshot.
+    pub fn read_fs_symlink(&mut self, turn_id: u64, entry: &TreeEntry) -> Result<String> {
+        self.check_turn_not_expired(turn_id)?;
+        let mut blobs = self.turn_blobs(turn_id)?;
+        crate::fs_store::read_symlink_target(&mut blobs, entry)
+    }
+
+    /// Every link of a context's hash chain, root first.
+    pub fn context_chain(&self, context_id: u64) -> Result<Vec<ChainLink>> {
+        self.turn_store.context_chain(context_id)
+    }
+
+    /// Every context's head turn and chain head, by context id.
+    pub fn chain_heads(&self) -> Vec<(u64, u64, [u8; 32])> {
+        self.turn_store.chain_heads()
+    }
+
+    /// Stored chain hash of a turn; the root chain hash for turn 0.
+    pub fn turn_chain_hash(&self, turn_id: u64) -> Result<[u8; 32]> {
+        if turn_id == 0 {
+            return Ok(crate::turn_store::ROOT_CHAIN_HASH);
+        }
+        self.turn_store.chain_hash(turn_id)
+    }
+
+    /// Inclusion proof of a turn in a context's history.
+    pub fn turn_proof(&self, turn_id: u64, context_id: u64) -> Result<TurnProof> {
+        self.check_not_expired(context_id)?;
+        self.turn_store.turn_proof(turn_id, context_id)
+    }
+
+    /// Check the store for tampering or corruption: every turn's chain hash
+    /// is recomputed from its record and metadata, and every cross-file
+    /// invariant is verified.
+    pub fn fsck(&mut self) -> FsckReport {
+        let chains = self.turn_store.verify_chains();
+        let invariants = self.check_invariants(VerifyScope::Full, false);
+        FsckReport {
+            ok: chains.is_ok() && invariants.ok,
+            chains,
+            invariants,
+        }
+    }
+
+    /// Check that the turn log, chain hashes, heads, head history, blob
+    /// pack and secondary indexes agree (see [`crate::invariants`]).
+    pub fn verify_invariants(&mut self, scope: VerifyScope) -> InvariantReport {
+        self.check_invariants(scope, true)
+    }
+
+    fn check_invariants(&mut self, scope: VerifyScope, check_chains: bool) -> InvariantReport {
+        let mut report = InvariantReport::new(scope);
+        let heads: HashMap<u64, ContextHead> = self
+            .turn_store
+            .heads()
+            .map(|head| (head.context_id, head.clone()))
+            .collect();
+        let contexts = InvariantReport::sample(scope, heads.keys().copied().collect());
+
+        // Turns to check: all of them, or the head chains of the sample.
+        let mut turn_ids: Vec<u64> = match scope {
+            VerifyScope::Full => self.turn_store.turns().map(|t| t.turn_id).collect(),
+            VerifyScope::Sample(_) => {
+                let mut seen = HashSet::new();
+                for context_id in &contexts {
+                    let mut turn_id = heads[context_id].head_turn_id;
+                    while turn_id != 0 && seen.insert(turn_id) {
+                        turn_id = match self.turn_store.get_turn(turn_id) {
+                            Ok(record) => record.parent_turn_id,
+                            Err(_) => 0,
+                        };
+                    }
+                }
+                seen.into_iter().collect()
+            }
+        };
+        turn_ids.sort_unstable();
+
+        for turn_id in turn_ids {
+            let Ok(record) = self.turn_store.get_turn(turn_id) else {
+                // A sampled head pointing at a missing turn; reported below.
+                continue;
+            };
+            report.turns_checked += 1;
+            if record.parent_turn_id == 0 {
+                if record.depth != 0 {
+                    report.violation(
+                        Invariant::DepthFollowsParent,
+                        None,
+                        Some(turn_id),
+                        format!("root turn has depth {}", record.depth),
+                    );
+                }
+            } else {
+                match self.turn_store.get_turn(record.parent_turn_id) {
+                    Ok(parent) if parent.depth.checked_add(1) != Some(record.depth) => report
+                        .violation(
+                            Invariant::DepthFollowsParent,
+                            None,
+                            Some(turn_id),
+                            format!(
+                                "depth {} but parent {} has depth {}",
+                                record.depth, parent.turn_id, parent.depth
+                            ),
+                        ),
+                    Ok(_) => {}
+                    Err(_) => report.violation(
+                        Invariant::ParentExists,
+                        None,
+                        Some(turn_id),
+                        format!("parent turn {} not found", record.parent_turn_id),
+                    ),
+                }
+            }
+            if check_chains && !self.turn_store.chain_hash_matches(turn_id) {
+                report.violation(
+                    Invariant::ChainHash,
+                    None,
+                    Some(turn_id),
+                    "stored chain hash does not match".into(),
+                );
+            }
+            // Payloads sealed with a shredded or locked key cannot be located.
+            let storage_hash = match self.keys.turn_key(turn_id) {
+                None => Some(record.payload_hash),
+                Some(key_id) => self
+                    .keys
+                    .data_key(key_id)
+                    .ok()
+                    .map(|key| key.storage_hash(&record.payload_hash)),
+            };
+            if storage_hash.is_some_and(|hash| !self.blob_store.contains(&hash)) {
+                report.violation(
+                    Invariant::PayloadStored,
+                    None,
+                    Some(turn_id),
+                    format!("payload {} missing", hex::encode(record.payload_hash)),
+                );
+            }
+        }
+
+        for context_id in contexts {
+            let head = &heads[&context_id];
+            report.contexts_checked += 1;
+            if head.head_turn_id == 0 {
+                if head.head_depth != 0 {
+                    report.violation(
+                        Invariant::HeadDepth,
+                        Some(context_id),
+                        None,
+                        format!("empty context has depth {}", head.head_depth),
+                    );
+                }
+            } else {
+                match self.turn_store.get_turn(head.head_turn_id) {
+                    Ok(turn) if turn.depth != head.head_depth => report.violation(
+                        Invariant::HeadDepth,
+                        Some(context_id),
+                        Some(head.head_turn_id),
+                        format!(
+                            "head depth {} but head turn has depth {}",
+                            head.head_depth, turn.depth
+                        ),
+                    ),
+                    Ok(_) => {}
+                    Err(_) => report.violation(
+                        Invariant::HeadTurnExists,
+                        Some(context_id),
+                        Some(head.head_turn_id),
+                        "head turn not found".into(),
+                    ),
+                }
+            }
+            let last_move = self
+                .turn_store
+                .head_history(context_id)
+                .ok()
+                .and_then(|history| history.last().map(|m| (m.turn_id, m.depth)));
+            if last_move != Some((head.head_turn_id, head.head_depth)) {
+                report.violation(
+                    Invariant::HeadHistory,
+                    Some(context_id),
+                    None,
+                    format!("latest head move {last_move:?} is not the head"),
+                );
+            }
+
+            // Empty contexts are indexed with their first turn.
+            if !self.indexed || head.head_turn_id == 0 {
+                continue;
+            }
+            if !self.secondary_indexes.all_contexts().contains(&context_id) {
+                report.violation(
+                    Invariant::IndexCoversContext,
+                    Some(context_id),
+                    None,
+                    "context is not indexed".into(),
+                );
+                continue;
+            }
+            if !self
+                .secondary_indexes
+                .has_depth(context_id, head.head_depth)
+            {
+                report.violation(
+                    Invariant::IndexDepth,
+                    Some(context_id),
+                    None,
+                    format!("not indexed at head depth {}", head.head_depth),
+                );
+            }
+            let held = self.holds.get(context_id).is_some();
+            if self.secondary_indexes.is_on_hold(context_id) != held {
+                report.violation(
+                    Invariant::IndexHold,
+                    Some(context_id),
+                    None,
+                    format!("hold log says on_hold = {held}, index disagrees"),
+                );
+            }
+        }
+        report
+    }
+
+    /// Reachability of snapshot blobs from the current fs roots and from
+    /// orphan roots left behind by re-attaching a turn. References are
+    /// recounted, as for [`Store::collect_blobs`].
+    pub fn fs_reachability(&mut self) -> FsReachability {
+        let (payload_refs, fs_refs, attachment_refs, _) = self.count_blob_refs();
+        self.fs_reachability_with(&payload_refs, &fs_refs, &attachment_refs)
+    }
+
+    /// Walk the orphan roots, counting stored blobs that nothing live
+    /// references. Trees of shredded turns are not walked.
+    fn fs_reachability_with(
+        &mut self,
+        payload_refs: &RefCounts,
+        fs_refs: &RefCounts,
+        attachment_refs: &RefCounts,
+    ) -> FsReachability {
+        let held = self.held_turns();
+        let (held_roots, orphans): (Vec<_>, Vec<_>) = self
+            .fs_roots
+            .superseded()
+            .into_iter()
+            .partition(|(turn_id, _)| held.contains(turn_id));
+        let reachable = fs_refs.stats();
+        let mut reach = FsReachability {
+            live_roots: self.fs_roots.stats().entries_total as u64,
+            orphan_roots: orphans.len() as u64,
+            held_roots: held_roots.len() as u64,
+            reachable_blobs: reachable.unique_blobs,
+            reachable_bytes: reachable.unique_bytes,
+            ..Default::default()
+        };
+        let is_live = |hash: &[u8; 32]| {
+            payload_refs.count(hash) > 0
+                || fs_refs.count(hash) > 0
+                || attachment_refs.count(hash) > 0
+        };
+        let mut seen = HashSet::new();
+        for (turn_id, root) in orphans {
+            let key = match self.keys.turn_key(turn_id) {
+                Some(key_id) => match self.keys.data_key(key_id) {
+                    Ok(key) => Some(key),
+                    Err(_) => continue,
+                },
+                None => None,
+            };
+            let mut blobs = SealedBlobs {
+                blobs: &mut self.blob_store,
+                key,
+            };
+            let mut pending = vec![(root, true)];
+            while let Some((hash, is_dir)) = pending.pop() {
+                let storage_hash = blobs.storage_hash(&hash);
+                // A live tree's entries are live too.
+                if !seen.insert(storage_hash) || is_live(&storage_hash) {
+                    continue;
+                }
+                let Some(len) = blobs.blobs.raw_len(&storage_hash) else {
+                    continue;
+                };
+                reach.unreachable_blobs += 1;
+                reach.unreachable_bytes += len as u64;
+                if is_dir {
+                    pending.extend(tree_children(&mut blobs, &hash));
+                }
+            }
+        }
+        reach
+    }
+
+    /// Turns of contexts on legal hold: the ancestors of every head each
+    /// held context has had, so turns since rewound away count too.
+    fn held_turns(&self) -> HashSet<u64> {
+        let mut turns = HashSet::new();
+        for context_id in self.holds.held() {
+            let Ok(moves) = self.turn_store.head_history(context_id) else {
+                continue;
+            };
+            for head in moves {
+                let mut turn_id = head.turn_id;
+                while turn_id != 0 && turns.insert(turn_id) {
+                    turn_id = self
+                        .turn_store
+                        .get_turn(turn_id)
+                        .map_or(0, |turn| turn.parent_turn_id);
+                }
+            }
+        }
+        turns
+    }
+
+    /// Drop orphan fs roots from the roots index, recording each in the
+    /// audit trail (`fs/pruned_roots.jsonl`) first. Their blobs become
+    /// garbage for the next [`Store::collect_blobs`]. Superseded roots of
+    /// contexts on legal hold are kept. Returns the reachability before
+    /// pruning; with `dry_run` nothing is changed. Refused while sealed data
+    /// cannot be read, like the collection.
+    pub fn prune_fs_roots(&mut self, dry_run: bool) -> Result<FsReachability> {
+        let (payload_refs, fs_refs, attachment_refs, complete) = self.count_blob_refs();
+        if !complete {
+            return Err(StoreError::InvalidInput(
+                "cannot prune fs roots while sealed data is locked".into(),
+            ));
+        }
+        let reach = self.fs_reachability_with(&payload_refs, &fs_refs, &attachment_refs);
+        if dry_run || reach.orphan_roots == 0 {
+            return Ok(reach);
+        }
+        let held = self.held_turns();
+        let orphans: Vec<(u64, [u8; 32])> = self
+            .fs_roots
+            .superseded()
+            .into_iter()
+            .filter(|(turn_id, _)| !held.contains(turn_id))
+            .collect();
+        self.fs_pruned.record(&orphans)?;
+        let pruned = self.fs_roots.compact(|turn_id| held.contains(&turn_id))?;
+        tracing::info!(
+            pruned_roots = pruned.len(),
+            unreachable_bytes = reach.unreachable_bytes,
+            "pruned orphan fs roots"
+        );
+        Ok(reach)
+    }
+
+    /// Orphan fs roots pruned so far, oldest first.
+    pub fn pruned_fs_roots(&self) -> &[PrunedRoot] {
+        self.fs_pruned.entries()
+    }
+
+    /// Live and dead blobs in the pack. A blob is live while a turn payload,
+    /// an attached fs snapshot or a named attachment references it.
+    pub fn blob_gc_stats(&self) -> BlobGcStats {
+        let mut stats = BlobGcStats::default();
+        for (hash, entry) in self.blob_store.entries() {
+            if self.blob_is_live(hash) {
+                stats.live_blobs += 1;
+                stats.live_bytes += entry.record_len();
+            } else {
+                stats.dead_blobs += 1;
+                stats.dead_bytes += entry.record_len();
+            }
+        }
+        stats
+    }
+
+    /// Remove unreferenced blobs from the pack. References are recounted
+    /// from the turn log and fs roots first, so the sweep does not depend on
+    /// incremental counts. Blobs written since the previous collection are
+    /// kept (see [`BlobStore::sweep`]). Refused while sealed data cannot be
+    /// read, since its blobs could not be told apart from garbage.
+    pub fn collect_blobs(&mut self, dry_run: bool) -> Result<SweepStats> {
+        if !self.rebuild_blob_refs() {
+            return Err(StoreError::InvalidInput(
+                "cannot collect blobs while sealed data is locked".into(),
+            ));
+        }
+        let payload_refs = &self.payload_refs;
+        let fs_refs = &self.fs_refs;
+        let attachment_refs = &self.attachment_refs;
+        let start = std::time::Instant::now();
+        let sweep = self.blob_store.sweep(
+            |hash| {
+                payload_refs.count(hash) > 0
+                    || fs_refs.count(hash) > 0
+                    || attachment_refs.count(hash) > 0
+            },
+            dry_run,
+        )?;
+        let elapsed = start.elapsed().as_secs_f64();
+        if !dry_run && sweep.swept_blobs > 0 && elapsed > 0.0 {
+            self.collection_rate = Some(sweep.pack_bytes_after as f64 / elapsed);
+        }
+        Ok(sweep)
+    }
+
+    /// What [`Store::collect_blobs`] would do now, computed without changing
+    /// the store or its reference counts. Contexts whose key was shredded
+    /// since the last collection expire: the collection drops their sealed
+    /// blobs. The duration estimate assumes the pack is copied at the rate of
+    /// the last collection in this process, or [`DEFAULT_COLLECTION_RATE`].
+    pub fn compaction_plan(&mut self) -> Result<CompactionPlan> {
+        let (payload_refs, fs_refs, attachment_refs, complete) = self.count_blob_refs();
+        let blob_stats = self.blob_store.stats();
+        let sweep = if complete {
+            self.blob_store.sweep(
+                |hash| {
+                    payload_refs.count(hash) > 0
+                        || fs_refs.count(hash) > 0
+                        || attachment_refs.count(hash) > 0
+                },
+                true,
+            )?
+        } else {
+            // The collection would be refused, so nothing is reclaimed.
+            SweepStats {
+                kept_blobs: blob_stats.blobs_total as u64,
+                pack_bytes_before: blob_stats.pack_bytes,
+                pack_bytes_after: blob_stats.pack_bytes,
+                idx_bytes_before: blob_stats.idx_bytes,
+                idx_bytes_after: blob_stats.idx_bytes,
+                ..Default::default()
+            }
+        };
+
+        let last_collection = self.blob_store.last_sweep_unix_ms();
+        let mut contexts_to_expire = Vec::new();
+        for key in self.keys.list() {
+            let Some(shredded_at) = key.shredded_at_unix_ms else {
+                continue;
+            };
+            if !complete || last_collection.is_some_and(|at| at > shredded_at) {
+                continue;
+            }
+            for context_id in self.keys.contexts_with_key(key.id) {
+                contexts_to_expire.push(ExpiringContext {
+                    context_id: context_id.to_string(),
+                    key_id: key.key_id.clone(),
+                    shredded_at_unix_ms: shredded_at,
+                });
+            }
+        }
+        contexts_to_expire.sort_by_key(|c| c.context_id.parse::<u64>().unwrap_or(0));
+
+        let mut blockers = Vec::new();
+        if !complete {
+            blockers.push(CompactionBlocker {
+                kind: "sealed_data_locked",
+                detail: "sealed data cannot be read without CXDB_MASTER_KEY, so the \
+                         collection would be refused"
+                    .into(),
+                blobs: 0,
+                bytes: 0,
+            });
+        }
+        if sweep.young_blobs > 0 {
+            blockers.push(CompactionBlocker {
+                kind: "young_blobs",
+                detail: "unreferenced blobs written since the last collection are kept \
+                         until the next one"
+                    .into(),
+                blobs: sweep.young_blobs,
+                bytes: sweep.young_bytes,
+            });
+        }
+
+        let (rate, estimate_basis) = match self.collection_rate {
+            Some(rate) => (rate, "last_collection"),
+            None => (DEFAULT_COLLECTION_RATE, "default"),
+        };
+        let estimated_duration_ms = if sweep.swept_blobs > 0 {
+            (sweep.pack_bytes_after as f64 / rate * 1000.0).ceil() as u64
+        } else {
+            0
+        };
+        let fs = self.fs_reachability_with(&payload_refs, &fs_refs, &attachment_refs);
+        let roots_bytes = self.fs_roots.stats().file_bytes;
+        let roots_bytes_after = if complete {
+            let held = self.held_turns();
+            self.fs_roots
+                .compacted_bytes(|turn_id| held.contains(&turn_id))
+                .min(roots_bytes)
+        } else {
+            roots_bytes
+        };
+
+        let file = |name: &'static str, before: u64, after: u64| CompactionFile {
+            file: name,
+            bytes_before: before,
+            bytes_after: after,
+            reclaim_bytes: before.saturating_sub(after),
+        };
+        Ok(CompactionPlan {
+            runnable: complete,
+            reclaim_blobs: sweep.swept_blobs,
+            reclaim_bytes: (sweep.pack_bytes_before + sweep.idx_bytes_before + roots_bytes)
+                .saturating_sub(sweep.pack_bytes_after + sweep.idx_bytes_after + roots_bytes_after),
+            files: vec![
+                file(
+                    "blobs/blobs.pack",
+                    sweep.pack_bytes_before,
+                    sweep.pack_bytes_after,
+                ),
+                file(
+                    "blobs/blobs.idx",
+                    sweep.idx_bytes_before,
+                    sweep.idx_bytes_after,
+                ),
+                file("fs/roots.idx", roots_bytes, roots_bytes_after),
+            ],
+            contexts_to_expire,
+            estimated_duration_ms,
+            estimate_basis,
+            last_collection_unix_ms: last_collection,
+            fs,
+            blockers,
+        })
+    }
+
+    fn blob_is_live(&self, hash: &[u8; 32]) -> bool {
+        self.payload_refs.count(hash) > 0
+            || self.fs_refs.count(hash) > 0
+            || self.attachment_refs.count(hash) > 0
     }
 
     pub fn stats(&mut self) -> StoreStats {
@@ -459,6 +4060,9 @@ impl Store {
             fs_roots_total: fs_stats.entries_total,
             fs_roots_bytes: fs_stats.file_bytes,
             fs_content_bytes,
+            dedup: self.payload_refs.stats(),
+            blob_gc: self.blob_gc_stats(),
+            append_side_effect_failures: self.append_side_effect_failures,
         }
     }
 
@@ -522,6 +4126,47 @@ impl Store {
     }
 }
 
+/// Provenance spawn reason of contexts created by [`Store::clone_context`].
+pub const CLONE_SPAWN_REASON: &str = "clone";
+
+/// A context copied by [`Store::clone_context`].
+#[derive(Debug, Clone)]
+pub struct ContextClone {
+    pub source_context_id: u64,
+    /// Last turn of the source that was copied.
+    pub up_to_turn_id: u64,
+    /// Head of the new context.
+    pub head: ContextHead,
+    pub turns: u32,
+    pub fs_snapshots: u32,
+    pub attachments: u32,
+}
+
+/// Most contexts returned by [`Store::context_tree`].
+pub const MAX_TREE_NODES: usize = 1000;
+
+/// A context's place in its lineage tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextTreeNode {
+    pub context_id: u64,
+    pub head_depth: u32,
+    pub title: Option<String>,
+    /// Provenance spawn reason, `rollover` for rolled-over contexts.
+    pub spawn_reason: Option<String>,
+    pub rolled_over_from: Option<u64>,
+    pub rolled_over_to: Option<u64>,
+    /// Contexts spawned or rolled over from this one, by id.
+    pub children: Vec<ContextTreeNode>,
+}
+
+/// Lineage tree returned by [`Store::context_tree`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextTree {
+    pub root: ContextTreeNode,
+    /// Whether contexts were left out past [`MAX_TREE_NODES`].
+    pub truncated: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct StoreStats {
     pub turns_total: usize,
@@ -537,6 +4182,98 @@ pub struct StoreStats {
     pub fs_roots_total: usize,
     pub fs_roots_bytes: u64,
     pub fs_content_bytes: u64,
+    pub dedup: DedupStats,
+    pub blob_gc: BlobGcStats,
+    /// Steps that failed after a turn was appended, e.g. recording its
+    /// tokens or preview. The turns themselves were kept.
+    pub append_side_effect_failures: u64,
+}
+
+/// Blob liveness totals. Byte counts are pack bytes, so `dead_bytes` is what
+/// a collection would reclaim once the blobs age past the sweep epoch.
+#[derive(Debug, Clone, Default)]
+pub struct BlobGcStats {
+    pub live_blobs: u64,
+    pub live_bytes: u64,
+    pub dead_blobs: u64,
+    pub dead_bytes: u64,
+}
+
+/// Count the references of a turn's fs root into `refs` (see
+/// [`Store::retain_fs_root`]).
+fn retain_fs_tree(
+    keys: &mut KeyRing,
+    blob_store: &mut BlobStore,
+    refs: &mut RefCounts,
+    turn_id: u64,
+    root: [u8; 32],
+) -> Result<()> {
+    let key = match keys.turn_key(turn_id) {
+        Some(key_id) => Some(keys.data_key(key_id)?),
+        None => None,
+    };
+    let mut blobs = SealedBlobs {
+        blobs: blob_store,
+        key,
+    };
+    let mut pending = vec![(root, true)];
+    while let Some((hash, is_dir)) = pending.pop() {
+        let storage_hash = blobs.storage_hash(&hash);
+        let len = blobs.blobs.raw_len(&storage_hash).unwrap_or(0) as u64;
+        if refs.retain(storage_hash, len) == 1 && is_dir {
+            pending.extend(tree_children(&mut blobs, &hash));
+        }
+    }
+    Ok(())
+}
+
+/// Entries of a tree blob as (hash, is_dir); empty if the tree cannot be read.
+fn tree_children(blobs: &mut SealedBlobs<'_>, tree_hash: &[u8; 32]) -> Vec<([u8; 32], bool)> {
+    load_tree_entries(blobs, tree_hash)
+        .map(|entries| {
+            entries
+                .iter()
+                .filter_map(|entry| {
+                    let hash = entry.hash_array().ok()?;
+                    Some((hash, entry.kind_enum() == EntryKind::Directory))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Decompress an appended payload and check it against its declared length
+/// and BLAKE3 hash.
+pub fn decode_payload(
+    compression: u32,
+    uncompressed_len: u32,
+    content_hash: &[u8; 32],
+    payload_bytes: &[u8],
+) -> Result<Vec<u8>> {
+    let raw_bytes = match compression {
+        0 => payload_bytes.to_vec(),
+        1 => zstd::decode_all(payload_bytes)
+            .map_err(|e| StoreError::InvalidInput(format!("zstd decode failed: {e}")))?,
+        other => {
+            return Err(StoreError::InvalidInput(format!(
+                "unsupported compression: {other}"
+            )))
+        }
+    };
+
+    if raw_bytes.len() as u32 != uncompressed_len {
+        return Err(StoreError::InvalidInput(
+            "uncompressed length mismatch".into(),
+        ));
+    }
+
+    let mut hasher = Hasher::new();
+    hasher.update(&raw_bytes);
+    let hash = hasher.finalize();
+    if hash.as_bytes() != content_hash {
+        return Err(StoreError::InvalidInput("content hash mismatch".into()));
+    }
+    Ok(raw_bytes)
 }
 
 /// Extract context metadata from a msgpack-encoded ConversationItem payload.
@@ -0,0 +1,484 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-context subscriptions.
+//!
+//! A subscription asks to be notified of new turns on one context,
+//! optionally only turns of some declared types. Its target is either a
+//! webhook URL, which receives a `POST` per turn, or an email-like sink
+//! identifier (`oncall@example.com`, `mailto:...`), for which a
+//! `subscription_notified` event is published on the event bus for the
+//! configured event sink to hand to a mailer.
+//!
+//! Webhook notifications go through the shared [`Webhooks`] queue, which
+//! retries failed deliveries and refuses internal addresses. A subscription
+//! whose context has had no new turns for `CXDB_SUBSCRIPTION_IDLE_SECS` is
+//! removed, as are the subscriptions of expired contexts.
+//!
+//! Subscriptions are persisted in `meta/subscriptions.json`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::error::{Result, StoreError};
+use crate::events::{EventBus, StoreEvent};
+use crate::util::{env_u64, unix_ms};
+use crate::webhooks::{WebhookOrigin, Webhooks};
+
+const DEFAULT_IDLE_SECS: u64 = 7 * 24 * 60 * 60;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Subscriptions allowed on one context.
+pub const MAX_SUBSCRIPTIONS_PER_CONTEXT: usize = 32;
+const MAX_TARGET_LEN: usize = 2048;
+
+/// Subscription settings, loaded from the environment.
+#[derive(Debug, Clone)]
+pub struct SubscriptionConfig {
+    /// Context inactivity after which its subscriptions are removed.
+    pub idle: Duration,
+}
+
+impl SubscriptionConfig {
+    pub fn from_env() -> Self {
+        Self {
+            idle: Duration::from_secs(env_u64("CXDB_SUBSCRIPTION_IDLE_SECS", DEFAULT_IDLE_SECS)),
+        }
+    }
+}
+
+impl Default for SubscriptionConfig {
+    fn default() -> Self {
+        Self {
+            idle: Duration::from_secs(DEFAULT_IDLE_SECS),
+        }
+    }
+}
+
+/// Body of `POST /v1/contexts/:id/subscriptions`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscriptionSpec {
+    pub target: String,
+    /// Declared type ids to notify about; empty for every turn.
+    #[serde(default)]
+    pub turn_types: Vec<String>,
+}
+
+/// How a subscription's notifications are delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetKind {
+    /// `POST` to an `http(s)://` URL.
+    Webhook,
+    /// A `subscription_notified` event for the event sink.
+    Sink,
+}
+
+impl TargetKind {
+    /// Classify a target, rejecting anything that is neither an http(s) URL
+    /// nor an email-like identifier.
+    pub fn of(target: &str) -> Result<Self> {
+        if target.is_empty() || target.len() > MAX_TARGET_LEN {
+            return Err(StoreError::InvalidInput(format!(
+                "target must be 1-{MAX_TARGET_LEN} bytes"
+            )));
+        }
+        if let Some(rest) = target
+            .strip_prefix("https://")
+            .or_else(|| target.strip_prefix("http://"))
+        {
+            if rest.is_empty() || rest.starts_with('/') {
+                return Err(StoreError::InvalidInput("target URL has no host".into()));
+            }
+            return Ok(Self::Webhook);
+        }
+        let address = target.strip_prefix("mailto:").unwrap_or(target);
+        match address.split_once('@') {
+            Some((user, host))
+                if !user.is_empty()
+                    && !host.is_empty()
+                    && !host.contains('@')
+                    && !address.chars().any(|c| c.is_whitespace() || c == '/') =>
+            {
+                Ok(Self::Sink)
+            }
+            _ => Err(StoreError::InvalidInput(
+                "target must be an http(s) URL or an email-like identifier".into(),
+            )),
+        }
+    }
+}
+
+/// Public view of a subscription.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionInfo {
+    pub id: String,
+    pub context_id: String,
+    pub target: String,
+    pub kind: TargetKind,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub turn_types: Vec<String>,
+    pub created_at_unix_ms: u64,
+    /// Last new turn on the context, or creation of the subscription.
+    pub last_activity_at_unix_ms: u64,
+    #[serde(default)]
+    pub delivered: u64,
+    /// Notifications dropped after their last attempt failed.
+    #[serde(default)]
+    pub failed: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// One notification owed to a subscription.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    pub subscription_id: u64,
+    pub context_id: u64,
+    pub target: String,
+    pub kind: TargetKind,
+    pub turn_id: String,
+    pub parent_turn_id: String,
+    pub depth: u32,
+    pub declared_type_id: Option<String>,
+    pub declared_type_version: Option<u32>,
+}
+
+#[derive(Default)]
+struct SubscriptionTable {
+    next_id: u64,
+    subscriptions: BTreeMap<u64, SubscriptionInfo>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedSubscriptions {
+    next_id: u64,
+    subscriptions: Vec<SubscriptionInfo>,
+}
+
+/// Registry of subscriptions shared between the HTTP API and the dispatcher.
+pub struct Subscriptions {
+    path: PathBuf,
+    table: RwLock<SubscriptionTable>,
+    /// Set when activity or delivery counters changed since the last save.
+    dirty: AtomicBool,
+}
+
+impl Subscriptions {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("subscriptions.json");
+        let mut table = SubscriptionTable {
+            next_id: 1,
+            ..Default::default()
+        };
+        if path.exists() {
+            let bytes = fs::read(&path)?;
+            let persisted: PersistedSubscriptions = serde_json::from_slice(&bytes)
+                .map_err(|e| StoreError::Corrupt(format!("subscriptions.json: {e}")))?;
+            table.next_id = persisted.next_id.max(1);
+            for info in persisted.subscriptions {
+                let id: u64 = info
+                    .id
+                    .parse()
+                    .map_err(|_| StoreError::Corrupt(format!("subscription id {}", info.id)))?;
+                table.next_id = table.next_id.max(id + 1);
+                table.subscriptions.insert(id, info);
+            }
+        }
+        Ok(Self {
+            path,
+            table: RwLock::new(table),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    /// Subscribe to a context. The caller checks that the context exists.
+    pub fn create(&self, context_id: u64, spec: SubscriptionSpec) -> Result<SubscriptionInfo> {
+        let target = spec.target.trim().to_string();
+        let kind = TargetKind::of(&target)?;
+        if spec.turn_types.iter().any(|t| t.trim().is_empty()) {
+            return Err(StoreError::InvalidInput(
+                "turn_types must not contain empty type ids".into(),
+            ));
+        }
+        let mut turn_types = spec.turn_types;
+        turn_types.sort();
+        turn_types.dedup();
+
+        let mut table = self.table.write().unwrap();
+        let context = context_id.to_string();
+        let existing = table
+            .subscriptions
+            .values()
+            .filter(|s| s.context_id == context)
+            .count();
+        if existing >= MAX_SUBSCRIPTIONS_PER_CONTEXT {
+            return Err(StoreError::InvalidInput(format!(
+                "context already has {MAX_SUBSCRIPTIONS_PER_CONTEXT} subscriptions"
+            )));
+        }
+        let id = table.next_id;
+        table.next_id += 1;
+        let now = unix_ms();
+        let info = SubscriptionInfo {
+            id: id.to_string(),
+            context_id: context,
+            target,
+            kind,
+            turn_types,
+            created_at_unix_ms: now,
+            last_activity_at_unix_ms: now,
+            delivered: 0,
+            failed: 0,
+            last_error: None,
+        };
+        table.subscriptions.insert(id, info.clone());
+        if let Err(err) = self.persist(&table) {
+            table.subscriptions.remove(&id);
+            return Err(err);
+        }
+        Ok(info)
+    }
+
+    pub fn get(&self, id: u64) -> Option<SubscriptionInfo> {
+        self.table.read().unwrap().subscriptions.get(&id).cloned()
+    }
+
+    /// A context's subscriptions, oldest first.
+    pub fn list(&self, context_id: u64) -> Vec<SubscriptionInfo> {
+        let context = context_id.to_string();
+        self.table
+            .read()
+            .unwrap()
+            .subscriptions
+            .values()
+            .filter(|s| s.context_id == context)
+            .cloned()
+            .collect()
+    }
+
+    /// Remove one of a context's subscriptions. Returns false if it did not
+    /// exist.
+    pub fn remove(&self, context_id: u64, id: u64) -> Result<bool> {
+        let mut table = self.table.write().unwrap();
+        match table.subscriptions.get(&id) {
+            Some(info) if info.context_id == context_id.to_string() => {}
+            _ => return Ok(false),
+        }
+        let info = table.subscriptions.remove(&id).expect("checked above");
+        if let Err(err) = self.persist(&table) {
+            table.subscriptions.insert(id, info);
+            return Err(err);
+        }
+        Ok(true)
+    }
+
+    /// Update activity for a store event and return the notifications it
+    /// owes. Subscriptions of an expired context are removed.
+    pub fn on_event(&self, event: &StoreEvent) -> Vec<Delivery> {
+        match event {
+            StoreEvent::TurnAppended {
+                context_id,
+                turn_id,
+                parent_turn_id,
+                depth,
+                declared_type_id,
+                declared_type_version,
+            } => {
+                let now = unix_ms();
+                let mut table = self.table.write().unwrap();
+                let mut deliveries = Vec::new();
+                for (id, info) in table.subscriptions.iter_mut() {
+                    if &info.context_id != context_id {
+                        continue;
+                    }
+                    info.last_activity_at_unix_ms = now;
+                    self.dirty.store(true, Ordering::SeqCst);
+                    let wanted = info.turn_types.is_empty()
+                        || declared_type_id
+                            .as_ref()
+                            .is_some_and(|t| info.turn_types.contains(t));
+                    if !wanted {
+                        continue;
+                    }
+                    deliveries.push(Delivery {
+                        subscription_id: *id,
+                        context_id: context_id.parse().unwrap_or(0),
+                        target: info.target.clone(),
+                        kind: info.kind,
+                        turn_id: turn_id.clone(),
+                        parent_turn_id: parent_turn_id.clone(),
+                        depth: *depth,
+                        declared_type_id: declared_type_id.clone(),
+                        declared_type_version: *declared_type_version,
+                    });
+                }
+                deliveries
+            }
+            StoreEvent::ContextExpired { context_id, .. } => {
+                self.remove_where(|info| &info.context_id == context_id);
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Remove subscriptions whose context has been inactive for `idle`
+    /// as of `now_ms`, and save pending activity updates. Returns the
+    /// removed subscriptions.
+    pub fn expire_idle(&self, now_ms: u64, idle: Duration) -> Vec<SubscriptionInfo> {
+        let idle_ms = idle.as_millis() as u64;
+        let removed = self
+            .remove_where(|info| now_ms.saturating_sub(info.last_activity_at_unix_ms) >= idle_ms);
+        if removed.is_empty() && self.dirty.swap(false, Ordering::SeqCst) {
+            let table = self.table.read().unwrap();
+            if let Err(e) = self.persist(&table) {
+                eprintln!("subscriptions: failed to save: {e}");
+                self.dirty.store(true, Ordering::SeqCst);
+            }
+        }
+        removed
+    }
+
+    /// Record the final outcome of a notification.
+    pub fn record_delivery(&self, id: u64, result: std::result::Result<(), String>) {
+        if let Some(info) = self.table.write().unwrap().subscriptions.get_mut(&id) {
+            match result {
+                Ok(()) => info.delivered += 1,
+                Err(_) => info.failed += 1,
+            }
+            info.last_error = result.err();
+            self.dirty.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn remove_where(&self, pred: impl Fn(&SubscriptionInfo) -> bool) -> Vec<SubscriptionInfo> {
+        let mut table = self.table.write().unwrap();
+        let ids: Vec<u64> = table
+            .subscriptions
+            .iter()
+            .filter(|(_, info)| pred(info))
+            .map(|(id, _)| *id)
+            .collect();
+        if ids.is_empty() {
+            return Vec::new();
+        }
+        let removed: Vec<SubscriptionInfo> = ids
+            .iter()
+            .filter_map(|id| table.subscriptions.remove(id))
+            .collect();
+        self.dirty.store(false, Ordering::SeqCst);
+        if let Err(e) = self.persist(&table) {
+            eprintln!("subscriptions: failed to save: {e}");
+            self.dirty.store(true, Ordering::SeqCst);
+        }
+        removed
+    }
+
+    fn persist(&self, table: &SubscriptionTable) -> Result<()> {
+        let persisted = PersistedSubscriptions {
+            next_id: table.next_id,
+            subscriptions: table.subscriptions.values().cloned().collect(),
+        };
+        let bytes = serde_json::to_vec_pretty(&persisted)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, bytes)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+impl WebhookOrigin for Subscriptions {
+    fn kind(&self) -> &'static str {
+        "subscription"
+    }
+
+    fn is_active(&self, id: u64) -> bool {
+        self.get(id).is_some()
+    }
+
+    fn record_delivery(&self, id: u64, result: std::result::Result<(), String>) {
+        Subscriptions::record_delivery(self, id, result);
+    }
+}
+
+/// Start the thread that delivers subscription notifications.
+pub fn start_subscription_dispatcher(
+    config: SubscriptionConfig,
+    subscriptions: Arc<Subscriptions>,
+    webhooks: Arc<Webhooks>,
+    event_bus: Arc<EventBus>,
+) -> thread::JoinHandle<()> {
+    let subscriber = event_bus.subscribe();
+    thread::spawn(move || {
+        let mut last_sweep = Instant::now();
+        loop {
+            let mut events = Vec::new();
+            if let Some(event) = subscriber.recv_timeout(POLL_INTERVAL) {
+                events.push(event);
+                while let Some(event) = subscriber.try_recv() {
+                    events.push(event);
+                }
+            }
+            for event in &events {
+                for delivery in subscriptions.on_event(event) {
+                    match delivery.kind {
+                        TargetKind::Webhook => webhooks.send(
+                            &delivery.target,
+                            webhook_body(&delivery),
+                            Arc::clone(&subscriptions) as Arc<dyn WebhookOrigin>,
+                            delivery.subscription_id,
+                        ),
+                        TargetKind::Sink => {
+                            event_bus.publish(notified_event(&delivery));
+                            subscriptions.record_delivery(delivery.subscription_id, Ok(()));
+                        }
+                    }
+                }
+            }
+
+            if last_sweep.elapsed() >= SWEEP_INTERVAL {
+                last_sweep = Instant::now();
+                for info in subscriptions.expire_idle(unix_ms(), config.idle) {
+                    eprintln!(
+                        "subscription {} on context {} expired after inactivity",
+                        info.id, info.context_id
+                    );
+                }
+            }
+        }
+    })
+}
+
+fn notified_event(delivery: &Delivery) -> StoreEvent {
+    StoreEvent::SubscriptionNotified {
+        subscription_id: delivery.subscription_id.to_string(),
+        context_id: delivery.context_id.to_string(),
+        target: delivery.target.clone(),
+        turn_id: delivery.turn_id.clone(),
+        declared_type_id: delivery.declared_type_id.clone(),
+    }
+}
+
+fn webhook_body(delivery: &Delivery) -> serde_json::Value {
+    json!({
+        "subscription_id": delivery.subscription_id.to_string(),
+        "context_id": delivery.context_id.to_string(),
+        "turn_id": delivery.turn_id,
+        "parent_turn_id": delivery.parent_turn_id,
+        "depth": delivery.depth,
+        "declared_type_id": delivery.declared_type_id,
+        "declared_type_version": delivery.declared_type_version,
+        "notified_at": unix_ms(),
+    })
+}
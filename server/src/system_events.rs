@@ -0,0 +1,232 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Server-appended system event turns.
+//!
+//! Operational milestones (a context forked, a filesystem snapshot attached,
+//! a legal hold placed or released) are recorded in the conversation timeline
+//! as typed `cxdb:SystemEvent` turns appended at the context's head, so they
+//! render inline in the viewer. The type is declared by the embedded
+//! `cxdb.system-events.v1` bundle (see [`crate::registry::builtin`]), so
+//! clients cannot publish their own versions of it.
+//!
+//! `CXDB_SYSTEM_EVENTS` selects the recorded milestones, as a comma-separated
+//! list of kinds or `all`:
+//!
+//! ```text
+//! CXDB_SYSTEM_EVENTS=context_forked,fs_attached
+//! ```
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use rmpv::Value;
+
+use crate::error::{Result, StoreError};
+use crate::events::EventBus;
+
+/// Declared type id of system event turns.
+pub const SYSTEM_EVENT_TYPE_ID: &str = "cxdb:SystemEvent";
+/// Declared type version of system event turns.
+pub const SYSTEM_EVENT_TYPE_VERSION: u32 = 1;
+
+/// A milestone that can be recorded as a system event turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SystemEventKind {
+    /// A context was created from another context's turn.
+    ContextForked,
+    /// A filesystem snapshot was attached to a context's head turn.
+    FsAttached,
+    /// A legal hold was placed on a context.
+    HoldPlaced,
+    /// A context's legal hold was released.
+    HoldReleased,
+    /// A context was rolled over into a new context.
+    ContextRolledOver,
+}
+
+impl SystemEventKind {
+    pub const ALL: [SystemEventKind; 5] = [
+        SystemEventKind::ContextForked,
+        SystemEventKind::FsAttached,
+        SystemEventKind::HoldPlaced,
+        SystemEventKind::HoldReleased,
+        SystemEventKind::ContextRolledOver,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SystemEventKind::ContextForked => "context_forked",
+            SystemEventKind::FsAttached => "fs_attached",
+            SystemEventKind::HoldPlaced => "hold_placed",
+            SystemEventKind::HoldReleased => "hold_released",
+            SystemEventKind::ContextRolledOver => "context_rolled_over",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == name)
+    }
+}
+
+/// Milestones recorded as system event turns, loaded from the environment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SystemEventConfig {
+    pub kinds: HashSet<SystemEventKind>,
+}
+
+impl SystemEventConfig {
+    /// Returns None when `CXDB_SYSTEM_EVENTS` is unset or empty.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var("CXDB_SYSTEM_EVENTS") {
+            Ok(list) if !list.trim().is_empty() => Self::parse(&list).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Parse a comma-separated list of kinds, or `all`.
+    pub fn parse(list: &str) -> Result<Self> {
+        let mut kinds = HashSet::new();
+        for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            if name == "all" {
+                kinds.extend(SystemEventKind::ALL);
+                continue;
+            }
+            let kind = SystemEventKind::parse(name).ok_or_else(|| {
+                StoreError::InvalidInput(format!(
+                    "CXDB_SYSTEM_EVENTS: unknown kind {name:?} (expected {} or all)",
+                    SystemEventKind::ALL.map(|k| k.as_str()).join(", ")
+                ))
+            })?;
+            kinds.insert(kind);
+        }
+        Ok(Self { kinds })
+    }
+
+    pub fn records(&self, kind: SystemEventKind) -> bool {
+        self.kinds.contains(&kind)
+    }
+
+    /// Kinds in declaration order, for logging.
+    pub fn describe(&self) -> String {
+        SystemEventKind::ALL
+            .into_iter()
+            .filter(|kind| self.records(*kind))
+            .map(|kind| kind.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// A milestone to record in a context's timeline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemEvent {
+    pub kind: SystemEventKind,
+    /// One-line, human-readable description.
+    pub summary: String,
+    /// Turn the milestone refers to, such as the fork point.
+    pub turn_id: Option<u64>,
+    /// Principal that caused the milestone, when known.
+    pub principal: Option<String>,
+    pub attrs: BTreeMap<String, String>,
+}
+
+impl SystemEvent {
+    pub fn new(kind: SystemEventKind, summary: impl Into<String>) -> Self {
+        Self {
+            kind,
+            summary: summary.into(),
+            turn_id: None,
+            principal: None,
+            attrs: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_turn(mut self, turn_id: u64) -> Self {
+        self.turn_id = Some(turn_id);
+        self
+    }
+
+    pub fn with_principal(mut self, principal: &str) -> Self {
+        self.principal = Some(principal.to_string());
+        self
+    }
+
+    pub fn with_attr(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.attrs.insert(key.to_string(), value.into());
+        self
+    }
+}
+
+/// System event recording state held by the store.
+pub struct SystemEvents {
+    config: SystemEventConfig,
+    event_bus: Arc<EventBus>,
+}
+
+impl SystemEvents {
+    pub fn new(config: SystemEventConfig, event_bus: Arc<EventBus>) -> Self {
+        Self { config, event_bus }
+    }
+
+    pub fn records(&self, kind: SystemEventKind) -> bool {
+        self.config.records(kind)
+    }
+
+    pub fn event_bus(&self) -> &Arc<EventBus> {
+        &self.event_bus
+    }
+}
+
+/// Encode a system event turn payload as a msgpack map with numeric tags
+/// matching the `cxdb:SystemEvent` v1 descriptor.
+pub fn encode_system_event_payload(
+    event: &SystemEvent,
+    context_id: u64,
+    occurred_at_unix_ms: u64,
+) -> Result<Vec<u8>> {
+    let mut fields = vec![
+        (Value::from(1), Value::from(event.kind.as_str())),
+        (Value::from(2), Value::from(event.summary.as_str())),
+        (Value::from(3), Value::from(occurred_at_unix_ms)),
+        (Value::from(4), Value::from(context_id)),
+    ];
+    if let Some(turn_id) = event.turn_id {
+        fields.push((Value::from(5), Value::from(turn_id)));
+    }
+    if let Some(principal) = &event.principal {
+        fields.push((Value::from(6), Value::from(principal.as_str())));
+    }
+    if !event.attrs.is_empty() {
+        let attrs = event
+            .attrs
+            .iter()
+            .map(|(k, v)| (Value::from(k.as_str()), Value::from(v.as_str())))
+            .collect();
+        fields.push((Value::from(7), Value::Map(attrs)));
+    }
+
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &Value::Map(fields))
+        .map_err(|e| StoreError::InvalidInput(format!("msgpack encode error: {e}")))?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kinds() {
+        let config = SystemEventConfig::parse("fs_attached, context_forked").unwrap();
+        assert!(config.records(SystemEventKind::FsAttached));
+        assert!(config.records(SystemEventKind::ContextForked));
+        assert!(!config.records(SystemEventKind::HoldPlaced));
+        assert_eq!(config.describe(), "context_forked,fs_attached");
+
+        let all = SystemEventConfig::parse("all").unwrap();
+        assert_eq!(all.kinds.len(), SystemEventKind::ALL.len());
+
+        assert!(SystemEventConfig::parse("context_forked,lease_acquired").is_err());
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Client tag renames and merges.
+//!
+//! A rename moves every context tagged `from` to an unused tag; a merge
+//! folds one or more source tags into a target tag that may already be in
+//! use. Both retag contexts through their metadata overrides, so turns are
+//! never rewritten. Each change is appended to a JSON-lines log with the
+//! contexts it moved and the principal that made it; the log is the audit
+//! trail of tag administration.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::jsonl_log::open_log;
+
+/// Kind of tag change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagOperation {
+    Rename,
+    Merge,
+}
+
+impl TagOperation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TagOperation::Rename => "rename",
+            TagOperation::Merge => "merge",
+        }
+    }
+}
+
+/// One rename or merge of client tags.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagChange {
+    pub operation: TagOperation,
+    /// Tags the contexts were moved off.
+    pub sources: Vec<String>,
+    pub target: String,
+    /// Contexts retagged, by id.
+    pub context_ids: Vec<u64>,
+    #[serde(default)]
+    pub reason: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub principal: Option<String>,
+    pub at_unix_ms: u64,
+}
+
+/// Body of a rename request.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TagRenameRequest {
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub reason: String,
+}
+
+/// Body of a merge request.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TagMergeRequest {
+    pub sources: Vec<String>,
+    pub into: String,
+    #[serde(default)]
+    pub reason: String,
+}
+
+pub struct TagChangeLog {
+    file: File,
+    changes: Vec<TagChange>,
+}
+
+impl TagChangeLog {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("tag_changes.jsonl");
+        let (file, changes) = open_log::<TagChange>(&path)?;
+
+        Ok(Self { file, changes })
+    }
+
+    /// Every recorded change, oldest first.
+    pub fn changes(&self) -> &[TagChange] {
+        &self.changes
+    }
+
+    /// Append a change. The caller applies it to the contexts' metadata.
+    pub fn record(&mut self, change: TagChange) -> Result<()> {
+        let mut line = serde_json::to_vec(&change)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.changes.push(change);
+        Ok(())
+    }
+}
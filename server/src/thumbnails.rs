@@ -0,0 +1,399 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Downscaled previews of image blobs.
+//!
+//! When `CXDB_THUMBNAILS` is on, `GET /v1/blobs/:hash/thumbnail` decodes a
+//! PNG, JPEG, GIF or WebP blob (an attachment or a file in a filesystem
+//! snapshot) and returns it scaled to the requested width. Thumbnails are
+//! keyed by content hash and width, so a rendered preview never goes stale,
+//! and kept in an in-memory LRU with a byte budget. Images with transparency
+//! are encoded as PNG, everything else as JPEG. Images are never upscaled.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor;
+use std::sync::Mutex;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageFormat, ImageReader, Limits};
+use serde::Serialize;
+
+use crate::error::{Result, StoreError};
+
+/// Width used when the request does not name one.
+pub const DEFAULT_WIDTH: u32 = 256;
+
+/// Accepted widths, in pixels.
+pub const MIN_WIDTH: u32 = 16;
+pub const MAX_WIDTH: u32 = 1024;
+
+/// Default for `CXDB_THUMBNAIL_CACHE_BYTES`.
+const DEFAULT_CACHE_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Default for `CXDB_THUMBNAIL_MAX_SOURCE_BYTES`.
+const DEFAULT_MAX_SOURCE_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Default for `CXDB_THUMBNAIL_MAX_DECODE_BYTES`.
+const DEFAULT_MAX_DECODE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// JPEG quality of opaque thumbnails.
+const JPEG_QUALITY: u8 = 80;
+
+/// Approximate per-entry bookkeeping, counted against the budget.
+const ENTRY_OVERHEAD: usize = 96;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThumbnailConfig {
+    /// Memory for rendered thumbnails; 0 renders every request.
+    pub cache_bytes: u64,
+    /// Larger blobs are refused without being read.
+    pub max_source_bytes: u64,
+    /// Cap on the memory a decoder may allocate, which bounds the pixel
+    /// count of images small on disk but huge when decoded.
+    pub max_decode_bytes: u64,
+}
+
+impl Default for ThumbnailConfig {
+    fn default() -> Self {
+        Self {
+            cache_bytes: DEFAULT_CACHE_BYTES,
+            max_source_bytes: DEFAULT_MAX_SOURCE_BYTES,
+            max_decode_bytes: DEFAULT_MAX_DECODE_BYTES,
+        }
+    }
+}
+
+impl ThumbnailConfig {
+    /// Load config from environment variables. Returns None unless
+    /// `CXDB_THUMBNAILS` is `on`, `true` or `1`.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        match var("CXDB_THUMBNAILS").as_deref() {
+            None | Some("off") | Some("false") | Some("0") => return Ok(None),
+            Some("on") | Some("true") | Some("1") => {}
+            Some(other) => {
+                return Err(StoreError::InvalidInput(format!(
+                    "unknown CXDB_THUMBNAILS {other:?} (expected on or off)"
+                )))
+            }
+        }
+        let number = |name: &str, default: u64| -> Result<u64> {
+            match var(name) {
+                Some(v) => v
+                    .parse()
+                    .map_err(|_| StoreError::InvalidInput(format!("invalid {name} {v:?}"))),
+                None => Ok(default),
+            }
+        };
+        Ok(Some(Self {
+            cache_bytes: number("CXDB_THUMBNAIL_CACHE_BYTES", DEFAULT_CACHE_BYTES)?,
+            max_source_bytes: number("CXDB_THUMBNAIL_MAX_SOURCE_BYTES", DEFAULT_MAX_SOURCE_BYTES)?,
+            max_decode_bytes: number("CXDB_THUMBNAIL_MAX_DECODE_BYTES", DEFAULT_MAX_DECODE_BYTES)?,
+        }))
+    }
+}
+
+/// Parse the `w` query parameter.
+pub fn parse_width(value: Option<&str>) -> Result<u32> {
+    let Some(value) = value else {
+        return Ok(DEFAULT_WIDTH);
+    };
+    value
+        .parse::<u32>()
+        .ok()
+        .filter(|w| (MIN_WIDTH..=MAX_WIDTH).contains(w))
+        .ok_or_else(|| {
+            StoreError::InvalidInput(format!(
+                "invalid w {value:?}: expected {MIN_WIDTH}-{MAX_WIDTH}"
+            ))
+        })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thumbnail {
+    /// `image/png` or `image/jpeg`.
+    pub content_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+/// Decode an image and scale it to at most `width` pixels wide, keeping its
+/// aspect ratio. Content that is not a supported image is InvalidInput.
+pub fn render(source: &[u8], width: u32, max_decode_bytes: u64) -> Result<Thumbnail> {
+    let not_image =
+        |detail: String| StoreError::InvalidInput(format!("not a previewable image: {detail}"));
+    let mut reader = ImageReader::new(Cursor::new(source))
+        .with_guessed_format()
+        .map_err(|e| not_image(e.to_string()))?;
+    match reader.format() {
+        Some(ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif | ImageFormat::WebP) => {}
+        Some(other) => return Err(not_image(format!("{other:?} is not supported"))),
+        None => return Err(not_image("unknown format".into())),
+    }
+    let mut limits = Limits::default();
+    limits.max_alloc = Some(max_decode_bytes);
+    reader.limits(limits);
+    let image = reader.decode().map_err(|e| not_image(e.to_string()))?;
+
+    let image = if image.width() > width {
+        let height = ((image.height() as u64 * width as u64 + image.width() as u64 / 2)
+            / image.width() as u64)
+            .max(1) as u32;
+        image.thumbnail_exact(width, height)
+    } else {
+        image
+    };
+    let (width, height) = (image.width(), image.height());
+
+    let mut data = Vec::new();
+    let encode_error = |e: image::ImageError| StoreError::Io(std::io::Error::other(e));
+    let content_type = if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(image.to_rgba8())
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .map_err(encode_error)?;
+        "image/png"
+    } else {
+        JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY)
+            .encode_image(&image.to_rgb8())
+            .map_err(encode_error)?;
+        "image/jpeg"
+    };
+    Ok(Thumbnail {
+        content_type,
+        width,
+        height,
+        data,
+    })
+}
+
+/// Cache accounting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ThumbnailStats {
+    pub entries: usize,
+    pub bytes: u64,
+    pub budget_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+type CacheKey = ([u8; 32], u32);
+
+#[derive(Debug)]
+struct CacheEntry {
+    thumbnail: Thumbnail,
+    /// Position in `recency`.
+    tick: u64,
+}
+
+#[derive(Debug, Default)]
+struct ThumbnailCache {
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// Access tick -> key, oldest first.
+    recency: BTreeMap<u64, CacheKey>,
+    next_tick: u64,
+    bytes: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+pub struct Thumbnailer {
+    config: ThumbnailConfig,
+    cache: Mutex<ThumbnailCache>,
+}
+
+impl Thumbnailer {
+    pub fn new(config: ThumbnailConfig) -> Self {
+        Self {
+            config,
+            cache: Mutex::new(ThumbnailCache::default()),
+        }
+    }
+
+    /// Thumbnail of the blob with `hash`, rendered from `load` on a cache
+    /// miss. `size` is the blob's length; blobs over the source limit are
+    /// refused before `load` runs.
+    pub fn thumbnail(
+        &self,
+        hash: [u8; 32],
+        size: u64,
+        width: u32,
+        load: impl FnOnce() -> Result<Vec<u8>>,
+    ) -> Result<Thumbnail> {
+        if let Some(thumbnail) = self.cached(&(hash, width)) {
+            return Ok(thumbnail);
+        }
+        if size > self.config.max_source_bytes {
+            return Err(StoreError::InvalidInput(format!(
+                "blob is {size} bytes; thumbnails are limited to {} byte images",
+                self.config.max_source_bytes
+            )));
+        }
+        let thumbnail = render(&load()?, width, self.config.max_decode_bytes)?;
+        self.insert((hash, width), thumbnail.clone());
+        Ok(thumbnail)
+    }
+
+    fn cached(&self, key: &CacheKey) -> Option<Thumbnail> {
+        let mut cache = self.cache.lock().unwrap();
+        let tick = cache.next_tick;
+        cache.next_tick += 1;
+        let Some(entry) = cache.entries.get_mut(key) else {
+            cache.misses += 1;
+            return None;
+        };
+        let old_tick = std::mem::replace(&mut entry.tick, tick);
+        let thumbnail = entry.thumbnail.clone();
+        cache.recency.remove(&old_tick);
+        cache.recency.insert(tick, *key);
+        cache.hits += 1;
+        Some(thumbnail)
+    }
+
+    /// Cache a thumbnail, evicting cold entries over budget.
+    fn insert(&self, key: CacheKey, thumbnail: Thumbnail) {
+        let bytes = (ENTRY_OVERHEAD + thumbnail.data.len()) as u64;
+        if bytes > self.config.cache_bytes {
+            return;
+        }
+        let mut cache = self.cache.lock().unwrap();
+        if cache.entries.contains_key(&key) {
+            return;
+        }
+        let tick = cache.next_tick;
+        cache.next_tick += 1;
+        cache.recency.insert(tick, key);
+        cache.entries.insert(key, CacheEntry { thumbnail, tick });
+        cache.bytes += bytes;
+        while cache.bytes > self.config.cache_bytes {
+            let Some((_, oldest)) = cache.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = cache.entries.remove(&oldest) {
+                cache.bytes -= (ENTRY_OVERHEAD + entry.thumbnail.data.len()) as u64;
+                cache.evictions += 1;
+            }
+        }
+    }
+
+    pub fn stats(&self) -> ThumbnailStats {
+        let cache = self.cache.lock().unwrap();
+        ThumbnailStats {
+            entries: cache.entries.len(),
+            bytes: cache.bytes,
+            budget_bytes: self.config.cache_bytes,
+            hits: cache.hits,
+            misses: cache.misses,
+            evictions: cache.evictions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    fn encode(image: DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut data = Vec::new();
+        image.write_to(&mut Cursor::new(&mut data), format).unwrap();
+        data
+    }
+
+    fn decode(thumbnail: &Thumbnail) -> DynamicImage {
+        image::load_from_memory(&thumbnail.data).unwrap()
+    }
+
+    #[test]
+    fn test_render_scales_to_width_and_keeps_aspect_ratio() {
+        let source = encode(
+            RgbImage::from_pixel(1000, 500, Rgb([200, 10, 10])).into(),
+            ImageFormat::Png,
+        );
+        let thumbnail = render(&source, 256, DEFAULT_MAX_DECODE_BYTES).unwrap();
+        assert_eq!(thumbnail.content_type, "image/jpeg");
+        assert_eq!((thumbnail.width, thumbnail.height), (256, 128));
+        let decoded = decode(&thumbnail);
+        assert_eq!((decoded.width(), decoded.height()), (256, 128));
+
+        // Small images are re-encoded at their own size.
+        let thumbnail = render(&source, 1024, DEFAULT_MAX_DECODE_BYTES).unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (1000, 500));
+    }
+
+    #[test]
+    fn test_render_keeps_transparency_as_png() {
+        let source = encode(
+            RgbaImage::from_pixel(64, 64, Rgba([0, 0, 255, 128])).into(),
+            ImageFormat::Png,
+        );
+        let thumbnail = render(&source, 32, DEFAULT_MAX_DECODE_BYTES).unwrap();
+        assert_eq!(thumbnail.content_type, "image/png");
+        assert_eq!(decode(&thumbnail).to_rgba8().get_pixel(5, 5)[3], 128);
+    }
+
+    #[test]
+    fn test_render_rejects_non_images_and_oversized_decodes() {
+        assert!(matches!(
+            render(b"%PDF-1.7 not an image", 256, DEFAULT_MAX_DECODE_BYTES),
+            Err(StoreError::InvalidInput(_))
+        ));
+        let source = encode(
+            RgbImage::from_pixel(2000, 2000, Rgb([1, 2, 3])).into(),
+            ImageFormat::Png,
+        );
+        assert!(matches!(
+            render(&source, 256, 1024 * 1024),
+            Err(StoreError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_width() {
+        assert_eq!(parse_width(None).unwrap(), DEFAULT_WIDTH);
+        assert_eq!(parse_width(Some("64")).unwrap(), 64);
+        for bad in ["0", "8", "4096", "wide"] {
+            assert!(parse_width(Some(bad)).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_cache_hits_and_evicts_least_recently_used() {
+        let source = encode(
+            RgbImage::from_pixel(300, 300, Rgb([9, 9, 9])).into(),
+            ImageFormat::Png,
+        );
+        let size = render(&source, 64, DEFAULT_MAX_DECODE_BYTES)
+            .unwrap()
+            .data
+            .len();
+        let thumbnailer = Thumbnailer::new(ThumbnailConfig {
+            cache_bytes: 2 * (ENTRY_OVERHEAD + size) as u64,
+            ..ThumbnailConfig::default()
+        });
+        let get = |hash: u8| {
+            thumbnailer
+                .thumbnail([hash; 32], source.len() as u64, 64, || Ok(source.clone()))
+                .unwrap()
+        };
+        get(1);
+        get(2);
+        get(1);
+        get(3);
+        let stats = thumbnailer.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 3));
+        assert_eq!((stats.entries, stats.evictions), (2, 1));
+
+        // Blob 2 was evicted; blob 1 is still served without loading.
+        let cached = thumbnailer.thumbnail([1; 32], source.len() as u64, 64, || {
+            panic!("cached thumbnail reloaded")
+        });
+        assert!(cached.is_ok());
+        assert!(matches!(
+            thumbnailer.thumbnail([4; 32], u64::MAX, 64, || Ok(Vec::new())),
+            Err(StoreError::InvalidInput(_))
+        ));
+    }
+}
@@ -0,0 +1,259 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Context title auto-derivation.
+//!
+//! When enabled, the store derives a title for untitled contexts from the first
+//! appended turn whose projected type carries a `text` field. Turns that
+//! declare a role or item type (e.g. `assistant`) are skipped unless it names
+//! a user turn, so titles come from what the user asked rather than replies.
+//!
+//! The append path holds the store lock, and the registry lock is taken after
+//! the store lock everywhere else, so the store never waits for the registry.
+//! When it is busy the turn is kept as a [`TitleCandidate`] and derived later
+//! by [`derive_pending_titles`], which locks the two one at a time.
+
+use std::sync::{Arc, Mutex, TryLockError};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value as JsonValue;
+
+use crate::error::Result;
+use crate::events::{EventBus, StoreEvent};
+use crate::projection::{
+    project_msgpack, BytesRender, EnumRender, RenderOptions, TimeRender, U64Format,
+};
+use crate::registry::Registry;
+use crate::store::{ContextMetadata, Store};
+
+const DEFAULT_MAX_CHARS: usize = 80;
+
+/// Configuration for title auto-derivation, loaded from the environment.
+#[derive(Debug, Clone)]
+pub struct TitleConfig {
+    /// Maximum title length in characters.
+    pub max_chars: usize,
+}
+
+impl TitleConfig {
+    /// Returns Some when `CXDB_TITLE_AUTODERIVE` is set to a truthy value.
+    pub fn from_env() -> Option<Self> {
+        let enabled = std::env::var("CXDB_TITLE_AUTODERIVE")
+            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        if !enabled {
+            return None;
+        }
+        let max_chars = std::env::var("CXDB_TITLE_MAX_CHARS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(DEFAULT_MAX_CHARS);
+        Some(Self { max_chars })
+    }
+}
+
+impl Default for TitleConfig {
+    fn default() -> Self {
+        Self {
+            max_chars: DEFAULT_MAX_CHARS,
+        }
+    }
+}
+
+/// Title derivation state held by the store.
+#[derive(Clone)]
+pub struct TitleDeriver {
+    config: TitleConfig,
+    registry: Arc<Mutex<Registry>>,
+}
+
+/// A turn whose title was not derived at append because the registry was
+/// busy.
+#[derive(Debug, Clone)]
+pub struct TitleCandidate {
+    pub context_id: u64,
+    pub type_id: String,
+    pub type_version: u32,
+    pub payload: Vec<u8>,
+}
+
+/// Outcome of [`TitleDeriver::try_derive`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TitleAttempt {
+    Derived(Option<String>),
+    /// The registry is locked; try again without holding the store.
+    Busy,
+}
+
+impl TitleDeriver {
+    pub fn new(config: TitleConfig, registry: Arc<Mutex<Registry>>) -> Self {
+        Self { config, registry }
+    }
+
+    /// Derive a title from a turn payload, if its type projects to a user text field.
+    /// Waits for the registry, so callers must not hold the store lock.
+    pub fn derive(&self, type_id: &str, type_version: u32, payload: &[u8]) -> Option<String> {
+        let registry = self.registry.lock().unwrap();
+        self.derive_with(&registry, type_id, type_version, payload)
+    }
+
+    /// Like [`TitleDeriver::derive`], without waiting for the registry.
+    pub fn try_derive(&self, type_id: &str, type_version: u32, payload: &[u8]) -> TitleAttempt {
+        match self.registry.try_lock() {
+            Ok(registry) => {
+                TitleAttempt::Derived(self.derive_with(&registry, type_id, type_version, payload))
+            }
+            Err(TryLockError::WouldBlock) => TitleAttempt::Busy,
+            Err(TryLockError::Poisoned(err)) => panic!("registry lock poisoned: {err}"),
+        }
+    }
+
+    fn derive_with(
+        &self,
+        registry: &Registry,
+        type_id: &str,
+        type_version: u32,
+        payload: &[u8],
+    ) -> Option<String> {
+        // Machine-generated summaries never name a context.
+        if type_id == crate::hooks::SUMMARY_TYPE_ID {
+            return None;
+        }
+        let desc = registry.get_type_version(type_id, type_version)?;
+        let options = RenderOptions {
+            bytes_render: BytesRender::LenOnly,
+            u64_format: U64Format::Number,
+            enum_render: EnumRender::Label,
+            time_render: TimeRender::UnixMs,
+            include_unknown: false,
+            apply_defaults: false,
+        };
+        let projected = project_msgpack(payload, desc, registry, &options).ok()?;
+        let text = find_user_text(&projected.data, 0)?;
+        normalize_title(text, self.config.max_chars)
+    }
+}
+
+/// Derive titles for the candidates the store deferred, taking the store and
+/// registry locks one at a time. Returns the contexts that gained a title.
+pub fn derive_pending_titles(store: &Mutex<Store>) -> Result<Vec<(u64, ContextMetadata)>> {
+    let (deriver, candidates) = {
+        let mut store = store.lock().unwrap();
+        (store.title_deriver(), store.take_title_candidates())
+    };
+    let Some(deriver) = deriver else {
+        return Ok(Vec::new());
+    };
+    let mut titled = Vec::new();
+    for candidate in candidates {
+        let Some(title) = deriver.derive(
+            &candidate.type_id,
+            candidate.type_version,
+            &candidate.payload,
+        ) else {
+            continue;
+        };
+        if let Some(metadata) = store
+            .lock()
+            .unwrap()
+            .apply_derived_title(candidate.context_id, title)?
+        {
+            titled.push((candidate.context_id, metadata));
+        }
+    }
+    Ok(titled)
+}
+
+/// Derive deferred titles in the background, after each appended turn, and
+/// publish the metadata change.
+pub fn start_title_worker(
+    store: Arc<Mutex<Store>>,
+    event_bus: Arc<EventBus>,
+) -> thread::JoinHandle<()> {
+    let subscriber = event_bus.subscribe();
+    thread::spawn(move || loop {
+        let Some(StoreEvent::TurnAppended { .. }) = subscriber.recv_timeout(Duration::from_secs(1))
+        else {
+            continue;
+        };
+        match derive_pending_titles(&store) {
+            Ok(titled) => {
+                for (context_id, metadata) in titled {
+                    event_bus.publish(StoreEvent::ContextMetadataUpdated {
+                        context_id: context_id.to_string(),
+                        client_tag: metadata.client_tag,
+                        title: metadata.title,
+                        labels: metadata.labels,
+                        has_provenance: metadata.provenance.is_some(),
+                    });
+                }
+            }
+            Err(err) => tracing::warn!(error = %err, "title derivation failed"),
+        }
+    })
+}
+
+/// Find a `text` string in a projected payload, searching nested objects up to
+/// two levels deep. Objects that declare a non-user role/item type are skipped.
+fn find_user_text(value: &JsonValue, depth: usize) -> Option<&str> {
+    let obj = value.as_object()?;
+    for key in ["role", "item_type"] {
+        if let Some(kind) = obj.get(key).and_then(|v| v.as_str()) {
+            let kind = kind.to_ascii_lowercase();
+            if !kind.contains("user") {
+                return None;
+            }
+        }
+    }
+    if let Some(text) = obj.get("text").and_then(|v| v.as_str()) {
+        if !text.trim().is_empty() {
+            return Some(text);
+        }
+    }
+    if depth >= 2 {
+        return None;
+    }
+    obj.values()
+        .filter(|v| v.is_object())
+        .find_map(|v| find_user_text(v, depth + 1))
+}
+
+/// Collapse whitespace and truncate to `max_chars` characters.
+pub fn normalize_title(text: &str, max_chars: usize) -> Option<String> {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.is_empty() {
+        return None;
+    }
+    let truncated: String = collapsed.chars().take(max_chars).collect();
+    Some(truncated.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_normalize_title() {
+        assert_eq!(
+            normalize_title("  fix the\n\tflaky   test  ", 80).as_deref(),
+            Some("fix the flaky test")
+        );
+        assert_eq!(normalize_title("héllo wörld", 5).as_deref(), Some("héllo"));
+        assert_eq!(normalize_title(" \n ", 10), None);
+    }
+
+    #[test]
+    fn test_find_user_text() {
+        let user = json!({ "item_type": "user_input", "user_input": { "text": "hi there" } });
+        assert_eq!(find_user_text(&user, 0), Some("hi there"));
+
+        let assistant = json!({ "item_type": "assistant_turn", "assistant": { "text": "hello" } });
+        assert_eq!(find_user_text(&assistant, 0), None);
+
+        let plain = json!({ "text": "plain message" });
+        assert_eq!(find_user_text(&plain, 0), Some("plain message"));
+    }
+}
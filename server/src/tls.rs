@@ -0,0 +1,242 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! TLS for the binary protocol port, with optional client certificates.
+//!
+//! When `CXDB_TLS_CERT` and `CXDB_TLS_KEY` are set the binary listener speaks
+//! TLS. Setting `CXDB_TLS_CLIENT_CA` turns on mutual TLS: client certificates
+//! are verified against that CA bundle and the verified certificate becomes
+//! the connection's [`PeerIdentity`]. The identity's principal is attached to
+//! the session, recorded as writer identity in provenance, and used as the key
+//! for the access policy (see [`crate::access`]).
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use serde::Serialize;
+use tokio::net::TcpStream;
+use x509_parser::extensions::GeneralName;
+
+use crate::error::{Result, StoreError};
+use crate::metadata_overrides::MetadataPatch;
+use crate::store::Provenance;
+use crate::util::env_u64;
+
+/// Writer method recorded in provenance for certificate-authenticated writers.
+pub const WRITER_METHOD_MTLS: &str = "mtls";
+
+/// TLS settings for the binary listener, loaded from the environment.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM certificate chain presented by the server.
+    pub cert_path: PathBuf,
+    /// PEM private key for `cert_path`.
+    pub key_path: PathBuf,
+    /// PEM CA bundle used to verify client certificates (None disables mTLS).
+    pub client_ca_path: Option<PathBuf>,
+    /// Reject clients that do not present a certificate. Only meaningful
+    /// with `client_ca_path`.
+    pub require_client_cert: bool,
+    /// How long a client may take to finish the handshake before the
+    /// connection is dropped.
+    pub handshake_timeout: Duration,
+}
+
+impl TlsConfig {
+    /// Returns None unless both `CXDB_TLS_CERT` and `CXDB_TLS_KEY` are set.
+    pub fn from_env() -> Option<Self> {
+        let cert_path = env_path("CXDB_TLS_CERT")?;
+        let key_path = env_path("CXDB_TLS_KEY")?;
+        let require_client_cert = std::env::var("CXDB_TLS_REQUIRE_CLIENT_CERT")
+            .map(|v| v != "0" && !v.eq_ignore_ascii_case("false"))
+            .unwrap_or(true);
+        Some(Self {
+            cert_path,
+            key_path,
+            client_ca_path: env_path("CXDB_TLS_CLIENT_CA"),
+            require_client_cert,
+            handshake_timeout: Duration::from_millis(env_u64(
+                "CXDB_TLS_HANDSHAKE_TIMEOUT_MS",
+                10_000,
+            )),
+        })
+    }
+}
+
+fn env_path(key: &str) -> Option<PathBuf> {
+    std::env::var(key)
+        .ok()
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Identity taken from a verified client certificate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PeerIdentity {
+    /// Stable name used for sessions, provenance and access policies: the
+    /// first URI SAN (e.g. a SPIFFE ID), else the subject CN, else the first
+    /// DNS SAN, else the first email SAN.
+    pub principal: String,
+    pub common_name: Option<String>,
+    pub dns_names: Vec<String>,
+    pub uris: Vec<String>,
+    pub emails: Vec<String>,
+    /// Issuer distinguished name, e.g. `CN=internal-ca, O=Example`.
+    pub issuer: String,
+}
+
+impl PeerIdentity {
+    /// Extract the identity from a DER-encoded certificate.
+    pub fn from_der(der: &[u8]) -> Result<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der)
+            .map_err(|e| StoreError::InvalidInput(format!("client certificate: {e}")))?;
+
+        let common_name = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(str::to_string);
+
+        let mut dns_names = Vec::new();
+        let mut uris = Vec::new();
+        let mut emails = Vec::new();
+        if let Ok(Some(san)) = cert.subject_alternative_name() {
+            for name in &san.value.general_names {
+                match name {
+                    GeneralName::DNSName(v) => dns_names.push(v.to_string()),
+                    GeneralName::URI(v) => uris.push(v.to_string()),
+                    GeneralName::RFC822Name(v) => emails.push(v.to_string()),
+                    _ => {}
+                }
+            }
+        }
+
+        let principal = uris
+            .first()
+            .or(common_name.as_ref())
+            .or(dns_names.first())
+            .or(emails.first())
+            .cloned()
+            .ok_or_else(|| {
+                StoreError::InvalidInput("client certificate has no CN or SAN identity".into())
+            })?;
+
+        Ok(Self {
+            principal,
+            common_name,
+            dns_names,
+            uris,
+            emails,
+            issuer: cert.issuer().to_string(),
+        })
+    }
+
+    /// Metadata patch recording this identity as the context's writer. It
+    /// overrides any writer identity the client put in its first turn.
+    pub fn provenance_patch(&self) -> MetadataPatch {
+        MetadataPatch {
+            provenance: Some(Provenance {
+                writer_method: Some(WRITER_METHOD_MTLS.to_string()),
+                writer_subject: Some(self.principal.clone()),
+                writer_issuer: Some(self.issuer.clone()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+/// A TLS stream for one client connection.
+pub type TlsStream = tokio_rustls::server::TlsStream<TcpStream>;
+
+/// Performs server-side TLS handshakes for the binary listener.
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    acceptor: tokio_rustls::TlsAcceptor,
+    handshake_timeout: Duration,
+}
+
+impl TlsAcceptor {
+    pub fn new(config: &TlsConfig) -> Result<Self> {
+        let certs = CertificateDer::pem_file_iter(&config.cert_path)
+            .and_then(|iter| iter.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| pem_error(&config.cert_path, e))?;
+        let key = PrivateKeyDer::from_pem_file(&config.key_path)
+            .map_err(|e| pem_error(&config.key_path, e))?;
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()
+            .map_err(tls_error)?;
+
+        let builder = match &config.client_ca_path {
+            Some(ca_path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in
+                    CertificateDer::pem_file_iter(ca_path).map_err(|e| pem_error(ca_path, e))?
+                {
+                    roots
+                        .add(cert.map_err(|e| pem_error(ca_path, e))?)
+                        .map_err(tls_error)?;
+                }
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                let verifier = if config.require_client_cert {
+                    verifier.build()
+                } else {
+                    verifier.allow_unauthenticated().build()
+                }
+                .map_err(tls_error)?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let server_config = builder.with_single_cert(certs, key).map_err(tls_error)?;
+        Ok(Self {
+            acceptor: tokio_rustls::TlsAcceptor::from(Arc::new(server_config)),
+            handshake_timeout: config.handshake_timeout,
+        })
+    }
+
+    /// Complete the handshake on `tcp` and return the stream together with
+    /// the verified client identity, if the client presented a certificate.
+    /// A client that stalls mid-handshake fails with `DeadlineExceeded`
+    /// once the handshake timeout passes.
+    pub async fn accept(&self, tcp: TcpStream) -> Result<(TlsStream, Option<PeerIdentity>)> {
+        let stream =
+            match tokio::time::timeout(self.handshake_timeout, self.acceptor.accept(tcp)).await {
+                Ok(stream) => stream?,
+                Err(_) => {
+                    return Err(StoreError::DeadlineExceeded(format!(
+                        "tls: handshake not finished within {:?}",
+                        self.handshake_timeout
+                    )))
+                }
+            };
+        let identity = match stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|c| c.first())
+        {
+            Some(cert) => Some(PeerIdentity::from_der(cert.as_ref())?),
+            None => None,
+        };
+        Ok((stream, identity))
+    }
+}
+
+fn pem_error(path: &std::path::Path, err: impl std::fmt::Display) -> StoreError {
+    StoreError::InvalidInput(format!("tls: {}: {err}", path.display()))
+}
+
+fn tls_error(err: impl std::fmt::Display) -> StoreError {
+    StoreError::InvalidInput(format!("tls: {err}"))
+}
@@ -0,0 +1,165 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Byte-pair encoding token counts from tiktoken rank tables.
+//!
+//! A `.tiktoken` file holds one `<base64 token> <rank>` pair per line. Text is
+//! first split into pieces by a pre-tokenizer regex, then each piece is merged
+//! pairwise, lowest rank first, until no adjacent pair is in the table. Only
+//! the number of resulting tokens is needed, so token ids are never built.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use base64::Engine;
+use regex::Regex;
+
+use super::Tokenizer;
+use crate::error::{Result, StoreError};
+
+/// The cl100k pre-tokenizer. The `regex` crate has no lookahead, so the
+/// `\s+(?!\S)` alternative is emulated in [`BpeTokenizer::pieces`].
+const DEFAULT_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+";
+
+pub struct BpeTokenizer {
+    name: String,
+    ranks: HashMap<Vec<u8>, u32>,
+    pattern: Regex,
+    /// Whether `pattern` is the default one that needs lookahead emulation.
+    emulate_lookahead: bool,
+}
+
+impl BpeTokenizer {
+    /// Load a `.tiktoken` rank file; the tokenizer is named after the file stem.
+    pub fn load(path: &Path, pattern: Option<&str>) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut ranks = HashMap::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || {
+                StoreError::InvalidInput(format!("{}:{}: invalid rank", path.display(), index + 1))
+            };
+            let (token, rank) = line.split_once(' ').ok_or_else(invalid)?;
+            let token = base64::engine::general_purpose::STANDARD
+                .decode(token)
+                .map_err(|_| invalid())?;
+            let rank = rank.trim().parse::<u32>().map_err(|_| invalid())?;
+            ranks.insert(token, rank);
+        }
+        let name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("bpe")
+            .to_string();
+        Self::from_ranks(name, ranks, pattern)
+    }
+
+    pub fn from_ranks(
+        name: String,
+        ranks: HashMap<Vec<u8>, u32>,
+        pattern: Option<&str>,
+    ) -> Result<Self> {
+        let pattern_str = pattern.unwrap_or(DEFAULT_PATTERN);
+        let pattern = Regex::new(pattern_str)
+            .map_err(|e| StoreError::InvalidInput(format!("invalid pre-tokenizer pattern: {e}")))?;
+        Ok(Self {
+            name,
+            ranks,
+            pattern,
+            emulate_lookahead: pattern_str == DEFAULT_PATTERN,
+        })
+    }
+
+    /// Split text into pre-tokenizer pieces.
+    fn pieces<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let mut out = Vec::new();
+        let mut pos = 0;
+        while let Some(m) = self.pattern.find_at(text, pos) {
+            let mut end = m.end();
+            let piece = m.as_str();
+            // `\s+(?!\S)`: a whitespace run followed by a word leaves its last
+            // character to that word.
+            if self.emulate_lookahead
+                && piece.chars().count() > 1
+                && piece.chars().all(char::is_whitespace)
+                && !piece.ends_with(['\r', '\n'])
+                && text[end..]
+                    .chars()
+                    .next()
+                    .is_some_and(|c| !c.is_whitespace())
+            {
+                end -= piece.chars().last().map_or(0, char::len_utf8);
+            }
+            if end == m.start() {
+                break;
+            }
+            out.push(&text[m.start()..end]);
+            pos = end;
+        }
+        out
+    }
+
+    /// Tokens in one piece after merging byte pairs.
+    fn count_piece(&self, piece: &[u8]) -> u64 {
+        if piece.len() <= 1 || self.ranks.contains_key(piece) {
+            return 1;
+        }
+        // Part boundaries; part i spans bounds[i]..bounds[i + 1].
+        let mut bounds: Vec<usize> = (0..=piece.len()).collect();
+        loop {
+            let best = (0..bounds.len().saturating_sub(2))
+                .filter_map(|i| {
+                    self.ranks
+                        .get(&piece[bounds[i]..bounds[i + 2]])
+                        .map(|rank| (*rank, i))
+                })
+                .min();
+            match best {
+                Some((_, i)) => {
+                    bounds.remove(i + 1);
+                }
+                None => break,
+            }
+        }
+        (bounds.len() - 1) as u64
+    }
+}
+
+impl Tokenizer for BpeTokenizer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn count(&self, text: &str) -> u64 {
+        self.pieces(text)
+            .into_iter()
+            .map(|piece| self.count_piece(piece.as_bytes()))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pieces_merge_by_rank() {
+        let ranks: HashMap<Vec<u8>, u32> = [
+            "h", "e", "l", "o", " ", "w", "r", "d", "he", "ll", "hell", "hello", " w", "or",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(rank, token)| (token.as_bytes().to_vec(), rank as u32))
+        .collect();
+        let bpe = BpeTokenizer::from_ranks("test".into(), ranks, None).unwrap();
+
+        assert_eq!(bpe.pieces("hello  world"), ["hello", " ", " world"]);
+        // "hello" is a single token; " world" merges to " w", "or", "l", "d".
+        assert_eq!(bpe.count("hello world"), 5);
+        assert_eq!(bpe.count(""), 0);
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Persisted token counts.
+//!
+//! Each entry records a turn's own token count and the running total along its
+//! parent chain, for the context it was appended to (or forked into). Entries
+//! are only written when a turn has tokens or a context's total changes, so
+//! contexts of unannotated types cost nothing. The log is append-only
+//! JSON lines; the latest entry per context wins.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StoreError};
+use crate::jsonl_log::open_log;
+use crate::turn_store::CommitPipeline;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TurnTokens {
+    /// Tokens in the turn's own payload.
+    pub tokens: u64,
+    /// Tokens along the turn's parent chain, itself included.
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ContextTokens {
+    /// Tokens along the head chain, shared history included.
+    pub total: u64,
+    /// Tokens of turns appended to this context.
+    pub appended: u64,
+    /// Turn the total was last recorded at.
+    #[serde(skip)]
+    pub turn_id: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LedgerEntry {
+    context_id: u64,
+    turn_id: u64,
+    tokens: u64,
+    total: u64,
+}
+
+pub struct TokenLedger {
+    file: File,
+    /// Turns with a non-zero count.
+    turns: HashMap<u64, TurnTokens>,
+    contexts: HashMap<u64, ContextTokens>,
+}
+
+impl TokenLedger {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("turn_tokens.jsonl");
+        let (file, entries) = open_log::<LedgerEntry>(&path)?;
+
+        let mut ledger = Self {
+            file,
+            turns: HashMap::new(),
+            contexts: HashMap::new(),
+        };
+        for entry in entries {
+            ledger.apply(&entry);
+        }
+        Ok(ledger)
+    }
+
+    /// Whether any turn has been counted; when not, every total is 0.
+    pub fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+
+    pub fn turn(&self, turn_id: u64) -> Option<TurnTokens> {
+        self.turns.get(&turn_id).copied()
+    }
+
+    pub fn context(&self, context_id: u64) -> ContextTokens {
+        self.contexts.get(&context_id).copied().unwrap_or_default()
+    }
+
+    pub fn contexts(&self) -> impl Iterator<Item = (u64, &ContextTokens)> {
+        self.contexts.iter().map(|(id, tokens)| (*id, tokens))
+    }
+
+    /// Sync the log with every group commit batch.
+    pub fn track_files(&self, pipeline: &CommitPipeline) -> Result<()> {
+        pipeline.track("meta/turn_tokens.jsonl", &self.file)
+    }
+
+    /// Record that `turn_id` is now the head of `context_id`, carrying
+    /// `tokens` of its own and `total` along its chain. Forks record their
+    /// base turn with no tokens of their own.
+    pub fn record(&mut self, context_id: u64, turn_id: u64, tokens: u64, total: u64) -> Result<()> {
+        let entry = LedgerEntry {
+            context_id,
+            turn_id,
+            tokens,
+            total,
+        };
+        if tokens > 0 || self.context(context_id).total != total {
+            let mut line = serde_json::to_vec(&entry)
+                .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+            line.push(b'\n');
+            self.file.write_all(&line)?;
+            self.file.sync_data()?;
+        }
+        self.apply(&entry);
+        Ok(())
+    }
+
+    fn apply(&mut self, entry: &LedgerEntry) {
+        if entry.tokens > 0 {
+            self.turns.insert(
+                entry.turn_id,
+                TurnTokens {
+                    tokens: entry.tokens,
+                    total: entry.total,
+                },
+            );
+        }
+        let context = self.contexts.entry(entry.context_id).or_default();
+        context.total = entry.total;
+        context.appended += entry.tokens;
+        context.turn_id = entry.turn_id;
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Token accounting for turn payloads.
+//!
+//! Fields annotated with `count_tokens` in the type registry are counted at
+//! append time; string content anywhere below an annotated field counts, and
+//! unannotated `ref`, array, map and `oneof` fields are searched for annotated
+//! fields of the referenced types. Counts are kept in a [`TokenLedger`].
+//!
+//! The tokenizer is pluggable: a tiktoken rank file configured with
+//! `CXDB_TOKENIZER_BPE_FILE`, or an approximation of four characters per
+//! token when none is configured.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use rmpv::Value;
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::registry::{FieldSpec, ItemsSpec, Registry, TypeVersionSpec};
+
+mod bpe;
+mod ledger;
+
+pub use bpe::BpeTokenizer;
+pub use ledger::{ContextTokens, TokenLedger, TurnTokens};
+
+/// Nested type references followed before giving up, as a guard against
+/// self-referencing types.
+const MAX_NESTING: usize = 16;
+
+pub trait Tokenizer: Send + Sync {
+    fn name(&self) -> &str;
+    fn count(&self, text: &str) -> u64;
+}
+
+/// Estimates one token per four characters.
+pub struct ApproxTokenizer;
+
+impl Tokenizer for ApproxTokenizer {
+    fn name(&self) -> &str {
+        "approx"
+    }
+
+    fn count(&self, text: &str) -> u64 {
+        (text.chars().count() as u64).div_ceil(4)
+    }
+}
+
+/// Tokenizer selection, loaded from the environment.
+#[derive(Debug, Clone, Default)]
+pub struct TokenizerConfig {
+    /// `.tiktoken` rank file (`CXDB_TOKENIZER_BPE_FILE`).
+    pub bpe_file: Option<PathBuf>,
+    /// Pre-tokenizer regex overriding the cl100k one (`CXDB_TOKENIZER_PATTERN`).
+    pub pattern: Option<String>,
+}
+
+impl TokenizerConfig {
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        Self {
+            bpe_file: var("CXDB_TOKENIZER_BPE_FILE").map(PathBuf::from),
+            pattern: var("CXDB_TOKENIZER_PATTERN"),
+        }
+    }
+
+    pub fn load(&self) -> Result<Box<dyn Tokenizer>> {
+        Ok(match &self.bpe_file {
+            Some(path) => Box::new(BpeTokenizer::load(path, self.pattern.as_deref())?),
+            None => Box::new(ApproxTokenizer),
+        })
+    }
+}
+
+/// Counts tokens in payloads according to their registry descriptors.
+pub struct TokenCounter {
+    tokenizer: Box<dyn Tokenizer>,
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl TokenCounter {
+    pub fn new(tokenizer: Box<dyn Tokenizer>, registry: Arc<Mutex<Registry>>) -> Self {
+        Self {
+            tokenizer,
+            registry,
+        }
+    }
+
+    pub fn tokenizer_name(&self) -> &str {
+        self.tokenizer.name()
+    }
+
+    /// Tokens in the annotated fields of a payload; 0 for unregistered types
+    /// and payloads that are not msgpack maps.
+    pub fn count(&self, type_id: &str, type_version: u32, payload: &[u8]) -> u64 {
+        let registry = self.registry.lock().unwrap();
+        let Some(desc) = registry.get_type_version(type_id, type_version) else {
+            return 0;
+        };
+        let Ok(value) = rmpv::decode::read_value(&mut &payload[..]) else {
+            return 0;
+        };
+        self.count_fields(&value, desc, &registry, 0)
+    }
+
+    fn count_fields(
+        &self,
+        value: &Value,
+        desc: &TypeVersionSpec,
+        registry: &Registry,
+        depth: usize,
+    ) -> u64 {
+        let Value::Map(entries) = value else {
+            return 0;
+        };
+        entries
+            .iter()
+            .filter_map(|(key, value)| {
+                let field = desc.fields.get(&key_to_tag(key)?)?;
+                Some(if field.count_tokens {
+                    self.count_strings(value)
+                } else {
+                    self.count_nested(value, field, registry, depth)
+                })
+            })
+            .sum()
+    }
+
+    fn count_strings(&self, value: &Value) -> u64 {
+        match value {
+            Value::String(s) => s.as_str().map_or(0, |s| self.tokenizer.count(s)),
+            Value::Array(items) => items.iter().map(|v| self.count_strings(v)).sum(),
+            Value::Map(entries) => entries.iter().map(|(_, v)| self.count_strings(v)).sum(),
+            _ => 0,
+        }
+    }
+
+    fn count_nested(
+        &self,
+        value: &Value,
+        field: &FieldSpec,
+        registry: &Registry,
+        depth: usize,
+    ) -> u64 {
+        if let (Some(type_ref), "ref") = (&field.type_ref, field.field_type.as_str()) {
+            return self.count_ref(value, type_ref, registry, depth);
+        }
+        match (&field.items, &field.shape, value) {
+            (Some(items), _, Value::Array(values)) => values
+                .iter()
+                .map(|v| self.count_items(v, items, registry, depth))
+                .sum(),
+            (_, Some(shape), _) => self.count_items(value, shape, registry, depth),
+            _ => 0,
+        }
+    }
+
+    fn count_items(
+        &self,
+        value: &Value,
+        spec: &ItemsSpec,
+        registry: &Registry,
+        depth: usize,
+    ) -> u64 {
+        match (spec, value) {
+            (ItemsSpec::Ref(type_ref), _) => self.count_ref(value, type_ref, registry, depth),
+            (ItemsSpec::Map { value_type, .. }, Value::Map(entries)) => entries
+                .iter()
+                .map(|(_, v)| self.count_items(v, value_type, registry, depth))
+                .sum(),
+            (ItemsSpec::OneOf(variants), Value::Map(entries)) if entries.len() == 1 => {
+                let (tag, inner) = &entries[0];
+                match key_to_tag(tag).and_then(|tag| variants.get(&tag)) {
+                    Some(type_ref) => self.count_ref(inner, type_ref, registry, depth),
+                    None => 0,
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    fn count_ref(&self, value: &Value, type_ref: &str, registry: &Registry, depth: usize) -> u64 {
+        if depth >= MAX_NESTING {
+            return 0;
+        }
+        match registry.get_latest_type_version(type_ref) {
+            Some(desc) => self.count_fields(value, desc, registry, depth + 1),
+            None => 0,
+        }
+    }
+}
+
+fn key_to_tag(key: &Value) -> Option<u64> {
+    match key {
+        Value::Integer(int) => int.as_u64(),
+        Value::String(s) => s.as_str()?.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Token totals for the metrics snapshot.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TokenStats {
+    /// Tokenizer counting new turns; None when counting is disabled.
+    pub tokenizer: Option<String>,
+    /// Tokens of every counted turn.
+    pub total: u64,
+    /// Contexts with at least one counted turn.
+    pub contexts: usize,
+    /// Tokens appended per client tag, heaviest first.
+    pub by_tag: Vec<TagTokens>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TagTokens {
+    pub tag: String,
+    pub tokens: u64,
+    pub contexts: usize,
+}
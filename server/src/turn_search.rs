@@ -0,0 +1,348 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Search within the branch of a single context.
+//!
+//! `GET /v1/contexts/:id/turns/search` scans the context's turns oldest
+//! first, projecting each payload with the registry, and returns the turns
+//! that match every filter: a declared type, `field.<path>=value` equality on
+//! projected fields, and a case-insensitive substring of any string value.
+//! Text matches come with short snippets around the hits so a viewer can show
+//! why a turn matched. The store keeps no per-context text index, so a search
+//! reads the branch in pages and stops at `limit` matches or at the request
+//! deadline; `next_from_depth` continues where it left off.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+use crate::deadline::Deadline;
+use crate::error::{Result, StoreError};
+use crate::projection::cache::project_cached;
+use crate::projection::text::{lookup, scalar_text};
+use crate::projection::RenderOptions;
+use crate::registry::Registry;
+use crate::store::{Store, TurnWithMeta};
+
+/// Turns read from the store at a time.
+const PAGE_SIZE: u32 = 256;
+
+/// Default and maximum number of matches per response.
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 200;
+
+/// Snippets returned per matching turn.
+const MAX_SNIPPETS: usize = 3;
+
+/// Characters of context kept on each side of the first hit of a snippet.
+const SNIPPET_CONTEXT: usize = 40;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TurnSearchQuery {
+    /// Case-insensitive substring of any string value.
+    pub text: Option<String>,
+    /// Declared type id the turn must have.
+    pub type_id: Option<String>,
+    /// Dotted path -> value the projected field must equal.
+    pub fields: Vec<(String, String)>,
+    /// First depth scanned.
+    pub from_depth: u32,
+    /// Last depth scanned; None scans to the head.
+    pub to_depth: Option<u32>,
+    pub limit: usize,
+}
+
+impl TurnSearchQuery {
+    /// Parse `text`, `type_id`, `field.<path>`, `from_depth` and `limit`.
+    /// At least one filter is required.
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self> {
+        let non_empty = |name: &str| params.get(name).filter(|v| !v.is_empty()).cloned();
+        let mut fields: Vec<(String, String)> = params
+            .iter()
+            .filter_map(|(key, value)| {
+                let path = key.strip_prefix("field.")?;
+                (!path.is_empty()).then(|| (path.to_string(), value.clone()))
+            })
+            .collect();
+        fields.sort();
+        let from_depth = match params.get("from_depth") {
+            Some(v) => v
+                .parse()
+                .map_err(|_| StoreError::InvalidInput("invalid from_depth".into()))?,
+            None => 0,
+        };
+        let limit = match params.get("limit") {
+            Some(v) => v
+                .parse::<usize>()
+                .map_err(|_| StoreError::InvalidInput("invalid limit".into()))?
+                .clamp(1, MAX_LIMIT),
+            None => DEFAULT_LIMIT,
+        };
+        let query = Self {
+            text: non_empty("text"),
+            type_id: non_empty("type_id"),
+            fields,
+            from_depth,
+            to_depth: None,
+            limit,
+        };
+        if query.text.is_none() && query.type_id.is_none() && query.fields.is_empty() {
+            return Err(StoreError::InvalidInput(
+                "turn search needs text, type_id or a field.<path> filter".into(),
+            ));
+        }
+        Ok(query)
+    }
+}
+
+/// A run of a string value around one or more hits.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Snippet {
+    /// Dotted path of the value within the projected turn.
+    pub path: String,
+    pub text: String,
+    /// `[start, end)` character offsets of the hits within `text`.
+    pub highlights: Vec<[usize; 2]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TurnMatch {
+    pub turn_id: u64,
+    pub depth: u32,
+    pub type_id: String,
+    pub type_version: u32,
+    /// Empty unless the query has `text`.
+    pub snippets: Vec<Snippet>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TurnSearchResult {
+    pub matches: Vec<TurnMatch>,
+    /// Turns examined.
+    pub scanned: u64,
+    /// Depth to continue from; None once the range is exhausted.
+    pub next_from_depth: Option<u32>,
+    /// The deadline passed before the range was exhausted.
+    pub partial: bool,
+}
+
+/// Scan `context_id`'s branch for turns matching `query`.
+pub fn search_turns(
+    store: &mut Store,
+    registry: &Registry,
+    context_id: u64,
+    query: &TurnSearchQuery,
+    options: &RenderOptions,
+    deadline: &Deadline,
+) -> Result<TurnSearchResult> {
+    let head = store.get_head(context_id)?;
+    let last_depth = query
+        .to_depth
+        .map_or(head.head_depth, |to| to.min(head.head_depth));
+    let needle = query.text.as_deref().map(folded);
+    let mut result = TurnSearchResult::default();
+    let mut depth = query.from_depth;
+
+    while head.head_turn_id != 0 && depth <= last_depth {
+        let page = PAGE_SIZE.min(last_depth - depth + 1);
+        let turns = store.get_range_by_depth(context_id, depth, page, true)?;
+        if turns.is_empty() {
+            break;
+        }
+        for item in &turns {
+            if deadline.is_expired() {
+                result.partial = true;
+                result.next_from_depth = Some(item.record.depth);
+                return Ok(result);
+            }
+            result.scanned += 1;
+            let found =
+                match match_turn(item, registry, query, needle.as_deref(), options, deadline) {
+                    Ok(found) => found,
+                    // Projection gave up on the budget; resume at this turn.
+                    Err(StoreError::DeadlineExceeded(_)) => {
+                        result.scanned -= 1;
+                        result.partial = true;
+                        result.next_from_depth = Some(item.record.depth);
+                        return Ok(result);
+                    }
+                    Err(e) => return Err(e),
+                };
+            if let Some(found) = found {
+                result.matches.push(found);
+                if result.matches.len() >= query.limit {
+                    let next = item.record.depth + 1;
+                    result.next_from_depth = (next <= last_depth).then_some(next);
+                    return Ok(result);
+                }
+            }
+        }
+        depth = turns.last().map_or(last_depth, |t| t.record.depth) + 1;
+    }
+    Ok(result)
+}
+
+fn match_turn(
+    item: &TurnWithMeta,
+    registry: &Registry,
+    query: &TurnSearchQuery,
+    needle: Option<&[char]>,
+    options: &RenderOptions,
+    deadline: &Deadline,
+) -> Result<Option<TurnMatch>> {
+    if query
+        .type_id
+        .as_ref()
+        .is_some_and(|t| *t != item.meta.declared_type_id)
+    {
+        return Ok(None);
+    }
+    let mut snippets = Vec::new();
+    if needle.is_some() || !query.fields.is_empty() {
+        // Turns of types the registry does not know cannot be projected and
+        // never match a content filter.
+        let Some(desc) =
+            registry.get_type_version(&item.meta.declared_type_id, item.meta.declared_type_version)
+        else {
+            return Ok(None);
+        };
+        let Some(payload) = item.payload.as_ref() else {
+            return Ok(None);
+        };
+        let projected = project_cached(
+            &item.record.payload_hash,
+            payload,
+            &item.meta.declared_type_id,
+            desc,
+            registry,
+            options,
+            deadline,
+        )?;
+        let fields_match = query.fields.iter().all(|(path, expected)| {
+            lookup(&projected.data, path).is_some_and(|v| scalar_text(v) == *expected)
+        });
+        if !fields_match {
+            return Ok(None);
+        }
+        if let Some(needle) = needle {
+            if !collect_snippets(&projected.data, "", needle, &mut snippets) {
+                return Ok(None);
+            }
+        }
+    }
+    Ok(Some(TurnMatch {
+        turn_id: item.record.turn_id,
+        depth: item.record.depth,
+        type_id: item.meta.declared_type_id.clone(),
+        type_version: item.meta.declared_type_version,
+        snippets,
+    }))
+}
+
+/// Lowercase `s` one character at a time, so offsets into the result are
+/// offsets into `s`.
+fn folded(s: &str) -> Vec<char> {
+    s.chars()
+        .map(|c| c.to_lowercase().next().unwrap_or(c))
+        .collect()
+}
+
+/// Character offsets of the non-overlapping occurrences of `needle`.
+fn find_all(haystack: &[char], needle: &[char]) -> Vec<usize> {
+    let mut hits = Vec::new();
+    if needle.is_empty() || needle.len() > haystack.len() {
+        return hits;
+    }
+    let mut i = 0;
+    while i + needle.len() <= haystack.len() {
+        if haystack[i..i + needle.len()] == *needle {
+            hits.push(i);
+            i += needle.len();
+        } else {
+            i += 1;
+        }
+    }
+    hits
+}
+
+/// Walk the string values of `value`, adding a snippet for each that
+/// contains `needle` until `MAX_SNIPPETS` are kept. Returns whether any
+/// value matched.
+fn collect_snippets(
+    value: &JsonValue,
+    path: &str,
+    needle: &[char],
+    out: &mut Vec<Snippet>,
+) -> bool {
+    let child = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{path}.{key}")
+        }
+    };
+    match value {
+        JsonValue::String(s) => {
+            let chars: Vec<char> = s.chars().collect();
+            let hits = find_all(&folded(s), needle);
+            let Some(&first) = hits.first() else {
+                return false;
+            };
+            if out.len() < MAX_SNIPPETS {
+                let start = first.saturating_sub(SNIPPET_CONTEXT);
+                let end = (first + needle.len() + SNIPPET_CONTEXT).min(chars.len());
+                out.push(Snippet {
+                    path: path.to_string(),
+                    text: chars[start..end].iter().collect(),
+                    highlights: hits
+                        .iter()
+                        .filter(|&&hit| hit + needle.len() <= end)
+                        .map(|&hit| [hit - start, hit - start + needle.len()])
+                        .collect(),
+                });
+            }
+            true
+        }
+        JsonValue::Object(obj) => obj.iter().fold(false, |matched, (key, field)| {
+            collect_snippets(field, &child(key), needle, out) || matched
+        }),
+        JsonValue::Array(items) => items.iter().enumerate().fold(false, |matched, (i, item)| {
+            collect_snippets(item, &child(&i.to_string()), needle, out) || matched
+        }),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_snippets_highlight_every_hit_in_the_window() {
+        let data = json!({
+            "role": "assistant",
+            "parts": [{ "text": "Request TIMEOUT after 30s; retrying. Another timeout." }],
+        });
+        let mut snippets = Vec::new();
+        assert!(collect_snippets(
+            &data,
+            "",
+            &folded("timeout"),
+            &mut snippets
+        ));
+        assert_eq!(snippets.len(), 1);
+        assert_eq!(snippets[0].path, "parts.0.text");
+        let text: Vec<char> = snippets[0].text.chars().collect();
+        for [start, end] in &snippets[0].highlights {
+            let hit: String = text[*start..*end].iter().collect();
+            assert_eq!(hit.to_lowercase(), "timeout");
+        }
+        assert_eq!(snippets[0].highlights.len(), 2);
+
+        let mut none = Vec::new();
+        assert!(!collect_snippets(&data, "", &folded("deadline"), &mut none));
+        assert!(none.is_empty());
+    }
+}
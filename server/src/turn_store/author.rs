@@ -0,0 +1,97 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Turn-level provenance: which session appended a turn.
+//!
+//! Several agents may append into one shared context, so the context's
+//! first-turn provenance does not say who wrote each turn. Turns appended
+//! over the binary protocol record the session, its client tag and the
+//! authenticated principal in `turns.authors`. Turns appended by the server
+//! itself (e.g. summaries) have no author.
+
+use std::fs::File;
+use std::io::Read;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crc32fast::Hasher;
+
+use crate::error::{Result, StoreError};
+
+/// Marks an absent principal in a `turns.authors` entry.
+const NO_PRINCIPAL: u32 = u32::MAX;
+
+/// Longest client tag or principal read back; longer lengths are corrupt.
+const MAX_FIELD_LEN: u32 = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TurnAuthor {
+    pub session_id: u64,
+    /// Client tag from the session's HELLO; empty when it sent none.
+    pub client_tag: String,
+    /// Verified client certificate identity, when the session had one.
+    pub principal: Option<String>,
+}
+
+/// `turns.authors` entry: turn_id u64, session_id u64, client_tag_len u32,
+/// client_tag, principal_len u32 (`u32::MAX` when absent), principal, and a
+/// CRC-32 of everything before it.
+pub fn encode_author_entry(turn_id: u64, author: &TurnAuthor) -> Result<Vec<u8>> {
+    let principal = author.principal.as_deref().unwrap_or("");
+    let mut buf = Vec::with_capacity(28 + author.client_tag.len() + principal.len());
+    buf.write_u64::<LittleEndian>(turn_id)?;
+    buf.write_u64::<LittleEndian>(author.session_id)?;
+    buf.write_u32::<LittleEndian>(author.client_tag.len() as u32)?;
+    buf.extend_from_slice(author.client_tag.as_bytes());
+    match &author.principal {
+        Some(principal) => {
+            buf.write_u32::<LittleEndian>(principal.len() as u32)?;
+            buf.extend_from_slice(principal.as_bytes());
+        }
+        None => buf.write_u32::<LittleEndian>(NO_PRINCIPAL)?,
+    }
+    let mut hasher = Hasher::new();
+    hasher.update(&buf);
+    buf.write_u32::<LittleEndian>(hasher.finalize())?;
+    Ok(buf)
+}
+
+pub fn read_author_entry(reader: &mut File) -> Result<(u64, TurnAuthor)> {
+    let mut hasher = Hasher::new();
+    let turn_id = reader.read_u64::<LittleEndian>()?;
+    let session_id = reader.read_u64::<LittleEndian>()?;
+    hasher.update(&turn_id.to_le_bytes());
+    hasher.update(&session_id.to_le_bytes());
+    let client_tag = read_string(reader, &mut hasher)?
+        .ok_or_else(|| StoreError::Corrupt("author entry without client tag".into()))?;
+    let principal = read_string(reader, &mut hasher)?;
+    let crc = reader.read_u32::<LittleEndian>()?;
+    if crc != hasher.finalize() {
+        return Err(StoreError::Corrupt("author crc mismatch".into()));
+    }
+    Ok((
+        turn_id,
+        TurnAuthor {
+            session_id,
+            client_tag,
+            principal,
+        },
+    ))
+}
+
+/// A length-prefixed string, or None for the `NO_PRINCIPAL` marker.
+fn read_string(reader: &mut File, hasher: &mut Hasher) -> Result<Option<String>> {
+    let len = reader.read_u32::<LittleEndian>()?;
+    hasher.update(&len.to_le_bytes());
+    if len == NO_PRINCIPAL {
+        return Ok(None);
+    }
+    if len > MAX_FIELD_LEN {
+        return Err(StoreError::Corrupt("author field too long".into()));
+    }
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf)?;
+    hasher.update(&buf);
+    String::from_utf8(buf)
+        .map(Some)
+        .map_err(|_| StoreError::Corrupt("invalid author utf8".into()))
+}
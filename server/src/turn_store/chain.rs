@@ -0,0 +1,116 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Hash chain over turns, for tamper evidence.
+//!
+//! Every turn carries a chain hash committing to its parent's chain hash,
+//! its payload hash and its metadata, so a context's head chain hash commits
+//! to its entire history. Chain hashes are stored in `turns.chain` when the
+//! turn is appended; fsck recomputes them and reports turns whose stored
+//! hash no longer matches.
+
+use std::fs::File;
+use std::io::Read;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crc32fast::Hasher;
+use serde::Serialize;
+
+use super::{TurnMeta, TurnRecord};
+use crate::error::{Result, StoreError};
+
+/// Chain hash of the (absent) parent of a root turn.
+pub const ROOT_CHAIN_HASH: [u8; 32] = [0u8; 32];
+
+/// Mismatched turns listed in a verification report; the count is exact.
+const MAX_REPORTED: usize = 100;
+
+/// BLAKE3 over the parent's chain hash, the payload hash and the turn's
+/// metadata, little-endian:
+/// `parent_chain || payload_hash || turn_id u64 || parent_turn_id u64 ||
+/// depth u32 || created_at_unix_ms u64 || type_id_len u32 || type_id ||
+/// type_version u32 || encoding u32 || compression u32 || uncompressed_len u32`.
+pub fn chain_hash(parent_chain: &[u8; 32], record: &TurnRecord, meta: &TurnMeta) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(parent_chain);
+    hasher.update(&record.payload_hash);
+    hasher.update(&record.turn_id.to_le_bytes());
+    hasher.update(&record.parent_turn_id.to_le_bytes());
+    hasher.update(&record.depth.to_le_bytes());
+    hasher.update(&record.created_at_unix_ms.to_le_bytes());
+    hasher.update(&(meta.declared_type_id.len() as u32).to_le_bytes());
+    hasher.update(meta.declared_type_id.as_bytes());
+    hasher.update(&meta.declared_type_version.to_le_bytes());
+    hasher.update(&meta.encoding.to_le_bytes());
+    hasher.update(&meta.compression.to_le_bytes());
+    hasher.update(&meta.uncompressed_len.to_le_bytes());
+    *hasher.finalize().as_bytes()
+}
+
+/// `turns.chain` entry: turn_id u64, chain hash, CRC-32 of both.
+pub fn encode_chain_entry(turn_id: u64, hash: &[u8; 32]) -> Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(8 + 32 + 4);
+    buf.write_u64::<LittleEndian>(turn_id)?;
+    buf.extend_from_slice(hash);
+    let mut hasher = Hasher::new();
+    hasher.update(&buf);
+    buf.write_u32::<LittleEndian>(hasher.finalize())?;
+    Ok(buf)
+}
+
+pub fn read_chain_entry(reader: &mut File) -> Result<(u64, [u8; 32])> {
+    let turn_id = reader.read_u64::<LittleEndian>()?;
+    let mut hash = [0u8; 32];
+    reader.read_exact(&mut hash)?;
+    let crc = reader.read_u32::<LittleEndian>()?;
+    let mut hasher = Hasher::new();
+    hasher.update(&turn_id.to_le_bytes());
+    hasher.update(&hash);
+    if crc != hasher.finalize() {
+        return Err(StoreError::Corrupt("chain crc mismatch".into()));
+    }
+    Ok((turn_id, hash))
+}
+
+/// One turn's chain inputs and chain hash, enough to recompute the hash
+/// from its parent's.
+#[derive(Debug, Clone)]
+pub struct ChainLink {
+    pub record: TurnRecord,
+    pub meta: TurnMeta,
+    pub chain_hash: [u8; 32],
+}
+
+/// A turn's chain hash and the links from it to a context's head. Applying
+/// the links in order to the turn's chain hash yields `chain_head`.
+#[derive(Debug, Clone)]
+pub struct TurnProof {
+    pub turn: ChainLink,
+    pub context_id: u64,
+    pub head_turn_id: u64,
+    pub chain_head: [u8; 32],
+    /// Descendants of the turn up to the head, oldest first.
+    pub path: Vec<ChainLink>,
+}
+
+/// Result of recomputing every stored chain hash.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChainVerification {
+    pub turns_checked: u64,
+    pub mismatched_turns: u64,
+    /// The first mismatched turn ids, in turn order.
+    pub mismatched_turn_ids: Vec<u64>,
+}
+
+impl ChainVerification {
+    pub fn is_ok(&self) -> bool {
+        self.mismatched_turns == 0
+    }
+
+    pub(super) fn record_mismatch(&mut self, turn_id: u64) {
+        self.mismatched_turns += 1;
+        if self.mismatched_turn_ids.len() < MAX_REPORTED {
+            self.mismatched_turn_ids.push(turn_id);
+        }
+    }
+}
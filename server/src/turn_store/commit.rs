@@ -0,0 +1,246 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Group commit: one fsync for a batch of appends.
+//!
+//! Appends write the turn files, the blob pack and the side logs under the
+//! store lock, which leaves the bytes in the page cache. With group commit enabled, a writer
+//! that needs its append durable calls [`CommitPipeline::commit`] after
+//! releasing the lock. The first such caller becomes the batch leader: it
+//! waits out the commit window so appends from other connections can join,
+//! then syncs every tracked file once and wakes the whole batch. Callers that
+//! arrive while a sync is running form the next batch.
+//!
+//! A caller takes its ticket after its writes completed, and the leader reads
+//! the last ticket before it starts syncing, so every ticket up to that one
+//! is covered by the sync.
+//!
+//! A failed sync fails every commit of its batch and poisons the pipeline:
+//! after an fsync error the kernel may have dropped the dirty pages, so a
+//! later sync that succeeds says nothing about the earlier writes. Every
+//! commit fails from then on, until the server restarts and recovers from
+//! what reached the disk.
+
+use std::fs::File;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::error::{Result, StoreError};
+use crate::metrics::{WindowedHistogram, WindowedSummary};
+
+const DEFAULT_WINDOW_US: u64 = 2_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommitConfig {
+    /// Sync appends before acknowledging them. Off, appends are acknowledged
+    /// once written and reach disk when the OS flushes them.
+    pub enabled: bool,
+    /// How long a batch leader waits for more appends before syncing.
+    pub window: Duration,
+}
+
+impl Default for CommitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window: Duration::from_micros(DEFAULT_WINDOW_US),
+        }
+    }
+}
+
+impl CommitConfig {
+    pub fn from_env() -> Self {
+        let enabled = std::env::var("CXDB_GROUP_COMMIT")
+            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        let window_us = std::env::var("CXDB_GROUP_COMMIT_WINDOW_US")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_WINDOW_US);
+        Self {
+            enabled,
+            window: Duration::from_micros(window_us),
+        }
+    }
+}
+
+/// Group commit accounting, reported in the metrics snapshot.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommitStats {
+    pub window_us: u64,
+    /// Commits acknowledged as durable.
+    pub commits_total: u64,
+    /// Syncs run; each covers one batch of commits.
+    pub batches_total: u64,
+    /// Commits per batch; 0 before the first batch.
+    pub mean_batch_size: f64,
+    pub max_batch_size: u64,
+    pub sync_failures_total: u64,
+    /// The sync error that poisoned the pipeline, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed: Option<String>,
+    /// Time to sync the tracked files, per batch.
+    pub sync_ms: WindowedSummary,
+    /// Time from a commit request to its acknowledgement, per commit.
+    pub wait_ms: WindowedSummary,
+    /// Names of the files each batch syncs.
+    pub tracked_files: Vec<&'static str>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Last ticket handed out.
+    issued: u64,
+    /// Every ticket up to this one is durable.
+    durable: u64,
+    /// A leader is collecting or syncing a batch.
+    leading: bool,
+    /// The sync error that poisoned the pipeline.
+    failed: Option<String>,
+    /// Tracked files by name; re-tracking a name replaces its handle.
+    files: Vec<(&'static str, File)>,
+    batches: u64,
+    max_batch: u64,
+    sync_failures: u64,
+    sync_latency: WindowedHistogram,
+    wait_latency: WindowedHistogram,
+}
+
+pub struct CommitPipeline {
+    window: Duration,
+    state: Mutex<State>,
+    synced: Condvar,
+}
+
+impl CommitPipeline {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            state: Mutex::new(State::default()),
+            synced: Condvar::new(),
+        }
+    }
+
+    /// Sync `file` with every batch, replacing the handle tracked as `name`.
+    pub fn track(&self, name: &'static str, file: &File) -> Result<()> {
+        let file = file.try_clone()?;
+        let mut state = self.state.lock().unwrap();
+        match state.files.iter_mut().find(|(n, _)| *n == name) {
+            Some(entry) => entry.1 = file,
+            None => state.files.push((name, file)),
+        }
+        Ok(())
+    }
+
+    /// Block until everything written before the call is on disk.
+    pub fn commit(&self) -> Result<()> {
+        let start = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.issued += 1;
+        let ticket = state.issued;
+
+        loop {
+            if let Some(err) = &state.failed {
+                return Err(poisoned(err));
+            }
+            if state.durable >= ticket {
+                break;
+            }
+            if state.leading {
+                state = self.synced.wait(state).unwrap();
+                continue;
+            }
+            state.leading = true;
+            drop(state);
+            let led = self.lead_batch();
+            state = self.state.lock().unwrap();
+            led?;
+        }
+
+        state.wait_latency.record(start.elapsed(), unix_secs());
+        Ok(())
+    }
+
+    /// Collect and sync one batch as its leader, without the state lock.
+    /// However it ends, `leading` is cleared and the waiters are woken.
+    fn lead_batch(&self) -> Result<()> {
+        let _leading = Leading(self);
+        if !self.window.is_zero() {
+            std::thread::sleep(self.window);
+        }
+
+        let (covered, files) = {
+            let state = self.state.lock().unwrap();
+            let files: std::io::Result<Vec<File>> =
+                state.files.iter().map(|(_, f)| f.try_clone()).collect();
+            (state.issued, files)
+        };
+        // Nothing was synced, so waiters may retry with a new leader.
+        let files = files?;
+
+        let sync_start = Instant::now();
+        let synced = files.iter().try_for_each(File::sync_data);
+        let sync_time = sync_start.elapsed();
+
+        let mut state = self.state.lock().unwrap();
+        if let Err(err) = synced {
+            state.sync_failures += 1;
+            let err = err.to_string();
+            let poisoned = poisoned(&err);
+            state.failed = Some(err);
+            return Err(poisoned);
+        }
+        let batch = covered - state.durable;
+        state.durable = covered;
+        state.batches += 1;
+        state.max_batch = state.max_batch.max(batch);
+        state.sync_latency.record(sync_time, unix_secs());
+        Ok(())
+    }
+
+    pub fn stats(&self) -> CommitStats {
+        let state = self.state.lock().unwrap();
+        let now = unix_secs();
+        CommitStats {
+            window_us: self.window.as_micros() as u64,
+            commits_total: state.durable,
+            batches_total: state.batches,
+            mean_batch_size: if state.batches > 0 {
+                state.durable as f64 / state.batches as f64
+            } else {
+                0.0
+            },
+            max_batch_size: state.max_batch,
+            sync_failures_total: state.sync_failures,
+            failed: state.failed.clone(),
+            sync_ms: state.sync_latency.summary(now),
+            wait_ms: state.wait_latency.summary(now),
+            tracked_files: state.files.iter().map(|(name, _)| *name).collect(),
+        }
+    }
+}
+
+/// Clears `leading` and wakes the waiters when a batch leader is done.
+struct Leading<'a>(&'a CommitPipeline);
+
+impl Drop for Leading<'_> {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().leading = false;
+        self.0.synced.notify_all();
+    }
+}
+
+fn poisoned(err: &str) -> StoreError {
+    StoreError::Io(std::io::Error::other(format!(
+        "group commit sync failed ({err}); writes since may be lost, restart the server"
+    )))
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,73 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! How each context's head moved over time.
+//!
+//! `heads.tbl` is append-only: every create, fork and append writes the
+//! context's new head, and only the last record per context is its current
+//! head. The earlier records are its head history. Loading replays them in
+//! order and derives why each move happened from the record before it, so
+//! stores written before history was exposed have it too.
+
+use super::{ContextHead, TurnRecord};
+
+/// Why a context's head moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadCause {
+    /// An empty context was created.
+    Created,
+    /// The context was created from an existing turn.
+    Forked,
+    /// A turn was appended on top of the head.
+    Appended,
+    /// A turn was appended to an earlier turn, leaving the old head on a
+    /// side branch (a rewind or re-parent).
+    Branched,
+}
+
+impl HeadCause {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Forked => "forked",
+            Self::Appended => "appended",
+            Self::Branched => "branched",
+        }
+    }
+
+    /// Cause of moving to `head`, given the context's previous head (None
+    /// for a new context) and the new head turn, if any.
+    pub(super) fn of(
+        previous: Option<&ContextHead>,
+        head: &ContextHead,
+        turn: Option<&TurnRecord>,
+    ) -> Self {
+        match previous {
+            None if head.head_turn_id == 0 => Self::Created,
+            None => Self::Forked,
+            Some(previous) => match turn {
+                Some(turn) if turn.parent_turn_id != previous.head_turn_id => Self::Branched,
+                _ => Self::Appended,
+            },
+        }
+    }
+}
+
+/// One move of a context's head.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadMove {
+    pub turn_id: u64,
+    pub depth: u32,
+    pub at_unix_ms: u64,
+    pub cause: HeadCause,
+}
+
+/// A context as it was at an earlier instant.
+#[derive(Debug, Clone)]
+pub struct HeadAsOf {
+    /// The head it had then. `created_at_unix_ms` is when the context was
+    /// created, not when its head last moved.
+    pub head: ContextHead,
+    /// When the head moved to `head` — the context's last activity then.
+    pub moved_at_unix_ms: u64,
+}
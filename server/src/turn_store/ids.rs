@@ -0,0 +1,195 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Allocation of context and turn ids.
+//!
+//! The turn store relies on ids only ever growing: a turn's id is larger
+//! than its parent's, and listings order contexts by id. A generator is
+//! handed the largest id issued so far and must return a larger one, so a
+//! store can switch generators at any restart and existing ids stay valid.
+//!
+//! `sequential` (the default) counts up from 1. `snowflake` packs the
+//! milliseconds since 2025-01-01 UTC, a node id and a per-millisecond
+//! sequence into 63 bits, so stores on different nodes never issue the same
+//! id. Snowflake ids are far larger than any counter, so a store switched
+//! from counters continues above its existing ids.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Result, StoreError};
+
+/// Start of snowflake timestamps: 2025-01-01T00:00:00Z.
+pub const SNOWFLAKE_EPOCH_UNIX_MS: u64 = 1_735_689_600_000;
+
+const NODE_BITS: u32 = 10;
+const SEQUENCE_BITS: u32 = 12;
+const TIMESTAMP_BITS: u32 = 41;
+
+/// Largest node id a snowflake generator accepts.
+pub const MAX_NODE_ID: u16 = (1 << NODE_BITS) - 1;
+const MAX_SEQUENCE: u64 = (1 << SEQUENCE_BITS) - 1;
+const MAX_TIMESTAMP: u64 = (1 << TIMESTAMP_BITS) - 1;
+
+pub trait IdGenerator: Send {
+    /// An id larger than `last`, the largest id issued so far (0 if none).
+    fn next_id(&mut self, last: u64) -> u64;
+
+    /// Short description for startup logs and config checks.
+    fn describe(&self) -> String;
+}
+
+/// Ids counting up from 1.
+#[derive(Debug, Default)]
+pub struct SequentialIds;
+
+impl IdGenerator for SequentialIds {
+    fn next_id(&mut self, last: u64) -> u64 {
+        last + 1
+    }
+
+    fn describe(&self) -> String {
+        "sequential".to_string()
+    }
+}
+
+/// Timestamp, node id and sequence, most significant first.
+#[derive(Debug)]
+pub struct SnowflakeIds {
+    node_id: u16,
+}
+
+impl SnowflakeIds {
+    pub fn new(node_id: u16) -> Result<Self> {
+        if node_id > MAX_NODE_ID {
+            return Err(StoreError::InvalidInput(format!(
+                "node id {node_id} out of range (0-{MAX_NODE_ID})"
+            )));
+        }
+        Ok(Self { node_id })
+    }
+
+    /// The id issued at `now_unix_ms` after `last`. When the clock has not
+    /// moved past `last` (several ids in one millisecond, a clock stepping
+    /// back, or ids from another node), the id continues from `last`.
+    fn next_id_at(&self, last: u64, now_unix_ms: u64) -> u64 {
+        let now = now_unix_ms
+            .saturating_sub(SNOWFLAKE_EPOCH_UNIX_MS)
+            .min(MAX_TIMESTAMP);
+        let (last_timestamp, last_node, last_sequence) = split(last);
+        let next = compose(now.max(last_timestamp), self.node_id, 0);
+        if next > last {
+            next
+        } else if last_node == self.node_id && last_sequence < MAX_SEQUENCE {
+            last + 1
+        } else {
+            compose(last_timestamp + 1, self.node_id, 0)
+        }
+    }
+}
+
+impl IdGenerator for SnowflakeIds {
+    fn next_id(&mut self, last: u64) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self.next_id_at(last, now)
+    }
+
+    fn describe(&self) -> String {
+        format!("snowflake (node {})", self.node_id)
+    }
+}
+
+fn compose(timestamp: u64, node_id: u16, sequence: u64) -> u64 {
+    (timestamp << (NODE_BITS + SEQUENCE_BITS)) | (u64::from(node_id) << SEQUENCE_BITS) | sequence
+}
+
+fn split(id: u64) -> (u64, u16, u64) {
+    (
+        id >> (NODE_BITS + SEQUENCE_BITS),
+        ((id >> SEQUENCE_BITS) & u64::from(MAX_NODE_ID)) as u16,
+        id & MAX_SEQUENCE,
+    )
+}
+
+/// Which generator a deployment uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdGeneratorConfig {
+    Sequential,
+    Snowflake { node_id: u16 },
+}
+
+impl IdGeneratorConfig {
+    /// Load config from `CXDB_ID_GENERATOR` (`sequential` or `snowflake`,
+    /// default `sequential`) and `CXDB_NODE_ID` (0-1023, default 0).
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("CXDB_ID_GENERATOR").ok().as_deref() {
+            None | Some("") | Some("sequential") => Ok(Self::Sequential),
+            Some("snowflake") => {
+                let node_id = match std::env::var("CXDB_NODE_ID").ok().as_deref() {
+                    None | Some("") => 0,
+                    Some(raw) => raw
+                        .parse::<u16>()
+                        .ok()
+                        .filter(|&id| id <= MAX_NODE_ID)
+                        .ok_or_else(|| {
+                            StoreError::InvalidInput(format!(
+                                "invalid CXDB_NODE_ID {raw:?} (expected 0-{MAX_NODE_ID})"
+                            ))
+                        })?,
+                };
+                Ok(Self::Snowflake { node_id })
+            }
+            Some(other) => Err(StoreError::InvalidInput(format!(
+                "unknown CXDB_ID_GENERATOR {other:?} (expected sequential or snowflake)"
+            ))),
+        }
+    }
+
+    pub fn build(self) -> Result<Box<dyn IdGenerator>> {
+        Ok(match self {
+            Self::Sequential => Box::new(SequentialIds),
+            Self::Snowflake { node_id } => Box::new(SnowflakeIds::new(node_id)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = SNOWFLAKE_EPOCH_UNIX_MS + 1_000;
+
+    #[test]
+    fn snowflake_ids_carry_timestamp_node_and_sequence() {
+        let ids = SnowflakeIds::new(5).unwrap();
+        let first = ids.next_id_at(0, NOW);
+        assert_eq!(split(first), (1_000, 5, 0));
+        let second = ids.next_id_at(first, NOW);
+        assert_eq!(split(second), (1_000, 5, 1));
+        assert_eq!(split(ids.next_id_at(second, NOW + 3)), (1_003, 5, 0));
+    }
+
+    #[test]
+    fn snowflake_ids_stay_monotonic() {
+        let ids = SnowflakeIds::new(5).unwrap();
+        // Counter ids from a sequential store.
+        assert!(ids.next_id_at(42, NOW) > 42);
+        // The clock stepped back.
+        let last = compose(2_000, 5, 7);
+        assert_eq!(ids.next_id_at(last, NOW), last + 1);
+        // The sequence is exhausted.
+        let last = compose(1_000, 5, MAX_SEQUENCE);
+        assert_eq!(split(ids.next_id_at(last, NOW)), (1_001, 5, 0));
+        // A later id from a node with a higher id.
+        let last = compose(1_000, 9, 0);
+        assert_eq!(split(ids.next_id_at(last, NOW)), (1_001, 5, 0));
+    }
+
+    #[test]
+    fn node_ids_are_bounded() {
+        assert!(SnowflakeIds::new(MAX_NODE_ID).is_ok());
+        assert!(SnowflakeIds::new(MAX_NODE_ID + 1).is_err());
+    }
+}
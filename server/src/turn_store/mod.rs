@@ -1,7 +1,7 @@
 // Copyright 2025 StrongDM Inc
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::Path;
@@ -11,6 +11,46 @@ use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use crc32fast::Hasher;
 
 use crate::error::{Result, StoreError};
+use crate::metrics::ContextFootprint;
+
+mod author;
+mod chain;
+mod commit;
+mod head_history;
+mod ids;
+
+pub use author::TurnAuthor;
+pub use chain::{chain_hash, ChainLink, ChainVerification, TurnProof, ROOT_CHAIN_HASH};
+pub use commit::{CommitConfig, CommitPipeline, CommitStats};
+pub use head_history::{HeadAsOf, HeadCause, HeadMove};
+pub use ids::{
+    IdGenerator, IdGeneratorConfig, SequentialIds, SnowflakeIds, MAX_NODE_ID,
+    SNOWFLAKE_EPOCH_UNIX_MS,
+};
+
+/// Magic and version at the start of `turns.idx`. Index files without the
+/// header are the original 16-byte-entry format and are migrated on open.
+const INDEX_MAGIC: &[u8; 4] = b"CXTI";
+pub const INDEX_VERSION: u32 = 2;
+
+/// Version of the `turns.idx` in `dir`: 1 for the original headerless
+/// format (or no index at all), otherwise the version in its header.
+pub fn index_version(dir: &Path) -> Result<u32> {
+    let mut header = [0u8; 8];
+    let mut file = match File::open(dir.join("turns.idx")) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(1),
+        Err(e) => return Err(e.into()),
+    };
+    match file.read_exact(&mut header) {
+        Ok(()) if &header[..4] == INDEX_MAGIC => {
+            Ok(u32::from_le_bytes(header[4..].try_into().unwrap()))
+        }
+        Ok(()) => Ok(1),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(1),
+        Err(e) => Err(e.into()),
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct TurnRecord {
@@ -31,6 +71,8 @@ pub struct TurnMeta {
     pub encoding: u32,
     pub compression: u32,
     pub uncompressed_len: u32,
+    /// Session that appended the turn, when recorded.
+    pub author: Option<TurnAuthor>,
 }
 
 #[derive(Debug, Clone)]
@@ -52,14 +94,28 @@ pub struct TurnStore {
     turns_idx: File,
     turns_meta: File,
     heads_tbl: File,
+    turns_chain: File,
+    turns_authors: File,
 
     turns: HashMap<u64, TurnRecord>,
     turn_index: HashMap<u64, u64>,
+    /// Skip pointers: `skips[turn_id][k - 1]` is the ancestor 2^k turns
+    /// back. A turn at depth d has one for each k >= 1 where 2^k divides d.
+    skips: HashMap<u64, Vec<u64>>,
     turn_meta: HashMap<u64, TurnMeta>,
     heads: HashMap<u64, ContextHead>,
+    /// Every head each context has had, oldest first.
+    head_history: HashMap<u64, Vec<HeadMove>>,
+    /// Stored chain hash of every turn.
+    chain: HashMap<u64, [u8; 32]>,
 
-    next_turn_id: u64,
-    next_context_id: u64,
+    /// Largest turn and context ids issued so far.
+    last_turn_id: u64,
+    last_context_id: u64,
+    /// Id handed out by `reserve_turn_id` for the next append.
+    reserved_turn_id: Option<u64>,
+    /// Allocates new turn and context ids.
+    ids: Box<dyn IdGenerator>,
 }
 
 impl TurnStore {
@@ -72,28 +128,40 @@ impl TurnStore {
 
         let turns_log = OpenOptions::new()
             .create(true)
-            .truncate(true)
+            .truncate(false)
             .read(true)
             .write(true)
             .open(&turns_log_path)?;
         let turns_idx = OpenOptions::new()
             .create(true)
-            .truncate(true)
+            .truncate(false)
             .read(true)
             .write(true)
             .open(&turns_idx_path)?;
         let turns_meta = OpenOptions::new()
             .create(true)
-            .truncate(true)
+            .truncate(false)
             .read(true)
             .write(true)
             .open(&turns_meta_path)?;
         let heads_tbl = OpenOptions::new()
             .create(true)
-            .truncate(true)
+            .truncate(false)
             .read(true)
             .write(true)
             .open(&heads_tbl_path)?;
+        let turns_chain = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(dir.join("turns.chain"))?;
+        let turns_authors = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(dir.join("turns.authors"))?;
 
         let mut store = Self {
             turns_log_path,
@@ -104,23 +172,44 @@ impl TurnStore {
             turns_idx,
             turns_meta,
             heads_tbl,
+            turns_chain,
+            turns_authors,
             turns: HashMap::new(),
             turn_index: HashMap::new(),
+            skips: HashMap::new(),
             turn_meta: HashMap::new(),
             heads: HashMap::new(),
-            next_turn_id: 1,
-            next_context_id: 1,
+            head_history: HashMap::new(),
+            chain: HashMap::new(),
+            last_turn_id: 0,
+            last_context_id: 0,
+            reserved_turn_id: None,
+            ids: Box::new(SequentialIds),
         };
 
         store.load_turns()?;
         store.load_meta()?;
         store.load_heads()?;
+        store.load_index()?;
         store.rebuild_index()?;
+        store.load_chain()?;
+        store.load_authors()?;
         store.update_counters();
 
         Ok(store)
     }
 
+    /// Sync the turn files with every group commit batch.
+    pub fn track_files(&self, pipeline: &CommitPipeline) -> Result<()> {
+        pipeline.track("turns.log", &self.turns_log)?;
+        pipeline.track("turns.idx", &self.turns_idx)?;
+        pipeline.track("turns.meta", &self.turns_meta)?;
+        pipeline.track("heads.tbl", &self.heads_tbl)?;
+        pipeline.track("turns.chain", &self.turns_chain)?;
+        pipeline.track("turns.authors", &self.turns_authors)?;
+        Ok(())
+    }
+
     pub fn stats(&self) -> TurnStoreStats {
         TurnStoreStats {
             turns_total: self.turns.len(),
@@ -225,6 +314,7 @@ impl TurnStore {
                     encoding,
                     compression,
                     uncompressed_len,
+                    author: None,
                 },
             );
         }
@@ -234,6 +324,7 @@ impl TurnStore {
 
     fn load_heads(&mut self) -> Result<()> {
         self.heads.clear();
+        self.head_history.clear();
         self.heads_tbl.seek(SeekFrom::Start(0))?;
         loop {
             let start = self.heads_tbl.stream_position()?;
@@ -292,38 +383,245 @@ impl TurnStore {
                 break;
             }
 
-            self.heads.insert(
+            self.set_head(ContextHead {
                 context_id,
-                ContextHead {
-                    context_id,
-                    head_turn_id,
-                    head_depth,
-                    created_at_unix_ms,
-                    flags,
-                },
+                head_turn_id,
+                head_depth,
+                created_at_unix_ms,
+                flags,
+            });
+        }
+        Ok(())
+    }
+
+    /// Load skip pointers from `turns.idx`, then compute any the file does
+    /// not hold: all of them for an index in the original format, or for
+    /// entries past a torn tail.
+    fn load_index(&mut self) -> Result<()> {
+        self.skips.clear();
+        let mut buf = Vec::new();
+        self.turns_idx.seek(SeekFrom::Start(0))?;
+        self.turns_idx.read_to_end(&mut buf)?;
+
+        let versioned = buf.len() >= 8 && &buf[..4] == INDEX_MAGIC;
+        if versioned {
+            let version = u32::from_le_bytes(buf[4..8].try_into().unwrap());
+            if version != INDEX_VERSION {
+                return Err(StoreError::Corrupt(format!(
+                    "unsupported turns.idx version {version}"
+                )));
+            }
+            let mut cursor = std::io::Cursor::new(&buf[8..]);
+            while let Ok((turn_id, skips)) = read_index_entry(&mut cursor) {
+                if !skips.is_empty() && self.valid_skips(turn_id, &skips) {
+                    self.skips.insert(turn_id, skips);
+                }
+            }
+        }
+
+        let mut turn_ids: Vec<u64> = self.turns.keys().copied().collect();
+        // Parents have lower ids than their children, so ascending order
+        // computes every turn's ancestors' pointers before its own.
+        turn_ids.sort_unstable();
+        let mut computed = 0usize;
+        for turn_id in turn_ids {
+            let turn = &self.turns[&turn_id];
+            if skip_levels(turn.depth) == 0 || self.skips.contains_key(&turn_id) {
+                continue;
+            }
+            let skips = self.compute_skips(turn.parent_turn_id, turn.depth);
+            self.skips.insert(turn_id, skips);
+            computed += 1;
+        }
+        if !buf.is_empty() && !versioned {
+            tracing::info!(
+                turns = self.turns.len(),
+                "migrating turns.idx to skip pointer format"
             );
+        } else if computed > 0 && versioned {
+            tracing::info!(computed, "rebuilt missing turns.idx skip pointers");
+        }
+        Ok(())
+    }
+
+    /// Load chain hashes from `turns.chain` and append any missing ones: all
+    /// of them for a store written before chaining, or turns past a torn
+    /// tail. Stored hashes are not checked here; see [`Self::verify_chains`].
+    /// Load `turns.authors` into the turn metadata, dropping a torn tail.
+    fn load_authors(&mut self) -> Result<()> {
+        self.turns_authors.seek(SeekFrom::Start(0))?;
+        loop {
+            let start = self.turns_authors.stream_position()?;
+            match author::read_author_entry(&mut self.turns_authors) {
+                Ok((turn_id, author)) => {
+                    if let Some(meta) = self.turn_meta.get_mut(&turn_id) {
+                        meta.author = Some(author);
+                    }
+                }
+                Err(StoreError::Io(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    self.turns_authors.set_len(start)?;
+                    break;
+                }
+                Err(StoreError::Corrupt(_)) => {
+                    self.turns_authors.set_len(start)?;
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
         }
         Ok(())
     }
 
+    fn load_chain(&mut self) -> Result<()> {
+        self.chain.clear();
+        self.turns_chain.seek(SeekFrom::Start(0))?;
+        loop {
+            let start = self.turns_chain.stream_position()?;
+            match chain::read_chain_entry(&mut self.turns_chain) {
+                Ok((turn_id, hash)) => {
+                    self.chain.insert(turn_id, hash);
+                }
+                Err(StoreError::Io(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    self.turns_chain.set_len(start)?;
+                    break;
+                }
+                Err(StoreError::Corrupt(_)) => {
+                    self.turns_chain.set_len(start)?;
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut missing: Vec<u64> = self
+            .turns
+            .keys()
+            .filter(|id| !self.chain.contains_key(id))
+            .copied()
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        // Parents have lower ids than their children.
+        missing.sort_unstable();
+        let mut buf = Vec::with_capacity(44 * missing.len());
+        for turn_id in &missing {
+            let hash = self.compute_chain_hash(&self.turns[turn_id]);
+            buf.extend_from_slice(&chain::encode_chain_entry(*turn_id, &hash)?);
+            self.chain.insert(*turn_id, hash);
+        }
+        self.turns_chain.seek(SeekFrom::End(0))?;
+        self.turns_chain.write_all(&buf)?;
+        self.turns_chain.flush()?;
+        tracing::info!(turns = missing.len(), "computed missing turn chain hashes");
+        Ok(())
+    }
+
+    /// Chain hash of a turn from its parent's stored chain hash.
+    fn compute_chain_hash(&self, record: &TurnRecord) -> [u8; 32] {
+        let parent = if record.parent_turn_id == 0 {
+            ROOT_CHAIN_HASH
+        } else {
+            self.chain
+                .get(&record.parent_turn_id)
+                .copied()
+                .unwrap_or(ROOT_CHAIN_HASH)
+        };
+        let meta = self
+            .turn_meta
+            .get(&record.turn_id)
+            .cloned()
+            .unwrap_or(TurnMeta {
+                declared_type_id: String::new(),
+                declared_type_version: 0,
+                encoding: 0,
+                compression: 0,
+                uncompressed_len: 0,
+                author: None,
+            });
+        chain_hash(&parent, record, &meta)
+    }
+
+    /// Whether stored skip pointers match the turn log.
+    fn valid_skips(&self, turn_id: u64, skips: &[u64]) -> bool {
+        let Some(turn) = self.turns.get(&turn_id) else {
+            return false;
+        };
+        skips.len() == skip_levels(turn.depth)
+            && skips.iter().enumerate().all(|(i, ancestor)| {
+                self.turns
+                    .get(ancestor)
+                    .is_some_and(|a| a.depth + (2 << i) == turn.depth)
+            })
+    }
+
     fn rebuild_index(&mut self) -> Result<()> {
         self.turns_idx.set_len(0)?;
         self.turns_idx.seek(SeekFrom::Start(0))?;
+        self.turns_idx.write_all(INDEX_MAGIC)?;
+        self.turns_idx.write_u32::<LittleEndian>(INDEX_VERSION)?;
+        let mut buf = Vec::new();
         for (turn_id, offset) in self.turn_index.iter() {
-            self.turns_idx.write_u64::<LittleEndian>(*turn_id)?;
-            self.turns_idx.write_u64::<LittleEndian>(*offset)?;
+            let skips = self.skips.get(turn_id).map_or(&[][..], |s| s.as_slice());
+            write_index_entry(&mut buf, *turn_id, *offset, skips)?;
         }
+        self.turns_idx.write_all(&buf)?;
         self.turns_idx.flush()?;
         Ok(())
     }
 
-    fn update_counters(&mut self) {
-        if let Some(max_id) = self.turns.keys().max().cloned() {
-            self.next_turn_id = max_id + 1;
+    /// Skip pointers for a new turn at `depth` whose parent is `parent_turn_id`.
+    fn compute_skips(&self, parent_turn_id: u64, depth: u32) -> Vec<u64> {
+        let levels = skip_levels(depth);
+        let mut skips = Vec::with_capacity(levels);
+        let mut ancestor = parent_turn_id;
+        for level in 1..=levels {
+            // 2^level back is 2^(level-1) back from 2^(level-1) back.
+            ancestor = self.jump(ancestor, level - 1);
+            skips.push(ancestor);
         }
-        if let Some(max_ctx) = self.heads.keys().max().cloned() {
-            self.next_context_id = max_ctx + 1;
+        skips
+    }
+
+    /// The ancestor 2^level turns above `turn_id`, falling back to the
+    /// parent when the pointer is missing.
+    fn jump(&self, turn_id: u64, level: usize) -> u64 {
+        if level > 0 {
+            if let Some(&ancestor) = self.skips.get(&turn_id).and_then(|s| s.get(level - 1)) {
+                return ancestor;
+            }
         }
+        self.turns.get(&turn_id).map_or(0, |t| t.parent_turn_id)
+    }
+
+    fn update_counters(&mut self) {
+        self.last_turn_id = self.turns.keys().max().copied().unwrap_or(0);
+        self.last_context_id = self.heads.keys().max().copied().unwrap_or(0);
+    }
+
+    /// The id the next `append_turn` will give its turn, so records that
+    /// must be durable before the turn can name it.
+    pub fn reserve_turn_id(&mut self) -> u64 {
+        *self
+            .reserved_turn_id
+            .get_or_insert_with(|| self.ids.next_id(self.last_turn_id))
+    }
+
+    /// Largest turn id in the store (0 if empty).
+    pub fn last_turn_id(&self) -> u64 {
+        self.last_turn_id
+    }
+
+    /// Allocate ids for new turns and contexts with `ids` from now on.
+    /// Existing ids are kept; new ones continue above them.
+    pub fn set_id_generator(&mut self, ids: Box<dyn IdGenerator>) {
+        self.ids = ids;
+        self.reserved_turn_id = None;
+    }
+
+    /// Description of the id generator in use.
+    pub fn id_generator(&self) -> String {
+        self.ids.describe()
     }
 
     fn now_unix_ms() -> u64 {
@@ -344,8 +642,8 @@ impl TurnStore {
             (turn.turn_id, turn.depth)
         };
 
-        let context_id = self.next_context_id;
-        self.next_context_id += 1;
+        let context_id = self.ids.next_id(self.last_context_id);
+        self.last_context_id = context_id;
 
         let head = ContextHead {
             context_id,
@@ -356,7 +654,7 @@ impl TurnStore {
         };
 
         self.write_head(&head)?;
-        self.heads.insert(context_id, head.clone());
+        self.set_head(head.clone());
         Ok(head)
     }
 
@@ -405,8 +703,9 @@ impl TurnStore {
             }
         };
 
-        let turn_id = self.next_turn_id;
-        self.next_turn_id += 1;
+        let turn_id = self.reserve_turn_id();
+        self.reserved_turn_id = None;
+        self.last_turn_id = turn_id;
 
         let record = TurnRecord {
             turn_id,
@@ -424,9 +723,11 @@ impl TurnStore {
         self.turns_log.write_all(&bytes)?;
         self.turns_log.flush()?;
 
+        let skips = self.compute_skips(parent_id, depth);
+        let mut entry = Vec::with_capacity(17 + 8 * skips.len());
+        write_index_entry(&mut entry, turn_id, offset, &skips)?;
         self.turns_idx.seek(SeekFrom::End(0))?;
-        self.turns_idx.write_u64::<LittleEndian>(turn_id)?;
-        self.turns_idx.write_u64::<LittleEndian>(offset)?;
+        self.turns_idx.write_all(&entry)?;
         self.turns_idx.flush()?;
 
         // store meta
@@ -450,10 +751,21 @@ impl TurnStore {
                 encoding,
                 compression,
                 uncompressed_len,
+                author: None,
             },
         );
         self.turns.insert(turn_id, record.clone());
         self.turn_index.insert(turn_id, offset);
+        if !skips.is_empty() {
+            self.skips.insert(turn_id, skips);
+        }
+
+        let hash = self.compute_chain_hash(&record);
+        self.turns_chain.seek(SeekFrom::End(0))?;
+        self.turns_chain
+            .write_all(&chain::encode_chain_entry(turn_id, &hash)?)?;
+        self.turns_chain.flush()?;
+        self.chain.insert(turn_id, hash);
 
         // update head
         let head = ContextHead {
@@ -464,11 +776,81 @@ impl TurnStore {
             flags: 0,
         };
         self.write_head(&head)?;
-        self.heads.insert(context_id, head);
+        self.set_head(head);
 
         Ok(record)
     }
 
+    /// Record the session that appended a turn.
+    pub fn set_author(&mut self, turn_id: u64, author: TurnAuthor) -> Result<()> {
+        let meta = self
+            .turn_meta
+            .get_mut(&turn_id)
+            .ok_or_else(|| StoreError::NotFound("turn".into()))?;
+        self.turns_authors.seek(SeekFrom::End(0))?;
+        self.turns_authors
+            .write_all(&author::encode_author_entry(turn_id, &author)?)?;
+        self.turns_authors.flush()?;
+        meta.author = Some(author);
+        Ok(())
+    }
+
+    /// Make `head` the context's current head and record the move.
+    fn set_head(&mut self, head: ContextHead) {
+        let cause = HeadCause::of(
+            self.heads.get(&head.context_id),
+            &head,
+            self.turns.get(&head.head_turn_id),
+        );
+        self.head_history
+            .entry(head.context_id)
+            .or_default()
+            .push(HeadMove {
+                turn_id: head.head_turn_id,
+                depth: head.head_depth,
+                at_unix_ms: head.created_at_unix_ms,
+                cause,
+            });
+        self.heads.insert(head.context_id, head);
+    }
+
+    /// Every head the context has had, oldest first.
+    pub fn head_history(&self, context_id: u64) -> Result<&[HeadMove]> {
+        self.head_history
+            .get(&context_id)
+            .map(Vec::as_slice)
+            .ok_or_else(|| StoreError::NotFound("context".into()))
+    }
+
+    /// Contexts that existed at `as_of_ms`, each with the head it had at
+    /// that instant, ordered as `list_recent_contexts` would have ordered
+    /// them then.
+    pub fn list_contexts_as_of(&self, as_of_ms: u64, limit: u32) -> Vec<HeadAsOf> {
+        let mut contexts: Vec<HeadAsOf> = self
+            .head_history
+            .iter()
+            .filter_map(|(context_id, moves)| {
+                let head = moves
+                    .iter()
+                    .take_while(|m| m.at_unix_ms <= as_of_ms)
+                    .last()?;
+                Some(HeadAsOf {
+                    head: ContextHead {
+                        context_id: *context_id,
+                        head_turn_id: head.turn_id,
+                        head_depth: head.depth,
+                        created_at_unix_ms: moves[0].at_unix_ms,
+                        flags: self.heads.get(context_id).map_or(0, |h| h.flags),
+                    },
+                    moved_at_unix_ms: head.at_unix_ms,
+                })
+            })
+            .collect();
+        contexts.sort_by_key(|c| std::cmp::Reverse(c.moved_at_unix_ms));
+        contexts.truncate(limit as usize);
+        contexts
+    }
+
     fn write_head(&mut self, head: &ContextHead) -> Result<()> {
         let mut buf = Vec::with_capacity(8 + 8 + 4 + 4 + 8 + 4);
         buf.write_u64::<LittleEndian>(head.context_id)?;
@@ -562,22 +944,243 @@ impl TurnStore {
             .get(&context_id)
             .ok_or_else(|| StoreError::NotFound("context".into()))?;
 
-        // Walk back from head to find the turn with depth=0
-        let mut current = head.head_turn_id;
-        while current != 0 {
-            let rec = self
+        if head.head_turn_id == 0 {
+            return Err(StoreError::NotFound("first turn".into()));
+        }
+        self.ancestor_at_depth(head.head_turn_id, 0).cloned()
+    }
+
+    /// The ancestor of `turn_id` (or the turn itself) at `depth`, found in
+    /// O(log depth) steps by following skip pointers.
+    pub fn ancestor_at_depth(&self, turn_id: u64, depth: u32) -> Result<&TurnRecord> {
+        let mut turn = self
+            .turns
+            .get(&turn_id)
+            .ok_or_else(|| StoreError::NotFound("turn".into()))?;
+        if depth > turn.depth {
+            return Err(StoreError::NotFound("turn at depth".into()));
+        }
+        while turn.depth > depth {
+            // The longest jump this turn has that does not pass `depth`.
+            let level = turn
+                .depth
+                .trailing_zeros()
+                .min((turn.depth - depth).ilog2()) as usize;
+            turn = self
                 .turns
-                .get(&current)
+                .get(&self.jump(turn.turn_id, level))
                 .ok_or_else(|| StoreError::NotFound("turn".into()))?;
-            if rec.depth == 0 {
-                return Ok(rec.clone());
+        }
+        Ok(turn)
+    }
+
+    /// Up to `limit` turns of a context starting at `start_depth`, oldest
+    /// first. Seeks to the end of the range instead of walking from the head.
+    pub fn get_range_by_depth(
+        &self,
+        context_id: u64,
+        start_depth: u32,
+        limit: u32,
+    ) -> Result<Vec<TurnRecord>> {
+        let head = self
+            .heads
+            .get(&context_id)
+            .ok_or_else(|| StoreError::NotFound("context".into()))?;
+        if head.head_turn_id == 0 {
+            return Ok(Vec::new());
+        }
+        self.get_ancestry(head.head_turn_id, start_depth, limit)
+    }
+
+    /// Up to `limit` turns of the root-to-`turn_id` path starting at
+    /// `start_depth`, oldest first. Like `get_range_by_depth`, it seeks to
+    /// the end of the range through the skip pointers.
+    pub fn get_ancestry(
+        &self,
+        turn_id: u64,
+        start_depth: u32,
+        limit: u32,
+    ) -> Result<Vec<TurnRecord>> {
+        let tip = self
+            .turns
+            .get(&turn_id)
+            .ok_or_else(|| StoreError::NotFound("turn".into()))?;
+        if limit == 0 || start_depth > tip.depth {
+            return Ok(Vec::new());
+        }
+
+        let end_depth = start_depth.saturating_add(limit - 1).min(tip.depth);
+        let mut results = Vec::with_capacity((end_depth - start_depth + 1) as usize);
+        let mut current = self.ancestor_at_depth(turn_id, end_depth)?;
+        loop {
+            results.push(current.clone());
+            if current.depth == start_depth {
+                break;
             }
-            current = rec.parent_turn_id;
+            current = self
+                .turns
+                .get(&current.parent_turn_id)
+                .ok_or_else(|| StoreError::NotFound("turn".into()))?;
+        }
+        results.reverse();
+        Ok(results)
+    }
+
+    /// All turn records, in no particular order.
+    pub fn turns(&self) -> impl Iterator<Item = &TurnRecord> {
+        self.turns.values()
+    }
+
+    /// All context heads, in no particular order.
+    pub fn heads(&self) -> impl Iterator<Item = &ContextHead> {
+        self.heads.values()
+    }
+
+    /// Turns and uncompressed payload bytes per context, with the time of
+    /// its last append. Shared history counts once, against the oldest
+    /// context that reaches it. The tag is left empty.
+    pub fn context_footprints(&self) -> Vec<ContextFootprint> {
+        let mut heads: Vec<&ContextHead> = self.heads.values().collect();
+        heads.sort_by_key(|h| h.context_id);
+        let mut seen: HashSet<u64> = HashSet::with_capacity(self.turns.len());
+        heads
+            .into_iter()
+            .map(|head| {
+                let mut footprint = ContextFootprint {
+                    context_id: head.context_id,
+                    tag: String::new(),
+                    last_append_unix_ms: self
+                        .turns
+                        .get(&head.head_turn_id)
+                        .map(|t| t.created_at_unix_ms)
+                        .unwrap_or(head.created_at_unix_ms),
+                    turns: 0,
+                    bytes: 0,
+                };
+                let mut turn_id = head.head_turn_id;
+                while turn_id != 0 && seen.insert(turn_id) {
+                    let Some(turn) = self.turns.get(&turn_id) else {
+                        break;
+                    };
+                    footprint.turns += 1;
+                    footprint.bytes += self
+                        .turn_meta
+                        .get(&turn_id)
+                        .map(|m| m.uncompressed_len as u64)
+                        .unwrap_or(0);
+                    turn_id = turn.parent_turn_id;
+                }
+                footprint
+            })
+            .collect()
+    }
+
+    /// Stored chain hash of a turn.
+    pub fn chain_hash(&self, turn_id: u64) -> Result<[u8; 32]> {
+        self.chain
+            .get(&turn_id)
+            .copied()
+            .ok_or_else(|| StoreError::NotFound("turn".into()))
+    }
+
+    /// Every context's head turn and chain head, by context id. A context
+    /// without turns has the root chain hash.
+    pub fn chain_heads(&self) -> Vec<(u64, u64, [u8; 32])> {
+        let mut heads: Vec<(u64, u64, [u8; 32])> = self
+            .heads
+            .values()
+            .map(|h| {
+                let chain = self
+                    .chain
+                    .get(&h.head_turn_id)
+                    .copied()
+                    .unwrap_or(ROOT_CHAIN_HASH);
+                (h.context_id, h.head_turn_id, chain)
+            })
+            .collect();
+        heads.sort_unstable_by_key(|(context_id, _, _)| *context_id);
+        heads
+    }
+
+    fn chain_link(&self, turn_id: u64) -> Result<ChainLink> {
+        Ok(ChainLink {
+            record: self.get_turn(turn_id)?,
+            meta: self.get_turn_meta(turn_id)?,
+            chain_hash: self.chain_hash(turn_id)?,
+        })
+    }
+
+    /// Links from `turn_id` up to (excluding) `stop_at`, oldest first.
+    fn chain_links(&self, mut turn_id: u64, stop_at: u64) -> Result<Vec<ChainLink>> {
+        let mut links = Vec::new();
+        while turn_id != stop_at && turn_id != 0 {
+            let link = self.chain_link(turn_id)?;
+            turn_id = link.record.parent_turn_id;
+            links.push(link);
         }
+        links.reverse();
+        Ok(links)
+    }
+
+    /// Every link of a context's head chain, root first. The last link's
+    /// chain hash is the context's chain head.
+    pub fn context_chain(&self, context_id: u64) -> Result<Vec<ChainLink>> {
+        let head = self.get_head(context_id)?;
+        self.chain_links(head.head_turn_id, 0)
+    }
+
+    /// Proof that `turn_id` is in the history of a context's head.
+    pub fn turn_proof(&self, turn_id: u64, context_id: u64) -> Result<TurnProof> {
+        let head = self.get_head(context_id)?;
+        let turn = self.chain_link(turn_id)?;
+        if !self.is_ancestor(turn_id, head.head_turn_id) {
+            return Err(StoreError::InvalidInput(
+                "turn is not on the context's head chain".into(),
+            ));
+        }
+        Ok(TurnProof {
+            path: self.chain_links(head.head_turn_id, turn_id)?,
+            chain_head: self.chain_hash(head.head_turn_id)?,
+            turn,
+            context_id,
+            head_turn_id: head.head_turn_id,
+        })
+    }
+
+    /// Recompute every turn's chain hash from its parent's stored hash and
+    /// compare it with the stored one.
+    pub fn verify_chains(&self) -> ChainVerification {
+        let mut turn_ids: Vec<u64> = self.turns.keys().copied().collect();
+        turn_ids.sort_unstable();
+        let mut report = ChainVerification::default();
+        for turn_id in turn_ids {
+            report.turns_checked += 1;
+            let expected = self.compute_chain_hash(&self.turns[&turn_id]);
+            if self.chain.get(&turn_id) != Some(&expected) {
+                report.record_mismatch(turn_id);
+            }
+        }
+        report
+    }
+
+    /// Whether a turn's stored chain hash matches the one recomputed from its
+    /// record and its parent's stored hash.
+    pub fn chain_hash_matches(&self, turn_id: u64) -> bool {
+        self.turns.get(&turn_id).is_some_and(|record| {
+            self.chain.get(&turn_id) == Some(&self.compute_chain_hash(record))
+        })
+    }
 
-        Err(StoreError::NotFound("first turn".into()))
+    /// Whether `ancestor_id` is `turn_id` or one of its ancestors.
+    pub fn is_ancestor(&self, ancestor_id: u64, turn_id: u64) -> bool {
+        let Some(ancestor) = self.turns.get(&ancestor_id) else {
+            return false;
+        };
+        self.ancestor_at_depth(turn_id, ancestor.depth)
+            .is_ok_and(|turn| turn.turn_id == ancestor_id)
     }
 
+    #[allow(clippy::unnecessary_sort_by)]
     pub fn list_recent_contexts(&self, limit: u32) -> Vec<ContextHead> {
         let mut contexts: Vec<ContextHead> = self.heads.values().cloned().collect();
         // Sort by created_at descending (most recent first)
@@ -598,6 +1201,37 @@ pub struct TurnStoreStats {
     pub heads_table_bytes: u64,
 }
 
+/// Number of skip pointers a turn at `depth` carries.
+fn skip_levels(depth: u32) -> usize {
+    if depth == 0 {
+        0
+    } else {
+        depth.trailing_zeros() as usize
+    }
+}
+
+/// One `turns.idx` entry: turn id, log offset, skip pointer count and the
+/// skip pointers.
+fn write_index_entry(buf: &mut Vec<u8>, turn_id: u64, offset: u64, skips: &[u64]) -> Result<()> {
+    buf.write_u64::<LittleEndian>(turn_id)?;
+    buf.write_u64::<LittleEndian>(offset)?;
+    buf.write_u8(skips.len() as u8)?;
+    for ancestor in skips {
+        buf.write_u64::<LittleEndian>(*ancestor)?;
+    }
+    Ok(())
+}
+
+fn read_index_entry(reader: &mut impl Read) -> std::io::Result<(u64, Vec<u64>)> {
+    let turn_id = reader.read_u64::<LittleEndian>()?;
+    let _offset = reader.read_u64::<LittleEndian>()?;
+    let count = reader.read_u8()?;
+    let skips = (0..count)
+        .map(|_| reader.read_u64::<LittleEndian>())
+        .collect::<std::io::Result<Vec<u64>>>()?;
+    Ok((turn_id, skips))
+}
+
 fn file_len(path: &std::path::PathBuf) -> u64 {
     std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
 }
@@ -659,3 +1293,147 @@ fn read_turn_record(reader: &mut File) -> Result<TurnRecord> {
         created_at_unix_ms,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn append(store: &mut TurnStore, context_id: u64) -> TurnRecord {
+        store
+            .append_turn(context_id, 0, [0u8; 32], 1, "t".into(), 1, 0, 0)
+            .unwrap()
+    }
+
+    /// Ancestor at `depth` found by walking parent links.
+    fn walk_to_depth(store: &TurnStore, turn_id: u64, depth: u32) -> u64 {
+        let mut turn = &store.turns[&turn_id];
+        while turn.depth > depth {
+            turn = &store.turns[&turn.parent_turn_id];
+        }
+        turn.turn_id
+    }
+
+    #[test]
+    fn test_skip_pointers_seek_to_any_depth() {
+        let dir = tempdir().unwrap();
+        let mut store = TurnStore::open(dir.path()).unwrap();
+        let ctx = store.create_context(0).unwrap();
+        let turns: Vec<u64> = (0..1_000)
+            .map(|_| append(&mut store, ctx.context_id).turn_id)
+            .collect();
+        let fork = store.fork_context(turns[600]).unwrap();
+        let fork_tip = (0..300)
+            .map(|_| append(&mut store, fork.context_id).turn_id)
+            .last()
+            .unwrap();
+
+        for tip in [turns[999], turns[512], fork_tip] {
+            let tip_depth = store.turns[&tip].depth;
+            for depth in 0..=tip_depth {
+                let found = store.ancestor_at_depth(tip, depth).unwrap();
+                assert_eq!(found.turn_id, walk_to_depth(&store, tip, depth));
+            }
+        }
+        assert!(store.ancestor_at_depth(turns[10], 11).is_err());
+        assert!(store.is_ancestor(turns[600], fork_tip));
+        assert!(!store.is_ancestor(turns[601], fork_tip));
+        assert_eq!(
+            store.get_first_turn(fork.context_id).unwrap().turn_id,
+            turns[0]
+        );
+
+        let range = store.get_range_by_depth(fork.context_id, 599, 4).unwrap();
+        let depths: Vec<u32> = range.iter().map(|t| t.depth).collect();
+        assert_eq!(depths, vec![599, 600, 601, 602]);
+        assert_eq!(range[1].turn_id, turns[600]);
+        assert_ne!(range[2].turn_id, turns[601]);
+        let tail = store.get_range_by_depth(ctx.context_id, 998, 10).unwrap();
+        assert_eq!(tail.len(), 2);
+        assert!(store
+            .get_range_by_depth(ctx.context_id, 1_000, 10)
+            .unwrap()
+            .is_empty());
+
+        // Ancestry pages the path to any turn, not only to a head.
+        let path = store.get_ancestry(turns[700], 698, 10).unwrap();
+        let ids: Vec<u64> = path.iter().map(|t| t.turn_id).collect();
+        assert_eq!(ids, turns[698..=700].to_vec());
+        let fork_path = store.get_ancestry(fork_tip, 0, 2_000).unwrap();
+        assert_eq!(fork_path.len(), 901);
+        assert_eq!(fork_path[600].turn_id, turns[600]);
+        assert_eq!(fork_path[900].turn_id, fork_tip);
+        assert!(store.get_ancestry(turns[10], 11, 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_legacy_index_is_migrated() {
+        let dir = tempdir().unwrap();
+        let mut store = TurnStore::open(dir.path()).unwrap();
+        let ctx = store.create_context(0).unwrap();
+        for _ in 0..100 {
+            append(&mut store, ctx.context_id);
+        }
+        let expected = store.skips.clone();
+        let tip = store.get_head(ctx.context_id).unwrap().head_turn_id;
+
+        // Rewrite turns.idx in the original format: 16-byte entries, no header.
+        store.turns_idx.set_len(0).unwrap();
+        store.turns_idx.seek(SeekFrom::Start(0)).unwrap();
+        for (turn_id, offset) in store.turn_index.clone() {
+            store.turns_idx.write_u64::<LittleEndian>(turn_id).unwrap();
+            store.turns_idx.write_u64::<LittleEndian>(offset).unwrap();
+        }
+        store.load_index().unwrap();
+        assert_eq!(store.skips, expected);
+        store.rebuild_index().unwrap();
+
+        // The rewritten index carries the skip pointers.
+        store.skips.clear();
+        store.load_index().unwrap();
+        assert_eq!(store.skips, expected);
+        let mut header = [0u8; 8];
+        store.turns_idx.seek(SeekFrom::Start(0)).unwrap();
+        store.turns_idx.read_exact(&mut header).unwrap();
+        assert_eq!(&header[..4], INDEX_MAGIC);
+        assert_eq!(store.ancestor_at_depth(tip, 3).unwrap().depth, 3);
+    }
+
+    #[test]
+    fn test_chain_proofs_and_tamper_detection() {
+        let dir = tempdir().unwrap();
+        let mut store = TurnStore::open(dir.path()).unwrap();
+        let ctx = store.create_context(0).unwrap().context_id;
+        let turns: Vec<u64> = (0..5).map(|_| append(&mut store, ctx).turn_id).collect();
+
+        let links = store.context_chain(ctx).unwrap();
+        assert_eq!(links.len(), 5);
+        let mut hash = ROOT_CHAIN_HASH;
+        for link in &links {
+            hash = chain_hash(&hash, &link.record, &link.meta);
+            assert_eq!(hash, link.chain_hash);
+        }
+
+        let proof = store.turn_proof(turns[1], ctx).unwrap();
+        assert_eq!(proof.path.len(), 3);
+        let head = proof.path.iter().fold(proof.turn.chain_hash, |hash, link| {
+            chain_hash(&hash, &link.record, &link.meta)
+        });
+        assert_eq!(head, proof.chain_head);
+        let fork = store.fork_context(turns[1]).unwrap().context_id;
+        let forked = append(&mut store, fork).turn_id;
+        assert!(store.turn_proof(forked, ctx).is_err());
+
+        // Hashes lost with a torn chain file are recomputed on load.
+        let expected = store.chain.clone();
+        store.turns_chain.set_len(44 * 2 + 10).unwrap();
+        store.load_chain().unwrap();
+        assert_eq!(store.chain, expected);
+        assert!(store.verify_chains().is_ok());
+
+        store.turns.get_mut(&turns[2]).unwrap().payload_hash = [1u8; 32];
+        let report = store.verify_chains();
+        assert_eq!(report.turns_checked, 6);
+        assert_eq!(report.mismatched_turn_ids, vec![turns[2]]);
+    }
+}
@@ -0,0 +1,76 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Binary protocol over a Unix domain socket.
+//!
+//! Same-host agents can connect through a socket file instead of loopback
+//! TCP. Framing and semantics are those of the TCP listener; TLS does not
+//! apply. The kernel reports the connecting process's uid and gid, which are
+//! kept with the session and written into the provenance of contexts it
+//! creates.
+
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::error::{Result, StoreError};
+use crate::metadata_overrides::MetadataPatch;
+use crate::store::Provenance;
+
+/// Credentials of the process on the other end of a Unix socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PeerCredentials {
+    pub uid: u32,
+    pub gid: u32,
+    /// Not reported on every platform.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<i32>,
+}
+
+impl PeerCredentials {
+    /// Metadata patch recording the peer's uid and gid in the provenance of
+    /// a context the session creates.
+    pub fn provenance_patch(&self) -> MetadataPatch {
+        MetadataPatch {
+            provenance: Some(Provenance {
+                client_uid: Some(self.uid as i64),
+                client_gid: Some(self.gid as i64),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+/// Bind the socket at `path` and set its permission bits. A socket file left
+/// by an earlier run is replaced; any other file at the path is refused.
+#[cfg(unix)]
+pub fn bind(path: &Path, mode: u32) -> Result<std::os::unix::net::UnixListener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(StoreError::InvalidInput(format!(
+                "{} exists and is not a socket",
+                path.display()
+            )))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let listener = std::os::unix::net::UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(listener)
+}
+
+/// Credentials of an accepted connection's peer.
+#[cfg(unix)]
+pub fn peer_credentials(stream: &tokio::net::UnixStream) -> Result<PeerCredentials> {
+    let cred = stream.peer_cred()?;
+    Ok(PeerCredentials {
+        uid: cred.uid(),
+        gid: cred.gid(),
+        pid: cred.pid(),
+    })
+}
@@ -0,0 +1,438 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! CQL watch expressions.
+//!
+//! A watch is a registered CQL query whose result set is re-evaluated when
+//! the store changes (debounced) and on a fixed interval (so time- and
+//! liveness-based predicates are picked up too). Each evaluation is diffed
+//! against the previous result set; contexts entering or leaving it are
+//! published as `watch_triggered` events and, when configured, POSTed to the
+//! watch's webhook through the shared [`Webhooks`] queue.
+//!
+//! Watch definitions are persisted in `meta/watches.json`. Result sets are
+//! not: after a restart the first evaluation re-establishes the baseline
+//! without reporting it, whereas a newly registered watch reports its
+//! initial matches as entering.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::cql::{self, CqlQuery};
+use crate::error::{Result, StoreError};
+use crate::events::{EventBus, StoreEvent};
+use crate::metrics::SessionTracker;
+use crate::store::Store;
+use crate::util::{env_u64, unix_ms};
+use crate::webhooks::{WebhookOrigin, Webhooks};
+
+const DEFAULT_INTERVAL_MS: u64 = 5_000;
+const DEFAULT_DEBOUNCE_MS: u64 = 250;
+
+/// Watcher settings, loaded from the environment.
+#[derive(Debug, Clone)]
+pub struct WatchConfig {
+    /// Maximum time between evaluations.
+    pub interval: Duration,
+    /// Minimum time between evaluations triggered by store events.
+    pub debounce: Duration,
+}
+
+impl WatchConfig {
+    pub fn from_env() -> Self {
+        Self {
+            interval: Duration::from_millis(env_u64("CXDB_WATCH_INTERVAL_MS", DEFAULT_INTERVAL_MS)),
+            debounce: Duration::from_millis(env_u64("CXDB_WATCH_DEBOUNCE_MS", DEFAULT_DEBOUNCE_MS)),
+        }
+    }
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(DEFAULT_INTERVAL_MS),
+            debounce: Duration::from_millis(DEFAULT_DEBOUNCE_MS),
+        }
+    }
+}
+
+/// Body of `POST /v1/watches`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WatchSpec {
+    pub name: String,
+    pub query: String,
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+}
+
+/// Public view of a registered watch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchInfo {
+    pub id: String,
+    pub name: String,
+    pub query: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    pub created_at_unix_ms: u64,
+    /// Contexts matching at the last evaluation; None before the first one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub match_count: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_evaluated_at_unix_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_triggered_at_unix_ms: Option<u64>,
+    /// Last evaluation or webhook delivery error.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Result-set change produced by one evaluation of a watch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchDelta {
+    pub watch_id: u64,
+    pub name: String,
+    pub query: String,
+    pub webhook_url: Option<String>,
+    pub entered: Vec<u64>,
+    pub left: Vec<u64>,
+}
+
+struct WatchState {
+    info: WatchInfo,
+    query: CqlQuery,
+    matches: Option<HashSet<u64>>,
+    report_initial: bool,
+}
+
+#[derive(Default)]
+struct WatchTable {
+    next_id: u64,
+    watches: BTreeMap<u64, WatchState>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedWatches {
+    next_id: u64,
+    watches: Vec<WatchInfo>,
+}
+
+/// Registry of watches shared between the HTTP API and the watcher thread.
+pub struct Watches {
+    path: PathBuf,
+    table: RwLock<WatchTable>,
+    /// Set when a watch is registered so the watcher evaluates promptly.
+    wake: AtomicBool,
+}
+
+impl Watches {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join("watches.json");
+        let mut table = WatchTable {
+            next_id: 1,
+            ..Default::default()
+        };
+        if path.exists() {
+            let bytes = fs::read(&path)?;
+            let persisted: PersistedWatches = serde_json::from_slice(&bytes)
+                .map_err(|e| StoreError::Corrupt(format!("watches.json: {e}")))?;
+            table.next_id = persisted.next_id.max(1);
+            for info in persisted.watches {
+                let id: u64 = info
+                    .id
+                    .parse()
+                    .map_err(|_| StoreError::Corrupt(format!("watch id {}", info.id)))?;
+                let query = cql::parse(&info.query)
+                    .map_err(|e| StoreError::Corrupt(format!("watch {id}: {}", e.message)))?;
+                table.next_id = table.next_id.max(id + 1);
+                table.watches.insert(
+                    id,
+                    WatchState {
+                        info,
+                        query,
+                        matches: None,
+                        report_initial: false,
+                    },
+                );
+            }
+        }
+        Ok(Self {
+            path,
+            table: RwLock::new(table),
+            wake: AtomicBool::new(false),
+        })
+    }
+
+    /// Register a watch after validating its query.
+    pub fn register(&self, spec: WatchSpec) -> Result<WatchInfo> {
+        if spec.name.trim().is_empty() {
+            return Err(StoreError::InvalidInput("name is required".into()));
+        }
+        let query = cql::parse(&spec.query)
+            .map_err(|e| StoreError::InvalidInput(format!("invalid query: {}", e.message)))?;
+        let mut table = self.table.write().unwrap();
+        let id = table.next_id;
+        table.next_id += 1;
+        let info = WatchInfo {
+            id: id.to_string(),
+            name: spec.name,
+            query: spec.query,
+            webhook_url: spec.webhook_url.filter(|u| !u.is_empty()),
+            created_at_unix_ms: unix_ms(),
+            match_count: None,
+            last_evaluated_at_unix_ms: None,
+            last_triggered_at_unix_ms: None,
+            last_error: None,
+        };
+        table.watches.insert(
+            id,
+            WatchState {
+                info: info.clone(),
+                query,
+                matches: None,
+                report_initial: true,
+            },
+        );
+        if let Err(err) = self.persist(&table) {
+            table.watches.remove(&id);
+            return Err(err);
+        }
+        self.wake.store(true, Ordering::SeqCst);
+        Ok(info)
+    }
+
+    pub fn get(&self, id: u64) -> Option<WatchInfo> {
+        self.table
+            .read()
+            .unwrap()
+            .watches
+            .get(&id)
+            .map(|w| w.info.clone())
+    }
+
+    /// Context ids matching a watch at its last evaluation, newest first.
+    pub fn matches(&self, id: u64) -> Option<Vec<u64>> {
+        let table = self.table.read().unwrap();
+        let mut ids: Vec<u64> = table
+            .watches
+            .get(&id)?
+            .matches
+            .as_ref()
+            .map(|m| m.iter().copied().collect())
+            .unwrap_or_default();
+        ids.sort_unstable_by(|a, b| b.cmp(a));
+        Some(ids)
+    }
+
+    pub fn list(&self) -> Vec<WatchInfo> {
+        self.table
+            .read()
+            .unwrap()
+            .watches
+            .values()
+            .map(|w| w.info.clone())
+            .collect()
+    }
+
+    /// Remove a watch. Returns false if it did not exist.
+    pub fn remove(&self, id: u64) -> Result<bool> {
+        let mut table = self.table.write().unwrap();
+        let Some(state) = table.watches.remove(&id) else {
+            return Ok(false);
+        };
+        if let Err(err) = self.persist(&table) {
+            table.watches.insert(id, state);
+            return Err(err);
+        }
+        Ok(true)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.read().unwrap().watches.is_empty()
+    }
+
+    /// Evaluate every watch against the store and return the non-empty deltas.
+    pub fn evaluate(&self, store: &mut Store, live_contexts: &HashSet<u64>) -> Vec<WatchDelta> {
+        let now = unix_ms();
+        let mut table = self.table.write().unwrap();
+        let mut deltas = Vec::new();
+        for (id, watch) in table.watches.iter_mut() {
+            let current: HashSet<u64> =
+                match store.search_contexts_parsed(&watch.query, live_contexts, None) {
+                    Ok(result) => result.context_ids.into_iter().collect(),
+                    Err(e) => {
+                        watch.info.last_error = Some(e.message);
+                        continue;
+                    }
+                };
+            watch.info.last_evaluated_at_unix_ms = Some(now);
+            watch.info.match_count = Some(current.len());
+
+            let (mut entered, mut left): (Vec<u64>, Vec<u64>) = match &watch.matches {
+                Some(previous) => (
+                    current.difference(previous).copied().collect(),
+                    previous.difference(&current).copied().collect(),
+                ),
+                None if watch.report_initial => (current.iter().copied().collect(), Vec::new()),
+                None => (Vec::new(), Vec::new()),
+            };
+            watch.matches = Some(current);
+            watch.report_initial = false;
+            if entered.is_empty() && left.is_empty() {
+                continue;
+            }
+            entered.sort_unstable();
+            left.sort_unstable();
+            watch.info.last_triggered_at_unix_ms = Some(now);
+            deltas.push(WatchDelta {
+                watch_id: *id,
+                name: watch.info.name.clone(),
+                query: watch.info.query.clone(),
+                webhook_url: watch.info.webhook_url.clone(),
+                entered,
+                left,
+            });
+        }
+        deltas
+    }
+
+    /// Record the outcome of a webhook delivery.
+    pub fn record_delivery(&self, id: u64, result: std::result::Result<(), String>) {
+        if let Some(watch) = self.table.write().unwrap().watches.get_mut(&id) {
+            watch.info.last_error = result.err();
+        }
+    }
+
+    fn take_wake(&self) -> bool {
+        self.wake.swap(false, Ordering::SeqCst)
+    }
+
+    fn persist(&self, table: &WatchTable) -> Result<()> {
+        let persisted = PersistedWatches {
+            next_id: table.next_id,
+            watches: table
+                .watches
+                .values()
+                .map(|w| WatchInfo {
+                    match_count: None,
+                    last_evaluated_at_unix_ms: None,
+                    last_triggered_at_unix_ms: None,
+                    last_error: None,
+                    ..w.info.clone()
+                })
+                .collect(),
+        };
+        let bytes = serde_json::to_vec_pretty(&persisted)
+            .map_err(|e| StoreError::InvalidInput(format!("json encode error: {e}")))?;
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, bytes)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+impl WebhookOrigin for Watches {
+    fn kind(&self) -> &'static str {
+        "watch"
+    }
+
+    fn is_active(&self, id: u64) -> bool {
+        self.get(id).is_some()
+    }
+
+    fn record_delivery(&self, id: u64, result: std::result::Result<(), String>) {
+        Watches::record_delivery(self, id, result);
+    }
+}
+
+/// Start the watcher thread.
+pub fn start_watcher(
+    config: WatchConfig,
+    watches: Arc<Watches>,
+    store: Arc<Mutex<Store>>,
+    session_tracker: Arc<SessionTracker>,
+    webhooks: Arc<Webhooks>,
+    event_bus: Arc<EventBus>,
+) -> thread::JoinHandle<()> {
+    let subscriber = event_bus.subscribe();
+    thread::spawn(move || {
+        let mut dirty = true;
+        let mut last_run: Option<Instant> = None;
+        loop {
+            if let Some(event) = subscriber.recv_timeout(config.debounce) {
+                dirty |= changes_matches(&event);
+                // Drain whatever else is queued before deciding.
+                while let Some(event) = subscriber.try_recv() {
+                    dirty |= changes_matches(&event);
+                }
+            }
+            dirty |= watches.take_wake();
+
+            let since_last = last_run.map(|t| t.elapsed()).unwrap_or(Duration::MAX);
+            let due = since_last >= config.interval || (dirty && since_last >= config.debounce);
+            if !due || watches.is_empty() {
+                continue;
+            }
+            dirty = false;
+            last_run = Some(Instant::now());
+
+            let live_contexts = session_tracker.get_live_context_ids();
+            let deltas = {
+                let mut store = store.lock().unwrap();
+                watches.evaluate(&mut store, &live_contexts)
+            };
+            for delta in deltas {
+                event_bus.publish(StoreEvent::WatchTriggered {
+                    watch_id: delta.watch_id.to_string(),
+                    name: delta.name.clone(),
+                    entered: delta.entered.iter().map(|id| id.to_string()).collect(),
+                    left: delta.left.iter().map(|id| id.to_string()).collect(),
+                });
+                if let Some(url) = &delta.webhook_url {
+                    webhooks.send(
+                        url,
+                        webhook_body(&delta),
+                        Arc::clone(&watches) as Arc<dyn WebhookOrigin>,
+                        delta.watch_id,
+                    );
+                }
+            }
+        }
+    })
+}
+
+/// Whether an event can change some watch's result set.
+fn changes_matches(event: &StoreEvent) -> bool {
+    !matches!(
+        event,
+        StoreEvent::WatchTriggered { .. }
+            | StoreEvent::OperationCompleted { .. }
+            | StoreEvent::PresenceChanged { .. }
+            | StoreEvent::ProjectCreated { .. }
+            | StoreEvent::StatusChanged { .. }
+            | StoreEvent::SubscriptionNotified { .. }
+            | StoreEvent::QuotaWarning { .. }
+            | StoreEvent::DepthWarning { .. }
+            | StoreEvent::BookmarkAdded { .. }
+            | StoreEvent::BookmarkDeleted { .. }
+    )
+}
+
+fn webhook_body(delta: &WatchDelta) -> serde_json::Value {
+    json!({
+        "watch_id": delta.watch_id.to_string(),
+        "name": delta.name,
+        "query": delta.query,
+        "entered": delta.entered.iter().map(|id| id.to_string()).collect::<Vec<_>>(),
+        "left": delta.left.iter().map(|id| id.to_string()).collect::<Vec<_>>(),
+        "triggered_at": unix_ms(),
+    })
+}
@@ -0,0 +1,310 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Outbound webhook delivery for watches, subscriptions and quota warnings.
+//!
+//! All hand their webhooks to one [`Webhooks`] queue, served by one thread.
+//! A failed delivery (non-2xx, timeout, refused address) is retried with
+//! exponential backoff up to `CXDB_WEBHOOK_MAX_ATTEMPTS` attempts in total,
+//! after which its origin records the failure. The queue is in memory, so
+//! retries pending at shutdown are dropped.
+//!
+//! Webhook URLs come from API callers, so delivery refuses to connect to
+//! loopback, private, link-local (including cloud metadata endpoints),
+//! shared, multicast and unspecified addresses. The check is made on the
+//! addresses the host resolves to when connecting, so a name cannot be
+//! re-pointed at an internal address after it is checked. Hosts listed in
+//! `CXDB_WEBHOOK_ALLOW_HOSTS` are exempt. Redirects are not followed.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value as JsonValue;
+
+use crate::util::env_u64;
+
+const DEFAULT_MAX_ATTEMPTS: u64 = 5;
+const DEFAULT_RETRY_BASE_MS: u64 = 1_000;
+const DEFAULT_TIMEOUT_MS: u64 = 5_000;
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+/// Webhook delivery settings, loaded from the environment.
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// Delivery attempts per webhook, including the first.
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled for each later one.
+    pub retry_base: Duration,
+    pub timeout: Duration,
+    /// Hosts delivered to whatever they resolve to, lowercased.
+    pub allow_hosts: Vec<String>,
+}
+
+impl WebhookConfig {
+    pub fn from_env() -> Self {
+        Self {
+            max_attempts: env_u64("CXDB_WEBHOOK_MAX_ATTEMPTS", DEFAULT_MAX_ATTEMPTS)
+                .clamp(1, u32::MAX as u64) as u32,
+            retry_base: Duration::from_millis(env_u64(
+                "CXDB_WEBHOOK_RETRY_BASE_MS",
+                DEFAULT_RETRY_BASE_MS,
+            )),
+            timeout: Duration::from_millis(env_u64("CXDB_WEBHOOK_TIMEOUT_MS", DEFAULT_TIMEOUT_MS)),
+            allow_hosts: std::env::var("CXDB_WEBHOOK_ALLOW_HOSTS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|host| host.trim().to_ascii_lowercase())
+                .filter(|host| !host.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Delay before retrying a delivery that has failed `attempts` times.
+    pub fn retry_delay(&self, attempts: u32) -> Duration {
+        let factor = 1u32 << attempts.saturating_sub(1).min(16);
+        self.retry_base.saturating_mul(factor).min(MAX_RETRY_DELAY)
+    }
+
+    fn allows_host(&self, host: &str) -> bool {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        self.allow_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host))
+    }
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS as u32,
+            retry_base: Duration::from_millis(DEFAULT_RETRY_BASE_MS),
+            timeout: Duration::from_millis(DEFAULT_TIMEOUT_MS),
+            allow_hosts: Vec::new(),
+        }
+    }
+}
+
+/// Whether webhooks may be delivered to `ip` without an allowlist entry:
+/// false for loopback, private, link-local, shared (CGNAT), multicast,
+/// broadcast, documentation and unspecified addresses.
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_public_v4(v4),
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_public_v4(v4),
+            None => is_public_v6(v6),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)))
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local fc00::/7 and link-local fe80::/10.
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+}
+
+/// Owner of queued webhooks, told how each one ended.
+pub trait WebhookOrigin: Send + Sync {
+    /// Noun used in logs ("watch", "subscription").
+    fn kind(&self) -> &'static str;
+    /// Whether `id` still exists; retries for removed ones are dropped.
+    fn is_active(&self, id: u64) -> bool;
+    /// Record the final outcome of a webhook for `id`.
+    fn record_delivery(&self, id: u64, result: std::result::Result<(), String>);
+}
+
+/// A webhook waiting for its next attempt.
+struct Webhook {
+    url: String,
+    body: JsonValue,
+    origin: Arc<dyn WebhookOrigin>,
+    id: u64,
+    /// Attempts made so far.
+    attempts: u32,
+}
+
+/// Items waiting for their next attempt, soonest first.
+pub struct RetryQueue<T> {
+    heap: BinaryHeap<Reverse<(Instant, u64)>>,
+    pending: BTreeMap<u64, T>,
+    next_seq: u64,
+}
+
+impl<T> Default for RetryQueue<T> {
+    fn default() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            pending: BTreeMap::new(),
+            next_seq: 0,
+        }
+    }
+}
+
+impl<T> RetryQueue<T> {
+    pub fn push(&mut self, item: T, due: Instant) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.heap.push(Reverse((due, seq)));
+        self.pending.insert(seq, item);
+    }
+
+    /// Take the next item due at `now`, if any.
+    pub fn pop_due(&mut self, now: Instant) -> Option<T> {
+        match self.heap.peek() {
+            Some(Reverse((due, _))) if *due <= now => {
+                let Reverse((_, seq)) = self.heap.pop()?;
+                self.pending.remove(&seq)
+            }
+            _ => None,
+        }
+    }
+
+    /// When the next item comes due.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.heap.peek().map(|Reverse((due, _))| *due)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// The webhook queue shared by watches, subscriptions and quotas.
+pub struct Webhooks {
+    config: WebhookConfig,
+    queue: Mutex<RetryQueue<Webhook>>,
+    wake: Condvar,
+}
+
+impl Webhooks {
+    pub fn new(config: WebhookConfig) -> Self {
+        Self {
+            config,
+            queue: Mutex::new(RetryQueue::default()),
+            wake: Condvar::new(),
+        }
+    }
+
+    /// Queue a `POST` of `body` to `url` on behalf of `origin`'s `id`. The
+    /// attempt number is added to the body as `attempt`.
+    pub fn send(&self, url: &str, body: JsonValue, origin: Arc<dyn WebhookOrigin>, id: u64) {
+        let webhook = Webhook {
+            url: url.to_string(),
+            body,
+            origin,
+            id,
+            attempts: 0,
+        };
+        self.queue.lock().unwrap().push(webhook, Instant::now());
+        self.wake.notify_one();
+    }
+
+    /// Webhooks queued or waiting for a retry.
+    pub fn pending(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    /// Block until a webhook is due and take it.
+    fn next(&self) -> Webhook {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            if let Some(webhook) = queue.pop_due(now) {
+                return webhook;
+            }
+            queue = match queue.next_due() {
+                Some(due) => self.wake.wait_timeout(queue, due - now).unwrap().0,
+                None => self.wake.wait(queue).unwrap(),
+            };
+        }
+    }
+}
+
+/// Start the thread that delivers queued webhooks.
+pub fn start_webhook_dispatcher(webhooks: Arc<Webhooks>) -> thread::JoinHandle<()> {
+    let config = webhooks.config.clone();
+    let resolver_config = config.clone();
+    let agent = ureq::AgentBuilder::new()
+        .timeout(config.timeout)
+        .redirects(0)
+        .resolver(move |netloc: &str| resolve_public(&resolver_config, netloc))
+        .build();
+    thread::spawn(move || loop {
+        let mut webhook = webhooks.next();
+        if !webhook.origin.is_active(webhook.id) {
+            continue;
+        }
+        webhook.attempts += 1;
+        let mut body = webhook.body.clone();
+        if let Some(fields) = body.as_object_mut() {
+            fields.insert("attempt".into(), webhook.attempts.into());
+        }
+        let result = agent
+            .post(&webhook.url)
+            .send_json(body)
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+        match result {
+            Ok(()) => webhook.origin.record_delivery(webhook.id, Ok(())),
+            Err(_) if webhook.attempts < config.max_attempts => {
+                let due = Instant::now() + config.retry_delay(webhook.attempts);
+                webhooks.queue.lock().unwrap().push(webhook, due);
+            }
+            Err(err) => {
+                eprintln!(
+                    "{} {} webhook failed after {} attempts: {err}",
+                    webhook.origin.kind(),
+                    webhook.id,
+                    webhook.attempts
+                );
+                webhook.origin.record_delivery(webhook.id, Err(err));
+            }
+        }
+    })
+}
+
+/// Resolve `host:port`, keeping only addresses webhooks may be sent to.
+fn resolve_public(config: &WebhookConfig, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = netloc.to_socket_addrs()?.collect();
+    let host = netloc.rsplit_once(':').map_or(netloc, |(host, _)| host);
+    if config.allows_host(host) {
+        return Ok(addrs);
+    }
+    let public: Vec<SocketAddr> = addrs
+        .into_iter()
+        .filter(|addr| is_public_address(addr.ip()))
+        .collect();
+    if public.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("webhook host {host} resolves only to non-public addresses"),
+        ));
+    }
+    Ok(public)
+}
@@ -0,0 +1,168 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::append;
+use cxdb_server::archive::{export_context, import_context};
+use cxdb_server::blob_store::BlobStore;
+use cxdb_server::deadline::Deadline;
+use cxdb_server::fs_store::{encode_tree_entries, SnapshotMeta, TreeEntry};
+use cxdb_server::store::Store;
+use tempfile::tempdir;
+
+/// Store `content` as a file named `name` and return the root tree's hash.
+fn snapshot(store: &mut Store, name: &str, content: &[u8]) -> [u8; 32] {
+    let hash = *blake3::hash(content).as_bytes();
+    store.put_blob(hash, content, None, None).unwrap();
+    let root = encode_tree_entries(&[TreeEntry {
+        name: name.into(),
+        kind: 0,
+        mode: 0o644,
+        size: content.len() as u64,
+        hash: hash.to_vec(),
+    }])
+    .unwrap();
+    let root_hash = *blake3::hash(&root).as_bytes();
+    store.put_blob(root_hash, &root, None, None).unwrap();
+    root_hash
+}
+
+fn payloads(store: &mut Store, context_id: u64) -> Vec<Vec<u8>> {
+    let chain = store.context_chain(context_id).unwrap();
+    chain
+        .iter()
+        .map(|link| {
+            let turn = store.get_turn(link.record.turn_id, true).unwrap();
+            turn.payload.unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn exported_contexts_import_into_another_data_dir() {
+    let source_dir = tempdir().expect("tempdir");
+    let mut source = Store::open(source_dir.path()).expect("open store");
+    let ctx = source.create_context(0).unwrap().context_id;
+    let first = append(&mut source, ctx, b"one");
+    let root = snapshot(&mut source, "notes.txt", b"draft");
+    let meta = SnapshotMeta {
+        base_path: Some("/work/repo".into()),
+        ..Default::default()
+    };
+    source
+        .attach_fs_with_meta(first, root, Some(meta.clone()))
+        .unwrap();
+    append(&mut source, ctx, b"two");
+    let third = append(&mut source, ctx, b"three");
+    let root = snapshot(&mut source, "notes.txt", b"final");
+    source.attach_fs(third, root).unwrap();
+
+    let mut archive = Vec::new();
+    let exported = export_context(&mut source, ctx, &mut archive).unwrap();
+    assert_eq!(exported.turns, 3);
+    // Two roots and two file contents.
+    assert_eq!(exported.fs_blobs, 4);
+
+    let target_dir = tempdir().expect("tempdir");
+    let mut target = Store::open(target_dir.path()).expect("open store");
+    let unrelated = target.create_context(0).unwrap().context_id;
+    append(&mut target, unrelated, b"unrelated");
+    let imported = import_context(&mut target, &archive[..]).unwrap();
+    assert_eq!(imported.turns, 3);
+    let copy: u64 = imported.context_id.parse().unwrap();
+    assert_ne!(copy, ctx);
+
+    // The copy survives reopening the data dir.
+    drop(target);
+    let mut target = Store::open(target_dir.path()).expect("reopen store");
+    assert_eq!(
+        payloads(&mut target, copy),
+        vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]
+    );
+    let chain = target.context_chain(copy).unwrap();
+    let (second, third) = (chain[1].record.turn_id, chain[2].record.turn_id);
+    let (content, _) = target
+        .get_fs_file(second, "notes.txt", false, &Deadline::none())
+        .unwrap();
+    assert_eq!(content, b"draft");
+    assert_eq!(target.get_fs_snapshot(second).unwrap().meta, Some(meta));
+    let (content, _) = target
+        .get_fs_file(third, "notes.txt", false, &Deadline::none())
+        .unwrap();
+    assert_eq!(content, b"final");
+    assert!(target.fsck().ok);
+
+    // A truncated archive is rejected.
+    let lines: Vec<&[u8]> = archive.split(|&b| b == b'\n').collect();
+    let truncated = lines[..3].join(&b'\n');
+    assert!(import_context(&mut target, &truncated[..]).is_err());
+}
+
+#[test]
+fn blob_index_is_rebuilt_from_the_pack() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let ctx = store.create_context(0).unwrap().context_id;
+    let turn = append(&mut store, ctx, b"payload");
+    let root = snapshot(&mut store, "a.txt", b"contents");
+    store.attach_fs(turn, root).unwrap();
+    drop(store);
+
+    // Lose the index and tear the last pack record.
+    let blobs = dir.path().join("blobs");
+    std::fs::remove_file(blobs.join("blobs.idx")).unwrap();
+    let pack = blobs.join("blobs.pack");
+    let pack_len = std::fs::metadata(&pack).unwrap().len();
+    let mut torn = std::fs::read(&pack).unwrap();
+    torn.extend_from_slice(b"BLSB partial record");
+    std::fs::write(&pack, &torn).unwrap();
+
+    let stats = BlobStore::rebuild_index(&blobs).unwrap();
+    assert_eq!(stats.blobs, 3);
+    assert_eq!(stats.pack_bytes, pack_len + 19);
+    assert_eq!(stats.unindexed_bytes, 19);
+
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    assert_eq!(
+        store.get_turn(turn, true).unwrap().payload.unwrap(),
+        b"payload"
+    );
+    let (content, _) = store
+        .get_fs_file(turn, "a.txt", false, &Deadline::none())
+        .unwrap();
+    assert_eq!(content, b"contents");
+}
+
+#[test]
+fn bench_writes_its_workload_through_the_store() {
+    use std::sync::{Arc, Mutex};
+
+    use cxdb_server::bench::{run_bench, BenchConfig, Distribution, DEFAULT_BUNDLE};
+    use cxdb_server::registry::RegistryBundle;
+
+    let dir = tempdir().expect("tempdir");
+    let store = Arc::new(Mutex::new(Store::open(dir.path()).expect("open store")));
+    let bundle: RegistryBundle = serde_json::from_str(DEFAULT_BUNDLE).unwrap();
+    let config = BenchConfig {
+        contexts: 8,
+        turns: Distribution::Fixed(5),
+        turn_bytes: Distribution::Fixed(300),
+        fork_rate: 0.5,
+        fs_rate: 0.2,
+        fs_files: 4,
+        writers: 2,
+        ..Default::default()
+    };
+    let report = run_bench(Arc::clone(&store), &bundle, &config).unwrap();
+    assert_eq!(report.contexts, 8);
+    assert_eq!(report.turns, 40);
+    assert_eq!(report.latency.append.count, 40);
+    // Payloads land close to the requested size.
+    assert!((38 * 300..=40 * 300).contains(&report.payload_bytes));
+
+    let mut store = store.lock().unwrap();
+    assert_eq!(store.turn_store.stats().turns_total, 40);
+    assert_eq!(store.turn_store.stats().contexts_total, 8);
+    assert!(store.fsck().ok);
+}
@@ -0,0 +1,87 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::append;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use cxdb_server::anchoring::{leaf_hash, root_from_path, AnchorConfig, AnchorTarget, Anchors};
+use cxdb_server::store::Store;
+use tempfile::tempdir;
+
+/// A notary that answers every anchor with a receipt and keeps the bodies.
+fn start_notary() -> (String, Arc<Mutex<Vec<String>>>) {
+    let server = tiny_http::Server::http("127.0.0.1:0").expect("bind notary");
+    let url = format!("http://{}/anchor", server.server_addr());
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let bodies = Arc::clone(&received);
+    thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body).unwrap();
+            let receipt = format!("receipt-{}", bodies.lock().unwrap().len() + 1);
+            bodies.lock().unwrap().push(body);
+            let _ = request.respond(tiny_http::Response::from_string(receipt));
+        }
+    });
+    (url, received)
+}
+
+#[test]
+fn anchors_are_published_and_verified_against_the_store() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(&dir.path().join("data")).expect("open store");
+    let mut contexts = Vec::new();
+    for i in 0..3 {
+        let ctx = store.create_context(0).expect("create context").context_id;
+        append(&mut store, ctx, format!("hello {i}").as_bytes());
+        contexts.push(ctx);
+    }
+    let store = Mutex::new(store);
+
+    let (url, received) = start_notary();
+    let anchors = Anchors::open(
+        &dir.path().join("meta"),
+        AnchorConfig {
+            target: AnchorTarget::Https { url },
+            interval: Duration::from_secs(3600),
+            retain: 2,
+        },
+    )
+    .expect("open anchors");
+
+    let record = anchors.anchor(&store, false).unwrap().expect("anchored");
+    assert_eq!((record.seq, record.contexts), (1, 3));
+    assert_eq!(record.published.as_deref(), Some("receipt-1"));
+    assert!(received.lock().unwrap()[0].contains(&record.merkle_root));
+    // Nothing changed since the last published anchor.
+    assert!(anchors.anchor(&store, false).unwrap().is_none());
+
+    let report = anchors
+        .verify(&store.lock().unwrap(), 1, Some(contexts[1]))
+        .unwrap();
+    assert!(report.ok);
+    let proof = report.proof.expect("proof");
+    let chain: [u8; 32] = hex::decode(&proof.chain_head).unwrap().try_into().unwrap();
+    let leaf = leaf_hash(contexts[1], proof.head_turn_id.parse().unwrap(), &chain);
+    assert_eq!(hex::encode(leaf), proof.leaf);
+    let root = root_from_path(leaf, &proof.path).unwrap();
+    assert_eq!(hex::encode(root), record.merkle_root);
+
+    // New turns move the heads, so the next interval anchors again; the old
+    // anchor still verifies against the turns it recorded.
+    append(&mut store.lock().unwrap(), contexts[0], b"more");
+    let second = anchors.anchor(&store, false).unwrap().expect("anchored");
+    assert_eq!(second.seq, 2);
+    assert_ne!(second.merkle_root, record.merkle_root);
+    assert!(anchors.verify(&store.lock().unwrap(), 1, None).unwrap().ok);
+    assert_eq!(anchors.list()[0].seq, 2);
+
+    // Heads of anchors beyond the retention are pruned.
+    anchors.anchor(&store, true).unwrap();
+    assert!(anchors.verify(&store.lock().unwrap(), 1, None).is_err());
+    assert!(anchors.verify(&store.lock().unwrap(), 3, None).unwrap().ok);
+}
@@ -0,0 +1,116 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::append;
+use cxdb_server::archive::{export_context, import_context};
+use cxdb_server::error::StoreError;
+use cxdb_server::store::Store;
+use tempfile::tempdir;
+
+#[test]
+fn attachments_are_named_blobs_that_survive_collection_and_reopening() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let ctx = store.create_context(0).unwrap().context_id;
+    let turn = append(&mut store, ctx, b"turn");
+
+    let chart = store
+        .attach_data(turn, "chart.png", "image/png", b"png bytes")
+        .unwrap();
+    assert_eq!(chart.size, 9);
+    assert_eq!(chart.hash, *blake3::hash(b"png bytes").as_bytes());
+
+    // Link a blob that is already stored.
+    let report = b"%PDF-1.7";
+    let hash = *blake3::hash(report).as_bytes();
+    store.put_blob(hash, report, Some(ctx), None).unwrap();
+    assert!(matches!(
+        store.attach_blob(turn, "report.pdf", "application/pdf", hash, Some(99)),
+        Err(StoreError::InvalidInput(_))
+    ));
+    store
+        .attach_blob(turn, "report.pdf", "application/pdf", hash, Some(8))
+        .unwrap();
+    assert!(matches!(
+        store.attach_blob(turn, "missing", "text/plain", [7; 32], None),
+        Err(StoreError::NotFound(_))
+    ));
+    for (name, mime_type) in [("a/b", "text/plain"), ("..", "text/plain"), ("x", "png")] {
+        assert!(matches!(
+            store.attach_data(turn, name, mime_type, b"x"),
+            Err(StoreError::InvalidInput(_))
+        ));
+    }
+
+    let names: Vec<String> = store
+        .turn_attachments(turn)
+        .into_iter()
+        .map(|a| a.name)
+        .collect();
+    assert_eq!(names, vec!["chart.png", "report.pdf"]);
+
+    // Replacing a name leaves the old content unreferenced.
+    store
+        .attach_data(turn, "chart.png", "image/png", b"new png")
+        .unwrap();
+    store.collect_blobs(false).unwrap();
+    store.detach(turn, "report.pdf").unwrap();
+    assert!(matches!(
+        store.detach(turn, "report.pdf"),
+        Err(StoreError::NotFound(_))
+    ));
+    let sweep = store.collect_blobs(false).unwrap();
+    assert_eq!(sweep.swept_blobs, 2);
+
+    drop(store);
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    let (attachment, content) = store.get_attachment(turn, "chart.png").unwrap();
+    assert_eq!(attachment.mime_type, "image/png");
+    assert_eq!(content, b"new png");
+    assert_eq!(store.turn_attachments(turn).len(), 1);
+    assert_eq!(store.collect_blobs(false).unwrap().swept_blobs, 0);
+}
+
+#[test]
+fn attachments_travel_in_context_archives() {
+    let source_dir = tempdir().expect("tempdir");
+    let mut source = Store::open(source_dir.path()).expect("open store");
+    let ctx = source.create_context(0).unwrap().context_id;
+    let first = append(&mut source, ctx, b"one");
+    let second = append(&mut source, ctx, b"two");
+    source
+        .attach_data(first, "plot 1.svg", "image/svg+xml", b"<svg/>")
+        .unwrap();
+    // The same content twice is written once.
+    source
+        .attach_data(second, "plot 2.svg", "image/svg+xml", b"<svg/>")
+        .unwrap();
+    source
+        .attach_data(second, "notes.txt", "text/plain; charset=utf-8", b"notes")
+        .unwrap();
+
+    let mut archive = Vec::new();
+    let exported = export_context(&mut source, ctx, &mut archive).unwrap();
+    assert_eq!(exported.attachments, 3);
+    assert_eq!(exported.fs_blobs, 0);
+    let text = String::from_utf8(archive.clone()).unwrap();
+    assert_eq!(text.matches("\"data\"").count(), 2);
+
+    let target_dir = tempdir().expect("tempdir");
+    let mut target = Store::open(target_dir.path()).expect("open store");
+    let imported = import_context(&mut target, &archive[..]).unwrap();
+    assert_eq!(imported.attachments, 3);
+    let copy: u64 = imported.context_id.parse().unwrap();
+    let chain = target.context_chain(copy).unwrap();
+    let (first, second) = (chain[0].record.turn_id, chain[1].record.turn_id);
+    assert_eq!(
+        target.get_attachment(first, "plot 1.svg").unwrap().1,
+        b"<svg/>"
+    );
+    let (notes, content) = target.get_attachment(second, "notes.txt").unwrap();
+    assert_eq!(notes.mime_type, "text/plain; charset=utf-8");
+    assert_eq!(content, b"notes");
+    assert_eq!(target.turn_attachments(second).len(), 2);
+}
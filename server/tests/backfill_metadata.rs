@@ -0,0 +1,136 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use cxdb_server::backfill::{BackfillPatch, BackfillRequest, MappingFormat};
+use cxdb_server::events::EventBus;
+use cxdb_server::metadata_overrides::MetadataPatch;
+use cxdb_server::operations::{OperationState, Operations, OperationsConfig};
+use cxdb_server::store::{Provenance, Store};
+use tempfile::tempdir;
+
+fn wait_for(operations: &Operations, id: u64) -> cxdb_server::operations::OperationInfo {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let info = operations.get(id).expect("operation");
+        if info.state.is_finished() {
+            return info;
+        }
+        assert!(Instant::now() < deadline, "operation did not finish");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn backfill_by_filter_updates_metadata_and_indexes() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let a = common::create_tagged_context(&mut store, "gen");
+    let b = common::create_tagged_context(&mut store, "gen");
+    let other = common::create_tagged_context(&mut store, "other");
+
+    let request = BackfillRequest {
+        filter: Some(r#"tag = "gen""#.to_string()),
+        patch: Some(BackfillPatch {
+            set: MetadataPatch {
+                provenance: Some(Provenance {
+                    service_name: Some("generator".to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            add_labels: vec!["team:payments".to_string()],
+        }),
+        batch_size: Some(1),
+        ..Default::default()
+    };
+    let plan = request.plan(&mut store, &HashSet::new()).expect("plan");
+    assert_eq!(plan.targets.len(), 2);
+
+    let shared = Arc::new(Mutex::new(store));
+    let event_bus = Arc::new(EventBus::new());
+    let operations = Operations::start(OperationsConfig::default(), Arc::clone(&event_bus));
+    let id = {
+        let store = Arc::clone(&shared);
+        operations.submit("backfill_metadata", move |op| {
+            cxdb_server::backfill::run_backfill(plan, &store, &event_bus, op)
+        })
+    };
+    let info = wait_for(&operations, id);
+    assert_eq!(info.state, OperationState::Succeeded);
+    assert_eq!((info.done, info.total), (2, Some(2)));
+
+    let mut store = shared.lock().unwrap();
+    let result = store
+        .search_contexts(
+            r#"service = "generator" AND label.team = "payments""#,
+            &HashSet::new(),
+            None,
+        )
+        .expect("search");
+    let mut ids = result.context_ids;
+    ids.sort_unstable();
+    assert_eq!(ids, vec![a, b]);
+
+    // Extracted metadata is preserved under the override
+    let meta = store.get_context_metadata(a).expect("metadata");
+    assert_eq!(meta.client_tag.as_deref(), Some("gen"));
+    assert!(store
+        .get_context_metadata(other)
+        .and_then(|m| m.provenance)
+        .is_none());
+    drop(store);
+    drop(shared);
+
+    // Overrides survive a restart
+    let mut reopened = Store::open(dir.path()).expect("reopen");
+    let prov = reopened
+        .get_context_metadata(b)
+        .and_then(|m| m.provenance)
+        .expect("provenance");
+    assert_eq!(prov.service_name.as_deref(), Some("generator"));
+}
+
+#[test]
+fn backfill_mapping_reports_missing_contexts() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let a = common::create_tagged_context(&mut store, "gen");
+
+    let request = BackfillRequest {
+        mapping: Some(format!("context_id,title\n{a},Renamed\n999,Missing\n")),
+        mapping_format: Some(MappingFormat::Csv),
+        ..Default::default()
+    };
+    let plan = request.plan(&mut store, &HashSet::new()).expect("plan");
+
+    let store = Arc::new(Mutex::new(store));
+    let event_bus = Arc::new(EventBus::new());
+    let operations = Operations::start(OperationsConfig::default(), Arc::clone(&event_bus));
+    let id = {
+        let store = Arc::clone(&store);
+        operations.submit("backfill_metadata", move |op| {
+            cxdb_server::backfill::run_backfill(plan, &store, &event_bus, op)
+        })
+    };
+    let info = wait_for(&operations, id);
+    let result = info.result.expect("result");
+    assert_eq!(result["updated"], 1);
+    assert_eq!(result["failed"], 1);
+    assert_eq!(result["errors"][0]["context_id"], "999");
+
+    let mut store = store.lock().unwrap();
+    let meta = store.get_context_metadata(a).expect("metadata");
+    assert_eq!(meta.title.as_deref(), Some("Renamed"));
+    assert_eq!(
+        store
+            .get_metadata_override(a)
+            .and_then(|p| p.title_source.as_deref()),
+        Some("backfill")
+    );
+}
@@ -0,0 +1,138 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use cxdb_server::error::StoreError;
+use cxdb_server::events::StoreEvent;
+use cxdb_server::store::Store;
+use tempfile::tempdir;
+
+fn append(store: &mut Store, context_id: u64, text: &str) -> u64 {
+    common::append_typed(store, context_id, "com.example.Note", &rmp_payload(text))
+}
+
+fn rmp_payload(text: &str) -> Vec<u8> {
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(
+        &mut buf,
+        &rmpv::Value::Map(vec![(rmpv::Value::from(1), rmpv::Value::from(text))]),
+    )
+    .expect("encode");
+    buf
+}
+
+#[test]
+fn bookmarks_are_listed_in_timeline_order_and_survive_reopen() {
+    let dir = tempdir().expect("tempdir");
+    let (context_id, first, third, deleted_id) = {
+        let mut store = Store::open(dir.path()).expect("open store");
+        let context_id = store.create_context(0).unwrap().context_id;
+        let first = append(&mut store, context_id, "one");
+        append(&mut store, context_id, "two");
+        let third = append(&mut store, context_id, "three");
+
+        let late = store
+            .add_bookmark(
+                context_id,
+                third,
+                " Interesting part ",
+                Some("starts here".into()),
+                Some("ana@example.com".into()),
+            )
+            .unwrap();
+        assert_eq!(late.name, "Interesting part");
+        assert_eq!(late.depth, 2);
+        let early = store
+            .add_bookmark(context_id, first, "Setup", None, None)
+            .unwrap();
+        assert!(early.bookmark_id > late.bookmark_id);
+
+        let listed: Vec<u64> = store
+            .bookmarks(context_id)
+            .iter()
+            .map(|b| b.turn_id)
+            .collect();
+        assert_eq!(listed, vec![first, third]);
+
+        store
+            .delete_bookmark(
+                context_id,
+                early.bookmark_id,
+                Some("ana@example.com".into()),
+            )
+            .unwrap();
+        (context_id, first, third, early.bookmark_id)
+    };
+
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    let bookmarks = store.bookmarks(context_id);
+    assert_eq!(bookmarks.len(), 1);
+    assert_eq!(bookmarks[0].turn_id, third);
+    assert_eq!(bookmarks[0].note.as_deref(), Some("starts here"));
+    assert_eq!(bookmarks[0].author.as_deref(), Some("ana@example.com"));
+
+    // Ids are not reused after a delete.
+    let again = store
+        .add_bookmark(context_id, first, "Setup", None, None)
+        .unwrap();
+    assert!(again.bookmark_id > deleted_id);
+}
+
+#[test]
+fn bookmarks_reject_turns_outside_the_context_and_bad_input() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let a = store.create_context(0).unwrap().context_id;
+    let turn_a = append(&mut store, a, "a");
+    let b = store.create_context(0).unwrap().context_id;
+    let turn_b = append(&mut store, b, "b");
+
+    assert!(matches!(
+        store.add_bookmark(a, turn_b, "Elsewhere", None, None),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        store.add_bookmark(a, turn_a, "  ", None, None),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        store.add_bookmark(a, turn_a, &"x".repeat(201), None, None),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        store.add_bookmark(999, turn_a, "Missing", None, None),
+        Err(StoreError::NotFound(_))
+    ));
+    assert!(matches!(
+        store.delete_bookmark(a, 42, None),
+        Err(StoreError::NotFound(_))
+    ));
+
+    // A bookmark belongs to one context even if forks share the turn.
+    let bookmark = store.add_bookmark(a, turn_a, "Start", None, None).unwrap();
+    let fork = store.fork_context(turn_a).unwrap().context_id;
+    assert!(store.bookmarks(fork).is_empty());
+    assert!(matches!(
+        store.delete_bookmark(fork, bookmark.bookmark_id, None),
+        Err(StoreError::NotFound(_))
+    ));
+}
+
+#[test]
+fn bookmark_events_are_flat_sse_payloads() {
+    let (name, data) = StoreEvent::BookmarkAdded {
+        context_id: "7".into(),
+        bookmark_id: "1".into(),
+        turn_id: "3141".into(),
+        depth: 12,
+        name: "Interesting part".into(),
+        author: None,
+    }
+    .to_sse();
+    assert_eq!(name, "bookmark_added");
+    let data: serde_json::Value = serde_json::from_str(&data).unwrap();
+    assert_eq!(data["turn_id"], "3141");
+    assert_eq!(data["depth"], 12);
+    assert!(data.get("author").is_none());
+}
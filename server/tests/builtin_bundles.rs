@@ -0,0 +1,148 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs;
+use std::path::Path;
+
+use cxdb_server::error::StoreError;
+use cxdb_server::registry::builtin::{ingest_builtin_bundles, EMBEDDED_SOURCE};
+use cxdb_server::registry::{BuiltinOutcome, Registry, TypeVersionRef};
+use tempfile::tempdir;
+
+fn bundle(registry_version: u32, versions: &[(u32, &str)]) -> String {
+    let versions: Vec<String> = versions
+        .iter()
+        .map(|(version, field_type)| {
+            format!(
+                r#""{version}": {{ "fields": {{ "1": {{ "name": "text", "type": "{field_type}" }} }} }}"#
+            )
+        })
+        .collect();
+    format!(
+        r#"{{
+  "registry_version": {registry_version},
+  "bundle_id": "acme.baseline",
+  "types": {{ "acme.Message": {{ "versions": {{ {} }} }} }}
+}}"#,
+        versions.join(", ")
+    )
+}
+
+fn write_baseline(dir: &Path, raw: &str) {
+    fs::create_dir_all(dir).unwrap();
+    fs::write(dir.join("baseline.json"), raw).unwrap();
+}
+
+fn outcome(outcomes: &[(String, BuiltinOutcome)], bundle_id: &str) -> BuiltinOutcome {
+    outcomes
+        .iter()
+        .find(|(id, _)| id == bundle_id)
+        .map(|(_, outcome)| outcome.clone())
+        .expect("bundle ingested")
+}
+
+#[test]
+fn builtin_bundles_are_ingested_idempotently() {
+    let dir = tempdir().expect("tempdir");
+    let bundles = dir.path().join("bundles");
+    write_baseline(&bundles, &bundle(1, &[(1, "string")]));
+
+    let mut registry = Registry::open(&dir.path().join("registry")).unwrap();
+    let outcomes = ingest_builtin_bundles(&mut registry, Some(&bundles)).unwrap();
+    assert_eq!(outcome(&outcomes, "acme.baseline"), BuiltinOutcome::Created);
+    assert!(registry.get_type_version("acme.Message", 1).is_some());
+
+    // Restarting with the same bundles changes nothing.
+    drop(registry);
+    let mut registry = Registry::open(&dir.path().join("registry")).unwrap();
+    let outcomes = ingest_builtin_bundles(&mut registry, Some(&bundles)).unwrap();
+    assert!(outcomes
+        .iter()
+        .all(|(_, outcome)| *outcome == BuiltinOutcome::Unchanged));
+
+    // A newer bundle adds its versions.
+    write_baseline(&bundles, &bundle(2, &[(1, "string"), (2, "string")]));
+    let outcomes = ingest_builtin_bundles(&mut registry, Some(&bundles)).unwrap();
+    assert_eq!(
+        outcome(&outcomes, "acme.baseline"),
+        BuiltinOutcome::Merged(vec![TypeVersionRef {
+            type_id: "acme.Message".into(),
+            version: 2,
+        }])
+    );
+    assert!(registry.get_type_version("acme.Message", 2).is_some());
+
+    // An older bundle leaves the stored one alone.
+    write_baseline(&bundles, &bundle(1, &[(1, "string")]));
+    let outcomes = ingest_builtin_bundles(&mut registry, Some(&bundles)).unwrap();
+    assert_eq!(
+        outcome(&outcomes, "acme.baseline"),
+        BuiltinOutcome::Outdated { stored_version: 2 }
+    );
+
+    // Changing a registered version fails startup.
+    write_baseline(&bundles, &bundle(3, &[(1, "u64"), (2, "string")]));
+    assert!(matches!(
+        ingest_builtin_bundles(&mut registry, Some(&bundles)),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert!(registry.get_type_version("acme.Message", 1).is_some());
+}
+
+#[test]
+fn listings_mark_builtin_provenance() {
+    let dir = tempdir().expect("tempdir");
+    let bundles = dir.path().join("bundles");
+    write_baseline(&bundles, &bundle(1, &[(1, "string")]));
+    let mut registry = Registry::open(&dir.path().join("registry")).unwrap();
+    ingest_builtin_bundles(&mut registry, Some(&bundles)).unwrap();
+
+    let client = r#"{
+      "registry_version": 1,
+      "bundle_id": "client.app",
+      "types": { "client.Note": { "versions": { "1": { "fields": { "1": { "name": "text", "type": "string" } } } } } }
+    }"#;
+    registry
+        .put_bundle("client.app", client.as_bytes())
+        .unwrap();
+
+    let listed = registry.list_bundles();
+    let find = |id: &str| listed.iter().find(|b| b.bundle_id == id).unwrap();
+    let baseline = find("acme.baseline");
+    assert_eq!(baseline.provenance, "builtin");
+    let expected_source = bundles.join("baseline.json").display().to_string();
+    assert_eq!(baseline.source.as_deref(), Some(expected_source.as_str()));
+    assert_eq!(baseline.types, vec!["acme.Message".to_string()]);
+    let summary = find("cxdb.hooks.summary.v1");
+    assert_eq!(summary.provenance, "builtin");
+    assert_eq!(summary.source.as_deref(), Some(EMBEDDED_SOURCE));
+    let app = find("client.app");
+    assert_eq!(app.provenance, "client");
+    assert_eq!(app.source, None);
+}
+
+#[test]
+fn clients_cannot_redefine_builtin_types() {
+    let dir = tempdir().expect("tempdir");
+    let bundles = dir.path().join("bundles");
+    write_baseline(&bundles, &bundle(1, &[(1, "string")]));
+    let mut registry = Registry::open(&dir.path().join("registry")).unwrap();
+    ingest_builtin_bundles(&mut registry, Some(&bundles)).unwrap();
+
+    // Republishing a builtin version verbatim in another bundle is fine.
+    let same = bundle(1, &[(1, "string")]).replace("acme.baseline", "client.copy");
+    registry.put_bundle("client.copy", same.as_bytes()).unwrap();
+
+    let added = bundle(1, &[(1, "string"), (2, "string")]).replace("acme.baseline", "client.new");
+    assert!(matches!(
+        registry.put_bundle("client.new", added.as_bytes()),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        registry.merge_bundle(
+            "client.copy",
+            added.replace("client.new", "client.copy").as_bytes()
+        ),
+        Err(StoreError::InvalidInput(_))
+    ));
+}
@@ -0,0 +1,111 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use cxdb_server::deadline::Deadline;
+use cxdb_server::fs_store::{EntryKind, OverlayChange, TreeEntry};
+use cxdb_server::http::{parse_byte_range, ByteRange};
+use cxdb_server::store::Store;
+use tempfile::tempdir;
+
+#[test]
+fn range_headers_resolve_against_the_body_length() {
+    let range = |header: &str| parse_byte_range(Some(header), 1000);
+    assert_eq!(
+        range("bytes=0-99"),
+        ByteRange::Partial { start: 0, end: 99 }
+    );
+    assert_eq!(
+        range("bytes=900-"),
+        ByteRange::Partial {
+            start: 900,
+            end: 999
+        }
+    );
+    assert_eq!(
+        range("bytes=-100"),
+        ByteRange::Partial {
+            start: 900,
+            end: 999
+        }
+    );
+    assert_eq!(
+        range("bytes=990-5000"),
+        ByteRange::Partial {
+            start: 990,
+            end: 999
+        }
+    );
+    assert_eq!(range("bytes=1000-"), ByteRange::Unsatisfiable);
+    assert_eq!(range("bytes=-0"), ByteRange::Unsatisfiable);
+
+    assert_eq!(range("bytes=0-9,20-29"), ByteRange::Full);
+    assert_eq!(range("bytes=9-0"), ByteRange::Full);
+    assert_eq!(range("items=0-9"), ByteRange::Full);
+    assert_eq!(parse_byte_range(None, 1000), ByteRange::Full);
+}
+
+#[test]
+fn fs_file_ranges_read_compressed_and_raw_blobs_in_place() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let ctx = store.create_context(0).expect("create context");
+    let payload = b"turn";
+    let (turn, _) = common::try_append(&mut store, ctx.context_id, 0, "com.example.Test", payload)
+        .expect("append");
+
+    // A log compresses well and is stored with zstd; the noise stays raw.
+    let log: Vec<u8> = (0..20_000)
+        .flat_map(|i| format!("line {i}: ok\n").into_bytes())
+        .collect();
+    let noise: Vec<u8> = (0..4096u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect();
+    let file = |content: &[u8]| TreeEntry {
+        name: String::new(),
+        kind: EntryKind::File as u8,
+        mode: 0o644,
+        size: content.len() as u64,
+        hash: blake3::hash(content).as_bytes().to_vec(),
+    };
+    let changes = [
+        OverlayChange {
+            path: "app.log".into(),
+            entry: Some(file(&log)),
+        },
+        OverlayChange {
+            path: "noise.bin".into(),
+            entry: Some(file(&noise)),
+        },
+    ];
+    store
+        .attach_fs_overlay(turn.turn_id, None, &[&log, &noise], &changes)
+        .unwrap();
+
+    let log_hash = *blake3::hash(&log).as_bytes();
+    assert!(store.blob_store.stored_len(&log_hash).unwrap() < log.len() as u32);
+
+    let none = Deadline::none();
+    for (path, content) in [("app.log", &log), ("noise.bin", &noise)] {
+        let (entry, total) = store
+            .stat_fs_file(turn.turn_id, path, false, &none)
+            .unwrap();
+        assert_eq!(total, content.len() as u64);
+
+        let tail = store
+            .read_fs_range(turn.turn_id, &entry, total - 100, 100)
+            .unwrap();
+        assert_eq!(tail, &content[content.len() - 100..]);
+        let middle = store.read_fs_range(turn.turn_id, &entry, 1000, 37).unwrap();
+        assert_eq!(middle, &content[1000..1037]);
+        // Reads past the end are clamped to the blob.
+        let clamped = store
+            .read_fs_range(turn.turn_id, &entry, total - 10, 1000)
+            .unwrap();
+        assert_eq!(clamped, &content[content.len() - 10..]);
+    }
+
+    assert_eq!(store.get_blob_range(&log_hash, 5, 6).unwrap(), b"0: ok\n");
+    assert!(store.get_blob_range(&[0u8; 32], 0, 1).is_err());
+}
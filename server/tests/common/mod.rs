@@ -0,0 +1,110 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+//! Helpers shared by the integration tests. Each test crate pulls this in
+//! with `mod common;` and uses only part of it.
+#![allow(dead_code)]
+
+use std::collections::HashSet;
+
+use cxdb_server::error::Result;
+use cxdb_server::store::{ContextMetadata, Store};
+use cxdb_server::turn_store::{TurnAuthor, TurnRecord};
+use rmpv::Value;
+
+/// Appends `payload` as an uncompressed msgpack turn of `type_id` under
+/// `parent_turn_id` (0 appends to the head), recording `author`.
+pub fn try_append_as(
+    store: &mut Store,
+    context_id: u64,
+    parent_turn_id: u64,
+    type_id: &str,
+    payload: &[u8],
+    author: Option<TurnAuthor>,
+) -> Result<(TurnRecord, Option<ContextMetadata>)> {
+    store.append_turn_with_author(
+        context_id,
+        parent_turn_id,
+        type_id.to_string(),
+        1,
+        1,
+        0,
+        payload.len() as u32,
+        *blake3::hash(payload).as_bytes(),
+        payload,
+        author,
+    )
+}
+
+/// [`try_append_as`] without an author.
+pub fn try_append(
+    store: &mut Store,
+    context_id: u64,
+    parent_turn_id: u64,
+    type_id: &str,
+    payload: &[u8],
+) -> Result<(TurnRecord, Option<ContextMetadata>)> {
+    try_append_as(store, context_id, parent_turn_id, type_id, payload, None)
+}
+
+/// Appends a turn of `type_id` to the head and returns its id.
+pub fn append_typed(store: &mut Store, context_id: u64, type_id: &str, payload: &[u8]) -> u64 {
+    try_append(store, context_id, 0, type_id, payload)
+        .expect("append")
+        .0
+        .turn_id
+}
+
+/// Appends a `com.example.Test` turn under `parent_turn_id` and returns its id.
+pub fn append_child(
+    store: &mut Store,
+    context_id: u64,
+    parent_turn_id: u64,
+    payload: &[u8],
+) -> u64 {
+    try_append(
+        store,
+        context_id,
+        parent_turn_id,
+        "com.example.Test",
+        payload,
+    )
+    .expect("append")
+    .0
+    .turn_id
+}
+
+/// Appends a `com.example.Test` turn to the head and returns its id.
+pub fn append(store: &mut Store, context_id: u64, payload: &[u8]) -> u64 {
+    append_typed(store, context_id, "com.example.Test", payload)
+}
+
+/// Creates a context whose first turn carries client tag `tag` and returns
+/// its id.
+pub fn create_tagged_context(store: &mut Store, tag: &str) -> u64 {
+    let context_id = store.create_context(0).expect("create context").context_id;
+    let meta = Value::Map(vec![(Value::from(1), Value::from(tag))]);
+    append(
+        store,
+        context_id,
+        &msgpack(&Value::Map(vec![(Value::from(30), meta)])),
+    );
+    context_id
+}
+
+/// Ids of the contexts matching the CQL `query`, ascending.
+pub fn search(store: &mut Store, query: &str) -> Vec<u64> {
+    let mut ids = store
+        .search_contexts(query, &HashSet::new(), None)
+        .expect("search")
+        .context_ids;
+    ids.sort_unstable();
+    ids
+}
+
+/// Encodes `value` as msgpack.
+pub fn msgpack(value: &Value) -> Vec<u8> {
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, value).expect("encode msgpack");
+    buf
+}
@@ -0,0 +1,190 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::append;
+use cxdb_server::deadline::Deadline;
+use cxdb_server::error::StoreError;
+use cxdb_server::keys::{EncryptionConfig, EncryptionMode};
+use cxdb_server::store::{Store, CLONE_SPAWN_REASON};
+use rmpv::Value;
+use tempfile::tempdir;
+
+fn tree(name: &str, content: &[u8]) -> Vec<u8> {
+    let entry = Value::Map(vec![
+        (Value::from(1), Value::from(name)),
+        (Value::from(2), Value::from(0)),
+        (Value::from(3), Value::from(0o644)),
+        (Value::from(4), Value::from(content.len() as u64)),
+        (
+            Value::from(5),
+            Value::Binary(blake3::hash(content).as_bytes().to_vec()),
+        ),
+    ]);
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &Value::Array(vec![entry])).unwrap();
+    buf
+}
+
+/// A context of three turns; the second has a snapshot holding `notes.txt`
+/// and an attachment.
+fn source_context(store: &mut Store) -> (u64, Vec<u64>) {
+    let context_id = store.create_context(0).unwrap().context_id;
+    let first = append(store, context_id, b"first");
+    let second = append(store, context_id, b"second");
+    let file = b"remember the milk".to_vec();
+    let root = tree("notes.txt", &file);
+    let root_hash = *blake3::hash(&root).as_bytes();
+    store
+        .put_blob(
+            *blake3::hash(&file).as_bytes(),
+            &file,
+            Some(context_id),
+            None,
+        )
+        .unwrap();
+    store
+        .put_blob(root_hash, &root, Some(context_id), None)
+        .unwrap();
+    store.attach_fs(second, root_hash).unwrap();
+    store
+        .attach_data(second, "plot.svg", "image/svg+xml", b"<svg/>")
+        .unwrap();
+    let third = append(store, context_id, b"third");
+    (context_id, vec![first, second, third])
+}
+
+#[test]
+fn clone_copies_turns_snapshots_and_attachments_into_an_independent_context() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let (source, turns) = source_context(&mut store);
+    let refs_before = store.dedup_stats();
+
+    let clone = store.clone_context(source, None).unwrap();
+    assert_eq!(clone.source_context_id, source);
+    assert_eq!(clone.up_to_turn_id, turns[2]);
+    assert_eq!(
+        (clone.turns, clone.fs_snapshots, clone.attachments),
+        (3, 1, 1)
+    );
+    assert_eq!(clone.head.head_depth, 2);
+    let cloned = store.get_last(clone.head.context_id, 10, true).unwrap();
+    for (copy, original) in cloned.iter().zip(&turns) {
+        let original = store.get_turn(*original, true).unwrap();
+        assert_ne!(copy.record.turn_id, original.record.turn_id);
+        assert_eq!(copy.record.payload_hash, original.record.payload_hash);
+        assert_eq!(copy.payload, original.payload);
+    }
+    // Payloads are shared rather than stored again.
+    assert_eq!(
+        store.dedup_stats().unique_blobs,
+        refs_before.unique_blobs,
+        "clone stored new payload blobs"
+    );
+
+    let copy_of_second = cloned[1].record.turn_id;
+    let (content, _) = store
+        .get_fs_file(copy_of_second, "notes.txt", false, &Deadline::none())
+        .unwrap();
+    assert_eq!(content, b"remember the milk");
+    let attachments = store.turn_attachments(copy_of_second);
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0].name, "plot.svg");
+
+    let provenance = store
+        .get_context_metadata(clone.head.context_id)
+        .and_then(|m| m.provenance)
+        .unwrap();
+    assert_eq!(provenance.parent_context_id, Some(source));
+    assert_eq!(provenance.spawn_reason.as_deref(), Some(CLONE_SPAWN_REASON));
+
+    // Appending to the clone leaves the source alone.
+    append(&mut store, clone.head.context_id, b"what if");
+    assert_eq!(store.get_head(source).unwrap().head_turn_id, turns[2]);
+}
+
+#[test]
+fn clone_stops_at_the_requested_turn() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let (source, turns) = source_context(&mut store);
+
+    let clone = store.clone_context(source, Some(turns[0])).unwrap();
+    assert_eq!(clone.turns, 1);
+    assert_eq!(clone.fs_snapshots, 0);
+    assert_eq!(clone.head.head_depth, 0);
+
+    let other = store.create_context(0).unwrap().context_id;
+    let foreign = append(&mut store, other, b"elsewhere");
+    assert!(matches!(
+        store.clone_context(source, Some(foreign)),
+        Err(StoreError::InvalidInput(_))
+    ));
+    let empty = store.create_context(0).unwrap().context_id;
+    assert!(matches!(
+        store.clone_context(empty, None),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        store.clone_context(999, None),
+        Err(StoreError::NotFound(_))
+    ));
+}
+
+#[test]
+fn encrypted_clones_get_their_own_key() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    store
+        .enable_encryption(&EncryptionConfig {
+            mode: EncryptionMode::Context,
+            master_key: [9u8; 32],
+        })
+        .expect("enable encryption");
+    let (source, _) = source_context(&mut store);
+
+    let clone = store.clone_context(source, None).unwrap();
+    let copy_of_second = store.get_last(clone.head.context_id, 10, false).unwrap()[1]
+        .record
+        .turn_id;
+
+    // The clone survives shredding the source's key.
+    store.shred_context_key(source).unwrap();
+    let last = store.get_last(clone.head.context_id, 1, true).unwrap();
+    assert_eq!(last[0].payload.as_deref(), Some(&b"third"[..]));
+    let (content, _) = store
+        .get_fs_file(copy_of_second, "notes.txt", false, &Deadline::none())
+        .unwrap();
+    assert_eq!(content, b"remember the milk");
+}
+
+#[test]
+fn failed_clones_leave_no_context_behind() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    store
+        .enable_encryption(&EncryptionConfig {
+            mode: EncryptionMode::Context,
+            master_key: [9u8; 32],
+        })
+        .expect("enable encryption");
+    let (source, _) = source_context(&mut store);
+    store.shred_context_key(source).unwrap();
+
+    // The copy fails reading the first payload, after the new context exists.
+    let err = store.clone_context(source, None).unwrap_err();
+    assert!(matches!(err, StoreError::Shredded(_)), "{err:?}");
+    let listed: Vec<u64> = store
+        .list_recent_contexts(10)
+        .iter()
+        .map(|head| head.context_id)
+        .collect();
+    assert_eq!(listed, vec![source]);
+    let partial = source + 1;
+    assert!(matches!(
+        store.get_head(partial),
+        Err(StoreError::Expired(_))
+    ));
+}
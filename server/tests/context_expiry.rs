@@ -0,0 +1,130 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use std::time::Duration;
+
+use cxdb_server::error::StoreError;
+use cxdb_server::store::Store;
+use tempfile::tempdir;
+
+fn append(store: &mut Store, context_id: u64) -> Result<(), StoreError> {
+    common::try_append(store, context_id, 0, "com.example.Test", b"turn").map(|_| ())
+}
+
+#[test]
+fn expired_contexts_are_tombstoned_by_the_sweep() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let short = store
+        .create_context_with_ttl(0, None, Some(Duration::from_secs(60)))
+        .unwrap();
+    let long = store
+        .create_context_with_ttl(0, None, Some(Duration::from_secs(7 * 24 * 3600)))
+        .unwrap()
+        .context_id;
+    let plain = store.create_context(0).unwrap().context_id;
+    append(&mut store, short.context_id).unwrap();
+
+    let expires_at = store.expiry(short.context_id).unwrap().expires_at_unix_ms;
+    assert_eq!(expires_at, short.created_at_unix_ms + 60_000);
+    assert!(store.expiry(plain).is_none());
+    assert_eq!(
+        common::search(&mut store, r#"expires < "+1d""#),
+        vec![short.context_id]
+    );
+    assert_eq!(common::search(&mut store, r#"expires > "+1d""#), vec![long]);
+    assert!(common::search(&mut store, r#"expires < "-0d""#).is_empty());
+
+    assert!(store.expire_due(expires_at - 1).unwrap().is_empty());
+    let expired = store.expire_due(expires_at).unwrap();
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].context_id, short.context_id);
+    assert_eq!(expired[0].expired_at_unix_ms, Some(expires_at));
+    // Already tombstoned contexts are not expired again.
+    assert!(store.expire_due(expires_at + 1).unwrap().is_empty());
+
+    assert!(matches!(
+        store.get_head(short.context_id),
+        Err(StoreError::Expired(_))
+    ));
+    assert!(matches!(
+        store.get_last(short.context_id, 10, false),
+        Err(StoreError::Expired(_))
+    ));
+    assert!(matches!(
+        append(&mut store, short.context_id),
+        Err(StoreError::Expired(_))
+    ));
+    let mut listed: Vec<u64> = store
+        .list_recent_contexts(10)
+        .iter()
+        .map(|h| h.context_id)
+        .collect();
+    listed.sort_unstable();
+    assert_eq!(listed, vec![long, plain]);
+    assert!(common::search(&mut store, r#"expires < "+1d""#).is_empty());
+
+    drop(store);
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    assert!(matches!(
+        store.get_head(short.context_id),
+        Err(StoreError::Expired(_))
+    ));
+    assert_eq!(common::search(&mut store, r#"expires > "-1d""#), vec![long]);
+}
+
+#[test]
+fn held_contexts_outlive_their_ttl() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let context_id = store
+        .create_context_with_ttl(0, None, Some(Duration::from_secs(1)))
+        .unwrap()
+        .context_id;
+    store.place_hold(context_id, "litigation", "legal").unwrap();
+
+    let expires_at = store.expiry(context_id).unwrap().expires_at_unix_ms;
+    assert!(store.expire_due(expires_at + 1000).unwrap().is_empty());
+    store.get_head(context_id).unwrap();
+
+    store.release_hold(context_id, "", "legal").unwrap();
+    assert_eq!(store.expire_due(expires_at + 2000).unwrap().len(), 1);
+    assert!(matches!(
+        store.get_head(context_id),
+        Err(StoreError::Expired(_))
+    ));
+}
+
+#[test]
+fn turns_of_expired_contexts_are_refused_unless_a_fork_shares_them() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let context_id = store
+        .create_context_with_ttl(0, None, Some(Duration::from_secs(60)))
+        .unwrap()
+        .context_id;
+    let shared = common::append(&mut store, context_id, b"shared");
+    let fork = store.fork_context(shared).unwrap().context_id;
+    let own = common::append(&mut store, context_id, b"own");
+
+    let expires_at = store.expiry(context_id).unwrap().expires_at_unix_ms;
+    assert_eq!(store.expire_due(expires_at).unwrap().len(), 1);
+
+    assert!(matches!(
+        store.get_turn(own, true),
+        Err(StoreError::Expired(_))
+    ));
+    assert!(matches!(
+        store.get_attachment(own, "notes.txt"),
+        Err(StoreError::Expired(_))
+    ));
+    assert!(matches!(
+        store.turn_proof(shared, context_id),
+        Err(StoreError::Expired(_))
+    ));
+    // The fork still reads the turn it shares with the expired context.
+    store.get_turn(shared, true).unwrap();
+    store.turn_proof(shared, fork).unwrap();
+}
@@ -0,0 +1,226 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::{append_typed, search};
+use std::sync::{Arc, Mutex};
+
+use cxdb_server::error::StoreError;
+use cxdb_server::metadata_cache::MetadataCacheConfig;
+use cxdb_server::previews::{tail, PreviewConfig};
+use cxdb_server::registry::Registry;
+use cxdb_server::store::Store;
+use rmpv::Value;
+use tempfile::tempdir;
+
+const BUNDLE: &str = r#"
+{
+  "registry_version": 1,
+  "bundle_id": "2025-12-19T00:00:00Z#previews",
+  "types": {
+    "com.example.Message": {
+      "versions": {
+        "1": {
+          "fields": {
+            "1": { "name": "role", "type": "string" },
+            "2": { "name": "text", "type": "string" }
+          },
+          "summary_field": "text"
+        }
+      }
+    },
+    "com.example.Log": {
+      "versions": {
+        "1": {
+          "fields": {
+            "1": { "name": "line", "type": "string" }
+          }
+        }
+      }
+    }
+  }
+}
+"#;
+
+fn payload(fields: Vec<(i64, &str)>) -> Vec<u8> {
+    let map = fields
+        .into_iter()
+        .map(|(tag, text)| (Value::from(tag), Value::from(text)))
+        .collect();
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &Value::Map(map)).expect("encode");
+    buf
+}
+
+fn open_store(dir: &std::path::Path) -> Store {
+    open_store_with(dir, MetadataCacheConfig::default())
+}
+
+fn open_store_with(dir: &std::path::Path, config: MetadataCacheConfig) -> Store {
+    let registry = Arc::new(Mutex::new(
+        Registry::open(&dir.join("registry")).expect("registry"),
+    ));
+    registry
+        .lock()
+        .unwrap()
+        .put_bundle("2025-12-19T00:00:00Z#previews", BUNDLE.as_bytes())
+        .ok();
+    let mut store = Store::open_with_metadata_cache(dir, config).expect("open store");
+    store.enable_previews(PreviewConfig { max_chars: 16 }, registry);
+    store
+}
+
+#[test]
+fn summary_field_must_name_a_string_field() {
+    let dir = tempdir().expect("tempdir");
+    let mut registry = Registry::open(dir.path()).expect("registry");
+    for (id, summary_field, field_type) in [
+        ("2025-12-18T00:00:00Z#missing", "body", "string"),
+        ("2025-12-18T00:00:01Z#numeric", "text", "u64"),
+    ] {
+        let bundle = BUNDLE
+            .replace("2025-12-19T00:00:00Z#previews", id)
+            .replace(
+                r#""summary_field": "text""#,
+                &format!(r#""summary_field": "{summary_field}""#),
+            )
+            .replace(
+                r#""2": { "name": "text", "type": "string" }"#,
+                &format!(r#""2": {{ "name": "text", "type": "{field_type}" }}"#),
+            );
+        let err = registry.put_bundle(id, bundle.as_bytes()).unwrap_err();
+        assert!(matches!(err, StoreError::InvalidInput(_)), "{err:?}");
+    }
+
+    registry
+        .put_bundle("2025-12-19T00:00:00Z#previews", BUNDLE.as_bytes())
+        .expect("put bundle");
+    let spec = registry
+        .get_type_version("com.example.Message", 1)
+        .expect("type");
+    assert_eq!(spec.summary_field, Some(2));
+}
+
+#[test]
+fn previews_follow_the_latest_summary_turn_and_forks() {
+    assert_eq!(tail("  a\n  b ", 10).as_deref(), Some("a b"));
+    assert_eq!(tail("héllo wörld", 5).as_deref(), Some("wörld"));
+    assert_eq!(tail(" \n ", 10), None);
+
+    let dir = tempdir().expect("tempdir");
+    let (first, forked) = {
+        let mut store = open_store(dir.path());
+        let first = store.create_context(0).expect("create").context_id;
+        let other = store.create_context(0).expect("create").context_id;
+        assert_eq!(store.context_preview(first), None);
+
+        append_typed(
+            &mut store,
+            first,
+            "com.example.Message",
+            &payload(vec![(1, "user"), (2, "Why is CI red?")]),
+        );
+        let base = append_typed(
+            &mut store,
+            first,
+            "com.example.Message",
+            &payload(vec![
+                (1, "user"),
+                (2, "please fix the flaky   integration test"),
+            ]),
+        );
+        // Types without a summary field and blank text keep the preview.
+        append_typed(
+            &mut store,
+            first,
+            "com.example.Log",
+            &payload(vec![(1, "cargo test")]),
+        );
+        append_typed(
+            &mut store,
+            first,
+            "com.example.Message",
+            &payload(vec![(1, "assistant"), (2, "  ")]),
+        );
+        assert_eq!(store.context_preview(first), Some("integration test"));
+
+        append_typed(
+            &mut store,
+            other,
+            "com.example.Message",
+            &payload(vec![(2, "deploy to prod")]),
+        );
+
+        let forked = store.fork_context(base).expect("fork").context_id;
+        assert_eq!(store.context_preview(forked), Some("integration test"));
+        append_typed(
+            &mut store,
+            forked,
+            "com.example.Message",
+            &payload(vec![(2, "try again")]),
+        );
+        assert_eq!(store.context_preview(forked), Some("try again"));
+        assert_eq!(store.context_preview(first), Some("integration test"));
+
+        assert_eq!(
+            search(&mut store, r#"preview CONTAINS "Integration""#),
+            vec![first]
+        );
+        assert_eq!(
+            search(&mut store, r#"preview contains "o""#),
+            vec![first, other]
+        );
+        assert!(search(&mut store, r#"preview CONTAINS "flaky""#).is_empty());
+        assert!(store
+            .search_contexts(r#"preview = "try again""#, &Default::default(), None)
+            .is_err());
+        (first, forked)
+    };
+
+    let mut store = open_store(dir.path());
+    assert_eq!(store.context_preview(first), Some("integration test"));
+    assert_eq!(store.context_preview(forked), Some("try again"));
+    assert_eq!(
+        search(&mut store, r#"preview CONTAINS "again""#),
+        vec![forked]
+    );
+}
+
+#[test]
+fn spilled_previews_are_still_shown_and_searchable() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = open_store_with(
+        dir.path(),
+        MetadataCacheConfig {
+            index_text_bytes: Some(4 * 1024),
+            ..Default::default()
+        },
+    );
+    let ids: Vec<u64> = (0..200)
+        .map(|i| {
+            let ctx = store.create_context(0).expect("create").context_id;
+            let text = format!("build {i} is red");
+            append_typed(
+                &mut store,
+                ctx,
+                "com.example.Message",
+                &payload(vec![(2, text.as_str())]),
+            );
+            ctx
+        })
+        .collect();
+
+    let stats = store.index_stats();
+    assert!(stats.spilled_contexts > 0);
+    assert!(stats.text_bytes <= 4 * 1024, "{}", stats.text_bytes);
+    assert_eq!(store.context_preview(ids[0]), Some("build 0 is red"));
+    assert_eq!(
+        search(&mut store, r#"preview CONTAINS "build 7 is""#),
+        vec![ids[7]]
+    );
+    assert_eq!(
+        search(&mut store, r#"preview CONTAINS "is red""#).len(),
+        200
+    );
+}
@@ -0,0 +1,85 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use std::time::Duration;
+
+use cxdb_server::cpu_profile::{CpuProfiler, ProfileFormat, MAX_SECONDS};
+use cxdb_server::error::StoreError;
+
+#[test]
+fn parses_formats_and_bounds_the_capture_length() {
+    assert_eq!(
+        ProfileFormat::parse("flamegraph").unwrap(),
+        ProfileFormat::Flamegraph
+    );
+    assert_eq!(
+        ProfileFormat::parse("svg").unwrap(),
+        ProfileFormat::Flamegraph
+    );
+    assert_eq!(ProfileFormat::parse("pprof").unwrap(), ProfileFormat::Pprof);
+    assert!(matches!(
+        ProfileFormat::parse("perf"),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert_eq!(ProfileFormat::Pprof.extension(), "pb");
+    assert_eq!(ProfileFormat::Flamegraph.content_type(), "image/svg+xml");
+
+    let profiler = CpuProfiler::default();
+    for duration in [Duration::ZERO, Duration::from_secs(MAX_SECONDS + 1)] {
+        let err = profiler
+            .capture(duration, ProfileFormat::Flamegraph)
+            .unwrap_err();
+        assert!(matches!(err, StoreError::InvalidInput(_)), "{err:?}");
+    }
+}
+
+#[cfg(not(feature = "cpu-profiling"))]
+#[test]
+fn captures_need_the_cpu_profiling_feature() {
+    let err = CpuProfiler::default()
+        .capture(Duration::from_secs(1), ProfileFormat::Pprof)
+        .unwrap_err();
+    match err {
+        StoreError::InvalidInput(message) => assert!(message.contains("cpu-profiling")),
+        other => panic!("expected InvalidInput, got {other:?}"),
+    }
+}
+
+#[cfg(feature = "cpu-profiling")]
+#[test]
+fn captures_one_profile_at_a_time() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let profiler = Arc::new(CpuProfiler::new(199));
+    let done = Arc::new(AtomicBool::new(false));
+    let busy = {
+        let profiler = Arc::clone(&profiler);
+        let done = Arc::clone(&done);
+        std::thread::spawn(move || {
+            let svg = profiler.capture(Duration::from_secs(1), ProfileFormat::Flamegraph);
+            done.store(true, Ordering::Release);
+            svg
+        })
+    };
+    std::thread::sleep(Duration::from_millis(200));
+    let err = profiler
+        .capture(Duration::from_secs(1), ProfileFormat::Pprof)
+        .unwrap_err();
+    assert!(matches!(err, StoreError::Cancelled(_)), "{err:?}");
+
+    // Stay on CPU until the capture ends so the profile has samples.
+    let mut x = 0u64;
+    while !done.load(Ordering::Acquire) {
+        for _ in 0..100_000 {
+            x = std::hint::black_box(x.wrapping_mul(31).wrapping_add(1));
+        }
+    }
+    let svg = busy.join().unwrap().expect("flamegraph");
+    assert!(String::from_utf8_lossy(&svg).contains("<svg"));
+
+    let pprof = profiler
+        .capture(Duration::from_secs(1), ProfileFormat::Pprof)
+        .expect("pprof");
+    assert!(!pprof.is_empty());
+}
@@ -430,3 +430,66 @@ fn test_index_all_context_ids() {
     let all = indexes.all_contexts();
     assert_eq!(all.len(), 5);
 }
+
+// ============================================================================
+// Namespaced Label Tests
+// ============================================================================
+
+fn create_label_indexes() -> SecondaryIndexes {
+    let mut indexes = SecondaryIndexes::new();
+    let labeled = |labels: &[&str]| ContextMetadata {
+        labels: Some(labels.iter().map(|l| l.to_string()).collect()),
+        ..Default::default()
+    };
+    indexes.add_context(1, Some(&labeled(&["team:payments", "env:prod"])), 1000, 1);
+    indexes.add_context(
+        2,
+        Some(&labeled(&["team:payments:billing", "env:staging"])),
+        2000,
+        1,
+    );
+    indexes.add_context(3, Some(&labeled(&["team:search", "urgent"])), 3000, 1);
+    indexes
+}
+
+#[test]
+fn test_execute_label_namespace_query() {
+    let indexes = create_label_indexes();
+    let live_contexts = HashSet::new();
+
+    let query = parse(r#"label.team = "payments""#).unwrap();
+    let result = execute(&query.ast, &indexes, &live_contexts).unwrap();
+    assert_eq!(result, HashSet::from([1]));
+
+    let query = parse(r#"label.team ^= "payments""#).unwrap();
+    let result = execute(&query.ast, &indexes, &live_contexts).unwrap();
+    assert_eq!(result, HashSet::from([1, 2]));
+
+    let query = parse(r#"label.env IN ("prod", "staging") AND NOT label.team = "search""#).unwrap();
+    let result = execute(&query.ast, &indexes, &live_contexts).unwrap();
+    assert_eq!(result, HashSet::from([1, 2]));
+
+    // Full labels still match the flat label index
+    let query = parse(r#"label = "team:search""#).unwrap();
+    let result = execute(&query.ast, &indexes, &live_contexts).unwrap();
+    assert_eq!(result, HashSet::from([3]));
+}
+
+#[test]
+fn test_index_label_namespace_values() {
+    let indexes = create_label_indexes();
+
+    assert_eq!(
+        indexes.label_namespaces(),
+        vec![("env".to_string(), 2), ("team".to_string(), 3)]
+    );
+    assert_eq!(
+        indexes.label_namespace_values("team"),
+        vec![
+            ("payments".to_string(), 1),
+            ("payments:billing".to_string(), 1),
+            ("search".to_string(), 1),
+        ]
+    );
+    assert!(indexes.label_namespace_values("missing").is_empty());
+}
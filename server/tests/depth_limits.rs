@@ -0,0 +1,136 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use std::collections::BTreeMap;
+
+use cxdb_server::depth_limits::DepthLimits;
+use cxdb_server::error::StoreError;
+use cxdb_server::protocol::ErrorCode;
+use cxdb_server::quotas::QuotaLevel;
+use cxdb_server::store::Store;
+use cxdb_server::turn_store::TurnRecord;
+use rmpv::Value;
+use tempfile::tempdir;
+
+fn payload(client_tag: Option<&str>) -> Vec<u8> {
+    let mut fields = vec![(Value::from(1), Value::from("turn"))];
+    if let Some(tag) = client_tag {
+        fields.push((
+            Value::from(30),
+            Value::Map(vec![(Value::from(1), Value::from(tag))]),
+        ));
+    }
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &Value::Map(fields)).expect("encode");
+    buf
+}
+
+fn append(
+    store: &mut Store,
+    context_id: u64,
+    parent_turn_id: u64,
+    payload: &[u8],
+) -> Result<TurnRecord, StoreError> {
+    common::try_append(
+        store,
+        context_id,
+        parent_turn_id,
+        "com.example.Turn",
+        payload,
+    )
+    .map(|(record, _)| record)
+}
+
+fn limits() -> DepthLimits {
+    DepthLimits {
+        default: Some(5),
+        tags: BTreeMap::from([("ci".to_string(), 3)]),
+        warn_at: 0.6,
+    }
+}
+
+#[test]
+fn warnings_fire_at_the_threshold_and_the_last_turn() {
+    let limits = limits();
+    assert_eq!(limits.limit(Some("ci")), Some(3));
+    assert_eq!(limits.limit(Some("")), Some(5));
+    assert_eq!(limits.limit(None), Some(5));
+
+    let levels: Vec<_> = (0..5)
+        .map(|depth| limits.warning(7, None, depth).map(|w| w.level))
+        .collect();
+    assert_eq!(
+        levels,
+        [
+            None,
+            None,
+            Some(QuotaLevel::Warning),
+            None,
+            Some(QuotaLevel::Exceeded)
+        ]
+    );
+    let warning = limits.warning(7, Some("ci"), 1).expect("warning");
+    assert_eq!((warning.limit, warning.level), (3, QuotaLevel::Warning));
+    assert!((warning.percent - 200.0 / 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn appends_beyond_the_limit_are_rejected() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    store.enable_depth_limits(limits());
+
+    let ci = store.create_context(0).expect("create").context_id;
+    let other = store.create_context(0).expect("create").context_id;
+    let root = append(&mut store, ci, 0, &payload(Some("ci"))).unwrap();
+    assert_eq!(root.depth, 0);
+    assert_eq!(store.context_depth_limit(ci), Some(3));
+    assert_eq!(store.context_depth_limit(other), Some(5));
+
+    let mut warnings = Vec::new();
+    for _ in 0..2 {
+        let depth = append(&mut store, ci, 0, &payload(None)).unwrap().depth;
+        warnings.extend(store.depth_warning(ci, depth).map(|w| w.level));
+    }
+    assert_eq!(warnings, [QuotaLevel::Warning, QuotaLevel::Exceeded]);
+
+    let err = append(&mut store, ci, 0, &payload(None)).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            StoreError::DepthLimitExceeded {
+                depth: 3,
+                limit: 3,
+                ..
+            }
+        ),
+        "{err:?}"
+    );
+    assert_eq!(ErrorCode::of(&err), ErrorCode::DepthLimitExceeded);
+    assert_eq!(ErrorCode::of(&err).status(), 409);
+    assert!(err.to_string().contains("fork"));
+    assert_eq!(store.get_head(ci).unwrap().head_depth, 2);
+
+    // Appending below a shallower turn, or in a fork of it, is still allowed.
+    let branch = append(&mut store, ci, root.turn_id, &payload(None)).unwrap();
+    assert_eq!(branch.depth, 1);
+    let forked = store.fork_context(root.turn_id).expect("fork").context_id;
+    assert_eq!(
+        append(&mut store, forked, 0, &payload(None)).unwrap().depth,
+        1
+    );
+
+    // Untagged contexts get the default limit.
+    for depth in 0..5 {
+        assert_eq!(
+            append(&mut store, other, 0, &payload(None)).unwrap().depth,
+            depth
+        );
+    }
+    assert!(matches!(
+        append(&mut store, other, 0, &payload(None)),
+        Err(StoreError::DepthLimitExceeded { limit: 5, .. })
+    ));
+}
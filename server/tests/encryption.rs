@@ -0,0 +1,267 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::append;
+use cxdb_server::deadline::Deadline;
+use cxdb_server::error::StoreError;
+use cxdb_server::keys::{EncryptionConfig, EncryptionMode};
+use cxdb_server::store::Store;
+use rmpv::Value;
+use tempfile::tempdir;
+
+fn open(dir: &std::path::Path, mode: EncryptionMode) -> Store {
+    let mut store = Store::open(dir).expect("open store");
+    store
+        .enable_encryption(&EncryptionConfig {
+            mode,
+            master_key: [9u8; 32],
+        })
+        .expect("enable encryption");
+    store
+}
+
+fn tree(name: &str, content: &[u8]) -> Vec<u8> {
+    let entry = Value::Map(vec![
+        (Value::from(1), Value::from(name)),
+        (Value::from(2), Value::from(0)),
+        (Value::from(3), Value::from(0o644)),
+        (Value::from(4), Value::from(content.len() as u64)),
+        (
+            Value::from(5),
+            Value::Binary(blake3::hash(content).as_bytes().to_vec()),
+        ),
+    ]);
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &Value::Array(vec![entry])).unwrap();
+    buf
+}
+
+#[test]
+fn sealed_payloads_and_fs_blobs_are_unreadable_after_shredding() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = open(dir.path(), EncryptionMode::Context);
+
+    let ctx = store.create_context(0).expect("create context");
+    let payload = b"the launch code is 0000".to_vec();
+    let content_hash = *blake3::hash(&payload).as_bytes();
+
+    // Filesystem blobs uploaded for the context are sealed with its key.
+    let file = b"api_key = hunter2".to_vec();
+    let root = tree("secrets.env", &file);
+    let root_hash = *blake3::hash(&root).as_bytes();
+    assert!(store
+        .put_blob(
+            *blake3::hash(&file).as_bytes(),
+            &file,
+            Some(ctx.context_id),
+            None
+        )
+        .unwrap());
+    assert!(store
+        .put_blob(root_hash, &root, Some(ctx.context_id), None)
+        .unwrap());
+
+    let turn_id = append(&mut store, ctx.context_id, &payload);
+    store.attach_fs(turn_id, root_hash).expect("attach fs");
+
+    // Nothing is stored under the plaintext content hashes.
+    assert!(!store.blob_store.contains(&content_hash));
+    assert!(!store.blob_store.contains(&root_hash));
+
+    let last = store.get_last(ctx.context_id, 1, true).unwrap();
+    assert_eq!(last[0].payload.as_deref(), Some(&payload[..]));
+    let (content, _) = store
+        .get_fs_file(turn_id, "secrets.env", false, &Deadline::none())
+        .unwrap();
+    assert_eq!(content, file);
+
+    // A fork shares the key of its base turn.
+    let fork = store.fork_context(turn_id).expect("fork");
+    append(&mut store, fork.context_id, b"fork turn");
+
+    assert_eq!(store.blob_gc_stats().dead_blobs, 0);
+    let info = store.shred_context_key(ctx.context_id).unwrap();
+    assert!(info.shredded);
+    assert_eq!(info.context_count, 2);
+    // Shredding tombstones every sealed blob of both contexts.
+    assert_eq!(store.blob_gc_stats().live_blobs, 0);
+
+    for context_id in [ctx.context_id, fork.context_id] {
+        let err = store.get_last(context_id, 1, true).unwrap_err();
+        assert!(matches!(err, StoreError::Shredded(_)), "{err:?}");
+    }
+    let err = store
+        .get_fs_file(turn_id, "secrets.env", false, &Deadline::none())
+        .unwrap_err();
+    assert!(matches!(err, StoreError::Shredded(_)), "{err:?}");
+
+    // Turn metadata without payloads is still available.
+    assert_eq!(store.get_last(ctx.context_id, 1, false).unwrap().len(), 1);
+}
+
+#[test]
+fn tag_mode_shares_keys_between_contexts_of_a_tag() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = open(dir.path(), EncryptionMode::Tag);
+
+    let first_turn = |tag: &str| {
+        let value = Value::Map(vec![(
+            Value::from(30),
+            Value::Map(vec![(Value::from(1), Value::from(tag))]),
+        )]);
+        let mut buf = Vec::new();
+        rmpv::encode::write_value(&mut buf, &value).unwrap();
+        buf
+    };
+
+    let a = store.create_context(0).unwrap().context_id;
+    let b = store.create_context(0).unwrap().context_id;
+    let c = store.create_context(0).unwrap().context_id;
+    append(&mut store, a, &first_turn("tenant-a"));
+    append(&mut store, b, &first_turn("tenant-a"));
+    append(&mut store, c, &first_turn("tenant-b"));
+
+    let err = store.shred_context_key(a).unwrap_err();
+    assert!(matches!(err, StoreError::InvalidInput(_)), "{err:?}");
+
+    let info = store.shred_tag_key("tenant-a").unwrap();
+    assert_eq!(info.context_count, 2);
+    assert!(store.get_last(a, 1, true).is_err());
+    assert!(store.get_last(b, 1, true).is_err());
+    assert!(store.get_last(c, 1, true).is_ok());
+
+    let keys = store.list_keys();
+    assert_eq!(keys.len(), 2);
+    assert_eq!(keys.iter().filter(|k| k.shredded).count(), 1);
+}
+
+#[test]
+fn compaction_plan_reports_what_a_collection_would_reclaim() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = open(dir.path(), EncryptionMode::Context);
+    let doomed = store.create_context(0).unwrap().context_id;
+    let kept = store.create_context(0).unwrap().context_id;
+    append(&mut store, doomed, b"forget me");
+    append(&mut store, kept, b"keep me");
+    store.collect_blobs(false).unwrap();
+
+    store.shred_context_key(doomed).unwrap();
+    let stray = b"uploaded but never attached";
+    store
+        .put_blob(*blake3::hash(stray).as_bytes(), stray, None, None)
+        .unwrap();
+
+    let plan = store.compaction_plan().unwrap();
+    assert!(plan.runnable);
+    let expiring: Vec<&str> = plan
+        .contexts_to_expire
+        .iter()
+        .map(|c| c.context_id.as_str())
+        .collect();
+    assert_eq!(expiring, vec![doomed.to_string()]);
+    assert_eq!(plan.reclaim_blobs, 1);
+    assert_eq!(plan.files[0].file, "blobs/blobs.pack");
+    assert!(plan.files[..2].iter().all(|f| f.reclaim_bytes > 0));
+    // No snapshot was re-attached, so the fs roots index stays as is.
+    assert_eq!(plan.files[2].file, "fs/roots.idx");
+    assert_eq!(plan.files[2].reclaim_bytes, 0);
+    assert_eq!(plan.estimate_basis, "default");
+    // The blob written since the last collection survives the next one.
+    assert_eq!(plan.blockers.len(), 1);
+    assert_eq!(plan.blockers[0].kind, "young_blobs");
+    assert_eq!(plan.blockers[0].blobs, 1);
+
+    // Planning changes nothing; the collection then does what it said.
+    assert_eq!(store.blob_gc_stats().dead_blobs, 2);
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    let sweep = store.collect_blobs(false).unwrap();
+    assert_eq!(sweep.swept_blobs, plan.reclaim_blobs);
+    assert_eq!(sweep.pack_bytes_after, plan.files[0].bytes_after);
+    assert_eq!(sweep.idx_bytes_after, plan.files[1].bytes_after);
+
+    let plan = store.compaction_plan().unwrap();
+    assert!(plan.contexts_to_expire.is_empty());
+    assert_eq!(plan.reclaim_blobs, 1);
+    assert_eq!(plan.estimate_basis, "last_collection");
+}
+
+#[test]
+fn legal_holds_block_shredding_and_are_searchable() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = open(dir.path(), EncryptionMode::Context);
+    let held = store.create_context(0).unwrap().context_id;
+    let other = store.create_context(0).unwrap().context_id;
+    let turn_id = append(&mut store, held, b"evidence");
+    append(&mut store, other, b"chatter");
+
+    let err = store
+        .place_hold(held, " ", "legal@example.com")
+        .unwrap_err();
+    assert!(matches!(err, StoreError::InvalidInput(_)), "{err:?}");
+    store
+        .place_hold(held, "case 1234", "legal@example.com")
+        .unwrap();
+    assert_eq!(store.hold(held).unwrap().reason, "case 1234");
+    let search = |store: &mut Store, query: &str| {
+        store
+            .search_contexts(query, &std::collections::HashSet::new(), None)
+            .unwrap()
+            .context_ids
+    };
+    assert_eq!(search(&mut store, "on_hold = true"), vec![held]);
+    assert_eq!(search(&mut store, "on_hold = false"), vec![other]);
+
+    // The hold covers forks sharing the held context's key.
+    let fork = store.fork_context(turn_id).unwrap().context_id;
+    append(&mut store, fork, b"fork turn");
+    for err in [
+        store.shred_context_key(held).unwrap_err(),
+        store.shred_context_key(fork).unwrap_err(),
+    ] {
+        assert!(matches!(err, StoreError::InvalidInput(_)), "{err:?}");
+    }
+    assert_eq!(store.get_last(held, 1, true).unwrap().len(), 1);
+
+    // Releasing is audited alongside the placement, then shredding works.
+    store
+        .release_hold(held, "case closed", "legal@example.com")
+        .unwrap();
+    assert!(store.release_hold(held, "", "legal@example.com").is_err());
+    let history = store.hold_history(held);
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[1].reason, "case closed");
+    assert!(search(&mut store, "on_hold = true").is_empty());
+    store.shred_context_key(held).unwrap();
+    let err = store.place_hold(held, "too late", "legal@example.com");
+    assert!(matches!(err, Err(StoreError::Shredded(_))), "{err:?}");
+}
+
+#[test]
+fn holds_placed_after_a_torn_write_survive_restarts() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let first = store.create_context(0).unwrap().context_id;
+    let second = store.create_context(0).unwrap().context_id;
+    store
+        .place_hold(first, "case 1", "legal@example.com")
+        .unwrap();
+    drop(store);
+
+    // A crash mid-write leaves part of a hold behind.
+    let path = dir.path().join("meta").join("holds.jsonl");
+    let mut log = std::fs::read(&path).unwrap();
+    log.extend_from_slice(b"{\"context_id\":2,\"act\xe2");
+    std::fs::write(&path, log).unwrap();
+
+    let mut store = Store::open(dir.path()).expect("reopen with a torn hold");
+    store
+        .place_hold(second, "case 2", "legal@example.com")
+        .unwrap();
+    drop(store);
+
+    let store = Store::open(dir.path()).expect("reopen");
+    assert_eq!(store.hold(first).unwrap().reason, "case 1");
+    assert_eq!(store.hold(second).unwrap().reason, "case 2");
+}
@@ -0,0 +1,113 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::append;
+use std::collections::HashSet;
+use std::io::Read;
+
+use arrow_array::{Array, StringArray, UInt64Array};
+use cxdb_server::export::{write_parquet, CsvStream};
+use cxdb_server::store::Store;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use rmpv::Value;
+use tempfile::tempdir;
+
+fn payload(metadata: Option<(&str, &str, &str)>) -> Vec<u8> {
+    let mut fields = vec![(Value::from(1), Value::from("hello"))];
+    if let Some((tag, user, service)) = metadata {
+        fields.push((
+            Value::from(30),
+            Value::Map(vec![
+                (Value::from(1), Value::from(tag)),
+                (
+                    Value::from(10),
+                    Value::Map(vec![
+                        (Value::from(20), Value::from(user)),
+                        (Value::from(40), Value::from(service)),
+                    ]),
+                ),
+            ]),
+        ));
+    }
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &Value::Map(fields)).expect("encode");
+    buf
+}
+
+#[test]
+fn export_rows_as_csv_and_parquet() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+
+    let first = payload(Some(("agent-a", "alice", "billing")));
+    let second = payload(None);
+    let ctx = store.create_context(0).unwrap().context_id;
+    append(&mut store, ctx, &first);
+    append(&mut store, ctx, &second);
+    let other = store.create_context(0).unwrap().context_id;
+    append(
+        &mut store,
+        other,
+        &payload(Some(("agent-b", "bob", "search"))),
+    );
+    let empty = store.create_context(0).unwrap().context_id;
+
+    let ids = store
+        .search_contexts("tag = \"agent-a\"", &HashSet::new(), None)
+        .expect("search")
+        .context_ids;
+    let rows = store.export_rows(Some(&ids)).expect("export rows");
+    assert_eq!(rows.len(), 1);
+    let row = &rows[0];
+    assert_eq!(row.context_id, ctx);
+    assert_eq!(row.client_tag.as_deref(), Some("agent-a"));
+    assert_eq!(row.user.as_deref(), Some("alice"));
+    assert_eq!(row.service.as_deref(), Some("billing"));
+    assert_eq!(row.turns, 2);
+    assert_eq!(row.payload_bytes, (first.len() + second.len()) as u64);
+
+    let all = store.export_rows(None).expect("export rows");
+    assert_eq!(all.len(), 3);
+    let empty_row = all.iter().find(|r| r.context_id == empty).unwrap();
+    assert_eq!((empty_row.turns, empty_row.payload_bytes), (0, 0));
+
+    let mut csv = String::new();
+    CsvStream::new(rows.clone())
+        .read_to_string(&mut csv)
+        .expect("read csv");
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "context_id,client_tag,title,user,service,host,created_at_unix_ms,\
+         head_turn_id,head_depth,turns,payload_bytes,tokens"
+    );
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].starts_with(&format!("{ctx},agent-a,,alice,billing,,")));
+
+    let parquet = write_parquet(&all).expect("write parquet");
+    let reader = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(parquet))
+        .expect("parquet reader")
+        .build()
+        .expect("build reader");
+    let batches: Vec<_> = reader.map(|b| b.expect("batch")).collect();
+    assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 3);
+    let batch = &batches[0];
+    let context_ids = batch
+        .column_by_name("context_id")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<UInt64Array>()
+        .unwrap();
+    let tags = batch
+        .column_by_name("client_tag")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    for (i, row) in all.iter().enumerate() {
+        assert_eq!(context_ids.value(i), row.context_id);
+        assert_eq!(tags.is_null(i), row.client_tag.is_none());
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::{append, search};
+
+use cxdb_server::error::StoreError;
+use cxdb_server::store::Store;
+use tempfile::tempdir;
+
+const ULID: &str = "01HZX3K9Q7VJ8Y2N4M6P0R5T1W";
+const UUID: &str = "3f2b8c1e-6a4d-4f7b-9c2e-1d5a8b7e9f03";
+
+#[test]
+fn external_ids_are_unique_and_survive_reopening() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let first = store
+        .create_context_with_external_id(0, Some(ULID))
+        .unwrap()
+        .context_id;
+    let plain = store.create_context(0).unwrap().context_id;
+
+    let before = store.create_context(0).unwrap().context_id;
+    assert!(matches!(
+        store.create_context_with_external_id(0, Some(ULID)),
+        Err(StoreError::InvalidInput(_))
+    ));
+    for invalid in ["", "has space", "slash/id", &"x".repeat(129)] {
+        assert!(matches!(
+            store.create_context_with_external_id(0, Some(invalid)),
+            Err(StoreError::InvalidInput(_))
+        ));
+    }
+    // Rejected creations allocate no context.
+    assert_eq!(store.create_context(0).unwrap().context_id, before + 1);
+
+    assert_eq!(store.context_by_external_id(ULID), Some(first));
+    assert_eq!(store.external_id(first), Some(ULID));
+    assert_eq!(store.external_id(plain), None);
+    assert_eq!(store.context_by_external_id(UUID), None);
+
+    drop(store);
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    assert_eq!(store.context_by_external_id(ULID), Some(first));
+    assert!(store
+        .create_context_with_external_id(0, Some(ULID))
+        .is_err());
+    let second = store
+        .create_context_with_external_id(0, Some(UUID))
+        .unwrap()
+        .context_id;
+    assert_eq!(store.external_id(second), Some(UUID));
+}
+
+#[test]
+fn cql_matches_external_ids() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let first = store
+        .create_context_with_external_id(0, Some(ULID))
+        .unwrap()
+        .context_id;
+    let second = store
+        .create_context_with_external_id(0, Some(UUID))
+        .unwrap()
+        .context_id;
+    let plain = store.create_context(0).unwrap().context_id;
+    for ctx in [first, second, plain] {
+        append(&mut store, ctx, b"turn");
+    }
+
+    assert_eq!(
+        search(&mut store, &format!("external_id = \"{ULID}\"")),
+        vec![first]
+    );
+    assert_eq!(
+        search(
+            &mut store,
+            &format!("external_id IN (\"{ULID}\", \"{UUID}\")")
+        ),
+        vec![first, second]
+    );
+    assert_eq!(
+        search(&mut store, &format!("external_id != \"{ULID}\"")),
+        vec![second, plain]
+    );
+
+    drop(store);
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    assert_eq!(
+        search(&mut store, &format!("external_id = \"{UUID}\"")),
+        vec![second]
+    );
+}
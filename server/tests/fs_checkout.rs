@@ -0,0 +1,235 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use std::net::TcpListener;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use cxdb_server::cpu_profile::CpuProfiler;
+use cxdb_server::deadline::Deadline;
+use cxdb_server::error::StoreError;
+use cxdb_server::events::EventBus;
+use cxdb_server::fs_store::checkout::{checkout, Manifest, ManifestKind};
+use cxdb_server::fs_store::{EntryKind, OverlayChange, TreeEntry};
+use cxdb_server::http::{serve_http, HttpConfig, HttpState};
+use cxdb_server::memory::MemoryConfig;
+use cxdb_server::metrics::{Metrics, SessionTracker};
+use cxdb_server::operations::{Operations, OperationsConfig};
+use cxdb_server::presence::{Presence, PresenceConfig};
+use cxdb_server::registry::Registry;
+use cxdb_server::replay::Replays;
+use cxdb_server::shares::{ShareConfig, Shares};
+use cxdb_server::startup::{Readiness, StartupPhase};
+use cxdb_server::store::Store;
+use cxdb_server::subscriptions::Subscriptions;
+use cxdb_server::watches::Watches;
+use tempfile::tempdir;
+
+fn entry(kind: EntryKind, mode: u32, content: &[u8]) -> TreeEntry {
+    TreeEntry {
+        name: String::new(),
+        kind: kind as u8,
+        mode,
+        size: content.len() as u64,
+        hash: blake3::hash(content).as_bytes().to_vec(),
+    }
+}
+
+/// A turn whose snapshot has nested files, a duplicate, a top-level
+/// `manifest` file and a symlink.
+fn snapshot_turn(store: &mut Store) -> u64 {
+    let context_id = store.create_context(0).unwrap().context_id;
+    let turn_id = common::append(store, context_id, b"turn");
+    let main = b"fn main() {}\n";
+    let script = b"#!/bin/sh\necho hi\n";
+    let notes = b"shipping list";
+    let change = |path: &str, entry: TreeEntry| OverlayChange {
+        path: path.into(),
+        entry: Some(entry),
+    };
+    store
+        .attach_fs_overlay(
+            turn_id,
+            None,
+            &[main, script, notes, b"bin/run.sh"],
+            &[
+                change("src/main.rs", entry(EntryKind::File, 0o644, main)),
+                change("src/copy of main.rs", entry(EntryKind::File, 0o644, main)),
+                change("bin/run.sh", entry(EntryKind::File, 0o755, script)),
+                change("manifest", entry(EntryKind::File, 0o600, notes)),
+                change("run", entry(EntryKind::Symlink, 0o777, b"bin/run.sh")),
+            ],
+        )
+        .unwrap();
+    turn_id
+}
+
+fn assert_checked_out(dest: &Path) {
+    assert_eq!(
+        std::fs::read(dest.join("src/main.rs")).unwrap(),
+        b"fn main() {}\n"
+    );
+    assert_eq!(
+        std::fs::read(dest.join("src/copy of main.rs")).unwrap(),
+        b"fn main() {}\n"
+    );
+    assert_eq!(
+        std::fs::read(dest.join("manifest")).unwrap(),
+        b"shipping list"
+    );
+    let mode = std::fs::metadata(dest.join("bin/run.sh"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o755);
+    assert_eq!(
+        std::fs::read_link(dest.join("run")).unwrap(),
+        Path::new("bin/run.sh")
+    );
+}
+
+#[test]
+fn manifests_are_sorted_and_fetch_each_blob_once() {
+    let dir = tempdir().unwrap();
+    let mut store = Store::open(dir.path()).unwrap();
+    let turn_id = snapshot_turn(&mut store);
+
+    let manifest = store.fs_manifest(turn_id, &Deadline::none()).unwrap();
+    let paths: Vec<&str> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        vec![
+            "bin",
+            "bin/run.sh",
+            "manifest",
+            "run",
+            "src",
+            "src/copy of main.rs",
+            "src/main.rs"
+        ]
+    );
+    let run = &manifest.entries[3];
+    assert_eq!(run.kind, ManifestKind::Symlink);
+    assert_eq!(run.target.as_deref(), Some("bin/run.sh"));
+    assert_eq!(manifest.totals.files, 4);
+    assert_eq!(manifest.totals.unique_blobs, 3);
+    assert_eq!(
+        manifest,
+        store.fs_manifest(turn_id, &Deadline::none()).unwrap()
+    );
+
+    let dest = dir.path().join("checkout");
+    let stats = checkout(&manifest, &dest, |blob| {
+        let mut hash = [0u8; 32];
+        hex::decode_to_slice(&blob.hash, &mut hash).unwrap();
+        store.read_fs_blob(turn_id, &hash)
+    })
+    .unwrap();
+    assert_eq!((stats.files, stats.fetched_blobs), (4, 3));
+    assert_checked_out(&dest);
+
+    // The destination must be empty, and content must match its hash.
+    assert!(matches!(
+        checkout(&manifest, &dest, |_| Ok(Vec::new())),
+        Err(StoreError::InvalidInput(_))
+    ));
+    let err = checkout(&manifest, &dir.path().join("tampered"), |blob| {
+        Ok(vec![0; blob.size as usize])
+    })
+    .unwrap_err();
+    assert!(matches!(err, StoreError::Corrupt(_)), "{err:?}");
+}
+
+#[test]
+fn checkout_refuses_paths_outside_the_destination() {
+    let dir = tempdir().unwrap();
+    let mut manifest = Manifest::new(1, &[0; 32], Vec::new());
+    manifest
+        .entries
+        .push(cxdb_server::fs_store::checkout::ManifestEntry {
+            path: "../escape".into(),
+            kind: ManifestKind::Dir,
+            mode: "755".into(),
+            size: 0,
+            hash: hex::encode([0; 32]),
+            target: None,
+        });
+    let err = checkout(&manifest, &dir.path().join("out"), |_| Ok(Vec::new())).unwrap_err();
+    assert!(matches!(err, StoreError::InvalidInput(_)), "{err:?}");
+    assert!(!dir.path().join("escape").exists());
+}
+
+#[test]
+fn manifest_fetch_urls_serve_the_content_over_http() {
+    let dir = tempdir().unwrap();
+    let event_bus = Arc::new(EventBus::new());
+    let readiness = Arc::new(Readiness::new());
+    readiness.set_phase(StartupPhase::Ready);
+    let mut store = Store::open(dir.path()).unwrap();
+    let turn_id = snapshot_turn(&mut store);
+    let state = HttpState {
+        config: HttpConfig::default(),
+        store: Arc::new(Mutex::new(store)),
+        registry: Arc::new(Mutex::new(
+            Registry::open(&dir.path().join("registry")).unwrap(),
+        )),
+        metrics: Arc::new(Metrics::new(dir.path().to_path_buf())),
+        session_tracker: Arc::new(SessionTracker::new()),
+        event_bus: Arc::clone(&event_bus),
+        operations: Operations::start(OperationsConfig::default(), Arc::clone(&event_bus)),
+        watches: Arc::new(Watches::open(&dir.path().join("meta")).unwrap()),
+        readiness,
+        anchors: None,
+        presence: Arc::new(Presence::new(PresenceConfig::default(), event_bus)),
+        access_log: None,
+        thumbnails: None,
+        renderer_assets: None,
+        shares: Arc::new(Shares::open(&dir.path().join("meta"), ShareConfig::default()).unwrap()),
+        subscriptions: Arc::new(Subscriptions::open(&dir.path().join("meta")).unwrap()),
+        oidc: None,
+        memory: MemoryConfig::default(),
+        cpu_profiler: Arc::new(CpuProfiler::default()),
+        summary_hook: None,
+        replays: Arc::new(Replays::new()),
+        registry_sync: None,
+        federation: None,
+    };
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    serve_http(listener, state, runtime.handle()).unwrap();
+
+    let manifest: Manifest = ureq::get(&format!("http://{addr}/v1/turns/{turn_id}/fs/manifest"))
+        .call()
+        .unwrap()
+        .into_json()
+        .unwrap();
+    let notes = manifest
+        .fetch
+        .iter()
+        .find(|b| b.paths == ["manifest"])
+        .unwrap();
+    assert_eq!(
+        notes.url,
+        format!("/v1/turns/{turn_id}/fs/%6Danifest?follow_symlinks=0")
+    );
+
+    let dest = dir.path().join("checkout");
+    checkout(&manifest, &dest, |blob| {
+        let mut content = Vec::new();
+        std::io::Read::read_to_end(
+            &mut ureq::get(&format!("http://{addr}{}", blob.url))
+                .call()
+                .unwrap()
+                .into_reader(),
+            &mut content,
+        )
+        .unwrap();
+        Ok(content)
+    })
+    .unwrap();
+    assert_checked_out(&dest);
+}
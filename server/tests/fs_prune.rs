@@ -0,0 +1,180 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::append;
+use cxdb_server::fs_store::{EntryKind, OverlayChange, TreeEntry};
+use cxdb_server::store::Store;
+use tempfile::tempdir;
+
+fn file(path: &str, content: &[u8]) -> OverlayChange {
+    OverlayChange {
+        path: path.into(),
+        entry: Some(TreeEntry {
+            name: String::new(),
+            kind: EntryKind::File as u8,
+            mode: 0o644,
+            size: content.len() as u64,
+            hash: blake3::hash(content).as_bytes().to_vec(),
+        }),
+    }
+}
+
+#[test]
+fn retried_attach_leaves_an_orphan_root_that_compaction_prunes() {
+    let dir = tempdir().expect("tempdir");
+    let (turn_id, current) = {
+        let mut store = Store::open(dir.path()).expect("open store");
+        let context_id = store.create_context(0).unwrap().context_id;
+        let turn_id = append(&mut store, context_id, b"turn");
+
+        let shared = b"shared readme";
+        store
+            .attach_fs_overlay(
+                turn_id,
+                None,
+                &[shared, b"first try"],
+                &[file("README", shared), file("notes.txt", b"first try")],
+            )
+            .unwrap();
+        let current = store
+            .attach_fs_overlay(
+                turn_id,
+                None,
+                &[shared, b"second"],
+                &[file("README", shared), file("notes.txt", b"second")],
+            )
+            .unwrap()
+            .root_hash;
+
+        // The old root tree and its own file only; the shared file is live.
+        let reach = store.fs_reachability();
+        assert_eq!((reach.live_roots, reach.orphan_roots), (1, 1));
+        assert_eq!(reach.reachable_blobs, 3);
+        assert_eq!(reach.unreachable_blobs, 2);
+        assert!(reach.unreachable_bytes > b"first try".len() as u64);
+
+        let plan = store.compaction_plan().unwrap();
+        assert_eq!(plan.fs, reach);
+        let roots_idx = plan
+            .files
+            .iter()
+            .find(|f| f.file == "fs/roots.idx")
+            .unwrap();
+        assert_eq!((roots_idx.bytes_before, roots_idx.bytes_after), (88, 44));
+
+        let dry = store.prune_fs_roots(true).unwrap();
+        assert_eq!(dry.orphan_roots, 1);
+        assert!(store.pruned_fs_roots().is_empty());
+
+        let pruned = store.prune_fs_roots(false).unwrap();
+        assert_eq!(pruned, reach);
+        assert_eq!(store.fs_reachability().orphan_roots, 0);
+        (turn_id, current)
+    };
+
+    let roots_idx = std::fs::metadata(dir.path().join("fs/roots.idx")).unwrap();
+    assert_eq!(roots_idx.len(), 44);
+
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    assert_eq!(store.get_fs_root_direct(turn_id), Some(current));
+    assert_eq!(store.fs_reachability().orphan_roots, 0);
+    let audit = store.pruned_fs_roots();
+    assert_eq!(audit.len(), 1);
+    assert_eq!(audit[0].turn_id, turn_id);
+    assert_ne!(audit[0].root_hash, hex::encode(current));
+}
+
+#[test]
+fn reattaching_the_original_root_only_orphans_the_replacement() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let context_id = store.create_context(0).unwrap().context_id;
+    let turn_id = append(&mut store, context_id, b"turn");
+    let other_turn = append(&mut store, context_id, b"turn");
+
+    let a = store
+        .attach_fs_overlay(turn_id, None, &[b"a"], &[file("f", b"a")])
+        .unwrap()
+        .root_hash;
+    let b = store
+        .attach_fs_overlay(turn_id, None, &[b"b"], &[file("f", b"b")])
+        .unwrap()
+        .root_hash;
+    store.attach_fs(turn_id, a).unwrap();
+    // A root replaced on one turn is still live while another turn holds it.
+    store.attach_fs(other_turn, b).unwrap();
+    store.attach_fs(other_turn, a).unwrap();
+
+    let reach = store.fs_reachability();
+    assert_eq!((reach.live_roots, reach.orphan_roots), (2, 2));
+    assert_eq!(reach.unreachable_blobs, 2);
+
+    store.prune_fs_roots(false).unwrap();
+    let mut audited: Vec<(u64, String)> = store
+        .pruned_fs_roots()
+        .iter()
+        .map(|p| (p.turn_id, p.root_hash.clone()))
+        .collect();
+    audited.sort();
+    assert_eq!(
+        audited,
+        vec![(turn_id, hex::encode(b)), (other_turn, hex::encode(b))]
+    );
+    assert_eq!(store.get_fs_root_direct(turn_id), Some(a));
+    assert_eq!(store.get_fs_root_direct(other_turn), Some(a));
+}
+
+#[test]
+fn superseded_roots_of_held_contexts_survive_compaction() {
+    let dir = tempdir().expect("tempdir");
+    let (held_ctx, held_turn, free_turn, first) = {
+        let mut store = Store::open(dir.path()).expect("open store");
+        let held_ctx = store.create_context(0).unwrap().context_id;
+        let held_turn = append(&mut store, held_ctx, b"held");
+        let free_ctx = store.create_context(0).unwrap().context_id;
+        let free_turn = append(&mut store, free_ctx, b"free");
+
+        let first = store
+            .attach_fs_overlay(held_turn, None, &[b"evidence"], &[file("f", b"evidence")])
+            .unwrap()
+            .root_hash;
+        store
+            .attach_fs_overlay(held_turn, None, &[b"later"], &[file("f", b"later")])
+            .unwrap();
+        store
+            .attach_fs_overlay(free_turn, None, &[b"x"], &[file("f", b"x")])
+            .unwrap();
+        store
+            .attach_fs_overlay(free_turn, None, &[b"y"], &[file("f", b"y")])
+            .unwrap();
+        store.place_hold(held_ctx, "litigation", "legal").unwrap();
+
+        let reach = store.fs_reachability();
+        assert_eq!((reach.orphan_roots, reach.held_roots), (1, 1));
+        store.prune_fs_roots(false).unwrap();
+        // The second collection sweeps blobs the first found young.
+        store.collect_blobs(false).unwrap();
+        store.collect_blobs(false).unwrap();
+        let audit = store.pruned_fs_roots();
+        assert_eq!(audit.len(), 1);
+        assert_eq!(audit[0].turn_id, free_turn);
+        (held_ctx, held_turn, free_turn, first)
+    };
+
+    // The held record reloads as superseded, and its tree is still stored.
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    let reach = store.fs_reachability();
+    assert_eq!((reach.orphan_roots, reach.held_roots), (0, 1));
+    assert!(store.get_blob(&first).is_ok());
+    assert!(store.get_blob(blake3::hash(b"evidence").as_bytes()).is_ok());
+    assert!(store.get_blob(blake3::hash(b"x").as_bytes()).is_err());
+    assert_ne!(store.get_fs_root_direct(held_turn), Some(first));
+    assert!(store.get_fs_root_direct(free_turn).is_some());
+
+    // Once the hold is released the root is an ordinary orphan.
+    store.release_hold(held_ctx, "closed", "legal").unwrap();
+    let reach = store.fs_reachability();
+    assert_eq!((reach.orphan_roots, reach.held_roots), (1, 0));
+}
@@ -0,0 +1,118 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::append;
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use cxdb_server::store::Store;
+use cxdb_server::turn_store::{CommitConfig, CommitPipeline};
+use tempfile::tempdir;
+
+#[test]
+fn concurrent_appends_share_a_sync() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    store
+        .enable_group_commit(CommitConfig {
+            enabled: true,
+            window: Duration::from_millis(50),
+        })
+        .unwrap();
+    let store = Arc::new(Mutex::new(store));
+
+    let writers = 8;
+    let barrier = Arc::new(Barrier::new(writers));
+    let handles: Vec<_> = (0..writers)
+        .map(|i| {
+            let store = Arc::clone(&store);
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                let mut guard = store.lock().unwrap();
+                let context_id = guard.create_context(0).unwrap().context_id;
+                let turn_id = append(&mut guard, context_id, format!("turn {i}").as_bytes());
+                let commit = guard.commit_pipeline().expect("group commit enabled");
+                drop(guard);
+                commit.commit().unwrap();
+                (context_id, turn_id)
+            })
+        })
+        .collect();
+    let appended: Vec<(u64, u64)> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+    let stats = store.lock().unwrap().commit_stats().unwrap();
+    assert_eq!(stats.commits_total, writers as u64);
+    assert!(
+        stats.batches_total < writers as u64,
+        "{} batches for {writers} commits",
+        stats.batches_total
+    );
+    assert!(stats.mean_batch_size > 1.0);
+    assert_eq!(stats.sync_ms.lifetime.count, stats.batches_total);
+    assert_eq!(stats.wait_ms.lifetime.count, writers as u64);
+    for file in [
+        "turns.log",
+        "blobs.pack",
+        "keys/turns.log",
+        "fs/roots.idx",
+        "meta/overrides.jsonl",
+        "meta/turn_tokens.jsonl",
+    ] {
+        assert!(stats.tracked_files.contains(&file), "{file} is not synced");
+    }
+
+    drop(store);
+    let store = Store::open(dir.path()).expect("reopen store");
+    for (context_id, turn_id) in appended {
+        assert_eq!(store.get_head(context_id).unwrap().head_turn_id, turn_id);
+    }
+}
+
+#[test]
+fn group_commit_is_off_by_default() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    store.enable_group_commit(CommitConfig::default()).unwrap();
+    assert!(store.commit_pipeline().is_none());
+    assert!(store.commit_stats().is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn a_failed_sync_fails_its_batch_and_every_later_commit() {
+    use std::os::fd::OwnedFd;
+    use std::os::unix::net::UnixStream;
+
+    let pipeline = Arc::new(CommitPipeline::new(Duration::from_millis(50)));
+    // Syncing a socket fails with EINVAL.
+    let (socket, _peer) = UnixStream::pair().unwrap();
+    let file = std::fs::File::from(OwnedFd::from(socket));
+    pipeline.track("socket", &file).unwrap();
+
+    let writers = 4;
+    let barrier = Arc::new(Barrier::new(writers));
+    let handles: Vec<_> = (0..writers)
+        .map(|_| {
+            let pipeline = Arc::clone(&pipeline);
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                pipeline.commit()
+            })
+        })
+        .collect();
+    for handle in handles {
+        assert!(handle.join().unwrap().is_err());
+    }
+
+    // No later sync can vouch for the lost writes.
+    assert!(pipeline.commit().is_err());
+    let stats = pipeline.stats();
+    assert_eq!(stats.sync_failures_total, 1);
+    assert_eq!(stats.commits_total, 0);
+    assert!(stats.failed.is_some());
+}
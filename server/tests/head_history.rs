@@ -0,0 +1,119 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::append_child;
+use cxdb_server::error::StoreError;
+use cxdb_server::invariants::VerifyScope;
+use cxdb_server::store::Store;
+use cxdb_server::turn_store::{HeadCause, HeadMove};
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn moves(history: &[HeadMove]) -> Vec<(u64, u32, HeadCause)> {
+    history
+        .iter()
+        .map(|m| (m.turn_id, m.depth, m.cause))
+        .collect()
+}
+
+#[test]
+fn head_history_records_each_move_and_its_cause() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let context_id = store.create_context(0).unwrap().context_id;
+    let first = append_child(&mut store, context_id, 0, b"turn");
+    let second = append_child(&mut store, context_id, 0, b"turn");
+    // Rewind: continue from the first turn, leaving the second on a branch.
+    let rewound = append_child(&mut store, context_id, first, b"turn");
+    let fork_id = store.fork_context(second).unwrap().context_id;
+    let on_fork = append_child(&mut store, fork_id, 0, b"turn");
+
+    let expected = vec![
+        (0, 0, HeadCause::Created),
+        (first, 0, HeadCause::Appended),
+        (second, 1, HeadCause::Appended),
+        (rewound, 1, HeadCause::Branched),
+    ];
+    let history = store.head_history(context_id).unwrap();
+    assert_eq!(moves(&history), expected);
+    assert!(history
+        .windows(2)
+        .all(|w| w[0].at_unix_ms <= w[1].at_unix_ms));
+    let fork_expected = vec![
+        (second, 1, HeadCause::Forked),
+        (on_fork, 2, HeadCause::Appended),
+    ];
+    assert_eq!(moves(&store.head_history(fork_id).unwrap()), fork_expected);
+    assert!(matches!(
+        store.head_history(999),
+        Err(StoreError::NotFound(_))
+    ));
+
+    // History is rebuilt from heads.tbl on open.
+    drop(store);
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    assert_eq!(moves(&store.head_history(context_id).unwrap()), expected);
+    assert_eq!(moves(&store.head_history(fork_id).unwrap()), fork_expected);
+    store.verify_invariants(VerifyScope::Full).assert_ok();
+}
+
+#[test]
+fn contexts_can_be_listed_as_of_an_earlier_instant() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let early = store.create_context(0).unwrap().context_id;
+    let first = append_child(&mut store, early, 0, b"turn");
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let second = append_child(&mut store, early, 0, b"turn");
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let late = store.create_context(0).unwrap().context_id;
+
+    let history = store.head_history(early).unwrap();
+    let at_first = history[1].at_unix_ms;
+    let listed = store.list_contexts_as_of(at_first, 10);
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].head.context_id, early);
+    assert_eq!(
+        (listed[0].head.head_turn_id, listed[0].head.head_depth),
+        (first, 0)
+    );
+    // Creation time is when the context was created; the last move is
+    // when its head got to where it was.
+    assert_eq!(listed[0].head.created_at_unix_ms, history[0].at_unix_ms);
+    assert_eq!(listed[0].moved_at_unix_ms, at_first);
+
+    let now = history[2]
+        .at_unix_ms
+        .max(store.head_history(late).unwrap()[0].at_unix_ms);
+    let listed = store.list_contexts_as_of(now, 10);
+    let ids: Vec<u64> = listed.iter().map(|e| e.head.context_id).collect();
+    assert_eq!(ids, vec![late, early]);
+    assert_eq!(listed[1].head.head_turn_id, second);
+    assert_eq!(listed[1].head.created_at_unix_ms, history[0].at_unix_ms);
+    assert_eq!(listed[1].moved_at_unix_ms, history[2].at_unix_ms);
+    assert_eq!(store.list_contexts_as_of(now, 1).len(), 1);
+    assert!(store
+        .list_contexts_as_of(history[0].at_unix_ms - 1, 10)
+        .is_empty());
+}
+
+#[test]
+fn contexts_tombstoned_later_are_listed_as_of_before_they_expired() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let short = store
+        .create_context_with_ttl(0, None, Some(Duration::from_secs(60)))
+        .unwrap();
+    let turn = append_child(&mut store, short.context_id, 0, b"turn");
+    let expires_at = store.expiry(short.context_id).unwrap().expires_at_unix_ms;
+    let moved_at = store.head_history(short.context_id).unwrap()[1].at_unix_ms;
+    store.expire_due(expires_at).unwrap();
+
+    let listed = store.list_contexts_as_of(moved_at, 10);
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].head.context_id, short.context_id);
+    assert_eq!(listed[0].head.head_turn_id, turn);
+    assert!(store.list_contexts_as_of(expires_at, 10).is_empty());
+}
@@ -0,0 +1,828 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use cxdb_server::config::Config;
+use cxdb_server::cpu_profile::CpuProfiler;
+use cxdb_server::error::StoreError;
+use cxdb_server::events::EventBus;
+use cxdb_server::federation::{Federation, FederationConfig, FederationPeer};
+use cxdb_server::fs_store::{EntryKind, OverlayChange, TreeEntry};
+use cxdb_server::http::{serve_http, serve_http_on, start_http, HttpConfig, HttpState};
+use cxdb_server::memory::MemoryConfig;
+use cxdb_server::metadata_overrides::MetadataPatch;
+use cxdb_server::metrics::{Metrics, SessionTracker};
+use cxdb_server::operations::{Operations, OperationsConfig};
+use cxdb_server::presence::{Presence, PresenceConfig};
+use cxdb_server::registry::sync::{RegistrySync, RegistrySyncConfig};
+use cxdb_server::registry::Registry;
+use cxdb_server::replay::Replays;
+use cxdb_server::shares::{ShareConfig, Shares};
+use cxdb_server::startup::{Readiness, StartupPhase};
+use cxdb_server::store::Store;
+use cxdb_server::subscriptions::Subscriptions;
+use cxdb_server::watches::Watches;
+use serde_json::{json, Value};
+use tempfile::tempdir;
+
+fn http_state(dir: &Path, config: HttpConfig) -> HttpState {
+    let event_bus = Arc::new(EventBus::new());
+    let readiness = Arc::new(Readiness::new());
+    readiness.set_phase(StartupPhase::Ready);
+    HttpState {
+        config,
+        store: Arc::new(Mutex::new(Store::open(dir).unwrap())),
+        registry: Arc::new(Mutex::new(Registry::open(&dir.join("registry")).unwrap())),
+        metrics: Arc::new(Metrics::new(dir.to_path_buf())),
+        session_tracker: Arc::new(SessionTracker::new()),
+        event_bus: Arc::clone(&event_bus),
+        operations: Operations::start(OperationsConfig::default(), Arc::clone(&event_bus)),
+        watches: Arc::new(Watches::open(&dir.join("meta")).unwrap()),
+        readiness,
+        anchors: None,
+        presence: Arc::new(Presence::new(PresenceConfig::default(), event_bus)),
+        access_log: None,
+        thumbnails: None,
+        renderer_assets: None,
+        shares: Arc::new(Shares::open(&dir.join("meta"), ShareConfig::default()).unwrap()),
+        subscriptions: Arc::new(Subscriptions::open(&dir.join("meta")).unwrap()),
+        oidc: None,
+        memory: MemoryConfig::default(),
+        cpu_profiler: Arc::new(CpuProfiler::default()),
+        summary_hook: None,
+        replays: Arc::new(Replays::new()),
+        registry_sync: None,
+        federation: None,
+    }
+}
+
+fn serve(runtime: &tokio::runtime::Runtime, dir: &Path, config: HttpConfig) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    serve_http(listener, http_state(dir, config), runtime.handle()).unwrap();
+    addr
+}
+
+/// Open `GET /v1/events` and wait for its `connected` event.
+fn open_event_stream(addr: SocketAddr) -> BufReader<TcpStream> {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    stream
+        .write_all(b"GET /v1/events HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        head.push_str(&line);
+        if line.contains("event: connected") {
+            break;
+        }
+    }
+    assert!(head.starts_with("HTTP/1.1 200"), "{head}");
+    assert!(head.to_ascii_lowercase().contains("text/event-stream"));
+    reader
+}
+
+#[test]
+fn serves_buffered_and_posted_requests() {
+    let dir = tempdir().unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = serve(&runtime, dir.path(), HttpConfig::default());
+
+    let health = ureq::get(&format!("http://{addr}/healthz")).call().unwrap();
+    assert_eq!(health.content_type(), "text/plain");
+    assert_eq!(health.into_string().unwrap(), "ok");
+
+    let batch: Value = ureq::post(&format!("http://{addr}/v1/contexts/batch-get"))
+        .send_json(json!({ "context_ids": [42] }))
+        .unwrap()
+        .into_json()
+        .unwrap();
+    assert_eq!(batch["contexts"][0]["error"]["code"], 404);
+
+    match ureq::get(&format!("http://{addr}/v1/nope")).call() {
+        Err(ureq::Error::Status(404, response)) => {
+            let body: Value = response.into_json().unwrap();
+            assert_eq!(body["error"]["code"], 404);
+        }
+        other => panic!("expected 404, got {other:?}"),
+    }
+}
+
+#[test]
+fn reports_memory_and_refuses_unconfigured_profile_dumps() {
+    let dir = tempdir().unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = serve(&runtime, dir.path(), HttpConfig::default());
+
+    let memory: Value = ureq::get(&format!("http://{addr}/v1/admin/memory"))
+        .call()
+        .unwrap()
+        .into_json()
+        .unwrap();
+    // The test harness does not install the counting allocator.
+    assert!(memory["heap"].is_null());
+    assert_eq!(memory["profiling"], false);
+    for subsystem in [
+        "indexes_bytes",
+        "metadata_cache_bytes",
+        "payload_cache_bytes",
+        "projection_cache_bytes",
+        "event_queues_bytes",
+    ] {
+        assert!(memory["subsystems"][subsystem].is_u64(), "{subsystem}");
+    }
+
+    match ureq::post(&format!("http://{addr}/v1/admin/memory/profile")).call() {
+        Err(ureq::Error::Status(404, _)) => {}
+        other => panic!("expected 404, got {other:?}"),
+    }
+}
+
+#[test]
+fn validates_cpu_profile_requests() {
+    let dir = tempdir().unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = serve(&runtime, dir.path(), HttpConfig::default());
+
+    for query in ["seconds=0", "seconds=121", "seconds=ten", "format=perf"] {
+        match ureq::post(&format!("http://{addr}/v1/admin/profile?{query}")).call() {
+            Err(ureq::Error::Status(422, _)) => {}
+            other => panic!("{query}: expected 422, got {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn rolls_contexts_over_and_links_them_in_the_tree() {
+    let dir = tempdir().unwrap();
+    let context_id = {
+        let mut store = Store::open(dir.path()).unwrap();
+        let context_id = store.create_context(0).unwrap().context_id;
+        common::append_typed(
+            &mut store,
+            context_id,
+            "com.example.Turn",
+            b"\x81\x01\xa2hi",
+        );
+        context_id
+    };
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = serve(&runtime, dir.path(), HttpConfig::default());
+    let rollover_url = format!("http://{addr}/v1/contexts/{context_id}/rollover");
+
+    // Without a summary hook the caller has to write the digest.
+    match ureq::post(&rollover_url).call() {
+        Err(ureq::Error::Status(422, _)) => {}
+        other => panic!("expected 422, got {other:?}"),
+    }
+    let response = ureq::post(&rollover_url)
+        .send_json(json!({ "digest": "Said hi.", "generator": "agent-v2" }))
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let rollover: Value = response.into_json().unwrap();
+    assert_eq!(rollover["context_id"], context_id.to_string());
+    assert_eq!(rollover["generator"], "agent-v2");
+    let new_id = rollover["rolled_over_to"].as_str().unwrap().to_string();
+
+    match ureq::post(&rollover_url).send_json(json!({ "digest": "again" })) {
+        Err(ureq::Error::Status(422, _)) => {}
+        other => panic!("expected 422, got {other:?}"),
+    }
+    let old: Value = ureq::get(&format!("http://{addr}/v1/contexts/{context_id}"))
+        .call()
+        .unwrap()
+        .into_json()
+        .unwrap();
+    assert_eq!(old["rolled_over_to"], new_id.as_str());
+    let new: Value = ureq::get(&format!("http://{addr}/v1/contexts/{new_id}"))
+        .call()
+        .unwrap()
+        .into_json()
+        .unwrap();
+    assert_eq!(new["rolled_over_from"], context_id.to_string());
+    assert_eq!(new["head_depth"], 0);
+
+    let tree: Value = ureq::get(&format!("http://{addr}/v1/contexts/{new_id}/tree"))
+        .call()
+        .unwrap()
+        .into_json()
+        .unwrap();
+    assert_eq!(tree["truncated"], false);
+    assert_eq!(tree["root"]["context_id"], context_id.to_string());
+    let child = &tree["root"]["children"][0];
+    assert_eq!(child["context_id"], new_id.as_str());
+    assert_eq!(child["spawn_reason"], "rollover");
+    assert_eq!(child["rolled_over_from"], context_id.to_string());
+}
+
+#[test]
+fn refuses_turns_of_expired_contexts() {
+    let dir = tempdir().unwrap();
+    let (first, turn_id) = {
+        let mut store = Store::open(dir.path()).unwrap();
+        let context_id = store
+            .create_context_with_ttl(0, None, Some(Duration::from_secs(60)))
+            .unwrap()
+            .context_id;
+        let first = common::append(&mut store, context_id, b"first");
+        let turn_id = common::append(&mut store, context_id, b"second");
+        let expires_at = store.expiry(context_id).unwrap().expires_at_unix_ms;
+        store.expire_due(expires_at).unwrap();
+        (first, turn_id)
+    };
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = serve(&runtime, dir.path(), HttpConfig::default());
+
+    for path in [
+        format!("/v1/turns/{turn_id}"),
+        format!("/v1/turns/{turn_id}/ancestry"),
+        format!("/v1/turns/{turn_id}/attachments"),
+        format!("/v1/turns/{first}/diff/{turn_id}"),
+        format!("/v1/turns/{turn_id}/fs"),
+    ] {
+        match ureq::get(&format!("http://{addr}{path}")).call() {
+            Err(ureq::Error::Status(410, _)) => {}
+            other => panic!("expected 410 for {path}, got {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn replays_a_context_as_a_stream_of_turns() {
+    let dir = tempdir().unwrap();
+    let (context_id, turns) = {
+        let mut store = Store::open(dir.path()).unwrap();
+        let context_id = store.create_context(0).unwrap().context_id;
+        let mut turns = Vec::new();
+        for payload in [&b"\x81\x01\xa2hi"[..], &b"\x81\x01\xa3bye"[..]] {
+            turns.push(common::append_typed(
+                &mut store,
+                context_id,
+                "com.example.Turn",
+                payload,
+            ));
+        }
+        store
+            .attach_fs_overlay(
+                turns[1],
+                None,
+                &[b"notes"],
+                &[OverlayChange {
+                    path: "notes.txt".into(),
+                    entry: Some(TreeEntry {
+                        name: String::new(),
+                        kind: EntryKind::File as u8,
+                        mode: 0o644,
+                        size: 5,
+                        hash: blake3::hash(b"notes").as_bytes().to_vec(),
+                    }),
+                }],
+            )
+            .unwrap();
+        (context_id, turns)
+    };
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = serve(&runtime, dir.path(), HttpConfig::default());
+    let replay_url = format!("http://{addr}/v1/contexts/{context_id}/replay");
+
+    let response = ureq::get(&format!("{replay_url}?speed=max&view=raw"))
+        .call()
+        .unwrap();
+    assert_eq!(response.content_type(), "text/event-stream");
+    let body = response.into_string().unwrap();
+    let events: Vec<&str> = body
+        .lines()
+        .filter_map(|line| line.strip_prefix("event: "))
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            "replay_started",
+            "turn",
+            "turn",
+            "fs_attached",
+            "replay_completed"
+        ]
+    );
+    assert!(body.contains(&format!("\"turn_id\":\"{}\"", turns[1])));
+    assert!(body.contains("\"delivered\":2"));
+
+    // Starting from a later turn skips the ones before it.
+    let body = ureq::get(&format!(
+        "{replay_url}?speed=max&view=raw&from_turn={}",
+        turns[1]
+    ))
+    .call()
+    .unwrap()
+    .into_string()
+    .unwrap();
+    assert_eq!(body.matches("event: turn\n").count(), 1);
+
+    // Finished replays can no longer be controlled.
+    match ureq::post(&format!("http://{addr}/v1/replays/1/pause")).call() {
+        Err(ureq::Error::Status(404, _)) => {}
+        other => panic!("expected 404, got {other:?}"),
+    }
+    for query in ["speed=fast", "from_turn=999"] {
+        match ureq::get(&format!("{replay_url}?{query}")).call() {
+            Err(ureq::Error::Status(422, _)) => {}
+            other => panic!("{query}: expected 422, got {other:?}"),
+        }
+    }
+}
+
+fn bundle(bundle_id: &str, types: &[&str]) -> String {
+    let types: Vec<String> = types
+        .iter()
+        .map(|type_id| {
+            format!(
+                r#""{type_id}": {{ "versions": {{ "1": {{ "fields": {{ "1": {{ "name": "text", "type": "string" }} }} }} }} }}"#
+            )
+        })
+        .collect();
+    format!(
+        r#"{{ "registry_version": 1, "bundle_id": "{bundle_id}", "types": {{ {} }}, "enums": {{}} }}"#,
+        types.join(", ")
+    )
+}
+
+fn put_bundle(addr: SocketAddr, bundle_id: &str, types: &[&str]) {
+    ureq::put(&format!("http://{addr}/v1/registry/bundles/{bundle_id}"))
+        .set("Content-Type", "application/json")
+        .send_string(&bundle(bundle_id, types))
+        .unwrap();
+}
+
+fn bundle_ids(report: &Value, outcome: &str) -> Vec<String> {
+    report[outcome]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|b| b["bundle_id"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn pulls_and_pushes_registry_bundles_between_servers() {
+    let staging_dir = tempdir().unwrap();
+    let prod_dir = tempdir().unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let staging = serve(&runtime, staging_dir.path(), HttpConfig::default());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let prod = listener.local_addr().unwrap();
+    let mut state = http_state(prod_dir.path(), HttpConfig::default());
+    state.registry_sync = Some(Arc::new(RegistrySync::new(RegistrySyncConfig {
+        allowed_remotes: vec![format!("http://{staging}")],
+        token: None,
+    })));
+    serve_http(listener, state, runtime.handle()).unwrap();
+    let sync = |direction: &str, query: &[(&str, &str)]| -> Value {
+        let mut request = ureq::post(&format!("http://{prod}/v1/admin/registry/{direction}"));
+        for (name, value) in query {
+            request = request.query(name, value);
+        }
+        request.call().unwrap().into_json().unwrap()
+    };
+    let remote = format!("http://{staging}/");
+
+    put_bundle(staging, "app.v1", &["app.Message"]);
+    put_bundle(staging, "app.shared", &["app.Shared"]);
+    put_bundle(prod, "app.shared", &["app.Shared", "app.Extra"]);
+
+    // A dry run transfers nothing.
+    let report = sync("pull", &[("from", &remote), ("dry_run", "true")]);
+    assert_eq!(report["dry_run"], true);
+    assert_eq!(bundle_ids(&report, "applied"), vec!["app.v1"]);
+    match ureq::get(&format!("http://{prod}/v1/registry/bundles/app.v1")).call() {
+        Err(ureq::Error::Status(404, _)) => {}
+        other => panic!("expected 404, got {other:?}"),
+    }
+
+    let report = sync("pull", &[("from", &remote)]);
+    assert_eq!(report["direction"], "pull");
+    assert_eq!(report["remote"], format!("http://{staging}"));
+    assert_eq!(bundle_ids(&report, "applied"), vec!["app.v1"]);
+    assert_eq!(bundle_ids(&report, "conflicts"), vec!["app.shared"]);
+    let pulled = ureq::get(&format!(
+        "http://{prod}/v1/registry/types/app.Message/versions/1"
+    ))
+    .call()
+    .unwrap();
+    assert_eq!(pulled.status(), 200);
+
+    // Pulled bundles are identical afterwards.
+    let report = sync("pull", &[("from", &remote)]);
+    assert!(bundle_ids(&report, "applied").is_empty());
+    assert_eq!(bundle_ids(&report, "skipped"), vec!["app.v1"]);
+
+    // Pushing in merge mode adds prod's extra type to staging's bundle.
+    put_bundle(prod, "app.prod", &["app.Prod"]);
+    let report = sync("push", &[("to", &remote), ("mode", "merge")]);
+    assert_eq!(report["mode"], "merge");
+    assert_eq!(
+        bundle_ids(&report, "applied"),
+        vec!["app.prod", "app.shared"]
+    );
+    assert_eq!(bundle_ids(&report, "skipped"), vec!["app.v1"]);
+    for type_id in ["app.Prod", "app.Extra"] {
+        let response = ureq::get(&format!(
+            "http://{staging}/v1/registry/types/{type_id}/versions/1"
+        ))
+        .call()
+        .unwrap();
+        assert_eq!(response.status(), 200, "{type_id}");
+    }
+
+    // Only configured remotes are reachable.
+    match ureq::post(&format!("http://{prod}/v1/admin/registry/pull"))
+        .query("from", "http://127.0.0.1:1")
+        .call()
+    {
+        Err(ureq::Error::Status(403, _)) => {}
+        other => panic!("expected 403, got {other:?}"),
+    }
+    match ureq::post(&format!("http://{staging}/v1/admin/registry/pull"))
+        .query("from", &format!("http://{prod}"))
+        .call()
+    {
+        Err(ureq::Error::Status(404, _)) => {}
+        other => panic!("expected 404, got {other:?}"),
+    }
+}
+
+/// A store with one context tagged `tag` holding one turn.
+fn tagged_store(dir: &Path, tag: &str) -> u64 {
+    let mut store = Store::open(dir).unwrap();
+    let context_id = store.create_context(0).unwrap().context_id;
+    let patch = MetadataPatch {
+        client_tag: Some(tag.to_string()),
+        ..Default::default()
+    };
+    store.apply_metadata_patch(context_id, &patch, &[]).unwrap();
+    common::append_typed(
+        &mut store,
+        context_id,
+        "com.example.Turn",
+        b"\x81\x01\xa2hi",
+    );
+    context_id
+}
+
+#[test]
+fn federates_search_and_proxies_turns_to_peers() {
+    let eu_dir = tempdir().unwrap();
+    let us_dir = tempdir().unwrap();
+    let eu_context = tagged_store(eu_dir.path(), "billing");
+    let us_context = tagged_store(us_dir.path(), "billing");
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let eu = serve(&runtime, eu_dir.path(), HttpConfig::default());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let us = listener.local_addr().unwrap();
+    let mut state = http_state(us_dir.path(), HttpConfig::default());
+    let peers = vec![
+        FederationPeer::new("eu", &format!("http://{eu}")).unwrap(),
+        FederationPeer::new("ap", "http://127.0.0.1:1").unwrap(),
+    ];
+    state.federation = Some(Arc::new(Federation::new(FederationConfig::new(
+        "us", peers,
+    ))));
+    serve_http(listener, state, runtime.handle()).unwrap();
+
+    let search: Value = ureq::get(&format!("http://{us}/v1/federated/contexts/search"))
+        .query("q", "tag = \"billing\"")
+        .call()
+        .unwrap()
+        .into_json()
+        .unwrap();
+    let mut found: Vec<(String, String)> = search["contexts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| {
+            (
+                c["origin"].as_str().unwrap().to_string(),
+                c["context_id"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    found.sort();
+    assert_eq!(
+        found,
+        vec![
+            ("eu".to_string(), eu_context.to_string()),
+            ("us".to_string(), us_context.to_string()),
+        ]
+    );
+    assert_eq!(search["total_count"], 2);
+    // The unreachable peer is reported, not fatal.
+    assert_eq!(search["partial"], true);
+    let origins = search["origins"].as_array().unwrap();
+    assert_eq!(origins.len(), 3);
+    assert_eq!(origins[2]["origin"], "ap");
+    assert_eq!(origins[2]["ok"], false);
+    assert!(origins[2]["error"].is_string());
+
+    // Turns are fetched from the owning peer and cached.
+    let turns_url = format!("http://{us}/v1/federated/eu/contexts/{eu_context}/turns?view=raw");
+    let first = ureq::get(&turns_url).call().unwrap();
+    assert_eq!(first.header("X-Cxdb-Origin"), Some("eu"));
+    assert_eq!(first.header("X-Cxdb-Cache"), Some("miss"));
+    let turns: Value = first.into_json().unwrap();
+    assert_eq!(turns["turns"].as_array().unwrap().len(), 1);
+    let second = ureq::get(&turns_url).call().unwrap();
+    assert_eq!(second.header("X-Cxdb-Cache"), Some("hit"));
+
+    // Errors of the peer are passed on.
+    match ureq::get(&format!("http://{us}/v1/federated/eu/contexts/999/turns")).call() {
+        Err(ureq::Error::Status(404, _)) => {}
+        other => panic!("expected 404, got {other:?}"),
+    }
+    match ureq::get(&format!("http://{us}/v1/federated/nowhere/contexts/1")).call() {
+        Err(ureq::Error::Status(404, _)) => {}
+        other => panic!("expected 404, got {other:?}"),
+    }
+    // This server's own contexts redirect to the local endpoint.
+    let local = ureq::AgentBuilder::new()
+        .redirects(0)
+        .build()
+        .get(&format!(
+            "http://{us}/v1/federated/us/contexts/{us_context}"
+        ))
+        .call()
+        .unwrap();
+    assert_eq!(local.status(), 307);
+    assert_eq!(
+        local.header("Location"),
+        Some(format!("/v1/contexts/{us_context}").as_str())
+    );
+
+    // Servers without peers have no federated routes.
+    match ureq::get(&format!("http://{eu}/v1/federated/contexts/search?q=x")).call() {
+        Err(ureq::Error::Status(404, _)) => {}
+        other => panic!("expected 404, got {other:?}"),
+    }
+}
+
+#[test]
+fn federation_serves_peers_only_residencies_they_may_hold() {
+    let eu_dir = tempdir().unwrap();
+    let plain = tagged_store(eu_dir.path(), "billing");
+    let resident = {
+        let mut store = Store::open(eu_dir.path()).unwrap();
+        let context_id = store.create_context(0).unwrap().context_id;
+        let patch = MetadataPatch {
+            client_tag: Some("billing".to_string()),
+            ..Default::default()
+        };
+        store
+            .apply_metadata_patch(context_id, &patch, &["residency:eu".to_string()])
+            .unwrap();
+        common::append_typed(
+            &mut store,
+            context_id,
+            "com.example.Turn",
+            b"\x81\x01\xa2hi",
+        );
+        context_id
+    };
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let eu = serve(&runtime, eu_dir.path(), HttpConfig::default());
+
+    let federate = |name: &str, residencies: &[&str]| {
+        let dir = tempdir().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut state = http_state(dir.path(), HttpConfig::default());
+        let mut config = FederationConfig::new(
+            name,
+            vec![FederationPeer::new("eu", &format!("http://{eu}")).unwrap()],
+        );
+        config.residencies = residencies.iter().map(|r| r.to_string()).collect();
+        state.federation = Some(Arc::new(Federation::new(config)));
+        serve_http(listener, state, runtime.handle()).unwrap();
+        (dir, addr)
+    };
+    let eu_ids = |addr: SocketAddr| -> Vec<String> {
+        let search: Value = ureq::get(&format!("http://{addr}/v1/federated/contexts/search"))
+            .query("q", "tag = \"billing\"")
+            .call()
+            .unwrap()
+            .into_json()
+            .unwrap();
+        let mut ids: Vec<String> = search["contexts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|c| c["origin"] == "eu")
+            .map(|c| c["context_id"].as_str().unwrap().to_string())
+            .collect();
+        ids.sort();
+        ids
+    };
+
+    // A server that may not hold eu data neither finds nor reads it.
+    let (_us_dir, us) = federate("us", &[]);
+    assert_eq!(eu_ids(us), vec![plain.to_string()]);
+    match ureq::get(&format!(
+        "http://{us}/v1/federated/eu/contexts/{resident}/turns"
+    ))
+    .call()
+    {
+        Err(ureq::Error::Status(403, _)) => {}
+        other => panic!("expected 403, got {other:?}"),
+    }
+    ureq::get(&format!(
+        "http://{us}/v1/federated/eu/contexts/{plain}/turns?view=raw"
+    ))
+    .call()
+    .unwrap();
+
+    // One that may hold it gets both.
+    let (_de_dir, de) = federate("de", &["eu"]);
+    assert_eq!(eu_ids(de), vec![plain.to_string(), resident.to_string()]);
+    ureq::get(&format!("http://{de}/v1/federated/eu/contexts/{resident}"))
+        .call()
+        .unwrap();
+
+    // Requests that are not federated are unaffected.
+    ureq::get(&format!(
+        "http://{eu}/v1/contexts/{resident}/turns?view=raw"
+    ))
+    .call()
+    .unwrap();
+}
+
+#[test]
+fn rejects_bodies_over_the_limit() {
+    let dir = tempdir().unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = HttpConfig {
+        max_body_bytes: 64,
+        ..HttpConfig::default()
+    };
+    let addr = serve(&runtime, dir.path(), config);
+
+    let ids: Vec<u64> = (0..100).collect();
+    match ureq::post(&format!("http://{addr}/v1/contexts/batch-get"))
+        .send_json(json!({ "context_ids": ids }))
+    {
+        Err(ureq::Error::Status(413, response)) => {
+            let body: Value = response.into_json().unwrap();
+            assert_eq!(body["error"]["code"], 413);
+        }
+        other => panic!("expected 413, got {other:?}"),
+    }
+}
+
+#[test]
+fn event_streams_do_not_hold_a_request_slot() {
+    let dir = tempdir().unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = HttpConfig {
+        max_concurrency: 1,
+        ..HttpConfig::default()
+    };
+    let addr = serve(&runtime, dir.path(), config);
+
+    let _first = open_event_stream(addr);
+    let _second = open_event_stream(addr);
+    let health = ureq::get(&format!("http://{addr}/healthz"))
+        .timeout(Duration::from_secs(10))
+        .call()
+        .unwrap();
+    assert_eq!(health.status(), 200);
+}
+
+#[test]
+fn slow_uploads_do_not_hold_a_request_slot() {
+    let dir = tempdir().unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = HttpConfig {
+        max_concurrency: 1,
+        body_timeout_ms: 500,
+        ..HttpConfig::default()
+    };
+    let addr = serve(&runtime, dir.path(), config);
+
+    // Headers promise a body that never arrives.
+    let mut stalled = TcpStream::connect(addr).unwrap();
+    stalled
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    stalled
+        .write_all(
+            b"POST /v1/contexts/batch-get HTTP/1.1\r\nHost: localhost\r\n\
+              Content-Type: application/json\r\nContent-Length: 100\r\n\r\n{",
+        )
+        .unwrap();
+
+    let health = ureq::get(&format!("http://{addr}/healthz"))
+        .timeout(Duration::from_secs(10))
+        .call()
+        .unwrap();
+    assert_eq!(health.status(), 200);
+
+    let mut response = String::new();
+    let _ = stalled.read_to_string(&mut response);
+    assert!(response.starts_with("HTTP/1.1 408"), "{response}");
+}
+
+#[test]
+fn streams_exports_without_a_length() {
+    let dir = tempdir().unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = serve(&runtime, dir.path(), HttpConfig::default());
+
+    let export = ureq::get(&format!("http://{addr}/v1/contexts/export?format=csv"))
+        .call()
+        .unwrap();
+    assert_eq!(export.header("Transfer-Encoding"), Some("chunked"));
+    assert_eq!(export.content_type(), "text/csv");
+    let body = export.into_string().unwrap();
+    assert!(body.starts_with("context_id"), "{body}");
+}
+
+/// Send one request over a Unix socket and return the response head and
+/// body.
+#[cfg(unix)]
+fn unix_request(path: &Path, request: &str) -> (String, String) {
+    let mut stream = std::os::unix::net::UnixStream::connect(path).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.to_string(), body.to_string())
+}
+
+#[cfg(unix)]
+#[test]
+fn serves_the_gateway_on_a_unix_socket_instead_of_tcp() {
+    let dir = tempdir().unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let socket = dir.path().join("http.sock");
+    let config = Config {
+        data_dir: dir.path().to_path_buf(),
+        bind_addr: "127.0.0.1:0".into(),
+        http_bind_addr: None,
+        http_unix_socket_path: Some(socket.clone()),
+        http_unix_socket_mode: 0o600,
+        max_connections: 1,
+        idle_timeout: None,
+        unix_socket_path: None,
+        unix_socket_mode: 0o660,
+    };
+    start_http(
+        &config,
+        http_state(dir.path(), HttpConfig::default()),
+        runtime.handle(),
+    )
+    .unwrap();
+    let mode =
+        std::os::unix::fs::PermissionsExt::mode(&std::fs::metadata(&socket).unwrap().permissions());
+    assert_eq!(mode & 0o777, 0o600);
+
+    let (head, body) = unix_request(
+        &socket,
+        "GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+    assert!(head.starts_with("HTTP/1.1 200"), "{head}");
+    assert_eq!(body, "ok");
+
+    let payload = r#"{"context_ids":[42]}"#;
+    let (head, body) = unix_request(
+        &socket,
+        &format!(
+            "POST /v1/contexts/batch-get HTTP/1.1\r\nHost: localhost\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{payload}",
+            payload.len()
+        ),
+    );
+    assert!(head.starts_with("HTTP/1.1 200"), "{head}");
+    let batch: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(batch["contexts"][0]["error"]["code"], 404);
+
+    // Without either listener there is nothing to serve on.
+    let err = serve_http_on(
+        Vec::new(),
+        http_state(&dir.path().join("other"), HttpConfig::default()),
+        runtime.handle(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, StoreError::InvalidInput(_)), "{err:?}");
+}
@@ -0,0 +1,44 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::append;
+use cxdb_server::store::Store;
+use cxdb_server::turn_store::{IdGeneratorConfig, SNOWFLAKE_EPOCH_UNIX_MS};
+use tempfile::tempdir;
+
+#[test]
+fn snowflake_ids_continue_above_sequential_ones() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let old_context = store.create_context(0).unwrap().context_id;
+    let old_turn = append(&mut store, old_context, b"turn");
+    assert_eq!((old_context, old_turn), (1, 1));
+    drop(store);
+
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    let snowflake = IdGeneratorConfig::Snowflake { node_id: 7 };
+    store.set_id_generator(snowflake.build().unwrap());
+    let created = store.create_context(0).unwrap();
+    let context_id = created.context_id;
+    let first = append(&mut store, context_id, b"turn");
+    let second = append(&mut store, context_id, b"turn");
+    assert!(context_id > old_context);
+    assert!(first > old_turn && second > first);
+    // Node id in bits 12-21, milliseconds since the epoch above them.
+    assert_eq!((first >> 12) & 0x3ff, 7);
+    assert!((first >> 22) + SNOWFLAKE_EPOCH_UNIX_MS >= created.created_at_unix_ms);
+
+    // Existing contexts keep their ids and accept appends.
+    let appended = append(&mut store, old_context, b"turn");
+    assert!(appended > second);
+    assert_eq!(store.get_last(old_context, 10, false).unwrap().len(), 2);
+    drop(store);
+
+    // Switching back continues above the snowflake ids.
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    let next = store.create_context(0).unwrap().context_id;
+    assert_eq!(next, context_id + 1);
+    assert_eq!(append(&mut store, next, b"turn"), appended + 1);
+}
@@ -0,0 +1,351 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::{append_typed, search};
+use std::sync::{Arc, Mutex};
+
+use cxdb_server::error::{Result, StoreError};
+use cxdb_server::index_plugins::{
+    parse_entries, IndexPlugin, IndexPluginSpec, IndexPlugins, IndexPluginsConfig, PluginLimits,
+};
+use cxdb_server::registry::Registry;
+use cxdb_server::store::Store;
+use rmpv::Value;
+use serde_json::json;
+use tempfile::tempdir;
+
+const BUNDLE: &str = r#"
+{
+  "registry_version": 1,
+  "bundle_id": "2025-12-19T00:00:00Z#plugins",
+  "types": {
+    "com.example.Message": {
+      "versions": {
+        "1": {
+          "fields": {
+            "1": { "name": "model", "type": "string" },
+            "2": { "name": "tools", "type": "array", "items": "string" }
+          }
+        }
+      }
+    }
+  }
+}
+"#;
+
+struct FnPlugin<F>(F);
+
+impl<F> IndexPlugin for FnPlugin<F>
+where
+    F: Fn(&serde_json::Value) -> Result<serde_json::Value> + Send,
+{
+    fn index(&self, input: &[u8]) -> Result<Vec<u8>> {
+        let input = serde_json::from_slice(input).unwrap();
+        Ok(serde_json::to_vec(&(self.0)(&input)?).unwrap())
+    }
+}
+
+fn spec(name: &str, types: &[&str]) -> IndexPluginSpec {
+    IndexPluginSpec {
+        name: name.into(),
+        wasm: format!("{name}.wasm").into(),
+        types: types.iter().map(|t| t.to_string()).collect(),
+        fuel: None,
+        memory_bytes: None,
+    }
+}
+
+fn plugin<F>(name: &str, types: &[&str], f: F) -> (IndexPluginSpec, Box<dyn IndexPlugin>)
+where
+    F: Fn(&serde_json::Value) -> Result<serde_json::Value> + Send + 'static,
+{
+    (spec(name, types), Box::new(FnPlugin(f)))
+}
+
+fn registry(dir: &std::path::Path) -> Arc<Mutex<Registry>> {
+    let registry = Registry::open(&dir.join("registry")).unwrap();
+    let registry = Arc::new(Mutex::new(registry));
+    registry
+        .lock()
+        .unwrap()
+        .put_bundle("2025-12-19T00:00:00Z#plugins", BUNDLE.as_bytes())
+        .unwrap();
+    registry
+}
+
+fn message(model: &str, tools: &[&str]) -> Vec<u8> {
+    let tools = tools.iter().map(|t| Value::from(*t)).collect();
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(
+        &mut buf,
+        &Value::Map(vec![
+            (Value::from(1), Value::from(model)),
+            (Value::from(2), Value::Array(tools)),
+        ]),
+    )
+    .unwrap();
+    buf
+}
+
+#[test]
+fn config_and_entries_are_validated() {
+    let specs = IndexPluginsConfig::parse(
+        r#"[{"name": "model", "wasm": "model.wasm", "types": ["com.example.*"], "fuel": 1000}]"#,
+    )
+    .unwrap();
+    assert!(specs[0].matches("com.example.Message") && !specs[0].matches("org.Message"));
+    let config = IndexPluginsConfig {
+        plugins: specs.clone(),
+        limits: PluginLimits {
+            fuel: 5,
+            memory_bytes: 65536,
+        },
+        max_failures: 3,
+    };
+    assert_eq!(
+        config.limits_of(&specs[0]),
+        PluginLimits {
+            fuel: 1000,
+            memory_bytes: 65536
+        }
+    );
+    for bad in [
+        r#"[{"name": "a"}]"#,
+        r#"[{"name": "a", "wasm": "a.wasm"}, {"name": "a", "wasm": "b.wasm"}]"#,
+        r#"[{"name": "a", "wasm": "a.wasm", "fuel": 0}]"#,
+        r#"[{"name": "a", "wasm": "a.wasm", "url": "http://x"}]"#,
+    ] {
+        assert!(
+            matches!(
+                IndexPluginsConfig::parse(bad),
+                Err(StoreError::InvalidInput(_))
+            ),
+            "{bad}"
+        );
+    }
+
+    let entries =
+        parse_entries(br#"{"model": "gpt-4o", "tool": ["grep", 2, true], "skip": null}"#).unwrap();
+    assert_eq!(
+        entries,
+        vec![
+            ("model".to_string(), "gpt-4o".to_string()),
+            ("tool".to_string(), "grep".to_string()),
+            ("tool".to_string(), "2".to_string()),
+            ("tool".to_string(), "true".to_string()),
+        ]
+    );
+    let long = format!(r#"{{"k": "{}"}}"#, "x".repeat(300));
+    for bad in [
+        &br#"["model"]"#[..],
+        br#"{"model.name": "x"}"#,
+        br#"{"model": {"name": "x"}}"#,
+        long.as_bytes(),
+        b"not json",
+    ] {
+        assert!(parse_entries(bad).is_err());
+    }
+}
+
+#[test]
+fn plugin_entries_are_queryable_as_custom_fields() {
+    let dir = tempdir().unwrap();
+    let registry = registry(dir.path());
+    let plugins = IndexPlugins::from_plugins(
+        vec![
+            plugin("model", &["com.example.*"], |turn| {
+                Ok(json!({ "model": turn["data"]["model"], "tool": turn["data"]["tools"] }))
+            }),
+            plugin("depth", &[], |turn| Ok(json!({ "depth": turn["depth"] }))),
+        ],
+        registry,
+        3,
+    );
+    let (ctx, other, fork, first) = {
+        let mut store = Store::open(dir.path()).unwrap();
+        store.enable_index_plugins(plugins);
+        let ctx = store.create_context(0).unwrap().context_id;
+        let first = append_typed(
+            &mut store,
+            ctx,
+            "com.example.Message",
+            &message("gpt-4o", &["grep"]),
+        );
+        append_typed(
+            &mut store,
+            ctx,
+            "com.example.Message",
+            &message("claude", &["sed", "grep"]),
+        );
+        let other = store.create_context(0).unwrap().context_id;
+        append_typed(&mut store, other, "org.example.Note", b"unregistered");
+        let fork = store.fork_context(first).unwrap().context_id;
+
+        assert_eq!(
+            search(&mut store, r#"custom.model = "gpt-4o""#),
+            [ctx, fork]
+        );
+        assert_eq!(search(&mut store, r#"custom.model = "claude""#), [ctx]);
+        assert_eq!(
+            search(&mut store, r#"custom.tool IN ("sed", "awk")"#),
+            [ctx]
+        );
+        assert_eq!(search(&mut store, r#"custom.model ^= "gpt""#), [ctx, fork]);
+        assert_eq!(
+            search(&mut store, r#"custom.depth = "0""#),
+            [ctx, other, fork]
+        );
+        assert_eq!(
+            search(&mut store, r#"custom.model != "claude""#),
+            [other, fork]
+        );
+        assert!(search(&mut store, r#"custom.unknown = "x""#).is_empty());
+
+        let stats = store.index_plugin_stats().unwrap();
+        assert_eq!((stats[0].invocations, stats[0].entries), (2, 5));
+        assert_eq!(stats[1].invocations, 3);
+        assert_eq!(store.index_stats().custom_key_entries, 3);
+        (ctx, other, fork, first)
+    };
+
+    // Entries are persisted; a restart without plugins indexes them again.
+    let mut store = Store::open(dir.path()).unwrap();
+    assert_eq!(
+        store.turn_custom_index(first).unwrap()["tool"],
+        vec!["grep".to_string()]
+    );
+    assert_eq!(
+        search(&mut store, r#"custom.model = "gpt-4o""#),
+        [ctx, fork]
+    );
+    assert_eq!(
+        search(&mut store, r#"custom.depth = "0""#),
+        [ctx, other, fork]
+    );
+    assert!(store.index_plugin_stats().is_none());
+}
+
+#[test]
+fn failing_plugins_never_fail_appends_and_are_disabled() {
+    let dir = tempdir().unwrap();
+    let plugins = IndexPlugins::from_plugins(
+        vec![
+            plugin("broken", &[], |_| {
+                Err(StoreError::InvalidInput("wasm: all fuel consumed".into()))
+            }),
+            plugin("invalid", &[], |_| Ok(json!({ "bad key": "x" }))),
+            plugin("good", &[], |_| Ok(json!({ "ok": "yes" }))),
+        ],
+        registry(dir.path()),
+        2,
+    );
+    let mut store = Store::open(dir.path()).unwrap();
+    store.enable_index_plugins(plugins);
+    let ctx = store.create_context(0).unwrap().context_id;
+    for _ in 0..3 {
+        append_typed(&mut store, ctx, "com.example.Note", b"payload");
+    }
+    assert_eq!(search(&mut store, r#"custom.ok = "yes""#), [ctx]);
+
+    let stats = store.index_plugin_stats().unwrap();
+    for failing in &stats[..2] {
+        assert_eq!((failing.invocations, failing.failures), (2, 2));
+        assert!(failing.disabled);
+        assert!(failing.last_error.is_some());
+    }
+    assert_eq!(stats[2].invocations, 3);
+    assert!(!stats[2].disabled);
+}
+
+/// Answers `{"lang": "wat"}` for every turn.
+#[cfg(feature = "wasm-plugins")]
+const CONSTANT_WAT: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (data (i32.const 0) "{\"lang\": \"wat\"}")
+  (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+  (func (export "index") (param i32 i32) (result i64)
+    (i64.const 15)))
+"#;
+
+/// Never returns.
+#[cfg(feature = "wasm-plugins")]
+const SPIN_WAT: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+  (func (export "index") (param i32 i32) (result i64)
+    (loop (br 0))
+    (i64.const 0)))
+"#;
+
+#[cfg(feature = "wasm-plugins")]
+#[test]
+fn wasm_plugins_run_within_their_fuel_budget() {
+    let dir = tempdir().unwrap();
+    std::fs::write(dir.path().join("lang.wat"), CONSTANT_WAT).unwrap();
+    std::fs::write(dir.path().join("spin.wat"), SPIN_WAT).unwrap();
+    let mut config = IndexPluginsConfig {
+        plugins: IndexPluginsConfig::parse(
+            r#"[{"name": "lang", "wasm": "lang.wat"}, {"name": "spin", "wasm": "spin.wat", "fuel": 10000}]"#,
+        )
+        .unwrap(),
+        limits: PluginLimits {
+            fuel: 1_000_000,
+            memory_bytes: 1 << 20,
+        },
+        max_failures: 1,
+    };
+    for plugin in &mut config.plugins {
+        plugin.wasm = dir.path().join(&plugin.wasm);
+    }
+    let plugins = IndexPlugins::load(&config, registry(dir.path())).unwrap();
+
+    let mut store = Store::open(dir.path()).unwrap();
+    store.enable_index_plugins(plugins);
+    let ctx = store.create_context(0).unwrap().context_id;
+    append_typed(&mut store, ctx, "com.example.Note", b"payload");
+    assert_eq!(search(&mut store, r#"custom.lang = "wat""#), [ctx]);
+    let stats = store.index_plugin_stats().unwrap();
+    assert_eq!(stats[0].failures, 0);
+    assert!(stats[1].disabled);
+    assert!(stats[1].last_error.as_deref().unwrap().contains("wasm"));
+
+    // Modules that import host functions are refused at load time.
+    std::fs::write(
+        dir.path().join("import.wat"),
+        r#"(module (import "env" "log" (func)) (memory (export "memory") 1))"#,
+    )
+    .unwrap();
+    config.plugins = vec![IndexPluginSpec {
+        wasm: dir.path().join("import.wat"),
+        ..spec("import", &[])
+    }];
+    assert!(matches!(
+        IndexPlugins::load(&config, registry(&dir.path().join("other"))),
+        Err(StoreError::InvalidInput(_))
+    ));
+}
+
+#[cfg(not(feature = "wasm-plugins"))]
+#[test]
+fn configured_plugins_need_the_wasm_feature() {
+    let dir = tempdir().unwrap();
+    let config = IndexPluginsConfig {
+        plugins: vec![spec("model", &[])],
+        limits: PluginLimits {
+            fuel: 1,
+            memory_bytes: 1,
+        },
+        max_failures: 1,
+    };
+    let err = IndexPlugins::load(&config, registry(dir.path()))
+        .err()
+        .unwrap();
+    assert!(
+        matches!(&err, StoreError::InvalidInput(msg) if msg.contains("wasm-plugins")),
+        "{err:?}"
+    );
+}
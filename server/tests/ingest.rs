@@ -0,0 +1,234 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::time::Duration;
+
+use base64::Engine;
+use cxdb_server::error::{Result, StoreError};
+use cxdb_server::ingest::{
+    IngestConfig, IngestPipeline, IngestStage, OnError, TransformRequest, Transformer,
+    TransformerSpec,
+};
+use cxdb_server::protocol::AppendTurnRequest;
+use cxdb_server::store::Store;
+use tempfile::tempdir;
+
+struct FnTransformer<F>(F);
+
+impl<F> Transformer for FnTransformer<F>
+where
+    F: Fn(&[u8]) -> Result<Option<Vec<u8>>> + Send + Sync,
+{
+    fn transform(&self, request: &TransformRequest) -> Result<Option<Vec<u8>>> {
+        let payload = base64::engine::general_purpose::STANDARD
+            .decode(&request.payload_b64)
+            .unwrap();
+        (self.0)(&payload)
+    }
+}
+
+fn stage<F>(name: &str, types: &[&str], on_error: OnError, f: F) -> IngestStage
+where
+    F: Fn(&[u8]) -> Result<Option<Vec<u8>>> + Send + Sync + 'static,
+{
+    IngestStage {
+        spec: TransformerSpec {
+            name: name.into(),
+            url: None,
+            wasm: None,
+            types: types.iter().map(|t| t.to_string()).collect(),
+            on_error,
+            timeout_ms: None,
+        },
+        transformer: Box::new(FnTransformer(f)),
+    }
+}
+
+fn request(type_id: &str, payload: &[u8], compress: bool) -> AppendTurnRequest {
+    AppendTurnRequest {
+        context_id: 1,
+        declared_type_id: type_id.into(),
+        declared_type_version: 1,
+        encoding: 1,
+        compression: compress as u32,
+        uncompressed_len: payload.len() as u32,
+        content_hash: *blake3::hash(payload).as_bytes(),
+        payload_bytes: if compress {
+            zstd::encode_all(payload, 3).unwrap()
+        } else {
+            payload.to_vec()
+        },
+        ..Default::default()
+    }
+}
+
+#[test]
+fn config_refuses_wasm_duplicates_and_missing_urls() {
+    let specs = IngestConfig::parse(
+        r#"[{"name": "strip", "url": "http://127.0.0.1:1/strip", "types": ["a.*"]}]"#,
+    )
+    .unwrap();
+    assert!(specs[0].matches("a.Tool") && !specs[0].matches("b.Tool"));
+    assert_eq!(specs[0].on_error, OnError::Reject);
+
+    for bad in [
+        r#"[{"name": "w", "wasm": "strip.wasm"}]"#,
+        r#"[{"name": "a", "url": "http://x"}, {"name": "a", "url": "http://y"}]"#,
+        r#"[{"name": "a"}]"#,
+        r#"[{"name": "a", "url": "ftp://x"}]"#,
+        r#"[{"name": "a", "url": "http://x", "typo": 1}]"#,
+    ] {
+        assert!(
+            matches!(IngestConfig::parse(bad), Err(StoreError::InvalidInput(_))),
+            "{bad}"
+        );
+    }
+}
+
+#[test]
+fn matching_transformers_run_in_order_and_rewrite_the_request() {
+    let pipeline = IngestPipeline::from_stages(vec![
+        stage("upper", &["com.example.*"], OnError::Reject, |p| {
+            Ok(Some(p.to_ascii_uppercase()))
+        }),
+        stage("noop", &[], OnError::Reject, |_| Ok(None)),
+        stage("broken", &[], OnError::Skip, |_| {
+            Err(StoreError::Io(std::io::Error::other("down")))
+        }),
+        stage("suffix", &["com.example.Tool"], OnError::Reject, |p| {
+            Ok(Some([p, b"!"].concat()))
+        }),
+        stage("other", &["org.*"], OnError::Reject, |_| unreachable!()),
+    ]);
+
+    let original = b"\x1b[31mred\x1b[0m";
+    let mut req = request("com.example.Tool", original, true);
+    let audit = pipeline.apply(&mut req).unwrap().unwrap();
+    assert_eq!(req.payload_bytes, b"\x1B[31MRED\x1B[0M!");
+    assert_eq!(req.compression, 0);
+    assert_eq!(req.uncompressed_len, req.payload_bytes.len() as u32);
+    assert_eq!(
+        req.content_hash,
+        *blake3::hash(&req.payload_bytes).as_bytes()
+    );
+    assert_eq!(
+        audit.original_hash,
+        blake3::hash(original).to_hex().as_str()
+    );
+    assert_eq!(audit.original_len, original.len() as u64);
+    assert_eq!(audit.transformers, vec!["upper", "suffix"]);
+
+    // Nothing matched or nothing changed: the request is left alone.
+    let mut untouched = request("net.example.Tool", b"abc", false);
+    assert_eq!(pipeline.apply(&mut untouched).unwrap(), None);
+    assert_eq!(untouched.payload_bytes, b"abc");
+    let mut same = request("com.example.Note", b"ABC", false);
+    assert_eq!(pipeline.apply(&mut same).unwrap(), None);
+
+    // The client's payload is verified before any transformer sees it.
+    let mut forged = request("com.example.Tool", b"abc", false);
+    forged.content_hash = [0; 32];
+    assert!(matches!(
+        pipeline.apply(&mut forged),
+        Err(StoreError::InvalidInput(_))
+    ));
+}
+
+#[test]
+fn failing_transformers_reject_the_append_unless_skipped() {
+    let pipeline = IngestPipeline::from_stages(vec![stage("strict", &[], OnError::Reject, |_| {
+        Err(StoreError::InvalidInput("rejected with 422: no".into()))
+    })]);
+    let mut req = request("com.example.Tool", b"abc", false);
+    let err = pipeline.apply(&mut req).unwrap_err();
+    assert!(
+        matches!(&err, StoreError::InvalidInput(msg) if msg.contains("strict")),
+        "{err:?}"
+    );
+    assert_eq!(req.payload_bytes, b"abc");
+}
+
+/// Serve one transformer request, answering with the payload reversed.
+fn reversing_endpoint() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(request["declared_type_id"], "com.example.Tool");
+        let engine = base64::engine::general_purpose::STANDARD;
+        let mut payload = engine
+            .decode(request["payload_b64"].as_str().unwrap())
+            .unwrap();
+        payload.reverse();
+        let response = serde_json::json!({ "payload_b64": engine.encode(payload) }).to_string();
+        write!(
+            reader.get_mut(),
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+            response.len()
+        )
+        .unwrap();
+    });
+    format!("http://{addr}/transform")
+}
+
+#[test]
+fn http_transformers_rewrite_payloads_and_the_audit_survives_reopen() {
+    let url = reversing_endpoint();
+    let pipeline = IngestPipeline::new(IngestConfig {
+        transformers: IngestConfig::parse(&format!(r#"[{{"name": "reverse", "url": "{url}"}}]"#))
+            .unwrap(),
+        timeout: Duration::from_secs(10),
+    });
+    let mut req = request("com.example.Tool", b"stressed", true);
+    let audit = pipeline.apply(&mut req).unwrap().unwrap();
+    assert_eq!(req.payload_bytes, b"desserts");
+
+    let dir = tempdir().unwrap();
+    let turn_id = {
+        let mut store = Store::open(dir.path()).unwrap();
+        let context_id = store.create_context(0).unwrap().context_id;
+        let (record, _) = store
+            .append_turn(
+                context_id,
+                0,
+                req.declared_type_id.clone(),
+                req.declared_type_version,
+                req.encoding,
+                req.compression,
+                req.uncompressed_len,
+                req.content_hash,
+                &req.payload_bytes,
+            )
+            .unwrap();
+        store.record_ingest(record.turn_id, audit.clone()).unwrap();
+        record.turn_id
+    };
+
+    let store = Store::open(dir.path()).unwrap();
+    let record = store.turn_ingest(turn_id).unwrap();
+    assert_eq!(record.audit, audit);
+    assert_eq!(
+        record.audit.original_hash,
+        blake3::hash(b"stressed").to_hex().as_str()
+    );
+    assert!(store.turn_ingest(turn_id + 1).is_none());
+}
@@ -0,0 +1,124 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::append_child;
+use std::io::Write;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use cxdb_server::invariants::{Invariant, VerifyScope};
+use cxdb_server::store::Store;
+use tempfile::tempdir;
+
+/// A store with branches, forks, an empty context and a hold.
+fn populated(store: &mut Store) -> (u64, u64) {
+    let context_id = store.create_context(0).unwrap().context_id;
+    let first = append_child(store, context_id, 0, b"one");
+    let second = append_child(store, context_id, 0, b"two");
+    append_child(store, context_id, first, b"branch");
+    let fork = store.fork_context(second).unwrap().context_id;
+    append_child(store, fork, 0, b"fork");
+    store.create_context(0).unwrap();
+    store.place_hold(fork, "case 1", "legal").unwrap();
+    (context_id, fork)
+}
+
+#[test]
+fn a_consistent_store_upholds_every_invariant() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    populated(&mut store);
+
+    let report = store.verify_invariants(VerifyScope::Full);
+    report.assert_ok();
+    assert!(report.full);
+    assert_eq!(report.contexts_checked, 3);
+    assert_eq!(report.turns_checked, 4);
+
+    let sample = store.verify_invariants(VerifyScope::Sample(1));
+    sample.assert_ok();
+    assert!(!sample.full);
+    assert_eq!(sample.contexts_checked, 1);
+
+    drop(store);
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    store.verify_invariants(VerifyScope::Full).assert_ok();
+    assert!(store.fsck().ok);
+}
+
+#[test]
+fn a_head_out_of_step_with_its_turn_is_reported() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let (context_id, _) = populated(&mut store);
+    let head = store.get_head(context_id).unwrap();
+    drop(store);
+
+    // Append a well-formed heads.tbl record claiming the wrong depth.
+    let mut record = Vec::new();
+    record.write_u64::<LittleEndian>(context_id).unwrap();
+    record.write_u64::<LittleEndian>(head.head_turn_id).unwrap();
+    record
+        .write_u32::<LittleEndian>(head.head_depth + 5)
+        .unwrap();
+    record.write_u32::<LittleEndian>(head.flags).unwrap();
+    record
+        .write_u64::<LittleEndian>(head.created_at_unix_ms)
+        .unwrap();
+    let crc = crc32fast::hash(&record);
+    record.write_u32::<LittleEndian>(crc).unwrap();
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(dir.path().join("turns").join("heads.tbl"))
+        .unwrap()
+        .write_all(&record)
+        .unwrap();
+
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    let report = store.verify_invariants(VerifyScope::Full);
+    assert!(!report.ok);
+    assert_eq!(report.violation_count, 1);
+    let violation = &report.violations[0];
+    assert_eq!(violation.invariant, Invariant::HeadDepth);
+    assert_eq!(violation.context_id, Some(context_id));
+    assert_eq!(violation.turn_id, Some(head.head_turn_id));
+
+    let fsck = store.fsck();
+    assert!(!fsck.ok);
+    assert!(fsck.chains.is_ok());
+    assert_eq!(fsck.invariants.violations, report.violations);
+}
+
+#[test]
+#[should_panic(expected = "1 store invariant violations")]
+fn assert_ok_panics_with_the_violations() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let context_id = store.create_context(0).unwrap().context_id;
+    append_child(&mut store, context_id, 0, b"one");
+    let head = store.get_head(context_id).unwrap();
+    drop(store);
+
+    let mut record = Vec::new();
+    record.write_u64::<LittleEndian>(context_id).unwrap();
+    record
+        .write_u64::<LittleEndian>(head.head_turn_id + 100)
+        .unwrap();
+    record.write_u32::<LittleEndian>(head.head_depth).unwrap();
+    record.write_u32::<LittleEndian>(head.flags).unwrap();
+    record
+        .write_u64::<LittleEndian>(head.created_at_unix_ms)
+        .unwrap();
+    let crc = crc32fast::hash(&record);
+    record.write_u32::<LittleEndian>(crc).unwrap();
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(dir.path().join("turns").join("heads.tbl"))
+        .unwrap()
+        .write_all(&record)
+        .unwrap();
+
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    store.verify_invariants(VerifyScope::Full).assert_ok();
+}
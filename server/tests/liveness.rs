@@ -0,0 +1,51 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashSet;
+use std::thread;
+use std::time::Duration;
+
+use cxdb_server::metrics::{LivenessConfig, LivenessMode, SessionTracker};
+
+fn tracker(mode: LivenessMode, window: Duration) -> SessionTracker {
+    SessionTracker::new().with_liveness(LivenessConfig { mode, window })
+}
+
+#[test]
+fn liveness_modes_combine_sessions_and_activity() {
+    let window = Duration::from_secs(60);
+    for (mode, expected) in [
+        (LivenessMode::Session, HashSet::from([1])),
+        (LivenessMode::Activity, HashSet::from([1, 2])),
+        (LivenessMode::Any, HashSet::from([1, 2])),
+    ] {
+        let tracker = tracker(mode, window);
+        // Context 1 was created by a connected session; context 2 only
+        // receives heartbeats from an HTTP writer.
+        tracker.register(7, "agent".into(), None, None);
+        tracker.add_context(7, 1);
+        tracker.record_context_activity(2);
+
+        assert_eq!(tracker.get_live_context_ids(), expected, "{mode:?}");
+        assert_eq!(tracker.is_context_live(2), mode != LivenessMode::Session);
+        assert!(!tracker.is_context_live(3));
+    }
+}
+
+#[test]
+fn activity_expires_after_the_window() {
+    let tracker = tracker(LivenessMode::Any, Duration::from_millis(20));
+    tracker.register(7, "agent".into(), None, None);
+    tracker.add_context(7, 1);
+    tracker.record_context_activity(2);
+    assert!(tracker.context_last_activity(2).is_some());
+
+    thread::sleep(Duration::from_millis(40));
+    assert!(!tracker.is_context_live(2));
+    assert_eq!(tracker.context_last_activity(2), None);
+    // The open session still keeps its context live.
+    assert_eq!(tracker.get_live_context_ids(), HashSet::from([1]));
+
+    tracker.unregister(7);
+    assert!(tracker.get_live_context_ids().is_empty());
+}
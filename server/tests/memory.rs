@@ -0,0 +1,45 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use cxdb_server::error::StoreError;
+use cxdb_server::memory::{heap_stats, CountingAllocator, MemoryConfig};
+use tempfile::tempdir;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator::new();
+
+#[test]
+fn counts_live_and_peak_heap_bytes() {
+    let before = heap_stats().expect("counting allocator is installed");
+    let block = vec![1u8; 64 << 20];
+    let during = heap_stats().unwrap();
+    assert!(during.allocated_bytes >= 64 << 20);
+    assert!(during.allocations_total > before.allocations_total);
+    assert!(during.peak_bytes >= during.allocated_bytes);
+    drop(block);
+
+    let after = heap_stats().unwrap();
+    assert!(after.peak_bytes >= 64 << 20);
+    assert!(after.allocated_bytes < after.peak_bytes);
+    assert!(after.deallocations_total > before.deallocations_total);
+    assert_eq!(after.jemalloc.is_some(), cfg!(feature = "jemalloc"));
+}
+
+#[test]
+fn profile_dumps_need_a_directory() {
+    let err = MemoryConfig::default().dump_profile().unwrap_err();
+    assert!(matches!(err, StoreError::NotFound(_)), "{err:?}");
+
+    let dir = tempdir().unwrap();
+    let config = MemoryConfig {
+        profile_dir: Some(dir.path().join("profiles")),
+    };
+    // Without jemalloc, or with profiling inactive, dumps are refused.
+    if !cfg!(feature = "jemalloc") {
+        let err = config.dump_profile().unwrap_err();
+        assert!(
+            matches!(&err, StoreError::InvalidInput(msg) if msg.contains("jemalloc")),
+            "{err:?}"
+        );
+    }
+}
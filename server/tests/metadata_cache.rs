@@ -0,0 +1,254 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use std::collections::HashSet;
+
+use cxdb_server::metadata_cache::MetadataCacheConfig;
+use cxdb_server::metadata_overrides::MetadataPatch;
+use cxdb_server::store::Store;
+use rmpv::Value;
+use tempfile::tempdir;
+
+const CONTEXTS: u64 = 2_000;
+const BUDGET: u64 = 16 * 1024;
+
+fn create_tagged_context(store: &mut Store, tag: &str) -> u64 {
+    create_context_with(store, vec![(1, tag)])
+}
+
+/// Creates a context whose first turn carries the given metadata fields
+/// (1 = client tag, 2 = title).
+fn create_context_with(store: &mut Store, fields: Vec<(u64, &str)>) -> u64 {
+    let ctx = store.create_context(0).expect("create context");
+    let payload = {
+        let meta = Value::Map(
+            fields
+                .into_iter()
+                .map(|(key, value)| (Value::from(key), Value::from(value)))
+                .collect(),
+        );
+        let mut buf = Vec::new();
+        rmpv::encode::write_value(&mut buf, &Value::Map(vec![(Value::from(30), meta)]))
+            .expect("encode");
+        buf
+    };
+    common::append(store, ctx.context_id, &payload);
+    ctx.context_id
+}
+
+#[test]
+fn metadata_cache_stays_within_budget_and_reloads_evicted_entries() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open_with_metadata_cache(
+        dir.path(),
+        MetadataCacheConfig {
+            budget_bytes: Some(BUDGET),
+            ..Default::default()
+        },
+    )
+    .expect("open store");
+
+    let ids: Vec<u64> = (0..CONTEXTS)
+        .map(|i| create_tagged_context(&mut store, &format!("tag-{}", i % 4)))
+        .collect();
+
+    let stats = store.index_stats();
+    assert_eq!(stats.contexts_indexed as u64, CONTEXTS);
+    assert!(stats.memory_bytes > 0);
+    let cache = &stats.metadata_cache;
+    assert!(cache.bytes <= BUDGET, "{} > {BUDGET}", cache.bytes);
+    assert!((cache.entries as u64) < CONTEXTS);
+    assert!(cache.evictions > 0);
+
+    // Evicted metadata is loaded again from the first turn.
+    let misses = cache.misses;
+    let meta = store.get_context_metadata(ids[0]).expect("metadata");
+    assert_eq!(meta.client_tag.as_deref(), Some("tag-0"));
+    assert_eq!(store.index_stats().metadata_cache.misses, misses + 1);
+
+    // Indexes still cover every context.
+    let result = store
+        .search_contexts(r#"tag = "tag-1""#, &HashSet::new(), None)
+        .expect("search");
+    assert_eq!(result.total_count as u64, CONTEXTS / 4);
+
+    // Patching an evicted context unindexes its old metadata.
+    store
+        .apply_metadata_patch(
+            ids[1],
+            &MetadataPatch {
+                client_tag: Some("moved".to_string()),
+                ..Default::default()
+            },
+            &[],
+        )
+        .expect("patch");
+    let result = store
+        .search_contexts(r#"tag = "tag-1""#, &HashSet::new(), None)
+        .expect("search");
+    assert_eq!(result.total_count as u64, CONTEXTS / 4 - 1);
+    assert!(store.index_stats().metadata_cache.bytes <= BUDGET);
+}
+
+#[test]
+fn index_text_stays_within_budget_and_spilled_titles_still_match() {
+    const TEXT_BUDGET: u64 = 8 * 1024;
+    let dir = tempdir().expect("tempdir");
+    let config = MetadataCacheConfig {
+        index_text_bytes: Some(TEXT_BUDGET),
+        ..Default::default()
+    };
+    let mut store = Store::open_with_metadata_cache(dir.path(), config).expect("open store");
+
+    let ids: Vec<u64> = (0..500u64)
+        .map(|i| create_context_with(&mut store, vec![(2, &format!("Deploy {} of svc", i % 5))]))
+        .collect();
+
+    let stats = store.index_stats();
+    assert!(
+        stats.text_bytes <= TEXT_BUDGET,
+        "{} > {TEXT_BUDGET}",
+        stats.text_bytes
+    );
+    assert!(stats.spilled_contexts > 0);
+    assert!(stats.spilled_contexts < 500);
+
+    let deploys_of = |ns: &[u64]| -> Vec<u64> {
+        ids.iter()
+            .enumerate()
+            .filter(|(i, _)| ns.contains(&(*i as u64 % 5)))
+            .map(|(_, &id)| id)
+            .collect()
+    };
+    assert_eq!(
+        common::search(&mut store, r#"title = "Deploy 3 of svc""#),
+        deploys_of(&[3])
+    );
+    assert_eq!(
+        common::search(&mut store, r#"title ~= "deploy 3 OF SVC""#),
+        deploys_of(&[3])
+    );
+    assert_eq!(
+        common::search(&mut store, r#"title ^= "Deploy 1""#),
+        deploys_of(&[1])
+    );
+    assert_eq!(
+        common::search(&mut store, r#"title ^~= "deploy 1""#),
+        deploys_of(&[1])
+    );
+    assert_eq!(
+        common::search(
+            &mut store,
+            r#"title IN ("Deploy 0 of svc", "Deploy 4 of svc")"#
+        ),
+        deploys_of(&[0, 4])
+    );
+    assert_eq!(
+        common::search(&mut store, r#"title != "Deploy 2 of svc""#).len(),
+        400
+    );
+    assert_eq!(
+        common::search(&mut store, r#"NOT title = "Deploy 2 of svc""#).len(),
+        400
+    );
+
+    // Spilling survives a reopen: the rebuilt indexes spill the same way.
+    drop(store);
+    let mut store = Store::open_with_metadata_cache(dir.path(), config).expect("reopen store");
+    assert!(store.index_stats().text_bytes <= TEXT_BUDGET);
+    assert_eq!(
+        common::search(&mut store, r#"title = "Deploy 3 of svc""#),
+        deploys_of(&[3])
+    );
+}
+
+/// Resident set size of this process, from /proc/self/statm.
+fn rss_bytes() -> u64 {
+    let statm = std::fs::read_to_string("/proc/self/statm").expect("read statm");
+    let pages: u64 = statm
+        .split_whitespace()
+        .nth(1)
+        .and_then(|v| v.parse().ok())
+        .expect("parse statm");
+    pages * 4096
+}
+
+fn stress_contexts() -> u64 {
+    std::env::var("CXDB_STRESS_CONTEXTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1_000_000)
+}
+
+/// Child of [`rss_does_not_grow_with_title_length_under_millions_of_contexts`]:
+/// fills a budgeted store with contexts whose titles are
+/// `CXDB_RSS_PROBE_TITLE_LEN` long and prints the resulting RSS.
+#[test]
+#[ignore]
+fn rss_probe() {
+    let Some(title_len) = std::env::var("CXDB_RSS_PROBE_TITLE_LEN")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+    else {
+        return;
+    };
+    let dir = tempdir().expect("tempdir");
+    let config = MetadataCacheConfig {
+        budget_bytes: Some(BUDGET),
+        index_text_bytes: Some(BUDGET),
+    };
+    let mut store = Store::open_with_metadata_cache(dir.path(), config).expect("open store");
+    let title = |i: u64| format!("{i:020}{}", "x".repeat(title_len));
+    let contexts = stress_contexts();
+    for i in 0..contexts {
+        create_context_with(&mut store, vec![(2, &title(i))]);
+    }
+
+    let stats = store.index_stats();
+    assert!(stats.text_bytes <= BUDGET);
+    assert!(stats.metadata_cache.bytes <= BUDGET);
+    assert_eq!(
+        common::search(&mut store, &format!(r#"title = "{}""#, title(1))).len(),
+        1
+    );
+    println!("rss={}", rss_bytes());
+}
+
+/// Creates `CXDB_STRESS_CONTEXTS` (default 1,000,000) contexts twice, in
+/// child processes, with short and with 1 KiB titles. The turn records and
+/// fixed-size index entries grow with the context count either way, but
+/// under the budgets the titles are spilled and the metadata cache evicts,
+/// so the long titles must not show up in RSS: unbounded, the indexes
+/// would hold four copies of each.
+///
+/// Run with `cargo test --release --test metadata_cache -- --ignored`.
+#[test]
+#[ignore]
+fn rss_does_not_grow_with_title_length_under_millions_of_contexts() {
+    const LONG_TITLE: u64 = 1024;
+    let rss_with_titles = |title_len: u64| -> u64 {
+        let output = std::process::Command::new(std::env::current_exe().expect("test binary"))
+            .args(["--ignored", "--exact", "rss_probe", "--nocapture"])
+            .env("CXDB_RSS_PROBE_TITLE_LEN", title_len.to_string())
+            .output()
+            .expect("run probe");
+        assert!(output.status.success(), "probe failed: {output:?}");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout
+            .split("rss=")
+            .nth(1)
+            .and_then(|rest| rest.split_whitespace().next()?.parse().ok())
+            .expect("probe reported rss")
+    };
+    let short = rss_with_titles(8);
+    let long = rss_with_titles(LONG_TITLE);
+    let contexts = stress_contexts();
+    eprintln!("{contexts} contexts: rss {short} bytes with short titles, {long} with long ones");
+    assert!(
+        long.saturating_sub(short) < contexts * LONG_TITLE / 8,
+        "long titles added {} bytes of rss",
+        long.saturating_sub(short)
+    );
+}
@@ -0,0 +1,238 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use std::time::Duration;
+
+use cxdb_server::events::EventBusStats;
+use cxdb_server::http::route_template;
+use cxdb_server::metadata_overrides::MetadataPatch;
+use cxdb_server::metrics::{MessageSample, Metrics};
+use cxdb_server::protocol::{request_summary, MsgType};
+use cxdb_server::registry::Registry;
+use cxdb_server::store::Store;
+use tempfile::tempdir;
+
+#[test]
+fn route_templates_replace_parameters() {
+    assert_eq!(
+        route_template(&["v1", "contexts", "42", "turns"]),
+        "/v1/contexts/:id/turns"
+    );
+    assert_eq!(
+        route_template(&["v1", "turns", "7", "fs", "src", "main.rs"]),
+        "/v1/turns/:id/fs/*"
+    );
+    assert_eq!(
+        route_template(&["v1", "turns", "7", "fs"]),
+        "/v1/turns/:id/fs"
+    );
+    assert_eq!(
+        route_template(&["v1", "turns", "7", "fs", "manifest"]),
+        "/v1/turns/:id/fs/manifest"
+    );
+    assert_eq!(
+        route_template(&["v1", "contexts", "batch-get"]),
+        "/v1/contexts/batch-get"
+    );
+    assert_eq!(
+        route_template(&["v1", "contexts", "42", "turns", "latest", "attachments"]),
+        "/v1/contexts/:id/turns/latest/attachments"
+    );
+    assert_eq!(route_template(&["metrics"]), "/metrics");
+}
+
+#[test]
+fn snapshot_exposes_percentiles_per_route_and_prometheus_histograms() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(&dir.path().join("data")).expect("open store");
+    let registry = Registry::open(&dir.path().join("registry")).expect("open registry");
+    let metrics = Metrics::new(dir.path().to_path_buf());
+
+    for ms in 1..=100 {
+        metrics.record_append(Duration::from_millis(ms));
+    }
+    metrics.record_http(
+        "GET",
+        "/v1/contexts/:id/turns",
+        200,
+        Duration::from_millis(3),
+    );
+    metrics.record_http(
+        "GET",
+        "/v1/contexts/:id/turns",
+        404,
+        Duration::from_millis(1),
+    );
+
+    let snapshot = metrics.snapshot(&mut store, &registry, EventBusStats::default());
+    let append = &snapshot.latency.operations["append"];
+    assert_eq!(append.lifetime.count, 100);
+    assert_eq!(append.window_5m.count, 100);
+    let p99 = append.window_5m.p99.unwrap();
+    assert!((87.0..=112.0).contains(&p99), "p99 = {p99}");
+    assert_eq!(snapshot.perf.append_latency_ms.count, 100);
+
+    assert_eq!(snapshot.latency.http.len(), 2);
+    assert_eq!(snapshot.latency.http[0].status, 200);
+    assert_eq!(snapshot.latency.http[1].status, 404);
+
+    let text = snapshot.to_prometheus();
+    assert!(text.contains("# TYPE cxdb_operation_duration_seconds histogram"));
+    assert!(text.contains("cxdb_operation_duration_seconds_bucket{op=\"append\",le=\"+Inf\"} 100"));
+    assert!(text.contains("cxdb_operation_duration_seconds_bucket{op=\"append\",le=\"0.05\"} 50"));
+    assert!(text.contains(
+        "cxdb_http_request_duration_seconds_count{method=\"GET\",route=\"/v1/contexts/:id/turns\",status=\"404\"} 1"
+    ));
+    assert!(text.contains("cxdb_operation_duration_seconds_5m{op=\"append\",quantile=\"0.99\"}"));
+}
+
+#[test]
+fn tag_breakdown_ranks_heaviest_tags_in_json_and_prometheus() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(&dir.path().join("data")).expect("open store");
+    let registry = Registry::open(&dir.path().join("registry")).expect("open registry");
+    let metrics = Metrics::new(dir.path().to_path_buf());
+
+    metrics.record_tag_append("ingest", 4096);
+    metrics.record_tag_append("ingest", 4096);
+    metrics.record_tag_append("dashboard", 10);
+    metrics.record_tag_read("dashboard", 1000, Duration::from_millis(4));
+    metrics.record_tag_read("", 5, Duration::from_millis(1));
+
+    let snapshot = metrics.snapshot(&mut store, &registry, EventBusStats::default());
+    let tags = &snapshot.tags;
+    let names: Vec<&str> = tags.tags.iter().map(|t| t.tag.as_str()).collect();
+    assert_eq!(names, ["_untagged", "dashboard", "ingest"]);
+    assert_eq!(tags.heaviest[0].tag, "ingest");
+    assert_eq!(tags.heaviest[0].total_bytes, 8192);
+    assert_eq!(tags.heaviest[1].total_bytes, 1010);
+    assert_eq!(tags.tags[1].read_latency.lifetime.count, 1);
+
+    let text = snapshot.to_prometheus();
+    assert!(text.contains("cxdb_tag_appends_total{tag=\"ingest\"} 2"));
+    assert!(text.contains("cxdb_tag_read_bytes_total{tag=\"dashboard\"} 1000"));
+    assert!(text.contains("cxdb_tag_read_duration_seconds_count{tag=\"_untagged\"} 1"));
+}
+
+#[test]
+fn protocol_messages_are_broken_down_by_type() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(&dir.path().join("data")).expect("open store");
+    let registry = Registry::open(&dir.path().join("registry")).expect("open registry");
+    let metrics = Metrics::new(dir.path().to_path_buf());
+
+    let get_last = MsgType::name(MsgType::GetLast as u16);
+    for (response_bytes, error) in [(64_000, false), (30, true)] {
+        metrics.record_message(
+            get_last,
+            &MessageSample {
+                request_bytes: 16,
+                response_bytes,
+                duration: Duration::from_millis(40),
+                error,
+                slow: !error,
+            },
+        );
+    }
+
+    let snapshot = metrics.snapshot(&mut store, &registry, EventBusStats::default());
+    let message = &snapshot.protocol[0];
+    assert_eq!(message.msg_type, "GET_LAST");
+    assert_eq!((message.count, message.errors, message.slow), (2, 1, 1));
+    assert_eq!(message.response_bytes, 64_030);
+    assert_eq!(message.max_response_bytes, 64_000);
+
+    let text = snapshot.to_prometheus();
+    assert!(text.contains("cxdb_protocol_messages_total{msg_type=\"GET_LAST\"} 2"));
+    assert!(text.contains("cxdb_protocol_request_bytes_total{msg_type=\"GET_LAST\"} 32"));
+    assert!(text.contains("cxdb_protocol_message_duration_seconds_count{msg_type=\"GET_LAST\"} 2"));
+
+    // The slow-request log summarizes parameters without payload bodies.
+    let mut request = Vec::new();
+    request.extend_from_slice(&7u64.to_le_bytes());
+    request.extend_from_slice(&100_000u32.to_le_bytes());
+    request.extend_from_slice(&1u32.to_le_bytes());
+    assert_eq!(
+        request_summary(MsgType::GetLast as u16, 0, &request),
+        "context_id=7 limit=100000 include_payload=1"
+    );
+    assert_eq!(
+        request_summary(MsgType::GetLast as u16, 0, &request[..5]),
+        "payload_bytes=5"
+    );
+}
+
+#[test]
+fn http_compression_is_reported_per_encoding() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(&dir.path().join("data")).expect("open store");
+    let registry = Registry::open(&dir.path().join("registry")).expect("open registry");
+    let metrics = Metrics::new(dir.path().to_path_buf());
+
+    metrics.record_http_compression("zstd", 1_000_000, 90_000, Duration::from_millis(4));
+    metrics.record_http_compression("zstd", 200_000, 30_000, Duration::from_millis(1));
+    metrics.record_http_compression("gzip", 50_000, 8_000, Duration::from_millis(2));
+
+    let snapshot = metrics.snapshot(&mut store, &registry, EventBusStats::default());
+    let zstd = &snapshot.http_compression["zstd"];
+    assert_eq!((zstd.responses, zstd.input_bytes), (2, 1_200_000));
+    assert_eq!((zstd.output_bytes, zstd.saved_bytes), (120_000, 1_080_000));
+    assert!((zstd.duration_seconds - 0.005).abs() < 1e-9);
+
+    let text = snapshot.to_prometheus();
+    assert!(text.contains("cxdb_http_compression_saved_bytes_total{encoding=\"zstd\"} 1080000"));
+    assert!(text.contains("cxdb_http_compressed_responses_total{encoding=\"gzip\"} 1"));
+}
+
+#[test]
+fn data_age_counts_shared_history_once() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(&dir.path().join("data")).expect("open store");
+    let registry = Registry::open(&dir.path().join("registry")).expect("open registry");
+    let metrics = Metrics::new(dir.path().to_path_buf());
+
+    let payload = [0x80u8; 1];
+    let append = |store: &mut Store, context_id: u64| common::append(store, context_id, &payload);
+    let ctx = store.create_context(0).expect("create context").context_id;
+    let first = append(&mut store, ctx);
+    append(&mut store, ctx);
+    let fork = store.fork_context(first).expect("fork").context_id;
+    append(&mut store, fork);
+
+    let snapshot = metrics.snapshot(&mut store, &registry, EventBusStats::default());
+    let recent = &snapshot.data_age.buckets[0];
+    assert_eq!(recent.age, "0-30d");
+    assert_eq!((recent.contexts, recent.turns, recent.bytes), (2, 3, 3));
+    assert_eq!(snapshot.data_age.buckets[3].contexts, 0);
+    assert_eq!(snapshot.data_age.by_tag[0].tag, "_untagged");
+
+    let text = snapshot.to_prometheus();
+    assert!(text.contains("cxdb_data_age_turns{age=\"0-30d\"} 3"));
+    assert!(text.contains("cxdb_data_age_contexts{age=\"180d+\"} 0"));
+    assert!(text.contains("cxdb_tag_data_age_bytes{tag=\"_untagged\",age=\"0-30d\"} 3"));
+}
+
+#[test]
+fn data_age_takes_tags_from_the_indexes() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(&dir.path().join("data")).expect("open store");
+    let registry = Registry::open(&dir.path().join("registry")).expect("open registry");
+    let metrics = Metrics::new(dir.path().to_path_buf());
+
+    let ctx = store.create_context(0).expect("create context").context_id;
+    common::append(&mut store, ctx, b"ab");
+    let patch = MetadataPatch {
+        client_tag: Some("ingest".to_string()),
+        ..Default::default()
+    };
+    store.apply_metadata_patch(ctx, &patch, &[]).unwrap();
+
+    let snapshot = metrics.snapshot(&mut store, &registry, EventBusStats::default());
+    assert_eq!(snapshot.data_age.by_tag.len(), 1);
+    assert_eq!(snapshot.data_age.by_tag[0].tag, "ingest");
+    assert!(snapshot
+        .to_prometheus()
+        .contains("cxdb_tag_data_age_bytes{tag=\"ingest\",age=\"0-30d\"} 2"));
+}
@@ -0,0 +1,265 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use cxdb_server::access::Access;
+use cxdb_server::error::StoreError;
+use cxdb_server::oidc::{OidcConfig, OidcVerifier, RoleRules};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde_json::{json, Value};
+
+const ISSUER: &str = "https://login.example.com";
+
+struct SigningKey {
+    kid: String,
+    pair: EcdsaKeyPair,
+}
+
+impl SigningKey {
+    fn generate(kid: &str) -> Self {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+            .unwrap();
+        Self {
+            kid: kid.to_string(),
+            pair,
+        }
+    }
+
+    fn jwk(&self) -> Value {
+        // Uncompressed point: 0x04 || x || y.
+        let point = self.pair.public_key().as_ref();
+        json!({
+            "kty": "EC",
+            "kid": self.kid,
+            "alg": "ES256",
+            "use": "sig",
+            "crv": "P-256",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+        })
+    }
+
+    fn sign(&self, claims: Value) -> String {
+        let header = json!({"alg": "ES256", "typ": "JWT", "kid": self.kid});
+        let signed = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let signature = self
+            .pair
+            .sign(&SystemRandom::new(), signed.as_bytes())
+            .unwrap();
+        format!("{signed}.{}", URL_SAFE_NO_PAD.encode(signature.as_ref()))
+    }
+}
+
+/// Serves whatever key set is currently in `keys` and counts fetches.
+fn serve_jwks(keys: Arc<Mutex<Vec<Value>>>) -> (String, Arc<Mutex<usize>>) {
+    let server = tiny_http::Server::http("127.0.0.1:0").expect("bind jwks");
+    let url = format!("http://{}/jwks.json", server.server_addr());
+    let fetches = Arc::new(Mutex::new(0));
+    let counter = Arc::clone(&fetches);
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            *counter.lock().unwrap() += 1;
+            let body = json!({"keys": *keys.lock().unwrap()}).to_string();
+            let _ = request.respond(tiny_http::Response::from_string(body));
+        }
+    });
+    (url, fetches)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn claims(extra: Value) -> Value {
+    let mut claims = json!({
+        "iss": ISSUER,
+        "aud": "cxdb",
+        "sub": "user-123",
+        "email": "ada@example.com",
+        "exp": now() + 300,
+    });
+    claims
+        .as_object_mut()
+        .unwrap()
+        .extend(extra.as_object().unwrap().clone());
+    claims
+}
+
+fn verifier(jwks_url: &str) -> OidcVerifier {
+    let mut config = OidcConfig::new(ISSUER);
+    config.audience = Some("cxdb".into());
+    config.jwks_url = Some(jwks_url.to_string());
+    OidcVerifier::new(config)
+}
+
+fn rejected(result: Result<impl std::fmt::Debug, StoreError>) -> String {
+    match result {
+        Err(StoreError::Unauthenticated(msg)) => msg,
+        other => panic!("expected unauthenticated, got {other:?}"),
+    }
+}
+
+#[test]
+fn valid_tokens_resolve_to_a_principal() {
+    let key = SigningKey::generate("k1");
+    let (url, _) = serve_jwks(Arc::new(Mutex::new(vec![key.jwk()])));
+    let verifier = verifier(&url);
+
+    let identity = verifier.verify(&key.sign(claims(json!({})))).unwrap();
+    assert_eq!(identity.principal, "ada@example.com");
+    assert_eq!(identity.subject, "user-123");
+    assert_eq!(identity.access, Access::Write);
+
+    // Without the principal claim the subject is used; audience lists work.
+    let token = key.sign(claims(json!({"email": null, "aud": ["other", "cxdb"]})));
+    assert_eq!(verifier.verify(&token).unwrap().principal, "user-123");
+
+    let header = format!("Bearer {}", key.sign(claims(json!({}))));
+    let identity = verifier.authenticate(Some(&header)).unwrap();
+    assert_eq!(identity.unwrap().principal, "ada@example.com");
+    assert!(verifier.authenticate(None).unwrap().is_none());
+    assert!(verifier.authenticate(Some("Basic abc")).unwrap().is_none());
+}
+
+#[test]
+fn tokens_with_bad_claims_or_signatures_are_rejected() {
+    let key = SigningKey::generate("k1");
+    let (url, _) = serve_jwks(Arc::new(Mutex::new(vec![key.jwk()])));
+    let verifier = verifier(&url);
+
+    let issuer = rejected(verifier.verify(&key.sign(claims(json!({"iss": "https://evil"})))));
+    assert!(issuer.contains("issuer"), "{issuer}");
+    let audience = rejected(verifier.verify(&key.sign(claims(json!({"aud": "other"})))));
+    assert!(audience.contains("audience"), "{audience}");
+    let expired = rejected(verifier.verify(&key.sign(claims(json!({"exp": now() - 120})))));
+    assert!(expired.contains("expired"), "{expired}");
+    let early = rejected(verifier.verify(&key.sign(claims(json!({"nbf": now() + 600})))));
+    assert!(early.contains("not yet valid"), "{early}");
+    // Expiry within the leeway is tolerated.
+    assert!(verifier
+        .verify(&key.sign(claims(json!({"exp": now() - 10}))))
+        .is_ok());
+
+    // A token signed by another key under the same kid.
+    let impostor = SigningKey::generate("k1");
+    rejected(verifier.verify(&impostor.sign(claims(json!({})))));
+    // Tampered claims.
+    let token = key.sign(claims(json!({})));
+    let mut parts: Vec<&str> = token.split('.').collect();
+    let forged = URL_SAFE_NO_PAD.encode(claims(json!({"email": "root@example.com"})).to_string());
+    parts[1] = &forged;
+    rejected(verifier.verify(&parts.join(".")));
+    rejected(verifier.verify("not-a-token"));
+}
+
+#[test]
+fn role_rules_map_claims_to_access() {
+    let key = SigningKey::generate("k1");
+    let (url, _) = serve_jwks(Arc::new(Mutex::new(vec![key.jwk()])));
+    let mut config = OidcConfig::new(ISSUER);
+    config.jwks_url = Some(url);
+    config.roles = serde_json::from_value::<RoleRules>(json!({
+        "default": "read",
+        "rules": [
+            { "claim": "email", "value": "*@contractor.example.com", "access": "none" },
+            { "claim": "groups", "value": "cxdb-admins", "access": "write" }
+        ]
+    }))
+    .unwrap();
+    let verifier = OidcVerifier::new(config);
+    let access = |extra: Value| verifier.verify(&key.sign(claims(extra))).unwrap().access;
+
+    assert_eq!(access(json!({})), Access::Read);
+    assert_eq!(
+        access(json!({"groups": ["staff", "cxdb-admins"]})),
+        Access::Write
+    );
+    assert_eq!(
+        access(json!({"email": "pat@contractor.example.com", "groups": ["cxdb-admins"]})),
+        Access::None
+    );
+}
+
+#[test]
+fn unknown_keys_trigger_a_refetch_for_rotation() {
+    let old = SigningKey::generate("old");
+    let keys = Arc::new(Mutex::new(vec![old.jwk()]));
+    let (url, fetches) = serve_jwks(Arc::clone(&keys));
+    let mut config = OidcConfig::new(ISSUER);
+    config.jwks_url = Some(url);
+    config.jwks_refetch_min = Duration::ZERO;
+    let verifier = OidcVerifier::new(config);
+
+    verifier.verify(&old.sign(claims(json!({})))).unwrap();
+    verifier.verify(&old.sign(claims(json!({})))).unwrap();
+    assert_eq!(*fetches.lock().unwrap(), 1, "keys are cached");
+
+    // The issuer rotates to a new key.
+    let new = SigningKey::generate("new");
+    *keys.lock().unwrap() = vec![new.jwk()];
+    verifier.verify(&new.sign(claims(json!({})))).unwrap();
+    assert_eq!(*fetches.lock().unwrap(), 2);
+    let retired = rejected(verifier.verify(&old.sign(claims(json!({})))));
+    assert!(retired.contains("unknown signing key"), "{retired}");
+}
+
+#[test]
+fn unknown_keys_are_not_refetched_too_often() {
+    let key = SigningKey::generate("k1");
+    let (url, fetches) = serve_jwks(Arc::new(Mutex::new(vec![key.jwk()])));
+    let verifier = verifier(&url);
+    verifier.verify(&key.sign(claims(json!({})))).unwrap();
+
+    let stranger = SigningKey::generate("k2");
+    for _ in 0..3 {
+        rejected(verifier.verify(&stranger.sign(claims(json!({})))));
+    }
+    assert_eq!(*fetches.lock().unwrap(), 1);
+}
+
+#[test]
+fn concurrent_requests_share_one_key_fetch() {
+    let key = SigningKey::generate("k1");
+    let server = tiny_http::Server::http("127.0.0.1:0").expect("bind jwks");
+    let url = format!("http://{}/jwks.json", server.server_addr());
+    let fetches = Arc::new(Mutex::new(0));
+    {
+        let fetches = Arc::clone(&fetches);
+        let body = json!({"keys": [key.jwk()]}).to_string();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                *fetches.lock().unwrap() += 1;
+                thread::sleep(Duration::from_millis(200));
+                let _ = request.respond(tiny_http::Response::from_string(body.clone()));
+            }
+        });
+    }
+    let verifier = Arc::new(verifier(&url));
+    let token = key.sign(claims(json!({})));
+    let workers: Vec<_> = (0..8)
+        .map(|_| {
+            let verifier = Arc::clone(&verifier);
+            let token = token.clone();
+            thread::spawn(move || verifier.verify(&token).map(|id| id.principal))
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap().unwrap();
+    }
+    assert_eq!(*fetches.lock().unwrap(), 1);
+}
@@ -0,0 +1,147 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashSet;
+
+use cxdb_server::error::StoreError;
+use cxdb_server::invariants::VerifyScope;
+use cxdb_server::metadata_overrides::MetadataPatch;
+use cxdb_server::store::{Provenance, Store};
+use tempfile::tempdir;
+
+fn owned_context(store: &mut Store, owner: &str) -> u64 {
+    let context_id = store.create_context(0).unwrap().context_id;
+    let patch = MetadataPatch {
+        provenance: Some(Provenance {
+            on_behalf_of: Some(owner.to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    store.apply_metadata_patch(context_id, &patch, &[]).unwrap();
+    context_id
+}
+
+fn owned_by(store: &mut Store, user: &str) -> Vec<u64> {
+    let mut ids = store
+        .search_contexts(&format!("user = \"{user}\""), &HashSet::new(), None)
+        .unwrap()
+        .context_ids;
+    ids.sort_unstable();
+    ids
+}
+
+#[test]
+fn transfer_reassigns_owner_and_records_audit_trail() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let context_id = owned_context(&mut store, "ana@example.com");
+
+    let entry = store
+        .transfer_ownership(context_id, "bea@example.com", "Ana left", "admin")
+        .unwrap();
+    assert_eq!(entry.previous_owner.as_deref(), Some("ana@example.com"));
+    assert_eq!(entry.owner, "bea@example.com");
+    assert_eq!(store.owner(context_id).as_deref(), Some("bea@example.com"));
+    assert!(owned_by(&mut store, "ana@example.com").is_empty());
+    assert_eq!(owned_by(&mut store, "bea@example.com"), vec![context_id]);
+
+    assert!(matches!(
+        store.transfer_ownership(context_id, "bea@example.com", "", "admin"),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        store.transfer_ownership(context_id, " ", "", "admin"),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        store.transfer_ownership(999, "bea@example.com", "", "admin"),
+        Err(StoreError::NotFound(_))
+    ));
+
+    // A context without an owner can be given one.
+    let unowned = store.create_context(0).unwrap().context_id;
+    let entry = store
+        .transfer_ownership(unowned, "bea@example.com", "", "admin")
+        .unwrap();
+    assert_eq!(entry.previous_owner, None);
+
+    // The owner and the audit trail survive a restart.
+    drop(store);
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    assert_eq!(store.owner(context_id).as_deref(), Some("bea@example.com"));
+    let history = store.ownership_history(context_id);
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].reason, "Ana left");
+    assert_eq!(history[0].principal, "admin");
+}
+
+#[test]
+fn bulk_transfer_moves_every_matching_context() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let first = owned_context(&mut store, "ana@example.com");
+    let second = owned_context(&mut store, "ana@example.com");
+    let other = owned_context(&mut store, "cy@example.com");
+    let already = owned_context(&mut store, "bea@example.com");
+
+    let bulk = store
+        .transfer_ownership_matching(
+            "user = \"ana@example.com\" OR user = \"bea@example.com\"",
+            &HashSet::new(),
+            "bea@example.com",
+            "Ana left",
+            "admin",
+        )
+        .unwrap();
+    let moved: Vec<u64> = bulk.transferred.iter().map(|t| t.context_id).collect();
+    assert_eq!(moved, vec![first, second]);
+    assert_eq!(bulk.unchanged, vec![already]);
+    assert_eq!(
+        owned_by(&mut store, "bea@example.com"),
+        vec![first, second, already]
+    );
+    assert_eq!(owned_by(&mut store, "cy@example.com"), vec![other]);
+    store.verify_invariants(VerifyScope::Full).assert_ok();
+
+    assert!(matches!(
+        store.transfer_ownership_matching("user =", &HashSet::new(), "bea", "", "admin"),
+        Err(StoreError::InvalidInput(_))
+    ));
+}
+
+#[test]
+fn transfer_to_an_owner_without_email_clears_the_previous_email() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let context_id = owned_context(&mut store, "ana@example.com");
+    let email = |store: &mut Store| {
+        store
+            .get_context_metadata(context_id)
+            .and_then(|m| m.provenance)
+            .and_then(|p| p.on_behalf_of_email)
+    };
+    store
+        .transfer_ownership(context_id, "bea@example.com", "", "admin")
+        .unwrap();
+    assert_eq!(email(&mut store).as_deref(), Some("bea@example.com"));
+
+    store
+        .transfer_ownership(context_id, "deploy-bot", "", "admin")
+        .unwrap();
+    assert_eq!(store.owner(context_id).as_deref(), Some("deploy-bot"));
+    assert_eq!(email(&mut store), None);
+
+    drop(store);
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    assert_eq!(email(&mut store), None);
+
+    // An owner with an email sets one again.
+    store
+        .transfer_ownership(context_id, "cy@example.com", "", "admin")
+        .unwrap();
+    assert_eq!(email(&mut store).as_deref(), Some("cy@example.com"));
+    drop(store);
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    assert_eq!(email(&mut store).as_deref(), Some("cy@example.com"));
+}
@@ -0,0 +1,62 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use cxdb_server::payload_cache::{PayloadCacheConfig, PrefetchRequest};
+use cxdb_server::store::Store;
+use tempfile::tempdir;
+
+const PAGE: u32 = 10;
+
+#[test]
+fn paging_backwards_is_served_from_prefetched_payloads() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    store.enable_payload_cache(PayloadCacheConfig {
+        budget_bytes: 1024 * 1024,
+    });
+    let prefetches = store.enable_prefetch().expect("cache enabled");
+
+    let ctx = store.create_context(0).expect("create context");
+    for i in 0..100u32 {
+        let payload = format!("turn {i}").into_bytes();
+        common::append(&mut store, ctx.context_id, &payload);
+    }
+
+    let newest = store
+        .get_last(ctx.context_id, PAGE, true)
+        .expect("get last");
+    let page = store
+        .get_before(ctx.context_id, newest[0].record.turn_id, PAGE, true)
+        .expect("get before");
+    assert_eq!(store.payload_cache_stats().misses, 2 * PAGE as u64);
+
+    // Serving a page queues the page before it.
+    let request = prefetches.try_recv().expect("prefetch queued");
+    assert_eq!(
+        request,
+        PrefetchRequest {
+            context_id: ctx.context_id,
+            before_turn_id: page[0].record.turn_id,
+            limit: PAGE,
+        }
+    );
+    assert_eq!(store.prefetch(&request).expect("prefetch"), PAGE as usize);
+
+    let next = store
+        .get_before(ctx.context_id, page[0].record.turn_id, PAGE, true)
+        .expect("get before");
+    assert_eq!(next[0].payload.as_deref(), Some(&b"turn 70"[..]));
+    let stats = store.payload_cache_stats();
+    assert_eq!((stats.hits, stats.misses), (PAGE as u64, 2 * PAGE as u64));
+    assert_eq!(stats.prefetched, PAGE as u64);
+    assert!(stats.hit_rate > 0.3);
+
+    // Pages without payloads do not read ahead.
+    while prefetches.try_recv().is_ok() {}
+    store
+        .get_before(ctx.context_id, next[0].record.turn_id, PAGE, false)
+        .expect("get before");
+    assert!(prefetches.try_recv().is_err());
+}
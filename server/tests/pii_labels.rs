@@ -0,0 +1,57 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use cxdb_server::pii::PiiConfig;
+use cxdb_server::store::Store;
+use rmpv::Value;
+use tempfile::tempdir;
+
+fn append(store: &mut Store, context_id: u64, type_id: &str, text: &str) -> Option<Vec<String>> {
+    let payload = common::msgpack(&Value::Map(vec![(Value::from(2), Value::from(text))]));
+    let (_, metadata) =
+        common::try_append(store, context_id, 0, type_id, &payload).expect("append");
+    metadata.and_then(|m| m.labels)
+}
+
+#[test]
+fn appended_pii_labels_the_context() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let mut config = PiiConfig::parse("all").expect("config");
+    config.exclude_types = vec!["com.example.ToolOutput".into()];
+    store.enable_pii_scanning(config);
+
+    let ctx = store.create_context(0).expect("create context").context_id;
+    append(&mut store, ctx, "com.example.Message", "hello");
+    // Excluded types are not scanned.
+    let labels = append(
+        &mut store,
+        ctx,
+        "com.example.ToolOutput",
+        "card 4111 1111 1111 1111",
+    );
+    assert_eq!(labels, None);
+
+    let labels = append(
+        &mut store,
+        ctx,
+        "com.example.Message",
+        "reach me at jane@example.com",
+    );
+    assert_eq!(labels, Some(vec!["pii:email".to_string()]));
+    // An existing label is not added twice.
+    let labels = append(&mut store, ctx, "com.example.Message", "or bob@example.org");
+    assert_eq!(labels, None);
+
+    assert_eq!(
+        store.label_namespace_values("pii"),
+        vec![("email".to_string(), 1)]
+    );
+    let stats = store.pii_stats().expect("scanning enabled");
+    assert_eq!(stats.turns_scanned, 3);
+    assert_eq!(stats.turns_excluded, 1);
+    assert_eq!(stats.detections["email"], 2);
+    assert_eq!(stats.detections["credit_card"], 0);
+}
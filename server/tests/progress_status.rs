@@ -0,0 +1,49 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use cxdb_server::metrics::{ProgressStatus, SessionTracker, MAX_STATUS_LEN};
+
+fn status(text: &str, progress: Option<f64>, session_id: Option<u64>) -> Option<ProgressStatus> {
+    ProgressStatus::new(text.to_string(), progress, session_id).expect("valid status")
+}
+
+#[test]
+fn statuses_are_validated() {
+    assert!(ProgressStatus::new("x".repeat(MAX_STATUS_LEN), None, None).is_ok());
+    assert!(ProgressStatus::new("x".repeat(MAX_STATUS_LEN + 1), None, None).is_err());
+    assert!(ProgressStatus::new("step".into(), Some(1.5), None).is_err());
+    assert!(ProgressStatus::new("step".into(), Some(-0.1), None).is_err());
+    // An empty status without progress is a clear.
+    assert_eq!(status("", None, None), None);
+    assert!(status("", Some(0.5), None).is_some());
+}
+
+#[test]
+fn context_statuses_end_with_their_session() {
+    let tracker = SessionTracker::new();
+    tracker.register(7, "agent".into(), None, None);
+    tracker.set_context_status(1, status("step 3/10: running tests", Some(0.3), Some(7)));
+    tracker.set_context_status(2, status("waiting for review", None, None));
+    tracker.set_session_status(7, status("planning", None, Some(7)));
+
+    let current = tracker.context_status(1).expect("status");
+    assert_eq!(current.status, "step 3/10: running tests");
+    assert_eq!(current.progress, Some(0.3));
+    assert!(tracker.context_last_activity(1).is_some());
+    let sessions = tracker.get_active_sessions();
+    assert_eq!(sessions[0].status.as_ref().unwrap().status, "planning");
+
+    tracker.set_context_status(1, status("step 4/10: linting", Some(0.4), Some(7)));
+    assert_eq!(
+        tracker.context_status(1).unwrap().status,
+        "step 4/10: linting"
+    );
+
+    // Statuses set over HTTP outlive protocol sessions.
+    tracker.unregister(7);
+    assert_eq!(tracker.context_status(1), None);
+    assert!(tracker.context_status(2).is_some());
+
+    tracker.set_context_status(2, None);
+    assert_eq!(tracker.context_status(2), None);
+}
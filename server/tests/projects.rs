@@ -0,0 +1,83 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::{append, search};
+
+use cxdb_server::error::StoreError;
+use cxdb_server::store::Store;
+use tempfile::tempdir;
+
+#[test]
+fn contexts_are_grouped_into_projects_and_searchable_by_them() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let a = store.create_context(0).unwrap().context_id;
+    let b = store.create_context(0).unwrap().context_id;
+    let c = store.create_context(0).unwrap().context_id;
+    for payload in [&b"one"[..], b"two", b"three"] {
+        append(&mut store, a, payload);
+    }
+    append(&mut store, b, b"one");
+    append(&mut store, c, b"one");
+
+    let project = store
+        .create_project("exp-42", Some("Ablation"), None, Some("ana"))
+        .unwrap();
+    assert_eq!(project.name, "Ablation");
+    assert_eq!(project.created_by.as_deref(), Some("ana"));
+    store.create_project("exp-43", None, None, None).unwrap();
+    assert_eq!(store.project("exp-43").unwrap().name, "exp-43");
+    assert!(matches!(
+        store.create_project("exp-42", None, None, None),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        store.create_project("has space", None, None, None),
+        Err(StoreError::InvalidInput(_))
+    ));
+
+    assert!(store.assign_project("exp-42", a, Some("ana")).unwrap());
+    assert!(store.assign_project("exp-42", b, None).unwrap());
+    assert!(!store.assign_project("exp-42", b, None).unwrap());
+    assert!(store.assign_project("exp-43", b, None).unwrap());
+    assert!(matches!(
+        store.assign_project("missing", a, None),
+        Err(StoreError::NotFound(_))
+    ));
+    assert!(matches!(
+        store.assign_project("exp-42", 999, None),
+        Err(StoreError::NotFound(_))
+    ));
+
+    assert_eq!(store.project_contexts("exp-42").unwrap(), vec![a, b]);
+    assert_eq!(store.context_projects(b), vec!["exp-42", "exp-43"]);
+    assert_eq!(search(&mut store, "project = \"exp-42\""), vec![a, b]);
+    assert_eq!(search(&mut store, "project = \"exp-43\""), vec![b]);
+    assert_eq!(search(&mut store, "project != \"exp-42\""), vec![c]);
+    assert_eq!(
+        search(&mut store, "project IN (\"exp-42\", \"exp-43\")"),
+        vec![a, b]
+    );
+
+    let stats = store.project_stats("exp-42").unwrap();
+    assert_eq!(stats.contexts, 2);
+    assert_eq!(stats.turns, 4);
+    assert!(stats.first_created_at_unix_ms <= stats.last_created_at_unix_ms);
+    assert_eq!(store.project_stats("exp-43").unwrap().turns, 1);
+
+    assert!(store.unassign_project("exp-42", b, None).unwrap());
+    assert!(!store.unassign_project("exp-42", b, None).unwrap());
+    assert_eq!(search(&mut store, "project = \"exp-42\""), vec![a]);
+
+    // Projects and assignments survive reopening the data dir.
+    drop(store);
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    let ids: Vec<String> = store.projects().into_iter().map(|p| p.id).collect();
+    assert_eq!(ids, vec!["exp-42", "exp-43"]);
+    assert_eq!(store.project_contexts("exp-42").unwrap(), vec![a]);
+    assert_eq!(store.context_projects(b), vec!["exp-43"]);
+    assert_eq!(search(&mut store, "project = \"exp-42\""), vec![a]);
+    assert_eq!(search(&mut store, "project = \"exp-43\""), vec![b]);
+}
@@ -0,0 +1,305 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use std::path::{Path, PathBuf};
+
+use cxdb_server::error::StoreError;
+use cxdb_server::protocol::compat::{check_fixtures, golden_fixtures, FrameFixture};
+use cxdb_server::protocol::{
+    AppendTurnRequest, AttachFsOverlayRequest, AttachFsRequest, ErrorResponse, GetLastResponse,
+    HelloRequest, OverlayBlob, OverlayChangeItem, PutBlobRequest, TurnItem, WireStruct,
+    SCHEMA_VERSION,
+};
+use proptest::prelude::*;
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/protocol")
+}
+
+#[test]
+fn checked_in_fixtures_match_the_current_encoders() {
+    let dir = fixtures_dir().join(format!("v{SCHEMA_VERSION}"));
+    let golden = golden_fixtures();
+    for fixture in &golden {
+        let path = dir.join(format!("{}.json", fixture.name));
+        let on_disk: FrameFixture = serde_json::from_slice(
+            &std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {e}", path.display())),
+        )
+        .unwrap();
+        assert_eq!(
+            &on_disk, fixture,
+            "{} is stale; a layout change needs a new schema version \
+             (cxdb-server protocol write-fixtures)",
+            fixture.name
+        );
+    }
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), golden.len());
+}
+
+#[test]
+fn fixtures_of_every_schema_version_still_decode() {
+    let mut versions = 0;
+    for entry in std::fs::read_dir(fixtures_dir()).unwrap() {
+        let dir = entry.unwrap().path();
+        let report = check_fixtures(&dir).unwrap();
+        assert!(report.ok(), "{}: {:?}", dir.display(), report.failures);
+        assert!(report.checked > 0);
+        if dir.ends_with(format!("v{SCHEMA_VERSION}")) {
+            assert!(report.uncovered.is_empty(), "{:?}", report.uncovered);
+        }
+        versions += 1;
+    }
+    assert!(versions > 0);
+}
+
+#[test]
+fn client_fixtures_decode() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../clients/rust/tests/fixtures");
+    let report = check_fixtures(&dir).unwrap();
+    assert!(report.ok(), "{:?}", report.failures);
+    assert!(report.checked >= 10);
+    // msgpack and fstree vectors share the directory.
+    assert!(report.skipped > 0);
+}
+
+#[test]
+fn layout_drift_is_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let write = |name: &str, msg_type: u16, flags: u16, payload_hex: &str| {
+        let fixture = FrameFixture {
+            name: name.into(),
+            message: None,
+            msg_type,
+            flags,
+            payload_hex: payload_hex.into(),
+            notes: None,
+        };
+        std::fs::write(
+            dir.path().join(format!("{name}.json")),
+            serde_json::to_vec(&fixture).unwrap(),
+        )
+        .unwrap();
+    };
+    // GET_HEAD with a trailing byte the current layout does not know about.
+    write("get_head_extra", 4, 0, "2a0000000000000001");
+    // GET_LAST missing include_payload.
+    write("get_last_short", 6, 0, "01000000000000000a000000");
+    write("ctx_create", 2, 0, "0000000000000000");
+    write("unknown", 77, 0, "");
+
+    let report = check_fixtures(dir.path()).unwrap();
+    assert!(!report.ok());
+    assert_eq!(report.checked, 4);
+    let failed: Vec<&str> = report.failures.iter().map(|f| f.fixture.as_str()).collect();
+    assert_eq!(failed, vec!["get_head_extra", "get_last_short", "unknown"]);
+    assert!(report.failures[1].reason.contains("include_payload"));
+    assert!(!report.uncovered.contains(&"CtxCreateRequest"));
+}
+
+/// Encode, decode under `flags`, and check the value and that no strict
+/// prefix of the payload decodes.
+fn assert_round_trip<T: WireStruct + PartialEq + std::fmt::Debug>(value: &T, flags: u16) {
+    let bytes = value.encode().unwrap();
+    assert_eq!(&T::decode(&bytes, flags).unwrap(), value);
+    for len in 0..bytes.len() {
+        match T::decode(&bytes[..len], flags) {
+            Err(StoreError::MalformedFrame { message, .. }) => assert_eq!(message, T::NAME),
+            other => panic!("{len}-byte prefix of {} decoded: {other:?}", T::NAME),
+        }
+    }
+}
+
+fn hash() -> impl Strategy<Value = [u8; 32]> {
+    any::<[u8; 32]>()
+}
+
+fn bytes() -> impl Strategy<Value = Vec<u8>> {
+    prop::collection::vec(any::<u8>(), 0..64)
+}
+
+/// `OptStr` sends `None` as an empty string, so only non-empty strings are
+/// distinguishable from absence.
+fn opt_str() -> impl Strategy<Value = Option<String>> {
+    prop::option::of("\\PC{1,16}")
+}
+
+prop_compose! {
+    fn hello_request()(
+        protocol_version in any::<u16>(),
+        client_tag in "\\PC{0,16}",
+        client_meta_json in opt_str(),
+        resume in prop::option::of((any::<u64>(), bytes())),
+        auth_token in prop::option::of("\\PC{0,64}"),
+    ) -> HelloRequest {
+        let (resume_session_id, resume_token) = resume.unzip();
+        HelloRequest {
+            protocol_version,
+            client_tag,
+            client_meta_json,
+            resume_session_id,
+            resume_token,
+            auth_token,
+        }
+    }
+}
+
+prop_compose! {
+    fn append_turn_request()(
+        ids in any::<(u64, u64)>(),
+        declared_type_id in "\\PC{0,24}",
+        numbers in any::<(u32, u32, u32, u32)>(),
+        content_hash in hash(),
+        payload_bytes in bytes(),
+        idempotency_key in bytes(),
+        fs_root_hash in prop::option::of(hash()),
+    ) -> AppendTurnRequest {
+        AppendTurnRequest {
+            context_id: ids.0,
+            parent_turn_id: ids.1,
+            declared_type_id,
+            declared_type_version: numbers.0,
+            encoding: numbers.1,
+            compression: numbers.2,
+            uncompressed_len: numbers.3,
+            content_hash,
+            payload_bytes,
+            idempotency_key,
+            fs_root_hash,
+        }
+    }
+}
+
+prop_compose! {
+    fn attach_fs_request()(
+        turn_id in any::<u64>(),
+        fs_root_hash in hash(),
+        meta in prop::option::of((
+            any::<u64>(),
+            opt_str(),
+            opt_str(),
+            opt_str(),
+            any::<u8>(),
+            any::<u64>(),
+        )),
+    ) -> AttachFsRequest {
+        let mut req = AttachFsRequest { turn_id, fs_root_hash, ..Default::default() };
+        if let Some((captured_at, base_path, git_commit, git_branch, git_dirty, total)) = meta {
+            req.captured_at_unix_ms = Some(captured_at);
+            req.base_path = Some(base_path);
+            req.git_commit = Some(git_commit);
+            req.git_branch = Some(git_branch);
+            req.git_dirty = Some(git_dirty);
+            req.total_entries = Some(total);
+        }
+        req
+    }
+}
+
+prop_compose! {
+    fn turn_item(include_payload: bool)(
+        ids in any::<(u64, u64, u32)>(),
+        declared_type_id in "\\PC{0,24}",
+        numbers in any::<(u32, u32, u32, u32)>(),
+        content_hash in hash(),
+        payload in bytes(),
+    ) -> TurnItem {
+        TurnItem {
+            turn_id: ids.0,
+            parent_turn_id: ids.1,
+            depth: ids.2,
+            declared_type_id,
+            declared_type_version: numbers.0,
+            encoding: numbers.1,
+            compression: numbers.2,
+            uncompressed_len: numbers.3,
+            content_hash,
+            payload: include_payload.then_some(payload),
+        }
+    }
+}
+
+fn get_last_response() -> impl Strategy<Value = (GetLastResponse, bool)> {
+    any::<bool>().prop_flat_map(|include_payload| {
+        prop::collection::vec(turn_item(include_payload), 0..4)
+            .prop_map(move |turns| (GetLastResponse { turns }, include_payload))
+    })
+}
+
+prop_compose! {
+    fn attach_fs_overlay_request()(
+        turn_id in any::<u64>(),
+        base_root_hash in hash(),
+        blobs in prop::collection::vec(bytes().prop_map(|data| OverlayBlob { data }), 0..4),
+        changes in prop::collection::vec(
+            ("\\PC{0,24}", any::<u8>(), any::<u32>(), any::<u64>(), hash()).prop_map(
+                |(path, kind, mode, size, hash)| OverlayChangeItem { path, kind, mode, size, hash },
+            ),
+            0..4,
+        ),
+    ) -> AttachFsOverlayRequest {
+        AttachFsOverlayRequest { turn_id, base_root_hash, blobs, changes }
+    }
+}
+
+proptest! {
+    #[test]
+    fn hello_requests_round_trip(req in hello_request()) {
+        let flags = u16::from(req.resume_session_id.is_some())
+            | u16::from(req.auth_token.is_some()) << 1;
+        assert_round_trip(&req, flags);
+    }
+
+    #[test]
+    fn append_requests_round_trip(req in append_turn_request()) {
+        let flags = u16::from(req.fs_root_hash.is_some());
+        assert_round_trip(&req, flags);
+    }
+
+    #[test]
+    fn attach_fs_requests_round_trip(req in attach_fs_request()) {
+        let flags = u16::from(req.total_entries.is_some());
+        assert_round_trip(&req, flags);
+    }
+
+    #[test]
+    fn put_blob_requests_round_trip(
+        hash in hash(),
+        data in bytes(),
+        context_id in prop::option::of(any::<u64>()),
+    ) {
+        let flags = u16::from(context_id.is_some());
+        assert_round_trip(&PutBlobRequest { hash, data, context_id }, flags);
+    }
+
+    #[test]
+    fn overlay_requests_round_trip(req in attach_fs_overlay_request()) {
+        assert_round_trip(&req, 0);
+    }
+
+    #[test]
+    fn get_last_responses_round_trip((resp, include_payload) in get_last_response()) {
+        assert_round_trip(&resp, u16::from(include_payload));
+    }
+
+    #[test]
+    fn error_responses_round_trip(
+        code in any::<u32>(),
+        detail in "\\PC{0,64}",
+        error_code in any::<u16>(),
+        retryable in any::<u8>(),
+        retry_after_ms in any::<u32>(),
+    ) {
+        assert_round_trip(&ErrorResponse { code, detail, error_code, retryable, retry_after_ms }, 0);
+    }
+
+    #[test]
+    fn arbitrary_payloads_never_panic(payload in bytes(), flags in any::<u16>()) {
+        let _ = HelloRequest::decode(&payload, flags);
+        let _ = AppendTurnRequest::decode(&payload, flags);
+        let _ = AttachFsRequest::decode(&payload, flags);
+        let _ = AttachFsOverlayRequest::decode(&payload, flags);
+        let _ = PutBlobRequest::decode(&payload, flags);
+        let _ = GetLastResponse::decode(&payload, flags);
+        let _ = ErrorResponse::decode(&payload, flags);
+    }
+}
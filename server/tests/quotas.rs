@@ -0,0 +1,186 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use cxdb_server::events::{EventBusStats, StoreEvent};
+use cxdb_server::metrics::Metrics;
+use cxdb_server::quotas::{QuotaLevel, QuotaPolicy, QuotaResource, Quotas};
+use cxdb_server::registry::Registry;
+use cxdb_server::store::Store;
+use cxdb_server::webhooks::{WebhookConfig, Webhooks};
+use serde_json::json;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+const DAY: u64 = 86_400;
+
+fn webhooks() -> Arc<Webhooks> {
+    Arc::new(Webhooks::new(WebhookConfig::default()))
+}
+
+fn policy(value: serde_json::Value) -> QuotaPolicy {
+    let dir = tempdir().expect("tempdir");
+    let path = dir.path().join("quotas.json");
+    std::fs::write(&path, value.to_string()).unwrap();
+    QuotaPolicy::load(&path).unwrap()
+}
+
+#[test]
+fn warnings_fire_once_per_threshold_crossing() {
+    let quotas = Quotas::new(
+        policy(json!({
+            "tags": { "ci": { "turns": 10, "bytes": 1000 } }
+        })),
+        webhooks(),
+    );
+    let now = 100 * DAY;
+
+    for _ in 0..7 {
+        assert!(quotas.record("ci", 0, 1, 10, now).is_empty());
+    }
+    let warnings = quotas.record("ci", 0, 1, 10, now);
+    assert_eq!(warnings.len(), 1);
+    let warning = &warnings[0];
+    assert_eq!(warning.resource, QuotaResource::Turns);
+    assert_eq!(warning.level, QuotaLevel::Warning);
+    assert_eq!((warning.usage, warning.limit), (8, 10));
+    assert_eq!(warning.percent, 80.0);
+    assert!(quotas.record("ci", 0, 1, 10, now).is_empty());
+
+    // Reaching the limit warns again, and so does the byte quota.
+    let warnings = quotas.record("ci", 0, 1, 800, now + 60);
+    let levels: Vec<_> = warnings.iter().map(|w| (w.resource, w.level)).collect();
+    assert_eq!(
+        levels,
+        [
+            (QuotaResource::Turns, QuotaLevel::Exceeded),
+            (QuotaResource::Bytes, QuotaLevel::Warning)
+        ]
+    );
+    assert!(quotas.record("ci", 0, 1, 0, now + 60).is_empty());
+
+    // Usage ages out of the window, after which crossings warn again.
+    let later = now + 2 * DAY;
+    for _ in 0..7 {
+        assert!(quotas.record("ci", 0, 1, 0, later).is_empty());
+    }
+    assert_eq!(quotas.record("ci", 0, 1, 0, later).len(), 1);
+
+    // Tags without limits are not counted.
+    assert!(quotas.record("other", 1, 100, 100_000, now).is_empty());
+    let tags: Vec<String> = quotas
+        .report(later)
+        .tags
+        .into_iter()
+        .map(|t| t.tag)
+        .collect();
+    assert_eq!(tags, ["ci"]);
+}
+
+#[test]
+fn report_lists_usage_limits_and_percentages() {
+    let quotas = Quotas::new(
+        policy(json!({
+            "warn_at": 0.5,
+            "default": { "contexts": 4 },
+            "tags": { "ci": { "turns": 100 }, "idle": { "turns": 5 } }
+        })),
+        webhooks(),
+    );
+    let now = 10 * DAY;
+    quotas.record("", 2, 0, 0, now);
+    quotas.record("ci", 0, 30, 300, now);
+
+    let report = quotas.report(now);
+    assert_eq!(report.window_secs, DAY);
+    assert_eq!(report.warn_at, 0.5);
+    let tags: Vec<&str> = report.tags.iter().map(|t| t.tag.as_str()).collect();
+    assert_eq!(tags, ["_untagged", "ci", "idle"]);
+
+    let untagged = &report.tags[0];
+    assert_eq!(untagged.contexts.usage, 2);
+    assert_eq!(untagged.contexts.limit, Some(4));
+    assert_eq!(untagged.contexts.percent, Some(50.0));
+    assert_eq!(untagged.contexts.level, QuotaLevel::Warning);
+    let ci = &report.tags[1];
+    assert_eq!(ci.turns.percent, Some(30.0));
+    assert_eq!(ci.turns.level, QuotaLevel::Ok);
+    assert_eq!(ci.bytes.usage, 300);
+    assert_eq!(ci.bytes.limit, None);
+    assert_eq!(report.tags[2].turns.usage, 0);
+}
+
+#[test]
+fn invalid_policies_are_rejected() {
+    let dir = tempdir().expect("tempdir");
+    let path = dir.path().join("quotas.json");
+    for bad in [
+        json!({ "window_secs": 0 }),
+        json!({ "warn_at": 1.5 }),
+        json!({ "tags": { "ci": { "turns": "many" } } }),
+    ] {
+        std::fs::write(&path, bad.to_string()).unwrap();
+        assert!(QuotaPolicy::load(&path).is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn metrics_export_quota_usage_and_warning_events() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(&dir.path().join("data")).expect("open store");
+    let registry = Registry::open(&dir.path().join("registry")).expect("open registry");
+    let metrics = Metrics::new(dir.path().to_path_buf()).with_quotas(
+        policy(json!({
+            "tags": { "ingest": { "contexts": 1, "bytes": 10000 } }
+        })),
+        webhooks(),
+    );
+
+    let warnings = metrics.record_tag_context("ingest");
+    assert_eq!(warnings.len(), 1);
+    let (name, data) = warnings[0].to_event().to_sse();
+    assert_eq!(name, "quota_warning");
+    let data: serde_json::Value = serde_json::from_str(&data).unwrap();
+    assert_eq!(data["resource"], "contexts");
+    assert_eq!(data["level"], "exceeded");
+    assert!(matches!(
+        warnings[0].to_event(),
+        StoreEvent::QuotaWarning { limit: 1, .. }
+    ));
+    assert!(metrics.record_tag_append("ingest", 4096).is_empty());
+
+    let snapshot = metrics.snapshot(&mut store, &registry, EventBusStats::default());
+    let quotas = snapshot.quotas.as_ref().expect("quotas configured");
+    assert_eq!(quotas.tags[0].bytes.usage, 4096);
+    let text = snapshot.to_prometheus();
+    assert!(text.contains("cxdb_quota_usage{tag=\"ingest\",resource=\"bytes\"} 4096"));
+    assert!(text.contains("cxdb_quota_limit{tag=\"ingest\",resource=\"contexts\"} 1"));
+    assert!(!text.contains("cxdb_quota_limit{tag=\"ingest\",resource=\"turns\"}"));
+
+    assert!(Metrics::new(dir.path().to_path_buf())
+        .quota_report()
+        .is_none());
+}
+
+#[test]
+fn warnings_are_queued_as_webhooks() {
+    let webhooks = webhooks();
+    let quotas = Quotas::new(
+        policy(json!({
+            "tags": { "ci": { "turns": 1, "bytes": 10 } },
+            "webhook_url": "https://alerts.example.com/cxdb-quota"
+        })),
+        Arc::clone(&webhooks),
+    );
+    assert_eq!(quotas.record("ci", 0, 1, 5, DAY).len(), 1);
+    assert_eq!(webhooks.pending(), 1);
+    assert_eq!(quotas.record("ci", 0, 0, 5, DAY).len(), 1);
+    assert_eq!(webhooks.pending(), 2);
+
+    // Without a webhook_url warnings are only returned.
+    let quotas = Quotas::new(
+        policy(json!({ "tags": { "ci": { "turns": 1 } } })),
+        Arc::clone(&webhooks),
+    );
+    assert_eq!(quotas.record("ci", 0, 1, 0, DAY).len(), 1);
+    assert_eq!(webhooks.pending(), 2);
+}
@@ -0,0 +1,136 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use cxdb_server::error::StoreError;
+use cxdb_server::registry::diff::{BundleDiff, Change, DiffBase};
+use cxdb_server::registry::Registry;
+use serde_json::json;
+use tempfile::tempdir;
+
+const V1: &str = r#"{
+  "registry_version": 1,
+  "bundle_id": "app.v1",
+  "types": {
+    "app.Message": { "versions": { "1": { "fields": {
+      "1": { "name": "role", "type": "u8", "enum": "app.Role" },
+      "2": { "name": "text", "type": "string" }
+    } } } },
+    "app.Legacy": { "versions": { "1": { "fields": { "1": { "name": "blob", "type": "bytes" } } } } }
+  },
+  "enums": { "app.Role": { "1": "system", "2": "user" } }
+}"#;
+
+const V2: &str = r#"{
+  "registry_version": 2,
+  "bundle_id": "app.v2",
+  "types": {
+    "app.Message": { "versions": {
+      "1": { "fields": {
+        "1": { "name": "role", "type": "u8", "enum": "app.Role" },
+        "2": { "name": "text", "type": "string" }
+      }, "renderer": { "esm_url": "builtin:MessageRenderer" } },
+      "2": { "fields": {
+        "1": { "name": "role", "type": "u8", "enum": "app.Role" },
+        "2": { "name": "text", "type": "string" },
+        "3": { "name": "status", "type": "u8", "enum": "app.Status" }
+      } }
+    } },
+    "app.Tool": { "versions": { "1": { "fields": { "1": { "name": "name", "type": "string" } } } } }
+  },
+  "enums": {
+    "app.Role": { "1": "system", "2": "user" },
+    "app.Status": { "1": "ok", "2": "error" }
+  }
+}"#;
+
+fn changes(diff: &BundleDiff) -> Vec<(String, Change)> {
+    diff.types
+        .iter()
+        .map(|t| (t.type_id.clone(), t.change))
+        .chain(diff.enums.iter().map(|e| (e.enum_id.clone(), e.change)))
+        .collect()
+}
+
+fn open_with_bundles() -> (tempfile::TempDir, Registry) {
+    let dir = tempdir().expect("tempdir");
+    let mut registry = Registry::open(dir.path()).expect("open registry");
+    registry.put_bundle("app.v1", V1.as_bytes()).unwrap();
+    registry.put_bundle("app.v2", V2.as_bytes()).unwrap();
+    (dir, registry)
+}
+
+#[test]
+fn diff_against_another_bundle() {
+    let (_dir, registry) = open_with_bundles();
+
+    let diff = registry
+        .diff_bundle("app.v2", DiffBase::Bundle("app.v1"))
+        .unwrap();
+    assert_eq!(diff.against, "app.v1");
+    assert!(!diff.identical);
+    assert_eq!(
+        changes(&diff),
+        vec![
+            ("app.Legacy".to_string(), Change::Removed),
+            ("app.Message".to_string(), Change::Changed),
+            ("app.Tool".to_string(), Change::Added),
+            ("app.Status".to_string(), Change::Added),
+        ]
+    );
+    let message = serde_json::to_value(&diff.types[1]).unwrap();
+    assert_eq!(
+        message["versions"],
+        json!([
+            {
+                "version": 1,
+                "change": "changed",
+                "renderer": {
+                    "attribute": "renderer",
+                    "before": null,
+                    "after": { "esm_url": "builtin:MessageRenderer", "component": null, "integrity": null, "text_template": null }
+                }
+            },
+            { "version": 2, "change": "added" }
+        ])
+    );
+
+    assert!(
+        registry
+            .diff_bundle("app.v2", DiffBase::Bundle("app.v2"))
+            .unwrap()
+            .identical
+    );
+    assert!(matches!(
+        registry.diff_bundle("app.v2", DiffBase::Bundle("missing")),
+        Err(StoreError::NotFound(_))
+    ));
+}
+
+#[test]
+fn diff_against_current_registry() {
+    let (_dir, registry) = open_with_bundles();
+
+    // The registry has app.Message v2 and the renderer v2 added to v1.
+    let diff = registry.diff_bundle("app.v1", DiffBase::Current).unwrap();
+    assert_eq!(diff.against, "current");
+    assert_eq!(
+        changes(&diff),
+        vec![("app.Message".to_string(), Change::Changed)]
+    );
+    let versions: Vec<(u32, Change, bool)> = diff.types[0]
+        .versions
+        .iter()
+        .map(|v| (v.version, v.change, v.renderer.is_some()))
+        .collect();
+    assert_eq!(
+        versions,
+        vec![(1, Change::Changed, true), (2, Change::Removed, false)]
+    );
+
+    assert!(
+        registry
+            .diff_bundle("app.v2", DiffBase::Current)
+            .unwrap()
+            .identical
+    );
+}
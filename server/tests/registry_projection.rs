@@ -1,7 +1,12 @@
 // Copyright 2025 StrongDM Inc
 // SPDX-License-Identifier: Apache-2.0
 
-use cxdb_server::projection::project_msgpack;
+use cxdb_server::deadline::Deadline;
+use cxdb_server::error::StoreError;
+use cxdb_server::http::type_version_to_json;
+use cxdb_server::projection::cache::{project_cached, ProjectionCacheConfig};
+use cxdb_server::projection::text::render_text;
+use cxdb_server::projection::{project_msgpack, project_msgpack_with_deadline};
 use cxdb_server::projection::{BytesRender, EnumRender, RenderOptions, TimeRender, U64Format};
 use cxdb_server::registry::Registry;
 use rmpv::Value;
@@ -14,6 +19,7 @@ fn default_options() -> RenderOptions {
         enum_render: EnumRender::Label,
         time_render: TimeRender::Iso,
         include_unknown: true,
+        apply_defaults: false,
     }
 }
 
@@ -68,6 +74,7 @@ fn registry_ingest_and_project() {
         enum_render: EnumRender::Label,
         time_render: TimeRender::Iso,
         include_unknown: true,
+        apply_defaults: false,
     };
 
     let projection = project_msgpack(&buf, desc, &registry, &options).expect("project");
@@ -180,6 +187,60 @@ fn nested_type_references() {
     assert_eq!(first_item.get("count").unwrap().as_i64().unwrap(), 1);
 }
 
+#[test]
+fn projection_respects_deadline() {
+    let dir = tempdir().expect("tempdir");
+    let mut registry = Registry::open(dir.path()).expect("open registry");
+
+    let bundle = r#"
+    {
+      "registry_version": 1,
+      "bundle_id": "deadline-test",
+      "types": {
+        "test:Batch": {
+          "versions": {
+            "1": {
+              "fields": {
+                "1": { "name": "values", "type": "array", "items": "string" }
+              }
+            }
+          }
+        }
+      },
+      "enums": {}
+    }
+    "#;
+    registry
+        .put_bundle("deadline-test", bundle.as_bytes())
+        .expect("put bundle");
+    let desc = registry
+        .get_type_version("test:Batch", 1)
+        .expect("descriptor");
+
+    let values = (0..1000)
+        .map(|i| Value::String(format!("v{i}").into()))
+        .collect();
+    let value = Value::Map(vec![(Value::Integer(1.into()), Value::Array(values))]);
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &value).expect("encode msgpack");
+
+    let projection = project_msgpack_with_deadline(
+        &buf,
+        desc,
+        &registry,
+        &default_options(),
+        &Deadline::from_budget_ms(0),
+    )
+    .expect("project");
+    assert_eq!(projection.data["values"].as_array().unwrap().len(), 1000);
+
+    let expired = Deadline::after(std::time::Duration::ZERO);
+    let err = project_msgpack_with_deadline(&buf, desc, &registry, &default_options(), &expired)
+        .err()
+        .expect("deadline exceeded");
+    assert!(matches!(err, StoreError::DeadlineExceeded(_)));
+}
+
 #[test]
 fn bundle_with_renderer_parses() {
     let dir = tempdir().expect("tempdir");
@@ -333,3 +394,441 @@ fn get_all_renderers() {
     assert_eq!(c_renderer.esm_url, "builtin:RendererC");
     assert_eq!(c_renderer.component.as_ref().unwrap(), "CWrapper");
 }
+
+#[test]
+fn merge_applies_additions_and_reports_conflicts_precisely() {
+    let dir = tempdir().expect("tempdir");
+    let mut registry = Registry::open(dir.path()).expect("open registry");
+
+    let v1 = r#"
+    {
+      "registry_version": 1,
+      "bundle_id": "app",
+      "types": {
+        "com.example.Message": {
+          "versions": {
+            "1": { "fields": { "1": { "name": "role", "type": "u8" }, "2": { "name": "text", "type": "string" } } }
+          }
+        }
+      }
+    }
+    "#;
+    let report = registry.merge_bundle("app", v1.as_bytes()).unwrap();
+    assert!(report.created);
+    assert_eq!(report.added_versions.len(), 1);
+
+    // Same bundle_id with one more version: only the addition is applied.
+    let v2 = r#"
+    {
+      "registry_version": 1,
+      "bundle_id": "app",
+      "types": {
+        "com.example.Message": {
+          "versions": {
+            "1": { "fields": { "1": { "name": "role", "type": "u8" }, "2": { "name": "text", "type": "string" } } },
+            "2": { "fields": { "1": { "name": "role", "type": "u8" }, "2": { "name": "text", "type": "string" }, "3": { "name": "lang", "type": "string" } } }
+          }
+        }
+      }
+    }
+    "#;
+    let report = registry.merge_bundle("app", v2.as_bytes()).unwrap();
+    assert!(!report.created);
+    assert!(report.conflicts.is_empty());
+    assert_eq!(report.unchanged_versions, 1);
+    assert_eq!(report.added_versions[0].version, 2);
+    assert_eq!(
+        registry
+            .get_latest_type_version("com.example.Message")
+            .unwrap()
+            .version,
+        2
+    );
+
+    // Changing a registered version and reusing a tag are both reported, and
+    // nothing is applied.
+    let bad = r#"
+    {
+      "registry_version": 1,
+      "bundle_id": "app",
+      "types": {
+        "com.example.Message": {
+          "versions": {
+            "1": { "fields": { "1": { "name": "role", "type": "string" }, "2": { "name": "text", "type": "string" } } },
+            "3": { "fields": { "3": { "name": "lang", "type": "u32" } } }
+          }
+        }
+      }
+    }
+    "#;
+    let report = registry.merge_bundle("app", bad.as_bytes()).unwrap();
+    assert_eq!(report.conflicts.len(), 2);
+    assert_eq!(
+        (report.conflicts[0].version, report.conflicts[0].tag),
+        (Some(1), Some(1))
+    );
+    assert!(report.conflicts[0].reason.contains("type u8 -> string"));
+    assert_eq!(
+        (report.conflicts[1].version, report.conflicts[1].tag),
+        (Some(3), Some(3))
+    );
+    assert!(registry
+        .get_type_version("com.example.Message", 3)
+        .is_none());
+
+    // The stored bundle is the union, and survives a reopen.
+    drop(registry);
+    let registry = Registry::open(dir.path()).expect("reopen registry");
+    let stored: serde_json::Value =
+        serde_json::from_slice(registry.get_bundle("app").unwrap()).unwrap();
+    assert_eq!(
+        stored["types"]["com.example.Message"]["versions"]
+            .as_object()
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[test]
+fn map_and_oneof_fields_project_and_round_trip() {
+    let dir = tempdir().expect("tempdir");
+    let mut registry = Registry::open(dir.path()).expect("open registry");
+
+    let bundle = r#"
+    {
+      "registry_version": 1,
+      "bundle_id": "unions",
+      "types": {
+        "com.example.Text": {
+          "versions": { "1": { "fields": { "1": { "name": "text", "type": "string" } } } }
+        },
+        "com.example.Image": {
+          "versions": { "1": { "fields": { "1": { "name": "url", "type": "string" } } } }
+        },
+        "com.example.Message": {
+          "versions": {
+            "1": {
+              "fields": {
+                "1": { "name": "labels", "type": "map", "key_type": "string", "value_type": "string" },
+                "2": { "name": "counts", "type": "map", "key_type": "u32", "value_type": "u64" },
+                "3": {
+                  "name": "parts", "type": "array",
+                  "items": { "type": "oneof", "variants": { "1": "com.example.Text", "2": "com.example.Image" } }
+                },
+                "4": {
+                  "name": "by_lang", "type": "map",
+                  "value_type": { "type": "ref", "ref": "com.example.Text" }
+                },
+                "5": { "name": "first", "type": "oneof", "variants": { "1": "com.example.Text" } }
+              }
+            }
+          }
+        }
+      }
+    }
+    "#;
+    registry
+        .put_bundle("unions", bundle.as_bytes())
+        .expect("put bundle");
+
+    let text = |s: &str| Value::Map(vec![(Value::from(1), Value::from(s))]);
+    let value = Value::Map(vec![
+        (
+            Value::from(1),
+            Value::Map(vec![(Value::from("env"), Value::from("prod"))]),
+        ),
+        (
+            Value::from(2),
+            Value::Map(vec![(Value::from(7), Value::from(3))]),
+        ),
+        (
+            Value::from(3),
+            Value::Array(vec![
+                Value::Map(vec![(Value::from(1), text("hi"))]),
+                Value::Map(vec![(
+                    Value::from(2),
+                    Value::Map(vec![(Value::from(1), Value::from("a.png"))]),
+                )]),
+                Value::Map(vec![(Value::from(9), Value::from("?"))]),
+            ]),
+        ),
+        (
+            Value::from(4),
+            Value::Map(vec![(Value::from("fr"), text("salut"))]),
+        ),
+        (Value::from(5), Value::from("not a union")),
+    ]);
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &value).expect("encode msgpack");
+
+    let desc = registry
+        .get_type_version("com.example.Message", 1)
+        .expect("descriptor");
+    let projection = project_msgpack(&buf, desc, &registry, &default_options()).expect("project");
+    assert_eq!(
+        projection.data,
+        serde_json::json!({
+            "labels": { "env": "prod" },
+            "counts": { "7": "3" },
+            "parts": [
+                { "type": "com.example.Text", "value": { "text": "hi" } },
+                { "type": "com.example.Image", "value": { "url": "a.png" } },
+                { "9": "?" }
+            ],
+            "by_lang": { "fr": { "text": "salut" } },
+            "first": "not a union"
+        })
+    );
+
+    // Publishing the served descriptor again yields the same fields.
+    let served = type_version_to_json(desc);
+    let fields = desc.fields.clone();
+    let republished = serde_json::json!({
+        "registry_version": 1,
+        "bundle_id": "copy",
+        "types": { "com.example.Copy": { "versions": { "1": served } } }
+    });
+    registry
+        .put_bundle("copy", republished.to_string().as_bytes())
+        .expect("put copy");
+    let copy = registry
+        .get_type_version("com.example.Copy", 1)
+        .expect("copy descriptor");
+    assert_eq!(copy.fields, fields);
+
+    let missing_variants = bundle.replace(
+        r#""variants": { "1": "com.example.Text" }"#,
+        r#""variants": {}"#,
+    );
+    let err = registry
+        .put_bundle("broken", missing_variants.as_bytes())
+        .unwrap_err();
+    assert!(matches!(err, StoreError::InvalidInput(_)), "{err:?}");
+}
+
+#[test]
+fn defaults_fill_missing_fields_only_when_requested() {
+    let dir = tempdir().expect("tempdir");
+    let mut registry = Registry::open(dir.path()).expect("open registry");
+
+    let bundle = r#"
+    {
+      "registry_version": 1,
+      "bundle_id": "defaults",
+      "types": {
+        "com.example.Message": {
+          "versions": {
+            "2": {
+              "fields": {
+                "1": { "name": "text", "type": "string" },
+                "2": { "name": "legacy_role", "type": "string", "deprecated": "use role" },
+                "3": { "name": "priority", "type": "u64", "optional": true, "default": 0 },
+                "4": { "name": "role", "type": "u8", "enum": "com.example.Role", "default": 1 },
+                "5": { "name": "draft", "type": "bool", "deprecated": true }
+              }
+            }
+          }
+        }
+      },
+      "enums": { "com.example.Role": { "1": "user", "2": "assistant" } }
+    }
+    "#;
+    registry
+        .put_bundle("defaults", bundle.as_bytes())
+        .expect("put bundle");
+    let desc = registry
+        .get_type_version("com.example.Message", 2)
+        .expect("descriptor");
+
+    // A turn written before priority and role existed.
+    let value = Value::Map(vec![(Value::from(1), Value::from("hi"))]);
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &value).expect("encode msgpack");
+
+    let plain = project_msgpack(&buf, desc, &registry, &default_options()).expect("project");
+    assert_eq!(plain.data, serde_json::json!({ "text": "hi" }));
+    assert!(plain.defaulted.is_none());
+
+    let options = RenderOptions {
+        apply_defaults: true,
+        ..default_options()
+    };
+    let filled = project_msgpack(&buf, desc, &registry, &options).expect("project");
+    // Defaults are rendered like payload values.
+    assert_eq!(
+        filled.data,
+        serde_json::json!({ "text": "hi", "priority": "0", "role": "user" })
+    );
+    assert_eq!(
+        filled.defaulted,
+        Some(vec!["priority".to_string(), "role".to_string()])
+    );
+
+    let served = type_version_to_json(desc);
+    assert_eq!(served["fields"]["2"]["deprecated"], "use role");
+    assert_eq!(served["fields"]["5"]["deprecated"], true);
+    assert_eq!(served["fields"]["3"]["default"], 0);
+    assert!(served["fields"]["1"].get("deprecated").is_none());
+}
+
+#[test]
+fn text_view_uses_template_or_dumps_fields() {
+    let dir = tempdir().expect("tempdir");
+    let mut registry = Registry::open(dir.path()).expect("open registry");
+
+    let bundle = r#"
+    {
+      "registry_version": 1,
+      "bundle_id": "text-test",
+      "types": {
+        "test:Chat": {
+          "versions": {
+            "1": {
+              "fields": {
+                "1": { "name": "role", "type": "string" },
+                "2": { "name": "text", "type": "string" },
+                "3": { "name": "tags", "type": "array", "items": "string" }
+              },
+              "renderer": {
+                "esm_url": "builtin:ChatRenderer",
+                "text_template": "**{{role}}**: {{text}} [{{tags.0}}]{{missing.path}}"
+              }
+            }
+          }
+        },
+        "test:Plain": {
+          "versions": {
+            "1": {
+              "fields": {
+                "1": { "name": "role", "type": "string" },
+                "2": { "name": "text", "type": "string" },
+                "3": { "name": "tags", "type": "array", "items": "string" }
+              }
+            }
+          }
+        }
+      },
+      "enums": {}
+    }
+    "#;
+    registry
+        .put_bundle("text-test", bundle.as_bytes())
+        .expect("put bundle");
+
+    let value = Value::Map(vec![
+        (Value::from(1), Value::from("user")),
+        (Value::from(2), Value::from("first line\nsecond line")),
+        (
+            Value::from(3),
+            Value::Array(vec![Value::from("a"), Value::from("b")]),
+        ),
+    ]);
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &value).unwrap();
+
+    let chat = registry.get_type_version("test:Chat", 1).unwrap();
+    let projected = project_msgpack(&buf, chat, &registry, &default_options()).unwrap();
+    let template = chat
+        .renderer
+        .as_ref()
+        .and_then(|r| r.text_template.as_deref());
+    assert_eq!(
+        render_text(&projected.data, template),
+        "**user**: first line\nsecond line [a]"
+    );
+
+    let plain = registry.get_type_version("test:Plain", 1).unwrap();
+    let projected = project_msgpack(&buf, plain, &registry, &default_options()).unwrap();
+    assert_eq!(
+        render_text(&projected.data, None),
+        "**role**: user\n**tags**:\n  - a\n  - b\n**text**:\n  first line\n  second line"
+    );
+}
+
+#[test]
+fn projection_cache_invalidated_by_new_referenced_version() {
+    let dir = tempdir().expect("tempdir");
+    let mut registry = Registry::open(dir.path()).expect("open registry");
+    registry.enable_projection_cache(ProjectionCacheConfig {
+        budget_bytes: 1024 * 1024,
+    });
+
+    let bundle = |nested_fields: &str| {
+        format!(
+            r#"{{
+              "registry_version": 1,
+              "bundle_id": "cache-test",
+              "types": {{
+                "test:Item": {{ "versions": {{ "1": {{ "fields": {{
+                  "1": {{ "name": "nested", "type": "ref", "ref": "test:Nested" }}
+                }} }} }} }},
+                "test:Nested": {{ "versions": {{ {nested_fields} }} }},
+                "test:Other": {{ "versions": {{ "1": {{ "fields": {{
+                  "1": {{ "name": "text", "type": "string" }}
+                }} }} }} }}
+              }}
+            }}"#
+        )
+    };
+    let v1 = r#""1": { "fields": { "1": { "name": "name", "type": "string" } } }"#;
+    registry
+        .put_bundle("cache-test", bundle(v1).as_bytes())
+        .expect("put bundle");
+
+    let encode = |value: Value| {
+        let mut buf = Vec::new();
+        rmpv::encode::write_value(&mut buf, &value).unwrap();
+        buf
+    };
+    let item = encode(Value::Map(vec![(
+        Value::from(1),
+        Value::Map(vec![
+            (Value::from(1), Value::from("bar")),
+            (Value::from(2), Value::from("label")),
+        ]),
+    )]));
+    let other = encode(Value::Map(vec![(Value::from(1), Value::from("hi"))]));
+
+    let options = default_options();
+    let project = |registry: &Registry, type_id: &str, hash: u8, payload: &[u8]| {
+        let desc = registry.get_type_version(type_id, 1).expect("descriptor");
+        project_cached(
+            &[hash; 32],
+            payload,
+            type_id,
+            desc,
+            registry,
+            &options,
+            &Deadline::none(),
+        )
+        .expect("project")
+        .data
+    };
+
+    let first = project(&registry, "test:Item", 1, &item);
+    assert_eq!(first["nested"], serde_json::json!({ "name": "bar" }));
+    assert_eq!(project(&registry, "test:Item", 1, &item), first);
+    project(&registry, "test:Other", 2, &other);
+    let stats = registry.projection_cache_stats();
+    assert_eq!((stats.entries, stats.hits, stats.misses), (2, 1, 2));
+
+    // A new version of the referenced type drops the projections using it.
+    let v2 = r#""1": { "fields": { "1": { "name": "name", "type": "string" } } },
+                "2": { "fields": { "1": { "name": "name", "type": "string" },
+                                   "2": { "name": "label", "type": "string" } } }"#;
+    let report = registry
+        .merge_bundle("cache-test", bundle(v2).as_bytes())
+        .expect("merge bundle");
+    assert!(report.conflicts.is_empty());
+    let stats = registry.projection_cache_stats();
+    assert_eq!((stats.entries, stats.invalidations), (1, 1));
+
+    let updated = project(&registry, "test:Item", 1, &item);
+    assert_eq!(
+        updated["nested"],
+        serde_json::json!({ "name": "bar", "label": "label" })
+    );
+    project(&registry, "test:Other", 2, &other);
+    assert_eq!(registry.projection_cache_stats().hits, 2);
+}
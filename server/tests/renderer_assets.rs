@@ -0,0 +1,117 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use base64::Engine;
+use cxdb_server::error::StoreError;
+use cxdb_server::registry::RendererSpec;
+use cxdb_server::renderer_assets::{RendererAssetConfig, RendererAssets};
+
+const MODULE: &str = "export default function Renderer() { return null; }";
+
+/// An origin serving MODULE at /renderer.js, counting requests.
+fn start_origin() -> (String, Arc<AtomicUsize>) {
+    let server = tiny_http::Server::http("127.0.0.1:0").expect("bind origin");
+    let origin = format!("http://{}", server.server_addr());
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&requests);
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let response = if request.url() == "/renderer.js" {
+                tiny_http::Response::from_string(MODULE)
+            } else {
+                tiny_http::Response::from_string("not found").with_status_code(404)
+            };
+            let _ = request.respond(response);
+        }
+    });
+    (origin, requests)
+}
+
+fn sha384(body: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA384, body);
+    format!(
+        "sha384-{}",
+        base64::engine::general_purpose::STANDARD.encode(digest)
+    )
+}
+
+fn spec(esm_url: &str, integrity: Option<String>) -> RendererSpec {
+    RendererSpec {
+        esm_url: esm_url.to_string(),
+        component: None,
+        integrity,
+        text_template: None,
+    }
+}
+
+#[test]
+fn modules_are_verified_cached_and_refetched_on_spec_change() {
+    let (origin, requests) = start_origin();
+    let assets = RendererAssets::new(RendererAssetConfig {
+        allowed_origins: vec![origin.clone()],
+        max_bytes: 1024,
+        require_integrity: true,
+    });
+    let url = format!("{origin}/renderer.js");
+
+    let good = spec(&url, Some(sha384(MODULE.as_bytes())));
+    assert!(assets.proxies(&good));
+    let asset = assets.asset("app.Message", &good).unwrap();
+    assert_eq!(asset.body.as_slice(), MODULE.as_bytes());
+    assert_eq!(assets.asset("app.Message", &good).unwrap().etag, asset.etag);
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    // A new spec for the type refetches and checks the new integrity.
+    let tampered = spec(&url, Some(sha384(b"something else")));
+    assert!(matches!(
+        assets.asset("app.Message", &tampered),
+        Err(StoreError::Corrupt(_))
+    ));
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+    let stats = assets.stats();
+    assert_eq!(
+        (stats.hits, stats.fetches, stats.integrity_failures),
+        (1, 2, 1)
+    );
+
+    let unpinned = spec(&url, None);
+    assert!(!assets.proxies(&unpinned));
+    assert!(matches!(
+        assets.asset("app.Other", &unpinned),
+        Err(StoreError::InvalidInput(_))
+    ));
+    let elsewhere = spec(
+        "https://cdn.example.com/renderer.js",
+        Some(sha384(MODULE.as_bytes())),
+    );
+    assert!(matches!(
+        assets.asset("app.Other", &elsewhere),
+        Err(StoreError::PermissionDenied(_))
+    ));
+    assert!(matches!(
+        assets.asset("app.Other", &spec("builtin:MessageRenderer", None)),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn oversized_modules_are_refused() {
+    let (origin, _) = start_origin();
+    let assets = RendererAssets::new(RendererAssetConfig {
+        allowed_origins: vec![origin.clone()],
+        max_bytes: 8,
+        require_integrity: false,
+    });
+    let unpinned = spec(&format!("{origin}/renderer.js"), None);
+    assert!(assets.proxies(&unpinned));
+    assert!(matches!(
+        assets.asset("app.Message", &unpinned),
+        Err(StoreError::InvalidInput(_))
+    ));
+}
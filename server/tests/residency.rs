@@ -0,0 +1,106 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use cxdb_server::keys::{EncryptionConfig, EncryptionMode};
+use cxdb_server::metadata_overrides::MetadataPatch;
+use cxdb_server::residency::{resident_contexts, ResidencyConfig};
+use cxdb_server::store::Store;
+use tempfile::tempdir;
+
+fn labeled_context(store: &mut Store, labels: &[&str]) -> u64 {
+    let context_id = store.create_context(0).unwrap().context_id;
+    common::append_typed(store, context_id, "com.example.Turn", b"hello");
+    let labels: Vec<String> = labels.iter().map(|l| l.to_string()).collect();
+    store
+        .apply_metadata_patch(context_id, &MetadataPatch::default(), &labels)
+        .unwrap();
+    context_id
+}
+
+#[test]
+fn residency_labels_partition_and_refuse_s3_sync() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let plain = labeled_context(&mut store, &["team:payments"]);
+    let eu = labeled_context(&mut store, &["residency:eu", "team:payments"]);
+
+    let contexts = resident_contexts(&mut store);
+    let residencies = |id: u64| {
+        contexts
+            .iter()
+            .find(|c| c.context_id == id)
+            .map(|c| c.residencies.clone())
+            .unwrap()
+    };
+    assert!(residencies(plain).is_empty());
+    assert_eq!(residencies(eu), vec!["eu".to_string()]);
+
+    // Without a destination for eu, startup is refused and nothing of the
+    // store is copied whole.
+    let unconfigured = ResidencyConfig::default().plan(contexts.clone());
+    assert!(!unconfigured.raw_files);
+    let err = unconfigured.refuse_excluded().unwrap_err().to_string();
+    assert!(err.contains(&format!("context {eu}")), "{err}");
+
+    // With one, eu is archived to its own bucket and the rest to the
+    // primary bucket.
+    let config = ResidencyConfig::parse("", "eu=cxdb-eu/prod@eu-central-1", "us-west-2").unwrap();
+    let plan = config.plan(contexts.clone());
+    plan.refuse_excluded().unwrap();
+    assert!(!plan.raw_files);
+    let primary: Vec<u64> = plan.primary.iter().map(|c| c.context_id).collect();
+    assert_eq!(primary, vec![plain]);
+    let targeted: Vec<u64> = plan.targets["eu"].iter().map(|c| c.context_id).collect();
+    assert_eq!(targeted, vec![eu]);
+
+    // A primary bucket that may hold eu takes the store files whole.
+    let plan = ResidencyConfig::parse("eu", "", "us-west-2")
+        .unwrap()
+        .plan(contexts);
+    assert!(plan.raw_files);
+}
+
+#[test]
+fn sealed_contexts_are_never_archived() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let plain = labeled_context(&mut store, &[]);
+    store
+        .enable_encryption(&EncryptionConfig {
+            mode: EncryptionMode::Context,
+            master_key: [9u8; 32],
+        })
+        .expect("enable encryption");
+    let sealed = labeled_context(&mut store, &[]);
+    let head = store.get_head(sealed).unwrap().head_turn_id;
+    // A fork that has not written yet shows its parent's sealed turn.
+    let fork = store.fork_context(head).unwrap().context_id;
+    let eu = labeled_context(&mut store, &["residency:eu"]);
+
+    assert!(!store.is_context_sealed(plain));
+    assert!(store.is_context_sealed(sealed));
+    assert!(store.is_context_sealed(fork));
+
+    // Store files carry sealed payloads as they are stored.
+    let contexts = resident_contexts(&mut store);
+    let config = ResidencyConfig::parse("eu", "", "us-west-2").unwrap();
+    assert!(config.plan(contexts.clone()).raw_files);
+
+    // Archives would hold them in the clear, so they are excluded.
+    let config = ResidencyConfig::parse("", "eu=cxdb-eu", "us-west-2").unwrap();
+    let plan = config.plan(contexts);
+    assert_eq!(
+        plan.primary
+            .iter()
+            .map(|c| c.context_id)
+            .collect::<Vec<_>>(),
+        vec![plain]
+    );
+    assert!(plan.targets.is_empty());
+    let mut excluded: Vec<u64> = plan.excluded.iter().map(|c| c.context_id).collect();
+    excluded.sort();
+    assert_eq!(excluded, vec![sealed, fork, eu]);
+    assert!(plan.refuse_excluded().is_err());
+}
@@ -0,0 +1,186 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::append;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use cxdb_server::error::{Result, StoreError};
+use cxdb_server::hooks::{
+    rollover_digest, HookTrigger, Summarizer, SummaryHookConfig, SummaryRequest, SummaryResponse,
+    SUMMARY_TYPE_ID,
+};
+use cxdb_server::registry::Registry;
+use cxdb_server::rollovers::ROLLOVER_SPAWN_REASON;
+use cxdb_server::store::Store;
+use rmpv::Value;
+use tempfile::tempdir;
+
+fn payload(text: &str, metadata: Option<(&str, &str)>) -> Vec<u8> {
+    let mut fields = vec![(Value::from(1), Value::from(text))];
+    if let Some((tag, title)) = metadata {
+        fields.push((
+            Value::from(30),
+            Value::Map(vec![
+                (Value::from(1), Value::from(tag)),
+                (Value::from(2), Value::from(title)),
+            ]),
+        ));
+    }
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &Value::Map(fields)).expect("encode");
+    buf
+}
+
+fn digest(text: &str) -> SummaryResponse {
+    SummaryResponse {
+        summary: text.to_string(),
+        model: None,
+    }
+}
+
+struct FixedSummarizer(Mutex<Vec<SummaryRequest>>);
+
+impl Summarizer for FixedSummarizer {
+    fn summarize(&self, request: &SummaryRequest) -> Result<Option<SummaryResponse>> {
+        self.0.lock().unwrap().push(request.clone());
+        Ok(Some(digest("hook digest")))
+    }
+}
+
+#[test]
+fn rollover_seeds_a_linked_context_with_the_digest() {
+    let dir = tempdir().expect("tempdir");
+    let (old, new, through) = {
+        let mut store = Store::open(dir.path()).expect("open store");
+        let old = store.create_context(0).expect("create").context_id;
+        append(
+            &mut store,
+            old,
+            &payload("hi", Some(("ci", "Fix the build"))),
+        );
+        let through = append(&mut store, old, &payload("more", None));
+
+        let empty = store.create_context(0).expect("create").context_id;
+        assert!(matches!(
+            store.rollover_context(empty, &digest("x"), "client", None),
+            Err(StoreError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            store.rollover_context(old, &digest("  "), "client", None),
+            Err(StoreError::InvalidInput(_))
+        ));
+
+        let rollover = store
+            .rollover_context(old, &digest("User is fixing CI"), "client", None)
+            .expect("rollover");
+        assert_eq!(rollover.from_context_id, old);
+        assert_eq!(rollover.summarized_through_turn_id, through);
+        let new = rollover.to_context_id;
+        let err = store
+            .rollover_context(old, &digest("again"), "client", None)
+            .unwrap_err();
+        assert!(err.to_string().contains("already rolled over"), "{err}");
+
+        let first = store
+            .get_turn(rollover.digest_turn_id, false)
+            .expect("turn");
+        assert_eq!(first.record.depth, 0);
+        assert_eq!(first.meta.declared_type_id, SUMMARY_TYPE_ID);
+        let metadata = store.get_context_metadata(new).expect("metadata");
+        assert_eq!(metadata.client_tag.as_deref(), Some("ci"));
+        assert_eq!(metadata.title.as_deref(), Some("Fix the build"));
+        let provenance = metadata.provenance.expect("provenance");
+        assert_eq!(provenance.parent_context_id, Some(old));
+        assert_eq!(provenance.root_context_id, Some(old));
+        assert_eq!(
+            provenance.spawn_reason.as_deref(),
+            Some(ROLLOVER_SPAWN_REASON)
+        );
+        (old, new, through)
+    };
+
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    assert_eq!(
+        store.rolled_over_to(old).map(|r| r.to_context_id),
+        Some(new)
+    );
+    assert_eq!(
+        store
+            .rolled_over_from(new)
+            .map(|r| r.summarized_through_turn_id),
+        Some(through)
+    );
+    assert!(store.rolled_over_to(new).is_none());
+
+    // The tree starts at the original context from either end.
+    let child = store
+        .rollover_context(new, &digest("still fixing CI"), "client", None)
+        .expect("second rollover")
+        .to_context_id;
+    for id in [old, new, child] {
+        let tree = store.context_tree(id).expect("tree");
+        assert!(!tree.truncated);
+        assert_eq!(tree.root.context_id, old);
+        assert_eq!(tree.root.rolled_over_to, Some(new));
+        let next = &tree.root.children[0];
+        assert_eq!(
+            (next.context_id, next.rolled_over_from, next.rolled_over_to),
+            (new, Some(old), Some(child))
+        );
+        assert_eq!(next.children[0].spawn_reason.as_deref(), Some("rollover"));
+    }
+    let provenance = store
+        .get_context_metadata(child)
+        .and_then(|m| m.provenance)
+        .expect("provenance");
+    assert_eq!(
+        (provenance.parent_context_id, provenance.root_context_id),
+        (Some(new), Some(old))
+    );
+}
+
+#[test]
+fn hook_digests_cover_the_head_at_request_time() {
+    let dir = tempdir().expect("tempdir");
+    let registry = Arc::new(Mutex::new(
+        Registry::open(&dir.path().join("registry")).expect("registry"),
+    ));
+    let store = Arc::new(Mutex::new(Store::open(dir.path()).expect("open store")));
+    let context_id = {
+        let mut store = store.lock().unwrap();
+        let context_id = store.create_context(0).expect("create").context_id;
+        append(&mut store, context_id, &payload("hello", None));
+        context_id
+    };
+    let head = store.lock().unwrap().get_head(context_id).unwrap();
+
+    let config = SummaryHookConfig {
+        url: "http://127.0.0.1:9/summarize".to_string(),
+        name: "test-hook".to_string(),
+        idle_timeout: None,
+        turn_threshold: None,
+        recent_turns: 10,
+        request_timeout: Duration::from_secs(1),
+    };
+    let summarizer = FixedSummarizer(Mutex::new(Vec::new()));
+    let (through, response) = rollover_digest(&config, &summarizer, &store, &registry, context_id)
+        .expect("digest")
+        .expect("some digest");
+    assert_eq!(through, head.head_turn_id);
+    assert_eq!(response.summary, "hook digest");
+    let requests = summarizer.0.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].trigger, HookTrigger::Rollover);
+    assert_eq!(requests[0].turns.len(), 1);
+
+    let rollover = store
+        .lock()
+        .unwrap()
+        .rollover_context(context_id, &response, &config.name, Some(through))
+        .expect("rollover");
+    assert_eq!(rollover.generator, "test-hook");
+    assert_eq!(rollover.summarized_through_turn_id, through);
+}
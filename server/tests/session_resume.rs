@@ -0,0 +1,92 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use std::thread;
+use std::time::Duration;
+
+use cxdb_server::metrics::{SessionResumeConfig, SessionTracker};
+
+fn tracker(grace: Duration) -> SessionTracker {
+    SessionTracker::with_resume(SessionResumeConfig { grace })
+}
+
+#[test]
+fn resumed_session_keeps_its_contexts_live() {
+    let tracker = tracker(Duration::from_secs(30));
+    tracker.register(1, "agent".into(), Some("10.0.0.1:5000".into()), None);
+    tracker.add_context(1, 100);
+    let token = tracker.issue_resume_token(1).unwrap();
+
+    // The connection drops; the session stays, detached.
+    assert_eq!(tracker.disconnect(1, 1), None);
+    assert!(tracker.is_context_live(100));
+    assert!(tracker.get_active_sessions()[0].detached_at.is_some());
+
+    assert!(tracker.resume(1, b"bogus", 2, None, None).is_none());
+    let session = tracker
+        .resume(1, &token, 2, None, Some("10.0.0.1:5001".into()))
+        .unwrap();
+    assert_eq!(session.client_tag, "agent");
+    assert_eq!(session.contexts_created, vec![100]);
+    assert_eq!(session.detached_at, None);
+    assert_eq!(tracker.get_peer_addr(1).as_deref(), Some("10.0.0.1:5001"));
+
+    // Issuing a new token retires the old one.
+    let next = tracker.issue_resume_token(1).unwrap();
+    assert_ne!(next, token);
+    assert!(tracker.resume(1, &token, 3, None, None).is_none());
+
+    // The first connection noticing the drop late does not end the session.
+    assert_eq!(tracker.disconnect(1, 1), None);
+    assert_eq!(tracker.get_active_sessions()[0].detached_at, None);
+    assert!(tracker.is_context_live(100));
+}
+
+#[test]
+fn resume_requires_the_same_principal() {
+    let tracker = tracker(Duration::from_secs(30));
+    tracker.register(1, "agent".into(), None, Some("spiffe://a".into()));
+    let token = tracker.issue_resume_token(1).unwrap();
+    assert_eq!(tracker.disconnect(1, 1), None);
+
+    assert!(tracker.resume(1, &token, 2, None, None).is_none());
+    assert!(tracker
+        .resume(1, &token, 2, Some("spiffe://b"), None)
+        .is_none());
+    assert!(tracker
+        .resume(1, &token, 2, Some("spiffe://a"), None)
+        .is_some());
+}
+
+#[test]
+fn detached_sessions_expire_after_the_grace_window() {
+    let tracker = tracker(Duration::from_millis(20));
+    tracker.register(1, "agent".into(), None, None);
+    tracker.add_context(1, 100);
+    let token = tracker.issue_resume_token(1).unwrap();
+    assert_eq!(tracker.disconnect(1, 1), None);
+    assert!(tracker.expire_detached().is_empty());
+
+    thread::sleep(Duration::from_millis(40));
+    assert!(tracker.resume(1, &token, 2, None, None).is_none());
+    let expired = tracker.expire_detached();
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].contexts_created, vec![100]);
+    assert!(!tracker.is_context_live(100));
+    assert!(tracker.get_active_sessions().is_empty());
+}
+
+#[test]
+fn sessions_end_with_their_connection_without_resumption() {
+    let plain = SessionTracker::new();
+    plain.register(1, "agent".into(), None, None);
+    plain.add_context(1, 100);
+    assert_eq!(plain.issue_resume_token(1), None);
+    assert_eq!(plain.disconnect(1, 1), Some(vec![100]));
+    assert!(!plain.is_context_live(100));
+
+    // Resumable trackers end sessions that never asked for a token.
+    let resumable = tracker(Duration::from_secs(30));
+    resumable.register(2, "legacy".into(), None, None);
+    assert_eq!(resumable.disconnect(2, 2), Some(Vec::new()));
+}
@@ -0,0 +1,142 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use cxdb_server::error::{Result, StoreError};
+use cxdb_server::events::{EventBus, StoreEvent};
+use cxdb_server::sinks::{
+    start_sink, EventPublisher, Outbox, SinkConfig, SinkFormat, SinkKind, SinkMessage,
+};
+use serde_json::Value as JsonValue;
+use tempfile::tempdir;
+
+/// Fails its first `failures` batches, then records what it is sent.
+struct FlakyPublisher {
+    failures: u32,
+    sent: Arc<Mutex<Vec<SinkMessage>>>,
+}
+
+impl EventPublisher for FlakyPublisher {
+    fn publish(&mut self, messages: &[SinkMessage]) -> Result<()> {
+        if self.failures > 0 {
+            self.failures -= 1;
+            return Err(StoreError::Io(std::io::Error::other("broker unavailable")));
+        }
+        self.sent.lock().unwrap().extend_from_slice(messages);
+        Ok(())
+    }
+}
+
+fn config() -> SinkConfig {
+    SinkConfig {
+        kind: SinkKind::Kafka,
+        servers: "unused:9092".to_string(),
+        format: SinkFormat::Json,
+        events: ["context_created".to_string(), "turn_appended".to_string()].into(),
+        topic_template: "cxdb.{event_type}".to_string(),
+        topics: HashMap::from([("turn_appended".to_string(), "turns".to_string())]),
+        batch_size: 16,
+        max_backoff: Duration::from_millis(20),
+    }
+}
+
+#[test]
+fn outbox_events_are_redelivered_until_acknowledged() {
+    let dir = tempdir().expect("tempdir");
+    let config = config();
+    let outbox = Arc::new(
+        Outbox::open(dir.path(), config.kind.as_str(), config.events.clone()).expect("outbox"),
+    );
+    let bus = EventBus::new();
+    bus.attach_outbox(Arc::clone(&outbox));
+
+    bus.publish(StoreEvent::ContextCreated {
+        context_id: "7".to_string(),
+        session_id: "1".to_string(),
+        client_tag: "agent".to_string(),
+        created_at: 1,
+        external_id: None,
+    });
+    bus.publish(StoreEvent::ClientConnected {
+        session_id: "1".to_string(),
+        client_tag: "agent".to_string(),
+    });
+    bus.publish(StoreEvent::TurnAppended {
+        context_id: "7".to_string(),
+        turn_id: "100".to_string(),
+        parent_turn_id: "0".to_string(),
+        depth: 0,
+        declared_type_id: None,
+        declared_type_version: None,
+    });
+    let stats = bus.stats().sink.expect("sink stats");
+    assert_eq!((stats.head_seq, stats.pending), (2, 2));
+
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let publisher = FlakyPublisher {
+        failures: 2,
+        sent: Arc::clone(&sent),
+    };
+    let _thread = start_sink(config, Arc::clone(&outbox), Box::new(publisher));
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while outbox.stats().pending > 0 {
+        assert!(Instant::now() < deadline, "outbox never drained");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let stats = outbox.stats();
+    assert_eq!((stats.acked_seq, stats.published_total), (2, 2));
+    assert_eq!(stats.failures_total, 2);
+
+    let sent = sent.lock().unwrap();
+    let routed: Vec<(&str, &str, &str)> = sent
+        .iter()
+        .map(|m| (m.topic.as_str(), m.key.as_str(), m.id.as_str()))
+        .collect();
+    assert_eq!(
+        routed,
+        [
+            ("cxdb.context_created", "7", "cxdb-1"),
+            ("turns", "7", "cxdb-2")
+        ]
+    );
+    let envelope: JsonValue = serde_json::from_slice(&sent[1].payload).expect("json");
+    assert_eq!(envelope["schema_version"], 1);
+    assert_eq!(envelope["seq"], 2);
+    assert_eq!(envelope["event_type"], "turn_appended");
+    assert_eq!(envelope["data"]["turn_id"], "100");
+}
+
+#[test]
+fn events_recorded_after_a_torn_write_survive_reopening() {
+    let dir = tempdir().expect("tempdir");
+    let config = config();
+    let created = |context_id: &str| StoreEvent::ContextCreated {
+        context_id: context_id.to_string(),
+        session_id: "1".to_string(),
+        client_tag: "agent".to_string(),
+        created_at: 1,
+        external_id: None,
+    };
+    let open = || Outbox::open(dir.path(), config.kind.as_str(), config.events.clone());
+
+    let outbox = open().expect("outbox");
+    outbox.record(&created("1")).expect("record");
+    drop(outbox);
+    // A crash mid-write leaves part of a line, not necessarily valid UTF-8.
+    let path = dir.path().join("sink_outbox.jsonl");
+    let mut data = std::fs::read(&path).unwrap();
+    data.extend_from_slice(b"{\"seq\":2,\"event_type\":\"context_cr\xc3");
+    std::fs::write(&path, data).unwrap();
+
+    let outbox = open().expect("outbox with a torn tail");
+    assert_eq!(outbox.stats().pending, 1);
+    outbox.record(&created("2")).expect("record");
+    drop(outbox);
+
+    let stats = open().expect("outbox").stats();
+    assert_eq!((stats.head_seq, stats.pending), (2, 2));
+}
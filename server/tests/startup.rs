@@ -0,0 +1,70 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use cxdb_server::metadata_cache::MetadataCacheConfig;
+use cxdb_server::startup::{warm_up, Readiness, StartupPhase};
+use cxdb_server::store::Store;
+use tempfile::tempdir;
+
+fn count(store: &mut Store, query: &str) -> usize {
+    store
+        .search_contexts(query, &HashSet::new(), None)
+        .expect("search")
+        .total_count
+}
+
+#[test]
+fn index_build_runs_in_batches() {
+    let dir = tempdir().expect("tempdir");
+    let mut store =
+        Store::open_unindexed(dir.path(), MetadataCacheConfig::default()).expect("open store");
+    for _ in 0..5 {
+        common::create_tagged_context(&mut store, "agent");
+    }
+
+    let mut build = store.begin_index_build();
+    assert_eq!((build.indexed(), build.total()), (0, 5));
+    assert_eq!(count(&mut store, r#"tag = "agent""#), 0);
+
+    assert!(!store.continue_index_build(&mut build, 2));
+    assert_eq!(build.indexed(), 2);
+    assert_eq!(count(&mut store, r#"tag = "agent""#), 2);
+
+    assert!(store.continue_index_build(&mut build, 10));
+    assert!(build.is_done());
+    assert_eq!(count(&mut store, r#"tag = "agent""#), 5);
+}
+
+#[test]
+fn warm_up_reports_progress_and_becomes_ready() {
+    let dir = tempdir().expect("tempdir");
+    let mut store =
+        Store::open_unindexed(dir.path(), MetadataCacheConfig::default()).expect("open store");
+    for i in 0..3 {
+        common::create_tagged_context(&mut store, &format!("tag-{i}"));
+    }
+    let store = Mutex::new(store);
+
+    let readiness = Readiness::new();
+    let status = readiness.status();
+    assert_eq!(
+        (status.status, status.phase),
+        ("warming", StartupPhase::OpeningStore)
+    );
+    assert!((1..=60).contains(&readiness.retry_after_secs()));
+
+    warm_up(&store, &readiness);
+    let status = readiness.status();
+    assert!(readiness.is_ready());
+    assert_eq!(
+        (status.status, status.phase),
+        ("ready", StartupPhase::Ready)
+    );
+    assert_eq!((status.contexts_indexed, status.contexts_total), (3, 3));
+    assert_eq!(count(&mut store.lock().unwrap(), r#"tag = "tag-1""#), 1);
+}
@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use blake3::Hasher;
+use cxdb_server::error::StoreError;
+use cxdb_server::read_marks::{ReadMark, ReadMarks};
 use cxdb_server::store::Store;
 use tempfile::tempdir;
 
@@ -59,3 +61,624 @@ fn append_and_fork() {
     assert_eq!(last.len(), 2);
     assert_eq!(last[0].record.turn_id, first.turn_id);
 }
+
+#[test]
+fn identical_payloads_are_stored_once_and_reference_counted() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+
+    let prompt = vec![b'x'; 4096];
+    let hash = *blake3::hash(&prompt).as_bytes();
+    for _ in 0..3 {
+        let ctx = store.create_context(0).expect("create context");
+        store
+            .append_turn(
+                ctx.context_id,
+                0,
+                "com.example.SystemPrompt".to_string(),
+                1,
+                1,
+                0,
+                prompt.len() as u32,
+                hash,
+                &prompt,
+            )
+            .expect("append");
+    }
+
+    assert_eq!(store.stats().blobs_total, 1);
+    assert_eq!(store.payload_ref_count(&hash), 3);
+    let dedup = store.dedup_stats();
+    assert_eq!((dedup.references, dedup.unique_blobs), (3, 1));
+    assert_eq!(dedup.hits(), 2);
+    assert_eq!(dedup.saved_bytes(), 2 * 4096);
+}
+
+#[test]
+fn overlay_attach_builds_on_the_inherited_snapshot() {
+    use cxdb_server::deadline::Deadline;
+    use cxdb_server::fs_store::{encode_tree_entries, OverlayChange, TreeEntry};
+
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let ctx = store.create_context(0).expect("create context");
+    let append = |store: &mut Store, parent: u64, payload: &[u8]| {
+        store
+            .append_turn(
+                ctx.context_id,
+                parent,
+                "com.example.Test".to_string(),
+                1,
+                1,
+                0,
+                payload.len() as u32,
+                *blake3::hash(payload).as_bytes(),
+                payload,
+            )
+            .expect("append")
+            .0
+            .turn_id
+    };
+    let file = |content: &[u8]| TreeEntry {
+        name: String::new(),
+        kind: 0,
+        mode: 0o644,
+        size: content.len() as u64,
+        hash: blake3::hash(content).as_bytes().to_vec(),
+    };
+
+    let first = append(&mut store, 0, b"one");
+    let readme = TreeEntry {
+        name: "README".into(),
+        ..file(b"v1")
+    };
+    let root = encode_tree_entries(&[readme]).unwrap();
+    store
+        .put_blob(*blake3::hash(b"v1").as_bytes(), b"v1", None, None)
+        .unwrap();
+    let root_hash = *blake3::hash(&root).as_bytes();
+    store.put_blob(root_hash, &root, None, None).unwrap();
+    store.attach_fs(first, root_hash).unwrap();
+
+    // The second turn inherits the first snapshot; the overlay only sends the new file.
+    let second = append(&mut store, first, b"two");
+    let changes = [OverlayChange {
+        path: "docs/guide.md".into(),
+        entry: Some(file(b"guide")),
+    }];
+    let result = store
+        .attach_fs_overlay(second, None, &[b"guide"], &changes)
+        .unwrap();
+    assert_eq!(result.trees_written, 2);
+    assert_eq!(store.get_fs_root_direct(second), Some(result.root_hash));
+
+    let none = Deadline::none();
+    let (content, _) = store.get_fs_file(second, "README", false, &none).unwrap();
+    assert_eq!(content, b"v1");
+    let (content, _) = store
+        .get_fs_file(second, "docs/guide.md", false, &none)
+        .unwrap();
+    assert_eq!(content, b"guide");
+    assert!(store
+        .get_fs_file(first, "docs/guide.md", false, &none)
+        .is_err());
+}
+
+#[test]
+fn snapshot_metadata_is_inherited_and_contexts_with_fs_are_searchable() {
+    use std::collections::HashSet;
+
+    use cxdb_server::fs_store::{encode_tree_entries, SnapshotMeta};
+
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let append = |store: &mut Store, context_id: u64, payload: &[u8]| {
+        store
+            .append_turn(
+                context_id,
+                0,
+                "com.example.Test".to_string(),
+                1,
+                1,
+                0,
+                payload.len() as u32,
+                *blake3::hash(payload).as_bytes(),
+                payload,
+            )
+            .expect("append")
+            .0
+            .turn_id
+    };
+
+    let with_fs = store.create_context(0).unwrap().context_id;
+    let without_fs = store.create_context(0).unwrap().context_id;
+    let first = append(&mut store, with_fs, b"one");
+    append(&mut store, without_fs, b"other");
+
+    let root = encode_tree_entries(&[]).unwrap();
+    let root_hash = *blake3::hash(&root).as_bytes();
+    store.put_blob(root_hash, &root, None, None).unwrap();
+    let meta = SnapshotMeta {
+        captured_at: Some(1_700_000_000_000),
+        base_path: Some("/work/repo".into()),
+        git_commit: Some("9fceb02".into()),
+        git_branch: Some("main".into()),
+        git_dirty: Some(true),
+        total_entries: Some(0),
+    };
+    store
+        .attach_fs_with_meta(first, root_hash, Some(meta.clone()))
+        .unwrap();
+
+    // Later turns and forks see the snapshot and its metadata.
+    let second = append(&mut store, with_fs, b"two");
+    let snapshot = store.get_fs_snapshot(second).unwrap();
+    assert_eq!(snapshot.attached_turn_id, first);
+    assert_eq!(snapshot.meta, Some(meta));
+    let fork = store.fork_context(second).unwrap().context_id;
+
+    let live = HashSet::new();
+    let found = store.search_contexts("has_fs = true", &live, None).unwrap();
+    assert_eq!(found.context_ids, vec![fork, with_fs]);
+    let found = store
+        .search_contexts("has_fs = false", &live, None)
+        .unwrap();
+    assert_eq!(found.context_ids, vec![without_fs]);
+
+    // Re-attaching without metadata drops the old metadata.
+    store.attach_fs(first, root_hash).unwrap();
+    assert_eq!(store.get_fs_snapshot(second).unwrap().meta, None);
+}
+
+#[test]
+fn contexts_are_searchable_by_snapshot_count_and_size() {
+    use std::collections::HashSet;
+
+    use cxdb_server::fs_store::{encode_tree_entries, EntryKind, TreeEntry};
+
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let append = |store: &mut Store, context_id: u64, payload: &[u8]| {
+        store
+            .append_turn(
+                context_id,
+                0,
+                "com.example.Test".to_string(),
+                1,
+                1,
+                0,
+                payload.len() as u32,
+                *blake3::hash(payload).as_bytes(),
+                payload,
+            )
+            .expect("append")
+            .0
+            .turn_id
+    };
+    // A snapshot of `size` bytes: one file at the root and one in `src/`.
+    let snapshot = |store: &mut Store, size: u64| {
+        let content = format!("content of {size}");
+        let hash = *blake3::hash(content.as_bytes()).as_bytes();
+        store
+            .put_blob(hash, content.as_bytes(), None, None)
+            .unwrap();
+        let file = |name: &str, size: u64| TreeEntry {
+            name: name.into(),
+            kind: EntryKind::File as u8,
+            mode: 0o644,
+            size,
+            hash: hash.to_vec(),
+        };
+        let sub = encode_tree_entries(&[file("lib.rs", size / 2)]).unwrap();
+        let sub_hash = *blake3::hash(&sub).as_bytes();
+        store.put_blob(sub_hash, &sub, None, None).unwrap();
+        let root = encode_tree_entries(&[
+            file("README", size - size / 2),
+            TreeEntry {
+                name: "src".into(),
+                kind: EntryKind::Directory as u8,
+                mode: 0o755,
+                size: 0,
+                hash: sub_hash.to_vec(),
+            },
+        ])
+        .unwrap();
+        let root_hash = *blake3::hash(&root).as_bytes();
+        store.put_blob(root_hash, &root, None, None).unwrap();
+        root_hash
+    };
+
+    let ctx = store.create_context(0).unwrap().context_id;
+    let plain = store.create_context(0).unwrap().context_id;
+    append(&mut store, plain, b"plain");
+    let first = append(&mut store, ctx, b"one");
+    let small = snapshot(&mut store, 1_000);
+    store.attach_fs(first, small).unwrap();
+    let second = append(&mut store, ctx, b"two");
+    let fork = store.fork_context(second).unwrap().context_id;
+    let large = snapshot(&mut store, 200_000_000);
+    store.attach_fs(second, large).unwrap();
+    let third = append(&mut store, ctx, b"three");
+    store.attach_fs(third, small).unwrap();
+
+    let live = HashSet::new();
+    let search =
+        |store: &mut Store, query: &str| store.search_contexts(query, &live, None).unwrap();
+    assert_eq!(
+        search(&mut store, "fs_bytes > 100000000").context_ids,
+        vec![fork, ctx]
+    );
+    assert_eq!(search(&mut store, "fs_count >= 3").context_ids, vec![ctx]);
+    assert_eq!(search(&mut store, "fs_count = 2").context_ids, vec![fork]);
+    assert_eq!(search(&mut store, "fs_count = 0").context_ids, vec![plain]);
+    assert_eq!(
+        search(&mut store, "fs_bytes < 5000").context_ids,
+        vec![plain]
+    );
+
+    // A rebuild recomputes the aggregates from the attachments.
+    let mut build = store.begin_index_build();
+    store.continue_index_build(&mut build, usize::MAX);
+    assert_eq!(
+        search(&mut store, "fs_bytes >= 200000000 AND fs_count = 3").context_ids,
+        vec![ctx]
+    );
+}
+
+#[test]
+fn symlink_targets_round_trip_through_snapshots() {
+    use cxdb_server::deadline::Deadline;
+    use cxdb_server::fs_store::{EntryKind, OverlayChange, TreeEntry};
+
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let ctx = store.create_context(0).expect("create context");
+    let payload = b"turn";
+    let (turn, _) = store
+        .append_turn(
+            ctx.context_id,
+            0,
+            "com.example.Test".to_string(),
+            1,
+            1,
+            0,
+            payload.len() as u32,
+            *blake3::hash(payload).as_bytes(),
+            payload,
+        )
+        .expect("append");
+
+    let script = b"#!/bin/sh\necho hi\n";
+    let target = b"bin/run.sh";
+    let changes = [
+        OverlayChange {
+            path: "bin/run.sh".into(),
+            entry: Some(TreeEntry {
+                name: String::new(),
+                kind: EntryKind::File as u8,
+                mode: 0o755,
+                size: script.len() as u64,
+                hash: blake3::hash(script).as_bytes().to_vec(),
+            }),
+        },
+        OverlayChange {
+            path: "run".into(),
+            entry: Some(TreeEntry {
+                name: String::new(),
+                kind: EntryKind::Symlink as u8,
+                mode: 0o777,
+                size: target.len() as u64,
+                hash: blake3::hash(target).as_bytes().to_vec(),
+            }),
+        },
+    ];
+    store
+        .attach_fs_overlay(turn.turn_id, None, &[script, target], &changes)
+        .unwrap();
+
+    let none = Deadline::none();
+    let entries = store
+        .list_fs_entries(turn.turn_id, "", false, &none)
+        .unwrap();
+    let link = entries.iter().find(|e| e.name == "run").unwrap();
+    assert_eq!(link.kind_enum(), EntryKind::Symlink);
+    assert_eq!(
+        store.read_fs_symlink(turn.turn_id, link).unwrap(),
+        "bin/run.sh"
+    );
+
+    let (content, entry) = store
+        .get_fs_file(turn.turn_id, "run", false, &none)
+        .unwrap();
+    assert_eq!(
+        (content.as_slice(), entry.kind_enum()),
+        (&target[..], EntryKind::Symlink)
+    );
+    let (content, entry) = store.get_fs_file(turn.turn_id, "run", true, &none).unwrap();
+    assert_eq!(content, script);
+    assert_eq!(entry.mode, 0o755);
+}
+
+#[test]
+fn unreferenced_blobs_are_swept_once_they_outlive_a_collection() {
+    use cxdb_server::deadline::Deadline;
+    use cxdb_server::fs_store::{EntryKind, OverlayChange, TreeEntry};
+
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let ctx = store.create_context(0).expect("create context");
+    let payload = b"turn";
+    let (turn, _) = store
+        .append_turn(
+            ctx.context_id,
+            0,
+            "com.example.Test".to_string(),
+            1,
+            1,
+            0,
+            payload.len() as u32,
+            *blake3::hash(payload).as_bytes(),
+            payload,
+        )
+        .expect("append");
+
+    let file = |content: &[u8]| {
+        vec![OverlayChange {
+            path: "notes.txt".into(),
+            entry: Some(TreeEntry {
+                name: String::new(),
+                kind: EntryKind::File as u8,
+                mode: 0o644,
+                size: content.len() as u64,
+                hash: blake3::hash(content).as_bytes().to_vec(),
+            }),
+        }]
+    };
+    let first = store
+        .attach_fs_overlay(turn.turn_id, None, &[b"v1"], &file(b"v1"))
+        .unwrap();
+    // Replacing the snapshot drops the old root and the old file content.
+    store
+        .attach_fs_overlay(turn.turn_id, None, &[b"v2"], &file(b"v2"))
+        .unwrap();
+    let orphan = b"never attached";
+    store
+        .put_blob(*blake3::hash(orphan).as_bytes(), orphan, None, None)
+        .unwrap();
+
+    let stats = store.blob_gc_stats();
+    assert_eq!((stats.live_blobs, stats.dead_blobs), (3, 3));
+
+    // Everything was written since the last (never run) collection.
+    let sweep = store.collect_blobs(false).unwrap();
+    assert_eq!((sweep.swept_blobs, sweep.young_blobs), (0, 3));
+
+    let dry = store.collect_blobs(true).unwrap();
+    assert_eq!(dry.swept_blobs, 3);
+    assert_eq!(store.blob_gc_stats().dead_blobs, 3);
+
+    let sweep = store.collect_blobs(false).unwrap();
+    assert_eq!(sweep.swept_blobs, 3);
+    assert_eq!(
+        sweep.pack_bytes_after,
+        sweep.pack_bytes_before - sweep.swept_bytes
+    );
+    let stats = store.blob_gc_stats();
+    assert_eq!((stats.live_blobs, stats.dead_blobs), (3, 0));
+
+    assert!(store.get_blob(&first.root_hash).is_err());
+    assert!(store.get_blob(blake3::hash(b"v1").as_bytes()).is_err());
+    let (content, _) = store
+        .get_fs_file(turn.turn_id, "notes.txt", false, &Deadline::none())
+        .unwrap();
+    assert_eq!(content, b"v2");
+    let last = store.get_last(ctx.context_id, 1, true).unwrap();
+    assert_eq!(last[0].payload.as_deref(), Some(&payload[..]));
+}
+
+#[test]
+fn interrupted_sweeps_are_finished_or_discarded_on_open() {
+    use cxdb_server::blob_store::BlobStore;
+
+    let live = b"live blob";
+    let dead = b"dead blob";
+    let hash = |data: &[u8]| *blake3::hash(data).as_bytes();
+    let dir = tempdir().expect("tempdir");
+    let mut blobs = BlobStore::open(dir.path()).unwrap();
+    blobs.put_if_absent(hash(live), live).unwrap();
+    blobs.put_if_absent(hash(dead), dead).unwrap();
+    blobs.sweep(|_| true, false).unwrap();
+    drop(blobs);
+    let old_pack = std::fs::read(dir.path().join("blobs.pack")).unwrap();
+    let old_idx = std::fs::read(dir.path().join("blobs.idx")).unwrap();
+
+    let mut blobs = BlobStore::open(dir.path()).unwrap();
+    let stats = blobs.sweep(|h| *h == hash(live), false).unwrap();
+    assert_eq!(stats.swept_blobs, 1);
+    drop(blobs);
+    let new_pack = std::fs::read(dir.path().join("blobs.pack")).unwrap();
+    let new_idx = std::fs::read(dir.path().join("blobs.idx")).unwrap();
+    let write = |name: &str, bytes: &[u8]| std::fs::write(dir.path().join(name), bytes).unwrap();
+
+    // Crashed after the marker and the pack rename, before the index rename.
+    write("blobs.pack", &new_pack);
+    write("blobs.idx", &old_idx);
+    write("blobs.idx.tmp", &new_idx);
+    write("sweep.commit", b"");
+    let mut blobs = BlobStore::open(dir.path()).unwrap();
+    assert_eq!(blobs.get(&hash(live)).unwrap(), live);
+    assert!(!blobs.contains(&hash(dead)));
+    drop(blobs);
+    assert!(!dir.path().join("sweep.commit").exists());
+
+    // Crashed while writing the temporaries: the old pair stays.
+    write("blobs.pack", &old_pack);
+    write("blobs.idx", &old_idx);
+    write("blobs.pack.tmp", &new_pack[..new_pack.len() / 2]);
+    let mut blobs = BlobStore::open(dir.path()).unwrap();
+    assert_eq!(blobs.get(&hash(live)).unwrap(), live);
+    assert_eq!(blobs.get(&hash(dead)).unwrap(), dead);
+    assert!(!dir.path().join("blobs.pack.tmp").exists());
+}
+
+#[test]
+fn read_marks_count_unread_turns_per_principal_and_persist() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let ctx = store.create_context(0).expect("create context");
+    let other = store.create_context(0).expect("create context");
+
+    let unread = |store: &Store, principal: &str, context_id: u64| {
+        let head = store.get_head(context_id).unwrap();
+        ReadMark::unread_turns(
+            store.read_mark(principal, context_id),
+            head.head_turn_id,
+            head.head_depth,
+        )
+    };
+    let append = |store: &mut Store, context_id: u64, n: u32| {
+        let payload = n.to_le_bytes();
+        store
+            .append_turn(
+                context_id,
+                0,
+                "com.example.Test".to_string(),
+                1,
+                1,
+                0,
+                payload.len() as u32,
+                *blake3::hash(&payload).as_bytes(),
+                &payload,
+            )
+            .expect("append")
+            .0
+            .turn_id
+    };
+
+    assert_eq!(unread(&store, "ana@example.com", ctx.context_id), 0);
+    let first = append(&mut store, ctx.context_id, 1);
+    append(&mut store, ctx.context_id, 2);
+    assert_eq!(unread(&store, "ana@example.com", ctx.context_id), 2);
+
+    store
+        .mark_read("ana@example.com", ctx.context_id, None)
+        .unwrap();
+    append(&mut store, ctx.context_id, 3);
+    assert_eq!(unread(&store, "ana@example.com", ctx.context_id), 1);
+    assert_eq!(unread(&store, "bo@example.com", ctx.context_id), 3);
+
+    // Marking an earlier turn makes everything after it unread again.
+    let mark = store
+        .mark_read("bo@example.com", ctx.context_id, Some(first))
+        .unwrap();
+    assert_eq!(mark.depth, 0);
+    assert_eq!(unread(&store, "bo@example.com", ctx.context_id), 2);
+
+    let foreign = append(&mut store, other.context_id, 4);
+    let err = store
+        .mark_read("bo@example.com", ctx.context_id, Some(foreign))
+        .unwrap_err();
+    assert!(matches!(err, StoreError::InvalidInput(_)), "{err:?}");
+
+    // Marks are read back from the log.
+    let marks = ReadMarks::open(&dir.path().join("meta")).expect("open read marks");
+    let mark = marks.get("bo@example.com", ctx.context_id).unwrap();
+    assert_eq!(mark.turn_id, first);
+}
+
+#[test]
+fn compare_aligns_forks_at_their_common_ancestor() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let append = |store: &mut Store, context_id: u64, payload: &[u8]| {
+        store
+            .append_turn(
+                context_id,
+                0,
+                "com.example.Test".to_string(),
+                1,
+                1,
+                0,
+                payload.len() as u32,
+                *blake3::hash(payload).as_bytes(),
+                payload,
+            )
+            .expect("append")
+            .0
+            .turn_id
+    };
+
+    let base = store.create_context(0).unwrap().context_id;
+    append(&mut store, base, b"shared one");
+    let fork_point = append(&mut store, base, b"shared two");
+    let fork = store.fork_context(fork_point).unwrap().context_id;
+    let a1 = append(&mut store, base, b"strategy a");
+    let b1 = append(&mut store, fork, b"strategy b");
+    let b2 = append(&mut store, fork, b"strategy b, step 2");
+
+    let cmp = store.compare_contexts(base, fork, 1).unwrap();
+    assert_eq!(cmp.common_ancestor.as_ref().unwrap().turn_id, fork_point);
+    let ids = |turns: &[cxdb_server::store::TurnWithMeta]| -> Vec<u64> {
+        turns.iter().map(|t| t.record.turn_id).collect()
+    };
+    assert_eq!(ids(&cmp.a.turns), [a1]);
+    assert_eq!(cmp.a.stats.total_turns, 3);
+    assert_eq!(cmp.a.stats.divergent_bytes, 10);
+    // The limit caps loaded turns but not the stats.
+    assert_eq!(ids(&cmp.b.turns), [b1]);
+    assert_eq!(cmp.b.stats.divergent_turns, 2);
+    assert_eq!(cmp.b.stats.divergent_bytes, 28);
+    assert_eq!(cmp.b.turns[0].payload.as_deref(), Some(&b"strategy b"[..]));
+
+    let cmp = store.compare_contexts(fork, fork, 10).unwrap();
+    assert_eq!(cmp.common_ancestor.unwrap().turn_id, b2);
+    assert!(cmp.a.turns.is_empty() && cmp.b.turns.is_empty());
+
+    // Unrelated contexts diverge from the start.
+    let other = store.create_context(0).unwrap().context_id;
+    append(&mut store, other, b"unrelated");
+    let cmp = store.compare_contexts(base, other, 10).unwrap();
+    assert!(cmp.common_ancestor.is_none());
+    assert_eq!(cmp.a.stats.divergent_turns, 3);
+    assert_eq!(cmp.b.stats.divergent_turns, 1);
+}
+
+#[test]
+fn range_by_depth_seeks_into_deep_history() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let ctx = store.create_context(0).expect("create context");
+
+    for i in 0..5_000u32 {
+        let payload = i.to_le_bytes().to_vec();
+        store
+            .append_turn(
+                ctx.context_id,
+                0,
+                "com.example.Test".to_string(),
+                1,
+                1,
+                0,
+                payload.len() as u32,
+                *blake3::hash(&payload).as_bytes(),
+                &payload,
+            )
+            .expect("append");
+    }
+
+    let page = store
+        .get_range_by_depth(ctx.context_id, 1_234, 3, true)
+        .expect("range");
+    let depths: Vec<u32> = page.iter().map(|t| t.record.depth).collect();
+    assert_eq!(depths, vec![1_234, 1_235, 1_236]);
+    assert_eq!(
+        page[0].payload.as_deref(),
+        Some(&1_234u32.to_le_bytes()[..])
+    );
+
+    // The page before it continues from the oldest turn returned.
+    let before = store
+        .get_before(ctx.context_id, page[0].record.turn_id, 2, false)
+        .expect("before");
+    assert_eq!(before[1].record.depth, 1_233);
+}
@@ -0,0 +1,107 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::append;
+use std::fs;
+
+use cxdb_server::error::StoreError;
+use cxdb_server::format::{read_superblock, DataDirLock, FORMAT_VERSION, SUPERBLOCK_FILE};
+use cxdb_server::store::Store;
+use cxdb_server::turn_store::{index_version, INDEX_VERSION};
+use tempfile::tempdir;
+
+#[test]
+fn new_stores_are_stamped_with_the_current_format() {
+    let dir = tempdir().expect("tempdir");
+    drop(Store::open(dir.path()).expect("open store"));
+
+    let superblock = read_superblock(dir.path()).unwrap().expect("superblock");
+    assert_eq!(superblock.format_version, FORMAT_VERSION);
+    assert!(superblock.migrations.is_empty());
+
+    // Reopening leaves the stamp as it was.
+    drop(Store::open(dir.path()).expect("reopen store"));
+    assert_eq!(read_superblock(dir.path()).unwrap(), Some(superblock));
+    assert!(!dir.path().join("backups").exists());
+}
+
+#[test]
+fn unstamped_stores_are_migrated_with_a_backup() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let context_id = store.create_context(0).unwrap().context_id;
+    let turns: Vec<u64> = (0..5)
+        .map(|i| append(&mut store, context_id, format!("turn {i}").as_bytes()))
+        .collect();
+    drop(store);
+
+    // A version 1 directory: no superblock, and turns.idx in its original
+    // headerless format.
+    fs::remove_file(dir.path().join(SUPERBLOCK_FILE)).unwrap();
+    let legacy_index = vec![0u8; 16];
+    fs::write(dir.path().join("turns/turns.idx"), &legacy_index).unwrap();
+    assert_eq!(index_version(&dir.path().join("turns")).unwrap(), 1);
+
+    let mut store = Store::open(dir.path()).expect("migrate store");
+    let superblock = read_superblock(dir.path()).unwrap().expect("superblock");
+    assert_eq!(superblock.format_version, FORMAT_VERSION);
+    assert_eq!(superblock.migrations.len(), 1);
+    let migration = &superblock.migrations[0];
+    assert_eq!((migration.from_version, migration.to_version), (1, 2));
+    assert_eq!(migration.name, "turns-index-header");
+
+    let backup = dir.path().join(&migration.backup).join("turns/turns.idx");
+    assert_eq!(fs::read(backup).unwrap(), legacy_index);
+    let index = fs::read(dir.path().join("turns/turns.idx")).unwrap();
+    assert_eq!(&index[..4], b"CXTI");
+    assert_eq!(
+        index_version(&dir.path().join("turns")).unwrap(),
+        INDEX_VERSION
+    );
+
+    let last = store.get_last(context_id, 10, true).unwrap();
+    let ids: Vec<u64> = last.iter().map(|t| t.record.turn_id).collect();
+    assert_eq!(ids, turns);
+    assert_eq!(last[4].payload.as_deref(), Some(&b"turn 4"[..]));
+}
+
+#[test]
+fn stores_from_a_newer_server_are_refused_untouched() {
+    let dir = tempdir().expect("tempdir");
+    drop(Store::open(dir.path()).expect("open store"));
+
+    let path = dir.path().join(SUPERBLOCK_FILE);
+    let newer = fs::read_to_string(&path).unwrap().replace(
+        &format!("\"format_version\": {FORMAT_VERSION}"),
+        "\"format_version\": 99",
+    );
+    fs::write(&path, &newer).unwrap();
+
+    match Store::open(dir.path()) {
+        Err(err @ StoreError::UnsupportedFormat { found: 99, .. }) => {
+            assert!(err.to_string().contains("reads up to version"), "{err}");
+        }
+        other => panic!("expected UnsupportedFormat, got {:?}", other.err()),
+    }
+    assert_eq!(fs::read_to_string(&path).unwrap(), newer);
+}
+
+#[test]
+fn a_data_directory_is_opened_by_one_holder_at_a_time() {
+    let dir = tempdir().expect("tempdir");
+    let store = Store::open(dir.path()).expect("open store");
+
+    let busy = |err: StoreError| matches!(err, StoreError::Io(ref e) if e.kind() == std::io::ErrorKind::ResourceBusy);
+    assert!(busy(Store::open(dir.path()).err().expect("second open")));
+    assert!(busy(DataDirLock::acquire(dir.path()).unwrap_err()));
+
+    drop(store);
+    let lock = DataDirLock::acquire(dir.path()).expect("lock released on close");
+    assert!(busy(
+        Store::open(dir.path()).err().expect("open while locked")
+    ));
+    drop(lock);
+    Store::open(dir.path()).expect("reopen store");
+}
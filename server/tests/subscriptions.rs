@@ -0,0 +1,203 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cxdb_server::events::{EventBus, StoreEvent};
+use cxdb_server::subscriptions::{
+    start_subscription_dispatcher, SubscriptionConfig, SubscriptionSpec, Subscriptions, TargetKind,
+};
+use cxdb_server::webhooks::{start_webhook_dispatcher, WebhookConfig, Webhooks};
+use tempfile::tempdir;
+
+fn spec(target: &str, turn_types: &[&str]) -> SubscriptionSpec {
+    SubscriptionSpec {
+        target: target.to_string(),
+        turn_types: turn_types.iter().map(|t| t.to_string()).collect(),
+    }
+}
+
+fn appended(context_id: u64, turn_id: u64, type_id: &str) -> StoreEvent {
+    StoreEvent::TurnAppended {
+        context_id: context_id.to_string(),
+        turn_id: turn_id.to_string(),
+        parent_turn_id: (turn_id - 1).to_string(),
+        depth: turn_id as u32,
+        declared_type_id: Some(type_id.to_string()),
+        declared_type_version: Some(1),
+    }
+}
+
+#[test]
+fn targets_are_classified() {
+    let kind = |t: &str| TargetKind::of(t).ok();
+    assert_eq!(
+        kind("https://hooks.example.com/cxdb"),
+        Some(TargetKind::Webhook)
+    );
+    assert_eq!(kind("http://localhost:9000"), Some(TargetKind::Webhook));
+    assert_eq!(kind("oncall@example.com"), Some(TargetKind::Sink));
+    assert_eq!(kind("mailto:oncall@example.com"), Some(TargetKind::Sink));
+    assert_eq!(kind("https://"), None);
+    assert_eq!(kind("ftp://example.com"), None);
+    assert_eq!(kind("@example.com"), None);
+    assert_eq!(kind("on call@example.com"), None);
+    assert_eq!(kind(""), None);
+}
+
+#[test]
+fn turns_notify_matching_subscriptions() {
+    let dir = tempdir().expect("tempdir");
+    let subs = Subscriptions::open(dir.path()).expect("open");
+    let all = subs
+        .create(1, spec("https://hooks.example.com/a", &[]))
+        .unwrap();
+    let typed = subs
+        .create(1, spec("oncall@example.com", &["com.example.Error"]))
+        .unwrap();
+    subs.create(2, spec("https://hooks.example.com/b", &[]))
+        .unwrap();
+    assert!(subs.create(1, spec("not a target", &[])).is_err());
+    assert!(subs.create(1, spec("x@example.com", &[""])).is_err());
+    assert_eq!(typed.kind, TargetKind::Sink);
+    assert_eq!(subs.list(1).len(), 2);
+
+    let deliveries = subs.on_event(&appended(1, 5, "com.example.Message"));
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].subscription_id.to_string(), all.id);
+    assert_eq!(deliveries[0].turn_id, "5");
+
+    let deliveries = subs.on_event(&appended(1, 6, "com.example.Error"));
+    let ids: Vec<String> = deliveries
+        .iter()
+        .map(|d| d.subscription_id.to_string())
+        .collect();
+    assert_eq!(ids, vec![all.id.clone(), typed.id.clone()]);
+
+    // Removal is scoped to the context.
+    let typed_id: u64 = typed.id.parse().unwrap();
+    assert!(!subs.remove(2, typed_id).unwrap());
+    assert!(subs.remove(1, typed_id).unwrap());
+    assert_eq!(subs.list(1).len(), 1);
+
+    // Expired contexts lose their subscriptions.
+    subs.on_event(&StoreEvent::ContextExpired {
+        context_id: "2".into(),
+        expires_at: 0,
+        expired_at: 0,
+    });
+    assert!(subs.list(2).is_empty());
+}
+
+#[test]
+fn subscriptions_expire_after_context_inactivity() {
+    let dir = tempdir().expect("tempdir");
+    let subs = Subscriptions::open(dir.path()).expect("open");
+    let quiet = subs
+        .create(1, spec("https://hooks.example.com/a", &[]))
+        .unwrap();
+    let busy = subs
+        .create(2, spec("https://hooks.example.com/b", &[]))
+        .unwrap();
+    let idle = Duration::from_secs(3600);
+
+    // A turn on context 2 an hour later keeps its subscription alive.
+    let later = quiet.created_at_unix_ms + 3_600_000;
+    thread::sleep(Duration::from_millis(5));
+    subs.on_event(&appended(2, 1, "com.example.Message"));
+    let active = subs.get(busy.id.parse().unwrap()).unwrap();
+    assert!(active.last_activity_at_unix_ms > quiet.created_at_unix_ms);
+
+    assert!(subs.expire_idle(later - 1, idle).is_empty());
+    let removed = subs.expire_idle(later, idle);
+    assert_eq!(removed.len(), 1);
+    assert_eq!(removed[0].id, quiet.id);
+
+    // Removals and activity survive a restart.
+    drop(subs);
+    let subs = Subscriptions::open(dir.path()).expect("reopen");
+    assert!(subs.list(1).is_empty());
+    let reopened = subs.get(busy.id.parse().unwrap()).unwrap();
+    assert_eq!(
+        reopened.last_activity_at_unix_ms,
+        active.last_activity_at_unix_ms
+    );
+    let next = subs
+        .create(1, spec("https://hooks.example.com/c", &[]))
+        .unwrap();
+    assert_eq!(next.id, "3");
+}
+
+#[test]
+fn dispatcher_retries_webhooks_and_publishes_sink_notifications() {
+    // A webhook that fails its first request.
+    let server = tiny_http::Server::http("127.0.0.1:0").expect("bind webhook");
+    let url = format!("http://{}/hook", server.server_addr());
+    let requests = Arc::new(AtomicUsize::new(0));
+    let bodies = Arc::new(Mutex::new(Vec::new()));
+    {
+        let requests = Arc::clone(&requests);
+        let bodies = Arc::clone(&bodies);
+        thread::spawn(move || {
+            for mut request in server.incoming_requests() {
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                let status = if requests.fetch_add(1, Ordering::SeqCst) == 0 {
+                    500
+                } else {
+                    bodies.lock().unwrap().push(body);
+                    200
+                };
+                let _ = request.respond(tiny_http::Response::empty(status));
+            }
+        });
+    }
+
+    let dir = tempdir().expect("tempdir");
+    let subs = Arc::new(Subscriptions::open(dir.path()).expect("open"));
+    let hook = subs.create(7, spec(&url, &[])).unwrap();
+    let mail = subs.create(7, spec("oncall@example.com", &[])).unwrap();
+    let bus = Arc::new(EventBus::new());
+    let observer = bus.subscribe();
+    let webhooks = Arc::new(Webhooks::new(WebhookConfig {
+        retry_base: Duration::from_millis(10),
+        allow_hosts: vec!["127.0.0.1".into()],
+        ..WebhookConfig::default()
+    }));
+    let _webhooks = start_webhook_dispatcher(Arc::clone(&webhooks));
+    let _dispatcher = start_subscription_dispatcher(
+        SubscriptionConfig::default(),
+        Arc::clone(&subs),
+        webhooks,
+        Arc::clone(&bus),
+    );
+    bus.publish(appended(7, 3, "com.example.Message"));
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while subs.get(hook.id.parse().unwrap()).unwrap().delivered == 0 {
+        assert!(Instant::now() < deadline, "webhook was not delivered");
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+    let body: serde_json::Value = serde_json::from_str(&bodies.lock().unwrap()[0]).unwrap();
+    assert_eq!(body["context_id"], "7");
+    assert_eq!(body["turn_id"], "3");
+    assert_eq!(body["attempt"], 2);
+
+    let mut notified = None;
+    while let Some(event) = observer.recv_timeout(Duration::from_millis(500)) {
+        if let StoreEvent::SubscriptionNotified {
+            subscription_id,
+            target,
+            ..
+        } = event
+        {
+            notified = Some((subscription_id, target));
+            break;
+        }
+    }
+    assert_eq!(notified, Some((mail.id, "oncall@example.com".to_string())));
+}
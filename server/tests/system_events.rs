@@ -0,0 +1,181 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use common::append;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cxdb_server::error::StoreError;
+use cxdb_server::events::{EventBus, StoreEvent};
+use cxdb_server::fs_store::{encode_tree_entries, TreeEntry};
+use cxdb_server::projection::project_msgpack;
+use cxdb_server::projection::{BytesRender, EnumRender, RenderOptions, TimeRender, U64Format};
+use cxdb_server::registry::builtin::ingest_builtin_bundles;
+use cxdb_server::registry::{BuiltinOutcome, Registry};
+use cxdb_server::store::Store;
+use cxdb_server::system_events::{
+    SystemEventConfig, SystemEvents, SYSTEM_EVENT_TYPE_ID, SYSTEM_EVENT_TYPE_VERSION,
+};
+use serde_json::Value as JsonValue;
+use tempfile::tempdir;
+
+/// Projected payload of a context's head turn, which must be a system event.
+fn head_event(store: &mut Store, registry: &Registry, context_id: u64) -> JsonValue {
+    let head = store.get_head(context_id).unwrap();
+    let turn = store.get_turn(head.head_turn_id, true).unwrap();
+    assert_eq!(turn.meta.declared_type_id, SYSTEM_EVENT_TYPE_ID);
+    assert_eq!(turn.meta.declared_type_version, SYSTEM_EVENT_TYPE_VERSION);
+    let desc = registry
+        .get_type_version(SYSTEM_EVENT_TYPE_ID, SYSTEM_EVENT_TYPE_VERSION)
+        .unwrap();
+    let options = RenderOptions {
+        bytes_render: BytesRender::Base64,
+        u64_format: U64Format::Number,
+        enum_render: EnumRender::Label,
+        time_render: TimeRender::UnixMs,
+        include_unknown: false,
+        apply_defaults: false,
+    };
+    project_msgpack(&turn.payload.unwrap(), desc, registry, &options)
+        .unwrap()
+        .data
+}
+
+#[test]
+fn system_event_type_is_reserved() {
+    let dir = tempdir().expect("tempdir");
+    let mut registry = Registry::open(dir.path()).unwrap();
+    let outcomes = ingest_builtin_bundles(&mut registry, None).unwrap();
+    assert!(outcomes.contains(&("cxdb.system-events.v1".to_string(), BuiltinOutcome::Created)));
+
+    let client_bundle = br#"{
+      "registry_version": 1,
+      "bundle_id": "client.bundle",
+      "types": {
+        "cxdb:SystemEvent": {
+          "versions": { "2": { "fields": { "1": { "name": "kind", "type": "string" } } } }
+        }
+      }
+    }"#;
+    assert!(matches!(
+        registry.put_bundle("client.bundle", client_bundle),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        registry.merge_bundle("client.bundle", client_bundle),
+        Err(StoreError::InvalidInput(_))
+    ));
+
+    // Registering again on restart is a no-op that keeps the reservation.
+    drop(registry);
+    let mut registry = Registry::open(dir.path()).unwrap();
+    let outcomes = ingest_builtin_bundles(&mut registry, None).unwrap();
+    assert!(outcomes
+        .iter()
+        .all(|(_, outcome)| *outcome == BuiltinOutcome::Unchanged));
+    assert!(registry.put_bundle("client.bundle", client_bundle).is_err());
+    assert!(registry
+        .get_type_version(SYSTEM_EVENT_TYPE_ID, SYSTEM_EVENT_TYPE_VERSION)
+        .is_some());
+}
+
+#[test]
+fn milestones_append_system_event_turns() {
+    let dir = tempdir().expect("tempdir");
+    let mut registry = Registry::open(&dir.path().join("registry")).unwrap();
+    ingest_builtin_bundles(&mut registry, None).unwrap();
+    let bus = Arc::new(EventBus::new());
+    let subscriber = bus.subscribe();
+    let mut store = Store::open(dir.path()).expect("open store");
+    store.enable_system_events(SystemEvents::new(
+        SystemEventConfig::parse("all").unwrap(),
+        Arc::clone(&bus),
+    ));
+
+    let source = store.create_context(0).unwrap().context_id;
+    let first = append(&mut store, source, b"one");
+
+    // Fork: the new context's head is the system event on top of the base turn.
+    let fork = store.fork_context(first).unwrap();
+    let event = head_event(&mut store, &registry, fork.context_id);
+    assert_eq!(event["kind"], "context_forked");
+    assert_eq!(event["turn_id"], first);
+    assert_eq!(event["context_id"], fork.context_id);
+    assert_eq!(fork.head_depth, 1);
+    assert_eq!(store.get_head(source).unwrap().head_turn_id, first);
+    match subscriber.recv_timeout(Duration::from_secs(1)) {
+        Some(StoreEvent::TurnAppended {
+            context_id,
+            turn_id,
+            declared_type_id,
+            ..
+        }) => {
+            assert_eq!(context_id, fork.context_id.to_string());
+            assert_eq!(turn_id, fork.head_turn_id.to_string());
+            assert_eq!(declared_type_id.as_deref(), Some(SYSTEM_EVENT_TYPE_ID));
+        }
+        other => panic!("expected TurnAppended, got {other:?}"),
+    }
+
+    // Filesystem snapshot attached to the head turn.
+    let entry = TreeEntry {
+        name: "README".into(),
+        kind: 0,
+        mode: 0o644,
+        size: 2,
+        hash: blake3::hash(b"v1").as_bytes().to_vec(),
+    };
+    let root = encode_tree_entries(&[entry]).unwrap();
+    let root_hash = *blake3::hash(&root).as_bytes();
+    store
+        .put_blob(*blake3::hash(b"v1").as_bytes(), b"v1", None, None)
+        .unwrap();
+    store.put_blob(root_hash, &root, None, None).unwrap();
+    store.attach_fs(first, root_hash).unwrap();
+    let event = head_event(&mut store, &registry, source);
+    assert_eq!(event["kind"], "fs_attached");
+    assert_eq!(event["turn_id"], first);
+    assert_eq!(event["attrs"]["root_hash"], hex::encode(root_hash));
+    // The event turn sees the snapshot it reports.
+    let head = store.get_head(source).unwrap().head_turn_id;
+    assert_eq!(store.get_fs_root(head), Some(root_hash));
+
+    // Holds record who placed and released them.
+    store.place_hold(source, "litigation", "alice").unwrap();
+    let event = head_event(&mut store, &registry, source);
+    assert_eq!(event["kind"], "hold_placed");
+    assert_eq!(event["principal"], "alice");
+    assert_eq!(event["summary"], "Legal hold placed: litigation");
+    store.release_hold(source, "", "bob").unwrap();
+    let event = head_event(&mut store, &registry, source);
+    assert_eq!(event["kind"], "hold_released");
+    assert_eq!(event["summary"], "Legal hold released");
+
+    // Empty contexts get no event as their first turn.
+    let empty = store.create_context(0).unwrap().context_id;
+    store.place_hold(empty, "litigation", "alice").unwrap();
+    assert_eq!(store.get_head(empty).unwrap().head_turn_id, 0);
+}
+
+#[test]
+fn only_configured_kinds_are_recorded() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    store.enable_system_events(SystemEvents::new(
+        SystemEventConfig::parse("hold_placed").unwrap(),
+        Arc::new(EventBus::new()),
+    ));
+
+    let source = store.create_context(0).unwrap().context_id;
+    let first = append(&mut store, source, b"one");
+    let fork = store.fork_context(first).unwrap();
+    assert_eq!(fork.head_turn_id, first);
+
+    store.place_hold(source, "audit", "alice").unwrap();
+    let head = store.get_head(source).unwrap().head_turn_id;
+    assert_ne!(head, first);
+    store.release_hold(source, "done", "alice").unwrap();
+    assert_eq!(store.get_head(source).unwrap().head_turn_id, head);
+}
@@ -0,0 +1,128 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashSet;
+
+use cxdb_server::error::StoreError;
+use cxdb_server::invariants::VerifyScope;
+use cxdb_server::metadata_overrides::MetadataPatch;
+use cxdb_server::store::Store;
+use cxdb_server::tag_changes::TagOperation;
+use tempfile::tempdir;
+
+fn tagged_context(store: &mut Store, tag: &str) -> u64 {
+    let context_id = store.create_context(0).unwrap().context_id;
+    let patch = MetadataPatch {
+        client_tag: Some(tag.to_string()),
+        ..Default::default()
+    };
+    store.apply_metadata_patch(context_id, &patch, &[]).unwrap();
+    context_id
+}
+
+fn tagged(store: &mut Store, tag: &str) -> Vec<u64> {
+    let mut ids = store
+        .search_contexts(&format!("tag = \"{tag}\""), &HashSet::new(), None)
+        .unwrap()
+        .context_ids;
+    ids.sort_unstable();
+    ids
+}
+
+#[test]
+fn rename_moves_every_context_and_records_audit_trail() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let first = tagged_context(&mut store, "billing-svc");
+    let second = tagged_context(&mut store, "billing-svc");
+    let other = tagged_context(&mut store, "search");
+
+    let change = store
+        .rename_tag(
+            "billing-svc",
+            " payments ",
+            "service renamed",
+            Some("admin"),
+        )
+        .unwrap();
+    assert_eq!(change.operation, TagOperation::Rename);
+    assert_eq!(change.sources, vec!["billing-svc"]);
+    assert_eq!(change.target, "payments");
+    assert_eq!(change.context_ids, vec![first, second]);
+    assert!(tagged(&mut store, "billing-svc").is_empty());
+    assert_eq!(tagged(&mut store, "payments"), vec![first, second]);
+    assert_eq!(tagged(&mut store, "search"), vec![other]);
+    assert_eq!(
+        store
+            .get_context_metadata(first)
+            .and_then(|m| m.client_tag)
+            .as_deref(),
+        Some("payments")
+    );
+    store.verify_invariants(VerifyScope::Full).assert_ok();
+
+    // A tag in use can only be merged into; an unused source is not found.
+    assert!(matches!(
+        store.rename_tag("payments", "search", "", None),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        store.rename_tag("payments", "payments", "", None),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        store.rename_tag("billing-svc", "billing", "", None),
+        Err(StoreError::NotFound(_))
+    ));
+
+    // The new tags and the audit trail survive a restart.
+    drop(store);
+    let mut store = Store::open(dir.path()).expect("reopen store");
+    assert_eq!(tagged(&mut store, "payments"), vec![first, second]);
+    let changes = store.tag_changes();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].reason, "service renamed");
+    assert_eq!(changes[0].principal.as_deref(), Some("admin"));
+}
+
+#[test]
+fn merge_folds_sources_into_a_tag_in_use() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+    let old = tagged_context(&mut store, "agent-v1");
+    let older = tagged_context(&mut store, "agent-beta");
+    let current = tagged_context(&mut store, "agent");
+
+    let change = store
+        .merge_tags(
+            &[
+                "agent-v1".to_string(),
+                "agent-beta".to_string(),
+                "agent-v1".to_string(),
+                "unused".to_string(),
+            ],
+            "agent",
+            "",
+            None,
+        )
+        .unwrap();
+    assert_eq!(change.operation, TagOperation::Merge);
+    assert_eq!(change.sources, vec!["agent-v1", "agent-beta", "unused"]);
+    assert_eq!(change.context_ids, vec![old, older]);
+    assert_eq!(tagged(&mut store, "agent"), vec![old, older, current]);
+    assert!(tagged(&mut store, "agent-v1").is_empty());
+    store.verify_invariants(VerifyScope::Full).assert_ok();
+
+    assert!(matches!(
+        store.merge_tags(&["agent".to_string()], "agent", "", None),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        store.merge_tags(&[], "agent", "", None),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        store.merge_tags(&["agent-v1".to_string()], "agent", "", None),
+        Err(StoreError::NotFound(_))
+    ));
+}
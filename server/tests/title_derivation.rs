@@ -0,0 +1,168 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use cxdb_server::registry::Registry;
+use cxdb_server::store::Store;
+use cxdb_server::title::{derive_pending_titles, TitleConfig};
+use rmpv::Value;
+use tempfile::tempdir;
+
+const BUNDLE: &str = r#"
+{
+  "registry_version": 1,
+  "bundle_id": "2025-12-19T00:00:00Z#title",
+  "types": {
+    "com.example.Message": {
+      "versions": {
+        "1": {
+          "fields": {
+            "1": { "name": "role", "type": "string" },
+            "2": { "name": "text", "type": "string" }
+          }
+        }
+      }
+    }
+  }
+}
+"#;
+
+fn message(role: &str, text: &str, title: Option<&str>) -> Vec<u8> {
+    let mut fields = vec![
+        (Value::from(1), Value::from(role)),
+        (Value::from(2), Value::from(text)),
+    ];
+    if let Some(title) = title {
+        fields.push((
+            Value::from(30),
+            Value::Map(vec![(Value::from(2), Value::from(title))]),
+        ));
+    }
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &Value::Map(fields)).expect("encode");
+    buf
+}
+
+fn append(store: &mut Store, context_id: u64, payload: &[u8]) {
+    common::append_typed(store, context_id, "com.example.Message", payload);
+}
+
+fn open_store(dir: &std::path::Path) -> Store {
+    let registry = Arc::new(Mutex::new(
+        Registry::open(&dir.join("registry")).expect("registry"),
+    ));
+    registry
+        .lock()
+        .unwrap()
+        .put_bundle("2025-12-19T00:00:00Z#title", BUNDLE.as_bytes())
+        .ok();
+    let mut store = Store::open(dir).expect("open store");
+    store.enable_title_derivation(TitleConfig { max_chars: 24 }, registry);
+    store
+}
+
+#[test]
+fn derives_title_from_first_user_turn_and_persists() {
+    let dir = tempdir().expect("tempdir");
+    let context_id = {
+        let mut store = open_store(dir.path());
+        let ctx = store.create_context(0).expect("create");
+        append(
+            &mut store,
+            ctx.context_id,
+            &message("system", "be helpful", None),
+        );
+        assert!(store
+            .get_context_metadata(ctx.context_id)
+            .and_then(|m| m.title)
+            .is_none());
+
+        append(
+            &mut store,
+            ctx.context_id,
+            &message(
+                "user",
+                "  Why is the\n build   failing on main today?",
+                None,
+            ),
+        );
+        append(
+            &mut store,
+            ctx.context_id,
+            &message("user", "second question", None),
+        );
+
+        let title = store
+            .get_context_metadata(ctx.context_id)
+            .and_then(|m| m.title);
+        assert_eq!(title.as_deref(), Some("Why is the build failing"));
+
+        let live = Default::default();
+        let result = store
+            .search_contexts(r#"title ^= "Why is""#, &live, None)
+            .expect("search");
+        assert_eq!(result.context_ids, vec![ctx.context_id]);
+        ctx.context_id
+    };
+
+    // Derived title survives a restart.
+    let mut store = Store::open(dir.path()).expect("reopen");
+    let title = store.get_context_metadata(context_id).and_then(|m| m.title);
+    assert_eq!(title.as_deref(), Some("Why is the build failing"));
+}
+
+#[test]
+fn client_title_is_not_replaced() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = open_store(dir.path());
+    let ctx = store.create_context(0).expect("create");
+    append(
+        &mut store,
+        ctx.context_id,
+        &message("user", "hello there", Some("Client Title")),
+    );
+
+    let title = store
+        .get_context_metadata(ctx.context_id)
+        .and_then(|m| m.title);
+    assert_eq!(title.as_deref(), Some("Client Title"));
+    assert!(store.get_metadata_override(ctx.context_id).is_none());
+}
+
+#[test]
+fn title_is_deferred_while_the_registry_is_busy() {
+    let dir = tempdir().expect("tempdir");
+    let registry = Arc::new(Mutex::new(
+        Registry::open(&dir.path().join("registry")).expect("registry"),
+    ));
+    registry
+        .lock()
+        .unwrap()
+        .put_bundle("2025-12-19T00:00:00Z#title", BUNDLE.as_bytes())
+        .ok();
+    let mut store = Store::open(dir.path()).expect("open store");
+    store.enable_title_derivation(TitleConfig { max_chars: 24 }, Arc::clone(&registry));
+    let context_id = store.create_context(0).expect("create").context_id;
+
+    // The append does not wait for a registry held elsewhere.
+    let held = registry.lock().unwrap();
+    append(
+        &mut store,
+        context_id,
+        &message("user", "deferred title", None),
+    );
+    drop(held);
+    assert!(store
+        .get_context_metadata(context_id)
+        .and_then(|m| m.title)
+        .is_none());
+
+    let store = Mutex::new(store);
+    let titled = derive_pending_titles(&store).expect("derive");
+    assert_eq!(titled.len(), 1);
+    assert_eq!(titled[0].1.title.as_deref(), Some("deferred title"));
+    assert!(derive_pending_titles(&store).expect("derive").is_empty());
+}
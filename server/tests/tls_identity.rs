@@ -0,0 +1,211 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cxdb_server::error::StoreError;
+use cxdb_server::tls::{PeerIdentity, TlsAcceptor, TlsConfig};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType, IsCa, KeyPair,
+    SanType,
+};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use tempfile::tempdir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+struct Ca {
+    cert: Certificate,
+    key: KeyPair,
+}
+
+fn ca(name: &str) -> Ca {
+    let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let mut dn = DistinguishedName::new();
+    dn.push(DnType::CommonName, name);
+    params.distinguished_name = dn;
+    let key = KeyPair::generate().unwrap();
+    let cert = params.self_signed(&key).unwrap();
+    Ca { cert, key }
+}
+
+fn leaf(ca: &Ca, cn: &str, sans: Vec<SanType>) -> (Certificate, KeyPair) {
+    let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    let mut dn = DistinguishedName::new();
+    dn.push(DnType::CommonName, cn);
+    params.distinguished_name = dn;
+    params.subject_alt_names = sans;
+    let key = KeyPair::generate().unwrap();
+    let cert = params.signed_by(&key, &ca.cert, &ca.key).unwrap();
+    (cert, key)
+}
+
+fn key_der(key: &KeyPair) -> PrivateKeyDer<'static> {
+    PrivateKeyDer::from(PrivatePkcs8KeyDer::from(key.serialize_der()))
+}
+
+fn server_config(dir: &Path, client_ca: &Ca, require_client_cert: bool) -> TlsConfig {
+    let server_ca = ca("server-ca");
+    let (cert, key) = leaf(
+        &server_ca,
+        "cxdb",
+        vec![SanType::DnsName("localhost".try_into().unwrap())],
+    );
+    std::fs::write(dir.join("server.pem"), cert.pem()).unwrap();
+    std::fs::write(dir.join("server.key"), key.serialize_pem()).unwrap();
+    std::fs::write(dir.join("server-ca.pem"), server_ca.cert.pem()).unwrap();
+    std::fs::write(dir.join("client-ca.pem"), client_ca.cert.pem()).unwrap();
+    TlsConfig {
+        cert_path: dir.join("server.pem"),
+        key_path: dir.join("server.key"),
+        client_ca_path: Some(dir.join("client-ca.pem")),
+        require_client_cert,
+        handshake_timeout: Duration::from_secs(10),
+    }
+}
+
+fn client_config(dir: &Path, client: Option<(&Certificate, &KeyPair)>) -> Arc<ClientConfig> {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let server_ca = std::fs::read_to_string(dir.join("server-ca.pem")).unwrap();
+    let server_ca = CertificateDer::from_pem_slice(server_ca.as_bytes()).unwrap();
+    let mut roots = RootCertStore::empty();
+    roots.add(server_ca).unwrap();
+    let builder = ClientConfig::builder().with_root_certificates(roots);
+    let config = match client {
+        Some((cert, key)) => builder
+            .with_client_auth_cert(vec![cert.der().clone()], key_der(key))
+            .unwrap(),
+        None => builder.with_no_client_auth(),
+    };
+    Arc::new(config)
+}
+
+/// Accept one connection, echo one byte back and return the peer identity.
+fn serve_once(
+    acceptor: TlsAcceptor,
+) -> (
+    String,
+    thread::JoinHandle<cxdb_server::error::Result<Option<PeerIdentity>>>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let handle = thread::spawn(move || {
+        let (tcp, _) = listener.accept().unwrap();
+        runtime().block_on(async move {
+            let (mut stream, identity) = acceptor.accept(tokio_stream(tcp)).await?;
+            let mut buf = [0u8; 1];
+            stream.read_exact(&mut buf).await?;
+            stream.write_all(&buf).await?;
+            stream.flush().await?;
+            Ok(identity)
+        })
+    });
+    (addr, handle)
+}
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+/// `tcp` registered with the current runtime.
+fn tokio_stream(tcp: TcpStream) -> tokio::net::TcpStream {
+    tcp.set_nonblocking(true).unwrap();
+    tokio::net::TcpStream::from_std(tcp).unwrap()
+}
+
+fn connect(addr: &str, config: Arc<ClientConfig>) -> std::io::Result<u8> {
+    let tcp = TcpStream::connect(addr)?;
+    let name = ServerName::try_from("localhost").unwrap();
+    let conn = ClientConnection::new(config, name).unwrap();
+    let mut stream = StreamOwned::new(conn, tcp);
+    stream.write_all(&[42])?;
+    stream.flush()?;
+    let mut buf = [0u8; 1];
+    stream.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+#[test]
+fn client_certificate_identity_is_extracted() {
+    let dir = tempdir().unwrap();
+    let client_ca = ca("client-ca");
+    let acceptor = TlsAcceptor::new(&server_config(dir.path(), &client_ca, true)).unwrap();
+    let (cert, key) = leaf(
+        &client_ca,
+        "agent-runner",
+        vec![
+            SanType::URI("spiffe://prod/agents/runner".try_into().unwrap()),
+            SanType::DnsName("runner.internal".try_into().unwrap()),
+        ],
+    );
+
+    let (addr, server) = serve_once(acceptor);
+    let echoed = connect(&addr, client_config(dir.path(), Some((&cert, &key)))).unwrap();
+    assert_eq!(echoed, 42);
+
+    let identity = server.join().unwrap().unwrap().expect("identity");
+    assert_eq!(identity.principal, "spiffe://prod/agents/runner");
+    assert_eq!(identity.common_name.as_deref(), Some("agent-runner"));
+    assert_eq!(identity.dns_names, vec!["runner.internal".to_string()]);
+    assert!(identity.issuer.contains("client-ca"), "{}", identity.issuer);
+
+    let provenance = identity.provenance_patch().provenance.unwrap();
+    assert_eq!(provenance.writer_method.as_deref(), Some("mtls"));
+    assert_eq!(
+        provenance.writer_subject.as_deref(),
+        Some("spiffe://prod/agents/runner")
+    );
+}
+
+#[test]
+fn missing_client_certificate_is_rejected_when_required() {
+    let dir = tempdir().unwrap();
+    let client_ca = ca("client-ca");
+    let acceptor = TlsAcceptor::new(&server_config(dir.path(), &client_ca, true)).unwrap();
+
+    let (addr, server) = serve_once(acceptor);
+    let _ = connect(&addr, client_config(dir.path(), None));
+    assert!(server.join().unwrap().is_err());
+}
+
+#[test]
+fn optional_client_certificate_allows_anonymous_sessions() {
+    let dir = tempdir().unwrap();
+    let client_ca = ca("client-ca");
+    let acceptor = TlsAcceptor::new(&server_config(dir.path(), &client_ca, false)).unwrap();
+
+    let (addr, server) = serve_once(acceptor);
+    assert_eq!(connect(&addr, client_config(dir.path(), None)).unwrap(), 42);
+    assert_eq!(server.join().unwrap().unwrap(), None);
+}
+
+#[test]
+fn stalled_handshake_times_out() {
+    let dir = tempdir().unwrap();
+    let client_ca = ca("client-ca");
+    let mut config = server_config(dir.path(), &client_ca, false);
+    config.handshake_timeout = Duration::from_millis(200);
+    let acceptor = TlsAcceptor::new(&config).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    // Connect and never send a ClientHello.
+    let _silent = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (tcp, _) = listener.accept().unwrap();
+    let started = Instant::now();
+    let runtime = runtime();
+    let Err(err) = runtime.block_on(async { acceptor.accept(tokio_stream(tcp)).await }) else {
+        panic!("stalled handshake was accepted");
+    };
+    assert!(matches!(err, StoreError::DeadlineExceeded(_)), "{err}");
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
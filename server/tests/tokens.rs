@@ -0,0 +1,143 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use cxdb_server::registry::Registry;
+use cxdb_server::store::Store;
+use cxdb_server::tokens::{ApproxTokenizer, TokenCounter, TokenLedger};
+use rmpv::Value;
+use tempfile::tempdir;
+
+const BUNDLE: &str = r#"
+{
+  "registry_version": 1,
+  "bundle_id": "2025-12-19T00:00:00Z#tokens",
+  "types": {
+    "com.example.Message": {
+      "versions": {
+        "1": {
+          "fields": {
+            "1": { "name": "role", "type": "string" },
+            "2": { "name": "text", "type": "string", "count_tokens": true },
+            "3": { "name": "calls", "type": "array", "items": { "type": "ref", "ref": "com.example.Call" } }
+          }
+        }
+      }
+    },
+    "com.example.Call": {
+      "versions": {
+        "1": {
+          "fields": {
+            "1": { "name": "name", "type": "string" },
+            "2": { "name": "arguments", "type": "map", "count_tokens": true }
+          }
+        }
+      }
+    }
+  }
+}
+"#;
+
+fn message(tag: Option<&str>, text: &str, call_args: &[&str]) -> Vec<u8> {
+    let calls = call_args
+        .iter()
+        .map(|arg| {
+            Value::Map(vec![
+                (Value::from(1), Value::from("not counted at all")),
+                (
+                    Value::from(2),
+                    Value::Map(vec![(Value::from("q"), Value::from(*arg))]),
+                ),
+            ])
+        })
+        .collect();
+    let mut fields = vec![
+        (Value::from(1), Value::from("user")),
+        (Value::from(2), Value::from(text)),
+        (Value::from(3), Value::Array(calls)),
+    ];
+    if let Some(tag) = tag {
+        fields.push((
+            Value::from(30),
+            Value::Map(vec![(Value::from(1), Value::from(tag))]),
+        ));
+    }
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &Value::Map(fields)).expect("encode");
+    buf
+}
+
+fn append(store: &mut Store, context_id: u64, payload: &[u8]) -> u64 {
+    common::append_typed(store, context_id, "com.example.Message", payload)
+}
+
+#[test]
+fn annotated_fields_are_counted_and_aggregated() {
+    let dir = tempdir().expect("tempdir");
+    let registry = Arc::new(Mutex::new(
+        Registry::open(&dir.path().join("registry")).expect("registry"),
+    ));
+    registry
+        .lock()
+        .unwrap()
+        .put_bundle("2025-12-19T00:00:00Z#tokens", BUNDLE.as_bytes())
+        .expect("put bundle");
+    let mut store = Store::open(dir.path()).expect("open store");
+    store.enable_token_counting(TokenCounter::new(Box::new(ApproxTokenizer), registry));
+    assert_eq!(store.token_stats().tokenizer.as_deref(), Some("approx"));
+
+    // 16 characters of text (4 tokens) plus 8 characters of call arguments
+    // (2 tokens); roles and call names are not annotated.
+    let ctx = store.create_context(0).unwrap().context_id;
+    let first = append(
+        &mut store,
+        ctx,
+        &message(Some("agent-a"), "sixteen chars!!!", &["abcd", "efgh"]),
+    );
+    assert_eq!(store.turn_tokens(first).unwrap().tokens, 6);
+    let second = append(&mut store, ctx, &message(None, "four", &[]));
+    assert_eq!(store.turn_tokens(second).unwrap().total, 7);
+    assert_eq!(store.context_tokens(ctx).total, 7);
+
+    // A fork starts from its base turn's total; its own appends are its own.
+    let fork = store.fork_context(first).unwrap().context_id;
+    assert_eq!(store.context_tokens(fork).total, 6);
+    append(&mut store, fork, &message(None, "twelve chars", &[]));
+    assert_eq!(store.context_tokens(fork).total, 9);
+    assert_eq!(store.context_tokens(fork).appended, 3);
+
+    let other = store.create_context(0).unwrap().context_id;
+    append(&mut store, other, &message(Some("agent-b"), "a", &[]));
+
+    let search = |store: &mut Store, query: &str| {
+        let mut ids = store
+            .search_contexts(query, &HashSet::new(), None)
+            .expect("search")
+            .context_ids;
+        ids.sort_unstable();
+        ids
+    };
+    assert_eq!(search(&mut store, "tokens > 6"), [ctx, fork]);
+    assert_eq!(search(&mut store, "tokens <= 6"), [other]);
+
+    let stats = store.token_stats();
+    assert_eq!(stats.total, 11);
+    assert_eq!(stats.contexts, 3);
+    let by_tag: Vec<(&str, u64)> = stats
+        .by_tag
+        .iter()
+        .map(|t| (t.tag.as_str(), t.tokens))
+        .collect();
+    // The fork inherits its base context's tag.
+    assert_eq!(by_tag, [("agent-a", 10), ("agent-b", 1)]);
+
+    // Counts are read back from the ledger.
+    let ledger = TokenLedger::open(&dir.path().join("meta")).expect("open ledger");
+    assert_eq!(ledger.turn(first).unwrap().tokens, 6);
+    assert_eq!(ledger.context(fork).total, 9);
+    assert_eq!(ledger.context(ctx).appended, 7);
+}
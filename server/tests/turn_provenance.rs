@@ -0,0 +1,90 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use cxdb_server::store::Store;
+use cxdb_server::turn_store::TurnAuthor;
+use tempfile::tempdir;
+
+fn append(store: &mut Store, context_id: u64, text: &str, author: Option<TurnAuthor>) -> u64 {
+    common::try_append_as(
+        store,
+        context_id,
+        0,
+        "com.example.Test",
+        text.as_bytes(),
+        author,
+    )
+    .expect("append")
+    .0
+    .turn_id
+}
+
+fn author(session_id: u64, client_tag: &str, principal: Option<&str>) -> Option<TurnAuthor> {
+    Some(TurnAuthor {
+        session_id,
+        client_tag: client_tag.to_string(),
+        principal: principal.map(str::to_string),
+    })
+}
+
+#[test]
+fn turns_record_their_author_and_contexts_are_searchable_by_it() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(dir.path()).expect("open store");
+
+    // Two agents share one context; a third works alone.
+    let shared = store.create_context(0).unwrap().context_id;
+    let planner = append(
+        &mut store,
+        shared,
+        "plan",
+        author(1, "planner", Some("alice")),
+    );
+    let coder = append(&mut store, shared, "code", author(2, "coder", None));
+    let solo = store.create_context(0).unwrap().context_id;
+    append(&mut store, solo, "solo", author(3, "coder", Some("bob")));
+    let summary = append(&mut store, solo, "summary", None);
+
+    let meta = store.get_turn(planner, false).unwrap().meta;
+    assert_eq!(meta.author, author(1, "planner", Some("alice")));
+    assert_eq!(
+        store.get_turn(coder, false).unwrap().meta.author,
+        author(2, "coder", None)
+    );
+    assert_eq!(store.get_turn(summary, false).unwrap().meta.author, None);
+
+    assert_eq!(
+        common::search(&mut store, r#"author = "alice""#),
+        vec![shared]
+    );
+    assert_eq!(
+        common::search(&mut store, r#"author_tag = "coder""#),
+        vec![shared, solo]
+    );
+    assert_eq!(
+        common::search(&mut store, r#"author IN ("alice", "bob")"#),
+        vec![shared, solo]
+    );
+    assert_eq!(
+        common::search(&mut store, r#"author != "alice""#),
+        vec![solo]
+    );
+
+    // A fork inherits the authors of the turns it sees, and the index
+    // rebuild finds the same authors.
+    let fork = store.fork_context(planner).unwrap().context_id;
+    assert_eq!(
+        common::search(&mut store, r#"author_tag = "planner""#),
+        vec![shared, fork]
+    );
+    assert!(!common::search(&mut store, r#"author_tag = "coder""#).contains(&fork));
+    let mut build = store.begin_index_build();
+    store.continue_index_build(&mut build, usize::MAX);
+    assert_eq!(
+        common::search(&mut store, r#"author_tag = "planner""#),
+        vec![shared, fork]
+    );
+    assert_eq!(common::search(&mut store, r#"author = "bob""#), vec![solo]);
+}
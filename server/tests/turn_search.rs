@@ -0,0 +1,148 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use std::collections::HashMap;
+
+use cxdb_server::deadline::Deadline;
+use cxdb_server::error::StoreError;
+use cxdb_server::projection::{BytesRender, EnumRender, RenderOptions, TimeRender, U64Format};
+use cxdb_server::registry::Registry;
+use cxdb_server::store::Store;
+use cxdb_server::turn_search::{search_turns, TurnSearchQuery};
+use rmpv::Value;
+use tempfile::tempdir;
+
+const BUNDLE: &str = r#"
+{
+  "registry_version": 1,
+  "bundle_id": "2025-12-19T00:00:00Z#test",
+  "types": {
+    "com.example.Message": {
+      "versions": {
+        "1": {
+          "fields": {
+            "1": { "name": "role", "type": "u8", "enum": "com.example.Role" },
+            "2": { "name": "text", "type": "string" }
+          }
+        }
+      }
+    }
+  },
+  "enums": {
+    "com.example.Role": { "1": "user", "2": "assistant" }
+  }
+}
+"#;
+
+fn options() -> RenderOptions {
+    RenderOptions {
+        bytes_render: BytesRender::Base64,
+        u64_format: U64Format::String,
+        enum_render: EnumRender::Label,
+        time_render: TimeRender::Iso,
+        include_unknown: false,
+        apply_defaults: false,
+    }
+}
+
+fn append(store: &mut Store, context_id: u64, type_id: &str, role: u8, text: &str) {
+    let payload = common::msgpack(&Value::Map(vec![
+        (Value::Integer(1.into()), Value::Integer(role.into())),
+        (Value::Integer(2.into()), Value::String(text.into())),
+    ]));
+    common::append_typed(store, context_id, type_id, &payload);
+}
+
+fn query(params: &[(&str, &str)]) -> TurnSearchQuery {
+    let params: HashMap<String, String> = params
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    TurnSearchQuery::from_params(&params).expect("query")
+}
+
+#[test]
+fn search_filters_by_text_type_and_field() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(&dir.path().join("store")).expect("open store");
+    let mut registry = Registry::open(&dir.path().join("registry")).expect("open registry");
+    registry
+        .put_bundle("2025-12-19T00:00:00Z#test", BUNDLE.as_bytes())
+        .expect("put bundle");
+
+    let ctx = store.create_context(0).expect("create context").context_id;
+    append(
+        &mut store,
+        ctx,
+        "com.example.Message",
+        1,
+        "why did the job fail?",
+    );
+    append(
+        &mut store,
+        ctx,
+        "com.example.Message",
+        2,
+        "The call hit a Timeout.",
+    );
+    append(
+        &mut store,
+        ctx,
+        "com.example.Other",
+        2,
+        "timeout in an unknown type",
+    );
+    append(
+        &mut store,
+        ctx,
+        "com.example.Message",
+        1,
+        "raise the timeout then",
+    );
+
+    let search = |store: &mut Store, q: &TurnSearchQuery| {
+        search_turns(store, &registry, ctx, q, &options(), &Deadline::none()).expect("search")
+    };
+
+    let result = search(&mut store, &query(&[("text", "TIMEOUT")]));
+    let depths: Vec<u32> = result.matches.iter().map(|m| m.depth).collect();
+    let first_depth = depths[0];
+    assert_eq!(result.matches.len(), 2);
+    assert_eq!(result.scanned, 4);
+    assert_eq!(result.next_from_depth, None);
+    let snippet = &result.matches[0].snippets[0];
+    assert_eq!(snippet.path, "text");
+    assert_eq!(snippet.text, "The call hit a Timeout.");
+    assert_eq!(snippet.highlights, vec![[15, 22]]);
+
+    let result = search(
+        &mut store,
+        &query(&[("text", "timeout"), ("field.role", "user")]),
+    );
+    assert_eq!(result.matches.len(), 1);
+    assert_eq!(result.matches[0].depth, first_depth + 2);
+
+    let result = search(&mut store, &query(&[("type_id", "com.example.Other")]));
+    assert_eq!(result.matches.len(), 1);
+    assert!(result.matches[0].snippets.is_empty());
+
+    // A limit stops the scan and says where to continue.
+    let result = search(&mut store, &query(&[("text", "timeout"), ("limit", "1")]));
+    assert_eq!(result.matches.len(), 1);
+    assert_eq!(result.next_from_depth, Some(first_depth + 1));
+    let from = result.next_from_depth.unwrap().to_string();
+    let result = search(
+        &mut store,
+        &query(&[("text", "timeout"), ("from_depth", &from)]),
+    );
+    assert_eq!(result.matches.len(), 1);
+    assert_eq!(result.matches[0].depth, first_depth + 2);
+}
+
+#[test]
+fn search_requires_a_filter() {
+    let err = TurnSearchQuery::from_params(&HashMap::new()).unwrap_err();
+    assert!(matches!(err, StoreError::InvalidInput(_)));
+}
@@ -0,0 +1,100 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+#![cfg(unix)]
+
+use std::io::{Read, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::net::UnixStream;
+
+use cxdb_server::error::StoreError;
+use cxdb_server::metrics::SessionTracker;
+use cxdb_server::store::Store;
+use cxdb_server::unix_socket::{bind, peer_credentials, PeerCredentials};
+use tempfile::tempdir;
+
+#[test]
+fn bind_sets_the_mode_and_replaces_only_stale_sockets() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("cxdb.sock");
+
+    let listener = bind(&path, 0o600).unwrap();
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    drop(listener);
+
+    // The socket file outlives its listener; a restart takes it over.
+    let listener = bind(&path, 0o660).unwrap();
+    let mut client = UnixStream::connect(&path).unwrap();
+    let (mut server, _) = listener.accept().unwrap();
+    client.write_all(b"ping").unwrap();
+    let mut buf = [0u8; 4];
+    server.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+
+    let file = dir.path().join("not-a-socket");
+    std::fs::write(&file, b"data").unwrap();
+    assert!(matches!(
+        bind(&file, 0o660),
+        Err(StoreError::InvalidInput(_))
+    ));
+    assert_eq!(std::fs::read(&file).unwrap(), b"data");
+}
+
+#[test]
+fn peer_credentials_are_recorded_on_the_session_and_in_provenance() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("cxdb.sock");
+    let listener = bind(&path, 0o660).unwrap();
+    listener.set_nonblocking(true).unwrap();
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let credentials = runtime.block_on(async {
+        let listener = tokio::net::UnixListener::from_std(listener).unwrap();
+        let _client = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        peer_credentials(&stream).unwrap()
+    });
+    // The test process owns the directory it created.
+    let owner = std::fs::metadata(dir.path()).unwrap().uid();
+    assert_eq!(credentials.uid, owner);
+    assert_eq!(credentials.pid, Some(std::process::id() as i32));
+
+    let tracker = SessionTracker::new();
+    tracker.register(7, "agent".into(), Some("unix:/run/cxdb.sock".into()), None);
+    tracker.set_peer_credentials(7, Some(credentials));
+    let session = tracker.get_active_sessions().pop().unwrap();
+    assert_eq!(session.peer_credentials, Some(credentials));
+    let json = serde_json::to_value(&session).unwrap();
+    assert_eq!(json["peer_credentials"]["gid"], credentials.gid);
+
+    let mut store = Store::open(&dir.path().join("data")).unwrap();
+    let context_id = store.create_context(0).unwrap().context_id;
+    let metadata = store
+        .apply_metadata_patch(context_id, &credentials.provenance_patch(), &[])
+        .unwrap();
+    let provenance = metadata.provenance.unwrap();
+    assert_eq!(provenance.client_uid, Some(credentials.uid as i64));
+    assert_eq!(provenance.client_gid, Some(credentials.gid as i64));
+}
+
+#[test]
+fn sessions_without_a_unix_peer_omit_credentials() {
+    let tracker = SessionTracker::new();
+    tracker.register(1, "agent".into(), Some("127.0.0.1:5000".into()), None);
+    let json = serde_json::to_value(tracker.get_active_sessions().pop().unwrap()).unwrap();
+    assert!(json.get("peer_credentials").is_none());
+
+    let patch = PeerCredentials {
+        uid: 1000,
+        gid: 100,
+        pid: None,
+    }
+    .provenance_patch();
+    let provenance = patch.provenance.unwrap();
+    assert_eq!(
+        (provenance.client_uid, provenance.client_gid),
+        (Some(1000), Some(100))
+    );
+    assert!(provenance.client_address.is_none());
+}
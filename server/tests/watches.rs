@@ -0,0 +1,122 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+mod common;
+
+use std::collections::HashSet;
+
+use cxdb_server::metadata_overrides::MetadataPatch;
+use cxdb_server::store::Store;
+use cxdb_server::watches::{WatchSpec, Watches};
+use rmpv::Value;
+use tempfile::tempdir;
+
+fn append(store: &mut Store, context_id: u64, parent_turn_id: u64, payload: &Value) -> u64 {
+    common::append_child(store, context_id, parent_turn_id, &common::msgpack(payload))
+}
+
+fn create_context(store: &mut Store, tag: &str) -> (u64, u64) {
+    let ctx = store.create_context(0).expect("create context");
+    let meta = Value::Map(vec![(Value::from(1), Value::from(tag))]);
+    let first = Value::Map(vec![(Value::from(30), meta)]);
+    let turn_id = append(store, ctx.context_id, 0, &first);
+    (ctx.context_id, turn_id)
+}
+
+fn spec(query: &str) -> WatchSpec {
+    WatchSpec {
+        name: "deep prod agents".to_string(),
+        query: query.to_string(),
+        webhook_url: None,
+    }
+}
+
+#[test]
+fn watch_reports_entering_and_leaving_contexts() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(&dir.path().join("store")).expect("open store");
+    let watches = Watches::open(&dir.path().join("meta")).expect("open watches");
+    let live = HashSet::new();
+
+    let (deep, mut head) = create_context(&mut store, "prod-agent");
+    let (_shallow, _) = create_context(&mut store, "prod-agent");
+    let (_other, _) = create_context(&mut store, "dev-agent");
+
+    let info = watches
+        .register(spec(r#"tag = "prod-agent" AND depth > 2"#))
+        .expect("register");
+    let watch_id: u64 = info.id.parse().unwrap();
+
+    // Nothing matches yet.
+    assert!(watches.evaluate(&mut store, &live).is_empty());
+
+    for _ in 0..3 {
+        head = append(&mut store, deep, head, &Value::Map(vec![]));
+    }
+    let deltas = watches.evaluate(&mut store, &live);
+    assert_eq!(deltas.len(), 1);
+    assert_eq!(deltas[0].watch_id, watch_id);
+    assert_eq!(deltas[0].entered, vec![deep]);
+    assert!(deltas[0].left.is_empty());
+    assert_eq!(watches.matches(watch_id), Some(vec![deep]));
+
+    // No change, no delta.
+    assert!(watches.evaluate(&mut store, &live).is_empty());
+
+    // Retagging the context moves it out of the result set.
+    let patch = MetadataPatch {
+        client_tag: Some("retired-agent".to_string()),
+        ..Default::default()
+    };
+    store
+        .apply_metadata_patch(deep, &patch, &[])
+        .expect("patch");
+    let deltas = watches.evaluate(&mut store, &live);
+    assert_eq!(deltas[0].left, vec![deep]);
+    assert!(deltas[0].entered.is_empty());
+    assert_eq!(watches.get(watch_id).unwrap().match_count, Some(0));
+}
+
+#[test]
+fn new_watch_reports_initial_matches_but_reload_does_not() {
+    let dir = tempdir().expect("tempdir");
+    let mut store = Store::open(&dir.path().join("store")).expect("open store");
+    let live = HashSet::new();
+    let (a, _) = create_context(&mut store, "prod-agent");
+    let (b, _) = create_context(&mut store, "prod-agent");
+
+    {
+        let watches = Watches::open(&dir.path().join("meta")).expect("open watches");
+        watches
+            .register(spec(r#"tag = "prod-agent""#))
+            .expect("register");
+        let deltas = watches.evaluate(&mut store, &live);
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0].entered, vec![a, b]);
+    }
+
+    let watches = Watches::open(&dir.path().join("meta")).expect("reopen watches");
+    assert_eq!(watches.list().len(), 1);
+    assert!(watches.evaluate(&mut store, &live).is_empty());
+
+    let (c, _) = create_context(&mut store, "prod-agent");
+    let deltas = watches.evaluate(&mut store, &live);
+    assert_eq!(deltas[0].entered, vec![c]);
+}
+
+#[test]
+fn invalid_watch_query_is_rejected_and_removal_persists() {
+    let dir = tempdir().expect("tempdir");
+    let watches = Watches::open(dir.path()).expect("open watches");
+    assert!(watches.register(spec("tag = ")).is_err());
+
+    let info = watches.register(spec(r#"tag = "x""#)).expect("register");
+    let id: u64 = info.id.parse().unwrap();
+    assert!(watches.remove(id).expect("remove"));
+    assert!(!watches.remove(id).expect("remove again"));
+
+    let reopened = Watches::open(dir.path()).expect("reopen");
+    assert!(reopened.list().is_empty());
+    let next = reopened.register(spec(r#"tag = "y""#)).expect("register");
+    assert_ne!(next.id, info.id);
+}
@@ -0,0 +1,141 @@
+// Copyright 2025 StrongDM Inc
+// SPDX-License-Identifier: Apache-2.0
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cxdb_server::webhooks::{
+    is_public_address, start_webhook_dispatcher, RetryQueue, WebhookConfig, WebhookOrigin, Webhooks,
+};
+use serde_json::json;
+
+/// Records outcomes instead of owning anything.
+#[derive(Default)]
+struct Outcomes(Mutex<Vec<(u64, Result<(), String>)>>);
+
+impl WebhookOrigin for Outcomes {
+    fn kind(&self) -> &'static str {
+        "test"
+    }
+
+    fn is_active(&self, _id: u64) -> bool {
+        true
+    }
+
+    fn record_delivery(&self, id: u64, result: Result<(), String>) {
+        self.0.lock().unwrap().push((id, result));
+    }
+}
+
+impl Outcomes {
+    fn wait(&self) -> (u64, Result<(), String>) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(outcome) = self.0.lock().unwrap().first() {
+                return outcome.clone();
+            }
+            assert!(Instant::now() < deadline, "no webhook outcome");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+#[test]
+fn retries_back_off_and_come_due_in_order() {
+    let config = WebhookConfig {
+        retry_base: Duration::from_secs(1),
+        ..WebhookConfig::default()
+    };
+    assert_eq!(config.retry_delay(1), Duration::from_secs(1));
+    assert_eq!(config.retry_delay(3), Duration::from_secs(4));
+    assert_eq!(config.retry_delay(30), Duration::from_secs(300));
+
+    let now = Instant::now();
+    let mut queue = RetryQueue::default();
+    queue.push("second", now + Duration::from_secs(1));
+    queue.push("first", now);
+    assert_eq!(queue.next_due(), Some(now));
+    assert_eq!(queue.pop_due(now), Some("first"));
+    assert!(queue.pop_due(now).is_none());
+    assert_eq!(queue.len(), 1);
+    let later = now + Duration::from_secs(1);
+    assert_eq!(queue.pop_due(later), Some("second"));
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn internal_addresses_are_not_public() {
+    let public = |ip: &str| is_public_address(ip.parse::<IpAddr>().unwrap());
+    for ip in [
+        "127.0.0.1",
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.64.0.1",
+        "0.0.0.0",
+        "::1",
+        "::",
+        "fd00::1",
+        "fe80::1",
+        "::ffff:10.0.0.1",
+    ] {
+        assert!(!public(ip), "{ip}");
+    }
+    for ip in ["93.184.216.34", "8.8.8.8", "2606:4700::1111"] {
+        assert!(public(ip), "{ip}");
+    }
+}
+
+#[test]
+fn loopback_targets_need_an_allowlist_entry() {
+    let server = tiny_http::Server::http("127.0.0.1:0").expect("bind webhook");
+    let url = format!("http://{}/hook", server.server_addr());
+    let requests = Arc::new(AtomicUsize::new(0));
+    {
+        let requests = Arc::clone(&requests);
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                requests.fetch_add(1, Ordering::SeqCst);
+                let _ = request.respond(tiny_http::Response::empty(200));
+            }
+        });
+    }
+
+    let refused = Arc::new(Outcomes::default());
+    let webhooks = Arc::new(Webhooks::new(WebhookConfig {
+        max_attempts: 2,
+        retry_base: Duration::from_millis(10),
+        ..WebhookConfig::default()
+    }));
+    let _dispatcher = start_webhook_dispatcher(Arc::clone(&webhooks));
+    webhooks.send(
+        &url,
+        json!({}),
+        Arc::clone(&refused) as Arc<dyn WebhookOrigin>,
+        1,
+    );
+    let (id, result) = refused.wait();
+    assert_eq!(id, 1);
+    assert!(result.unwrap_err().contains("non-public"));
+    assert_eq!(requests.load(Ordering::SeqCst), 0);
+
+    let allowed = Arc::new(Outcomes::default());
+    let webhooks = Arc::new(Webhooks::new(WebhookConfig {
+        allow_hosts: vec!["127.0.0.1".into()],
+        ..WebhookConfig::default()
+    }));
+    let _dispatcher = start_webhook_dispatcher(Arc::clone(&webhooks));
+    webhooks.send(
+        &url,
+        json!({}),
+        Arc::clone(&allowed) as Arc<dyn WebhookOrigin>,
+        2,
+    );
+    assert_eq!(allowed.wait(), (2, Ok(())));
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    assert_eq!(webhooks.pending(), 0);
+}